@@ -10,7 +10,7 @@ use utils::config::{Config, utils::AsKey};
 
 use crate::{
     Server,
-    config::smtp::session::AddressMapping,
+    config::smtp::session::{AddressMapping, Rcpt},
     expr::{
         V_RECIPIENT, Variable, functions::ResolveVariable, if_block::IfBlock, tokenizer::TokenMap,
     },
@@ -23,14 +23,7 @@ impl Server {
         email: &str,
         session_id: u64,
     ) -> trc::Result<Option<u32>> {
-        let mut address = self
-            .core
-            .smtp
-            .session
-            .rcpt
-            .subaddressing
-            .to_subaddress(self, email, session_id)
-            .await;
+        let mut address = self.canonical_address(email, session_id).await;
 
         for _ in 0..2 {
             let result = directory.email_to_id(address.as_ref()).await?;
@@ -62,14 +55,7 @@ impl Server {
         session_id: u64,
     ) -> trc::Result<RcptType> {
         // Expand subaddress
-        let mut address = self
-            .core
-            .smtp
-            .session
-            .rcpt
-            .subaddressing
-            .to_subaddress(self, email, session_id)
-            .await;
+        let mut address = self.canonical_address(email, session_id).await;
 
         for _ in 0..2 {
             let rcpt_type = directory.rcpt(address.as_ref()).await?;
@@ -100,16 +86,7 @@ impl Server {
         session_id: u64,
     ) -> trc::Result<Vec<String>> {
         directory
-            .vrfy(
-                self.core
-                    .smtp
-                    .session
-                    .rcpt
-                    .subaddressing
-                    .to_subaddress(self, address, session_id)
-                    .await
-                    .as_ref(),
-            )
+            .vrfy(self.canonical_address(address, session_id).await.as_ref())
             .await
     }
 
@@ -120,18 +97,81 @@ impl Server {
         session_id: u64,
     ) -> trc::Result<Vec<String>> {
         directory
-            .expn(
-                self.core
-                    .smtp
-                    .session
-                    .rcpt
-                    .subaddressing
-                    .to_subaddress(self, address, session_id)
-                    .await
-                    .as_ref(),
-            )
+            .expn(self.canonical_address(address, session_id).await.as_ref())
             .await
     }
+
+    /// Returns the address used for directory lookups, with the sub-address
+    /// removed and the local part normalization rules applied.
+    pub async fn canonical_address<'x>(&'x self, email: &'x str, session_id: u64) -> Cow<'x, str> {
+        let rcpt = &self.core.smtp.session.rcpt;
+        let address = rcpt
+            .subaddressing
+            .to_subaddress(self, email, session_id)
+            .await;
+        let normalized = match rcpt.normalize_address(address.as_ref()) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(_) => None,
+        };
+        normalized.map(Cow::Owned).unwrap_or(address)
+    }
+}
+
+impl Rcpt {
+    /// Splits an address into its local part, sub-address and domain
+    /// at the first configured delimiter.
+    pub fn split_subaddress<'x>(&self, address: &'x str) -> Option<(&'x str, &'x str, &'x str)> {
+        let (local_part, domain_part) = address.rsplit_once('@')?;
+        let (local_part, detail) = local_part.split_once(|ch| self.delimiters.contains(&ch))?;
+        Some((local_part, detail, domain_part))
+    }
+
+    /// Applies the local part normalization rules of the address domain.
+    pub fn normalize_address<'x>(&self, address: &'x str) -> Cow<'x, str> {
+        let Some((local_part, domain_part)) = address.rsplit_once('@') else {
+            return address.into();
+        };
+        let mut normalized = Cow::Borrowed(local_part);
+        for rule in &self.normalize {
+            if rule
+                .domain
+                .as_ref()
+                .is_none_or(|domain| domain.eq_ignore_ascii_case(domain_part))
+            {
+                if let Cow::Owned(local_part) = rule
+                    .pattern
+                    .replace_all(normalized.as_ref(), rule.replace.as_str())
+                {
+                    normalized = local_part.into();
+                }
+            }
+        }
+
+        match normalized {
+            Cow::Owned(local_part) if !local_part.is_empty() => {
+                format!("{local_part}@{domain_part}").into()
+            }
+            _ => address.into(),
+        }
+    }
+
+    /// Rewrites an address so that its sub-address is separated with '+',
+    /// the delimiter Sieve scripts expect, and normalizes its local part.
+    pub fn sieve_address<'x>(&self, address: &'x str) -> Cow<'x, str> {
+        let address = match self.split_subaddress(address) {
+            Some((local_part, detail, domain_part))
+                if !address[local_part.len()..].starts_with('+') =>
+            {
+                Cow::Owned(format!("{local_part}+{detail}@{domain_part}"))
+            }
+            _ => Cow::Borrowed(address),
+        };
+        let normalized = match self.normalize_address(address.as_ref()) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(_) => None,
+        };
+        normalized.map(Cow::Owned).unwrap_or(address)
+    }
 }
 
 impl AddressMapping {
@@ -186,10 +226,10 @@ impl AddressMapping {
     ) -> Cow<'x, str> {
         match self {
             AddressMapping::Enable => {
-                if let Some((local_part, domain_part)) = address.rsplit_once('@') {
-                    if let Some((local_part, _)) = local_part.split_once('+') {
-                        return format!("{}@{}", local_part, domain_part).into();
-                    }
+                if let Some((local_part, _, domain_part)) =
+                    core.core.smtp.session.rcpt.split_subaddress(address)
+                {
+                    return format!("{}@{}", local_part, domain_part).into();
                 }
             }
             AddressMapping::Custom(if_block) => {
@@ -16,6 +16,7 @@ use jmap_proto::{
     request::RequestMethod,
     types::{acl::Acl, collection::Collection, id::Id},
 };
+use nlp::language::Language;
 use std::{
     hash::{DefaultHasher, Hash, Hasher},
     sync::Arc,
@@ -132,6 +133,7 @@ impl Server {
                 .map(ConcurrencyLimiter::new),
             obj_size: 0,
             revision,
+            impersonator: None,
         };
 
         for grant_account_id in [access_token.primary_id]
@@ -260,6 +262,27 @@ impl Server {
         }
     }
 
+    pub async fn account_language(&self, account_id: u32) -> Language {
+        if self.core.jmap.account_language {
+            match self.get_access_token(account_id).await {
+                Ok(access_token) => {
+                    return access_token
+                        .language()
+                        .unwrap_or(self.core.jmap.default_language);
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.account_id(account_id)
+                            .caused_by(trc::location!())
+                            .details("Failed to obtain account language")
+                    );
+                }
+            }
+        }
+
+        self.core.jmap.default_language
+    }
+
     pub async fn increment_token_revision(&self, changed_principals: ChangedPrincipals) {
         let mut nested_principals = Vec::new();
 
@@ -302,6 +325,9 @@ impl Server {
         }
 
         if !nested_principals.is_empty() {
+            // Group memberships changed, nested groups need to be resolved again
+            self.invalidate_group_caches();
+
             let mut fetched_ids = AHashSet::new();
             let mut ids = nested_principals.into_iter();
             let mut ids_stack = vec![];
@@ -416,6 +442,12 @@ impl AccessToken {
         self
     }
 
+    pub fn language(&self) -> Option<Language> {
+        self.locale
+            .as_deref()
+            .and_then(|locale| Language::from_iso_639(locale.split('_').next().unwrap_or(locale)))
+    }
+
     pub fn state(&self) -> u32 {
         // Hash state
         let mut s = DefaultHasher::new();
@@ -0,0 +1,206 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, sync::Arc};
+
+use compact_str::ToCompactString;
+use directory::{Directory, Permission, QueryBy};
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::asn1_rs::FromDer,
+    extensions::{GeneralName, ParsedExtension},
+};
+
+use crate::{
+    Server,
+    expr::{functions::ResolveVariable, *},
+    listener::ServerInstance,
+};
+
+use super::AccessToken;
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientCertificate {
+    pub subject: String,
+    pub issuer: String,
+    pub common_name: Option<String>,
+    pub emails: Vec<String>,
+    pub dns_names: Vec<String>,
+}
+
+struct ClientCertificateVars<'x> {
+    cert: &'x ClientCertificate,
+    instance: &'x ServerInstance,
+    remote_ip: IpAddr,
+}
+
+impl ClientCertificate {
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        let (_, parsed) = X509Certificate::from_der(der).ok()?;
+        let mut cert = ClientCertificate {
+            subject: parsed.subject().to_string(),
+            issuer: parsed.issuer().to_string(),
+            common_name: parsed
+                .subject()
+                .iter_common_name()
+                .find_map(|name| name.as_str().ok())
+                .map(|name| name.to_string()),
+            ..Default::default()
+        };
+
+        for ext in parsed.extensions() {
+            if let ParsedExtension::SubjectAlternativeName(san) = ext.parsed_extension() {
+                for name in &san.general_names {
+                    match name {
+                        GeneralName::RFC822Name(email) => cert.emails.push(email.to_lowercase()),
+                        GeneralName::DNSName(name) => cert.dns_names.push(name.to_lowercase()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Some(cert)
+    }
+}
+
+impl Server {
+    /// Authenticates a session using SASL EXTERNAL, the TLS client certificate
+    /// has already been validated against the listener's CA bundles during the
+    /// handshake so only the mapping to a principal is performed here.
+    pub async fn authenticate_external(
+        &self,
+        certificate: &[u8],
+        authzid: Option<&str>,
+        instance: &ServerInstance,
+        session_id: u64,
+        remote_ip: IpAddr,
+        directory: Option<&Directory>,
+    ) -> trc::Result<Arc<AccessToken>> {
+        let cert = ClientCertificate::parse(certificate).ok_or_else(|| {
+            trc::AuthEvent::Failed
+                .into_err()
+                .ctx(trc::Key::RemoteIp, remote_ip)
+                .details("Failed to parse client certificate")
+        })?;
+
+        // Map the certificate to a principal name
+        let name = self
+            .eval_if::<String, _>(
+                &self.core.network.client_cert_principal,
+                &ClientCertificateVars {
+                    cert: &cert,
+                    instance,
+                    remote_ip,
+                },
+                session_id,
+            )
+            .await
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| {
+                trc::AuthEvent::Failed
+                    .into_err()
+                    .ctx(trc::Key::RemoteIp, remote_ip)
+                    .details(cert.subject.clone())
+                    .reason("Client certificate does not map to a principal")
+            })?;
+
+        // An authorization identity, when provided, must match the mapped principal
+        if authzid.is_some_and(|authzid| !authzid.eq_ignore_ascii_case(&name)) {
+            return Err(trc::AuthEvent::Failed
+                .into_err()
+                .ctx(trc::Key::RemoteIp, remote_ip)
+                .ctx(trc::Key::AccountName, name)
+                .details(cert.subject)
+                .reason("Authorization identity does not match client certificate"));
+        }
+
+        let directory = directory.unwrap_or(&self.core.storage.directory);
+        let Some(principal) = directory.query(QueryBy::Name(&name), true).await? else {
+            return Err(trc::AuthEvent::Failed
+                .into_err()
+                .ctx(trc::Key::RemoteIp, remote_ip)
+                .ctx(trc::Key::AccountName, name)
+                .details(cert.subject)
+                .reason("Client certificate maps to an unknown principal"));
+        };
+
+        trc::event!(
+            Auth(trc::AuthEvent::Success),
+            AccountName = principal.name().to_string(),
+            AccountId = principal.id(),
+            SpanId = session_id,
+            Details = cert.subject,
+            Type = "client-certificate",
+        );
+
+        let access_token = self.get_access_token(principal).await?;
+        access_token.assert_has_permission(Permission::Authenticate)?;
+        Ok(access_token)
+    }
+}
+
+impl ResolveVariable for ClientCertificateVars<'_> {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        match variable {
+            V_CERT_SUBJECT => self.cert.subject.as_str().into(),
+            V_CERT_ISSUER => self.cert.issuer.as_str().into(),
+            V_CERT_CN => self.cert.common_name.as_deref().unwrap_or_default().into(),
+            V_CERT_EMAIL => self
+                .cert
+                .emails
+                .first()
+                .map(|email| email.as_str())
+                .unwrap_or_default()
+                .into(),
+            V_CERT_DNS => self
+                .cert
+                .dns_names
+                .first()
+                .map(|name| name.as_str())
+                .unwrap_or_default()
+                .into(),
+            V_LISTENER => self.instance.id.as_str().into(),
+            V_PROTOCOL => self.instance.protocol.as_str().into(),
+            V_REMOTE_IP => self.remote_ip.to_compact_string().into(),
+            _ => Variable::default(),
+        }
+    }
+
+    fn resolve_global(&self, _: &str) -> Variable<'_> {
+        Variable::Integer(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{Certificate, CertificateParams, DistinguishedName, DnType, SanType};
+
+    use super::ClientCertificate;
+
+    #[test]
+    fn client_certificate_names() {
+        let mut params = CertificateParams::new(vec!["Relay.Example.org".to_string()]);
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "relay-01");
+        params
+            .subject_alt_names
+            .push(SanType::Rfc822Name("Relay@Example.org".to_string()));
+        let der = Certificate::from_params(params)
+            .unwrap()
+            .serialize_der()
+            .unwrap();
+
+        let cert = ClientCertificate::parse(&der).unwrap();
+        assert_eq!(cert.subject, "CN=relay-01");
+        assert_eq!(cert.common_name.as_deref(), Some("relay-01"));
+        assert_eq!(cert.emails, vec!["relay@example.org".to_string()]);
+        assert_eq!(cert.dns_names, vec!["relay.example.org".to_string()]);
+        assert!(ClientCertificate::parse(b"not a certificate").is_none());
+    }
+}
@@ -0,0 +1,192 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, sync::Arc};
+
+use directory::{Permission, core::app_password::AuthProtocol};
+use store::{
+    Deserialize, IterateParams, ValueKey,
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, ReportClass, ValueClass, now},
+};
+use trc::AddContext;
+
+use crate::Server;
+
+use super::{AccessToken, AuthRequest, oauth::token::TokenInfo};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Impersonator {
+    pub account_id: u32,
+    pub name: String,
+}
+
+#[derive(
+    rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, serde::Serialize, Debug, Clone, PartialEq, Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpersonationEntry {
+    pub timestamp: u64,
+    pub admin_id: u32,
+    pub admin_name: String,
+    pub account_id: u32,
+    pub account_name: String,
+    pub tenant_id: Option<u32>,
+    pub protocol: String,
+    pub remote_ip: String,
+    pub action: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImpersonationLog {
+    pub items: Vec<ImpersonationEntry>,
+    pub total: usize,
+}
+
+impl Server {
+    pub(crate) async fn impersonate(
+        &self,
+        req: &AuthRequest<'_>,
+        token_info: &TokenInfo,
+    ) -> trc::Result<Arc<AccessToken>> {
+        // Impersonation is meant for troubleshooting mail clients and web applications
+        if !matches!(req.protocol, Some(AuthProtocol::Imap | AuthProtocol::Http)) {
+            return Err(trc::AuthEvent::Error
+                .into_err()
+                .details("Impersonation tokens are only valid for IMAP and HTTP"));
+        }
+
+        // Make sure the administrator is still allowed to impersonate accounts
+        let admin_id = token_info.client_id.parse::<u32>().map_err(|_| {
+            trc::AuthEvent::Error
+                .into_err()
+                .details("Invalid impersonation token")
+        })?;
+        let admin = self
+            .get_access_token(admin_id)
+            .await
+            .caused_by(trc::location!())?;
+        admin.assert_has_permission(Permission::ImpersonateAccount)?;
+
+        let mut access_token = self
+            .get_access_token(token_info.account_id)
+            .await
+            .caused_by(trc::location!())?
+            .as_ref()
+            .clone();
+        access_token.impersonator = Some(Impersonator {
+            account_id: admin_id,
+            name: admin.name.clone(),
+        });
+
+        Ok(Arc::new(access_token))
+    }
+
+    /// Adds an entry to the impersonation audit log if the access token was
+    /// obtained by an administrator acting as another account.
+    pub async fn record_impersonation(
+        &self,
+        access_token: &AccessToken,
+        protocol: AuthProtocol,
+        remote_ip: IpAddr,
+        action: impl Into<String>,
+        session_id: u64,
+    ) {
+        let Some(impersonator) = &access_token.impersonator else {
+            return;
+        };
+        let action = action.into();
+
+        trc::event!(
+            Security(trc::SecurityEvent::Impersonation),
+            SpanId = session_id,
+            AccountName = access_token.name.clone(),
+            Id = impersonator.name.clone(),
+            RemoteIp = remote_ip,
+            Details = action.clone(),
+        );
+
+        let now = now();
+        let entry = ImpersonationEntry {
+            timestamp: now,
+            admin_id: impersonator.account_id,
+            admin_name: impersonator.name.clone(),
+            account_id: access_token.primary_id,
+            account_name: access_token.name.clone(),
+            tenant_id: access_token.tenant.map(|tenant| tenant.id),
+            protocol: protocol.as_str().to_string(),
+            remote_ip: remote_ip.to_string(),
+            action,
+        };
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Report(ReportClass::Impersonation {
+                id: self.generate_snowflake_id(),
+                expires: now + self.core.jmap.impersonation.retention.as_secs(),
+            }),
+            Archiver::new(entry).serialize().unwrap_or_default(),
+        );
+        if let Err(err) = self.store().write(batch.build_all()).await {
+            trc::error!(
+                err.span_id(session_id)
+                    .caused_by(trc::location!())
+                    .details("Failed to write impersonation audit entry")
+            );
+        }
+    }
+
+    /// Returns a page of unexpired audit log entries, most recent first,
+    /// counting at most `max_total` matching entries.
+    pub async fn impersonation_log(
+        &self,
+        filter: impl Fn(&ImpersonationEntry) -> bool,
+        page: usize,
+        limit: usize,
+        max_total: usize,
+    ) -> trc::Result<ImpersonationLog> {
+        let mut log = ImpersonationLog {
+            items: Vec::new(),
+            total: 0,
+        };
+        let mut offset = page.saturating_sub(1) * limit;
+
+        self.store()
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Report(ReportClass::Impersonation {
+                        id: 0,
+                        expires: now(),
+                    })),
+                    ValueKey::from(ValueClass::Report(ReportClass::Impersonation {
+                        id: u64::MAX,
+                        expires: u64::MAX,
+                    })),
+                )
+                .descending(),
+                |_, value| {
+                    let entry = <Archive<AlignedBytes> as Deserialize>::deserialize(value)?
+                        .deserialize::<ImpersonationEntry>()
+                        .caused_by(trc::location!())?;
+
+                    if filter(&entry) {
+                        if offset == 0 {
+                            if limit == 0 || log.items.len() < limit {
+                                log.items.push(entry);
+                            }
+                        } else {
+                            offset -= 1;
+                        }
+
+                        log.total += 1;
+                    }
+
+                    Ok(max_total == 0 || log.total < max_total)
+                },
+            )
+            .await
+            .caused_by(trc::location!())
+            .map(|_| log)
+    }
+}
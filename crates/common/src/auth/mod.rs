@@ -7,8 +7,10 @@
 use std::{net::IpAddr, sync::Arc};
 
 use directory::{
-    Directory, Permission, Permissions, Principal, QueryBy, core::secret::verify_secret_hash,
+    Directory, Permission, Permissions, Principal, QueryBy,
+    core::{app_password::AuthProtocol, secret::verify_secret_hash},
 };
+use impersonate::Impersonator;
 use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use oauth::GrantType;
@@ -20,12 +22,16 @@ use utils::{
 use crate::{Server, listener::limiter::ConcurrencyLimiter};
 
 pub mod access_token;
+pub mod external;
+pub mod impersonate;
 pub mod oauth;
 pub mod rate_limit;
 pub mod roles;
 pub mod sasl;
+pub mod scram;
+pub mod webauthn;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct AccessToken {
     pub primary_id: u32,
     pub member_of: Vec<u32>,
@@ -42,6 +48,7 @@ pub struct AccessToken {
     pub concurrent_uploads: Option<ConcurrencyLimiter>,
     pub revision: u64,
     pub obj_size: u64,
+    pub impersonator: Option<Impersonator>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -63,6 +70,8 @@ pub struct AuthRequest<'x> {
     remote_ip: IpAddr,
     return_member_of: bool,
     directory: Option<&'x Directory>,
+    protocol: Option<AuthProtocol>,
+    trusted_device: Option<u32>,
 }
 
 impl Server {
@@ -73,16 +82,27 @@ impl Server {
         // Validate credentials
         match &req.credentials {
             Credentials::OAuthBearer { token } if !directory.has_bearer_token_support() => {
-                match self
-                    .validate_access_token(GrantType::AccessToken.into(), token)
-                    .await
-                {
-                    Ok(token_into) => self.get_access_token(token_into.account_id).await,
+                match self.validate_access_token(None, token).await {
+                    Ok(token_info) => match token_info.grant_type {
+                        GrantType::AccessToken => {
+                            self.get_access_token(token_info.account_id).await
+                        }
+                        GrantType::Impersonation => self.impersonate(req, &token_info).await,
+                        _ => Err(trc::AuthEvent::Error
+                            .into_err()
+                            .details("Invalid grant type")),
+                    },
                     Err(err) => Err(err),
                 }
             }
             _ => match self.authenticate_credentials(req, directory).await {
-                Ok(principal) => self.get_access_token(principal).await,
+                Ok(principal) => {
+                    if let Some(login) = req.credentials.login().filter(|_| self.has_auth_lockout())
+                    {
+                        self.record_auth_success(req.remote_ip, login).await?;
+                    }
+                    self.get_access_token(principal).await
+                }
                 Err(err) => Err(err),
             },
         }
@@ -91,6 +111,16 @@ impl Server {
                 .assert_has_permission(Permission::Authenticate)
                 .map(|_| token)
         })
+        .inspect(|token| {
+            // Track recently active accounts for cache warm-up
+            if self.core.network.cache_warm_up.is_some() {
+                self.inner
+                    .data
+                    .active_accounts
+                    .lock()
+                    .insert(token.primary_id());
+            }
+        })
     }
 
     async fn authenticate_credentials(
@@ -98,12 +128,55 @@ impl Server {
         req: &AuthRequest<'_>,
         directory: &Directory,
     ) -> trc::Result<Principal> {
+        // Reject requests from locked out addresses and accounts
+        if let Some(expires) = self
+            .is_auth_locked_out(req.remote_ip, req.credentials.login())
+            .await?
+        {
+            return Err(trc::SecurityEvent::AuthenticationLockout
+                .into_err()
+                .ctx(trc::Key::RemoteIp, req.remote_ip)
+                .ctx_opt(
+                    trc::Key::AccountName,
+                    req.credentials.login().map(|s| s.to_string()),
+                )
+                .ctx(trc::Key::Expires, expires));
+        }
+
         // First try to authenticate the user against the default directory
         let result = match directory
             .query(QueryBy::Credentials(&req.credentials), req.return_member_of)
             .await
         {
             Ok(Some(principal)) => {
+                // Scoped app passwords are only valid for some protocols and addresses,
+                // and protocols without 2FA support may be restricted to app passwords
+                if let Credentials::Plain { username, secret } = &req.credentials {
+                    let require_app_password = self.core.jmap.two_factor.enforce_app_passwords
+                        && req.protocol.is_some_and(|p| p != AuthProtocol::Http)
+                        && principal.has_second_factor();
+
+                    if require_app_password || principal.has_scoped_app_passwords() {
+                        let reason = match principal.app_password(secret).await? {
+                            Some(scope) if !scope.allows(req.protocol, &req.remote_ip) => {
+                                Some("App password not permitted for this protocol or address")
+                            }
+                            None if require_app_password => {
+                                Some("App password required for accounts with 2FA enabled")
+                            }
+                            _ => None,
+                        };
+
+                        if let Some(reason) = reason {
+                            return Err(trc::AuthEvent::Failed
+                                .into_err()
+                                .reason(reason)
+                                .ctx(trc::Key::RemoteIp, req.remote_ip)
+                                .ctx(trc::Key::AccountName, username.clone()));
+                        }
+                    }
+                }
+
                 trc::event!(
                     Auth(trc::AuthEvent::Success),
                     AccountName = principal.name().to_string(),
@@ -116,6 +189,26 @@ impl Server {
             Ok(None) => Ok(()),
             Err(err) => {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::MissingTotp)) {
+                    // The password is correct, trusted devices do not require a second factor
+                    if let (Some(account_id), Credentials::Plain { username, .. }) =
+                        (req.trusted_device, &req.credentials)
+                    {
+                        if let Some(principal) = directory
+                            .query(QueryBy::Name(username), req.return_member_of)
+                            .await?
+                            .filter(|principal| principal.id() == account_id)
+                        {
+                            trc::event!(
+                                Auth(trc::AuthEvent::Success),
+                                AccountName = principal.name().to_string(),
+                                AccountId = principal.id(),
+                                SpanId = req.session_id,
+                            );
+
+                            return Ok(principal);
+                        }
+                    }
+
                     return Err(err);
                 } else {
                     Err(err)
@@ -170,6 +263,12 @@ impl Server {
 
         if let Err(err) = result {
             Err(err)
+        } else if self.has_auth_lockout() {
+            let login = req.credentials.login();
+            self.record_auth_failure(req.remote_ip, login).await?;
+            Err(trc::AuthEvent::Failed
+                .ctx(trc::Key::RemoteIp, req.remote_ip)
+                .ctx_opt(trc::Key::AccountName, login.map(|s| s.to_string())))
         } else if self.has_auth_fail2ban() {
             let login = req.credentials.login();
             if self.is_auth_fail2banned(req.remote_ip, login).await? {
@@ -205,6 +304,8 @@ impl<'x> AuthRequest<'x> {
             remote_ip,
             return_member_of: true,
             directory: None,
+            protocol: None,
+            trusted_device: None,
         }
     }
 
@@ -233,6 +334,16 @@ impl<'x> AuthRequest<'x> {
         self.directory = Some(directory);
         self
     }
+
+    pub fn with_protocol(mut self, protocol: AuthProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    pub fn with_trusted_device(mut self, account_id: u32) -> Self {
+        self.trusted_device = Some(account_id);
+        self
+    }
 }
 
 impl CacheItemWeight for AccessToken {
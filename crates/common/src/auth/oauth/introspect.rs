@@ -4,10 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use directory::Permission;
 use serde::{Deserialize, Serialize};
+use store::{blake3, dispatch::lookup::KeyValue};
 use trc::{AddContext, AuthEvent, EventType};
 
-use crate::{Server, auth::AccessToken};
+use crate::{KV_OAUTH_REVOKED, Server, auth::AccessToken};
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct OAuthIntrospect {
@@ -83,4 +85,52 @@ impl Server {
             Err(err) => Err(err),
         }
     }
+
+    pub async fn revoke_access_token(
+        &self,
+        token: &str,
+        access_token: &AccessToken,
+    ) -> trc::Result<()> {
+        let token_info = match self.validate_access_token(None, token).await {
+            Ok(token_info) => token_info,
+            Err(err)
+                if matches!(
+                    err.event_type(),
+                    EventType::Auth(AuthEvent::Error) | EventType::Auth(AuthEvent::TokenExpired)
+                ) =>
+            {
+                // Invalid, expired or already revoked tokens are ignored
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+
+        // Tokens can be revoked by their owner or by the client they were issued to
+        if token_info.account_id != access_token.primary_id()
+            && token_info.client_id != access_token.name
+        {
+            access_token.assert_has_permission(Permission::OauthClientOverride)?;
+        }
+
+        // Revoked tokens are kept until they expire
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_OAUTH_REVOKED,
+                    blake3::hash(token.as_bytes()).as_bytes(),
+                    vec![],
+                )
+                .expires(token_info.expires_in),
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Auth(AuthEvent::TokenRevoked),
+            AccountId = token_info.account_id,
+            Id = token_info.client_id,
+        );
+
+        Ok(())
+    }
 }
@@ -26,6 +26,8 @@ pub enum GrantType {
     LiveMetrics,
     Troubleshoot,
     Rsvp,
+    TrustedDevice,
+    Impersonation,
 }
 
 impl GrantType {
@@ -37,6 +39,8 @@ impl GrantType {
             GrantType::LiveMetrics => "live_metrics",
             GrantType::Troubleshoot => "troubleshoot",
             GrantType::Rsvp => "rsvp",
+            GrantType::TrustedDevice => "trusted_device",
+            GrantType::Impersonation => "impersonation",
         }
     }
 
@@ -48,6 +52,8 @@ impl GrantType {
             GrantType::LiveMetrics => 3,
             GrantType::Troubleshoot => 4,
             GrantType::Rsvp => 5,
+            GrantType::TrustedDevice => 6,
+            GrantType::Impersonation => 7,
         }
     }
 
@@ -59,6 +65,8 @@ impl GrantType {
             3 => Some(GrantType::LiveMetrics),
             4 => Some(GrantType::Troubleshoot),
             5 => Some(GrantType::Rsvp),
+            6 => Some(GrantType::TrustedDevice),
+            7 => Some(GrantType::Impersonation),
             _ => None,
         }
     }
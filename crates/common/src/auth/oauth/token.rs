@@ -11,12 +11,13 @@ use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use store::{
     blake3,
+    dispatch::lookup::KeyValue,
     rand::{Rng, rng},
 };
 use trc::AddContext;
 use utils::codec::leb128::{Leb128Iterator, Leb128Vec};
 
-use crate::Server;
+use crate::{KV_OAUTH_REVOKED, Server};
 
 use super::{CLIENT_ID_MAX_LEN, GrantType, RANDOM_CODE_LEN, crypto::SymmetricEncrypt};
 
@@ -207,6 +208,21 @@ impl Server {
                     .reason(err)
             })?;
 
+        // Validate revocation
+        if self
+            .in_memory_store()
+            .key_exists(KeyValue::<()>::build_key(
+                KV_OAUTH_REVOKED,
+                blake3::hash(token_.as_bytes()).as_bytes(),
+            ))
+            .await
+            .caused_by(trc::location!())?
+        {
+            return Err(trc::AuthEvent::Error
+                .into_err()
+                .details("Token has been revoked"));
+        }
+
         // Success
         Ok(TokenInfo {
             grant_type,
@@ -0,0 +1,499 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, sync::Arc};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use directory::{
+    Directory, Permission, Principal, QueryBy,
+    core::scram::{ScramCredentials, sha256},
+};
+use store::rand::{Rng, distr::Alphanumeric, rng};
+use utils::crypto::{constant_time_eq, hmac_sha256};
+
+use crate::Server;
+
+use super::AccessToken;
+
+// Channel binding type as defined in RFC 9266
+pub const CHANNEL_BINDING_TYPE: &str = "tls-exporter";
+
+pub struct ScramSession {
+    plus: bool,
+    plus_advertised: bool,
+    channel_binding: Option<Vec<u8>>,
+    state: ScramState,
+}
+
+enum ScramState {
+    ClientFirst,
+    ClientFinal {
+        username: String,
+        principal: Option<Principal>,
+        credentials: ScramCredentials,
+        gs2_header: String,
+        auth_message: String,
+        nonce: String,
+    },
+    Verified(Arc<AccessToken>),
+    Done,
+}
+
+struct ClientFirst {
+    username: String,
+    gs2_header: String,
+    client_first_bare: String,
+    client_nonce: String,
+}
+
+pub enum ScramStep {
+    Challenge(Vec<u8>),
+    Success(Arc<AccessToken>),
+}
+
+impl ScramSession {
+    /// Creates a new SCRAM-SHA-256 exchange. The channel binding data should be provided
+    /// whenever the session is TLS protected, even for the non-PLUS variant.
+    /// `plus_advertised` indicates whether SCRAM-SHA-256-PLUS was offered to the client,
+    /// which is required to tell a downgrade attempt apart from a client that simply
+    /// supports channel binding.
+    pub fn new(plus: bool, plus_advertised: bool, channel_binding: Option<Vec<u8>>) -> Self {
+        ScramSession {
+            plus,
+            plus_advertised,
+            channel_binding,
+            state: ScramState::ClientFirst,
+        }
+    }
+
+    fn parse_client_first(&self, message: &[u8]) -> trc::Result<ClientFirst> {
+        let message = std::str::from_utf8(message).map_err(|_| invalid("Invalid UTF-8"))?;
+
+        // Parse GS2 header
+        let (cbind_flag, rest) = message
+            .split_once(',')
+            .ok_or_else(|| invalid("Missing GS2 header"))?;
+        let (authzid, client_first_bare) = rest
+            .split_once(',')
+            .ok_or_else(|| invalid("Missing GS2 header"))?;
+        let gs2_header = &message[..message.len() - client_first_bare.len()];
+
+        match cbind_flag {
+            "n" if !self.plus => {}
+            "y" if !self.plus => {
+                // The client supports channel binding but believes the server does not,
+                // which means that the PLUS variant was stripped from the advertisement.
+                if self.plus_advertised {
+                    return Err(invalid("Channel binding downgrade detected"));
+                }
+            }
+            _ => {
+                if !self.plus
+                    || cbind_flag.strip_prefix("p=") != Some(CHANNEL_BINDING_TYPE)
+                    || self.channel_binding.is_none()
+                {
+                    return Err(invalid("Unsupported channel binding"));
+                }
+            }
+        }
+
+        // Parse client-first-message-bare
+        let mut username = None;
+        let mut client_nonce = None;
+        for (pos, attribute) in client_first_bare.split(',').enumerate() {
+            match attribute.split_at_checked(2) {
+                Some(("n=", value)) if pos == 0 => {
+                    username = Some(decode_saslname(value)?);
+                }
+                Some(("r=", value)) if pos == 1 && !value.is_empty() => {
+                    client_nonce = Some(value.to_string());
+                }
+                Some(("m=", _)) => {
+                    return Err(invalid("Unsupported mandatory extension"));
+                }
+                _ if pos > 1 => {}
+                _ => {
+                    return Err(invalid("Invalid client-first-message"));
+                }
+            }
+        }
+        let username = username
+            .filter(|username| !username.is_empty())
+            .ok_or_else(|| invalid("Missing username"))?;
+        let client_nonce = client_nonce.ok_or_else(|| invalid("Missing nonce"))?;
+
+        // Proxy authentication is not supported
+        if let Some(authzid) = authzid.strip_prefix("a=") {
+            if decode_saslname(authzid)? != username {
+                return Err(invalid("Authorization identity not supported"));
+            }
+        } else if !authzid.is_empty() {
+            return Err(invalid("Invalid authorization identity"));
+        }
+
+        Ok(ClientFirst {
+            username,
+            gs2_header: gs2_header.to_string(),
+            client_first_bare: client_first_bare.to_string(),
+            client_nonce,
+        })
+    }
+
+    fn server_first(
+        &mut self,
+        client_first: ClientFirst,
+        principal: Option<Principal>,
+        credentials: ScramCredentials,
+        server_nonce: &str,
+    ) -> String {
+        let nonce = format!("{}{}", client_first.client_nonce, server_nonce);
+        let server_first = format!(
+            "r={},s={},i={}",
+            nonce,
+            STANDARD.encode(&credentials.salt),
+            credentials.iterations
+        );
+        self.state = ScramState::ClientFinal {
+            username: client_first.username,
+            principal,
+            credentials,
+            gs2_header: client_first.gs2_header,
+            auth_message: format!("{},{}", client_first.client_first_bare, server_first),
+            nonce,
+        };
+        server_first
+    }
+
+    fn verify_client_final(&self, message: &[u8]) -> trc::Result<String> {
+        let ScramState::ClientFinal {
+            credentials,
+            gs2_header,
+            auth_message,
+            nonce,
+            ..
+        } = &self.state
+        else {
+            return Err(invalid("Unexpected client-final-message"));
+        };
+        let message = std::str::from_utf8(message).map_err(|_| invalid("Invalid UTF-8"))?;
+        let (without_proof, proof) = message
+            .rsplit_once(",p=")
+            .ok_or_else(|| invalid("Missing client proof"))?;
+        let proof = STANDARD
+            .decode(proof)
+            .map_err(|_| invalid("Invalid client proof"))?;
+
+        let mut channel_binding = None;
+        let mut client_nonce = None;
+        for (pos, attribute) in without_proof.split(',').enumerate() {
+            match attribute.split_at_checked(2) {
+                Some(("c=", value)) if pos == 0 => {
+                    channel_binding = Some(value);
+                }
+                Some(("r=", value)) if pos == 1 => {
+                    client_nonce = Some(value);
+                }
+                _ if pos > 1 => {}
+                _ => {
+                    return Err(invalid("Invalid client-final-message"));
+                }
+            }
+        }
+
+        // Validate channel binding
+        let mut expected_binding = gs2_header.as_bytes().to_vec();
+        if gs2_header.starts_with("p=") {
+            expected_binding.extend_from_slice(self.channel_binding.as_deref().unwrap_or_default());
+        }
+        if channel_binding
+            .and_then(|value| STANDARD.decode(value).ok())
+            .is_none_or(|value| !constant_time_eq(&value, &expected_binding))
+        {
+            return Err(trc::AuthEvent::Failed
+                .into_err()
+                .details("Channel binding mismatch"));
+        }
+        if client_nonce != Some(nonce.as_str()) {
+            return Err(invalid("Nonce mismatch"));
+        }
+
+        // Verify client proof
+        let auth_message = format!("{auth_message},{without_proof}");
+        let client_signature = hmac_sha256(&credentials.stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.len() {
+            return Err(trc::AuthEvent::Failed.into_err());
+        }
+        let client_key = proof
+            .iter()
+            .zip(client_signature)
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<_>>();
+        if !constant_time_eq(&sha256(&client_key), &credentials.stored_key) {
+            return Err(trc::AuthEvent::Failed.into_err());
+        }
+
+        Ok(format!(
+            "v={}",
+            STANDARD.encode(hmac_sha256(
+                &credentials.server_key,
+                auth_message.as_bytes()
+            ))
+        ))
+    }
+
+    pub fn is_plus(&self) -> bool {
+        self.plus
+    }
+
+    pub fn has_started(&self) -> bool {
+        !matches!(self.state, ScramState::ClientFirst)
+    }
+
+    pub fn is_verified(&self) -> bool {
+        matches!(self.state, ScramState::Verified(_))
+    }
+}
+
+impl Server {
+    pub async fn authenticate_scram(
+        &self,
+        scram: &mut ScramSession,
+        message: &[u8],
+        session_id: u64,
+        remote_ip: IpAddr,
+        directory: Option<&Directory>,
+    ) -> trc::Result<ScramStep> {
+        match std::mem::replace(&mut scram.state, ScramState::Done) {
+            ScramState::ClientFirst => {
+                let client_first = scram.parse_client_first(message)?;
+                let directory = directory.unwrap_or(&self.core.storage.directory);
+
+                // Reject requests from locked out addresses and accounts
+                if let Some(expires) = self
+                    .is_auth_locked_out(remote_ip, Some(client_first.username.as_str()))
+                    .await?
+                {
+                    return Err(trc::SecurityEvent::AuthenticationLockout
+                        .into_err()
+                        .ctx(trc::Key::RemoteIp, remote_ip)
+                        .ctx(trc::Key::AccountName, client_first.username)
+                        .ctx(trc::Key::Expires, expires));
+                }
+
+                // Obtain the SCRAM credentials, unknown accounts or accounts without a
+                // usable secret receive random credentials to avoid account enumeration.
+                let principal = directory
+                    .query(QueryBy::Name(&client_first.username), true)
+                    .await?;
+                let salt = || rng().random::<[u8; 16]>().to_vec();
+                let mut credentials = None;
+                if let Some(principal) = principal
+                    .as_ref()
+                    .filter(|principal| !principal.has_second_factor())
+                {
+                    for secret in &principal.secrets {
+                        credentials = ScramCredentials::from_secret(secret, salt).await?;
+                        if credentials.is_some() {
+                            break;
+                        }
+                    }
+                }
+                let credentials = credentials.unwrap_or_else(|| ScramCredentials {
+                    salt: salt(),
+                    iterations: directory::core::scram::SCRAM_SHA256_ITERATIONS,
+                    stored_key: rng().random(),
+                    server_key: rng().random(),
+                });
+                let server_nonce = rng()
+                    .sample_iter(Alphanumeric)
+                    .take(24)
+                    .map(char::from)
+                    .collect::<String>();
+
+                Ok(ScramStep::Challenge(
+                    scram
+                        .server_first(client_first, principal, credentials, &server_nonce)
+                        .into_bytes(),
+                ))
+            }
+            state @ ScramState::ClientFinal { .. } => {
+                scram.state = state;
+                let result = scram.verify_client_final(message);
+                let ScramState::ClientFinal {
+                    username,
+                    principal,
+                    ..
+                } = std::mem::replace(&mut scram.state, ScramState::Done)
+                else {
+                    unreachable!()
+                };
+
+                match (result, principal) {
+                    (Ok(server_final), Some(principal)) => {
+                        trc::event!(
+                            Auth(trc::AuthEvent::Success),
+                            AccountName = principal.name().to_string(),
+                            AccountId = principal.id(),
+                            SpanId = session_id,
+                        );
+
+                        if self.has_auth_lockout() {
+                            self.record_auth_success(remote_ip, &username).await?;
+                        }
+
+                        let access_token = self.get_access_token(principal).await?;
+                        access_token.assert_has_permission(Permission::Authenticate)?;
+                        scram.state = ScramState::Verified(access_token);
+                        Ok(ScramStep::Challenge(server_final.into_bytes()))
+                    }
+                    (result, _) => {
+                        if let Err(err) = result {
+                            if !err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
+                                return Err(err.ctx(trc::Key::AccountName, username));
+                            }
+                        }
+
+                        if self.has_auth_lockout() {
+                            self.record_auth_failure(remote_ip, Some(username.as_str()))
+                                .await?;
+                            Err(trc::AuthEvent::Failed
+                                .ctx(trc::Key::RemoteIp, remote_ip)
+                                .ctx(trc::Key::AccountName, username))
+                        } else if self.has_auth_fail2ban()
+                            && self
+                                .is_auth_fail2banned(remote_ip, Some(username.as_str()))
+                                .await?
+                        {
+                            Err(trc::SecurityEvent::AuthenticationBan
+                                .into_err()
+                                .ctx(trc::Key::RemoteIp, remote_ip)
+                                .ctx(trc::Key::AccountName, username))
+                        } else {
+                            Err(trc::AuthEvent::Failed
+                                .ctx(trc::Key::RemoteIp, remote_ip)
+                                .ctx(trc::Key::AccountName, username))
+                        }
+                    }
+                }
+            }
+            ScramState::Verified(access_token) if message.is_empty() => {
+                Ok(ScramStep::Success(access_token))
+            }
+            _ => Err(invalid("Unexpected SCRAM message")),
+        }
+    }
+}
+
+fn decode_saslname(value: &str) -> trc::Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '=' {
+            match (chars.next(), chars.next()) {
+                (Some('2'), Some('C')) => result.push(','),
+                (Some('3'), Some('D')) => result.push('='),
+                _ => return Err(invalid("Invalid username encoding")),
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+    Ok(result)
+}
+
+fn invalid(reason: &'static str) -> trc::Error {
+    trc::AuthEvent::Error
+        .into_err()
+        .ctx(trc::Key::Reason, reason)
+        .details("Invalid SCRAM-SHA-256 exchange")
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use directory::core::scram::ScramCredentials;
+
+    use super::{ScramSession, ScramState};
+
+    #[test]
+    fn scram_sha256_exchange() {
+        // RFC 7677 example exchange
+        let credentials = ScramCredentials::from_password(
+            "pencil",
+            STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap(),
+            4096,
+        );
+        let mut scram = ScramSession::new(false, false, None);
+        let client_first = scram
+            .parse_client_first(b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO")
+            .unwrap();
+        assert_eq!(client_first.username, "user");
+        assert_eq!(
+            scram.server_first(
+                client_first,
+                None,
+                credentials,
+                "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0"
+            ),
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        );
+        assert_eq!(
+            scram
+                .verify_client_final(
+                    concat!(
+                        "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,",
+                        "p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+                    )
+                    .as_bytes()
+                )
+                .unwrap(),
+            "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+        );
+        assert!(
+            scram
+                .verify_client_final(
+                    concat!(
+                        "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,",
+                        "p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVA="
+                    )
+                    .as_bytes()
+                )
+                .is_err()
+        );
+        assert!(matches!(scram.state, ScramState::ClientFinal { .. }));
+
+        // Downgrade and channel binding checks
+        let scram = ScramSession::new(false, true, Some(vec![1, 2, 3]));
+        assert!(scram.parse_client_first(b"y,,n=user,r=abc").is_err());
+        assert!(scram.parse_client_first(b"n,,n=user,r=abc").is_ok());
+        assert!(
+            scram
+                .parse_client_first(b"p=tls-exporter,,n=user,r=abc")
+                .is_err()
+        );
+        let scram = ScramSession::new(false, false, None);
+        assert!(scram.parse_client_first(b"y,,n=user,r=abc").is_ok());
+        let scram = ScramSession::new(false, false, Some(vec![1, 2, 3]));
+        assert!(scram.parse_client_first(b"y,,n=user,r=abc").is_ok());
+        let scram = ScramSession::new(true, true, Some(vec![1, 2, 3]));
+        assert!(scram.parse_client_first(b"n,,n=user,r=abc").is_err());
+        assert!(
+            scram
+                .parse_client_first(b"p=tls-unique,,n=user,r=abc")
+                .is_err()
+        );
+        assert!(
+            scram
+                .parse_client_first(b"p=tls-exporter,a=other,n=user,r=abc")
+                .is_err()
+        );
+        let client_first = scram
+            .parse_client_first(b"p=tls-exporter,,n=us=2Cer,r=abc")
+            .unwrap();
+        assert_eq!(client_first.username, "us,er");
+        assert_eq!(client_first.gs2_header, "p=tls-exporter,,");
+    }
+}
@@ -0,0 +1,357 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// WebAuthn credentials are stored as "$webauthn$<name>$<alg>$<credential id>$<public key>"
+// secrets, where the algorithm is the COSE identifier and the raw public key is base64url
+// encoded. Attestation statements are not verified, the public key is obtained from the
+// SubjectPublicKeyInfo returned by the browser.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use directory::{Principal, backend::internal::SpecialSecrets};
+use ring::signature::{ECDSA_P256_SHA256_ASN1, ED25519, UnparsedPublicKey};
+use sha2::{Digest, Sha256};
+use store::{
+    dispatch::lookup::KeyValue,
+    rand::{Rng, rng},
+};
+use trc::AddContext;
+
+use crate::{KV_LOCK_WEBAUTHN, KV_WEBAUTHN_CHALLENGE, KV_WEBAUTHN_SIGN_COUNT, Server};
+
+const WEBAUTHN_CHALLENGE_EXPIRY: u64 = 300;
+
+const P256_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+const ED25519_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebAuthnAlgorithm {
+    Es256,
+    EdDsa,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebAuthnCredential<'x> {
+    pub name: &'x str,
+    pub algorithm: WebAuthnAlgorithm,
+    pub credential_id: &'x str,
+    pub public_key: Vec<u8>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnAssertion {
+    pub credential_id: String,
+    pub authenticator_data: String,
+    pub client_data: String,
+    pub signature: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    typ: String,
+    challenge: String,
+    origin: String,
+}
+
+impl WebAuthnAlgorithm {
+    pub fn from_cose(id: i64) -> Option<Self> {
+        match id {
+            -7 => Some(WebAuthnAlgorithm::Es256),
+            -8 => Some(WebAuthnAlgorithm::EdDsa),
+            _ => None,
+        }
+    }
+
+    pub fn cose_id(&self) -> i64 {
+        match self {
+            WebAuthnAlgorithm::Es256 => -7,
+            WebAuthnAlgorithm::EdDsa => -8,
+        }
+    }
+}
+
+impl<'x> WebAuthnCredential<'x> {
+    pub fn parse(secret: &'x str) -> Option<Self> {
+        let mut parts = secret.strip_prefix("$webauthn$")?.split('$');
+        let name = parts.next()?;
+        let algorithm = WebAuthnAlgorithm::from_cose(parts.next()?.parse().ok()?)?;
+        let credential_id = parts.next()?;
+        let public_key = URL_SAFE_NO_PAD.decode(parts.next()?).ok()?;
+
+        Some(WebAuthnCredential {
+            name,
+            algorithm,
+            credential_id,
+            public_key,
+        })
+    }
+
+    pub fn from_spki(
+        name: &'x str,
+        algorithm: WebAuthnAlgorithm,
+        credential_id: &'x str,
+        spki: &[u8],
+    ) -> Option<Self> {
+        let public_key = match algorithm {
+            WebAuthnAlgorithm::Es256 => spki
+                .strip_prefix(P256_SPKI_PREFIX)
+                .filter(|key| key.len() == 65 && key[0] == 0x04)?,
+            WebAuthnAlgorithm::EdDsa => spki
+                .strip_prefix(ED25519_SPKI_PREFIX)
+                .filter(|key| key.len() == 32)?,
+        };
+
+        Some(WebAuthnCredential {
+            name,
+            algorithm,
+            credential_id,
+            public_key: public_key.to_vec(),
+        })
+    }
+
+    pub fn build(&self) -> String {
+        format!(
+            "$webauthn${}${}${}${}",
+            self.name,
+            self.algorithm.cose_id(),
+            self.credential_id,
+            URL_SAFE_NO_PAD.encode(&self.public_key)
+        )
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self.algorithm {
+            WebAuthnAlgorithm::Es256 => {
+                UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &self.public_key)
+                    .verify(message, signature)
+            }
+            WebAuthnAlgorithm::EdDsa => {
+                UnparsedPublicKey::new(&ED25519, &self.public_key).verify(message, signature)
+            }
+        }
+        .is_ok()
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains('$')
+    }
+}
+
+impl Server {
+    /// Issues a single use WebAuthn challenge for the account.
+    pub async fn webauthn_challenge(&self, account_id: u32) -> trc::Result<String> {
+        let challenge = URL_SAFE_NO_PAD.encode(rng().random::<[u8; 32]>());
+
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_WEBAUTHN_CHALLENGE,
+                    account_id.to_be_bytes(),
+                    challenge.clone().into_bytes(),
+                )
+                .expires(WEBAUTHN_CHALLENGE_EXPIRY),
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(challenge)
+    }
+
+    /// Validates a WebAuthn registration and returns the secret to store.
+    pub async fn webauthn_register(
+        &self,
+        account_id: u32,
+        name: &str,
+        credential_id: &str,
+        algorithm: i64,
+        public_key: &str,
+        client_data: &str,
+    ) -> trc::Result<String> {
+        if !WebAuthnCredential::is_valid_name(name)
+            || credential_id.is_empty()
+            || URL_SAFE_NO_PAD.decode(credential_id).is_err()
+        {
+            return Err(webauthn_error("Invalid credential name or id"));
+        }
+        self.webauthn_client_data(account_id, client_data, "webauthn.create")
+            .await?;
+
+        let algorithm = WebAuthnAlgorithm::from_cose(algorithm)
+            .ok_or_else(|| webauthn_error("Unsupported credential algorithm"))?;
+        URL_SAFE_NO_PAD
+            .decode(public_key)
+            .ok()
+            .and_then(|spki| WebAuthnCredential::from_spki(name, algorithm, credential_id, &spki))
+            .map(|credential| credential.build())
+            .ok_or_else(|| webauthn_error("Invalid credential public key"))
+    }
+
+    /// Verifies a WebAuthn assertion against the credentials of the principal.
+    pub async fn webauthn_verify(
+        &self,
+        principal: &Principal,
+        assertion: &WebAuthnAssertion,
+    ) -> trc::Result<bool> {
+        let Some(credential) = principal
+            .secrets
+            .iter()
+            .filter(|secret| secret.is_webauthn())
+            .filter_map(|secret| WebAuthnCredential::parse(secret))
+            .find(|credential| credential.credential_id == assertion.credential_id)
+        else {
+            return Ok(false);
+        };
+        let client_data = self
+            .webauthn_client_data(principal.id(), &assertion.client_data, "webauthn.get")
+            .await?;
+        let (Ok(mut message), Ok(signature)) = (
+            URL_SAFE_NO_PAD.decode(&assertion.authenticator_data),
+            URL_SAFE_NO_PAD.decode(&assertion.signature),
+        ) else {
+            return Ok(false);
+        };
+
+        // The authenticator data starts with the relying party id hash followed by
+        // the flags, where the first bit indicates that the user was present, and
+        // the signature counter
+        let rp_id_hash = Sha256::digest(self.core.jmap.two_factor.webauthn_rp_id.as_bytes());
+        if message.len() < 37 || message[..32] != rp_id_hash[..] || message[32] & 0x01 == 0 {
+            return Ok(false);
+        }
+        let sign_count = u32::from_be_bytes(message[33..37].try_into().unwrap());
+        message.extend_from_slice(&Sha256::digest(&client_data));
+        if !credential.verify(&message, &signature) {
+            return Ok(false);
+        }
+
+        // Authenticators that implement a signature counter must always increase it,
+        // otherwise the credential may have been cloned
+        let mut key = principal.id().to_be_bytes().to_vec();
+        key.extend_from_slice(credential.credential_id.as_bytes());
+        let store = self.in_memory_store();
+        let last_sign_count = store
+            .key_get::<i64>(KeyValue::<()>::build_key(KV_WEBAUTHN_SIGN_COUNT, &key))
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default() as u32;
+        if !is_valid_sign_count(last_sign_count, sign_count) {
+            return Err(webauthn_error(
+                "WebAuthn signature counter did not increase, the authenticator may have been cloned",
+            ));
+        }
+        if sign_count > last_sign_count {
+            store
+                .key_set(KeyValue::with_prefix(
+                    KV_WEBAUTHN_SIGN_COUNT,
+                    key,
+                    (sign_count as i64).to_be_bytes().to_vec(),
+                ))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(true)
+    }
+
+    async fn webauthn_client_data(
+        &self,
+        account_id: u32,
+        client_data: &str,
+        typ: &str,
+    ) -> trc::Result<Vec<u8>> {
+        // Challenges can only be used once, the lock makes sure that concurrent
+        // requests cannot consume the same challenge
+        let key = KeyValue::<()>::build_key(KV_WEBAUTHN_CHALLENGE, account_id.to_be_bytes());
+        let store = self.in_memory_store();
+        let challenge = store
+            .key_get::<String>(key.clone())
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| webauthn_error("WebAuthn challenge not found or expired"))?;
+        if !store
+            .try_lock(
+                KV_LOCK_WEBAUTHN,
+                challenge.as_bytes(),
+                WEBAUTHN_CHALLENGE_EXPIRY,
+            )
+            .await
+            .caused_by(trc::location!())?
+        {
+            return Err(webauthn_error("WebAuthn challenge not found or expired"));
+        }
+        store.key_delete(key).await.caused_by(trc::location!())?;
+
+        let client_data = URL_SAFE_NO_PAD
+            .decode(client_data)
+            .map_err(|_| webauthn_error("Invalid client data encoding"))?;
+        let parsed = serde_json::from_slice::<ClientData>(&client_data)
+            .map_err(|_| webauthn_error("Invalid client data"))?;
+        if parsed.typ != typ
+            || parsed.challenge != challenge
+            || parsed.origin != self.core.jmap.two_factor.webauthn_origin
+        {
+            return Err(webauthn_error("Client data does not match the challenge"));
+        }
+
+        Ok(client_data)
+    }
+}
+
+fn is_valid_sign_count(last_sign_count: u32, sign_count: u32) -> bool {
+    (last_sign_count == 0 && sign_count == 0) || sign_count > last_sign_count
+}
+
+fn webauthn_error(details: &'static str) -> trc::Error {
+    trc::AuthEvent::Error.into_err().details(details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webauthn_credential() {
+        let mut spki = P256_SPKI_PREFIX.to_vec();
+        spki.push(0x04);
+        spki.extend_from_slice(&[1u8; 64]);
+
+        let credential =
+            WebAuthnCredential::from_spki("yubikey", WebAuthnAlgorithm::Es256, "abc", &spki)
+                .unwrap();
+        let secret = credential.build();
+        assert!(secret.starts_with("$webauthn$yubikey$-7$abc$"));
+        assert!(secret.is_webauthn() && !secret.is_password());
+        assert_eq!(WebAuthnCredential::parse(&secret), Some(credential));
+
+        assert!(
+            WebAuthnCredential::from_spki("yubikey", WebAuthnAlgorithm::EdDsa, "abc", &spki)
+                .is_none()
+        );
+        assert_eq!(
+            WebAuthnCredential::parse("$webauthn$name$-257$abc$AAAA"),
+            None
+        );
+    }
+
+    #[test]
+    fn webauthn_sign_count() {
+        // Authenticators without a counter always report zero
+        assert!(is_valid_sign_count(0, 0));
+        assert!(is_valid_sign_count(0, 1));
+        assert!(is_valid_sign_count(41, 42));
+
+        // Counters that do not increase indicate a cloned authenticator
+        assert!(!is_valid_sign_count(42, 42));
+        assert!(!is_valid_sign_count(42, 7));
+        assert!(!is_valid_sign_count(42, 0));
+    }
+}
@@ -6,7 +6,10 @@
 
 use std::{str::FromStr, time::Duration};
 
-use utils::{config::Config, template::Template};
+use utils::{
+    config::{Config, Rate, utils::ParseValue},
+    template::Template,
+};
 
 #[derive(Debug, Clone, Default)]
 pub struct GroupwareConfig {
@@ -39,16 +42,60 @@ pub struct GroupwareConfig {
     pub itip_http_rsvp_expiration: u64,
     pub itip_inbox_auto_expunge: Option<u64>,
     pub itip_template: Template<CalendarTemplateVariable>,
+    pub freebusy_federation: bool,
+    pub freebusy_privacy: FreeBusyPrivacy,
+    pub freebusy_granularity: i64,
+    pub freebusy_max_range: i64,
+    pub booking_policy: BookingPolicy,
+    pub booking_horizon: Option<i64>,
 
     // Addressbook settings
     pub max_vcard_size: usize,
     pub default_addressbook_name: Option<String>,
     pub default_addressbook_display_name: Option<String>,
+    pub gal: Option<GalConfig>,
+    pub collect: Option<CollectConfig>,
 
     // File storage settings
     pub max_file_size: usize,
 }
 
+#[derive(Debug, Clone)]
+pub struct GalConfig {
+    pub name: String,
+    pub display_name: String,
+    pub account: Option<String>,
+    pub sync_interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct CollectConfig {
+    pub name: String,
+    pub display_name: String,
+    pub max_recipients: usize,
+    pub rate: Option<Rate>,
+}
+
+// Level of detail shared with requesters outside the tenant of an account,
+// tenants can override it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FreeBusyPrivacy {
+    Hidden,
+    #[default]
+    Busy,
+    Detailed,
+}
+
+// How invitations addressed to resource and location principals are answered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BookingPolicy {
+    #[default]
+    Manual,
+    Accept,
+    AcceptAlways,
+    Decline,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, Hash)]
 pub enum CalendarTemplateVariable {
     #[default]
@@ -109,6 +156,49 @@ impl GroupwareConfig {
                     "Stalwart Address Book",
                 )
                 .unwrap_or_default(),
+            gal: if config
+                .property("contacts.directory.enable")
+                .unwrap_or(false)
+            {
+                Some(GalConfig {
+                    name: config
+                        .value("contacts.directory.href-name")
+                        .unwrap_or("directory")
+                        .to_string(),
+                    display_name: config
+                        .value("contacts.directory.display-name")
+                        .unwrap_or("Global Address List")
+                        .to_string(),
+                    account: config
+                        .value("contacts.directory.account")
+                        .map(|v| v.to_string()),
+                    sync_interval: config
+                        .property_or_default::<Duration>("contacts.directory.sync-interval", "1h")
+                        .unwrap_or(Duration::from_secs(60 * 60)),
+                })
+            } else {
+                None
+            },
+            collect: if config.property("contacts.collect.enable").unwrap_or(false) {
+                Some(CollectConfig {
+                    name: config
+                        .value("contacts.collect.href-name")
+                        .unwrap_or("collected")
+                        .to_string(),
+                    display_name: config
+                        .value("contacts.collect.display-name")
+                        .unwrap_or("Collected Addresses")
+                        .to_string(),
+                    max_recipients: config
+                        .property("contacts.collect.max-recipients")
+                        .unwrap_or(50),
+                    rate: config
+                        .property_or_default::<Option<Rate>>("contacts.collect.rate", "500/1d")
+                        .unwrap_or_default(),
+                })
+            } else {
+                None
+            },
             max_ical_size: config.property("calendar.max-size").unwrap_or(512 * 1024),
             max_ical_instances: config
                 .property("calendar.max-recurrence-expansions")
@@ -187,10 +277,57 @@ impl GroupwareConfig {
                 "/../../resources/html-templates/calendar-invite.html.min"
             )))
             .expect("Failed to parse calendar template"),
+            freebusy_federation: config
+                .property("calendar.free-busy.federation.enable")
+                .unwrap_or(false),
+            freebusy_privacy: config
+                .property_or_default("calendar.free-busy.federation.privacy", "busy")
+                .unwrap_or_default(),
+            freebusy_granularity: config
+                .property_or_default::<Duration>("calendar.free-busy.federation.granularity", "30m")
+                .map(|d| d.as_secs().max(60) as i64)
+                .unwrap_or(30 * 60),
+            freebusy_max_range: config
+                .property_or_default::<Duration>("calendar.free-busy.federation.max-range", "60d")
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(60 * 24 * 60 * 60),
+            booking_policy: config
+                .property_or_default("calendar.scheduling.booking.policy", "manual")
+                .unwrap_or_default(),
+            booking_horizon: config
+                .property_or_default::<Option<Duration>>(
+                    "calendar.scheduling.booking.horizon",
+                    "365d",
+                )
+                .map(|d| d.map(|d| d.as_secs() as i64))
+                .unwrap_or(Some(365 * 24 * 60 * 60)),
         }
     }
 }
 
+impl ParseValue for FreeBusyPrivacy {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        hashify::tiny_map_ignore_case!(value.as_bytes(),
+            b"none" => FreeBusyPrivacy::Hidden,
+            b"busy" => FreeBusyPrivacy::Busy,
+            b"detailed" => FreeBusyPrivacy::Detailed,
+        )
+        .ok_or_else(|| format!("Invalid free/busy privacy level {value:?}"))
+    }
+}
+
+impl ParseValue for BookingPolicy {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        hashify::tiny_map_ignore_case!(value.as_bytes(),
+            b"manual" => BookingPolicy::Manual,
+            b"accept" => BookingPolicy::Accept,
+            b"accept-always" => BookingPolicy::AcceptAlways,
+            b"decline" => BookingPolicy::Decline,
+        )
+        .ok_or_else(|| format!("Invalid booking policy {value:?}"))
+    }
+}
+
 impl FromStr for CalendarTemplateVariable {
     type Err = String;
 
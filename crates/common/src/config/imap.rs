@@ -20,6 +20,10 @@ pub struct ImapConfig {
 
     pub rate_requests: Option<Rate>,
     pub rate_concurrent: Option<u64>,
+
+    pub compress_enable: bool,
+    pub compress_level: u32,
+    pub compress_buffer_size: usize,
 }
 
 impl ImapConfig {
@@ -49,6 +53,16 @@ impl ImapConfig {
             allow_plain_auth: config
                 .property_or_default("imap.auth.allow-plain-text", "false")
                 .unwrap_or(false),
+            compress_enable: config
+                .property_or_default("imap.compress.enable", "true")
+                .unwrap_or(true),
+            compress_level: config
+                .property_or_default::<u32>("imap.compress.level", "6")
+                .unwrap_or(6)
+                .min(9),
+            compress_buffer_size: config
+                .property_or_default("imap.compress.buffer-size", "65536")
+                .unwrap_or(65536),
         }
     }
 }
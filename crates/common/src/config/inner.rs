@@ -9,8 +9,11 @@ use crate::{
     CacheSwap, Caches, Data, DavResource, DavResources, MailboxCache, MessageStoreCache,
     MessageUidCache, TlsConnectors,
     auth::{AccessToken, roles::RolePermissions},
-    config::smtp::resolver::{Policy, Tlsa},
-    listener::blocked::BlockedIps,
+    config::smtp::{
+        resolver::{Policy, Tlsa},
+        session::{ScanKey, ScanVerdict},
+    },
+    listener::{blocked::BlockedIps, iplist::parse_ip_list_entries},
     manager::webadmin::WebAdminManager,
 };
 use ahash::{AHashMap, AHashSet};
@@ -32,9 +35,16 @@ use utils::{
 impl Data {
     pub fn parse(config: &mut Config) -> Self {
         // Parse certificates
-        let mut certificates = AHashMap::new();
+        let mut managed_certificates = AHashMap::new();
         let mut subject_names = AHashSet::new();
-        parse_certificates(config, &mut certificates, &mut subject_names);
+        parse_certificates(config, &mut managed_certificates, &mut subject_names);
+        let certificates = managed_certificates
+            .values()
+            .flat_map(|cert| {
+                cert.sni_names()
+                    .map(|name| (name.to_string(), cert.key.clone()))
+            })
+            .collect::<AHashMap<_, _>>();
         if subject_names.is_empty() {
             subject_names.insert("localhost".to_string());
         }
@@ -50,6 +60,7 @@ impl Data {
 
         Data {
             tls_certificates: ArcSwap::from_pointee(certificates),
+            tls_managed_certificates: ArcSwap::from_pointee(managed_certificates),
             tls_self_signed_cert: build_self_signed_cert(
                 subject_names.into_iter().collect::<Vec<_>>(),
             )
@@ -60,10 +71,20 @@ impl Data {
             .ok()
             .map(Arc::new),
             blocked_ips: RwLock::new(BlockedIps::parse(config).blocked_ip_addresses),
+            ip_lists: RwLock::new(parse_ip_list_entries(config)),
+            ip_feeds: Default::default(),
             jmap_id_gen: id_generator.clone(),
             queue_id_gen: id_generator.clone(),
             span_id_gen: id_generator,
             queue_status: true.into(),
+            smtp_maintenance: false.into(),
+            partition_health: Default::default(),
+            dnsbl_status: Default::default(),
+            filter_health: Default::default(),
+            active_accounts: Default::default(),
+            rate_limiter_tokens: Default::default(),
+            archive_chain: Default::default(),
+            tls_ticket_keys: Default::default(),
             webadmin: config
                 .value("webadmin.path")
                 .map(|path| WebAdminManager::new(path.into()))
@@ -192,6 +213,12 @@ impl Caches {
                 MB_5,
                 ((std::mem::size_of::<Ipv4Addr>() + 255) * 2) as u64,
             ),
+            antivirus: CacheWithTtl::from_config(
+                config,
+                "antivirus",
+                MB_1,
+                (std::mem::size_of::<ScanKey>() + std::mem::size_of::<ScanVerdict>() + 32) as u64,
+            ),
         }
     }
 
@@ -224,12 +251,23 @@ impl Default for Data {
     fn default() -> Self {
         Self {
             tls_certificates: Default::default(),
+            tls_managed_certificates: Default::default(),
             tls_self_signed_cert: Default::default(),
             blocked_ips: Default::default(),
+            ip_lists: Default::default(),
+            ip_feeds: Default::default(),
             jmap_id_gen: Default::default(),
             queue_id_gen: Default::default(),
             span_id_gen: Default::default(),
             queue_status: true.into(),
+            smtp_maintenance: false.into(),
+            partition_health: Default::default(),
+            dnsbl_status: Default::default(),
+            filter_health: Default::default(),
+            active_accounts: Default::default(),
+            rate_limiter_tokens: Default::default(),
+            archive_chain: Default::default(),
+            tls_ticket_keys: Default::default(),
             webadmin: Default::default(),
             logos: Default::default(),
             smtp_connectors: Default::default(),
@@ -15,6 +15,8 @@ use jmap_proto::{
 };
 use utils::{config::Config, map::vec_map::VecMap};
 
+use crate::scripts::{LIST_ADDRBOOK_DEFAULT, LIST_ADDRBOOK_GROUPS, LIST_ADDRBOOK_PERSONAL};
+
 use super::settings::JmapConfig;
 
 impl JmapConfig {
@@ -108,6 +110,10 @@ impl JmapConfig {
         }
         if notification_methods.is_empty() {
             notification_methods.push("mailto".to_string());
+            notification_methods.push("https".to_string());
+            if config.value("sieve.untrusted.notify.xmpp.url").is_some() {
+                notification_methods.push("xmpp".to_string());
+            }
         }
 
         let mut capabilities: AHashSet<sieve::compiler::grammar::Capability> =
@@ -131,12 +137,10 @@ impl JmapConfig {
             Capability::Sieve,
             Capabilities::SieveAccount(SieveAccountCapabilities {
                 max_script_name: self.sieve_max_script_name,
-                max_script_size: config
-                    .property("sieve.untrusted.max-script-size")
-                    .unwrap_or(1024 * 1024),
+                max_script_size: self.sieve_max_script_size,
                 max_scripts: self.sieve_max_scripts,
                 max_redirects: config
-                    .property("sieve.untrusted.max-redirects")
+                    .property("sieve.untrusted.limits.redirects")
                     .unwrap_or(1),
                 extensions,
                 notification_methods: if !notification_methods.is_empty() {
@@ -144,7 +148,20 @@ impl JmapConfig {
                 } else {
                     None
                 },
-                ext_lists: None,
+                ext_lists: [
+                    LIST_ADDRBOOK_DEFAULT,
+                    LIST_ADDRBOOK_PERSONAL,
+                    LIST_ADDRBOOK_GROUPS,
+                ]
+                .into_iter()
+                .map(|list| list.to_string())
+                .chain(
+                    config
+                        .values("sieve.untrusted.ext-lists")
+                        .map(|(_, list)| list.to_string()),
+                )
+                .collect::<Vec<_>>()
+                .into(),
             }),
         );
 
@@ -176,5 +193,15 @@ impl JmapConfig {
             Capability::Quota,
             Capabilities::Empty(EmptyCapabilities::default()),
         );
+
+        // Add S/MIME verification capabilities
+        self.capabilities.session.append(
+            Capability::SmimeVerify,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::SmimeVerify,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
     }
 }
@@ -8,11 +8,14 @@ use std::{str::FromStr, time::Duration};
 
 use jmap_proto::request::capability::BaseCapabilities;
 use nlp::language::Language;
+use rustls_pemfile::certs;
+use rustls_pki_types::CertificateDer;
 use utils::config::{Config, Rate, cron::SimpleCron, utils::ParseValue};
 
 #[derive(Default, Clone)]
 pub struct JmapConfig {
     pub default_language: Language,
+    pub account_language: bool,
     pub query_max_results: usize,
     pub snippet_max_results: usize,
 
@@ -41,6 +44,7 @@ pub struct JmapConfig {
     pub mail_autoexpunge_after: Option<u64>,
 
     pub sieve_max_script_name: usize,
+    pub sieve_max_script_size: usize,
     pub sieve_max_scripts: usize,
 
     pub rate_authenticated: Option<Rate>,
@@ -61,6 +65,8 @@ pub struct JmapConfig {
 
     pub fallback_admin: Option<(String, String)>,
     pub master_user: Option<(String, String)>,
+    pub two_factor: TwoFactorConfig,
+    pub impersonation: ImpersonationConfig,
 
     pub default_folders: Vec<DefaultFolder>,
     pub shared_folder: String,
@@ -70,9 +76,61 @@ pub struct JmapConfig {
 
     pub encrypt: bool,
     pub encrypt_append: bool,
+    pub signature_trust: SignatureTrust,
 
     pub capabilities: BaseCapabilities,
     pub account_purge_frequency: SimpleCron,
+    pub mailbox_digests: Vec<MailboxDigest>,
+    pub mail_retention: Vec<RetentionRule>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TwoFactorConfig {
+    pub enforce_app_passwords: bool,
+    pub trusted_device_expiry: Option<Duration>,
+    pub webauthn_rp_id: String,
+    pub webauthn_origin: String,
+}
+
+// Signatures are only reported as verified when the signer chains up to one of
+// the configured S/MIME trust anchors or uses one of the trusted OpenPGP keys.
+#[derive(Clone, Debug, Default)]
+pub struct SignatureTrust {
+    pub smime_anchors: Vec<CertificateDer<'static>>,
+    pub pgp_keys: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImpersonationConfig {
+    pub max_duration: Duration,
+    pub retention: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct MailboxDigest {
+    pub id: String,
+    pub account: String,
+    pub mailbox: String,
+    pub frequency: SimpleCron,
+    pub sla: Option<Duration>,
+    pub from_name: String,
+    pub from_address: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetentionRule {
+    pub id: String,
+    pub tenant: Option<String>,
+    pub folders: Vec<String>,
+    pub max_age: Duration,
+    pub action: RetentionAction,
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetentionAction {
+    Delete,
+    Archive,
 }
 
 #[derive(Clone, Debug)]
@@ -204,7 +262,13 @@ impl JmapConfig {
             http_headers.push((
                 hyper::header::ACCESS_CONTROL_ALLOW_HEADERS,
                 hyper::header::HeaderValue::from_static(
-                    "Authorization, Content-Type, Accept, X-Requested-With",
+                    "Authorization, Content-Type, Accept, X-Requested-With, Tus-Resumable, Upload-Length, Upload-Offset, Upload-Metadata",
+                ),
+            ));
+            http_headers.push((
+                hyper::header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                hyper::header::HeaderValue::from_static(
+                    "Location, Tus-Resumable, Tus-Version, Tus-Extension, Tus-Max-Size, Upload-Length, Upload-Offset, Upload-Expires",
                 ),
             ));
             http_headers.push((
@@ -232,6 +296,9 @@ impl JmapConfig {
                     .unwrap_or("en"),
             )
             .unwrap_or(Language::English),
+            account_language: config
+                .property("storage.full-text.account-language")
+                .unwrap_or(true),
             query_max_results: config
                 .property("jmap.protocol.query.max-results")
                 .unwrap_or(5000),
@@ -291,6 +358,9 @@ impl JmapConfig {
             sieve_max_script_name: config
                 .property("sieve.untrusted.limits.name-length")
                 .unwrap_or(512),
+            sieve_max_script_size: config
+                .property("sieve.untrusted.limits.script-size")
+                .unwrap_or(1024 * 1024),
             sieve_max_scripts: config
                 .property("sieve.untrusted.limits.max-scripts")
                 .unwrap_or(256),
@@ -322,6 +392,7 @@ impl JmapConfig {
             encrypt_append: config
                 .property_or_default("email.encryption.append", "false")
                 .unwrap_or(false),
+            signature_trust: SignatureTrust::parse(config),
             http_use_forwarded: config.property("http.use-x-forwarded").unwrap_or(false),
             http_headers,
             push_attempt_interval: config
@@ -357,8 +428,24 @@ impl JmapConfig {
                     .value("authentication.master.secret")
                     .map(|p| (u.to_string(), p.to_string()))
             }),
+            two_factor: TwoFactorConfig::parse(config),
+            impersonation: ImpersonationConfig::parse(config),
             default_folders,
             shared_folder,
+            mailbox_digests: config
+                .sub_keys("email.digest", ".mailbox")
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .filter_map(|id| MailboxDigest::parse(config, &id))
+                .collect(),
+            mail_retention: config
+                .sub_keys("email.retention", ".max-age")
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .filter_map(|id| RetentionRule::parse(config, &id))
+                .collect(),
         };
 
         // Add capabilities
@@ -367,6 +454,167 @@ impl JmapConfig {
     }
 }
 
+impl TwoFactorConfig {
+    fn parse(config: &mut Config) -> Self {
+        let webauthn_rp_id = config
+            .value("authentication.2fa.webauthn.rp-id")
+            .or_else(|| config.value("server.hostname"))
+            .unwrap_or("localhost")
+            .to_string();
+
+        TwoFactorConfig {
+            enforce_app_passwords: config
+                .property_or_default("authentication.2fa.enforce-app-passwords", "false")
+                .unwrap_or(false),
+            trusted_device_expiry: config
+                .property_or_default::<Option<Duration>>(
+                    "authentication.2fa.trusted-device.expiry",
+                    "30d",
+                )
+                .unwrap_or_else(|| Some(Duration::from_secs(30 * 86400))),
+            webauthn_origin: config
+                .value("authentication.2fa.webauthn.origin")
+                .map(|origin| origin.to_string())
+                .unwrap_or_else(|| format!("https://{webauthn_rp_id}")),
+            webauthn_rp_id,
+        }
+    }
+}
+
+impl SignatureTrust {
+    fn parse(config: &mut Config) -> Self {
+        let mut smime_anchors = Vec::new();
+        let mut errors = Vec::new();
+        for (key, pem) in config.values("email.signature.smime.trust-anchors") {
+            match certs(&mut pem.as_bytes()).collect::<Result<Vec<_>, _>>() {
+                Ok(certs) if !certs.is_empty() => {
+                    smime_anchors.extend(certs);
+                }
+                Ok(_) => {
+                    errors.push((key.to_string(), "No certificates found".to_string()));
+                }
+                Err(err) => {
+                    errors.push((
+                        key.to_string(),
+                        format!("Failed to read certificate: {err}"),
+                    ));
+                }
+            }
+        }
+        for (key, err) in errors {
+            config.new_parse_error(key, err);
+        }
+        let pgp_keys = config
+            .values("email.signature.pgp.trusted-keys")
+            .map(|(_, key)| key.as_bytes().to_vec())
+            .collect();
+
+        SignatureTrust {
+            smime_anchors,
+            pgp_keys,
+        }
+    }
+}
+
+impl ImpersonationConfig {
+    fn parse(config: &mut Config) -> Self {
+        ImpersonationConfig {
+            max_duration: config
+                .property_or_default("authentication.impersonation.max-duration", "1h")
+                .unwrap_or(Duration::from_secs(3600)),
+            retention: config
+                .property_or_default("authentication.impersonation.retention", "90d")
+                .unwrap_or(Duration::from_secs(90 * 86400)),
+        }
+    }
+}
+
+impl MailboxDigest {
+    fn parse(config: &mut Config, id: &str) -> Option<Self> {
+        let from_address = config
+            .value(("email.digest", id, "from.address"))
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| {
+                format!(
+                    "no-reply@{}",
+                    config
+                        .value("report.domain")
+                        .or_else(|| config.value("server.hostname"))
+                        .unwrap_or("localhost")
+                )
+            });
+
+        Some(MailboxDigest {
+            id: id.to_string(),
+            account: config
+                .value_require(("email.digest", id, "account"))?
+                .to_string(),
+            mailbox: config
+                .value_require(("email.digest", id, "mailbox"))?
+                .to_string(),
+            frequency: config
+                .property_or_default::<SimpleCron>(("email.digest", id, "frequency"), "0 9 *")
+                .unwrap_or_else(|| SimpleCron::parse_value("0 9 *").unwrap()),
+            sla: config.property(("email.digest", id, "sla")),
+            from_name: config
+                .value(("email.digest", id, "from.name"))
+                .unwrap_or("Mailbox Digest")
+                .to_string(),
+            from_address,
+        })
+    }
+}
+
+impl RetentionRule {
+    fn parse(config: &mut Config, id: &str) -> Option<Self> {
+        if !config
+            .property_or_default::<bool>(("email.retention", id, "enable"), "true")
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        Some(RetentionRule {
+            id: id.to_string(),
+            tenant: config
+                .value(("email.retention", id, "tenant"))
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string()),
+            folders: config
+                .values(("email.retention", id, "folders"))
+                .map(|(_, v)| v.trim().trim_matches('/').to_string())
+                .filter(|v| !v.is_empty())
+                .collect(),
+            max_age: config.property_require(("email.retention", id, "max-age"))?,
+            action: config
+                .property_or_default(("email.retention", id, "action"), "delete")
+                .unwrap_or(RetentionAction::Delete),
+            dry_run: config
+                .property_or_default(("email.retention", id, "dry-run"), "false")
+                .unwrap_or(false),
+        })
+    }
+}
+
+impl ParseValue for RetentionAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        hashify::tiny_map_ignore_case!(value.as_bytes(),
+            b"delete" => RetentionAction::Delete,
+            b"archive" => RetentionAction::Archive,
+        )
+        .ok_or_else(|| format!("Unknown retention action {:?}", value))
+    }
+}
+
+impl RetentionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetentionAction::Delete => "delete",
+            RetentionAction::Archive => "archive",
+        }
+    }
+}
+
 impl ParseValue for SpecialUse {
     fn parse_value(value: &str) -> Result<Self, String> {
         hashify::tiny_map_ignore_case!(value.as_bytes(),
@@ -5,12 +5,16 @@
  */
 
 use self::{
-    imap::ImapConfig, jmap::settings::JmapConfig, scripts::Scripting, smtp::SmtpConfig,
-    storage::Storage,
+    imap::ImapConfig,
+    jmap::settings::JmapConfig,
+    scripts::Scripting,
+    smtp::SmtpConfig,
+    storage::{Storage, StoragePartitions},
 };
 use crate::{
     Core, Network, Security, auth::oauth::config::OAuthConfig, expr::*,
     listener::tls::AcmeProviders, manager::config::ConfigManager,
+    storage::extract::TextExtractors,
 };
 use arc_swap::ArcSwap;
 use base64::{Engine, engine::general_purpose};
@@ -20,6 +24,7 @@ use hyper::{
     HeaderMap,
     header::{AUTHORIZATION, HeaderName, HeaderValue},
 };
+use push_gateway::PushGatewayConfig;
 use ring::signature::{EcdsaKeyPair, RsaKeyPair};
 use spamfilter::SpamFilterConfig;
 use std::{str::FromStr, sync::Arc};
@@ -32,6 +37,7 @@ pub mod imap;
 pub mod inner;
 pub mod jmap;
 pub mod network;
+pub mod push_gateway;
 pub mod scripts;
 pub mod server;
 pub mod smtp;
@@ -188,11 +194,26 @@ impl Core {
             )
         }
 
+        let partitions = StoragePartitions::parse(config, &stores);
+
+        // Volatile data stores are only suitable for relays that queue and forward
+        let mut network = Network::parse(config);
+        if data.is_volatile() {
+            network.roles.serve_users = false;
+            config.new_build_warning(
+                "storage.data",
+                concat!(
+                    "Data store is volatile, mailbox access and local delivery ",
+                    "are disabled on this node"
+                ),
+            );
+        }
+
         Self {
             #[cfg(feature = "enterprise")]
             enterprise,
             sieve: Scripting::parse(config, &stores).await,
-            network: Network::parse(config),
+            network,
             smtp: SmtpConfig::parse(config).await,
             jmap: JmapConfig::parse(config),
             imap: ImapConfig::parse(config),
@@ -201,6 +222,7 @@ impl Core {
             metrics: Metrics::parse(config),
             spam: SpamFilterConfig::parse(config).await,
             groupware: GroupwareConfig::parse(config),
+            push_gateway: PushGatewayConfig::parse(config),
             storage: Storage {
                 data,
                 blob,
@@ -215,6 +237,8 @@ impl Core {
                 lookups: stores.in_memory_stores,
                 blobs: stores.blob_stores,
                 ftss: stores.fts_stores,
+                partitions,
+                extractors: TextExtractors::parse(config),
             },
         }
     }
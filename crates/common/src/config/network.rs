@@ -6,10 +6,13 @@
 
 use std::time::Duration;
 
-use crate::expr::{if_block::IfBlock, tokenizer::TokenMap};
-use ahash::AHashSet;
+use crate::{
+    expr::{if_block::IfBlock, tokenizer::TokenMap},
+    listener::iplist::{IpList, parse_ip_lists},
+};
+use ahash::{AHashMap, AHashSet};
 
-use utils::config::{Config, Rate};
+use utils::config::{Config, Rate, utils::ParseValue};
 
 use super::*;
 
@@ -20,10 +23,51 @@ pub struct Network {
     pub server_name: String,
     pub report_domain: String,
     pub security: Security,
+    pub ip_lists: AHashMap<String, IpList>,
     pub contact_form: Option<ContactForm>,
     pub http_response_url: IfBlock,
     pub http_allowed_endpoint: IfBlock,
+    pub client_cert_principal: IfBlock,
     pub asn_geo_lookup: AsnGeoLookupConfig,
+    pub cache_warm_up: Option<CacheWarmUp>,
+    pub grpc: Option<GrpcConfig>,
+    pub audit: Option<AuditLog>,
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    pub retention: Option<Duration>,
+    pub export: Option<AuditExport>,
+}
+
+#[derive(Clone)]
+pub struct AuditExport {
+    pub address: String,
+    pub format: AuditFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditFormat {
+    Syslog,
+    Cef,
+}
+
+#[derive(Clone)]
+pub struct GrpcConfig {
+    pub max_message_size: usize,
+    pub max_recipients: usize,
+    pub dkim_sign: bool,
+}
+
+#[derive(Clone)]
+pub struct CacheWarmUp {
+    pub delay: Duration,
+    pub snapshot_interval: Duration,
+    pub window: Duration,
+    pub max_accounts: usize,
+    pub concurrency: usize,
+    pub dns: bool,
+    pub dkim: bool,
 }
 
 #[derive(Clone)]
@@ -45,6 +89,9 @@ pub struct ClusterRoles {
     pub renew_acme: bool,
     pub calculate_metrics: bool,
     pub push_metrics: bool,
+    pub deliver_queue: bool,
+    pub serve_users: bool,
+    pub queue_nodes: Vec<u64>,
 }
 
 #[derive(Clone, Default)]
@@ -89,10 +136,22 @@ pub(crate) const HTTP_VARS: &[u32; 11] = &[
     V_METHOD,
 ];
 
+pub(crate) const CLIENT_CERT_VARS: &[u32; 8] = &[
+    V_LISTENER,
+    V_REMOTE_IP,
+    V_PROTOCOL,
+    V_CERT_SUBJECT,
+    V_CERT_ISSUER,
+    V_CERT_CN,
+    V_CERT_EMAIL,
+    V_CERT_DNS,
+];
+
 impl Default for Network {
     fn default() -> Self {
         Self {
             security: Default::default(),
+            ip_lists: Default::default(),
             contact_form: None,
             node_id: 1,
             http_response_url: IfBlock::new::<()>(
@@ -101,7 +160,15 @@ impl Default for Network {
                 "protocol + '://' + config_get('server.hostname') + ':' + local_port",
             ),
             http_allowed_endpoint: IfBlock::new::<()>("http.allowed-endpoint", [], "200"),
+            client_cert_principal: IfBlock::new::<()>(
+                "authentication.client-cert.principal",
+                [("!is_empty(cert_email)", "cert_email")],
+                "cert_cn",
+            ),
             asn_geo_lookup: AsnGeoLookupConfig::Disabled,
+            cache_warm_up: None,
+            grpc: None,
+            audit: None,
             server_name: Default::default(),
             report_domain: Default::default(),
             roles: ClusterRoles {
@@ -110,6 +177,9 @@ impl Default for Network {
                 renew_acme: true,
                 calculate_metrics: true,
                 push_metrics: true,
+                deliver_queue: true,
+                serve_users: true,
+                queue_nodes: vec![],
             },
         }
     }
@@ -203,13 +273,19 @@ impl Network {
             report_domain,
             server_name,
             security: Security::parse(config),
+            ip_lists: parse_ip_lists(config),
             contact_form: ContactForm::parse(config),
             asn_geo_lookup: AsnGeoLookupConfig::parse(config).unwrap_or_default(),
+            cache_warm_up: CacheWarmUp::parse(config),
+            grpc: GrpcConfig::parse(config),
+            audit: AuditLog::parse(config),
             ..Default::default()
         };
         let token_map = &TokenMap::default().with_variables(HTTP_VARS);
 
-        // Node roles
+        // Node roles, housekeeping tasks default to the nodes
+        // listed as housekeepers when not assigned individually
+        let housekeeper_ids = node_ids(config, "cluster.roles.housekeeper");
         for (value, key) in [
             (
                 &mut network.roles.purge_stores,
@@ -229,16 +305,29 @@ impl Network {
                 "cluster.roles.metrics.push",
             ),
         ] {
-            let node_ids = config
-                .properties::<u64>(key)
-                .into_iter()
-                .map(|(_, v)| v)
-                .collect::<AHashSet<_>>();
-            if !node_ids.is_empty() && !node_ids.contains(&network.node_id) {
+            let mut ids = node_ids(config, key);
+            if ids.is_empty() {
+                ids = housekeeper_ids.clone();
+            }
+            if !ids.is_empty() && !ids.contains(&network.node_id) {
                 *value = false;
             }
         }
 
+        // Nodes serving user protocols
+        let frontend_ids = node_ids(config, "cluster.roles.frontend");
+        network.roles.serve_users =
+            frontend_ids.is_empty() || frontend_ids.contains(&network.node_id);
+
+        // Nodes sharing the outbound queue
+        let mut queue_nodes = node_ids(config, "cluster.roles.queue")
+            .into_iter()
+            .collect::<Vec<_>>();
+        queue_nodes.sort_unstable();
+        network.roles.deliver_queue =
+            queue_nodes.is_empty() || queue_nodes.contains(&network.node_id);
+        network.roles.queue_nodes = queue_nodes;
+
         for (value, key) in [
             (&mut network.http_response_url, "http.url"),
             (&mut network.http_allowed_endpoint, "http.allowed-endpoint"),
@@ -247,11 +336,121 @@ impl Network {
                 *value = if_block;
             }
         }
+        if let Some(if_block) = IfBlock::try_parse(
+            config,
+            "authentication.client-cert.principal",
+            &TokenMap::default().with_variables(CLIENT_CERT_VARS),
+        ) {
+            network.client_cert_principal = if_block;
+        }
 
         network
     }
 }
 
+fn node_ids(config: &mut Config, key: &str) -> AHashSet<u64> {
+    config
+        .properties::<u64>(key)
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect()
+}
+
+impl GrpcConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default::<bool>("http.grpc.enable", "false")
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        Some(GrpcConfig {
+            max_message_size: config
+                .property_or_default("http.grpc.max-message-size", "52428800")
+                .unwrap_or(52428800),
+            max_recipients: config
+                .property_or_default("http.grpc.max-recipients", "1000")
+                .unwrap_or(1000),
+            dkim_sign: config
+                .property_or_default("http.grpc.dkim-sign", "true")
+                .unwrap_or(true),
+        })
+    }
+}
+
+impl AuditLog {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default::<bool>("audit.enable", "true")
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        Some(AuditLog {
+            retention: config
+                .property_or_default::<Option<Duration>>("audit.retention", "false")
+                .unwrap_or_default(),
+            export: config
+                .value("audit.export.address")
+                .map(|address| address.to_string())
+                .map(|address| AuditExport {
+                    address,
+                    format: config
+                        .property_or_default("audit.export.format", "syslog")
+                        .unwrap_or(AuditFormat::Syslog),
+                }),
+        })
+    }
+}
+
+impl ParseValue for AuditFormat {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "syslog" => Ok(AuditFormat::Syslog),
+            "cef" => Ok(AuditFormat::Cef),
+            _ => Err(format!("Invalid audit export format {:?}.", value)),
+        }
+    }
+}
+
+impl CacheWarmUp {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default::<bool>("cache.warm-up.enable", "true")
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        Some(CacheWarmUp {
+            delay: config
+                .property_or_default("cache.warm-up.delay", "5s")
+                .unwrap_or_else(|| Duration::from_secs(5)),
+            snapshot_interval: config
+                .property_or_default("cache.warm-up.snapshot-interval", "15m")
+                .unwrap_or_else(|| Duration::from_secs(15 * 60)),
+            window: config
+                .property_or_default("cache.warm-up.accounts.window", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
+            max_accounts: config
+                .property_or_default("cache.warm-up.accounts.max", "1000")
+                .unwrap_or(1000),
+            concurrency: config
+                .property_or_default::<usize>("cache.warm-up.concurrency", "4")
+                .unwrap_or(4)
+                .max(1),
+            dns: config
+                .property_or_default("cache.warm-up.dns", "true")
+                .unwrap_or(true),
+            dkim: config
+                .property_or_default("cache.warm-up.dkim", "true")
+                .unwrap_or(true),
+        })
+    }
+}
+
 impl AsnGeoLookupConfig {
     pub fn parse(config: &mut Config) -> Option<Self> {
         match config.value("asn.type")? {
@@ -0,0 +1,203 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use biscuit::{
+    ClaimsSet, JWT, RegisteredClaims, SingleOrMultiple,
+    jwa::SignatureAlgorithm,
+    jws::{RegisteredHeader, Secret},
+};
+use serde::{Deserialize, Serialize};
+use store::write::now;
+use utils::config::Config;
+use x509_parser::{certificate::X509Certificate, der_parser::asn1_rs::FromDer};
+
+use super::build_rsa_keypair;
+
+// Object identifier of the userId attribute, Apple issues the Mail push
+// certificates with the APNs topic stored in this attribute.
+const OID_USER_ID: &str = "0.9.2342.19200300.100.1.1";
+const FCM_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+
+#[derive(Clone, Default)]
+pub struct PushGatewayConfig {
+    pub apns: Option<ApnsConfig>,
+    pub fcm: Option<FcmConfig>,
+    pub unified_push: bool,
+    pub preview: bool,
+    pub timeout: Duration,
+    pub device_expiry: u64,
+    pub max_devices: usize,
+}
+
+#[derive(Clone)]
+pub struct ApnsConfig {
+    pub url: String,
+    pub topic: String,
+    pub identity: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct FcmConfig {
+    pub url: String,
+    pub token_uri: String,
+    pub client_email: String,
+    pub secret: Secret,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FcmClaims {
+    scope: String,
+}
+
+impl PushGatewayConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        PushGatewayConfig {
+            apns: ApnsConfig::parse(config),
+            fcm: FcmConfig::parse(config),
+            unified_push: config
+                .property_or_default("push-gateway.unified-push.enable", "false")
+                .unwrap_or(false),
+            preview: config
+                .property_or_default("push-gateway.preview", "true")
+                .unwrap_or(true),
+            timeout: config
+                .property_or_default("push-gateway.timeout", "10s")
+                .unwrap_or_else(|| Duration::from_secs(10)),
+            device_expiry: config
+                .property_or_default::<Duration>("push-gateway.device-expiry", "30d")
+                .unwrap_or_else(|| Duration::from_secs(30 * 86400))
+                .as_secs(),
+            max_devices: config
+                .property_or_default("push-gateway.max-devices", "10")
+                .unwrap_or(10),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.apns.is_some() || self.fcm.is_some() || self.unified_push
+    }
+}
+
+impl ApnsConfig {
+    fn parse(config: &mut Config) -> Option<Self> {
+        let cert = config.value("push-gateway.apns.cert")?.to_string();
+        let key = config
+            .value_require("push-gateway.apns.private-key")?
+            .to_string();
+
+        // APNs authenticates Apple Mail providers using the certificate it issued
+        let mut identity = Vec::with_capacity(cert.len() + key.len() + 1);
+        identity.extend_from_slice(cert.as_bytes());
+        identity.push(b'\n');
+        identity.extend_from_slice(key.as_bytes());
+        if let Err(err) = reqwest::Identity::from_pem(&identity) {
+            config.new_build_error(
+                "push-gateway.apns.cert",
+                format!("Failed to build APNs client identity: {err}"),
+            );
+            return None;
+        }
+
+        let topic = match config.value("push-gateway.apns.topic") {
+            Some(topic) => topic.to_string(),
+            None => match certificate_topic(&cert) {
+                Some(topic) => topic,
+                None => {
+                    config.new_build_error(
+                        "push-gateway.apns.topic",
+                        "Missing APNs topic and the certificate does not contain one",
+                    );
+                    return None;
+                }
+            },
+        };
+
+        Some(ApnsConfig {
+            url: config
+                .value("push-gateway.apns.url")
+                .unwrap_or("https://api.push.apple.com")
+                .trim_end_matches('/')
+                .to_string(),
+            topic,
+            identity,
+        })
+    }
+}
+
+impl FcmConfig {
+    fn parse(config: &mut Config) -> Option<Self> {
+        let project_id = config.value("push-gateway.fcm.project-id")?.to_string();
+        let client_email = config
+            .value_require("push-gateway.fcm.client-email")?
+            .to_string();
+        let secret = match build_rsa_keypair(config.value_require("push-gateway.fcm.private-key")?)
+        {
+            Ok(key) => Secret::RsaKeyPair(Arc::new(key)),
+            Err(err) => {
+                config.new_build_error(
+                    "push-gateway.fcm.private-key",
+                    format!("Failed to build FCM service account key: {err}"),
+                );
+                return None;
+            }
+        };
+
+        Some(FcmConfig {
+            url: format!("https://fcm.googleapis.com/v1/projects/{project_id}/messages:send"),
+            token_uri: config
+                .value("push-gateway.fcm.token-uri")
+                .unwrap_or("https://oauth2.googleapis.com/token")
+                .to_string(),
+            client_email,
+            secret,
+        })
+    }
+
+    /// Builds the signed assertion that is exchanged for an OAuth access
+    /// token using the service account credentials.
+    pub fn assertion(&self, expiry: u64) -> trc::Result<String> {
+        let now = now() as i64;
+
+        JWT::new_decoded(
+            From::from(RegisteredHeader {
+                algorithm: SignatureAlgorithm::RS256,
+                ..Default::default()
+            }),
+            ClaimsSet::<FcmClaims> {
+                registered: RegisteredClaims {
+                    issuer: Some(self.client_email.clone()),
+                    audience: Some(SingleOrMultiple::Single(self.token_uri.clone())),
+                    issued_at: Some(now.into()),
+                    expiry: Some((now + expiry as i64).into()),
+                    ..Default::default()
+                },
+                private: FcmClaims {
+                    scope: FCM_SCOPE.to_string(),
+                },
+            },
+        )
+        .into_encoded(&self.secret)
+        .map(|token| token.unwrap_encoded().to_string())
+        .map_err(|err| {
+            trc::PushSubscriptionEvent::Error
+                .into_err()
+                .reason(err)
+                .details("Failed to encode FCM assertion")
+        })
+    }
+}
+
+fn certificate_topic(pem: &str) -> Option<String> {
+    let der = rustls_pemfile::certs(&mut pem.as_bytes()).next()?.ok()?;
+    let (_, cert) = X509Certificate::from_der(der.as_ref()).ok()?;
+    cert.subject()
+        .iter_attributes()
+        .find(|attr| attr.attr_type().to_id_string() == OID_USER_ID)
+        .and_then(|attr| attr.as_str().ok())
+        .map(|topic| topic.to_string())
+}
@@ -6,14 +6,16 @@
 
 use std::{sync::Arc, time::Duration};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
+use base64::{Engine, engine::general_purpose::STANDARD};
 use sieve::{Compiler, Runtime, Sieve, compiler::grammar::Capability};
 use store::Stores;
-use utils::config::Config;
+use utils::config::{Config, utils::ParseValue};
 
 use crate::{
     VERSION_PUBLIC,
     scripts::{
+        LIST_ADDRBOOK_DEFAULT, LIST_ADDRBOOK_GROUPS, LIST_ADDRBOOK_PERSONAL,
         functions::{register_functions_trusted, register_functions_untrusted},
         plugins::RegisterSievePlugins,
     },
@@ -31,10 +33,48 @@ pub struct Scripting {
     pub sign: IfBlock,
     pub trusted_scripts: AHashMap<String, Arc<Sieve>>,
     pub untrusted_scripts: AHashMap<String, Arc<Sieve>>,
+    pub untrusted_lists: AHashSet<String>,
+    pub vacation_calendar: bool,
+    pub notify: SieveNotify,
+    pub forward_external: ForwardPolicy,
+}
+
+// Policy applied to redirects to external domains, tenants can override it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForwardPolicy {
+    #[default]
+    Allow,
+    Deny,
+    Approval,
+}
+
+#[derive(Clone, Debug)]
+pub struct SieveNotify {
+    pub timeout: Duration,
+    pub max_notifications: usize,
+    pub allowed_hosts: AHashSet<String>,
+    pub xmpp_url: Option<String>,
+    pub xmpp_auth: Option<String>,
 }
 
 impl Scripting {
     pub async fn parse(config: &mut Config, stores: &Stores) -> Self {
+        let notify = SieveNotify::parse(config);
+
+        // Parse lookup stores available to untrusted scripts
+        let mut untrusted_lists = AHashSet::new();
+        for (_, id) in config.values("sieve.untrusted.ext-lists") {
+            untrusted_lists.insert(id.to_string());
+        }
+        for id in &untrusted_lists {
+            if !stores.in_memory_stores.contains_key(id) {
+                config.new_build_error(
+                    "sieve.untrusted.ext-lists",
+                    format!("In-memory store {id:?} not found"),
+                );
+            }
+        }
+
         // Parse untrusted compiler
         let mut fnc_map_untrusted = register_functions_untrusted().register_plugins_untrusted();
         let untrusted_compiler = Compiler::new()
@@ -153,8 +193,14 @@ impl Scripting {
                     .collect::<Vec<_>>();
                 if !values.is_empty() {
                     values
+                } else if notify.xmpp_url.is_some() {
+                    vec![
+                        "mailto".to_string(),
+                        "https".to_string(),
+                        "xmpp".to_string(),
+                    ]
                 } else {
-                    vec!["mailto".to_string()]
+                    vec!["mailto".to_string(), "https".to_string()]
                 }
             })
             .with_protected_headers({
@@ -173,6 +219,16 @@ impl Scripting {
                     ]
                 }
             })
+            .with_valid_ext_lists(
+                [
+                    LIST_ADDRBOOK_DEFAULT,
+                    LIST_ADDRBOOK_PERSONAL,
+                    LIST_ADDRBOOK_GROUPS,
+                ]
+                .into_iter()
+                .map(|list| list.to_string())
+                .chain(untrusted_lists.iter().cloned()),
+            )
             .with_vacation_default_subject(
                 config
                     .value("sieve.untrusted.vacation.default-subject")
@@ -221,7 +277,6 @@ impl Scripting {
                 Capability::MboxMetadata,
                 Capability::ServerMetadata,
                 Capability::ImapSieve,
-                Capability::Duplicate,
             ])
             .with_capability(Capability::Expressions)
             .with_capability(Capability::While)
@@ -352,7 +407,56 @@ impl Scripting {
                 },
             ),
             untrusted_scripts,
+            untrusted_lists,
             trusted_scripts,
+            vacation_calendar: config
+                .property("sieve.untrusted.vacation.calendar-aware")
+                .unwrap_or(false),
+            notify,
+            forward_external: config
+                .property_or_default("sieve.untrusted.forward.external", "allow")
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl SieveNotify {
+    pub fn parse(config: &mut Config) -> Self {
+        SieveNotify {
+            timeout: config
+                .property_or_default("sieve.untrusted.notify.timeout", "10s")
+                .unwrap_or_else(|| Duration::from_secs(10)),
+            max_notifications: config
+                .property_or_default("sieve.untrusted.notify.max-notifications", "5")
+                .unwrap_or(5),
+            allowed_hosts: config
+                .values("sieve.untrusted.notify.https.allowed-hosts")
+                .map(|(_, v)| v.to_lowercase())
+                .collect(),
+            xmpp_url: config
+                .value("sieve.untrusted.notify.xmpp.url")
+                .map(|v| v.to_string()),
+            xmpp_auth: match (
+                config.value("sieve.untrusted.notify.xmpp.auth.username"),
+                config.value("sieve.untrusted.notify.xmpp.auth.secret"),
+            ) {
+                (Some(name), Some(secret)) => {
+                    format!("Basic {}", STANDARD.encode(format!("{name}:{secret}"))).into()
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Default for SieveNotify {
+    fn default() -> Self {
+        SieveNotify {
+            timeout: Duration::from_secs(10),
+            max_notifications: 5,
+            allowed_hosts: AHashSet::new(),
+            xmpp_url: None,
+            xmpp_auth: None,
         }
     }
 }
@@ -379,7 +483,11 @@ impl Default for Scripting {
                 ),
             ),
             untrusted_scripts: AHashMap::new(),
+            untrusted_lists: AHashSet::new(),
             trusted_scripts: AHashMap::new(),
+            vacation_calendar: false,
+            notify: SieveNotify::default(),
+            forward_external: ForwardPolicy::Allow,
         }
     }
 }
@@ -396,6 +504,20 @@ impl Clone for Scripting {
             sign: self.sign.clone(),
             trusted_scripts: self.trusted_scripts.clone(),
             untrusted_scripts: self.untrusted_scripts.clone(),
+            untrusted_lists: self.untrusted_lists.clone(),
+            vacation_calendar: self.vacation_calendar,
+            notify: self.notify.clone(),
         }
     }
 }
+
+impl ParseValue for ForwardPolicy {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        hashify::tiny_map_ignore_case!(value.as_bytes(),
+            b"allow" => ForwardPolicy::Allow,
+            b"deny" => ForwardPolicy::Deny,
+            b"approval" => ForwardPolicy::Approval,
+        )
+        .ok_or_else(|| format!("Invalid forward policy {value:?}"))
+    }
+}
@@ -4,12 +4,17 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{io::Cursor, net::SocketAddr, sync::Arc, time::Duration};
 
 use rustls::{
-    ALL_VERSIONS, ServerConfig, SupportedCipherSuite,
-    crypto::ring::{ALL_CIPHER_SUITES, default_provider},
+    ALL_VERSIONS, RootCertStore, ServerConfig, SupportedCipherSuite,
+    crypto::{
+        CryptoProvider,
+        ring::{ALL_CIPHER_SUITES, default_provider},
+    },
+    server::{NoServerSessionStorage, WebPkiClientVerifier, danger::ClientCertVerifier},
 };
+use rustls_pemfile::certs;
 
 use tokio::net::TcpSocket;
 use tokio_rustls::TlsAcceptor;
@@ -23,7 +28,7 @@ use utils::{
 
 use crate::{
     Inner,
-    listener::{TcpAcceptor, tls::CertificateResolver},
+    listener::{AuthPolicy, TcpAcceptor, ticket::ClusterTicketer, tls::CertificateResolver},
 };
 
 use super::{
@@ -196,6 +201,26 @@ impl Listeners {
             proxy_networks.push(network);
         }
 
+        // Parse authentication policy
+        let min_tls_version = match config
+            .value(("server.listener", id, "auth.min-tls-version"))
+            .map(|version| version.to_string())
+            .as_deref()
+        {
+            Some("TLSv1.2" | "0x0303") => Some(0x0303),
+            Some("TLSv1.3" | "0x0304") => Some(0x0304),
+            Some(version) => {
+                let message = format!("Unsupported TLS protocol {version:?}");
+                config.new_parse_error(("server.listener", id, "auth.min-tls-version"), message);
+                None
+            }
+            None => None,
+        };
+        let auth_policy = AuthPolicy {
+            allow_plain_text: config.property(("server.listener", id, "auth.allow-plain-text")),
+            min_tls_version,
+        };
+
         let span_id_gen = self.span_id_gen.clone();
         self.servers.push(Listener {
             max_connections: config
@@ -210,6 +235,7 @@ impl Listeners {
             listeners,
             proxy_networks,
             span_id_gen,
+            auth_policy,
         });
     }
 
@@ -274,7 +300,9 @@ impl Listeners {
                 }
 
                 // Build server config
-                let mut server_config = match ServerConfig::builder_with_provider(provider.into())
+                let provider = Arc::new(provider);
+                let client_verifier = build_client_verifier(config, id, provider.clone());
+                let mut server_config = match ServerConfig::builder_with_provider(provider)
                     .with_protocol_versions(if tls_v3 == tls_v2 {
                         ALL_VERSIONS
                     } else if tls_v3 {
@@ -282,9 +310,11 @@ impl Listeners {
                     } else {
                         TLS12_VERSION
                     }) {
-                    Ok(server_config) => server_config
-                        .with_no_client_auth()
-                        .with_cert_resolver(resolver.clone()),
+                    Ok(server_config) => match client_verifier {
+                        Some(verifier) => server_config.with_client_cert_verifier(verifier),
+                        None => server_config.with_no_client_auth(),
+                    }
+                    .with_cert_resolver(resolver.clone()),
                     Err(err) => {
                         config.new_build_error(
                             ("server.listener", id, "tls"),
@@ -302,6 +332,43 @@ impl Listeners {
                     )
                     .unwrap_or(true);
 
+                // Application protocols offered during the handshake
+                server_config.alpn_protocols = config
+                    .values(("server.listener", id, "tls.alpn"))
+                    .map(|(_, protocol)| protocol.as_bytes().to_vec())
+                    .collect();
+
+                // Session resumption using cluster-wide ticket keys
+                if config
+                    .property_or_else(
+                        ("server.listener", id, "tls.resumption.enable"),
+                        "server.tls.resumption.enable",
+                        "true",
+                    )
+                    .unwrap_or(true)
+                {
+                    server_config.ticketer = Arc::new(ClusterTicketer {
+                        keys: inner.data.tls_ticket_keys.clone(),
+                        lifetime: config
+                            .property_or_else(
+                                ("server.listener", id, "tls.resumption.lifetime"),
+                                "server.tls.resumption.lifetime",
+                                "12h",
+                            )
+                            .unwrap_or_else(|| Duration::from_secs(12 * 3600)),
+                        rotate: config
+                            .property_or_else(
+                                ("server.listener", id, "tls.resumption.rotate"),
+                                "server.tls.resumption.rotate",
+                                "1h",
+                            )
+                            .unwrap_or_else(|| Duration::from_secs(3600)),
+                    });
+                } else {
+                    server_config.session_storage = Arc::new(NoServerSessionStorage {});
+                    server_config.send_tls13_tickets = 0;
+                }
+
                 // Build acceptor
                 let default_config = Arc::new(server_config);
                 TcpAcceptor::Tls {
@@ -320,6 +387,59 @@ impl Listeners {
     }
 }
 
+fn build_client_verifier(
+    config: &mut Config,
+    id: &str,
+    provider: Arc<CryptoProvider>,
+) -> Option<Arc<dyn ClientCertVerifier>> {
+    // Client certificates are only requested when CA bundles are configured
+    let mut roots = RootCertStore::empty();
+    for (key, bundle) in config
+        .values(("server.listener", id, "tls.client-auth.ca"))
+        .map(|(key, bundle)| (key.to_string(), bundle.as_bytes().to_vec()))
+        .collect::<Vec<_>>()
+    {
+        match certs(&mut Cursor::new(bundle)).collect::<Result<Vec<_>, _>>() {
+            Ok(ca_certs) if !ca_certs.is_empty() => {
+                let (_, ignored) = roots.add_parsable_certificates(ca_certs);
+                if ignored > 0 {
+                    config.new_build_warning(
+                        key,
+                        format!("Ignored {ignored} invalid CA certificates"),
+                    );
+                }
+            }
+            Ok(_) => config.new_parse_error(key, "No CA certificates found"),
+            Err(err) => {
+                config.new_parse_error(key, format!("Failed to read CA certificates: {err}"))
+            }
+        }
+    }
+    if roots.is_empty() {
+        return None;
+    }
+
+    // Clients without a certificate may still authenticate by other means
+    let mut builder = WebPkiClientVerifier::builder_with_provider(roots.into(), provider);
+    if !config
+        .property_or_default(("server.listener", id, "tls.client-auth.required"), "false")
+        .unwrap_or(false)
+    {
+        builder = builder.allow_unauthenticated();
+    }
+
+    match builder.build() {
+        Ok(verifier) => Some(verifier),
+        Err(err) => {
+            config.new_build_error(
+                ("server.listener", id, "tls.client-auth"),
+                format!("Failed to build client certificate verifier: {err}"),
+            );
+            None
+        }
+    }
+}
+
 impl ParseValue for ServerProtocol {
     fn parse_value(value: &str) -> Result<Self, String> {
         if value.eq_ignore_ascii_case("smtp") {
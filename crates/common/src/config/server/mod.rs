@@ -11,7 +11,7 @@ use serde::{Deserialize, Serialize};
 use tokio::net::TcpSocket;
 use utils::{config::ipmask::IpAddrMask, snowflake::SnowflakeIdGenerator};
 
-use crate::listener::TcpAcceptor;
+use crate::listener::{AuthPolicy, TcpAcceptor};
 
 pub mod listener;
 pub mod tls;
@@ -31,6 +31,7 @@ pub struct Listener {
     pub proxy_networks: Vec<IpAddrMask>,
     pub max_connections: u64,
     pub span_id_gen: Arc<SnowflakeIdGenerator>,
+    pub auth_policy: AuthPolicy,
 }
 
 #[derive(Debug)]
@@ -6,8 +6,7 @@
 
 use std::{
     io::Cursor,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
-    sync::Arc,
+    net::{IpAddr, SocketAddr},
     time::Duration,
 };
 
@@ -17,6 +16,10 @@ use base64::{
     engine::general_purpose::{self, STANDARD},
 };
 use dns_update::{DnsUpdater, TsigAlgorithm, providers::rfc2136::DnsAddress};
+use hickory_proto::rr::{
+    Name,
+    dnssec::{rdata::tsig, tsig::TSigner},
+};
 use rcgen::generate_simple_self_signed;
 use rustls::{
     SupportedProtocolVersion,
@@ -27,16 +30,15 @@ use rustls::{
 use rustls_pemfile::{Item, certs, read_one};
 use rustls_pki_types::PrivateKeyDer;
 use utils::config::Config;
-use x509_parser::{
-    certificate::X509Certificate,
-    der_parser::asn1_rs::FromDer,
-    extensions::{GeneralName, ParsedExtension},
-};
 
 use crate::listener::{
     acme::{
-        AcmeProvider, ChallengeSettings, EabSettings, directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+        AcmeProvider, ChallengeSettings, EabSettings,
+        directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+        provider::{ChallengeUpdater, DnsProvider},
+        tlsa::TlsaSettings,
     },
+    certificate::{CertificateFiles, ManagedCertificate},
     tls::AcmeProviders,
 };
 
@@ -163,6 +165,16 @@ impl AcmeProviders {
                 .property::<bool>(("acme", acme_id, "default"))
                 .unwrap_or_default();
 
+            // Automatic TLSA record maintenance
+            let tlsa = if config
+                .property_or_default::<bool>(("acme", acme_id, "tlsa.enable"), "false")
+                .unwrap_or_default()
+            {
+                build_tlsa_settings(config, acme_id, &domains)
+            } else {
+                None
+            };
+
             if !domains.is_empty() {
                 match AcmeProvider::new(
                     acme_id.to_string(),
@@ -171,6 +183,7 @@ impl AcmeProviders {
                     contact,
                     challenge,
                     eab,
+                    tlsa,
                     renew_before,
                     default,
                 ) {
@@ -189,7 +202,7 @@ impl AcmeProviders {
 }
 
 #[allow(clippy::unnecessary_to_owned)]
-fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
+fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<ChallengeUpdater> {
     match config.value_require(("acme", acme_id, "provider"))? {
         "rfc2136-tsig" => {
             let algorithm: TsigAlgorithm = config
@@ -234,6 +247,7 @@ fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
                 )
             })
             .ok()
+            .map(ChallengeUpdater::DnsUpdate)
         }
         "cloudflare" => {
             let timeout = config
@@ -255,7 +269,9 @@ fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
                 )
             })
             .ok()
+            .map(ChallengeUpdater::DnsUpdate)
         }
+        "route53" => build_dns_provider(config, acme_id, "").map(ChallengeUpdater::Provider),
         _ => {
             config.new_parse_error(("acme", acme_id, "provider"), "Unsupported provider");
             None
@@ -263,105 +279,223 @@ fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
     }
 }
 
+// Parses the settings of a DNS provider, keys are relative to "acme.<id>.<prefix>"
+fn build_dns_provider(config: &mut Config, acme_id: &str, prefix: &str) -> Option<DnsProvider> {
+    let key = |name: &str| format!("{prefix}{name}");
+    let timeout = config
+        .property_or_default(("acme", acme_id, key("timeout").as_str()), "30s")
+        .unwrap_or_else(|| Duration::from_secs(30));
+    let updater = match config.value_require(("acme", acme_id, key("provider").as_str()))? {
+        "rfc2136-tsig" => {
+            let algorithm = match config
+                .value(("acme", acme_id, key("tsig-algorithm").as_str()))
+                .unwrap_or("hmac-sha256")
+            {
+                "hmac-sha256" => tsig::TsigAlgorithm::HmacSha256,
+                "hmac-sha384" => tsig::TsigAlgorithm::HmacSha384,
+                "hmac-sha512" => tsig::TsigAlgorithm::HmacSha512,
+                _ => {
+                    config.new_parse_error(
+                        ("acme", acme_id, key("tsig-algorithm").as_str()),
+                        "Unsupported algorithm",
+                    );
+                    return None;
+                }
+            };
+            let secret = STANDARD
+                .decode(
+                    config
+                        .value_require(("acme", acme_id, key("secret").as_str()))?
+                        .trim(),
+                )
+                .map_err(|_| {
+                    config.new_parse_error(
+                        ("acme", acme_id, key("secret").as_str()),
+                        "Failed to base64 decode secret",
+                    )
+                })
+                .ok()?;
+            let host =
+                config.property_require::<IpAddr>(("acme", acme_id, key("host").as_str()))?;
+            let port = config
+                .property_or_default::<u16>(("acme", acme_id, key("port").as_str()), "53")
+                .unwrap_or(53);
+
+            let key_name = config
+                .value_require(("acme", acme_id, key("key").as_str()))?
+                .trim()
+                .to_string();
+            let signer = Name::from_ascii(&key_name)
+                .and_then(|key_name| TSigner::new(secret, algorithm, key_name.to_lowercase(), 300))
+                .map_err(|err| {
+                    config.new_parse_error(
+                        ("acme", acme_id, key("key").as_str()),
+                        format!("Invalid TSIG key: {err}"),
+                    )
+                })
+                .ok()?;
+
+            DnsProvider::Rfc2136 {
+                addr: SocketAddr::new(host, port),
+                is_tcp: config.value(("acme", acme_id, key("protocol").as_str())) == Some("tcp"),
+                signer,
+            }
+        }
+        "cloudflare" => DnsProvider::Cloudflare {
+            secret: config
+                .value_require(("acme", acme_id, key("secret").as_str()))?
+                .trim()
+                .to_string(),
+            email: config
+                .value(("acme", acme_id, key("user").as_str()))
+                .map(|s| s.trim().to_string()),
+            timeout,
+        },
+        "route53" => DnsProvider::Route53 {
+            access_key: config
+                .value_require(("acme", acme_id, key("key").as_str()))?
+                .trim()
+                .to_string(),
+            secret_key: config
+                .value_require(("acme", acme_id, key("secret").as_str()))?
+                .trim()
+                .to_string(),
+            session_token: config
+                .value(("acme", acme_id, key("session-token").as_str()))
+                .map(|s| s.trim().to_string()),
+            zone_id: config
+                .value_require(("acme", acme_id, key("zone-id").as_str()))?
+                .trim()
+                .to_string(),
+            timeout,
+        },
+        _ => {
+            config.new_parse_error(
+                ("acme", acme_id, key("provider").as_str()),
+                "Unsupported provider",
+            );
+            return None;
+        }
+    };
+
+    Some(updater)
+}
+
+fn build_tlsa_settings(
+    config: &mut Config,
+    acme_id: &str,
+    domains: &[String],
+) -> Option<TlsaSettings> {
+    let updater = build_dns_provider(config, acme_id, "tlsa.")?;
+
+    // Publish records for all non-wildcard names covered by the certificate by default
+    let mut hosts = config
+        .values(("acme", acme_id, "tlsa.hosts"))
+        .map(|(_, s)| s.trim().trim_end_matches('.').to_lowercase())
+        .collect::<Vec<_>>();
+    if hosts.is_empty() {
+        hosts = domains
+            .iter()
+            .filter(|domain| !domain.starts_with("*."))
+            .map(|domain| domain.to_lowercase())
+            .collect();
+    }
+    let ttl: Duration = config
+        .property_or_default(("acme", acme_id, "tlsa.ttl"), "1h")
+        .unwrap_or_else(|| Duration::from_secs(3600));
+    let rollover: Duration = config
+        .property_or_default(("acme", acme_id, "tlsa.rollover"), "2d")
+        .unwrap_or_else(|| Duration::from_secs(2 * 86400));
+    if rollover < ttl * 2 {
+        config.new_build_error(
+            ("acme", acme_id, "tlsa.rollover"),
+            "Rollover window must be at least twice the TTL of the records",
+        );
+        return None;
+    }
+
+    Some(TlsaSettings {
+        updater,
+        hosts,
+        origin: config
+            .value(("acme", acme_id, "tlsa.origin"))
+            .map(|s| s.trim().to_string()),
+        port: config
+            .property_or_default::<u16>(("acme", acme_id, "tlsa.smtp-port"), "25")
+            .unwrap_or(25),
+        ttl: ttl.as_secs() as u32,
+        rollover,
+    })
+}
+
 pub(crate) fn parse_certificates(
     config: &mut Config,
-    certificates: &mut AHashMap<String, Arc<CertifiedKey>>,
+    certificates: &mut AHashMap<String, ManagedCertificate>,
     subject_names: &mut AHashSet<String>,
 ) {
     // Parse certificates
-    for cert_id in config
+    let mut cert_ids = config
         .sub_keys("certificate", ".cert")
+        .chain(config.sub_keys("certificate", ".cert-file"))
         .map(|s| s.to_string())
-        .collect::<Vec<_>>()
-    {
+        .collect::<Vec<_>>();
+    cert_ids.sort_unstable();
+    cert_ids.dedup();
+
+    for cert_id in cert_ids {
         let cert_id = cert_id.as_str();
-        let key_cert = ("certificate", cert_id, "cert");
-        let key_pk = ("certificate", cert_id, "private-key");
-
-        let cert = config
-            .value_require(key_cert)
-            .map(|s| s.as_bytes().to_vec());
-        let pk = config.value_require(key_pk).map(|s| s.as_bytes().to_vec());
-
-        if let (Some(cert), Some(pk)) = (cert, pk) {
-            match build_certified_key(cert, pk) {
-                Ok(cert) => {
-                    match cert
-                        .end_entity_cert()
-                        .map_err(|err| format!("Failed to obtain end entity cert: {err}"))
-                        .and_then(|cert| {
-                            X509Certificate::from_der(cert.as_ref())
-                                .map_err(|err| format!("Failed to parse end entity cert: {err}"))
-                        }) {
-                        Ok((_, parsed)) => {
-                            // Add CNs and SANs to the list of names
-                            let mut names = AHashSet::new();
-                            for name in parsed.subject().iter_common_name() {
-                                if let Ok(name) = name.as_str() {
-                                    names.insert(name.to_string());
-                                }
-                            }
-                            for ext in parsed.extensions() {
-                                if let ParsedExtension::SubjectAlternativeName(san) =
-                                    ext.parsed_extension()
-                                {
-                                    for name in &san.general_names {
-                                        let name = match name {
-                                            GeneralName::DNSName(name) => name.to_string(),
-                                            GeneralName::IPAddress(ip) => match ip.len() {
-                                                4 => Ipv4Addr::from(
-                                                    <[u8; 4]>::try_from(*ip).unwrap(),
-                                                )
-                                                .to_string(),
-                                                16 => Ipv6Addr::from(
-                                                    <[u8; 16]>::try_from(*ip).unwrap(),
-                                                )
-                                                .to_string(),
-                                                _ => continue,
-                                            },
-                                            _ => {
-                                                continue;
-                                            }
-                                        };
-                                        names.insert(name);
-                                    }
-                                }
-                            }
-
-                            // Add custom SNIs
-                            names.extend(
-                                config
-                                    .values(("certificate", cert_id, "subjects"))
-                                    .map(|(_, v)| v.trim().to_string()),
-                            );
-
-                            // Add domain names
-                            subject_names.extend(names.iter().cloned());
-
-                            // Add certificates
-                            let cert = Arc::new(cert);
-                            for name in names {
-                                certificates.insert(
-                                    name.strip_prefix("*.")
-                                        .map(|name| name.to_string())
-                                        .unwrap_or(name),
-                                    cert.clone(),
-                                );
-                            }
-
-                            // Add default certificate
-                            if config
-                                .property::<bool>(("certificate", cert_id, "default"))
-                                .unwrap_or_default()
-                            {
-                                certificates.insert("*".to_string(), cert.clone());
-                            }
-                        }
-                        Err(err) => config.new_build_error(format!("certificate.{cert_id}"), err),
-                    }
-                }
-                Err(err) => config.new_build_error(format!("certificate.{cert_id}"), err),
+
+        // Certificates read from disk are watched for changes
+        let mut files = None;
+        let key = if let Some(cert_path) = config
+            .value(("certificate", cert_id, "cert-file"))
+            .map(|s| s.trim().to_string())
+        {
+            let Some(pk_path) = config
+                .value_require(("certificate", cert_id, "private-key-file"))
+                .map(|s| s.trim().to_string())
+            else {
+                continue;
+            };
+            let mut cert_files = CertificateFiles::new(cert_path, pk_path);
+            let key = cert_files.read();
+            files = Some(cert_files);
+            key
+        } else {
+            let cert = config
+                .value_require(("certificate", cert_id, "cert"))
+                .map(|s| s.as_bytes().to_vec());
+            let pk = config
+                .value_require(("certificate", cert_id, "private-key"))
+                .map(|s| s.as_bytes().to_vec());
+            if let (Some(cert), Some(pk)) = (cert, pk) {
+                build_certified_key(cert, pk)
+            } else {
+                continue;
+            }
+        };
+
+        // Add custom SNIs
+        let subjects = config
+            .values(("certificate", cert_id, "subjects"))
+            .map(|(_, v)| v.trim().to_string())
+            .collect::<Vec<_>>();
+        let default = config
+            .property::<bool>(("certificate", cert_id, "default"))
+            .unwrap_or_default();
+        let ocsp_stapling = config
+            .property_or_default::<bool>(("certificate", cert_id, "ocsp-stapling"), "true")
+            .unwrap_or(true);
+
+        match key
+            .and_then(|key| ManagedCertificate::new(key, subjects, default, files, ocsp_stapling))
+        {
+            Ok(cert) => {
+                // Add domain names
+                subject_names.extend(cert.names.iter().cloned());
+                certificates.insert(cert_id.to_string(), cert);
             }
+            Err(err) => config.new_build_error(format!("certificate.{cert_id}"), err),
         }
     }
 }
@@ -0,0 +1,199 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use utils::config::{Config, utils::ParseValue};
+
+#[derive(Debug, Default, Clone)]
+pub struct MailingListConfig {
+    // Lists indexed by their lowercase address
+    pub lists: AHashMap<String, MailingList>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MailingList {
+    pub id: String,
+    pub address: String,
+    pub name: Option<String>,
+    pub subject_prefix: Option<String>,
+    pub footer_text: Option<String>,
+    pub footer_html: Option<String>,
+    pub reply_to: ReplyToPolicy,
+    pub moderation: ListModeration,
+    pub moderator: Option<String>,
+    pub bounce: Option<String>,
+    pub verp: bool,
+    pub help: Option<String>,
+    pub unsubscribe: Option<String>,
+    pub subscribe: Option<String>,
+    pub archive: Option<String>,
+    pub owner: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyToPolicy {
+    List,
+    Sender,
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListModeration {
+    None,
+    NonMembers,
+    All,
+}
+
+impl MailingListConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut lists = AHashMap::new();
+        for id in config
+            .sub_keys("mailing-list", ".address")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            if let Some(list) = parse_mailing_list(config, &id) {
+                lists.insert(list.address.clone(), list);
+            }
+        }
+
+        MailingListConfig { lists }
+    }
+}
+
+impl MailingList {
+    /// Returns the RFC 2919 list identifier, built from the list address.
+    pub fn list_id(&self) -> String {
+        let list_id = self.address.replace('@', ".");
+        if let Some(name) = &self.name {
+            format!("{name} <{list_id}>")
+        } else {
+            format!("<{list_id}>")
+        }
+    }
+
+    /// Returns the address that receives bounces, defaults to the
+    /// "-bounces" address of the list.
+    pub fn bounce_address(&self) -> String {
+        match (&self.bounce, self.address.rsplit_once('@')) {
+            (Some(bounce), _) => bounce.clone(),
+            (None, Some((local, domain))) => format!("{local}-bounces@{domain}"),
+            (None, None) => self.address.clone(),
+        }
+    }
+}
+
+fn parse_mailing_list(config: &mut Config, id: &str) -> Option<MailingList> {
+    let address = config
+        .value_require(("mailing-list", id, "address"))?
+        .trim()
+        .to_lowercase();
+    if !address.contains('@') {
+        config.new_parse_error(
+            ("mailing-list", id, "address"),
+            format!("Invalid list address {address:?}"),
+        );
+        return None;
+    }
+    let address_property = |config: &mut Config, key: &str| {
+        let value = config
+            .value(("mailing-list", id, key))?
+            .trim()
+            .to_lowercase();
+        if value.contains('@') {
+            Some(value)
+        } else {
+            config.new_parse_error(
+                ("mailing-list", id, key),
+                format!("Invalid address {value:?}"),
+            );
+            None
+        }
+    };
+    let moderator = address_property(config, "moderator");
+    let bounce = address_property(config, "bounce");
+    let moderation = config
+        .property_or_default::<ListModeration>(("mailing-list", id, "moderation"), "none")
+        .unwrap_or(ListModeration::None);
+    if moderation != ListModeration::None && moderator.is_none() {
+        config.new_parse_error(
+            ("mailing-list", id, "moderator"),
+            "A moderator is required for moderated lists",
+        );
+        return None;
+    }
+
+    // Header values are URIs, e.g. "mailto:list-help@example.org"
+    let uri = |key: &str| {
+        config
+            .value(("mailing-list", id, key))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.trim_start_matches('<').trim_end_matches('>').to_string())
+    };
+    let help = uri("headers.help");
+    let unsubscribe = uri("headers.unsubscribe");
+    let subscribe = uri("headers.subscribe");
+    let archive = uri("headers.archive");
+    let owner = uri("headers.owner");
+
+    Some(MailingList {
+        id: id.to_string(),
+        name: config
+            .value(("mailing-list", id, "name"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+        subject_prefix: config
+            .value(("mailing-list", id, "subject-prefix"))
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.trim().to_string()),
+        footer_text: config
+            .value(("mailing-list", id, "footer.text"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+        footer_html: config
+            .value(("mailing-list", id, "footer.html"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+        reply_to: config
+            .property_or_default::<ReplyToPolicy>(("mailing-list", id, "reply-to"), "keep")
+            .unwrap_or(ReplyToPolicy::Keep),
+        moderation,
+        moderator,
+        bounce,
+        verp: config
+            .property_or_default(("mailing-list", id, "verp"), "true")
+            .unwrap_or(true),
+        help,
+        unsubscribe,
+        subscribe,
+        archive,
+        owner,
+        address,
+    })
+}
+
+impl ParseValue for ReplyToPolicy {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        hashify::tiny_map_ignore_case!(value.as_bytes(),
+            b"list" => ReplyToPolicy::List,
+            b"sender" => ReplyToPolicy::Sender,
+            b"keep" => ReplyToPolicy::Keep,
+        )
+        .ok_or_else(|| format!("Invalid Reply-To policy {value:?}"))
+    }
+}
+
+impl ParseValue for ListModeration {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        hashify::tiny_map_ignore_case!(value.as_bytes(),
+            b"none" => ListModeration::None,
+            b"non-members" => ListModeration::NonMembers,
+            b"all" => ListModeration::All,
+        )
+        .ok_or_else(|| format!("Invalid list moderation {value:?}"))
+    }
+}
@@ -7,17 +7,19 @@
 use utils::config::{Config, Rate};
 
 pub mod auth;
+pub mod list;
 pub mod queue;
 pub mod report;
 pub mod resolver;
+pub mod routing;
 pub mod session;
 pub mod throttle;
 
 use crate::expr::{Expression, tokenizer::TokenMap};
 
 use self::{
-    auth::MailAuthConfig, queue::QueueConfig, report::ReportConfig, resolver::Resolvers,
-    session::SessionConfig,
+    auth::MailAuthConfig, list::MailingListConfig, queue::QueueConfig, report::ReportConfig,
+    resolver::Resolvers, session::SessionConfig,
 };
 
 use super::*;
@@ -29,6 +31,7 @@ pub struct SmtpConfig {
     pub resolvers: Resolvers,
     pub mail_auth: MailAuthConfig,
     pub report: ReportConfig,
+    pub list: MailingListConfig,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -38,6 +41,26 @@ pub struct QueueRateLimiter {
     pub expr: Expression,
     pub keys: u16,
     pub rate: Rate,
+    pub scope: RateLimiterScope,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "test_mode", derive(PartialEq, Eq))]
+pub enum RateLimiterScope {
+    /// Requests are counted separately by each node.
+    Node,
+    /// Requests are counted in a shared in-memory store, reserving `batch`
+    /// tokens on each roundtrip which are then handed out locally.
+    Cluster { store: Option<String>, batch: u64 },
+}
+
+impl Default for RateLimiterScope {
+    fn default() -> Self {
+        RateLimiterScope::Cluster {
+            store: None,
+            batch: 1,
+        }
+    }
 }
 
 pub const THROTTLE_RCPT: u16 = 1 << 0;
@@ -158,6 +181,7 @@ impl SmtpConfig {
             resolvers: Resolvers::parse(config).await,
             mail_auth: MailAuthConfig::parse(config),
             report: ReportConfig::parse(config),
+            list: MailingListConfig::parse(config),
         }
     }
 }
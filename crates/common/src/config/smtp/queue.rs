@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::{path::PathBuf, time::Duration};
+
 use ahash::AHashMap;
 use mail_auth::IpLookupStrategy;
 use mail_send::Credentials;
@@ -15,7 +17,10 @@ use crate::{
     expr::{if_block::IfBlock, *},
 };
 
-use self::throttle::parse_queue_rate_limiter;
+use self::{
+    routing::{DomainRoute, parse_domain_routes},
+    throttle::parse_queue_rate_limiter,
+};
 
 use super::*;
 
@@ -34,6 +39,7 @@ pub struct QueueConfig {
     pub ip_strategy: IfBlock,
     pub source_ip: QueueOutboundSourceIp,
     pub tls: QueueOutboundTls,
+    pub disable_capabilities: IfBlock,
     pub dsn: Dsn,
 
     // Timeouts
@@ -44,9 +50,40 @@ pub struct QueueConfig {
     pub outbound_limiters: QueueRateLimiters,
     pub quota: QueueQuotas,
     pub max_threads: usize,
+    pub fair_share: Option<QueueFairShare>,
+
+    // Message tracking retention
+    pub tracking: Option<Duration>,
+
+    // Quarantine retention
+    pub quarantine_retention: Duration,
 
     // Relay hosts
     pub relay_hosts: AHashMap<String, RelayHost>,
+    pub relay_budgets: Vec<RelayBudget>,
+
+    // Per-domain routes
+    pub routes: AHashMap<String, DomainRoute>,
+
+    // Compliance archiving
+    pub archive: Vec<ArchivePolicy>,
+
+    // Variable envelope return paths
+    pub verp: Verp,
+}
+
+#[derive(Clone)]
+pub struct QueueFairShare {
+    pub default_weight: u32,
+    pub weights: AHashMap<String, u32>,
+}
+
+#[derive(Clone)]
+pub struct Verp {
+    pub enable: IfBlock,
+    pub delimiter: char,
+    pub suppress: bool,
+    pub suppress_expiry: Option<Duration>,
 }
 
 #[derive(Clone)]
@@ -115,6 +152,41 @@ pub struct RelayHost {
     pub tls_allow_invalid_certs: bool,
 }
 
+#[derive(Clone)]
+pub struct RelayBudget {
+    pub id: String,
+    pub relay: String,
+    pub messages: u64,
+    pub alert: Option<u64>,
+    pub action: BudgetAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetAction {
+    Alert,
+    Direct,
+    #[default]
+    Defer,
+}
+
+#[derive(Clone)]
+pub struct ArchivePolicy {
+    pub id: String,
+    pub enable: IfBlock,
+    pub destination: ArchiveDestination,
+    pub retention: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveDestination {
+    // Journal reports sent to an archiving mailbox
+    Smtp { address: String, from: String },
+    // Write-once storage is provided by the blob store, e.g. an S3 bucket with Object Lock
+    Blob { store: String, prefix: String },
+    // Append-only directory on the local filesystem
+    File { path: PathBuf },
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum RequireOptional {
     #[default]
@@ -181,6 +253,7 @@ impl Default for QueueConfig {
                     "false",
                 ),
             },
+            disable_capabilities: IfBlock::empty("queue.outbound.disable-capabilities"),
             dsn: Dsn {
                 name: IfBlock::new::<()>("report.dsn.from-name", [], "'Mail Delivery Subsystem'"),
                 address: IfBlock::new::<()>(
@@ -205,10 +278,22 @@ impl Default for QueueConfig {
                 mta_sts: IfBlock::new::<()>("queue.outbound.timeouts.mta-sts", [], "10m"),
             },
             max_threads: 25,
+            fair_share: None,
+            tracking: None,
+            quarantine_retention: Duration::from_secs(30 * 86400),
             inbound_limiters: QueueRateLimiters::default(),
             outbound_limiters: QueueRateLimiters::default(),
             quota: QueueQuotas::default(),
             relay_hosts: Default::default(),
+            relay_budgets: Default::default(),
+            routes: Default::default(),
+            archive: Default::default(),
+            verp: Verp {
+                enable: IfBlock::new::<()>("queue.verp.enable", [], "false"),
+                delimiter: '+',
+                suppress: true,
+                suppress_expiry: None,
+            },
         }
     }
 }
@@ -267,6 +352,11 @@ impl QueueConfig {
                 "queue.outbound.tls.allow-invalid-certs",
                 &mx_vars,
             ),
+            (
+                &mut queue.disable_capabilities,
+                "queue.outbound.disable-capabilities",
+                &mx_vars,
+            ),
             (
                 &mut queue.timeout.connect,
                 "queue.outbound.timeouts.connect",
@@ -314,6 +404,7 @@ impl QueueConfig {
                 &sender_vars,
             ),
             (&mut queue.dsn.sign, "report.dsn.sign", &sender_vars),
+            (&mut queue.verp.enable, "queue.verp.enable", &sender_vars),
         ] {
             if let Some(if_block) = IfBlock::try_parse(config, key, token_map) {
                 *value = if_block;
@@ -329,6 +420,49 @@ impl QueueConfig {
         queue.outbound_limiters = parse_outbound_rate_limiters(config);
         queue.quota = parse_queue_quota(config);
 
+        // Parse tenant fair scheduling
+        if config
+            .property_or_default::<bool>("queue.fair-share.enable", "false")
+            .unwrap_or(false)
+        {
+            let mut weights = AHashMap::new();
+            for tenant in config
+                .sub_keys("queue.fair-share.weight", "")
+                .map(|tenant| tenant.to_string())
+                .collect::<Vec<_>>()
+            {
+                if let Some(weight) = config
+                    .property_require::<u32>(("queue.fair-share.weight", tenant.as_str()))
+                    .filter(|weight| *weight > 0)
+                {
+                    weights.insert(tenant, weight);
+                }
+            }
+            queue.fair_share = Some(QueueFairShare {
+                default_weight: config
+                    .property_or_default::<u32>("queue.fair-share.default-weight", "1")
+                    .unwrap_or(1)
+                    .max(1),
+                weights,
+            });
+        }
+
+        // Parse message tracking
+        if config
+            .property_or_default::<bool>("queue.tracking.enable", "false")
+            .unwrap_or(false)
+        {
+            queue.tracking = config
+                .property_or_default::<Duration>("queue.tracking.retention", "30d")
+                .unwrap_or(Duration::from_secs(30 * 86400))
+                .into();
+        }
+
+        // Parse quarantine retention
+        queue.quarantine_retention = config
+            .property_or_default::<Duration>("queue.quarantine.retention", "30d")
+            .unwrap_or(Duration::from_secs(30 * 86400));
+
         // Parse relay hosts
         queue.relay_hosts = config
             .sub_keys("remote", ".address")
@@ -338,6 +472,15 @@ impl QueueConfig {
             .filter_map(|id| parse_relay_host(config, &id).map(|host| (id, host)))
             .collect();
 
+        // Parse relay budgets
+        queue.relay_budgets = config
+            .sub_keys("queue.budget", ".relay")
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_relay_budget(config, &id, &queue.relay_hosts))
+            .collect();
+
         // Add local delivery host
         queue.relay_hosts.insert(
             "local".to_string(),
@@ -351,6 +494,41 @@ impl QueueConfig {
             },
         );
 
+        // Parse per-domain routes
+        queue.routes = parse_domain_routes(config, &mut queue.relay_hosts);
+
+        // Parse archiving policies
+        queue.archive = config
+            .sub_keys("queue.archive", ".destination.type")
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_archive_policy(config, &id, &rcpt_vars))
+            .collect();
+
+        // Parse VERP settings
+        if let Some(delimiter) = config
+            .value("queue.verp.delimiter")
+            .map(|value| value.to_string())
+        {
+            let mut chars = delimiter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(delimiter), None) if !matches!(delimiter, '@' | '=') => {
+                    queue.verp.delimiter = delimiter;
+                }
+                _ => {
+                    config.new_parse_error(
+                        "queue.verp.delimiter",
+                        format!("Invalid VERP delimiter {delimiter:?}"),
+                    );
+                }
+            }
+        }
+        queue.verp.suppress = config
+            .property_or_default("queue.verp.suppress", "true")
+            .unwrap_or(true);
+        queue.verp.suppress_expiry = config.property::<Duration>("queue.verp.suppress-expiry");
+
         queue
     }
 }
@@ -381,6 +559,108 @@ fn parse_relay_host(config: &mut Config, id: &str) -> Option<RelayHost> {
     })
 }
 
+fn parse_relay_budget(
+    config: &mut Config,
+    id: &str,
+    relay_hosts: &AHashMap<String, RelayHost>,
+) -> Option<RelayBudget> {
+    if !config
+        .property::<bool>(("queue.budget", id, "enable"))
+        .unwrap_or(true)
+    {
+        return None;
+    }
+
+    let relay = config
+        .value_require(("queue.budget", id, "relay"))?
+        .to_string();
+    if !relay_hosts.contains_key(&relay) {
+        config.new_build_error(
+            ("queue.budget", id, "relay"),
+            format!("Relay host {relay:?} not found"),
+        );
+        return None;
+    }
+
+    Some(RelayBudget {
+        id: id.to_string(),
+        relay,
+        messages: config.property_require(("queue.budget", id, "messages"))?,
+        alert: config
+            .property::<u64>(("queue.budget", id, "alert"))
+            .filter(|alert| (1..=100).contains(alert)),
+        action: config
+            .property_or_default(("queue.budget", id, "action"), "defer")
+            .unwrap_or_default(),
+    })
+}
+
+fn parse_archive_policy(
+    config: &mut Config,
+    id: &str,
+    token_map: &TokenMap,
+) -> Option<ArchivePolicy> {
+    let destination = match config
+        .value_require(("queue.archive", id, "destination.type"))?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "smtp" => ArchiveDestination::Smtp {
+            address: config
+                .value_require(("queue.archive", id, "destination.address"))?
+                .trim()
+                .to_lowercase(),
+            from: config
+                .value(("queue.archive", id, "destination.from"))
+                .filter(|v| v.contains('@'))
+                .unwrap_or("MAILER-DAEMON@localhost")
+                .to_string(),
+        },
+        "blob" => ArchiveDestination::Blob {
+            store: config
+                .value_require(("queue.archive", id, "destination.store"))?
+                .to_string(),
+            prefix: config
+                .value(("queue.archive", id, "destination.prefix"))
+                .unwrap_or("archive")
+                .trim_matches('/')
+                .to_string(),
+        },
+        "file" => ArchiveDestination::File {
+            path: config
+                .value_require(("queue.archive", id, "destination.path"))?
+                .into(),
+        },
+        destination => {
+            config.new_parse_error(
+                ("queue.archive", id, "destination.type"),
+                format!("Invalid archive destination {destination:?}"),
+            );
+            return None;
+        }
+    };
+
+    if let ArchiveDestination::Smtp { address, .. } = &destination {
+        if !address.contains('@') {
+            config.new_parse_error(
+                ("queue.archive", id, "destination.address"),
+                format!("Invalid journal address {address:?}"),
+            );
+            return None;
+        }
+    }
+
+    Some(ArchivePolicy {
+        id: id.to_string(),
+        enable: IfBlock::try_parse(config, ("queue.archive", id, "enable"), token_map)
+            .unwrap_or_else(|| {
+                IfBlock::new::<()>(format!("queue.archive.{id}.enable"), [], "false")
+            }),
+        destination,
+        retention: config.property::<Duration>(("queue.archive", id, "retention")),
+    })
+}
+
 fn parse_inbound_rate_limters(config: &mut Config) -> QueueRateLimiters {
     let mut throttle = QueueRateLimiters::default();
     let all_throttles = parse_queue_rate_limiter(
@@ -577,6 +857,17 @@ fn parse_queue_quota_item(config: &mut Config, prefix: impl AsKey, id: &str) ->
     }
 }
 
+impl ParseValue for BudgetAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "alert" => Ok(BudgetAction::Alert),
+            "direct" => Ok(BudgetAction::Direct),
+            "defer" => Ok(BudgetAction::Defer),
+            _ => Err(format!("Invalid budget action value {:?}.", value,)),
+        }
+    }
+}
+
 impl ParseValue for RequireOptional {
     fn parse_value(value: &str) -> Result<Self, String> {
         match value {
@@ -676,3 +967,36 @@ impl std::fmt::Debug for RelayHost {
             .finish()
     }
 }
+
+impl Verp {
+    /// Encodes a recipient in the local part of a return path,
+    /// e.g. "bounces+jane=example.org@example.com".
+    pub fn encode(&self, return_path: &str, rcpt: &str) -> String {
+        match return_path.rsplit_once('@') {
+            Some((local, domain)) if !rcpt.is_empty() => {
+                format!(
+                    "{local}{}{}@{domain}",
+                    self.delimiter,
+                    rcpt.replacen('@', "=", 1)
+                )
+            }
+            _ => return_path.to_string(),
+        }
+    }
+
+    /// Decodes a VERP address, returning the original return path and the
+    /// recipient encoded in it.
+    pub fn decode(&self, address: &str) -> Option<(String, String)> {
+        let (local, domain) = address.rsplit_once('@')?;
+        let (local, rcpt) = local.split_once(self.delimiter)?;
+        let (rcpt_local, rcpt_domain) = rcpt.rsplit_once('=')?;
+        if local.is_empty() || rcpt_local.is_empty() || !rcpt_domain.contains('.') {
+            return None;
+        }
+
+        Some((
+            format!("{local}@{domain}"),
+            format!("{rcpt_local}@{rcpt_domain}"),
+        ))
+    }
+}
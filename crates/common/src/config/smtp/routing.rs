@@ -0,0 +1,287 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::{AHashMap, AHashSet};
+use mail_send::Credentials;
+use serde::{Deserialize, Serialize};
+use utils::config::{Config, ConfigKey, Rate, utils::ParseValue};
+
+use crate::{Server, config::server::ServerProtocol};
+
+use super::{
+    QueueRateLimiter, THROTTLE_RCPT_DOMAIN,
+    queue::{RelayHost, RequireOptional},
+};
+
+pub const ROUTE_KEY: &str = "queue.route";
+pub const ROUTE_RELAY_MX: &str = "mx";
+
+#[derive(Debug, Clone, Default)]
+pub struct DomainRoute {
+    pub relay: Option<String>,
+    pub tls: DomainRouteTls,
+    pub rate: Option<QueueRateLimiter>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DomainRouteTls {
+    pub starttls: Option<RequireOptional>,
+    pub dane: Option<RequireOptional>,
+    pub mta_sts: Option<RequireOptional>,
+    pub allow_invalid_certs: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainRouteEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_implicit: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starttls: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dane: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mta_sts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_invalid_certs: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<String>,
+}
+
+pub fn parse_domain_routes(
+    config: &mut Config,
+    relay_hosts: &mut AHashMap<String, RelayHost>,
+) -> AHashMap<String, DomainRoute> {
+    let mut routes = AHashMap::new();
+    let mut errors = Vec::new();
+
+    for (domain, value) in config.iterate_prefix(ROUTE_KEY) {
+        let result = serde_json::from_str::<DomainRouteEntry>(value)
+            .map_err(|err| err.to_string())
+            .and_then(|entry| entry.build(domain, relay_hosts));
+        match result {
+            Ok((route, relay_host)) => {
+                if let Some(relay_host) = relay_host {
+                    relay_hosts.insert(DomainRouteEntry::relay_id(domain), relay_host);
+                }
+                routes.insert(domain.to_string(), route);
+            }
+            Err(err) => {
+                errors.push((domain.to_string(), err));
+            }
+        }
+    }
+
+    for (domain, err) in errors {
+        config.new_parse_error((ROUTE_KEY, domain.as_str()), err);
+    }
+
+    routes
+}
+
+impl DomainRouteEntry {
+    pub fn relay_id(domain: &str) -> String {
+        format!("route.{domain}")
+    }
+
+    pub fn validate_domain(domain: &str) -> Result<String, String> {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+        if !domain.is_empty()
+            && domain.len() <= 255
+            && domain
+                .split('.')
+                .all(|label| !label.is_empty() && label.len() <= 63)
+            && domain
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || matches!(ch, '.' | '-' | '_'))
+        {
+            Ok(domain)
+        } else {
+            Err(format!("Invalid domain name {domain:?}."))
+        }
+    }
+
+    pub fn build(
+        &self,
+        domain: &str,
+        relay_hosts: &AHashMap<String, RelayHost>,
+    ) -> Result<(DomainRoute, Option<RelayHost>), String> {
+        // Inline relay hosts take precedence over named remotes
+        let (relay, relay_host) = if let Some(address) = &self.address {
+            if self.relay.is_some() {
+                return Err("Routes cannot specify both a relay and an address.".to_string());
+            }
+            let protocol = self
+                .protocol
+                .as_deref()
+                .map(ServerProtocol::parse_value)
+                .transpose()?
+                .unwrap_or(ServerProtocol::Smtp);
+            if !matches!(protocol, ServerProtocol::Smtp | ServerProtocol::Lmtp) {
+                return Err(format!("Unsupported relay protocol {protocol}."));
+            }
+            let auth = match (&self.username, &self.secret) {
+                (Some(username), Some(secret)) => {
+                    Some(Credentials::new(username.clone(), secret.clone()))
+                }
+                (None, None) => None,
+                _ => {
+                    return Err("Relay credentials require both a username and a secret.".into());
+                }
+            };
+
+            (
+                Some(Self::relay_id(domain)),
+                Some(RelayHost {
+                    address: address.trim().to_string(),
+                    port: self.port.unwrap_or(25),
+                    protocol,
+                    auth,
+                    tls_implicit: self.tls_implicit.unwrap_or(false),
+                    tls_allow_invalid_certs: self.allow_invalid_certs.unwrap_or(false),
+                }),
+            )
+        } else if let Some(relay) = &self.relay {
+            if self.port.is_some()
+                || self.protocol.is_some()
+                || self.tls_implicit.is_some()
+                || self.username.is_some()
+                || self.secret.is_some()
+            {
+                return Err("Relay host settings require an address.".to_string());
+            }
+            if relay != ROUTE_RELAY_MX && !relay_hosts.contains_key(relay) {
+                return Err(format!("Relay host {relay:?} not found."));
+            }
+            (Some(relay.clone()), None)
+        } else {
+            (None, None)
+        };
+
+        let parse_tls = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(RequireOptional::parse_value)
+                .transpose()
+        };
+
+        Ok((
+            DomainRoute {
+                relay,
+                tls: DomainRouteTls {
+                    starttls: parse_tls(&self.starttls)?,
+                    dane: parse_tls(&self.dane)?,
+                    mta_sts: parse_tls(&self.mta_sts)?,
+                    allow_invalid_certs: self.allow_invalid_certs,
+                },
+                rate: self
+                    .rate
+                    .as_deref()
+                    .map(Rate::parse_value)
+                    .transpose()?
+                    .filter(|rate| rate.requests > 0)
+                    .map(|rate| QueueRateLimiter {
+                        id: Self::relay_id(domain),
+                        expr: Default::default(),
+                        keys: THROTTLE_RCPT_DOMAIN,
+                        rate,
+                        scope: Default::default(),
+                    }),
+            },
+            relay_host,
+        ))
+    }
+}
+
+impl Server {
+    pub async fn domain_routes_export(&self) -> trc::Result<Vec<(String, DomainRouteEntry)>> {
+        let mut routes = Vec::new();
+
+        for (domain, value) in self
+            .core
+            .storage
+            .config
+            .list(&format!("{ROUTE_KEY}."), true)
+            .await?
+        {
+            match serde_json::from_str::<DomainRouteEntry>(&value) {
+                Ok(entry) => routes.push((domain, entry)),
+                Err(err) => {
+                    trc::event!(
+                        Config(trc::ConfigEvent::ParseError),
+                        Key = format!("{ROUTE_KEY}.{domain}"),
+                        Reason = err.to_string(),
+                    );
+                }
+            }
+        }
+
+        Ok(routes)
+    }
+
+    pub fn domain_routes_validate(
+        &self,
+        routes: Vec<(String, DomainRouteEntry)>,
+    ) -> Result<Vec<ConfigKey>, Vec<(String, String)>> {
+        let mut errors = Vec::new();
+        let mut keys = Vec::with_capacity(routes.len());
+        let mut domains = AHashSet::with_capacity(routes.len());
+
+        for (domain, entry) in routes {
+            let domain = match DomainRouteEntry::validate_domain(&domain) {
+                Ok(domain) => domain,
+                Err(err) => {
+                    errors.push((domain, err));
+                    continue;
+                }
+            };
+            if !domains.insert(domain.clone()) {
+                errors.push((domain, "Duplicate domain.".to_string()));
+            } else if let Err(err) = entry.build(&domain, &self.core.smtp.queue.relay_hosts) {
+                errors.push((domain, err));
+            } else {
+                keys.push(ConfigKey {
+                    key: format!("{ROUTE_KEY}.{domain}"),
+                    value: serde_json::to_string(&entry).unwrap_or_default(),
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(keys)
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub async fn domain_routes_apply(
+        &self,
+        keys: Vec<ConfigKey>,
+        replace: bool,
+    ) -> trc::Result<()> {
+        if replace {
+            self.core
+                .storage
+                .config
+                .replace_prefix(&format!("{ROUTE_KEY}."), keys)
+                .await
+        } else {
+            self.core.storage.config.set(keys, true).await
+        }
+    }
+}
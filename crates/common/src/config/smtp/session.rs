@@ -7,7 +7,7 @@
 use std::{
     net::{SocketAddr, ToSocketAddrs},
     str::FromStr,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use ahash::AHashSet;
@@ -17,8 +17,12 @@ use hyper::{
     HeaderMap,
     header::{AUTHORIZATION, CONTENT_TYPE, HeaderName, HeaderValue},
 };
+use regex::Regex;
 use smtp_proto::*;
-use utils::config::{Config, utils::ParseValue};
+use utils::{
+    cache::CacheItemWeight,
+    config::{Config, Rate, utils::ParseValue},
+};
 
 use crate::{
     config::CONNECTION_VARS,
@@ -36,6 +40,7 @@ pub struct SessionConfig {
     pub transfer_limit: IfBlock,
 
     pub connect: Connect,
+    pub maintenance: Maintenance,
     pub ehlo: Ehlo,
     pub auth: Auth,
     pub mail: Mail,
@@ -46,6 +51,10 @@ pub struct SessionConfig {
 
     pub milters: Vec<Milter>,
     pub hooks: Vec<MTAHook>,
+    pub antivirus: Vec<Antivirus>,
+    pub sandbox: Option<Sandbox>,
+    pub anomaly: Option<AccountAnomaly>,
+    pub proxy: Proxy,
 }
 
 #[derive(Clone)]
@@ -55,6 +64,37 @@ pub struct Connect {
     pub greeting: IfBlock,
 }
 
+#[derive(Clone)]
+pub struct Maintenance {
+    pub allow: IfBlock,
+    pub windows: Vec<(u64, u64)>,
+    pub message: String,
+}
+
+// Transactions are handed over to a backend pool at the first recipient, before it is
+// verified locally, recipient and message policy are then enforced by the backend
+#[derive(Clone)]
+pub struct Proxy {
+    pub backend: IfBlock,
+    pub pools: Vec<ProxyPool>,
+}
+
+#[derive(Clone)]
+pub struct ProxyPool {
+    pub id: String,
+    pub hosts: Vec<String>,
+    pub forward: ProxyForward,
+    pub timeout_connect: Duration,
+    pub timeout_command: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyForward {
+    Xclient,
+    ProxyProtocol,
+    None,
+}
+
 #[derive(Clone)]
 pub struct Ehlo {
     pub script: IfBlock,
@@ -91,6 +131,15 @@ pub struct Mail {
     pub script: IfBlock,
     pub rewrite: IfBlock,
     pub is_allowed: IfBlock,
+    pub limits: SendingLimits,
+}
+
+#[derive(Clone)]
+pub struct SendingLimits {
+    pub messages_hour: IfBlock,
+    pub messages_day: IfBlock,
+    pub recipients_hour: IfBlock,
+    pub recipients_day: IfBlock,
 }
 
 #[derive(Clone)]
@@ -106,10 +155,21 @@ pub struct Rcpt {
 
     // Limits
     pub max_recipients: IfBlock,
+    pub max_expansion: IfBlock,
+    pub max_expansion_depth: IfBlock,
 
     // Catch-all and sub-addressing
     pub catch_all: AddressMapping,
     pub subaddressing: AddressMapping,
+    pub delimiters: Vec<char>,
+    pub normalize: Vec<AddressNormalization>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressNormalization {
+    pub domain: Option<String>,
+    pub pattern: Regex,
+    pub replace: String,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -124,11 +184,14 @@ pub enum AddressMapping {
 pub struct Data {
     pub script: IfBlock,
     pub spam_filter: IfBlock,
+    pub quarantine: IfBlock,
 
     // Limits
     pub max_messages: IfBlock,
     pub max_message_size: IfBlock,
     pub max_received_headers: IfBlock,
+    pub loop_delivered_to: IfBlock,
+    pub loop_dsn_chains: IfBlock,
 
     // Headers
     pub add_received: IfBlock,
@@ -138,6 +201,93 @@ pub struct Data {
     pub add_message_id: IfBlock,
     pub add_date: IfBlock,
     pub add_delivered_to: bool,
+
+    // Rewriting
+    pub rewrite: Vec<RewriteRule>,
+
+    // Attachment policy
+    pub attachments: Vec<AttachmentRule>,
+
+    // Data loss prevention
+    pub dlp: Vec<DlpRule>,
+
+    // Large attachments
+    pub large_files: LargeFiles,
+
+    // Moderated submission
+    pub moderation: Moderation,
+}
+
+#[derive(Clone)]
+pub struct Moderation {
+    pub moderator: IfBlock,
+    pub external_recipients: bool,
+    pub attachments: bool,
+    pub dlp_hits: bool,
+    pub url: Option<String>,
+    pub expiry: Duration,
+    pub from: String,
+}
+
+#[derive(Clone)]
+pub struct LargeFiles {
+    pub threshold: IfBlock,
+    pub url: IfBlock,
+    pub expiry: IfBlock,
+    pub min_size: usize,
+    pub store: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct RewriteRule {
+    pub id: String,
+    pub enable: IfBlock,
+    pub headers_add: Vec<(String, String)>,
+    pub headers_replace: Vec<(String, String)>,
+    pub headers_remove: Vec<String>,
+    pub from_display_name: Option<IfBlock>,
+    pub footer_text: Option<String>,
+    pub footer_html: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct AttachmentRule {
+    pub id: String,
+    pub enable: IfBlock,
+    pub extensions: AHashSet<String>,
+    pub content_types: Vec<String>,
+    pub file_types: AHashSet<String>,
+    pub archive_depth: usize,
+    pub action: AttachmentAction,
+    pub notify: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttachmentAction {
+    Drop,
+    Rename { suffix: String },
+    Replace { text: String },
+}
+
+#[derive(Clone)]
+pub struct DlpRule {
+    pub id: String,
+    pub enable: IfBlock,
+    pub regex: Vec<Regex>,
+    pub keywords: Vec<String>,
+    pub dictionary: Option<String>,
+    pub attachments: bool,
+    pub threshold: usize,
+    pub action: DlpAction,
+    pub notify: Vec<String>,
+    pub notify_from: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DlpAction {
+    Block { reason: String },
+    Quarantine,
+    Notify,
 }
 
 #[derive(Clone)]
@@ -152,12 +302,13 @@ pub struct Milter {
     pub timeout_data: Duration,
     pub tls: bool,
     pub tls_allow_invalid_certs: bool,
-    pub tempfail_on_error: bool,
     pub max_frame_len: usize,
     pub protocol_version: MilterVersion,
     pub flags_actions: Option<u32>,
     pub flags_protocol: Option<u32>,
     pub run_on_stage: AHashSet<Stage>,
+    pub fail_closed: AHashSet<Stage>,
+    pub circuit_breaker: Option<CircuitBreaker>,
 }
 
 #[derive(Clone, Copy)]
@@ -174,9 +325,203 @@ pub struct MTAHook {
     pub timeout: Duration,
     pub headers: HeaderMap,
     pub tls_allow_invalid_certs: bool,
-    pub tempfail_on_error: bool,
     pub run_on_stage: AHashSet<Stage>,
+    pub fail_closed: AHashSet<Stage>,
+    pub circuit_breaker: Option<CircuitBreaker>,
     pub max_response_size: usize,
+    pub full_message: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    pub errors: Rate,
+    pub open_for: Duration,
+}
+
+pub const FILTER_LATENCY_BUCKETS: [u64; 8] = [5, 10, 50, 100, 500, 1000, 5000, 10000];
+
+/// Latency histogram, error budget and circuit state of a milter or MTA hook.
+#[derive(Clone, Debug, Default)]
+pub struct FilterHealth {
+    pub requests: u64,
+    pub failures: u64,
+    pub latency_sum: u64,
+    pub latency_buckets: [u64; FILTER_LATENCY_BUCKETS.len() + 1],
+    pub window_start: u64,
+    pub window_errors: u64,
+    pub open_until: u64,
+    pub disabled_until: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterState {
+    Available,
+    CircuitOpen,
+    Disabled,
+}
+
+impl FilterHealth {
+    pub fn state(&self, now: u64) -> FilterState {
+        if self.disabled_until > now {
+            FilterState::Disabled
+        } else if self.open_until > now {
+            FilterState::CircuitOpen
+        } else {
+            FilterState::Available
+        }
+    }
+
+    /// Records a request outcome, returns `true` when the error budget is
+    /// exhausted and the circuit is opened.
+    pub fn record(
+        &mut self,
+        elapsed: u64,
+        is_error: bool,
+        circuit_breaker: Option<&CircuitBreaker>,
+        now: u64,
+    ) -> bool {
+        self.requests += 1;
+        self.latency_sum += elapsed;
+        self.latency_buckets[FILTER_LATENCY_BUCKETS
+            .iter()
+            .position(|bucket| elapsed <= *bucket)
+            .unwrap_or(FILTER_LATENCY_BUCKETS.len())] += 1;
+
+        if is_error {
+            self.failures += 1;
+
+            if let Some(circuit_breaker) = circuit_breaker {
+                if now >= self.window_start + circuit_breaker.errors.period.as_secs() {
+                    self.window_start = now;
+                    self.window_errors = 0;
+                }
+                self.window_errors += 1;
+
+                if self.window_errors > circuit_breaker.errors.requests && self.open_until <= now {
+                    self.open_until = now + circuit_breaker.open_for.as_secs();
+                    self.window_start = now;
+                    self.window_errors = 0;
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+#[derive(Clone)]
+pub struct Antivirus {
+    pub enable: IfBlock,
+    pub id: String,
+    pub addrs: Vec<SocketAddr>,
+    pub hostname: String,
+    pub port: u16,
+    pub timeout_connect: Duration,
+    pub timeout_scan: Duration,
+    pub max_size: usize,
+    pub chunk_size: usize,
+    pub pool_size: usize,
+    pub pool_idle_timeout: Duration,
+    pub pool: Arc<parking_lot::Mutex<Vec<AntivirusConnection>>>,
+    pub cache_ttl: Option<Duration>,
+    pub action_infected: AntivirusAction,
+    pub action_error: AntivirusAction,
+}
+
+pub struct AntivirusConnection {
+    pub stream: tokio::net::TcpStream,
+    pub next_id: u32,
+    pub last_used: Instant,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntivirusAction {
+    Accept,
+    Reject,
+    TempFail,
+    Discard,
+    Quarantine,
+    Tag,
+}
+
+#[derive(Clone)]
+pub struct Sandbox {
+    pub enable: IfBlock,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub timeout: Duration,
+    pub tls_allow_invalid_certs: bool,
+    pub extensions: AHashSet<String>,
+    pub max_size: usize,
+    pub mode: SandboxMode,
+    pub verdict_timeout: Duration,
+    pub poll_interval: Duration,
+    pub action_malicious: SandboxAction,
+    pub action_timeout: SandboxAction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SandboxMode {
+    Hold,
+    Deliver,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SandboxAction {
+    Release,
+    Tag,
+    Quarantine,
+}
+
+#[derive(Clone)]
+pub struct AccountAnomaly {
+    pub enable: IfBlock,
+    pub travel_window: Option<Duration>,
+    pub volume: Option<VolumeBaseline>,
+    pub actions: AHashSet<AnomalyAction>,
+    pub notify: Vec<String>,
+    pub notify_from: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VolumeBaseline {
+    pub period: Duration,
+    pub min_volume: u64,
+    pub factor: f64,
+    pub samples: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnomalyAction {
+    Hold,
+    Reauth,
+    Notify,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScanKey(pub [u8; 32]);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String),
+}
+
+impl CacheItemWeight for ScanKey {
+    fn weight(&self) -> u64 {
+        std::mem::size_of::<ScanKey>() as u64
+    }
+}
+
+impl CacheItemWeight for ScanVerdict {
+    fn weight(&self) -> u64 {
+        (std::mem::size_of::<ScanVerdict>()
+            + match self {
+                ScanVerdict::Clean => 0,
+                ScanVerdict::Infected(name) => name.len(),
+            }) as u64
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -201,6 +546,36 @@ impl SessionConfig {
         let mut session = SessionConfig::default();
         session.rcpt.catch_all = AddressMapping::parse(config, "session.rcpt.catch-all");
         session.rcpt.subaddressing = AddressMapping::parse(config, "session.rcpt.sub-addressing");
+        if let Some(delimiters) = config
+            .values("session.rcpt.delimiters")
+            .map(|(_, v)| v.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|value| {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(delimiter), None) if !matches!(delimiter, '@' | '.') => Some(delimiter),
+                    _ => {
+                        config.new_parse_error(
+                            "session.rcpt.delimiters",
+                            format!("Invalid sub-addressing delimiter {value:?}"),
+                        );
+                        None
+                    }
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|delimiters| !delimiters.is_empty())
+        {
+            session.rcpt.delimiters = delimiters;
+        }
+        session.rcpt.normalize = config
+            .sub_keys("session.rcpt.normalize", ".match")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_address_normalization(config, &id))
+            .collect();
         session.milters = config
             .sub_keys("session.milter", ".hostname")
             .map(|s| s.to_string())
@@ -215,7 +590,44 @@ impl SessionConfig {
             .into_iter()
             .filter_map(|id| parse_hooks(config, &id, &has_rcpt_vars))
             .collect();
+        session.antivirus = config
+            .sub_keys("session.antivirus", ".hostname")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_antivirus(config, &id, &has_rcpt_vars))
+            .collect();
+        session.sandbox = parse_sandbox(config, &has_rcpt_vars);
+        session.anomaly = parse_account_anomaly(config, &has_rcpt_vars);
         session.mta_sts_policy = Policy::try_parse(config);
+        session.proxy.pools = config
+            .sub_keys("session.proxy.pool", ".hosts")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_proxy_pool(config, &id))
+            .collect();
+        session.data.rewrite = config
+            .sub_keys("session.data.rewrite", "")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_rewrite_rule(config, &id, &has_rcpt_vars))
+            .collect();
+        session.data.attachments = config
+            .sub_keys("session.data.attachments", "")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_attachment_rule(config, &id, &has_rcpt_vars))
+            .collect();
+        session.data.dlp = config
+            .sub_keys("session.data.dlp", "")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| parse_dlp_rule(config, &id, &has_rcpt_vars))
+            .collect();
 
         for (value, key, token_map) in [
             (&mut session.duration, "session.duration", &has_conn_vars),
@@ -240,6 +652,11 @@ impl SessionConfig {
                 "session.connect.greeting",
                 &has_conn_vars,
             ),
+            (
+                &mut session.maintenance.allow,
+                "session.maintenance.allow",
+                &has_conn_vars,
+            ),
             (
                 &mut session.extensions.pipelining,
                 "session.extensions.pipelining",
@@ -350,6 +767,26 @@ impl SessionConfig {
                 "session.mail.is-allowed",
                 &has_sender_vars,
             ),
+            (
+                &mut session.mail.limits.messages_hour,
+                "session.mail.limits.messages.hour",
+                &has_sender_vars,
+            ),
+            (
+                &mut session.mail.limits.messages_day,
+                "session.mail.limits.messages.day",
+                &has_sender_vars,
+            ),
+            (
+                &mut session.mail.limits.recipients_hour,
+                "session.mail.limits.recipients.hour",
+                &has_sender_vars,
+            ),
+            (
+                &mut session.mail.limits.recipients_day,
+                "session.mail.limits.recipients.day",
+                &has_sender_vars,
+            ),
             (
                 &mut session.rcpt.script,
                 "session.rcpt.script",
@@ -380,11 +817,26 @@ impl SessionConfig {
                 "session.rcpt.max-recipients",
                 &has_sender_vars,
             ),
+            (
+                &mut session.rcpt.max_expansion,
+                "session.rcpt.max-expansion",
+                &has_sender_vars,
+            ),
+            (
+                &mut session.rcpt.max_expansion_depth,
+                "session.rcpt.max-expansion-depth",
+                &has_sender_vars,
+            ),
             (
                 &mut session.rcpt.rewrite,
                 "session.rcpt.rewrite",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.proxy.backend,
+                "session.proxy.backend",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.data.script,
                 "session.data.script",
@@ -405,11 +857,46 @@ impl SessionConfig {
                 "session.data.limits.received-headers",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.data.loop_delivered_to,
+                "session.data.loop.delivered-to",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.loop_dsn_chains,
+                "session.data.loop.dsn-chains",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.data.spam_filter,
                 "session.data.spam-filter",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.data.quarantine,
+                "session.data.quarantine",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.large_files.threshold,
+                "session.data.large-files.threshold",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.large_files.url,
+                "session.data.large-files.url",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.large_files.expiry,
+                "session.data.large-files.expiry",
+                &has_rcpt_vars,
+            ),
+            (
+                &mut session.data.moderation.moderator,
+                "session.data.moderation.moderator",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.data.add_received,
                 "session.data.add-headers.received",
@@ -448,6 +935,51 @@ impl SessionConfig {
         session.data.add_delivered_to = config
             .property_or_default("session.data.add-headers.delivered-to", "true")
             .unwrap_or(true);
+        session.data.large_files.min_size = config
+            .property_or_default("session.data.large-files.min-size", "1048576")
+            .unwrap_or(1024 * 1024);
+        session.data.large_files.store = config
+            .value("session.data.large-files.store")
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string());
+        session.data.moderation.external_recipients = config
+            .property_or_default("session.data.moderation.match.external-recipients", "false")
+            .unwrap_or(false);
+        session.data.moderation.attachments = config
+            .property_or_default("session.data.moderation.match.attachments", "false")
+            .unwrap_or(false);
+        session.data.moderation.dlp_hits = config
+            .property_or_default("session.data.moderation.match.dlp-hits", "false")
+            .unwrap_or(false);
+        session.data.moderation.url = config
+            .value("session.data.moderation.url")
+            .map(|v| v.trim_end_matches('/').to_string())
+            .filter(|v| !v.is_empty());
+        session.data.moderation.expiry = config
+            .property_or_default("session.data.moderation.expiry", "3d")
+            .unwrap_or(Duration::from_secs(3 * 86400));
+        if let Some(from) = config
+            .value("session.data.moderation.notify.from")
+            .filter(|v| v.contains('@'))
+        {
+            session.data.moderation.from = from.to_string();
+        }
+        for (key, window) in config
+            .values("session.maintenance.schedule")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            match parse_maintenance_window(&window) {
+                Ok(window) => session.maintenance.windows.push(window),
+                Err(err) => config.new_parse_error(key, err),
+            }
+        }
+        if let Some(message) = config
+            .value("session.maintenance.message")
+            .filter(|v| !v.is_empty())
+        {
+            session.maintenance.message = message.to_string();
+        }
         session
     }
 }
@@ -490,9 +1022,6 @@ fn parse_milter(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<M
         tls_allow_invalid_certs: config
             .property_or_default(("session.milter", id, "allow-invalid-certs"), "false")
             .unwrap_or_default(),
-        tempfail_on_error: config
-            .property_or_default(("session.milter", id, "options.tempfail-on-error"), "true")
-            .unwrap_or(true),
         max_frame_len: config
             .property_or_default(
                 ("session.milter", id, "options.max-response-size"),
@@ -516,6 +1045,8 @@ fn parse_milter(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<M
         flags_actions: config.property(("session.milter", id, "options.flags.actions")),
         flags_protocol: config.property(("session.milter", id, "options.flags.protocol")),
         run_on_stage: parse_stages(config, "session.milter", id),
+        fail_closed: parse_fail_closed(config, "session.milter", id),
+        circuit_breaker: parse_circuit_breaker(config, "session.milter", id),
     })
 }
 
@@ -579,24 +1110,598 @@ fn parse_hooks(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<MT
         tls_allow_invalid_certs: config
             .property_or_default(("session.hook", id, "allow-invalid-certs"), "false")
             .unwrap_or_default(),
-        tempfail_on_error: config
-            .property_or_default(("session.hook", id, "options.tempfail-on-error"), "true")
-            .unwrap_or(true),
         run_on_stage: parse_stages(config, "session.hook", id),
+        fail_closed: parse_fail_closed(config, "session.hook", id),
+        circuit_breaker: parse_circuit_breaker(config, "session.hook", id),
         max_response_size: config
             .property_or_default(
                 ("session.hook", id, "options.max-response-size"),
                 "52428800",
             )
             .unwrap_or(52428800),
+        full_message: config
+            .property_or_default(("session.hook", id, "options.full-message"), "false")
+            .unwrap_or_default(),
+        headers,
+    })
+}
+
+fn parse_antivirus(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<Antivirus> {
+    let hostname = config
+        .value_require(("session.antivirus", id, "hostname"))?
+        .to_string();
+    let port = config
+        .property_or_default(("session.antivirus", id, "port"), "3310")
+        .unwrap_or(3310);
+
+    Some(Antivirus {
+        enable: IfBlock::try_parse(config, ("session.antivirus", id, "enable"), token_map)
+            .unwrap_or_else(|| {
+                IfBlock::new::<()>(format!("session.antivirus.{id}.enable"), [], "false")
+            }),
+        id: id.to_string(),
+        addrs: format!("{}:{}", hostname, port)
+            .to_socket_addrs()
+            .map_err(|err| {
+                config.new_build_error(
+                    ("session.antivirus", id, "hostname"),
+                    format!("Unable to resolve antivirus hostname {hostname}: {err}"),
+                )
+            })
+            .ok()?
+            .collect(),
+        hostname,
+        port,
+        timeout_connect: config
+            .property_or_default(("session.antivirus", id, "timeout.connect"), "10s")
+            .unwrap_or_else(|| Duration::from_secs(10)),
+        timeout_scan: config
+            .property_or_default(("session.antivirus", id, "timeout.scan"), "60s")
+            .unwrap_or_else(|| Duration::from_secs(60)),
+        max_size: config
+            .property_or_default(("session.antivirus", id, "options.max-size"), "26214400")
+            .unwrap_or(26214400),
+        chunk_size: config
+            .property_or_default::<usize>(("session.antivirus", id, "options.chunk-size"), "65536")
+            .unwrap_or(65536)
+            .max(1),
+        pool_size: config
+            .property_or_default(("session.antivirus", id, "pool.max-connections"), "10")
+            .unwrap_or(10),
+        pool_idle_timeout: config
+            .property_or_default(("session.antivirus", id, "pool.idle-timeout"), "25s")
+            .unwrap_or_else(|| Duration::from_secs(25)),
+        pool: Default::default(),
+        cache_ttl: config
+            .property_or_default::<Option<Duration>>(("session.antivirus", id, "cache.ttl"), "1h")
+            .unwrap_or_else(|| Some(Duration::from_secs(3600))),
+        action_infected: config
+            .property_or_default(("session.antivirus", id, "action.infected"), "reject")
+            .unwrap_or(AntivirusAction::Reject),
+        action_error: config
+            .property_or_default(("session.antivirus", id, "action.error"), "tempfail")
+            .unwrap_or(AntivirusAction::TempFail),
+    })
+}
+
+fn parse_sandbox(config: &mut Config, token_map: &TokenMap) -> Option<Sandbox> {
+    let url = config.value("session.sandbox.url")?.to_string();
+    let mut headers = HeaderMap::new();
+
+    for (header, value) in config
+        .values("session.sandbox.headers")
+        .map(|(_, v)| {
+            if let Some((k, v)) = v.split_once(':') {
+                Ok((
+                    HeaderName::from_str(k.trim()).map_err(|err| {
+                        format!(
+                            "Invalid header found in property \"session.sandbox.headers\": {err}",
+                        )
+                    })?,
+                    HeaderValue::from_str(v.trim()).map_err(|err| {
+                        format!(
+                            "Invalid header found in property \"session.sandbox.headers\": {err}",
+                        )
+                    })?,
+                ))
+            } else {
+                Err(format!(
+                    "Invalid header found in property \"session.sandbox.headers\": {v}",
+                ))
+            }
+        })
+        .collect::<Result<Vec<(HeaderName, HeaderValue)>, String>>()
+        .map_err(|e| config.new_parse_error("session.sandbox.headers", e))
+        .unwrap_or_default()
+    {
+        headers.insert(header, value);
+    }
+
+    if let (Some(name), Some(secret)) = (
+        config.value("session.sandbox.auth.username"),
+        config.value("session.sandbox.auth.secret"),
+    ) {
+        headers.insert(
+            AUTHORIZATION,
+            format!("Basic {}", STANDARD.encode(format!("{}:{}", name, secret)))
+                .parse()
+                .unwrap(),
+        );
+    }
+
+    let mut extensions = config
+        .values("session.sandbox.extensions")
+        .map(|(_, v)| v.trim().trim_start_matches('.').to_ascii_lowercase())
+        .collect::<AHashSet<_>>();
+    if extensions.is_empty() {
+        extensions = [
+            "exe", "dll", "scr", "com", "bat", "cmd", "ps1", "vbs", "js", "jse", "wsf", "hta",
+            "jar", "msi", "lnk", "iso", "img", "docm", "xlsm", "pptm", "pdf", "zip", "rar", "7z",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    }
+
+    Some(Sandbox {
+        enable: IfBlock::try_parse(config, "session.sandbox.enable", token_map)
+            .unwrap_or_else(|| IfBlock::new::<()>("session.sandbox.enable", [], "false")),
+        url: url.trim_end_matches('/').to_string(),
         headers,
+        timeout: config
+            .property_or_default("session.sandbox.timeout", "30s")
+            .unwrap_or_else(|| Duration::from_secs(30)),
+        tls_allow_invalid_certs: config
+            .property_or_default("session.sandbox.allow-invalid-certs", "false")
+            .unwrap_or_default(),
+        extensions,
+        max_size: config
+            .property_or_default("session.sandbox.max-size", "52428800")
+            .unwrap_or(52428800),
+        mode: config
+            .property_or_default("session.sandbox.mode", "hold")
+            .unwrap_or(SandboxMode::Hold),
+        verdict_timeout: config
+            .property_or_default("session.sandbox.verdict.timeout", "30m")
+            .unwrap_or_else(|| Duration::from_secs(30 * 60)),
+        poll_interval: config
+            .property_or_default::<Duration>("session.sandbox.verdict.poll-interval", "30s")
+            .unwrap_or_else(|| Duration::from_secs(30))
+            .max(Duration::from_secs(1)),
+        action_malicious: config
+            .property_or_default("session.sandbox.action.malicious", "quarantine")
+            .unwrap_or(SandboxAction::Quarantine),
+        action_timeout: config
+            .property_or_default("session.sandbox.action.timeout", "release")
+            .unwrap_or(SandboxAction::Release),
+    })
+}
+
+fn parse_account_anomaly(config: &mut Config, token_map: &TokenMap) -> Option<AccountAnomaly> {
+    let enable = IfBlock::try_parse(config, "session.anomaly.enable", token_map)?;
+    let volume = if config
+        .property_or_default("session.anomaly.volume.enable", "true")
+        .unwrap_or(true)
+    {
+        Some(VolumeBaseline {
+            period: config
+                .property_or_default::<Duration>("session.anomaly.volume.period", "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600))
+                .max(Duration::from_secs(60)),
+            min_volume: config
+                .property_or_default("session.anomaly.volume.min-recipients", "100")
+                .unwrap_or(100),
+            factor: config
+                .property_or_default::<f64>("session.anomaly.volume.factor", "5.0")
+                .unwrap_or(5.0)
+                .max(1.0),
+            samples: config
+                .property_or_default::<u64>("session.anomaly.volume.samples", "24")
+                .unwrap_or(24)
+                .max(1),
+        })
+    } else {
+        None
+    };
+
+    let mut actions = config
+        .properties::<AnomalyAction>("session.anomaly.action")
+        .into_iter()
+        .map(|(_, action)| action)
+        .collect::<AHashSet<_>>();
+    if actions.is_empty() {
+        actions = [AnomalyAction::Hold, AnomalyAction::Notify]
+            .into_iter()
+            .collect();
+    }
+
+    let anomaly = AccountAnomaly {
+        enable,
+        travel_window: config
+            .property_or_default::<Option<Duration>>("session.anomaly.travel.window", "4h")
+            .unwrap_or_default(),
+        volume,
+        actions,
+        notify: config
+            .values("session.anomaly.notify.to")
+            .map(|(_, v)| v.trim().to_lowercase())
+            .filter(|v| v.contains('@'))
+            .collect(),
+        notify_from: config
+            .value("session.anomaly.notify.from")
+            .filter(|v| v.contains('@'))
+            .unwrap_or("MAILER-DAEMON@localhost")
+            .to_string(),
+    };
+
+    if anomaly.actions.contains(&AnomalyAction::Notify) && anomaly.notify.is_empty() {
+        config.new_build_warning(
+            "session.anomaly.notify.to",
+            "Anomaly detection with notify action does not define any administrator addresses",
+        );
+    }
+
+    Some(anomaly)
+}
+
+fn parse_proxy_pool(config: &mut Config, id: &str) -> Option<ProxyPool> {
+    let hosts = config
+        .values(("session.proxy.pool", id, "hosts"))
+        .map(|(_, host)| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .collect::<Vec<_>>();
+    if hosts.is_empty() {
+        config.new_parse_error(
+            ("session.proxy.pool", id, "hosts"),
+            "At least one backend host is required",
+        );
+        return None;
+    }
+
+    Some(ProxyPool {
+        id: id.to_string(),
+        hosts,
+        forward: config
+            .property_or_default(("session.proxy.pool", id, "forward"), "xclient")
+            .unwrap_or(ProxyForward::Xclient),
+        timeout_connect: config
+            .property_or_default(("session.proxy.pool", id, "timeout.connect"), "30s")
+            .unwrap_or_else(|| Duration::from_secs(30)),
+        timeout_command: config
+            .property_or_default(("session.proxy.pool", id, "timeout.command"), "5m")
+            .unwrap_or_else(|| Duration::from_secs(300)),
     })
 }
 
+fn parse_rewrite_rule(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<RewriteRule> {
+    let mut headers_add = Vec::new();
+    let mut headers_replace = Vec::new();
+    let mut headers_remove = Vec::new();
+    let mut invalid = Vec::new();
+
+    for (key, list) in [
+        ("headers.add", &mut headers_add),
+        ("headers.replace", &mut headers_replace),
+    ] {
+        for (_, value) in config.values(("session.data.rewrite", id, key)) {
+            if let Some((name, value)) = value
+                .split_once(':')
+                .filter(|(name, _)| is_valid_header_name(name.trim()))
+            {
+                list.push((name.trim().to_string(), value.trim().to_string()));
+            } else {
+                invalid.push((key, value.to_string()));
+            }
+        }
+    }
+    for (_, value) in config.values(("session.data.rewrite", id, "headers.remove")) {
+        if is_valid_header_name(value.trim()) {
+            headers_remove.push(value.trim().to_string());
+        } else {
+            invalid.push(("headers.remove", value.to_string()));
+        }
+    }
+    for (key, value) in invalid {
+        config.new_parse_error(
+            ("session.data.rewrite", id, key),
+            format!("Invalid header {value:?}"),
+        );
+    }
+
+    let rule = RewriteRule {
+        id: id.to_string(),
+        enable: IfBlock::try_parse(config, ("session.data.rewrite", id, "enable"), token_map)
+            .unwrap_or_else(|| {
+                IfBlock::new::<()>(format!("session.data.rewrite.{id}.enable"), [], "false")
+            }),
+        headers_add,
+        headers_replace,
+        headers_remove,
+        from_display_name: IfBlock::try_parse(
+            config,
+            ("session.data.rewrite", id, "from.display-name"),
+            token_map,
+        ),
+        footer_text: config
+            .value(("session.data.rewrite", id, "footer.text"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+        footer_html: config
+            .value(("session.data.rewrite", id, "footer.html"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+    };
+
+    if rule.headers_add.is_empty()
+        && rule.headers_replace.is_empty()
+        && rule.headers_remove.is_empty()
+        && rule.from_display_name.is_none()
+        && rule.footer_text.is_none()
+        && rule.footer_html.is_none()
+    {
+        config.new_build_warning(
+            ("session.data.rewrite", id),
+            "Rewrite rule does not define any actions",
+        );
+        None
+    } else {
+        Some(rule)
+    }
+}
+
+fn parse_attachment_rule(
+    config: &mut Config,
+    id: &str,
+    token_map: &TokenMap,
+) -> Option<AttachmentRule> {
+    let action = match config
+        .value(("session.data.attachments", id, "action"))
+        .unwrap_or("replace")
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "drop" => AttachmentAction::Drop,
+        "rename" => AttachmentAction::Rename {
+            suffix: config
+                .value(("session.data.attachments", id, "rename.suffix"))
+                .filter(|v| !v.is_empty())
+                .unwrap_or(".disarmed")
+                .to_string(),
+        },
+        "replace" => AttachmentAction::Replace {
+            text: config
+                .value(("session.data.attachments", id, "replace.text"))
+                .filter(|v| !v.is_empty())
+                .unwrap_or("This attachment was removed by the mail server policy.")
+                .to_string(),
+        },
+        action => {
+            config.new_parse_error(
+                ("session.data.attachments", id, "action"),
+                format!("Invalid attachment action {action:?}"),
+            );
+            return None;
+        }
+    };
+
+    let rule = AttachmentRule {
+        id: id.to_string(),
+        enable: IfBlock::try_parse(
+            config,
+            ("session.data.attachments", id, "enable"),
+            token_map,
+        )
+        .unwrap_or_else(|| {
+            IfBlock::new::<()>(format!("session.data.attachments.{id}.enable"), [], "false")
+        }),
+        extensions: config
+            .values(("session.data.attachments", id, "match.extensions"))
+            .map(|(_, v)| v.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        content_types: config
+            .values(("session.data.attachments", id, "match.content-types"))
+            .map(|(_, v)| v.trim().to_ascii_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        file_types: config
+            .values(("session.data.attachments", id, "match.file-types"))
+            .map(|(_, v)| v.trim().to_ascii_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        archive_depth: config
+            .property_or_default(("session.data.attachments", id, "archive.max-depth"), "2")
+            .unwrap_or(2),
+        action,
+        notify: config
+            .value(("session.data.attachments", id, "notify.text"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+    };
+
+    if rule.extensions.is_empty() && rule.content_types.is_empty() && rule.file_types.is_empty() {
+        config.new_build_warning(
+            ("session.data.attachments", id),
+            "Attachment rule does not define any match conditions",
+        );
+        None
+    } else {
+        Some(rule)
+    }
+}
+
+fn parse_address_normalization(config: &mut Config, id: &str) -> Option<AddressNormalization> {
+    let pattern = config
+        .value_require(("session.rcpt.normalize", id, "match"))?
+        .to_string();
+    let pattern = match Regex::new(&pattern) {
+        Ok(pattern) => pattern,
+        Err(err) => {
+            config.new_parse_error(
+                ("session.rcpt.normalize", id, "match"),
+                format!("Invalid regular expression: {err}"),
+            );
+            return None;
+        }
+    };
+
+    Some(AddressNormalization {
+        domain: config
+            .value(("session.rcpt.normalize", id, "domain"))
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty()),
+        pattern,
+        replace: config
+            .value(("session.rcpt.normalize", id, "replace"))
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+fn parse_dlp_rule(config: &mut Config, id: &str, token_map: &TokenMap) -> Option<DlpRule> {
+    let action = match config
+        .value(("session.data.dlp", id, "action"))
+        .unwrap_or("block")
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "block" => DlpAction::Block {
+            reason: config
+                .value(("session.data.dlp", id, "block.reason"))
+                .filter(|v| !v.is_empty())
+                .unwrap_or("Message content violates the data loss prevention policy.")
+                .to_string(),
+        },
+        "quarantine" => DlpAction::Quarantine,
+        "notify" => DlpAction::Notify,
+        action => {
+            config.new_parse_error(
+                ("session.data.dlp", id, "action"),
+                format!("Invalid DLP action {action:?}"),
+            );
+            return None;
+        }
+    };
+
+    let mut regex = Vec::new();
+    for (key, value) in config
+        .values(("session.data.dlp", id, "match.regex"))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>()
+    {
+        match Regex::new(&value) {
+            Ok(value) => regex.push(value),
+            Err(err) => {
+                config.new_parse_error(key, format!("Invalid regular expression: {err}"));
+            }
+        }
+    }
+
+    let rule = DlpRule {
+        id: id.to_string(),
+        enable: IfBlock::try_parse(config, ("session.data.dlp", id, "enable"), token_map)
+            .unwrap_or_else(|| {
+                IfBlock::new::<()>(format!("session.data.dlp.{id}.enable"), [], "false")
+            }),
+        regex,
+        keywords: config
+            .values(("session.data.dlp", id, "match.keywords"))
+            .map(|(_, v)| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        dictionary: config
+            .value(("session.data.dlp", id, "match.dictionary"))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string()),
+        attachments: config
+            .property_or_default(("session.data.dlp", id, "scan-attachments"), "true")
+            .unwrap_or(true),
+        threshold: config
+            .property_or_default::<usize>(("session.data.dlp", id, "threshold"), "1")
+            .unwrap_or(1)
+            .max(1),
+        action,
+        notify: config
+            .values(("session.data.dlp", id, "notify.to"))
+            .map(|(_, v)| v.trim().to_lowercase())
+            .filter(|v| v.contains('@'))
+            .collect(),
+        notify_from: config
+            .value(("session.data.dlp", id, "notify.from"))
+            .filter(|v| v.contains('@'))
+            .unwrap_or("MAILER-DAEMON@localhost")
+            .to_string(),
+    };
+
+    if rule.regex.is_empty() && rule.keywords.is_empty() && rule.dictionary.is_none() {
+        config.new_build_warning(
+            ("session.data.dlp", id),
+            "DLP rule does not define any match conditions",
+        );
+        None
+    } else if rule.action == DlpAction::Notify && rule.notify.is_empty() {
+        config.new_build_warning(
+            ("session.data.dlp", id, "notify.to"),
+            "DLP rule with notify action does not define any auditor addresses",
+        );
+        None
+    } else {
+        Some(rule)
+    }
+}
+
+fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|ch| ch.is_ascii_graphic() && ch != b':')
+}
+
 fn parse_stages(config: &mut Config, prefix: &str, id: &str) -> AHashSet<Stage> {
+    let mut stages = parse_stage_list(config, prefix, id, "stages");
+    if stages.is_empty() {
+        stages.insert(Stage::Data);
+    }
+
+    stages
+}
+
+fn parse_fail_closed(config: &mut Config, prefix: &str, id: &str) -> AHashSet<Stage> {
+    if config
+        .values((prefix, id, "options.fail-closed"))
+        .next()
+        .is_some()
+    {
+        parse_stage_list(config, prefix, id, "options.fail-closed")
+    } else if config
+        .property_or_default((prefix, id, "options.tempfail-on-error"), "true")
+        .unwrap_or(true)
+    {
+        [
+            Stage::Connect,
+            Stage::Ehlo,
+            Stage::Auth,
+            Stage::Mail,
+            Stage::Rcpt,
+            Stage::Data,
+        ]
+        .into_iter()
+        .collect()
+    } else {
+        AHashSet::default()
+    }
+}
+
+fn parse_circuit_breaker(config: &mut Config, prefix: &str, id: &str) -> Option<CircuitBreaker> {
+    Some(CircuitBreaker {
+        errors: config.property((prefix, id, "circuit-breaker.errors"))?,
+        open_for: config
+            .property_or_default((prefix, id, "circuit-breaker.open-for"), "1m")
+            .unwrap_or_else(|| Duration::from_secs(60)),
+    })
+}
+
+fn parse_stage_list(config: &mut Config, prefix: &str, id: &str, key: &str) -> AHashSet<Stage> {
     let mut stages = AHashSet::default();
     let mut invalid = Vec::new();
-    for (_, value) in config.values((prefix, id, "stages")) {
+    for (_, value) in config.values((prefix, id, key)) {
         let value = value.to_ascii_lowercase();
         let state = match value.as_str() {
             "connect" => Stage::Connect,
@@ -615,15 +1720,11 @@ fn parse_stages(config: &mut Config, prefix: &str, id: &str) -> AHashSet<Stage>
 
     if !invalid.is_empty() {
         config.new_parse_error(
-            (prefix, id, "stages"),
+            (prefix, id, key),
             format!("Invalid stages: {}", invalid.join(", ")),
         );
     }
 
-    if stages.is_empty() {
-        stages.insert(Stage::Data);
-    }
-
     stages
 }
 
@@ -646,6 +1747,18 @@ impl Default for SessionConfig {
                     "config_get('server.hostname') + ' Stalwart ESMTP at your service'",
                 ),
             },
+            maintenance: Maintenance {
+                allow: IfBlock::new::<()>(
+                    "session.maintenance.allow",
+                    [
+                        ("local_port != 25", "true"),
+                        ("remote_ip == '127.0.0.1' || remote_ip == '::1'", "true"),
+                    ],
+                    "false",
+                ),
+                windows: Vec::new(),
+                message: "Service temporarily unavailable due to scheduled maintenance, please try again later".to_string(),
+            },
             ehlo: Ehlo {
                 script: IfBlock::empty("session.ehlo.script"),
                 require: IfBlock::new::<()>("session.ehlo.require", [], "true"),
@@ -695,6 +1808,12 @@ impl Default for SessionConfig {
                     [],
                     "!is_empty(authenticated_as) || !key_exists('blocked-domains', sender_domain)",
                 ),
+                limits: SendingLimits {
+                    messages_hour: IfBlock::empty("session.mail.limits.messages.hour"),
+                    messages_day: IfBlock::empty("session.mail.limits.messages.day"),
+                    recipients_hour: IfBlock::empty("session.mail.limits.recipients.hour"),
+                    recipients_day: IfBlock::empty("session.mail.limits.recipients.day"),
+                },
             },
             rcpt: Rcpt {
                 script: IfBlock::empty("session.rcpt.script"),
@@ -715,12 +1834,21 @@ impl Default for SessionConfig {
                 errors_max: IfBlock::new::<()>("session.rcpt.errors.total", [], "5"),
                 errors_wait: IfBlock::new::<()>("session.rcpt.errors.wait", [], "5s"),
                 max_recipients: IfBlock::new::<()>("session.rcpt.max-recipients", [], "100"),
+                max_expansion: IfBlock::new::<()>("session.rcpt.max-expansion", [], "1000"),
+                max_expansion_depth: IfBlock::new::<()>(
+                    "session.rcpt.max-expansion-depth",
+                    [],
+                    "5",
+                ),
                 catch_all: AddressMapping::Enable,
                 subaddressing: AddressMapping::Enable,
+                delimiters: vec!['+'],
+                normalize: vec![],
             },
             data: Data {
                 script: IfBlock::empty("session.data.script"),
                 spam_filter: IfBlock::new::<()>("session.data.spam-filter", [], "true"),
+                quarantine: IfBlock::new::<()>("session.data.quarantine", [], "false"),
                 max_messages: IfBlock::new::<()>("session.data.limits.messages", [], "10"),
                 max_message_size: IfBlock::new::<()>("session.data.limits.size", [], "104857600"),
                 max_received_headers: IfBlock::new::<()>(
@@ -728,6 +1856,8 @@ impl Default for SessionConfig {
                     [],
                     "50",
                 ),
+                loop_delivered_to: IfBlock::new::<()>("session.data.loop.delivered-to", [], "true"),
+                loop_dsn_chains: IfBlock::new::<()>("session.data.loop.dsn-chains", [], "true"),
                 add_received: IfBlock::new::<()>(
                     "session.data.add-headers.received",
                     [("local_port == 25", "true")],
@@ -759,6 +1889,29 @@ impl Default for SessionConfig {
                     "false",
                 ),
                 add_delivered_to: false,
+                rewrite: Default::default(),
+                attachments: Default::default(),
+                dlp: Default::default(),
+                moderation: Moderation {
+                    moderator: IfBlock::new::<()>("session.data.moderation.moderator", [], "false"),
+                    external_recipients: false,
+                    attachments: false,
+                    dlp_hits: false,
+                    url: None,
+                    expiry: Duration::from_secs(3 * 86400),
+                    from: "MAILER-DAEMON@localhost".to_string(),
+                },
+                large_files: LargeFiles {
+                    threshold: IfBlock::new::<()>(
+                        "session.data.large-files.threshold",
+                        [],
+                        "26214400",
+                    ),
+                    url: IfBlock::new::<()>("session.data.large-files.url", [], "false"),
+                    expiry: IfBlock::new::<()>("session.data.large-files.expiry", [], "7d"),
+                    min_size: 1024 * 1024,
+                    store: None,
+                },
             },
             extensions: Extensions {
                 pipelining: IfBlock::new::<()>("session.extensions.pipelining", [], "true"),
@@ -799,6 +1952,99 @@ impl Default for SessionConfig {
             mta_sts_policy: None,
             milters: Default::default(),
             hooks: Default::default(),
+            antivirus: Default::default(),
+            sandbox: None,
+            anomaly: None,
+            proxy: Proxy {
+                backend: IfBlock::empty("session.proxy.backend"),
+                pools: Default::default(),
+            },
+        }
+    }
+}
+
+fn parse_maintenance_window(value: &str) -> Result<(u64, u64), String> {
+    let (start, end) = value
+        .split_once('/')
+        .ok_or_else(|| format!("Invalid maintenance window {value:?}, expected start/end"))?;
+    let mut window = [0u64; 2];
+    for (pos, timestamp) in [start, end].into_iter().enumerate() {
+        window[pos] = mail_parser::DateTime::parse_rfc3339(timestamp.trim())
+            .filter(|dt| dt.is_valid())
+            .map(|dt| dt.to_timestamp() as u64)
+            .ok_or_else(|| format!("Invalid RFC3339 timestamp {timestamp:?}"))?;
+    }
+    if window[0] < window[1] {
+        Ok((window[0], window[1]))
+    } else {
+        Err(format!(
+            "Invalid maintenance window {value:?}, start must precede end"
+        ))
+    }
+}
+
+impl ParseValue for ProxyForward {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "xclient" => Ok(ProxyForward::Xclient),
+            "proxy-protocol" => Ok(ProxyForward::ProxyProtocol),
+            "none" => Ok(ProxyForward::None),
+            _ => Err(format!("Invalid proxy forwarding method {value:?}")),
+        }
+    }
+}
+
+impl ParseValue for AntivirusAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "accept" => Ok(AntivirusAction::Accept),
+            "reject" => Ok(AntivirusAction::Reject),
+            "tempfail" => Ok(AntivirusAction::TempFail),
+            "discard" => Ok(AntivirusAction::Discard),
+            "quarantine" => Ok(AntivirusAction::Quarantine),
+            "tag" => Ok(AntivirusAction::Tag),
+            _ => Err(format!("Invalid antivirus action {value:?}")),
+        }
+    }
+}
+
+impl ParseValue for SandboxMode {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "hold" => Ok(SandboxMode::Hold),
+            "deliver" => Ok(SandboxMode::Deliver),
+            _ => Err(format!("Invalid sandbox mode {value:?}")),
+        }
+    }
+}
+
+impl SendingLimits {
+    pub fn is_empty(&self) -> bool {
+        self.messages_hour.is_empty()
+            && self.messages_day.is_empty()
+            && self.recipients_hour.is_empty()
+            && self.recipients_day.is_empty()
+    }
+}
+
+impl ParseValue for AnomalyAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "hold" => Ok(AnomalyAction::Hold),
+            "reauth" => Ok(AnomalyAction::Reauth),
+            "notify" => Ok(AnomalyAction::Notify),
+            _ => Err(format!("Invalid anomaly action {value:?}")),
+        }
+    }
+}
+
+impl ParseValue for SandboxAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "release" => Ok(SandboxAction::Release),
+            "tag" => Ok(SandboxAction::Tag),
+            "quarantine" => Ok(SandboxAction::Quarantine),
+            _ => Err(format!("Invalid sandbox action {value:?}")),
         }
     }
 }
@@ -813,9 +2059,10 @@ impl ParseValue for Mechanism {
             "PLAIN" => AUTH_PLAIN,
             "XOAUTH2" => AUTH_XOAUTH2,
             "OAUTHBEARER" => AUTH_OAUTHBEARER,
-            /*"SCRAM-SHA-256-PLUS" => AUTH_SCRAM_SHA_256_PLUS,
+            "SCRAM-SHA-256-PLUS" => AUTH_SCRAM_SHA_256_PLUS,
             "SCRAM-SHA-256" => AUTH_SCRAM_SHA_256,
-            "SCRAM-SHA-1-PLUS" => AUTH_SCRAM_SHA_1_PLUS,
+            "EXTERNAL" => AUTH_EXTERNAL,
+            /*"SCRAM-SHA-1-PLUS" => AUTH_SCRAM_SHA_1_PLUS,
             "SCRAM-SHA-1" => AUTH_SCRAM_SHA_1,
             "XOAUTH" => AUTH_XOAUTH,
             "9798-M-DSA-SHA1" => AUTH_9798_M_DSA_SHA1,
@@ -828,7 +2075,6 @@ impl ParseValue for Mechanism {
             "EAP-AES128-PLUS" => AUTH_EAP_AES128_PLUS,
             "ECDH-X25519-CHALLENGE" => AUTH_ECDH_X25519_CHALLENGE,
             "ECDSA-NIST256P-CHALLENGE" => AUTH_ECDSA_NIST256P_CHALLENGE,
-            "EXTERNAL" => AUTH_EXTERNAL,
             "GS2-KRB5" => AUTH_GS2_KRB5,
             "GS2-KRB5-PLUS" => AUTH_GS2_KRB5_PLUS,
             "GSS-SPNEGO" => AUTH_GSS_SPNEGO,
@@ -891,7 +2137,10 @@ impl ConstantValue for Mechanism {
             .add_constant("login", Mechanism(AUTH_LOGIN))
             .add_constant("plain", Mechanism(AUTH_PLAIN))
             .add_constant("xoauth2", Mechanism(AUTH_XOAUTH2))
-            .add_constant("oauthbearer", Mechanism(AUTH_OAUTHBEARER));
+            .add_constant("oauthbearer", Mechanism(AUTH_OAUTHBEARER))
+            .add_constant("scram_sha_256", Mechanism(AUTH_SCRAM_SHA_256))
+            .add_constant("scram_sha_256_plus", Mechanism(AUTH_SCRAM_SHA_256_PLUS))
+            .add_constant("external", Mechanism(AUTH_EXTERNAL));
     }
 }
 
@@ -942,3 +2191,44 @@ impl ConstantValue for MtPriority {
             .add_constant("nsep", MtPriority::Nsep);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_circuit_breaker() {
+        let circuit_breaker = CircuitBreaker {
+            errors: Rate {
+                requests: 2,
+                period: Duration::from_secs(60),
+            },
+            open_for: Duration::from_secs(30),
+        };
+        let mut health = FilterHealth::default();
+
+        // Successful requests do not consume the error budget
+        assert!(!health.record(3, false, Some(&circuit_breaker), 1000));
+        assert!(!health.record(20_000, false, Some(&circuit_breaker), 1000));
+        assert_eq!(health.latency_buckets[0], 1);
+        assert_eq!(health.latency_buckets[FILTER_LATENCY_BUCKETS.len()], 1);
+
+        // The circuit opens once the error budget is exhausted
+        assert!(!health.record(10, true, Some(&circuit_breaker), 1000));
+        assert!(!health.record(10, true, Some(&circuit_breaker), 1001));
+        assert!(health.record(10, true, Some(&circuit_breaker), 1002));
+        assert_eq!(health.state(1010), FilterState::CircuitOpen);
+        assert_eq!(health.state(1032), FilterState::Available);
+        assert_eq!(health.requests, 5);
+        assert_eq!(health.failures, 3);
+
+        // Errors outside the window start a new budget
+        assert!(!health.record(10, true, Some(&circuit_breaker), 1100));
+        assert!(!health.record(10, true, Some(&circuit_breaker), 1101));
+        assert!(!health.record(10, true, Some(&circuit_breaker), 1200));
+
+        // Disabled filters are reported as such
+        health.disabled_until = 1300;
+        assert_eq!(health.state(1250), FilterState::Disabled);
+    }
+}
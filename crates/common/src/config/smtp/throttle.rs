@@ -77,14 +77,39 @@ fn parse_queue_rate_limiter_item(
         }
     }
 
+    let rate = config
+        .property_require::<Rate>((prefix.as_str(), "rate"))
+        .filter(|r| r.requests > 0)?;
+    let scope = match config
+        .value((prefix.as_str(), "scope"))
+        .unwrap_or("cluster")
+        .to_string()
+        .as_str()
+    {
+        "node" => RateLimiterScope::Node,
+        "cluster" => RateLimiterScope::Cluster {
+            store: config
+                .value((prefix.as_str(), "store"))
+                .map(|store| store.to_string()),
+            batch: config
+                .property_or_default::<u64>((prefix.as_str(), "cache"), "1")
+                .unwrap_or(1)
+                .clamp(1, rate.requests),
+        },
+        scope => {
+            let err = format!("Invalid rate limiter scope {scope:?}");
+            config.new_parse_error((prefix.as_str(), "scope"), err);
+            RateLimiterScope::default()
+        }
+    };
+
     Some(QueueRateLimiter {
         id: rate_limiter_id.to_string(),
         expr: Expression::try_parse(config, (prefix.as_str(), "match"), token_map)
             .unwrap_or_default(),
         keys,
-        rate: config
-            .property_require::<Rate>((prefix.as_str(), "rate"))
-            .filter(|r| r.requests > 0)?,
+        rate,
+        scope,
     })
 }
 
@@ -15,7 +15,7 @@ use nlp::bayes::BayesClassifier;
 use tokio::net::lookup_host;
 use utils::{
     cache::CacheItemWeight,
-    config::{Config, utils::ParseValue},
+    config::{Config, Rate, utils::ParseValue},
     glob::GlobMap,
 };
 
@@ -30,6 +30,7 @@ pub struct SpamFilterConfig {
     pub lists: SpamFilterLists,
     pub pyzor: Option<PyzorConfig>,
     pub reputation: Option<ReputationConfig>,
+    pub url_reputation: Option<UrlReputationConfig>,
     pub bayes: Option<BayesConfig>,
     pub scores: SpamFilterScoreConfig,
     pub expiry: SpamFilterExpiryConfig,
@@ -51,6 +52,44 @@ pub struct SpamFilterScoreConfig {
     pub spam_threshold: f64,
 }
 
+/// Spam filter settings overridden at the tenant, domain or account level.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpamFilterOverrides {
+    pub enabled: Option<bool>,
+    pub reject_threshold: Option<f64>,
+    pub discard_threshold: Option<f64>,
+    pub spam_threshold: Option<f64>,
+    pub modules: Vec<(String, bool)>,
+    pub add_status_header: Option<bool>,
+    pub add_result_header: Option<bool>,
+}
+
+pub const SPAM_FILTER_MODULES: &[&str] = &[
+    "ip",
+    "dmarc",
+    "ehlo",
+    "headers",
+    "received",
+    "message-id",
+    "date",
+    "subject",
+    "from",
+    "reply-to",
+    "recipient",
+    "domain",
+    "url",
+    "mime",
+    "html",
+    "llm",
+    "trusted-reply",
+    "spam-trap",
+    "pyzor",
+    "bayes",
+    "rules",
+    "reputation",
+    "url-reputation",
+];
+
 #[derive(Debug, Clone, Default)]
 pub struct SpamFilterExpiryConfig {
     pub grey_list: Option<u64>,
@@ -63,6 +102,8 @@ pub struct DnsBlConfig {
     pub max_domain_checks: usize,
     pub max_email_checks: usize,
     pub max_url_checks: usize,
+    pub max_failures: u32,
+    pub disable_for: Duration,
     pub servers: Vec<DnsBlServer>,
 }
 
@@ -92,6 +133,28 @@ pub struct BayesConfig {
     pub account_score_spam: f64,
     pub account_score_ham: f64,
     pub account_classify: bool,
+    pub account_train_global: bool,
+    pub account_train_rate: Option<Rate>,
+    pub account_train_dedup: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UrlReputationConfig {
+    pub feeds: Vec<UrlReputationFeed>,
+    pub max_urls: usize,
+    pub cache_listed: u64,
+    pub cache_clean: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct UrlReputationFeed {
+    pub id: String,
+    pub store: String,
+    pub tag: String,
+    pub score: f64,
+    pub match_url: bool,
+    pub match_host: bool,
+    pub match_domain: bool,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -171,12 +234,22 @@ pub struct DnsBlServer {
     pub id: String,
     pub zone: IfBlock,
     pub scope: Element,
-    pub tags: IfBlock,
+    pub tags: Option<IfBlock>,
+    pub return_codes: Vec<(IpAddr, String)>,
+    pub weight: f64,
+    pub timeout: Option<Duration>,
+}
+
+/// Runtime health of a DNSBL server, used to skip lists that stop responding.
+#[derive(Debug, Clone, Default)]
+pub struct DnsBlStatus {
+    pub failures: u32,
+    pub disabled_until: u64,
 }
 
 impl SpamFilterConfig {
     pub async fn parse(config: &mut Config) -> Self {
-        SpamFilterConfig {
+        let mut spam = SpamFilterConfig {
             enabled: config
                 .property_or_default("spam-filter.enable", "true")
                 .unwrap_or(true),
@@ -188,11 +261,25 @@ impl SpamFilterConfig {
             lists: SpamFilterLists::parse(config),
             pyzor: PyzorConfig::parse(config).await,
             reputation: ReputationConfig::parse(config),
+            url_reputation: UrlReputationConfig::parse(config),
             bayes: BayesConfig::parse(config),
             scores: SpamFilterScoreConfig::parse(config),
             expiry: SpamFilterExpiryConfig::parse(config),
             headers: SpamFilterHeaderConfig::parse(config),
+        };
+
+        // Feed tags are scored unless an explicit score was configured
+        if let Some(url_reputation) = &spam.url_reputation {
+            for feed in &url_reputation.feeds {
+                if spam.lists.scores.get(&feed.tag).is_none() {
+                    spam.lists
+                        .scores
+                        .insert(&feed.tag, SpamFilterAction::Allow(feed.score));
+                }
+            }
         }
+
+        spam
     }
 }
 
@@ -289,6 +376,12 @@ impl DnsBlConfig {
             max_url_checks: config
                 .property_or_default("spam-filter.dnsbl.max-check.url", "50")
                 .unwrap_or(20),
+            max_failures: config
+                .property_or_default("spam-filter.dnsbl.health.max-failures", "5")
+                .unwrap_or(5),
+            disable_for: config
+                .property_or_default("spam-filter.dnsbl.health.disable-for", "10m")
+                .unwrap_or(Duration::from_secs(600)),
             servers,
         }
     }
@@ -308,6 +401,43 @@ impl DnsBlServer {
         let scope =
             config.property_require::<Element>(("spam-filter.dnsbl.server", id_, "scope"))?;
 
+        let mut return_codes = Vec::new();
+        for (code, tag) in config
+            .iterate_prefix(("spam-filter.dnsbl.server", id_, "return-code"))
+            .map(|(code, tag)| (code.to_string(), tag.trim().to_string()))
+            .collect::<Vec<_>>()
+        {
+            match code.parse::<IpAddr>() {
+                Ok(ip) if !tag.is_empty() => {
+                    return_codes.push((ip, tag));
+                }
+                _ => {
+                    config.new_parse_error(
+                        (
+                            "spam-filter.dnsbl.server",
+                            id_,
+                            "return-code",
+                            code.as_str(),
+                        ),
+                        format!("Invalid return code {code:?} or empty tag"),
+                    );
+                }
+            }
+        }
+
+        let tags = IfBlock::try_parse(
+            config,
+            ("spam-filter.dnsbl.server", id_, "tag"),
+            &Element::Ip.token_map(),
+        );
+        if tags.is_none() && return_codes.is_empty() {
+            config.new_parse_error(
+                ("spam-filter.dnsbl.server", id_, "tag"),
+                "Either a tag expression or a return code map is required",
+            );
+            return None;
+        }
+
         DnsBlServer {
             zone: IfBlock::try_parse(
                 config,
@@ -315,11 +445,12 @@ impl DnsBlServer {
                 &scope.token_map(),
             )?,
             scope,
-            tags: IfBlock::try_parse(
-                config,
-                ("spam-filter.dnsbl.server", id_, "tag"),
-                &Element::Ip.token_map(),
-            )?,
+            tags,
+            return_codes,
+            weight: config
+                .property_or_default(("spam-filter.dnsbl.server", id_, "weight"), "1.0")
+                .unwrap_or(1.0),
+            timeout: config.property(("spam-filter.dnsbl.server", id_, "timeout")),
             id,
         }
         .into()
@@ -577,11 +708,170 @@ impl BayesConfig {
             auto_learn_card_is_ham: config
                 .property_or_default("spam-filter.bayes.auto-learn.card-is-ham", "true")
                 .unwrap_or(true),
+            account_train_global: config
+                .property_or_default("spam-filter.bayes.account.train-global", "false")
+                .unwrap_or(false),
+            account_train_rate: config
+                .property_or_default::<Option<Rate>>(
+                    "spam-filter.bayes.account.train-rate",
+                    "100/1d",
+                )
+                .unwrap_or_default(),
+            account_train_dedup: config
+                .property_or_default::<Duration>("spam-filter.bayes.account.train-dedup", "30d")
+                .unwrap_or(Duration::from_secs(30 * 86400))
+                .as_secs(),
         }
         .into()
     }
 }
 
+impl UrlReputationConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default("spam-filter.url-reputation.enable", "true")
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        let mut feeds = Vec::new();
+        for id in config
+            .sub_keys("spam-filter.url-reputation.feed", ".store")
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>()
+        {
+            let id_ = id.as_str();
+            if !config
+                .property_or_default(("spam-filter.url-reputation.feed", id_, "enable"), "true")
+                .unwrap_or(true)
+            {
+                continue;
+            }
+            let mut feed = UrlReputationFeed {
+                store: config
+                    .value_require(("spam-filter.url-reputation.feed", id_, "store"))
+                    .unwrap_or_default()
+                    .to_string(),
+                tag: config
+                    .value(("spam-filter.url-reputation.feed", id_, "tag"))
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| {
+                        format!("URL_REPUTATION_{}", id.to_uppercase().replace('-', "_"))
+                    }),
+                score: config
+                    .property_or_default(("spam-filter.url-reputation.feed", id_, "score"), "5.0")
+                    .unwrap_or(5.0),
+                match_url: false,
+                match_host: false,
+                match_domain: false,
+                id,
+            };
+            for value in config
+                .values(("spam-filter.url-reputation.feed", feed.id.as_str(), "match"))
+                .map(|(_, v)| v.to_string())
+                .collect::<Vec<_>>()
+            {
+                match value.as_str() {
+                    "url" => feed.match_url = true,
+                    "host" => feed.match_host = true,
+                    "domain" => feed.match_domain = true,
+                    other => {
+                        config.new_parse_error(
+                            ("spam-filter.url-reputation.feed", feed.id.as_str(), "match"),
+                            format!("Invalid match type {other:?}"),
+                        );
+                    }
+                }
+            }
+            if !feed.match_url && !feed.match_host && !feed.match_domain {
+                feed.match_url = true;
+                feed.match_host = true;
+            }
+            feeds.push(feed);
+        }
+
+        if feeds.is_empty() {
+            return None;
+        }
+
+        UrlReputationConfig {
+            feeds,
+            max_urls: config
+                .property_or_default("spam-filter.url-reputation.max-urls", "50")
+                .unwrap_or(50),
+            cache_listed: config
+                .property_or_default::<Duration>("spam-filter.url-reputation.cache.listed", "1d")
+                .unwrap_or(Duration::from_secs(86400))
+                .as_secs(),
+            cache_clean: config
+                .property_or_default::<Duration>("spam-filter.url-reputation.cache.clean", "6h")
+                .unwrap_or(Duration::from_secs(6 * 3600))
+                .as_secs(),
+        }
+        .into()
+    }
+}
+
+impl SpamFilterOverrides {
+    /// Parses a list of `key=value` settings as stored in a principal.
+    pub fn parse(settings: &[String]) -> Result<Self, String> {
+        let mut overrides = SpamFilterOverrides::default();
+
+        for setting in settings {
+            let (key, value) = setting
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Invalid spam filter setting {setting:?}"))?;
+            match key {
+                "enable" => overrides.enabled = Some(bool::parse_value(value)?),
+                "threshold.reject" => overrides.reject_threshold = Some(f64::parse_value(value)?),
+                "threshold.discard" => overrides.discard_threshold = Some(f64::parse_value(value)?),
+                "threshold.spam" => overrides.spam_threshold = Some(f64::parse_value(value)?),
+                "header.status" => overrides.add_status_header = Some(bool::parse_value(value)?),
+                "header.result" => overrides.add_result_header = Some(bool::parse_value(value)?),
+                _ => {
+                    let module = key
+                        .strip_prefix("module.")
+                        .filter(|module| SPAM_FILTER_MODULES.contains(module))
+                        .ok_or_else(|| format!("Unknown spam filter setting {key:?}"))?;
+                    overrides
+                        .modules
+                        .push((module.to_string(), bool::parse_value(value)?));
+                }
+            }
+        }
+
+        Ok(overrides)
+    }
+
+    /// Fills the settings not defined at this level with those of a broader scope.
+    pub fn merge(&mut self, other: SpamFilterOverrides) {
+        self.enabled = self.enabled.or(other.enabled);
+        self.reject_threshold = self.reject_threshold.or(other.reject_threshold);
+        self.discard_threshold = self.discard_threshold.or(other.discard_threshold);
+        self.spam_threshold = self.spam_threshold.or(other.spam_threshold);
+        self.add_status_header = self.add_status_header.or(other.add_status_header);
+        self.add_result_header = self.add_result_header.or(other.add_result_header);
+        for (module, enabled) in other.modules {
+            if !self.modules.iter().any(|(m, _)| *m == module) {
+                self.modules.push((module, enabled));
+            }
+        }
+    }
+
+    pub fn is_module_enabled(&self, module: &str) -> bool {
+        self.modules
+            .iter()
+            .find(|(m, _)| m == module)
+            .is_none_or(|(_, enabled)| *enabled)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &SpamFilterOverrides::default()
+    }
+}
+
 impl SpamFilterScoreConfig {
     pub fn parse(config: &mut Config) -> Self {
         SpamFilterScoreConfig {
@@ -868,6 +1158,10 @@ impl ResolveVariable for IpResolver {
 }
 
 impl IpResolver {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
     pub fn new(ip: IpAddr) -> Self {
         Self {
             ip_string: ip.to_string(),
@@ -894,3 +1188,80 @@ impl CacheItemWeight for IpResolver {
         (std::mem::size_of::<IpResolver>() + self.ip_string.len() + self.reverse.len()) as u64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spam_filter_overrides() {
+        let user = SpamFilterOverrides::parse(&[
+            "threshold.spam = 8.5".to_string(),
+            "module.bayes=false".to_string(),
+        ])
+        .unwrap();
+        let tenant = SpamFilterOverrides::parse(&[
+            "threshold.spam=3".to_string(),
+            "threshold.reject=20".to_string(),
+            "module.bayes=true".to_string(),
+            "module.pyzor=false".to_string(),
+            "header.result=false".to_string(),
+        ])
+        .unwrap();
+
+        let mut overrides = user;
+        overrides.merge(tenant);
+        assert_eq!(overrides.spam_threshold, Some(8.5));
+        assert_eq!(overrides.reject_threshold, Some(20.0));
+        assert_eq!(overrides.add_result_header, Some(false));
+        assert!(!overrides.is_module_enabled("bayes"));
+        assert!(!overrides.is_module_enabled("pyzor"));
+        assert!(overrides.is_module_enabled("url"));
+
+        assert!(SpamFilterOverrides::parse(&["module.unknown=false".to_string()]).is_err());
+        assert!(SpamFilterOverrides::parse(&["threshold.spam".to_string()]).is_err());
+        assert!(SpamFilterOverrides::parse(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dnsbl_return_codes() {
+        let mut config = Config::new(
+            r#"
+[spam-filter.dnsbl.server.zen]
+scope = "ip"
+zone = "ip_reverse + '.zen.example.org'"
+weight = 1.5
+timeout = "2s"
+
+[spam-filter.dnsbl.server.zen.return-code]
+"127.0.0.2" = "RBL_ZEN_SBL"
+"127.0.0.10" = "RBL_ZEN_PBL"
+
+[spam-filter.dnsbl.server.broken]
+scope = "ip"
+zone = "ip_reverse + '.broken.example.org'"
+"#,
+        )
+        .unwrap();
+
+        let dnsbl = DnsBlConfig::parse(&mut config);
+        assert_eq!(dnsbl.servers.len(), 1);
+        let server = &dnsbl.servers[0];
+        assert_eq!(server.id, "zen");
+        assert_eq!(server.weight, 1.5);
+        assert_eq!(server.timeout, Some(Duration::from_secs(2)));
+        assert!(server.tags.is_none());
+        assert!(
+            server
+                .return_codes
+                .contains(&("127.0.0.10".parse().unwrap(), "RBL_ZEN_PBL".to_string()))
+        );
+        assert_eq!(server.return_codes.len(), 2);
+        assert_eq!(dnsbl.max_failures, 5);
+        assert!(
+            config
+                .errors
+                .contains_key("spam-filter.dnsbl.server.broken.tag")
+        );
+    }
+}
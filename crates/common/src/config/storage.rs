@@ -4,13 +4,18 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use ahash::AHashMap;
 use directory::Directory;
-use store::{BlobStore, FtsStore, InMemoryStore, PubSubStore, PurgeSchedule, Store};
+use store::{BlobStore, FtsStore, InMemoryStore, PubSubStore, PurgeSchedule, Store, Stores};
+use utils::config::Config;
 
-use crate::manager::config::ConfigManager;
+use crate::{
+    expr::{V_RECIPIENT, V_RECIPIENT_DOMAIN, if_block::IfBlock, tokenizer::TokenMap},
+    manager::config::ConfigManager,
+    storage::extract::TextExtractors,
+};
 
 #[derive(Default, Clone)]
 pub struct Storage {
@@ -28,4 +33,99 @@ pub struct Storage {
     pub blobs: AHashMap<String, BlobStore>,
     pub lookups: AHashMap<String, InMemoryStore>,
     pub ftss: AHashMap<String, FtsStore>,
+
+    pub partitions: StoragePartitions,
+    pub extractors: TextExtractors,
+}
+
+#[derive(Default, Clone)]
+pub struct StoragePartitions {
+    pub partitions: Vec<StoragePartition>,
+    pub assign: Option<IfBlock>,
+    pub check_interval: Duration,
+    pub check_timeout: Duration,
+    pub failure_threshold: u32,
+}
+
+#[derive(Clone)]
+pub struct StoragePartition {
+    pub id: String,
+    pub store: Option<Store>,
+    pub blob: Option<BlobStore>,
+}
+
+impl StoragePartitions {
+    pub fn parse(config: &mut Config, stores: &Stores) -> Self {
+        let mut partitions = Vec::new();
+
+        for id in config
+            .sub_keys("storage.partition", ".store")
+            .chain(config.sub_keys("storage.partition", ".blob"))
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+        {
+            if partitions.iter().any(|p: &StoragePartition| p.id == id) {
+                continue;
+            }
+
+            let store = config
+                .value(("storage.partition", id.as_str(), "store"))
+                .map(|store_id| store_id.to_string())
+                .and_then(|store_id| {
+                    let store = stores.stores.get(&store_id).cloned();
+                    if store.is_none() {
+                        config.new_parse_error(
+                            ("storage.partition", id.as_str(), "store"),
+                            format!("Data store {store_id:?} not found"),
+                        );
+                    }
+                    store
+                });
+            let blob = config
+                .value(("storage.partition", id.as_str(), "blob"))
+                .map(|store_id| store_id.to_string())
+                .and_then(|store_id| {
+                    let store = stores.blob_stores.get(&store_id).cloned();
+                    if store.is_none() {
+                        config.new_parse_error(
+                            ("storage.partition", id.as_str(), "blob"),
+                            format!("Blob store {store_id:?} not found"),
+                        );
+                    }
+                    store
+                });
+
+            if store.is_some() || blob.is_some() {
+                partitions.push(StoragePartition { id, store, blob });
+            }
+        }
+
+        let assign = if !partitions.is_empty() {
+            IfBlock::try_parse(
+                config,
+                "storage.partition.assign",
+                &TokenMap::default().with_variables(&[V_RECIPIENT, V_RECIPIENT_DOMAIN]),
+            )
+        } else {
+            None
+        };
+
+        StoragePartitions {
+            partitions,
+            assign,
+            check_interval: config
+                .property_or_default("storage.partition.health.interval", "30s")
+                .unwrap_or(Duration::from_secs(30)),
+            check_timeout: config
+                .property_or_default("storage.partition.health.timeout", "5s")
+                .unwrap_or(Duration::from_secs(5)),
+            failure_threshold: config
+                .property_or_default("storage.partition.health.failures", "3")
+                .unwrap_or(3),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.partitions.is_empty() && self.assign.is_some()
+    }
 }
@@ -19,7 +19,7 @@ use opentelemetry_sdk::{
 };
 use opentelemetry_semantic_conventions::resource::SERVICE_VERSION;
 use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
-use store::Stores;
+use store::{PubSubStore, Stores};
 use trc::{EventType, Level, TelemetryEvent, ipc::subscriber::Interests};
 use utils::config::{Config, utils::ParseValue};
 
@@ -38,6 +38,7 @@ pub enum TelemetrySubscriberType {
     LogTracer(LogTracer),
     OtelTracer(OtelTracer),
     Webhook(WebhookTracer),
+    EventBus(EventBusTracer),
     #[cfg(unix)]
     JournalTracer(crate::telemetry::tracers::journald::Subscriber),
     #[cfg(feature = "enterprise")]
@@ -88,6 +89,13 @@ pub struct WebhookTracer {
     pub headers: HeaderMap,
 }
 
+pub struct EventBusTracer {
+    pub store: PubSubStore,
+    pub topic: String,
+    pub node_id: u64,
+    pub consume: bool,
+}
+
 #[derive(Debug)]
 #[cfg(feature = "enterprise")]
 pub struct StoreTracer {
@@ -477,6 +485,9 @@ impl Tracers {
                 TelemetrySubscriberType::Webhook(_) => {
                     EventType::Telemetry(TelemetryEvent::WebhookError).into()
                 }
+                TelemetrySubscriberType::EventBus(_) => {
+                    EventType::Telemetry(TelemetryEvent::EventBusError).into()
+                }
                 #[cfg(unix)]
                 TelemetrySubscriberType::JournalTracer(_) => {
                     EventType::Telemetry(TelemetryEvent::JournalError).into()
@@ -556,6 +567,17 @@ impl Tracers {
             }
         }
 
+        // Parse event buses
+        for id in config
+            .sub_keys("event-bus", ".topic")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            if let Some(event_bus) = parse_event_bus(config, &id, stores, &mut global_interests) {
+                tracers.push(event_bus);
+            }
+        }
+
         // Add default tracer if none were found
         #[cfg(not(feature = "test_mode"))]
         if tracers.is_empty() {
@@ -798,6 +820,65 @@ fn parse_webhook(
     }
 }
 
+fn parse_event_bus(
+    config: &mut Config,
+    id: &str,
+    stores: &Stores,
+    global_interests: &mut Interests,
+) -> Option<TelemetrySubscriber> {
+    let store_id = config.value_require(("event-bus", id, "store"))?;
+    let Some(store) = stores.pubsub_stores.get(store_id).cloned() else {
+        let err = format!("PubSub store {store_id} not found");
+        config.new_build_error(("event-bus", id, "store"), err);
+        return None;
+    };
+
+    // Build tracer
+    let mut tracer = TelemetrySubscriber {
+        id: format!("b_{id}"),
+        interests: Default::default(),
+        lossy: config
+            .property_or_default(("event-bus", id, "lossy"), "false")
+            .unwrap_or(false),
+        typ: TelemetrySubscriberType::EventBus(EventBusTracer {
+            store,
+            topic: config
+                .value_require(("event-bus", id, "topic"))?
+                .to_string(),
+            node_id: config.property("cluster.node-id").unwrap_or(1),
+            consume: config
+                .property_or_default(("event-bus", id, "consume"), "false")
+                .unwrap_or(false),
+        }),
+    };
+
+    // Parse event bus events, queue, delivery and authentication events are published by default
+    let mut events = config
+        .properties::<EventOrMany>(("event-bus", id, "events"))
+        .into_iter()
+        .map(|(_, e)| e)
+        .collect::<Vec<_>>();
+    if events.is_empty() {
+        events = ["queue.", "delivery.", "auth."]
+            .into_iter()
+            .map(|prefix| EventOrMany::StartsWith(prefix.to_string()))
+            .collect();
+    }
+    apply_events(events, true, |event_type| {
+        if event_type != EventType::Telemetry(TelemetryEvent::EventBusError) {
+            tracer.interests.set(event_type);
+            global_interests.set(event_type);
+        }
+    });
+
+    if !tracer.interests.is_empty() {
+        Some(tracer)
+    } else {
+        config.new_build_warning(("event-bus", id), "No events enabled for event bus");
+        None
+    }
+}
+
 enum EventOrMany {
     Event(EventType),
     StartsWith(String),
@@ -884,6 +965,14 @@ impl ParseValue for EventOrMany {
     }
 }
 
+impl std::fmt::Debug for EventBusTracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBusTracer")
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
 impl std::fmt::Debug for OtelMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("OtelMetrics")
@@ -7,11 +7,15 @@
 use crate::{
     Inner, Server,
     auth::{AccessToken, ResourceToken, TenantInfo},
-    config::smtp::{
-        auth::{ArcSealer, DkimSigner, LazySignature, ResolvedSignature, build_signature},
-        queue::RelayHost,
+    config::{
+        smtp::{
+            auth::{ArcSealer, DkimSigner, LazySignature, ResolvedSignature, build_signature},
+            queue::RelayHost,
+            session::{CircuitBreaker, FilterState},
+        },
+        spamfilter::SpamFilterOverrides,
     },
-    ipc::{BroadcastEvent, StateEvent},
+    ipc::{BroadcastEvent, PushGatewayEvent, StateEvent},
 };
 use directory::{Directory, QueryBy, Type, backend::internal::manage::ManageDirectory};
 use jmap_proto::types::{
@@ -62,6 +66,13 @@ impl Server {
         &self.core.storage.directory
     }
 
+    pub fn invalidate_group_caches(&self) {
+        self.core.storage.directory.invalidate_group_cache();
+        for directory in self.core.storage.directories.values() {
+            directory.invalidate_group_cache();
+        }
+    }
+
     pub fn get_directory(&self, name: &str) -> Option<&Arc<Directory>> {
         self.core.storage.directories.get(name)
     }
@@ -350,7 +361,107 @@ impl Server {
         })
     }
 
+    pub fn filter_state(&self, filter_id: &str) -> FilterState {
+        self.inner
+            .data
+            .filter_health
+            .lock()
+            .get(filter_id)
+            .map_or(FilterState::Available, |health| health.state(now()))
+    }
+
+    /// Records the outcome of a milter or MTA hook request, returns `true`
+    /// if the circuit breaker was opened as a result.
+    pub fn record_filter_result(
+        &self,
+        filter_id: &str,
+        elapsed: std::time::Duration,
+        is_error: bool,
+        circuit_breaker: Option<&CircuitBreaker>,
+    ) -> bool {
+        self.inner
+            .data
+            .filter_health
+            .lock()
+            .entry(filter_id.to_string())
+            .or_default()
+            .record(elapsed.as_millis() as u64, is_error, circuit_breaker, now())
+    }
+
     #[inline(always)]
+    /// Resolves the spam filter overrides of an account, merging those defined
+    /// on the account with the ones of its domain and tenant.
+    pub async fn spam_filter_overrides(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<Option<SpamFilterOverrides>> {
+        let Some(principal) = self
+            .core
+            .storage
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .add_context(|err| err.caused_by(trc::location!()).account_id(account_id))?
+        else {
+            return Ok(None);
+        };
+
+        let mut scopes = vec![principal.spam_filter().to_vec()];
+        if let Some(domain) = principal
+            .emails
+            .first()
+            .and_then(|email| email.rsplit_once('@'))
+            .map(|(_, domain)| domain)
+        {
+            if let Some(domain_id) = self
+                .store()
+                .get_principal_id(domain)
+                .await
+                .caused_by(trc::location!())?
+            {
+                if let Some(domain) = self
+                    .store()
+                    .get_principal(domain_id)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    scopes.push(domain.spam_filter().to_vec());
+                }
+            }
+        }
+        if let Some(tenant_id) = principal.tenant() {
+            if let Some(tenant) = self
+                .store()
+                .get_principal(tenant_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                scopes.push(tenant.spam_filter().to_vec());
+            }
+        }
+
+        // More specific scopes take precedence
+        let mut overrides = SpamFilterOverrides::default();
+        for settings in scopes.into_iter().filter(|s| !s.is_empty()) {
+            match SpamFilterOverrides::parse(&settings) {
+                Ok(scope) => overrides.merge(scope),
+                Err(err) => {
+                    trc::event!(
+                        Spam(trc::SpamEvent::OverrideError),
+                        AccountId = account_id,
+                        Reason = err,
+                    );
+                }
+            }
+        }
+
+        Ok(if !overrides.is_empty() {
+            Some(overrides)
+        } else {
+            None
+        })
+    }
+
     pub async fn get_archive(
         &self,
         account_id: u32,
@@ -676,6 +787,18 @@ impl Server {
         }
     }
 
+    pub async fn notify_push_gateway(&self, event: PushGatewayEvent) {
+        if self.core.push_gateway.is_enabled()
+            && self.inner.ipc.push_gateway_tx.send(event).await.is_err()
+        {
+            trc::event!(
+                Server(trc::ServerEvent::ThreadError),
+                Details = "Error sending push gateway event.",
+                CausedBy = trc::location!()
+            );
+        }
+    }
+
     #[allow(clippy::blocks_in_conditions)]
     pub async fn put_blob(
         &self,
@@ -702,6 +825,8 @@ impl Server {
             .await
             .caused_by(trc::location!())?;
 
+        // Identical contents are stored once, refresh the commit time of existing
+        // blobs so they are not purged before the reservation is linked
         if !self
             .core
             .storage
@@ -717,18 +842,24 @@ impl Server {
                 .put_blob(hash.as_ref(), data)
                 .await
                 .caused_by(trc::location!())?;
-
-            // Commit blob
-            let mut batch = BatchBuilder::new();
-            batch.set(BlobOp::Commit { hash: hash.clone() }, Vec::new());
-            self.core
-                .storage
-                .data
-                .write(batch.build_all())
-                .await
-                .caused_by(trc::location!())?;
+        } else {
+            trc::event!(
+                Store(trc::StoreEvent::BlobDeduplicate),
+                Key = hash.as_ref(),
+                Size = data.len(),
+            );
         }
 
+        // Commit blob
+        let mut batch = BatchBuilder::new();
+        batch.set(BlobOp::Commit { hash: hash.clone() }, now().serialize());
+        self.core
+            .storage
+            .data
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
         Ok(BlobId {
             hash,
             class: BlobClass::Reserved {
@@ -14,11 +14,35 @@ use crate::Server;
 
 use super::{
     BinaryOperator, Constant, Expression, ExpressionItem, Setting, StringCow, UnaryOperator,
-    Variable,
-    functions::{FUNCTIONS, ResolveVariable},
+    VARIABLES_MAP, Variable,
+    functions::{ASYNC_FUNCTIONS, F_COUNTER_INCR, F_KEY_SET, FUNCTIONS, ResolveVariable},
     if_block::IfBlock,
 };
 
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvalTrace {
+    pub branch: EvalBranch,
+    pub condition: Option<usize>,
+    pub steps: Vec<EvalStep>,
+    pub result: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EvalBranch {
+    If,
+    Then,
+    Else,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvalStep {
+    pub operation: String,
+    pub stack: serde_json::Value,
+}
+
 impl Server {
     pub async fn eval_if<'x, R: TryFrom<Variable<'x>>, V: ResolveVariable>(
         &'x self,
@@ -137,6 +161,31 @@ impl Server {
             }
         }
     }
+
+    /// Evaluates an if block recording each executed branch and operation,
+    /// used to debug expressions from the management API. Functions that
+    /// modify stored data are not executed.
+    pub async fn eval_if_trace<'x, V: ResolveVariable>(
+        &'x self,
+        if_block: &'x IfBlock,
+        resolver: &'x V,
+        trace: &mut Vec<EvalTrace>,
+        session_id: u64,
+    ) -> trc::Result<Variable<'x>> {
+        if if_block.is_empty() {
+            return Ok(Variable::default());
+        }
+
+        (EvalContext {
+            resolver,
+            core: self,
+            expr: if_block,
+            captures: Vec::new(),
+            session_id,
+        })
+        .eval_trace(trace)
+        .await
+    }
 }
 
 struct EvalContext<'x, V: ResolveVariable, T, C> {
@@ -183,10 +232,77 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, IfBlock, Vec<CompactString>> {
         .eval()
         .await
     }
+
+    async fn eval_trace(&mut self, trace: &mut Vec<EvalTrace>) -> trc::Result<Variable<'x>> {
+        for (pos, if_then) in self.expr.if_then.iter().enumerate() {
+            let mut steps = Vec::new();
+            let result = (EvalContext {
+                resolver: self.resolver,
+                core: self.core,
+                expr: &if_then.expr,
+                captures: &mut self.captures,
+                session_id: self.session_id,
+            })
+            .eval_with(Some(&mut steps))
+            .await?;
+            let matched = result.to_bool();
+            trace.push(EvalTrace {
+                branch: EvalBranch::If,
+                condition: Some(pos),
+                steps,
+                result: (&result).into(),
+            });
+
+            if matched {
+                let mut steps = Vec::new();
+                let result = (EvalContext {
+                    resolver: self.resolver,
+                    core: self.core,
+                    expr: &if_then.then,
+                    captures: &mut self.captures,
+                    session_id: self.session_id,
+                })
+                .eval_with(Some(&mut steps))
+                .await?;
+                trace.push(EvalTrace {
+                    branch: EvalBranch::Then,
+                    condition: Some(pos),
+                    steps,
+                    result: (&result).into(),
+                });
+                return Ok(result);
+            }
+        }
+
+        let mut steps = Vec::new();
+        let result = (EvalContext {
+            resolver: self.resolver,
+            core: self.core,
+            expr: &self.expr.default,
+            captures: &mut self.captures,
+            session_id: self.session_id,
+        })
+        .eval_with(Some(&mut steps))
+        .await?;
+        trace.push(EvalTrace {
+            branch: EvalBranch::Else,
+            condition: None,
+            steps,
+            result: (&result).into(),
+        });
+        Ok(result)
+    }
 }
 
 impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactString>> {
     async fn eval(&mut self) -> trc::Result<Variable<'x>> {
+        self.eval_with(None).await
+    }
+
+    async fn eval_with(
+        &mut self,
+        mut trace: Option<&mut Vec<EvalStep>>,
+    ) -> trc::Result<Variable<'x>> {
         let mut stack = Vec::new();
         let mut exprs = self.expr.items.iter();
 
@@ -266,6 +382,11 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactStri
 
                     let result = if let Some((_, fnc, _)) = FUNCTIONS.get(*id as usize) {
                         (fnc)(arguments)
+                    } else if trace.is_some()
+                        && matches!(*id - FUNCTIONS.len() as u32, F_KEY_SET | F_COUNTER_INCR)
+                    {
+                        // Do not modify stored data while tracing
+                        Variable::default()
                     } else {
                         Box::pin(self.core.eval_fnc(
                             *id - FUNCTIONS.len() as u32,
@@ -315,6 +436,13 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactStri
                     stack.push(Variable::Integer(!self.captures.is_empty() as i64));
                 }
             }
+
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.push(EvalStep {
+                    operation: expr.to_string(),
+                    stack: stack.last().map(Into::into).unwrap_or_default(),
+                });
+            }
         }
 
         Ok(stack.pop().unwrap_or_default())
@@ -676,6 +804,92 @@ impl Display for Variable<'_> {
     }
 }
 
+impl Display for ExpressionItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionItem::Variable(id) => match VARIABLES_MAP.iter().find(|(_, v)| v == id) {
+                Some((name, _)) => write!(f, "variable {name}"),
+                None => write!(f, "variable #{id}"),
+            },
+            ExpressionItem::Global(name) => write!(f, "global ${name}"),
+            ExpressionItem::Setting(setting) => match setting {
+                Setting::Hostname => f.write_str("setting server.hostname"),
+                Setting::ReportDomain => f.write_str("setting report.domain"),
+                Setting::NodeId => f.write_str("setting cluster.node-id"),
+                Setting::Other(key) => write!(f, "setting {key}"),
+            },
+            ExpressionItem::Capture(id) => write!(f, "capture ${id}"),
+            ExpressionItem::Constant(constant) => match constant {
+                Constant::Integer(value) => write!(f, "constant {value}"),
+                Constant::Float(value) => write!(f, "constant {value}"),
+                Constant::String(value) => write!(f, "constant {value:?}"),
+            },
+            ExpressionItem::BinaryOperator(op) => f.write_str(match op {
+                BinaryOperator::Add => "operator +",
+                BinaryOperator::Subtract => "operator -",
+                BinaryOperator::Multiply => "operator *",
+                BinaryOperator::Divide => "operator /",
+                BinaryOperator::And => "operator &&",
+                BinaryOperator::Or => "operator ||",
+                BinaryOperator::Xor => "operator ^",
+                BinaryOperator::Eq => "operator ==",
+                BinaryOperator::Ne => "operator !=",
+                BinaryOperator::Lt => "operator <",
+                BinaryOperator::Le => "operator <=",
+                BinaryOperator::Gt => "operator >",
+                BinaryOperator::Ge => "operator >=",
+            }),
+            ExpressionItem::UnaryOperator(op) => f.write_str(match op {
+                UnaryOperator::Not => "operator !",
+                UnaryOperator::Minus => "operator -",
+            }),
+            ExpressionItem::Regex(regex) => write!(f, "matches {:?}", regex.as_str()),
+            ExpressionItem::JmpIf { val, pos } => write!(f, "skip {pos} if {val}"),
+            ExpressionItem::Function { id, num_args } => {
+                let name = FUNCTIONS
+                    .get(*id as usize)
+                    .map(|(name, _, _)| *name)
+                    .or_else(|| {
+                        ASYNC_FUNCTIONS
+                            .iter()
+                            .find(|(_, fnc_id, _)| *fnc_id == *id - FUNCTIONS.len() as u32)
+                            .map(|(name, _, _)| *name)
+                    })
+                    .unwrap_or("unknown");
+                write!(f, "function {name}/{num_args}")
+            }
+            ExpressionItem::ArrayAccess => f.write_str("array access"),
+            ExpressionItem::ArrayBuild(num_items) => write!(f, "array build/{num_items}"),
+        }
+    }
+}
+
+impl From<&Variable<'_>> for serde_json::Value {
+    fn from(value: &Variable<'_>) -> Self {
+        match value {
+            Variable::String(v) => serde_json::Value::String(v.as_str().to_string()),
+            Variable::Integer(v) => serde_json::Value::from(*v),
+            Variable::Float(v) => serde_json::Value::from(*v),
+            Variable::Array(v) => serde_json::Value::Array(v.iter().map(Into::into).collect()),
+        }
+    }
+}
+
+impl From<serde_json::Value> for Variable<'_> {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(v) => Variable::String(StringCow::Owned(v.into())),
+            serde_json::Value::Number(v) => match v.as_i64() {
+                Some(v) => Variable::Integer(v),
+                None => Variable::Float(v.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::Bool(v) => Variable::Integer(v as i64),
+            serde_json::Value::Array(v) => Variable::Array(v.into_iter().map(Into::into).collect()),
+            serde_json::Value::Null | serde_json::Value::Object(_) => Variable::default(),
+        }
+    }
+}
+
 impl<'x> From<&'x Constant> for Variable<'x> {
     fn from(value: &'x Constant) -> Self {
         match value {
@@ -102,6 +102,16 @@ impl Server {
                     .map(Variable::Integer)
                     .caused_by(trc::location!())
             }
+            F_IN_IP_LIST => {
+                let list = params.next_as_string();
+                let ip = params.next_as_string();
+
+                Ok(ip
+                    .as_str()
+                    .parse::<IpAddr>()
+                    .is_ok_and(|ip| self.ip_list_contains(list.as_str(), &ip))
+                    .into())
+            }
             F_DNS_QUERY => self.dns_query(params).await,
             F_SQL_QUERY => self.sql_query(params, session_id).await,
             _ => Ok(Variable::default()),
@@ -38,6 +38,11 @@ pub const V_HEADERS: u32 = 23;
 pub const V_METHOD: u32 = 24;
 pub const V_ASN: u32 = 25;
 pub const V_COUNTRY: u32 = 26;
+pub const V_CERT_SUBJECT: u32 = 27;
+pub const V_CERT_ISSUER: u32 = 28;
+pub const V_CERT_CN: u32 = 29;
+pub const V_CERT_EMAIL: u32 = 30;
+pub const V_CERT_DNS: u32 = 31;
 
 pub const VARIABLES_MAP: &[(&str, u32)] = &[
     ("rcpt", V_RECIPIENT),
@@ -67,6 +72,11 @@ pub const VARIABLES_MAP: &[(&str, u32)] = &[
     ("method", V_METHOD),
     ("asn", V_ASN),
     ("country", V_COUNTRY),
+    ("cert_subject", V_CERT_SUBJECT),
+    ("cert_issuer", V_CERT_ISSUER),
+    ("cert_cn", V_CERT_CN),
+    ("cert_email", V_CERT_EMAIL),
+    ("cert_dns", V_CERT_DNS),
 ];
 
 use compact_str::CompactString;
@@ -28,6 +28,11 @@ pub enum HousekeeperEvent {
         renew_at: Instant,
     },
     Purge(PurgeType),
+    Notify {
+        from: String,
+        to: Vec<String>,
+        body: Vec<u8>,
+    },
     ReloadSettings,
     Exit,
 }
@@ -71,6 +76,7 @@ pub enum BroadcastEvent {
     StateChange(StateChange),
     ReloadSettings,
     ReloadBlockedIps,
+    ReloadCertificates,
 }
 
 #[derive(Debug)]
@@ -99,6 +105,22 @@ pub struct EncryptionKeys {
     pub auth: Vec<u8>,
 }
 
+#[derive(Debug)]
+pub enum PushGatewayEvent {
+    NewMessage {
+        account_id: u32,
+        document_id: u32,
+        preview: Option<PushPreview>,
+    },
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub struct PushPreview {
+    pub from: Option<String>,
+    pub subject: Option<String>,
+}
+
 #[derive(Debug)]
 pub enum QueueEvent {
     Refresh,
@@ -15,18 +15,25 @@ use config::{
     imap::ImapConfig,
     jmap::settings::{JmapConfig, SpecialUse},
     network::Network,
+    push_gateway::PushGatewayConfig,
     scripts::Scripting,
     smtp::{
         SmtpConfig,
         resolver::{Policy, Tlsa},
+        session::{FilterHealth, ScanKey, ScanVerdict},
     },
-    spamfilter::{IpResolver, SpamFilterConfig},
+    spamfilter::{DnsBlStatus, IpResolver, SpamFilterConfig},
     storage::Storage,
     telemetry::Metrics,
 };
-use ipc::{BroadcastEvent, HousekeeperEvent, QueueEvent, ReportingEvent, StateEvent};
+use ipc::{
+    BroadcastEvent, HousekeeperEvent, PushGatewayEvent, QueueEvent, ReportingEvent, StateEvent,
+};
 use jmap_proto::types::value::AclGrant;
-use listener::{asn::AsnGeoLookupData, blocked::Security, tls::AcmeProviders};
+use listener::{
+    asn::AsnGeoLookupData, blocked::Security, certificate::ManagedCertificate, iplist::IpSet,
+    ticket::TicketKeys, tls::AcmeProviders,
+};
 use mail_auth::{MX, Txt};
 use manager::webadmin::{Resource, WebAdminManager};
 use nlp::bayes::{TokenHash, Weights};
@@ -38,6 +45,7 @@ use std::{
     sync::{Arc, atomic::AtomicBool},
     time::Duration,
 };
+use storage::partition::PartitionHealth;
 use tinyvec::TinyVec;
 use tokio::sync::{Notify, Semaphore, mpsc};
 use tokio_rustls::TlsConnector;
@@ -72,7 +80,7 @@ pub static USER_AGENT: &str = "Stalwart/1.0.0";
 pub static DAEMON_NAME: &str = concat!("Stalwart v", env!("CARGO_PKG_VERSION"),);
 pub static PROD_ID: &str = "-//Stalwart Labs LLC//Stalwart Server//EN";
 
-pub const DATABASE_SCHEMA_VERSION: u32 = 2;
+pub const DATABASE_SCHEMA_VERSION: u32 = 3;
 
 pub const LONG_1D_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24);
 pub const LONG_1Y_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24 * 365);
@@ -106,6 +114,37 @@ pub const KV_LOCK_TASK: u8 = 23;
 pub const KV_LOCK_HOUSEKEEPER: u8 = 24;
 pub const KV_LOCK_DAV: u8 = 25;
 pub const KV_SIEVE_ID: u8 = 26;
+pub const KV_BAYES_TRAINED: u8 = 27;
+pub const KV_RATE_LIMIT_BAYES: u8 = 28;
+pub const KV_URL_REPUTATION: u8 = 29;
+pub const KV_MAILBOX_DIGEST: u8 = 30;
+pub const KV_RELAY_BUDGET: u8 = 31;
+pub const KV_ACTIVE_ACCOUNTS: u8 = 32;
+pub const KV_TLS_TICKET_SECRET: u8 = 33;
+pub const KV_UPLOAD: u8 = 34;
+pub const KV_LOCK_UPLOAD: u8 = 35;
+pub const KV_RATE_LIMIT_COLLECT: u8 = 36;
+pub const KV_BLOB_TIERING: u8 = 37;
+pub const KV_PORTABILITY_JOB: u8 = 38;
+pub const KV_LOCK_MAIL_SYNC: u8 = 40;
+pub const KV_ARCHIVE_CHAIN: u8 = 41;
+pub const KV_WEBAUTHN_CHALLENGE: u8 = 42;
+pub const KV_OAUTH_REVOKED: u8 = 43;
+pub const KV_LOCKOUT_SCORE: u8 = 44;
+pub const KV_LOCKOUT: u8 = 45;
+pub const KV_LOCKOUT_LEVEL: u8 = 46;
+pub const KV_KNOWN_DEVICE: u8 = 47;
+pub const KV_SUBMISSION_LOCATION: u8 = 48;
+pub const KV_SUBMISSION_VOLUME: u8 = 49;
+pub const KV_SUBMISSION_BASELINE: u8 = 50;
+pub const KV_SENDING_LIMIT: u8 = 51;
+pub const KV_QUEUE_ROUTE: u8 = 52;
+pub const KV_DELIVERABILITY_PROBE: u8 = 53;
+pub const KV_LOCK_ENCRYPTION: u8 = 54;
+pub const KV_PUSH_DEVICES: u8 = 55;
+pub const KV_QUEUE_NODE: u8 = 56;
+pub const KV_WEBAUTHN_SIGN_COUNT: u8 = 57;
+pub const KV_LOCK_WEBAUTHN: u8 = 58;
 
 pub const IDX_UID: u8 = 0;
 pub const IDX_EMAIL: u8 = 1;
@@ -126,9 +165,12 @@ pub struct Inner {
 
 pub struct Data {
     pub tls_certificates: ArcSwap<AHashMap<String, Arc<CertifiedKey>>>,
+    pub tls_managed_certificates: ArcSwap<AHashMap<String, ManagedCertificate>>,
     pub tls_self_signed_cert: Option<Arc<CertifiedKey>>,
 
     pub blocked_ips: RwLock<AHashSet<IpAddr>>,
+    pub ip_lists: RwLock<AHashMap<String, Arc<IpSet>>>,
+    pub ip_feeds: RwLock<AHashMap<String, Arc<IpSet>>>,
 
     pub asn_geo_data: AsnGeoLookupData,
 
@@ -136,6 +178,14 @@ pub struct Data {
     pub queue_id_gen: SnowflakeIdGenerator,
     pub span_id_gen: SnowflakeIdGenerator,
     pub queue_status: AtomicBool,
+    pub smtp_maintenance: AtomicBool,
+    pub partition_health: RwLock<AHashMap<String, PartitionHealth>>,
+    pub dnsbl_status: Mutex<AHashMap<String, DnsBlStatus>>,
+    pub filter_health: Mutex<AHashMap<String, FilterHealth>>,
+    pub active_accounts: Mutex<AHashSet<u32>>,
+    pub rate_limiter_tokens: Mutex<AHashMap<ThrottleKey, RateLimiterTokens>>,
+    pub archive_chain: tokio::sync::Mutex<()>,
+    pub tls_ticket_keys: Arc<TicketKeys>,
 
     pub webadmin: WebAdminManager,
     pub logos: Mutex<AHashMap<String, Option<Resource<Vec<u8>>>>>,
@@ -164,6 +214,8 @@ pub struct Caches {
     pub dns_tlsa: CacheWithTtl<String, Arc<Tlsa>>,
     pub dbs_mta_sts: CacheWithTtl<String, Arc<Policy>>,
     pub dns_rbl: CacheWithTtl<String, Option<Arc<IpResolver>>>,
+
+    pub antivirus: CacheWithTtl<ScanKey, ScanVerdict>,
 }
 
 #[derive(Debug, Clone)]
@@ -223,10 +275,11 @@ pub struct MailboxCache {
     pub acls: TinyVec<[AclGrant; 2]>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct HttpAuthCache {
     pub account_id: u32,
     pub revision: u64,
+    pub remote_ip: IpAddr,
 }
 
 pub struct Ipc {
@@ -236,6 +289,7 @@ pub struct Ipc {
     pub queue_tx: mpsc::Sender<QueueEvent>,
     pub report_tx: mpsc::Sender<ReportingEvent>,
     pub broadcast_tx: Option<mpsc::Sender<BroadcastEvent>>,
+    pub push_gateway_tx: mpsc::Sender<PushGatewayEvent>,
     pub local_delivery_sm: Arc<Semaphore>,
 }
 
@@ -329,6 +383,7 @@ pub struct Core {
     pub groupware: GroupwareConfig,
     pub spam: SpamFilterConfig,
     pub imap: ImapConfig,
+    pub push_gateway: PushGatewayConfig,
     pub metrics: Metrics,
     #[cfg(feature = "enterprise")]
     pub enterprise: Option<enterprise::Enterprise>,
@@ -392,6 +447,13 @@ impl AsRef<[u8]> for ThrottleKey {
     }
 }
 
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RateLimiterTokens {
+    pub window: u64,
+    pub tokens: u64,
+    pub expires: u64,
+}
+
 #[derive(Default)]
 pub struct ThrottleKeyHasher {
     hash: u64,
@@ -470,6 +532,7 @@ impl Default for Caches {
             dns_ipv6: CacheWithTtl::new(1024, 10 * 1024 * 1024),
             dns_tlsa: CacheWithTtl::new(1024, 10 * 1024 * 1024),
             dbs_mta_sts: CacheWithTtl::new(1024, 10 * 1024 * 1024),
+            antivirus: CacheWithTtl::new(1024, 1024 * 1024),
         }
     }
 }
@@ -484,6 +547,7 @@ impl Default for Ipc {
             queue_tx: mpsc::channel(IPC_CHANNEL_BUFFER).0,
             report_tx: mpsc::channel(IPC_CHANNEL_BUFFER).0,
             broadcast_tx: None,
+            push_gateway_tx: mpsc::channel(IPC_CHANNEL_BUFFER).0,
             local_delivery_sm: Arc::new(Semaphore::new(10)),
         }
     }
@@ -10,7 +10,7 @@ use utils::config::ConfigKey;
 
 use crate::Server;
 
-use super::AcmeProvider;
+use super::{AcmeProvider, tlsa::NextKey};
 
 impl Server {
     pub(crate) async fn load_cert(&self, provider: &AcmeProvider) -> trc::Result<Option<Vec<u8>>> {
@@ -61,6 +61,62 @@ impl Server {
         })
     }
 
+    pub(crate) async fn load_next_key(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<NextKey>> {
+        self.read_if_exists(provider, "next-key", provider.domains.as_slice())
+            .await
+            .map(|next_key| next_key.and_then(|next_key| NextKey::deserialize(&next_key)))
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to load next key")
+            })
+    }
+
+    pub(crate) async fn store_next_key(
+        &self,
+        provider: &AcmeProvider,
+        next_key: &NextKey,
+    ) -> trc::Result<()> {
+        self.write(
+            provider,
+            "next-key",
+            provider.domains.as_slice(),
+            next_key.serialize(),
+        )
+        .await
+        .add_context(|err| {
+            err.caused_by(trc::location!())
+                .details("Failed to store next key")
+        })
+    }
+
+    pub(crate) async fn load_tlsa_records(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        self.read_if_exists(provider, "tlsa", provider.domains.as_slice())
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to load TLSA records")
+            })
+    }
+
+    pub(crate) async fn store_tlsa_records(
+        &self,
+        provider: &AcmeProvider,
+        records: &[u8],
+    ) -> trc::Result<()> {
+        self.write(provider, "tlsa", provider.domains.as_slice(), records)
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to store TLSA records")
+            })
+    }
+
     async fn read_if_exists(
         &self,
         provider: &AcmeProvider,
@@ -8,17 +8,22 @@ pub mod cache;
 pub mod directory;
 pub mod jose;
 pub mod order;
+pub mod provider;
 pub mod resolver;
+pub mod tlsa;
 
 use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use arc_swap::ArcSwap;
-use dns_update::DnsUpdater;
 use rustls::sign::CertifiedKey;
 
 use crate::Server;
 
-use self::directory::{Account, ChallengeType};
+use self::{
+    directory::{Account, ChallengeType},
+    provider::ChallengeUpdater,
+    tlsa::TlsaSettings,
+};
 
 pub struct AcmeProvider {
     pub id: String,
@@ -27,6 +32,7 @@ pub struct AcmeProvider {
     pub contact: Vec<String>,
     pub challenge: ChallengeSettings,
     pub eab: Option<EabSettings>,
+    pub tlsa: Option<TlsaSettings>,
     renew_before: chrono::Duration,
     account_key: ArcSwap<Vec<u8>>,
     default: bool,
@@ -43,7 +49,7 @@ pub enum ChallengeSettings {
     Http01,
     TlsAlpn01,
     Dns01 {
-        updater: DnsUpdater,
+        updater: ChallengeUpdater,
         origin: Option<String>,
         polling_interval: Duration,
         propagation_timeout: Duration,
@@ -64,6 +70,7 @@ impl AcmeProvider {
         contact: Vec<String>,
         challenge: ChallengeSettings,
         eab: Option<EabSettings>,
+        tlsa: Option<TlsaSettings>,
         renew_before: Duration,
         default: bool,
     ) -> trc::Result<Self> {
@@ -85,6 +92,7 @@ impl AcmeProvider {
             account_key: Default::default(),
             challenge,
             eab,
+            tlsa,
             default,
         })
     }
@@ -3,7 +3,6 @@
 use chrono::{DateTime, TimeZone, Utc};
 
 use compact_str::CompactString;
-use dns_update::DnsRecord;
 use futures::future::try_join_all;
 use rcgen::{CertificateParams, DistinguishedName, PKCS_ECDSA_P256_SHA256};
 use rustls::crypto::ring::sign::any_ecdsa_type;
@@ -30,8 +29,9 @@ impl Server {
         cached: bool,
     ) -> trc::Result<Duration> {
         let (cert, validity) = parse_cert(&pem)?;
+        let cert = Arc::new(cert);
 
-        self.set_cert(provider, Arc::new(cert));
+        self.set_cert(provider, cert.clone());
 
         let renew_at = (validity[1] - provider.renew_before - Utc::now())
             .max(chrono::Duration::zero())
@@ -52,10 +52,25 @@ impl Server {
             self.store_cert(provider, &pem).await?;
         }
 
+        // Publish the TLSA records of this certificate and of the next one
+        if let Err(err) = self.update_tlsa(provider, &cert).await {
+            trc::error!(err.details("Failed to update TLSA records"));
+        }
+
         Ok(renew_at)
     }
 
     pub async fn renew(&self, provider: &AcmeProvider) -> trc::Result<Duration> {
+        if let Some(wait) = self.tlsa_rollover_wait(provider).await? {
+            trc::event!(
+                Acme(AcmeEvent::TlsaRolloverPending),
+                Id = provider.id.to_string(),
+                Hostname = provider.domains.as_slice(),
+                Due = trc::Value::Timestamp(Utc::now().timestamp() as u64 + wait.as_secs()),
+            );
+            return Ok(wait);
+        }
+
         let mut backoff = 0;
         loop {
             match self.order(provider).await {
@@ -91,6 +106,7 @@ impl Server {
         let mut params = CertificateParams::new(provider.domains.clone());
         params.distinguished_name = DistinguishedName::new();
         params.alg = &PKCS_ECDSA_P256_SHA256;
+        params.key_pair = self.next_key_pair(provider).await?;
         let cert = rcgen::Certificate::from_params(params).map_err(|err| {
             EventType::Acme(AcmeEvent::Error)
                 .caused_by(trc::location!())
@@ -265,14 +281,7 @@ impl Server {
 
                         // Create the record
                         if let Err(err) = updater
-                            .create(
-                                &name,
-                                DnsRecord::TXT {
-                                    content: dns_proof.clone(),
-                                },
-                                *ttl,
-                                &origin,
-                            )
+                            .create_txt(&name, dns_proof.clone(), *ttl, &origin)
                             .await
                         {
                             return Err(EventType::Acme(AcmeEvent::DnsRecordCreationFailed)
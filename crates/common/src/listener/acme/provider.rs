@@ -0,0 +1,707 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// DNS providers used by the ACME subsystem to publish DNS-01 challenge and
+// TLSA records. Each provider replaces a whole RRset at once, an empty set
+// removes the records of that type from the name. The dns-update crate has
+// no TLSA or Route53 support, RFC 2136 updates are built and authenticated
+// with hickory-proto instead, the library dns-update itself relies on.
+
+use std::{net::SocketAddr, time::Duration};
+
+use chrono::Utc;
+use dns_update::{DnsRecord, DnsUpdater};
+use hickory_proto::{
+    op::{Message, MessageVerifier, ResponseCode, UpdateMessage, update_message},
+    rr::{
+        Name, RData, Record, RecordType,
+        dnssec::tsig::TSigner,
+        rdata::{
+            TXT,
+            tlsa::{CertUsage, Matching, Selector, TLSA},
+        },
+    },
+};
+use ring::hmac;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com/client/v4";
+const ROUTE53_HOST: &str = "route53.amazonaws.com";
+const DNS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub enum DnsProvider {
+    Rfc2136 {
+        addr: SocketAddr,
+        is_tcp: bool,
+        signer: TSigner,
+    },
+    Cloudflare {
+        secret: String,
+        email: Option<String>,
+        timeout: Duration,
+    },
+    Route53 {
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+        zone_id: String,
+        timeout: Duration,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsRecordSet {
+    Txt(Vec<String>),
+    // "3 1 1" records with the SHA-256 digest of the public key
+    Tlsa(Vec<[u8; 32]>),
+}
+
+// DNS-01 challenges keep using the dns-update crate for the providers it
+// supports, so that existing TSIG algorithms continue to work.
+#[derive(Clone)]
+pub enum ChallengeUpdater {
+    DnsUpdate(DnsUpdater),
+    Provider(DnsProvider),
+}
+
+impl ChallengeUpdater {
+    pub async fn delete(&self, name: &str, origin: &str) -> Result<(), String> {
+        match self {
+            ChallengeUpdater::DnsUpdate(updater) => updater
+                .delete(name, origin)
+                .await
+                .map_err(|err| err.to_string()),
+            ChallengeUpdater::Provider(provider) => {
+                provider
+                    .update(name, origin, &DnsRecordSet::Txt(vec![]), 0)
+                    .await
+            }
+        }
+    }
+
+    pub async fn create_txt(
+        &self,
+        name: &str,
+        content: String,
+        ttl: u32,
+        origin: &str,
+    ) -> Result<(), String> {
+        match self {
+            ChallengeUpdater::DnsUpdate(updater) => updater
+                .create(name, DnsRecord::TXT { content }, ttl, origin)
+                .await
+                .map_err(|err| err.to_string()),
+            ChallengeUpdater::Provider(provider) => {
+                provider
+                    .update(name, origin, &DnsRecordSet::Txt(vec![content]), ttl)
+                    .await
+            }
+        }
+    }
+}
+
+impl DnsRecordSet {
+    fn record_type(&self) -> RecordType {
+        match self {
+            DnsRecordSet::Txt(_) => RecordType::TXT,
+            DnsRecordSet::Tlsa(_) => RecordType::TLSA,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            DnsRecordSet::Txt(_) => "TXT",
+            DnsRecordSet::Tlsa(_) => "TLSA",
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            DnsRecordSet::Txt(values) => values.is_empty(),
+            DnsRecordSet::Tlsa(digests) => digests.is_empty(),
+        }
+    }
+
+    fn rdata(&self) -> Vec<RData> {
+        match self {
+            DnsRecordSet::Txt(values) => values
+                .iter()
+                .map(|value| RData::TXT(TXT::new(vec![value.clone()])))
+                .collect(),
+            DnsRecordSet::Tlsa(digests) => digests
+                .iter()
+                .map(|digest| {
+                    RData::TLSA(TLSA::new(
+                        CertUsage::DomainIssued,
+                        Selector::Spki,
+                        Matching::Sha256,
+                        digest.to_vec(),
+                    ))
+                })
+                .collect(),
+        }
+    }
+
+    // Record values in zone file presentation format
+    fn presentation(&self) -> Vec<String> {
+        match self {
+            DnsRecordSet::Txt(values) => values
+                .iter()
+                .map(|value| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect(),
+            DnsRecordSet::Tlsa(digests) => digests
+                .iter()
+                .map(|digest| format!("3 1 1 {}", hex(digest)))
+                .collect(),
+        }
+    }
+}
+
+impl DnsProvider {
+    // Replaces the RRset of a name with the given records
+    pub async fn update(
+        &self,
+        name: &str,
+        origin: &str,
+        records: &DnsRecordSet,
+        ttl: u32,
+    ) -> Result<(), String> {
+        match self {
+            DnsProvider::Rfc2136 {
+                addr,
+                is_tcp,
+                signer,
+            } => {
+                let mut message = build_update(name, origin, records, ttl)?;
+                let mut verifier = message
+                    .finalize(signer, Utc::now().timestamp() as u32)
+                    .map_err(|err| format!("Failed to sign DNS update: {err}"))?
+                    .ok_or_else(|| "Failed to sign DNS update".to_string())?;
+                let request = message
+                    .to_vec()
+                    .map_err(|err| format!("Failed to encode DNS update: {err}"))?;
+
+                let response = tokio::time::timeout(DNS_TIMEOUT, async {
+                    if *is_tcp {
+                        let mut stream = TcpStream::connect(addr).await?;
+                        stream
+                            .write_all(&(request.len() as u16).to_be_bytes())
+                            .await?;
+                        stream.write_all(&request).await?;
+                        let len = stream.read_u16().await? as usize;
+                        let mut response = vec![0u8; len];
+                        stream.read_exact(&mut response).await?;
+                        Ok::<_, std::io::Error>(response)
+                    } else {
+                        let socket = UdpSocket::bind(if addr.is_ipv4() {
+                            "0.0.0.0:0"
+                        } else {
+                            "[::]:0"
+                        })
+                        .await?;
+                        socket.connect(addr).await?;
+                        socket.send(&request).await?;
+                        let mut response = vec![0u8; 4096];
+                        let len = socket.recv(&mut response).await?;
+                        response.truncate(len);
+                        Ok::<_, std::io::Error>(response)
+                    }
+                })
+                .await
+                .map_err(|_| "DNS server timed out".to_string())?
+                .map_err(|err| format!("Failed to send DNS update: {err}"))?;
+
+                check_update_response(message.id(), &response, &mut verifier)
+            }
+            DnsProvider::Cloudflare {
+                secret,
+                email,
+                timeout,
+            } => {
+                let client = reqwest::Client::builder()
+                    .timeout(*timeout)
+                    .build()
+                    .map_err(|err| format!("Failed to create HTTP client: {err}"))?;
+                let request = |method: reqwest::Method, path: String| {
+                    let request = client.request(method, format!("{CLOUDFLARE_API}{path}"));
+                    if let Some(email) = email {
+                        request
+                            .header("X-Auth-Email", email)
+                            .header("X-Auth-Key", secret)
+                    } else {
+                        request.bearer_auth(secret)
+                    }
+                };
+                let name = name.trim_end_matches('.');
+                let record_type = records.type_name();
+
+                let zone_id = cloudflare_request(request(
+                    reqwest::Method::GET,
+                    format!("/zones?name={}", origin.trim_end_matches('.')),
+                ))
+                .await?["result"][0]["id"]
+                    .as_str()
+                    .map(|id| id.to_string())
+                    .ok_or_else(|| format!("Zone {origin} not found"))?;
+
+                // Remove stale records first, then add the missing ones
+                let mut pending = match records {
+                    DnsRecordSet::Txt(values) => values.clone(),
+                    DnsRecordSet::Tlsa(digests) => digests.iter().map(|d| hex(d)).collect(),
+                };
+                let existing = cloudflare_request(request(
+                    reqwest::Method::GET,
+                    format!("/zones/{zone_id}/dns_records?type={record_type}&name={name}"),
+                ))
+                .await?;
+                for record in existing["result"].as_array().into_iter().flatten() {
+                    let value = match records {
+                        DnsRecordSet::Txt(_) => record["content"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .trim_matches('"')
+                            .to_string(),
+                        DnsRecordSet::Tlsa(_) => record["data"]["certificate"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_ascii_lowercase(),
+                    };
+                    if let Some(pos) = pending.iter().position(|v| *v == value) {
+                        pending.swap_remove(pos);
+                    } else if let Some(record_id) = record["id"].as_str() {
+                        cloudflare_request(request(
+                            reqwest::Method::DELETE,
+                            format!("/zones/{zone_id}/dns_records/{record_id}"),
+                        ))
+                        .await?;
+                    }
+                }
+                for value in pending {
+                    let record = match records {
+                        DnsRecordSet::Txt(_) => json!({
+                            "type": "TXT",
+                            "name": name,
+                            "ttl": ttl,
+                            "content": value,
+                        }),
+                        DnsRecordSet::Tlsa(_) => json!({
+                            "type": "TLSA",
+                            "name": name,
+                            "ttl": ttl,
+                            "data": {
+                                "usage": 3,
+                                "selector": 1,
+                                "matching_type": 1,
+                                "certificate": value,
+                            },
+                        }),
+                    };
+                    cloudflare_request(
+                        request(
+                            reqwest::Method::POST,
+                            format!("/zones/{zone_id}/dns_records"),
+                        )
+                        .header("Content-Type", "application/json")
+                        .body(record.to_string()),
+                    )
+                    .await?;
+                }
+
+                Ok(())
+            }
+            DnsProvider::Route53 {
+                access_key,
+                secret_key,
+                session_token,
+                zone_id,
+                timeout,
+            } => {
+                // Deleting requires the exact current values, stale records
+                // are replaced instead by the next UPSERT
+                if records.is_empty() {
+                    return Ok(());
+                }
+
+                let zone_id = zone_id.trim_start_matches("/hostedzone/");
+                let path = format!("/2013-04-01/hostedzone/{zone_id}/rrset");
+                let body = build_route53_change(name, records, ttl);
+                let date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+                let authorization = route53_authorization(
+                    access_key,
+                    secret_key,
+                    session_token.as_deref(),
+                    &path,
+                    &date,
+                    &body,
+                );
+
+                let mut request = reqwest::Client::builder()
+                    .timeout(*timeout)
+                    .build()
+                    .map_err(|err| format!("Failed to create HTTP client: {err}"))?
+                    .post(format!("https://{ROUTE53_HOST}{path}"))
+                    .header("Content-Type", "text/xml")
+                    .header("X-Amz-Date", &date)
+                    .header("Authorization", authorization);
+                if let Some(session_token) = session_token {
+                    request = request.header("X-Amz-Security-Token", session_token);
+                }
+                let response = request
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|err| format!("Route53 request failed: {err}"))?;
+
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Route53 request failed with status {}: {}",
+                        response.status(),
+                        response.text().await.unwrap_or_default()
+                    ))
+                }
+            }
+        }
+    }
+}
+
+async fn cloudflare_request(request: reqwest::RequestBuilder) -> Result<serde_json::Value, String> {
+    let response = request
+        .send()
+        .await
+        .map_err(|err| format!("Cloudflare request failed: {err}"))?
+        .bytes()
+        .await
+        .map_err(|err| format!("Failed to read Cloudflare response: {err}"))?;
+    let response = serde_json::from_slice::<serde_json::Value>(&response)
+        .map_err(|err| format!("Invalid Cloudflare response: {err}"))?;
+
+    if response["success"].as_bool() == Some(true) {
+        Ok(response)
+    } else {
+        Err(format!("Cloudflare request failed: {}", response["errors"]))
+    }
+}
+
+// Replaces the RRset of a name as described in RFC 2136, Section 2.5.2 and 2.5.1
+fn build_update(
+    name: &str,
+    origin: &str,
+    records: &DnsRecordSet,
+    ttl: u32,
+) -> Result<Message, String> {
+    let parse_name = |name: &str| {
+        Name::from_ascii(name)
+            .map(|mut name| {
+                name.set_fqdn(true);
+                name.to_lowercase()
+            })
+            .map_err(|err| format!("Invalid DNS name {name:?}: {err}"))
+    };
+    let name = parse_name(name)?;
+    let origin = parse_name(origin)?;
+    if !origin.zone_of(&name) {
+        return Err(format!("{name} does not belong to zone {origin}"));
+    }
+
+    let mut message = update_message::delete_rrset(
+        Record::with(name.clone(), records.record_type(), 0),
+        origin,
+        false,
+    );
+    for rdata in records.rdata() {
+        message.add_update(Record::from_rdata(name.clone(), ttl, rdata));
+    }
+
+    Ok(message)
+}
+
+// Responses to signed updates have to carry a valid TSIG record (RFC 8945, Section 5.3),
+// unsigned error responses are only used to report the reason of the failure
+fn check_update_response(
+    id: u16,
+    response: &[u8],
+    verifier: &mut MessageVerifier,
+) -> Result<(), String> {
+    let response_code = match verifier(response) {
+        Ok(response) if response.id() == id => response.response_code(),
+        Ok(_) => return Err("Invalid response from DNS server".to_string()),
+        Err(err) => {
+            return match Message::from_vec(response) {
+                Ok(response)
+                    if response.id() == id && response.response_code() != ResponseCode::NoError =>
+                {
+                    Err(format!(
+                        "DNS server rejected update: {}",
+                        response.response_code()
+                    ))
+                }
+                _ => Err(format!("Failed to verify DNS server response: {err}")),
+            };
+        }
+    };
+
+    if response_code == ResponseCode::NoError {
+        Ok(())
+    } else {
+        Err(format!("DNS server rejected update: {response_code}"))
+    }
+}
+
+fn build_route53_change(name: &str, records: &DnsRecordSet, ttl: u32) -> String {
+    let mut values = String::new();
+    for value in records.presentation() {
+        values.push_str(&format!(
+            "<ResourceRecord><Value>{}</Value></ResourceRecord>",
+            value
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        ));
+    }
+
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>",
+            "<ChangeResourceRecordSetsRequest xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">",
+            "<ChangeBatch><Changes><Change><Action>UPSERT</Action><ResourceRecordSet>",
+            "<Name>{}.</Name><Type>{}</Type><TTL>{}</TTL><ResourceRecords>{}</ResourceRecords>",
+            "</ResourceRecordSet></Change></Changes></ChangeBatch></ChangeResourceRecordSetsRequest>"
+        ),
+        name.trim_end_matches('.'),
+        records.type_name(),
+        ttl,
+        values
+    )
+}
+
+// AWS Signature Version 4, Route53 is a global service signed for us-east-1
+fn route53_authorization(
+    access_key: &str,
+    secret_key: &str,
+    session_token: Option<&str>,
+    path: &str,
+    date: &str,
+    body: &str,
+) -> String {
+    let day = &date[..8];
+    let scope = format!("{day}/us-east-1/route53/aws4_request");
+    let (headers, signed_headers) = if let Some(session_token) = session_token {
+        (
+            format!(
+                "content-type:text/xml\nhost:{ROUTE53_HOST}\nx-amz-date:{date}\nx-amz-security-token:{session_token}\n"
+            ),
+            "content-type;host;x-amz-date;x-amz-security-token",
+        )
+    } else {
+        (
+            format!("content-type:text/xml\nhost:{ROUTE53_HOST}\nx-amz-date:{date}\n"),
+            "content-type;host;x-amz-date",
+        )
+    };
+    let canonical_request = format!(
+        "POST\n{path}\n\n{headers}\n{signed_headers}\n{}",
+        hex(&Sha256::digest(body.as_bytes()))
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{date}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let mut key = format!("AWS4{secret_key}").into_bytes();
+    for part in [day, "us-east-1", "route53", "aws4_request", &string_to_sign] {
+        key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), part.as_bytes())
+            .as_ref()
+            .to_vec();
+    }
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={access_key}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+        hex(&key)
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_proto::{
+        op::{Message, MessageType, ResponseCode, UpdateMessage},
+        rr::{
+            Name, RData, RecordType,
+            dnssec::{
+                rdata::{
+                    DNSSECRData,
+                    tsig::{TSIG, TsigAlgorithm, make_tsig_record, message_tbs},
+                },
+                tsig::TSigner,
+            },
+        },
+    };
+
+    use super::{
+        DnsRecordSet, build_route53_change, build_update, check_update_response,
+        route53_authorization,
+    };
+
+    #[test]
+    fn dns_update_messages() {
+        // The RRset is deleted and replaced by the new records
+        let message = build_update(
+            "_25._tcp.MX.example.org",
+            "example.org.",
+            &DnsRecordSet::Tlsa(vec![[0xab; 32]]),
+            3600,
+        )
+        .unwrap();
+        assert_eq!(
+            message.zones()[0].name(),
+            &Name::from_ascii("example.org.").unwrap()
+        );
+        let updates = message.updates();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(
+            updates[0].name(),
+            &Name::from_ascii("_25._tcp.mx.example.org.").unwrap()
+        );
+        assert_eq!(updates[0].record_type(), RecordType::TLSA);
+        assert_eq!(updates[0].ttl(), 0);
+        match updates[1].data() {
+            Some(RData::TLSA(tlsa)) => {
+                assert_eq!(tlsa.cert_data(), &[0xab; 32]);
+                assert_eq!(updates[1].ttl(), 3600);
+            }
+            other => panic!("Unexpected record {other:?}"),
+        }
+
+        // Names have to be valid and belong to the zone
+        assert!(
+            build_update(
+                &format!("{}.example.org", "a".repeat(64)),
+                "example.org",
+                &DnsRecordSet::Txt(vec!["proof".to_string()]),
+                60
+            )
+            .is_err()
+        );
+        assert!(
+            build_update(
+                "_acme-challenge.example.net",
+                "example.org",
+                &DnsRecordSet::Txt(vec!["proof".to_string()]),
+                60
+            )
+            .unwrap_err()
+            .contains("does not belong")
+        );
+
+        // Responses have to be signed with the same key
+        let key_name = Name::from_ascii("update-key.").unwrap();
+        let signer = TSigner::new(
+            b"secret".to_vec(),
+            TsigAlgorithm::HmacSha256,
+            key_name.clone(),
+            300,
+        )
+        .unwrap();
+        let sign = |response_code: ResponseCode, signer: &TSigner| {
+            let mut message = build_update(
+                "_acme-challenge.example.org",
+                "example.org",
+                &DnsRecordSet::Txt(vec!["proof".to_string()]),
+                60,
+            )
+            .unwrap();
+            let verifier = message.finalize(signer, 1_700_000_000).unwrap().unwrap();
+            let request_mac = match message.signature()[0].data() {
+                Some(RData::DNSSEC(DNSSECRData::TSIG(tsig))) => tsig.mac().to_vec(),
+                _ => unreachable!(),
+            };
+            let mut response = Message::new();
+            response
+                .set_id(message.id())
+                .set_message_type(MessageType::Response)
+                .set_response_code(response_code);
+            let unsigned = response.to_vec().unwrap();
+            let pre_tsig = TSIG::new(
+                TsigAlgorithm::HmacSha256,
+                1_700_000_000,
+                300,
+                vec![],
+                message.id(),
+                0,
+                vec![],
+            );
+            let mac = signer
+                .sign(&message_tbs(Some(&request_mac), &response, &pre_tsig, &key_name).unwrap())
+                .unwrap();
+            response.add_tsig(make_tsig_record(key_name.clone(), pre_tsig.set_mac(mac)));
+            (message.id(), unsigned, response.to_vec().unwrap(), verifier)
+        };
+        let (id, _, signed, mut verifier) = sign(ResponseCode::NoError, &signer);
+        assert_eq!(check_update_response(id, &signed, &mut verifier), Ok(()));
+        let (id, unsigned, _, mut verifier) = sign(ResponseCode::NoError, &signer);
+        assert!(
+            check_update_response(id, &unsigned, &mut verifier)
+                .unwrap_err()
+                .contains("Failed to verify")
+        );
+        let other_signer = TSigner::new(
+            b"other".to_vec(),
+            TsigAlgorithm::HmacSha256,
+            key_name.clone(),
+            300,
+        )
+        .unwrap();
+        let (id, _, signed, _) = sign(ResponseCode::NoError, &other_signer);
+        let (_, _, _, mut verifier) = sign(ResponseCode::NoError, &signer);
+        assert!(check_update_response(id, &signed, &mut verifier).is_err());
+        let (id, unsigned, _, mut verifier) = sign(ResponseCode::Refused, &signer);
+        assert!(
+            check_update_response(id, &unsigned, &mut verifier)
+                .unwrap_err()
+                .contains("rejected")
+        );
+
+        assert!(
+            build_route53_change(
+                "_acme-challenge.example.org",
+                &DnsRecordSet::Txt(vec!["proof".to_string()]),
+                60
+            )
+            .contains(concat!(
+                "<Name>_acme-challenge.example.org.</Name><Type>TXT</Type><TTL>60</TTL>",
+                "<ResourceRecords><ResourceRecord><Value>\"proof\"</Value></ResourceRecord>"
+            ))
+        );
+
+        assert_eq!(
+            route53_authorization(
+                "AKIDEXAMPLE",
+                "secret",
+                None,
+                "/2013-04-01/hostedzone/Z1/rrset",
+                "20240101T000000Z",
+                "<xml/>"
+            )
+            .split_once(", Signature=")
+            .map(|(credential, signature)| (credential.to_string(), signature.len())),
+            Some((
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240101/us-east-1/route53/aws4_request, SignedHeaders=content-type;host;x-amz-date".to_string(),
+                64
+            ))
+        );
+    }
+}
@@ -0,0 +1,202 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// TLSA records are maintained using the "current + next" scheme described in
+// RFC 7671, Section 8.1. The key pair of the next certificate is generated in
+// advance and its 3 1 1 record is published alongside the one of the active
+// certificate. Renewals wait until the next key has been published for at
+// least the configured rollover window, so that resolvers never see a
+// certificate whose key is missing from the cached TLSA RRset.
+
+use std::time::Duration;
+
+use rcgen::{KeyPair, PKCS_ECDSA_P256_SHA256};
+use rustls::sign::CertifiedKey;
+use sha2::{Digest, Sha256};
+use store::write::now;
+use trc::{AcmeEvent, EventType};
+use x509_parser::parse_x509_certificate;
+
+use crate::Server;
+
+use super::{
+    AcmeProvider,
+    provider::{DnsProvider, DnsRecordSet},
+};
+
+#[derive(Clone)]
+pub struct TlsaSettings {
+    pub updater: DnsProvider,
+    pub hosts: Vec<String>,
+    pub origin: Option<String>,
+    pub port: u16,
+    pub ttl: u32,
+    pub rollover: Duration,
+}
+
+pub(crate) struct NextKey {
+    pub published_at: u64,
+    pub der: Vec<u8>,
+    pub digest: [u8; 32],
+}
+
+impl Server {
+    pub(crate) async fn update_tlsa(
+        &self,
+        provider: &AcmeProvider,
+        cert: &CertifiedKey,
+    ) -> trc::Result<()> {
+        let Some(tlsa) = &provider.tlsa else {
+            return Ok(());
+        };
+        let current = cert
+            .cert
+            .first()
+            .and_then(|cert| parse_x509_certificate(cert).ok())
+            .map(|(_, cert)| spki_digest(cert.subject_pki.raw))
+            .ok_or_else(|| {
+                EventType::Acme(AcmeEvent::Error)
+                    .caused_by(trc::location!())
+                    .details("Failed to parse certificate")
+            })?;
+
+        // Generate a new key pair once the previous one is in use
+        let mut next = match self.load_next_key(provider).await? {
+            Some(next) if next.digest != current => next,
+            _ => {
+                let next = NextKey::generate()?;
+                self.store_next_key(provider, &next).await?;
+                next
+            }
+        };
+
+        let mut digests = vec![current];
+        if next.digest != current {
+            digests.push(next.digest);
+        }
+        let records = digests.concat();
+        if self.load_tlsa_records(provider).await?.as_deref() == Some(records.as_slice()) {
+            return Ok(());
+        }
+
+        for host in &tlsa.hosts {
+            let name = format!("_{}._tcp.{host}", tlsa.port);
+            let origin = tlsa
+                .origin
+                .as_deref()
+                .or_else(|| psl::domain_str(host))
+                .unwrap_or(host);
+            tlsa.updater
+                .update(
+                    &name,
+                    origin,
+                    &DnsRecordSet::Tlsa(digests.clone()),
+                    tlsa.ttl,
+                )
+                .await
+                .map_err(|err| {
+                    EventType::Acme(AcmeEvent::TlsaRecordsUpdateFailed)
+                        .ctx(trc::Key::Id, provider.id.to_string())
+                        .ctx(trc::Key::Hostname, name)
+                        .ctx(trc::Key::Details, origin.to_string())
+                        .reason(err)
+                })?;
+        }
+        self.store_tlsa_records(provider, &records).await?;
+
+        // The rollover window starts once the next key has been published
+        if next.published_at == 0 {
+            next.published_at = now();
+            self.store_next_key(provider, &next).await?;
+        }
+
+        trc::event!(
+            Acme(AcmeEvent::TlsaRecordsUpdated),
+            Id = provider.id.to_string(),
+            Hostname = tlsa.hosts.as_slice(),
+            Total = digests.len(),
+        );
+
+        Ok(())
+    }
+
+    pub(crate) async fn tlsa_rollover_wait(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<Duration>> {
+        let Some(tlsa) = &provider.tlsa else {
+            return Ok(None);
+        };
+
+        // Keys that were never published do not hold back renewals
+        let now = now();
+        Ok(self
+            .load_next_key(provider)
+            .await?
+            .filter(|next| next.published_at != 0)
+            .map(|next| next.published_at + tlsa.rollover.as_secs())
+            .filter(|ready_at| *ready_at > now)
+            .map(|ready_at| Duration::from_secs(ready_at - now)))
+    }
+
+    pub(crate) async fn next_key_pair(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<KeyPair>> {
+        let next = if provider.tlsa.is_some() {
+            self.load_next_key(provider).await?
+        } else {
+            None
+        };
+
+        next.map(|next| KeyPair::from_der(&next.der))
+            .transpose()
+            .map_err(|err| {
+                EventType::Acme(AcmeEvent::Error)
+                    .caused_by(trc::location!())
+                    .reason(err)
+            })
+    }
+}
+
+impl NextKey {
+    fn generate() -> trc::Result<Self> {
+        let key_pair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256).map_err(|err| {
+            EventType::Acme(AcmeEvent::Error)
+                .caused_by(trc::location!())
+                .reason(err)
+        })?;
+
+        Ok(NextKey {
+            published_at: 0,
+            der: key_pair.serialize_der(),
+            digest: spki_digest(&key_pair.public_key_der()),
+        })
+    }
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.der.len() + 8);
+        bytes.extend_from_slice(&self.published_at.to_be_bytes());
+        bytes.extend_from_slice(&self.der);
+        bytes
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let published_at = u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?);
+        let der = bytes.get(8..)?.to_vec();
+        let digest = spki_digest(&KeyPair::from_der(&der).ok()?.public_key_der());
+
+        Some(NextKey {
+            published_at,
+            der,
+            digest,
+        })
+    }
+}
+
+fn spki_digest(spki: &[u8]) -> [u8; 32] {
+    Sha256::digest(spki).into()
+}
@@ -18,7 +18,10 @@ use utils::{
 
 use crate::{
     KV_RATE_LIMIT_AUTH, KV_RATE_LIMIT_LOITER, KV_RATE_LIMIT_RCPT, KV_RATE_LIMIT_SCAN, Server,
-    ip_to_bytes, ipc::BroadcastEvent, manager::config::MatchType,
+    ip_to_bytes,
+    ipc::BroadcastEvent,
+    listener::{iplist::IpListAction, lockout::AuthLockout},
+    manager::config::MatchType,
 };
 
 #[derive(Debug, Clone)]
@@ -36,6 +39,8 @@ pub struct Security {
     auth_fail_rate: Option<Rate>,
     rcpt_fail_rate: Option<Rate>,
     loiter_fail_rate: Option<Rate>,
+
+    pub lockout: Option<AuthLockout>,
 }
 
 pub const BLOCKED_IP_KEY: &str = "server.blocked-ip";
@@ -132,6 +137,7 @@ impl Security {
             scanner_fail_rate: config
                 .property_or_default::<Option<Rate>>("server.auto-ban.scan.rate", "30/1d")
                 .unwrap_or_default(),
+            lockout: AuthLockout::parse(config),
         }
     }
 }
@@ -264,6 +270,7 @@ impl Server {
                     .blocked_ip_networks
                     .iter()
                     .any(|network| network.matches(ip)))
+            || (self.is_ip_listed(ip, IpListAction::Block) && !self.is_ip_allowed(ip))
     }
 
     pub fn is_ip_allowed(&self, ip: &IpAddr) -> bool {
@@ -276,6 +283,7 @@ impl Server {
                     .allowed_ip_networks
                     .iter()
                     .any(|network| network.matches(ip)))
+            || self.is_ip_listed(ip, IpListAction::Allow)
     }
 }
 
@@ -330,6 +338,7 @@ impl Default for Security {
             loiter_fail_rate: Default::default(),
             scanner_fail_rate: Default::default(),
             http_banned_paths: Default::default(),
+            lockout: Default::default(),
         }
     }
 }
@@ -0,0 +1,803 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ahash::AHashMap;
+use rasn::types::{Any, Integer, ObjectIdentifier, OctetString};
+use rasn_ocsp::{
+    CertId, CertStatus, OcspRequest, OcspResponse, OcspResponseStatus, Request, ResponseData,
+    TbsRequest,
+};
+use rustls::sign::CertifiedKey;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use store::write::now;
+use trc::{AddContext, TlsEvent};
+use utils::config::ConfigKey;
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::asn1_rs::{self, BitString, FromDer},
+    extensions::{GeneralName, ParsedExtension},
+    verify::verify_signature,
+    x509::{AlgorithmIdentifier, SubjectPublicKeyInfo},
+};
+
+use crate::{Server, config::server::tls::build_certified_key, ipc::BroadcastEvent};
+
+const OCSP_TIMEOUT: Duration = Duration::from_secs(30);
+const OCSP_RETRY_INTERVAL: u64 = 3600;
+const OCSP_DEFAULT_INTERVAL: u64 = 12 * 3600;
+const OCSP_CLOCK_SKEW: i64 = 300;
+const OID_OCSP: &str = "1.3.6.1.5.5.7.48.1";
+const OID_OCSP_BASIC: &[u32] = &[1, 3, 6, 1, 5, 5, 7, 48, 1, 1];
+const OID_SHA1: &[u32] = &[1, 3, 14, 3, 2, 26];
+
+#[derive(Clone)]
+pub struct ManagedCertificate {
+    pub key: Arc<CertifiedKey>,
+    pub names: Vec<String>,
+    pub subjects: Vec<String>,
+    pub default: bool,
+    pub info: CertificateInfo,
+    pub files: Option<CertificateFiles>,
+    pub ocsp_stapling: bool,
+    pub ocsp_url: Option<String>,
+    pub ocsp_refresh_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: i64,
+    pub not_after: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CertificateFiles {
+    pub cert: PathBuf,
+    pub private_key: PathBuf,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateUpload {
+    pub cert: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    #[serde(default)]
+    pub default: bool,
+    #[serde(default = "default_ocsp_stapling")]
+    pub ocsp_stapling: bool,
+}
+
+impl ManagedCertificate {
+    pub fn new(
+        key: CertifiedKey,
+        subjects: Vec<String>,
+        default: bool,
+        files: Option<CertificateFiles>,
+        ocsp_stapling: bool,
+    ) -> Result<Self, String> {
+        let (_, parsed) = key
+            .end_entity_cert()
+            .map_err(|err| format!("Failed to obtain end entity cert: {err}"))
+            .and_then(|cert| {
+                X509Certificate::from_der(cert.as_ref())
+                    .map_err(|err| format!("Failed to parse end entity cert: {err}"))
+            })?;
+
+        // Add CNs and SANs to the list of names
+        let mut names = Vec::new();
+        let mut ocsp_url = None;
+        for name in parsed.subject().iter_common_name() {
+            if let Ok(name) = name.as_str() {
+                names.push(name.to_string());
+            }
+        }
+        for ext in parsed.extensions() {
+            match ext.parsed_extension() {
+                ParsedExtension::SubjectAlternativeName(san) => {
+                    for name in &san.general_names {
+                        names.push(match name {
+                            GeneralName::DNSName(name) => name.to_string(),
+                            GeneralName::IPAddress(ip) => match ip.len() {
+                                4 => Ipv4Addr::from(<[u8; 4]>::try_from(*ip).unwrap()).to_string(),
+                                16 => {
+                                    Ipv6Addr::from(<[u8; 16]>::try_from(*ip).unwrap()).to_string()
+                                }
+                                _ => continue,
+                            },
+                            _ => continue,
+                        });
+                    }
+                }
+                ParsedExtension::AuthorityInfoAccess(aia) if ocsp_stapling => {
+                    ocsp_url = aia
+                        .accessdescs
+                        .iter()
+                        .filter(|desc| desc.access_method.to_id_string() == OID_OCSP)
+                        .find_map(|desc| match &desc.access_location {
+                            GeneralName::URI(uri) => Some(uri.to_string()),
+                            _ => None,
+                        });
+                }
+                _ => {}
+            }
+        }
+
+        // Add custom SNIs
+        names.extend(subjects.iter().cloned());
+        names.sort_unstable();
+        names.dedup();
+
+        // Responses can only be requested when the issuer is part of the chain
+        let has_issuer = key.cert.len() > 1;
+        let info = CertificateInfo {
+            subject: parsed.subject().to_string(),
+            issuer: parsed.issuer().to_string(),
+            serial: parsed.raw_serial_as_string(),
+            not_before: parsed.validity().not_before.timestamp(),
+            not_after: parsed.validity().not_after.timestamp(),
+        };
+
+        Ok(ManagedCertificate {
+            key: Arc::new(key),
+            names,
+            subjects,
+            default,
+            info,
+            files,
+            ocsp_stapling,
+            ocsp_url: ocsp_url.filter(|_| has_issuer),
+            ocsp_refresh_at: 0,
+        })
+    }
+
+    // Names the certificate is served for, wildcards match their parent domain
+    pub fn sni_names(&self) -> impl Iterator<Item = &str> {
+        self.names
+            .iter()
+            .map(|name| name.strip_prefix("*.").unwrap_or(name))
+            .chain(self.default.then_some("*"))
+    }
+}
+
+impl CertificateFiles {
+    pub fn new(cert: impl Into<PathBuf>, private_key: impl Into<PathBuf>) -> Self {
+        CertificateFiles {
+            cert: cert.into(),
+            private_key: private_key.into(),
+            modified: None,
+        }
+    }
+
+    pub async fn last_modified(&self) -> Option<SystemTime> {
+        let mut last_modified = None;
+        for path in [&self.cert, &self.private_key] {
+            if let Ok(modified) = tokio::fs::metadata(path)
+                .await
+                .and_then(|metadata| metadata.modified())
+            {
+                last_modified = last_modified.max(Some(modified));
+            }
+        }
+        last_modified
+    }
+
+    // Configuration parsing is synchronous, refreshes use `reload` instead
+    pub fn read(&mut self) -> Result<CertifiedKey, String> {
+        self.modified = [&self.cert, &self.private_key]
+            .into_iter()
+            .filter_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .max();
+        let cert = std::fs::read(&self.cert)
+            .map_err(|err| format!("Failed to read {}: {err}", self.cert.display()))?;
+        let private_key = std::fs::read(&self.private_key)
+            .map_err(|err| format!("Failed to read {}: {err}", self.private_key.display()))?;
+        build_certified_key(cert, private_key)
+    }
+
+    pub async fn reload(&mut self) -> Result<CertifiedKey, String> {
+        self.modified = self.last_modified().await;
+        let cert = tokio::fs::read(&self.cert)
+            .await
+            .map_err(|err| format!("Failed to read {}: {err}", self.cert.display()))?;
+        let private_key = tokio::fs::read(&self.private_key)
+            .await
+            .map_err(|err| format!("Failed to read {}: {err}", self.private_key.display()))?;
+        build_certified_key(cert, private_key)
+    }
+}
+
+impl Server {
+    // Reloads certificates whose files changed on disk and refreshes stapled
+    // OCSP responses that are about to expire
+    pub async fn refresh_certificates(&self) {
+        let mut certificates = self
+            .inner
+            .data
+            .tls_managed_certificates
+            .load()
+            .as_ref()
+            .clone();
+        let mut has_changes = false;
+
+        for (id, cert) in certificates.iter_mut() {
+            let files = match &cert.files {
+                Some(files) if files.last_modified().await != files.modified => Some(files.clone()),
+                _ => None,
+            };
+            if let Some(mut files) = files {
+                has_changes = true;
+                match files.reload().await.and_then(|key| {
+                    ManagedCertificate::new(
+                        key,
+                        cert.subjects.clone(),
+                        cert.default,
+                        Some(files.clone()),
+                        cert.ocsp_stapling,
+                    )
+                }) {
+                    Ok(new_cert) => {
+                        *cert = new_cert;
+                        trc::event!(
+                            Tls(TlsEvent::CertificateReloaded),
+                            Id = id.to_string(),
+                            Hostname = cert.names.as_slice(),
+                            ValidTo = trc::Value::Timestamp(cert.info.not_after as u64),
+                        );
+                    }
+                    Err(err) => {
+                        // Keep serving the previous certificate
+                        cert.files = Some(files);
+                        trc::event!(
+                            Tls(TlsEvent::CertificateReloadError),
+                            Id = id.to_string(),
+                            Reason = err,
+                        );
+                    }
+                }
+            }
+
+            let now = now();
+            if let Some(url) = cert
+                .ocsp_url
+                .as_deref()
+                .filter(|_| cert.ocsp_refresh_at <= now)
+            {
+                has_changes = true;
+                match fetch_ocsp_response(url, &cert.key).await {
+                    Ok((response, next_update)) => {
+                        let mut key = cert.key.as_ref().clone();
+                        key.ocsp = Some(response);
+                        cert.key = Arc::new(key);
+                        // Refresh halfway through the validity of the response
+                        cert.ocsp_refresh_at = next_update
+                            .filter(|next_update| *next_update > now)
+                            .map(|next_update| now + ((next_update - now) / 2))
+                            .unwrap_or(now + OCSP_DEFAULT_INTERVAL)
+                            .max(now + OCSP_RETRY_INTERVAL);
+                        trc::event!(
+                            Tls(TlsEvent::OcspResponseUpdated),
+                            Id = id.to_string(),
+                            Url = url.to_string(),
+                            NextRetry = trc::Value::Timestamp(cert.ocsp_refresh_at),
+                        );
+                    }
+                    Err(err) => {
+                        cert.ocsp_refresh_at = now + OCSP_RETRY_INTERVAL;
+                        trc::event!(
+                            Tls(TlsEvent::OcspResponseError),
+                            Id = id.to_string(),
+                            Url = url.to_string(),
+                            Reason = err,
+                        );
+                    }
+                }
+            }
+        }
+
+        if has_changes {
+            self.replace_certificates(certificates);
+        }
+    }
+
+    // Replaces the managed certificates, the names served by ACME are not affected
+    pub fn replace_certificates(&self, mut certificates: AHashMap<String, ManagedCertificate>) {
+        let current = self.inner.data.tls_managed_certificates.load();
+        let mut sni = self.inner.data.tls_certificates.load().as_ref().clone();
+
+        for cert in current.values() {
+            for name in cert.sni_names() {
+                if sni.get(name).is_some_and(|key| Arc::ptr_eq(key, &cert.key)) {
+                    sni.remove(name);
+                }
+            }
+        }
+        for (id, cert) in certificates.iter_mut() {
+            // Keep the stapled OCSP response if the certificate did not change
+            if let Some(previous) = current.get(id).filter(|previous| {
+                cert.key.ocsp.is_none()
+                    && previous.key.ocsp.is_some()
+                    && previous.key.cert == cert.key.cert
+            }) {
+                let mut key = cert.key.as_ref().clone();
+                key.ocsp = previous.key.ocsp.clone();
+                cert.key = Arc::new(key);
+                cert.ocsp_refresh_at = previous.ocsp_refresh_at;
+            }
+
+            for name in cert.sni_names() {
+                sni.insert(name.to_string(), cert.key.clone());
+            }
+        }
+
+        self.inner
+            .data
+            .tls_managed_certificates
+            .store(certificates.into());
+        self.inner.data.tls_certificates.store(sni.into());
+    }
+
+    pub async fn store_certificate(&self, id: &str, upload: CertificateUpload) -> trc::Result<()> {
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid certificate id")
+                .ctx(trc::Key::Id, id.to_string()));
+        }
+
+        // Validate the certificate before writing anything
+        build_certified_key(
+            upload.cert.as_bytes().to_vec(),
+            upload.private_key.as_bytes().to_vec(),
+        )
+        .and_then(|key| {
+            ManagedCertificate::new(
+                key,
+                upload.subjects.clone(),
+                upload.default,
+                None,
+                upload.ocsp_stapling,
+            )
+        })
+        .map_err(|err| {
+            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                .reason(err)
+                .ctx(trc::Key::Id, id.to_string())
+        })?;
+
+        let prefix = format!("certificate.{id}.");
+        let mut keys = vec![
+            ConfigKey::from((format!("{prefix}cert"), upload.cert)),
+            ConfigKey::from((format!("{prefix}private-key"), upload.private_key)),
+            ConfigKey::from((format!("{prefix}default"), upload.default.to_string())),
+            ConfigKey::from((
+                format!("{prefix}ocsp-stapling"),
+                upload.ocsp_stapling.to_string(),
+            )),
+        ];
+        for (idx, subject) in upload.subjects.into_iter().enumerate() {
+            keys.push(ConfigKey::from((
+                format!("{prefix}subjects.{idx}"),
+                subject,
+            )));
+        }
+
+        self.core
+            .storage
+            .config
+            .replace_prefix(&prefix, keys)
+            .await
+            .caused_by(trc::location!())?;
+        self.reload_certificates().await?;
+        self.cluster_broadcast(BroadcastEvent::ReloadCertificates)
+            .await;
+
+        Ok(())
+    }
+
+    pub async fn delete_certificate(&self, id: &str) -> trc::Result<bool> {
+        if !self
+            .inner
+            .data
+            .tls_managed_certificates
+            .load()
+            .contains_key(id)
+        {
+            return Ok(false);
+        }
+
+        self.core
+            .storage
+            .config
+            .clear_prefix(format!("certificate.{id}."))
+            .await
+            .caused_by(trc::location!())?;
+        self.reload_certificates().await?;
+        self.cluster_broadcast(BroadcastEvent::ReloadCertificates)
+            .await;
+
+        Ok(true)
+    }
+}
+
+fn default_ocsp_stapling() -> bool {
+    true
+}
+
+async fn fetch_ocsp_response(
+    url: &str,
+    key: &CertifiedKey,
+) -> Result<(Vec<u8>, Option<u64>), String> {
+    let (cert, issuer) = match (key.cert.first(), key.cert.get(1)) {
+        (Some(cert), Some(issuer)) => (cert, issuer),
+        _ => return Err("Certificate chain does not include the issuer".to_string()),
+    };
+    let (_, cert) = X509Certificate::from_der(cert.as_ref())
+        .map_err(|err| format!("Failed to parse certificate: {err}"))?;
+    let (_, issuer) = X509Certificate::from_der(issuer.as_ref())
+        .map_err(|err| format!("Failed to parse issuer certificate: {err}"))?;
+    let cert_id = ocsp_cert_id(&cert, &issuer);
+    let request = rasn::der::encode(&OcspRequest {
+        tbs_request: TbsRequest {
+            version: Default::default(),
+            requestor_name: None,
+            request_list: vec![Request {
+                req_cert: cert_id.clone(),
+                single_request_extensions: None,
+            }],
+            request_extensions: None,
+        },
+        optional_signature: None,
+    })
+    .map_err(|err| format!("Failed to encode OCSP request: {err}"))?;
+
+    let response = reqwest::Client::builder()
+        .timeout(OCSP_TIMEOUT)
+        .build()
+        .map_err(|err| format!("Failed to create HTTP client: {err}"))?
+        .post(url)
+        .header("Content-Type", "application/ocsp-request")
+        .body(request)
+        .send()
+        .await
+        .map_err(|err| format!("OCSP request failed: {err}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "OCSP responder returned status {}",
+            response.status()
+        ));
+    }
+    let response = response
+        .bytes()
+        .await
+        .map_err(|err| format!("Failed to read OCSP response: {err}"))?
+        .to_vec();
+    let next_update = parse_ocsp_response(&response, &cert_id, &issuer, now() as i64)?;
+
+    Ok((response, next_update))
+}
+
+// CertID as defined in RFC 6960, Section 4.1.1
+fn ocsp_cert_id(cert: &X509Certificate<'_>, issuer: &X509Certificate<'_>) -> CertId {
+    CertId {
+        hash_algorithm: rasn_pkix::AlgorithmIdentifier {
+            algorithm: ObjectIdentifier::new_unchecked(OID_SHA1.into()),
+            parameters: Some(Any::new(vec![0x05, 0x00])),
+        },
+        issuer_name_hash: OctetString::from(Sha1::digest(issuer.subject().as_raw()).to_vec()),
+        issuer_key_hash: OctetString::from(
+            Sha1::digest(&issuer.public_key().subject_public_key.data).to_vec(),
+        ),
+        serial_number: Integer::from_signed_bytes_be(cert.raw_serial()),
+    }
+}
+
+// Validates an OCSPResponse for the requested certificate and returns its nextUpdate time
+fn parse_ocsp_response(
+    response: &[u8],
+    cert_id: &CertId,
+    issuer: &X509Certificate<'_>,
+    now: i64,
+) -> Result<Option<u64>, String> {
+    let invalid = || "Invalid OCSP response".to_string();
+    let response = rasn::der::decode::<OcspResponse>(response).map_err(|_| invalid())?;
+    let bytes = match response.status {
+        OcspResponseStatus::Successful => response
+            .bytes
+            .filter(|bytes| &**bytes.r#type == OID_OCSP_BASIC)
+            .ok_or_else(invalid)?,
+        status => return Err(format!("OCSP responder returned error: {status:?}")),
+    };
+    let basic = BasicResponse::parse(&bytes.response).ok_or_else(invalid)?;
+
+    // Responses have to be signed by the issuer or by a responder it delegated to
+    let is_signed_by = |key: &SubjectPublicKeyInfo<'_>| {
+        verify_signature(key, &basic.algorithm, &basic.signature, basic.tbs).is_ok()
+    };
+    if !is_signed_by(issuer.public_key())
+        && !basic.certs.iter().any(|responder| {
+            is_delegated_responder(responder, issuer, now) && is_signed_by(responder.public_key())
+        })
+    {
+        return Err("OCSP response is not signed by the issuer".to_string());
+    }
+
+    // SingleResponse of the requested certificate
+    let data = rasn::der::decode::<ResponseData>(basic.tbs).map_err(|_| invalid())?;
+    let response = data
+        .responses
+        .into_iter()
+        .find(|response| {
+            response.cert_id.hash_algorithm.algorithm == cert_id.hash_algorithm.algorithm
+                && response.cert_id.issuer_name_hash == cert_id.issuer_name_hash
+                && response.cert_id.issuer_key_hash == cert_id.issuer_key_hash
+                && response.cert_id.serial_number == cert_id.serial_number
+        })
+        .ok_or_else(|| "OCSP response does not include the certificate".to_string())?;
+    match response.cert_status {
+        CertStatus::Good => {}
+        CertStatus::Revoked(_) => return Err("Certificate has been revoked".to_string()),
+        CertStatus::Unknown(_) => return Err("Certificate status is unknown".to_string()),
+    }
+
+    let next_update = response
+        .next_update
+        .map(|next_update| next_update.timestamp());
+    if response.this_update.timestamp() > now + OCSP_CLOCK_SKEW {
+        Err("OCSP response is not yet valid".to_string())
+    } else if next_update.is_some_and(|next_update| next_update < now) {
+        Err("OCSP response has expired".to_string())
+    } else {
+        Ok(next_update.map(|next_update| next_update.max(0) as u64))
+    }
+}
+
+// Delegated responders are issued by the certificate issuer for OCSP signing only
+fn is_delegated_responder(
+    responder: &X509Certificate<'_>,
+    issuer: &X509Certificate<'_>,
+    now: i64,
+) -> bool {
+    responder.issuer() == issuer.subject()
+        && responder.validity().not_before.timestamp() <= now
+        && responder.validity().not_after.timestamp() >= now
+        && responder
+            .extended_key_usage()
+            .ok()
+            .flatten()
+            .is_some_and(|eku| eku.value.ocsp_signing)
+        && responder
+            .verify_signature(Some(issuer.public_key()))
+            .is_ok()
+}
+
+// BasicOCSPResponse, the signature is verified over the exact ResponseData bytes received
+struct BasicResponse<'x> {
+    tbs: &'x [u8],
+    algorithm: AlgorithmIdentifier<'x>,
+    signature: BitString<'x>,
+    certs: Vec<X509Certificate<'x>>,
+}
+
+impl<'x> BasicResponse<'x> {
+    fn parse(bytes: &'x [u8]) -> Option<Self> {
+        let (_, basic) = asn1_rs::Any::from_der(bytes).ok()?;
+        let (rest, _) = asn1_rs::Any::from_der(basic.data).ok()?;
+        let tbs = &basic.data[..basic.data.len() - rest.len()];
+        let (rest, algorithm) = AlgorithmIdentifier::from_der(rest).ok()?;
+        let (rest, signature) = BitString::from_der(rest).ok()?;
+
+        // Optional [0] EXPLICIT SEQUENCE OF Certificate
+        let mut certs = Vec::new();
+        if !rest.is_empty() {
+            let (_, explicit) = asn1_rs::Any::from_der(rest).ok()?;
+            let (_, sequence) = asn1_rs::Any::from_der(explicit.data).ok()?;
+            let mut bytes = sequence.data;
+            while !bytes.is_empty() {
+                let (rest, cert) = X509Certificate::from_der(bytes).ok()?;
+                certs.push(cert);
+                bytes = rest;
+            }
+        }
+
+        Some(BasicResponse {
+            tbs,
+            algorithm,
+            signature,
+            certs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, FixedOffset};
+    use rasn::types::{BitString, ObjectIdentifier, OctetString};
+    use rasn_ocsp::{
+        BasicOcspResponse, CertStatus, OcspResponse, OcspResponseStatus, ResponderId,
+        ResponseBytes, ResponseData, RevokedInfo, SingleResponse,
+    };
+    use rasn_pkix::AlgorithmIdentifier;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+    use ring::{
+        rand::SystemRandom,
+        signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair},
+    };
+    use x509_parser::{certificate::X509Certificate, der_parser::asn1_rs::FromDer};
+
+    use super::{OID_OCSP_BASIC, ocsp_cert_id, parse_ocsp_response};
+
+    const NOW: i64 = 1704067200;
+
+    fn time(timestamp: i64) -> DateTime<FixedOffset> {
+        DateTime::from_timestamp(timestamp, 0)
+            .unwrap()
+            .fixed_offset()
+    }
+
+    fn response(signer: &Certificate, single: SingleResponse) -> Vec<u8> {
+        let data = ResponseData {
+            version: Default::default(),
+            responder_id: ResponderId::ByKey(OctetString::from(vec![0u8; 20])),
+            produced_at: time(NOW),
+            responses: vec![single],
+            response_extensions: None,
+        };
+        let rng = SystemRandom::new();
+        let signature = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            &signer.serialize_private_key_der(),
+            &rng,
+        )
+        .unwrap()
+        .sign(&rng, &rasn::der::encode(&data).unwrap())
+        .unwrap();
+        let basic = BasicOcspResponse {
+            tbs_response_data: data,
+            signature_algorithm: AlgorithmIdentifier {
+                algorithm: ObjectIdentifier::new(vec![1, 2, 840, 10045, 4, 3, 2]).unwrap(),
+                parameters: None,
+            },
+            signature: BitString::from_vec(signature.as_ref().to_vec()),
+            certs: None,
+        };
+
+        rasn::der::encode(&OcspResponse {
+            status: OcspResponseStatus::Successful,
+            bytes: Some(ResponseBytes {
+                r#type: ObjectIdentifier::new(OID_OCSP_BASIC.to_vec()).unwrap(),
+                response: OctetString::from(rasn::der::encode(&basic).unwrap()),
+            }),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn ocsp_response() {
+        let mut ca_params = CertificateParams::new(vec![]);
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "Test CA");
+        let ca = Certificate::from_params(ca_params).unwrap();
+        let ca_der = ca.serialize_der().unwrap();
+        let leaf =
+            Certificate::from_params(CertificateParams::new(vec!["mail.example.org".to_string()]))
+                .unwrap();
+        let leaf_der = leaf.serialize_der_with_signer(&ca).unwrap();
+        let (_, issuer) = X509Certificate::from_der(&ca_der).unwrap();
+        let (_, cert) = X509Certificate::from_der(&leaf_der).unwrap();
+        let cert_id = ocsp_cert_id(&cert, &issuer);
+        let single = |cert_status: CertStatus, next_update: i64| SingleResponse {
+            cert_id: cert_id.clone(),
+            cert_status,
+            this_update: time(NOW),
+            next_update: Some(time(next_update)),
+            single_extensions: None,
+        };
+
+        // Good responses signed by the issuer are accepted
+        assert_eq!(
+            parse_ocsp_response(
+                &response(&ca, single(CertStatus::Good, NOW + 86400)),
+                &cert_id,
+                &issuer,
+                NOW
+            ),
+            Ok(Some((NOW + 86400) as u64))
+        );
+
+        // Revoked and expired responses are rejected
+        assert!(
+            parse_ocsp_response(
+                &response(
+                    &ca,
+                    single(
+                        CertStatus::Revoked(RevokedInfo {
+                            revocation_time: time(NOW),
+                            revocation_reason: None,
+                        }),
+                        NOW + 86400
+                    )
+                ),
+                &cert_id,
+                &issuer,
+                NOW
+            )
+            .unwrap_err()
+            .contains("revoked")
+        );
+        assert!(
+            parse_ocsp_response(
+                &response(&ca, single(CertStatus::Good, NOW - 1)),
+                &cert_id,
+                &issuer,
+                NOW
+            )
+            .unwrap_err()
+            .contains("expired")
+        );
+
+        // Responses for other certificates are rejected
+        let mut other_id = cert_id.clone();
+        other_id.serial_number += 1;
+        assert!(
+            parse_ocsp_response(
+                &response(&ca, single(CertStatus::Good, NOW + 86400)),
+                &other_id,
+                &issuer,
+                NOW
+            )
+            .unwrap_err()
+            .contains("does not include")
+        );
+
+        // Responses signed by an unrelated key are rejected
+        assert!(
+            parse_ocsp_response(
+                &response(&leaf, single(CertStatus::Good, NOW + 86400)),
+                &cert_id,
+                &issuer,
+                NOW
+            )
+            .unwrap_err()
+            .contains("not signed")
+        );
+
+        // Responder errors are reported
+        assert!(
+            parse_ocsp_response(
+                &rasn::der::encode(&OcspResponse {
+                    status: OcspResponseStatus::TryLater,
+                    bytes: None,
+                })
+                .unwrap(),
+                &cert_id,
+                &issuer,
+                NOW
+            )
+            .unwrap_err()
+            .contains("TryLater")
+        );
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    borrow::Cow,
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::SessionStream;
+
+/// Raw DEFLATE stream (RFC 1951) as used by IMAP COMPRESS=DEFLATE (RFC 4978).
+/// Each direction keeps its own zlib context for the lifetime of the session
+/// and buffers at most `buffer_size` bytes of pending compressed data.
+pub struct DeflateStream<T> {
+    inner: T,
+    compress: Compress,
+    decompress: Decompress,
+    buffer_size: usize,
+
+    read_buf: Box<[u8]>,
+    read_pos: usize,
+    read_len: usize,
+    read_pending: bool,
+
+    write_buf: Vec<u8>,
+    write_pos: usize,
+    needs_sync: bool,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> DeflateStream<T> {
+    pub fn new(inner: T, level: u32, buffer_size: usize) -> Self {
+        let buffer_size = buffer_size.max(1024);
+
+        DeflateStream {
+            inner,
+            compress: Compress::new(Compression::new(level.min(9)), false),
+            decompress: Decompress::new(false),
+            buffer_size,
+            read_buf: vec![0u8; buffer_size].into_boxed_slice(),
+            read_pos: 0,
+            read_len: 0,
+            read_pending: false,
+            write_buf: Vec::with_capacity(buffer_size),
+            write_pos: 0,
+            needs_sync: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_pos < self.write_buf.len() {
+            let written = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.write_buf[self.write_pos..])
+            )?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_pos += written;
+        }
+        self.write_buf.clear();
+        self.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn deflate(&mut self, mut input: &[u8], flush: FlushCompress) -> io::Result<()> {
+        loop {
+            if self.write_buf.capacity() - self.write_buf.len() < 64 {
+                self.write_buf.reserve(self.buffer_size);
+            }
+            let total_in = self.compress.total_in();
+            self.compress
+                .compress_vec(input, &mut self.write_buf, flush)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            input = &input[(self.compress.total_in() - total_in) as usize..];
+
+            // Keep going while there is input left or the output buffer was
+            // filled, as a sync flush may not have been fully emitted yet.
+            if input.is_empty() && self.write_buf.len() < self.write_buf.capacity() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for DeflateStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            // Decompress buffered input, or drain output zlib is still holding
+            if this.read_pos < this.read_len || this.read_pending {
+                let output = buf.initialize_unfilled();
+                let output_len = output.len();
+                let total_in = this.decompress.total_in();
+                let total_out = this.decompress.total_out();
+                let status = this
+                    .decompress
+                    .decompress(
+                        &this.read_buf[this.read_pos..this.read_len],
+                        output,
+                        FlushDecompress::Sync,
+                    )
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let consumed = (this.decompress.total_in() - total_in) as usize;
+                let produced = (this.decompress.total_out() - total_out) as usize;
+                this.read_pos += consumed;
+                this.read_pending = produced == output_len;
+                buf.advance(produced);
+
+                if produced > 0 || status == Status::StreamEnd {
+                    return Poll::Ready(Ok(()));
+                } else if consumed > 0 {
+                    continue;
+                }
+            }
+
+            // Need more compressed input
+            if this.read_pos > 0 {
+                this.read_buf.copy_within(this.read_pos..this.read_len, 0);
+                this.read_len -= this.read_pos;
+                this.read_pos = 0;
+            }
+            if this.read_len == this.read_buf.len() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Compressed input exceeds buffer size",
+                )));
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.read_buf[this.read_len..]);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            let bytes_read = read_buf.filled().len();
+            if bytes_read == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            this.read_len += bytes_read;
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for DeflateStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+
+        let buf = &buf[..buf.len().min(this.buffer_size)];
+        this.deflate(buf, FlushCompress::None)?;
+        this.needs_sync = true;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.needs_sync {
+            this.deflate(&[], FlushCompress::Sync)?;
+            this.needs_sync = false;
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<T: SessionStream> SessionStream for DeflateStream<T> {
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
+
+    fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
+        self.inner.tls_version_and_cipher()
+    }
+
+    fn tls_channel_binding(&self) -> Option<Vec<u8>> {
+        self.inner.tls_channel_binding()
+    }
+
+    fn tls_client_certificate(&self) -> Option<Vec<u8>> {
+        self.inner.tls_client_certificate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::DeflateStream;
+
+    #[tokio::test]
+    async fn deflate_roundtrip() {
+        let (client, server) = tokio::io::duplex(4096);
+        let mut client = DeflateStream::new(client, 6, 1024);
+        let mut server = DeflateStream::new(server, 1, 1024);
+
+        for message in [
+            b"a001 NOOP\r\n".to_vec(),
+            b"* 1 FETCH (BODY[] {4096}\r\n".repeat(200),
+            b"a002 LOGOUT\r\n".to_vec(),
+        ] {
+            client.write_all(&message).await.unwrap();
+            client.flush().await.unwrap();
+
+            let mut received = vec![0u8; message.len()];
+            server.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, message);
+
+            server.write_all(&message).await.unwrap();
+            server.flush().await.unwrap();
+
+            let mut received = vec![0u8; message.len()];
+            client.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, message);
+        }
+    }
+}
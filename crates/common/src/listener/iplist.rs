@@ -0,0 +1,377 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+use store::write::now;
+use utils::config::{
+    Config, ConfigKey,
+    ipmask::{IpAddrMask, IpAddrOrMask},
+    utils::ParseValue,
+};
+
+use crate::{Server, ipc::BroadcastEvent};
+
+pub const IP_LIST_KEY: &str = "ip-list";
+pub const IP_LIST_ENTRY: &str = ".entry.";
+
+#[derive(Debug, Clone)]
+pub struct IpList {
+    pub id: String,
+    pub action: IpListAction,
+    pub description: Option<String>,
+    pub feed: Option<IpListFeed>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpListAction {
+    Allow,
+    Block,
+}
+
+#[derive(Debug, Clone)]
+pub struct IpListFeed {
+    pub url: String,
+    pub refresh: Duration,
+    pub timeout: Duration,
+    pub max_entries: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpListEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct IpSet {
+    pub addresses: AHashMap<IpAddr, IpListEntry>,
+    pub networks: Vec<(IpAddrMask, String, IpListEntry)>,
+}
+
+pub fn parse_ip_lists(config: &mut Config) -> AHashMap<String, IpList> {
+    let mut lists = AHashMap::new();
+
+    for id in config
+        .sub_keys(IP_LIST_KEY, ".action")
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+    {
+        let id_ = id.as_str();
+        if !config
+            .property_or_default((IP_LIST_KEY, id_, "enable"), "true")
+            .unwrap_or(true)
+        {
+            continue;
+        }
+        let Some(action) = config.property_require::<IpListAction>((IP_LIST_KEY, id_, "action"))
+        else {
+            continue;
+        };
+        let feed = config
+            .value((IP_LIST_KEY, id_, "feed.url"))
+            .map(|url| url.to_string())
+            .map(|url| IpListFeed {
+                url,
+                refresh: config
+                    .property_or_default((IP_LIST_KEY, id_, "feed.refresh"), "12h")
+                    .unwrap_or(Duration::from_secs(12 * 3600)),
+                timeout: config
+                    .property_or_default((IP_LIST_KEY, id_, "feed.timeout"), "30s")
+                    .unwrap_or(Duration::from_secs(30)),
+                max_entries: config
+                    .property_or_default((IP_LIST_KEY, id_, "feed.max-entries"), "100000")
+                    .unwrap_or(100000),
+            });
+
+        lists.insert(
+            id.clone(),
+            IpList {
+                description: config
+                    .value((IP_LIST_KEY, id_, "description"))
+                    .map(|v| v.to_string()),
+                id,
+                action,
+                feed,
+            },
+        );
+    }
+
+    lists
+}
+
+pub fn parse_ip_list_entries(config: &mut Config) -> AHashMap<String, Arc<IpSet>> {
+    let mut sets: AHashMap<String, IpSet> = AHashMap::new();
+    let mut errors = Vec::new();
+    let now = now();
+
+    for (key, value) in config.iterate_prefix(IP_LIST_KEY) {
+        let Some((id, ip)) = key.split_once(IP_LIST_ENTRY) else {
+            continue;
+        };
+        let entry = if !value.is_empty() {
+            match serde_json::from_str::<IpListEntry>(value) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    errors.push((key.to_string(), err.to_string()));
+                    continue;
+                }
+            }
+        } else {
+            IpListEntry::default()
+        };
+        if entry.is_expired(now) {
+            continue;
+        }
+
+        let set = sets.entry(id.to_string()).or_default();
+        match IpAddrOrMask::parse_value(ip) {
+            Ok(IpAddrOrMask::Ip(ip)) => {
+                set.addresses.insert(ip, entry);
+            }
+            Ok(IpAddrOrMask::Mask(mask)) => {
+                set.networks.push((mask, ip.to_string(), entry));
+            }
+            Err(err) => {
+                errors.push((key.to_string(), err));
+            }
+        }
+    }
+
+    for (key, err) in errors {
+        config.new_parse_error((IP_LIST_KEY, key.as_str()), err);
+    }
+
+    sets.into_iter()
+        .map(|(id, set)| (id, Arc::new(set)))
+        .collect()
+}
+
+impl IpSet {
+    pub fn contains(&self, ip: &IpAddr, now: u64) -> bool {
+        self.addresses
+            .get(ip)
+            .is_some_and(|entry| !entry.is_expired(now))
+            || self
+                .networks
+                .iter()
+                .any(|(network, _, entry)| network.matches(ip) && !entry.is_expired(now))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len() + self.networks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.networks.is_empty()
+    }
+
+    pub fn parse_feed(contents: &str, max_entries: usize) -> Self {
+        let mut set = IpSet::default();
+        let created = now();
+
+        for line in contents.lines() {
+            // Feeds are plain lists, one address or network per line, with optional comments
+            let Some(ip) = line
+                .split(['#', ';'])
+                .next()
+                .and_then(|line| line.split_whitespace().next())
+            else {
+                continue;
+            };
+            let entry = IpListEntry {
+                created,
+                ..Default::default()
+            };
+            match IpAddrOrMask::parse_value(ip) {
+                Ok(IpAddrOrMask::Ip(ip)) => {
+                    set.addresses.insert(ip, entry);
+                }
+                Ok(IpAddrOrMask::Mask(mask)) => {
+                    set.networks.push((mask, ip.to_string(), entry));
+                }
+                Err(_) => continue,
+            }
+            if set.len() >= max_entries {
+                break;
+            }
+        }
+
+        set
+    }
+}
+
+impl IpListEntry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+impl Server {
+    pub fn is_ip_listed(&self, ip: &IpAddr, action: IpListAction) -> bool {
+        let lists = &self.core.network.ip_lists;
+        if lists.is_empty() {
+            return false;
+        }
+
+        let now = now();
+        let entries = self.inner.data.ip_lists.read();
+        let feeds = self.inner.data.ip_feeds.read();
+        lists.values().any(|list| {
+            list.action == action
+                && (entries
+                    .get(&list.id)
+                    .is_some_and(|set| set.contains(ip, now))
+                    || feeds.get(&list.id).is_some_and(|set| set.contains(ip, now)))
+        })
+    }
+
+    pub fn ip_list_contains(&self, list_id: &str, ip: &IpAddr) -> bool {
+        let now = now();
+        self.inner
+            .data
+            .ip_lists
+            .read()
+            .get(list_id)
+            .is_some_and(|set| set.contains(ip, now))
+            || self
+                .inner
+                .data
+                .ip_feeds
+                .read()
+                .get(list_id)
+                .is_some_and(|set| set.contains(ip, now))
+    }
+
+    pub async fn ip_list_update(
+        &self,
+        list_id: &str,
+        entries: Vec<(String, IpListEntry)>,
+        replace: bool,
+    ) -> trc::Result<()> {
+        let prefix = format!("{IP_LIST_KEY}.{list_id}{IP_LIST_ENTRY}");
+
+        // Remove entries not present in the new set
+        if replace {
+            for key in self
+                .core
+                .storage
+                .config
+                .list(&prefix, true)
+                .await?
+                .into_keys()
+            {
+                if !entries.iter().any(|(ip, _)| ip == &key) {
+                    self.core
+                        .storage
+                        .config
+                        .clear(format!("{prefix}{key}"))
+                        .await?;
+                }
+            }
+        }
+
+        self.core
+            .storage
+            .config
+            .set(
+                entries
+                    .into_iter()
+                    .map(|(ip, entry)| ConfigKey {
+                        key: format!("{prefix}{ip}"),
+                        value: serde_json::to_string(&entry).unwrap_or_default(),
+                    })
+                    .collect::<Vec<_>>(),
+                true,
+            )
+            .await?;
+
+        self.reload_ip_lists().await?;
+        self.cluster_broadcast(BroadcastEvent::ReloadBlockedIps)
+            .await;
+
+        Ok(())
+    }
+
+    pub async fn ip_list_remove(&self, list_id: &str, ip: &str) -> trc::Result<()> {
+        self.core
+            .storage
+            .config
+            .clear(format!("{IP_LIST_KEY}.{list_id}{IP_LIST_ENTRY}{ip}"))
+            .await?;
+
+        self.reload_ip_lists().await?;
+        self.cluster_broadcast(BroadcastEvent::ReloadBlockedIps)
+            .await;
+
+        Ok(())
+    }
+
+    pub async fn reload_ip_lists(&self) -> trc::Result<()> {
+        let mut config = self.core.storage.config.build_config(IP_LIST_KEY).await?;
+
+        // Swap all entries at once so lookups never observe a partial list
+        *self.inner.data.ip_lists.write() = parse_ip_list_entries(&mut config);
+
+        Ok(())
+    }
+
+    pub async fn refresh_ip_list_feed(&self, list: &IpList) -> Result<usize, String> {
+        let Some(feed) = &list.feed else {
+            return Ok(0);
+        };
+
+        let response = reqwest::Client::builder()
+            .timeout(feed.timeout)
+            .build()
+            .map_err(|err| format!("Failed to create HTTP client: {err}"))?
+            .get(&feed.url)
+            .send()
+            .await
+            .map_err(|err| format!("Failed to fetch {}: {err}", feed.url))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to fetch {}: HTTP status {}",
+                feed.url,
+                response.status()
+            ));
+        }
+        let contents = response
+            .text()
+            .await
+            .map_err(|err| format!("Failed to read response body from {}: {err}", feed.url))?;
+
+        let set = IpSet::parse_feed(&contents, feed.max_entries);
+        let num_entries = set.len();
+        self.inner
+            .data
+            .ip_feeds
+            .write()
+            .insert(list.id.clone(), Arc::new(set));
+
+        Ok(num_entries)
+    }
+}
+
+impl ParseValue for IpListAction {
+    fn parse_value(value: &str) -> utils::config::Result<Self> {
+        match value {
+            "allow" => Ok(IpListAction::Allow),
+            "block" => Ok(IpListAction::Block),
+            _ => Err(format!("Invalid IP list action {value:?}.")),
+        }
+    }
+}
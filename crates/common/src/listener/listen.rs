@@ -45,6 +45,7 @@ impl Listener {
             acceptor,
             shutdown_rx,
             span_id_gen: self.span_id_gen,
+            auth_policy: self.auth_policy,
         });
         let is_tls = matches!(instance.acceptor, TcpAcceptor::Tls { implicit, .. } if implicit);
         let is_https = is_tls && self.protocol == ServerProtocol::Http;
@@ -358,6 +359,19 @@ impl TcpListener {
 }
 
 impl ServerInstance {
+    /// Returns whether clear-text authentication (PLAIN, LOGIN, USER/PASS) is allowed
+    /// on the stream. TLS sessions must meet the listener's minimum protocol version,
+    /// while unencrypted sessions fall back to the protocol default unless overridden.
+    pub fn is_plain_auth_allowed(&self, stream: &impl SessionStream, default: bool) -> bool {
+        if stream.is_tls() {
+            self.auth_policy.min_tls_version.is_none_or(|min_version| {
+                tls_version_code(&stream.tls_version_and_cipher().0) >= min_version
+            })
+        } else {
+            self.auth_policy.allow_plain_text.unwrap_or(default)
+        }
+    }
+
     pub async fn tls_accept<T: SessionStream>(
         &self,
         stream: T,
@@ -410,3 +424,13 @@ impl ServerInstance {
         }
     }
 }
+
+pub fn tls_version_code(version: &str) -> u16 {
+    match version {
+        "TLSv1.0" | "TLSv1" => 0x0301,
+        "TLSv1.1" => 0x0302,
+        "TLSv1.2" => 0x0303,
+        "TLSv1.3" => 0x0304,
+        _ => 0,
+    }
+}
@@ -0,0 +1,517 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, time::Duration};
+
+use directory::QueryBy;
+use mail_builder::{
+    MessageBuilder,
+    headers::{
+        HeaderType,
+        address::{Address, EmailAddress},
+    },
+};
+use store::{dispatch::lookup::KeyValue, write::now};
+use trc::AddContext;
+use utils::config::{Config, ipmask::IpAddrMask};
+
+use crate::{
+    KV_KNOWN_DEVICE, KV_LOCKOUT, KV_LOCKOUT_LEVEL, KV_LOCKOUT_SCORE, Server, ip_to_bytes,
+    ipc::HousekeeperEvent,
+};
+
+#[derive(Debug, Clone)]
+pub struct AuthLockout {
+    pub account_threshold: i64,
+    pub ip_threshold: i64,
+    pub duration: Duration,
+    pub max_duration: Duration,
+    pub decay: Duration,
+    pub reset: Duration,
+    pub known_device_expiry: Option<Duration>,
+    pub trusted_networks: Vec<IpAddrMask>,
+    pub notify: Option<LockoutNotify>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LockoutNotify {
+    pub from_name: String,
+    pub from_address: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutTarget<'x> {
+    Account(&'x str),
+    Ip(IpAddr),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockoutStatus {
+    pub score: i64,
+    pub threshold: i64,
+    pub level: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<u64>,
+}
+
+impl AuthLockout {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        if !config
+            .property_or_default::<bool>("authentication.lockout.enable", "false")
+            .unwrap_or_default()
+        {
+            return None;
+        }
+
+        let notify = if config
+            .property_or_default::<bool>("authentication.lockout.notify.enable", "true")
+            .unwrap_or(true)
+        {
+            let from_address = config
+                .value("authentication.lockout.notify.from.address")
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| {
+                    format!(
+                        "no-reply@{}",
+                        config
+                            .value("report.domain")
+                            .or_else(|| config.value("server.hostname"))
+                            .unwrap_or("localhost")
+                    )
+                });
+
+            Some(LockoutNotify {
+                from_name: config
+                    .value("authentication.lockout.notify.from.name")
+                    .unwrap_or("Security Alert")
+                    .to_string(),
+                from_address,
+            })
+        } else {
+            None
+        };
+
+        Some(AuthLockout {
+            account_threshold: config
+                .property_or_default::<u64>("authentication.lockout.threshold.account", "10")
+                .unwrap_or(10)
+                .max(1) as i64,
+            ip_threshold: config
+                .property_or_default::<u64>("authentication.lockout.threshold.ip", "30")
+                .unwrap_or(30)
+                .max(1) as i64,
+            duration: config
+                .property_or_default("authentication.lockout.duration", "5m")
+                .unwrap_or_else(|| Duration::from_secs(300)),
+            max_duration: config
+                .property_or_default("authentication.lockout.max-duration", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
+            decay: config
+                .property_or_default("authentication.lockout.decay", "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+            reset: config
+                .property_or_default("authentication.lockout.reset", "7d")
+                .unwrap_or_else(|| Duration::from_secs(7 * 86400)),
+            known_device_expiry: config
+                .property_or_default::<Option<Duration>>(
+                    "authentication.lockout.known-device.expiry",
+                    "30d",
+                )
+                .unwrap_or_default(),
+            trusted_networks: config
+                .properties::<IpAddrMask>("authentication.lockout.trusted-networks")
+                .into_iter()
+                .map(|(_, network)| network)
+                .collect(),
+            notify,
+        })
+    }
+
+    /// Lockout durations double with each consecutive lockout.
+    pub fn lockout_duration(&self, level: i64) -> u64 {
+        let duration = self.duration.as_secs().max(1);
+        duration
+            .checked_shl(level.clamp(1, 32) as u32 - 1)
+            .unwrap_or(u64::MAX)
+            .min(self.max_duration.as_secs().max(duration))
+    }
+}
+
+impl LockoutTarget<'_> {
+    fn key(&self) -> Vec<u8> {
+        match self {
+            LockoutTarget::Account(login) => {
+                let mut key = Vec::with_capacity(login.len() + 1);
+                key.push(b'a');
+                key.extend_from_slice(login.to_lowercase().as_bytes());
+                key
+            }
+            LockoutTarget::Ip(ip) => {
+                let mut key = ip_to_bytes(ip);
+                key.insert(0, b'i');
+                key
+            }
+        }
+    }
+}
+
+impl Server {
+    pub fn has_auth_lockout(&self) -> bool {
+        self.core.network.security.lockout.is_some()
+    }
+
+    /// Returns the number of seconds remaining if either the IP address or the
+    /// account are locked out. Known devices are exempt from account lockouts.
+    pub async fn is_auth_locked_out(
+        &self,
+        ip: IpAddr,
+        login: Option<&str>,
+    ) -> trc::Result<Option<u64>> {
+        let Some(lockout) = &self.core.network.security.lockout else {
+            return Ok(None);
+        };
+        if self.is_lockout_exempt(lockout, &ip) {
+            return Ok(None);
+        }
+
+        if let Some(expires) = self.auth_lockout_expiry(LockoutTarget::Ip(ip)).await? {
+            return Ok(Some(expires));
+        }
+        if let Some(login) = login.filter(|login| !login.is_empty()) {
+            if let Some(expires) = self
+                .auth_lockout_expiry(LockoutTarget::Account(login))
+                .await?
+            {
+                if !self.is_known_device(lockout, ip, login).await? {
+                    return Ok(Some(expires));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub async fn record_auth_failure(&self, ip: IpAddr, login: Option<&str>) -> trc::Result<()> {
+        let Some(lockout) = &self.core.network.security.lockout else {
+            return Ok(());
+        };
+        if self.is_lockout_exempt(lockout, &ip) {
+            return Ok(());
+        }
+        let login = login.filter(|login| !login.is_empty());
+        let is_known_device = if let Some(login) = login {
+            self.is_known_device(lockout, ip, login).await?
+        } else {
+            false
+        };
+
+        // Failures from unknown devices weigh more on the address score
+        let target = LockoutTarget::Ip(ip);
+        if self
+            .incr_auth_score(lockout, &target, if is_known_device { 1 } else { 2 })
+            .await?
+            >= lockout.ip_threshold
+        {
+            self.auth_lockout(lockout, target, ip).await?;
+        }
+
+        // Failures from known devices do not count towards account lockouts,
+        // which prevents attackers from locking out legitimate users
+        if let Some(login) = login.filter(|_| !is_known_device) {
+            let target = LockoutTarget::Account(login);
+            if self.incr_auth_score(lockout, &target, 1).await? >= lockout.account_threshold {
+                self.auth_lockout(lockout, target, ip).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn record_auth_success(&self, ip: IpAddr, login: &str) -> trc::Result<()> {
+        if let Some(expiry) = self
+            .core
+            .network
+            .security
+            .lockout
+            .as_ref()
+            .and_then(|lockout| lockout.known_device_expiry)
+        {
+            self.in_memory_store()
+                .key_set(
+                    KeyValue::with_prefix(KV_KNOWN_DEVICE, device_fingerprint(ip, login), vec![])
+                        .expires(expiry.as_secs()),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn auth_lockout_status(
+        &self,
+        target: LockoutTarget<'_>,
+    ) -> trc::Result<Option<LockoutStatus>> {
+        let Some(lockout) = &self.core.network.security.lockout else {
+            return Ok(None);
+        };
+        let key = target.key();
+        let store = self.in_memory_store();
+
+        Ok(Some(LockoutStatus {
+            score: store
+                .counter_get(KeyValue::<()>::build_key(KV_LOCKOUT_SCORE, &key))
+                .await
+                .caused_by(trc::location!())?,
+            threshold: match target {
+                LockoutTarget::Account(_) => lockout.account_threshold,
+                LockoutTarget::Ip(_) => lockout.ip_threshold,
+            },
+            level: store
+                .counter_get(KeyValue::<()>::build_key(KV_LOCKOUT_LEVEL, &key))
+                .await
+                .caused_by(trc::location!())?,
+            locked_until: self
+                .auth_lockout_expiry(target)
+                .await?
+                .map(|expires| now() + expires),
+        }))
+    }
+
+    pub async fn auth_lockout_clear(&self, target: LockoutTarget<'_>) -> trc::Result<()> {
+        let key = target.key();
+        let store = self.in_memory_store();
+
+        store
+            .key_delete(KeyValue::<()>::build_key(KV_LOCKOUT, &key))
+            .await
+            .caused_by(trc::location!())?;
+        for prefix in [KV_LOCKOUT_SCORE, KV_LOCKOUT_LEVEL] {
+            store
+                .counter_delete(KeyValue::<()>::build_key(prefix, &key))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+
+    async fn auth_lockout_expiry(&self, target: LockoutTarget<'_>) -> trc::Result<Option<u64>> {
+        let now = now();
+        self.in_memory_store()
+            .key_get::<i64>(KeyValue::<()>::build_key(KV_LOCKOUT, target.key()))
+            .await
+            .caused_by(trc::location!())
+            .map(|until| {
+                until
+                    .map(|until| until as u64)
+                    .filter(|until| *until > now)
+                    .map(|until| until - now)
+            })
+    }
+
+    async fn incr_auth_score(
+        &self,
+        lockout: &AuthLockout,
+        target: &LockoutTarget<'_>,
+        weight: i64,
+    ) -> trc::Result<i64> {
+        self.in_memory_store()
+            .counter_incr(
+                KeyValue::with_prefix(KV_LOCKOUT_SCORE, target.key(), weight)
+                    .expires(lockout.decay.as_secs()),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn auth_lockout(
+        &self,
+        lockout: &AuthLockout,
+        target: LockoutTarget<'_>,
+        ip: IpAddr,
+    ) -> trc::Result<()> {
+        let key = target.key();
+        let store = self.in_memory_store();
+        let level = store
+            .counter_incr(
+                KeyValue::with_prefix(KV_LOCKOUT_LEVEL, &key, 1).expires(lockout.reset.as_secs()),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())?;
+        let duration = lockout.lockout_duration(level);
+
+        store
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_LOCKOUT,
+                    &key,
+                    ((now() + duration) as i64).to_be_bytes().to_vec(),
+                )
+                .expires(duration),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        store
+            .counter_delete(KeyValue::<()>::build_key(KV_LOCKOUT_SCORE, &key))
+            .await
+            .caused_by(trc::location!())?;
+
+        let login = match target {
+            LockoutTarget::Account(login) => Some(login),
+            LockoutTarget::Ip(_) => None,
+        };
+
+        trc::event!(
+            Security(trc::SecurityEvent::AuthenticationLockout),
+            RemoteIp = ip,
+            AccountName = login.map(|login| login.to_string()),
+            Duration = Duration::from_secs(duration),
+            Total = level,
+        );
+
+        // Let the account owner know about the suspicious activity
+        if let (Some(notify), Some(login)) = (&lockout.notify, login) {
+            if let Err(err) = self.notify_auth_lockout(notify, login, ip, duration).await {
+                trc::error!(
+                    err.details("Failed to send lockout notification")
+                        .ctx(trc::Key::AccountName, login.to_string())
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn notify_auth_lockout(
+        &self,
+        notify: &LockoutNotify,
+        login: &str,
+        ip: IpAddr,
+        duration: u64,
+    ) -> trc::Result<()> {
+        let Some(rcpt) = self
+            .directory()
+            .query(QueryBy::Name(login), false)
+            .await
+            .caused_by(trc::location!())?
+            .and_then(|principal| principal.emails.into_iter().next())
+        else {
+            return Ok(());
+        };
+
+        let body = MessageBuilder::new()
+            .from(Address::Address(EmailAddress {
+                name: Some(notify.from_name.as_str().into()),
+                email: notify.from_address.as_str().into(),
+            }))
+            .to(Address::Address(EmailAddress {
+                name: None,
+                email: rcpt.as_str().into(),
+            }))
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .subject("Sign-in attempts to your account were blocked")
+            .text_body(format!(
+                concat!(
+                    "There have been several failed sign-in attempts to your account {} ",
+                    "from the address {}.\r\n\r\n",
+                    "Sign-ins from unrecognized devices have been blocked for the next {} minutes. ",
+                    "If these attempts were not made by you, consider changing your password ",
+                    "and enabling two-factor authentication.\r\n"
+                ),
+                login,
+                ip,
+                duration.div_ceil(60)
+            ))
+            .write_to_vec()
+            .unwrap_or_default();
+
+        self.inner
+            .ipc
+            .housekeeper_tx
+            .send(HousekeeperEvent::Notify {
+                from: notify.from_address.clone(),
+                to: vec![rcpt],
+                body,
+            })
+            .await
+            .map_err(|err| {
+                trc::EventType::Server(trc::ServerEvent::ThreadError)
+                    .reason(err)
+                    .details("Failed to send housekeeper event")
+            })
+    }
+
+    async fn is_known_device(
+        &self,
+        lockout: &AuthLockout,
+        ip: IpAddr,
+        login: &str,
+    ) -> trc::Result<bool> {
+        if lockout.known_device_expiry.is_some() {
+            self.in_memory_store()
+                .key_exists(KeyValue::<()>::build_key(
+                    KV_KNOWN_DEVICE,
+                    device_fingerprint(ip, login),
+                ))
+                .await
+                .caused_by(trc::location!())
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn is_lockout_exempt(&self, lockout: &AuthLockout, ip: &IpAddr) -> bool {
+        self.is_ip_allowed(ip)
+            || lockout
+                .trusted_networks
+                .iter()
+                .any(|network| network.matches(ip))
+    }
+}
+
+// Devices are identified by the account and the network they connect from,
+// which survives address changes within the same /24 or /64 network.
+fn device_fingerprint(ip: IpAddr, login: &str) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(login.to_lowercase().as_bytes());
+    match ip {
+        IpAddr::V4(ip) => hasher.update(&ip.octets()[..3]),
+        IpAddr::V6(ip) => hasher.update(&ip.octets()[..8]),
+    };
+    *hasher.finalize().as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_duration() {
+        let lockout = AuthLockout {
+            account_threshold: 10,
+            ip_threshold: 30,
+            duration: Duration::from_secs(300),
+            max_duration: Duration::from_secs(3600),
+            decay: Duration::from_secs(3600),
+            reset: Duration::from_secs(86400),
+            known_device_expiry: None,
+            trusted_networks: vec![],
+            notify: None,
+        };
+
+        assert_eq!(
+            (1..=6)
+                .map(|level| lockout.lockout_duration(level))
+                .collect::<Vec<_>>(),
+            vec![300, 600, 1200, 2400, 3600, 3600]
+        );
+        assert_eq!(lockout.lockout_duration(i64::MAX), 3600);
+    }
+}
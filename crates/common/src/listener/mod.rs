@@ -28,9 +28,14 @@ use self::limiter::{ConcurrencyLimiter, InFlight};
 pub mod acme;
 pub mod asn;
 pub mod blocked;
+pub mod certificate;
+pub mod compress;
+pub mod iplist;
 pub mod limiter;
 pub mod listen;
+pub mod lockout;
 pub mod stream;
+pub mod ticket;
 pub mod tls;
 
 pub struct ServerInstance {
@@ -41,6 +46,13 @@ pub struct ServerInstance {
     pub proxy_networks: Vec<IpAddrMask>,
     pub shutdown_rx: watch::Receiver<bool>,
     pub span_id_gen: Arc<SnowflakeIdGenerator>,
+    pub auth_policy: AuthPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuthPolicy {
+    pub allow_plain_text: Option<bool>,
+    pub min_tls_version: Option<u16>,
 }
 
 #[derive(Default)]
@@ -79,6 +91,8 @@ pub struct SessionData<T: SessionStream> {
 pub trait SessionStream: AsyncRead + AsyncWrite + Unpin + 'static + Sync + Send {
     fn is_tls(&self) -> bool;
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>);
+    fn tls_channel_binding(&self) -> Option<Vec<u8>>;
+    fn tls_client_certificate(&self) -> Option<Vec<u8>>;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -86,6 +100,7 @@ pub enum SessionResult {
     Continue,
     Close,
     UpgradeTls,
+    UpgradeCompress,
 }
 
 pub trait SessionManager: Sync + Send + 'static + Clone {
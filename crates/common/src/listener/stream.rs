@@ -23,6 +23,14 @@ impl SessionStream for TcpStream {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         (Cow::Borrowed(""), Cow::Borrowed(""))
     }
+
+    fn tls_channel_binding(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn tls_client_certificate(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 impl<T: SessionStream> SessionStream for TlsStream<T> {
@@ -62,6 +70,25 @@ impl<T: SessionStream> SessionStream for TlsStream<T> {
             .into(),
         )
     }
+
+    fn tls_channel_binding(&self) -> Option<Vec<u8>> {
+        // RFC 9266 defines tls-exporter channel bindings for TLS 1.3 only
+        let (_, conn) = self.get_ref();
+        if conn.protocol_version() == Some(rustls::ProtocolVersion::TLSv1_3) {
+            conn.export_keying_material(vec![0u8; 32], b"EXPORTER-Channel-Binding", None)
+                .ok()
+        } else {
+            None
+        }
+    }
+
+    fn tls_client_certificate(&self) -> Option<Vec<u8>> {
+        // Client certificates are validated by the listener's verifier during the handshake
+        let (_, conn) = self.get_ref();
+        conn.peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| cert.to_vec())
+    }
 }
 
 impl SessionStream for ProxiedStream<TcpStream> {
@@ -82,6 +109,14 @@ impl SessionStream for ProxiedStream<TcpStream> {
             })
             .unwrap_or((Cow::Borrowed("unknown"), Cow::Borrowed("unknown")))
     }
+
+    fn tls_channel_binding(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn tls_client_certificate(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -140,4 +175,12 @@ impl SessionStream for NullIo {
             std::borrow::Cow::Borrowed(""),
         )
     }
+
+    fn tls_channel_binding(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn tls_client_certificate(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
@@ -0,0 +1,233 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::{Mutex, RwLock};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use rustls::server::ProducesTickets;
+
+const SECRET_LEN: usize = 32;
+const EPOCH_LEN: usize = std::mem::size_of::<u64>();
+const MAX_CACHED_KEYS: usize = 4;
+
+/// Secret used to derive TLS session ticket keys. Every node in the cluster
+/// loads the same secret from the in-memory store and derives one key per
+/// rotation epoch, so tickets issued by one node can be redeemed on any other
+/// and keys rotate without any coordination between nodes.
+pub struct TicketKeys {
+    secret: RwLock<[u8; SECRET_LEN]>,
+    keys: Mutex<Vec<(u64, u64, Arc<LessSafeKey>)>>,
+    rng: SystemRandom,
+}
+
+#[derive(Clone)]
+pub struct ClusterTicketer {
+    pub keys: Arc<TicketKeys>,
+    pub lifetime: Duration,
+    pub rotate: Duration,
+}
+
+impl TicketKeys {
+    pub fn new() -> Self {
+        let rng = SystemRandom::new();
+        let mut secret = [0u8; SECRET_LEN];
+        let _ = rng.fill(&mut secret);
+
+        TicketKeys {
+            secret: RwLock::new(secret),
+            keys: Mutex::new(Vec::with_capacity(MAX_CACHED_KEYS)),
+            rng,
+        }
+    }
+
+    pub fn generate_secret(&self) -> [u8; SECRET_LEN] {
+        let mut secret = [0u8; SECRET_LEN];
+        let _ = self.rng.fill(&mut secret);
+        secret
+    }
+
+    /// Installs a new secret, returning whether it differs from the current
+    /// one or `None` if it has an invalid length.
+    pub fn set_secret(&self, secret: &[u8]) -> Option<bool> {
+        let secret = <[u8; SECRET_LEN]>::try_from(secret).ok()?;
+        let mut keys = self.keys.lock();
+        let mut current = self.secret.write();
+        if *current != secret {
+            *current = secret;
+            keys.clear();
+            Some(true)
+        } else {
+            Some(false)
+        }
+    }
+
+    fn key(&self, epoch: u64, rotate: u64) -> Option<Arc<LessSafeKey>> {
+        let mut keys = self.keys.lock();
+        if let Some((_, _, key)) = keys
+            .iter()
+            .find(|(key_epoch, key_rotate, _)| *key_epoch == epoch && *key_rotate == rotate)
+        {
+            return Some(key.clone());
+        }
+
+        let secret = hmac::Key::new(hmac::HMAC_SHA256, self.secret.read().as_slice());
+        let mut context = Vec::with_capacity(EPOCH_LEN * 2 + 16);
+        context.extend_from_slice(b"tls-ticket-key");
+        context.extend_from_slice(&rotate.to_be_bytes());
+        context.extend_from_slice(&epoch.to_be_bytes());
+        let key = Arc::new(LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, hmac::sign(&secret, &context).as_ref()).ok()?,
+        ));
+
+        if keys.len() == MAX_CACHED_KEYS {
+            keys.remove(0);
+        }
+        keys.push((epoch, rotate, key.clone()));
+
+        Some(key)
+    }
+}
+
+impl Default for TicketKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClusterTicketer {
+    fn rotate_secs(&self) -> u64 {
+        self.rotate.as_secs().max(1)
+    }
+
+    fn current_epoch(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+            / self.rotate_secs()
+    }
+
+    fn is_valid_epoch(&self, epoch: u64) -> bool {
+        // Accept keys from previous epochs while tickets issued with them are
+        // still alive, plus one epoch ahead to tolerate clock skew between nodes
+        let current = self.current_epoch();
+        let previous = self.lifetime.as_secs().div_ceil(self.rotate_secs()) + 1;
+        epoch <= current + 1 && epoch + previous >= current
+    }
+}
+
+impl ProducesTickets for ClusterTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime.as_secs().min(u32::MAX as u64) as u32
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let epoch = self.current_epoch();
+        let key = self.keys.key(epoch, self.rotate_secs())?;
+        let mut nonce = [0u8; NONCE_LEN];
+        self.keys.rng.fill(&mut nonce).ok()?;
+
+        let mut ticket =
+            Vec::with_capacity(EPOCH_LEN + NONCE_LEN + plain.len() + key.algorithm().tag_len());
+        ticket.extend_from_slice(&epoch.to_be_bytes());
+        ticket.extend_from_slice(&nonce);
+        ticket.extend_from_slice(plain);
+        let tag = key
+            .seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(epoch.to_be_bytes()),
+                &mut ticket[EPOCH_LEN + NONCE_LEN..],
+            )
+            .ok()?;
+        ticket.extend_from_slice(tag.as_ref());
+
+        Some(ticket)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        let epoch = u64::from_be_bytes(cipher.get(..EPOCH_LEN)?.try_into().ok()?);
+        if !self.is_valid_epoch(epoch) {
+            return None;
+        }
+        let nonce =
+            Nonce::try_assume_unique_for_key(cipher.get(EPOCH_LEN..EPOCH_LEN + NONCE_LEN)?).ok()?;
+        let key = self.keys.key(epoch, self.rotate_secs())?;
+        let mut plain = cipher.get(EPOCH_LEN + NONCE_LEN..)?.to_vec();
+        let plain_len = key
+            .open_in_place(nonce, Aad::from(epoch.to_be_bytes()), &mut plain)
+            .ok()?
+            .len();
+        plain.truncate(plain_len);
+
+        Some(plain)
+    }
+}
+
+impl Debug for ClusterTicketer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClusterTicketer")
+            .field("lifetime", &self.lifetime)
+            .field("rotate", &self.rotate)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use rustls::server::ProducesTickets;
+
+    use super::{ClusterTicketer, TicketKeys};
+
+    #[test]
+    fn ticket_roundtrip() {
+        let secret = [7u8; 32];
+        let node_a = Arc::new(TicketKeys::new());
+        let node_b = Arc::new(TicketKeys::new());
+        let ticketer_a = ClusterTicketer {
+            keys: node_a.clone(),
+            lifetime: Duration::from_secs(3600),
+            rotate: Duration::from_secs(600),
+        };
+        let ticketer_b = ClusterTicketer {
+            keys: node_b.clone(),
+            ..ticketer_a.clone()
+        };
+
+        // Tickets can only be redeemed by nodes sharing the same secret
+        let ticket = ticketer_a.encrypt(b"session state").unwrap();
+        assert_eq!(ticketer_a.decrypt(&ticket).unwrap(), b"session state");
+        assert!(ticketer_b.decrypt(&ticket).is_none());
+
+        assert_eq!(node_a.set_secret(&secret), Some(true));
+        assert_eq!(node_b.set_secret(&secret), Some(true));
+        assert_eq!(node_b.set_secret(&secret), Some(false));
+        assert_eq!(node_b.set_secret(&secret[..16]), None);
+        let ticket = ticketer_a.encrypt(b"session state").unwrap();
+        assert_eq!(ticketer_b.decrypt(&ticket).unwrap(), b"session state");
+
+        // Tampered tickets and expired epochs are rejected
+        let mut tampered = ticket.clone();
+        *tampered.last_mut().unwrap() ^= 0xff;
+        assert!(ticketer_b.decrypt(&tampered).is_none());
+        let mut expired = ticket;
+        expired[..8].copy_from_slice(&0u64.to_be_bytes());
+        assert!(ticketer_b.decrypt(&expired).is_none());
+    }
+}
@@ -0,0 +1,365 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::BTreeMap;
+
+use directory::{
+    QueryBy,
+    backend::internal::{PrincipalField, lookup::DirectoryStore, manage::ManageDirectory},
+};
+use mail_parser::DateTime;
+use store::{
+    Deserialize, IterateParams, ValueKey, blake3,
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, ReportClass, ValueClass},
+};
+use tokio::net::UdpSocket;
+use trc::AddContext;
+
+use crate::{Server, config::network::AuditFormat};
+
+pub type AuditSnapshot = BTreeMap<String, String>;
+
+#[derive(
+    rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, serde::Serialize, Debug, Clone, PartialEq, Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub account_id: u32,
+    pub account_name: String,
+    pub tenant_id: Option<u32>,
+    pub remote_ip: String,
+    pub method: String,
+    pub path: String,
+    pub object: Option<String>,
+    pub result: String,
+    pub changes: Vec<AuditChange>,
+}
+
+#[derive(
+    rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, serde::Serialize, Debug, Clone, PartialEq, Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditChange {
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+// Objects whose state is captured before and after a change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditTarget {
+    Settings {
+        keys: Vec<String>,
+        prefixes: Vec<String>,
+    },
+    Principal {
+        name: String,
+        id: Option<u32>,
+    },
+    Other,
+}
+
+impl Server {
+    pub async fn audit_snapshot(&self, target: &AuditTarget) -> trc::Result<AuditSnapshot> {
+        let mut snapshot = AuditSnapshot::new();
+
+        match target {
+            AuditTarget::Settings { keys, prefixes } => {
+                for key in keys {
+                    if let Some(value) = self.core.storage.config.get(key).await? {
+                        snapshot.insert(key.clone(), value);
+                    }
+                }
+                for prefix in prefixes {
+                    snapshot.extend(self.core.storage.config.list(prefix, false).await?);
+                }
+            }
+            AuditTarget::Principal { name, id } => {
+                let id = match id {
+                    Some(id) => Some(*id),
+                    None => self
+                        .store()
+                        .get_principal_info(name)
+                        .await
+                        .caused_by(trc::location!())?
+                        .map(|info| info.id),
+                };
+                let Some(principal) = (match id {
+                    Some(id) => self
+                        .store()
+                        .query(QueryBy::Id(id), true)
+                        .await
+                        .caused_by(trc::location!())?,
+                    None => None,
+                }) else {
+                    return Ok(snapshot);
+                };
+
+                for (field, value) in self
+                    .store()
+                    .map_principal(principal, &[])
+                    .await
+                    .caused_by(trc::location!())?
+                    .fields
+                {
+                    let value = serde_json::to_string(&value).unwrap_or_default();
+                    snapshot.insert(
+                        field.as_str().to_string(),
+                        if field == PrincipalField::Secrets {
+                            // Only record that the secrets changed, never their contents
+                            format!(
+                                "[redacted {}]",
+                                &blake3::hash(value.as_bytes()).to_hex()[..16]
+                            )
+                        } else {
+                            value
+                        },
+                    );
+                }
+            }
+            AuditTarget::Other => {}
+        }
+
+        Ok(snapshot)
+    }
+
+    pub async fn record_audit(&self, mut entry: AuditEntry) {
+        let Some(audit) = &self.core.network.audit else {
+            return;
+        };
+        entry.id = self.generate_snowflake_id();
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Report(ReportClass::Audit {
+                id: entry.id,
+                expires: audit
+                    .retention
+                    .map_or(u64::MAX, |retention| entry.timestamp + retention.as_secs()),
+            }),
+            Archiver::new(entry.clone()).serialize().unwrap_or_default(),
+        );
+        if let Err(err) = self.store().write(batch.build_all()).await {
+            trc::error!(
+                err.caused_by(trc::location!())
+                    .details("Failed to write audit log entry")
+            );
+        }
+
+        if let Some(export) = &audit.export {
+            let hostname = &self.core.network.server_name;
+            let message = match export.format {
+                AuditFormat::Syslog => entry.to_syslog(hostname),
+                AuditFormat::Cef => entry.to_cef(hostname),
+            };
+            if let Err(err) = send_syslog(&export.address, message.as_bytes()).await {
+                trc::error!(
+                    trc::NetworkEvent::WriteError
+                        .into_err()
+                        .reason(err)
+                        .details("Failed to export audit log entry")
+                        .ctx(trc::Key::Url, export.address.clone())
+                );
+            }
+        }
+    }
+
+    pub async fn audit_log(&self) -> trc::Result<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+        self.store()
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Report(ReportClass::Audit { id: 0, expires: 0 })),
+                    ValueKey::from(ValueClass::Report(ReportClass::Audit {
+                        id: u64::MAX,
+                        expires: u64::MAX,
+                    })),
+                ),
+                |_, value| {
+                    entries.push(
+                        <Archive<AlignedBytes> as Deserialize>::deserialize(value)?
+                            .deserialize::<AuditEntry>()
+                            .caused_by(trc::location!())?,
+                    );
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())
+            .map(|_| entries)
+    }
+}
+
+pub fn audit_diff(before: &AuditSnapshot, after: &AuditSnapshot) -> Vec<AuditChange> {
+    let mut changes = Vec::new();
+    for (key, value) in before {
+        match after.get(key) {
+            Some(new_value) if new_value == value => {}
+            new_value => changes.push(AuditChange {
+                key: key.clone(),
+                before: Some(value.clone()),
+                after: new_value.cloned(),
+            }),
+        }
+    }
+    for (key, value) in after {
+        if !before.contains_key(key) {
+            changes.push(AuditChange {
+                key: key.clone(),
+                before: None,
+                after: Some(value.clone()),
+            });
+        }
+    }
+    changes.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+impl AuditEntry {
+    pub fn to_syslog(&self, hostname: &str) -> String {
+        // RFC 5424 message using the log audit facility and notice severity
+        format!(
+            "<109>1 {} {hostname} stalwart - audit [audit@32473 user=\"{}\" src=\"{}\" method=\"{}\" path=\"{}\" object=\"{}\" result=\"{}\"] {}",
+            DateTime::from_timestamp(self.timestamp as i64).to_rfc3339(),
+            escape_sd(&self.account_name),
+            escape_sd(&self.remote_ip),
+            escape_sd(&self.method),
+            escape_sd(&self.path),
+            escape_sd(self.object.as_deref().unwrap_or_default()),
+            escape_sd(&self.result),
+            self.summary()
+        )
+    }
+
+    pub fn to_cef(&self, hostname: &str) -> String {
+        format!(
+            "<109>1 {} {hostname} stalwart - audit - CEF:0|Stalwart Labs|Stalwart Server|{}|{}|Management API {}|{}|rt={} suser={} src={} requestMethod={} request={} outcome={} cs1Label=object cs1={} msg={}",
+            DateTime::from_timestamp(self.timestamp as i64).to_rfc3339(),
+            env!("CARGO_PKG_VERSION"),
+            escape_cef_header(&self.method),
+            escape_cef_header(&self.method),
+            if self.result == "success" { 3 } else { 6 },
+            self.timestamp * 1000,
+            escape_cef_value(&self.account_name),
+            escape_cef_value(&self.remote_ip),
+            escape_cef_value(&self.method),
+            escape_cef_value(&self.path),
+            escape_cef_value(&self.result),
+            escape_cef_value(self.object.as_deref().unwrap_or_default()),
+            escape_cef_value(&self.summary())
+        )
+    }
+
+    fn summary(&self) -> String {
+        if self.changes.is_empty() {
+            format!("{} {}", self.method, self.path)
+        } else {
+            format!(
+                "{} {} changed {}",
+                self.method,
+                self.path,
+                self.changes
+                    .iter()
+                    .map(|change| change.key.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
+    }
+}
+
+async fn send_syslog(address: &str, message: &[u8]) -> std::io::Result<()> {
+    let address = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| std::io::Error::other("Address could not be resolved"))?;
+    let socket = UdpSocket::bind(if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await?;
+    socket.send_to(message, address).await.map(|_| ())
+}
+
+fn escape_sd(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '"' | '\\' | ']') {
+            result.push('\\');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+fn escape_cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn escape_cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audit_changes() {
+        let before = AuditSnapshot::from_iter([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "2".to_string()),
+            ("c".to_string(), "3".to_string()),
+        ]);
+        let after = AuditSnapshot::from_iter([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "20".to_string()),
+            ("d".to_string(), "4".to_string()),
+        ]);
+        let changes = audit_diff(&before, &after);
+        assert_eq!(
+            changes
+                .iter()
+                .map(|c| (c.key.as_str(), c.before.as_deref(), c.after.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("b", Some("2"), Some("20")),
+                ("c", Some("3"), None),
+                ("d", None, Some("4")),
+            ]
+        );
+
+        let entry = AuditEntry {
+            id: 1,
+            timestamp: 0,
+            account_id: 0,
+            account_name: "admin".to_string(),
+            tenant_id: None,
+            remote_ip: "10.0.0.1".to_string(),
+            method: "POST".to_string(),
+            path: "/api/settings".to_string(),
+            object: Some("a=b".to_string()),
+            result: "success".to_string(),
+            changes,
+        };
+        assert!(
+            entry
+                .to_cef("mx.example.org")
+                .ends_with("cs1=a\\=b msg=POST /api/settings changed b, c, d")
+        );
+        assert!(entry.to_syslog("mx.example.org").contains(
+            "[audit@32473 user=\"admin\" src=\"10.0.0.1\" method=\"POST\" path=\"/api/settings\" object=\"a=b\" result=\"success\"]"
+        ));
+    }
+}
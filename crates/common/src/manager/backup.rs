@@ -13,10 +13,12 @@ use std::{
 };
 
 use ahash::{AHashMap, AHashSet};
+use argon2::Argon2;
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
     BitmapKey, Deserialize, IndexKey, IterateParams, LogKey, SUBSPACE_BITMAP_ID,
     SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SerializeInfallible, U32_LEN, U64_LEN, ValueKey,
+    rand,
     write::{
         AnyKey, BitmapClass, BitmapHash, BlobOp, DirectoryClass, InMemoryClass, QueueClass,
         QueueEvent, TagValue, ValueClass, key::DeserializeBigEndian,
@@ -24,15 +26,26 @@ use store::{
 };
 
 use utils::{
-    BLOB_HASH_LEN, BlobHash, UnwrapFailure,
+    BLOB_HASH_LEN, BlobHash,
     codec::leb128::{Leb128_, Leb128Reader},
-    failed,
 };
 
-use crate::Core;
+use crate::{Core, auth::oauth::crypto::SymmetricEncrypt};
 
 pub(super) const MAGIC_MARKER: u8 = 123;
+pub(super) const ENCRYPTED_MARKER: u8 = 124;
 pub(super) const FILE_VERSION: u8 = 2;
+pub(super) const BLOB_MANIFEST: &str = "blob.manifest";
+pub(super) const SALT_LEN: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Mailbox properties stored as counters rather than values
+pub(crate) const MAILBOX_COUNTERS: [Property; 4] = [
+    Property::EmailIds,
+    Property::TotalEmails,
+    Property::UnreadEmails,
+    Property::DeletedEmails,
+];
 
 #[derive(Debug)]
 pub(super) enum Op {
@@ -60,80 +73,93 @@ pub(super) enum Family {
     None = 255,
 }
 
-type TaskHandle = (tokio::task::JoinHandle<()>, std::thread::JoinHandle<()>);
+type TaskHandle = (
+    tokio::task::JoinHandle<trc::Result<()>>,
+    tokio::task::JoinHandle<trc::Result<()>>,
+);
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct BackupParams {
     dest: PathBuf,
     families: AHashSet<Family>,
+    encryption_key: Option<String>,
+    incremental: Option<PathBuf>,
 }
 
 impl Core {
-    pub async fn backup(&self, params: BackupParams) {
+    pub async fn backup(&self, params: BackupParams) -> trc::Result<()> {
         if !params.dest.exists() {
-            std::fs::create_dir_all(&params.dest).failed("Failed to create backup directory");
+            tokio::fs::create_dir_all(&params.dest)
+                .await
+                .failed("Failed to create backup directory")?;
         } else if !params.dest.is_dir() {
-            eprintln!("Backup destination {:?} is not a directory.", params.dest);
-            std::process::exit(1);
+            return Err(failed(&format!(
+                "Backup destination {:?} is not a directory.",
+                params.dest
+            )));
         }
 
         let mut sync_handles = Vec::new();
+        let mut result = Ok(());
 
         for (async_handle, sync_handle) in [
             params
                 .has_family(Family::Property)
-                .then(|| self.backup_properties(&params.dest)),
+                .then(|| self.backup_properties(&params)),
             params
                 .has_family(Family::FtsIndex)
-                .then(|| self.backup_fts_index(&params.dest)),
+                .then(|| self.backup_fts_index(&params)),
             params
                 .has_family(Family::Acl)
-                .then(|| self.backup_acl(&params.dest)),
+                .then(|| self.backup_acl(&params)),
             params
                 .has_family(Family::Blob)
-                .then(|| self.backup_blob(&params.dest)),
+                .then(|| self.backup_blob(&params)),
             params
                 .has_family(Family::Config)
-                .then(|| self.backup_config(&params.dest)),
+                .then(|| self.backup_config(&params)),
             params
                 .has_family(Family::LookupValue)
-                .then(|| self.backup_lookup(&params.dest)),
+                .then(|| self.backup_lookup(&params)),
             params
                 .has_family(Family::Directory)
-                .then(|| self.backup_directory(&params.dest)),
+                .then(|| self.backup_directory(&params)),
             params
                 .has_family(Family::Queue)
-                .then(|| self.backup_queue(&params.dest)),
+                .then(|| self.backup_queue(&params)),
             params
                 .has_family(Family::Index)
-                .then(|| self.backup_index(&params.dest)),
+                .then(|| self.backup_index(&params)),
             params
                 .has_family(Family::Bitmap)
-                .then(|| self.backup_bitmaps(&params.dest)),
+                .then(|| self.backup_bitmaps(&params)),
             params
                 .has_family(Family::Log)
-                .then(|| self.backup_logs(&params.dest)),
+                .then(|| self.backup_logs(&params)),
         ]
         .into_iter()
         .flatten()
         {
-            async_handle.await.failed("Task failed");
+            // Wait for all tasks to finish before reporting the first error
+            result = result.and(async_handle.await.failed("Task failed").and_then(|r| r));
             sync_handles.push(sync_handle);
         }
 
         for handle in sync_handles {
-            handle.join().expect("Failed to join thread");
+            result = result.and(handle.await.failed("Writer failed").and_then(|r| r));
         }
+
+        result
     }
 
-    fn backup_properties(&self, dest: &Path) -> TaskHandle {
+    fn backup_properties(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("property"));
+        let (handle, writer) = spawn_writer(params, "property");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Property))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut keys = BTreeSet::new();
 
@@ -166,7 +192,7 @@ impl Core {
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
 
                 let mut last_account_id = u32::MAX;
                 let mut last_collection = u8::MAX;
@@ -176,44 +202,46 @@ impl Core {
                     if account_id != last_account_id {
                         writer
                             .send(Op::AccountId(account_id))
-                            .failed("Failed to send account id");
+                            .failed("Failed to send account id")?;
                         last_account_id = account_id;
                     }
 
                     if collection != last_collection {
                         writer
                             .send(Op::Collection(collection))
-                            .failed("Failed to send collection");
+                            .failed("Failed to send collection")?;
                         last_collection = collection;
                     }
 
                     if document_id != last_document_id {
                         writer
                             .send(Op::DocumentId(document_id))
-                            .failed("Failed to send document id");
+                            .failed("Failed to send document id")?;
                         last_document_id = document_id;
                     }
 
-                    // Obtain UID counter
+                    // Obtain UID and message counters
                     if collection == u8::from(Collection::Mailbox)
                         && u8::from(Property::Value) == field
                     {
-                        let value = store
-                            .get_counter(ValueKey {
-                                account_id,
-                                collection,
-                                document_id,
-                                class: ValueClass::Property(Property::EmailIds.into()),
-                            })
-                            .await
-                            .failed("Failed to get counter");
-                        if value != 0 {
-                            writer
-                                .send(Op::KeyValue((
-                                    vec![u8::from(Property::EmailIds)],
-                                    value.serialize(),
-                                )))
-                                .failed("Failed to send key value");
+                        for property in MAILBOX_COUNTERS {
+                            let value = store
+                                .get_counter(ValueKey {
+                                    account_id,
+                                    collection,
+                                    document_id,
+                                    class: ValueClass::Property(property.into()),
+                                })
+                                .await
+                                .failed("Failed to get counter")?;
+                            if value != 0 {
+                                writer
+                                    .send(Op::KeyValue((
+                                        vec![u8::from(property)],
+                                        value.serialize(),
+                                    )))
+                                    .failed("Failed to send key value")?;
+                            }
                         }
                     }
 
@@ -226,26 +254,28 @@ impl Core {
                             class: ValueClass::Property(field),
                         })
                         .await
-                        .failed("Failed to get value")
-                        .failed("Expected value")
+                        .failed("Failed to get value")?
+                        .failed("Expected value")?
                         .0;
                     writer
                         .send(Op::KeyValue((vec![field], value)))
-                        .failed("Failed to send key value");
+                        .failed("Failed to send key value")?;
                 }
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_fts_index(&self, dest: &Path) -> TaskHandle {
+    fn backup_fts_index(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("fts_index"));
+        let (handle, writer) = spawn_writer(params, "fts_index");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::FtsIndex))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut last_account_id = u32::MAX;
                 let mut last_collection = u8::MAX;
@@ -280,46 +310,48 @@ impl Core {
                             if account_id != last_account_id {
                                 writer
                                     .send(Op::AccountId(account_id))
-                                    .failed("Failed to send account id");
+                                    .failed("Failed to send account id")?;
                                 last_account_id = account_id;
                             }
 
                             if collection != last_collection {
                                 writer
                                     .send(Op::Collection(collection))
-                                    .failed("Failed to send collection");
+                                    .failed("Failed to send collection")?;
                                 last_collection = collection;
                             }
 
                             writer
                                 .send(Op::DocumentId(document_id))
-                                .failed("Failed to send document id");
+                                .failed("Failed to send document id")?;
 
                             writer
                                 .send(Op::KeyValue((
                                     key.range(U32_LEN..key.len() - U32_LEN - 1)?.to_vec(),
                                     value.to_vec(),
                                 )))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_acl(&self, dest: &Path) -> TaskHandle {
+    fn backup_acl(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("acl"));
+        let (handle, writer) = spawn_writer(params, "acl");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Acl))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut last_account_id = u32::MAX;
                 let mut last_collection = u8::MAX;
@@ -350,21 +382,21 @@ impl Core {
                             if account_id != last_account_id {
                                 writer
                                     .send(Op::AccountId(account_id))
-                                    .failed("Failed to send account id");
+                                    .failed("Failed to send account id")?;
                                 last_account_id = account_id;
                             }
 
                             if collection != last_collection {
                                 writer
                                     .send(Op::Collection(collection))
-                                    .failed("Failed to send collection");
+                                    .failed("Failed to send collection")?;
                                 last_collection = collection;
                             }
 
                             if document_id != last_document_id {
                                 writer
                                     .send(Op::DocumentId(document_id))
-                                    .failed("Failed to send document id");
+                                    .failed("Failed to send document id")?;
                                 last_document_id = document_id;
                             }
 
@@ -373,27 +405,39 @@ impl Core {
                                     grant_account_id.to_be_bytes().to_vec(),
                                     value.to_vec(),
                                 )))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_blob(&self, dest: &Path) -> TaskHandle {
+    fn backup_blob(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
         let blob_store = self.storage.blob.clone();
-        let (handle, writer) = spawn_writer(dest.join("blob"));
+        let (handle, writer) = spawn_writer(params, "blob");
+        let manifest_path = params.dest.join(BLOB_MANIFEST);
+        let base_manifest = params
+            .incremental
+            .as_ref()
+            .map(|base| base.join(BLOB_MANIFEST));
         (
             tokio::spawn(async move {
+                let mut manifest = if let Some(path) = base_manifest {
+                    read_blob_manifest(&path).await?
+                } else {
+                    AHashSet::new()
+                };
                 writer
                     .send(Op::Family(Family::Blob))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut hashes = Vec::new();
 
@@ -428,16 +472,16 @@ impl Core {
                             if account_id != u32::MAX && document_id != u32::MAX {
                                 writer
                                     .send(Op::AccountId(account_id))
-                                    .failed("Failed to send account id");
+                                    .failed("Failed to send account id")?;
                                 writer
                                     .send(Op::Collection(collection))
-                                    .failed("Failed to send collection");
+                                    .failed("Failed to send collection")?;
                                 writer
                                     .send(Op::DocumentId(document_id))
-                                    .failed("Failed to send document id");
+                                    .failed("Failed to send document id")?;
                                 writer
                                     .send(Op::KeyValue((hash, vec![])))
-                                    .failed("Failed to send key value");
+                                    .failed("Failed to send key value")?;
                             } else {
                                 hashes.push(hash);
                             }
@@ -446,24 +490,30 @@ impl Core {
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
 
                 if !hashes.is_empty() {
                     writer
                         .send(Op::AccountId(u32::MAX))
-                        .failed("Failed to send account id");
+                        .failed("Failed to send account id")?;
                     writer
                         .send(Op::DocumentId(u32::MAX))
-                        .failed("Failed to send document id");
+                        .failed("Failed to send document id")?;
                     for hash in hashes {
+                        // Incremental backups only include blobs missing from the base backup
+                        if manifest.contains(&hash) {
+                            continue;
+                        }
+
                         if let Some(value) = blob_store
                             .get_blob(&hash, 0..usize::MAX)
                             .await
-                            .failed("Failed to get blob")
+                            .failed("Failed to get blob")?
                         {
                             writer
-                                .send(Op::KeyValue((hash, value)))
-                                .failed("Failed to send key value");
+                                .send(Op::KeyValue((hash.clone(), value)))
+                                .failed("Failed to send key value")?;
+                            manifest.insert(hash);
                         } else {
                             eprintln!(
                                 "Warning: blob hash {hash:?} does not exist in blob store. Skipping."
@@ -471,19 +521,28 @@ impl Core {
                         }
                     }
                 }
+
+                tokio::fs::write(
+                    manifest_path,
+                    manifest.into_iter().flatten().collect::<Vec<_>>(),
+                )
+                .await
+                .failed("Failed to write blob manifest")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_config(&self, dest: &Path) -> TaskHandle {
+    fn backup_config(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("config"));
+        let (handle, writer) = spawn_writer(params, "config");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Config))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 store
                     .iterate(
@@ -511,26 +570,28 @@ impl Core {
                         |key, value| {
                             writer
                                 .send(Op::KeyValue((key.to_vec(), value.to_vec())))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_lookup(&self, dest: &Path) -> TaskHandle {
+    fn backup_lookup(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("lookup"));
+        let (handle, writer) = spawn_writer(params, "lookup");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::LookupValue))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 store
                     .iterate(
@@ -558,17 +619,17 @@ impl Core {
                         |key, value| {
                             writer
                                 .send(Op::KeyValue((key.to_vec(), value.to_vec())))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
 
                 writer
                     .send(Op::Family(Family::LookupCounter))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut counters = Vec::new();
 
@@ -608,7 +669,7 @@ impl Core {
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
 
                 for key in counters {
                     let value = store
@@ -616,27 +677,29 @@ impl Core {
                             InMemoryClass::Counter(key.clone()),
                         )))
                         .await
-                        .failed("Failed to get counter");
+                        .failed("Failed to get counter")?;
 
                     if value != 0 {
                         writer
                             .send(Op::KeyValue((key, value.serialize())))
-                            .failed("Failed to send key value");
+                            .failed("Failed to send key value")?;
                     }
                 }
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_directory(&self, dest: &Path) -> TaskHandle {
+    fn backup_directory(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("directory"));
+        let (handle, writer) = spawn_writer(params, "directory");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Directory))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut principal_ids = Vec::new();
 
@@ -666,13 +729,13 @@ impl Core {
 
                             writer
                                 .send(Op::KeyValue((key.to_vec(), value.to_vec())))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
 
                 for principal_bytes in principal_ids {
                     let value = store
@@ -681,11 +744,11 @@ impl Core {
                                 principal_bytes
                                     .as_slice()
                                     .deserialize_leb128()
-                                    .failed("Failed to deserialize principal id"),
+                                    .failed("Failed to deserialize principal id")?,
                             ),
                         )))
                         .await
-                        .failed("Failed to get counter");
+                        .failed("Failed to get counter")?;
                     if value != 0 {
                         let mut key = Vec::with_capacity(U32_LEN + 1);
                         key.push(4u8);
@@ -693,22 +756,24 @@ impl Core {
 
                         writer
                             .send(Op::KeyValue((key, value.serialize())))
-                            .failed("Failed to send key value");
+                            .failed("Failed to send key value")?;
                     }
                 }
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_queue(&self, dest: &Path) -> TaskHandle {
+    fn backup_queue(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("queue"));
+        let (handle, writer) = spawn_writer(params, "queue");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Queue))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 store
                     .iterate(
@@ -733,13 +798,13 @@ impl Core {
 
                             writer
                                 .send(Op::KeyValue((key, value.to_vec())))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
 
                 store
                     .iterate(
@@ -770,26 +835,28 @@ impl Core {
 
                             writer
                                 .send(Op::KeyValue((key, value.to_vec())))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_index(&self, dest: &Path) -> TaskHandle {
+    fn backup_index(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("index"));
+        let (handle, writer) = spawn_writer(params, "index");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Index))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut last_account_id = u32::MAX;
                 let mut last_collection = u8::MAX;
@@ -823,46 +890,48 @@ impl Core {
                             if account_id != last_account_id {
                                 writer
                                     .send(Op::AccountId(account_id))
-                                    .failed("Failed to send account id");
+                                    .failed("Failed to send account id")?;
                                 last_account_id = account_id;
                             }
 
                             if collection != last_collection {
                                 writer
                                     .send(Op::Collection(collection))
-                                    .failed("Failed to send collection");
+                                    .failed("Failed to send collection")?;
                                 last_collection = collection;
                             }
 
                             writer
                                 .send(Op::DocumentId(document_id))
-                                .failed("Failed to send document id");
+                                .failed("Failed to send document id")?;
 
                             writer
                                 .send(Op::KeyValue((key, vec![])))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_bitmaps(&self, dest: &Path) -> TaskHandle {
+    fn backup_bitmaps(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
 
-        let (handle, writer) = spawn_writer(dest.join("bitmap"));
+        let (handle, writer) = spawn_writer(params, "bitmap");
         (
             tokio::spawn(async move {
                 const BM_MARKER: u8 = 1 << 7;
 
                 writer
                     .send(Op::Family(Family::Bitmap))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut bitmaps: AHashMap<(u32, u8), AHashSet<BitmapClass>> = AHashMap::new();
 
@@ -956,16 +1025,16 @@ impl Core {
                             },
                         )
                         .await
-                        .failed("Failed to iterate over data store");
+                        .failed("Failed to iterate over data store")?;
                 }
 
                 for ((account_id, collection), classes) in bitmaps {
                     writer
                         .send(Op::AccountId(account_id))
-                        .failed("Failed to send account id");
+                        .failed("Failed to send account id")?;
                     writer
                         .send(Op::Collection(collection))
-                        .failed("Failed to send collection");
+                        .failed("Failed to send collection")?;
 
                     for class in classes {
                         if let Some(bitmap) = store
@@ -976,7 +1045,7 @@ impl Core {
                                 document_id: 0,
                             })
                             .await
-                            .failed("Failed to get bitmap")
+                            .failed("Failed to get bitmap")?
                         {
                             let key = match class {
                                 BitmapClass::DocumentIds => {
@@ -1011,27 +1080,29 @@ impl Core {
                             let mut bytes = Vec::with_capacity(bitmap.serialized_size());
                             bitmap
                                 .serialize_into(&mut bytes)
-                                .failed("Failed to serialize bitmap");
+                                .failed("Failed to serialize bitmap")?;
 
                             writer
                                 .send(Op::KeyValue((key, bytes)))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
                         }
                     }
                 }
+
+                Ok(())
             }),
             handle,
         )
     }
 
-    fn backup_logs(&self, dest: &Path) -> TaskHandle {
+    fn backup_logs(&self, params: &BackupParams) -> TaskHandle {
         let store = self.storage.data.clone();
-        let (handle, writer) = spawn_writer(dest.join("log"));
+        let (handle, writer) = spawn_writer(params, "log");
         (
             tokio::spawn(async move {
                 writer
                     .send(Op::Family(Family::Log))
-                    .failed("Failed to send family");
+                    .failed("Failed to send family")?;
 
                 let mut last_account_id = u32::MAX;
                 let mut last_collection = u8::MAX;
@@ -1056,90 +1127,243 @@ impl Core {
                             let key = key.range(U32_LEN + 1..usize::MAX)?.to_vec();
 
                             if key.len() != U64_LEN {
-                                failed(&format!("Found invalid log entry {key:?} {value:?}"));
+                                return Err(failed(&format!(
+                                    "Found invalid log entry {key:?} {value:?}"
+                                )));
                             }
 
                             if account_id != last_account_id {
                                 writer
                                     .send(Op::AccountId(account_id))
-                                    .failed("Failed to send account id");
+                                    .failed("Failed to send account id")?;
                                 last_account_id = account_id;
                             }
 
                             if collection != last_collection {
                                 writer
                                     .send(Op::Collection(collection))
-                                    .failed("Failed to send collection");
+                                    .failed("Failed to send collection")?;
                                 last_collection = collection;
                             }
 
                             writer
                                 .send(Op::KeyValue((key, value.to_vec())))
-                                .failed("Failed to send key value");
+                                .failed("Failed to send key value")?;
 
                             Ok(true)
                         },
                     )
                     .await
-                    .failed("Failed to iterate over data store");
+                    .failed("Failed to iterate over data store")?;
+
+                Ok(())
             }),
             handle,
         )
     }
 }
 
-fn spawn_writer(path: PathBuf) -> (std::thread::JoinHandle<()>, SyncSender<Op>) {
+fn spawn_writer(
+    params: &BackupParams,
+    name: &str,
+) -> (tokio::task::JoinHandle<trc::Result<()>>, SyncSender<Op>) {
     let (tx, rx) = mpsc::sync_channel(10);
+    let path = params.dest.join(name);
+    let encryption_key = params.encryption_key.clone();
 
-    let handle = std::thread::spawn(move || {
+    let handle = tokio::task::spawn_blocking(move || {
         println!("Exporting database to {}.", path.to_str().unwrap());
 
-        let mut file =
-            BufWriter::new(std::fs::File::create(path).failed("Failed to create backup file"));
+        let file =
+            BufWriter::new(std::fs::File::create(path).failed("Failed to create backup file")?);
+        let mut file = if let Some(key) = encryption_key {
+            BackupWriter::encrypted(file, &key)?
+        } else {
+            BackupWriter::Plain(file)
+        };
         file.write_all(&[MAGIC_MARKER, FILE_VERSION])
-            .failed("Failed to write version");
+            .failed("Failed to write version")?;
 
         while let Ok(op) = rx.recv() {
             match op {
                 Op::Family(f) => {
                     file.write_all(&[0u8, f as u8])
-                        .failed("Failed to write family");
+                        .failed("Failed to write family")?;
                 }
                 Op::KeyValue((k, v)) => {
                     file.write_all(&[if !v.is_empty() { 1u8 } else { 2u8 }])
-                        .failed("Failed to write key");
+                        .failed("Failed to write key")?;
                     file.write_all(&(k.len() as u32).serialize())
-                        .failed("Failed to write key value");
-                    file.write_all(&k).failed("Failed to write key");
+                        .failed("Failed to write key value")?;
+                    file.write_all(&k).failed("Failed to write key")?;
                     if !v.is_empty() {
                         file.write_all(&(v.len() as u32).serialize())
-                            .failed("Failed to write key value");
-                        file.write_all(&v).failed("Failed to write key value");
+                            .failed("Failed to write key value")?;
+                        file.write_all(&v).failed("Failed to write key value")?;
                     }
                 }
                 Op::AccountId(v) => {
-                    file.write_all(&[3u8]).failed("Failed to write account id");
+                    file.write_all(&[3u8])
+                        .failed("Failed to write account id")?;
                     file.write_all(&v.serialize())
-                        .failed("Failed to write account id");
+                        .failed("Failed to write account id")?;
                 }
                 Op::Collection(v) => {
                     file.write_all(&[4u8, v])
-                        .failed("Failed to write collection");
+                        .failed("Failed to write collection")?;
                 }
                 Op::DocumentId(v) => {
-                    file.write_all(&[5u8]).failed("Failed to write document id");
+                    file.write_all(&[5u8])
+                        .failed("Failed to write document id")?;
                     file.write_all(&v.serialize())
-                        .failed("Failed to write document id");
+                        .failed("Failed to write document id")?;
                 }
             }
         }
 
-        file.flush().failed("Failed to flush backup file");
+        file.finish().failed("Failed to flush backup file")
     });
 
     (handle, tx)
 }
 
+// Encrypted backups start with a random salt followed by a sequence of
+// AES-256-GCM-SIV sealed chunks. Each chunk is prefixed by a flag marking the
+// last chunk and its length, both of which are bound to the chunk nonce so
+// that reordered or truncated files are detected on restore.
+enum BackupWriter {
+    Plain(BufWriter<std::fs::File>),
+    Encrypted {
+        file: BufWriter<std::fs::File>,
+        cipher: SymmetricEncrypt,
+        buf: Vec<u8>,
+        counter: u64,
+    },
+}
+
+impl BackupWriter {
+    fn encrypted(mut file: BufWriter<std::fs::File>, key: &str) -> trc::Result<Self> {
+        let salt: [u8; SALT_LEN] = rand::random();
+        let cipher = backup_cipher(key, &salt)?;
+        file.write_all(&[ENCRYPTED_MARKER])
+            .and_then(|_| file.write_all(&salt))
+            .failed("Failed to write encryption header")?;
+
+        Ok(BackupWriter::Encrypted {
+            file,
+            cipher,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            counter: 0,
+        })
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            BackupWriter::Plain(file) => file.write_all(bytes),
+            BackupWriter::Encrypted { buf, .. } => {
+                buf.extend_from_slice(bytes);
+                if buf.len() >= CHUNK_SIZE {
+                    self.write_chunk(false)
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn write_chunk(&mut self, is_last: bool) -> std::io::Result<()> {
+        if let BackupWriter::Encrypted {
+            file,
+            cipher,
+            buf,
+            counter,
+        } = self
+        {
+            let chunk = cipher
+                .encrypt(buf, &chunk_nonce(*counter, is_last))
+                .map_err(std::io::Error::other)?;
+            file.write_all(&[is_last as u8])?;
+            file.write_all(&(chunk.len() as u32).serialize())?;
+            file.write_all(&chunk)?;
+            buf.clear();
+            *counter += 1;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.write_chunk(true)?;
+        match self {
+            BackupWriter::Plain(mut file) | BackupWriter::Encrypted { mut file, .. } => {
+                file.flush()
+            }
+        }
+    }
+}
+
+// The backup key is usually a passphrase, so it is stretched using Argon2id
+// with the per-file salt before being used as the encryption key.
+pub(super) fn backup_cipher(key: &str, salt: &[u8]) -> trc::Result<SymmetricEncrypt> {
+    let mut derived_key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(key.as_bytes(), salt, &mut derived_key)
+        .map_err(|err| {
+            trc::StoreEvent::CryptoError
+                .into_err()
+                .details("Failed to derive backup encryption key")
+                .reason(err)
+        })?;
+
+    Ok(SymmetricEncrypt::new(
+        &derived_key,
+        "stalwart backup encryption",
+    ))
+}
+
+pub(super) fn chunk_nonce(counter: u64, is_last: bool) -> [u8; SymmetricEncrypt::NONCE_LEN] {
+    let mut nonce = [0u8; SymmetricEncrypt::NONCE_LEN];
+    nonce[..U64_LEN].copy_from_slice(&counter.to_be_bytes());
+    nonce[U64_LEN] = is_last as u8;
+    nonce
+}
+
+async fn read_blob_manifest(path: &Path) -> trc::Result<AHashSet<Vec<u8>>> {
+    tokio::fs::read(path)
+        .await
+        .failed(&format!("Failed to read blob manifest {path:?}"))
+        .map(|manifest| {
+            manifest
+                .chunks_exact(BLOB_HASH_LEN)
+                .map(|hash| hash.to_vec())
+                .collect()
+        })
+}
+
+// Backups and restores can also be started from the management API, so
+// failures are returned to the caller instead of terminating the process.
+pub(super) trait BackupFailure<T> {
+    fn failed(self, message: &str) -> trc::Result<T>;
+}
+
+impl<T> BackupFailure<T> for Option<T> {
+    fn failed(self, message: &str) -> trc::Result<T> {
+        self.ok_or_else(|| failed(message))
+    }
+}
+
+impl<T, E: std::fmt::Display> BackupFailure<T> for Result<T, E> {
+    fn failed(self, message: &str) -> trc::Result<T> {
+        self.map_err(|err| failed(message).reason(err))
+    }
+}
+
+pub(super) fn failed(message: &str) -> trc::Error {
+    trc::StoreEvent::UnexpectedError
+        .into_err()
+        .details(message.to_string())
+}
+
 pub(super) trait DeserializeBytes {
     fn range(&self, range: Range<usize>) -> trc::Result<&[u8]>;
     fn deserialize_u8(&self, offset: usize) -> trc::Result<u8>;
@@ -1167,31 +1391,56 @@ impl DeserializeBytes for &[u8] {
 
 impl BackupParams {
     pub fn new(dest: PathBuf) -> Self {
+        Self {
+            dest,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_env(dest: PathBuf) -> Result<Self, String> {
         let mut params = Self {
             dest,
             families: AHashSet::new(),
+            encryption_key: std::env::var("BACKUP_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            incremental: std::env::var("BACKUP_BASE").ok().map(PathBuf::from),
         };
 
         if let Ok(families) = std::env::var("EXPORT_TYPES") {
-            params.parse_families(&families);
+            params.parse_families(families.split(','))?;
         }
 
-        params
+        Ok(params)
     }
 
-    fn parse_families(&mut self, families: &str) {
-        for family in families.split(',') {
-            let family = family.trim();
-            match Family::parse(family) {
-                Ok(family) => {
-                    self.families.insert(family);
-                }
-                Err(err) => {
-                    eprintln!("Backup failed: {err}.");
-                    std::process::exit(1);
-                }
-            }
+    pub fn with_families<'x>(
+        mut self,
+        families: impl IntoIterator<Item = &'x str>,
+    ) -> Result<Self, String> {
+        self.parse_families(families)?;
+        Ok(self)
+    }
+
+    pub fn with_encryption_key(mut self, key: impl Into<String>) -> Self {
+        self.encryption_key = Some(key.into());
+        self
+    }
+
+    pub fn with_incremental(mut self, base: impl Into<PathBuf>) -> Self {
+        self.incremental = Some(base.into());
+        self
+    }
+
+    fn parse_families<'x>(
+        &mut self,
+        families: impl IntoIterator<Item = &'x str>,
+    ) -> Result<(), String> {
+        for family in families {
+            self.families.insert(Family::parse(family.trim())?);
         }
+
+        Ok(())
     }
 
     fn has_family(&self, family: Family) -> bool {
@@ -27,7 +27,9 @@ use crate::{
     Caches, Core, Data, IPC_CHANNEL_BUFFER, Inner, Ipc,
     config::{network::AsnGeoLookupConfig, server::Listeners, telemetry::Telemetry},
     core::BuildServer,
-    ipc::{BroadcastEvent, HousekeeperEvent, QueueEvent, ReportingEvent, StateEvent},
+    ipc::{
+        BroadcastEvent, HousekeeperEvent, PushGatewayEvent, QueueEvent, ReportingEvent, StateEvent,
+    },
 };
 
 use super::{
@@ -35,6 +37,7 @@ use super::{
     backup::BackupParams,
     config::{ConfigManager, Patterns},
     console::store_console,
+    restore::RestoreParams,
 };
 
 pub struct BootManager {
@@ -50,6 +53,7 @@ pub struct IpcReceivers {
     pub queue_rx: Option<mpsc::Receiver<QueueEvent>>,
     pub report_rx: Option<mpsc::Receiver<ReportingEvent>>,
     pub broadcast_rx: Option<mpsc::Receiver<BroadcastEvent>>,
+    pub push_gateway_rx: Option<mpsc::Receiver<PushGatewayEvent>>,
 }
 
 const HELP: &str = concat!(
@@ -63,17 +67,27 @@ Options:
   -c, --config <PATH>              Start server with the specified configuration file
   -e, --export <PATH>              Export all store data to a specific path
   -i, --import <PATH>              Import store data from a specific path
+  -b, --backup <PATH>              Alias of --export
+  -r, --restore <PATH>             Alias of --import
   -o, --console                    Open the store console
+  -z, --train-dictionary <PATH>    Train a zstd compression dictionary from stored blobs
   -I, --init <PATH>                Initialize a new server at a specific path
   -h, --help                       Print help
   -V, --version                    Print version
+
+Backup environment variables:
+  EXPORT_TYPES                     Comma separated list of data types to export
+  BACKUP_KEY                       Encrypt or decrypt backup files using this key
+  BACKUP_BASE                      Only export blobs missing from this previous backup
+  RESTORE_ACCOUNT                  Only restore the data of this account name or id
 "#
 );
 
 #[derive(PartialEq, Eq)]
 enum StoreOp {
     Export(BackupParams),
-    Import(PathBuf),
+    Import(RestoreParams),
+    TrainDictionary(PathBuf),
     Console,
     None,
 }
@@ -113,11 +127,17 @@ impl BootManager {
                         quickstart(value);
                         std::process::exit(0);
                     }
-                    ("export" | "e", Some(value)) => {
-                        import_export = StoreOp::Export(BackupParams::new(value.into()));
+                    ("export" | "e" | "backup" | "b", Some(value)) => {
+                        import_export = StoreOp::Export(
+                            BackupParams::from_env(value.into())
+                                .failed("Invalid backup parameters"),
+                        );
+                    }
+                    ("import" | "i" | "restore" | "r", Some(value)) => {
+                        import_export = StoreOp::Import(RestoreParams::from_env(value.into()));
                     }
-                    ("import" | "i", Some(value)) => {
-                        import_export = StoreOp::Import(value.into());
+                    ("train-dictionary" | "z", Some(value)) => {
+                        import_export = StoreOp::TrainDictionary(value.into());
                     }
                     ("console" | "o", None) => {
                         import_export = StoreOp::Console;
@@ -463,7 +483,8 @@ impl BootManager {
                 Box::pin(Core::parse(&mut config, stores, manager))
                     .await
                     .backup(path)
-                    .await;
+                    .await
+                    .failed("Backup failed");
                 std::process::exit(0);
             }
             StoreOp::Import(path) => {
@@ -474,7 +495,24 @@ impl BootManager {
                 Box::pin(Core::parse(&mut config, stores, manager))
                     .await
                     .restore(path)
-                    .await;
+                    .await
+                    .failed("Restore failed");
+                std::process::exit(0);
+            }
+            StoreOp::TrainDictionary(path) => {
+                // Enable telemetry
+                telemetry.enable(false);
+
+                // Train dictionary using a sample of the stored blobs
+                let core = Box::pin(Core::parse(&mut config, stores, manager)).await;
+                let dictionary = core
+                    .storage
+                    .blob
+                    .train_dictionary(&core.storage.data, 10_000, 112_640)
+                    .await
+                    .failed("Failed to train dictionary");
+                std::fs::write(&path, dictionary).failed("Failed to write dictionary");
+                println!("Dictionary written to {}.", path.to_str().unwrap());
                 std::process::exit(0);
             }
             StoreOp::Console => {
@@ -499,6 +537,7 @@ pub fn build_ipc(config: &mut Config, has_pubsub: bool) -> (Ipc, IpcReceivers) {
     let (queue_tx, queue_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
     let (report_tx, report_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
     let (broadcast_tx, broadcast_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
+    let (push_gateway_tx, push_gateway_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
     (
         Ipc {
             state_tx,
@@ -506,6 +545,7 @@ pub fn build_ipc(config: &mut Config, has_pubsub: bool) -> (Ipc, IpcReceivers) {
             queue_tx,
             report_tx,
             broadcast_tx: has_pubsub.then_some(broadcast_tx),
+            push_gateway_tx,
             task_tx: Arc::new(Notify::new()),
             local_delivery_sm: Arc::new(Semaphore::new(
                 config
@@ -520,6 +560,7 @@ pub fn build_ipc(config: &mut Config, has_pubsub: bool) -> (Ipc, IpcReceivers) {
             queue_rx: Some(queue_rx),
             report_rx: Some(report_rx),
             broadcast_rx: has_pubsub.then_some(broadcast_rx),
+            push_gateway_rx: Some(push_gateway_rx),
         },
     )
 }
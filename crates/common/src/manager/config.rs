@@ -10,7 +10,7 @@ use std::{
     sync::Arc,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use arc_swap::ArcSwap;
 use store::{
     Deserialize, IterateParams, Store, ValueKey,
@@ -266,6 +266,46 @@ impl ConfigManager {
             .await
     }
 
+    pub async fn replace_prefix(&self, prefix: &str, keys: Vec<ConfigKey>) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        let mut local = self.cfg_local.load().as_ref().clone();
+        let mut has_local_changes = false;
+        let new_keys = keys.iter().map(|k| k.key.clone()).collect::<AHashSet<_>>();
+
+        // Remove keys that are not part of the new set
+        for key in self.db_list(prefix, false).await?.into_keys() {
+            if !new_keys.contains(&key) {
+                batch.clear(ValueClass::Config(key.into_bytes()));
+            }
+        }
+        local.retain(|key, _| {
+            let keep = !key.starts_with(prefix) || new_keys.contains(key);
+            has_local_changes |= !keep;
+            keep
+        });
+
+        for key in keys {
+            if self.cfg_local_patterns.is_local_key(&key.key) {
+                if local.get(&key.key) != Some(&key.value) {
+                    local.insert(key.key, key.value);
+                    has_local_changes = true;
+                }
+            } else {
+                batch.set(ValueClass::Config(key.key.into_bytes()), key.value);
+            }
+        }
+
+        // Apply all database changes in a single transaction
+        if !batch.is_empty() {
+            self.cfg_store.write(batch.build_all()).await?;
+        }
+        if has_local_changes {
+            self.update_local(local).await?;
+        }
+
+        Ok(())
+    }
+
     async fn update_local(&self, map: BTreeMap<String, String>) -> trc::Result<()> {
         let mut cfg_text = String::with_capacity(1024);
         for (key, value) in &map {
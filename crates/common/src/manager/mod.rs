@@ -13,6 +13,7 @@ use crate::USER_AGENT;
 
 use self::config::ConfigManager;
 
+pub mod audit;
 pub mod backup;
 pub mod boot;
 pub mod config;
@@ -15,7 +15,11 @@ use crate::{
         server::{Listeners, tls::parse_certificates},
         telemetry::Telemetry,
     },
-    listener::blocked::{BLOCKED_IP_KEY, BlockedIps},
+    listener::{
+        blocked::{BLOCKED_IP_KEY, BlockedIps},
+        certificate::ManagedCertificate,
+        iplist::parse_ip_list_entries,
+    },
 };
 
 use super::config::{ConfigManager, Patterns};
@@ -36,16 +40,20 @@ impl Server {
             .await?;
         *self.inner.data.blocked_ips.write() = BlockedIps::parse(&mut config).blocked_ip_addresses;
 
+        // Update IP lists
+        self.reload_ip_lists().await?;
+
         Ok(config.into())
     }
 
     pub async fn reload_certificates(&self) -> trc::Result<ReloadResult> {
         let mut config = self.core.storage.config.build_config("certificate").await?;
-        let mut certificates = self.inner.data.tls_certificates.load().as_ref().clone();
+        let mut certificates = AHashMap::new();
 
         parse_certificates(&mut config, &mut certificates, &mut Default::default());
 
-        self.inner.data.tls_certificates.store(certificates.into());
+        self.keep_failed_certificates(&config, &mut certificates);
+        self.replace_certificates(certificates);
 
         Ok(config.into())
     }
@@ -110,20 +118,17 @@ impl Server {
         }
 
         // Update TLS certificates
-        let mut new_certificates = AHashMap::new();
-        parse_certificates(&mut config, &mut new_certificates, &mut Default::default());
-        let mut current_certificates = self.inner.data.tls_certificates.load().as_ref().clone();
-        for (cert_id, cert) in new_certificates {
-            current_certificates.insert(cert_id, cert);
-        }
-        self.inner
-            .data
-            .tls_certificates
-            .store(current_certificates.into());
+        let mut certificates = AHashMap::new();
+        parse_certificates(&mut config, &mut certificates, &mut Default::default());
+        self.keep_failed_certificates(&config, &mut certificates);
+        self.replace_certificates(certificates);
 
         // Update blocked IPs
         *self.inner.data.blocked_ips.write() = BlockedIps::parse(&mut config).blocked_ip_addresses;
 
+        // Update IP lists
+        *self.inner.data.ip_lists.write() = parse_ip_list_entries(&mut config);
+
         // Parser servers
         let mut servers = Listeners::parse(&mut config);
         servers.parse_tcp_acceptors(&mut config, self.inner.clone());
@@ -138,6 +143,23 @@ impl Server {
             config.into()
         })
     }
+
+    // Certificates that fail to load keep serving their previous version
+    fn keep_failed_certificates(
+        &self,
+        config: &Config,
+        certificates: &mut AHashMap<String, ManagedCertificate>,
+    ) {
+        for (cert_id, cert) in self.inner.data.tls_managed_certificates.load().iter() {
+            if !certificates.contains_key(cert_id)
+                && config
+                    .errors
+                    .contains_key(&format!("certificate.{cert_id}"))
+            {
+                certificates.insert(cert_id.clone(), cert.clone());
+            }
+        }
+    }
 }
 
 impl From<Config> for ReloadResult {
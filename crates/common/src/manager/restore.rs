@@ -9,9 +9,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::Core;
-use ahash::AHashMap;
-use jmap_proto::types::{collection::Collection, property::Property};
+use crate::{Core, auth::oauth::crypto::SymmetricEncrypt};
+use ahash::{AHashMap, AHashSet};
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_proto::types::collection::Collection;
 use store::{
     BlobStore, Key, LogKey, SUBSPACE_LOGS, SerializeInfallible, Store, U32_LEN,
     roaring::RoaringBitmap,
@@ -28,41 +29,131 @@ use tokio::{
     fs::File,
     io::{AsyncReadExt, BufReader},
 };
-use utils::{BlobHash, UnwrapFailure, failed};
+use utils::BlobHash;
 
-use super::backup::{DeserializeBytes, FILE_VERSION, Family, MAGIC_MARKER, Op};
+use super::backup::{
+    BLOB_MANIFEST, BackupFailure, DeserializeBytes, ENCRYPTED_MARKER, FILE_VERSION, Family,
+    MAGIC_MARKER, MAILBOX_COUNTERS, Op, SALT_LEN, backup_cipher, chunk_nonce, failed,
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RestoreParams {
+    src: PathBuf,
+    encryption_key: Option<String>,
+    account: Option<String>,
+}
 
 impl Core {
-    pub async fn restore(&self, src: PathBuf) {
-        // Backup the core
-        if src.is_dir() {
+    pub async fn restore(&self, params: RestoreParams) -> trc::Result<()> {
+        // Obtain the account to restore, if any
+        let account_id = if let Some(account) = &params.account {
+            if let Ok(account_id) = account.parse::<u32>() {
+                Some(account_id)
+            } else {
+                Some(
+                    self.storage
+                        .data
+                        .get_principal_id(account)
+                        .await
+                        .failed("Failed to obtain account id")?
+                        .failed(&format!("Account {account:?} does not exist"))?,
+                )
+            }
+        } else {
+            None
+        };
+        let encryption_key = params.encryption_key.as_deref();
+
+        if params.src.is_dir() {
             // Iterate directory and spawn a task for each file
             let mut tasks = Vec::new();
-            for entry in std::fs::read_dir(&src).failed("Failed to read directory") {
-                let entry = entry.failed("Failed to read entry");
+            let mut entries = tokio::fs::read_dir(&params.src)
+                .await
+                .failed("Failed to read directory")?;
+            while let Some(entry) = entries.next_entry().await.failed("Failed to read entry")? {
                 let path = entry.path();
-                if path.is_file() {
+                let is_file = entry
+                    .file_type()
+                    .await
+                    .failed("Failed to read entry")?
+                    .is_file();
+                if is_file && path.file_name().is_some_and(|name| name != BLOB_MANIFEST) {
                     let storage = self.storage.clone();
                     let blob_store = self.storage.blob.clone();
+                    let encryption_key = encryption_key.map(|key| key.to_string());
                     tasks.push(tokio::spawn(async move {
-                        restore_file(storage.data, blob_store, &path).await;
+                        restore_file(
+                            storage.data,
+                            blob_store,
+                            &path,
+                            encryption_key.as_deref(),
+                            account_id,
+                        )
+                        .await
                     }));
                 }
             }
 
+            // Wait for all files to be restored before reporting the first error
+            let mut result = Ok(());
             for task in tasks {
-                task.await.failed("Failed to wait for task");
+                result = result.and(task.await.failed("Failed to wait for task").and_then(|r| r));
             }
+            result
         } else {
-            restore_file(self.storage.data.clone(), self.storage.blob.clone(), &src).await;
+            restore_file(
+                self.storage.data.clone(),
+                self.storage.blob.clone(),
+                &params.src,
+                encryption_key,
+                account_id,
+            )
+            .await
         }
     }
 }
 
-async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
+impl RestoreParams {
+    pub fn new(src: PathBuf) -> Self {
+        Self {
+            src,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_env(src: PathBuf) -> Self {
+        Self {
+            src,
+            encryption_key: std::env::var("BACKUP_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            account: std::env::var("RESTORE_ACCOUNT")
+                .ok()
+                .filter(|account| !account.is_empty()),
+        }
+    }
+
+    pub fn with_encryption_key(mut self, key: impl Into<String>) -> Self {
+        self.encryption_key = Some(key.into());
+        self
+    }
+
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+}
+
+async fn restore_file(
+    store: Store,
+    blob_store: BlobStore,
+    path: &Path,
+    encryption_key: Option<&str>,
+    restore_account_id: Option<u32>,
+) -> trc::Result<()> {
     println!("Importing database dump from {}.", path.to_str().unwrap());
 
-    let mut reader = OpReader::new(path).await;
+    let mut reader = OpReader::new(path, encryption_key).await?;
     let mut account_id = u32::MAX;
     let mut document_id = u32::MAX;
     let mut collection = u8::MAX;
@@ -74,8 +165,9 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
     let mut batch = BatchBuilder::new();
 
     let mut change_ids: AHashMap<u32, u64> = AHashMap::new();
+    let mut linked_hashes: AHashSet<Vec<u8>> = AHashSet::new();
 
-    while let Some(op) = reader.next().await {
+    while let Some(op) = reader.next().await? {
         match op {
             Op::Family(f) => family = f,
             Op::AccountId(a) => {
@@ -91,6 +183,33 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                 batch.update_document(document_id);
             }
             Op::KeyValue((key, value)) => {
+                // When restoring a single account, skip server-wide data and
+                // blobs that are not linked to the account
+                if let Some(restore_account_id) = restore_account_id {
+                    let is_selected = match family {
+                        Family::Property
+                        | Family::FtsIndex
+                        | Family::Acl
+                        | Family::Index
+                        | Family::Bitmap
+                        | Family::Log => account_id == restore_account_id,
+                        Family::Blob if account_id == restore_account_id => {
+                            linked_hashes.insert(key.clone());
+                            true
+                        }
+                        Family::Blob => {
+                            account_id == u32::MAX
+                                && document_id == u32::MAX
+                                && linked_hashes.contains(&key)
+                        }
+                        _ => false,
+                    };
+
+                    if !is_selected {
+                        continue;
+                    }
+                }
+
                 batch_size += key.len() + value.len() + U32_LEN * 2;
 
                 match family {
@@ -98,14 +217,14 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                         let field = key
                             .as_slice()
                             .deserialize_u8(0)
-                            .expect("Failed to deserialize field");
+                            .failed("Failed to deserialize field")?;
                         if collection == u8::from(Collection::Mailbox)
-                            && u8::from(Property::EmailIds) == field
+                            && MAILBOX_COUNTERS.iter().any(|p| u8::from(p) == field)
                         {
                             batch.add(
                                 ValueClass::Property(field),
                                 i64::deserialize(&value)
-                                    .expect("Failed to deserialize mailbox uidnext"),
+                                    .failed("Failed to deserialize mailbox counter")?,
                             );
                         } else {
                             batch.set(ValueClass::Property(field), value);
@@ -124,7 +243,9 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                                     (hash, len as u8)
                                 }
                                 invalid => {
-                                    panic!("Invalid text bitmap key length {invalid}");
+                                    return Err(failed(&format!(
+                                        "Invalid text bitmap key length {invalid}"
+                                    )));
                                 }
                             };
 
@@ -136,13 +257,14 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                             ValueClass::Acl(
                                 key.as_slice()
                                     .deserialize_be_u32(0)
-                                    .expect("Failed to deserialize acl"),
+                                    .failed("Failed to deserialize acl")?,
                             ),
                             value,
                         );
                     }
                     Family::Blob => {
-                        let hash = BlobHash::try_from_hash_slice(&key).expect("Invalid blob hash");
+                        let hash =
+                            BlobHash::try_from_hash_slice(&key).failed("Invalid blob hash")?;
 
                         if account_id != u32::MAX && document_id != u32::MAX {
                             if reader.version == 1 && collection == email_collection {
@@ -161,7 +283,7 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                             blob_store
                                 .put_blob(&key, &value)
                                 .await
-                                .expect("Failed to write blob");
+                                .failed("Failed to write blob")?;
                             batch.set(ValueClass::Blob(BlobOp::Commit { hash }), vec![]);
                         }
                     }
@@ -174,39 +296,39 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                     Family::LookupCounter => {
                         batch.add(
                             ValueClass::InMemory(InMemoryClass::Counter(key)),
-                            i64::deserialize(&value).expect("Failed to deserialize counter"),
+                            i64::deserialize(&value).failed("Failed to deserialize counter")?,
                         );
                     }
                     Family::Directory => {
                         let key = key.as_slice();
                         let class: DirectoryClass =
-                            match key.first().expect("Failed to read directory key type") {
+                            match key.first().failed("Failed to read directory key type")? {
                                 0 => DirectoryClass::NameToId(
                                     key.get(1..)
-                                        .expect("Failed to read directory string")
+                                        .failed("Failed to read directory string")?
                                         .to_vec(),
                                 ),
                                 1 => DirectoryClass::EmailToId(
                                     key.get(1..)
-                                        .expect("Failed to read directory string")
+                                        .failed("Failed to read directory string")?
                                         .to_vec(),
                                 ),
                                 2 => DirectoryClass::Principal(
                                     key.get(1..)
-                                        .expect("Failed to read range for principal id")
+                                        .failed("Failed to read range for principal id")?
                                         .deserialize_leb128::<u32>()
-                                        .expect("Failed to deserialize principal id"),
+                                        .failed("Failed to deserialize principal id")?,
                                 ),
                                 4 => {
                                     batch.add(
                                         ValueClass::Directory(DirectoryClass::UsedQuota(
                                             key.get(1..)
-                                                .expect("Failed to read principal id")
+                                                .failed("Failed to read principal id")?
                                                 .deserialize_leb128()
-                                                .expect("Failed to read principal id"),
+                                                .failed("Failed to read principal id")?,
                                         )),
                                         i64::deserialize(&value)
-                                            .expect("Failed to deserialize quota"),
+                                            .failed("Failed to deserialize quota")?,
                                     );
 
                                     continue;
@@ -214,35 +336,35 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                                 5 => DirectoryClass::MemberOf {
                                     principal_id: key
                                         .deserialize_be_u32(1)
-                                        .expect("Failed to read principal id"),
+                                        .failed("Failed to read principal id")?,
 
                                     member_of: key
                                         .deserialize_be_u32(1 + U32_LEN)
-                                        .expect("Failed to read principal id"),
+                                        .failed("Failed to read principal id")?,
                                 },
                                 6 => DirectoryClass::Members {
                                     principal_id: key
                                         .deserialize_be_u32(1)
-                                        .expect("Failed to read principal id"),
+                                        .failed("Failed to read principal id")?,
 
                                     has_member: key
                                         .deserialize_be_u32(1 + U32_LEN)
-                                        .expect("Failed to read principal id"),
+                                        .failed("Failed to read principal id")?,
                                 },
 
-                                _ => failed("Invalid directory key"),
+                                _ => return Err(failed("Invalid directory key")),
                             };
                         batch.set(ValueClass::Directory(class), value);
                     }
                     Family::Queue => {
                         let key = key.as_slice();
 
-                        match key.first().expect("Failed to read queue key type") {
+                        match key.first().failed("Failed to read queue key type")? {
                             0 => {
                                 batch.set(
                                     ValueClass::Queue(QueueClass::Message(
                                         key.deserialize_be_u64(1)
-                                            .expect("Failed to deserialize queue message id"),
+                                            .failed("Failed to deserialize queue message id")?,
                                     )),
                                     value,
                                 );
@@ -252,74 +374,76 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                                     ValueClass::Queue(QueueClass::MessageEvent(QueueEvent {
                                         due: key
                                             .deserialize_be_u64(1)
-                                            .expect("Failed to deserialize queue message id"),
+                                            .failed("Failed to deserialize queue message id")?,
                                         queue_id: key
                                             .deserialize_be_u64(1 + U64_LEN)
-                                            .expect("Failed to deserialize queue message id"),
+                                            .failed("Failed to deserialize queue message id")?,
                                     })),
                                     value,
                                 );
                             }
-                            _ => failed("Invalid queue key"),
+                            _ => return Err(failed("Invalid queue key")),
                         }
                     }
                     Family::Index => {
                         batch.any_op(Operation::Index {
-                            field: key.first().copied().expect("Failed to read index field"),
-                            key: key.get(1..).expect("Failed to read index key").to_vec(),
+                            field: key.first().copied().failed("Failed to read index field")?,
+                            key: key.get(1..).failed("Failed to read index key")?.to_vec(),
                             set: true,
                         });
                     }
                     Family::Bitmap => {
                         let key = key.as_slice();
-                        let class: BitmapClass =
-                            match key.first().expect("Failed to read bitmap class") {
-                                0 => BitmapClass::DocumentIds,
-                                1 => BitmapClass::Tag {
-                                    field: key.get(1).copied().expect("Failed to read field"),
-                                    value: TagValue::Id(
-                                        key.deserialize_be_u32(2).expect("Failed to read tag id"),
-                                    ),
-                                },
-                                2 => BitmapClass::Tag {
-                                    field: key.get(1).copied().expect("Failed to read field"),
-                                    value: TagValue::Text(
-                                        key.get(2..).expect("Failed to read tag text").to_vec(),
-                                    ),
-                                },
-                                3 => BitmapClass::Tag {
-                                    field: key.get(1).copied().expect("Failed to read field"),
-                                    value: TagValue::Id(
-                                        key.get(2)
+                        let class: BitmapClass = match key
+                            .first()
+                            .failed("Failed to read bitmap class")?
+                        {
+                            0 => BitmapClass::DocumentIds,
+                            1 => BitmapClass::Tag {
+                                field: key.get(1).copied().failed("Failed to read field")?,
+                                value: TagValue::Id(
+                                    key.deserialize_be_u32(2).failed("Failed to read tag id")?,
+                                ),
+                            },
+                            2 => BitmapClass::Tag {
+                                field: key.get(1).copied().failed("Failed to read field")?,
+                                value: TagValue::Text(
+                                    key.get(2..).failed("Failed to read tag text")?.to_vec(),
+                                ),
+                            },
+                            3 => BitmapClass::Tag {
+                                field: key.get(1).copied().failed("Failed to read field")?,
+                                value: TagValue::Id(
+                                    key.get(2)
+                                        .copied()
+                                        .failed("Failed to read tag static id")?
+                                        .into(),
+                                ),
+                            },
+                            4 => {
+                                if reader.version == 1 && collection == email_collection {
+                                    continue;
+                                }
+
+                                BitmapClass::Text {
+                                    field: key.get(1).copied().failed("Failed to read field")?,
+                                    token: BitmapHash {
+                                        len: key
+                                            .get(2)
                                             .copied()
-                                            .expect("Failed to read tag static id")
-                                            .into(),
-                                    ),
-                                },
-                                4 => {
-                                    if reader.version == 1 && collection == email_collection {
-                                        continue;
-                                    }
-
-                                    BitmapClass::Text {
-                                        field: key.get(1).copied().expect("Failed to read field"),
-                                        token: BitmapHash {
-                                            len: key
-                                                .get(2)
-                                                .copied()
-                                                .expect("Failed to read tag static id"),
-                                            hash: key
-                                                .get(3..11)
-                                                .expect("Failed to read tag static id")
-                                                .try_into()
-                                                .unwrap(),
-                                        },
-                                    }
+                                            .failed("Failed to read tag static id")?,
+                                        hash: key
+                                            .get(3..11)
+                                            .failed("Failed to read tag static id")?
+                                            .try_into()
+                                            .unwrap(),
+                                    },
                                 }
-                                _ => failed("Invalid bitmap class"),
-                            };
+                            }
+                            _ => return Err(failed("Invalid bitmap class")),
+                        };
                         let document_ids = RoaringBitmap::deserialize_from(&value[..])
-                            .expect("Failed to deserialize bitmap");
+                            .failed("Failed to deserialize bitmap")?;
 
                         for document_id in document_ids {
                             batch.any_op(Operation::DocumentId { document_id });
@@ -332,7 +456,7 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                                 store
                                     .write(batch.build_all())
                                     .await
-                                    .failed("Failed to write batch");
+                                    .failed("Failed to write batch")?;
                                 batch = BatchBuilder::new();
                                 batch
                                     .with_account_id(account_id)
@@ -344,7 +468,7 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                         let change_id = key
                             .as_slice()
                             .deserialize_be_u64(0)
-                            .expect("Failed to deserialize change id");
+                            .failed("Failed to deserialize change id")?;
                         let change_ids = change_ids.entry(account_id).or_default();
                         *change_ids = std::cmp::max(*change_ids, change_id);
 
@@ -364,7 +488,7 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                             },
                         });
                     }
-                    Family::None => failed("No family specified in file"),
+                    Family::None => return Err(failed("No family specified in file")),
                 }
             }
         }
@@ -373,7 +497,7 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
             store
                 .write(batch.build_all())
                 .await
-                .failed("Failed to write batch");
+                .failed("Failed to write batch")?;
             batch = BatchBuilder::new();
             batch
                 .with_account_id(account_id)
@@ -387,7 +511,7 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
         store
             .write(batch.build_all())
             .await
-            .failed("Failed to write batch");
+            .failed("Failed to write batch")?;
     }
 
     if !change_ids.is_empty() {
@@ -402,80 +526,194 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
         store
             .write(batch.build_all())
             .await
-            .failed("Failed to write batch");
+            .failed("Failed to write batch")?;
     }
+
+    Ok(())
 }
 
 struct OpReader {
     version: u8,
     file: BufReader<File>,
+    decrypter: Option<ChunkDecrypter>,
 }
 
-impl OpReader {
-    async fn new(path: &Path) -> Self {
-        let mut file = BufReader::new(File::open(&path).await.failed("Failed to open file"));
+struct ChunkDecrypter {
+    cipher: SymmetricEncrypt,
+    counter: u64,
+    buf: Vec<u8>,
+    pos: usize,
+    is_last: bool,
+}
 
-        if file
+impl OpReader {
+    async fn new(path: &Path, encryption_key: Option<&str>) -> trc::Result<Self> {
+        let mut file = BufReader::new(File::open(&path).await.failed("Failed to open file")?);
+        let mut marker = file
             .read_u8()
             .await
-            .failed(&format!("Failed to read magic marker from {path:?}"))
-            != MAGIC_MARKER
-        {
-            failed(&format!("Invalid magic marker in {path:?}"));
+            .failed(&format!("Failed to read magic marker from {path:?}"))?;
+
+        let decrypter = if marker == ENCRYPTED_MARKER {
+            let key = encryption_key.failed(&format!(
+                "Backup file {path:?} is encrypted, set BACKUP_KEY to restore it"
+            ))?;
+            let mut salt = [0u8; SALT_LEN];
+            file.read_exact(&mut salt)
+                .await
+                .failed(&format!("Failed to read encryption header from {path:?}"))?;
+
+            // Key derivation is CPU intensive, run it outside the async runtime
+            let key = key.to_string();
+            let cipher = tokio::task::spawn_blocking(move || backup_cipher(&key, &salt))
+                .await
+                .failed("Failed to derive backup encryption key")??;
+
+            Some(ChunkDecrypter {
+                cipher,
+                counter: 0,
+                buf: Vec::new(),
+                pos: 0,
+                is_last: false,
+            })
+        } else {
+            None
+        };
+
+        let mut reader = Self {
+            file,
+            version: 0,
+            decrypter,
+        };
+
+        if reader.decrypter.is_some() {
+            marker = reader
+                .read_u8()
+                .await
+                .failed(&format!("Failed to read magic marker from {path:?}"))?;
+        }
+
+        if marker != MAGIC_MARKER {
+            return Err(failed(&format!("Invalid magic marker in {path:?}")));
         }
 
-        let version = file
+        reader.version = reader
             .read_u8()
             .await
-            .failed(&format!("Failed to read version from {path:?}"));
+            .failed(&format!("Failed to read version from {path:?}"))?;
 
-        if version > FILE_VERSION {
-            failed(&format!("Invalid file version in {path:?}"));
+        if reader.version > FILE_VERSION {
+            return Err(failed(&format!("Invalid file version in {path:?}")));
         }
 
-        Self { file, version }
+        Ok(reader)
     }
 
-    async fn next(&mut self) -> Option<Op> {
-        match self.file.read_u8().await {
+    async fn next(&mut self) -> trc::Result<Option<Op>> {
+        Ok(match self.read_u8().await {
             Ok(byte) => match byte {
                 0 => Op::Family(
-                    Family::try_from(self.expect_u8().await).failed("Failed to read family"),
+                    Family::try_from(self.expect_u8().await?).failed("Failed to read family")?,
                 ),
                 1 => Op::KeyValue((
-                    self.expect_sized_bytes().await,
-                    self.expect_sized_bytes().await,
+                    self.expect_sized_bytes().await?,
+                    self.expect_sized_bytes().await?,
                 )),
-                2 => Op::KeyValue((self.expect_sized_bytes().await, vec![])),
-                3 => Op::AccountId(self.expect_u32_be().await),
-                4 => Op::Collection(self.expect_u8().await),
-                5 => Op::DocumentId(self.expect_u32_be().await),
+                2 => Op::KeyValue((self.expect_sized_bytes().await?, vec![])),
+                3 => Op::AccountId(self.expect_u32_be().await?),
+                4 => Op::Collection(self.expect_u8().await?),
+                5 => Op::DocumentId(self.expect_u32_be().await?),
                 unknown => {
-                    failed(&format!("Unknown op type {unknown}"));
+                    return Err(failed(&format!("Unknown op type {unknown}")));
                 }
             }
             .into(),
             Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
-            Err(err) => failed(&format!("Failed to read file: {err:?}")),
+            Err(err) => return Err(failed("Failed to read file").reason(err)),
+        })
+    }
+
+    async fn read_exact(&mut self, bytes: &mut [u8]) -> std::io::Result<()> {
+        let Some(decrypter) = &mut self.decrypter else {
+            return self.file.read_exact(bytes).await.map(|_| ());
+        };
+
+        let mut offset = 0;
+        while offset < bytes.len() {
+            if decrypter.pos == decrypter.buf.len() {
+                if decrypter.is_last {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                decrypter.next_chunk(&mut self.file).await?;
+            }
+
+            let len = (bytes.len() - offset).min(decrypter.buf.len() - decrypter.pos);
+            bytes[offset..offset + len]
+                .copy_from_slice(&decrypter.buf[decrypter.pos..decrypter.pos + len]);
+            decrypter.pos += len;
+            offset += len;
         }
+
+        Ok(())
     }
 
-    async fn expect_u8(&mut self) -> u8 {
-        self.file.read_u8().await.failed("Failed to read u8")
+    async fn read_u8(&mut self) -> std::io::Result<u8> {
+        let mut bytes = [0u8; 1];
+        self.read_exact(&mut bytes).await?;
+        Ok(bytes[0])
     }
 
-    async fn expect_u32_be(&mut self) -> u32 {
-        self.file.read_u32().await.failed("Failed to read u32")
+    async fn expect_u8(&mut self) -> trc::Result<u8> {
+        self.read_u8().await.failed("Failed to read u8")
     }
 
-    async fn expect_sized_bytes(&mut self) -> Vec<u8> {
-        let len = self.expect_u32_be().await as usize;
+    async fn expect_u32_be(&mut self) -> trc::Result<u32> {
+        let mut bytes = [0u8; U32_LEN];
+        self.read_exact(&mut bytes)
+            .await
+            .failed("Failed to read u32")?;
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    async fn expect_sized_bytes(&mut self) -> trc::Result<Vec<u8>> {
+        let len = self.expect_u32_be().await? as usize;
         let mut bytes = vec![0; len];
-        self.file
-            .read_exact(&mut bytes)
+        self.read_exact(&mut bytes)
             .await
-            .failed("Failed to read bytes");
-        bytes
+            .failed("Failed to read bytes")?;
+        Ok(bytes)
+    }
+}
+
+impl ChunkDecrypter {
+    async fn next_chunk(&mut self, file: &mut BufReader<File>) -> std::io::Result<()> {
+        // A missing last chunk means the backup was truncated
+        let is_last = file.read_u8().await.map_err(truncated)? == 1;
+        let mut chunk = vec![0; file.read_u32().await.map_err(truncated)? as usize];
+        file.read_exact(&mut chunk).await.map_err(truncated)?;
+
+        self.buf = self
+            .cipher
+            .decrypt(&chunk, &chunk_nonce(self.counter, is_last))
+            .map_err(|_| {
+                std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Failed to decrypt backup chunk, the key is invalid or the file is corrupted",
+                )
+            })?;
+        self.pos = 0;
+        self.counter += 1;
+        self.is_last = is_last;
+
+        Ok(())
+    }
+}
+
+fn truncated(err: std::io::Error) -> std::io::Error {
+    if err.kind() == ErrorKind::UnexpectedEof {
+        std::io::Error::new(ErrorKind::InvalidData, "Encrypted backup file is truncated")
+    } else {
+        err
     }
 }
 
@@ -0,0 +1,92 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Display;
+
+use sieve::compiler::CompileError;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SieveDiagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub rule: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<&'static str>,
+}
+
+impl From<&CompileError> for SieveDiagnostic {
+    fn from(err: &CompileError) -> Self {
+        // Derive a stable rule identifier from the error variant name
+        let variant = format!("{:?}", err.error_type());
+        let mut rule = String::with_capacity(variant.len() + 4);
+        let mut prev_upper = true;
+        for ch in variant.chars().take_while(|ch| ch.is_ascii_alphanumeric()) {
+            if ch.is_ascii_uppercase() {
+                // Acronyms such as "EOF" or "URI" are kept as a single word
+                if !prev_upper {
+                    rule.push('-');
+                }
+                rule.push(ch.to_ascii_lowercase());
+                prev_upper = true;
+            } else {
+                rule.push(ch);
+                prev_upper = false;
+            }
+        }
+
+        SieveDiagnostic {
+            line: err.line_num(),
+            column: err.line_pos(),
+            suggestion: suggestion(&rule),
+            rule,
+            message: err.to_string(),
+        }
+    }
+}
+
+fn suggestion(rule: &str) -> Option<&'static str> {
+    match rule {
+        "undeclared-capability" => {
+            Some("Add the extension to the require statement at the top of the script.")
+        }
+        "unterminated-string" => Some("Close the string with a double quote."),
+        "unterminated-comment" => Some("Close the comment with '*/'."),
+        "unterminated-multiline" => {
+            Some("End the multi-line string with a line containing a single dot.")
+        }
+        "unterminated-block" => Some("Add the missing closing brace."),
+        "unexpected-eof" => Some("Check for a missing semicolon or closing brace."),
+        "unexpected-token" => Some("Check for a missing semicolon or a misspelled command."),
+        "invalid-regex" => Some("Check the syntax of the regular expression."),
+        "unsupported-comparator" => {
+            Some("Use a supported comparator such as \"i;ascii-casemap\" or \"i;octet\".")
+        }
+        "script-too-long" | "string-too-long" => {
+            Some("Reduce the size of the script or split it into included scripts.")
+        }
+        "too-many-includes" => Some("Reduce the number of included scripts."),
+        _ if rule.starts_with("too-many-nested") => {
+            Some("Reduce the nesting depth of blocks and tests.")
+        }
+        _ => None,
+    }
+}
+
+impl Display for SieveDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {} [{}]: {}",
+            self.line, self.column, self.rule, self.message
+        )?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " {suggestion}")?;
+        }
+        Ok(())
+    }
+}
@@ -12,9 +12,15 @@ use unicode_security::mixed_script::AugmentedScriptSet;
 
 use crate::IntoString;
 
+pub mod diagnostic;
 pub mod functions;
+pub mod notify;
 pub mod plugins;
 
+pub const LIST_ADDRBOOK_DEFAULT: &str = "urn:ietf:params:sieve:addrbook:default";
+pub const LIST_ADDRBOOK_PERSONAL: &str = "urn:ietf:params:sieve:addrbook:personal";
+pub const LIST_ADDRBOOK_GROUPS: &str = "urn:ietf:params:sieve:addrbook:groups";
+
 #[derive(Debug, serde::Serialize)]
 #[serde(tag = "action")]
 #[serde(rename_all = "camelCase")]
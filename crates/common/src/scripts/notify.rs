@@ -0,0 +1,345 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use mail_builder::{
+    MessageBuilder,
+    headers::{
+        HeaderType,
+        address::{Address, EmailAddress},
+    },
+};
+use percent_encoding::percent_decode_str;
+use reqwest::{Url, redirect::Policy};
+use serde::Serialize;
+use sieve::Importance;
+
+use crate::Server;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyMethod {
+    Mailto {
+        recipients: Vec<String>,
+        subject: Option<String>,
+        body: Option<String>,
+    },
+    Https {
+        url: String,
+    },
+    Xmpp {
+        jid: String,
+    },
+}
+
+pub struct Notification<'x> {
+    pub method: &'x str,
+    pub from: Option<&'x str>,
+    pub importance: &'x Importance,
+    pub options: &'x [String],
+    pub message: &'x str,
+    pub envelope_from: &'x str,
+    pub envelope_to: &'x str,
+    pub subject: Option<&'x str>,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'x> {
+    method: &'x str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<&'x str>,
+    importance: &'static str,
+    options: AHashMap<&'x str, &'x str>,
+    message: &'x str,
+    envelope: WebhookEnvelope<'x>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<&'x str>,
+}
+
+#[derive(Serialize)]
+struct WebhookEnvelope<'x> {
+    from: &'x str,
+    to: &'x str,
+}
+
+#[derive(Serialize)]
+struct XmppPayload<'x> {
+    to: &'x str,
+    from: &'x str,
+    body: &'x str,
+    importance: &'static str,
+}
+
+impl NotifyMethod {
+    pub fn parse(uri: &str) -> Option<Self> {
+        let (scheme, value) = uri.split_once(':')?;
+
+        if scheme.eq_ignore_ascii_case("mailto") {
+            let (addresses, query) = value.split_once('?').unwrap_or((value, ""));
+            let mut recipients = addresses
+                .split(',')
+                .map(|addr| decode(addr).trim().to_lowercase())
+                .filter(|addr| addr.contains('@'))
+                .collect::<Vec<_>>();
+            let mut subject = None;
+            let mut body = None;
+
+            for param in query.split('&') {
+                if let Some((name, value)) = param.split_once('=') {
+                    let value = decode(value);
+                    if name.eq_ignore_ascii_case("subject") {
+                        subject = Some(value);
+                    } else if name.eq_ignore_ascii_case("body") {
+                        body = Some(value);
+                    } else if name.eq_ignore_ascii_case("to") {
+                        recipients.extend(
+                            value
+                                .split(',')
+                                .map(|addr| addr.trim().to_lowercase())
+                                .filter(|addr| addr.contains('@')),
+                        );
+                    }
+                }
+            }
+
+            (!recipients.is_empty()).then_some(NotifyMethod::Mailto {
+                recipients,
+                subject,
+                body,
+            })
+        } else if scheme.eq_ignore_ascii_case("https") {
+            Url::parse(uri)
+                .ok()
+                .filter(|url| url.host_str().is_some())
+                .map(|url| NotifyMethod::Https { url: url.into() })
+        } else if scheme.eq_ignore_ascii_case("xmpp") {
+            let jid = decode(value.split_once('?').map_or(value, |(jid, _)| jid))
+                .trim_start_matches('/')
+                .to_lowercase();
+            jid.contains('@').then_some(NotifyMethod::Xmpp { jid })
+        } else {
+            None
+        }
+    }
+}
+
+impl Notification<'_> {
+    fn importance(&self) -> &'static str {
+        match self.importance {
+            Importance::High => "high",
+            Importance::Normal => "normal",
+            Importance::Low => "low",
+        }
+    }
+
+    fn text(&self) -> String {
+        if !self.message.is_empty() {
+            self.message.to_string()
+        } else if let Some(subject) = self.subject {
+            format!("{}: {}", self.envelope_from, subject)
+        } else {
+            format!("New message from {}", self.envelope_from)
+        }
+    }
+
+    pub fn build_mailto(
+        &self,
+        sender: &str,
+        recipients: &[String],
+        method: &NotifyMethod,
+    ) -> Vec<u8> {
+        let (subject, body) = match method {
+            NotifyMethod::Mailto { subject, body, .. } => (subject.clone(), body.clone()),
+            _ => (None, None),
+        };
+        let text = self.text();
+
+        let mut builder = MessageBuilder::new()
+            .from(Address::Address(EmailAddress {
+                name: None,
+                email: sender.into(),
+            }))
+            .header(
+                "To",
+                HeaderType::Address(Address::List(
+                    recipients
+                        .iter()
+                        .map(|to| {
+                            Address::Address(EmailAddress {
+                                name: None,
+                                email: to.as_str().into(),
+                            })
+                        })
+                        .collect(),
+                )),
+            )
+            .header("Auto-Submitted", HeaderType::Text("auto-notified".into()))
+            .header("Importance", HeaderType::Text(self.importance().into()))
+            .header(
+                "X-Priority",
+                HeaderType::Text(
+                    match self.importance {
+                        Importance::High => "1",
+                        Importance::Normal => "3",
+                        Importance::Low => "5",
+                    }
+                    .into(),
+                ),
+            )
+            .subject(subject.unwrap_or_else(|| text.clone()))
+            .text_body(body.unwrap_or(text));
+
+        if let Some(from) = self.from {
+            builder = builder.reply_to(Address::Address(EmailAddress {
+                name: None,
+                email: from.into(),
+            }));
+        }
+
+        builder.write_to_vec().unwrap_or_default()
+    }
+}
+
+impl Server {
+    pub async fn send_sieve_notification(
+        &self,
+        method: &NotifyMethod,
+        notification: &Notification<'_>,
+    ) -> trc::Result<()> {
+        let config = &self.core.sieve.notify;
+
+        let (url, body, auth) = match method {
+            NotifyMethod::Https { url } => {
+                if !config.allowed_hosts.is_empty()
+                    && !Url::parse(url)
+                        .ok()
+                        .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+                        .is_some_and(|host| config.allowed_hosts.contains(&host))
+                {
+                    return Err(trc::SieveEvent::NotifyError
+                        .into_err()
+                        .details("Webhook host is not allowed")
+                        .ctx(trc::Key::Url, url.to_string()));
+                }
+
+                let text = notification.text();
+                let body = serde_json::to_string(&WebhookPayload {
+                    method: notification.method,
+                    from: notification.from,
+                    importance: notification.importance(),
+                    options: notification
+                        .options
+                        .iter()
+                        .map(|option| option.split_once('=').unwrap_or((option.as_str(), "")))
+                        .collect(),
+                    message: &text,
+                    envelope: WebhookEnvelope {
+                        from: notification.envelope_from,
+                        to: notification.envelope_to,
+                    },
+                    subject: notification.subject,
+                })
+                .unwrap_or_default();
+
+                (url.as_str(), body, None)
+            }
+            NotifyMethod::Xmpp { jid } => {
+                let Some(url) = &config.xmpp_url else {
+                    return Err(trc::SieveEvent::NotSupported
+                        .into_err()
+                        .details("No XMPP gateway configured"));
+                };
+
+                let text = notification.text();
+                let body = serde_json::to_string(&XmppPayload {
+                    to: jid,
+                    from: notification.envelope_to,
+                    body: &text,
+                    importance: notification.importance(),
+                })
+                .unwrap_or_default();
+
+                (url.as_str(), body, config.xmpp_auth.as_deref())
+            }
+            NotifyMethod::Mailto { .. } => {
+                return Err(trc::SieveEvent::UnexpectedError
+                    .into_err()
+                    .details("Mailto notifications are delivered as messages"));
+            }
+        };
+
+        let mut request = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(Policy::none())
+            .build()
+            .map_err(|err| {
+                trc::SieveEvent::NotifyError
+                    .into_err()
+                    .reason(err)
+                    .details("Failed to build request")
+            })?
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(body);
+        if let Some(auth) = auth {
+            request = request.header("Authorization", auth);
+        }
+
+        let response = request.send().await.map_err(|err| {
+            trc::SieveEvent::NotifyError
+                .into_err()
+                .reason(err)
+                .details("Failed to send notification")
+                .ctx(trc::Key::Url, url.to_string())
+        })?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(trc::SieveEvent::NotifyError
+                .into_err()
+                .details("Notification endpoint returned an error")
+                .ctx(trc::Key::Url, url.to_string())
+                .ctx(trc::Key::Code, response.status().as_u16()))
+        }
+    }
+}
+
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NotifyMethod;
+
+    #[test]
+    fn parse_notify_methods() {
+        assert_eq!(
+            NotifyMethod::parse(
+                "mailto:Jane@example.org,bill%40example.org?subject=Hi%20there&body=New%20mail"
+            ),
+            Some(NotifyMethod::Mailto {
+                recipients: vec!["jane@example.org".into(), "bill@example.org".into()],
+                subject: Some("Hi there".into()),
+                body: Some("New mail".into()),
+            })
+        );
+        assert_eq!(
+            NotifyMethod::parse("https://hooks.example.org/notify?id=1"),
+            Some(NotifyMethod::Https {
+                url: "https://hooks.example.org/notify?id=1".into()
+            })
+        );
+        assert_eq!(
+            NotifyMethod::parse("xmpp:romeo@example.org?message;subject=Hi"),
+            Some(NotifyMethod::Xmpp {
+                jid: "romeo@example.org".into()
+            })
+        );
+        assert_eq!(NotifyMethod::parse("mailto:?subject=Hi"), None);
+        assert_eq!(NotifyMethod::parse("tel:+1234567"), None);
+    }
+}
@@ -0,0 +1,206 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+pub mod office;
+pub mod pdf;
+pub mod remote;
+
+use std::{sync::Arc, time::Duration};
+
+use office::{ArchiveExtractor, OfficeExtractor};
+use pdf::PdfExtractor;
+use remote::HttpExtractor;
+use trc::MessageIngestEvent;
+use utils::config::Config;
+
+#[derive(Clone, Default)]
+pub struct TextExtractors {
+    pub extractors: Arc<Vec<Extractor>>,
+    pub limits: ExtractLimits,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtractLimits {
+    pub max_size: usize,
+    pub max_text: usize,
+    pub max_entries: usize,
+}
+
+pub enum Extractor {
+    Pdf(PdfExtractor),
+    Office(OfficeExtractor),
+    Archive(ArchiveExtractor),
+    Http(HttpExtractor),
+}
+
+pub struct ExtractInput<'x> {
+    pub content_type: Option<&'x str>,
+    pub file_name: Option<&'x str>,
+    pub contents: &'x [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Pdf,
+    Docx,
+    Xlsx,
+    Pptx,
+    OpenDocument,
+    Zip,
+    Text,
+    Other,
+}
+
+pub trait TextExtractor: Sync + Send {
+    fn extract(
+        &self,
+        input: &ExtractInput<'_>,
+        limits: &ExtractLimits,
+    ) -> impl Future<Output = Result<Option<String>, String>> + Send;
+}
+
+impl TextExtractor for Extractor {
+    async fn extract(
+        &self,
+        input: &ExtractInput<'_>,
+        limits: &ExtractLimits,
+    ) -> Result<Option<String>, String> {
+        match self {
+            Extractor::Pdf(extractor) => extractor.extract(input, limits).await,
+            Extractor::Office(extractor) => extractor.extract(input, limits).await,
+            Extractor::Archive(extractor) => extractor.extract(input, limits).await,
+            Extractor::Http(extractor) => extractor.extract(input, limits).await,
+        }
+    }
+}
+
+impl TextExtractors {
+    pub fn parse(config: &mut Config) -> Self {
+        if !config
+            .property("storage.full-text.extract.enable")
+            .unwrap_or(false)
+        {
+            return TextExtractors::default();
+        }
+
+        let mut extractors = Vec::new();
+        if config
+            .property("storage.full-text.extract.builtin")
+            .unwrap_or(true)
+        {
+            extractors.push(Extractor::Pdf(PdfExtractor));
+            extractors.push(Extractor::Office(OfficeExtractor));
+            extractors.push(Extractor::Archive(ArchiveExtractor));
+        }
+        if let Some(url) = config
+            .value("storage.full-text.extract.http.url")
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+        {
+            extractors.push(Extractor::Http(HttpExtractor {
+                url,
+                timeout: config
+                    .property_or_default::<Duration>("storage.full-text.extract.http.timeout", "30s")
+                    .unwrap_or(Duration::from_secs(30)),
+                content_types: config
+                    .values("storage.full-text.extract.http.content-types")
+                    .map(|(_, v)| v.to_ascii_lowercase())
+                    .collect(),
+            }));
+        }
+
+        TextExtractors {
+            extractors: Arc::new(extractors),
+            limits: ExtractLimits {
+                max_size: config
+                    .property("storage.full-text.extract.max-size")
+                    .unwrap_or(10 * 1024 * 1024),
+                max_text: config
+                    .property("storage.full-text.extract.max-text")
+                    .unwrap_or(1024 * 1024),
+                max_entries: config
+                    .property("storage.full-text.extract.archive.max-entries")
+                    .unwrap_or(100),
+            },
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.extractors.is_empty()
+    }
+
+    pub async fn extract(&self, input: ExtractInput<'_>) -> Option<String> {
+        if input.contents.is_empty() || input.contents.len() > self.limits.max_size {
+            return None;
+        }
+
+        for extractor in self.extractors.iter() {
+            match extractor.extract(&input, &self.limits).await {
+                Ok(Some(mut text)) => {
+                    truncate_text(&mut text, self.limits.max_text);
+                    return Some(text);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    trc::event!(
+                        MessageIngest(MessageIngestEvent::Error),
+                        Details = "Failed to extract attachment text",
+                        Reason = err,
+                    );
+                    return None;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl ExtractInput<'_> {
+    pub fn file_type(&self) -> FileType {
+        let extension = self
+            .file_name
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        let by_type = self.content_type.map(|ct| ct.to_ascii_lowercase());
+
+        match (by_type.as_deref(), extension.as_deref()) {
+            (Some("application/pdf"), _) | (_, Some("pdf")) => FileType::Pdf,
+            (Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"), _)
+            | (_, Some("docx")) => FileType::Docx,
+            (Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"), _)
+            | (_, Some("xlsx")) => FileType::Xlsx,
+            (Some("application/vnd.openxmlformats-officedocument.presentationml.presentation"), _)
+            | (_, Some("pptx")) => FileType::Pptx,
+            (Some(ct), _) if ct.starts_with("application/vnd.oasis.opendocument.") => {
+                FileType::OpenDocument
+            }
+            (_, Some("odt" | "ods" | "odp")) => FileType::OpenDocument,
+            (Some("application/zip" | "application/x-zip-compressed"), _) | (_, Some("zip")) => {
+                FileType::Zip
+            }
+            (Some("text/plain" | "text/csv" | "text/markdown"), _)
+            | (_, Some("txt" | "csv" | "md" | "log")) => FileType::Text,
+            _ => {
+                if self.contents.starts_with(b"%PDF-") {
+                    FileType::Pdf
+                } else {
+                    FileType::Other
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn truncate_text(text: &mut String, max_len: usize) {
+    if text.len() > max_len {
+        let mut pos = max_len;
+        while !text.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        text.truncate(pos);
+    }
+}
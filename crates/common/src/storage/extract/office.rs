@@ -0,0 +1,219 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::io::{Cursor, Read};
+
+use zip::ZipArchive;
+
+use super::{ExtractInput, ExtractLimits, FileType, TextExtractor, truncate_text};
+
+pub struct OfficeExtractor;
+
+pub struct ArchiveExtractor;
+
+impl TextExtractor for OfficeExtractor {
+    async fn extract(
+        &self,
+        input: &ExtractInput<'_>,
+        limits: &ExtractLimits,
+    ) -> Result<Option<String>, String> {
+        let file_type = input.file_type();
+        if !matches!(
+            file_type,
+            FileType::Docx | FileType::Xlsx | FileType::Pptx | FileType::OpenDocument
+        ) {
+            return Ok(None);
+        }
+
+        let mut archive = ZipArchive::new(Cursor::new(input.contents))
+            .map_err(|err| format!("Failed to open document: {err}"))?;
+        let mut entries = (0..archive.len())
+            .filter_map(|idx| {
+                let name = archive.name_for_index(idx)?;
+                let is_text = match file_type {
+                    FileType::Docx => matches!(
+                        name,
+                        "word/document.xml" | "word/footnotes.xml" | "word/endnotes.xml"
+                    ),
+                    FileType::Xlsx => name == "xl/sharedStrings.xml",
+                    FileType::Pptx => {
+                        name.starts_with("ppt/slides/slide") && name.ends_with(".xml")
+                    }
+                    _ => name == "content.xml",
+                };
+                is_text.then(|| (idx, name.to_string()))
+            })
+            .collect::<Vec<_>>();
+        // Slides are stored as slide1.xml, slide2.xml, ..., slide10.xml
+        entries.sort_unstable_by_key(|(_, name)| (name.len(), name.clone()));
+
+        let mut text = String::new();
+        for (idx, _) in entries.into_iter().take(limits.max_entries) {
+            let xml = read_entry(&mut archive, idx, limits.max_size)?;
+            xml_to_text(&String::from_utf8_lossy(&xml), &mut text);
+            if text.len() >= limits.max_text {
+                break;
+            }
+        }
+
+        Ok(Some(text).filter(|text| !text.trim().is_empty()))
+    }
+}
+
+impl TextExtractor for ArchiveExtractor {
+    async fn extract(
+        &self,
+        input: &ExtractInput<'_>,
+        limits: &ExtractLimits,
+    ) -> Result<Option<String>, String> {
+        if input.file_type() != FileType::Zip {
+            return Ok(None);
+        }
+
+        let mut archive = ZipArchive::new(Cursor::new(input.contents))
+            .map_err(|err| format!("Failed to open archive: {err}"))?;
+        let mut text = String::new();
+        for idx in 0..archive.len().min(limits.max_entries) {
+            let Some(name) = archive.name_for_index(idx) else {
+                continue;
+            };
+            let entry = ExtractInput {
+                content_type: None,
+                file_name: Some(name),
+                contents: &[],
+            };
+            if entry.file_type() != FileType::Text {
+                continue;
+            }
+            let name = name.to_string();
+
+            // Index the entry name followed by its contents
+            let contents = read_entry(&mut archive, idx, limits.max_size)?;
+            text.push_str(&name);
+            text.push('\n');
+            text.push_str(&String::from_utf8_lossy(&contents));
+            text.push('\n');
+            if text.len() >= limits.max_text {
+                truncate_text(&mut text, limits.max_text);
+                break;
+            }
+        }
+
+        Ok(Some(text).filter(|text| !text.trim().is_empty()))
+    }
+}
+
+fn read_entry(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    idx: usize,
+    max_size: usize,
+) -> Result<Vec<u8>, String> {
+    let file = archive
+        .by_index(idx)
+        .map_err(|err| format!("Failed to read archive entry: {err}"))?;
+    let mut contents = Vec::with_capacity((file.size() as usize).min(max_size));
+    file.take(max_size as u64)
+        .read_to_end(&mut contents)
+        .map_err(|err| format!("Failed to decompress archive entry: {err}"))?;
+    Ok(contents)
+}
+
+// Collects the text nodes of an Office Open XML or OpenDocument part, paragraphs,
+// table cells and shared strings are separated by line breaks.
+pub(crate) fn xml_to_text(xml: &str, text: &mut String) {
+    let mut chars = xml.char_indices();
+    let mut text_start = None;
+
+    while let Some((pos, ch)) = chars.next() {
+        match ch {
+            '<' => {
+                if let Some(start) = text_start.take() {
+                    unescape_xml(&xml[start..pos], text);
+                }
+                let tag_start = pos + 1;
+                let mut tag_end = xml.len();
+                for (pos, ch) in chars.by_ref() {
+                    if ch == '>' {
+                        tag_end = pos;
+                        break;
+                    }
+                }
+                let tag = &xml[tag_start..tag_end];
+                if let Some(name) = tag.strip_prefix('/') {
+                    let local_name = name.rsplit_once(':').map_or(name, |(_, name)| name);
+                    if matches!(local_name, "p" | "si" | "tc" | "h" | "table-cell")
+                        && !text.ends_with('\n')
+                    {
+                        text.push('\n');
+                    }
+                } else if matches!(tag.trim_end_matches('/'), "w:tab" | "text:tab" | "text:s") {
+                    text.push(' ');
+                }
+            }
+            _ => {
+                if text_start.is_none() {
+                    text_start = Some(pos);
+                }
+            }
+        }
+    }
+
+    if let Some(start) = text_start {
+        unescape_xml(&xml[start..], text);
+    }
+}
+
+fn unescape_xml(value: &str, text: &mut String) {
+    let mut value = value;
+    while let Some(pos) = value.find('&') {
+        text.push_str(&value[..pos]);
+        value = &value[pos..];
+        if let Some(end) = value.find(';').filter(|end| *end <= 10) {
+            let entity = &value[1..end];
+            match entity {
+                "amp" => text.push('&'),
+                "lt" => text.push('<'),
+                "gt" => text.push('>'),
+                "quot" => text.push('"'),
+                "apos" => text.push('\''),
+                _ => {
+                    if let Some(ch) = entity
+                        .strip_prefix("#x")
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                        .and_then(char::from_u32)
+                    {
+                        text.push(ch);
+                    }
+                }
+            }
+            value = &value[end + 1..];
+        } else {
+            text.push('&');
+            value = &value[1..];
+        }
+    }
+    text.push_str(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::xml_to_text;
+
+    #[test]
+    fn office_xml_text() {
+        let mut text = String::new();
+        xml_to_text(
+            concat!(
+                "<w:document><w:body><w:p><w:r><w:t>Tom &amp; Jerry</w:t></w:r>",
+                "<w:r><w:tab/><w:t xml:space=\"preserve\">caf&#233;</w:t></w:r></w:p>",
+                "<w:p><w:r><w:t>Second</w:t></w:r></w:p></w:body></w:document>"
+            ),
+            &mut text,
+        );
+        assert_eq!(text, "Tom & Jerry café\nSecond\n");
+    }
+}
@@ -0,0 +1,302 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use super::{ExtractInput, ExtractLimits, FileType, TextExtractor};
+
+// Extracts the text layer of a PDF document by decoding its content streams and
+// collecting the strings shown by the text operators. Scanned documents and text
+// encoded with embedded CID fonts are not supported.
+pub struct PdfExtractor;
+
+impl TextExtractor for PdfExtractor {
+    async fn extract(
+        &self,
+        input: &ExtractInput<'_>,
+        limits: &ExtractLimits,
+    ) -> Result<Option<String>, String> {
+        if input.file_type() != FileType::Pdf {
+            return Ok(None);
+        }
+        if !input.contents.starts_with(b"%PDF-") {
+            return Err("Invalid PDF header".to_string());
+        }
+
+        let mut text = String::new();
+        for (dictionary, stream) in PdfStreams::new(input.contents) {
+            let contents = if contains(dictionary, b"/FlateDecode") {
+                let mut contents = Vec::new();
+                if ZlibDecoder::new(stream)
+                    .take(limits.max_size as u64)
+                    .read_to_end(&mut contents)
+                    .is_err()
+                {
+                    continue;
+                }
+                contents
+            } else if !contains(dictionary, b"/Filter") {
+                stream.to_vec()
+            } else {
+                continue;
+            };
+
+            // Skip images, fonts and other non-content streams
+            if !contains(dictionary, b"/Subtype")
+                && !contains(dictionary, b"/Length1")
+                && contains(&contents, b"BT")
+            {
+                content_stream_text(&contents, &mut text);
+                if text.len() >= limits.max_text {
+                    break;
+                }
+            }
+        }
+
+        Ok(Some(text).filter(|text| !text.trim().is_empty()))
+    }
+}
+
+struct PdfStreams<'x> {
+    data: &'x [u8],
+    pos: usize,
+}
+
+impl<'x> PdfStreams<'x> {
+    fn new(data: &'x [u8]) -> Self {
+        PdfStreams { data, pos: 0 }
+    }
+}
+
+impl<'x> Iterator for PdfStreams<'x> {
+    type Item = (&'x [u8], &'x [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.pos + find(&self.data[self.pos..], b"stream")?;
+            let mut stream_start = start + 6;
+            self.pos = stream_start;
+
+            // Skip "endstream" and keywords that are not followed by an end of line
+            if start >= 3 && &self.data[start - 3..start] == b"end" {
+                continue;
+            }
+            match self.data.get(stream_start) {
+                Some(b'\r') if self.data.get(stream_start + 1) == Some(&b'\n') => {
+                    stream_start += 2;
+                }
+                Some(b'\n' | b'\r') => {
+                    stream_start += 1;
+                }
+                _ => continue,
+            }
+
+            let stream_end =
+                stream_start + find(&self.data[stream_start..], b"endstream").unwrap_or(0);
+            if stream_end <= stream_start {
+                return None;
+            }
+            self.pos = stream_end + 9;
+
+            let dictionary_start = rfind(&self.data[..start], b"obj").unwrap_or(start);
+            return Some((
+                &self.data[dictionary_start..start],
+                &self.data[stream_start..stream_end],
+            ));
+        }
+    }
+}
+
+fn content_stream_text(contents: &[u8], text: &mut String) {
+    let mut pos = 0;
+    let mut in_text = false;
+    let mut line = String::new();
+
+    while pos < contents.len() {
+        match contents[pos] {
+            b'(' if in_text => {
+                let (string, next_pos) = literal_string(contents, pos + 1);
+                decode_string(&string, &mut line);
+                pos = next_pos;
+                continue;
+            }
+            b'<' if in_text && contents.get(pos + 1) != Some(&b'<') => {
+                let end = contents[pos..]
+                    .iter()
+                    .position(|&ch| ch == b'>')
+                    .map_or(contents.len(), |end| pos + end);
+                let string = hex_string(&contents[pos + 1..end]);
+                // Strings that are not printable are usually CID font glyphs
+                if string.first().is_some_and(|&ch| ch == 0xfe)
+                    || string.iter().all(|&ch| ch.is_ascii_graphic() || ch == b' ')
+                {
+                    decode_string(&string, &mut line);
+                }
+                pos = end + 1;
+                continue;
+            }
+            b'%' if !in_text => {
+                while pos < contents.len() && !matches!(contents[pos], b'\r' | b'\n') {
+                    pos += 1;
+                }
+                continue;
+            }
+            ch if ch.is_ascii_alphabetic() || ch == b'\'' || ch == b'"' || ch == b'*' => {
+                let start = pos;
+                while pos < contents.len()
+                    && (contents[pos].is_ascii_alphabetic() || b"'\"*".contains(&contents[pos]))
+                {
+                    pos += 1;
+                }
+                match &contents[start..pos] {
+                    b"BT" => {
+                        in_text = true;
+                    }
+                    b"ET" => {
+                        in_text = false;
+                        flush_line(&mut line, text);
+                    }
+                    b"Td" | b"TD" | b"T*" | b"'" | b"\"" | b"Tm" => {
+                        flush_line(&mut line, text);
+                    }
+                    b"TJ" | b"Tj" => {
+                        if !line.is_empty() && !line.ends_with(' ') {
+                            line.push(' ');
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+
+    flush_line(&mut line, text);
+}
+
+fn flush_line(line: &mut String, text: &mut String) {
+    let trimmed = line.trim();
+    if !trimmed.is_empty() {
+        text.push_str(trimmed);
+        text.push('\n');
+    }
+    line.clear();
+}
+
+fn literal_string(contents: &[u8], mut pos: usize) -> (Vec<u8>, usize) {
+    let mut string = Vec::new();
+    let mut depth = 0;
+
+    while pos < contents.len() {
+        match contents[pos] {
+            b'\\' => {
+                pos += 1;
+                match contents.get(pos) {
+                    Some(b'n') => string.push(b'\n'),
+                    Some(b'r') => string.push(b'\r'),
+                    Some(b't') => string.push(b'\t'),
+                    Some(b'b') => string.push(0x08),
+                    Some(b'f') => string.push(0x0c),
+                    Some(ch @ b'0'..=b'7') => {
+                        let mut value = (ch - b'0') as u32;
+                        for _ in 0..2 {
+                            match contents.get(pos + 1) {
+                                Some(ch @ b'0'..=b'7') => {
+                                    value = value * 8 + (ch - b'0') as u32;
+                                    pos += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        string.push(value as u8);
+                    }
+                    Some(b'\r' | b'\n') => {}
+                    Some(ch) => string.push(*ch),
+                    None => break,
+                }
+            }
+            b'(' => {
+                depth += 1;
+                string.push(b'(');
+            }
+            b')' => {
+                if depth == 0 {
+                    return (string, pos + 1);
+                }
+                depth -= 1;
+                string.push(b')');
+            }
+            ch => string.push(ch),
+        }
+        pos += 1;
+    }
+
+    (string, pos)
+}
+
+fn hex_string(contents: &[u8]) -> Vec<u8> {
+    let digits = contents
+        .iter()
+        .filter_map(|ch| (*ch as char).to_digit(16))
+        .collect::<Vec<_>>();
+    digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4 | pair.get(1).copied().unwrap_or(0)) as u8)
+        .collect()
+}
+
+fn decode_string(string: &[u8], text: &mut String) {
+    if let Some(utf16) = string.strip_prefix(&[0xfe, 0xff]) {
+        let units = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        text.extend(char::decode_utf16(units).filter_map(|ch| ch.ok()));
+    } else {
+        // PDFDocEncoding matches Latin-1 for printable characters
+        text.extend(
+            string
+                .iter()
+                .filter(|&&ch| ch >= 0x20 || ch == b'\t')
+                .map(|&ch| ch as char),
+        );
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::content_stream_text;
+
+    #[test]
+    fn pdf_content_stream_text() {
+        let mut text = String::new();
+        content_stream_text(
+            b"BT /F1 12 Tf 72 712 Td (Quarterly \\(draft\\) report) Tj 0 -14 Td [(Re) -20 (venue)] TJ ET\nBT <48656c6c6f> Tj ET",
+            &mut text,
+        );
+        assert_eq!(text, "Quarterly (draft) report\nRevenue\nHello\n");
+    }
+}
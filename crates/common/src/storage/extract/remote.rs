@@ -0,0 +1,65 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+
+use super::{ExtractInput, ExtractLimits, TextExtractor};
+
+// Sends the attachment to an external extraction service, such as Apache Tika,
+// which replies with the plain text contents of the document.
+pub struct HttpExtractor {
+    pub url: String,
+    pub timeout: Duration,
+    pub content_types: Vec<String>,
+}
+
+impl TextExtractor for HttpExtractor {
+    async fn extract(
+        &self,
+        input: &ExtractInput<'_>,
+        _: &ExtractLimits,
+    ) -> Result<Option<String>, String> {
+        let content_type = input
+            .content_type
+            .unwrap_or("application/octet-stream")
+            .to_ascii_lowercase();
+        if !self.content_types.is_empty()
+            && !self
+                .content_types
+                .iter()
+                .any(|ct| ct == &content_type || ct == "*")
+        {
+            return Ok(None);
+        }
+
+        let response = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|err| format!("Failed to create HTTP client: {err}"))?
+            .put(&self.url)
+            .header(CONTENT_TYPE, content_type)
+            .header(ACCEPT, "text/plain")
+            .body(input.contents.to_vec())
+            .send()
+            .await
+            .map_err(|err| format!("Failed to send attachment to {}: {err}", self.url))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to extract attachment text using {}: HTTP status {}",
+                self.url,
+                response.status()
+            ));
+        }
+
+        response
+            .text()
+            .await
+            .map(|text| Some(text).filter(|text| !text.trim().is_empty()))
+            .map_err(|err| format!("Failed to read response body from {}: {err}", self.url))
+    }
+}
@@ -14,7 +14,10 @@ use rkyv::{
 use std::{borrow::Cow, fmt::Debug};
 use store::{
     Serialize, SerializeInfallible,
-    write::{Archive, Archiver, BatchBuilder, BlobOp, DirectoryClass, IntoOperations, TagValue},
+    write::{
+        Archive, Archiver, BatchBuilder, BlobOp, DirectoryClass, IntoOperations, TagValue,
+        ValueClass,
+    },
 };
 use utils::BlobHash;
 
@@ -40,6 +43,11 @@ pub enum IndexValue<'x> {
     Quota {
         used: u32,
     },
+    Counter {
+        collection: u8,
+        field: u8,
+        ids: Vec<u32>,
+    },
     LogContainer {
         sync_collection: u8,
     },
@@ -402,6 +410,13 @@ fn build_index(batch: &mut BatchBuilder, item: IndexValue<'_>, tenant_id: Option
                 batch.add(DirectoryClass::UsedQuota(tenant_id), value);
             }
         }
+        IndexValue::Counter {
+            collection,
+            field,
+            ids,
+        } => {
+            add_counters(batch, collection, field, ids, if set { 1 } else { -1 });
+        }
         IndexValue::LogItem {
             sync_collection,
             prefix,
@@ -554,6 +569,27 @@ fn merge_index(
                 batch.add(DirectoryClass::UsedQuota(tenant_id), value);
             }
         }
+        (
+            IndexValue::Counter {
+                collection,
+                field,
+                ids: old_ids,
+            },
+            IndexValue::Counter { ids: new_ids, .. },
+        ) => {
+            let removed_ids = old_ids
+                .iter()
+                .filter(|id| !new_ids.contains(id))
+                .copied()
+                .collect::<Vec<_>>();
+            let added_ids = new_ids
+                .iter()
+                .filter(|id| !old_ids.contains(id))
+                .copied()
+                .collect::<Vec<_>>();
+            add_counters(batch, collection, field, removed_ids, -1);
+            add_counters(batch, collection, field, added_ids, 1);
+        }
         (
             IndexValue::LogItem {
                 sync_collection,
@@ -590,6 +626,26 @@ fn merge_index(
     Ok(())
 }
 
+// Counters belong to other documents, so the batch is pointed at each one of
+// them and then restored to the document being indexed.
+fn add_counters(batch: &mut BatchBuilder, collection: u8, field: u8, ids: Vec<u32>, value: i64) {
+    if let (false, Some(current_collection), Some(current_document_id)) = (
+        ids.is_empty(),
+        batch.last_collection(),
+        batch.last_document_id(),
+    ) {
+        batch.with_collection(collection);
+        for document_id in ids {
+            batch
+                .update_document(document_id)
+                .add(ValueClass::Property(field), value);
+        }
+        batch
+            .with_collection(current_collection)
+            .update_document(current_document_id);
+    }
+}
+
 impl IndexableObject for () {
     fn index_values(&self) -> impl Iterator<Item = IndexValue<'_>> {
         std::iter::empty()
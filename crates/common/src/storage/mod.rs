@@ -5,5 +5,7 @@
  */
 
 pub mod blob;
+pub mod extract;
 pub mod index;
+pub mod partition;
 pub mod state;
@@ -0,0 +1,162 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use store::{
+    ValueKey,
+    write::{ValueClass, now},
+};
+use trc::StoreEvent;
+
+use crate::{
+    Server,
+    config::storage::StoragePartition,
+    expr::{V_RECIPIENT, V_RECIPIENT_DOMAIN, Variable, functions::ResolveVariable},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct PartitionHealth {
+    pub failures: u32,
+    pub degraded: bool,
+    pub last_check: u64,
+    pub last_error: Option<String>,
+    pub latency: u64,
+}
+
+struct PartitionRecipient<'x>(&'x str);
+
+impl Server {
+    pub async fn check_partitions(&self) {
+        let config = &self.core.storage.partitions;
+
+        for partition in &config.partitions {
+            let time = Instant::now();
+            let result = match tokio::time::timeout(
+                config.check_timeout,
+                probe_partition(partition),
+            )
+            .await
+            {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(err)) => Err(err.to_string()),
+                Err(_) => Err("Health check timed out".to_string()),
+            };
+            let latency = time.elapsed().as_millis() as u64;
+
+            let mut health_map = self.inner.data.partition_health.write();
+            let health = health_map.entry(partition.id.clone()).or_default();
+            health.last_check = now();
+            health.latency = latency;
+
+            match result {
+                Ok(()) => {
+                    if health.degraded {
+                        trc::event!(
+                            Store(StoreEvent::PartitionRecovered),
+                            Id = partition.id.clone(),
+                            Elapsed = time.elapsed(),
+                        );
+                    }
+                    health.failures = 0;
+                    health.degraded = false;
+                    health.last_error = None;
+                }
+                Err(err) => {
+                    health.failures += 1;
+                    if !health.degraded && health.failures >= config.failure_threshold {
+                        health.degraded = true;
+                        trc::event!(
+                            Store(StoreEvent::PartitionDegraded),
+                            Id = partition.id.clone(),
+                            Total = health.failures,
+                            Reason = err.clone(),
+                        );
+                    }
+                    health.last_error = Some(err);
+                }
+            }
+        }
+    }
+
+    pub async fn degraded_partition(&self, rcpt: &str, session_id: u64) -> Option<String> {
+        let config = &self.core.storage.partitions;
+        if !config.is_enabled() {
+            return None;
+        }
+
+        let partition_id = self
+            .eval_if::<String, _>(
+                config.assign.as_ref()?,
+                &PartitionRecipient(rcpt),
+                session_id,
+            )
+            .await?;
+
+        if self
+            .inner
+            .data
+            .partition_health
+            .read()
+            .get(&partition_id)
+            .is_some_and(|health| health.degraded)
+        {
+            Some(partition_id)
+        } else {
+            None
+        }
+    }
+
+    pub fn partition_status(&self) -> Vec<(String, PartitionHealth)> {
+        let health_map = self.inner.data.partition_health.read();
+        self.core
+            .storage
+            .partitions
+            .partitions
+            .iter()
+            .map(|partition| {
+                (
+                    partition.id.clone(),
+                    health_map.get(&partition.id).cloned().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+}
+
+async fn probe_partition(partition: &StoragePartition) -> trc::Result<()> {
+    if let Some(store) = &partition.store {
+        store
+            .get_value::<String>(ValueKey::from(ValueClass::Config(
+                b"storage.partition.probe".to_vec(),
+            )))
+            .await?;
+    }
+    if let Some(blob) = &partition.blob {
+        blob.get_blob(b"partition-probe", 0..1).await?;
+    }
+
+    Ok(())
+}
+
+impl ResolveVariable for PartitionRecipient<'_> {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        match variable {
+            V_RECIPIENT => self.0.into(),
+            V_RECIPIENT_DOMAIN => self
+                .0
+                .rsplit_once('@')
+                .map(|(_, domain)| domain)
+                .unwrap_or_default()
+                .into(),
+            _ => Variable::default(),
+        }
+    }
+
+    fn resolve_global(&self, _: &str) -> Variable<'_> {
+        Variable::Integer(0)
+    }
+}
@@ -111,6 +111,8 @@ impl MetricsStore for Store {
                 EventType::Security(SecurityEvent::AbuseBan),
                 EventType::Security(SecurityEvent::LoiterBan),
                 EventType::Security(SecurityEvent::IpBlocked),
+                EventType::Security(SecurityEvent::AuthenticationLockout),
+                EventType::Security(SecurityEvent::AccountAnomaly),
                 EventType::IncomingReport(IncomingReportEvent::DmarcReport),
                 EventType::IncomingReport(IncomingReportEvent::DmarcReportWithWarnings),
                 EventType::IncomingReport(IncomingReportEvent::TlsReport),
@@ -160,6 +162,8 @@ impl MetricsStore for Store {
                         | MetricType::DeliveryTotalTime
                         | MetricType::DeliveryTime
                         | MetricType::DnsLookupTime
+                        | MetricType::MtaHookTime
+                        | MetricType::MilterTime
                 ) {
                     let history = history.histograms.entry(histogram_id).or_default();
                     let sum = histogram.sum();
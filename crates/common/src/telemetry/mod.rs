@@ -8,6 +8,7 @@ pub mod metrics;
 pub mod tracers;
 pub mod webhooks;
 
+use tracers::event_bus::spawn_event_bus_tracer;
 use tracers::log::spawn_log_tracer;
 use tracers::otel::spawn_otel_tracer;
 use tracers::stdout::spawn_console_tracer;
@@ -103,6 +104,9 @@ impl TelemetrySubscriberType {
             }
             TelemetrySubscriberType::LogTracer(settings) => spawn_log_tracer(builder, settings),
             TelemetrySubscriberType::Webhook(settings) => spawn_webhook_tracer(builder, settings),
+            TelemetrySubscriberType::EventBus(settings) => {
+                spawn_event_bus_tracer(builder, settings)
+            }
             TelemetrySubscriberType::OtelTracer(settings) => spawn_otel_tracer(builder, settings),
             #[cfg(unix)]
             TelemetrySubscriberType::JournalTracer(subscriber) => {
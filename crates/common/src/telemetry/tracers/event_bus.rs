@@ -0,0 +1,191 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use crate::config::telemetry::EventBusTracer;
+use store::PubSubStore;
+use tokio::sync::watch;
+use trc::{
+    Collector, Event, EventType, Key, TelemetryEvent, Value, ipc::subscriber::SubscriberBuilder,
+    serializers::json::JsonEventSerializer,
+};
+
+pub(crate) fn spawn_event_bus_tracer(builder: SubscriberBuilder, settings: EventBusTracer) {
+    let (_, mut rx) = builder.register();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    if settings.consume {
+        spawn_event_bus_consumer(
+            settings.store.clone(),
+            // Topics are only leaked when the event bus is (re)created
+            Box::leak(settings.topic.clone().into_boxed_str()),
+            settings.node_id,
+            shutdown_rx,
+        );
+    }
+
+    tokio::spawn(async move {
+        while let Some(events) = rx.recv().await {
+            let total = events.len();
+
+            for (pos, event) in events.into_iter().enumerate() {
+                // Events received from other nodes are not published again
+                if event.keys.iter().any(|(key, _)| *key == Key::NodeId) {
+                    continue;
+                }
+
+                // Each event is published as a separate message
+                let message = match serde_json::to_value(
+                    JsonEventSerializer::new(event).with_id().with_spans(),
+                )
+                .and_then(|mut message| {
+                    message["node"] = settings.node_id.into();
+                    serde_json::to_vec(&message)
+                }) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        trc::event!(
+                            Telemetry(TelemetryEvent::EventBusError),
+                            Details = "Failed to serialize event",
+                            Reason = err.to_string()
+                        );
+                        continue;
+                    }
+                };
+
+                if let Err(err) = settings.store.publish(&settings.topic, message).await {
+                    trc::event!(
+                        Telemetry(TelemetryEvent::EventBusError),
+                        Details = "Failed to publish events",
+                        Total = total - pos,
+                        CausedBy = err
+                    );
+                    break;
+                }
+            }
+        }
+
+        // Stop the consumer once the event bus is removed
+        let _ = shutdown_tx.send(true);
+    });
+}
+
+fn spawn_event_bus_consumer(
+    store: PubSubStore,
+    topic: &'static str,
+    node_id: u64,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    tokio::spawn(async move {
+        let mut retry_count = 0;
+
+        loop {
+            let mut stream = match store.subscribe(topic).await {
+                Ok(stream) => {
+                    retry_count = 0;
+                    stream
+                }
+                Err(err) => {
+                    trc::event!(
+                        Telemetry(TelemetryEvent::EventBusError),
+                        Details = "Failed to subscribe to event bus",
+                        CausedBy = err
+                    );
+
+                    match tokio::time::timeout(
+                        Duration::from_secs(1 << std::cmp::min(retry_count, 6)),
+                        shutdown_rx.changed(),
+                    )
+                    .await
+                    {
+                        Ok(_) => break,
+                        Err(_) => {
+                            retry_count += 1;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            loop {
+                tokio::select! {
+                    message = stream.next() => {
+                        let Some(message) = message else {
+                            trc::event!(
+                                Telemetry(TelemetryEvent::EventBusError),
+                                Details = "Event bus subscription closed",
+                            );
+                            break;
+                        };
+
+                        // Dispatch events published by other nodes to the local subscribers
+                        match parse_event(message.payload()) {
+                            Some((from_node_id, _)) if from_node_id == node_id => {}
+                            Some((_, event)) => {
+                                if Collector::has_interest(event.inner.id()) {
+                                    event.send();
+                                }
+                            }
+                            None => {
+                                trc::event!(
+                                    Telemetry(TelemetryEvent::EventBusError),
+                                    Details = "Invalid event received",
+                                    Contents = String::from_utf8_lossy(
+                                        message.payload()
+                                    ).into_owned(),
+                                );
+                            }
+                        }
+                    },
+                    _ = shutdown_rx.changed() => {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn parse_event(message: &[u8]) -> Option<(u64, Event<EventType>)> {
+    let message = serde_json::from_slice::<serde_json::Value>(message).ok()?;
+    let node_id = message.get("node")?.as_u64()?;
+    let typ = EventType::try_parse(message.get("type")?.as_str()?)?;
+    let mut keys = vec![(Key::NodeId, Value::UInt(node_id))];
+    for (key, value) in message.get("data")?.as_object()? {
+        // Keys are published in camel case but parsed in kebab case
+        let mut name = String::with_capacity(key.len() + 2);
+        for ch in key.chars() {
+            if ch.is_ascii_uppercase() {
+                name.push('-');
+            }
+            name.push(ch.to_ascii_lowercase());
+        }
+        // Span ids are only meaningful on the node that created them
+        if let (Some(key), Some(value)) = (Key::try_parse(&name), parse_value(value)) {
+            if !matches!(key, Key::NodeId | Key::SpanId) {
+                keys.push((key, value));
+            }
+        }
+    }
+
+    Some((node_id, Event::with_keys(typ, keys)))
+}
+
+fn parse_value(value: &serde_json::Value) -> Option<Value> {
+    match value {
+        serde_json::Value::String(value) => Some(Value::String(value.as_str().into())),
+        serde_json::Value::Number(value) => value
+            .as_u64()
+            .map(Value::UInt)
+            .or_else(|| value.as_i64().map(Value::Int))
+            .or_else(|| value.as_f64().map(Value::Float)),
+        serde_json::Value::Bool(value) => Some(Value::Bool(*value)),
+        serde_json::Value::Array(values) => Some(Value::Array(
+            values.iter().filter_map(parse_value).collect(),
+        )),
+        serde_json::Value::Null | serde_json::Value::Object(_) => None,
+    }
+}
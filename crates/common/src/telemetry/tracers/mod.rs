@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod event_bus;
 #[cfg(unix)]
 pub mod journald;
 pub mod log;
@@ -20,16 +20,20 @@ use calcard::{
         Uri,
     },
 };
-use common::{Server, auth::AccessToken};
+use common::{Server, auth::AccessToken, config::groupware::FreeBusyPrivacy};
 use dav_proto::{
     RequestHeaders,
     schema::{
-        property::Rfc1123DateTime,
+        property::{Rfc1123DateTime, TimeRange},
         request::FreeBusyQuery,
         response::{CalCondition, Href, ScheduleResponse, ScheduleResponseItem},
     },
 };
-use groupware::{DestroyArchive, cache::GroupwareCache, calendar::CalendarScheduling};
+use groupware::{
+    DestroyArchive,
+    cache::GroupwareCache,
+    calendar::{CalendarScheduling, availability::CalendarAvailability},
+};
 use http_proto::HttpResponse;
 use hyper::StatusCode;
 use jmap_proto::types::collection::{Collection, SyncCollection};
@@ -350,27 +354,72 @@ impl CalendarSchedulingHandler for Server {
                 .await
                 .caused_by(trc::location!())?
             {
-                let resources = self
-                    .fetch_dav_resources(access_token, account_id, SyncCollection::Calendar)
+                // Accounts outside the requester's tenant are subject to its privacy policy
+                let account_token = self
+                    .get_access_token(account_id)
                     .await
                     .caused_by(trc::location!())?;
-                if let Some(resource) = self
-                    .core
-                    .groupware
-                    .default_calendar_name
-                    .as_ref()
-                    .and_then(|name| resources.by_path(name))
-                {
-                    let mut free_busy = self
-                        .build_freebusy_object(
-                            access_token,
-                            FreeBusyQuery::new(from_date.timestamp(), to_date.timestamp()),
-                            &resources,
-                            account_id,
-                            resource,
+                let is_external =
+                    account_token.tenant.map(|t| t.id) != access_token.tenant.map(|t| t.id);
+                let free_busy = if is_external {
+                    let privacy = self
+                        .free_busy_privacy(&account_token)
+                        .await
+                        .caused_by(trc::location!())?;
+                    if privacy == FreeBusyPrivacy::Hidden {
+                        response.items.0.push(ScheduleResponseItem {
+                            recipient: Href(format!("mailto:{email}")),
+                            request_status: "3.7;Invalid calendar user or insufficient permissions"
+                                .into(),
+                            calendar_data: None,
+                        });
+                        continue;
+                    }
+
+                    Some(
+                        self.free_busy_object(
+                            &account_token,
+                            TimeRange {
+                                start: from_date.timestamp(),
+                                end: to_date.timestamp().min(
+                                    from_date
+                                        .timestamp()
+                                        .saturating_add(self.core.groupware.freebusy_max_range),
+                                ),
+                            },
+                            privacy,
+                        )
+                        .await
+                        .caused_by(trc::location!())?,
+                    )
+                } else {
+                    let resources = self
+                        .fetch_dav_resources(access_token, account_id, SyncCollection::Calendar)
+                        .await
+                        .caused_by(trc::location!())?;
+                    if let Some(resource) = self
+                        .core
+                        .groupware
+                        .default_calendar_name
+                        .as_ref()
+                        .and_then(|name| resources.by_path(name))
+                    {
+                        Some(
+                            self.build_freebusy_object(
+                                access_token,
+                                FreeBusyQuery::new(from_date.timestamp(), to_date.timestamp()),
+                                &resources,
+                                account_id,
+                                resource,
+                            )
+                            .await?,
                         )
-                        .await?;
+                    } else {
+                        None
+                    }
+                };
 
+                if let Some(mut free_busy) = free_busy {
                     // Add iTIP method
                     free_busy.components[0].entries.push(ICalendarEntry {
                         name: ICalendarProperty::Method,
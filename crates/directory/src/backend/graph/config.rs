@@ -0,0 +1,123 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use store::Store;
+use utils::config::{
+    Config,
+    utils::{AsKey, ParseValue},
+};
+
+use super::{GraphConfig, GraphDirectory, GraphFields, Template, TemplatePart};
+
+impl GraphDirectory {
+    pub fn from_config(config: &mut Config, prefix: impl AsKey, data_store: Store) -> Option<Self> {
+        let prefix = prefix.as_key();
+
+        Some(GraphDirectory {
+            config: GraphConfig {
+                tenant_id: config.value_require((&prefix, "tenant-id"))?.to_string(),
+                client_id: config
+                    .value_require((&prefix, "auth.client-id"))?
+                    .to_string(),
+                client_secret: config.value_require((&prefix, "auth.secret"))?.to_string(),
+                login_url: config
+                    .value((&prefix, "endpoint.login"))
+                    .unwrap_or("https://login.microsoftonline.com")
+                    .trim_end_matches('/')
+                    .to_string(),
+                graph_url: config
+                    .value((&prefix, "endpoint.graph"))
+                    .unwrap_or("https://graph.microsoft.com/v1.0")
+                    .trim_end_matches('/')
+                    .to_string(),
+                timeout: config
+                    .property_or_default::<Duration>((&prefix, "timeout"), "30s")
+                    .unwrap_or_else(|| Duration::from_secs(30)),
+                sync_interval: config
+                    .property_or_default::<Duration>((&prefix, "sync.interval"), "1h")
+                    .unwrap_or_else(|| Duration::from_secs(3600)),
+                user_filter: config
+                    .value((&prefix, "sync.filter"))
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_string()),
+                sync_groups: config
+                    .property_or_default((&prefix, "sync.groups"), "true")
+                    .unwrap_or(true),
+                fields: GraphFields {
+                    name: config
+                        .property_or_default((&prefix, "fields.name"), "{userPrincipalName}")?,
+                    email: config.property_or_default((&prefix, "fields.email"), "{mail}")?,
+                    full_name: config
+                        .property_or_default::<Option<Template>>(
+                            (&prefix, "fields.full-name"),
+                            "{displayName}",
+                        )
+                        .flatten(),
+                    aliases: config
+                        .property_or_default::<Option<String>>(
+                            (&prefix, "fields.aliases"),
+                            "proxyAddresses",
+                        )
+                        .flatten(),
+                    quota: config
+                        .value((&prefix, "fields.quota"))
+                        .filter(|v| !v.is_empty())
+                        .map(|v| v.to_string()),
+                    group_name: config
+                        .property_or_default((&prefix, "fields.group-name"), "{mailNickname}")?,
+                },
+            },
+            data_store,
+        })
+    }
+}
+
+impl Template {
+    pub fn attributes(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            TemplatePart::Attribute(attribute) => Some(attribute.as_str()),
+            TemplatePart::Literal(_) => None,
+        })
+    }
+}
+
+impl ParseValue for Template {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = value;
+
+        while let Some((literal, tail)) = rest.split_once('{') {
+            let (attribute, tail) = tail
+                .split_once('}')
+                .ok_or_else(|| format!("Unterminated attribute in template {value:?}"))?;
+            let attribute = attribute.trim();
+            if attribute.is_empty() || attribute.contains('{') {
+                return Err(format!("Invalid attribute name in template {value:?}"));
+            }
+            if !literal.is_empty() {
+                parts.push(TemplatePart::Literal(literal.to_string()));
+            }
+            parts.push(TemplatePart::Attribute(attribute.to_string()));
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+
+        if parts
+            .iter()
+            .any(|part| matches!(part, TemplatePart::Attribute(_)))
+        {
+            Ok(Template { parts })
+        } else {
+            Err(format!(
+                "Template {value:?} does not reference any attribute"
+            ))
+        }
+    }
+}
@@ -0,0 +1,276 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use mail_send::Credentials;
+use reqwest::StatusCode;
+use trc::{AddContext, AuthEvent};
+
+use crate::{
+    Principal, PrincipalData, QueryBy, ROLE_USER, Type,
+    backend::{
+        RcptType,
+        internal::{
+            lookup::DirectoryStore,
+            manage::{self, ManageDirectory, UpdatePrincipal},
+        },
+    },
+};
+
+use super::{GraphConfig, GraphDirectory, Template, TemplatePart};
+
+pub(super) type GraphObject = serde_json::Map<String, serde_json::Value>;
+
+impl GraphDirectory {
+    pub async fn query(
+        &self,
+        by: QueryBy<'_>,
+        return_member_of: bool,
+    ) -> trc::Result<Option<Principal>> {
+        match &by {
+            QueryBy::Credentials(Credentials::OAuthBearer { token }) => {
+                // Fetch the signed-in user's profile using their own token
+                let response = self
+                    .client()
+                    .map_err(|err| {
+                        AuthEvent::Error
+                            .into_err()
+                            .reason(err)
+                            .details("Failed to build client")
+                    })?
+                    .get(format!(
+                        "{}/me?$select={}",
+                        self.config.graph_url,
+                        self.config.user_attributes()
+                    ))
+                    .bearer_auth(token)
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        AuthEvent::Error
+                            .into_err()
+                            .reason(err)
+                            .details("HTTP request failed")
+                    })?;
+
+                match response.status() {
+                    StatusCode::OK => {
+                        let response = response.bytes().await.map_err(|err| {
+                            AuthEvent::Error
+                                .into_err()
+                                .reason(err)
+                                .details("Failed to read Graph response")
+                        })?;
+                        let user =
+                            serde_json::from_slice::<GraphObject>(&response).map_err(|err| {
+                                AuthEvent::Error
+                                    .into_err()
+                                    .reason(err)
+                                    .details("Failed to deserialize Graph response")
+                            })?;
+                        let external_principal =
+                            self.config.build_user(&user).ok_or_else(|| {
+                                AuthEvent::Error
+                                    .into_err()
+                                    .details("Graph user is missing required attributes")
+                                    .ctx_opt(
+                                        trc::Key::Id,
+                                        user.get("id")
+                                            .and_then(|v| v.as_str())
+                                            .map(|v| v.to_string()),
+                                    )
+                            })?;
+
+                        // Fetch principal
+                        let id = self
+                            .data_store
+                            .get_or_create_principal_id(external_principal.name(), Type::Individual)
+                            .await
+                            .caused_by(trc::location!())?;
+                        let mut principal = self
+                            .data_store
+                            .query(QueryBy::Id(id), return_member_of)
+                            .await
+                            .caused_by(trc::location!())?
+                            .ok_or_else(|| manage::not_found(id).caused_by(trc::location!()))?;
+
+                        // Keep the internal store up to date with Microsoft Graph
+                        let changes = principal.update_external(external_principal);
+                        if !changes.is_empty() {
+                            self.data_store
+                                .update_principal(
+                                    UpdatePrincipal::by_id(principal.id)
+                                        .with_updates(changes)
+                                        .create_domains(),
+                                )
+                                .await
+                                .caused_by(trc::location!())?;
+                        }
+
+                        Ok(Some(principal))
+                    }
+                    StatusCode::UNAUTHORIZED => Err(trc::AuthEvent::Failed
+                        .into_err()
+                        .code(401)
+                        .details("Unauthorized")),
+                    other => Err(trc::AuthEvent::Error
+                        .into_err()
+                        .code(other.as_u16())
+                        .ctx(trc::Key::Reason, response.text().await.unwrap_or_default())
+                        .details("Unexpected status code")),
+                }
+            }
+            _ => self.data_store.query(by, return_member_of).await,
+        }
+    }
+
+    pub async fn email_to_id(&self, address: &str) -> trc::Result<Option<u32>> {
+        self.data_store.email_to_id(address).await
+    }
+
+    pub async fn rcpt(&self, address: &str) -> trc::Result<RcptType> {
+        self.data_store.rcpt(address).await
+    }
+
+    pub async fn vrfy(&self, address: &str) -> trc::Result<Vec<String>> {
+        self.data_store.vrfy(address).await
+    }
+
+    pub async fn expn(&self, address: &str) -> trc::Result<Vec<String>> {
+        self.data_store.expn(address).await
+    }
+
+    pub async fn is_local_domain(&self, domain: &str) -> trc::Result<bool> {
+        self.data_store.is_local_domain(domain).await
+    }
+
+    pub(super) fn client(&self) -> reqwest::Result<reqwest::Client> {
+        #[cfg(feature = "test_mode")]
+        let client = reqwest::Client::builder().danger_accept_invalid_certs(true);
+
+        #[cfg(not(feature = "test_mode"))]
+        let client = reqwest::Client::builder();
+
+        client.timeout(self.config.timeout).build()
+    }
+}
+
+impl GraphConfig {
+    // Comma separated list of the top-level attributes used by the user mapping
+    pub(super) fn user_attributes(&self) -> String {
+        let fields = &self.fields;
+        let mut attributes = vec!["id", "accountEnabled"];
+        for attribute in fields
+            .name
+            .attributes()
+            .chain(fields.email.attributes())
+            .chain(fields.full_name.iter().flat_map(|t| t.attributes()))
+            .chain(fields.aliases.as_deref())
+            .chain(fields.quota.as_deref())
+        {
+            let attribute = attribute
+                .split_once('.')
+                .map_or(attribute, |(root, _)| root);
+            if !attributes.contains(&attribute) {
+                attributes.push(attribute);
+            }
+        }
+
+        attributes.join(",")
+    }
+
+    pub(super) fn build_user(&self, user: &GraphObject) -> Option<Principal> {
+        let fields = &self.fields;
+        let name = fields.name.render(user)?.to_lowercase();
+        let email = fields
+            .email
+            .render(user)
+            .filter(|email| email.contains('@'))?
+            .to_lowercase();
+        let emails = self.addresses(user, vec![email]);
+
+        Some(Principal {
+            id: u32::MAX,
+            typ: Type::Individual,
+            name,
+            description: fields
+                .full_name
+                .as_ref()
+                .and_then(|template| template.render(user)),
+            secrets: Default::default(),
+            emails,
+            quota: fields
+                .quota
+                .as_deref()
+                .and_then(|attribute| attribute_value(user, attribute))
+                .and_then(|value| match value {
+                    serde_json::Value::Number(number) => number.as_u64(),
+                    serde_json::Value::String(string) => string.trim().parse().ok(),
+                    _ => None,
+                })
+                .filter(|quota| *quota > 0),
+            tenant: Default::default(),
+            data: vec![PrincipalData::Roles(vec![ROLE_USER])],
+        })
+    }
+
+    // Appends the SMTP proxy addresses of an object to its primary addresses
+    pub(super) fn addresses(&self, object: &GraphObject, mut emails: Vec<String>) -> Vec<String> {
+        if let Some(aliases) = self
+            .fields
+            .aliases
+            .as_deref()
+            .and_then(|attribute| attribute_value(object, attribute))
+            .and_then(|value| value.as_array())
+        {
+            for alias in aliases.iter().filter_map(|alias| alias.as_str()) {
+                // Proxy addresses are prefixed with their type, only SMTP addresses are kept
+                let alias = match alias.split_once(':') {
+                    Some((typ, address)) if typ.eq_ignore_ascii_case("smtp") => address,
+                    Some(_) => continue,
+                    None => alias,
+                }
+                .to_lowercase();
+                if alias.contains('@') && !emails.contains(&alias) {
+                    emails.push(alias);
+                }
+            }
+        }
+
+        emails
+    }
+}
+
+impl Template {
+    // Returns None if any of the referenced attributes is missing or empty
+    pub(super) fn render(&self, object: &GraphObject) -> Option<String> {
+        let mut result = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => result.push_str(literal),
+                TemplatePart::Attribute(attribute) => match attribute_value(object, attribute)? {
+                    serde_json::Value::String(value) if !value.is_empty() => result.push_str(value),
+                    serde_json::Value::Number(value) => result.push_str(&value.to_string()),
+                    _ => return None,
+                },
+            }
+        }
+
+        Some(result)
+    }
+}
+
+// Looks up an attribute, nested attributes are separated by dots
+pub(super) fn attribute_value<'x>(
+    object: &'x GraphObject,
+    attribute: &str,
+) -> Option<&'x serde_json::Value> {
+    let mut path = attribute.split('.');
+    let mut value = object.get(path.next()?)?;
+    for name in path {
+        value = value.as_object()?.get(name)?;
+    }
+    Some(value)
+}
@@ -0,0 +1,59 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+pub mod config;
+pub mod lookup;
+pub mod sync;
+
+use std::time::Duration;
+
+use store::Store;
+
+pub struct GraphDirectory {
+    config: GraphConfig,
+    pub(crate) data_store: Store,
+}
+
+struct GraphConfig {
+    pub tenant_id: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub login_url: String,
+    pub graph_url: String,
+    pub timeout: Duration,
+    pub sync_interval: Duration,
+    pub user_filter: Option<String>,
+    pub sync_groups: bool,
+    pub fields: GraphFields,
+}
+
+struct GraphFields {
+    pub name: Template,
+    pub email: Template,
+    pub full_name: Option<Template>,
+    pub aliases: Option<String>,
+    pub quota: Option<String>,
+    pub group_name: Template,
+}
+
+// Attribute templates such as "{mailNickname}@example.org", where each
+// placeholder is replaced by the value of a (possibly nested) Graph attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Attribute(String),
+}
+
+impl GraphDirectory {
+    pub fn sync_interval(&self) -> Duration {
+        self.config.sync_interval
+    }
+}
@@ -0,0 +1,368 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::{AHashMap, AHashSet};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use trc::{AddContext, StoreEvent};
+
+use crate::{
+    Principal, QueryBy, Type,
+    backend::internal::{
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+        manage::{self, ManageDirectory, UpdatePrincipal},
+    },
+};
+
+use super::{GraphDirectory, lookup::GraphObject};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GraphSyncResult {
+    pub users: usize,
+    pub groups: usize,
+    pub skipped: usize,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct PageResponse {
+    #[serde(default)]
+    value: Vec<GraphObject>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+impl GraphDirectory {
+    /// Synchronizes users and groups from Microsoft Graph into the internal store.
+    pub async fn sync(&self) -> trc::Result<GraphSyncResult> {
+        let client = self.client().map_err(|err| {
+            StoreEvent::GraphError
+                .into_err()
+                .reason(err)
+                .details("Failed to build client")
+        })?;
+        let token = self.app_token(&client).await?;
+        let mut result = GraphSyncResult::default();
+
+        // Synchronize users
+        let mut query = vec![
+            ("$select", self.config.user_attributes()),
+            ("$top", "999".to_string()),
+        ];
+        if let Some(filter) = &self.config.user_filter {
+            query.push(("$filter", filter.clone()));
+            query.push(("$count", "true".to_string()));
+        }
+        let mut user_ids = AHashMap::new();
+        for user in self
+            .fetch_all(&client, &token, "users", &query)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if user.get("accountEnabled") == Some(&serde_json::Value::Bool(false)) {
+                result.skipped += 1;
+                continue;
+            }
+            let (Some(graph_id), Some(external)) = (
+                user.get("id").and_then(|v| v.as_str()),
+                self.config.build_user(&user),
+            ) else {
+                // Users without a mailbox are not synchronized
+                result.skipped += 1;
+                continue;
+            };
+
+            match self.upsert(external, Type::Individual).await {
+                Ok(id) => {
+                    user_ids.insert(graph_id.to_string(), id);
+                    result.users += 1;
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.ctx(trc::Key::Id, graph_id.to_string())
+                            .details("Failed to synchronize Graph user")
+                    );
+                    result.skipped += 1;
+                }
+            }
+        }
+
+        if !self.config.sync_groups {
+            return Ok(result);
+        }
+
+        // Synchronize groups
+        let mut attributes = vec![
+            "id",
+            "displayName",
+            "mail",
+            "mailEnabled",
+            "securityEnabled",
+        ];
+        for attribute in self
+            .config
+            .fields
+            .group_name
+            .attributes()
+            .chain(self.config.fields.aliases.as_deref())
+        {
+            let attribute = attribute
+                .split_once('.')
+                .map_or(attribute, |(root, _)| root);
+            if !attributes.contains(&attribute) {
+                attributes.push(attribute);
+            }
+        }
+        let query = [
+            ("$select", attributes.join(",")),
+            ("$top", "999".to_string()),
+        ];
+        let mut group_ids = AHashMap::new();
+        for group in self
+            .fetch_all(&client, &token, "groups", &query)
+            .await
+            .caused_by(trc::location!())?
+        {
+            let (Some(graph_id), Some(name)) = (
+                group.get("id").and_then(|v| v.as_str()),
+                self.config.fields.group_name.render(&group),
+            ) else {
+                result.skipped += 1;
+                continue;
+            };
+
+            // Distribution groups become mailing lists, security groups become groups
+            let is_enabled = |field: &str| group.get(field).and_then(|v| v.as_bool()) == Some(true);
+            let typ = if is_enabled("mailEnabled") && !is_enabled("securityEnabled") {
+                Type::List
+            } else {
+                Type::Group
+            };
+            let emails = group
+                .get("mail")
+                .and_then(|v| v.as_str())
+                .filter(|v| v.contains('@'))
+                .map(|v| vec![v.to_lowercase()])
+                .unwrap_or_default();
+            let external = Principal {
+                id: u32::MAX,
+                typ,
+                name: name.to_lowercase(),
+                description: group
+                    .get("displayName")
+                    .and_then(|v| v.as_str())
+                    .map(|v| v.to_string()),
+                secrets: Default::default(),
+                emails: self.config.addresses(&group, emails),
+                quota: Default::default(),
+                tenant: Default::default(),
+                data: Default::default(),
+            };
+
+            match self.upsert(external, typ).await {
+                Ok(id) => {
+                    group_ids.insert(graph_id.to_string(), id);
+                    result.groups += 1;
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.ctx(trc::Key::Id, graph_id.to_string())
+                            .details("Failed to synchronize Graph group")
+                    );
+                    result.skipped += 1;
+                }
+            }
+        }
+
+        // Synchronize group memberships once all principals exist
+        let query = [("$select", "id".to_string()), ("$top", "999".to_string())];
+        for (graph_id, group_id) in &group_ids {
+            let mut members = AHashSet::new();
+            for member in self
+                .fetch_all(
+                    &client,
+                    &token,
+                    &format!("groups/{graph_id}/members"),
+                    &query,
+                )
+                .await
+                .caused_by(trc::location!())?
+            {
+                if let Some(id) = member
+                    .get("id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|id| user_ids.get(id).or_else(|| group_ids.get(id)))
+                    .filter(|id| *id != group_id)
+                {
+                    members.insert(*id);
+                }
+            }
+
+            if let Err(err) = self.update_members(*group_id, members).await {
+                trc::error!(
+                    err.ctx(trc::Key::Id, graph_id.to_string())
+                        .details("Failed to synchronize Graph group members")
+                );
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Obtains an application token using the client credentials grant
+    async fn app_token(&self, client: &Client) -> trc::Result<String> {
+        let response = client
+            .post(format!(
+                "{}/{}/oauth2/v2.0/token",
+                self.config.login_url, self.config.tenant_id
+            ))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+                ("scope", "https://graph.microsoft.com/.default"),
+            ])
+            .send()
+            .await
+            .map_err(|err| {
+                StoreEvent::GraphError
+                    .into_err()
+                    .reason(err)
+                    .details("Token request failed")
+            })?;
+
+        read_response::<TokenResponse>(response)
+            .await
+            .map(|response| response.access_token)
+    }
+
+    // Fetches all objects of a collection, following the pagination links
+    async fn fetch_all(
+        &self,
+        client: &Client,
+        token: &str,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> trc::Result<Vec<GraphObject>> {
+        let mut request = client
+            .get(format!("{}/{path}", self.config.graph_url))
+            .query(query);
+        let mut objects = Vec::new();
+
+        loop {
+            let response = request
+                .bearer_auth(token)
+                .header("ConsistencyLevel", "eventual")
+                .send()
+                .await
+                .map_err(|err| {
+                    StoreEvent::GraphError
+                        .into_err()
+                        .reason(err)
+                        .details("HTTP request failed")
+                        .ctx(trc::Key::Path, path.to_string())
+                })?;
+            let page = read_response::<PageResponse>(response).await?;
+            objects.extend(page.value);
+
+            match page.next_link {
+                Some(next_link) => {
+                    request = client.get(next_link);
+                }
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    async fn upsert(&self, external: Principal, typ: Type) -> trc::Result<u32> {
+        let id = self
+            .data_store
+            .get_or_create_principal_id(external.name(), typ)
+            .await
+            .caused_by(trc::location!())?;
+        let mut principal = self
+            .data_store
+            .query(QueryBy::Id(id), false)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| manage::not_found(id).caused_by(trc::location!()))?;
+
+        let changes = principal.update_external(external);
+        if !changes.is_empty() {
+            self.data_store
+                .update_principal(
+                    UpdatePrincipal::by_id(id)
+                        .with_updates(changes)
+                        .create_domains(),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(id)
+    }
+
+    async fn update_members(&self, group_id: u32, members: AHashSet<u32>) -> trc::Result<()> {
+        let current = self
+            .data_store
+            .get_members(group_id)
+            .await
+            .caused_by(trc::location!())?;
+        if current.len() == members.len() && current.iter().all(|id| members.contains(id)) {
+            return Ok(());
+        }
+
+        let mut names = Vec::with_capacity(members.len());
+        for id in members {
+            if let Some(name) = self
+                .data_store
+                .get_principal_name(id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                names.push(name);
+            }
+        }
+
+        self.data_store
+            .update_principal(UpdatePrincipal::by_id(group_id).with_updates(vec![
+                PrincipalUpdate::set(PrincipalField::Members, PrincipalValue::StringList(names)),
+            ]))
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+async fn read_response<T: for<'de> Deserialize<'de>>(
+    response: reqwest::Response,
+) -> trc::Result<T> {
+    match response.status() {
+        StatusCode::OK => {
+            let bytes = response.bytes().await.map_err(|err| {
+                StoreEvent::GraphError
+                    .into_err()
+                    .reason(err)
+                    .details("Failed to read Graph response")
+            })?;
+            serde_json::from_slice::<T>(&bytes).map_err(|err| {
+                StoreEvent::GraphError
+                    .into_err()
+                    .reason(err)
+                    .details("Failed to deserialize Graph response")
+            })
+        }
+        other => Err(StoreEvent::GraphError
+            .into_err()
+            .code(other.as_u16())
+            .ctx(trc::Key::Reason, response.text().await.unwrap_or_default())
+            .details("Unexpected status code")),
+    }
+}
@@ -9,14 +9,16 @@ use super::{
     SpecialSecrets, lookup::DirectoryStore,
 };
 use crate::{
-    MemberOf, Permission, PermissionGrant, Permissions, Principal, PrincipalData, PrincipalQuota,
-    QueryBy, ROLE_ADMIN, ROLE_TENANT_ADMIN, ROLE_USER, Type, backend::RcptType,
-    core::principal::build_search_index,
+    ArchivedPrincipalData, MemberOf, Permission, PermissionGrant, Permissions, Principal,
+    PrincipalData, PrincipalQuota, QueryBy, ROLE_ADMIN, ROLE_TENANT_ADMIN, ROLE_USER, Type,
+    backend::RcptType,
+    core::{app_password::AppPassword, principal::build_search_index},
 };
 use ahash::{AHashMap, AHashSet};
 use compact_str::CompactString;
 use jmap_proto::types::collection::Collection;
 use nlp::tokenizers::word::WordTokenizer;
+use std::time::Duration;
 use store::{
     Deserialize, IterateParams, Serialize, SerializeInfallible, Store, U32_LEN, ValueKey,
     backend::MAX_TOKEN_LENGTH,
@@ -27,7 +29,7 @@ use store::{
     },
 };
 use trc::AddContext;
-use utils::sanitize_email;
+use utils::{config::utils::ParseValue, sanitize_email};
 
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct PrincipalList<T> {
@@ -381,9 +383,54 @@ impl ManageDirectory for Store {
         if let Some(picture) = principal_set.take_str(PrincipalField::Locale) {
             principal_create.data.push(PrincipalData::Locale(picture));
         }
+        if let Some(reason) = principal_set.take_str(PrincipalField::LegalHold) {
+            principal_create.data.push(PrincipalData::LegalHold(reason));
+        }
+        if let Some(policy) = principal_set.take_str(PrincipalField::ForwardPolicy) {
+            if !is_forward_policy(&policy) {
+                return Err(error(
+                    "Invalid forward policy",
+                    "Valid policies are 'allow', 'deny' and 'approval'".into(),
+                ));
+            }
+            principal_create
+                .data
+                .push(PrincipalData::ForwardPolicy(policy));
+        }
+        if let Some(policy) = principal_set.take_str(PrincipalField::FreeBusyPolicy) {
+            if !is_free_busy_policy(&policy) {
+                return Err(error(
+                    "Invalid free/busy policy",
+                    "Valid policies are 'none', 'busy' and 'detailed'".into(),
+                ));
+            }
+            principal_create
+                .data
+                .push(PrincipalData::FreeBusyPolicy(policy));
+        }
+        for field in [
+            PrincipalField::BookingPolicy,
+            PrincipalField::BookingHorizon,
+            PrincipalField::BookingDelegate,
+        ] {
+            if let Some(value) = principal_set.take_str(field) {
+                if !matches!(principal_create.typ, Type::Resource | Type::Location) {
+                    return Err(error(
+                        "Invalid field",
+                        "Booking settings can only be set on resources and locations".into(),
+                    ));
+                }
+                principal_create.data.push(booking_data(field, value)?);
+            }
+        }
         if let Some(urls) = principal_set.take_str_array(PrincipalField::Urls) {
             principal_create.data.push(PrincipalData::Urls(urls));
         }
+        if let Some(settings) = principal_set.take_str_array(PrincipalField::SpamFilter) {
+            principal_create
+                .data
+                .push(PrincipalData::SpamFilter(settings));
+        }
         if let Some(urls) = principal_set.take_str_array(PrincipalField::ExternalMembers) {
             principal_create
                 .data
@@ -640,6 +687,18 @@ impl ManageDirectory for Store {
             .caused_by(trc::location!())?;
         let typ = Type::from(&principal.typ);
 
+        // Accounts under legal hold cannot be deleted
+        if principal
+            .data
+            .iter()
+            .any(|data| matches!(data, ArchivedPrincipalData::LegalHold(_)))
+        {
+            return Err(error(
+                "Account is under legal hold",
+                "Release the legal hold before deleting this account".into(),
+            ));
+        }
+
         let mut batch = BatchBuilder::new();
         batch.with_account_id(u32::MAX);
 
@@ -1140,7 +1199,18 @@ impl ManageDirectory for Store {
                     // Password changed, update changed principals
                     changed_principals.add_change(principal_id, principal_type, change.field);
 
-                    if secret.is_app_password() || secret.is_otp_auth() {
+                    if let Some(name) = secret
+                        .strip_prefix("$app$")
+                        .filter(|name| !name.is_empty() && !name.contains('$'))
+                    {
+                        // Revoke a single app password by name
+                        principal
+                            .secrets
+                            .retain(|v| AppPassword::parse(v).is_none_or(|app| app.name != name));
+                    } else if secret.is_app_password()
+                        || secret.is_otp_auth()
+                        || secret.is_webauthn()
+                    {
                         principal
                             .secrets
                             .retain(|v| *v != secret && !v.starts_with(secret.as_str()));
@@ -1177,6 +1247,85 @@ impl ManageDirectory for Store {
                         principal.data.push(PrincipalData::Locale(value));
                     }
                 }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::LegalHold,
+                    PrincipalValue::String(value),
+                ) => {
+                    principal
+                        .data
+                        .retain(|v| !matches!(v, PrincipalData::LegalHold(_)));
+                    if !value.is_empty() {
+                        principal.data.push(PrincipalData::LegalHold(value));
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::ForwardPolicy,
+                    PrincipalValue::String(value),
+                ) => {
+                    if !value.is_empty() && !is_forward_policy(&value) {
+                        return Err(error(
+                            "Invalid forward policy",
+                            "Valid policies are 'allow', 'deny' and 'approval'".into(),
+                        ));
+                    }
+                    principal
+                        .data
+                        .retain(|v| !matches!(v, PrincipalData::ForwardPolicy(_)));
+                    if !value.is_empty() {
+                        principal.data.push(PrincipalData::ForwardPolicy(value));
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::FreeBusyPolicy,
+                    PrincipalValue::String(value),
+                ) => {
+                    if !value.is_empty() && !is_free_busy_policy(&value) {
+                        return Err(error(
+                            "Invalid free/busy policy",
+                            "Valid policies are 'none', 'busy' and 'detailed'".into(),
+                        ));
+                    }
+                    principal
+                        .data
+                        .retain(|v| !matches!(v, PrincipalData::FreeBusyPolicy(_)));
+                    if !value.is_empty() {
+                        principal.data.push(PrincipalData::FreeBusyPolicy(value));
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::BookingPolicy
+                    | PrincipalField::BookingHorizon
+                    | PrincipalField::BookingDelegate,
+                    PrincipalValue::String(value),
+                ) if matches!(principal_type, Type::Resource | Type::Location) => {
+                    let value = if !value.is_empty() {
+                        Some(booking_data(change.field, value)?)
+                    } else {
+                        None
+                    };
+                    principal.data.retain(|v| {
+                        !matches!(
+                            (change.field, v),
+                            (
+                                PrincipalField::BookingPolicy,
+                                PrincipalData::BookingPolicy(_)
+                            ) | (
+                                PrincipalField::BookingHorizon,
+                                PrincipalData::BookingHorizon(_)
+                            ) | (
+                                PrincipalField::BookingDelegate,
+                                PrincipalData::BookingDelegate(_)
+                            )
+                        )
+                    });
+                    if let Some(value) = value {
+                        principal.data.push(value);
+                    }
+                }
                 (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::Integer(quota))
                     if matches!(
                         principal_type,
@@ -1801,9 +1950,24 @@ impl ManageDirectory for Store {
                         principal.data.push(PrincipalData::Urls(items));
                     }
                 }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::SpamFilter,
+                    PrincipalValue::StringList(items),
+                ) => {
+                    principal
+                        .data
+                        .retain(|v| !matches!(v, PrincipalData::SpamFilter(_)));
+
+                    if !items.is_empty() {
+                        principal.data.push(PrincipalData::SpamFilter(items));
+                    }
+                }
                 (
                     PrincipalAction::AddItem,
-                    PrincipalField::Urls | PrincipalField::ExternalMembers,
+                    PrincipalField::Urls
+                    | PrincipalField::ExternalMembers
+                    | PrincipalField::SpamFilter,
                     PrincipalValue::String(mut item),
                 ) => {
                     if matches!(change.field, PrincipalField::ExternalMembers) {
@@ -1836,6 +2000,13 @@ impl ManageDirectory for Store {
                                 found = true;
                                 break;
                             }
+                            (PrincipalData::SpamFilter(settings), PrincipalField::SpamFilter) => {
+                                if !settings.contains(&item) {
+                                    settings.push(item.clone());
+                                }
+                                found = true;
+                                break;
+                            }
                             _ => {}
                         }
                     }
@@ -1848,13 +2019,18 @@ impl ManageDirectory for Store {
                             PrincipalField::ExternalMembers => principal
                                 .data
                                 .push(PrincipalData::ExternalMembers(vec![item])),
+                            PrincipalField::SpamFilter => {
+                                principal.data.push(PrincipalData::SpamFilter(vec![item]))
+                            }
                             _ => {}
                         }
                     }
                 }
                 (
                     PrincipalAction::RemoveItem,
-                    PrincipalField::Urls | PrincipalField::ExternalMembers,
+                    PrincipalField::Urls
+                    | PrincipalField::ExternalMembers
+                    | PrincipalField::SpamFilter,
                     PrincipalValue::String(item),
                 ) => {
                     for data in &mut principal.data {
@@ -1870,6 +2046,10 @@ impl ManageDirectory for Store {
                                 emails.retain(|v| *v != item);
                                 break;
                             }
+                            (PrincipalData::SpamFilter(settings), PrincipalField::SpamFilter) => {
+                                settings.retain(|v| *v != item);
+                                break;
+                            }
                             _ => {}
                         }
                     }
@@ -2208,6 +2388,36 @@ impl ManageDirectory for Store {
                         result.set(PrincipalField::Locale, compact_string);
                     }
                 }
+                PrincipalData::LegalHold(reason) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::LegalHold) {
+                        result.set(PrincipalField::LegalHold, reason);
+                    }
+                }
+                PrincipalData::ForwardPolicy(policy) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::ForwardPolicy) {
+                        result.set(PrincipalField::ForwardPolicy, policy);
+                    }
+                }
+                PrincipalData::FreeBusyPolicy(policy) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::FreeBusyPolicy) {
+                        result.set(PrincipalField::FreeBusyPolicy, policy);
+                    }
+                }
+                PrincipalData::BookingPolicy(policy) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::BookingPolicy) {
+                        result.set(PrincipalField::BookingPolicy, policy);
+                    }
+                }
+                PrincipalData::BookingHorizon(horizon) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::BookingHorizon) {
+                        result.set(PrincipalField::BookingHorizon, horizon);
+                    }
+                }
+                PrincipalData::BookingDelegate(delegate) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::BookingDelegate) {
+                        result.set(PrincipalField::BookingDelegate, delegate);
+                    }
+                }
                 PrincipalData::ExternalMembers(compact_strings) => {
                     if fields.is_empty() || fields.contains(&PrincipalField::ExternalMembers) {
                         result.set(PrincipalField::ExternalMembers, compact_strings);
@@ -2218,6 +2428,11 @@ impl ManageDirectory for Store {
                         result.set(PrincipalField::Urls, compact_strings);
                     }
                 }
+                PrincipalData::SpamFilter(settings) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::SpamFilter) {
+                        result.set(PrincipalField::SpamFilter, settings);
+                    }
+                }
                 PrincipalData::PrincipalQuota(principal_quotas_) => {
                     principal_quotas = principal_quotas_;
                 }
@@ -2647,6 +2862,50 @@ pub fn enterprise() -> trc::Error {
     trc::ManageEvent::NotSupported.ctx(trc::Key::Details, "Enterprise feature")
 }
 
+fn is_forward_policy(policy: &str) -> bool {
+    matches!(policy, "allow" | "deny" | "approval")
+}
+
+fn is_free_busy_policy(policy: &str) -> bool {
+    matches!(policy, "none" | "busy" | "detailed")
+}
+
+fn booking_data(field: PrincipalField, value: String) -> trc::Result<PrincipalData> {
+    match field {
+        PrincipalField::BookingPolicy => {
+            if matches!(
+                value.as_str(),
+                "manual" | "accept" | "accept-always" | "decline"
+            ) {
+                Ok(PrincipalData::BookingPolicy(value))
+            } else {
+                Err(error(
+                    "Invalid booking policy",
+                    "Valid policies are 'manual', 'accept', 'accept-always' and 'decline'".into(),
+                ))
+            }
+        }
+        PrincipalField::BookingHorizon => {
+            if Duration::parse_value(&value).is_ok() {
+                Ok(PrincipalData::BookingHorizon(value))
+            } else {
+                Err(error(
+                    "Invalid booking horizon",
+                    "Booking horizons are durations such as '180d'".into(),
+                ))
+            }
+        }
+        _ => sanitize_email(&value)
+            .map(PrincipalData::BookingDelegate)
+            .ok_or_else(|| {
+                error(
+                    "Invalid booking delegate",
+                    "Booking delegates must be valid email addresses".into(),
+                )
+            }),
+    }
+}
+
 pub fn error(details: impl Into<trc::Value>, reason: Option<impl Into<trc::Value>>) -> trc::Error {
     trc::ManageEvent::Error
         .ctx(trc::Key::Details, details)
@@ -112,6 +112,13 @@ pub enum PrincipalField {
     Urls,
     ExternalMembers,
     Locale,
+    SpamFilter,
+    LegalHold,
+    ForwardPolicy,
+    FreeBusyPolicy,
+    BookingPolicy,
+    BookingHorizon,
+    BookingDelegate,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -200,6 +207,13 @@ impl PrincipalField {
             PrincipalField::Urls => 15,
             PrincipalField::ExternalMembers => 16,
             PrincipalField::Locale => 17,
+            PrincipalField::SpamFilter => 18,
+            PrincipalField::LegalHold => 19,
+            PrincipalField::ForwardPolicy => 20,
+            PrincipalField::FreeBusyPolicy => 21,
+            PrincipalField::BookingPolicy => 22,
+            PrincipalField::BookingHorizon => 23,
+            PrincipalField::BookingDelegate => 24,
         }
     }
 
@@ -223,6 +237,13 @@ impl PrincipalField {
             15 => Some(PrincipalField::Urls),
             16 => Some(PrincipalField::ExternalMembers),
             17 => Some(PrincipalField::Locale),
+            18 => Some(PrincipalField::SpamFilter),
+            19 => Some(PrincipalField::LegalHold),
+            20 => Some(PrincipalField::ForwardPolicy),
+            21 => Some(PrincipalField::FreeBusyPolicy),
+            22 => Some(PrincipalField::BookingPolicy),
+            23 => Some(PrincipalField::BookingHorizon),
+            24 => Some(PrincipalField::BookingDelegate),
             _ => None,
         }
     }
@@ -247,6 +268,13 @@ impl PrincipalField {
             PrincipalField::Urls => "urls",
             PrincipalField::ExternalMembers => "externalMembers",
             PrincipalField::Locale => "locale",
+            PrincipalField::SpamFilter => "spamFilter",
+            PrincipalField::LegalHold => "legalHold",
+            PrincipalField::ForwardPolicy => "forwardPolicy",
+            PrincipalField::FreeBusyPolicy => "freeBusyPolicy",
+            PrincipalField::BookingPolicy => "bookingPolicy",
+            PrincipalField::BookingHorizon => "bookingHorizon",
+            PrincipalField::BookingDelegate => "bookingDelegate",
         }
     }
 
@@ -270,6 +298,13 @@ impl PrincipalField {
             "urls" => Some(PrincipalField::Urls),
             "externalMembers" => Some(PrincipalField::ExternalMembers),
             "locale" => Some(PrincipalField::Locale),
+            "spamFilter" => Some(PrincipalField::SpamFilter),
+            "legalHold" => Some(PrincipalField::LegalHold),
+            "forwardPolicy" => Some(PrincipalField::ForwardPolicy),
+            "freeBusyPolicy" => Some(PrincipalField::FreeBusyPolicy),
+            "bookingPolicy" => Some(PrincipalField::BookingPolicy),
+            "bookingHorizon" => Some(PrincipalField::BookingHorizon),
+            "bookingDelegate" => Some(PrincipalField::BookingDelegate),
             _ => None,
         }
     }
@@ -278,6 +313,7 @@ impl PrincipalField {
 pub trait SpecialSecrets {
     fn is_otp_auth(&self) -> bool;
     fn is_app_password(&self) -> bool;
+    fn is_webauthn(&self) -> bool;
     fn is_password(&self) -> bool;
 }
 
@@ -293,7 +329,11 @@ where
         self.as_ref().starts_with("$app$")
     }
 
+    fn is_webauthn(&self) -> bool {
+        self.as_ref().starts_with("$webauthn$")
+    }
+
     fn is_password(&self) -> bool {
-        !self.is_otp_auth() && !self.is_app_password()
+        !self.is_otp_auth() && !self.is_app_password() && !self.is_webauthn()
     }
 }
@@ -10,10 +10,11 @@ use ldap3::LdapConnSettings;
 use store::Store;
 use utils::config::{Config, utils::AsKey};
 
-use crate::core::config::build_pool;
+use crate::core::{cache::GroupCache, config::build_pool};
 
 use super::{
     AuthBind, Bind, LdapConnectionManager, LdapDirectory, LdapFilter, LdapFilterItem, LdapMappings,
+    LdapWrite, SecretWrite,
 };
 
 impl LdapDirectory {
@@ -91,6 +92,7 @@ impl LdapDirectory {
                 .map(|(_, v)| v.to_string())
                 .collect(),
             attrs_principal: vec!["objectClass".to_string()],
+            attrs_group: Vec::new(),
         };
 
         for attr in [
@@ -109,6 +111,14 @@ impl LdapDirectory {
                 .extend(attr.iter().filter(|a| !a.is_empty()).cloned());
         }
 
+        mappings.attrs_group = mappings
+            .attr_name
+            .iter()
+            .chain(mappings.attr_groups.iter())
+            .filter(|a| !a.is_empty())
+            .cloned()
+            .collect();
+
         let auth_bind = match config
             .value((&prefix, "bind.auth.method"))
             .unwrap_or("default")
@@ -130,8 +140,58 @@ impl LdapDirectory {
             }
         };
 
+        let write = if config
+            .property_or_default::<bool>((&prefix, "write.enable"), "false")
+            .unwrap_or_default()
+        {
+            let secret = match config
+                .value((&prefix, "write.secret.method"))
+                .unwrap_or("disabled")
+            {
+                "attribute" => SecretWrite::Attribute,
+                "password-modify" => SecretWrite::PasswordModify,
+                "disabled" => SecretWrite::Disabled,
+                unknown => {
+                    config.new_parse_error(
+                        (&prefix, "write.secret.method"),
+                        format!("Unknown LDAP secret write method: {unknown}"),
+                    );
+                    return None;
+                }
+            };
+
+            // Written attributes default to the first attribute used for lookups
+            let attribute = |key: &str, defaults: &[String]| {
+                config
+                    .value((&prefix, format!("write.attributes.{key}").as_str()))
+                    .map(|v| v.to_string())
+                    .or_else(|| defaults.first().cloned())
+                    .filter(|v| !v.is_empty())
+            };
+
+            Some(LdapWrite {
+                attr_quota: attribute("quota", &mappings.attr_quota),
+                attr_email_address: attribute("email", &mappings.attr_email_address),
+                attr_email_alias: attribute("email-alias", &mappings.attr_email_alias),
+                attr_secret: attribute("secret", &mappings.attr_secret),
+                secret,
+                bind: Bind::new(
+                    config
+                        .value_require((&prefix, "write.bind.dn"))?
+                        .to_string(),
+                    config
+                        .value_require((&prefix, "write.bind.secret"))?
+                        .to_string(),
+                ),
+            })
+        } else {
+            None
+        };
+
         Some(LdapDirectory {
+            groups: GroupCache::from_config(config, &prefix),
             mappings,
+            write,
             pool: build_pool(config, &prefix, manager)
                 .map_err(|e| {
                     config.new_parse_error(prefix, format!("Failed to build LDAP pool: {e:?}"))
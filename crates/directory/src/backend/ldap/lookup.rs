@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use ahash::AHashSet;
 use ldap3::{Ldap, LdapConnAsync, ResultEntry, Scope, SearchEntry};
 use mail_send::Credentials;
 use store::xxhash_rust;
@@ -18,6 +19,7 @@ use crate::{
             manage::{self, ManageDirectory, UpdatePrincipal},
         },
     },
+    core::cache::GroupEntry,
 };
 
 use super::{AuthBind, LdapDirectory, LdapMappings};
@@ -208,42 +210,40 @@ impl LdapDirectory {
             }
         };
 
-        // Query groups
+        // Query groups, following the memberships of groups up to the configured depth
         if !member_of.is_empty() && return_member_of {
             let mut data = Vec::with_capacity(member_of.len());
-            for mut name in member_of {
-                if name.contains('=') {
-                    let (rs, _res) = conn
-                        .search(
-                            &name,
-                            Scope::Base,
-                            "objectClass=*",
-                            &self.mappings.attr_name,
-                        )
-                        .await
-                        .map_err(|err| err.into_error().caused_by(trc::location!()))?
-                        .success()
-                        .map_err(|err| err.into_error().caused_by(trc::location!()))?;
-                    for entry in rs {
-                        'outer: for (attr, value) in SearchEntry::construct(entry).attrs {
-                            if self.mappings.attr_name.contains(&attr) {
-                                if let Some(group) = value.into_iter().next() {
-                                    if !group.is_empty() {
-                                        name = group;
-                                        break 'outer;
-                                    }
-                                }
-                            }
-                        }
+            let mut seen = AHashSet::new();
+            let mut groups = member_of;
+            for depth in 0..=self.groups.max_depth {
+                let mut parents = Vec::new();
+                for group in groups {
+                    if !seen.insert(group.clone()) {
+                        continue;
                     }
-                }
+                    let entry = if let Some(entry) = self.groups.get(&group) {
+                        entry
+                    } else {
+                        let entry = self.fetch_group(&mut conn, &group).await?;
+                        self.groups.insert(group, entry)
+                    };
 
-                data.push(
-                    self.data_store
-                        .get_or_create_principal_id(&name, Type::Group)
+                    let id = self
+                        .data_store
+                        .get_or_create_principal_id(&entry.name, Type::Group)
                         .await
-                        .caused_by(trc::location!())?,
-                );
+                        .caused_by(trc::location!())?;
+                    if !data.contains(&id) {
+                        data.push(id);
+                    }
+                    if depth < self.groups.max_depth {
+                        parents.extend(entry.member_of.iter().cloned());
+                    }
+                }
+                if parents.is_empty() {
+                    break;
+                }
+                groups = parents;
             }
 
             external_principal.data.push(PrincipalData::MemberOf(data));
@@ -386,6 +386,50 @@ impl LdapDirectory {
 }
 
 impl LdapDirectory {
+    // Groups referenced by DN are looked up to obtain their name and memberships
+    async fn fetch_group(&self, conn: &mut Ldap, group: &str) -> trc::Result<GroupEntry> {
+        let mut entry = GroupEntry {
+            name: group.to_string(),
+            member_of: Vec::new(),
+        };
+        if !group.contains('=') {
+            return Ok(entry);
+        }
+
+        let (rs, _res) = conn
+            .search(
+                group,
+                Scope::Base,
+                "objectClass=*",
+                if self.groups.max_depth > 0 {
+                    &self.mappings.attrs_group
+                } else {
+                    &self.mappings.attr_name
+                },
+            )
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+        let mut has_name = false;
+        for result in rs {
+            for (attr, value) in SearchEntry::construct(result).attrs {
+                if self.mappings.attr_name.contains(&attr) {
+                    if let Some(name) = value.into_iter().next().filter(|name| !name.is_empty()) {
+                        if !has_name {
+                            entry.name = name;
+                            has_name = true;
+                        }
+                    }
+                } else if self.mappings.attr_groups.contains(&attr) {
+                    entry.member_of.extend(value);
+                }
+            }
+        }
+
+        Ok(entry)
+    }
+
     async fn find_principal(
         &self,
         conn: &mut Ldap,
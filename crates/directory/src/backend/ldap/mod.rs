@@ -8,14 +8,19 @@ use deadpool::managed::Pool;
 use ldap3::{LdapConnSettings, ldap_escape};
 use store::Store;
 
+use crate::core::cache::GroupCache;
+
 pub mod config;
 pub mod lookup;
 pub mod pool;
+pub mod write;
 
 pub struct LdapDirectory {
     pool: Pool<LdapConnectionManager>,
     mappings: LdapMappings,
     auth_bind: AuthBind,
+    write: Option<LdapWrite>,
+    pub(crate) groups: GroupCache,
     pub(crate) data_store: Store,
 }
 
@@ -34,6 +39,7 @@ pub struct LdapMappings {
     attr_email_alias: Vec<String>,
     attr_quota: Vec<String>,
     attrs_principal: Vec<String>,
+    attrs_group: Vec<String>,
 }
 
 #[derive(Debug, Default)]
@@ -101,6 +107,23 @@ impl Bind {
     }
 }
 
+// Write-through of principal changes made through the management API
+pub(crate) struct LdapWrite {
+    bind: Bind,
+    attr_quota: Option<String>,
+    attr_email_address: Option<String>,
+    attr_email_alias: Option<String>,
+    attr_secret: Option<String>,
+    secret: SecretWrite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SecretWrite {
+    Disabled,
+    Attribute,
+    PasswordModify,
+}
+
 pub(crate) enum AuthBind {
     Template {
         template: LdapFilter,
@@ -0,0 +1,238 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::HashSet;
+
+use ldap3::{LdapConnAsync, Mod, Scope, SearchEntry, exop::PasswordModify};
+use trc::AddContext;
+
+use crate::{
+    IntoError,
+    backend::internal::{
+        PrincipalAction, PrincipalField, PrincipalUpdate, PrincipalValue, SpecialSecrets,
+    },
+};
+
+use super::{LdapDirectory, SecretWrite};
+
+impl LdapDirectory {
+    /// Returns true when changes to the field are written back to the LDAP server.
+    pub fn can_write(&self, field: PrincipalField) -> bool {
+        self.write.as_ref().is_some_and(|write| match field {
+            PrincipalField::Quota => write.attr_quota.is_some(),
+            PrincipalField::Emails => write.attr_email_address.is_some(),
+            PrincipalField::Secrets => match write.secret {
+                SecretWrite::Attribute => write.attr_secret.is_some(),
+                SecretWrite::PasswordModify => true,
+                SecretWrite::Disabled => false,
+            },
+            _ => false,
+        })
+    }
+
+    /// Writes the supported principal changes back to the LDAP entry of the principal.
+    pub async fn write(&self, name: &str, changes: &[PrincipalUpdate]) -> trc::Result<()> {
+        let Some(write) = &self.write else {
+            return Ok(());
+        };
+        if !changes.iter().any(|change| self.can_write(change.field)) {
+            return Ok(());
+        }
+
+        // Obtain the entry and its current addresses
+        let filter = self.mappings.filter_name.build(name);
+        let attrs = [&write.attr_email_address, &write.attr_email_alias]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        let entry = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .search(&self.mappings.base_dn, Scope::Subtree, &filter, &attrs)
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .0
+            .into_iter()
+            .next()
+            .map(SearchEntry::construct)
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .details("LDAP entry not found")
+                    .ctx(trc::Key::Details, filter.clone())
+            })?;
+        let current = |attr: &Option<String>| {
+            attr.as_ref()
+                .and_then(|attr| entry.attrs.get(attr))
+                .cloned()
+                .unwrap_or_default()
+        };
+        let mut addresses = current(&write.attr_email_address);
+        let mut aliases = current(&write.attr_email_alias);
+        let mut addresses_changed = false;
+        let mut mods = Vec::new();
+        let mut password = None;
+
+        for change in changes {
+            match (&change.action, change.field, &change.value) {
+                (PrincipalAction::Set, PrincipalField::Quota, value) => {
+                    let Some(attr) = &write.attr_quota else {
+                        continue;
+                    };
+                    let quota = match value {
+                        PrincipalValue::Integer(quota) => *quota,
+                        PrincipalValue::String(quota) => quota.parse().unwrap_or_default(),
+                        _ => continue,
+                    };
+                    mods.push(Mod::Replace(
+                        attr.clone(),
+                        if quota > 0 {
+                            HashSet::from([quota.to_string()])
+                        } else {
+                            HashSet::new()
+                        },
+                    ));
+                }
+                (PrincipalAction::Set, PrincipalField::Emails, value) => {
+                    let mut emails = match value {
+                        PrincipalValue::String(email) => vec![email.to_lowercase()],
+                        PrincipalValue::StringList(emails) => {
+                            emails.iter().map(|email| email.to_lowercase()).collect()
+                        }
+                        _ => continue,
+                    };
+
+                    // The first address is the primary one, the rest are aliases
+                    if write.attr_email_alias.is_some() && !emails.is_empty() {
+                        aliases = emails.split_off(1);
+                    } else {
+                        aliases.clear();
+                    }
+                    addresses = emails;
+                    addresses_changed = true;
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::Emails,
+                    PrincipalValue::String(email),
+                ) => {
+                    let email = email.to_lowercase();
+                    if !addresses.contains(&email) && !aliases.contains(&email) {
+                        if write.attr_email_alias.is_some() && !addresses.is_empty() {
+                            aliases.push(email);
+                        } else {
+                            addresses.push(email);
+                        }
+                        addresses_changed = true;
+                    }
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::Emails,
+                    PrincipalValue::String(email),
+                ) => {
+                    let email = email.to_lowercase();
+                    addresses.retain(|v| !v.eq_ignore_ascii_case(&email));
+                    aliases.retain(|v| !v.eq_ignore_ascii_case(&email));
+                    addresses_changed = true;
+                }
+                (
+                    PrincipalAction::Set | PrincipalAction::AddItem,
+                    PrincipalField::Secrets,
+                    value,
+                ) if write.secret != SecretWrite::Disabled => {
+                    // Only passwords are written, app passwords and OTP URLs stay local
+                    password = match value {
+                        PrincipalValue::String(secret) => Some(secret),
+                        PrincipalValue::StringList(secrets) => {
+                            secrets.iter().find(|s| s.is_password())
+                        }
+                        _ => None,
+                    }
+                    .filter(|secret| !secret.is_empty() && secret.is_password())
+                    .or(password);
+                }
+                _ => {}
+            }
+        }
+
+        if addresses_changed {
+            for (attr, values) in [
+                (&write.attr_email_address, addresses),
+                (&write.attr_email_alias, aliases),
+            ] {
+                if let Some(attr) = attr {
+                    mods.push(Mod::Replace(attr.clone(), values.into_iter().collect()));
+                }
+            }
+        }
+        if let (Some(password), SecretWrite::Attribute, Some(attr)) =
+            (password, write.secret, &write.attr_secret)
+        {
+            mods.push(Mod::Replace(
+                attr.clone(),
+                HashSet::from([password.to_string()]),
+            ));
+        }
+        if mods.is_empty() && password.is_none() {
+            return Ok(());
+        }
+
+        // Changes are written using a separate connection bound with write permissions
+        let (conn, mut ldap) = LdapConnAsync::with_settings(
+            self.pool.manager().settings.clone(),
+            &self.pool.manager().address,
+        )
+        .await
+        .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+        ldap3::drive!(conn);
+        ldap.simple_bind(&write.bind.dn, &write.bind.password)
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+
+        let attributes = mods
+            .iter()
+            .filter_map(|m| match m {
+                Mod::Replace(attr, _) => Some(trc::Value::from(attr.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !mods.is_empty() {
+            ldap.modify(&entry.dn, mods)
+                .await
+                .map_err(|err| err.into_error().caused_by(trc::location!()))?
+                .success()
+                .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+        }
+        if let (Some(password), SecretWrite::PasswordModify) = (password, write.secret) {
+            ldap.extended(PasswordModify {
+                user_id: Some(entry.dn.as_str()),
+                old_pass: None,
+                new_pass: Some(password.as_str()),
+            })
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+        }
+        let _ = ldap.unbind().await;
+
+        trc::event!(
+            Store(trc::StoreEvent::LdapWrite),
+            Details = entry.dn,
+            Result = attributes
+        );
+
+        Ok(())
+    }
+}
@@ -4,13 +4,16 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::Duration;
+use std::{sync::Mutex, time::Duration};
 
 use base64::{Engine, engine::general_purpose};
 use store::Store;
-use utils::config::{Config, utils::AsKey};
+use utils::{
+    cache::CacheWithTtl,
+    config::{Config, utils::AsKey},
+};
 
-use super::{Authentication, EndpointType, OpenIdConfig, OpenIdDirectory};
+use super::{Authentication, EndpointType, JwtValidation, OpenIdConfig, OpenIdDirectory};
 
 impl OpenIdDirectory {
     pub fn from_config(config: &mut Config, prefix: impl AsKey, data_store: Store) -> Option<Self> {
@@ -46,10 +49,32 @@ impl OpenIdDirectory {
                 }
             },
             "userinfo" => EndpointType::UserInfo,
+            "jwks" => {
+                let audience = config
+                    .values((&prefix, "jwt.audience"))
+                    .map(|(_, v)| v.to_string())
+                    .collect::<Vec<_>>();
+                if audience.is_empty() {
+                    config.new_build_error(
+                        (&prefix, "jwt.audience"),
+                        "At least one audience must be specified",
+                    );
+                    return None;
+                }
+
+                EndpointType::Jwt(JwtValidation {
+                    issuer: config.value_require((&prefix, "jwt.issuer"))?.to_string(),
+                    audience,
+                    leeway: config
+                        .property_or_default::<Duration>((&prefix, "jwt.leeway"), "1m")
+                        .unwrap_or_else(|| Duration::from_secs(60))
+                        .as_secs(),
+                })
+            }
             _ => {
                 config.new_build_error(
                     (&prefix, "endpoint.method"),
-                    "Invalid endpoint method, must be 'introspect' or 'userinfo'",
+                    "Invalid endpoint method, must be 'introspect', 'userinfo' or 'jwks'",
                 );
                 return None;
             }
@@ -69,8 +94,17 @@ impl OpenIdDirectory {
                 full_name_field: config
                     .value((&prefix, "fields.full-name"))
                     .map(|v| v.to_string()),
+                jwks_ttl: config
+                    .property_or_default::<Duration>((&prefix, "jwt.keys.cache-ttl"), "1h")
+                    .unwrap_or_else(|| Duration::from_secs(3600)),
+                jwks_min_refresh: config
+                    .property_or_default::<Duration>((&prefix, "jwt.keys.min-refresh"), "30s")
+                    .unwrap_or_else(|| Duration::from_secs(30)),
             },
             data_store,
+            jwks: CacheWithTtl::new(16, 1024 * 1024),
+            jwks_unknown: CacheWithTtl::new(16, 1024 * 1024),
+            jwks_last_fetch: Mutex::new(None),
         })
     }
 }
@@ -0,0 +1,340 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use reqwest::StatusCode;
+use rustls_pki_types::{AlgorithmIdentifier, SignatureVerificationAlgorithm, alg_id};
+use serde::de::DeserializeOwned;
+use trc::AuthEvent;
+use utils::cache::CacheItemWeight;
+
+use super::{JwtValidation, OpenIdDirectory, lookup::OpenIdResponse};
+
+static SIGNATURE_ALGORITHMS: LazyLock<&'static [&'static dyn SignatureVerificationAlgorithm]> =
+    LazyLock::new(|| {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .all
+    });
+
+#[derive(Debug, Clone)]
+pub struct JsonWebKey {
+    key_alg: AlgorithmIdentifier,
+    public_key: Vec<u8>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct JsonWebKeySet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    use_: Option<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+impl OpenIdDirectory {
+    pub(super) async fn validate_jwt(
+        &self,
+        token: &str,
+        validation: &JwtValidation,
+    ) -> trc::Result<OpenIdResponse> {
+        // Parse token
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid_token("Malformed JWT"));
+        };
+        let jwt_header = decode_json::<JwtHeader>(header)?;
+        let (key_alg, signature_alg) = jws_algorithm(&jwt_header.alg)
+            .ok_or_else(|| invalid_token("Unsupported JWT algorithm"))?;
+        let kid = jwt_header
+            .kid
+            .ok_or_else(|| invalid_token("Missing key id in JWT header"))?;
+        let mut signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid_token("Invalid JWT signature encoding"))?;
+
+        // ECDSA signatures are encoded as r || s in JWS
+        if key_alg == alg_id::ECDSA_P256 || key_alg == alg_id::ECDSA_P384 {
+            signature = ecdsa_signature_to_der(&signature)
+                .ok_or_else(|| invalid_token("Invalid JWT signature"))?;
+        }
+
+        // Obtain the signing key, refreshing the key set on unknown key ids
+        // at most once per refresh interval
+        let key = match self.jwks.get(&kid) {
+            Some(key) => key,
+            None if self.jwks_unknown.get(&kid).is_none() && self.try_begin_jwks_fetch() => {
+                self.fetch_jwks().await?;
+                match self.jwks.get(&kid) {
+                    Some(key) => key,
+                    None => {
+                        self.jwks_unknown
+                            .insert(kid, true, self.config.jwks_min_refresh);
+                        return Err(invalid_token("Unknown JWT key id"));
+                    }
+                }
+            }
+            None => return Err(invalid_token("Unknown JWT key id")),
+        };
+        if !key.verify(
+            signature_alg,
+            &token.as_bytes()[..header.len() + payload.len() + 1],
+            &signature,
+        ) {
+            return Err(invalid_token("Invalid JWT signature"));
+        }
+
+        // Validate claims
+        let claims = decode_json::<OpenIdResponse>(payload)?;
+        if claims.get("iss").and_then(|v| v.as_str()) != Some(validation.issuer.as_str()) {
+            return Err(invalid_token("JWT issuer mismatch"));
+        }
+        let has_audience = match claims.get("aud") {
+            Some(serde_json::Value::String(aud)) => validation.audience.contains(aud),
+            Some(serde_json::Value::Array(auds)) => auds
+                .iter()
+                .filter_map(|aud| aud.as_str())
+                .any(|aud| validation.audience.iter().any(|v| v == aud)),
+            _ => false,
+        };
+        if !has_audience {
+            return Err(invalid_token("JWT audience mismatch"));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        match claims.get("exp").and_then(|v| v.as_u64()) {
+            Some(exp) if exp.saturating_add(validation.leeway) >= now => {}
+            _ => return Err(invalid_token("JWT has expired")),
+        }
+        if claims
+            .get("nbf")
+            .and_then(|v| v.as_u64())
+            .is_some_and(|nbf| nbf > now.saturating_add(validation.leeway))
+        {
+            return Err(invalid_token("JWT is not yet valid"));
+        }
+
+        Ok(claims)
+    }
+
+    fn try_begin_jwks_fetch(&self) -> bool {
+        let mut last_fetch = self
+            .jwks_last_fetch
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if last_fetch.is_some_and(|last| last.elapsed() < self.config.jwks_min_refresh) {
+            false
+        } else {
+            *last_fetch = Some(Instant::now());
+            true
+        }
+    }
+
+    async fn fetch_jwks(&self) -> trc::Result<()> {
+        let response = self
+            .http_client()?
+            .get(&self.config.endpoint)
+            .send()
+            .await
+            .map_err(|err| {
+                AuthEvent::Error
+                    .into_err()
+                    .reason(err)
+                    .details("HTTP request failed")
+            })?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let response = response.bytes().await.map_err(|err| {
+                    AuthEvent::Error
+                        .into_err()
+                        .reason(err)
+                        .details("Failed to read JWKS response")
+                })?;
+                let key_set =
+                    serde_json::from_slice::<JsonWebKeySet>(&response).map_err(|err| {
+                        AuthEvent::Error
+                            .into_err()
+                            .reason(err)
+                            .details("Failed to deserialize JWKS response")
+                    })?;
+
+                for (kid, key) in key_set.keys.into_iter().filter_map(Jwk::into_key) {
+                    self.jwks.insert(kid, Arc::new(key), self.config.jwks_ttl);
+                }
+
+                Ok(())
+            }
+            other => Err(trc::AuthEvent::Error
+                .into_err()
+                .code(other.as_u16())
+                .ctx(trc::Key::Reason, response.text().await.unwrap_or_default())
+                .details("Unexpected status code")),
+        }
+    }
+}
+
+impl JsonWebKey {
+    fn verify(&self, signature_alg: AlgorithmIdentifier, message: &[u8], signature: &[u8]) -> bool {
+        SIGNATURE_ALGORITHMS
+            .iter()
+            .filter(|alg| {
+                alg.public_key_alg_id() == self.key_alg && alg.signature_alg_id() == signature_alg
+            })
+            .any(|alg| {
+                alg.verify_signature(&self.public_key, message, signature)
+                    .is_ok()
+            })
+    }
+}
+
+impl Jwk {
+    fn into_key(self) -> Option<(String, JsonWebKey)> {
+        if self.use_.as_deref().is_some_and(|use_| use_ != "sig") {
+            return None;
+        }
+        let kid = self.kid?;
+        let decode = |value: Option<String>| URL_SAFE_NO_PAD.decode(value?).ok();
+
+        let key = match (self.kty.as_str(), self.crv.as_deref()) {
+            ("RSA", _) => {
+                let mut key = Vec::new();
+                der_integer(&mut key, &decode(self.n)?);
+                der_integer(&mut key, &decode(self.e)?);
+                JsonWebKey {
+                    key_alg: alg_id::RSA_ENCRYPTION,
+                    public_key: der_sequence(&key),
+                }
+            }
+            ("EC", Some(crv @ ("P-256" | "P-384"))) => {
+                let (key_alg, len) = if crv == "P-256" {
+                    (alg_id::ECDSA_P256, 32)
+                } else {
+                    (alg_id::ECDSA_P384, 48)
+                };
+                let (x, y) = (decode(self.x)?, decode(self.y)?);
+                if x.len() != len || y.len() != len {
+                    return None;
+                }
+                let mut public_key = Vec::with_capacity(1 + len * 2);
+                public_key.push(0x04);
+                public_key.extend_from_slice(&x);
+                public_key.extend_from_slice(&y);
+                JsonWebKey {
+                    key_alg,
+                    public_key,
+                }
+            }
+            ("OKP", Some("Ed25519")) => JsonWebKey {
+                key_alg: alg_id::ED25519,
+                public_key: decode(self.x).filter(|x| x.len() == 32)?,
+            },
+            _ => return None,
+        };
+
+        Some((kid, key))
+    }
+}
+
+impl CacheItemWeight for JsonWebKey {
+    fn weight(&self) -> u64 {
+        (std::mem::size_of::<JsonWebKey>() + self.public_key.len()) as u64
+    }
+}
+
+fn jws_algorithm(alg: &str) -> Option<(AlgorithmIdentifier, AlgorithmIdentifier)> {
+    match alg {
+        "RS256" => Some((alg_id::RSA_ENCRYPTION, alg_id::RSA_PKCS1_SHA256)),
+        "RS384" => Some((alg_id::RSA_ENCRYPTION, alg_id::RSA_PKCS1_SHA384)),
+        "RS512" => Some((alg_id::RSA_ENCRYPTION, alg_id::RSA_PKCS1_SHA512)),
+        "PS256" => Some((alg_id::RSA_ENCRYPTION, alg_id::RSA_PSS_SHA256)),
+        "PS384" => Some((alg_id::RSA_ENCRYPTION, alg_id::RSA_PSS_SHA384)),
+        "PS512" => Some((alg_id::RSA_ENCRYPTION, alg_id::RSA_PSS_SHA512)),
+        "ES256" => Some((alg_id::ECDSA_P256, alg_id::ECDSA_SHA256)),
+        "ES384" => Some((alg_id::ECDSA_P384, alg_id::ECDSA_SHA384)),
+        "EdDSA" => Some((alg_id::ED25519, alg_id::ED25519)),
+        _ => None,
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(value: &str) -> trc::Result<T> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .ok()
+        .and_then(|value| serde_json::from_slice(&value).ok())
+        .ok_or_else(|| invalid_token("Malformed JWT"))
+}
+
+fn invalid_token(details: &'static str) -> trc::Error {
+    AuthEvent::Failed.into_err().code(401).details(details)
+}
+
+fn ecdsa_signature_to_der(signature: &[u8]) -> Option<Vec<u8>> {
+    if signature.is_empty() || signature.len() % 2 != 0 {
+        return None;
+    }
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let mut value = Vec::with_capacity(signature.len() + 6);
+    der_integer(&mut value, r);
+    der_integer(&mut value, s);
+    Some(der_sequence(&value))
+}
+
+fn der_integer(out: &mut Vec<u8>, bytes: &[u8]) {
+    let bytes = bytes
+        .iter()
+        .position(|&b| b != 0)
+        .map_or(&[0u8][..], |pos| &bytes[pos..]);
+    let pad = bytes[0] & 0x80 != 0;
+    out.push(0x02);
+    der_length(out, bytes.len() + pad as usize);
+    if pad {
+        out.push(0);
+    }
+    out.extend_from_slice(bytes);
+}
+
+fn der_sequence(value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len() + 4);
+    out.push(0x30);
+    der_length(&mut out, value.len());
+    out.extend_from_slice(value);
+    out
+}
+
+fn der_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let bytes = &bytes[bytes.iter().position(|&b| b != 0).unwrap_or(0)..];
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+}
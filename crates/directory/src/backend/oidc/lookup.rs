@@ -24,7 +24,7 @@ use crate::{
 
 use super::{OpenIdConfig, OpenIdDirectory};
 
-type OpenIdResponse = HashMap<String, serde_json::Value>;
+pub(super) type OpenIdResponse = HashMap<String, serde_json::Value>;
 
 impl OpenIdDirectory {
     pub async fn query(
@@ -34,109 +34,124 @@ impl OpenIdDirectory {
     ) -> trc::Result<Option<Principal>> {
         match &by {
             QueryBy::Credentials(Credentials::OAuthBearer { token }) => {
-                // Send request
-                #[cfg(feature = "test_mode")]
-                let client = reqwest::Client::builder().danger_accept_invalid_certs(true);
+                // Obtain the claims, either from a signed JWT or from the OIDC server
+                let external_principal = match &self.config.endpoint_type {
+                    EndpointType::Jwt(validation) => self.validate_jwt(token, validation).await?,
+                    EndpointType::Introspect(authentication) => {
+                        self.fetch_claims(token, Some(authentication)).await?
+                    }
+                    EndpointType::UserInfo => self.fetch_claims(token, None).await?,
+                }
+                .build_principal(&self.config)?;
+
+                // Fetch principal
+                let id = self
+                    .data_store
+                    .get_or_create_principal_id(external_principal.name(), Type::Individual)
+                    .await
+                    .caused_by(trc::location!())?;
+                let mut principal = self
+                    .data_store
+                    .query(QueryBy::Id(id), return_member_of)
+                    .await
+                    .caused_by(trc::location!())?
+                    .ok_or_else(|| manage::not_found(id).caused_by(trc::location!()))?;
+
+                // Keep the internal store up to date with the OIDC server
+                let changes = principal.update_external(external_principal);
+                if !changes.is_empty() {
+                    self.data_store
+                        .update_principal(
+                            UpdatePrincipal::by_id(principal.id)
+                                .with_updates(changes)
+                                .create_domains(),
+                        )
+                        .await
+                        .caused_by(trc::location!())?;
+                }
 
-                #[cfg(not(feature = "test_mode"))]
-                let client = reqwest::Client::builder();
+                Ok(Some(principal))
+            }
+            _ => self.data_store.query(by, return_member_of).await,
+        }
+    }
 
+    async fn fetch_claims(
+        &self,
+        token: &str,
+        introspect: Option<&Authentication>,
+    ) -> trc::Result<OpenIdResponse> {
+        // Send request
+        let client = self.http_client()?;
+        let client = match introspect {
+            Some(authentication) => {
                 let client = client
-                    .timeout(self.config.endpoint_timeout)
-                    .build()
-                    .map_err(|err| {
-                        AuthEvent::Error
-                            .into_err()
-                            .reason(err)
-                            .details("Failed to build client")
-                    })?;
-
-                let client = match &self.config.endpoint_type {
-                    EndpointType::UserInfo => client.get(&self.config.endpoint).bearer_auth(token),
-                    EndpointType::Introspect(authentication) => {
-                        let client = client.post(&self.config.endpoint).form(&[
-                            ("token", token.as_str()),
-                            ("token_type_hint", "access_token"),
-                        ]);
-                        match authentication {
-                            Authentication::Header(header) => client.header(AUTHORIZATION, header),
-                            Authentication::Bearer => client.bearer_auth(token),
-                            Authentication::None => client,
-                        }
-                    }
-                };
+                    .post(&self.config.endpoint)
+                    .form(&[("token", token), ("token_type_hint", "access_token")]);
+                match authentication {
+                    Authentication::Header(header) => client.header(AUTHORIZATION, header),
+                    Authentication::Bearer => client.bearer_auth(token),
+                    Authentication::None => client,
+                }
+            }
+            None => client.get(&self.config.endpoint).bearer_auth(token),
+        };
 
-                let response = client.send().await.map_err(|err| {
+        let response = client.send().await.map_err(|err| {
+            AuthEvent::Error
+                .into_err()
+                .reason(err)
+                .details("HTTP request failed")
+        })?;
+
+        match response.status() {
+            StatusCode::OK => {
+                // Fetch response
+                let response = response.bytes().await.map_err(|err| {
                     AuthEvent::Error
                         .into_err()
                         .reason(err)
-                        .details("HTTP request failed")
+                        .details("Failed to read OIDC response")
                 })?;
 
-                match response.status() {
-                    StatusCode::OK => {
-                        // Fetch response
-                        let response = response.bytes().await.map_err(|err| {
-                            AuthEvent::Error
-                                .into_err()
-                                .reason(err)
-                                .details("Failed to read OIDC response")
-                        })?;
-
-                        // Deserialize response
-                        let external_principal =
-                            serde_json::from_slice::<OpenIdResponse>(&response)
-                                .map_err(|err| {
-                                    AuthEvent::Error
-                                        .into_err()
-                                        .reason(err)
-                                        .details("Failed to deserialize OIDC response")
-                                })?
-                                .build_principal(&self.config)?;
-
-                        // Fetch principal
-                        let id = self
-                            .data_store
-                            .get_or_create_principal_id(external_principal.name(), Type::Individual)
-                            .await
-                            .caused_by(trc::location!())?;
-                        let mut principal = self
-                            .data_store
-                            .query(QueryBy::Id(id), return_member_of)
-                            .await
-                            .caused_by(trc::location!())?
-                            .ok_or_else(|| manage::not_found(id).caused_by(trc::location!()))?;
-
-                        // Keep the internal store up to date with the OIDC server
-                        let changes = principal.update_external(external_principal);
-                        if !changes.is_empty() {
-                            self.data_store
-                                .update_principal(
-                                    UpdatePrincipal::by_id(principal.id)
-                                        .with_updates(changes)
-                                        .create_domains(),
-                                )
-                                .await
-                                .caused_by(trc::location!())?;
-                        }
-
-                        Ok(Some(principal))
-                    }
-                    StatusCode::UNAUTHORIZED => Err(trc::AuthEvent::Failed
-                        .into_err()
-                        .code(401)
-                        .details("Unauthorized")),
-                    other => Err(trc::AuthEvent::Error
+                // Deserialize response
+                serde_json::from_slice::<OpenIdResponse>(&response).map_err(|err| {
+                    AuthEvent::Error
                         .into_err()
-                        .code(other.as_u16())
-                        .ctx(trc::Key::Reason, response.text().await.unwrap_or_default())
-                        .details("Unexpected status code")),
-                }
+                        .reason(err)
+                        .details("Failed to deserialize OIDC response")
+                })
             }
-            _ => self.data_store.query(by, return_member_of).await,
+            StatusCode::UNAUTHORIZED => Err(trc::AuthEvent::Failed
+                .into_err()
+                .code(401)
+                .details("Unauthorized")),
+            other => Err(trc::AuthEvent::Error
+                .into_err()
+                .code(other.as_u16())
+                .ctx(trc::Key::Reason, response.text().await.unwrap_or_default())
+                .details("Unexpected status code")),
         }
     }
 
+    pub(super) fn http_client(&self) -> trc::Result<reqwest::Client> {
+        #[cfg(feature = "test_mode")]
+        let client = reqwest::Client::builder().danger_accept_invalid_certs(true);
+
+        #[cfg(not(feature = "test_mode"))]
+        let client = reqwest::Client::builder();
+
+        client
+            .timeout(self.config.endpoint_timeout)
+            .build()
+            .map_err(|err| {
+                AuthEvent::Error
+                    .into_err()
+                    .reason(err)
+                    .details("Failed to build client")
+            })
+    }
+
     pub async fn email_to_id(&self, address: &str) -> trc::Result<Option<u32>> {
         self.data_store.email_to_id(address).await
     }
@@ -5,15 +5,25 @@
  */
 
 pub mod config;
+pub mod jwt;
 pub mod lookup;
 
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use store::Store;
+use utils::cache::CacheWithTtl;
+
+use jwt::JsonWebKey;
 
 pub struct OpenIdDirectory {
     config: OpenIdConfig,
     pub(crate) data_store: Store,
+    jwks: CacheWithTtl<String, Arc<JsonWebKey>>,
+    jwks_unknown: CacheWithTtl<String, bool>,
+    jwks_last_fetch: Mutex<Option<Instant>>,
 }
 
 struct OpenIdConfig {
@@ -23,12 +33,15 @@ struct OpenIdConfig {
     pub email_field: String,
     pub username_field: Option<String>,
     pub full_name_field: Option<String>,
+    pub jwks_ttl: Duration,
+    pub jwks_min_refresh: Duration,
 }
 
 #[derive(Debug)]
 pub enum EndpointType {
     Introspect(Authentication),
     UserInfo,
+    Jwt(JwtValidation),
 }
 
 #[derive(Debug)]
@@ -37,3 +50,10 @@ pub enum Authentication {
     Bearer,
     None,
 }
+
+#[derive(Debug)]
+pub struct JwtValidation {
+    pub issuer: String,
+    pub audience: Vec<String>,
+    pub leeway: u64,
+}
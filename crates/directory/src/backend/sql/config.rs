@@ -7,6 +7,8 @@
 use store::{Store, Stores};
 use utils::config::{Config, utils::AsKey};
 
+use crate::core::cache::GroupCache;
+
 use super::{SqlDirectory, SqlMappings};
 
 impl SqlDirectory {
@@ -67,6 +69,7 @@ impl SqlDirectory {
         Some(SqlDirectory {
             sql_store,
             mappings,
+            groups: GroupCache::from_config(config, &prefix),
             data_store,
         })
     }
@@ -14,8 +14,10 @@ use crate::{
             manage::{self, ManageDirectory, UpdatePrincipal},
         },
     },
+    core::cache::GroupEntry,
 };
 
+use ahash::AHashSet;
 use mail_send::Credentials;
 use store::{NamedRows, Rows, Value};
 use trc::AddContext;
@@ -130,27 +132,49 @@ impl SqlDirectory {
             return Ok(None);
         };
 
-        // Obtain members
+        // Obtain members, following the memberships of groups up to the configured depth
         if return_member_of && !self.mappings.query_members.is_empty() {
             let mut data = Vec::new();
-            for row in self
-                .sql_store
-                .sql_query::<Rows>(
-                    &self.mappings.query_members,
-                    vec![external_principal.name().into()],
-                )
+            let mut seen = AHashSet::new();
+            let mut groups = self
+                .query_member_of(external_principal.name())
                 .await
-                .caused_by(trc::location!())?
-                .rows
-            {
-                if let Some(Value::Text(account_id)) = row.values.first() {
+                .caused_by(trc::location!())?;
+            for depth in 0..=self.groups.max_depth {
+                let mut parents = Vec::new();
+                for group in groups {
+                    if !seen.insert(group.clone()) {
+                        continue;
+                    }
                     data.push(
                         self.data_store
-                            .get_or_create_principal_id(account_id, Type::Group)
+                            .get_or_create_principal_id(&group, Type::Group)
                             .await
                             .caused_by(trc::location!())?,
                     );
+                    if depth < self.groups.max_depth {
+                        let entry = if let Some(entry) = self.groups.get(&group) {
+                            entry
+                        } else {
+                            let member_of = self
+                                .query_member_of(&group)
+                                .await
+                                .caused_by(trc::location!())?;
+                            self.groups.insert(
+                                group.clone(),
+                                GroupEntry {
+                                    name: group,
+                                    member_of,
+                                },
+                            )
+                        };
+                        parents.extend(entry.member_of.iter().cloned());
+                    }
+                }
+                if parents.is_empty() {
+                    break;
                 }
+                groups = parents;
             }
             if !data.is_empty() {
                 external_principal.data.push(PrincipalData::MemberOf(data));
@@ -261,6 +285,20 @@ impl SqlDirectory {
     pub async fn is_local_domain(&self, domain: &str) -> trc::Result<bool> {
         self.data_store.is_local_domain(domain).await
     }
+
+    async fn query_member_of(&self, name: &str) -> trc::Result<Vec<String>> {
+        Ok(self
+            .sql_store
+            .sql_query::<Rows>(&self.mappings.query_members, vec![name.into()])
+            .await?
+            .rows
+            .into_iter()
+            .filter_map(|row| match row.values.into_iter().next() {
+                Some(Value::Text(group)) if !group.is_empty() => Some(group.into_owned()),
+                _ => None,
+            })
+            .collect())
+    }
 }
 
 impl SqlMappings {
@@ -6,12 +6,15 @@
 
 use store::Store;
 
+use crate::core::cache::GroupCache;
+
 pub mod config;
 pub mod lookup;
 
 pub struct SqlDirectory {
     sql_store: Store,
     mappings: SqlMappings,
+    pub(crate) groups: GroupCache,
     pub(crate) data_store: Store,
 }
 
@@ -0,0 +1,229 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// App passwords are stored as "$app$<name>$<hash>" secrets. Scoped app passwords
+// append their restrictions to the name, for example
+// "$app$phone?protocols=imap,smtp&ips=192.168.1.0/24$<hash>".
+
+use std::{fmt::Write, net::IpAddr};
+
+use mail_builder::encoders::base64::base64_encode;
+use sha2::{Digest, Sha256};
+use store::rand::{Rng, distr::Alphanumeric, rng};
+use utils::config::{ipmask::IpAddrMask, utils::ParseValue};
+
+use crate::Principal;
+
+use super::secret::verify_secret_hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthProtocol {
+    Imap,
+    Pop3,
+    Smtp,
+    ManageSieve,
+    Http,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AppPasswordScope {
+    pub protocols: Vec<AuthProtocol>,
+    pub ips: Vec<IpAddrMask>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPassword<'x> {
+    pub name: &'x str,
+    pub scope: AppPasswordScope,
+    pub hash: &'x str,
+}
+
+impl<'x> AppPassword<'x> {
+    pub fn parse(secret: &'x str) -> Option<Self> {
+        let (name, hash) = secret.strip_prefix("$app$")?.split_once('$')?;
+        let (name, scope) = match name.split_once('?') {
+            Some((name, scope)) => (name, AppPasswordScope::parse(scope).unwrap_or_default()),
+            None => (name, AppPasswordScope::default()),
+        };
+
+        Some(AppPassword { name, scope, hash })
+    }
+
+    pub fn build(name: &str, scope: &AppPasswordScope, hash: &str) -> String {
+        if scope.is_empty() {
+            format!("$app${name}${hash}")
+        } else {
+            format!("$app${name}?{}${hash}", scope.encode())
+        }
+    }
+
+    /// Generates a random app password, returning it along with its hash.
+    pub fn generate() -> (String, String) {
+        let password = rng()
+            .sample_iter(Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect::<String>();
+        let hash = format!(
+            "{{SHA256}}{}",
+            String::from_utf8(base64_encode(&Sha256::digest(password.as_bytes())[..]).unwrap())
+                .unwrap()
+        );
+
+        (password, hash)
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains(['$', '?'])
+    }
+}
+
+impl AppPasswordScope {
+    pub fn is_empty(&self) -> bool {
+        self.protocols.is_empty() && self.ips.is_empty()
+    }
+
+    /// Returns true when the protocol and address are allowed by the scope,
+    /// empty restrictions allow any value.
+    pub fn allows(&self, protocol: Option<AuthProtocol>, remote_ip: &IpAddr) -> bool {
+        (self.protocols.is_empty() || protocol.is_some_and(|p| self.protocols.contains(&p)))
+            && (self.ips.is_empty() || self.ips.iter().any(|ip| ip.matches(remote_ip)))
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut scope = AppPasswordScope::default();
+        for (key, values) in value.split('&').filter_map(|param| param.split_once('=')) {
+            for value in values.split(',').filter(|v| !v.is_empty()) {
+                match key {
+                    "protocols" => {
+                        let protocol = AuthProtocol::parse(value)
+                            .ok_or_else(|| format!("Invalid protocol {value:?}"))?;
+                        if !scope.protocols.contains(&protocol) {
+                            scope.protocols.push(protocol);
+                        }
+                    }
+                    "ips" => {
+                        scope.ips.push(IpAddrMask::parse_value(value)?);
+                    }
+                    _ => return Err(format!("Invalid scope parameter {key:?}")),
+                }
+            }
+        }
+
+        Ok(scope)
+    }
+
+    pub fn encode(&self) -> String {
+        let mut result = String::new();
+        if !self.protocols.is_empty() {
+            result.push_str("protocols=");
+            for (pos, protocol) in self.protocols.iter().enumerate() {
+                if pos > 0 {
+                    result.push(',');
+                }
+                result.push_str(protocol.as_str());
+            }
+        }
+        if !self.ips.is_empty() {
+            if !result.is_empty() {
+                result.push('&');
+            }
+            result.push_str("ips=");
+            for (pos, ip) in self.ips.iter().enumerate() {
+                if pos > 0 {
+                    result.push(',');
+                }
+                let _ = match ip {
+                    IpAddrMask::V4 { addr, mask } => write!(result, "{addr}/{}", mask.count_ones()),
+                    IpAddrMask::V6 { addr, mask } => write!(result, "{addr}/{}", mask.count_ones()),
+                };
+            }
+        }
+
+        result
+    }
+}
+
+impl AuthProtocol {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "imap" => Some(AuthProtocol::Imap),
+            "pop3" => Some(AuthProtocol::Pop3),
+            "smtp" => Some(AuthProtocol::Smtp),
+            "sieve" => Some(AuthProtocol::ManageSieve),
+            "http" => Some(AuthProtocol::Http),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthProtocol::Imap => "imap",
+            AuthProtocol::Pop3 => "pop3",
+            AuthProtocol::Smtp => "smtp",
+            AuthProtocol::ManageSieve => "sieve",
+            AuthProtocol::Http => "http",
+        }
+    }
+}
+
+impl Principal {
+    /// Returns the scope of the app password matching the secret, if any.
+    pub async fn app_password(&self, code: &str) -> trc::Result<Option<AppPasswordScope>> {
+        for secret in &self.secrets {
+            if let Some(app) = AppPassword::parse(secret) {
+                if verify_secret_hash(app.hash, code).await? {
+                    return Ok(Some(app.scope));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn has_scoped_app_passwords(&self) -> bool {
+        self.secrets
+            .iter()
+            .any(|secret| AppPassword::parse(secret).is_some_and(|app| !app.scope.is_empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_password_scope() {
+        let scope = AppPasswordScope::parse("protocols=IMAP,smtp&ips=192.168.1.0/24").unwrap();
+        let secret = AppPassword::build("phone", &scope, "{SHA256}hash");
+        assert_eq!(
+            secret,
+            "$app$phone?protocols=imap,smtp&ips=192.168.1.0/24${SHA256}hash"
+        );
+        assert_eq!(
+            AppPassword::parse(&secret),
+            Some(AppPassword {
+                name: "phone",
+                scope: scope.clone(),
+                hash: "{SHA256}hash",
+            })
+        );
+        assert_eq!(
+            AppPassword::parse("$app$laptop$hash").map(|app| app.scope.is_empty()),
+            Some(true)
+        );
+
+        let lan: IpAddr = "192.168.1.20".parse().unwrap();
+        let wan: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(scope.allows(Some(AuthProtocol::Imap), &lan));
+        assert!(!scope.allows(Some(AuthProtocol::Pop3), &lan));
+        assert!(!scope.allows(Some(AuthProtocol::Smtp), &wan));
+        assert!(!scope.allows(None, &lan));
+        assert!(AppPasswordScope::default().allows(None, &wan));
+        assert!(AppPasswordScope::parse("protocols=ftp").is_err());
+        assert!(!AppPassword::is_valid_name("a$b"));
+    }
+}
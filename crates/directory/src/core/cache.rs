@@ -4,10 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use utils::{
-    cache::CacheWithTtl,
+    cache::{CacheItemWeight, CacheWithTtl},
     config::{Config, utils::AsKey},
 };
 
@@ -20,6 +20,19 @@ pub struct CachedDirectory {
     ttl_neg: Duration,
 }
 
+// Group entries of external directories, used to resolve nested groups
+pub struct GroupCache {
+    pub max_depth: usize,
+    entries: CacheWithTtl<String, Arc<GroupEntry>>,
+    ttl: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct GroupEntry {
+    pub name: String,
+    pub member_of: Vec<String>,
+}
+
 impl CachedDirectory {
     pub fn try_from_config(config: &mut Config, prefix: impl AsKey) -> Option<Self> {
         let prefix = prefix.as_key();
@@ -65,3 +78,48 @@ impl CachedDirectory {
         );
     }
 }
+
+impl GroupCache {
+    pub fn from_config(config: &mut Config, prefix: impl AsKey) -> Self {
+        let prefix = prefix.as_key();
+        let cache_size = config
+            .property_or_default((&prefix, "groups.cache.size"), "1048576")
+            .unwrap_or(1048576);
+
+        GroupCache {
+            max_depth: config
+                .property_or_default((&prefix, "groups.max-depth"), "0")
+                .unwrap_or_default(),
+            entries: CacheWithTtl::new(100, cache_size),
+            ttl: config
+                .property_or_default((&prefix, "groups.cache.ttl"), "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+        }
+    }
+
+    pub fn get(&self, group: &str) -> Option<Arc<GroupEntry>> {
+        self.entries.get(group)
+    }
+
+    pub fn insert(&self, group: String, entry: GroupEntry) -> Arc<GroupEntry> {
+        let entry = Arc::new(entry);
+        self.entries.insert(group, entry.clone(), self.ttl);
+        entry
+    }
+
+    pub fn invalidate(&self) {
+        self.entries.clear();
+    }
+}
+
+impl CacheItemWeight for GroupEntry {
+    fn weight(&self) -> u64 {
+        (std::mem::size_of::<GroupEntry>()
+            + self.name.len()
+            + self
+                .member_of
+                .iter()
+                .map(|group| group.len() + std::mem::size_of::<String>())
+                .sum::<usize>()) as u64
+    }
+}
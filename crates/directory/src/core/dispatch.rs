@@ -8,7 +8,10 @@ use trc::AddContext;
 
 use crate::{
     Directory, DirectoryInner, Principal, QueryBy,
-    backend::{RcptType, internal::lookup::DirectoryStore},
+    backend::{
+        RcptType,
+        internal::{PrincipalField, PrincipalUpdate, lookup::DirectoryStore},
+    },
 };
 
 impl Directory {
@@ -25,6 +28,7 @@ impl Directory {
             DirectoryInner::Smtp(store) => store.query(by).await,
             DirectoryInner::Memory(store) => store.query(by).await,
             DirectoryInner::OpenId(store) => store.query(by, return_member_of).await,
+            DirectoryInner::Graph(store) => store.query(by, return_member_of).await,
         }
         .caused_by(trc::location!())
     }
@@ -38,6 +42,7 @@ impl Directory {
             DirectoryInner::Smtp(store) => store.email_to_id(address).await,
             DirectoryInner::Memory(store) => store.email_to_id(address).await,
             DirectoryInner::OpenId(store) => store.email_to_id(address).await,
+            DirectoryInner::Graph(store) => store.email_to_id(address).await,
         }
         .caused_by(trc::location!())
     }
@@ -58,6 +63,7 @@ impl Directory {
             DirectoryInner::Smtp(store) => store.is_local_domain(domain).await,
             DirectoryInner::Memory(store) => store.is_local_domain(domain).await,
             DirectoryInner::OpenId(store) => store.is_local_domain(domain).await,
+            DirectoryInner::Graph(store) => store.is_local_domain(domain).await,
         }
         .caused_by(trc::location!())?;
 
@@ -85,6 +91,7 @@ impl Directory {
             DirectoryInner::Smtp(store) => store.rcpt(email).await,
             DirectoryInner::Memory(store) => store.rcpt(email).await,
             DirectoryInner::OpenId(store) => store.rcpt(email).await,
+            DirectoryInner::Graph(store) => store.rcpt(email).await,
         }
         .caused_by(trc::location!())?;
 
@@ -105,6 +112,7 @@ impl Directory {
             DirectoryInner::Smtp(store) => store.vrfy(address).await,
             DirectoryInner::Memory(store) => store.vrfy(address).await,
             DirectoryInner::OpenId(store) => store.vrfy(address).await,
+            DirectoryInner::Graph(store) => store.vrfy(address).await,
         }
         .caused_by(trc::location!())
     }
@@ -118,6 +126,7 @@ impl Directory {
             DirectoryInner::Smtp(store) => store.expn(address).await,
             DirectoryInner::Memory(store) => store.expn(address).await,
             DirectoryInner::OpenId(store) => store.expn(address).await,
+            DirectoryInner::Graph(store) => store.expn(address).await,
         }
         .caused_by(trc::location!())
     }
@@ -130,9 +139,34 @@ impl Directory {
             | DirectoryInner::Imap(_)
             | DirectoryInner::Smtp(_)
             | DirectoryInner::Memory(_) => false,
-            DirectoryInner::OpenId(_) => true,
+            DirectoryInner::OpenId(_) | DirectoryInner::Graph(_) => true,
         }
     }
+
+    /// Returns true when changes to the field are written back to an external directory.
+    pub fn can_write_through(&self, field: PrincipalField) -> bool {
+        match &self.store {
+            DirectoryInner::Ldap(store) => store.can_write(field),
+            _ => false,
+        }
+    }
+
+    /// Discards cached group entries used to resolve nested groups.
+    pub fn invalidate_group_cache(&self) {
+        match &self.store {
+            DirectoryInner::Ldap(store) => store.groups.invalidate(),
+            DirectoryInner::Sql(store) => store.groups.invalidate(),
+            _ => {}
+        }
+    }
+
+    pub async fn write_through(&self, name: &str, changes: &[PrincipalUpdate]) -> trc::Result<()> {
+        match &self.store {
+            DirectoryInner::Ldap(store) => store.write(name, changes).await,
+            _ => Ok(()),
+        }
+        .caused_by(trc::location!())
+    }
 }
 
 impl DirectoryInner {
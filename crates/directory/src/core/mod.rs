@@ -6,10 +6,12 @@
 
 use crate::Permission;
 
+pub mod app_password;
 pub mod cache;
 pub mod config;
 pub mod dispatch;
 pub mod principal;
+pub mod scram;
 pub mod secret;
 
 impl Permission {
@@ -251,6 +253,46 @@ impl Permission {
             Permission::CalendarSchedulingReceive => {
                 "Receive calendar scheduling requests via e-mail"
             }
+            Permission::QuarantineList => "View quarantined messages",
+            Permission::QuarantineGet => "Retrieve the metadata of quarantined messages",
+            Permission::QuarantineGetContents => "Retrieve the contents of quarantined messages",
+            Permission::QuarantineUpdate => "Add disposition notes to quarantined messages",
+            Permission::QuarantineRelease => "Release quarantined messages for delivery",
+            Permission::QuarantineReject => "Reject and remove quarantined messages",
+            Permission::MessageQueueSubmit => "Submit messages directly to the queue",
+            Permission::SuppressionList => "View the recipient suppression list",
+            Permission::SuppressionGet => "Retrieve recipient suppression entries",
+            Permission::SuppressionUpdate => "Add recipients to the suppression list",
+            Permission::SuppressionDelete => "Remove recipients from the suppression list",
+            Permission::ContactsCollect => {
+                "Collect correspondents of sent messages into an address book"
+            }
+            Permission::StoreBackup => "Create backups of the server data",
+            Permission::StoreRestore => "Restore the server data from a backup",
+            Permission::AccountExport => "Export an account to a portable archive",
+            Permission::AccountImport => "Import an account from a portable archive",
+            Permission::MailSyncList => "List mail sync jobs from remote servers",
+            Permission::MailSyncGet => "Retrieve mail sync jobs and their progress",
+            Permission::MailSyncUpdate => "Create, modify and start mail sync jobs",
+            Permission::MailSyncDelete => "Delete mail sync jobs",
+            Permission::RetentionList => "List mailbox retention rules",
+            Permission::RetentionRun => "Apply or preview retention rules on an account",
+            Permission::LegalHoldGet => "View the legal hold status of an account",
+            Permission::LegalHoldUpdate => "Place or release a legal hold on an account",
+            Permission::ForwardApprovalList => {
+                "List pending requests to forward to external domains"
+            }
+            Permission::ForwardApprovalUpdate => {
+                "Approve or reject requests to forward to external domains"
+            }
+            Permission::ImpersonateAccount => "Obtain temporary tokens to act as another account",
+            Permission::ImpersonationLogList => "View the audit log of impersonated sessions",
+            Permission::AuditLogList => "Search and export the management audit log",
+            Permission::CertificateList => "View the TLS certificates in use",
+            Permission::CertificateUpdate => "Upload or replace TLS certificates",
+            Permission::CertificateDelete => "Remove TLS certificates",
+            Permission::EncryptionRecoveryGet => "View tenant encryption recovery keys",
+            Permission::EncryptionRecoveryUpdate => "Manage tenant encryption recovery keys",
         }
     }
 }
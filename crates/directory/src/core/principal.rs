@@ -146,6 +146,79 @@ impl Principal {
             .unwrap_or_default()
     }
 
+    pub fn spam_filter(&self) -> &[String] {
+        self.data
+            .iter()
+            .find_map(|item| {
+                if let PrincipalData::SpamFilter(items) = item {
+                    items.as_slice().into()
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn legal_hold(&self) -> Option<&String> {
+        self.data.iter().find_map(|item| {
+            if let PrincipalData::LegalHold(reason) = item {
+                reason.into()
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn forward_policy(&self) -> Option<&String> {
+        self.data.iter().find_map(|item| {
+            if let PrincipalData::ForwardPolicy(policy) = item {
+                policy.into()
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn free_busy_policy(&self) -> Option<&String> {
+        self.data.iter().find_map(|item| {
+            if let PrincipalData::FreeBusyPolicy(policy) = item {
+                policy.into()
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn booking_policy(&self) -> Option<&String> {
+        self.data.iter().find_map(|item| {
+            if let PrincipalData::BookingPolicy(policy) = item {
+                policy.into()
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn booking_horizon(&self) -> Option<&String> {
+        self.data.iter().find_map(|item| {
+            if let PrincipalData::BookingHorizon(horizon) = item {
+                horizon.into()
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn booking_delegate(&self) -> Option<&String> {
+        self.data.iter().find_map(|item| {
+            if let PrincipalData::BookingDelegate(delegate) = item {
+                delegate.into()
+            } else {
+                None
+            }
+        })
+    }
+
     pub fn roles_mut(&mut self) -> Option<&mut Vec<u32>> {
         self.data.iter_mut().find_map(|item| {
             if let PrincipalData::Roles(items) = item {
@@ -1096,7 +1169,13 @@ impl<'de> serde::Deserialize<'de> for PrincipalSet {
                         PrincipalField::Description
                         | PrincipalField::Tenant
                         | PrincipalField::Picture
-                        | PrincipalField::Locale => {
+                        | PrincipalField::Locale
+                        | PrincipalField::LegalHold
+                        | PrincipalField::ForwardPolicy
+                        | PrincipalField::FreeBusyPolicy
+                        | PrincipalField::BookingPolicy
+                        | PrincipalField::BookingHorizon
+                        | PrincipalField::BookingDelegate => {
                             if let Some(v) = map.next_value::<Option<String>>()? {
                                 if v.len() <= MAX_STRING_LEN {
                                     PrincipalValue::String(v)
@@ -1123,18 +1202,17 @@ impl<'de> serde::Deserialize<'de> for PrincipalSet {
                         | PrincipalField::EnabledPermissions
                         | PrincipalField::DisabledPermissions
                         | PrincipalField::Urls
-                        | PrincipalField::ExternalMembers => {
-                            match map.next_value::<StringOrMany>()? {
-                                StringOrMany::One(v) => PrincipalValue::StringList(vec![v]),
-                                StringOrMany::Many(v) => {
-                                    if !v.is_empty() {
-                                        PrincipalValue::StringList(v)
-                                    } else {
-                                        continue;
-                                    }
+                        | PrincipalField::ExternalMembers
+                        | PrincipalField::SpamFilter => match map.next_value::<StringOrMany>()? {
+                            StringOrMany::One(v) => PrincipalValue::StringList(vec![v]),
+                            StringOrMany::Many(v) => {
+                                if !v.is_empty() {
+                                    PrincipalValue::StringList(v)
+                                } else {
+                                    continue;
                                 }
                             }
-                        }
+                        },
                         PrincipalField::UsedQuota => {
                             // consume and ignore
                             map.next_value::<IgnoredAny>()?;
@@ -1411,6 +1489,7 @@ impl Permission {
                 | Permission::CalendarAlarms
                 | Permission::CalendarSchedulingSend
                 | Permission::CalendarSchedulingReceive
+                | Permission::ContactsCollect
         )
     }
 
@@ -1472,6 +1551,12 @@ impl Permission {
                 | Permission::ApiKeyCreate
                 | Permission::ApiKeyUpdate
                 | Permission::ApiKeyDelete
+                | Permission::QuarantineList
+                | Permission::QuarantineGet
+                | Permission::QuarantineGetContents
+                | Permission::QuarantineUpdate
+                | Permission::QuarantineRelease
+                | Permission::QuarantineReject
         ) || self.is_user_permission()
     }
 
@@ -0,0 +1,167 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha2::{Digest, Sha256};
+use utils::crypto::{constant_time_eq, hmac_sha256};
+
+use crate::backend::internal::SpecialSecrets;
+
+pub const SCRAM_SHA256_ITERATIONS: u32 = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramCredentials {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub stored_key: [u8; 32],
+    pub server_key: [u8; 32],
+}
+
+impl ScramCredentials {
+    pub fn from_password(password: &str, salt: Vec<u8>, iterations: u32) -> Self {
+        let mut salted_password = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut salted_password);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+
+        ScramCredentials {
+            stored_key: Sha256::digest(client_key).into(),
+            server_key: hmac_sha256(&salted_password, b"Server Key"),
+            salt,
+            iterations,
+        }
+    }
+
+    /// Derives the credentials on a blocking thread, PBKDF2 is too expensive
+    /// to run on the async runtime.
+    pub async fn derive(password: String, salt: Vec<u8>, iterations: u32) -> trc::Result<Self> {
+        tokio::task::spawn_blocking(move || Self::from_password(&password, salt, iterations))
+            .await
+            .map_err(|err| {
+                trc::EventType::Server(trc::ServerEvent::ThreadError)
+                    .caused_by(trc::location!())
+                    .reason(err)
+            })
+    }
+
+    /// Obtains the SCRAM credentials for a stored secret, which is only possible
+    /// for secrets stored in clear text or as a SCRAM-SHA-256 hash.
+    /// The provided salt is used when deriving the credentials from a clear text secret.
+    pub async fn from_secret(
+        secret: &str,
+        salt: impl FnOnce() -> Vec<u8>,
+    ) -> trc::Result<Option<Self>> {
+        if !secret.is_password() || secret.is_empty() {
+            return Ok(None);
+        }
+
+        let password = if let Some(secret) = secret.strip_prefix('{') {
+            match secret.split_once('}') {
+                Some(("SCRAM-SHA-256", secret)) => return Ok(Self::parse(secret)),
+                Some(("PLAIN" | "plain" | "CLEAR" | "clear", secret)) => secret,
+                _ => return Ok(None),
+            }
+        } else if !secret.starts_with('$') && !secret.starts_with('_') {
+            secret
+        } else {
+            return Ok(None);
+        };
+
+        Self::derive(password.to_string(), salt(), SCRAM_SHA256_ITERATIONS)
+            .await
+            .map(Some)
+    }
+
+    /// Parses a SCRAM-SHA-256 hash in the format "<iterations>,<salt>,<stored key>,<server key>"
+    /// with all binary values encoded in base64.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split(',');
+        let iterations = parts.next()?.parse::<u32>().ok().filter(|&i| i > 0)?;
+        let salt = STANDARD.decode(parts.next()?).ok()?;
+        let stored_key = STANDARD.decode(parts.next()?).ok()?.try_into().ok()?;
+        let server_key = STANDARD.decode(parts.next()?).ok()?.try_into().ok()?;
+
+        if parts.next().is_none() {
+            Some(ScramCredentials {
+                salt,
+                iterations,
+                stored_key,
+                server_key,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub async fn verify_password(&self, password: &str) -> trc::Result<bool> {
+        let credentials =
+            Self::derive(password.to_string(), self.salt.clone(), self.iterations).await?;
+        Ok(constant_time_eq(&credentials.stored_key, &self.stored_key)
+            && constant_time_eq(&credentials.server_key, &self.server_key))
+    }
+}
+
+impl std::fmt::Display for ScramCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{SCRAM-SHA-256}}{},{},{},{}",
+            self.iterations,
+            STANDARD.encode(&self.salt),
+            STANDARD.encode(self.stored_key),
+            STANDARD.encode(self.server_key)
+        )
+    }
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn scram_credentials() {
+        // Credentials from the RFC 7677 example exchange
+        let credentials = ScramCredentials::from_password(
+            "pencil",
+            STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap(),
+            4096,
+        );
+        let serialized = credentials.to_string();
+        let parsed =
+            ScramCredentials::parse(serialized.strip_prefix("{SCRAM-SHA-256}").unwrap()).unwrap();
+        assert_eq!(parsed, credentials);
+        assert!(parsed.verify_password("pencil").await.unwrap());
+        assert!(!parsed.verify_password("pen").await.unwrap());
+
+        assert_eq!(
+            ScramCredentials::from_secret(&serialized, Vec::new)
+                .await
+                .unwrap(),
+            Some(credentials.clone())
+        );
+        assert_eq!(
+            ScramCredentials::from_secret("pencil", || credentials.salt.clone())
+                .await
+                .unwrap(),
+            Some(credentials)
+        );
+        assert_eq!(
+            ScramCredentials::from_secret("$6$salt$hash", Vec::new)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            ScramCredentials::from_secret("otpauth://totp/abc", Vec::new)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
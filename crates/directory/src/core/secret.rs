@@ -21,6 +21,7 @@ use totp_rs::TOTP;
 
 use crate::Principal;
 use crate::backend::internal::SpecialSecrets;
+use crate::core::scram::ScramCredentials;
 
 impl Principal {
     pub async fn verify_secret(&self, mut code: &str) -> trc::Result<bool> {
@@ -30,9 +31,13 @@ impl Principal {
         let mut is_totp_verified = false;
         let mut is_authenticated = false;
         let mut is_app_authenticated = false;
+        let mut is_webauthn_required = false;
 
         for secret in self.secrets.iter() {
-            if secret.is_otp_auth() {
+            if secret.is_webauthn() {
+                // WebAuthn credentials cannot be verified using a secret
+                is_webauthn_required = true;
+            } else if secret.is_otp_auth() {
                 if !is_totp_verified && !is_totp_token_missing {
                     is_totp_required = true;
 
@@ -76,9 +81,15 @@ impl Principal {
 
         if is_authenticated {
             if !is_totp_required {
-                // Authenticated without TOTP enabled
+                if !is_webauthn_required {
+                    // Authenticated without 2FA enabled
 
-                Ok(true)
+                    Ok(true)
+                } else {
+                    // Password is correct but a WebAuthn credential is required
+
+                    Err(trc::AuthEvent::MissingTotp.into_err())
+                }
             } else if is_totp_token_missing {
                 // Only let the client know if the TOTP code is missing
                 // if the password is correct
@@ -106,6 +117,13 @@ impl Principal {
             Ok(false)
         }
     }
+
+    /// Returns true when the principal has TOTP or WebAuthn enabled.
+    pub fn has_second_factor(&self) -> bool {
+        self.secrets
+            .iter()
+            .any(|secret| secret.is_otp_auth() || secret.is_webauthn())
+    }
 }
 
 async fn verify_hash_prefix(hashed_secret: &str, secret: &str) -> trc::Result<bool> {
@@ -254,6 +272,16 @@ pub async fn verify_secret_hash(hashed_secret: &str, secret: &str) -> trc::Resul
                         Ok(unix_crypt::verify(secret, hashed_secret))
                     }
                 }
+                "SCRAM-SHA-256" => {
+                    ScramCredentials::parse(hashed_secret)
+                        .ok_or_else(|| {
+                            trc::AuthEvent::Error
+                                .ctx(trc::Key::Reason, "Invalid SCRAM-SHA-256 hash")
+                                .details(hashed_secret.to_string())
+                        })?
+                        .verify_password(secret)
+                        .await
+                }
                 "PLAIN" | "plain" | "CLEAR" | "clear" => Ok(hashed_secret == secret),
                 _ => Err(trc::AuthEvent::Error
                     .ctx(trc::Key::Reason, "Unsupported algorithm")
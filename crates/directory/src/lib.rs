@@ -57,6 +57,13 @@ pub enum PrincipalData {
     Urls(Vec<String>),
     PrincipalQuota(Vec<PrincipalQuota>),
     Locale(String),
+    SpamFilter(Vec<String>),
+    LegalHold(String),
+    ForwardPolicy(String),
+    FreeBusyPolicy(String),
+    BookingPolicy(String),
+    BookingHorizon(String),
+    BookingDelegate(String),
 }
 
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, PartialEq, Eq)]
@@ -377,6 +384,44 @@ pub enum Permission {
     CalendarAlarms,
     CalendarSchedulingSend,
     CalendarSchedulingReceive,
+
+    QuarantineList,
+    QuarantineGet,
+    QuarantineGetContents,
+    QuarantineUpdate,
+    QuarantineRelease,
+    QuarantineReject,
+
+    MessageQueueSubmit,
+    SuppressionList,
+    SuppressionGet,
+    SuppressionUpdate,
+    SuppressionDelete,
+
+    ContactsCollect,
+
+    StoreBackup,
+    StoreRestore,
+    AccountExport,
+    AccountImport,
+    MailSyncList,
+    MailSyncGet,
+    MailSyncUpdate,
+    MailSyncDelete,
+    RetentionList,
+    RetentionRun,
+    LegalHoldGet,
+    LegalHoldUpdate,
+    ForwardApprovalList,
+    ForwardApprovalUpdate,
+    ImpersonateAccount,
+    ImpersonationLogList,
+    AuditLogList,
+    CertificateList,
+    CertificateUpdate,
+    CertificateDelete,
+    EncryptionRecoveryGet,
+    EncryptionRecoveryUpdate,
     // WARNING: add new ids at the end (TODO: use static ids)
 }
 
@@ -392,6 +437,7 @@ pub enum DirectoryInner {
     Ldap(LdapDirectory),
     Sql(SqlDirectory),
     OpenId(backend::oidc::OpenIdDirectory),
+    Graph(backend::graph::GraphDirectory),
     Imap(ImapDirectory),
     Smtp(SmtpDirectory),
     Memory(MemoryDirectory),
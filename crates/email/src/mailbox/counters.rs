@@ -0,0 +1,149 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::cache::{MessageCacheFetch, email::MessageCacheAccess};
+use common::Server;
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    keyword::Keyword,
+    property::Property,
+};
+use std::future::Future;
+use store::{
+    ValueKey,
+    write::{BatchBuilder, ValueClass},
+};
+use trc::AddContext;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MailboxCounters {
+    pub total: u64,
+    pub unseen: u64,
+    pub deleted: u64,
+}
+
+pub const COUNTER_PROPERTIES: [Property; 3] = [
+    Property::TotalEmails,
+    Property::UnreadEmails,
+    Property::DeletedEmails,
+];
+
+const MAX_REBUILD_ATTEMPTS: usize = 3;
+
+pub trait MailboxCounterAccess: Sync + Send {
+    fn mailbox_counters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> impl Future<Output = trc::Result<MailboxCounters>> + Send;
+
+    fn rebuild_mailbox_counters(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<usize>> + Send;
+}
+
+impl MailboxCounterAccess for Server {
+    async fn mailbox_counters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> trc::Result<MailboxCounters> {
+        let mut values = [0u64; 3];
+        for (value, property) in values.iter_mut().zip(COUNTER_PROPERTIES) {
+            *value = self
+                .core
+                .storage
+                .data
+                .get_counter(ValueKey {
+                    account_id,
+                    collection: Collection::Mailbox.into(),
+                    document_id: mailbox_id,
+                    class: ValueClass::Property(property.into()),
+                })
+                .await
+                .caused_by(trc::location!())?
+                .max(0) as u64;
+        }
+
+        Ok(MailboxCounters {
+            total: values[0],
+            unseen: values[1],
+            deleted: values[2],
+        })
+    }
+
+    async fn rebuild_mailbox_counters(&self, account_id: u32) -> trc::Result<usize> {
+        // Counters are overwritten with the values recounted from the message cache.
+        // If any message changed between building the cache and committing the
+        // batch, the recount could be stale so it is attempted again.
+        for _ in 0..MAX_REBUILD_ATTEMPTS {
+            let cache = self
+                .get_cached_messages(account_id)
+                .await
+                .caused_by(trc::location!())?;
+            let mut batch = BatchBuilder::new();
+            let mut fixed = 0;
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Mailbox);
+
+            for mailbox in &cache.mailboxes.items {
+                let mailbox_id = mailbox.document_id;
+                let current = self
+                    .mailbox_counters(account_id, mailbox_id)
+                    .await
+                    .caused_by(trc::location!())?;
+                let expected = MailboxCounters {
+                    total: cache.in_mailbox(mailbox_id).count() as u64,
+                    unseen: cache
+                        .in_mailbox_without_keyword(mailbox_id, &Keyword::Seen)
+                        .count() as u64,
+                    deleted: cache
+                        .in_mailbox_with_keyword(mailbox_id, &Keyword::Deleted)
+                        .count() as u64,
+                };
+
+                if current != expected {
+                    batch.update_document(mailbox_id);
+                    for (property, value) in COUNTER_PROPERTIES.into_iter().zip([
+                        expected.total,
+                        expected.unseen,
+                        expected.deleted,
+                    ]) {
+                        batch.clear(property).add(property, value as i64);
+                    }
+                    fixed += 1;
+                }
+            }
+
+            if batch.is_empty() {
+                return Ok(0);
+            }
+
+            self.store()
+                .write(batch.build_all())
+                .await
+                .caused_by(trc::location!())?;
+
+            let change_id = self
+                .store()
+                .get_last_change_id(account_id, SyncCollection::Email)
+                .await
+                .caused_by(trc::location!())?
+                .unwrap_or_default();
+            if change_id == cache.last_change_id {
+                return Ok(fixed);
+            }
+        }
+
+        Err(trc::StoreEvent::AssertValueFailed
+            .into_err()
+            .details("Messages changed while rebuilding mailbox counters")
+            .account_id(account_id)
+            .caused_by(trc::location!()))
+    }
+}
@@ -7,6 +7,7 @@
 use super::*;
 use crate::{
     cache::{MessageCacheFetch, email::MessageCacheAccess},
+    mailbox::counters::COUNTER_PROPERTIES,
     message::{delete::EmailDeletion, metadata::MessageData},
 };
 use common::{
@@ -174,7 +175,11 @@ impl MailboxDestroy for Server {
                 .with_account_id(account_id)
                 .with_collection(Collection::Mailbox)
                 .delete_document(document_id)
-                .clear(Property::EmailIds)
+                .clear(Property::EmailIds);
+            for property in COUNTER_PROPERTIES {
+                batch.clear(property);
+            }
+            batch
                 .custom(ObjectIndexBuilder::<_, ()>::new().with_current(mailbox))
                 .caused_by(trc::location!())?;
         } else {
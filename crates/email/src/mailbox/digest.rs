@@ -0,0 +1,290 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::ticket::{Ticket, TicketStatus};
+use crate::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess, mailbox::MailboxCacheAccess},
+    message::metadata::MessageMetadata,
+};
+use common::{KV_MAILBOX_DIGEST, Server, config::jmap::settings::MailboxDigest};
+use directory::QueryBy;
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
+use mail_builder::{
+    MessageBuilder,
+    headers::{
+        HeaderType,
+        address::{Address, EmailAddress},
+    },
+};
+use std::{fmt::Write, future::Future};
+use store::{ahash::AHashMap, dispatch::lookup::KeyValue, write::now};
+use trc::AddContext;
+
+pub struct DigestMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct DigestSummary {
+    new_messages: usize,
+    open: usize,
+    pending: usize,
+    done: usize,
+    unassigned: usize,
+    sla_breaches: usize,
+    assigned: AHashMap<String, AssigneeSummary>,
+}
+
+#[derive(Default)]
+struct AssigneeSummary {
+    active: usize,
+    new_assignments: usize,
+    sla_breaches: usize,
+}
+
+const ASSIGNMENT_EXPIRY: u64 = 90 * 86400;
+
+pub trait MailboxDigestBuild: Sync + Send {
+    fn build_mailbox_digest(
+        &self,
+        digest: &MailboxDigest,
+    ) -> impl Future<Output = trc::Result<Vec<DigestMessage>>> + Send;
+}
+
+impl MailboxDigestBuild for Server {
+    async fn build_mailbox_digest(
+        &self,
+        digest: &MailboxDigest,
+    ) -> trc::Result<Vec<DigestMessage>> {
+        let directory = &self.core.storage.directory;
+        let Some(account_id) = directory
+            .query(QueryBy::Name(&digest.account), false)
+            .await
+            .caused_by(trc::location!())?
+            .map(|principal| principal.id())
+        else {
+            trc::event!(
+                Housekeeper(trc::HousekeeperEvent::Run),
+                Type = "mailbox_digest",
+                Id = digest.id.clone(),
+                Details = "Account not found",
+            );
+            return Ok(vec![]);
+        };
+
+        let cache = self
+            .get_cached_messages(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let Some(mailbox) = cache.mailbox_by_path(&digest.mailbox) else {
+            trc::event!(
+                Housekeeper(trc::HousekeeperEvent::Run),
+                Type = "mailbox_digest",
+                Id = digest.id.clone(),
+                Details = "Mailbox not found",
+            );
+            return Ok(vec![]);
+        };
+        let mailbox_id = mailbox.document_id;
+
+        // Obtain the last UID included in a digest
+        let key = digest_key(digest, None);
+        let last_uid = self
+            .in_memory_store()
+            .key_get::<String>(key.clone())
+            .await
+            .caused_by(trc::location!())?
+            .and_then(|uid| uid.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        // Summarize tickets
+        let mut summary = DigestSummary::default();
+        let mut max_uid = last_uid;
+        let now = now();
+        for message in cache.in_mailbox(mailbox_id) {
+            if let Some(uid) = message
+                .mailboxes
+                .iter()
+                .find(|m| m.mailbox_id == mailbox_id)
+                .map(|m| m.uid)
+            {
+                if uid > last_uid {
+                    summary.new_messages += 1;
+                    max_uid = max_uid.max(uid);
+                }
+            }
+
+            let keywords = cache.expand_keywords(message).collect::<Vec<Keyword>>();
+            let ticket = Ticket::from_keywords(&keywords);
+            match ticket.status {
+                TicketStatus::Open => summary.open += 1,
+                TicketStatus::Pending => summary.pending += 1,
+                TicketStatus::Done => summary.done += 1,
+            }
+            if ticket.is_unassigned() {
+                summary.unassigned += 1;
+            }
+
+            // Detect assignment changes since the last digest
+            let assignment_key = digest_key(digest, Some(message.document_id));
+            let previous_assignee = self
+                .in_memory_store()
+                .key_get::<String>(assignment_key.clone())
+                .await
+                .caused_by(trc::location!())?;
+            if previous_assignee != ticket.assignee {
+                if let Some(assignee) = &ticket.assignee {
+                    self.in_memory_store()
+                        .key_set(
+                            KeyValue::new(assignment_key, assignee.as_bytes().to_vec())
+                                .expires(ASSIGNMENT_EXPIRY),
+                        )
+                        .await
+                        .caused_by(trc::location!())?;
+                } else {
+                    self.in_memory_store()
+                        .key_delete(assignment_key)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+
+            // Check SLA on tickets that are still waiting for a response
+            let is_breached = if let (Some(sla), TicketStatus::Open) = (digest.sla, ticket.status) {
+                let received_at = self
+                    .get_archive_by_property(
+                        account_id,
+                        Collection::Email,
+                        message.document_id,
+                        Property::BodyStructure,
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                    .map(|archive| {
+                        archive
+                            .unarchive::<MessageMetadata>()
+                            .map(|metadata| u64::from(metadata.received_at))
+                    })
+                    .transpose()
+                    .caused_by(trc::location!())?
+                    .unwrap_or(now);
+                received_at + sla.as_secs() < now
+            } else {
+                false
+            };
+            if is_breached {
+                summary.sla_breaches += 1;
+            }
+
+            if let Some(assignee) = ticket.assignee {
+                if ticket.status != TicketStatus::Done {
+                    let assignee_summary = summary.assigned.entry(assignee.clone()).or_default();
+                    assignee_summary.active += 1;
+                    if is_breached {
+                        assignee_summary.sla_breaches += 1;
+                    }
+                    if previous_assignee.is_none_or(|previous| previous != assignee) {
+                        assignee_summary.new_assignments += 1;
+                    }
+                }
+            }
+        }
+
+        if max_uid != last_uid {
+            self.in_memory_store()
+                .key_set(KeyValue::new(key, max_uid.to_string().into_bytes()))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        // Build a digest for each subscribed member
+        let mut messages = Vec::with_capacity(mailbox.subscribers.len());
+        for member_id in mailbox.subscribers.iter() {
+            let Some(principal) = directory
+                .query(QueryBy::Id(*member_id), false)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let member = summary.assigned.get(&principal.name().to_lowercase());
+            if summary.new_messages == 0
+                && summary.open + summary.pending == 0
+                && member.is_none_or(|member| member.new_assignments == 0)
+            {
+                continue;
+            }
+            let Some(email) = principal.emails.into_iter().next() else {
+                continue;
+            };
+
+            let mut text = format!(
+                concat!(
+                    "Activity summary for the shared mailbox \"{}\" of account {}:\r\n\r\n",
+                    "New messages since the last digest: {}\r\n",
+                    "Open tickets: {}\r\n",
+                    "Pending tickets: {}\r\n",
+                    "Closed tickets: {}\r\n",
+                    "Unassigned tickets: {}\r\n",
+                ),
+                digest.mailbox,
+                digest.account,
+                summary.new_messages,
+                summary.open,
+                summary.pending,
+                summary.done,
+                summary.unassigned
+            );
+            if digest.sla.is_some() {
+                let _ = write!(text, "Tickets over SLA: {}\r\n", summary.sla_breaches);
+            }
+            if let Some(member) = member {
+                let _ = write!(
+                    text,
+                    "\r\nAssigned to you: {} ({} new since the last digest)\r\n",
+                    member.active, member.new_assignments
+                );
+                if digest.sla.is_some() {
+                    let _ = write!(text, "Your tickets over SLA: {}\r\n", member.sla_breaches);
+                }
+            }
+
+            messages.push(DigestMessage {
+                from: digest.from_address.clone(),
+                body: MessageBuilder::new()
+                    .from(Address::Address(EmailAddress {
+                        name: Some(digest.from_name.as_str().into()),
+                        email: digest.from_address.as_str().into(),
+                    }))
+                    .to(Address::Address(EmailAddress {
+                        name: None,
+                        email: email.as_str().into(),
+                    }))
+                    .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+                    .subject(format!("Activity digest for {}", digest.mailbox))
+                    .text_body(text)
+                    .write_to_vec()
+                    .unwrap_or_default(),
+                to: vec![email],
+            });
+        }
+
+        Ok(messages)
+    }
+}
+
+fn digest_key(digest: &MailboxDigest, document_id: Option<u32>) -> Vec<u8> {
+    let mut key = Vec::with_capacity(digest.id.len() + 6);
+    key.push(KV_MAILBOX_DIGEST);
+    key.extend_from_slice(digest.id.as_bytes());
+    if let Some(document_id) = document_id {
+        key.push(0);
+        key.extend_from_slice(&document_id.to_be_bytes());
+    }
+    key
+}
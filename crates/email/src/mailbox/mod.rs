@@ -7,9 +7,12 @@
 use common::config::jmap::settings::SpecialUse;
 use jmap_proto::types::value::AclGrant;
 
+pub mod counters;
 pub mod destroy;
+pub mod digest;
 pub mod index;
 pub mod manage;
+pub mod ticket;
 
 pub const INBOX_ID: u32 = 0;
 pub const TRASH_ID: u32 = 1;
@@ -18,6 +21,7 @@ pub const DRAFTS_ID: u32 = 3;
 pub const SENT_ID: u32 = 4;
 pub const ARCHIVE_ID: u32 = 5;
 pub const TOMBSTONE_ID: u32 = u32::MAX - 1;
+pub const HOLD_ID: u32 = u32::MAX - 2;
 
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, PartialEq, Eq)]
 #[rkyv(derive(Debug))]
@@ -0,0 +1,92 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use jmap_proto::types::keyword::Keyword;
+
+// Tickets in shared mailboxes are tracked using keywords, which makes them
+// available to both IMAP (as flags) and JMAP clients.
+pub const KEYWORD_STATUS_OPEN: &str = "$ticket-open";
+pub const KEYWORD_STATUS_PENDING: &str = "$ticket-pending";
+pub const KEYWORD_STATUS_DONE: &str = "$ticket-done";
+pub const KEYWORD_ASSIGNED_PREFIX: &str = "$assigned-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TicketStatus {
+    #[default]
+    Open,
+    Pending,
+    Done,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ticket {
+    pub status: TicketStatus,
+    pub assignee: Option<String>,
+}
+
+impl TicketStatus {
+    pub fn keyword(&self) -> Keyword {
+        Keyword::Other(self.as_keyword().to_string())
+    }
+
+    pub fn as_keyword(&self) -> &'static str {
+        match self {
+            TicketStatus::Open => KEYWORD_STATUS_OPEN,
+            TicketStatus::Pending => KEYWORD_STATUS_PENDING,
+            TicketStatus::Done => KEYWORD_STATUS_DONE,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TicketStatus::Open => "open",
+            TicketStatus::Pending => "pending",
+            TicketStatus::Done => "done",
+        }
+    }
+}
+
+impl Ticket {
+    pub fn from_keywords<'x>(keywords: impl IntoIterator<Item = &'x Keyword>) -> Self {
+        let mut ticket = Ticket::default();
+
+        for keyword in keywords {
+            if let Keyword::Other(keyword) = keyword {
+                if keyword.eq_ignore_ascii_case(KEYWORD_STATUS_DONE) {
+                    ticket.status = TicketStatus::Done;
+                } else if keyword.eq_ignore_ascii_case(KEYWORD_STATUS_PENDING) {
+                    if ticket.status != TicketStatus::Done {
+                        ticket.status = TicketStatus::Pending;
+                    }
+                } else if let Some(assignee) = assignee_from_keyword(keyword) {
+                    ticket.assignee = Some(assignee);
+                }
+            }
+        }
+
+        ticket
+    }
+
+    pub fn is_unassigned(&self) -> bool {
+        self.assignee.is_none() && self.status != TicketStatus::Done
+    }
+}
+
+pub fn assignee_keyword(account_name: &str) -> Keyword {
+    Keyword::Other(format!(
+        "{KEYWORD_ASSIGNED_PREFIX}{}",
+        account_name.to_lowercase()
+    ))
+}
+
+pub fn assignee_from_keyword(keyword: &str) -> Option<String> {
+    keyword
+        .get(..KEYWORD_ASSIGNED_PREFIX.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(KEYWORD_ASSIGNED_PREFIX))
+        .and_then(|_| keyword.get(KEYWORD_ASSIGNED_PREFIX.len()..))
+        .filter(|assignee| !assignee.is_empty())
+        .map(|assignee| assignee.to_lowercase())
+}
@@ -43,13 +43,28 @@ impl EmailBayesTrain for Server {
         message: Message<'_>,
         learn_spam: bool,
     ) {
-        self.bayes_train_if_balanced(
-            &self.spam_filter_init(SpamFilterInput::from_account_message(
-                &message, account_id, span_id,
-            )),
-            learn_spam,
-        )
-        .await
+        let Some(config) = &self.core.spam.bayes else {
+            return;
+        };
+
+        if config.account_classify {
+            self.bayes_train_if_balanced(
+                &self.spam_filter_init(SpamFilterInput::from_account_message(
+                    &message, account_id, span_id,
+                )),
+                learn_spam,
+            )
+            .await;
+        }
+
+        // User feedback can also be fed into the global model
+        if config.account_train_global {
+            self.bayes_train_if_balanced(
+                &self.spam_filter_init(SpamFilterInput::from_message(&message, span_id)),
+                learn_spam,
+            )
+            .await;
+        }
     }
 
     async fn email_bayes_queue_task_build(
@@ -7,7 +7,8 @@
 use super::{
     index::{MAX_ID_LENGTH, MAX_SORT_FIELD_LENGTH, TrimTextValue, VisitText},
     ingest::{EmailIngest, IngestedEmail, ThreadResult},
-    metadata::{MessageData, MessageMetadata},
+    metadata::{MessageBodyStats, MessageData, MessageMetadata},
+    signature::MessageSignature,
 };
 use crate::mailbox::UidMailbox;
 use common::{Server, auth::ResourceToken, storage::index::ObjectIndexBuilder};
@@ -25,7 +26,7 @@ use jmap_proto::{
 use mail_parser::{HeaderName, HeaderValue, parsers::fields::thread::thread_name};
 use store::{
     BlobClass,
-    write::{BatchBuilder, TaskQueueClass, ValueClass, now},
+    write::{Archiver, BatchBuilder, TaskQueueClass, ValueClass, now},
 };
 use trc::AddContext;
 
@@ -213,6 +214,46 @@ impl EmailCopy for Server {
                 true,
             )
             .caused_by(trc::location!())?;
+        if let Some(body_stats) = self
+            .get_archive_by_property(
+                from_account_id,
+                Collection::Email,
+                from_message_id,
+                Property::BodyStats,
+            )
+            .await?
+        {
+            batch.set(
+                Property::BodyStats,
+                Archiver::new(
+                    body_stats
+                        .deserialize::<MessageBodyStats>()
+                        .caused_by(trc::location!())?,
+                )
+                .serialize()
+                .caused_by(trc::location!())?,
+            );
+        }
+        if let Some(signature) = self
+            .get_archive_by_property(
+                from_account_id,
+                Collection::Email,
+                from_message_id,
+                Property::SmimeStatusAtDelivery,
+            )
+            .await?
+        {
+            batch.set(
+                Property::SmimeStatusAtDelivery,
+                Archiver::new(
+                    signature
+                        .deserialize::<MessageSignature>()
+                        .caused_by(trc::location!())?,
+                )
+                .serialize()
+                .caused_by(trc::location!())?,
+            );
+        }
 
         // Insert and obtain ids
         let change_id = self
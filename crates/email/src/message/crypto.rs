@@ -4,31 +4,46 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, collections::BTreeSet, fmt::Display, io::Cursor};
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fmt::Display,
+    io::{self, Write},
+};
 
 use aes::cipher::{BlockEncryptMut, KeyIvInit, block_padding::Pkcs7};
 
+use common::Server;
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    property::Property,
+};
 use mail_builder::{encoders::base64::base64_encode_mime, mime::make_boundary};
-use mail_parser::{Message, MimeHeaders, PartType, decoders::base64::base64_decode};
+use mail_parser::{Message, MessageParser, MimeHeaders, PartType, decoders::base64::base64_decode};
 use openpgp::{
     parse::Parse,
     serialize::stream,
     types::{KeyFlags, SymmetricAlgorithm},
 };
 use rand::{RngCore, SeedableRng, rngs::StdRng};
-use rasn::types::{ObjectIdentifier, OctetString};
+use rasn::types::{Integer, ObjectIdentifier, OctetString};
 use rasn_cms::{
-    AlgorithmIdentifier, CONTENT_DATA, CONTENT_ENVELOPED_DATA, EncryptedContent,
-    EncryptedContentInfo, EncryptedKey, EnvelopedData, IssuerAndSerialNumber,
+    AlgorithmIdentifier, CONTENT_DATA, CONTENT_ENVELOPED_DATA, EncryptedKey, IssuerAndSerialNumber,
     KeyTransRecipientInfo, RecipientIdentifier, RecipientInfo,
     algorithms::{AES128_CBC, AES256_CBC, RSA},
-    pkcs7_compat::EncapsulatedContentInfo,
 };
 use rsa::{Pkcs1v15Encrypt, RsaPublicKey, pkcs1::DecodeRsaPublicKey};
 use sequoia_openpgp as openpgp;
-use store::{Deserialize, write::Archive};
+use store::{
+    Deserialize,
+    write::{Archive, Archiver, BatchBuilder, TaskQueueClass, ValueClass, now},
+};
+use trc::AddContext;
+
+use super::metadata::{MessageData, MessageMetadata};
 
 const P: openpgp::policy::StandardPolicy<'static> = openpgp::policy::StandardPolicy::new();
+const BASE64_LINE_LEN: usize = 57;
 
 #[derive(Debug)]
 pub enum EncryptMessageError {
@@ -64,9 +79,11 @@ pub enum Algorithm {
     serde::Serialize,
     serde::Deserialize,
 )]
+#[rkyv(derive(PartialEq, Eq))]
 pub enum EncryptionMethod {
     PGP,
     SMIME,
+    Age,
 }
 
 #[derive(
@@ -104,15 +121,30 @@ pub enum EncryptionType {
         algo: Algorithm,
         certs: String,
     },
+    Age {
+        certs: String,
+    },
     #[default]
     Disabled,
 }
 
+impl EncryptionType {
+    pub fn method(&self) -> Option<EncryptionMethod> {
+        match self {
+            EncryptionType::PGP { .. } => Some(EncryptionMethod::PGP),
+            EncryptionType::SMIME { .. } => Some(EncryptionMethod::SMIME),
+            EncryptionType::Age { .. } => Some(EncryptionMethod::Age),
+            EncryptionType::Disabled => None,
+        }
+    }
+}
+
 #[allow(async_fn_in_trait)]
 pub trait EncryptMessage {
     async fn encrypt(
         &self,
         params: &ArchivedEncryptionParams,
+        recovery: Option<&ArchivedEncryptionParams>,
     ) -> Result<Vec<u8>, EncryptMessageError>;
     fn is_encrypted(&self) -> bool;
 }
@@ -121,6 +153,7 @@ impl EncryptMessage for Message<'_> {
     async fn encrypt(
         &self,
         params: &ArchivedEncryptionParams,
+        recovery: Option<&ArchivedEncryptionParams>,
     ) -> Result<Vec<u8>, EncryptMessageError> {
         let root = self.root_part();
         let raw_message = self.raw_message();
@@ -141,7 +174,20 @@ impl EncryptMessage for Message<'_> {
         inner_message.extend_from_slice(b"\r\n");
         inner_message.extend_from_slice(&raw_message[root.raw_body_offset() as usize..]);
 
-        // Encrypt inner message
+        // Tenant recovery keys are added as additional recipients
+        let certs = params
+            .certs
+            .iter()
+            .chain(
+                recovery
+                    .filter(|recovery| recovery.method == params.method)
+                    .into_iter()
+                    .flat_map(|recovery| recovery.certs.iter()),
+            )
+            .map(|cert| cert.as_slice());
+
+        // Encrypt inner message, the ciphertext is encoded straight into the
+        // outer message without intermediate copies
         match params.method {
             ArchivedEncryptionMethod::PGP => {
                 // Prepare encrypted message
@@ -180,9 +226,7 @@ impl EncryptMessage for Message<'_> {
                     .as_bytes(),
                 );
 
-                let certs = params
-                    .certs
-                    .iter()
+                let certs = certs
                     .map(openpgp::Cert::from_bytes)
                     .collect::<Result<Vec<_>, _>>()
                     .map_err(|err| {
@@ -194,7 +238,7 @@ impl EncryptMessage for Message<'_> {
 
                 // Encrypt contents (TODO: use rayon)
                 let algo = params.algo;
-                let encrypted_contents = tokio::task::spawn_blocking(move || {
+                outer_message = tokio::task::spawn_blocking(move || {
                     // Parse public key
                     let mut keys = Vec::with_capacity(certs.len());
                     let policy = openpgp::policy::StandardPolicy::new();
@@ -214,10 +258,7 @@ impl EncryptMessage for Message<'_> {
 
                     // Compose a writer stack corresponding to the output format and
                     // packet structure we want.
-                    let mut sink = Vec::with_capacity(inner_message.len());
-
-                    // Stream an OpenPGP message.
-                    let message = stream::Armorer::new(stream::Message::new(&mut sink))
+                    let message = stream::Armorer::new(stream::Message::new(&mut outer_message))
                         .build()
                         .map_err(|err| {
                             EncryptMessageError::Error(format!("Failed to create armorer: {}", err))
@@ -241,30 +282,19 @@ impl EncryptMessage for Message<'_> {
                                 err
                             ))
                         })?;
-                    std::io::copy(&mut Cursor::new(inner_message), &mut message).map_err(
-                        |err| {
-                            EncryptMessageError::Error(format!(
-                                "Failed to encrypt message: {}",
-                                err
-                            ))
-                        },
-                    )?;
+                    message.write_all(&inner_message).map_err(|err| {
+                        EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+                    })?;
                     message.finalize().map_err(|err| {
                         EncryptMessageError::Error(format!("Failed to finalize message: {}", err))
                     })?;
 
-                    String::from_utf8(sink).map_err(|err| {
-                        EncryptMessageError::Error(format!(
-                            "Failed to convert encrypted message to UTF-8: {}",
-                            err
-                        ))
-                    })
+                    Ok(outer_message)
                 })
                 .await
                 .map_err(|err| {
                     EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
                 })??;
-                outer_message.extend_from_slice(encrypted_contents.as_bytes());
                 outer_message.extend_from_slice(b"\r\n--");
                 outer_message.extend_from_slice(boundary.as_bytes());
                 outer_message.extend_from_slice(b"--\r\n");
@@ -279,20 +309,10 @@ impl EncryptMessage for Message<'_> {
                 let mut key = vec![0u8; params.algo.key_size()];
                 rng.fill_bytes(&mut key);
 
-                // Encrypt contents (TODO: use rayon)
-                let algo = params.algo;
-                let (encrypted_contents, key, iv) = tokio::task::spawn_blocking(move || {
-                    (algo.encrypt(&key, &iv, &inner_message), key, iv)
-                })
-                .await
-                .map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
-                })?;
-
                 // Encrypt key using public keys
                 #[allow(clippy::mutable_key_type)]
                 let mut recipient_infos = BTreeSet::new();
-                for cert in params.certs.iter() {
+                for cert in certs {
                     let cert =
                         rasn::der::decode::<rasn_pkix::Certificate>(cert).map_err(|err| {
                             EncryptMessageError::Error(format!(
@@ -314,8 +334,7 @@ impl EncryptMessage for Message<'_> {
                         .encrypt(&mut rng, Pkcs1v15Encrypt, &key[..])
                         .map_err(|err| {
                             EncryptMessageError::Error(format!("Failed to encrypt key: {}", err))
-                        })
-                        .unwrap();
+                        })?;
 
                     recipient_infos.insert(RecipientInfo::KeyTransRecipientInfo(
                         KeyTransRecipientInfo {
@@ -329,14 +348,7 @@ impl EncryptMessage for Message<'_> {
                             key_encryption_algorithm: AlgorithmIdentifier {
                                 algorithm: RSA.into(),
                                 parameters: Some(
-                                    rasn::der::encode(&())
-                                        .map_err(|err| {
-                                            EncryptMessageError::Error(format!(
-                                                "Failed to encode RSA algorithm identifier: {}",
-                                                err
-                                            ))
-                                        })?
-                                        .into(),
+                                    der_encode(&(), "RSA algorithm identifier")?.into(),
                                 ),
                             },
                             encrypted_key: EncryptedKey::from(encrypted_key),
@@ -344,59 +356,79 @@ impl EncryptMessage for Message<'_> {
                     ));
                 }
 
-                let pkcs7 = rasn::der::encode(&EncapsulatedContentInfo {
-                    content_type: CONTENT_ENVELOPED_DATA.into(),
-                    content: Some(
-                        rasn::der::encode(&EnvelopedData {
-                            version: 0.into(),
-                            originator_info: None,
-                            recipient_infos,
-                            encrypted_content_info: EncryptedContentInfo {
-                                content_type: CONTENT_DATA.into(),
-                                content_encryption_algorithm: AlgorithmIdentifier {
-                                    algorithm: params.algo.to_algorithm_identifier(),
-                                    parameters: Some(
-                                        rasn::der::encode(&OctetString::from(iv))
-                                            .map_err(|err| {
-                                                EncryptMessageError::Error(format!(
-                                                    "Failed to encode IV: {}",
-                                                    err
-                                                ))
-                                            })?
-                                            .into(),
-                                    ),
-                                },
-                                encrypted_content: Some(EncryptedContent::from(encrypted_contents)),
-                            },
-                            unprotected_attrs: None,
-                        })
+                // Build the PKCS7 envelope around the yet to be encrypted contents
+                let algo = params.algo;
+                let envelope =
+                    smime_envelope(algo, iv.clone(), recipient_infos, inner_message.len())?;
+
+                // Generate message
+                outer_message.extend_from_slice(
+                    concat!(
+                        "Content-Type: application/pkcs7-mime;\r\n",
+                        "\tname=\"smime.p7m\";\r\n",
+                        "\tsmime-type=enveloped-data\r\n",
+                        "Content-Disposition: attachment;\r\n",
+                        "\tfilename=\"smime.p7m\"\r\n",
+                        "Content-Transfer-Encoding: base64\r\n\r\n"
+                    )
+                    .as_bytes(),
+                );
+
+                // Encrypt contents (TODO: use rayon)
+                outer_message = tokio::task::spawn_blocking(move || {
+                    algo.encrypt_in_place(&key, &iv, &mut inner_message)?;
+
+                    let mut writer = Base64Writer::new(outer_message);
+                    writer
+                        .write_all(&envelope)
+                        .and_then(|_| writer.write_all(&inner_message))
+                        .and_then(|_| writer.finish())
                         .map_err(|err| {
                             EncryptMessageError::Error(format!(
-                                "Failed to encode EnvelopedData: {}",
+                                "Failed to base64 encode PKCS7: {}",
                                 err
                             ))
-                        })?
-                        .into(),
-                    ),
+                        })
                 })
+                .await
                 .map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to encode ContentInfo: {}", err))
-                })?;
+                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+                })??;
+            }
+            ArchivedEncryptionMethod::Age => {
+                let recipients = certs
+                    .map(parse_age_recipient)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| EncryptMessageError::Error("Invalid age recipient".into()))?;
 
                 // Generate message
                 outer_message.extend_from_slice(
                     concat!(
-                        "Content-Type: application/pkcs7-mime;\r\n",
-                        "\tname=\"smime.p7m\";\r\n",
-                        "\tsmime-type=enveloped-data\r\n",
+                        "Content-Type: application/octet-stream;\r\n",
+                        "\tname=\"encrypted.age\"\r\n",
                         "Content-Disposition: attachment;\r\n",
-                        "\tfilename=\"smime.p7m\"\r\n",
+                        "\tfilename=\"encrypted.age\"\r\n",
                         "Content-Transfer-Encoding: base64\r\n\r\n"
                     )
                     .as_bytes(),
                 );
-                base64_encode_mime(&pkcs7, &mut outer_message, false).map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to base64 encode PKCS7: {}", err))
+
+                // Encrypt contents (TODO: use rayon)
+                outer_message = tokio::task::spawn_blocking(move || {
+                    let mut writer = age::Encryptor::with_recipients(
+                        recipients.iter().map(|r| r as &dyn age::Recipient),
+                    )
+                    .map_err(io::Error::other)?
+                    .wrap_output(Base64Writer::new(outer_message))?;
+                    writer.write_all(&inner_message)?;
+                    writer.finish()?.finish()
+                })
+                .await
+                .map_err(|err| {
+                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+                })?
+                .map_err(|err| {
+                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
                 })?;
             }
         }
@@ -418,8 +450,9 @@ impl EncryptMessage for Message<'_> {
                     || sub_type.eq_ignore_ascii_case("pkcs7-signature")
                     || (sub_type.eq_ignore_ascii_case("octet-stream")
                         && self.attachment_name().is_some_and(|name| {
-                            name.rsplit_once('.')
-                                .is_some_and(|(_, ext)| ["p7m", "p7s", "p7c", "p7z"].contains(&ext))
+                            name.rsplit_once('.').is_some_and(|(_, ext)| {
+                                ["p7m", "p7s", "p7c", "p7z", "age"].contains(&ext)
+                            })
                         }))))
                 || (main_type.eq_ignore_ascii_case("multipart")
                     && sub_type.eq_ignore_ascii_case("encrypted"))
@@ -457,6 +490,151 @@ impl EncryptMessage for Message<'_> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredEmailEncryption {
+    Encrypted { change_id: u64 },
+    AlreadyEncrypted,
+    Skipped,
+}
+
+pub trait EncryptStoredEmail: Sync + Send {
+    fn encrypt_stored_email(
+        &self,
+        account_id: u32,
+        tenant_id: Option<u32>,
+        document_id: u32,
+        params: &ArchivedEncryptionParams,
+        recovery: Option<&ArchivedEncryptionParams>,
+    ) -> impl Future<Output = trc::Result<StoredEmailEncryption>> + Send;
+}
+
+impl EncryptStoredEmail for Server {
+    // Replaces the blob of a stored message with its encrypted version, the
+    // document id, IMAP UIDs, keywords and mailboxes are left untouched.
+    async fn encrypt_stored_email(
+        &self,
+        account_id: u32,
+        tenant_id: Option<u32>,
+        document_id: u32,
+        params: &ArchivedEncryptionParams,
+        recovery: Option<&ArchivedEncryptionParams>,
+    ) -> trc::Result<StoredEmailEncryption> {
+        let (Some(data_), Some(metadata_)) = (
+            self.get_archive(account_id, Collection::Email, document_id)
+                .await
+                .caused_by(trc::location!())?,
+            self.get_archive_by_property(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::BodyStructure,
+            )
+            .await
+            .caused_by(trc::location!())?,
+        ) else {
+            return Ok(StoredEmailEncryption::Skipped);
+        };
+        let data = data_
+            .to_unarchived::<MessageData>()
+            .caused_by(trc::location!())?;
+        let metadata = metadata_
+            .unarchive::<MessageMetadata>()
+            .caused_by(trc::location!())?;
+        let Some(raw_message) = self
+            .blob_store()
+            .get_blob(metadata.blob_hash.0.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(StoredEmailEncryption::Skipped);
+        };
+        let Some(message) = MessageParser::new().parse(&raw_message) else {
+            return Ok(StoredEmailEncryption::Skipped);
+        };
+        let encrypted = match message.encrypt(params, recovery).await {
+            Ok(encrypted) => encrypted,
+            Err(EncryptMessageError::AlreadyEncrypted) => {
+                return Ok(StoredEmailEncryption::AlreadyEncrypted);
+            }
+            Err(EncryptMessageError::Error(err)) => {
+                return Err(trc::StoreEvent::CryptoError
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .reason(err));
+            }
+        };
+        let Some(message) = MessageParser::new().parse(&encrypted) else {
+            return Ok(StoredEmailEncryption::Skipped);
+        };
+
+        // Store the encrypted copy, the unencrypted blob is purged once unlinked
+        let blob_id = self
+            .put_blob(account_id, &encrypted, false)
+            .await
+            .caused_by(trc::location!())?;
+        let (new_metadata, body_stats) = MessageMetadata::build(
+            message,
+            blob_id.hash.clone(),
+            u64::from(metadata.received_at),
+        );
+        let signature = self
+            .get_archive_by_property(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::SmimeStatusAtDelivery,
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Swap the metadata in a single batch, the quota is adjusted by the
+        // size difference and the message is queued for full-text reindexing
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .assert_value(ValueClass::Property(Property::Value.into()), &data);
+        metadata
+            .index(&mut batch, account_id, tenant_id, false)
+            .caused_by(trc::location!())?;
+        new_metadata
+            .index(&mut batch, account_id, tenant_id, true)
+            .caused_by(trc::location!())?;
+        batch
+            .set(
+                Property::BodyStats,
+                Archiver::new(body_stats)
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            )
+            .set(
+                ValueClass::TaskQueue(TaskQueueClass::IndexEmail {
+                    due: now(),
+                    hash: blob_id.hash,
+                }),
+                vec![],
+            )
+            .log_item_update(SyncCollection::Email, Some(u32::from(data.inner.thread_id)));
+        if let Some(signature) = signature {
+            batch.set(Property::SmimeStatusAtDelivery, signature.into_inner());
+        }
+
+        match self
+            .commit_batch(batch)
+            .await
+            .and_then(|ids| ids.last_change_id(account_id))
+        {
+            Ok(change_id) => {
+                self.notify_task_queue();
+                Ok(StoredEmailEncryption::Encrypted { change_id })
+            }
+            Err(err) if err.is_assertion_failure() => Ok(StoredEmailEncryption::Skipped),
+            Err(err) => Err(err.caused_by(trc::location!())),
+        }
+    }
+}
+
 impl ArchivedAlgorithm {
     fn key_size(&self) -> usize {
         match self {
@@ -472,13 +650,142 @@ impl ArchivedAlgorithm {
         }
     }
 
-    fn encrypt(&self, key: &[u8], iv: &[u8], contents: &[u8]) -> Vec<u8> {
+    fn encrypted_len(&self, len: usize) -> usize {
+        (len / 16 + 1) * 16
+    }
+
+    fn encrypt_in_place(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), EncryptMessageError> {
+        let len = contents.len();
+        contents.resize(self.encrypted_len(len), 0);
         match self {
             ArchivedAlgorithm::Aes128 => cbc::Encryptor::<aes::Aes128>::new(key.into(), iv.into())
-                .encrypt_padded_vec_mut::<Pkcs7>(contents),
+                .encrypt_padded_mut::<Pkcs7>(&mut contents[..], len)
+                .map(|_| ()),
             ArchivedAlgorithm::Aes256 => cbc::Encryptor::<aes::Aes256>::new(key.into(), iv.into())
-                .encrypt_padded_vec_mut::<Pkcs7>(contents),
+                .encrypt_padded_mut::<Pkcs7>(&mut contents[..], len)
+                .map(|_| ()),
         }
+        .map_err(|_| EncryptMessageError::Error("Failed to pad message".into()))
+    }
+}
+
+// Returns the DER encoded ContentInfo preceding the encrypted contents, which
+// are streamed afterwards as the value of the last element of the envelope.
+#[allow(clippy::mutable_key_type)]
+fn smime_envelope(
+    algo: ArchivedAlgorithm,
+    iv: Vec<u8>,
+    recipient_infos: BTreeSet<RecipientInfo>,
+    len: usize,
+) -> Result<Vec<u8>, EncryptMessageError> {
+    let encrypted_len = algo.encrypted_len(len);
+
+    // EncryptedContentInfo
+    let mut content_info = der_encode(&ObjectIdentifier::from(CONTENT_DATA), "content type")?;
+    content_info.extend(der_encode(
+        &AlgorithmIdentifier {
+            algorithm: algo.to_algorithm_identifier(),
+            parameters: Some(der_encode(&OctetString::from(iv), "IV")?.into()),
+        },
+        "content encryption algorithm",
+    )?);
+    der_header(0x80, encrypted_len, &mut content_info);
+
+    // EnvelopedData
+    let mut enveloped_data = der_encode(&Integer::from(0), "version")?;
+    enveloped_data.extend(der_encode(&recipient_infos, "recipient infos")?);
+    der_wrap(0x30, content_info, encrypted_len, &mut enveloped_data);
+
+    // ContentInfo
+    let mut content = Vec::with_capacity(enveloped_data.len() + 4);
+    der_wrap(0x30, enveloped_data, encrypted_len, &mut content);
+    let mut content_info = der_encode(
+        &ObjectIdentifier::from(CONTENT_ENVELOPED_DATA),
+        "content type",
+    )?;
+    der_wrap(0xa0, content, encrypted_len, &mut content_info);
+    let mut envelope = Vec::with_capacity(content_info.len() + 4);
+    der_wrap(0x30, content_info, encrypted_len, &mut envelope);
+
+    Ok(envelope)
+}
+
+fn der_encode<T: rasn::Encode>(value: &T, name: &str) -> Result<Vec<u8>, EncryptMessageError> {
+    rasn::der::encode(value)
+        .map_err(|err| EncryptMessageError::Error(format!("Failed to encode {name}: {err}")))
+}
+
+fn der_wrap(tag: u8, contents: Vec<u8>, trailing_len: usize, out: &mut Vec<u8>) {
+    der_header(tag, contents.len() + trailing_len, out);
+    out.extend(contents);
+}
+
+fn der_header(tag: u8, len: usize, out: &mut Vec<u8>) {
+    out.push(tag);
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let start = bytes.iter().position(|byte| *byte != 0).unwrap_or_default();
+        out.push(0x80 | (bytes.len() - start) as u8);
+        out.extend_from_slice(&bytes[start..]);
+    }
+}
+
+// Encodes to MIME base64 as data is written, one line at a time
+struct Base64Writer {
+    inner: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl Base64Writer {
+    fn new(inner: Vec<u8>) -> Self {
+        Base64Writer {
+            inner,
+            buf: Vec::with_capacity(BASE64_LINE_LEN),
+        }
+    }
+
+    fn finish(mut self) -> io::Result<Vec<u8>> {
+        if !self.buf.is_empty() {
+            base64_encode_mime(&self.buf, &mut self.inner, false)?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl Write for Base64Writer {
+    fn write(&mut self, mut bytes: &[u8]) -> io::Result<usize> {
+        let len = bytes.len();
+
+        // Complete any pending line
+        if !self.buf.is_empty() {
+            let (line, rest) = bytes.split_at((BASE64_LINE_LEN - self.buf.len()).min(len));
+            self.buf.extend_from_slice(line);
+            bytes = rest;
+            if self.buf.len() == BASE64_LINE_LEN {
+                base64_encode_mime(&self.buf, &mut self.inner, false)?;
+                self.buf.clear();
+            }
+        }
+
+        // Encode whole lines directly
+        let (lines, rest) = bytes.split_at(bytes.len() - (bytes.len() % BASE64_LINE_LEN));
+        if !lines.is_empty() {
+            base64_encode_mime(lines, &mut self.inner, false)?;
+        }
+        self.buf.extend_from_slice(rest);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
 
@@ -489,6 +796,8 @@ pub fn try_parse_certs(
     // Check if it's a PEM file
     let (method, certs) = if let Some(result) = try_parse_pem(&cert)? {
         result
+    } else if let Some(recipients) = try_parse_age(&cert) {
+        (EncryptionMethod::Age, recipients)
     } else if rasn::der::decode::<rasn_pkix::Certificate>(&cert[..]).is_ok() {
         (EncryptionMethod::SMIME, vec![cert])
     } else if let Ok(cert_) = openpgp::Cert::from_bytes(&cert[..]) {
@@ -508,6 +817,24 @@ pub fn try_parse_certs(
     }
 }
 
+// Recipient files list one "age1..." recipient per line, comments are allowed
+fn try_parse_age(bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut recipients = Vec::new();
+    for line in std::str::from_utf8(bytes).ok()?.lines() {
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            parse_age_recipient(line.as_bytes())?;
+            recipients.push(line.as_bytes().to_vec());
+        }
+    }
+
+    Some(recipients).filter(|recipients| !recipients.is_empty())
+}
+
+fn parse_age_recipient(recipient: &[u8]) -> Option<age::x25519::Recipient> {
+    std::str::from_utf8(recipient).ok()?.parse().ok()
+}
+
 fn has_pgp_keys(cert: openpgp::Cert) -> bool {
     cert.keys()
         .with_policy(&P, None)
@@ -651,6 +978,7 @@ fn try_parse_pem(
                 }
                 certs.push(cert);
             }
+            EncryptionMethod::Age => return Ok(None),
         }
         buf.clear();
     }
@@ -663,6 +991,7 @@ impl Display for EncryptionMethod {
         match self {
             EncryptionMethod::PGP => write!(f, "OpenPGP"),
             EncryptionMethod::SMIME => write!(f, "S/MIME"),
+            EncryptionMethod::Age => write!(f, "age"),
         }
     }
 }
@@ -4,8 +4,14 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use super::hold::LegalHold;
 use super::metadata::MessageData;
-use crate::{cache::MessageCacheFetch, mailbox::*, message::metadata::MessageMetadata};
+use super::retention::EmailRetention;
+use crate::{
+    cache::MessageCacheFetch,
+    mailbox::{counters::MailboxCounterAccess, *},
+    message::metadata::MessageMetadata,
+};
 use common::{KV_LOCK_PURGE_ACCOUNT, Server, storage::index::ObjectIndexBuilder};
 use groupware::calendar::storage::ItipAutoExpunge;
 use jmap_proto::types::collection::VanishedCollection;
@@ -54,7 +60,17 @@ impl EmailDeletion for Server {
         batch: &mut BatchBuilder,
         document_ids: RoaringBitmap,
     ) -> trc::Result<RoaringBitmap> {
-        // Tombstone message and untag it from the mailboxes
+        // Tombstone message and untag it from the mailboxes, messages of accounts
+        // under legal hold are moved to the hold area instead
+        let legal_hold = self
+            .legal_hold(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let tombstone_id = if legal_hold.is_some() {
+            HOLD_ID
+        } else {
+            TOMBSTONE_ID
+        };
         let mut deleted_ids = RoaringBitmap::new();
         batch
             .with_account_id(account_id)
@@ -78,7 +94,7 @@ impl EmailDeletion for Server {
                     .update_document(document_id)
                     .custom(ObjectIndexBuilder::<_, ()>::new().with_current(metadata))
                     .caused_by(trc::location!())?
-                    .tag(Property::MailboxIds, TagValue::Id(TOMBSTONE_ID))
+                    .tag(Property::MailboxIds, TagValue::Id(tombstone_id))
                     .commit_point();
 
                 deleted_ids.insert(document_id);
@@ -88,6 +104,16 @@ impl EmailDeletion for Server {
         )
         .await?;
 
+        if let Some(reason) = legal_hold.filter(|_| !deleted_ids.is_empty()) {
+            trc::event!(
+                Purge(trc::PurgeEvent::LegalHoldRetained),
+                AccountId = account_id,
+                DocumentId = deleted_ids.iter().map(trc::Value::from).collect::<Vec<_>>(),
+                Reason = reason,
+                Total = deleted_ids.len(),
+            );
+        }
+
         let not_destroyed = if document_ids.len() == deleted_ids.len() {
             RoaringBitmap::new()
         } else {
@@ -145,6 +171,16 @@ impl EmailDeletion for Server {
             }
         }
 
+        // Apply retention rules
+        if !self.core.jmap.mail_retention.is_empty() {
+            if let Err(err) = self.emails_apply_retention(account_id, false).await {
+                trc::error!(
+                    err.details("Failed to apply retention rules.")
+                        .account_id(account_id)
+                );
+            }
+        }
+
         // Auto-expunge iMIP messages
         if let Some(hold_period) = self.core.groupware.itip_inbox_auto_expunge {
             if let Err(err) = self.itip_auto_expunge(account_id, hold_period).await {
@@ -163,6 +199,25 @@ impl EmailDeletion for Server {
             );
         }
 
+        // Fix any drift in the mailbox counters
+        match self.rebuild_mailbox_counters(account_id).await {
+            Ok(0) => {}
+            Ok(fixed) => {
+                trc::event!(
+                    Store(trc::StoreEvent::CacheStale),
+                    AccountId = account_id,
+                    Total = fixed,
+                    Details = "Mailbox counters rebuilt",
+                );
+            }
+            Err(err) => {
+                trc::error!(
+                    err.details("Failed to rebuild mailbox counters.")
+                        .account_id(account_id)
+                );
+            }
+        }
+
         // Purge changelogs
         if let Some(history) = self.core.jmap.changes_max_history {
             if let Err(err) = self.delete_changes(account_id, history).await {
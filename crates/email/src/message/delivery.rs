@@ -4,7 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::Server;
+use common::{
+    Server,
+    ipc::{PushGatewayEvent, PushPreview},
+};
 
 use directory::Permission;
 use jmap_proto::types::{state::StateChange, type_state::DataType};
@@ -59,6 +62,19 @@ pub trait MailDelivery: Sync + Send {
 
 impl MailDelivery for Server {
     async fn deliver_message(&self, message: IngestMessage) -> LocalDeliveryResult {
+        // Mailboxes cannot be stored on a volatile data store
+        if self.core.storage.data.is_volatile() {
+            return LocalDeliveryResult {
+                status: (0..message.recipients.len())
+                    .map(|_| LocalDeliveryStatus::PermanentFailure {
+                        code: [5, 3, 0],
+                        reason: "Local delivery is disabled on relay nodes.".into(),
+                    })
+                    .collect::<Vec<_>>(),
+                autogenerated: vec![],
+            };
+        }
+
         // Obtain permit
         let _permit = match self.inner.ipc.local_delivery_sm.acquire().await {
             Ok(permit) => permit,
@@ -125,6 +141,19 @@ impl MailDelivery for Server {
             }
         };
 
+        // Build the preview sent to mobile devices
+        let push_preview = (self.core.push_gateway.is_enabled() && self.core.push_gateway.preview)
+            .then(|| MessageParser::new().parse_headers(&raw_message))
+            .flatten()
+            .map(|headers| PushPreview {
+                from: headers
+                    .from()
+                    .and_then(|from| from.first())
+                    .and_then(|addr| addr.name().or(addr.address()))
+                    .map(|from| from.to_string()),
+                subject: headers.subject().map(|subject| subject.to_string()),
+            });
+
         // Obtain the UIDs for each recipient
         let mut uids: AHashMap<u32, usize> = AHashMap::with_capacity(message.recipients.len());
         let mut result = LocalDeliveryResult {
@@ -172,7 +201,11 @@ impl MailDelivery for Server {
             }) {
                 Ok(access_token) => {
                     // Check if there is an active sieve script
-                    match self.sieve_script_get_active(uid).await {
+                    let active_script = match self.sieve_script_get_active(uid).await {
+                        Ok(None) => self.sieve_script_get_out_of_office(&access_token).await,
+                        result => result,
+                    };
+                    match active_script {
                         Ok(None) => {
                             // Ingest message
                             self.email_ingest(IngestEmail {
@@ -225,6 +258,14 @@ impl MailDelivery for Server {
                                 .with_change(DataType::Thread),
                         )
                         .await;
+
+                        // Wake up mobile devices
+                        self.notify_push_gateway(PushGatewayEvent::NewMessage {
+                            account_id: uid,
+                            document_id: ingested_message.id.document_id(),
+                            preview: push_preview.clone(),
+                        })
+                        .await;
                     }
 
                     LocalDeliveryStatus::Success
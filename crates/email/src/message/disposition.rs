@@ -0,0 +1,158 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{index::encode_message_id, ingest::EmailIngest, metadata::MessageData};
+use crate::mailbox::{JUNK_ID, UidMailbox};
+use common::{Server, storage::index::ObjectIndexBuilder};
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection, VanishedCollection},
+    keyword::Keyword,
+    property::Property,
+    state::StateChange,
+    type_state::DataType,
+};
+use std::future::Future;
+use store::{
+    IndexKey, IterateParams, U32_LEN,
+    ahash::AHashMap,
+    roaring::RoaringBitmap,
+    write::{BatchBuilder, key::DeserializeBigEndian},
+};
+use trc::AddContext;
+
+pub trait EmailDisposition: Sync + Send {
+    fn email_set_disposition(
+        &self,
+        account_id: u32,
+        message_id: &str,
+        keyword: &str,
+        move_to_junk: bool,
+    ) -> impl Future<Output = trc::Result<usize>> + Send;
+}
+
+impl EmailDisposition for Server {
+    /// Tags all copies of a delivered message with a keyword, optionally
+    /// moving them to the Junk folder, and notifies subscribers of the change.
+    async fn email_set_disposition(
+        &self,
+        account_id: u32,
+        message_id: &str,
+        keyword: &str,
+        move_to_junk: bool,
+    ) -> trc::Result<usize> {
+        // Find messages by Message-ID
+        let key = encode_message_id(message_id);
+        let mut document_ids = RoaringBitmap::new();
+        self.store()
+            .iterate(
+                IterateParams::new(
+                    IndexKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: 0,
+                        field: Property::References.into(),
+                        key: key.clone(),
+                    },
+                    IndexKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: u32::MAX,
+                        field: Property::References.into(),
+                        key,
+                    },
+                )
+                .no_values()
+                .ascending(),
+                |key, _| {
+                    document_ids.insert(key.deserialize_be_u32(key.len() - U32_LEN)?);
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        let keyword = Keyword::from(keyword);
+        let mut batch = BatchBuilder::new();
+        let mut changed_mailboxes: AHashMap<u32, Vec<u32>> = AHashMap::new();
+        let mut updated = 0;
+
+        for document_id in document_ids {
+            let Some(data_) = self
+                .get_archive(account_id, Collection::Email, document_id)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let data = data_
+                .to_unarchived::<MessageData>()
+                .caused_by(trc::location!())?;
+            let mut new_data = data.deserialize().caused_by(trc::location!())?;
+
+            new_data.add_keyword(keyword.clone());
+            if move_to_junk && !new_data.mailboxes.iter().any(|m| m.mailbox_id == JUNK_ID) {
+                for mailbox in new_data.mailboxes.drain(..) {
+                    changed_mailboxes
+                        .entry(mailbox.mailbox_id)
+                        .or_default()
+                        .push(mailbox.uid);
+                }
+                new_data.add_mailbox(UidMailbox::new(
+                    JUNK_ID,
+                    self.assign_imap_uid(account_id, JUNK_ID)
+                        .await
+                        .caused_by(trc::location!())?,
+                ));
+                new_data.add_keyword(Keyword::Junk);
+                changed_mailboxes.entry(JUNK_ID).or_default();
+            }
+
+            if !new_data.has_keyword_changes(data.inner)
+                && !new_data.has_mailbox_changes(data.inner)
+            {
+                continue;
+            }
+
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .update_document(document_id)
+                .custom(
+                    ObjectIndexBuilder::new()
+                        .with_current(data)
+                        .with_changes(new_data),
+                )
+                .caused_by(trc::location!())?
+                .commit_point();
+            updated += 1;
+        }
+
+        if !batch.is_empty() {
+            for (mailbox_id, removed_uids) in changed_mailboxes {
+                batch.log_container_property_change(SyncCollection::Email, mailbox_id);
+                for uid in removed_uids {
+                    batch.log_vanished_item(VanishedCollection::Email, (mailbox_id, uid));
+                }
+            }
+
+            let change_id = self
+                .commit_batch(batch)
+                .await
+                .and_then(|ids| ids.last_change_id(account_id))
+                .caused_by(trc::location!())?;
+
+            self.broadcast_state_change(
+                StateChange::new(account_id, change_id)
+                    .with_change(DataType::Email)
+                    .with_change(DataType::Mailbox)
+                    .with_change(DataType::Thread),
+            )
+            .await;
+        }
+
+        Ok(updated)
+    }
+}
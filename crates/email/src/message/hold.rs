@@ -0,0 +1,170 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::mailbox::{HOLD_ID, TOMBSTONE_ID};
+use common::Server;
+use directory::backend::internal::{
+    PrincipalField, PrincipalUpdate, PrincipalValue,
+    manage::{ManageDirectory, UpdatePrincipal},
+};
+use jmap_proto::types::{collection::Collection, property::Property};
+use std::future::Future;
+use store::{
+    BitmapKey,
+    roaring::RoaringBitmap,
+    write::{BatchBuilder, BitmapClass, TagValue},
+};
+use trc::AddContext;
+
+// Messages deleted from an account under legal hold are tagged with HOLD_ID
+// instead of TOMBSTONE_ID, which hides them from every mailbox while keeping
+// their metadata and blobs out of reach of the tombstone purge.
+pub trait LegalHold: Sync + Send {
+    fn legal_hold(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<String>>> + Send;
+
+    fn legal_hold_place(
+        &self,
+        account_id: u32,
+        tenant_id: Option<u32>,
+        reason: String,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn legal_hold_release(
+        &self,
+        account_id: u32,
+        tenant_id: Option<u32>,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+
+    fn emails_held(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<RoaringBitmap>> + Send;
+}
+
+impl LegalHold for Server {
+    async fn legal_hold(&self, account_id: u32) -> trc::Result<Option<String>> {
+        self.store()
+            .get_principal(account_id)
+            .await
+            .caused_by(trc::location!())
+            .map(|principal| principal.and_then(|p| p.legal_hold().cloned()))
+    }
+
+    async fn legal_hold_place(
+        &self,
+        account_id: u32,
+        tenant_id: Option<u32>,
+        reason: String,
+    ) -> trc::Result<()> {
+        if reason.is_empty() {
+            return Err(trc::ManageEvent::MissingParameter
+                .into_err()
+                .ctx(trc::Key::Key, "reason"));
+        }
+
+        let changed_principals = self
+            .store()
+            .update_principal(
+                UpdatePrincipal::by_id(account_id)
+                    .with_updates(vec![PrincipalUpdate::set(
+                        PrincipalField::LegalHold,
+                        PrincipalValue::String(reason.clone()),
+                    )])
+                    .with_tenant(tenant_id),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        self.increment_token_revision(changed_principals).await;
+
+        trc::event!(
+            Purge(trc::PurgeEvent::LegalHoldPlaced),
+            AccountId = account_id,
+            Reason = reason,
+        );
+
+        Ok(())
+    }
+
+    async fn legal_hold_release(
+        &self,
+        account_id: u32,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<u64> {
+        let reason = self
+            .legal_hold(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| {
+                trc::ManageEvent::NotFound
+                    .into_err()
+                    .details("Account is not under legal hold")
+                    .account_id(account_id)
+            })?;
+
+        let changed_principals = self
+            .store()
+            .update_principal(
+                UpdatePrincipal::by_id(account_id)
+                    .with_updates(vec![PrincipalUpdate::set(
+                        PrincipalField::LegalHold,
+                        PrincipalValue::String(String::new()),
+                    )])
+                    .with_tenant(tenant_id),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        self.increment_token_revision(changed_principals).await;
+
+        // Held messages are handed over to the tombstone purge
+        let held_ids = self
+            .emails_held(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let total = held_ids.len();
+        if total > 0 {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email);
+            for document_id in held_ids {
+                batch
+                    .update_document(document_id)
+                    .untag(Property::MailboxIds, TagValue::Id(HOLD_ID))
+                    .tag(Property::MailboxIds, TagValue::Id(TOMBSTONE_ID))
+                    .commit_point();
+            }
+            self.commit_batch(batch).await.caused_by(trc::location!())?;
+        }
+
+        trc::event!(
+            Purge(trc::PurgeEvent::LegalHoldReleased),
+            AccountId = account_id,
+            Reason = reason,
+            Total = total,
+        );
+
+        Ok(total)
+    }
+
+    async fn emails_held(&self, account_id: u32) -> trc::Result<RoaringBitmap> {
+        self.store()
+            .get_bitmap(BitmapKey {
+                account_id,
+                collection: Collection::Email.into(),
+                class: BitmapClass::Tag {
+                    field: Property::MailboxIds.into(),
+                    value: TagValue::Id(HOLD_ID),
+                },
+                document_id: 0,
+            })
+            .await
+            .caused_by(trc::location!())
+            .map(|ids| ids.unwrap_or_default())
+    }
+}
@@ -8,11 +8,18 @@ use std::borrow::Cow;
 
 use super::metadata::{
     ArchivedMessageData, ArchivedMessageMetadata, ArchivedMessageMetadataContents,
-    ArchivedMessageMetadataPart, ArchivedMetadataPartType, DecodedPartContent, MessageData,
-    MessageMetadata, MessageMetadataPart,
+    ArchivedMessageMetadataPart, ArchivedMetadataPartType, DecodedPartContent, MessageBodyStats,
+    MessageData, MessageMetadata, MessageMetadataPart,
+};
+use common::storage::{
+    extract::{ExtractInput, TextExtractors},
+    index::{IndexValue, IndexableObject, ObjectIndexBuilder},
+};
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    keyword::{ArchivedKeyword, Keyword},
+    property::Property,
 };
-use common::storage::index::{IndexValue, IndexableObject, ObjectIndexBuilder};
-use jmap_proto::types::{collection::SyncCollection, property::Property};
 use mail_parser::{
     Addr, Address, ArchivedAddress, ArchivedHeaderName, ArchivedHeaderValue, Group, HeaderName,
     HeaderValue,
@@ -43,6 +50,80 @@ impl MessageMetadata {
         &self.contents[0].parts[0]
     }
 
+    // Builds the metadata and body part statistics of a parsed message
+    pub fn build(
+        message: mail_parser::Message<'_>,
+        blob_hash: BlobHash,
+        received_at: u64,
+    ) -> (Self, MessageBodyStats) {
+        let mut has_attachments = false;
+        let mut preview = None;
+        let preview_part_id = message
+            .text_body
+            .first()
+            .or_else(|| message.html_body.first())
+            .copied()
+            .unwrap_or(u32::MAX);
+
+        for (part_id, part) in message.parts.iter().take(MAX_MESSAGE_PARTS).enumerate() {
+            let part_id = part_id as u32;
+            match &part.body {
+                mail_parser::PartType::Text(text) => {
+                    if part_id == preview_part_id {
+                        preview =
+                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
+                    }
+
+                    if !message.text_body.contains(&part_id)
+                        && !message.html_body.contains(&part_id)
+                    {
+                        has_attachments = true;
+                    }
+                }
+                mail_parser::PartType::Html(html) => {
+                    let text = html_to_text(html);
+                    if part_id == preview_part_id {
+                        preview =
+                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
+                    }
+
+                    if !message.text_body.contains(&part_id)
+                        && !message.html_body.contains(&part_id)
+                    {
+                        has_attachments = true;
+                    }
+                }
+                mail_parser::PartType::Binary(_) | mail_parser::PartType::Message(_)
+                    if !has_attachments =>
+                {
+                    has_attachments = true;
+                }
+                _ => {}
+            }
+        }
+
+        // Build metadata
+        let body_stats = MessageBodyStats::new(&message);
+        let root_part = message.root_part();
+        let metadata = MessageMetadata {
+            preview: preview.unwrap_or_default().into_owned(),
+            size: message.raw_message.len() as u32,
+            raw_headers: message
+                .raw_message
+                .as_ref()
+                .get(root_part.offset_header as usize..root_part.offset_body as usize)
+                .unwrap_or_default()
+                .to_vec(),
+            contents: vec![],
+            received_at,
+            has_attachments,
+            blob_hash,
+        }
+        .with_contents(message);
+
+        (metadata, body_stats)
+    }
+
     pub fn index(
         self,
         batch: &mut BatchBuilder,
@@ -59,6 +140,8 @@ impl MessageMetadata {
             // Delete metadata
             batch
                 .clear(Property::BodyStructure)
+                .clear(Property::BodyStats)
+                .clear(Property::SmimeStatusAtDelivery)
                 .unindex(Property::Size, self.size.serialize())
                 .unindex(Property::ReceivedAt, (self.received_at).serialize());
         }
@@ -226,7 +309,7 @@ impl MessageMetadata {
     }
 }
 
-fn encode_message_id(message_id: &str) -> Vec<u8> {
+pub(crate) fn encode_message_id(message_id: &str) -> Vec<u8> {
     let mut msg_id = Vec::with_capacity(message_id.len() + 1);
     msg_id.extend_from_slice(message_id.as_bytes());
     msg_id.push(0);
@@ -258,6 +341,8 @@ impl ArchivedMessageMetadata {
             // Delete metadata
             batch
                 .clear(Property::BodyStructure)
+                .clear(Property::BodyStats)
+                .clear(Property::SmimeStatusAtDelivery)
                 .unindex(Property::Size, u32::from(self.size).serialize())
                 .unindex(
                     Property::ReceivedAt,
@@ -482,69 +567,9 @@ impl IndexMessage for BatchBuilder {
         // Index receivedAt
         self.index(Property::ReceivedAt, received_at.serialize());
 
-        let mut has_attachments = false;
-        let mut preview = None;
-        let preview_part_id = message
-            .text_body
-            .first()
-            .or_else(|| message.html_body.first())
-            .copied()
-            .unwrap_or(u32::MAX);
-
-        for (part_id, part) in message.parts.iter().take(MAX_MESSAGE_PARTS).enumerate() {
-            let part_id = part_id as u32;
-            match &part.body {
-                mail_parser::PartType::Text(text) => {
-                    if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
-                    }
-
-                    if !message.text_body.contains(&part_id)
-                        && !message.html_body.contains(&part_id)
-                    {
-                        has_attachments = true;
-                    }
-                }
-                mail_parser::PartType::Html(html) => {
-                    let text = html_to_text(html);
-                    if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
-                    }
-
-                    if !message.text_body.contains(&part_id)
-                        && !message.html_body.contains(&part_id)
-                    {
-                        has_attachments = true;
-                    }
-                }
-                mail_parser::PartType::Binary(_) | mail_parser::PartType::Message(_)
-                    if !has_attachments =>
-                {
-                    has_attachments = true;
-                }
-                _ => {}
-            }
-        }
-
         // Build metadata
-        let root_part = message.root_part();
-        let metadata = MessageMetadata {
-            preview: preview.unwrap_or_default().into_owned(),
-            size: message.raw_message.len() as u32,
-            raw_headers: message
-                .raw_message
-                .as_ref()
-                .get(root_part.offset_header as usize..root_part.offset_body as usize)
-                .unwrap_or_default()
-                .to_vec(),
-            contents: vec![],
-            received_at,
-            has_attachments,
-            blob_hash,
-        }
-        .with_contents(message);
+        let (metadata, body_stats) = MessageMetadata::build(message, blob_hash, received_at);
+        let has_attachments = metadata.has_attachments;
         metadata.index_headers(self, true);
 
         // Store and index hasAttachment property
@@ -571,6 +596,12 @@ impl IndexMessage for BatchBuilder {
                 .serialize()
                 .caused_by(trc::location!())?,
         );
+        self.set(
+            Property::BodyStats,
+            Archiver::new(body_stats)
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
 
         Ok(self)
     }
@@ -578,6 +609,14 @@ impl IndexMessage for BatchBuilder {
 
 impl IndexableObject for MessageData {
     fn index_values(&self) -> impl Iterator<Item = IndexValue<'_>> {
+        let mailbox_ids = self
+            .mailboxes
+            .iter()
+            .map(|m| m.mailbox_id)
+            .collect::<Vec<_>>();
+        let is_seen = self.keywords.contains(&Keyword::Seen);
+        let is_deleted = self.keywords.contains(&Keyword::Deleted);
+
         [
             IndexValue::LogItem {
                 sync_collection: SyncCollection::Email.into(),
@@ -589,8 +628,11 @@ impl IndexableObject for MessageData {
             },
             IndexValue::LogContainerProperty {
                 sync_collection: SyncCollection::Email.into(),
-                ids: self.mailboxes.iter().map(|m| m.mailbox_id).collect(),
+                ids: mailbox_ids.clone(),
             },
+            mailbox_counter(Property::TotalEmails, &mailbox_ids, true),
+            mailbox_counter(Property::UnreadEmails, &mailbox_ids, !is_seen),
+            mailbox_counter(Property::DeletedEmails, &mailbox_ids, is_deleted),
         ]
         .into_iter()
     }
@@ -598,6 +640,20 @@ impl IndexableObject for MessageData {
 
 impl IndexableObject for &ArchivedMessageData {
     fn index_values(&self) -> impl Iterator<Item = IndexValue<'_>> {
+        let mailbox_ids = self
+            .mailboxes
+            .iter()
+            .map(|m| m.mailbox_id.to_native())
+            .collect::<Vec<_>>();
+        let is_seen = self
+            .keywords
+            .iter()
+            .any(|k| matches!(k, ArchivedKeyword::Seen));
+        let is_deleted = self
+            .keywords
+            .iter()
+            .any(|k| matches!(k, ArchivedKeyword::Deleted));
+
         [
             IndexValue::LogItem {
                 sync_collection: SyncCollection::Email.into(),
@@ -609,17 +665,30 @@ impl IndexableObject for &ArchivedMessageData {
             },
             IndexValue::LogContainerProperty {
                 sync_collection: SyncCollection::Email.into(),
-                ids: self
-                    .mailboxes
-                    .iter()
-                    .map(|m| m.mailbox_id.to_native())
-                    .collect(),
+                ids: mailbox_ids.clone(),
             },
+            mailbox_counter(Property::TotalEmails, &mailbox_ids, true),
+            mailbox_counter(Property::UnreadEmails, &mailbox_ids, !is_seen),
+            mailbox_counter(Property::DeletedEmails, &mailbox_ids, is_deleted),
         ]
         .into_iter()
     }
 }
 
+// Per-mailbox message counters, kept up to date in the same transaction
+// that adds, removes or flags a message.
+fn mailbox_counter(property: Property, mailbox_ids: &[u32], is_set: bool) -> IndexValue<'static> {
+    IndexValue::Counter {
+        collection: Collection::Mailbox.into(),
+        field: property.into(),
+        ids: if is_set {
+            mailbox_ids.to_vec()
+        } else {
+            Vec::new()
+        },
+    }
+}
+
 pub trait IndexMessageText<'x>: Sized {
     fn index_message(self, message: &'x ArchivedMessageMetadata, raw_message: &'x [u8]) -> Self;
 }
@@ -765,6 +834,47 @@ impl<'x> IndexMessageText<'x> for FtsDocument<'x, mail_parser::HeaderName<'x>> {
     }
 }
 
+impl ArchivedMessageMetadata {
+    pub async fn extract_attachments(
+        &self,
+        extractors: &TextExtractors,
+        raw_message: &[u8],
+    ) -> Vec<String> {
+        let mut texts = Vec::new();
+        if !extractors.is_enabled() {
+            return texts;
+        }
+
+        for part in self.contents[0].parts.iter().take(MAX_MESSAGE_PARTS) {
+            if !matches!(part.body, ArchivedMetadataPartType::Binary) {
+                continue;
+            }
+            let content_type = part.content_type().map(|ct| {
+                ct.c_subtype.as_ref().map_or_else(
+                    || ct.c_type.to_string(),
+                    |st| format!("{}/{}", ct.c_type, st),
+                )
+            });
+            let DecodedPartContent::Binary(contents) = part.decode_contents(raw_message) else {
+                continue;
+            };
+
+            if let Some(text) = extractors
+                .extract(ExtractInput {
+                    content_type: content_type.as_deref(),
+                    file_name: part.attachment_name(),
+                    contents: contents.as_ref(),
+                })
+                .await
+            {
+                texts.push(text);
+            }
+        }
+
+        texts
+    }
+}
+
 impl SortedAddressBuilder {
     pub fn new() -> Self {
         Self {
@@ -15,6 +15,7 @@ use crate::{
         crypto::EncryptionParams,
         index::{IndexMessage, MAX_ID_LENGTH, VisitText},
         metadata::MessageData,
+        signature::VerifySignature,
     },
 };
 use common::{IDX_EMAIL, Server, auth::AccessToken, storage::index::ObjectIndexBuilder};
@@ -49,7 +50,7 @@ use store::{
     ahash::AHashMap,
     query::Filter,
     roaring::RoaringBitmap,
-    write::{BatchBuilder, TaskQueueClass, ValueClass, key::DeserializeBigEndian, now},
+    write::{Archiver, BatchBuilder, TaskQueueClass, ValueClass, key::DeserializeBigEndian, now},
 };
 use store::{SerializeInfallible, rand::Rng};
 use trc::{AddContext, MessageIngestEvent};
@@ -166,42 +167,44 @@ impl EmailIngest for Server {
                 {
                     // Set the spam filter result
                     #[cfg(not(feature = "test_mode"))]
-                    {
-                        is_spam = self
-                            .core
-                            .spam
+                    let spam_status = self.core.spam.headers.status.as_ref().and_then(|name| {
+                        message
+                            .root_part()
                             .headers
-                            .status
-                            .as_ref()
-                            .and_then(|name| {
-                                message
-                                    .root_part()
-                                    .headers
-                                    .iter()
-                                    .find(|h| h.name.as_str().eq_ignore_ascii_case(name.as_str()))
-                                    .and_then(|v| v.value.as_text())
-                            })
-                            .is_some_and(|v| v.contains("Yes"));
-                    }
+                            .iter()
+                            .find(|h| h.name.as_str().eq_ignore_ascii_case(name.as_str()))
+                            .and_then(|v| v.value.as_text())
+                    });
 
                     #[cfg(feature = "test_mode")]
-                    {
-                        is_spam = self
-                            .core
-                            .spam
+                    let spam_status = self.core.spam.headers.status.as_ref().and_then(|name| {
+                        message
+                            .root_part()
                             .headers
-                            .status
-                            .as_ref()
-                            .and_then(|name| {
-                                message
-                                    .root_part()
-                                    .headers
-                                    .iter()
-                                    .rev()
-                                    .find(|h| h.name.as_str().eq_ignore_ascii_case(name.as_str()))
-                                    .and_then(|v| v.value.as_text())
-                            })
-                            .is_some_and(|v| v.contains("Yes"));
+                            .iter()
+                            .rev()
+                            .find(|h| h.name.as_str().eq_ignore_ascii_case(name.as_str()))
+                            .and_then(|v| v.value.as_text())
+                    });
+
+                    is_spam = spam_status.is_some_and(|v| v.contains("Yes"));
+
+                    // Apply the account's own tolerance level
+                    if let Some(overrides) = self
+                        .spam_filter_overrides(account_id)
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        if overrides.enabled == Some(false) {
+                            is_spam = false;
+                        } else if let Some((threshold, score)) =
+                            overrides.spam_threshold.zip(spam_status.and_then(|v| {
+                                v.split_once("score=")
+                                    .and_then(|(_, score)| score.trim().parse::<f64>().ok())
+                            }))
+                        {
+                            is_spam = score >= threshold;
+                        }
                     }
 
                     // If the message is classified as spam, check whether the sender address is present in the user's address book
@@ -309,14 +312,21 @@ impl EmailIngest for Server {
                 {
                     let mut sender = None;
                     for part in &message.parts {
+                        // Some clients omit the method parameter or attach the
+                        // iCalendar object as application/ics, the iTIP method
+                        // is validated from the METHOD property instead.
                         if part.content_type().is_some_and(|ct| {
-                            ct.ctype().eq_ignore_ascii_case("text")
-                                && ct
-                                    .subtype()
-                                    .is_some_and(|st| st.eq_ignore_ascii_case("calendar"))
-                                && ct.has_attribute("method")
+                            ct.subtype().is_some_and(|st| {
+                                (ct.ctype().eq_ignore_ascii_case("text")
+                                    && st.eq_ignore_ascii_case("calendar"))
+                                    || (ct.ctype().eq_ignore_ascii_case("application")
+                                        && st.eq_ignore_ascii_case("ics"))
+                            })
                         }) {
-                            if let Some(itip_message) = part.text_contents() {
+                            if let Some(itip_message) = part
+                                .text_contents()
+                                .or_else(|| std::str::from_utf8(part.contents()).ok())
+                            {
                                 if itip_message.len()
                                     < self.core.groupware.itip_inbound_max_ical_size
                                 {
@@ -352,7 +362,8 @@ impl EmailIngest for Server {
                                             Err(ItipIngestError::Message(itip_error)) => {
                                                 match itip_error {
                                                     ItipError::NothingToSend
-                                                    | ItipError::OtherSchedulingAgent => (),
+                                                    | ItipError::OtherSchedulingAgent
+                                                    | ItipError::MissingMethod => (),
                                                     err => {
                                                         trc::event!(
                                                             Calendar(trc::CalendarEvent::ItipMessageError),
@@ -384,6 +395,10 @@ impl EmailIngest for Server {
                                         Size = itip_message.len(),
                                     )
                                 }
+
+                                // Messages carrying the same object in several
+                                // parts (inline and attachment) are processed once
+                                break;
                             }
                         }
                     }
@@ -536,6 +551,9 @@ impl EmailIngest for Server {
             root_part.headers = extra_headers_parsed;
         }
 
+        // Verify signatures before the message is encrypted at rest
+        let signature = message.verify_signature(&self.core.jmap.signature_trust);
+
         // Encrypt message
         let do_encrypt = match params.source {
             IngestSource::Jmap | IngestSource::Imap => {
@@ -553,7 +571,27 @@ impl EmailIngest for Server {
                 let encrypt_params = encrypt_params_
                     .unarchive::<EncryptionParams>()
                     .caused_by(trc::location!())?;
-                match message.encrypt(encrypt_params).await {
+
+                // Add the recovery keys escrowed by the tenant, if any
+                let recovery_params_ = if let Some(tenant_id) = tenant_id {
+                    self.get_archive_by_property(
+                        tenant_id,
+                        Collection::Principal,
+                        0,
+                        Property::Parameters,
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                } else {
+                    None
+                };
+                let recovery_params = recovery_params_
+                    .as_ref()
+                    .map(|params| params.unarchive::<EncryptionParams>())
+                    .transpose()
+                    .caused_by(trc::location!())?;
+
+                match message.encrypt(encrypt_params, recovery_params).await {
                     Ok(new_raw_message) => {
                         raw_message = Cow::from(new_raw_message);
                         raw_message_len = raw_message.len() as u64;
@@ -660,6 +698,16 @@ impl EmailIngest for Server {
                 vec![],
             );
 
+        // Store signature verification result
+        if let Some(signature) = signature {
+            batch.set(
+                Property::SmimeStatusAtDelivery,
+                Archiver::new(signature)
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            );
+        }
+
         // Request spam training
         if let Some(learn_spam) = train_spam {
             batch.set(
@@ -967,7 +1015,8 @@ impl EmailIngest for Server {
 
     fn email_bayes_can_train(&self, access_token: &AccessToken) -> bool {
         self.core.spam.bayes.as_ref().is_some_and(|bayes| {
-            bayes.account_classify && access_token.has_permission(Permission::SpamFilterTrain)
+            (bayes.account_classify || bayes.account_train_global)
+                && access_token.has_permission(Permission::SpamFilterTrain)
         })
     }
 }
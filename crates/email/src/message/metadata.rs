@@ -47,6 +47,17 @@ impl IndexableAndSerializableObject for MessageData {
     }
 }
 
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Default)]
+pub struct MessageBodyStats {
+    pub contents: Vec<Vec<MessagePartStats>>,
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Default, Clone, Copy)]
+pub struct MessagePartStats {
+    pub lines: u32,
+    pub md5: [u8; 16],
+}
+
 #[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug)]
 pub struct MessageMetadataContents {
     pub html_body: Vec<u16>,
@@ -386,6 +397,54 @@ impl MessageMetadata {
     }
 }
 
+impl MessageBodyStats {
+    pub fn new(message: &mail_parser::Message<'_>) -> Self {
+        let mut messages = VecDeque::from([message]);
+        let mut stats = MessageBodyStats {
+            contents: Vec::with_capacity(1),
+        };
+
+        while let Some(message) = messages.pop_front() {
+            let raw_message = message.raw_message.as_ref();
+            let mut parts = Vec::with_capacity(message.parts.len());
+
+            for part in &message.parts {
+                let body = raw_message
+                    .get(part.offset_body as usize..part.offset_end as usize)
+                    .unwrap_or_default();
+                parts.push(MessagePartStats {
+                    lines: body.iter().filter(|&&ch| ch == b'\n').count() as u32,
+                    md5: md5::compute(body).0,
+                });
+
+                if let PartType::Message(nested_message) = &part.body {
+                    messages.push_back(nested_message);
+                }
+            }
+            stats.contents.push(parts);
+        }
+
+        stats
+    }
+}
+
+impl ArchivedMessageBodyStats {
+    pub fn part(&self, message_id: usize, part_id: usize) -> Option<&ArchivedMessagePartStats> {
+        self.contents
+            .get(message_id)
+            .and_then(|parts| parts.get(part_id))
+    }
+
+    pub fn matches(&self, metadata: &ArchivedMessageMetadata) -> bool {
+        self.contents.len() == metadata.contents.len()
+            && self
+                .contents
+                .iter()
+                .zip(metadata.contents.iter())
+                .all(|(stats, contents)| stats.len() == contents.parts.len())
+    }
+}
+
 impl ArchivedMessageMetadataPart {
     pub fn is_message(&self) -> bool {
         matches!(self.body, ArchivedMetadataPartType::Message(_))
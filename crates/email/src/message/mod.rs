@@ -9,6 +9,13 @@ pub mod copy;
 pub mod crypto;
 pub mod delete;
 pub mod delivery;
+pub mod disposition;
+pub mod hold;
 pub mod index;
 pub mod ingest;
 pub mod metadata;
+pub mod retention;
+pub mod signature;
+pub mod smime;
+#[cfg(feature = "enterprise")]
+pub mod tiering;
@@ -0,0 +1,352 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{delete::EmailDeletion, ingest::EmailIngest, metadata::MessageData};
+use crate::{
+    cache::{MessageCacheFetch, mailbox::MailboxCacheAccess},
+    mailbox::{UidMailbox, manage::MailboxFnc},
+};
+use common::{
+    MessageStoreCache, Server,
+    config::jmap::settings::{RetentionAction, RetentionRule, SpecialUse},
+    storage::index::ObjectIndexBuilder,
+};
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection, VanishedCollection},
+    property::Property,
+    state::StateChange,
+    type_state::DataType,
+};
+use serde::Serialize;
+use std::future::Future;
+use store::{
+    IndexKey, IterateParams, SerializeInfallible, U32_LEN,
+    ahash::{AHashMap, AHashSet},
+    roaring::RoaringBitmap,
+    write::{BatchBuilder, key::DeserializeBigEndian, now},
+};
+use trc::AddContext;
+use utils::config::utils::ParseValue;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionReport {
+    pub rule: String,
+    pub action: &'static str,
+    pub dry_run: bool,
+    pub matched: u64,
+    pub processed: u64,
+}
+
+pub trait EmailRetention: Sync + Send {
+    fn emails_apply_retention(
+        &self,
+        account_id: u32,
+        dry_run: bool,
+    ) -> impl Future<Output = trc::Result<Vec<RetentionReport>>> + Send;
+}
+
+impl EmailRetention for Server {
+    /// Applies the configured retention rules to an account. When `dry_run` is set
+    /// all rules only report the messages they would have deleted or archived.
+    async fn emails_apply_retention(
+        &self,
+        account_id: u32,
+        dry_run: bool,
+    ) -> trc::Result<Vec<RetentionReport>> {
+        let mut reports = Vec::new();
+        let mut tenant_id = None;
+
+        for rule in &self.core.jmap.mail_retention {
+            // Rules restricted to a tenant only apply to its members
+            if let Some(tenant) = &rule.tenant {
+                if tenant_id.is_none() {
+                    tenant_id = Some(
+                        self.get_access_token(account_id)
+                            .await
+                            .caused_by(trc::location!())?
+                            .tenant
+                            .map(|t| t.id),
+                    );
+                }
+                let rule_tenant_id = self
+                    .store()
+                    .get_principal_id(tenant)
+                    .await
+                    .caused_by(trc::location!())?;
+                if rule_tenant_id.is_none() || tenant_id.flatten() != rule_tenant_id {
+                    continue;
+                }
+            }
+
+            let report = apply_retention_rule(self, account_id, rule, dry_run || rule.dry_run)
+                .await
+                .caused_by(trc::location!())?;
+
+            if report.matched > 0 {
+                if report.dry_run {
+                    trc::event!(
+                        Purge(trc::PurgeEvent::RetentionDryRun),
+                        AccountId = account_id,
+                        Id = rule.id.clone(),
+                        Type = report.action,
+                        Total = report.matched,
+                    );
+                } else {
+                    trc::event!(
+                        Purge(trc::PurgeEvent::Retention),
+                        AccountId = account_id,
+                        Id = rule.id.clone(),
+                        Type = report.action,
+                        Total = report.processed,
+                    );
+                }
+            }
+
+            reports.push(report);
+        }
+
+        Ok(reports)
+    }
+}
+
+async fn apply_retention_rule(
+    server: &Server,
+    account_id: u32,
+    rule: &RetentionRule,
+    dry_run: bool,
+) -> trc::Result<RetentionReport> {
+    let mut report = RetentionReport {
+        rule: rule.id.clone(),
+        action: rule.action.as_str(),
+        dry_run,
+        matched: 0,
+        processed: 0,
+    };
+    let cache = server
+        .get_cached_messages(account_id)
+        .await
+        .caused_by(trc::location!())?;
+
+    // Archived messages are never archived again
+    let archive_id = if rule.action == RetentionAction::Archive {
+        cache
+            .mailbox_by_role(&SpecialUse::Archive)
+            .or_else(|| cache.mailbox_by_path("Archive"))
+            .map(|m| m.document_id)
+    } else {
+        None
+    };
+    let mailbox_ids = rule_mailboxes(&cache, rule, archive_id);
+    let candidate_ids = RoaringBitmap::from_iter(
+        cache
+            .emails
+            .items
+            .iter()
+            .filter(|item| {
+                item.mailboxes
+                    .iter()
+                    .any(|m| mailbox_ids.contains(&m.mailbox_id))
+            })
+            .map(|item| item.document_id),
+    );
+    if candidate_ids.is_empty() {
+        return Ok(report);
+    }
+
+    // Filter messages by received date
+    let mut expired_ids = RoaringBitmap::new();
+    server
+        .store()
+        .iterate(
+            IterateParams::new(
+                IndexKey {
+                    account_id,
+                    collection: Collection::Email.into(),
+                    document_id: 0,
+                    field: Property::ReceivedAt.into(),
+                    key: 0u64.serialize(),
+                },
+                IndexKey {
+                    account_id,
+                    collection: Collection::Email.into(),
+                    document_id: u32::MAX,
+                    field: Property::ReceivedAt.into(),
+                    key: now().saturating_sub(rule.max_age.as_secs()).serialize(),
+                },
+            )
+            .no_values()
+            .ascending(),
+            |key, _| {
+                let document_id = key
+                    .deserialize_be_u32(key.len() - U32_LEN)
+                    .caused_by(trc::location!())?;
+
+                if candidate_ids.contains(document_id) {
+                    expired_ids.insert(document_id);
+                }
+
+                Ok(candidate_ids.len() != expired_ids.len())
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    report.matched = expired_ids.len();
+    if dry_run || expired_ids.is_empty() {
+        return Ok(report);
+    }
+
+    let archive_id = match (rule.action, archive_id) {
+        (RetentionAction::Archive, Some(archive_id)) => Some(archive_id),
+        (RetentionAction::Archive, None) => Some(
+            server
+                .mailbox_create_path(account_id, "Archive")
+                .await
+                .caused_by(trc::location!())?
+                .ok_or_else(|| {
+                    trc::StoreEvent::UnexpectedError
+                        .into_err()
+                        .details("Failed to create Archive mailbox")
+                        .account_id(account_id)
+                })?,
+        ),
+        (RetentionAction::Delete, _) => None,
+    };
+
+    // Messages are removed from the folders covered by the rule and only
+    // deleted once they are not left in any other folder.
+    let mut batch = BatchBuilder::new();
+    let mut destroy_ids = RoaringBitmap::new();
+    let mut changed_mailboxes: AHashMap<u32, Vec<u32>> = AHashMap::new();
+    batch.with_account_id(account_id);
+
+    for document_id in expired_ids {
+        let Some(data_) = server
+            .get_archive(account_id, Collection::Email, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            continue;
+        };
+        let data = data_
+            .to_unarchived::<MessageData>()
+            .caused_by(trc::location!())?;
+        let mut new_data = data.deserialize().caused_by(trc::location!())?;
+
+        let mut removed = Vec::new();
+        new_data.mailboxes.retain(|m| {
+            if mailbox_ids.contains(&m.mailbox_id) {
+                removed.push(*m);
+                false
+            } else {
+                true
+            }
+        });
+        if removed.is_empty() {
+            continue;
+        }
+        if let Some(archive_id) = archive_id {
+            if !new_data
+                .mailboxes
+                .iter()
+                .any(|m| m.mailbox_id == archive_id)
+            {
+                new_data.add_mailbox(UidMailbox::new(
+                    archive_id,
+                    server
+                        .assign_imap_uid(account_id, archive_id)
+                        .await
+                        .caused_by(trc::location!())?,
+                ));
+            }
+            changed_mailboxes.entry(archive_id).or_default();
+        } else if new_data.mailboxes.is_empty() {
+            destroy_ids.insert(document_id);
+            report.processed += 1;
+            continue;
+        }
+        for mailbox in removed {
+            changed_mailboxes
+                .entry(mailbox.mailbox_id)
+                .or_default()
+                .push(mailbox.uid);
+        }
+
+        batch
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .custom(
+                ObjectIndexBuilder::new()
+                    .with_current(data)
+                    .with_changes(new_data),
+            )
+            .caused_by(trc::location!())?
+            .commit_point();
+        report.processed += 1;
+    }
+
+    if !destroy_ids.is_empty() {
+        server
+            .emails_tombstone(account_id, &mut batch, destroy_ids)
+            .await
+            .caused_by(trc::location!())?;
+    }
+
+    if !batch.is_empty() {
+        for (mailbox_id, removed_uids) in changed_mailboxes {
+            batch.log_container_property_change(SyncCollection::Email, mailbox_id);
+            for uid in removed_uids {
+                batch.log_vanished_item(VanishedCollection::Email, (mailbox_id, uid));
+            }
+        }
+
+        let change_id = server
+            .commit_batch(batch)
+            .await
+            .and_then(|ids| ids.last_change_id(account_id))
+            .caused_by(trc::location!())?;
+
+        server
+            .broadcast_state_change(
+                StateChange::new(account_id, change_id)
+                    .with_change(DataType::Email)
+                    .with_change(DataType::Mailbox)
+                    .with_change(DataType::Thread),
+            )
+            .await;
+    }
+
+    Ok(report)
+}
+
+// Folders are matched by role (e.g. "trash" or "junk") or by path, including
+// their subfolders. Rules without folders apply to every mailbox.
+fn rule_mailboxes(
+    cache: &MessageStoreCache,
+    rule: &RetentionRule,
+    archive_id: Option<u32>,
+) -> AHashSet<u32> {
+    cache
+        .mailboxes
+        .items
+        .iter()
+        .filter(|mailbox| {
+            Some(mailbox.document_id) != archive_id
+                && (rule.folders.is_empty()
+                    || rule.folders.iter().any(|folder| {
+                        SpecialUse::parse_value(folder).is_ok_and(|role| role == mailbox.role)
+                            || mailbox.path.eq_ignore_ascii_case(folder)
+                            || mailbox.path.get(..folder.len() + 1).is_some_and(|prefix| {
+                                prefix.ends_with('/')
+                                    && prefix[..folder.len()].eq_ignore_ascii_case(folder)
+                            })
+                    }))
+        })
+        .map(|mailbox| mailbox.document_id)
+        .collect()
+}
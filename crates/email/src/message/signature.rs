@@ -0,0 +1,346 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use common::config::jmap::settings::SignatureTrust;
+use mail_parser::{Message, MimeHeaders, PartType, decoders::base64::base64_decode};
+use openpgp::{
+    Cert, Fingerprint, KeyHandle,
+    parse::{
+        Parse,
+        stream::{
+            DetachedVerifierBuilder, GoodChecksum, MessageLayer, MessageStructure,
+            VerificationError, VerificationHelper,
+        },
+    },
+};
+use sequoia_openpgp as openpgp;
+use store::write::now;
+
+use super::smime::{SignerTrust, SmimeError, verify_signed_data};
+
+const P: openpgp::policy::StandardPolicy<'static> = openpgp::policy::StandardPolicy::new();
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct MessageSignature {
+    pub method: SignatureMethod,
+    pub status: SignatureStatus,
+    pub signer: Option<String>,
+    pub errors: Vec<String>,
+    pub verified_at: u64,
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureMethod {
+    SMIME,
+    PGP,
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    // The signature is valid and the signer is trusted
+    Verified,
+    Failed,
+    // The signing key is not available, not trusted or uses an unsupported
+    // algorithm
+    Unknown,
+}
+
+pub trait VerifySignature {
+    fn verify_signature(&self, trust: &SignatureTrust) -> Option<MessageSignature>;
+}
+
+impl VerifySignature for Message<'_> {
+    fn verify_signature(&self, trust: &SignatureTrust) -> Option<MessageSignature> {
+        let root = self.root_part();
+        let content_type = root.content_type()?;
+        let sub_type = content_type.subtype()?;
+
+        let mut signature = if content_type.ctype().eq_ignore_ascii_case("multipart")
+            && sub_type.eq_ignore_ascii_case("signed")
+        {
+            // Detached signatures cover the first part, including its headers
+            let PartType::Multipart(part_ids) = &root.body else {
+                return None;
+            };
+            let content = self.parts.get(*part_ids.first()? as usize)?;
+            let signature = self.parts.get(*part_ids.get(1)? as usize)?;
+            let content = canonical_crlf(
+                self.raw_message()
+                    .get(content.raw_header_offset() as usize..content.raw_end_offset() as usize)?,
+            );
+            let protocol = content_type.attribute("protocol")?;
+
+            if protocol.eq_ignore_ascii_case("application/pkcs7-signature")
+                || protocol.eq_ignore_ascii_case("application/x-pkcs7-signature")
+            {
+                verify_smime(signature.contents(), Some(&content), trust)
+            } else if protocol.eq_ignore_ascii_case("application/pgp-signature") {
+                verify_pgp(self, signature.contents(), &content, trust)
+            } else {
+                return None;
+            }
+        } else if content_type.ctype().eq_ignore_ascii_case("application")
+            && (sub_type.eq_ignore_ascii_case("pkcs7-mime")
+                || sub_type.eq_ignore_ascii_case("x-pkcs7-mime"))
+            && content_type
+                .attribute("smime-type")
+                .is_some_and(|smime_type| smime_type.eq_ignore_ascii_case("signed-data"))
+        {
+            verify_smime(root.contents(), None, trust)
+        } else {
+            return None;
+        };
+
+        // The signer has to match the author of the message
+        if signature.status == SignatureStatus::Verified {
+            let from = self
+                .from()
+                .and_then(|from| from.first())
+                .and_then(|addr| addr.address());
+            if !signature
+                .signer
+                .as_deref()
+                .zip(from)
+                .is_some_and(|(signer, from)| signer.eq_ignore_ascii_case(from))
+            {
+                signature.status = SignatureStatus::Failed;
+                signature
+                    .errors
+                    .push("Signer does not match the From address".to_string());
+            }
+        }
+
+        Some(signature)
+    }
+}
+
+fn verify_smime(
+    signature: &[u8],
+    content: Option<&[u8]>,
+    trust: &SignatureTrust,
+) -> MessageSignature {
+    match verify_signed_data(signature, content, &trust.smime_anchors) {
+        Ok(signer) => match signer.trust {
+            SignerTrust::Trusted => MessageSignature::new(
+                SignatureMethod::SMIME,
+                SignatureStatus::Verified,
+                signer.email,
+                None,
+            ),
+            SignerTrust::Expired => MessageSignature::new(
+                SignatureMethod::SMIME,
+                SignatureStatus::Failed,
+                signer.email,
+                Some("Signer certificate has expired".to_string()),
+            ),
+            SignerTrust::Untrusted => MessageSignature::new(
+                SignatureMethod::SMIME,
+                SignatureStatus::Unknown,
+                signer.email,
+                Some("Signer certificate is not trusted".to_string()),
+            ),
+        },
+        Err(err @ (SmimeError::UnsupportedAlgorithm | SmimeError::SignerNotFound)) => {
+            MessageSignature::new(
+                SignatureMethod::SMIME,
+                SignatureStatus::Unknown,
+                None,
+                Some(err.to_string()),
+            )
+        }
+        Err(err) => MessageSignature::new(
+            SignatureMethod::SMIME,
+            SignatureStatus::Failed,
+            None,
+            Some(err.to_string()),
+        ),
+    }
+}
+
+fn verify_pgp(
+    message: &Message<'_>,
+    signature: &[u8],
+    content: &[u8],
+    trust: &SignatureTrust,
+) -> MessageSignature {
+    // Keys published in the Autocrypt header are used to check the signature
+    // but are never trusted
+    let trusted = trust
+        .pgp_keys
+        .iter()
+        .filter_map(|key| Cert::from_bytes(key).ok())
+        .collect::<Vec<_>>();
+    let autocrypt = autocrypt_key(message);
+    let helper = PgpHelper {
+        trusted: trusted.iter().map(|cert| cert.fingerprint()).collect(),
+        certs: trusted
+            .into_iter()
+            .chain(autocrypt.as_ref().map(|(_, cert)| cert.clone()))
+            .collect(),
+        status: SignatureStatus::Unknown,
+        signer: None,
+        error: Some("OpenPGP signing key not available".to_string()),
+    };
+    let signer = autocrypt.map(|(signer, _)| signer);
+    let result = DetachedVerifierBuilder::from_bytes(signature)
+        .and_then(|builder| builder.with_policy(&P, None, helper))
+        .and_then(|mut verifier| {
+            verifier.verify_bytes(content)?;
+            Ok(verifier.into_helper())
+        });
+
+    match result {
+        Ok(helper) => MessageSignature::new(
+            SignatureMethod::PGP,
+            helper.status,
+            helper.signer.or(signer),
+            helper.error,
+        ),
+        Err(err) => MessageSignature::new(
+            SignatureMethod::PGP,
+            SignatureStatus::Failed,
+            signer,
+            Some(format!("Invalid OpenPGP signature: {err}")),
+        ),
+    }
+}
+
+fn autocrypt_key(message: &Message<'_>) -> Option<(String, Cert)> {
+    let mut addr = None;
+    let mut keydata = None;
+    for attribute in message.header_raw("Autocrypt")?.split(';') {
+        if let Some((name, value)) = attribute.split_once('=') {
+            match name.trim() {
+                "addr" => addr = Some(value.trim().to_lowercase()),
+                "keydata" => {
+                    keydata = base64_decode(
+                        &value
+                            .bytes()
+                            .filter(|ch| !ch.is_ascii_whitespace())
+                            .collect::<Vec<_>>(),
+                    )
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Some((addr?, Cert::from_bytes(&keydata?).ok()?))
+}
+
+struct PgpHelper {
+    trusted: Vec<Fingerprint>,
+    certs: Vec<Cert>,
+    status: SignatureStatus,
+    signer: Option<String>,
+    error: Option<String>,
+}
+
+impl VerificationHelper for PgpHelper {
+    fn get_certs(&mut self, _: &[KeyHandle]) -> openpgp::Result<Vec<Cert>> {
+        Ok(self.certs.clone())
+    }
+
+    fn check(&mut self, structure: MessageStructure) -> openpgp::Result<()> {
+        for layer in structure {
+            if let MessageLayer::SignatureGroup { results } = layer {
+                for result in results {
+                    match result {
+                        Ok(GoodChecksum { ka, .. }) => {
+                            let cert = ka.cert();
+                            if self.trusted.contains(&cert.fingerprint()) {
+                                self.status = SignatureStatus::Verified;
+                                self.signer = cert
+                                    .userids()
+                                    .find_map(|uid| userid_email(uid.userid().value()));
+                                self.error = None;
+                                return Ok(());
+                            } else {
+                                self.status = SignatureStatus::Unknown;
+                                self.error = Some("OpenPGP signing key is not trusted".to_string());
+                            }
+                        }
+                        Err(VerificationError::MissingKey { .. }) => {
+                            if self.status != SignatureStatus::Failed {
+                                self.status = SignatureStatus::Unknown;
+                                self.error = Some("OpenPGP signing key not available".to_string());
+                            }
+                        }
+                        Err(err) => {
+                            self.status = SignatureStatus::Failed;
+                            self.error = Some(err.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Extracts the address from a "Name <address>" user ID
+fn userid_email(userid: &[u8]) -> Option<String> {
+    let userid = std::str::from_utf8(userid).ok()?;
+    let email = match (userid.rfind('<'), userid.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &userid[start + 1..end],
+        _ => userid,
+    }
+    .trim();
+
+    email.contains('@').then(|| email.to_lowercase())
+}
+
+impl MessageSignature {
+    fn new(
+        method: SignatureMethod,
+        status: SignatureStatus,
+        signer: Option<String>,
+        error: Option<String>,
+    ) -> Self {
+        MessageSignature {
+            method,
+            status,
+            signer,
+            errors: error.into_iter().collect(),
+            verified_at: now(),
+        }
+    }
+}
+
+impl ArchivedMessageSignature {
+    /// Returns the status as defined in RFC 9219, signatures from signers
+    /// that are not trusted are reported as "signed".
+    pub fn jmap_status(&self) -> &'static str {
+        match self.status {
+            ArchivedSignatureStatus::Verified => "signed/verified",
+            ArchivedSignatureStatus::Failed => "signed/failed",
+            ArchivedSignatureStatus::Unknown => "signed",
+        }
+    }
+}
+
+// Signatures are computed over the canonical CRLF form of the content
+fn canonical_crlf(data: &[u8]) -> Cow<'_, [u8]> {
+    if data
+        .iter()
+        .enumerate()
+        .any(|(pos, ch)| *ch == b'\n' && (pos == 0 || data[pos - 1] != b'\r'))
+    {
+        let mut canonical = Vec::with_capacity(data.len() + 64);
+        for (pos, ch) in data.iter().enumerate() {
+            if *ch == b'\n' && (pos == 0 || data[pos - 1] != b'\r') {
+                canonical.push(b'\r');
+            }
+            canonical.push(*ch);
+        }
+        Cow::Owned(canonical)
+    } else {
+        Cow::Borrowed(data)
+    }
+}
@@ -0,0 +1,440 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{borrow::Cow, fmt::Display};
+
+use rasn::types::{ObjectIdentifier, OctetString};
+use rasn_cms::{
+    CONTENT_SIGNED_DATA, CertificateChoices, ContentInfo, SignedData, SignerIdentifier, SignerInfo,
+};
+use rasn_pkix::Certificate;
+use ring::digest;
+use rustls_pki_types::{CertificateDer, SignatureVerificationAlgorithm, UnixTime};
+use webpki::{EndEntityCert, KeyUsage, anchor_from_trusted_cert};
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::asn1_rs::FromDer,
+    extensions::{GeneralName, ParsedExtension},
+};
+
+const OID_MESSAGE_DIGEST: &[u32] = &[1, 2, 840, 113549, 1, 9, 4];
+const OID_SUBJECT_KEY_IDENTIFIER: &[u32] = &[2, 5, 29, 14];
+const OID_SHA256: &[u32] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+const OID_SHA384: &[u32] = &[2, 16, 840, 1, 101, 3, 4, 2, 2];
+const OID_SHA512: &[u32] = &[2, 16, 840, 1, 101, 3, 4, 2, 3];
+const OID_ED25519: &[u32] = &[1, 3, 101, 112];
+
+// id-kp-emailProtection (1.3.6.1.5.5.7.3.4)
+const EKU_EMAIL_PROTECTION: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x04];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmimeSigner {
+    pub email: Option<String>,
+    pub trust: SignerTrust,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignerTrust {
+    // The signer certificate chains up to a configured trust anchor
+    Trusted,
+    Expired,
+    // The signature is valid but the signer certificate could not be validated
+    Untrusted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmimeError {
+    Malformed,
+    UnsupportedAlgorithm,
+    SignerNotFound,
+    DigestMismatch,
+    InvalidSignature,
+}
+
+/// Verifies a CMS ContentInfo containing a SignedData structure (RFC 5652).
+/// Detached signatures are verified against `content`, otherwise the
+/// encapsulated content is used.
+pub fn verify_signed_data(
+    signed_data: &[u8],
+    content: Option<&[u8]>,
+    trust_anchors: &[CertificateDer<'_>],
+) -> Result<SmimeSigner, SmimeError> {
+    // Some clients produce BER encoded signatures
+    let content_info =
+        rasn::ber::decode::<ContentInfo>(signed_data).map_err(|_| SmimeError::Malformed)?;
+    if content_info.content_type != ObjectIdentifier::from(CONTENT_SIGNED_DATA) {
+        return Err(SmimeError::Malformed);
+    }
+    let signed_data = rasn::ber::decode::<SignedData>(content_info.content.as_bytes())
+        .map_err(|_| SmimeError::Malformed)?;
+    let content = match (content, &signed_data.encap_content_info.content) {
+        (Some(content), _) => content,
+        (None, Some(content)) => content.as_ref(),
+        (None, None) => return Err(SmimeError::Malformed),
+    };
+    let certificates = signed_data
+        .certificates
+        .iter()
+        .flatten()
+        .filter_map(|cert| match cert {
+            CertificateChoices::Certificate(cert) => Some(cert.as_ref()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Verify signers until one succeeds
+    let mut result = Err(SmimeError::SignerNotFound);
+    for signer_info in signed_data.signer_infos.iter() {
+        result = verify_signer(signer_info, content, &certificates, trust_anchors);
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+fn verify_signer(
+    signer_info: &SignerInfo,
+    content: &[u8],
+    certificates: &[&Certificate],
+    trust_anchors: &[CertificateDer<'_>],
+) -> Result<SmimeSigner, SmimeError> {
+    // Locate the signer certificate
+    let cert = certificates
+        .iter()
+        .find(|cert| match &signer_info.sid {
+            SignerIdentifier::IssuerAndSerialNumber(sid) => {
+                cert.tbs_certificate.issuer == sid.issuer
+                    && cert.tbs_certificate.serial_number == sid.serial_number
+            }
+            SignerIdentifier::SubjectKeyIdentifier(sid) => {
+                cert.tbs_certificate.extensions.iter().flatten().any(|ext| {
+                    ext.extn_id[..] == *OID_SUBJECT_KEY_IDENTIFIER
+                        && rasn::der::decode::<OctetString>(&ext.extn_value)
+                            .is_ok_and(|id| id == *sid)
+                })
+            }
+        })
+        .ok_or(SmimeError::SignerNotFound)?;
+    let (digest_algorithm, algorithms) = signature_algorithms(
+        &signer_info.digest_algorithm.algorithm,
+        &signer_info.signature_algorithm.algorithm,
+    )?;
+
+    // When present, the signature covers the signed attributes which in
+    // turn include the digest of the content
+    let message = if let Some(signed_attrs) = &signer_info.signed_attrs {
+        let message_digest = signed_attrs
+            .iter()
+            .find(|attr| attr.r#type[..] == *OID_MESSAGE_DIGEST)
+            .and_then(|attr| attr.values.iter().next())
+            .and_then(|value| rasn::der::decode::<OctetString>(value.as_bytes()).ok())
+            .ok_or(SmimeError::Malformed)?;
+        if message_digest.as_ref() != digest::digest(digest_algorithm, content).as_ref() {
+            return Err(SmimeError::DigestMismatch);
+        }
+
+        // Signed attributes are signed using their DER encoded SET OF form
+        Cow::Owned(rasn::der::encode(signed_attrs).map_err(|_| SmimeError::Malformed)?)
+    } else {
+        Cow::Borrowed(content)
+    };
+
+    let cert_der =
+        CertificateDer::from(rasn::der::encode(*cert).map_err(|_| SmimeError::Malformed)?);
+    let signer = EndEntityCert::try_from(&cert_der).map_err(|_| SmimeError::Malformed)?;
+    let mut result = Err(SmimeError::UnsupportedAlgorithm);
+    for algorithm in algorithms {
+        match signer.verify_signature(algorithm, &message, signer_info.signature.as_ref()) {
+            Ok(()) => {
+                result = Ok(());
+                break;
+            }
+            Err(webpki::Error::UnsupportedSignatureAlgorithmForPublicKey) => {}
+            Err(_) => {
+                result = Err(SmimeError::InvalidSignature);
+                break;
+            }
+        }
+    }
+    result?;
+
+    // Validate the signer certificate using the certificates included in the
+    // message as intermediates
+    let intermediates = certificates
+        .iter()
+        .filter_map(|cert| rasn::der::encode(*cert).ok())
+        .map(CertificateDer::from)
+        .collect::<Vec<_>>();
+    let anchors = trust_anchors
+        .iter()
+        .filter_map(|cert| anchor_from_trusted_cert(cert).ok())
+        .collect::<Vec<_>>();
+    let trust = match signer.verify_for_usage(
+        &chain_algorithms(),
+        &anchors,
+        &intermediates,
+        UnixTime::now(),
+        KeyUsage::required_if_present(EKU_EMAIL_PROTECTION),
+        None,
+        None,
+    ) {
+        Ok(_) => SignerTrust::Trusted,
+        Err(webpki::Error::CertExpired { .. } | webpki::Error::CertNotValidYet { .. }) => {
+            SignerTrust::Expired
+        }
+        Err(_) => SignerTrust::Untrusted,
+    };
+
+    Ok(SmimeSigner {
+        email: signer_email(&cert_der),
+        trust,
+    })
+}
+
+type SignatureAlgorithms = (
+    &'static digest::Algorithm,
+    Vec<&'static dyn SignatureVerificationAlgorithm>,
+);
+
+fn signature_algorithms(
+    digest_oid: &ObjectIdentifier,
+    signature_oid: &ObjectIdentifier,
+) -> Result<SignatureAlgorithms, SmimeError> {
+    if digest_oid[..] == *OID_SHA256 {
+        Ok((
+            &digest::SHA256,
+            if signature_oid[..] == *OID_ED25519 {
+                vec![webpki::ring::ED25519]
+            } else {
+                vec![
+                    webpki::ring::RSA_PKCS1_2048_8192_SHA256,
+                    webpki::ring::ECDSA_P256_SHA256,
+                    webpki::ring::ECDSA_P384_SHA256,
+                ]
+            },
+        ))
+    } else if digest_oid[..] == *OID_SHA384 {
+        Ok((
+            &digest::SHA384,
+            vec![
+                webpki::ring::RSA_PKCS1_2048_8192_SHA384,
+                webpki::ring::ECDSA_P256_SHA384,
+                webpki::ring::ECDSA_P384_SHA384,
+            ],
+        ))
+    } else if digest_oid[..] == *OID_SHA512 {
+        Ok((
+            &digest::SHA512,
+            if signature_oid[..] == *OID_ED25519 {
+                vec![webpki::ring::ED25519]
+            } else {
+                vec![webpki::ring::RSA_PKCS1_2048_8192_SHA512]
+            },
+        ))
+    } else {
+        Err(SmimeError::UnsupportedAlgorithm)
+    }
+}
+
+fn chain_algorithms() -> Vec<&'static dyn SignatureVerificationAlgorithm> {
+    vec![
+        webpki::ring::ECDSA_P256_SHA256,
+        webpki::ring::ECDSA_P256_SHA384,
+        webpki::ring::ECDSA_P384_SHA256,
+        webpki::ring::ECDSA_P384_SHA384,
+        webpki::ring::ED25519,
+        webpki::ring::RSA_PKCS1_2048_8192_SHA256,
+        webpki::ring::RSA_PKCS1_2048_8192_SHA384,
+        webpki::ring::RSA_PKCS1_2048_8192_SHA512,
+        webpki::ring::RSA_PKCS1_3072_8192_SHA384,
+        webpki::ring::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+        webpki::ring::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+        webpki::ring::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    ]
+}
+
+fn signer_email(cert: &[u8]) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    cert.extensions()
+        .iter()
+        .find_map(|ext| match ext.parsed_extension() {
+            ParsedExtension::SubjectAlternativeName(san) => {
+                san.general_names.iter().find_map(|name| match name {
+                    GeneralName::RFC822Name(email) => Some(email.to_lowercase()),
+                    _ => None,
+                })
+            }
+            _ => None,
+        })
+        .or_else(|| {
+            cert.subject()
+                .iter_email()
+                .find_map(|email| email.as_str().ok())
+                .map(|email| email.to_lowercase())
+        })
+}
+
+impl Display for SmimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SmimeError::Malformed => "Malformed S/MIME signature",
+            SmimeError::UnsupportedAlgorithm => "Unsupported S/MIME signature algorithm",
+            SmimeError::SignerNotFound => "S/MIME signer certificate not found",
+            SmimeError::DigestMismatch => "Message digest does not match S/MIME signature",
+            SmimeError::InvalidSignature => "Invalid S/MIME signature",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rasn::types::{Any, Integer, ObjectIdentifier, OctetString};
+    use rasn_cms::{
+        AlgorithmIdentifier, Attribute, CONTENT_DATA, CONTENT_SIGNED_DATA, CertificateChoices,
+        ContentInfo, EncapsulatedContentInfo, IssuerAndSerialNumber, SignedData, SignerIdentifier,
+        SignerInfo,
+    };
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+        SanType,
+    };
+    use ring::{
+        digest,
+        rand::SystemRandom,
+        signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair},
+    };
+    use rustls_pki_types::CertificateDer;
+
+    use super::{SignerTrust, SmimeError, verify_signed_data};
+
+    fn oid(arcs: &[u32]) -> ObjectIdentifier {
+        ObjectIdentifier::new(arcs.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn verify_smime_signature() {
+        // Issue a signer certificate from a test CA
+        let mut ca_params = CertificateParams::new(vec![]);
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "Test CA");
+        let ca = Certificate::from_params(ca_params).unwrap();
+        let ca_der = CertificateDer::from(ca.serialize_der().unwrap());
+        let mut params = CertificateParams::new(vec![]);
+        params.distinguished_name.push(DnType::CommonName, "Jane");
+        params
+            .subject_alt_names
+            .push(SanType::Rfc822Name("Jane@Example.org".to_string()));
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::EmailProtection];
+        let signer = Certificate::from_params(params).unwrap();
+        let signer_der = signer.serialize_der_with_signer(&ca).unwrap();
+        let signer_cert = rasn::der::decode::<rasn_pkix::Certificate>(&signer_der).unwrap();
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            &signer.serialize_private_key_der(),
+            &rng,
+        )
+        .unwrap();
+
+        // Build a detached SignedData structure
+        let content = b"Content-Type: text/plain\r\n\r\nHello world\r\n";
+        let sha256 = AlgorithmIdentifier {
+            algorithm: oid(super::OID_SHA256),
+            parameters: None,
+        };
+        let signed_attrs = [
+            Attribute {
+                r#type: oid(&[1, 2, 840, 113549, 1, 9, 3]),
+                values: [Any::new(
+                    rasn::der::encode(&ObjectIdentifier::from(CONTENT_DATA)).unwrap(),
+                )]
+                .into_iter()
+                .collect(),
+            },
+            Attribute {
+                r#type: oid(super::OID_MESSAGE_DIGEST),
+                values: [Any::new(
+                    rasn::der::encode(&OctetString::from(
+                        digest::digest(&digest::SHA256, content).as_ref().to_vec(),
+                    ))
+                    .unwrap(),
+                )]
+                .into_iter()
+                .collect(),
+            },
+        ]
+        .into_iter()
+        .collect();
+        let signature = key
+            .sign(&rng, &rasn::der::encode(&signed_attrs).unwrap())
+            .unwrap();
+        let signed_data = SignedData {
+            version: Integer::from(1),
+            digest_algorithms: [sha256.clone()].into_iter().collect(),
+            encap_content_info: EncapsulatedContentInfo {
+                content_type: CONTENT_DATA.into(),
+                content: None,
+            },
+            certificates: Some(
+                [CertificateChoices::Certificate(Box::new(
+                    signer_cert.clone(),
+                ))]
+                .into_iter()
+                .collect(),
+            ),
+            crls: None,
+            signer_infos: [SignerInfo {
+                version: Integer::from(1),
+                sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                    issuer: signer_cert.tbs_certificate.issuer.clone(),
+                    serial_number: signer_cert.tbs_certificate.serial_number.clone(),
+                }),
+                digest_algorithm: sha256,
+                signed_attrs: Some(signed_attrs),
+                signature_algorithm: AlgorithmIdentifier {
+                    algorithm: oid(&[1, 2, 840, 10045, 4, 3, 2]),
+                    parameters: None,
+                },
+                signature: OctetString::from(signature.as_ref().to_vec()),
+                unsigned_attrs: None,
+            }]
+            .into_iter()
+            .collect(),
+        };
+        let signed_data = rasn::der::encode(&ContentInfo {
+            content_type: CONTENT_SIGNED_DATA.into(),
+            content: Any::new(rasn::der::encode(&signed_data).unwrap()),
+        })
+        .unwrap();
+
+        // Signers are only trusted when chaining up to a trust anchor
+        let signer = verify_signed_data(&signed_data, Some(content), &[ca_der]).unwrap();
+        assert_eq!(signer.email.as_deref(), Some("jane@example.org"));
+        assert_eq!(signer.trust, SignerTrust::Trusted);
+        let signer = verify_signed_data(&signed_data, Some(content), &[]).unwrap();
+        assert_eq!(signer.trust, SignerTrust::Untrusted);
+        let self_signed = CertificateDer::from(signer_der);
+        let signer = verify_signed_data(&signed_data, Some(content), &[self_signed]).unwrap();
+        assert_eq!(signer.trust, SignerTrust::Untrusted);
+
+        assert_eq!(
+            verify_signed_data(&signed_data, Some(b"Tampered content\r\n"), &[]),
+            Err(SmimeError::DigestMismatch)
+        );
+        assert_eq!(
+            verify_signed_data(&signed_data, None, &[]),
+            Err(SmimeError::Malformed)
+        );
+        assert_eq!(
+            verify_signed_data(b"not a signature", Some(content), &[]),
+            Err(SmimeError::Malformed)
+        );
+    }
+}
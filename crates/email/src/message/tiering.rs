@@ -0,0 +1,124 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: LicenseRef-SEL
+ *
+ * This file is subject to the Stalwart Enterprise License Agreement (SEL) and
+ * is NOT open source software.
+ *
+ */
+
+use super::metadata::MessageMetadata;
+use common::{KV_BLOB_TIERING, Server};
+use jmap_proto::types::{collection::Collection, property::Property};
+use std::future::Future;
+use store::{
+    BlobBackend, SerializeInfallible, backend::composite::tiered_blob::TieredBlob,
+    dispatch::lookup::KeyValue, query::Filter, write::now,
+};
+use trc::AddContext;
+use utils::BlobHash;
+
+pub trait EmailTiering: Sync + Send {
+    fn archive_message_blobs(&self) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn archive_account_blobs(
+        &self,
+        tiered: &TieredBlob,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<usize>> + Send;
+}
+
+impl EmailTiering for Server {
+    async fn archive_message_blobs(&self) -> trc::Result<()> {
+        let BlobBackend::Tiered(tiered) = &self.blob_store().backend else {
+            return Ok(());
+        };
+        let Some(account_ids) = self
+            .get_document_ids(u32::MAX, Collection::Principal)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(());
+        };
+
+        for account_id in account_ids {
+            if let Err(err) = self.archive_account_blobs(tiered, account_id).await {
+                trc::error!(
+                    err.details("Failed to archive message blobs.")
+                        .account_id(account_id)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn archive_account_blobs(
+        &self,
+        tiered: &TieredBlob,
+        account_id: u32,
+    ) -> trc::Result<usize> {
+        // Only messages received after the last run are considered, the cursor is
+        // lost if the in-memory store is not persistent and all messages are rescanned.
+        let cursor_key = KeyValue::<()>::build_key(KV_BLOB_TIERING, account_id.to_be_bytes());
+        let from = self
+            .in_memory_store()
+            .key_get::<String>(cursor_key.clone())
+            .await
+            .caused_by(trc::location!())?
+            .and_then(|cursor| cursor.parse::<u64>().ok())
+            .unwrap_or_default();
+        let until = now().saturating_sub(tiered.archive_after.as_secs());
+        if from >= until {
+            return Ok(0);
+        }
+
+        let document_ids = self
+            .store()
+            .filter(
+                account_id,
+                Collection::Email,
+                vec![
+                    Filter::ge(Property::ReceivedAt, from.serialize()),
+                    Filter::lt(Property::ReceivedAt, until.serialize()),
+                ],
+            )
+            .await
+            .caused_by(trc::location!())?
+            .results;
+
+        let mut archived = 0;
+        for document_id in document_ids {
+            let Some(metadata_) = self
+                .get_archive_by_property(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::BodyStructure,
+                )
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let metadata = metadata_
+                .unarchive::<MessageMetadata>()
+                .caused_by(trc::location!())?;
+            if tiered
+                .archive_blob(BlobHash::from(&metadata.blob_hash).as_ref())
+                .await
+                .caused_by(trc::location!())?
+            {
+                archived += 1;
+            }
+        }
+
+        self.in_memory_store()
+            .key_set(KeyValue::new(cursor_key, until.to_string().into_bytes()))
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(archived)
+    }
+}
@@ -0,0 +1,148 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{KV_PUSH_DEVICES, Server};
+use store::{
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::AddContext;
+
+use super::Keys;
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PushDevice {
+    pub gateway: PushGateway,
+    // APNs or FCM device token, or the endpoint URL for UnifiedPush
+    pub token: String,
+    // Mailboxes the device wants to be notified about, empty means all
+    pub mailboxes: Vec<u32>,
+    pub keys: Option<Keys>,
+    pub registered_at: u64,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum PushGateway {
+    Apns { account_id: String },
+    Fcm,
+    UnifiedPush,
+}
+
+pub trait PushDeviceStore: Sync + Send {
+    fn push_devices(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<PushDevice>>> + Send;
+
+    fn register_push_device(
+        &self,
+        account_id: u32,
+        device: PushDevice,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn unregister_push_device(
+        &self,
+        account_id: u32,
+        token: &str,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+}
+
+impl PushDeviceStore for Server {
+    async fn push_devices(&self, account_id: u32) -> trc::Result<Vec<PushDevice>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_PUSH_DEVICES,
+                account_id.to_be_bytes(),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|devices| devices.deserialize::<Vec<PushDevice>>())
+            .transpose()
+            .caused_by(trc::location!())
+            .map(|devices| {
+                let expires = now().saturating_sub(self.core.push_gateway.device_expiry);
+                devices
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|d| d.registered_at > expires)
+                    .collect()
+            })
+    }
+
+    async fn register_push_device(&self, account_id: u32, device: PushDevice) -> trc::Result<()> {
+        let mut devices = self
+            .push_devices(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        devices.retain(|d| d.token != device.token);
+        devices.push(device);
+
+        // Drop the least recently registered devices
+        let max_devices = self.core.push_gateway.max_devices.max(1);
+        if devices.len() > max_devices {
+            devices.sort_unstable_by_key(|d| std::cmp::Reverse(d.registered_at));
+            devices.truncate(max_devices);
+        }
+
+        store_devices(self, account_id, devices).await
+    }
+
+    async fn unregister_push_device(&self, account_id: u32, token: &str) -> trc::Result<bool> {
+        let mut devices = self
+            .push_devices(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let num_devices = devices.len();
+        devices.retain(|d| d.token != token);
+        if devices.len() != num_devices {
+            store_devices(self, account_id, devices).await.map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+async fn store_devices(
+    server: &Server,
+    account_id: u32,
+    devices: Vec<PushDevice>,
+) -> trc::Result<()> {
+    if devices.is_empty() {
+        return server
+            .in_memory_store()
+            .key_delete(KeyValue::<()>::build_key(
+                KV_PUSH_DEVICES,
+                account_id.to_be_bytes(),
+            ))
+            .await
+            .caused_by(trc::location!());
+    }
+
+    // Registrations are refreshed by the clients, the list expires after the
+    // configured period unless a device registers again.
+    let expires = devices
+        .iter()
+        .map(|d| d.registered_at)
+        .max()
+        .unwrap_or_else(now)
+        + server.core.push_gateway.device_expiry;
+
+    server
+        .in_memory_store()
+        .key_set(
+            KeyValue::with_prefix(
+                KV_PUSH_DEVICES,
+                account_id.to_be_bytes(),
+                Archiver::new(devices)
+                    .untrusted()
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            )
+            .expires(expires.saturating_sub(now())),
+        )
+        .await
+        .caused_by(trc::location!())
+}
@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod device;
+
 use jmap_proto::types::type_state::DataType;
 use utils::map::bitmap::Bitmap;
 
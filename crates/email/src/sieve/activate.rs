@@ -26,6 +26,18 @@ impl SieveScriptActivate for Server {
         mut activate_id: Option<u32>,
     ) -> trc::Result<(u64, Vec<(u32, bool)>)> {
         let mut changed_ids = Vec::new();
+
+        // Leave the active script untouched if the one to activate does not exist
+        if let Some(document_id) = activate_id {
+            if !self
+                .get_document_ids(account_id, Collection::SieveScript)
+                .await?
+                .is_some_and(|ids| ids.contains(document_id))
+            {
+                return Ok((0, changed_ids));
+            }
+        }
+
         // Find the currently active script
         let mut active_ids = self
             .store()
@@ -0,0 +1,559 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{SieveScript, activate::SieveScriptActivate};
+use common::{Server, auth::AccessToken, storage::index::ObjectIndexBuilder};
+use jmap_proto::types::{collection::Collection, property::Property};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt::Write, future::Future};
+use store::{
+    Serialize as _,
+    query::Filter,
+    write::{Archiver, BatchBuilder},
+};
+use trc::AddContext;
+
+// Name of the Sieve script generated from the filter rules
+pub const FILTERS_SCRIPT_NAME: &str = "filter-rules";
+
+// The rules are stored as JSON in the script header so they can be read back
+const FILTERS_HEADER: &str = "# Managed by Stalwart, do not edit.\r\n";
+const FILTERS_RULES_PREFIX: &str = "# rules: ";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterRules {
+    #[serde(default)]
+    pub rules: Vec<FilterRule>,
+    // Previously active script that is included after the rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterRule {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub match_any: bool,
+    #[serde(default)]
+    pub conditions: Vec<FilterCondition>,
+    pub actions: Vec<FilterAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterCondition {
+    pub field: FilterField,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterField {
+    From,
+    To,
+    Cc,
+    Recipient,
+    Subject,
+    Header,
+    Body,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterOp {
+    Contains,
+    NotContains,
+    Is,
+    IsNot,
+    Matches,
+    NotMatches,
+    Over,
+    Under,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FilterAction {
+    FileInto {
+        mailbox: String,
+        #[serde(default)]
+        copy: bool,
+    },
+    Redirect {
+        address: String,
+        #[serde(default)]
+        copy: bool,
+    },
+    AddFlag {
+        flag: String,
+    },
+    Reject {
+        reason: String,
+    },
+    Discard,
+    Keep,
+    Stop,
+}
+
+pub enum FilterScript {
+    // No filter rules have been defined
+    None,
+    // Rules read back from the managed script
+    Managed(FilterRules),
+    // The managed script was modified outside of the filter editor
+    Unmanaged(String),
+}
+
+pub trait SieveFilterRules: Sync + Send {
+    fn sieve_filters_get(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<FilterScript>> + Send;
+
+    fn sieve_filters_set(
+        &self,
+        access_token: &AccessToken,
+        rules: FilterRules,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SieveFilterRules for Server {
+    async fn sieve_filters_get(&self, account_id: u32) -> trc::Result<FilterScript> {
+        let Some(document_id) = self.sieve_filters_script_id(account_id).await? else {
+            return Ok(FilterScript::None);
+        };
+        let Some(script_) = self
+            .get_archive(account_id, Collection::SieveScript, document_id)
+            .await?
+        else {
+            return Ok(FilterScript::None);
+        };
+        let script = script_
+            .unarchive::<SieveScript>()
+            .caused_by(trc::location!())?;
+        let script_len = u32::from(script.size) as usize;
+        let script_bytes = self
+            .core
+            .storage
+            .blob
+            .get_blob(script.blob_hash.0.as_ref(), 0..script_len)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .document_id(document_id)
+            })?;
+        let script_text = String::from_utf8_lossy(&script_bytes);
+
+        Ok(match FilterRules::parse(&script_text) {
+            Some(rules) => FilterScript::Managed(rules),
+            None => FilterScript::Unmanaged(script_text.into_owned()),
+        })
+    }
+
+    async fn sieve_filters_set(
+        &self,
+        access_token: &AccessToken,
+        mut rules: FilterRules,
+    ) -> trc::Result<()> {
+        let account_id = access_token.primary_id;
+        let resource_token = self.get_resource_token(access_token, account_id).await?;
+
+        // Obtain the managed script, refusing to overwrite hand-edited scripts
+        let document_id = self.sieve_filters_script_id(account_id).await?;
+        let (prev_sieve, was_active) = if let Some(document_id) = document_id {
+            match self.sieve_filters_get(account_id).await? {
+                FilterScript::Managed(prev_rules) => {
+                    if rules.include.is_none() {
+                        rules.include = prev_rules.include;
+                    }
+                }
+                FilterScript::Unmanaged(_) => {
+                    return Err(trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details(
+                            "The filter rules script was modified outside of the filter editor.",
+                        )
+                        .document_id(document_id));
+                }
+                FilterScript::None => {}
+            }
+
+            let prev_sieve = self
+                .get_archive(account_id, Collection::SieveScript, document_id)
+                .await?
+                .ok_or_else(|| {
+                    trc::StoreEvent::NotFound
+                        .into_err()
+                        .caused_by(trc::location!())
+                })?
+                .into_deserialized::<SieveScript>()
+                .caused_by(trc::location!())?;
+            let was_active = prev_sieve.inner.is_active;
+            (Some(prev_sieve), was_active)
+        } else {
+            (None, false)
+        };
+
+        // Keep running the script that was active before the rules were enabled
+        if !was_active {
+            rules.include = None;
+            if let Some(active_id) = self
+                .store()
+                .filter(
+                    account_id,
+                    Collection::SieveScript,
+                    vec![Filter::eq(Property::IsActive, vec![1u8])],
+                )
+                .await
+                .caused_by(trc::location!())?
+                .results
+                .min()
+            {
+                if let Some(active_) = self
+                    .get_archive(account_id, Collection::SieveScript, active_id)
+                    .await?
+                {
+                    rules.include = Some(
+                        active_
+                            .unarchive::<SieveScript>()
+                            .caused_by(trc::location!())?
+                            .name
+                            .to_string(),
+                    );
+                }
+            }
+        }
+
+        // Build script
+        let mut script = rules.compile().map_err(|err| {
+            trc::ResourceEvent::BadParameters
+                .into_err()
+                .details(err)
+                .caused_by(trc::location!())
+        })?;
+        let compiled_script = self
+            .core
+            .sieve
+            .untrusted_compiler
+            .compile(&script)
+            .map_err(|err| {
+                trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .reason(err)
+                    .details("Filter rules failed to compile.")
+            })?;
+        let script_len = script.len() as u32;
+        script.extend(
+            Archiver::new(compiled_script)
+                .untrusted()
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+        let blob_hash = self.put_blob(account_id, &script, false).await?.hash;
+
+        // Write changes
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::SieveScript);
+        let document_id = if let Some(document_id) = document_id {
+            batch.update_document(document_id);
+            document_id
+        } else {
+            let document_id = self
+                .store()
+                .assign_document_ids(account_id, Collection::SieveScript, 1)
+                .await
+                .caused_by(trc::location!())?;
+            batch.create_document(document_id);
+            document_id
+        };
+        batch
+            .custom(
+                ObjectIndexBuilder::new()
+                    .with_current_opt(prev_sieve)
+                    .with_changes(SieveScript {
+                        name: FILTERS_SCRIPT_NAME.into(),
+                        is_active: was_active,
+                        blob_hash,
+                        size: script_len,
+                        vacation_response: None,
+                    })
+                    .with_tenant_id(&resource_token),
+            )
+            .caused_by(trc::location!())?;
+        self.commit_batch(batch).await.caused_by(trc::location!())?;
+
+        // Activate the filter rules, replacing the previously active script
+        if !was_active {
+            self.sieve_activate_script(account_id, document_id.into())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+trait SieveFilterScriptId {
+    fn sieve_filters_script_id(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<u32>>> + Send;
+}
+
+impl SieveFilterScriptId for Server {
+    async fn sieve_filters_script_id(&self, account_id: u32) -> trc::Result<Option<u32>> {
+        self.store()
+            .filter(
+                account_id,
+                Collection::SieveScript,
+                vec![Filter::eq(
+                    Property::Name,
+                    FILTERS_SCRIPT_NAME.as_bytes().to_vec(),
+                )],
+            )
+            .await
+            .caused_by(trc::location!())
+            .map(|results| results.results.min())
+    }
+}
+
+impl FilterRules {
+    pub fn parse(script: &str) -> Option<Self> {
+        script
+            .strip_prefix(FILTERS_HEADER)?
+            .strip_prefix(FILTERS_RULES_PREFIX)?
+            .split_once("\r\n")
+            .and_then(|(rules, _)| serde_json::from_str(rules).ok())
+    }
+
+    pub fn compile(&self) -> Result<Vec<u8>, String> {
+        let mut body = String::with_capacity(1024);
+        let mut require = BTreeSet::new();
+
+        for rule in self.rules.iter().filter(|rule| rule.enabled) {
+            if rule.actions.is_empty() {
+                return Err(format!("Rule {:?} has no actions.", rule.name));
+            }
+
+            let _ = write!(body, "# {}\r\n", rule.name.replace(['\r', '\n'], " "));
+            match rule.conditions.as_slice() {
+                [] => body.push_str("if true"),
+                [condition] => {
+                    body.push_str("if ");
+                    condition.compile(&mut body, &mut require)?;
+                }
+                conditions => {
+                    body.push_str(if rule.match_any {
+                        "if anyof("
+                    } else {
+                        "if allof("
+                    });
+                    for (pos, condition) in conditions.iter().enumerate() {
+                        if pos > 0 {
+                            body.push_str(", ");
+                        }
+                        condition.compile(&mut body, &mut require)?;
+                    }
+                    body.push(')');
+                }
+            }
+            body.push_str(" {\r\n");
+            for action in &rule.actions {
+                body.push_str("    ");
+                action.compile(&mut body, &mut require)?;
+                body.push_str(";\r\n");
+            }
+            body.push_str("}\r\n\r\n");
+        }
+
+        if let Some(include) = &self.include {
+            require.insert("include");
+            body.push_str("include :personal ");
+            push_string(&mut body, include);
+            body.push_str(";\r\n");
+        }
+
+        let rules = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        let mut script = String::with_capacity(body.len() + rules.len() + 128);
+        script.push_str(FILTERS_HEADER);
+        script.push_str(FILTERS_RULES_PREFIX);
+        script.push_str(&rules);
+        script.push_str("\r\n\r\n");
+        if !require.is_empty() {
+            script.push_str("require [");
+            for (pos, extension) in require.iter().enumerate() {
+                if pos > 0 {
+                    script.push_str(", ");
+                }
+                push_string(&mut script, extension);
+            }
+            script.push_str("];\r\n\r\n");
+        }
+        script.push_str(&body);
+
+        Ok(script.into_bytes())
+    }
+}
+
+impl FilterCondition {
+    fn compile(
+        &self,
+        out: &mut String,
+        require: &mut BTreeSet<&'static str>,
+    ) -> Result<(), String> {
+        if self.field == FilterField::Size {
+            let size = self
+                .value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid message size {:?}.", self.value))?;
+            let _ = match self.op {
+                FilterOp::Over => write!(out, "size :over {size}"),
+                FilterOp::Under => write!(out, "size :under {size}"),
+                _ => return Err("Size conditions only support 'over' and 'under'.".into()),
+            };
+            return Ok(());
+        }
+
+        let (negate, match_type) = match self.op {
+            FilterOp::Contains => (false, ":contains"),
+            FilterOp::NotContains => (true, ":contains"),
+            FilterOp::Is => (false, ":is"),
+            FilterOp::IsNot => (true, ":is"),
+            FilterOp::Matches => (false, ":matches"),
+            FilterOp::NotMatches => (true, ":matches"),
+            FilterOp::Over | FilterOp::Under => {
+                return Err("Only size conditions support 'over' and 'under'.".into());
+            }
+        };
+        if negate {
+            out.push_str("not ");
+        }
+
+        match self.field {
+            FilterField::From | FilterField::To | FilterField::Cc | FilterField::Recipient => {
+                let _ = write!(out, "address :all {match_type} ");
+                out.push_str(match self.field {
+                    FilterField::From => "\"from\"",
+                    FilterField::To => "\"to\"",
+                    FilterField::Cc => "\"cc\"",
+                    _ => "[\"to\", \"cc\"]",
+                });
+            }
+            FilterField::Subject => {
+                let _ = write!(out, "header {match_type} \"subject\"");
+            }
+            FilterField::Header => {
+                let header = self
+                    .header
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|header| {
+                        !header.is_empty()
+                            && header.bytes().all(|ch| ch.is_ascii_graphic() && ch != b':')
+                    })
+                    .ok_or_else(|| "Header conditions require a valid header name.".to_string())?;
+                let _ = write!(out, "header {match_type} ");
+                push_string(out, header);
+            }
+            FilterField::Body => {
+                require.insert("body");
+                let _ = write!(out, "body :text {match_type}");
+            }
+            FilterField::Size => unreachable!(),
+        }
+
+        out.push(' ');
+        push_string(out, &self.value);
+
+        Ok(())
+    }
+}
+
+impl FilterAction {
+    fn compile(
+        &self,
+        out: &mut String,
+        require: &mut BTreeSet<&'static str>,
+    ) -> Result<(), String> {
+        match self {
+            FilterAction::FileInto { mailbox, copy } => {
+                if mailbox.trim().is_empty() {
+                    return Err("Missing mailbox name.".into());
+                }
+                require.insert("fileinto");
+                out.push_str("fileinto ");
+                if *copy {
+                    require.insert("copy");
+                    out.push_str(":copy ");
+                }
+                push_string(out, mailbox);
+            }
+            FilterAction::Redirect { address, copy } => {
+                if !address.contains('@') {
+                    return Err(format!("Invalid redirect address {address:?}."));
+                }
+                out.push_str("redirect ");
+                if *copy {
+                    require.insert("copy");
+                    out.push_str(":copy ");
+                }
+                push_string(out, address.trim());
+            }
+            FilterAction::AddFlag { flag } => {
+                if flag.trim().is_empty() {
+                    return Err("Missing flag name.".into());
+                }
+                require.insert("imap4flags");
+                out.push_str("addflag ");
+                push_string(out, flag.trim());
+            }
+            FilterAction::Reject { reason } => {
+                require.insert("reject");
+                out.push_str("reject ");
+                push_string(out, reason);
+            }
+            FilterAction::Discard => out.push_str("discard"),
+            FilterAction::Keep => out.push_str("keep"),
+            FilterAction::Stop => out.push_str("stop"),
+        }
+
+        Ok(())
+    }
+}
+
+fn push_string(out: &mut String, value: &str) {
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '\\' | '"' => {
+                out.push('\\');
+            }
+            '\r' | '\n' => {
+                continue;
+            }
+            _ => (),
+        }
+        out.push(ch);
+    }
+    out.push('"');
+}
+
+fn default_true() -> bool {
+    true
+}
@@ -0,0 +1,263 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Redirects to external domains are subject to a forward policy, set globally
+// with "sieve.untrusted.forward.external" and overridden per tenant. Under the
+// approval policy each external address an account forwards to needs to be
+// approved by an administrator. Requests are stored in the settings store
+// under "forward-approval.<account id>.<address>".
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken, config::scripts::ForwardPolicy};
+use directory::backend::internal::manage::ManageDirectory;
+use serde::{Deserialize, Serialize};
+use store::write::now;
+use trc::{AddContext, SieveEvent};
+use utils::config::{ConfigKey, utils::ParseValue};
+
+pub const FORWARD_APPROVAL_KEY: &str = "forward-approval";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForwardApproval {
+    pub status: ForwardApprovalStatus,
+    pub requested_at: u64,
+    #[serde(default)]
+    pub tenant_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ForwardApprovalStatus {
+    Pending,
+    Approved,
+}
+
+pub trait ExternalForward: Sync + Send {
+    fn forward_policy(
+        &self,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<ForwardPolicy>> + Send;
+
+    fn forward_filter(
+        &self,
+        access_token: &AccessToken,
+        recipients: Vec<String>,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<Vec<String>>> + Send;
+
+    fn forward_approvals(
+        &self,
+    ) -> impl Future<Output = trc::Result<Vec<(u32, String, ForwardApproval)>>> + Send;
+
+    fn forward_approval(
+        &self,
+        account_id: u32,
+        address: &str,
+    ) -> impl Future<Output = trc::Result<Option<ForwardApproval>>> + Send;
+
+    fn forward_approval_set(
+        &self,
+        account_id: u32,
+        address: &str,
+        approval: ForwardApproval,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn forward_approval_delete(
+        &self,
+        account_id: u32,
+        address: &str,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ExternalForward for Server {
+    async fn forward_policy(&self, access_token: &AccessToken) -> trc::Result<ForwardPolicy> {
+        if let Some(tenant) = access_token.tenant {
+            if let Some(policy) = self
+                .store()
+                .get_principal(tenant.id)
+                .await
+                .caused_by(trc::location!())?
+                .and_then(|tenant| {
+                    tenant
+                        .forward_policy()
+                        .and_then(|policy| ForwardPolicy::parse_value(policy).ok())
+                })
+            {
+                return Ok(policy);
+            }
+        }
+
+        Ok(self.core.sieve.forward_external)
+    }
+
+    /// Returns the recipients of a redirect allowed by the forward policy,
+    /// requesting approval for external addresses not yet approved.
+    async fn forward_filter(
+        &self,
+        access_token: &AccessToken,
+        recipients: Vec<String>,
+        session_id: u64,
+    ) -> trc::Result<Vec<String>> {
+        let mut external = Vec::new();
+        let mut allowed = Vec::with_capacity(recipients.len());
+        for rcpt in recipients {
+            let domain = rcpt.rsplit_once('@').map(|(_, d)| d).unwrap_or_default();
+            if self
+                .core
+                .storage
+                .directory
+                .is_local_domain(&domain.to_lowercase())
+                .await
+                .caused_by(trc::location!())?
+            {
+                allowed.push(rcpt);
+            } else {
+                external.push(rcpt);
+            }
+        }
+        if external.is_empty() {
+            return Ok(allowed);
+        }
+
+        let account_id = access_token.primary_id;
+        match self.forward_policy(access_token).await? {
+            ForwardPolicy::Allow => {
+                allowed.extend(external);
+            }
+            ForwardPolicy::Deny => {
+                trc::event!(
+                    Sieve(SieveEvent::ForwardDenied),
+                    AccountId = account_id,
+                    To = external
+                        .into_iter()
+                        .map(|rcpt| trc::Value::String(rcpt.into()))
+                        .collect::<Vec<_>>(),
+                    SpanId = session_id
+                );
+            }
+            ForwardPolicy::Approval => {
+                for rcpt in external {
+                    let address = rcpt.to_lowercase();
+                    match self.forward_approval(account_id, &address).await? {
+                        Some(approval) if approval.status == ForwardApprovalStatus::Approved => {
+                            allowed.push(rcpt);
+                        }
+                        Some(_) => {
+                            trc::event!(
+                                Sieve(SieveEvent::ForwardDenied),
+                                AccountId = account_id,
+                                To = rcpt,
+                                Reason = "Awaiting approval",
+                                SpanId = session_id
+                            );
+                        }
+                        None => {
+                            self.forward_approval_set(
+                                account_id,
+                                &address,
+                                ForwardApproval {
+                                    status: ForwardApprovalStatus::Pending,
+                                    requested_at: now(),
+                                    tenant_id: access_token.tenant.map(|t| t.id),
+                                },
+                            )
+                            .await?;
+
+                            trc::event!(
+                                Sieve(SieveEvent::ForwardApprovalRequested),
+                                AccountId = account_id,
+                                To = rcpt,
+                                SpanId = session_id
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(allowed)
+    }
+
+    async fn forward_approvals(&self) -> trc::Result<Vec<(u32, String, ForwardApproval)>> {
+        let mut approvals = Vec::new();
+        for (key, value) in self
+            .core
+            .storage
+            .config
+            .list(&format!("{FORWARD_APPROVAL_KEY}."), true)
+            .await?
+        {
+            let Some((account_id, address)) = key
+                .split_once('.')
+                .and_then(|(id, address)| Some((id.parse::<u32>().ok()?, address)))
+            else {
+                continue;
+            };
+            match serde_json::from_str::<ForwardApproval>(&value) {
+                Ok(approval) => approvals.push((account_id, address.to_string(), approval)),
+                Err(err) => {
+                    trc::error!(
+                        trc::EventType::Config(trc::ConfigEvent::ParseError)
+                            .from_json_error(err)
+                            .account_id(account_id)
+                            .details("Failed to parse forward approval")
+                    );
+                }
+            }
+        }
+
+        Ok(approvals)
+    }
+
+    async fn forward_approval(
+        &self,
+        account_id: u32,
+        address: &str,
+    ) -> trc::Result<Option<ForwardApproval>> {
+        self.core
+            .storage
+            .config
+            .get(format!("{FORWARD_APPROVAL_KEY}.{account_id}.{address}"))
+            .await?
+            .map(|value| {
+                serde_json::from_str::<ForwardApproval>(&value).map_err(|err| {
+                    trc::EventType::Config(trc::ConfigEvent::ParseError)
+                        .from_json_error(err)
+                        .account_id(account_id)
+                })
+            })
+            .transpose()
+    }
+
+    async fn forward_approval_set(
+        &self,
+        account_id: u32,
+        address: &str,
+        approval: ForwardApproval,
+    ) -> trc::Result<()> {
+        self.core
+            .storage
+            .config
+            .set(
+                [ConfigKey {
+                    key: format!("{FORWARD_APPROVAL_KEY}.{account_id}.{address}"),
+                    value: serde_json::to_string(&approval).unwrap_or_default(),
+                }],
+                true,
+            )
+            .await
+    }
+
+    async fn forward_approval_delete(&self, account_id: u32, address: &str) -> trc::Result<()> {
+        self.core
+            .storage
+            .config
+            .clear(format!("{FORWARD_APPROVAL_KEY}.{account_id}.{address}"))
+            .await
+    }
+}
@@ -4,7 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use super::{ActiveScript, SeenIdHash, SieveScript};
+use super::{
+    ActiveScript, SeenIdHash, SieveScript, forward::ExternalForward, lists::SieveListLookup,
+};
 use crate::{
     cache::{MessageCacheFetch, mailbox::MailboxCacheAccess},
     mailbox::{INBOX_ID, TRASH_ID, manage::MailboxFnc},
@@ -14,20 +16,27 @@ use crate::{
     },
 };
 use common::{
-    Server, auth::AccessToken, config::jmap::settings::SpecialUse, scripts::plugins::PluginContext,
+    Server,
+    auth::AccessToken,
+    config::jmap::settings::SpecialUse,
+    scripts::{
+        notify::{Notification, NotifyMethod},
+        plugins::PluginContext,
+    },
 };
 use directory::{Permission, QueryBy};
+use groupware::calendar::availability::CalendarAvailability;
 use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
 use mail_parser::MessageParser;
 use sieve::{Envelope, Event, Input, Mailbox, Recipient, Sieve};
 use std::future::Future;
 use std::{borrow::Cow, sync::Arc};
 use store::{
-    Deserialize, Serialize, SerializeInfallible,
+    Deserialize, Serialize,
     ahash::AHashMap,
     dispatch::lookup::KeyValue,
     query::Filter,
-    write::{AlignedBytes, Archive, ArchiveVersion, Archiver, BatchBuilder, BlobOp},
+    write::{AlignedBytes, Archive, ArchiveVersion, Archiver, BatchBuilder, BlobOp, now},
 };
 use trc::{AddContext, SieveEvent};
 use utils::config::utils::ParseValue;
@@ -57,6 +66,11 @@ pub trait SieveScriptIngest: Sync + Send {
         account_id: u32,
     ) -> impl Future<Output = trc::Result<Option<ActiveScript>>> + Send;
 
+    fn sieve_script_get_out_of_office(
+        &self,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<Option<ActiveScript>>> + Send;
+
     fn sieve_script_get_by_name(
         &self,
         account_id: u32,
@@ -101,6 +115,13 @@ impl SieveScriptIngest for Server {
             .await
             .caused_by(trc::location!())?;
 
+        // Notifications are never sent for automatically generated messages (RFC 5436)
+        let is_auto_submitted = message
+            .header("Auto-Submitted")
+            .and_then(|header| header.as_text())
+            .is_some_and(|value| !value.trim().eq_ignore_ascii_case("no"));
+        let subject = message.subject().map(|subject| subject.to_string());
+
         // Create Sieve instance
         let mut instance = self.core.sieve.untrusted_runtime.filter_parsed(message);
 
@@ -123,7 +144,15 @@ impl SieveScriptIngest for Server {
 
         // Set envelope
         instance.set_envelope(Envelope::From, envelope_from);
-        instance.set_envelope(Envelope::To, envelope_to);
+        instance.set_envelope(
+            Envelope::To,
+            self.core
+                .smtp
+                .session
+                .rcpt
+                .sieve_address(envelope_to)
+                .into_owned(),
+        );
 
         let mut input = Input::script(
             active_script.script_name.to_string(),
@@ -147,6 +176,7 @@ impl SieveScriptIngest for Server {
             imap_uids: Vec::new(),
         };
         let mut checked_ids: AHashMap<SeenIdHash, bool> = AHashMap::new();
+        let mut notifications_sent = 0;
 
         while let Some(event) = instance.run(input) {
             match event {
@@ -242,11 +272,9 @@ impl SieveScriptIngest for Server {
                         }
                     }
                     Event::DuplicateId { id, expiry, last } => {
-                        let id_hash = SeenIdHash::new(
-                            account_id,
-                            active_script.version.hash().unwrap_or_default(),
-                            &id,
-                        );
+                        // Responses are tracked per script rather than per script version,
+                        // so editing a vacation message does not reply again to the same senders
+                        let id_hash = SeenIdHash::new(account_id, active_script.document_id, &id);
                         if let Some(result) = checked_ids.get(&id_hash) {
                             input = (*result).into();
                         } else {
@@ -385,6 +413,30 @@ impl SieveScriptIngest for Server {
                                 }
                             };
 
+                            // Replies to the sender, such as vacation responses, are not forwards
+                            let (replies, forwards): (Vec<_>, Vec<_>) = recipients
+                                .into_iter()
+                                .partition(|rcpt| rcpt.eq_ignore_ascii_case(envelope_from));
+                            let mut recipients = replies;
+                            if !forwards.is_empty() {
+                                match self
+                                    .forward_filter(access_token, forwards, session_id)
+                                    .await
+                                {
+                                    Ok(forwards) => recipients.extend(forwards),
+                                    Err(err) => {
+                                        trc::error!(
+                                            err.span_id(session_id)
+                                                .caused_by(trc::location!())
+                                                .details("Failed to apply forward policy")
+                                        );
+                                    }
+                                }
+                            }
+                            if recipients.is_empty() {
+                                continue;
+                            }
+
                             if message.raw_message.len() <= self.core.jmap.mail_max_size {
                                 trc::event!(
                                     Sieve(SieveEvent::SendMessage),
@@ -426,9 +478,135 @@ impl SieveScriptIngest for Server {
                             continue;
                         }
                     }
-                    Event::ListContains { .. }
-                    | Event::Notify { .. }
-                    | Event::SetEnvelope { .. } => {
+                    Event::Notify {
+                        from,
+                        importance,
+                        options,
+                        message,
+                        method,
+                    } => {
+                        input = true.into();
+
+                        let Some(notify_method) = NotifyMethod::parse(&method) else {
+                            trc::event!(
+                                Sieve(SieveEvent::NotSupported),
+                                Details = "Unsupported notification method.",
+                                Url = method,
+                                SpanId = session_id
+                            );
+                            continue;
+                        };
+
+                        if notifications_sent >= self.core.sieve.notify.max_notifications {
+                            trc::event!(
+                                Sieve(SieveEvent::NotifyError),
+                                Details = "Too many notifications.",
+                                Url = method,
+                                Limit = self.core.sieve.notify.max_notifications,
+                                SpanId = session_id
+                            );
+                            continue;
+                        }
+
+                        let notification = Notification {
+                            method: &method,
+                            from: from.as_deref(),
+                            importance: &importance,
+                            options: &options,
+                            message: &message,
+                            envelope_from,
+                            envelope_to,
+                            subject: subject.as_deref(),
+                        };
+
+                        if let NotifyMethod::Mailto { recipients, .. } = &notify_method {
+                            // Avoid notification loops
+                            let recipients = recipients
+                                .iter()
+                                .filter(|rcpt| {
+                                    !rcpt.eq_ignore_ascii_case(envelope_to)
+                                        && !rcpt.eq_ignore_ascii_case(&mail_from)
+                                })
+                                .cloned()
+                                .collect::<Vec<_>>();
+                            if is_auto_submitted || recipients.is_empty() {
+                                trc::event!(
+                                    Sieve(SieveEvent::NotifyError),
+                                    Details = "Notification suppressed to prevent loops.",
+                                    Url = method,
+                                    SpanId = session_id
+                                );
+                                continue;
+                            }
+
+                            trc::event!(
+                                Sieve(SieveEvent::Notify),
+                                Url = method,
+                                To = recipients
+                                    .iter()
+                                    .map(|r| trc::Value::String(r.as_str().into()))
+                                    .collect::<Vec<_>>(),
+                                SpanId = session_id
+                            );
+
+                            autogenerated.push(AutogeneratedMessage {
+                                sender_address: String::new(),
+                                message: notification.build_mailto(
+                                    &mail_from,
+                                    &recipients,
+                                    &notify_method,
+                                ),
+                                recipients,
+                            });
+                        } else {
+                            match self
+                                .send_sieve_notification(&notify_method, &notification)
+                                .await
+                            {
+                                Ok(_) => {
+                                    trc::event!(
+                                        Sieve(SieveEvent::Notify),
+                                        Url = method,
+                                        SpanId = session_id
+                                    );
+                                }
+                                Err(err) => {
+                                    trc::error!(err.span_id(session_id));
+                                }
+                            }
+                        }
+                        notifications_sent += 1;
+                    }
+                    Event::ListContains {
+                        lists,
+                        values,
+                        match_as,
+                    } => {
+                        input = false.into();
+                        for list in lists {
+                            match self
+                                .sieve_list_contains(access_token, &list, &values, match_as)
+                                .await
+                            {
+                                Ok(Some(true)) => {
+                                    input = true.into();
+                                    break;
+                                }
+                                Ok(Some(false)) => {}
+                                Ok(None) => {
+                                    trc::event!(
+                                        Sieve(SieveEvent::ListNotFound),
+                                        SpanId = session_id,
+                                        Details = list,
+                                    );
+                                }
+                                Err(err) => {
+                                    trc::error!(err.span_id(session_id));
+                                }
+                            }
+                        }
+                    }
+                    Event::SetEnvelope { .. } => {
                         // Not allowed
                         input = false.into();
                     }
@@ -575,6 +753,53 @@ impl SieveScriptIngest for Server {
         }
     }
 
+    async fn sieve_script_get_out_of_office(
+        &self,
+        access_token: &AccessToken,
+    ) -> trc::Result<Option<ActiveScript>> {
+        // Enable the vacation response while the calendar shows the user as out of office
+        if !self.core.sieve.vacation_calendar {
+            return Ok(None);
+        }
+        match self.is_out_of_office(access_token, now() as i64).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(None),
+            Err(err) => {
+                trc::error!(
+                    err.account_id(access_token.primary_id)
+                        .details("Failed to obtain calendar availability")
+                        .caused_by(trc::location!())
+                );
+                return Ok(None);
+            }
+        }
+
+        let account_id = access_token.primary_id;
+        if let Some(document_id) = self
+            .store()
+            .filter(
+                account_id,
+                Collection::SieveScript,
+                vec![Filter::eq(Property::Name, "vacation".as_bytes().to_vec())],
+            )
+            .await
+            .caused_by(trc::location!())?
+            .results
+            .min()
+        {
+            let script = self.sieve_script_compile(account_id, document_id).await?;
+
+            Ok(Some(ActiveScript {
+                document_id,
+                script: Arc::new(script.script),
+                script_name: script.name,
+                version: script.version,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
     async fn sieve_script_get_by_name(
         &self,
         account_id: u32,
@@ -586,7 +811,7 @@ impl SieveScriptIngest for Server {
             .filter(
                 account_id,
                 Collection::SieveScript,
-                vec![Filter::eq(Property::Name, name.serialize())],
+                vec![Filter::eq(Property::Name, name.to_lowercase().into_bytes())],
             )
             .await
             .caused_by(trc::location!())?
@@ -0,0 +1,112 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    IDX_EMAIL, Server,
+    auth::AccessToken,
+    scripts::{LIST_ADDRBOOK_DEFAULT, LIST_ADDRBOOK_GROUPS, LIST_ADDRBOOK_PERSONAL},
+};
+use jmap_proto::types::collection::Collection;
+use sieve::MatchAs;
+use std::future::Future;
+use store::query::Filter;
+use trc::AddContext;
+use utils::sanitize_email;
+
+pub trait SieveListLookup: Sync + Send {
+    fn sieve_list_contains(
+        &self,
+        access_token: &AccessToken,
+        list: &str,
+        values: &[String],
+        match_as: MatchAs,
+    ) -> impl Future<Output = trc::Result<Option<bool>>> + Send;
+}
+
+impl SieveListLookup for Server {
+    async fn sieve_list_contains(
+        &self,
+        access_token: &AccessToken,
+        list: &str,
+        values: &[String],
+        match_as: MatchAs,
+    ) -> trc::Result<Option<bool>> {
+        if list == LIST_ADDRBOOK_DEFAULT || list == LIST_ADDRBOOK_PERSONAL {
+            // Addresses present in any of the user's address books
+            for address in values.iter().filter_map(|value| sanitize_email(value)) {
+                if !self
+                    .store()
+                    .filter(
+                        access_token.primary_id,
+                        Collection::ContactCard,
+                        vec![Filter::eq(IDX_EMAIL, address.into_bytes())],
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                    .results
+                    .is_empty()
+                {
+                    return Ok(Some(true));
+                }
+            }
+
+            Ok(Some(false))
+        } else if list == LIST_ADDRBOOK_GROUPS {
+            // Addresses belonging to members of any of the user's groups
+            if access_token.member_of.is_empty() {
+                return Ok(Some(false));
+            }
+
+            for address in values.iter().filter_map(|value| sanitize_email(value)) {
+                if let Some(id) = self
+                    .core
+                    .storage
+                    .directory
+                    .email_to_id(&address)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    if access_token.member_of.contains(&id)
+                        || self
+                            .get_access_token(id)
+                            .await
+                            .caused_by(trc::location!())?
+                            .member_of
+                            .iter()
+                            .any(|group_id| access_token.member_of.contains(group_id))
+                    {
+                        return Ok(Some(true));
+                    }
+                }
+            }
+
+            Ok(Some(false))
+        } else if self.core.sieve.untrusted_lists.contains(list) {
+            // Lists managed by the administrator
+            let Some(store) = self.core.storage.lookups.get(list) else {
+                return Ok(None);
+            };
+
+            for value in values {
+                if store
+                    .key_exists(if !matches!(match_as, MatchAs::Lowercase) {
+                        value.clone()
+                    } else {
+                        value.to_lowercase()
+                    })
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    return Ok(Some(true));
+                }
+            }
+
+            Ok(Some(false))
+        } else {
+            Ok(None)
+        }
+    }
+}
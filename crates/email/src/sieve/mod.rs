@@ -14,8 +14,11 @@ use utils::BlobHash;
 
 pub mod activate;
 pub mod delete;
+pub mod filters;
+pub mod forward;
 pub mod index;
 pub mod ingest;
+pub mod lists;
 
 #[derive(Debug, Clone)]
 pub struct ActiveScript {
@@ -103,6 +106,14 @@ impl SeenIdHash {
         SeenIdHash(hasher.finalize().into())
     }
 
+    pub fn new_global(script_id: &str, id: &str) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(script_id.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(id.as_bytes());
+        SeenIdHash(hasher.finalize().into())
+    }
+
     pub fn key(&self) -> Vec<u8> {
         let mut result = Vec::with_capacity(self.0.len() + 1);
         result.push(KV_SIEVE_ID);
@@ -0,0 +1,491 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{CalendarEvent, itip::ItipIngestError};
+use crate::{
+    cache::GroupwareCache,
+    scheduling::{
+        ItipError, ItipField, ItipMessage, ItipSummary, ItipTime, ItipValue,
+        itip::itip_build_envelope,
+    },
+};
+use ahash::AHashMap;
+use calcard::{
+    common::{PartialDateTime, timezone::Tz},
+    icalendar::{
+        ArchivedICalendarComponent, ArchivedICalendarComponentType, ArchivedICalendarProperty,
+        ArchivedICalendarStatus, ICalendar, ICalendarComponent, ICalendarComponentType,
+        ICalendarEntry, ICalendarFreeBusyType, ICalendarMethod, ICalendarParameter,
+        ICalendarParticipationStatus, ICalendarPeriod, ICalendarProperty, ICalendarTransparency,
+        ICalendarValue, Uri,
+    },
+};
+use common::{PROD_ID, Server, auth::AccessToken, config::groupware::FreeBusyPrivacy};
+use dav_proto::schema::property::TimeRange;
+use directory::Permission;
+use jmap_proto::types::collection::{Collection, SyncCollection};
+use std::future::Future;
+use trc::AddContext;
+use utils::{config::utils::ParseValue, sanitize_email};
+
+// Busy status set by most clients on events marked as "out of office"
+const BUSY_STATUS_PROPERTIES: [&str; 2] = [
+    "X-MICROSOFT-CDO-BUSYSTATUS",
+    "X-MICROSOFT-CDO-INTENDEDSTATUS",
+];
+const BUSY_STATUS_OOF: &str = "OOF";
+
+pub trait CalendarAvailability: Sync + Send {
+    fn is_out_of_office(
+        &self,
+        access_token: &AccessToken,
+        timestamp: i64,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn free_busy_privacy(
+        &self,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<FreeBusyPrivacy>> + Send;
+
+    fn free_busy_object(
+        &self,
+        access_token: &AccessToken,
+        range: TimeRange,
+        privacy: FreeBusyPrivacy,
+    ) -> impl Future<Output = trc::Result<ICalendar>> + Send;
+
+    fn published_free_busy(
+        &self,
+        email: &str,
+        start: i64,
+        end: i64,
+    ) -> impl Future<Output = trc::Result<Option<ICalendar>>> + Send;
+
+    fn itip_free_busy_reply(
+        &self,
+        access_token: &AccessToken,
+        itip: &ICalendar,
+        sender: &str,
+    ) -> impl Future<Output = Result<ItipMessage<ICalendar>, ItipIngestError>> + Send;
+}
+
+impl CalendarAvailability for Server {
+    async fn is_out_of_office(
+        &self,
+        access_token: &AccessToken,
+        timestamp: i64,
+    ) -> trc::Result<bool> {
+        let account_id = access_token.primary_id;
+        let resources = self
+            .fetch_dav_resources(access_token, account_id, SyncCollection::Calendar)
+            .await
+            .caused_by(trc::location!())?;
+
+        for resource in resources.resources.iter() {
+            if !resource
+                .event_time_range()
+                .is_some_and(|(start, end)| start <= timestamp && end > timestamp)
+            {
+                continue;
+            }
+
+            let Some(archive) = self
+                .get_archive(account_id, Collection::CalendarEvent, resource.document_id)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let event = archive
+                .unarchive::<CalendarEvent>()
+                .caused_by(trc::location!())?;
+
+            // Obtain the components that are taking place right now
+            let Some(instances) = event.data.expand(
+                Tz::UTC,
+                TimeRange {
+                    start: timestamp,
+                    end: timestamp + 1,
+                },
+            ) else {
+                continue;
+            };
+
+            for instance in instances {
+                if event
+                    .data
+                    .event
+                    .components
+                    .get(instance.comp_id as usize)
+                    .is_some_and(is_out_of_office_component)
+                {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn free_busy_privacy(&self, access_token: &AccessToken) -> trc::Result<FreeBusyPrivacy> {
+        if let Some(tenant) = access_token.tenant {
+            if let Some(privacy) = self
+                .store()
+                .get_principal(tenant.id)
+                .await
+                .caused_by(trc::location!())?
+                .and_then(|tenant| {
+                    tenant
+                        .free_busy_policy()
+                        .and_then(|policy| FreeBusyPrivacy::parse_value(policy).ok())
+                })
+            {
+                return Ok(privacy);
+            }
+        }
+
+        Ok(self.core.groupware.freebusy_privacy)
+    }
+
+    async fn free_busy_object(
+        &self,
+        access_token: &AccessToken,
+        range: TimeRange,
+        privacy: FreeBusyPrivacy,
+    ) -> trc::Result<ICalendar> {
+        let account_id = access_token.primary_id;
+        let mut periods: AHashMap<ICalendarFreeBusyType, Vec<(i64, i64)>> = AHashMap::new();
+
+        if privacy != FreeBusyPrivacy::Hidden {
+            let resources = self
+                .fetch_dav_resources(access_token, account_id, SyncCollection::Calendar)
+                .await
+                .caused_by(trc::location!())?;
+            let granularity = self.core.groupware.freebusy_granularity;
+
+            for resource in resources.resources.iter() {
+                if !resource
+                    .event_time_range()
+                    .is_some_and(|(start, end)| start < range.end && end > range.start)
+                {
+                    continue;
+                }
+
+                let Some(archive) = self
+                    .get_archive(account_id, Collection::CalendarEvent, resource.document_id)
+                    .await
+                    .caused_by(trc::location!())?
+                else {
+                    continue;
+                };
+                let event = archive
+                    .unarchive::<CalendarEvent>()
+                    .caused_by(trc::location!())?;
+                let Some(instances) = event.data.expand(Tz::UTC, range) else {
+                    continue;
+                };
+
+                for instance in instances {
+                    // Only opaque events are considered, as in CalDAV free-busy queries
+                    let Some(component) = event
+                        .data
+                        .event
+                        .components
+                        .get(instance.comp_id as usize)
+                        .filter(|comp| {
+                            matches!(comp.component_type, ArchivedICalendarComponentType::VEvent)
+                                && comp
+                                    .transparency()
+                                    .is_none_or(|t| t == &ICalendarTransparency::Opaque)
+                        })
+                    else {
+                        continue;
+                    };
+                    let fbtype = match component.status() {
+                        Some(ArchivedICalendarStatus::Cancelled) => continue,
+                        _ if privacy == FreeBusyPrivacy::Busy => ICalendarFreeBusyType::Busy,
+                        Some(ArchivedICalendarStatus::Tentative) => {
+                            ICalendarFreeBusyType::BusyTentative
+                        }
+                        _ if is_out_of_office_component(component) => {
+                            ICalendarFreeBusyType::BusyUnavailable
+                        }
+                        _ => ICalendarFreeBusyType::Busy,
+                    };
+
+                    // Round periods to the configured granularity to avoid
+                    // disclosing the exact times of each event
+                    let (mut start, mut end) = (instance.start, instance.end);
+                    if privacy == FreeBusyPrivacy::Busy {
+                        start -= start.rem_euclid(granularity);
+                        end += (granularity - end.rem_euclid(granularity)) % granularity;
+                    }
+                    let (start, end) = (start.max(range.start), end.min(range.end));
+                    if start < end {
+                        periods.entry(fbtype).or_default().push((start, end));
+                    }
+                }
+            }
+        }
+
+        // Build FreeBusy component
+        let mut entries = vec![
+            ICalendarEntry {
+                name: ICalendarProperty::Dtstamp,
+                params: vec![],
+                values: vec![ICalendarValue::PartialDateTime(Box::new(
+                    PartialDateTime::now(),
+                ))],
+            },
+            ICalendarEntry {
+                name: ICalendarProperty::Dtstart,
+                params: vec![],
+                values: vec![ICalendarValue::PartialDateTime(Box::new(
+                    PartialDateTime::from_utc_timestamp(range.start),
+                ))],
+            },
+            ICalendarEntry {
+                name: ICalendarProperty::Dtend,
+                params: vec![],
+                values: vec![ICalendarValue::PartialDateTime(Box::new(
+                    PartialDateTime::from_utc_timestamp(range.end),
+                ))],
+            },
+        ];
+        for (fbtype, periods) in periods {
+            entries.push(ICalendarEntry {
+                name: ICalendarProperty::Freebusy,
+                params: vec![ICalendarParameter::Fbtype(fbtype)],
+                values: merge_periods(periods)
+                    .into_iter()
+                    .map(|(start, end)| {
+                        ICalendarValue::Period(ICalendarPeriod::Range {
+                            start: PartialDateTime::from_utc_timestamp(start),
+                            end: PartialDateTime::from_utc_timestamp(end),
+                        })
+                    })
+                    .collect(),
+            });
+        }
+
+        Ok(ICalendar {
+            components: vec![
+                ICalendarComponent {
+                    component_type: ICalendarComponentType::VCalendar,
+                    entries: vec![
+                        ICalendarEntry {
+                            name: ICalendarProperty::Version,
+                            params: vec![],
+                            values: vec![ICalendarValue::Text("2.0".to_string())],
+                        },
+                        ICalendarEntry {
+                            name: ICalendarProperty::Prodid,
+                            params: vec![],
+                            values: vec![ICalendarValue::Text(PROD_ID.to_string())],
+                        },
+                    ],
+                    component_ids: vec![1],
+                },
+                ICalendarComponent {
+                    component_type: ICalendarComponentType::VFreebusy,
+                    entries,
+                    component_ids: vec![],
+                },
+            ],
+        })
+    }
+
+    async fn published_free_busy(
+        &self,
+        email: &str,
+        start: i64,
+        end: i64,
+    ) -> trc::Result<Option<ICalendar>> {
+        if !self.core.groupware.freebusy_federation {
+            return Ok(None);
+        }
+        let Some(email) = sanitize_email(email) else {
+            return Ok(None);
+        };
+        let Some(account_id) = self
+            .directory()
+            .email_to_id(&email)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        if !access_token.has_permission(Permission::CalendarSchedulingReceive) {
+            return Ok(None);
+        }
+        let privacy = self
+            .free_busy_privacy(&access_token)
+            .await
+            .caused_by(trc::location!())?;
+        if privacy == FreeBusyPrivacy::Hidden {
+            return Ok(None);
+        }
+
+        let range = TimeRange {
+            start,
+            end: end.min(start.saturating_add(self.core.groupware.freebusy_max_range)),
+        };
+        let mut ical = self
+            .free_busy_object(&access_token, range, privacy)
+            .await
+            .caused_by(trc::location!())?;
+        ical.components[1].entries.push(ICalendarEntry {
+            name: ICalendarProperty::Organizer,
+            params: vec![],
+            values: vec![ICalendarValue::Uri(Uri::Location(format!(
+                "mailto:{email}"
+            )))],
+        });
+
+        Ok(Some(ical))
+    }
+
+    async fn itip_free_busy_reply(
+        &self,
+        access_token: &AccessToken,
+        itip: &ICalendar,
+        sender: &str,
+    ) -> Result<ItipMessage<ICalendar>, ItipIngestError> {
+        if !self.core.groupware.freebusy_federation {
+            return Err(ItipIngestError::Message(ItipError::NothingToSend));
+        }
+
+        // Parse request
+        let mut range_start = None;
+        let mut range_end = None;
+        let mut organizer = None;
+        let mut attendee = None;
+        let mut uid = None;
+        let tz_resolver = itip.build_tz_resolver();
+        for entry in itip
+            .components
+            .iter()
+            .filter(|comp| comp.component_type == ICalendarComponentType::VFreebusy)
+            .flat_map(|comp| comp.entries.iter())
+        {
+            let tz_id = entry.tz_id();
+            match (&entry.name, entry.values.first()) {
+                (ICalendarProperty::Dtstart, Some(ICalendarValue::PartialDateTime(dt))) => {
+                    range_start = dt.to_date_time_with_tz(tz_resolver.resolve(tz_id));
+                }
+                (ICalendarProperty::Dtend, Some(ICalendarValue::PartialDateTime(dt))) => {
+                    range_end = dt.to_date_time_with_tz(tz_resolver.resolve(tz_id));
+                }
+                (ICalendarProperty::Uid, Some(ICalendarValue::Text(_))) => {
+                    uid = Some(entry);
+                }
+                (
+                    ICalendarProperty::Organizer | ICalendarProperty::Attendee,
+                    Some(ICalendarValue::Text(value) | ICalendarValue::Uri(Uri::Location(value))),
+                ) => {
+                    let email =
+                        sanitize_email(value.strip_prefix("mailto:").unwrap_or(value.as_str()));
+                    if entry.name == ICalendarProperty::Organizer {
+                        organizer = email.map(|email| (email, entry));
+                    } else if let Some(email) =
+                        email.filter(|email| access_token.emails.contains(email))
+                    {
+                        attendee = Some((email, entry));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (Some(range_start), Some(range_end), Some((organizer_email, organizer))) =
+            (range_start, range_end, organizer)
+        else {
+            return Err(ItipIngestError::Message(ItipError::NoSchedulingInfo));
+        };
+        if organizer_email != sender {
+            return Err(ItipIngestError::Message(
+                ItipError::SenderIsNotOrganizerNorAttendee,
+            ));
+        }
+        let Some((attendee_email, attendee)) = attendee else {
+            return Err(ItipIngestError::Message(ItipError::NotOrganizerNorAttendee));
+        };
+        let privacy = self
+            .free_busy_privacy(access_token)
+            .await
+            .caused_by(trc::location!())?;
+        if privacy == FreeBusyPrivacy::Hidden {
+            return Err(ItipIngestError::Message(ItipError::NothingToSend));
+        }
+
+        // Build reply
+        let range = TimeRange {
+            start: range_start.timestamp(),
+            end: range_end
+                .timestamp()
+                .min(range_start.timestamp() + self.core.groupware.freebusy_max_range),
+        };
+        let mut message = self
+            .free_busy_object(access_token, range, privacy)
+            .await
+            .caused_by(trc::location!())?;
+        message.components[0] = itip_build_envelope(ICalendarMethod::Reply);
+        message.components[0].component_ids.push(1);
+        let component = &mut message.components[1];
+        component.entries.push(organizer.clone());
+        component.entries.push(attendee.clone());
+        if let Some(uid) = uid {
+            component.entries.push(uid.clone());
+        }
+
+        Ok(ItipMessage {
+            from: attendee_email,
+            from_organizer: false,
+            to: vec![organizer_email],
+            summary: ItipSummary::Rsvp {
+                part_stat: ICalendarParticipationStatus::NeedsAction,
+                current: vec![ItipField {
+                    name: ICalendarProperty::Dtstart,
+                    value: ItipValue::Time(ItipTime {
+                        start: range.start,
+                        tz_id: Tz::UTC.as_id(),
+                    }),
+                }],
+            },
+            message,
+        })
+    }
+}
+
+fn is_out_of_office_component(component: &ArchivedICalendarComponent) -> bool {
+    component.entries.iter().any(|entry| {
+        matches!(&entry.name, ArchivedICalendarProperty::Other(name)
+            if BUSY_STATUS_PROPERTIES
+                .iter()
+                .any(|property| name.eq_ignore_ascii_case(property)))
+            && entry
+                .values
+                .first()
+                .and_then(|value| value.as_text())
+                .is_some_and(|value| value.eq_ignore_ascii_case(BUSY_STATUS_OOF))
+    })
+}
+
+fn merge_periods(mut periods: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    periods.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(periods.len());
+    for (start, end) in periods {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
@@ -0,0 +1,290 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{CalendarEvent, itip::ItipIngestError};
+use crate::{
+    cache::GroupwareCache,
+    scheduling::{
+        ItipError, ItipMessage, ItipSummary, event_update::itip_update, snapshot::itip_snapshot,
+    },
+};
+use calcard::{
+    common::timezone::Tz,
+    icalendar::{
+        ArchivedICalendarComponent, ArchivedICalendarComponentType, ArchivedICalendarParameter,
+        ArchivedICalendarParticipationStatus, ArchivedICalendarProperty, ArchivedICalendarStatus,
+        ICalendar, ICalendarComponentType, ICalendarParameter, ICalendarParticipationStatus,
+        ICalendarProperty, ICalendarTransparency, dates::TimeOrDelta,
+    },
+};
+use common::{Server, auth::AccessToken, config::groupware::BookingPolicy};
+use dav_proto::schema::property::TimeRange;
+use directory::{Principal, Type};
+use jmap_proto::types::collection::{Collection, SyncCollection};
+use std::{future::Future, time::Duration};
+use store::write::now;
+use trc::AddContext;
+use utils::config::utils::ParseValue;
+
+pub trait CalendarBooking: Sync + Send {
+    fn booking_principal(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<Principal>>> + Send;
+
+    fn itip_booking_reply(
+        &self,
+        access_token: &AccessToken,
+        itip: &ICalendar,
+        ical: &mut ICalendar,
+    ) -> impl Future<Output = Result<Vec<ItipMessage<ICalendar>>, ItipIngestError>> + Send;
+
+    fn has_booking_conflict(
+        &self,
+        access_token: &AccessToken,
+        uid: Option<&str>,
+        instances: &[(i64, i64)],
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+}
+
+impl CalendarBooking for Server {
+    async fn booking_principal(&self, account_id: u32) -> trc::Result<Option<Principal>> {
+        self.store()
+            .get_principal(account_id)
+            .await
+            .caused_by(trc::location!())
+            .map(|principal| principal.filter(|p| matches!(p.typ, Type::Resource | Type::Location)))
+    }
+
+    async fn itip_booking_reply(
+        &self,
+        access_token: &AccessToken,
+        itip: &ICalendar,
+        ical: &mut ICalendar,
+    ) -> Result<Vec<ItipMessage<ICalendar>>, ItipIngestError> {
+        // Only resources and locations are booked automatically
+        let Some(principal) = self
+            .booking_principal(access_token.primary_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(vec![]);
+        };
+        let policy = principal
+            .booking_policy()
+            .and_then(|policy| BookingPolicy::parse_value(policy).ok())
+            .unwrap_or(self.core.groupware.booking_policy);
+        if policy == BookingPolicy::Manual {
+            return Ok(vec![]);
+        }
+        let horizon = principal
+            .booking_horizon()
+            .and_then(|horizon| Duration::parse_value(horizon).ok())
+            .map(|horizon| horizon.as_secs() as i64)
+            .or(self.core.groupware.booking_horizon);
+        let delegate = principal.booking_delegate();
+
+        // Obtain the requested time slots
+        let instances = ical
+            .expand_dates(Tz::UTC, self.core.groupware.max_ical_instances)
+            .events
+            .into_iter()
+            .filter(|event| {
+                ical.component_by_id(event.comp_id)
+                    .is_some_and(|comp| comp.component_type == ICalendarComponentType::VEvent)
+            })
+            .map(|event| {
+                let start = event.start.timestamp();
+                let end = match event.end {
+                    TimeOrDelta::Time(time) => time.timestamp(),
+                    TimeOrDelta::Delta(delta) => start + delta.num_seconds(),
+                };
+                (start, end)
+            })
+            .collect::<Vec<_>>();
+        if instances.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let part_stat = if policy == BookingPolicy::Decline
+            || horizon.is_some_and(|horizon| {
+                let max_end = now() as i64 + horizon;
+                instances.iter().any(|(_, end)| *end > max_end)
+            })
+            || (policy == BookingPolicy::Accept
+                && self
+                    .has_booking_conflict(access_token, ical.uids().next(), &instances)
+                    .await
+                    .caused_by(trc::location!())?)
+        {
+            ICalendarParticipationStatus::Declined
+        } else if delegate.is_some() {
+            // Hold the slot until the delegate approves the booking
+            ICalendarParticipationStatus::Tentative
+        } else {
+            ICalendarParticipationStatus::Accepted
+        };
+
+        // Update the participation status of the resource
+        let old_ical = ical.clone();
+        let mut resource_email = None;
+        for component in &mut ical.components {
+            if component.component_type.is_scheduling_object() {
+                for entry in &mut component.entries {
+                    if entry.name != ICalendarProperty::Attendee {
+                        continue;
+                    }
+                    let Some(email) = entry
+                        .values
+                        .first()
+                        .and_then(|v| v.as_text())
+                        .map(|v| v.strip_prefix("mailto:").unwrap_or(v))
+                        .and_then(|v| {
+                            access_token
+                                .emails
+                                .iter()
+                                .find(|email| email.eq_ignore_ascii_case(v))
+                        })
+                    else {
+                        continue;
+                    };
+                    entry
+                        .params
+                        .retain(|param| !matches!(param, ICalendarParameter::Partstat(_)));
+                    entry
+                        .params
+                        .push(ICalendarParameter::Partstat(part_stat.clone()));
+                    resource_email = Some(email.clone());
+                }
+            }
+        }
+        let Some(resource_email) = resource_email else {
+            return Ok(vec![]);
+        };
+
+        let mut messages = match itip_update(ical, &old_ical, access_token.emails.as_slice()) {
+            Ok(messages) => messages,
+            Err(ItipError::NothingToSend) => vec![],
+            Err(err) => return Err(ItipIngestError::Message(err)),
+        };
+
+        // Ask the delegate to approve the booking
+        if let Some(delegate) =
+            delegate.filter(|_| part_stat == ICalendarParticipationStatus::Tentative)
+        {
+            let summary = itip_snapshot(itip, access_token.emails.as_slice(), false)?
+                .main_instance_or_default()
+                .build_summary(None, &[]);
+            messages.push(ItipMessage {
+                from: resource_email,
+                from_organizer: false,
+                to: vec![delegate.clone()],
+                summary: ItipSummary::Invite(summary),
+                message: itip.clone(),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    async fn has_booking_conflict(
+        &self,
+        access_token: &AccessToken,
+        uid: Option<&str>,
+        instances: &[(i64, i64)],
+    ) -> trc::Result<bool> {
+        let (Some(range_start), Some(range_end)) = (
+            instances.iter().map(|(start, _)| *start).min(),
+            instances.iter().map(|(_, end)| *end).max(),
+        ) else {
+            return Ok(false);
+        };
+        let account_id = access_token.primary_id;
+        let resources = self
+            .fetch_dav_resources(access_token, account_id, SyncCollection::Calendar)
+            .await
+            .caused_by(trc::location!())?;
+
+        for resource in resources.resources.iter() {
+            if !resource
+                .event_time_range()
+                .is_some_and(|(start, end)| start < range_end && end > range_start)
+            {
+                continue;
+            }
+
+            let Some(archive) = self
+                .get_archive(account_id, Collection::CalendarEvent, resource.document_id)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let event = archive
+                .unarchive::<CalendarEvent>()
+                .caused_by(trc::location!())?;
+
+            // Updates to an existing booking do not conflict with themselves
+            if uid.is_some_and(|uid| event.data.event.uids().next() == Some(uid)) {
+                continue;
+            }
+
+            let Some(booked) = event.data.expand(
+                Tz::UTC,
+                TimeRange {
+                    start: range_start,
+                    end: range_end,
+                },
+            ) else {
+                continue;
+            };
+
+            for booked in booked {
+                if event
+                    .data
+                    .event
+                    .components
+                    .get(booked.comp_id as usize)
+                    .is_some_and(|comp| is_booked(comp, &access_token.emails))
+                    && instances
+                        .iter()
+                        .any(|(start, end)| *start < booked.end && *end > booked.start)
+                {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+fn is_booked(component: &ArchivedICalendarComponent, emails: &[String]) -> bool {
+    matches!(
+        component.component_type,
+        ArchivedICalendarComponentType::VEvent
+    ) && component
+        .transparency()
+        .is_none_or(|t| t == &ICalendarTransparency::Opaque)
+        && !matches!(component.status(), Some(ArchivedICalendarStatus::Cancelled))
+        && !component.entries.iter().any(|entry| {
+            matches!(entry.name, ArchivedICalendarProperty::Attendee)
+                && entry
+                    .values
+                    .first()
+                    .and_then(|v| v.as_text())
+                    .map(|v| v.strip_prefix("mailto:").unwrap_or(v))
+                    .is_some_and(|v| emails.iter().any(|email| email.eq_ignore_ascii_case(v)))
+                && entry.params.iter().any(|param| {
+                    matches!(
+                        param,
+                        ArchivedICalendarParameter::Partstat(
+                            ArchivedICalendarParticipationStatus::Declined
+                        )
+                    )
+                })
+        })
+}
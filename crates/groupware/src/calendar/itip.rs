@@ -7,9 +7,13 @@
 use crate::{
     RFC_3986,
     cache::GroupwareCache,
-    calendar::{CalendarEvent, CalendarEventData, CalendarScheduling},
+    calendar::{
+        CalendarEvent, CalendarEventData, CalendarScheduling, availability::CalendarAvailability,
+        booking::CalendarBooking,
+    },
     scheduling::{
-        ItipError, ItipMessage,
+        ItipError, ItipMessage, ItipMessages,
+        event_update::itip_update,
         inbound::{
             MergeResult, itip_import_message, itip_merge_changes, itip_method, itip_process_message,
         },
@@ -89,6 +93,20 @@ impl ItipIngest for Server {
                     Err(ItipIngestError::Message(ItipError::ICalendarParseError))
                 }
             })?;
+
+        // Free/busy requests are answered directly
+        if itip_method(&itip).is_ok_and(|method| method == &ICalendarMethod::Request)
+            && itip
+                .components
+                .iter()
+                .any(|comp| comp.component_type == ICalendarComponentType::VFreebusy)
+        {
+            return self
+                .itip_free_busy_reply(access_token, &itip, sender)
+                .await
+                .map(Some);
+        }
+
         let itip_snapshots = itip_snapshot(&itip, access_token.emails.as_slice(), false)?;
         if !itip_snapshots.sender_is_organizer_or_attendee(sender) {
             return Err(ItipIngestError::Message(
@@ -139,6 +157,14 @@ impl ItipIngest for Server {
                         // Merge changes
                         itip_merge_changes(&mut event.data.event, changes);
 
+                        // Answer rescheduled bookings of resources
+                        let booking_messages = if itip_method(&itip)? == &ICalendarMethod::Request {
+                            self.itip_booking_reply(access_token, &itip, &mut event.data.event)
+                                .await?
+                        } else {
+                            vec![]
+                        };
+
                         // Calculate the new ical size
                         event.size = event.data.event.to_string().len() as u32;
                         if event.size > self.core.groupware.max_ical_size as u32 {
@@ -193,6 +219,11 @@ impl ItipIngest for Server {
                         event
                             .update(access_token, event_, account_id, document_id, &mut batch)
                             .caused_by(trc::location!())?;
+                        if !booking_messages.is_empty() {
+                            ItipMessages::new(booking_messages)
+                                .queue(&mut batch)
+                                .caused_by(trc::location!())?;
+                        }
                         if prev_email_alarm != next_email_alarm {
                             if let Some(prev_alarm) = prev_email_alarm {
                                 prev_alarm.delete_task(&mut batch);
@@ -228,6 +259,11 @@ impl ItipIngest for Server {
                     .caused_by(trc::location!())?
                     .results
                     .is_empty()
+                && self
+                    .booking_principal(account_id)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_none()
             {
                 return Err(ItipIngestError::Message(ItipError::AutoAddDisabled));
             } else if itip_method(&itip)? != &ICalendarMethod::Request {
@@ -238,6 +274,11 @@ impl ItipIngest for Server {
             let mut ical = itip.clone();
             itip_import_message(&mut ical)?;
 
+            // Answer booking requests addressed to resources
+            let booking_messages = self
+                .itip_booking_reply(access_token, &itip, &mut ical)
+                .await?;
+
             // Validate quota
             if self
                 .has_available_quota(resource_token, itip_message.len() as u64)
@@ -304,6 +345,11 @@ impl ItipIngest for Server {
                     &mut batch,
                 )
                 .caused_by(trc::location!())?;
+            if !booking_messages.is_empty() {
+                ItipMessages::new(booking_messages)
+                    .queue(&mut batch)
+                    .caused_by(trc::location!())?;
+            }
             itip_message
                 .insert(access_token, account_id, itip_document_id, &mut batch)
                 .caused_by(trc::location!())?;
@@ -361,6 +407,22 @@ impl ItipIngest for Server {
                 let mut description = None;
                 let mut found_participant = false;
 
+                // Booking delegates answer on behalf of the resource
+                let access_token = self
+                    .get_access_token(rsvp.account_id)
+                    .await
+                    .caused_by(trc::location!())?;
+                let is_delegate = self
+                    .booking_principal(rsvp.account_id)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some_and(|principal| {
+                        principal
+                            .booking_delegate()
+                            .is_some_and(|delegate| delegate.eq_ignore_ascii_case(&rsvp.attendee))
+                    });
+                let old_ical = is_delegate.then(|| new_event.data.event.clone());
+
                 for component in &mut new_event.data.event.components {
                     if component.component_type.is_scheduling_object() {
                         'outer: for entry in &mut component.entries {
@@ -370,9 +432,15 @@ impl ItipIngest for Server {
                                     .first()
                                     .and_then(|v| v.as_text())
                                     .is_some_and(|v| {
-                                        v.strip_prefix("mailto:")
-                                            .unwrap_or(v)
-                                            .eq_ignore_ascii_case(&rsvp.attendee)
+                                        let v = v.strip_prefix("mailto:").unwrap_or(v);
+                                        if is_delegate {
+                                            access_token
+                                                .emails
+                                                .iter()
+                                                .any(|email| email.eq_ignore_ascii_case(v))
+                                        } else {
+                                            v.eq_ignore_ascii_case(&rsvp.attendee)
+                                        }
                                     })
                             {
                                 let mut add_partstat = true;
@@ -415,11 +483,29 @@ impl ItipIngest for Server {
                 }
 
                 if did_change {
+                    // Notify the organizer of the booking decision
+                    let itip_messages = if let Some(old_ical) = old_ical {
+                        match itip_update(
+                            &mut new_event.data.event,
+                            &old_ical,
+                            access_token.emails.as_slice(),
+                        ) {
+                            Ok(messages) => Some(ItipMessages::new(messages)),
+                            Err(err) => {
+                                trc::event!(
+                                    Calendar(trc::CalendarEvent::ItipMessageError),
+                                    AccountId = rsvp.account_id,
+                                    DocumentId = rsvp.document_id,
+                                    Details = err.to_string(),
+                                );
+                                None
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
                     // Prepare write batch
-                    let access_token = self
-                        .get_access_token(rsvp.account_id)
-                        .await
-                        .caused_by(trc::location!())?;
                     let mut batch = BatchBuilder::new();
                     new_event
                         .update(
@@ -430,6 +516,11 @@ impl ItipIngest for Server {
                             &mut batch,
                         )
                         .caused_by(trc::location!())?;
+                    if let Some(itip_messages) = itip_messages {
+                        itip_messages
+                            .queue(&mut batch)
+                            .caused_by(trc::location!())?;
+                    }
 
                     self.commit_batch(batch).await.caused_by(trc::location!())?;
                 }
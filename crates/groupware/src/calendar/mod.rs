@@ -5,6 +5,8 @@
  */
 
 pub mod alarm;
+pub mod availability;
+pub mod booking;
 pub mod dates;
 pub mod expand;
 pub mod index;
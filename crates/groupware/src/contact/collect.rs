@@ -0,0 +1,189 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{AddressBook, ContactCard, gal::escape_text};
+use crate::cache::GroupwareCache;
+use calcard::{Entry, Parser};
+use common::{DavName, IDX_EMAIL, KV_RATE_LIMIT_COLLECT, Server, auth::AccessToken};
+use directory::Permission;
+use jmap_proto::types::collection::{Collection, SyncCollection};
+use std::fmt::Write;
+use store::{query::Filter, write::BatchBuilder};
+use trc::AddContext;
+use utils::sanitize_email;
+
+pub struct CollectedAddress {
+    pub address: String,
+    pub name: Option<String>,
+}
+
+pub trait ContactCollect: Sync + Send {
+    fn collect_addresses(
+        &self,
+        access_token: &AccessToken,
+        addresses: Vec<CollectedAddress>,
+    ) -> impl Future<Output = trc::Result<usize>> + Send;
+}
+
+impl ContactCollect for Server {
+    async fn collect_addresses(
+        &self,
+        access_token: &AccessToken,
+        addresses: Vec<CollectedAddress>,
+    ) -> trc::Result<usize> {
+        let Some(config) = &self.core.groupware.collect else {
+            return Ok(0);
+        };
+        if !access_token.has_permission(Permission::ContactsCollect) {
+            return Ok(0);
+        }
+        let account_id = access_token.primary_id;
+
+        // Skip own addresses and addresses already present in any address book
+        let mut new_addresses: Vec<CollectedAddress> = Vec::with_capacity(addresses.len());
+        for mut addr in addresses.into_iter().take(config.max_recipients) {
+            let Some(address) = sanitize_email(&addr.address) else {
+                continue;
+            };
+            if access_token.emails.iter().any(|email| email == &address)
+                || new_addresses.iter().any(|a| a.address == address)
+                || !self
+                    .store()
+                    .filter(
+                        account_id,
+                        Collection::ContactCard,
+                        vec![Filter::eq(IDX_EMAIL, address.as_bytes().to_vec())],
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                    .results
+                    .is_empty()
+            {
+                continue;
+            }
+            addr.address = address;
+            new_addresses.push(addr);
+        }
+        if new_addresses.is_empty() {
+            return Ok(0);
+        }
+
+        let resources = self
+            .fetch_dav_resources(access_token, account_id, SyncCollection::AddressBook)
+            .await
+            .caused_by(trc::location!())?;
+        let mut batch = BatchBuilder::new();
+        let addressbook_id = if let Some(resource) = resources
+            .by_path(&config.name)
+            .filter(|resource| resource.is_container())
+        {
+            resource.document_id()
+        } else {
+            let document_id = self
+                .store()
+                .assign_document_ids(account_id, Collection::AddressBook, 1)
+                .await
+                .caused_by(trc::location!())?;
+            AddressBook {
+                name: config.name.clone(),
+                display_name: config.display_name.clone().into(),
+                ..Default::default()
+            }
+            .insert(access_token, account_id, document_id, &mut batch)
+            .caused_by(trc::location!())?;
+            document_id
+        };
+
+        let mut collected = 0;
+        for addr in new_addresses {
+            if let Some(rate) = &config.rate {
+                if self
+                    .in_memory_store()
+                    .is_rate_allowed(
+                        KV_RATE_LIMIT_COLLECT,
+                        &account_id.to_be_bytes(),
+                        rate,
+                        false,
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some()
+                {
+                    trc::event!(
+                        Limit(trc::LimitEvent::TooManyRequests),
+                        AccountId = account_id,
+                        Details = "Contact collection rate exceeded",
+                    );
+                    break;
+                }
+            }
+
+            let Some((vcard_raw, vcard)) = build_vcard(&addr) else {
+                continue;
+            };
+            let document_id = self
+                .store()
+                .assign_document_ids(account_id, Collection::ContactCard, 1)
+                .await
+                .caused_by(trc::location!())?;
+            ContactCard {
+                names: vec![DavName {
+                    name: format!("{}.vcf", card_name(&addr.address)),
+                    parent_id: addressbook_id,
+                }],
+                card: vcard,
+                size: vcard_raw.len() as u32,
+                ..Default::default()
+            }
+            .insert(access_token, account_id, document_id, &mut batch)
+            .caused_by(trc::location!())?;
+            collected += 1;
+        }
+
+        if !batch.is_empty() {
+            self.commit_batch(batch)
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(collected)
+    }
+}
+
+fn build_vcard(addr: &CollectedAddress) -> Option<(String, calcard::vcard::VCard)> {
+    let mut vcard = String::with_capacity(128);
+    let _ = write!(
+        &mut vcard,
+        "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:urn:stalwart:collected:{}\r\nFN:{}\r\nEMAIL;PREF=1:{}\r\nEND:VCARD\r\n",
+        card_name(&addr.address),
+        escape_text(
+            addr.name
+                .as_deref()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+                .unwrap_or(&addr.address)
+        ),
+        escape_text(&addr.address),
+    );
+
+    match Parser::new(&vcard).entry() {
+        Entry::VCard(card) => Some((vcard, card)),
+        _ => None,
+    }
+}
+
+fn card_name(address: &str) -> String {
+    address
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_' | '@' | '+') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,323 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{AddressBook, ContactCard};
+use crate::{DestroyArchive, cache::GroupwareCache};
+use ahash::AHashMap;
+use calcard::{Entry, Parser};
+use common::{DavName, Server, auth::AccessToken, config::groupware::GalConfig};
+use directory::{Principal, QueryBy, Type, backend::internal::manage::ManageDirectory};
+use jmap_proto::types::{
+    acl::Acl,
+    collection::{Collection, SyncCollection},
+    value::AclGrant,
+};
+use std::fmt::Write;
+use store::write::BatchBuilder;
+use trc::AddContext;
+use utils::map::bitmap::Bitmap;
+
+pub trait GlobalAddressList: Sync + Send {
+    fn sync_global_address_lists(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl GlobalAddressList for Server {
+    async fn sync_global_address_lists(&self) -> trc::Result<()> {
+        let Some(gal) = &self.core.groupware.gal else {
+            return Ok(());
+        };
+
+        // Principals that do not belong to a tenant are published in the address book
+        // of the configured account, tenant principals in the tenant's own account.
+        let default_owner_id = if let Some(account) = &gal.account {
+            let owner_id = self
+                .core
+                .storage
+                .directory
+                .query(QueryBy::Name(account), false)
+                .await
+                .caused_by(trc::location!())?
+                .map(|principal| principal.id());
+            if owner_id.is_none() {
+                trc::error!(
+                    trc::ManageEvent::NotFound
+                        .into_err()
+                        .details("Global address list account not found")
+                        .ctx(trc::Key::Name, account.to_string())
+                );
+            }
+            owner_id
+        } else {
+            None
+        };
+
+        let mut scopes: AHashMap<u32, Vec<Principal>> = AHashMap::new();
+        for principal in self
+            .store()
+            .list_principals(
+                None,
+                None,
+                &[Type::Individual, Type::Group, Type::List, Type::Location],
+                true,
+                0,
+                0,
+            )
+            .await
+            .caused_by(trc::location!())?
+            .items
+        {
+            if let Some(owner_id) = principal.tenant().or(default_owner_id) {
+                scopes.entry(owner_id).or_default().push(principal);
+            }
+        }
+
+        for (owner_id, principals) in scopes {
+            if let Err(err) = sync_address_list(self, gal, owner_id, &principals).await {
+                trc::error!(
+                    err.account_id(owner_id)
+                        .details("Failed to synchronize global address list")
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn sync_address_list(
+    server: &Server,
+    gal: &GalConfig,
+    owner_id: u32,
+    principals: &[Principal],
+) -> trc::Result<()> {
+    let access_token = server
+        .get_access_token(owner_id)
+        .await
+        .caused_by(trc::location!())?;
+    let resources = server
+        .fetch_dav_resources(&access_token, owner_id, SyncCollection::AddressBook)
+        .await
+        .caused_by(trc::location!())?;
+
+    // Every individual in scope gets read-only access to the address book
+    let acls = principals
+        .iter()
+        .filter(|principal| principal.typ() == Type::Individual && principal.id() != owner_id)
+        .map(|principal| AclGrant {
+            account_id: principal.id(),
+            grants: Bitmap::from_iter([Acl::Read, Acl::ReadItems]),
+        })
+        .collect::<Vec<_>>();
+    let mut acl_changes = None;
+    let mut batch = BatchBuilder::new();
+
+    let addressbook_id = if let Some(resource) = resources
+        .by_path(&gal.name)
+        .filter(|resource| resource.is_container())
+    {
+        let document_id = resource.document_id();
+        let book_ = server
+            .get_archive(owner_id, Collection::AddressBook, document_id)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| {
+                trc::StoreEvent::NotFound
+                    .into_err()
+                    .document_id(document_id)
+                    .caused_by(trc::location!())
+            })?;
+        let book = book_
+            .to_unarchived::<AddressBook>()
+            .caused_by(trc::location!())?;
+        let current_acls = book
+            .inner
+            .acls
+            .iter()
+            .map(AclGrant::from)
+            .collect::<Vec<_>>();
+        if current_acls != acls {
+            let mut new_book = book
+                .deserialize::<AddressBook>()
+                .caused_by(trc::location!())?;
+            new_book.acls = acls.clone();
+            new_book
+                .update(&access_token, book, owner_id, document_id, &mut batch)
+                .caused_by(trc::location!())?;
+            acl_changes = Some(Some(current_acls));
+        }
+
+        document_id
+    } else {
+        let document_id = server
+            .store()
+            .assign_document_ids(owner_id, Collection::AddressBook, 1)
+            .await
+            .caused_by(trc::location!())?;
+        AddressBook {
+            name: gal.name.clone(),
+            display_name: gal.display_name.clone().into(),
+            acls: acls.clone(),
+            ..Default::default()
+        }
+        .insert(&access_token, owner_id, document_id, &mut batch)
+        .caused_by(trc::location!())?;
+        acl_changes = Some(None);
+
+        document_id
+    };
+
+    // Build a card for each principal and update only the ones that changed,
+    // so clients syncing with a sync-token receive the minimal set of changes.
+    let mut existing_cards = resources
+        .children(addressbook_id)
+        .filter_map(|resource| {
+            resource
+                .path()
+                .rsplit_once('/')
+                .map(|(_, name)| (name.to_string(), resource))
+        })
+        .collect::<AHashMap<_, _>>();
+
+    for principal in principals {
+        let Some((vcard_raw, vcard)) = build_vcard(principal) else {
+            continue;
+        };
+        let name = format!("{}.vcf", principal.id());
+
+        if let Some(resource) = existing_cards.remove(&name) {
+            let document_id = resource.document_id();
+            let Some(card_) = server
+                .get_archive(owner_id, Collection::ContactCard, document_id)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let card = card_
+                .to_unarchived::<ContactCard>()
+                .caused_by(trc::location!())?;
+            let mut new_card = card
+                .deserialize::<ContactCard>()
+                .caused_by(trc::location!())?;
+            if new_card.card != vcard {
+                new_card.card = vcard;
+                new_card.size = vcard_raw.len() as u32;
+                new_card
+                    .update(&access_token, card, owner_id, document_id, &mut batch)
+                    .caused_by(trc::location!())?;
+            }
+        } else {
+            let document_id = server
+                .store()
+                .assign_document_ids(owner_id, Collection::ContactCard, 1)
+                .await
+                .caused_by(trc::location!())?;
+            ContactCard {
+                names: vec![DavName {
+                    name,
+                    parent_id: addressbook_id,
+                }],
+                card: vcard,
+                size: vcard_raw.len() as u32,
+                ..Default::default()
+            }
+            .insert(&access_token, owner_id, document_id, &mut batch)
+            .caused_by(trc::location!())?;
+        }
+
+        if batch.is_large_batch() {
+            server
+                .commit_batch(std::mem::take(&mut batch))
+                .await
+                .caused_by(trc::location!())?;
+        }
+    }
+
+    // Remove principals that no longer exist
+    for resource in existing_cards.into_values() {
+        let document_id = resource.document_id();
+        if let Some(card_) = server
+            .get_archive(owner_id, Collection::ContactCard, document_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            DestroyArchive(
+                card_
+                    .to_unarchived::<ContactCard>()
+                    .caused_by(trc::location!())?,
+            )
+            .delete(
+                &access_token,
+                owner_id,
+                document_id,
+                addressbook_id,
+                resources.format_resource(resource).into(),
+                &mut batch,
+            )
+            .caused_by(trc::location!())?;
+        }
+    }
+
+    if !batch.is_empty() {
+        server
+            .commit_batch(batch)
+            .await
+            .caused_by(trc::location!())?;
+    }
+
+    if let Some(current_acls) = acl_changes {
+        server.refresh_acls(&acls, current_acls.as_deref()).await;
+    }
+
+    Ok(())
+}
+
+fn build_vcard(principal: &Principal) -> Option<(String, calcard::vcard::VCard)> {
+    if principal.emails.is_empty() {
+        return None;
+    }
+
+    let kind = match principal.typ() {
+        Type::Group | Type::List => "group",
+        Type::Location => "location",
+        _ => "individual",
+    };
+    let mut vcard = String::with_capacity(128);
+    let _ = write!(
+        &mut vcard,
+        "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:urn:stalwart:principal:{}\r\nKIND:{kind}\r\nFN:{}\r\n",
+        principal.id(),
+        escape_text(principal.description().unwrap_or(principal.name())),
+    );
+    for (idx, email) in principal.emails.iter().enumerate() {
+        if idx == 0 {
+            let _ = write!(&mut vcard, "EMAIL;PREF=1:{}\r\n", escape_text(email));
+        } else {
+            let _ = write!(&mut vcard, "EMAIL:{}\r\n", escape_text(email));
+        }
+    }
+    vcard.push_str("END:VCARD\r\n");
+
+    match Parser::new(&vcard).entry() {
+        Entry::VCard(card) => Some((vcard, card)),
+        _ => None,
+    }
+}
+
+pub(super) fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod collect;
+pub mod gal;
 pub mod index;
 pub mod storage;
 
@@ -4,9 +4,14 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::Arc;
-
-use common::{HttpAuthCache, Server, auth::AuthRequest, listener::limiter::InFlight};
+use std::{borrow::Cow, sync::Arc};
+
+use common::{
+    HttpAuthCache, Server,
+    auth::{AuthRequest, oauth::GrantType},
+    listener::limiter::InFlight,
+};
+use directory::core::app_password::AuthProtocol;
 use http_proto::{HttpRequest, HttpSessionData};
 use hyper::header;
 use mail_parser::decoders::base64::base64_decode;
@@ -15,6 +20,8 @@ use mail_send::Credentials;
 use common::auth::AccessToken;
 use std::future::Future;
 
+use super::trusted_device::TRUSTED_DEVICE_COOKIE;
+
 pub trait Authenticator: Sync + Send {
     fn authenticate_headers(
         &self,
@@ -32,12 +39,24 @@ impl Authenticator for Server {
         allow_api_access: bool,
     ) -> trc::Result<(Option<InFlight>, Arc<AccessToken>)> {
         if let Some((mechanism, token)) = req.authorization() {
+            // Basic auth requests from trusted devices do not require a second factor
+            let trusted_device = req
+                .cookie(TRUSTED_DEVICE_COOKIE)
+                .filter(|_| mechanism.eq_ignore_ascii_case("basic"));
+            let cache_key = match trusted_device {
+                Some(device) => Cow::Owned(format!("{token} {device}")),
+                None => Cow::Borrowed(token),
+            };
+
             // Check if the credentials are cached
-            if let Some(http_cache) = self.inner.cache.http_auth.get(token) {
+            if let Some(http_cache) = self.inner.cache.http_auth.get(cache_key.as_ref()) {
                 let access_token = self.get_access_token(http_cache.account_id).await?;
 
-                // Make sure the revision is still valid
-                if access_token.revision == http_cache.revision {
+                // Make sure the revision is still valid and, as app passwords can be
+                // restricted by address, that the request comes from the same address
+                if access_token.revision == http_cache.revision
+                    && http_cache.remote_ip == session.remote_ip
+                {
                     // Enforce authenticated rate limit
                     return self
                         .is_http_authenticated_request_allowed(&access_token)
@@ -80,22 +99,40 @@ impl Authenticator for Server {
             };
 
             // Authenticate
-            let access_token = self
-                .authenticate(&AuthRequest::from_credentials(
-                    credentials,
-                    session.session_id,
+            let mut auth_req =
+                AuthRequest::from_credentials(credentials, session.session_id, session.remote_ip)
+                    .with_protocol(AuthProtocol::Http);
+            if let Some(device) = trusted_device {
+                if let Ok(token_info) = self
+                    .validate_access_token(GrantType::TrustedDevice.into(), device)
+                    .await
+                {
+                    auth_req = auth_req.with_trusted_device(token_info.account_id);
+                }
+            }
+            let access_token = self.authenticate(&auth_req).await?;
+
+            if access_token.impersonator.is_none() {
+                // Cache credentials
+                self.inner.cache.http_auth.insert(
+                    cache_key.into_owned(),
+                    HttpAuthCache {
+                        account_id: access_token.primary_id(),
+                        revision: access_token.revision,
+                        remote_ip: session.remote_ip,
+                    },
+                );
+            } else {
+                // Impersonated requests are not cached so that each one is audited
+                self.record_impersonation(
+                    &access_token,
+                    AuthProtocol::Http,
                     session.remote_ip,
-                ))
-                .await?;
-
-            // Cache credentials
-            self.inner.cache.http_auth.insert(
-                token.to_string(),
-                HttpAuthCache {
-                    account_id: access_token.primary_id(),
-                    revision: access_token.revision,
-                },
-            );
+                    format!("{} {}", req.method(), req.uri().path()),
+                    session.session_id,
+                )
+                .await;
+            }
 
             // Enforce authenticated rate limit
             self.is_http_authenticated_request_allowed(&access_token)
@@ -117,6 +154,7 @@ impl Authenticator for Server {
 pub trait HttpHeaders {
     fn authorization(&self) -> Option<(&str, &str)>;
     fn authorization_basic(&self) -> Option<&str>;
+    fn cookie(&self, name: &str) -> Option<&str>;
 }
 
 impl HttpHeaders for HttpRequest {
@@ -136,9 +174,24 @@ impl HttpHeaders for HttpRequest {
             }
         })
     }
+
+    fn cookie(&self, name: &str) -> Option<&str> {
+        self.headers()
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(';'))
+            .find_map(|cookie| {
+                cookie
+                    .trim()
+                    .split_once('=')
+                    .filter(|(key, _)| *key == name)
+                    .map(|(_, value)| value)
+            })
+    }
 }
 
-fn decode_plain_auth(token: &str) -> Option<Credentials<String>> {
+pub(crate) fn decode_plain_auth(token: &str) -> Option<Credentials<String>> {
     base64_decode(token.as_bytes())
         .and_then(|token| String::from_utf8(token).ok())
         .and_then(|token| {
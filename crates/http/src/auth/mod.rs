@@ -6,3 +6,4 @@
 
 pub mod authenticate;
 pub mod oauth;
+pub mod trusted_device;
@@ -42,6 +42,7 @@ pub struct OAuthMetadata {
     pub device_authorization_endpoint: String,
     pub registration_endpoint: String,
     pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
     pub grant_types_supported: Vec<String>,
     pub response_types_supported: Vec<String>,
     pub scopes_supported: Vec<String>,
@@ -305,11 +306,13 @@ impl OAuthApiHandler for Server {
             token_endpoint: format!("{base_url}/auth/token"),
             device_authorization_endpoint: format!("{base_url}/auth/device"),
             introspection_endpoint: format!("{base_url}/auth/introspect"),
+            revocation_endpoint: format!("{base_url}/auth/revoke"),
             registration_endpoint: format!("{base_url}/auth/register"),
             grant_types_supported: vec![
                 "authorization_code".to_string(),
                 "implicit".to_string(),
                 "urn:ietf:params:oauth:grant-type:device_code".to_string(),
+                "client_credentials".to_string(),
             ],
             response_types_supported: vec![
                 "code".to_string(),
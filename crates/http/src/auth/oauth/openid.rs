@@ -84,6 +84,7 @@ impl OpenIdHandler for Server {
                 "authorization_code".into(),
                 "implicit".into(),
                 "urn:ietf:params:oauth:grant-type:device_code".into(),
+                "client_credentials".into(),
             ],
             scopes_supported: vec!["openid".into(), "offline_access".into()],
             subject_types_supported: vec!["public".into()],
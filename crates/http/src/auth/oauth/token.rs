@@ -8,6 +8,7 @@ use super::{
     ArchivedOAuthStatus, ErrorType, FormData, MAX_POST_LEN, OAuthCode, OAuthResponse, OAuthStatus,
     TokenResponse, registration::ClientRegistrationHandler,
 };
+use crate::auth::authenticate::{HttpHeaders, decode_plain_auth};
 use common::{
     KV_OAUTH, Server,
     auth::{
@@ -15,8 +16,10 @@ use common::{
         oauth::{GrantType, oidc::StandardClaims},
     },
 };
+use directory::{Permission, QueryBy, Type, backend::internal::lookup::DirectoryStore};
 use http_proto::*;
 use hyper::StatusCode;
+use mail_send::Credentials;
 use std::future::Future;
 use store::{
     dispatch::lookup::KeyValue,
@@ -38,6 +41,13 @@ pub trait TokenHandler: Sync + Send {
         session_id: u64,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn handle_token_revoke(
+        &self,
+        req: &mut HttpRequest,
+        access_token: &AccessToken,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
     fn issue_token(
         &self,
         account_id: u32,
@@ -248,6 +258,73 @@ impl TokenHandler for Server {
             } else {
                 response = TokenResponse::error(ErrorType::InvalidRequest);
             }
+        } else if grant_type.eq_ignore_ascii_case("client_credentials") {
+            // Clients authenticate using Basic auth or the request parameters
+            let credentials = req
+                .authorization_basic()
+                .and_then(decode_plain_auth)
+                .or_else(|| {
+                    params
+                        .get("client_id")
+                        .zip(params.get("client_secret"))
+                        .map(|(client_id, client_secret)| Credentials::Plain {
+                            username: client_id.to_string(),
+                            secret: client_secret.to_string(),
+                        })
+                });
+
+            response = TokenResponse::error(ErrorType::InvalidClient);
+            if let Some(credentials) = credentials {
+                if let Some(client) = self
+                    .store()
+                    .query(QueryBy::Credentials(&credentials), false)
+                    .await
+                    .caused_by(trc::location!())?
+                    .filter(|principal| principal.typ() == Type::OauthClient)
+                {
+                    // Service clients are granted the permissions assigned to their principal,
+                    // requested scopes have to be a subset of these permissions
+                    let access_token = self
+                        .get_access_token(client.id())
+                        .await
+                        .caused_by(trc::location!())?;
+                    let scopes = params
+                        .get("scope")
+                        .map(|scope| scope.split_ascii_whitespace().collect::<Vec<_>>())
+                        .unwrap_or_default();
+
+                    response = if !access_token.has_permission(Permission::Authenticate) {
+                        TokenResponse::error(ErrorType::UnauthorizedClient)
+                    } else if !scopes.iter().all(|scope| {
+                        Permission::from_name(scope)
+                            .is_some_and(|permission| access_token.has_permission(permission))
+                    }) {
+                        TokenResponse::error(ErrorType::InvalidScope)
+                    } else {
+                        let mut token = self
+                            .issue_token(client.id(), client.name(), issuer, None, false, false)
+                            .await
+                            .map_err(|err| {
+                                trc::AuthEvent::Error
+                                    .into_err()
+                                    .details(err)
+                                    .caused_by(trc::location!())
+                            })?;
+                        if !scopes.is_empty() {
+                            token.scope = Some(scopes.join(" "));
+                        }
+
+                        trc::event!(
+                            Auth(trc::AuthEvent::Success),
+                            AccountName = client.name().to_string(),
+                            AccountId = client.id(),
+                            SpanId = session.session_id,
+                        );
+
+                        TokenResponse::Granted(token)
+                    };
+                }
+            }
         }
 
         Ok(JsonResponse::with_status(
@@ -282,6 +359,29 @@ impl TokenHandler for Server {
             .map(|response| JsonResponse::new(response).no_cache().into_http_response())
     }
 
+    async fn handle_token_revoke(
+        &self,
+        req: &mut HttpRequest,
+        access_token: &AccessToken,
+        session_id: u64,
+    ) -> trc::Result<HttpResponse> {
+        // Parse token
+        let token = FormData::from_request(req, 1024, session_id)
+            .await?
+            .remove("token")
+            .ok_or_else(|| {
+                trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Token is missing.")
+            })?;
+
+        self.revoke_access_token(&token, access_token).await?;
+
+        Ok(JsonResponse::new(serde_json::json!({}))
+            .no_cache()
+            .into_http_response())
+    }
+
     async fn issue_token(
         &self,
         account_id: u32,
@@ -0,0 +1,163 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    auth::{
+        AuthRequest,
+        oauth::GrantType,
+        webauthn::{WebAuthnAssertion, WebAuthnCredential},
+    },
+};
+use directory::{QueryBy, core::app_password::AuthProtocol};
+use http_proto::{request::fetch_body, *};
+use hyper::header;
+use mail_send::Credentials;
+use serde_json::json;
+
+use super::authenticate::{HttpHeaders, decode_plain_auth};
+
+pub const TRUSTED_DEVICE_COOKIE: &str = "stalwart_trusted_device";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum TrustedDeviceRequest {
+    Totp { code: String },
+    WebauthnChallenge,
+    Webauthn(WebAuthnAssertion),
+}
+
+pub trait TrustedDeviceHandler: Sync + Send {
+    fn handle_trusted_device_request(
+        &self,
+        req: &mut HttpRequest,
+        session: HttpSessionData,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl TrustedDeviceHandler for Server {
+    async fn handle_trusted_device_request(
+        &self,
+        req: &mut HttpRequest,
+        session: HttpSessionData,
+    ) -> trc::Result<HttpResponse> {
+        let Some(expiry) = self.core.jmap.two_factor.trusted_device_expiry else {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        };
+
+        // Obtain the Basic auth credentials
+        let Some(Credentials::Plain { username, secret }) =
+            req.authorization_basic().and_then(decode_plain_auth)
+        else {
+            return Err(trc::AuthEvent::Failed
+                .into_err()
+                .details("Missing Basic Authorization header."));
+        };
+
+        // Parse request
+        let body = fetch_body(req, 8 * 1024, session.session_id).await;
+        let request =
+            serde_json::from_slice::<TrustedDeviceRequest>(body.as_deref().unwrap_or_default())
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+        let auth_request = |secret: String| {
+            AuthRequest::from_plain(
+                username.clone(),
+                secret,
+                session.session_id,
+                session.remote_ip,
+            )
+            .with_protocol(AuthProtocol::Http)
+            .without_members()
+        };
+
+        let account_id = match request {
+            TrustedDeviceRequest::Totp { code } => self
+                .authenticate(&auth_request(format!("{secret}${code}")))
+                .await?
+                .primary_id(),
+            TrustedDeviceRequest::WebauthnChallenge | TrustedDeviceRequest::Webauthn(_) => {
+                // Make sure the password is valid and a second factor is required
+                match self.authenticate(&auth_request(secret)).await {
+                    Err(err) if err.matches(trc::EventType::Auth(trc::AuthEvent::MissingTotp)) => {}
+                    Err(err) => return Err(err),
+                    Ok(_) => {
+                        return Err(trc::AuthEvent::Error
+                            .into_err()
+                            .details("Two-factor authentication is not enabled."));
+                    }
+                }
+                let principal = self
+                    .directory()
+                    .query(QueryBy::Name(&username), false)
+                    .await?
+                    .ok_or_else(|| trc::AuthEvent::Failed.into_err())?;
+
+                match request {
+                    TrustedDeviceRequest::Webauthn(assertion) => {
+                        if !self.webauthn_verify(&principal, &assertion).await? {
+                            return Err(trc::AuthEvent::Failed
+                                .into_err()
+                                .details("Invalid WebAuthn assertion.")
+                                .ctx(trc::Key::RemoteIp, session.remote_ip)
+                                .ctx(trc::Key::AccountName, username));
+                        }
+
+                        principal.id()
+                    }
+                    _ => {
+                        let challenge = self.webauthn_challenge(principal.id()).await?;
+                        let credentials = principal
+                            .secrets
+                            .iter()
+                            .filter_map(|secret| WebAuthnCredential::parse(secret))
+                            .map(|credential| credential.credential_id)
+                            .collect::<Vec<_>>();
+
+                        return Ok(JsonResponse::new(json!({
+                            "data": {
+                                "challenge": challenge,
+                                "rpId": self.core.jmap.two_factor.webauthn_rp_id,
+                                "credentials": credentials,
+                            },
+                        }))
+                        .no_cache()
+                        .into_http_response());
+                    }
+                }
+            }
+        };
+
+        // Issue the trusted device cookie
+        let token = self
+            .encode_access_token(
+                GrantType::TrustedDevice,
+                account_id,
+                "trusted-device",
+                expiry.as_secs(),
+            )
+            .await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": {
+                "expiresIn": expiry.as_secs(),
+            },
+        }))
+        .no_cache()
+        .into_http_response()
+        .with_header(
+            header::SET_COOKIE,
+            format!(
+                "{TRUSTED_DEVICE_COOKIE}={token}; Max-Age={}; Path=/; HttpOnly; Secure; SameSite=Strict",
+                expiry.as_secs()
+            ),
+        ))
+    }
+}
@@ -0,0 +1,518 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+pub mod proto;
+
+use std::{collections::HashMap, future::Future};
+
+use common::Server;
+use directory::Permission;
+use http_body_util::{StreamBody, combinators::BoxBody};
+use http_proto::{HttpRequest, HttpResponse, HttpSessionData, request::fetch_body};
+use hyper::{
+    HeaderMap, StatusCode,
+    body::{Bytes, Frame},
+    header::{CONTENT_TYPE, HeaderValue},
+};
+use prost::Message;
+use smtp::{
+    queue::{
+        self, ArchivedStatus, DomainPart, MessageSource,
+        spool::SmtpSpool,
+        suppression::{SmtpSuppression, SuppressionEntry},
+    },
+    reporting::SmtpReporting,
+};
+use store::{ahash::AHashSet, write::now};
+use trc::{
+    Event, EventDetails, EventType, Key, Value,
+    ipc::subscriber::{Interests, SubscriberBuilder},
+};
+use utils::sanitize_email;
+
+use crate::auth::authenticate::Authenticator;
+
+pub const GRPC_QUEUE_SERVICE: &str = "stalwart.queue.v1.QueueService";
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const FRAME_HEADER_LEN: usize = 5;
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GrpcCode {
+    Ok = 0,
+    InvalidArgument = 3,
+    NotFound = 5,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    Unimplemented = 12,
+    Internal = 13,
+    Unauthenticated = 16,
+}
+
+pub trait GrpcHandler: Sync + Send {
+    fn handle_grpc_request(
+        &self,
+        req: &mut HttpRequest,
+        method: &str,
+        session: &HttpSessionData,
+    ) -> impl Future<Output = HttpResponse> + Send;
+}
+
+impl GrpcHandler for Server {
+    async fn handle_grpc_request(
+        &self,
+        req: &mut HttpRequest,
+        method: &str,
+        session: &HttpSessionData,
+    ) -> HttpResponse {
+        if !is_grpc_request(req) {
+            return status_response(GrpcCode::Internal, "Invalid content type");
+        } else if req
+            .headers()
+            .get("grpc-encoding")
+            .is_some_and(|encoding| encoding.as_bytes() != b"identity")
+        {
+            return status_response(GrpcCode::Unimplemented, "Compression is not supported");
+        }
+
+        match handle_queue_method(self, req, method, session).await {
+            Ok(response) => response,
+            Err(err) => {
+                let (code, message) = error_status(&err);
+                let response = status_response(code, message);
+                trc::error!(err.span_id(session.session_id));
+                response
+            }
+        }
+    }
+}
+
+async fn handle_queue_method(
+    server: &Server,
+    req: &mut HttpRequest,
+    method: &str,
+    session: &HttpSessionData,
+) -> trc::Result<HttpResponse> {
+    let Some(config) = server.core.network.grpc.as_ref() else {
+        return Err(trc::ResourceEvent::NotFound.into_err());
+    };
+
+    // Authenticate request, the queue is shared by all tenants so tenant
+    // administrators are not allowed to access it.
+    let (_in_flight, access_token) = server.authenticate_headers(req, session, true).await?;
+    if access_token.tenant.is_some() {
+        return Err(trc::SecurityEvent::Unauthorized
+            .into_err()
+            .details("Tenant accounts cannot access the queue service"));
+    }
+
+    let body = fetch_body(
+        req,
+        config.max_message_size + FRAME_HEADER_LEN,
+        session.session_id,
+    )
+    .await
+    .ok_or_else(|| trc::LimitEvent::SizeRequest.into_err())?;
+    let body = decode_frame(&body)?;
+
+    match method {
+        "SubmitMessage" => {
+            access_token.assert_has_permission(Permission::MessageQueueSubmit)?;
+            let request = decode_message::<proto::SubmitMessageRequest>(body)?;
+
+            // Validate envelope
+            let sender = if !request.sender.is_empty() {
+                parse_address(&request.sender)?
+            } else {
+                String::new()
+            };
+            if request.recipients.is_empty() {
+                return Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("At least one recipient is required"));
+            } else if request.recipients.len() > config.max_recipients {
+                return Err(trc::LimitEvent::SizeRequest
+                    .into_err()
+                    .details("Too many recipients"));
+            } else if request.message.is_empty() {
+                return Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Message is empty"));
+            }
+            let recipients = request
+                .recipients
+                .iter()
+                .map(|rcpt| parse_address(rcpt))
+                .collect::<trc::Result<Vec<_>>>()?;
+
+            // Build message
+            let sender_domain = sender.domain_part().to_string();
+            let mut message =
+                server.new_message(sender.clone(), sender, sender_domain, session.session_id);
+            message.env_id = request.env_id;
+            for rcpt in recipients {
+                message.add_recipient(rcpt, server).await;
+            }
+            let queue_id = message.queue_id;
+
+            // Sign message
+            let signature = if config.dkim_sign {
+                server
+                    .sign_message(
+                        &mut message,
+                        &server.core.smtp.mail_auth.dkim.sign,
+                        &request.message,
+                    )
+                    .await
+            } else {
+                None
+            };
+
+            // Queue message
+            if message
+                .queue(
+                    signature.as_deref(),
+                    &request.message,
+                    session.session_id,
+                    server,
+                    MessageSource::Authenticated,
+                )
+                .await
+            {
+                Ok(unary_response(&proto::SubmitMessageResponse { queue_id }))
+            } else {
+                Err(trc::StoreEvent::UnexpectedError
+                    .into_err()
+                    .details("Failed to queue message"))
+            }
+        }
+        "GetMessageStatus" => {
+            access_token.assert_has_permission(Permission::MessageQueueGet)?;
+            let request = decode_message::<proto::GetMessageStatusRequest>(body)?;
+            let archive = server
+                .read_message_archive(request.queue_id)
+                .await?
+                .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+            let message = archive.unarchive::<queue::Message>()?;
+
+            Ok(unary_response(&proto::MessageStatus {
+                queue_id: message.queue_id.into(),
+                sender: message.return_path.to_string(),
+                created: message.created.into(),
+                size: message.size.into(),
+                recipients: message
+                    .recipients
+                    .iter()
+                    .map(|rcpt| {
+                        let domain = message.domains.get(u32::from(rcpt.domain_idx) as usize);
+                        let (status, response) = match &rcpt.status {
+                            ArchivedStatus::Scheduled => {
+                                (proto::DeliveryStatus::Scheduled, String::new())
+                            }
+                            ArchivedStatus::Completed(status) => (
+                                proto::DeliveryStatus::Completed,
+                                status.response.to_string(),
+                            ),
+                            ArchivedStatus::TemporaryFailure(status) => (
+                                proto::DeliveryStatus::TemporaryFailure,
+                                status.response.to_string(),
+                            ),
+                            ArchivedStatus::PermanentFailure(status) => (
+                                proto::DeliveryStatus::PermanentFailure,
+                                status.response.to_string(),
+                            ),
+                        };
+
+                        proto::RecipientStatus {
+                            address: rcpt.address.to_string(),
+                            status: status as i32,
+                            response,
+                            attempts: domain.map_or(0, |domain| domain.retry.inner.into()),
+                            next_retry: domain.map_or(0, |domain| domain.retry.due.into()),
+                            expires: domain.map_or(0, |domain| domain.expires.into()),
+                        }
+                    })
+                    .collect(),
+            }))
+        }
+        "StreamQueueEvents" => {
+            access_token.assert_has_permission(Permission::MessageQueueList)?;
+            let request = decode_message::<proto::StreamQueueEventsRequest>(body)?;
+            let queue_ids = request.queue_ids.into_iter().collect::<AHashSet<_>>();
+
+            let mut interests = Interests::default();
+            for event in EventType::variants() {
+                if matches!(event, EventType::Queue(_) | EventType::Delivery(_)) {
+                    interests.set(event);
+                }
+            }
+            let (_, mut rx) = SubscriberBuilder::new(format!("grpc-{}", session.session_id))
+                .with_interests(interests)
+                .with_global_interests(true)
+                .with_lossy(false)
+                .register();
+
+            Ok(HttpResponse::new(StatusCode::OK)
+                .with_content_type(GRPC_CONTENT_TYPE)
+                .with_stream_body(BoxBody::new(StreamBody::new(async_stream::stream! {
+                    while let Some(event_batch) = rx.recv().await {
+                        for event in event_batch {
+                            if let Some(event) = queue_event(&event)
+                                .filter(|event| queue_ids.is_empty() || queue_ids.contains(&event.queue_id))
+                            {
+                                yield Ok(Frame::data(encode_frame(&event)));
+                            }
+                        }
+                    }
+
+                    yield Ok(Frame::trailers(status_trailers(GrpcCode::Ok, "")));
+                }))))
+        }
+        "AddSuppression" => {
+            access_token.assert_has_permission(Permission::SuppressionUpdate)?;
+            let request = decode_message::<proto::AddSuppressionRequest>(body)?;
+            let address = parse_address(&request.address)?;
+            let entry = SuppressionEntry::new(request.reason)
+                .with_expires(request.expires_in.map(|expires_in| now() + expires_in));
+            server.add_suppression(&address, entry.clone()).await?;
+
+            Ok(unary_response(&suppression(address, entry)))
+        }
+        "GetSuppression" => {
+            access_token.assert_has_permission(Permission::SuppressionGet)?;
+            let request = decode_message::<proto::GetSuppressionRequest>(body)?;
+            let address = parse_address(&request.address)?;
+            let entry = server
+                .get_suppression(&address)
+                .await?
+                .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+            Ok(unary_response(&suppression(address, entry)))
+        }
+        "RemoveSuppression" => {
+            access_token.assert_has_permission(Permission::SuppressionDelete)?;
+            let request = decode_message::<proto::RemoveSuppressionRequest>(body)?;
+            let address = parse_address(&request.address)?;
+
+            Ok(unary_response(&proto::RemoveSuppressionResponse {
+                removed: server.remove_suppression(&address).await?,
+            }))
+        }
+        "ListSuppressions" => {
+            access_token.assert_has_permission(Permission::SuppressionList)?;
+            let request = decode_message::<proto::ListSuppressionsRequest>(body)?;
+            let limit = match request.limit as usize {
+                0 => DEFAULT_LIST_LIMIT,
+                limit => limit.min(MAX_LIST_LIMIT),
+            };
+            let items = server
+                .list_suppressions(
+                    Some(request.after.as_str()).filter(|after| !after.is_empty()),
+                    limit,
+                )
+                .await?
+                .into_iter()
+                .map(|(address, entry)| suppression(address, entry))
+                .collect();
+
+            Ok(unary_response(&proto::ListSuppressionsResponse { items }))
+        }
+        _ => Err(trc::ResourceEvent::NotFound
+            .into_err()
+            .details("Unknown method")),
+    }
+}
+
+pub fn is_grpc_request(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(GRPC_CONTENT_TYPE))
+}
+
+fn parse_address(address: &str) -> trc::Result<String> {
+    sanitize_email(address).ok_or_else(|| {
+        trc::ResourceEvent::BadParameters
+            .into_err()
+            .details("Invalid email address")
+            .ctx(Key::Value, address.to_string())
+    })
+}
+
+fn suppression(address: String, entry: SuppressionEntry) -> proto::Suppression {
+    proto::Suppression {
+        address,
+        reason: entry.reason,
+        created: entry.created,
+        expires: entry.expires,
+    }
+}
+
+fn queue_event(event: &Event<EventDetails>) -> Option<proto::QueueEvent> {
+    let mut queue_id = None;
+    let mut attributes = HashMap::new();
+
+    for (key, value) in event.keys.iter().chain(
+        event
+            .inner
+            .span
+            .as_ref()
+            .map_or(([]).iter(), |span| span.keys.iter()),
+    ) {
+        if *key == Key::QueueId {
+            queue_id = queue_id.or_else(|| value.to_uint());
+        } else if !matches!(value, Value::None) {
+            attributes
+                .entry(key.id().to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+
+    Some(proto::QueueEvent {
+        r#type: event.inner.typ.name().to_string(),
+        timestamp: event.inner.timestamp,
+        queue_id: queue_id?,
+        attributes,
+    })
+}
+
+fn error_status(err: &trc::Error) -> (GrpcCode, &str) {
+    let code = match err.event_type() {
+        EventType::Auth(_) => GrpcCode::Unauthenticated,
+        EventType::Security(_) => GrpcCode::PermissionDenied,
+        EventType::Resource(trc::ResourceEvent::NotFound) => GrpcCode::NotFound,
+        EventType::Resource(trc::ResourceEvent::BadParameters) => GrpcCode::InvalidArgument,
+        EventType::Limit(_) => GrpcCode::ResourceExhausted,
+        _ => GrpcCode::Internal,
+    };
+    let message = if code != GrpcCode::Internal {
+        err.value_as_str(Key::Details)
+            .unwrap_or_else(|| err.event_type().description())
+    } else {
+        err.event_type().description()
+    };
+
+    (code, message)
+}
+
+fn decode_frame(bytes: &[u8]) -> trc::Result<&[u8]> {
+    match bytes.split_first() {
+        Some((0, bytes)) if bytes.len() >= FRAME_HEADER_LEN - 1 => {
+            let (len, message) = bytes.split_at(FRAME_HEADER_LEN - 1);
+            if u32::from_be_bytes(len.try_into().unwrap()) as usize == message.len() {
+                Ok(message)
+            } else {
+                Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Invalid message length"))
+            }
+        }
+        Some((1, _)) => Err(trc::ResourceEvent::BadParameters
+            .into_err()
+            .details("Compressed messages are not supported")),
+        _ => Err(trc::ResourceEvent::BadParameters
+            .into_err()
+            .details("Invalid message frame")),
+    }
+}
+
+fn encode_frame(message: &impl Message) -> Bytes {
+    let message = message.encode_to_vec();
+    let mut frame = Vec::with_capacity(message.len() + FRAME_HEADER_LEN);
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+    frame.into()
+}
+
+fn decode_message<T: Message + Default>(bytes: &[u8]) -> trc::Result<T> {
+    T::decode(bytes).map_err(|err| trc::ResourceEvent::BadParameters.into_err().reason(err))
+}
+
+fn unary_response(message: &impl Message) -> HttpResponse {
+    let frame = encode_frame(message);
+
+    HttpResponse::new(StatusCode::OK)
+        .with_content_type(GRPC_CONTENT_TYPE)
+        .with_stream_body(BoxBody::new(StreamBody::new(async_stream::stream! {
+            yield Ok(Frame::data(frame));
+            yield Ok(Frame::trailers(status_trailers(GrpcCode::Ok, "")));
+        })))
+}
+
+// Trailers-only response, used for errors
+fn status_response(code: GrpcCode, message: &str) -> HttpResponse {
+    let mut response = HttpResponse::new(StatusCode::OK)
+        .with_content_type(GRPC_CONTENT_TYPE)
+        .with_header("grpc-status", (code as u8).to_string());
+    if !message.is_empty() {
+        response = response.with_header("grpc-message", encode_status_message(message));
+    }
+    response
+}
+
+fn status_trailers(code: GrpcCode, message: &str) -> HeaderMap {
+    let mut trailers = HeaderMap::with_capacity(2);
+    trailers.insert("grpc-status", HeaderValue::from(code as i32));
+    if !message.is_empty() {
+        if let Ok(message) = HeaderValue::try_from(encode_status_message(message)) {
+            trailers.insert("grpc-message", message);
+        }
+    }
+    trailers
+}
+
+fn encode_status_message(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (0x20..0x7f).contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_frame, encode_frame, encode_status_message, proto};
+    use prost::Message;
+
+    #[test]
+    fn grpc_framing() {
+        let request = proto::SubmitMessageRequest {
+            sender: "john@example.org".to_string(),
+            recipients: vec!["jane@example.org".to_string()],
+            message: b"Subject: test\r\n\r\nhello".to_vec(),
+            env_id: None,
+        };
+        let frame = encode_frame(&request);
+        assert_eq!(frame[0], 0);
+        assert_eq!(
+            u32::from_be_bytes(frame[1..5].try_into().unwrap()) as usize,
+            frame.len() - 5
+        );
+        assert_eq!(
+            proto::SubmitMessageRequest::decode(decode_frame(&frame).unwrap()).unwrap(),
+            request
+        );
+
+        let mut compressed = frame.to_vec();
+        compressed[0] = 1;
+        assert!(decode_frame(&compressed).is_err());
+        assert!(decode_frame(&frame[..frame.len() - 1]).is_err());
+        assert!(decode_frame(&[0, 0, 0]).is_err());
+        assert_eq!(decode_frame(&[0, 0, 0, 0, 0]).unwrap(), b"");
+
+        assert_eq!(
+            encode_status_message("Invalid 100% ümlaut"),
+            "Invalid 100%25 %C3%BCmlaut"
+        );
+    }
+}
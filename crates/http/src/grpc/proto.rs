@@ -0,0 +1,144 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Message types for the `stalwart.queue.v1` package, see `api/v1/queue.proto`.
+
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubmitMessageRequest {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(string, repeated, tag = "2")]
+    pub recipients: Vec<String>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub message: Vec<u8>,
+    #[prost(string, optional, tag = "4")]
+    pub env_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubmitMessageResponse {
+    #[prost(uint64, tag = "1")]
+    pub queue_id: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetMessageStatusRequest {
+    #[prost(uint64, tag = "1")]
+    pub queue_id: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum DeliveryStatus {
+    Scheduled = 0,
+    Completed = 1,
+    TemporaryFailure = 2,
+    PermanentFailure = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RecipientStatus {
+    #[prost(string, tag = "1")]
+    pub address: String,
+    #[prost(enumeration = "DeliveryStatus", tag = "2")]
+    pub status: i32,
+    #[prost(string, tag = "3")]
+    pub response: String,
+    #[prost(uint32, tag = "4")]
+    pub attempts: u32,
+    #[prost(uint64, tag = "5")]
+    pub next_retry: u64,
+    #[prost(uint64, tag = "6")]
+    pub expires: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageStatus {
+    #[prost(uint64, tag = "1")]
+    pub queue_id: u64,
+    #[prost(string, tag = "2")]
+    pub sender: String,
+    #[prost(uint64, tag = "3")]
+    pub created: u64,
+    #[prost(uint64, tag = "4")]
+    pub size: u64,
+    #[prost(message, repeated, tag = "5")]
+    pub recipients: Vec<RecipientStatus>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StreamQueueEventsRequest {
+    #[prost(uint64, repeated, tag = "1")]
+    pub queue_ids: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueueEvent {
+    #[prost(string, tag = "1")]
+    pub r#type: String,
+    #[prost(uint64, tag = "2")]
+    pub timestamp: u64,
+    #[prost(uint64, tag = "3")]
+    pub queue_id: u64,
+    #[prost(map = "string, string", tag = "4")]
+    pub attributes: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Suppression {
+    #[prost(string, tag = "1")]
+    pub address: String,
+    #[prost(string, tag = "2")]
+    pub reason: String,
+    #[prost(uint64, tag = "3")]
+    pub created: u64,
+    #[prost(uint64, optional, tag = "4")]
+    pub expires: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AddSuppressionRequest {
+    #[prost(string, tag = "1")]
+    pub address: String,
+    #[prost(string, tag = "2")]
+    pub reason: String,
+    #[prost(uint64, optional, tag = "3")]
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetSuppressionRequest {
+    #[prost(string, tag = "1")]
+    pub address: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RemoveSuppressionRequest {
+    #[prost(string, tag = "1")]
+    pub address: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RemoveSuppressionResponse {
+    #[prost(bool, tag = "1")]
+    pub removed: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListSuppressionsRequest {
+    #[prost(string, tag = "1")]
+    pub after: String,
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListSuppressionsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub items: Vec<Suppression>,
+}
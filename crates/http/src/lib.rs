@@ -7,6 +7,7 @@
 pub mod auth;
 pub mod autoconfig;
 pub mod form;
+pub mod grpc;
 pub mod management;
 pub mod request;
 
@@ -0,0 +1,295 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    auth::AccessToken,
+    config::network::AuditFormat,
+    manager::audit::{AuditEntry, AuditSnapshot, AuditTarget, audit_diff},
+};
+use directory::{Permission, backend::internal::manage::ManageDirectory};
+use hyper::Method;
+use serde_json::json;
+use store::write::now;
+use utils::url_params::UrlParams;
+
+use http_proto::{request::decode_path_element, *};
+
+use super::{Timestamp, settings::UpdateSettings};
+
+pub struct PendingAudit {
+    target: AuditTarget,
+    before: AuditSnapshot,
+    method: String,
+    path: String,
+    object: Option<String>,
+}
+
+pub trait AuditManagement: Sync + Send {
+    fn handle_manage_audit(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn audit_begin(
+        &self,
+        req: &HttpRequest,
+        path: &[&str],
+        body: Option<&[u8]>,
+    ) -> impl Future<Output = Option<PendingAudit>> + Send;
+
+    fn audit_end(
+        &self,
+        audit: PendingAudit,
+        result: &trc::Result<HttpResponse>,
+        access_token: &AccessToken,
+        session: &HttpSessionData,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl AuditManagement for Server {
+    async fn handle_manage_audit(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::AuditLogList)?;
+
+        let params = UrlParams::new(req.uri().query());
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        let mut entries = self.audit_log().await?;
+        entries
+            .retain(|entry| tenant_id.is_none_or(|tenant_id| entry.tenant_id == Some(tenant_id)));
+
+        match (path.get(1).copied(), req.method()) {
+            (None, &Method::GET) => {
+                let account = params.get("account");
+                let object = params.get("object");
+                let path_prefix = params.get("path");
+                let method = params.get("method");
+                let from = params
+                    .parse::<Timestamp>("from")
+                    .map(|t| t.into_inner())
+                    .unwrap_or_default();
+                let to = params
+                    .parse::<Timestamp>("to")
+                    .map(|t| t.into_inner())
+                    .unwrap_or(u64::MAX);
+                let page: usize = params.parse("page").unwrap_or_default();
+                let limit: usize = params.parse("limit").unwrap_or_default();
+
+                entries.retain(|entry| {
+                    account.is_none_or(|account| entry.account_name == account)
+                        && object.is_none_or(|object| entry.object.as_deref() == Some(object))
+                        && path_prefix.is_none_or(|prefix| entry.path.starts_with(prefix))
+                        && method.is_none_or(|method| entry.method.eq_ignore_ascii_case(method))
+                        && entry.timestamp >= from
+                        && entry.timestamp <= to
+                });
+                let total = entries.len();
+
+                // Most recent changes first
+                entries.sort_unstable_by(|a, b| b.id.cmp(&a.id));
+                let items = entries
+                    .into_iter()
+                    .skip(page.saturating_sub(1) * limit)
+                    .take(if limit > 0 { limit } else { usize::MAX })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": total,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("export"), &Method::GET) => {
+                let format = match params.get("format").unwrap_or("syslog") {
+                    "syslog" => AuditFormat::Syslog,
+                    "cef" => AuditFormat::Cef,
+                    format => {
+                        return Err(trc::ResourceEvent::BadParameters
+                            .into_err()
+                            .details("Invalid export format")
+                            .ctx(trc::Key::Value, format.to_string()));
+                    }
+                };
+                let hostname = &self.core.network.server_name;
+
+                entries.sort_unstable_by_key(|entry| entry.id);
+                let mut export = String::new();
+                for entry in entries {
+                    export.push_str(&match format {
+                        AuditFormat::Syslog => entry.to_syslog(hostname),
+                        AuditFormat::Cef => entry.to_cef(hostname),
+                    });
+                    export.push('\n');
+                }
+
+                Ok(HttpResponse::new(hyper::StatusCode::OK)
+                    .with_content_type("text/plain; charset=utf-8")
+                    .with_text_body(export))
+            }
+            (Some(id), &Method::GET) => {
+                let entry = id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|id| entries.into_iter().find(|entry| entry.id == id))
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": entry,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn audit_begin(
+        &self,
+        req: &HttpRequest,
+        path: &[&str],
+        body: Option<&[u8]>,
+    ) -> Option<PendingAudit> {
+        // Only mutations are audited
+        if self.core.network.audit.is_none()
+            || !matches!(
+                *req.method(),
+                Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+            )
+            || matches!(
+                path.first().copied().unwrap_or_default(),
+                "troubleshoot" | "oauth"
+            )
+        {
+            return None;
+        }
+
+        let object = path
+            .get(1)
+            .filter(|item| !item.is_empty())
+            .map(|item| decode_path_element(item).into_owned());
+        let target = match (path.first().copied(), path.get(1).copied(), req.method()) {
+            (Some("settings"), None, &Method::POST) => {
+                let mut keys = Vec::new();
+                let mut prefixes = Vec::new();
+                for change in
+                    serde_json::from_slice::<Vec<UpdateSettings>>(body.unwrap_or_default())
+                        .unwrap_or_default()
+                {
+                    match change {
+                        UpdateSettings::Delete { keys: deleted } => keys.extend(deleted),
+                        UpdateSettings::Clear { prefix, .. } => prefixes.push(prefix),
+                        UpdateSettings::Insert { prefix, values, .. } => {
+                            keys.extend(values.into_iter().map(|(key, _)| match &prefix {
+                                Some(prefix) => format!("{prefix}.{key}"),
+                                None => key,
+                            }))
+                        }
+                    }
+                }
+                AuditTarget::Settings { keys, prefixes }
+            }
+            (Some("settings"), Some(_), &Method::DELETE) => AuditTarget::Settings {
+                keys: object.iter().cloned().collect(),
+                prefixes: vec![],
+            },
+            (Some("principal"), None | Some("deploy"), &Method::POST) => {
+                match serde_json::from_slice::<serde_json::Value>(body.unwrap_or_default())
+                    .ok()
+                    .as_ref()
+                    .and_then(|principal| principal.get("name"))
+                    .and_then(|name| name.as_str())
+                {
+                    Some(name) => AuditTarget::Principal {
+                        name: name.to_string(),
+                        id: None,
+                    },
+                    None => AuditTarget::Other,
+                }
+            }
+            (Some("principal"), Some(_), &Method::PATCH | &Method::DELETE) => {
+                let name = object.clone().unwrap_or_default();
+                // Resolve the id now so that renamed principals can still be found
+                let id = self
+                    .store()
+                    .get_principal_info(&name)
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|info| info.id);
+                AuditTarget::Principal { name, id }
+            }
+            _ => AuditTarget::Other,
+        };
+        let object = match &target {
+            AuditTarget::Principal { name, .. } => Some(name.clone()),
+            _ => object,
+        };
+
+        let before = match self.audit_snapshot(&target).await {
+            Ok(before) => before,
+            Err(err) => {
+                trc::error!(err.details("Failed to capture audit snapshot"));
+                AuditSnapshot::new()
+            }
+        };
+
+        Some(PendingAudit {
+            target,
+            before,
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+            object,
+        })
+    }
+
+    async fn audit_end(
+        &self,
+        audit: PendingAudit,
+        result: &trc::Result<HttpResponse>,
+        access_token: &AccessToken,
+        session: &HttpSessionData,
+    ) {
+        let changes = if result.is_ok() {
+            match self.audit_snapshot(&audit.target).await {
+                Ok(after) => audit_diff(&audit.before, &after),
+                Err(err) => {
+                    trc::error!(err.details("Failed to capture audit snapshot"));
+                    vec![]
+                }
+            }
+        } else {
+            vec![]
+        };
+
+        self.record_audit(AuditEntry {
+            id: 0,
+            timestamp: now(),
+            account_id: access_token.primary_id,
+            account_name: access_token.name.clone(),
+            tenant_id: access_token.tenant.map(|t| t.id),
+            remote_ip: session.remote_ip.to_string(),
+            method: audit.method,
+            path: audit.path,
+            object: audit.object,
+            result: match result {
+                Ok(_) => "success".to_string(),
+                Err(err) => err.event_type().name().to_string(),
+            },
+            changes,
+        })
+        .await;
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    auth::AccessToken,
+    listener::certificate::{CertificateInfo, CertificateUpload, ManagedCertificate},
+};
+use directory::Permission;
+use hyper::Method;
+use serde::Serialize;
+use serde_json::json;
+
+use http_proto::{request::decode_path_element, *};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CertificateItem<'x> {
+    id: &'x str,
+    names: &'x [String],
+    default: bool,
+    #[serde(flatten)]
+    info: &'x CertificateInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ocsp_url: Option<&'x str>,
+    ocsp_stapled: bool,
+}
+
+pub trait CertificateManagement: Sync + Send {
+    fn handle_manage_certificate(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl CertificateManagement for Server {
+    async fn handle_manage_certificate(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let id = path.get(1).map(|id| decode_path_element(id));
+
+        match (id.as_deref(), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::CertificateList)?;
+
+                let certificates = self.inner.data.tls_managed_certificates.load();
+                let mut items = certificates
+                    .iter()
+                    .map(|(id, cert)| CertificateItem::new(id, cert))
+                    .collect::<Vec<_>>();
+                items.sort_unstable_by(|a, b| a.id.cmp(b.id));
+
+                Ok(JsonResponse::new(json!({
+                    "data": items,
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::CertificateList)?;
+
+                let certificates = self.inner.data.tls_managed_certificates.load();
+                let cert = certificates
+                    .get(id)
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": CertificateItem::new(id, cert),
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::PUT | &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::CertificateUpdate)?;
+
+                let upload = serde_json::from_slice::<CertificateUpload>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+                self.store_certificate(id, upload).await?;
+
+                let certificates = self.inner.data.tls_managed_certificates.load();
+                Ok(JsonResponse::new(json!({
+                    "data": certificates.get(id).map(|cert| CertificateItem::new(id, cert)),
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::CertificateDelete)?;
+
+                if self.delete_certificate(id).await? {
+                    Ok(JsonResponse::new(json!({
+                        "data": (),
+                    }))
+                    .into_http_response())
+                } else {
+                    Err(trc::ResourceEvent::NotFound.into_err())
+                }
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+impl<'x> CertificateItem<'x> {
+    fn new(id: &'x str, cert: &'x ManagedCertificate) -> Self {
+        CertificateItem {
+            id,
+            names: &cert.names,
+            default: cert.default,
+            info: &cert.info,
+            path: cert
+                .files
+                .as_ref()
+                .map(|files| files.cert.display().to_string()),
+            ocsp_url: cert.ocsp_url.as_deref(),
+            ocsp_stapled: cert.key.ocsp.is_some(),
+        }
+    }
+}
@@ -7,16 +7,21 @@
 use std::{future::Future, sync::Arc};
 
 use common::{Server, auth::AccessToken};
-use directory::backend::internal::manage;
+use directory::{
+    Permission, Type,
+    backend::internal::manage::{self, ManageDirectory},
+};
 use email::message::crypto::{
     Algorithm, ArchivedAlgorithm, ArchivedEncryptionMethod, EncryptMessage, EncryptMessageError,
     EncryptionMethod, EncryptionParams, EncryptionType, try_parse_certs,
 };
-use http_proto::*;
+use http_proto::{request::decode_path_element, *};
+use hyper::Method;
 use jmap_proto::types::{collection::Collection, property::Property};
 use mail_builder::encoders::base64::base64_encode_mime;
 use mail_parser::MessageParser;
 use serde_json::json;
+use services::encryption::AccountEncryption;
 use store::{
     Deserialize, Serialize,
     write::{AlignedBytes, Archive, Archiver, BatchBuilder},
@@ -34,46 +39,31 @@ pub trait CryptoHandler: Sync + Send {
         access_token: Arc<AccessToken>,
         body: Option<Vec<u8>>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_crypto_encrypt_start(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_crypto_encrypt_status(
+        &self,
+        access_token: Arc<AccessToken>,
+        job_id: &str,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_manage_encryption_recovery(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 }
 
 impl CryptoHandler for Server {
     async fn handle_crypto_get(&self, access_token: Arc<AccessToken>) -> trc::Result<HttpResponse> {
-        let ec = if let Some(params_) = self
-            .get_archive_by_property(
-                access_token.primary_id(),
-                Collection::Principal,
-                0,
-                Property::Parameters,
-            )
-            .await?
-        {
-            let params = params_
-                .unarchive::<EncryptionParams>()
-                .caused_by(trc::location!())?;
-            let algo = match &params.algo {
-                ArchivedAlgorithm::Aes128 => Algorithm::Aes128,
-                ArchivedAlgorithm::Aes256 => Algorithm::Aes256,
-            };
-            let method = match &params.method {
-                ArchivedEncryptionMethod::PGP => EncryptionMethod::PGP,
-                ArchivedEncryptionMethod::SMIME => EncryptionMethod::SMIME,
-            };
-            let mut certs = Vec::new();
-            certs.extend_from_slice(b"-----STALWART CERTIFICATE-----\r\n");
-            let _ = base64_encode_mime(&params_.into_inner(), &mut certs, false);
-            certs.extend_from_slice(b"\r\n");
-            let certs = String::from_utf8(certs).unwrap_or_default();
-
-            match method {
-                EncryptionMethod::PGP => EncryptionType::PGP { algo, certs },
-                EncryptionMethod::SMIME => EncryptionType::SMIME { algo, certs },
-            }
-        } else {
-            EncryptionType::Disabled
-        };
-
         Ok(JsonResponse::new(json!({
-            "data": ec,
+            "data": self.encryption_type(access_token.primary_id()).await?,
         }))
         .into_http_response())
     }
@@ -86,22 +76,205 @@ impl CryptoHandler for Server {
         let request = serde_json::from_slice::<EncryptionType>(body.as_deref().unwrap_or_default())
             .map_err(|err| trc::ResourceEvent::BadParameters.into_err().reason(err))?;
 
+        // Accounts have to use the same method as the tenant recovery keys
+        if let (Some(tenant), Some(method)) = (access_token.tenant, request.method()) {
+            if let Some(recovery_) = self
+                .get_archive_by_property(tenant.id, Collection::Principal, 0, Property::Parameters)
+                .await?
+            {
+                let recovery = recovery_
+                    .deserialize::<EncryptionParams>()
+                    .caused_by(trc::location!())?;
+                if recovery.method != method {
+                    return Err(manage::error(
+                        format!(
+                            "Encryption method must be {} as required by the tenant recovery keys",
+                            recovery.method
+                        ),
+                        None::<u32>,
+                    ));
+                }
+            }
+        }
+
+        let num_certs = self
+            .set_encryption_params(access_token.primary_id(), request)
+            .await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": num_certs,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_crypto_encrypt_start(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        // Make sure Encryption is enabled
+        if !self.core.jmap.encrypt {
+            return Err(manage::unsupported(
+                "Encryption-at-rest has been disabled by the system administrator",
+            ));
+        }
+
+        Ok(JsonResponse::new(json!({
+            "data": self.start_account_encryption(access_token.primary_id()).await?,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_crypto_encrypt_status(
+        &self,
+        access_token: Arc<AccessToken>,
+        job_id: &str,
+    ) -> trc::Result<HttpResponse> {
+        let job = self
+            .encryption_job(
+                access_token.primary_id(),
+                decode_path_element(job_id).as_ref(),
+            )
+            .await?
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+        Ok(JsonResponse::new(json!({
+            "data": job,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_manage_encryption_recovery(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let tenant = path
+            .get(1)
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let tenant_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(decode_path_element(tenant).as_ref())
+            .await?
+            .filter(|p| {
+                p.typ == Type::Tenant && p.has_tenant_access(access_token.tenant.map(|t| t.id))
+            })
+            .map(|p| p.id)
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+        match req.method() {
+            &Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::EncryptionRecoveryGet)?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.encryption_type(tenant_id).await?,
+                }))
+                .into_http_response())
+            }
+            &Method::POST => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::EncryptionRecoveryUpdate)?;
+
+                let request =
+                    serde_json::from_slice::<EncryptionType>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .from_json_error(err)
+                    })?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.set_encryption_params(tenant_id, request).await?,
+                }))
+                .into_http_response())
+            }
+            &Method::DELETE => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::EncryptionRecoveryUpdate)?;
+
+                self.set_encryption_params(tenant_id, EncryptionType::Disabled)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait EncryptionParamsStore: Sync + Send {
+    fn encryption_type(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<EncryptionType>> + Send;
+
+    fn set_encryption_params(
+        &self,
+        account_id: u32,
+        request: EncryptionType,
+    ) -> impl Future<Output = trc::Result<Option<usize>>> + Send;
+}
+
+impl EncryptionParamsStore for Server {
+    async fn encryption_type(&self, account_id: u32) -> trc::Result<EncryptionType> {
+        let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await?
+        else {
+            return Ok(EncryptionType::Disabled);
+        };
+
+        let params = params_
+            .unarchive::<EncryptionParams>()
+            .caused_by(trc::location!())?;
+        let algo = match &params.algo {
+            ArchivedAlgorithm::Aes128 => Algorithm::Aes128,
+            ArchivedAlgorithm::Aes256 => Algorithm::Aes256,
+        };
+        let method = match &params.method {
+            ArchivedEncryptionMethod::PGP => EncryptionMethod::PGP,
+            ArchivedEncryptionMethod::SMIME => EncryptionMethod::SMIME,
+            ArchivedEncryptionMethod::Age => EncryptionMethod::Age,
+        };
+        let mut certs = Vec::new();
+        certs.extend_from_slice(b"-----STALWART CERTIFICATE-----\r\n");
+        let _ = base64_encode_mime(&params_.into_inner(), &mut certs, false);
+        certs.extend_from_slice(b"\r\n");
+        let certs = String::from_utf8(certs).unwrap_or_default();
+
+        Ok(match method {
+            EncryptionMethod::PGP => EncryptionType::PGP { algo, certs },
+            EncryptionMethod::SMIME => EncryptionType::SMIME { algo, certs },
+            EncryptionMethod::Age => EncryptionType::Age { certs },
+        })
+    }
+
+    async fn set_encryption_params(
+        &self,
+        account_id: u32,
+        request: EncryptionType,
+    ) -> trc::Result<Option<usize>> {
         let (method, algo, mut certs) = match request {
             EncryptionType::PGP { algo, certs } => (EncryptionMethod::PGP, algo, certs),
             EncryptionType::SMIME { algo, certs } => (EncryptionMethod::SMIME, algo, certs),
+            // age always uses ChaCha20-Poly1305, the algorithm is ignored
+            EncryptionType::Age { certs } => (EncryptionMethod::Age, Algorithm::Aes256, certs),
             EncryptionType::Disabled => {
                 // Disable encryption at rest
                 let mut batch = BatchBuilder::new();
                 batch
-                    .with_account_id(access_token.primary_id())
+                    .with_account_id(account_id)
                     .with_collection(Collection::Principal)
                     .update_document(0)
                     .clear(Property::Parameters);
                 self.core.storage.data.write(batch.build_all()).await?;
-                return Ok(JsonResponse::new(json!({
-                    "data": (),
-                }))
-                .into_http_response());
+                return Ok(None);
             }
         };
         if !certs.ends_with("\n") {
@@ -134,6 +307,7 @@ impl CryptoHandler for Server {
             .encrypt(
                 <Archive<AlignedBytes> as Deserialize>::deserialize(params.as_slice())?
                     .unarchive::<EncryptionParams>()?,
+                None,
             )
             .await
         {
@@ -143,15 +317,12 @@ impl CryptoHandler for Server {
         // Save encryption params
         let mut batch = BatchBuilder::new();
         batch
-            .with_account_id(access_token.primary_id())
+            .with_account_id(account_id)
             .with_collection(Collection::Principal)
             .update_document(0)
             .set(Property::Parameters, params);
         self.core.storage.data.write(batch.build_all()).await?;
 
-        Ok(JsonResponse::new(json!({
-            "data": num_certs,
-        }))
-        .into_http_response())
+        Ok(Some(num_certs))
     }
 }
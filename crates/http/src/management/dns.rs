@@ -6,11 +6,12 @@
 
 use common::{Server, auth::AccessToken};
 use directory::{
-    Permission,
-    backend::internal::manage::{self},
+    Permission, Type,
+    backend::internal::manage::{self, ManageDirectory},
 };
 
 use hyper::Method;
+use mail_auth::hickory_resolver::{Name, proto::rr::RecordType};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sha1::Digest;
@@ -29,6 +30,30 @@ pub struct DnsRecord {
     content: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DnsRecordCheck {
+    #[serde(rename = "type")]
+    typ: String,
+    name: String,
+    expected: Vec<String>,
+    found: Vec<String>,
+    status: DnsCheckStatus,
+    dnssec: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DnsCheckStatus {
+    Ok,
+    Missing,
+    Mismatch,
+    Insecure,
+    Error,
+}
+
 pub trait DnsManagement: Sync + Send {
     fn handle_manage_dns(
         &self,
@@ -41,6 +66,11 @@ pub trait DnsManagement: Sync + Send {
         &self,
         domain_name: &str,
     ) -> impl Future<Output = trc::Result<Vec<DnsRecord>>> + Send;
+
+    fn check_dns_records(
+        &self,
+        domain_name: &str,
+    ) -> impl Future<Output = trc::Result<Vec<DnsRecordCheck>>> + Send;
 }
 
 impl DnsManagement for Server {
@@ -66,6 +96,58 @@ impl DnsManagement for Server {
                 }))
                 .into_http_response())
             }
+            ("zone", Some(domain), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DomainGet)?;
+
+                // TLSA records are only published when the zone is signed
+                let domain = decode_path_element(domain);
+                let records = self.build_dns_records(domain.as_ref()).await?;
+                let is_signed = lookup_records(self, domain.as_ref(), RecordType::SOA)
+                    .await
+                    .is_ok_and(|(records, is_secure)| !records.is_empty() && is_secure);
+
+                Ok(HttpResponse::new(hyper::StatusCode::OK)
+                    .with_content_type("text/plain; charset=utf-8")
+                    .with_text_body(build_zone(domain.as_ref(), &records, is_signed)))
+            }
+            ("check", domain, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DomainGet)?;
+
+                // Check a single domain or all domains visible to the tenant
+                let domains = if let Some(domain) = domain {
+                    vec![decode_path_element(domain).into_owned()]
+                } else {
+                    self.core
+                        .storage
+                        .data
+                        .list_principals(
+                            None,
+                            access_token.tenant.map(|t| t.id),
+                            &[Type::Domain],
+                            false,
+                            0,
+                            0,
+                        )
+                        .await?
+                        .items
+                        .into_iter()
+                        .map(|p| p.name)
+                        .collect()
+                };
+
+                let mut results = serde_json::Map::with_capacity(domains.len());
+                for domain in domains {
+                    let checks = self.check_dns_records(&domain).await?;
+                    results.insert(domain, serde_json::to_value(checks).unwrap_or_default());
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": results,
+                }))
+                .into_http_response())
+            }
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
@@ -287,4 +369,260 @@ impl DnsManagement for Server {
 
         Ok(records)
     }
+
+    async fn check_dns_records(&self, domain_name: &str) -> trc::Result<Vec<DnsRecordCheck>> {
+        // Group the expected records by name and type, TLSA records are
+        // alternatives of which at least one has to be published
+        let mut checks: Vec<DnsRecordCheck> = Vec::new();
+        for record in self.build_dns_records(domain_name).await? {
+            if let Some(check) = checks.iter_mut().find(|check| {
+                check.typ == "TLSA" && check.typ == record.typ && check.name == record.name
+            }) {
+                check.expected.push(record.content);
+            } else {
+                checks.push(DnsRecordCheck {
+                    typ: record.typ,
+                    name: record.name,
+                    expected: vec![record.content],
+                    found: vec![],
+                    status: DnsCheckStatus::Missing,
+                    dnssec: false,
+                    error: None,
+                });
+            }
+        }
+
+        for check in &mut checks {
+            let record_type = match check.typ.as_str() {
+                "MX" => RecordType::MX,
+                "CNAME" => RecordType::CNAME,
+                "TXT" => RecordType::TXT,
+                "SRV" => RecordType::SRV,
+                "TLSA" => RecordType::TLSA,
+                _ => continue,
+            };
+            let (found, is_secure) = match lookup_records(self, &check.name, record_type).await {
+                Ok(result) => result,
+                Err(err) => {
+                    check.status = DnsCheckStatus::Error;
+                    check.error = Some(err.to_string());
+                    continue;
+                }
+            };
+            check.dnssec = is_secure;
+
+            // Only compare against TXT records of the same kind
+            check.found = if record_type == RecordType::TXT {
+                let kind = txt_kind(&check.expected[0]);
+                found
+                    .into_iter()
+                    .filter(|value| txt_kind(value) == kind)
+                    .collect()
+            } else {
+                found
+            };
+
+            check.status = if check.found.is_empty() {
+                DnsCheckStatus::Missing
+            } else if !check.expected.iter().any(|expected| {
+                check
+                    .found
+                    .iter()
+                    .any(|found| record_matches(&check.typ, expected, found))
+            }) {
+                DnsCheckStatus::Mismatch
+            } else if check.typ == "TLSA" && !is_secure {
+                // DANE is ignored by senders unless the records are DNSSEC signed
+                DnsCheckStatus::Insecure
+            } else {
+                DnsCheckStatus::Ok
+            };
+        }
+
+        Ok(checks)
+    }
+}
+
+async fn lookup_records(
+    server: &Server,
+    name: &str,
+    record_type: RecordType,
+) -> mail_auth::Result<(Vec<String>, bool)> {
+    let lookup = match server
+        .core
+        .smtp
+        .resolvers
+        .dnssec
+        .resolver
+        .lookup(Name::from_str_relaxed(name)?, record_type)
+        .await
+    {
+        Ok(lookup) => lookup,
+        Err(err) => {
+            return match mail_auth::Error::from(err) {
+                mail_auth::Error::DnsRecordNotFound(_) => Ok((vec![], false)),
+                err => Err(err),
+            };
+        }
+    };
+
+    let mut records = Vec::new();
+    let mut is_secure = true;
+    for record in lookup.record_iter() {
+        if record.record_type() != record_type {
+            continue;
+        }
+        is_secure &= record.proof().is_secure();
+        records.push(if let Some(txt) = record.data().as_txt() {
+            // Multi-string TXT records are concatenated
+            txt.txt_data()
+                .iter()
+                .map(|part| String::from_utf8_lossy(part))
+                .collect::<String>()
+        } else {
+            record.data().to_string()
+        });
+    }
+    let is_secure = is_secure && !records.is_empty();
+
+    Ok((records, is_secure))
+}
+
+fn record_matches(typ: &str, expected: &str, found: &str) -> bool {
+    match typ {
+        "MX" => {
+            // The preference is up to the administrator
+            let expected = normalize_record(expected);
+            let found = normalize_record(found);
+            expected.split_once(' ').map(|(_, host)| host)
+                == found.split_once(' ').map(|(_, host)| host)
+        }
+        "TXT" if txt_kind(expected) == "v=dkim1" => {
+            // Only the public key needs to match
+            dkim_public_key(expected) == dkim_public_key(found)
+        }
+        _ => normalize_record(expected) == normalize_record(found),
+    }
+}
+
+fn normalize_record(value: &str) -> String {
+    value
+        .split_ascii_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_ascii_lowercase()
+}
+
+fn txt_kind(value: &str) -> String {
+    value
+        .split(|ch: char| ch == ';' || ch.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn dkim_public_key(value: &str) -> Option<String> {
+    value.split(';').find_map(|tag| {
+        tag.trim()
+            .strip_prefix("p=")
+            .map(|key| key.split_ascii_whitespace().collect())
+    })
+}
+
+fn build_zone(domain_name: &str, records: &[DnsRecord], is_signed: bool) -> String {
+    let mut zone = format!("; DNS records for {domain_name}\n");
+    let mut has_tlsa = false;
+
+    for record in records {
+        let content = if record.typ == "TXT" {
+            // TXT strings are limited to 255 characters each
+            record
+                .content
+                .as_bytes()
+                .chunks(255)
+                .map(|chunk| {
+                    format!(
+                        "\"{}\"",
+                        String::from_utf8_lossy(chunk)
+                            .replace('\\', "\\\\")
+                            .replace('"', "\\\"")
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            record.content.clone()
+        };
+        let line = format!("{} 3600 IN {} {content}\n", record.name, record.typ);
+
+        if record.typ == "TLSA" && !is_signed {
+            if !has_tlsa {
+                zone.push_str(
+                    "; The zone is not DNSSEC signed, publish the following TLSA records once it is\n",
+                );
+            }
+            zone.push_str("; ");
+        }
+        has_tlsa |= record.typ == "TLSA";
+        zone.push_str(&line);
+    }
+
+    zone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DnsRecord, build_zone, record_matches};
+
+    #[test]
+    fn dns_zone_and_checks() {
+        assert!(record_matches(
+            "MX",
+            "10 mx.example.org.",
+            "20 MX.example.org."
+        ));
+        assert!(!record_matches(
+            "MX",
+            "10 mx.example.org.",
+            "10 mx.example.net."
+        ));
+        assert!(record_matches(
+            "TXT",
+            "v=DKIM1; k=rsa; h=sha256; p=MIIBIjAN",
+            "v=DKIM1; k=rsa; p=MIIBIjAN"
+        ));
+        assert!(!record_matches(
+            "TXT",
+            "v=DMARC1; p=reject",
+            "v=DMARC1; p=none"
+        ));
+        assert!(record_matches("TLSA", "3 1 1 abcdef", "3 1 1 ABCDEF"));
+
+        let records = vec![
+            DnsRecord {
+                typ: "TXT".to_string(),
+                name: "example.org.".to_string(),
+                content: "v=spf1 mx ra=postmaster -all".to_string(),
+            },
+            DnsRecord {
+                typ: "TLSA".to_string(),
+                name: "_25._tcp.mx.example.org.".to_string(),
+                content: "3 1 1 abcdef".to_string(),
+            },
+        ];
+        assert_eq!(
+            build_zone("example.org", &records, false),
+            concat!(
+                "; DNS records for example.org\n",
+                "example.org. 3600 IN TXT \"v=spf1 mx ra=postmaster -all\"\n",
+                "; The zone is not DNSSEC signed, publish the following TLSA records once it is\n",
+                "; _25._tcp.mx.example.org. 3600 IN TLSA 3 1 1 abcdef\n",
+            )
+        );
+        assert!(
+            build_zone("example.org", &records, true)
+                .ends_with("\n_25._tcp.mx.example.org. 3600 IN TLSA 3 1 1 abcdef\n")
+        );
+    }
 }
@@ -8,10 +8,7 @@
  *
  */
 
-use std::{
-    fmt::Write,
-    time::{Duration, Instant},
-};
+use std::{fmt::Write, time::Duration};
 
 use common::{
     Server,
@@ -39,7 +36,7 @@ use trc::{
 };
 use utils::{snowflake::SnowflakeIdGenerator, url_params::UrlParams};
 
-use crate::management::Timestamp;
+use crate::management::{Timestamp, event_stream::trace_event_stream};
 
 pub trait TelemetryApi: Sync + Send {
     fn handle_telemetry_api_request(
@@ -189,117 +186,69 @@ impl TelemetryApi for Server {
                     }
                 }
 
-                let (_, mut rx) = SubscriberBuilder::new("live-tracer".to_string())
+                let (_, rx) = SubscriberBuilder::new("live-tracer".to_string())
                     .with_interests(Box::new(Bitset::all()))
                     .with_lossy(false)
                     .register();
-                let throttle = Duration::from_secs(1);
-                let ping_interval = Duration::from_secs(30);
-                let ping_payload = Bytes::from(format!(
-                    "event: ping\ndata: {{\"interval\": {}}}\n\n",
-                    ping_interval.as_millis()
-                ));
-                let mut last_ping = Instant::now();
-                let mut events = Vec::new();
                 let mut active_span_ids = AHashSet::new();
 
-                Ok(HttpResponse::new(StatusCode::OK)
-                .with_content_type("text/event-stream")
-                .with_cache_control("no-store")
-                .with_stream_body(BoxBody::new(StreamBody::new(
-                    async_stream::stream! {
-                        let mut last_message = Instant::now() - throttle;
-                        let mut timeout = ping_interval;
-
-                        loop {
-                            match tokio::time::timeout(timeout, rx.recv()).await {
-                                Ok(Some(event_batch)) => {
-                                    for event in event_batch {
-                                        if (filter.is_none() && key_filters.is_empty())
-                                            || event
-                                                .span_id()
-                                                .is_some_and(|span_id| active_span_ids.contains(&span_id))
-                                        {
-                                            events.push(event);
-                                        } else {
-                                            let mut matched_keys = AHashSet::new();
-                                            for (key, value) in event
-                                                .keys
-                                                .iter()
-                                                .chain(event.inner.span.as_ref().map_or(([]).iter(), |s| s.keys.iter()))
-                                            {
-                                                if let Some(needle) = key_filters.get(key).or(filter.as_ref()) {
-                                                    let matches = match value {
-                                                        Value::String(haystack) => haystack.contains(needle),
-                                                        Value::Timestamp(haystack) => {
-                                                            DateTime::from_timestamp(*haystack as i64)
-                                                                .to_rfc3339()
-                                                                .contains(needle)
-                                                        }
-                                                        Value::Bool(true) => needle == "true",
-                                                        Value::Bool(false) => needle == "false",
-                                                        Value::Ipv4(haystack) => haystack.to_string().contains(needle),
-                                                        Value::Ipv6(haystack) => haystack.to_string().contains(needle),
-                                                        Value::Event(_) |
-                                                        Value::Array(_) |
-                                                        Value::UInt(_) |
-                                                        Value::Int(_) |
-                                                        Value::Float(_) |
-                                                        Value::Duration(_) |
-                                                        Value::Bytes(_) |
-                                                        Value::None => false,
-                                                    };
+                Ok(trace_event_stream(
+                    rx,
+                    move |event| {
+                        if (filter.is_none() && key_filters.is_empty())
+                            || event
+                                .span_id()
+                                .is_some_and(|span_id| active_span_ids.contains(&span_id))
+                        {
+                            return true;
+                        }
 
-                                                    if matches {
-                                                        matched_keys.insert(*key);
-                                                        if filter.is_some() || matched_keys.len() == key_filters.len() {
-                                                            if let Some(span_id) = event.span_id() {
-                                                                active_span_ids.insert(span_id);
-                                                            }
-                                                            events.push(event);
-                                                            break;
-                                                        }
-                                                    }
-                                                }
-                                            }
+                        let mut matched_keys = AHashSet::new();
+                        for (key, value) in event.keys.iter().chain(
+                            event
+                                .inner
+                                .span
+                                .as_ref()
+                                .map_or(([]).iter(), |s| s.keys.iter()),
+                        ) {
+                            if let Some(needle) = key_filters.get(key).or(filter.as_ref()) {
+                                let matches = match value {
+                                    Value::String(haystack) => haystack.contains(needle),
+                                    Value::Timestamp(haystack) => {
+                                        DateTime::from_timestamp(*haystack as i64)
+                                            .to_rfc3339()
+                                            .contains(needle)
+                                    }
+                                    Value::Bool(true) => needle == "true",
+                                    Value::Bool(false) => needle == "false",
+                                    Value::Ipv4(haystack) => haystack.to_string().contains(needle),
+                                    Value::Ipv6(haystack) => haystack.to_string().contains(needle),
+                                    Value::Event(_)
+                                    | Value::Array(_)
+                                    | Value::UInt(_)
+                                    | Value::Int(_)
+                                    | Value::Float(_)
+                                    | Value::Duration(_)
+                                    | Value::Bytes(_)
+                                    | Value::None => false,
+                                };
+
+                                if matches {
+                                    matched_keys.insert(*key);
+                                    if filter.is_some() || matched_keys.len() == key_filters.len() {
+                                        if let Some(span_id) = event.span_id() {
+                                            active_span_ids.insert(span_id);
                                         }
+                                        return true;
                                     }
                                 }
-                                Ok(None) => {
-                                    break;
-                                }
-                                Err(_) => (),
                             }
-
-                            timeout = if !events.is_empty() {
-                                let elapsed = last_message.elapsed();
-                                if elapsed >= throttle {
-                                    last_message = Instant::now();
-                                    yield Ok(Frame::data(Bytes::from(format!(
-                                        "event: trace\ndata: {}\n\n",
-                                        serde_json::to_string(
-                                            &JsonEventSerializer::new(std::mem::take(&mut events))
-                                            .with_description()
-                                            .with_explanation()).unwrap_or_default()
-                                    ))));
-
-                                    ping_interval
-                                } else {
-                                    throttle - elapsed
-                                }
-                            } else {
-                                let elapsed = last_ping.elapsed();
-                                if elapsed >= ping_interval {
-                                    last_ping = Instant::now();
-                                    yield Ok(Frame::data(ping_payload.clone()));
-                                    ping_interval
-                                } else {
-                                    ping_interval - elapsed
-                                }
-                            };
                         }
+
+                        false
                     },
-                ))))
+                    || async { false },
+                ))
             }
             ("trace", id, &Method::GET) => {
                 // Validate the access token
@@ -0,0 +1,101 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use http_body_util::{StreamBody, combinators::BoxBody};
+use http_proto::*;
+use hyper::{
+    StatusCode,
+    body::{Bytes, Frame},
+};
+use tokio::sync::mpsc;
+use trc::{
+    Event, EventDetails, ipc::subscriber::EventBatch, serializers::json::JsonEventSerializer,
+};
+
+const THROTTLE: Duration = Duration::from_secs(1);
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Streams the events accepted by `filter` as server-sent events, sent at most once
+/// per second and with periodic pings while idle. The stream ends when the subscriber
+/// is closed or, after new events arrive or a ping is due, `is_finished` returns true.
+pub(crate) fn trace_event_stream<F, C, Fut>(
+    mut rx: mpsc::Receiver<EventBatch>,
+    mut filter: F,
+    mut is_finished: C,
+) -> HttpResponse
+where
+    F: FnMut(&Arc<Event<EventDetails>>) -> bool + Send + 'static,
+    C: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = bool> + Send,
+{
+    let ping_payload = Bytes::from(format!(
+        "event: ping\ndata: {{\"interval\": {}}}\n\n",
+        PING_INTERVAL.as_millis()
+    ));
+
+    HttpResponse::new(StatusCode::OK)
+        .with_content_type("text/event-stream")
+        .with_cache_control("no-store")
+        .with_stream_body(BoxBody::new(StreamBody::new(async_stream::stream! {
+            let mut last_message = Instant::now() - THROTTLE;
+            let mut last_ping = Instant::now();
+            let mut events = Vec::new();
+            let mut timeout = PING_INTERVAL;
+
+            loop {
+                let finished = match tokio::time::timeout(timeout, rx.recv()).await {
+                    Ok(Some(event_batch)) => {
+                        let num_events = events.len();
+                        events.extend(event_batch.into_iter().filter(|event| filter(event)));
+                        events.len() > num_events && is_finished().await
+                    }
+                    Ok(None) => {
+                        break;
+                    }
+                    Err(_) => events.is_empty() && is_finished().await,
+                };
+
+                timeout = if !events.is_empty() {
+                    let elapsed = last_message.elapsed();
+                    if elapsed >= THROTTLE || finished {
+                        last_message = Instant::now();
+                        yield Ok(Frame::data(Bytes::from(format!(
+                            "event: trace\ndata: {}\n\n",
+                            serde_json::to_string(
+                                &JsonEventSerializer::new(std::mem::take(&mut events))
+                                .with_description()
+                                .with_explanation()).unwrap_or_default()
+                        ))));
+
+                        PING_INTERVAL
+                    } else {
+                        THROTTLE - elapsed
+                    }
+                } else if !finished {
+                    let elapsed = last_ping.elapsed();
+                    if elapsed >= PING_INTERVAL {
+                        last_ping = Instant::now();
+                        yield Ok(Frame::data(ping_payload.clone()));
+                        PING_INTERVAL
+                    } else {
+                        PING_INTERVAL - elapsed
+                    }
+                } else {
+                    PING_INTERVAL
+                };
+
+                if finished {
+                    break;
+                }
+            }
+        })))
+}
@@ -0,0 +1,75 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc};
+
+use common::{Server, auth::AccessToken};
+use directory::backend::internal::manage;
+use email::sieve::filters::{FilterRules, FilterScript, SieveFilterRules};
+use http_proto::*;
+use serde_json::json;
+
+pub trait FilterRulesHandler: Sync + Send {
+    fn handle_filters_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_filters_put(
+        &self,
+        access_token: Arc<AccessToken>,
+        body: Option<Vec<u8>>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl FilterRulesHandler for Server {
+    async fn handle_filters_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let response = match self.sieve_filters_get(access_token.primary_id()).await? {
+            FilterScript::None => json!({
+                "managed": true,
+                "rules": [],
+            }),
+            FilterScript::Managed(rules) => json!({
+                "managed": true,
+                "rules": rules.rules,
+                "include": rules.include,
+            }),
+            FilterScript::Unmanaged(script) => json!({
+                "managed": false,
+                "script": script,
+            }),
+        };
+
+        Ok(JsonResponse::new(json!({
+            "data": response,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_filters_put(
+        &self,
+        access_token: Arc<AccessToken>,
+        body: Option<Vec<u8>>,
+    ) -> trc::Result<HttpResponse> {
+        let rules = serde_json::from_slice::<FilterRules>(body.as_deref().unwrap_or_default())
+            .map_err(|err| trc::ResourceEvent::BadParameters.into_err().reason(err))?;
+
+        // Validate the rules before touching the stored scripts
+        if let Err(err) = rules.compile() {
+            return Err(manage::error(err, None::<u32>));
+        }
+
+        self.sieve_filters_set(&access_token, rules).await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": (),
+        }))
+        .into_http_response())
+    }
+}
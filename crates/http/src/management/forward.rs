@@ -0,0 +1,110 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::{Permission, backend::internal::manage::ManageDirectory};
+use email::sieve::forward::{ExternalForward, ForwardApprovalStatus};
+use hyper::Method;
+use serde_json::json;
+
+use http_proto::{request::decode_path_element, *};
+
+pub trait ForwardApprovalManagement: Sync + Send {
+    fn handle_manage_forward_approval(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ForwardApprovalManagement for Server {
+    async fn handle_manage_forward_approval(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+
+        match (path.get(1).copied(), path.get(2).copied(), req.method()) {
+            (None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ForwardApprovalList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let pending_only = params.get("status") == Some("pending");
+                let mut items = Vec::new();
+                for (account_id, address, approval) in self.forward_approvals().await? {
+                    if (tenant_id.is_some() && approval.tenant_id != tenant_id)
+                        || (pending_only && approval.status != ForwardApprovalStatus::Pending)
+                    {
+                        continue;
+                    }
+                    let Some(name) = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal_name(account_id)
+                        .await?
+                    else {
+                        continue;
+                    };
+                    items.push(json!({
+                        "account": name,
+                        "address": address,
+                        "status": approval.status,
+                        "requestedAt": approval.requested_at,
+                    }));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(account), Some(address), &Method::POST | &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ForwardApprovalUpdate)?;
+
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(decode_path_element(account).as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(tenant_id))
+                    .map(|p| p.id)
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                let address = decode_path_element(address).to_lowercase();
+                let mut approval = self
+                    .forward_approval(account_id, &address)
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+                if req.method() == Method::POST {
+                    approval.status = ForwardApprovalStatus::Approved;
+                    self.forward_approval_set(account_id, &address, approval)
+                        .await?;
+                } else {
+                    // Rejected addresses can be requested again by the next redirect
+                    self.forward_approval_delete(account_id, &address).await?;
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
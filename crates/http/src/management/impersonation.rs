@@ -0,0 +1,154 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    auth::{AccessToken, impersonate::Impersonator, oauth::GrantType},
+};
+use directory::{
+    Permission,
+    backend::internal::manage::{self, ManageDirectory},
+    core::app_password::AuthProtocol,
+};
+use hyper::Method;
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use http_proto::{request::decode_path_element, *};
+
+pub trait ImpersonationManagement: Sync + Send {
+    fn handle_manage_impersonation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+        session: &HttpSessionData,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ImpersonationManagement for Server {
+    async fn handle_manage_impersonation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+        session: &HttpSessionData,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+
+        match (path.get(1).copied(), req.method()) {
+            (Some("log"), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ImpersonationLogList)?;
+
+                let account = params.get("account");
+                let admin = params.get("admin");
+                let page: usize = params.parse("page").unwrap_or_default();
+                let limit: usize = params.parse("limit").unwrap_or_default();
+                let max_total: usize = params.parse("max-total").unwrap_or_default();
+                let tenant_id = access_token.tenant.map(|t| t.id);
+
+                // Entries are keyed by expiry, so the most recent actions come first
+                let log = self
+                    .impersonation_log(
+                        |entry| {
+                            account.is_none_or(|account| entry.account_name == account)
+                                && admin.is_none_or(|admin| entry.admin_name == admin)
+                                && tenant_id
+                                    .is_none_or(|tenant_id| entry.tenant_id == Some(tenant_id))
+                        },
+                        page,
+                        limit,
+                        max_total,
+                    )
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": log.items,
+                        "total": log.total,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(account), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ImpersonateAccount)?;
+                if access_token.impersonator.is_some() {
+                    return Err(trc::SecurityEvent::Unauthorized
+                        .into_err()
+                        .details("Impersonated sessions cannot impersonate other accounts"));
+                }
+
+                let tenant_id = access_token.tenant.map(|t| t.id);
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(decode_path_element(account).as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(tenant_id))
+                    .map(|p| p.id)
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                if account_id == access_token.primary_id {
+                    return Err(manage::error(
+                        "Cannot impersonate your own account",
+                        None::<u32>,
+                    ));
+                }
+
+                // Administrators may not gain privileges they do not already hold
+                let mut target = self.get_access_token(account_id).await?.as_ref().clone();
+                let mut extra_permissions = target.permissions.clone();
+                extra_permissions.difference(&access_token.permissions);
+                if !extra_permissions.is_empty() {
+                    return Err(trc::SecurityEvent::Unauthorized
+                        .into_err()
+                        .details("Account has permissions not held by the administrator"));
+                }
+
+                let max_duration = self.core.jmap.impersonation.max_duration.as_secs();
+                let expires_in = params
+                    .parse::<u64>("expires-in")
+                    .filter(|expires_in| *expires_in > 0)
+                    .map_or(max_duration, |expires_in| expires_in.min(max_duration));
+                let token = self
+                    .encode_access_token(
+                        GrantType::Impersonation,
+                        account_id,
+                        &access_token.primary_id.to_string(),
+                        expires_in,
+                    )
+                    .await?;
+
+                // Issued tokens are part of the audit trail
+                target.impersonator = Some(Impersonator {
+                    account_id: access_token.primary_id,
+                    name: access_token.name.clone(),
+                });
+                self.record_impersonation(
+                    &target,
+                    AuthProtocol::Http,
+                    session.remote_ip,
+                    "Token issued",
+                    session.session_id,
+                )
+                .await;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "token": token,
+                        "expiresIn": expires_in,
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    auth::AccessToken,
+    listener::iplist::{IpListAction, IpListEntry},
+};
+use directory::{Permission, backend::internal::manage};
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::write::now;
+use utils::config::{ipmask::IpAddrOrMask, utils::ParseValue};
+
+use http_proto::{request::decode_path_element, *};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpListItem {
+    ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u64>,
+    #[serde(flatten)]
+    entry: IpListEntry,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IpListSummary<'x> {
+    id: &'x str,
+    action: IpListAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'x str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed: Option<&'x str>,
+    entries: usize,
+    feed_entries: usize,
+}
+
+pub trait IpListManagement: Sync + Send {
+    fn handle_manage_ip_list(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl IpListManagement for Server {
+    async fn handle_manage_ip_list(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let list_id = path.get(1).map(|id| decode_path_element(id));
+        let list = if let Some(list_id) = &list_id {
+            Some(
+                self.core
+                    .network
+                    .ip_lists
+                    .get(list_id.as_ref())
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?,
+            )
+        } else {
+            None
+        };
+
+        match (list, req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let entries = self.inner.data.ip_lists.read();
+                let feeds = self.inner.data.ip_feeds.read();
+                let mut lists = self
+                    .core
+                    .network
+                    .ip_lists
+                    .values()
+                    .map(|list| IpListSummary {
+                        id: &list.id,
+                        action: list.action,
+                        description: list.description.as_deref(),
+                        feed: list.feed.as_ref().map(|feed| feed.url.as_str()),
+                        entries: entries.get(&list.id).map_or(0, |set| set.len()),
+                        feed_entries: feeds.get(&list.id).map_or(0, |set| set.len()),
+                    })
+                    .collect::<Vec<_>>();
+                lists.sort_unstable_by(|a, b| a.id.cmp(b.id));
+
+                Ok(JsonResponse::new(json!({
+                    "data": lists,
+                }))
+                .into_http_response())
+            }
+            (Some(list), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let now = now();
+                let mut items = Vec::new();
+                if let Some(set) = self.inner.data.ip_lists.read().get(&list.id) {
+                    for (ip, entry) in &set.addresses {
+                        if !entry.is_expired(now) {
+                            items.push(IpListItem {
+                                ip: ip.to_string(),
+                                ttl: None,
+                                entry: entry.clone(),
+                            });
+                        }
+                    }
+                    for (_, ip, entry) in &set.networks {
+                        if !entry.is_expired(now) {
+                            items.push(IpListItem {
+                                ip: ip.clone(),
+                                ttl: None,
+                                entry: entry.clone(),
+                            });
+                        }
+                    }
+                }
+                items.sort_unstable_by(|a, b| a.ip.cmp(&b.ip));
+                let feed_entries = self
+                    .inner
+                    .data
+                    .ip_feeds
+                    .read()
+                    .get(&list.id)
+                    .map_or(0, |set| set.len());
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "feedEntries": feed_entries,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(list), method @ (&Method::POST | &Method::PUT)) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsUpdate)?;
+
+                let items =
+                    serde_json::from_slice::<Vec<IpListItem>>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+
+                let now = now();
+                let mut entries = Vec::with_capacity(items.len());
+                for item in items {
+                    let ip = item.ip.trim();
+                    if let Err(err) = IpAddrOrMask::parse_value(ip) {
+                        return Err(manage::error(err, None::<u32>));
+                    }
+                    let mut entry = item.entry;
+                    if let Some(ttl) = item.ttl {
+                        entry.expires = Some(now + ttl);
+                    }
+                    entry.created = now;
+                    entry.created_by = Some(access_token.name.clone());
+                    entries.push((ip.to_string(), entry));
+                }
+
+                // PUT replaces the whole list, POST only adds or updates entries
+                self.ip_list_update(&list.id, entries, method == Method::PUT)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(list), &Method::DELETE) if path.len() > 2 => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsDelete)?;
+
+                // Networks may be sent either encoded or as separate path elements
+                let ip = decode_path_element(&path[2..].join("/")).into_owned();
+                self.ip_list_remove(&list.id, ip.trim()).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
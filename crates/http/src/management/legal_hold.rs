@@ -0,0 +1,103 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::{Permission, backend::internal::manage::ManageDirectory};
+use email::message::hold::LegalHold;
+use hyper::Method;
+use serde::Deserialize;
+use serde_json::json;
+
+use http_proto::{request::decode_path_element, *};
+
+#[derive(Debug, Deserialize)]
+struct LegalHoldRequest {
+    reason: String,
+}
+
+pub trait LegalHoldManagement: Sync + Send {
+    fn handle_manage_legal_hold(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl LegalHoldManagement for Server {
+    async fn handle_manage_legal_hold(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let account = path
+            .get(1)
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        let account_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(decode_path_element(account).as_ref())
+            .await?
+            .filter(|p| p.has_tenant_access(tenant_id))
+            .map(|p| p.id)
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+        match req.method() {
+            &Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::LegalHoldGet)?;
+
+                let reason = self.legal_hold(account_id).await?;
+                let held = self.emails_held(account_id).await?.len();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "hold": reason.is_some(),
+                        "reason": reason,
+                        "heldMessages": held,
+                    },
+                }))
+                .into_http_response())
+            }
+            &Method::POST => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::LegalHoldUpdate)?;
+
+                let request =
+                    serde_json::from_slice::<LegalHoldRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+                self.legal_hold_place(account_id, tenant_id, request.reason)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            &Method::DELETE => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::LegalHoldUpdate)?;
+
+                // Released messages are removed by the next account purge
+                Ok(JsonResponse::new(json!({
+                    "data": self.legal_hold_release(account_id, tenant_id).await?,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
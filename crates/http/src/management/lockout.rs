@@ -0,0 +1,76 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, net::IpAddr};
+
+use common::{Server, auth::AccessToken, listener::lockout::LockoutTarget};
+use directory::{Permission, backend::internal::manage};
+use hyper::Method;
+use serde_json::json;
+
+use http_proto::{request::decode_path_element, *};
+
+pub trait LockoutManagement: Sync + Send {
+    fn handle_manage_lockout(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl LockoutManagement for Server {
+    async fn handle_manage_lockout(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let value = path
+            .get(2)
+            .map(|value| decode_path_element(value))
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let target = match path.get(1).copied() {
+            Some("account") => LockoutTarget::Account(value.as_ref()),
+            Some("ip") => LockoutTarget::Ip(
+                value
+                    .parse::<IpAddr>()
+                    .map_err(|_| manage::error("Invalid IP address", Some(value.to_string())))?,
+            ),
+            _ => return Err(trc::ResourceEvent::NotFound.into_err()),
+        };
+
+        match req.method() {
+            &Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let status = self
+                    .auth_lockout_status(target)
+                    .await?
+                    .ok_or_else(|| manage::unsupported("Authentication lockouts are disabled"))?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": status,
+                }))
+                .into_http_response())
+            }
+            &Method::DELETE => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsDelete)?;
+
+                self.auth_lockout_clear(target).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
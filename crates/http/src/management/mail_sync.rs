@@ -0,0 +1,159 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::{Permission, backend::internal::manage::ManageDirectory};
+use hyper::Method;
+use serde_json::json;
+use services::mail_sync::{MailSync, MailSyncJob};
+
+use http_proto::{request::decode_path_element, *};
+
+pub trait MailSyncManagement: Sync + Send {
+    fn handle_manage_mail_sync(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl MailSyncManagement for Server {
+    async fn handle_manage_mail_sync(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), path.get(2).copied(), req.method()) {
+            (None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailSyncList)?;
+
+                let tenant_id = access_token.tenant.map(|t| t.id);
+                let mut items = Vec::new();
+                for (account_id, mut job) in self.mail_sync_jobs().await? {
+                    let Some(name) = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal_name(account_id)
+                        .await?
+                    else {
+                        continue;
+                    };
+                    if tenant_id.is_some()
+                        && !self
+                            .core
+                            .storage
+                            .data
+                            .get_principal_info(&name)
+                            .await?
+                            .is_some_and(|p| p.has_tenant_access(tenant_id))
+                    {
+                        continue;
+                    }
+                    job.secret = String::new();
+                    items.push(json!({
+                        "account": name,
+                        "job": job,
+                        "state": self.mail_sync_state(account_id).await?,
+                    }));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(account), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailSyncGet)?;
+
+                let account_id = resolve_account(self, account, access_token).await?;
+                let mut job = self
+                    .mail_sync_job(account_id)
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                job.secret = String::new();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "job": job,
+                        "state": self.mail_sync_state(account_id).await?,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(account), None, &Method::POST | &Method::PUT) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailSyncUpdate)?;
+
+                let account_id = resolve_account(self, account, access_token).await?;
+                let job =
+                    serde_json::from_slice::<MailSyncJob>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+                self.mail_sync_update(account_id, job).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(account), Some("start"), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailSyncUpdate)?;
+
+                let account_id = resolve_account(self, account, access_token).await?;
+
+                // Returns false when a sync is already running for the account
+                Ok(JsonResponse::new(json!({
+                    "data": self.mail_sync_start(account_id).await?,
+                }))
+                .into_http_response())
+            }
+            (Some(account), None, &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailSyncDelete)?;
+
+                let account_id = resolve_account(self, account, access_token).await?;
+                self.mail_sync_delete(account_id).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+async fn resolve_account(
+    server: &Server,
+    account: &str,
+    access_token: &AccessToken,
+) -> trc::Result<u32> {
+    server
+        .core
+        .storage
+        .data
+        .get_principal_info(decode_path_element(account).as_ref())
+        .await?
+        .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+        .map(|p| p.id)
+        .ok_or_else(|| trc::ManageEvent::NotFound.into_err())
+}
@@ -4,23 +4,41 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod audit;
+pub mod certificate;
 pub mod crypto;
 pub mod dkim;
 pub mod dns;
 #[cfg(feature = "enterprise")]
 pub mod enterprise;
+pub mod event_stream;
+pub mod filters;
+pub mod forward;
+pub mod impersonation;
+pub mod iplist;
+pub mod legal_hold;
+pub mod lockout;
 pub mod log;
+pub mod mail_sync;
+pub mod portability;
 pub mod principal;
+pub mod push;
+pub mod quarantine;
 pub mod queue;
 pub mod reload;
 pub mod report;
+pub mod retention;
+pub mod routing;
 pub mod settings;
+pub mod sieve;
 pub mod spam;
 pub mod stores;
 pub mod troubleshoot;
 
 use std::{str::FromStr, sync::Arc};
 
+use audit::AuditManagement;
+use certificate::CertificateManagement;
 use common::{Server, auth::AccessToken};
 use crypto::CryptoHandler;
 use directory::{Permission, backend::internal::manage};
@@ -28,17 +46,30 @@ use dkim::DkimManagement;
 use dns::DnsManagement;
 #[cfg(feature = "enterprise")]
 use enterprise::telemetry::TelemetryApi;
+use filters::FilterRulesHandler;
+use forward::ForwardApprovalManagement;
 use hyper::{Method, StatusCode, header};
+use impersonation::ImpersonationManagement;
+use iplist::IpListManagement;
+use legal_hold::LegalHoldManagement;
+use lockout::LockoutManagement;
 use jmap::api::{ToJmapHttpResponse, ToRequestError};
 use jmap_proto::error::request::RequestError;
 use log::LogManagement;
+use mail_sync::MailSyncManagement;
 use mail_parser::DateTime;
+use portability::PortabilityManagement;
 use principal::PrincipalManager;
+use push::PushDeviceHandler;
+use quarantine::QuarantineManagement;
 use queue::QueueManagement;
 use reload::ManageReload;
 use report::ManageReports;
+use retention::RetentionManagement;
+use routing::RoutingManagement;
 use serde::Serialize;
 use settings::ManageSettings;
+use sieve::SieveManagement;
 use spam::ManageSpamHandler;
 use store::write::now;
 use stores::ManageStore;
@@ -90,11 +121,24 @@ impl ManagementApi for Server {
         access_token: Arc<AccessToken>,
         session: &HttpSessionData,
     ) -> trc::Result<HttpResponse> {
-        let body = fetch_body(req, 1024 * 1024, session.session_id).await;
+        // Account archives are larger than regular management requests
+        let max_size = if req.uri().path().starts_with("/api/portability/import/") {
+            self.core.jmap.upload_max_size
+        } else {
+            1024 * 1024
+        };
+        let body = fetch_body(req, max_size, session.session_id).await;
         let path = req.uri().path().split('/').skip(2).collect::<Vec<_>>();
 
-        match path.first().copied().unwrap_or_default() {
+        // Capture the state of the objects about to be modified
+        let audit = self.audit_begin(req, &path, body.as_deref()).await;
+
+        let result = match path.first().copied().unwrap_or_default() {
             "queue" => self.handle_manage_queue(req, path, &access_token).await,
+            "quarantine" => {
+                self.handle_manage_quarantine(req, path, body, &access_token)
+                    .await
+            }
             "settings" => {
                 self.handle_manage_settings(req, path, body, &access_token)
                     .await
@@ -114,6 +158,49 @@ impl ManagementApi for Server {
                 self.handle_manage_dkim(req, path, body, &access_token)
                     .await
             }
+            "certificate" => {
+                self.handle_manage_certificate(req, path, body, &access_token)
+                    .await
+            }
+            "sieve" => {
+                self.handle_manage_sieve(req, path, body, &access_token)
+                    .await
+            }
+            "routing" => self.handle_manage_routing(req, body, &access_token).await,
+            "portability" => {
+                self.handle_manage_portability(req, path, body, &access_token)
+                    .await
+            }
+            "mail-sync" => {
+                self.handle_manage_mail_sync(req, path, body, &access_token)
+                    .await
+            }
+            "retention" => {
+                self.handle_manage_retention(req, path, &access_token)
+                    .await
+            }
+            "legal-hold" => {
+                self.handle_manage_legal_hold(req, path, body, &access_token)
+                    .await
+            }
+            "forward-approval" => {
+                self.handle_manage_forward_approval(req, path, &access_token)
+                    .await
+            }
+            "ip-list" => {
+                self.handle_manage_ip_list(req, path, body, &access_token)
+                    .await
+            }
+            "lockout" => self.handle_manage_lockout(req, path, &access_token).await,
+            "encryption-recovery" => {
+                self.handle_manage_encryption_recovery(req, path, body, &access_token)
+                    .await
+            }
+            "audit" => self.handle_manage_audit(req, path, &access_token).await,
+            "impersonation" => {
+                self.handle_manage_impersonation(req, path, &access_token, session)
+                    .await
+            }
             "update" => self.handle_manage_update(req, path, &access_token).await,
             "logs" if req.method() == Method::GET => {
                 self.handle_view_logs(req, &access_token).await
@@ -139,13 +226,36 @@ impl ManagementApi for Server {
                     // Validate the access token
                     access_token.assert_has_permission(Permission::ManageEncryption)?;
 
-                    self.handle_crypto_post(access_token, body).await
+                    match path.get(2).copied() {
+                        None => self.handle_crypto_post(access_token, body).await,
+                        Some("encrypt") => self.handle_crypto_encrypt_start(access_token).await,
+                        _ => Err(trc::ResourceEvent::NotFound.into_err()),
+                    }
                 }
                 ("crypto", &Method::GET) => {
                     // Validate the access token
                     access_token.assert_has_permission(Permission::ManageEncryption)?;
 
-                    self.handle_crypto_get(access_token).await
+                    match (path.get(2).copied(), path.get(3).copied()) {
+                        (None, _) => self.handle_crypto_get(access_token).await,
+                        (Some("encrypt"), Some(job_id)) => {
+                            self.handle_crypto_encrypt_status(access_token, job_id)
+                                .await
+                        }
+                        _ => Err(trc::ResourceEvent::NotFound.into_err()),
+                    }
+                }
+                ("filters", &Method::GET) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::JmapSieveScriptGet)?;
+
+                    self.handle_filters_get(access_token).await
+                }
+                ("filters", &Method::PUT) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::JmapSieveScriptSet)?;
+
+                    self.handle_filters_put(access_token, body).await
                 }
                 ("auth", &Method::GET) => {
                     // Validate the access token
@@ -159,6 +269,33 @@ impl ManagementApi for Server {
 
                     self.handle_account_auth_post(req, access_token, body).await
                 }
+                ("webauthn", &Method::GET) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManagePasswords)?;
+
+                    self.handle_account_webauthn_get(access_token).await
+                }
+                ("push", &Method::GET) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::JmapPushSubscriptionGet)?;
+
+                    self.handle_push_devices_get(access_token).await
+                }
+                ("push", &Method::POST) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::JmapPushSubscriptionSet)?;
+
+                    self.handle_push_device_post(access_token, body).await
+                }
+                ("push", &Method::DELETE) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::JmapPushSubscriptionSet)?;
+
+                    match path.get(2).copied() {
+                        Some(token) => self.handle_push_device_delete(access_token, token).await,
+                        None => Err(trc::ResourceEvent::NotFound.into_err()),
+                    }
+                }
                 _ => Err(trc::ResourceEvent::NotFound.into_err()),
             },
             "troubleshoot" => {
@@ -191,7 +328,13 @@ impl ManagementApi for Server {
             }
             // SPDX-SnippetEnd
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        };
+
+        if let Some(audit) = audit {
+            self.audit_end(audit, &result, &access_token, session).await;
         }
+
+        result
     }
 }
 
@@ -0,0 +1,208 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::{Permission, backend::internal::manage::ManageDirectory};
+use http_body_util::{StreamBody, combinators::BoxBody};
+use hyper::{
+    Method, StatusCode,
+    body::{Bytes, Frame},
+};
+use serde_json::json;
+use services::portability::{AccountPortability, JobKind, JobStatus, MailFormat};
+use utils::url_params::UrlParams;
+
+use http_proto::{request::decode_path_element, *};
+
+pub trait PortabilityManagement: Sync + Send {
+    fn handle_manage_portability(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl PortabilityManagement for Server {
+    async fn handle_manage_portability(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (
+            path.get(1).copied(),
+            path.get(2).copied(),
+            path.get(3).copied(),
+            req.method(),
+        ) {
+            (Some("export"), Some(account), None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::AccountExport)?;
+
+                let account_id = resolve_account(self, account, access_token).await?;
+                let format = match UrlParams::new(req.uri().query())
+                    .get("format")
+                    .unwrap_or("mbox")
+                {
+                    "mbox" => MailFormat::Mbox,
+                    "eml" => MailFormat::Eml,
+                    format => {
+                        return Err(trc::ResourceEvent::BadParameters
+                            .into_err()
+                            .details(format!("Unsupported mail format {format:?}")));
+                    }
+                };
+                let job_id = self.start_account_export(account_id, format).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": job_id,
+                }))
+                .into_http_response())
+            }
+            (Some("import"), Some(account), None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::AccountImport)?;
+
+                let account_id = resolve_account(self, account, access_token).await?;
+                let archive = body.filter(|body| !body.is_empty()).ok_or_else(|| {
+                    trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details("Missing or too large account archive")
+                })?;
+                let job_id = self.start_account_import(account_id, archive).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": job_id,
+                }))
+                .into_http_response())
+            }
+            (Some("jobs"), Some(job_id), download, &Method::GET) => {
+                let job = self
+                    .portability_job(decode_path_element(job_id).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+                // Validate the access token
+                access_token.assert_has_permission(match job.kind {
+                    JobKind::Export => Permission::AccountExport,
+                    JobKind::Import => Permission::AccountImport,
+                })?;
+                assert_account_access(self, job.account_id, access_token).await?;
+
+                match download {
+                    None => Ok(JsonResponse::new(json!({
+                        "data": job,
+                    }))
+                    .into_http_response()),
+                    Some("download") => {
+                        let archive = job
+                            .archive
+                            .filter(|_| job.status == JobStatus::Completed)
+                            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                        let blob_store = self.blob_store().clone();
+                        let account_id = job.account_id;
+
+                        // Stream the archive parts as they are read from the blob store
+                        Ok(HttpResponse::new(StatusCode::OK)
+                            .with_content_type("application/zip")
+                            .with_content_disposition(format!(
+                                "attachment; filename=\"account-{account_id}.zip\""
+                            ))
+                            .with_cache_control("private, no-store")
+                            .with_content_length(archive.size as usize)
+                            .with_stream_body(BoxBody::new(StreamBody::new(
+                                async_stream::stream! {
+                                    for hash in archive.parts {
+                                        match blob_store
+                                            .get_blob(hash.as_slice(), 0..usize::MAX)
+                                            .await
+                                        {
+                                            Ok(Some(part)) => {
+                                                yield Ok(Frame::data(Bytes::from(part)));
+                                            }
+                                            Ok(None) => {
+                                                trc::event!(
+                                                    Store(trc::StoreEvent::NotFound),
+                                                    AccountId = account_id,
+                                                    Details = "Account archive part not found",
+                                                    CausedBy = trc::location!(),
+                                                );
+                                                break;
+                                            }
+                                            Err(err) => {
+                                                trc::error!(
+                                                    err.account_id(account_id)
+                                                        .details("Failed to read account archive")
+                                                );
+                                                break;
+                                            }
+                                        }
+                                    }
+                                },
+                            ))))
+                    }
+                    _ => Err(trc::ResourceEvent::NotFound.into_err()),
+                }
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+// Jobs are only visible to administrators with access to the job's account
+async fn assert_account_access(
+    server: &Server,
+    account_id: u32,
+    access_token: &AccessToken,
+) -> trc::Result<()> {
+    let tenant_id = access_token.tenant.map(|t| t.id);
+    if tenant_id.is_none() {
+        return Ok(());
+    }
+
+    let Some(name) = server
+        .core
+        .storage
+        .data
+        .get_principal_name(account_id)
+        .await?
+    else {
+        return Err(trc::ManageEvent::NotFound.into_err());
+    };
+    if server
+        .core
+        .storage
+        .data
+        .get_principal_info(&name)
+        .await?
+        .is_some_and(|p| p.has_tenant_access(tenant_id))
+    {
+        Ok(())
+    } else {
+        Err(trc::ManageEvent::NotFound.into_err())
+    }
+}
+
+async fn resolve_account(
+    server: &Server,
+    account: &str,
+    access_token: &AccessToken,
+) -> trc::Result<u32> {
+    server
+        .core
+        .storage
+        .data
+        .get_principal_info(decode_path_element(account).as_ref())
+        .await?
+        .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+        .map(|p| p.id)
+        .ok_or_else(|| trc::ManageEvent::NotFound.into_err())
+}
@@ -4,7 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::{KV_BAYES_MODEL_USER, Server, auth::AccessToken};
+use common::{
+    KV_BAYES_MODEL_USER, Server,
+    auth::{AccessToken, webauthn::WebAuthnCredential},
+};
 use directory::{
     DirectoryInner, Permission, QueryBy, Type,
     backend::internal::{
@@ -15,6 +18,7 @@ use directory::{
             self, ChangedPrincipals, ManageDirectory, PrincipalList, UpdatePrincipal, not_found,
         },
     },
+    core::app_password::{AppPassword, AppPasswordScope},
 };
 use http_proto::{request::decode_path_element, *};
 use hyper::{Method, header};
@@ -28,11 +32,37 @@ use utils::url_params::UrlParams;
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 pub enum AccountAuthRequest {
-    SetPassword { password: String },
-    EnableOtpAuth { url: String },
-    DisableOtpAuth { url: Option<String> },
-    AddAppPassword { name: String, password: String },
-    RemoveAppPassword { name: Option<String> },
+    SetPassword {
+        password: String,
+    },
+    EnableOtpAuth {
+        url: String,
+    },
+    DisableOtpAuth {
+        url: Option<String>,
+    },
+    AddAppPassword {
+        name: String,
+        password: Option<String>,
+        #[serde(default)]
+        protocols: Vec<String>,
+        #[serde(default)]
+        ips: Vec<String>,
+    },
+    RemoveAppPassword {
+        name: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    AddWebauthnCredential {
+        name: String,
+        credential_id: String,
+        algorithm: i64,
+        public_key: String,
+        client_data: String,
+    },
+    RemoveWebauthnCredential {
+        name: Option<String>,
+    },
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -41,6 +71,8 @@ pub struct AccountAuthResponse {
     pub otp_auth: bool,
     #[serde(rename = "appPasswords")]
     pub app_passwords: Vec<String>,
+    #[serde(rename = "webauthnCredentials")]
+    pub webauthn_credentials: Vec<String>,
 }
 
 pub trait PrincipalManager: Sync + Send {
@@ -64,6 +96,11 @@ pub trait PrincipalManager: Sync + Send {
         body: Option<Vec<u8>>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn handle_account_webauthn_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
     fn assert_supported_directory(&self, override_: bool) -> trc::Result<()>;
 }
 
@@ -575,7 +612,9 @@ impl PrincipalManager for Server {
                         for change in &changes {
                             match change.field {
                                 PrincipalField::Secrets => {
-                                    self.assert_supported_directory(false)?;
+                                    if !self.directory().can_write_through(change.field) {
+                                        self.assert_supported_directory(false)?;
+                                    }
                                 }
                                 PrincipalField::Name
                                 | PrincipalField::Emails
@@ -589,7 +628,20 @@ impl PrincipalManager for Server {
                                 | PrincipalField::Lists
                                 | PrincipalField::Urls
                                 | PrincipalField::ExternalMembers
-                                | PrincipalField::Locale => (),
+                                | PrincipalField::Locale
+                                | PrincipalField::SpamFilter
+                                | PrincipalField::FreeBusyPolicy
+                                | PrincipalField::BookingPolicy
+                                | PrincipalField::BookingHorizon
+                                | PrincipalField::BookingDelegate => (),
+                                PrincipalField::LegalHold => {
+                                    access_token
+                                        .assert_has_permission(Permission::LegalHoldUpdate)?;
+                                }
+                                PrincipalField::ForwardPolicy => {
+                                    access_token
+                                        .assert_has_permission(Permission::ForwardApprovalUpdate)?;
+                                }
                                 PrincipalField::Tenant => {
                                     // Tenants are not allowed to change their tenantId
                                     if access_token.tenant.is_some() {
@@ -657,6 +709,19 @@ impl PrincipalManager for Server {
                             }
                         }
 
+                        // Write supported changes back to the external directory first
+                        if changes
+                            .iter()
+                            .any(|change| self.directory().can_write_through(change.field))
+                        {
+                            let name = self
+                                .store()
+                                .get_principal_name(account_id)
+                                .await?
+                                .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                            self.directory().write_through(&name, &changes).await?;
+                        }
+
                         // Update principal
                         let changed_principals = self
                             .core
@@ -693,6 +758,7 @@ impl PrincipalManager for Server {
         let mut response = AccountAuthResponse {
             otp_auth: false,
             app_passwords: Vec::new(),
+            webauthn_credentials: Vec::new(),
         };
 
         if access_token.primary_id() != u32::MAX {
@@ -705,10 +771,10 @@ impl PrincipalManager for Server {
             for secret in &principal.secrets {
                 if secret.is_otp_auth() {
                     response.otp_auth = true;
-                } else if let Some((app_name, _)) =
-                    secret.strip_prefix("$app$").and_then(|s| s.split_once('$'))
-                {
-                    response.app_passwords.push(app_name.into());
+                } else if let Some(app) = AppPassword::parse(secret) {
+                    response.app_passwords.push(app.name.into());
+                } else if let Some(credential) = WebAuthnCredential::parse(secret) {
+                    response.webauthn_credentials.push(credential.name.into());
                 }
             }
         }
@@ -745,6 +811,8 @@ impl PrincipalManager for Server {
                 AccountAuthRequest::DisableOtpAuth { .. }
                     | AccountAuthRequest::EnableOtpAuth { .. }
                     | AccountAuthRequest::SetPassword { .. }
+                    | AccountAuthRequest::AddWebauthnCredential { .. }
+                    | AccountAuthRequest::RemoveWebauthnCredential { .. }
             )
         }) && req
             .headers()
@@ -793,11 +861,19 @@ impl PrincipalManager for Server {
             }
         }
 
-        // Make sure the current directory supports updates
-        self.assert_supported_directory(false)?;
+        // Make sure the current directory supports updates, password changes
+        // can be written back to external directories that allow it
+        let write_through = self.directory().can_write_through(PrincipalField::Secrets)
+            && requests
+                .iter()
+                .all(|request| matches!(request, AccountAuthRequest::SetPassword { .. }));
+        if !write_through {
+            self.assert_supported_directory(false)?;
+        }
 
         // Build actions
         let mut actions = Vec::with_capacity(requests.len());
+        let mut generated_password = None;
         for request in requests {
             let (action, secret) = match request {
                 AccountAuthRequest::SetPassword { password } => {
@@ -814,13 +890,65 @@ impl PrincipalManager for Server {
                     PrincipalAction::RemoveItem,
                     url.unwrap_or_else(|| "otpauth://".into()),
                 ),
-                AccountAuthRequest::AddAppPassword { name, password } => {
-                    (PrincipalAction::AddItem, format!("$app${name}${password}"))
+                AccountAuthRequest::AddAppPassword {
+                    name,
+                    password,
+                    protocols,
+                    ips,
+                } => {
+                    if !AppPassword::is_valid_name(&name) {
+                        return Err(manage::error("Invalid app password name", None::<u32>));
+                    }
+                    let scope = AppPasswordScope::parse(&format!(
+                        "protocols={}&ips={}",
+                        protocols.join(","),
+                        ips.join(",")
+                    ))
+                    .map_err(|err| manage::error(err, None::<u32>))?;
+
+                    // Generate a random password when none is provided
+                    let password = if let Some(password) = password {
+                        password
+                    } else {
+                        let (password, hash) = AppPassword::generate();
+                        generated_password = Some(password);
+                        hash
+                    };
+
+                    (
+                        PrincipalAction::AddItem,
+                        AppPassword::build(&name, &scope, &password),
+                    )
                 }
                 AccountAuthRequest::RemoveAppPassword { name } => (
                     PrincipalAction::RemoveItem,
                     format!("$app${}", name.unwrap_or_default()),
                 ),
+                AccountAuthRequest::AddWebauthnCredential {
+                    name,
+                    credential_id,
+                    algorithm,
+                    public_key,
+                    client_data,
+                } => (
+                    PrincipalAction::AddItem,
+                    self.webauthn_register(
+                        access_token.primary_id(),
+                        &name,
+                        &credential_id,
+                        algorithm,
+                        &public_key,
+                        &client_data,
+                    )
+                    .await?,
+                ),
+                AccountAuthRequest::RemoveWebauthnCredential { name } => (
+                    PrincipalAction::RemoveItem,
+                    match name {
+                        Some(name) => format!("$webauthn${name}$"),
+                        None => "$webauthn$".to_string(),
+                    },
+                ),
             };
 
             actions.push(PrincipalUpdate {
@@ -830,6 +958,15 @@ impl PrincipalManager for Server {
             });
         }
 
+        if write_through {
+            let name = self
+                .store()
+                .get_principal_name(access_token.primary_id())
+                .await?
+                .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+            self.directory().write_through(&name, &actions).await?;
+        }
+
         // Update password
         let changed_principals = self
             .core
@@ -846,8 +983,24 @@ impl PrincipalManager for Server {
         self.increment_token_revision(changed_principals).await;
 
         Ok(JsonResponse::new(json!({
-            "data": (),
+            "data": generated_password,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_account_webauthn_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let challenge = self.webauthn_challenge(access_token.primary_id()).await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": {
+                "challenge": challenge,
+                "rpId": self.core.jmap.two_factor.webauthn_rp_id,
+            },
         }))
+        .no_cache()
         .into_http_response())
     }
 
@@ -860,6 +1013,7 @@ impl PrincipalManager for Server {
             DirectoryInner::Smtp(_) => "SMTP",
             DirectoryInner::Memory(_) => "In-Memory",
             DirectoryInner::OpenId(_) => "OpenID",
+            DirectoryInner::Graph(_) => "Microsoft Graph",
         };
 
         if !override_ {
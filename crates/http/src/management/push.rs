@@ -0,0 +1,185 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc};
+
+use base64::{Engine, engine::general_purpose};
+use common::{Server, auth::AccessToken};
+use directory::backend::internal::manage;
+use email::push::{
+    Keys,
+    device::{PushDevice, PushDeviceStore, PushGateway},
+};
+use http_proto::{request::decode_path_element, *};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::write::now;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeviceType {
+    Apns,
+    Fcm,
+    UnifiedPush,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceRegistration {
+    #[serde(rename = "type")]
+    pub typ: DeviceType,
+    pub token: String,
+    #[serde(default)]
+    pub keys: Option<DeviceKeys>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    #[serde(rename = "type")]
+    pub typ: DeviceType,
+    pub token: String,
+    pub encrypted: bool,
+    pub registered_at: u64,
+}
+
+pub trait PushDeviceHandler: Sync + Send {
+    fn handle_push_devices_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_push_device_post(
+        &self,
+        access_token: Arc<AccessToken>,
+        body: Option<Vec<u8>>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_push_device_delete(
+        &self,
+        access_token: Arc<AccessToken>,
+        token: &str,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl PushDeviceHandler for Server {
+    async fn handle_push_devices_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let devices = self
+            .push_devices(access_token.primary_id())
+            .await?
+            .into_iter()
+            .map(|device| DeviceInfo {
+                typ: match device.gateway {
+                    PushGateway::Apns { .. } => DeviceType::Apns,
+                    PushGateway::Fcm => DeviceType::Fcm,
+                    PushGateway::UnifiedPush => DeviceType::UnifiedPush,
+                },
+                token: device.token,
+                encrypted: device.keys.is_some(),
+                registered_at: device.registered_at,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(JsonResponse::new(json!({
+            "data": devices,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_push_device_post(
+        &self,
+        access_token: Arc<AccessToken>,
+        body: Option<Vec<u8>>,
+    ) -> trc::Result<HttpResponse> {
+        let request =
+            serde_json::from_slice::<DeviceRegistration>(body.as_deref().unwrap_or_default())
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+        let config = &self.core.push_gateway;
+        let gateway = match request.typ {
+            DeviceType::Fcm if config.fcm.is_some() => PushGateway::Fcm,
+            DeviceType::UnifiedPush if config.unified_push => {
+                if !request.token.starts_with("https://") || request.token.len() > 512 {
+                    return Err(manage::error("Invalid UnifiedPush endpoint", None::<u32>));
+                }
+                PushGateway::UnifiedPush
+            }
+            // Apple Mail registers its devices using XAPPLEPUSHSERVICE
+            DeviceType::Apns => {
+                return Err(manage::unsupported(
+                    "APNs devices are registered through IMAP",
+                ));
+            }
+            _ => {
+                return Err(manage::unsupported(
+                    "Push gateway has been disabled by the system administrator",
+                ));
+            }
+        };
+        if request.token.is_empty() || request.token.len() > 512 {
+            return Err(manage::error("Invalid device token", None::<u32>));
+        }
+        let keys = if let Some(keys) = request.keys {
+            match (
+                general_purpose::URL_SAFE.decode(&keys.p256dh),
+                general_purpose::URL_SAFE.decode(&keys.auth),
+            ) {
+                (Ok(p256dh), Ok(auth)) => Some(Keys { p256dh, auth }),
+                _ => return Err(manage::error("Failed to decode keys", None::<u32>)),
+            }
+        } else {
+            None
+        };
+
+        self.register_push_device(
+            access_token.primary_id(),
+            PushDevice {
+                gateway,
+                token: request.token,
+                mailboxes: vec![],
+                keys,
+                registered_at: now(),
+            },
+        )
+        .await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": (),
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_push_device_delete(
+        &self,
+        access_token: Arc<AccessToken>,
+        token: &str,
+    ) -> trc::Result<HttpResponse> {
+        if self
+            .unregister_push_device(
+                access_token.primary_id(),
+                decode_path_element(token).as_ref(),
+            )
+            .await?
+        {
+            Ok(JsonResponse::new(json!({
+                "data": (),
+            }))
+            .into_http_response())
+        } else {
+            Err(trc::ManageEvent::NotFound.into_err())
+        }
+    }
+}
@@ -0,0 +1,467 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::{
+    Permission, Type,
+    backend::internal::manage::{self, ManageDirectory},
+};
+use hyper::{Method, StatusCode};
+use mail_parser::DateTime;
+use serde_json::json;
+use smtp::queue::{
+    self, ArchivedMessage, QueueId,
+    quarantine::{
+        ArchivedQuarantineEntry, ModerationAction, QuarantineEntry, QuarantineNote, SmtpQuarantine,
+        parse_moderation_token,
+    },
+    spool::SmtpSpool,
+};
+use store::{
+    Deserialize, IterateParams, ValueKey,
+    write::{AlignedBytes, Archive, QueueClass, ValueClass, key::DeserializeBigEndian, now},
+};
+use trc::AddContext;
+use utils::url_params::UrlParams;
+
+use super::queue::{IsTenantDomain, Message};
+use http_proto::{request::decode_path_element, *};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct QuarantinedMessage {
+    pub id: QueueId,
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderator: Option<String>,
+    pub quarantined_at: String,
+    pub expires_at: String,
+    pub message: Message,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Note {
+    pub author: String,
+    pub text: String,
+    pub created: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct Disposition {
+    note: Option<String>,
+}
+
+pub trait QuarantineManagement: Sync + Send {
+    fn handle_manage_quarantine(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_moderation_link(
+        &self,
+        req: &HttpRequest,
+        token: &str,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl QuarantineManagement for Server {
+    async fn handle_manage_quarantine(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        // Limit to tenant domains
+        let mut tenant_domains: Option<Vec<String>> = None;
+        #[cfg(feature = "enterprise")]
+        if self.core.is_enterprise_edition() {
+            if let Some(tenant) = access_token.tenant {
+                tenant_domains = self
+                    .core
+                    .storage
+                    .data
+                    .list_principals(None, tenant.id.into(), &[Type::Domain], false, 0, 0)
+                    .await
+                    .map(|principals| {
+                        principals
+                            .items
+                            .into_iter()
+                            .map(|p| p.name)
+                            .collect::<Vec<_>>()
+                    })
+                    .caused_by(trc::location!())?
+                    .into();
+            }
+        }
+
+        // SPDX-SnippetEnd
+
+        match (
+            path.get(1).copied().map(decode_path_element),
+            path.get(2).copied(),
+            req.method(),
+        ) {
+            (None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::QuarantineList)?;
+
+                let result = fetch_quarantined_messages(self, &params, &tenant_domains).await?;
+
+                Ok(JsonResponse::new(json!({
+                        "data":{
+                            "items": result.items,
+                            "total": result.total,
+                        },
+                }))
+                .into_http_response())
+            }
+            (Some(queue_id), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::QuarantineGet)?;
+
+                let queue_id = queue_id.parse().unwrap_or_default();
+                if let (Some(message_), Some(entry_)) = (
+                    self.read_message_archive(queue_id).await?,
+                    self.read_quarantine_entry(queue_id).await?,
+                ) {
+                    let message = message_.unarchive::<queue::Message>()?;
+                    let entry = entry_.unarchive::<QuarantineEntry>()?;
+                    if message.is_quarantined() && message.is_tenant_domain(&tenant_domains) {
+                        return Ok(JsonResponse::new(json!({
+                                "data": QuarantinedMessage::new(message, entry),
+                        }))
+                        .into_http_response());
+                    }
+                }
+
+                Err(trc::ResourceEvent::NotFound.into_err())
+            }
+            (Some(queue_id), Some("contents"), &Method::GET) => {
+                // Message bodies require a separate grant
+                access_token.assert_has_permission(Permission::QuarantineGetContents)?;
+
+                if let Some(message_) = self
+                    .read_message_archive(queue_id.parse().unwrap_or_default())
+                    .await?
+                {
+                    let message = message_.unarchive::<queue::Message>()?;
+                    if message.is_quarantined() && message.is_tenant_domain(&tenant_domains) {
+                        let contents = self
+                            .blob_store()
+                            .get_blob(message.blob_hash.0.as_slice(), 0..usize::MAX)
+                            .await?
+                            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                        return Ok(Resource::new("message/rfc822", contents).into_http_response());
+                    }
+                }
+
+                Err(trc::ResourceEvent::NotFound.into_err())
+            }
+            (Some(queue_id), Some(action @ ("note" | "release" | "reject")), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(match action {
+                    "note" => Permission::QuarantineUpdate,
+                    "release" => Permission::QuarantineRelease,
+                    _ => Permission::QuarantineReject,
+                })?;
+
+                let note = if let Some(body) = body.as_deref().filter(|body| !body.is_empty()) {
+                    serde_json::from_slice::<Disposition>(body)
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?
+                        .note
+                } else {
+                    params.get("note").map(|note| note.to_string())
+                }
+                .filter(|note| !note.trim().is_empty());
+
+                let queue_id: QueueId = queue_id.parse().unwrap_or_default();
+                let (Some(message), Some(entry_)) = (
+                    self.read_message(queue_id).await.filter(|message| {
+                        message.is_quarantined()
+                            && tenant_domains
+                                .as_ref()
+                                .is_none_or(|domains| message.has_domain(domains))
+                    }),
+                    self.read_quarantine_entry(queue_id).await?,
+                ) else {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                };
+
+                let found = match action {
+                    "note" => {
+                        let Some(note) = note.clone() else {
+                            return Err(manage::error("Note text is required", None::<u32>));
+                        };
+                        let mut entry = entry_.deserialize::<QuarantineEntry>()?;
+                        entry.notes.push(QuarantineNote {
+                            created: now(),
+                            author: access_token.name.clone(),
+                            text: note,
+                        });
+                        self.write_quarantine_entry(queue_id, entry).await?;
+
+                        trc::event!(
+                            Queue(trc::QueueEvent::QuarantineNoteAdded),
+                            QueueId = queue_id,
+                            AccountName = access_token.name.clone(),
+                            Details = note,
+                        );
+
+                        true
+                    }
+                    _ => {
+                        dispose_quarantined(
+                            self,
+                            message,
+                            action == "release",
+                            access_token.name.clone(),
+                            note,
+                        )
+                        .await?
+                    }
+                };
+
+                Ok(JsonResponse::new(json!({
+                        "data": found,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn handle_moderation_link(
+        &self,
+        req: &HttpRequest,
+        token: &str,
+    ) -> trc::Result<HttpResponse> {
+        let Some((queue_id, action)) = parse_moderation_token(self, token) else {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        };
+        let (Some(message), Some(entry_)) = (
+            self.read_message(queue_id)
+                .await
+                .filter(|message| message.is_quarantined()),
+            self.read_quarantine_entry(queue_id).await?,
+        ) else {
+            return Ok(HtmlResponse::with_status(
+                StatusCode::NOT_FOUND,
+                "<p>This message has already been processed or no longer exists.</p>".to_string(),
+            )
+            .into_http_response());
+        };
+        let entry = entry_.unarchive::<QuarantineEntry>()?;
+        let Some(moderator) = entry.moderator.as_ref().map(|m| m.to_string()) else {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        };
+        let label = match action {
+            ModerationAction::Approve => "approve",
+            ModerationAction::Reject => "reject",
+        };
+
+        // Links may be fetched by mail scanners, require an explicit confirmation
+        if req.method() != Method::POST {
+            return Ok(HtmlResponse::new(format!(
+                concat!(
+                    "<p>Do you want to {} the message from {} with subject \"{}\"?</p>",
+                    "<form method=\"post\"><button type=\"submit\">Confirm</button></form>"
+                ),
+                label,
+                html_escape(
+                    entry
+                        .from
+                        .as_deref()
+                        .unwrap_or(message.return_path.as_str())
+                ),
+                html_escape(entry.subject.as_deref().unwrap_or_default()),
+            ))
+            .into_http_response());
+        }
+
+        dispose_quarantined(
+            self,
+            message,
+            action == ModerationAction::Approve,
+            moderator,
+            None,
+        )
+        .await?;
+
+        Ok(HtmlResponse::new(format!("<p>The message was {label}d.</p>")).into_http_response())
+    }
+}
+
+async fn dispose_quarantined(
+    server: &Server,
+    message: queue::Message,
+    release: bool,
+    account_name: String,
+    note: Option<String>,
+) -> trc::Result<bool> {
+    let queue_id = message.queue_id;
+    if release {
+        let found = message.release_quarantine(server).await?;
+
+        trc::event!(
+            Queue(trc::QueueEvent::QuarantineReleased),
+            QueueId = queue_id,
+            AccountName = account_name,
+            Details = note,
+        );
+
+        Ok(found)
+    } else {
+        // Rejected messages are discarded without a DSN to avoid backscatter
+        let found = message.remove(server, 0).await;
+
+        trc::event!(
+            Queue(trc::QueueEvent::QuarantineRejected),
+            QueueId = queue_id,
+            AccountName = account_name,
+            Details = note,
+        );
+
+        Ok(found)
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl QuarantinedMessage {
+    fn new(message: &ArchivedMessage, entry: &ArchivedQuarantineEntry) -> Self {
+        QuarantinedMessage {
+            id: message.queue_id.into(),
+            reason: entry.reason.to_string(),
+            from: entry.from.as_ref().map(|from| from.to_string()),
+            subject: entry.subject.as_ref().map(|subject| subject.to_string()),
+            moderator: entry
+                .moderator
+                .as_ref()
+                .map(|moderator| moderator.to_string()),
+            quarantined_at: DateTime::from_timestamp(u64::from(entry.created) as i64).to_rfc3339(),
+            expires_at: DateTime::from_timestamp(u64::from(entry.expires) as i64).to_rfc3339(),
+            message: Message::from(message),
+            notes: entry
+                .notes
+                .iter()
+                .map(|note| Note {
+                    author: note.author.to_string(),
+                    text: note.text.to_string(),
+                    created: DateTime::from_timestamp(u64::from(note.created) as i64).to_rfc3339(),
+                })
+                .collect(),
+        }
+    }
+}
+
+struct QuarantinedMessages {
+    items: Vec<QuarantinedMessage>,
+    total: usize,
+}
+
+async fn fetch_quarantined_messages(
+    server: &Server,
+    params: &UrlParams<'_>,
+    tenant_domains: &Option<Vec<String>>,
+) -> trc::Result<QuarantinedMessages> {
+    let text = params.get("text");
+    let page = params.parse::<usize>("page").unwrap_or_default();
+    let limit = params.parse::<usize>("limit").unwrap_or_default();
+
+    // Collect quarantined ids first, messages live in a separate subspace
+    let mut ids = Vec::new();
+    server
+        .core
+        .storage
+        .data
+        .iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Queue(QueueClass::Quarantine(0))),
+                ValueKey::from(ValueClass::Queue(QueueClass::Quarantine(u64::MAX))),
+            )
+            .ascending(),
+            |key, value| {
+                ids.push((
+                    key.deserialize_be_u64(1)?,
+                    <Archive<AlignedBytes> as Deserialize>::deserialize(value)
+                        .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?,
+                ));
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    let mut result = QuarantinedMessages {
+        items: Vec::new(),
+        total: 0,
+    };
+    let mut offset = page.saturating_sub(1) * limit;
+
+    for (queue_id, entry_) in ids {
+        let Some(message_) = server.read_message_archive(queue_id).await? else {
+            continue;
+        };
+        let message = message_.unarchive::<queue::Message>()?;
+        let entry = entry_.unarchive::<QuarantineEntry>()?;
+
+        if message.is_quarantined()
+            && message.is_tenant_domain(tenant_domains)
+            && text.is_none_or(|text| {
+                message.return_path.contains(text)
+                    || message
+                        .recipients
+                        .iter()
+                        .any(|r| r.address_lcase.contains(text))
+                    || entry
+                        .subject
+                        .as_ref()
+                        .is_some_and(|subject| subject.contains(text))
+                    || entry.reason.contains(text)
+            })
+        {
+            if offset == 0 {
+                if limit == 0 || result.items.len() < limit {
+                    result.items.push(QuarantinedMessage::new(message, entry));
+                }
+            } else {
+                offset -= 1;
+            }
+
+            result.total += 1;
+        }
+    }
+
+    Ok(result)
+}
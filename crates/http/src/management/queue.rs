@@ -4,12 +4,24 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{future::Future, sync::atomic::Ordering};
+use std::{future::Future, sync::atomic::Ordering, time::Duration};
 
 use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
-use common::{Server, auth::AccessToken, ipc::QueueEvent};
+use common::{
+    Server,
+    auth::AccessToken,
+    config::smtp::{
+        queue::BudgetAction,
+        routing::ROUTE_RELAY_MX,
+        session::{FILTER_LATENCY_BUCKETS, FilterState},
+    },
+    ipc::QueueEvent,
+};
 
-use directory::{Permission, Type, backend::internal::manage::ManageDirectory};
+use directory::{
+    Permission, Type,
+    backend::internal::manage::{self, ManageDirectory},
+};
 use hyper::Method;
 use mail_auth::{
     dmarc::URI,
@@ -20,22 +32,32 @@ use mail_parser::DateTime;
 use serde::{Deserializer, Serializer};
 use serde_json::json;
 use smtp::{
+    inbound::sending::SmtpSendingLimits,
     queue::{
         self, ArchivedMessage, ArchivedStatus, DisplayArchivedResponse, ErrorDetails, HostResponse,
-        QueueId, Status, spool::SmtpSpool,
+        QueueId, Status,
+        budget::RelayBudgetCheck,
+        quarantine::QuarantineEntry,
+        spool::SmtpSpool,
+        tracking::{DeliveryAttempt, TrackingEntry},
     },
     reporting::{dmarc::DmarcReporting, tls::TlsReporting},
 };
 use store::{
     Deserialize, IterateParams, ValueKey,
+    ahash::{AHashMap, AHashSet},
     write::{
-        AlignedBytes, Archive, QueueClass, ReportEvent, ValueClass, key::DeserializeBigEndian, now,
+        AlignedBytes, Archive, QueueClass, ReportClass, ReportEvent, ValueClass,
+        key::DeserializeBigEndian, now,
     },
 };
-use trc::AddContext;
-use utils::url_params::UrlParams;
+use trc::{
+    AddContext, EventType, Key, Value,
+    ipc::subscriber::{Interests, SubscriberBuilder},
+};
+use utils::{config::utils::ParseValue, url_params::UrlParams};
 
-use super::FutureTimestamp;
+use super::{FutureTimestamp, event_stream::trace_event_stream};
 use http_proto::{request::decode_path_element, *};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -111,6 +133,19 @@ pub enum Report {
     },
 }
 
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackedMessage {
+    pub queue_id: QueueId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    pub from: String,
+    pub recipients: Vec<String>,
+    pub size: u64,
+    pub created: u64,
+    pub attempts: Vec<DeliveryAttempt>,
+}
+
 pub trait QueueManagement: Sync + Send {
     fn handle_manage_queue(
         &self,
@@ -176,6 +211,7 @@ impl QueueManagement for Server {
                             "data":{
                                 "items": result.values,
                                 "total": result.total,
+                                "cursor": result.cursor,
                                 "status": queue_status,
                             },
                     }))
@@ -184,12 +220,136 @@ impl QueueManagement for Server {
                             "data": {
                                 "items": result.ids,
                                 "total":  result.total,
+                                "cursor": result.cursor,
                                 "status": queue_status,
                             },
                     }))
                 }
                 .into_http_response())
             }
+            ("messages", None, &Method::POST) => {
+                let action = params.get("action").unwrap_or_default();
+                let relay = match action {
+                    "retry" | "hold" => {
+                        access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
+                        None
+                    }
+                    "reroute" => {
+                        access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
+                        let relay = params
+                            .get("relay")
+                            .ok_or_else(|| manage::error("Missing relay host", None::<u32>))?;
+                        if relay != ROUTE_RELAY_MX
+                            && !self.core.smtp.queue.relay_hosts.contains_key(relay)
+                        {
+                            return Err(manage::error(
+                                "Relay host not found",
+                                Some(relay.to_string()),
+                            ));
+                        }
+                        Some(relay)
+                    }
+                    "delete" => {
+                        access_token.assert_has_permission(Permission::MessageQueueDelete)?;
+                        None
+                    }
+                    _ => {
+                        return Err(manage::error("Invalid action", Some(action.to_string())));
+                    }
+                };
+                let time = params
+                    .parse::<FutureTimestamp>("at")
+                    .map(|t| t.into_inner())
+                    .unwrap_or_else(now);
+                let result = fetch_queued_messages(self, &params, &tenant_domains).await?;
+
+                // Pause the queue while the whole set is being updated
+                let is_active = self.inner.data.queue_status.load(Ordering::Relaxed);
+                if is_active && !result.ids.is_empty() {
+                    let _ = self.inner.ipc.queue_tx.send(QueueEvent::Paused(true)).await;
+                }
+
+                let mut total = 0;
+                let mut error = None;
+                for id in result.ids {
+                    let Some(mut message) = self.read_message(id).await else {
+                        continue;
+                    };
+                    let prev_event = message.next_event().unwrap_or_default();
+                    let applied = match action {
+                        "retry" => {
+                            let mut has_changes = false;
+                            for domain in &mut message.domains {
+                                if matches!(
+                                    domain.status,
+                                    Status::Scheduled | Status::TemporaryFailure(_)
+                                ) {
+                                    domain.retry.due = time;
+                                    has_changes = true;
+                                }
+                            }
+                            if has_changes {
+                                let next_event = message.next_event().unwrap_or_default();
+                                message
+                                    .save_changes(self, prev_event.into(), next_event.into())
+                                    .await
+                            } else {
+                                false
+                            }
+                        }
+                        "hold" => {
+                            match message
+                                .hold(
+                                    self,
+                                    QuarantineEntry::new(format!("Held by {}", access_token.name)),
+                                )
+                                .await
+                            {
+                                Ok(applied) => applied,
+                                Err(err) => {
+                                    error = Some(err);
+                                    break;
+                                }
+                            }
+                        }
+                        "reroute" => match message.reroute(self, relay.unwrap_or_default()).await {
+                            Ok(applied) => applied,
+                            Err(err) => {
+                                error = Some(err);
+                                break;
+                            }
+                        },
+                        _ => {
+                            message.remove(self, prev_event).await;
+                            true
+                        }
+                    };
+                    if applied {
+                        total += 1;
+                    }
+                }
+
+                if is_active {
+                    let _ = self
+                        .inner
+                        .ipc
+                        .queue_tx
+                        .send(QueueEvent::Paused(false))
+                        .await;
+                }
+                let _ = self.inner.ipc.queue_tx.send(QueueEvent::Refresh).await;
+
+                if let Some(error) = error {
+                    return Err(error);
+                }
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "total": total,
+                        },
+                }))
+                .into_http_response())
+            }
             ("messages", Some(queue_id), &Method::GET) => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueGet)?;
@@ -208,6 +368,38 @@ impl QueueManagement for Server {
                 }
                 Err(trc::ResourceEvent::NotFound.into_err())
             }
+            ("trace", Some(queue_id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueGet)?;
+
+                let queue_id = queue_id.parse::<QueueId>().unwrap_or_default();
+                let span_id = match self.read_message_archive(queue_id).await? {
+                    Some(message_) => {
+                        let message = message_.unarchive::<queue::Message>()?;
+                        if !message.is_tenant_domain(&tenant_domains) {
+                            return Err(trc::ResourceEvent::NotFound.into_err());
+                        }
+                        u64::from(message.span_id)
+                    }
+                    None => return Err(trc::ResourceEvent::NotFound.into_err()),
+                };
+
+                Ok(stream_message_trace(self.clone(), queue_id, span_id))
+            }
+            ("tracking", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueList)?;
+
+                let (items, total) = fetch_tracked_messages(self, &params, &tenant_domains).await?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": items,
+                            "total": total,
+                        },
+                }))
+                .into_http_response())
+            }
             ("messages", None, &Method::PATCH) => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
@@ -272,9 +464,10 @@ impl QueueManagement for Server {
                     .read_message(queue_id.parse().unwrap_or_default())
                     .await
                     .filter(|message| {
-                        tenant_domains
-                            .as_ref()
-                            .is_none_or(|domains| message.has_domain(domains))
+                        !message.is_quarantined()
+                            && tenant_domains
+                                .as_ref()
+                                .is_none_or(|domains| message.has_domain(domains))
                     })
                 {
                     let prev_event = message.next_event().unwrap_or_default();
@@ -591,6 +784,209 @@ impl QueueManagement for Server {
                 }))
                 .into_http_response())
             }
+            ("maintenance", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueGet)?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": self.inner.data.smtp_maintenance.load(Ordering::Relaxed),
+                }))
+                .into_http_response())
+            }
+            ("maintenance", Some(action), &Method::PATCH)
+                if matches!(action.as_ref(), "start" | "stop") =>
+            {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
+
+                let prev_status = self
+                    .inner
+                    .data
+                    .smtp_maintenance
+                    .swap(action == "start", Ordering::Relaxed);
+
+                Ok(JsonResponse::new(json!({
+                        "data": prev_status,
+                }))
+                .into_http_response())
+            }
+            ("filters", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueGet)?;
+
+                let session = &self.core.smtp.session;
+                let filter_ids = session
+                    .milters
+                    .iter()
+                    .map(|milter| format!("milter.{}", milter.id))
+                    .chain(session.hooks.iter().map(|hook| format!("hook.{}", hook.id)))
+                    .collect::<Vec<_>>();
+                let now = now();
+                let health_map = self.inner.data.filter_health.lock().clone();
+                let result = filter_ids
+                    .into_iter()
+                    .map(|filter_id| {
+                        let health = health_map.get(&filter_id).cloned().unwrap_or_default();
+                        json!({
+                            "id": filter_id,
+                            "state": match health.state(now) {
+                                FilterState::Available => "available",
+                                FilterState::CircuitOpen => "circuit-open",
+                                FilterState::Disabled => "disabled",
+                            },
+                            "requests": health.requests,
+                            "failures": health.failures,
+                            "latencyAvg": health.latency_sum.checked_div(health.requests).unwrap_or_default(),
+                            "latencyBuckets": FILTER_LATENCY_BUCKETS
+                                .iter()
+                                .map(|bucket| bucket.to_string())
+                                .chain(["+Inf".to_string()])
+                                .zip(health.latency_buckets)
+                                .map(|(bucket, count)| (bucket, count.into()))
+                                .collect::<serde_json::Map<_, _>>(),
+                            "openUntil": health.open_until,
+                            "disabledUntil": health.disabled_until,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                        "data": result,
+                }))
+                .into_http_response())
+            }
+            ("filters", Some(filter_id), method @ (&Method::PATCH | &Method::DELETE)) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
+
+                let session = &self.core.smtp.session;
+                if !session
+                    .milters
+                    .iter()
+                    .any(|milter| filter_id == format!("milter.{}", milter.id))
+                    && !session
+                        .hooks
+                        .iter()
+                        .any(|hook| filter_id == format!("hook.{}", hook.id))
+                {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+
+                // Disable the filter temporarily or re-enable it, closing its circuit
+                let mut health_map = self.inner.data.filter_health.lock();
+                let health = health_map.entry(filter_id.to_string()).or_default();
+                if method == Method::PATCH {
+                    let duration = params
+                        .get("duration")
+                        .map(Duration::parse_value)
+                        .transpose()
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .into_err()
+                                .details(err)
+                        })?
+                        .unwrap_or(Duration::from_secs(3600));
+                    health.disabled_until = now() + duration.as_secs();
+                } else {
+                    health.disabled_until = 0;
+                    health.open_until = 0;
+                    health.window_errors = 0;
+                }
+
+                Ok(JsonResponse::new(json!({
+                        "data": health.disabled_until,
+                }))
+                .into_http_response())
+            }
+            ("budgets", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueGet)?;
+
+                let mut domains = params
+                    .get("domain")
+                    .map(|domain| vec![domain.to_lowercase()])
+                    .or_else(|| tenant_domains.clone())
+                    .unwrap_or_default();
+                if let Some(tenant_domains) = &tenant_domains {
+                    domains.retain(|domain| tenant_domains.contains(domain));
+                }
+
+                let mut result = Vec::with_capacity(self.core.smtp.queue.relay_budgets.len());
+                for budget in &self.core.smtp.queue.relay_budgets {
+                    let mut usage = serde_json::Map::new();
+                    for domain in &domains {
+                        usage.insert(
+                            domain.clone(),
+                            self.relay_budget_usage(budget, domain)
+                                .await
+                                .caused_by(trc::location!())?
+                                .into(),
+                        );
+                    }
+
+                    result.push(json!({
+                        "id": budget.id,
+                        "relay": budget.relay,
+                        "messages": budget.messages,
+                        "alert": budget.alert,
+                        "action": match budget.action {
+                            BudgetAction::Alert => "alert",
+                            BudgetAction::Direct => "direct",
+                            BudgetAction::Defer => "defer",
+                        },
+                        "usage": usage,
+                    }));
+                }
+
+                Ok(JsonResponse::new(json!({
+                        "data": result,
+                }))
+                .into_http_response())
+            }
+            ("limits", Some(account), method @ (&Method::GET | &Method::DELETE)) => {
+                // Validate the access token
+                access_token.assert_has_permission(if method == Method::GET {
+                    Permission::MessageQueueGet
+                } else {
+                    Permission::MessageQueueDelete
+                })?;
+
+                // Limit to tenant accounts
+                if let Some(tenant_domains) = &tenant_domains {
+                    if !account
+                        .rsplit_once('@')
+                        .is_some_and(|(_, domain)| tenant_domains.iter().any(|d| d == domain))
+                    {
+                        return Err(trc::ResourceEvent::NotFound.into_err());
+                    }
+                }
+
+                let account_id = self
+                    .store()
+                    .get_principal_id(account.as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                if method == Method::GET {
+                    Ok(JsonResponse::new(json!({
+                            "data": {
+                                "limits": self.sending_limit(
+                                    &self.core.smtp.session.mail.limits,
+                                    account.as_ref(),
+                                ).await,
+                                "usage": self.sending_usage(account_id).await?,
+                            },
+                    }))
+                    .into_http_response())
+                } else {
+                    self.clear_sending_usage(account_id).await?;
+
+                    Ok(JsonResponse::new(json!({
+                            "data": (),
+                    }))
+                    .into_http_response())
+                }
+            }
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
@@ -659,10 +1055,72 @@ impl From<&ArchivedMessage> for Message {
     }
 }
 
+/// Streams all events related to a queued message as server-sent events,
+/// following any span (such as a delivery attempt) that references it,
+/// until the message leaves the queue.
+fn stream_message_trace(server: Server, queue_id: QueueId, span_id: u64) -> HttpResponse {
+    // Delivery events are only emitted while someone is interested in them
+    let mut interests = Interests::default();
+    for event in EventType::variants() {
+        if matches!(
+            event,
+            EventType::Queue(_)
+                | EventType::Delivery(_)
+                | EventType::Dane(_)
+                | EventType::MtaSts(_)
+        ) {
+            interests.set(event);
+        }
+    }
+    let (_, rx) = SubscriberBuilder::new(format!("queue-trace-{queue_id}"))
+        .with_interests(interests)
+        .with_global_interests(true)
+        .with_lossy(false)
+        .register();
+    let mut active_span_ids = AHashSet::from_iter([span_id]);
+
+    trace_event_stream(
+        rx,
+        move |event| {
+            if event
+                .span_id()
+                .is_some_and(|span_id| active_span_ids.contains(&span_id))
+            {
+                true
+            } else if event
+                .keys
+                .iter()
+                .chain(
+                    event
+                        .inner
+                        .span
+                        .as_ref()
+                        .map_or(([]).iter(), |s| s.keys.iter()),
+                )
+                .any(|(key, value)| {
+                    *key == Key::QueueId && matches!(value, Value::UInt(id) if *id == queue_id)
+                })
+            {
+                if let Some(span_id) = event.span_id() {
+                    active_span_ids.insert(span_id);
+                }
+                true
+            } else {
+                false
+            }
+        },
+        move || {
+            let server = server.clone();
+            async move { matches!(server.read_message_archive(queue_id).await, Ok(None)) }
+        },
+    )
+}
+
 struct QueuedMessages {
     ids: Vec<u64>,
     values: Vec<Message>,
     total: usize,
+    cursor: Option<u64>,
 }
 
 async fn fetch_queued_messages(
@@ -679,11 +1137,29 @@ async fn fetch_queued_messages(
     let after = params
         .parse::<FutureTimestamp>("after")
         .map(|t| t.into_inner());
+    let domain = params.get("domain").map(|domain| domain.to_lowercase());
+    let status = params
+        .get("status")
+        .map(|status| match status {
+            "scheduled" => Ok(Status::Scheduled),
+            "completed" => Ok(Status::Completed(())),
+            "temp_fail" => Ok(Status::TemporaryFailure(())),
+            "perm_fail" => Ok(Status::PermanentFailure(())),
+            _ => Err(manage::error("Invalid status", Some(status.to_string()))),
+        })
+        .transpose()?;
+    let min_size = params.parse::<u64>("min-size");
+    let max_size = params.parse::<u64>("max-size");
     let page = params.parse::<usize>("page").unwrap_or_default();
     let limit = params.parse::<usize>("limit").unwrap_or_default();
     let values = params.has_key("values");
 
-    let range_start = params.parse::<u64>("range-start").unwrap_or_default();
+    // Cursors hold the id of the last message returned by the previous page
+    let range_start = params
+        .parse::<u64>("cursor")
+        .map(|cursor| cursor.saturating_add(1))
+        .or_else(|| params.parse::<u64>("range-start"))
+        .unwrap_or_default();
     let range_end = params.parse::<u64>("range-end").unwrap_or(u64::MAX);
     let max_total = params.parse::<usize>("max-total").unwrap_or_default();
 
@@ -691,13 +1167,22 @@ async fn fetch_queued_messages(
         ids: Vec::new(),
         values: Vec::new(),
         total: 0,
+        cursor: None,
     };
     let from_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(range_start)));
     let to_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(range_end)));
-    let has_filters =
-        text.is_some() || from.is_some() || to.is_some() || before.is_some() || after.is_some();
+    let has_filters = text.is_some()
+        || from.is_some()
+        || to.is_some()
+        || before.is_some()
+        || after.is_some()
+        || domain.is_some()
+        || status.is_some()
+        || min_size.is_some()
+        || max_size.is_some();
     let mut offset = page.saturating_sub(1) * limit;
     let mut total_returned = 0;
+    let mut last_id = None;
 
     server
         .core
@@ -711,9 +1196,10 @@ async fn fetch_queued_messages(
                 let message = message_
                     .unarchive::<queue::Message>()
                     .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?;
-                let matches = tenant_domains
-                    .as_ref()
-                    .is_none_or(|domains| message.has_domain(domains))
+                let matches = !message.is_quarantined()
+                    && tenant_domains
+                        .as_ref()
+                        .is_none_or(|domains| message.has_domain(domains))
                     && (!has_filters
                         || (text
                             .as_ref()
@@ -739,17 +1225,46 @@ async fn fetch_queued_messages(
                                 .is_none_or(|before| message.next_delivery_event() < *before)
                             && after
                                 .as_ref()
-                                .is_none_or(|after| message.next_delivery_event() > *after)));
+                                .is_none_or(|after| message.next_delivery_event() > *after)
+                            && domain.as_ref().is_none_or(|domain| {
+                                message.domains.iter().any(|d| d.domain.as_str() == domain)
+                            })
+                            && status.as_ref().is_none_or(|status| {
+                                message.domains.iter().any(|d| {
+                                    matches!(
+                                        (status, &d.status),
+                                        (Status::Scheduled, ArchivedStatus::Scheduled)
+                                            | (Status::Completed(_), ArchivedStatus::Completed(_))
+                                            | (
+                                                Status::TemporaryFailure(_),
+                                                ArchivedStatus::TemporaryFailure(_)
+                                            )
+                                            | (
+                                                Status::PermanentFailure(_),
+                                                ArchivedStatus::PermanentFailure(_)
+                                            )
+                                    )
+                                })
+                            })
+                            && min_size
+                                .is_none_or(|min_size| u64::from(message.size) >= min_size)
+                            && max_size
+                                .is_none_or(|max_size| u64::from(message.size) <= max_size)));
 
                 if matches {
                     if offset == 0 {
                         if limit == 0 || total_returned < limit {
+                            let id = key.deserialize_be_u64(0)?;
                             if values {
                                 result.values.push(Message::from(message));
                             } else {
-                                result.ids.push(key.deserialize_be_u64(0)?);
+                                result.ids.push(id);
                             }
+                            last_id = Some(id);
                             total_returned += 1;
+                        } else if result.cursor.is_none() {
+                            // More results are available after the last returned message
+                            result.cursor = last_id;
                         }
                     } else {
                         offset -= 1;
@@ -766,6 +1281,105 @@ async fn fetch_queued_messages(
         .map(|_| result)
 }
 
+async fn fetch_tracked_messages(
+    server: &Server,
+    params: &UrlParams<'_>,
+    tenant_domains: &Option<Vec<String>>,
+) -> trc::Result<(Vec<TrackedMessage>, usize)> {
+    let queue_id = params.parse::<QueueId>("queue-id");
+    let message_id = params
+        .get("message-id")
+        .map(|id| id.trim_matches(|c| c == '<' || c == '>'));
+    let from = params.get("from").map(|from| from.to_lowercase());
+    let to = params.get("to").map(|to| to.to_lowercase());
+    let page: usize = params.parse("page").unwrap_or_default();
+    let limit: usize = params.parse("limit").unwrap_or_default();
+
+    // Group the tracking entries by message
+    let mut messages: AHashMap<QueueId, TrackedMessage> = AHashMap::new();
+    server
+        .store()
+        .iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Report(ReportClass::Tracking {
+                    id: 0,
+                    expires: 0,
+                })),
+                ValueKey::from(ValueClass::Report(ReportClass::Tracking {
+                    id: u64::MAX,
+                    expires: u64::MAX,
+                })),
+            ),
+            |_, value| {
+                let entry = <Archive<AlignedBytes> as Deserialize>::deserialize(value)?
+                    .deserialize::<TrackingEntry>()
+                    .caused_by(trc::location!())?;
+                if queue_id.is_none_or(|queue_id| queue_id == entry.queue_id()) {
+                    let message = messages.entry(entry.queue_id()).or_default();
+                    match entry {
+                        TrackingEntry::Queued(entry) => {
+                            message.queue_id = entry.queue_id;
+                            message.message_id = entry.message_id;
+                            message.from = entry.from;
+                            message.recipients = entry.recipients;
+                            message.size = entry.size;
+                            message.created = entry.created;
+                        }
+                        TrackingEntry::Attempt(attempt) => {
+                            message.queue_id = attempt.queue_id;
+                            message.attempts.push(attempt);
+                        }
+                    }
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    let mut results = messages
+        .into_values()
+        .filter(|message| {
+            message_id.is_none_or(|id| message.message_id.as_deref() == Some(id))
+                && from
+                    .as_ref()
+                    .is_none_or(|from| message.from.to_lowercase().contains(from))
+                && to.as_ref().is_none_or(|to| {
+                    message
+                        .recipients
+                        .iter()
+                        .any(|rcpt| rcpt.contains(to.as_str()))
+                })
+                && tenant_domains.as_ref().is_none_or(|domains| {
+                    [&message.from]
+                        .into_iter()
+                        .chain(message.recipients.iter())
+                        .any(|addr| {
+                            addr.rsplit_once('@')
+                                .is_some_and(|(_, domain)| domains.iter().any(|d| d == domain))
+                        })
+                })
+        })
+        .collect::<Vec<_>>();
+    let total = results.len();
+
+    // Newest messages first
+    results.sort_unstable_by(|a, b| b.queue_id.cmp(&a.queue_id));
+    for message in &mut results {
+        message
+            .attempts
+            .sort_unstable_by_key(|attempt| attempt.started);
+    }
+    let results = results
+        .into_iter()
+        .skip(page.saturating_sub(1) * limit)
+        .take(if limit > 0 { limit } else { usize::MAX })
+        .collect();
+
+    Ok((results, total))
+}
+
 struct QueuedReports {
     ids: Vec<QueueClass>,
     total: usize,
@@ -963,7 +1577,7 @@ fn is_zero(num: &i16) -> bool {
     *num == 0
 }
 
-trait IsTenantDomain {
+pub(super) trait IsTenantDomain {
     fn is_tenant_domain(&self, tenant_domains: &Option<Vec<String>>) -> bool;
 }
 impl IsTenantDomain for ArchivedMessage {
@@ -56,6 +56,14 @@ impl ManageReload for Server {
                 }))
                 .into_http_response())
             }
+            (Some("directory-groups"), &Method::GET) => {
+                self.invalidate_group_caches();
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
             (Some("certificate"), &Method::GET) => Ok(JsonResponse::new(json!({
                 "data": self.reload_certificates().await?.config,
             }))
@@ -161,6 +161,9 @@ impl ManageReports for Server {
                             }
                             _ => Err(trc::ResourceEvent::NotFound.into_err()),
                         },
+                        ReportClass::Tracking { .. }
+                        | ReportClass::Impersonation { .. }
+                        | ReportClass::Audit { .. } => Err(trc::ResourceEvent::NotFound.into_err()),
                     }
                 } else {
                     Err(trc::ResourceEvent::NotFound.into_err())
@@ -192,6 +195,13 @@ impl ManageReports for Server {
                                 ReportClass::Dmarc { .. } => ReportClass::Dmarc { id, expires },
                                 ReportClass::Tls { .. } => ReportClass::Tls { id, expires },
                                 ReportClass::Arf { .. } => ReportClass::Arf { id, expires },
+                                ReportClass::Tracking { .. } => {
+                                    ReportClass::Tracking { id, expires }
+                                }
+                                ReportClass::Impersonation { .. } => {
+                                    ReportClass::Impersonation { id, expires }
+                                }
+                                ReportClass::Audit { .. } => ReportClass::Audit { id, expires },
                             };
 
                             batch.clear(ValueClass::Report(report_id));
@@ -249,6 +259,9 @@ impl ManageReports for Server {
                             )
                             .await?
                             .is_none_or(|report| report.has_domain(domains)),
+                            ReportClass::Tracking { .. }
+                            | ReportClass::Impersonation { .. }
+                            | ReportClass::Audit { .. } => false,
                         };
 
                         if !is_tenant_report {
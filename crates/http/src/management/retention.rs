@@ -0,0 +1,88 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::{Permission, backend::internal::manage::ManageDirectory};
+use email::message::retention::EmailRetention;
+use hyper::Method;
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use http_proto::{request::decode_path_element, *};
+
+pub trait RetentionManagement: Sync + Send {
+    fn handle_manage_retention(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl RetentionManagement for Server {
+    async fn handle_manage_retention(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RetentionList)?;
+
+                // Rules are managed through the settings API under "email.retention"
+                let items = self
+                    .core
+                    .jmap
+                    .mail_retention
+                    .iter()
+                    .map(|rule| {
+                        json!({
+                            "id": rule.id,
+                            "tenant": rule.tenant,
+                            "folders": rule.folders,
+                            "maxAge": rule.max_age.as_secs(),
+                            "action": rule.action.as_str(),
+                            "dryRun": rule.dry_run,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(account), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RetentionRun)?;
+
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(decode_path_element(account).as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+                    .map(|p| p.id)
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                let dry_run = UrlParams::new(req.uri().query()).has_key("dry-run");
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.emails_apply_retention(account_id, dry_run).await?,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server, auth::AccessToken, config::smtp::routing::DomainRouteEntry, ipc::BroadcastEvent,
+};
+use directory::Permission;
+use hyper::{Method, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use http_proto::*;
+
+const CSV_COLUMNS: &[&str] = &[
+    "domain",
+    "relay",
+    "address",
+    "port",
+    "protocol",
+    "tls-implicit",
+    "username",
+    "secret",
+    "starttls",
+    "dane",
+    "mta-sts",
+    "allow-invalid-certs",
+    "rate",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DomainRouteItem {
+    domain: String,
+    #[serde(flatten)]
+    route: DomainRouteEntry,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DomainRouteError {
+    domain: String,
+    reason: String,
+}
+
+pub trait RoutingManagement: Sync + Send {
+    fn handle_manage_routing(
+        &self,
+        req: &HttpRequest,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl RoutingManagement for Server {
+    async fn handle_manage_routing(
+        &self,
+        req: &HttpRequest,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+
+        match req.method() {
+            &Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let routes = self.domain_routes_export().await?;
+
+                if params.get("format") == Some("csv") {
+                    Ok(HttpResponse::new(StatusCode::OK)
+                        .with_content_type("text/csv; charset=utf-8")
+                        .with_text_body(routes_to_csv(routes)))
+                } else {
+                    Ok(JsonResponse::new(json!({
+                        "data": routes
+                            .into_iter()
+                            .map(|(domain, route)| DomainRouteItem { domain, route })
+                            .collect::<Vec<_>>(),
+                    }))
+                    .into_http_response())
+                }
+            }
+            method @ (&Method::POST | &Method::PUT) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsUpdate)?;
+
+                let body = body.unwrap_or_default();
+                let is_csv = params.get("format") == Some("csv")
+                    || req
+                        .headers()
+                        .get(header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .is_some_and(|value| value.starts_with("text/csv"));
+                let routes = if is_csv {
+                    routes_from_csv(&body)
+                } else {
+                    serde_json::from_slice::<Vec<DomainRouteItem>>(&body)
+                        .map(|items| {
+                            items
+                                .into_iter()
+                                .map(|item| (item.domain, item.route))
+                                .collect()
+                        })
+                        .map_err(|err| err.to_string())
+                }
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                })?;
+
+                // Nothing is written unless every route in the table is valid
+                let keys = match self.domain_routes_validate(routes) {
+                    Ok(keys) => keys,
+                    Err(errors) => {
+                        return Ok(JsonResponse::with_status(
+                            StatusCode::BAD_REQUEST,
+                            json!({
+                                "error": "validationFailed",
+                                "details": errors
+                                    .into_iter()
+                                    .map(|(domain, reason)| DomainRouteError { domain, reason })
+                                    .collect::<Vec<_>>(),
+                            }),
+                        )
+                        .into_http_response());
+                    }
+                };
+                let count = keys.len();
+
+                if !params.has_key("dry-run") {
+                    // PUT replaces the whole table, POST only adds or updates routes
+                    self.domain_routes_apply(keys, method == Method::PUT)
+                        .await?;
+
+                    // Reload settings so the new routes take effect
+                    if let Some(core) = self.reload().await?.new_core {
+                        self.inner.shared_core.store(core.into());
+                        self.cluster_broadcast(BroadcastEvent::ReloadSettings).await;
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": count,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+fn routes_to_csv(routes: Vec<(String, DomainRouteEntry)>) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let _ = writer.write_record(CSV_COLUMNS);
+
+    for (domain, route) in routes {
+        let _ = writer.write_record([
+            domain,
+            route.relay.unwrap_or_default(),
+            route.address.unwrap_or_default(),
+            route.port.map(|v| v.to_string()).unwrap_or_default(),
+            route.protocol.unwrap_or_default(),
+            route
+                .tls_implicit
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            route.username.unwrap_or_default(),
+            route.secret.unwrap_or_default(),
+            route.starttls.unwrap_or_default(),
+            route.dane.unwrap_or_default(),
+            route.mta_sts.unwrap_or_default(),
+            route
+                .allow_invalid_certs
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            route.rate.unwrap_or_default(),
+        ]);
+    }
+
+    writer
+        .into_inner()
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_default()
+}
+
+fn routes_from_csv(bytes: &[u8]) -> Result<Vec<(String, DomainRouteEntry)>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(bytes);
+
+    // Columns are matched by name, so they can be given in any order
+    let headers = reader.headers().map_err(|err| err.to_string())?.clone();
+    let mut columns = Vec::with_capacity(headers.len());
+    for header in headers.iter() {
+        let header = header.to_ascii_lowercase().replace('_', "-");
+        match CSV_COLUMNS.iter().position(|column| *column == header) {
+            Some(idx) => columns.push(idx),
+            None => return Err(format!("Unknown column {header:?}.")),
+        }
+    }
+    if !columns.contains(&0) {
+        return Err("Missing \"domain\" column.".to_string());
+    }
+
+    let mut routes = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| err.to_string())?;
+        let mut domain = String::new();
+        let mut route = DomainRouteEntry::default();
+
+        for (value, column) in record.iter().zip(columns.iter()) {
+            if value.is_empty() {
+                continue;
+            }
+            let value_ = Some(value.to_string());
+            match *column {
+                0 => domain = value.to_string(),
+                1 => route.relay = value_,
+                2 => route.address = value_,
+                3 => route.port = Some(parse_csv_value(value, line)?),
+                4 => route.protocol = value_,
+                5 => route.tls_implicit = Some(parse_csv_value(value, line)?),
+                6 => route.username = value_,
+                7 => route.secret = value_,
+                8 => route.starttls = value_,
+                9 => route.dane = value_,
+                10 => route.mta_sts = value_,
+                11 => route.allow_invalid_certs = Some(parse_csv_value(value, line)?),
+                _ => route.rate = value_,
+            }
+        }
+
+        routes.push((domain, route));
+    }
+
+    Ok(routes)
+}
+
+fn parse_csv_value<T: std::str::FromStr>(value: &str, line: usize) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {value:?} on line {}.", line + 2))
+}
@@ -0,0 +1,57 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken, scripts::diagnostic::SieveDiagnostic};
+use directory::Permission;
+use hyper::Method;
+use serde_json::json;
+
+use http_proto::*;
+
+pub trait SieveManagement: Sync + Send {
+    fn handle_manage_sieve(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl SieveManagement for Server {
+    async fn handle_manage_sieve(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (Some("validate"), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SieveCheckScript)?;
+
+                // Scripts are validated with the same limits applied to user scripts
+                let script = body.unwrap_or_default();
+                let diagnostics = match self.core.sieve.untrusted_compiler.compile(&script) {
+                    Ok(_) => vec![],
+                    Err(err) => vec![SieveDiagnostic::from(&err)],
+                };
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "valid": diagnostics.is_empty(),
+                        "diagnostics": diagnostics,
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -243,6 +243,7 @@ impl ManageSpamHandler for Server {
                     env_from_flags: request.env_from_flags,
                     env_rcpt_to: request.env_rcpt_to.iter().map(String::as_str).collect(),
                     account_id: None,
+                    overrides: None,
                     is_test: true,
                 };
 
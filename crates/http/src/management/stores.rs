@@ -8,7 +8,7 @@ use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use common::{
     auth::AccessToken,
     ipc::{HousekeeperEvent, PurgeType},
-    manager::webadmin::Resource,
+    manager::{backup::BackupParams, restore::RestoreParams, webadmin::Resource},
     storage::index::ObjectIndexBuilder,
     *,
 };
@@ -16,9 +16,14 @@ use directory::{
     Permission,
     backend::internal::manage::{self, ManageDirectory},
 };
-use email::message::{ingest::EmailIngest, metadata::MessageData};
+use email::{
+    cache::MessageCacheFetch,
+    mailbox::counters::MailboxCounterAccess,
+    message::{ingest::EmailIngest, metadata::MessageData},
+};
 use hyper::Method;
 use jmap_proto::types::{collection::Collection, property::Property};
+use serde::Deserialize;
 use serde_json::json;
 use services::task_manager::fts::FtsIndexTask;
 use store::{
@@ -26,7 +31,7 @@ use store::{
     write::{Archiver, BatchBuilder, ValueClass},
 };
 use trc::AddContext;
-use utils::url_params::UrlParams;
+use utils::{BlobHash, url_params::UrlParams};
 
 use http_proto::{request::decode_path_element, *};
 
@@ -34,6 +39,24 @@ use http_proto::{request::decode_path_element, *};
 use super::enterprise::undelete::UndeleteApi;
 use std::future::Future;
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupRequest {
+    path: String,
+    #[serde(default)]
+    families: Vec<String>,
+    incremental: Option<String>,
+    encryption_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RestoreRequest {
+    path: String,
+    account: Option<String>,
+    encryption_key: Option<String>,
+}
+
 pub trait ManageStore: Sync + Send {
     fn handle_manage_store(
         &self,
@@ -65,6 +88,34 @@ impl ManageStore for Server {
             path.get(3).copied(),
             req.method(),
         ) {
+            (Some("blobs"), Some(blob_hash), Some("references"), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::BlobFetch)?;
+
+                let blob_hash = URL_SAFE_NO_PAD
+                    .decode(decode_path_element(blob_hash).as_bytes())
+                    .ok()
+                    .and_then(|hash| BlobHash::try_from_hash_slice(&hash).ok())
+                    .ok_or_else(|| {
+                        trc::ResourceEvent::BadParameters
+                            .into_err()
+                            .details("Invalid blob hash")
+                    })?;
+                let committed_at = self
+                    .store()
+                    .blob_committed_at(&blob_hash)
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                let references = self.store().blob_references(&blob_hash).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "references": references,
+                        "committedAt": committed_at,
+                    },
+                }))
+                .into_http_response())
+            }
             (Some("blobs"), Some(blob_hash), _, &Method::GET) => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::BlobFetch)?;
@@ -299,6 +350,156 @@ impl ManageStore for Server {
                 }))
                 .into_http_response())
             }
+            (
+                Some("counters"),
+                Some(account_id),
+                None,
+                method @ (&Method::GET | &Method::DELETE),
+            ) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PurgeAccount)?;
+
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_id(decode_path_element(account_id).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+
+                if method == Method::DELETE {
+                    self.rebuild_mailbox_counters(account_id).await?;
+                }
+
+                let cache = self.get_cached_messages(account_id).await?;
+                let mut result = serde_json::Map::new();
+                for mailbox in &cache.mailboxes.items {
+                    let counters = self
+                        .mailbox_counters(account_id, mailbox.document_id)
+                        .await?;
+                    result.insert(
+                        mailbox.path.clone(),
+                        json!({
+                            "total": counters.total,
+                            "unseen": counters.unseen,
+                            "deleted": counters.deleted,
+                        }),
+                    );
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": result,
+                }))
+                .into_http_response())
+            }
+            (Some("backup"), None, None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::StoreBackup)?;
+
+                let request =
+                    serde_json::from_slice::<BackupRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+                let mut params = BackupParams::new(request.path.clone().into())
+                    .with_families(request.families.iter().map(String::as_str))
+                    .map_err(|err| trc::ResourceEvent::BadParameters.into_err().details(err))?;
+                if let Some(key) = request.encryption_key {
+                    params = params.with_encryption_key(key);
+                }
+                if let Some(base) = request.incremental {
+                    params = params.with_incremental(base);
+                }
+
+                let core = self.core.clone();
+                tokio::spawn(async move {
+                    match core.backup(params).await {
+                        Ok(_) => {
+                            trc::event!(
+                                Store(trc::StoreEvent::BackupCompleted),
+                                Path = request.path
+                            );
+                        }
+                        Err(err) => {
+                            trc::error!(
+                                err.details("Backup failed")
+                                    .ctx(trc::Key::Path, request.path)
+                            );
+                        }
+                    }
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("restore"), None, None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::StoreRestore)?;
+
+                let request =
+                    serde_json::from_slice::<RestoreRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .from_json_error(err)
+                    })?;
+                let mut params = RestoreParams::new(request.path.clone().into());
+                if let Some(key) = request.encryption_key {
+                    params = params.with_encryption_key(key);
+                }
+                if let Some(account) = request.account {
+                    params = params.with_account(account);
+                }
+
+                let core = self.core.clone();
+                tokio::spawn(async move {
+                    match core.restore(params).await {
+                        Ok(_) => {
+                            trc::event!(
+                                Store(trc::StoreEvent::RestoreCompleted),
+                                Path = request.path
+                            );
+                        }
+                        Err(err) => {
+                            trc::error!(
+                                err.details("Restore failed")
+                                    .ctx(trc::Key::Path, request.path)
+                            );
+                        }
+                    }
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("partitions"), None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MetricsList)?;
+
+                let result = self
+                    .partition_status()
+                    .into_iter()
+                    .map(|(id, health)| {
+                        json!({
+                            "id": id,
+                            "status": if health.degraded { "degraded" } else { "healthy" },
+                            "failures": health.failures,
+                            "lastCheck": health.last_check,
+                            "lastError": health.last_error,
+                            "latency": health.latency,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": result,
+                }))
+                .into_http_response())
+            }
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
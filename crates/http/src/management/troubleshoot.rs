@@ -5,6 +5,8 @@
  */
 
 use std::{
+    borrow::Cow,
+    collections::HashMap,
     future::Future,
     net::{IpAddr, SocketAddr},
     time::{Duration, Instant},
@@ -13,10 +15,20 @@ use std::{
 use common::{
     Server,
     auth::{AccessToken, oauth::GrantType},
-    config::smtp::resolver::{Policy, Tlsa},
+    config::smtp::{
+        auth::VerifyStrategy,
+        queue::RequireOptional,
+        report::AggregateFrequency,
+        resolver::{Policy, Tlsa},
+        session::{Mechanism, MtPriority},
+    },
+    expr::{
+        Constant, Token, VARIABLES_MAP, Variable, eval::EvalTrace, functions::ResolveVariable,
+        if_block::IfBlock, tokenizer::TokenMap,
+    },
     psl,
 };
-use directory::backend::internal::manage;
+use directory::backend::internal::manage::{self, ManageDirectory};
 use http_body_util::{StreamBody, combinators::BoxBody};
 use hyper::{
     Method, StatusCode,
@@ -31,14 +43,22 @@ use mail_auth::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use smtp::outbound::{
-    client::{SmtpClient, StartTlsResult},
-    dane::{dnssec::TlsaLookup, verify::TlsaVerify},
-    lookup::{DnsLookup, ToNextHop},
-    mta_sts::{lookup::MtaStsLookup, verify::VerifyPolicy},
+use services::deliverability::{DeliverabilityProbe, ProbeRequest};
+use smtp::{
+    core::Session,
+    inbound::simulate::SimulationRequest,
+    outbound::{
+        client::{SmtpClient, StartTlsResult},
+        dane::{dnssec::TlsaLookup, verify::TlsaVerify},
+        lookup::{DnsLookup, ToNextHop},
+        mta_sts::{lookup::MtaStsLookup, verify::VerifyPolicy},
+    },
 };
 use tokio::{io::AsyncWriteExt, sync::mpsc};
-use utils::url_params::UrlParams;
+use utils::{
+    config::{Config, ConfigError},
+    url_params::UrlParams,
+};
 
 use http_proto::{request::decode_path_element, *};
 
@@ -118,6 +138,72 @@ impl TroubleshootApi for Server {
                 }))
                 .into_http_response())
             }
+            ("simulate", None, &Method::POST) => {
+                let request = serde_json::from_slice::<SimulationRequest>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+                // Simulate authenticated submissions using the account's access token
+                let authenticated_as = if let Some(name) = &request.authenticated_as {
+                    let account_id = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal_info(name)
+                        .await?
+                        .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+                        .map(|p| p.id)
+                        .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+                    Some(self.get_access_token(account_id).await?)
+                } else {
+                    None
+                };
+
+                Ok(JsonResponse::new(json!({
+                        "data": Session::simulate(self.clone(), request, authenticated_as).await,
+                }))
+                .into_http_response())
+            }
+            ("expression", None, &Method::POST) => {
+                let request = serde_json::from_slice::<ExpressionTroubleshootRequest>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": expression_troubleshoot(self, request).await?,
+                }))
+                .into_http_response())
+            }
+            ("probe", None, &Method::POST) => {
+                let request =
+                    serde_json::from_slice::<ProbeRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": self.probe_start(request).await?,
+                }))
+                .into_http_response())
+            }
+            ("probe", Some(id), &Method::GET) => {
+                let state = self
+                    .probe_state(decode_path_element(id).as_ref())
+                    .await?
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": state,
+                }))
+                .into_http_response())
+            }
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
@@ -1196,3 +1282,122 @@ impl From<DmarcPolicy> for dmarc::Policy {
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExpressionTroubleshootRequest {
+    key: String,
+    expression: Option<String>,
+    #[serde(default)]
+    variables: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExpressionTroubleshootResponse {
+    result: serde_json::Value,
+    error: Option<String>,
+    trace: Vec<EvalTrace>,
+    elapsed: u64,
+}
+
+struct ExpressionVariables(HashMap<u32, Variable<'static>>);
+
+impl ResolveVariable for ExpressionVariables {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        self.0.get(&variable).cloned().unwrap_or_default()
+    }
+
+    fn resolve_global(&self, _: &str) -> Variable<'_> {
+        Variable::Integer(0)
+    }
+}
+
+async fn expression_troubleshoot(
+    server: &Server,
+    request: ExpressionTroubleshootRequest,
+) -> trc::Result<ExpressionTroubleshootResponse> {
+    // Use the supplied expression or the one stored in the configuration
+    let mut config = Config::default();
+    if let Some(expression) = request.expression {
+        config.keys.insert(request.key.clone(), expression);
+    } else {
+        config.keys = server.core.storage.config.list(&request.key, false).await?;
+    }
+    if !config.keys.keys().any(|key| {
+        key == &request.key
+            || key
+                .strip_prefix(&request.key)
+                .is_some_and(|key| key.starts_with('.'))
+    }) {
+        return Err(trc::ResourceEvent::NotFound
+            .into_err()
+            .details("Expression not found in configuration")
+            .ctx(trc::Key::Key, request.key));
+    }
+
+    let if_block = IfBlock::try_parse(&mut config, request.key.as_str(), &expression_token_map())
+        .ok_or_else(|| {
+        manage::error(
+            "Invalid expression",
+            config.errors.into_values().next().map(|err| match err {
+                ConfigError::Parse { error }
+                | ConfigError::Build { error }
+                | ConfigError::Macro { error } => error,
+            }),
+        )
+    })?;
+
+    // Build the synthetic envelope
+    let mut variables = HashMap::with_capacity(request.variables.len());
+    for (name, value) in request.variables {
+        let Some((_, id)) = VARIABLES_MAP.iter().find(|(var_name, _)| *var_name == name) else {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Unknown variable")
+                .ctx(trc::Key::Key, name));
+        };
+        variables.insert(*id, Variable::from(value));
+    }
+    let variables = ExpressionVariables(variables);
+
+    let time = Instant::now();
+    let mut trace = Vec::new();
+    let (result, error) = match server
+        .eval_if_trace(&if_block, &variables, &mut trace, 0)
+        .await
+    {
+        Ok(result) => ((&result).into(), None),
+        Err(err) => (serde_json::Value::Null, Some(err.to_string())),
+    };
+
+    Ok(ExpressionTroubleshootResponse {
+        result,
+        error,
+        trace,
+        elapsed: time.elapsed().as_millis() as u64,
+    })
+}
+
+fn expression_token_map() -> TokenMap {
+    let mut token_map = TokenMap::default().with_all_variables();
+
+    // Constants evaluate to their names so that results are readable
+    for constants in [
+        TokenMap::default().with_constants::<VerifyStrategy>(),
+        TokenMap::default().with_constants::<RequireOptional>(),
+        TokenMap::default().with_constants::<common::config::smtp::queue::IpLookupStrategy>(),
+        TokenMap::default().with_constants::<AggregateFrequency>(),
+        TokenMap::default().with_constants::<Mechanism>(),
+        TokenMap::default().with_constants::<MtPriority>(),
+    ] {
+        for (name, _) in constants.tokens {
+            let constant = Constant::String(name.as_ref().into());
+            token_map
+                .tokens
+                .insert(Cow::Owned(name.into_owned()), Token::Constant(constant));
+        }
+    }
+
+    token_map
+}
@@ -10,16 +10,20 @@ use common::{
     Inner, KV_ACME, Server,
     auth::{AccessToken, oauth::GrantType},
     core::BuildServer,
-    ipc::StateEvent,
+    ipc::{PushGatewayEvent, StateEvent},
     listener::{SessionData, SessionManager, SessionStream},
     manager::webadmin::Resource,
 };
 use dav::{DavMethod, request::DavRequestHandler};
 use directory::Permission;
-use groupware::{DavResourceName, calendar::itip::ItipIngest};
+use groupware::{
+    DavResourceName,
+    calendar::{availability::CalendarAvailability, itip::ItipIngest},
+};
 use http_proto::{
     DownloadResponse, HtmlResponse, HttpContext, HttpRequest, HttpResponse, HttpResponseBody,
-    HttpSessionData, JsonProblemResponse, ToHttpResponse, form_urlencoded, request::fetch_body,
+    HttpSessionData, JsonProblemResponse, ToHttpResponse, form_urlencoded,
+    request::{decode_path_element, fetch_body},
 };
 use hyper::{
     Method, StatusCode, body,
@@ -27,20 +31,28 @@ use hyper::{
     server::conn::http1,
     service::service_fn,
 };
-use hyper_util::rt::TokioIo;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
 use jmap::{
     api::{
         ToJmapHttpResponse, event_source::EventSourceHandler, request::RequestHandler,
         session::SessionHandler,
     },
-    blob::{download::BlobDownload, upload::BlobUpload},
+    blob::{
+        download::BlobDownload,
+        resumable::{ResumableUpload, TUS_RESUMABLE, tus_options_response},
+        upload::BlobUpload,
+    },
     websocket::upgrade::WebSocketUpgrade,
 };
 use jmap_proto::{
     request::{Request, capability::Session},
     types::{blob::BlobId, id::Id},
 };
-use store::dispatch::lookup::KeyValue;
+use smtp::inbound::large_files::parse_large_file_token;
+use store::{dispatch::lookup::KeyValue, write::now};
 use trc::SecurityEvent;
 use utils::url_params::UrlParams;
 
@@ -52,10 +64,15 @@ use crate::{
             FormData, auth::OAuthApiHandler, openid::OpenIdHandler,
             registration::ClientRegistrationHandler, token::TokenHandler,
         },
+        trusted_device::TrustedDeviceHandler,
     },
     autoconfig::Autoconfig,
     form::FormHandler,
-    management::{ManagementApi, ToManageHttpResponse, troubleshoot::TroubleshootApi},
+    grpc::{GRPC_QUEUE_SERVICE, GrpcHandler},
+    management::{
+        ManagementApi, ToManageHttpResponse, quarantine::QuarantineManagement,
+        troubleshoot::TroubleshootApi,
+    },
 };
 
 pub trait ParseHttp: Sync + Send {
@@ -88,6 +105,12 @@ impl ParseHttp for Server {
         }
 
         match path.next().unwrap_or_default() {
+            "jmap" | "dav" if !self.core.network.roles.serve_users => {
+                // User traffic is served by the frontend nodes
+                return Ok(
+                    JsonProblemResponse(StatusCode::MISDIRECTED_REQUEST).into_http_response()
+                );
+            }
             "jmap" => {
                 match (path.next().unwrap_or_default(), req.method()) {
                     ("", &Method::POST) => {
@@ -148,6 +171,34 @@ impl ParseHttp for Server {
                             };
                         }
                     }
+                    (
+                        "upload",
+                        &Method::POST | &Method::PATCH | &Method::HEAD | &Method::DELETE,
+                    ) if req.headers().contains_key(TUS_RESUMABLE) => {
+                        // Authenticate request
+                        let (_in_flight, access_token) =
+                            self.authenticate_headers(&req, &session, false).await?;
+
+                        if let Some(account_id) =
+                            path.next().and_then(|p| Id::from_bytes(p.as_bytes()))
+                        {
+                            let upload_id =
+                                path.next().filter(|p| !p.is_empty()).map(|p| p.to_string());
+
+                            return self
+                                .handle_resumable_upload(
+                                    &mut req,
+                                    account_id,
+                                    upload_id.as_deref(),
+                                    access_token,
+                                    &session,
+                                )
+                                .await;
+                        }
+                    }
+                    ("upload", &Method::OPTIONS) => {
+                        return Ok(tus_options_response(self));
+                    }
                     ("upload", &Method::POST) => {
                         // Authenticate request
                         let (_in_flight, access_token) =
@@ -352,6 +403,12 @@ impl ParseHttp for Server {
 
                     return self.handle_token_request(&mut req, session).await;
                 }
+                ("trust", &Method::POST) => {
+                    self.is_http_anonymous_request_allowed(&session.remote_ip)
+                        .await?;
+
+                    return self.handle_trusted_device_request(&mut req, session).await;
+                }
                 ("introspect", &Method::POST) => {
                     // Authenticate request
                     let (_in_flight, access_token) =
@@ -361,6 +418,15 @@ impl ParseHttp for Server {
                         .handle_token_introspect(&mut req, &access_token, session.session_id)
                         .await;
                 }
+                ("revoke", &Method::POST) => {
+                    // Authenticate request
+                    let (_in_flight, access_token) =
+                        self.authenticate_headers(&req, &session, false).await?;
+
+                    return self
+                        .handle_token_revoke(&mut req, &access_token, session.session_id)
+                        .await;
+                }
                 ("userinfo", &Method::GET) => {
                     // Authenticate request
                     let (_in_flight, access_token) =
@@ -483,28 +549,50 @@ impl ParseHttp for Server {
                 self.is_http_anonymous_request_allowed(&session.remote_ip)
                     .await?;
 
-                if self.core.groupware.itip_http_rsvp_url.is_some()
-                    && req.method() == Method::GET
-                    && path.next().unwrap_or_default() == "rsvp"
-                {
-                    return self
-                        .http_rsvp_handle(
-                            req.uri().query().unwrap_or_default(),
-                            req.headers()
-                                .get(header::ACCEPT_LANGUAGE)
-                                .and_then(|v| v.to_str().ok())
-                                .map(|lang| {
-                                    let lang = lang.split_once(',').map_or(lang, |(l, _)| l);
-                                    lang.split_once(';').map_or(lang, |(l, _)| l)
-                                })
-                                .unwrap_or("en"),
-                        )
-                        .await
-                        .map(|response| {
-                            HtmlResponse::new(response)
-                                .into_http_response()
-                                .with_no_store()
-                        });
+                match (path.next().unwrap_or_default(), req.method()) {
+                    ("rsvp", &Method::GET) if self.core.groupware.itip_http_rsvp_url.is_some() => {
+                        return self
+                            .http_rsvp_handle(
+                                req.uri().query().unwrap_or_default(),
+                                req.headers()
+                                    .get(header::ACCEPT_LANGUAGE)
+                                    .and_then(|v| v.to_str().ok())
+                                    .map(|lang| {
+                                        let lang = lang.split_once(',').map_or(lang, |(l, _)| l);
+                                        lang.split_once(';').map_or(lang, |(l, _)| l)
+                                    })
+                                    .unwrap_or("en"),
+                            )
+                            .await
+                            .map(|response| {
+                                HtmlResponse::new(response)
+                                    .into_http_response()
+                                    .with_no_store()
+                            });
+                    }
+                    ("freebusy", &Method::GET) if self.core.groupware.freebusy_federation => {
+                        let email = path.next().unwrap_or_default();
+                        let params = UrlParams::new(req.uri().query());
+                        let start = params
+                            .get("start")
+                            .and_then(mail_parser::DateTime::parse_rfc3339)
+                            .map_or_else(|| now() as i64, |dt| dt.to_timestamp());
+                        let end = params
+                            .get("end")
+                            .and_then(mail_parser::DateTime::parse_rfc3339)
+                            .map_or(i64::MAX, |dt| dt.to_timestamp());
+
+                        return match self.published_free_busy(email, start, end).await? {
+                            Some(ical) => Ok(Resource::new(
+                                "text/calendar; charset=utf-8",
+                                ical.to_string().into_bytes(),
+                            )
+                            .into_http_response()
+                            .with_no_store()),
+                            None => Err(trc::ResourceEvent::NotFound.into_err()),
+                        };
+                    }
+                    _ => (),
                 }
             }
             "autodiscover" => {
@@ -522,6 +610,44 @@ impl ParseHttp for Server {
                         .await;
                 }
             }
+            "moderate" => {
+                // Limit anonymous requests
+                self.is_http_anonymous_request_allowed(&session.remote_ip)
+                    .await?;
+
+                if let Some(token) = path.next() {
+                    return self.handle_moderation_link(&req, token).await;
+                }
+            }
+            "download" if req.method() == Method::GET => {
+                // Limit anonymous requests
+                self.is_http_anonymous_request_allowed(&session.remote_ip)
+                    .await?;
+
+                // Large attachments replaced by download links during submission
+                if let (Some(hash), Some(name)) = (
+                    path.next()
+                        .and_then(|token| parse_large_file_token(self, token)),
+                    path.next(),
+                ) {
+                    let blob_store = match &self.core.smtp.session.data.large_files.store {
+                        Some(store_id) => self.core.storage.blobs.get(store_id),
+                        None => Some(&self.core.storage.blob),
+                    };
+                    if let Some(blob) = blob_store {
+                        if let Some(blob) = blob.get_blob(hash.as_slice(), 0..usize::MAX).await? {
+                            return Ok(DownloadResponse {
+                                filename: decode_path_element(name).into_owned(),
+                                content_type: "application/octet-stream".to_string(),
+                                blob,
+                            }
+                            .into_http_response());
+                        }
+                    }
+                }
+
+                return Err(trc::ResourceEvent::NotFound.into_err());
+            }
             "robots.txt" => {
                 // Limit anonymous requests
                 self.is_http_anonymous_request_allowed(&session.remote_ip)
@@ -636,6 +762,12 @@ impl ParseHttp for Server {
                     }
                 }
             }
+            GRPC_QUEUE_SERVICE
+                if self.core.network.grpc.is_some() && req.method() == Method::POST =>
+            {
+                let method = path.next().unwrap_or_default().to_string();
+                return Ok(self.handle_grpc_request(&mut req, &method, &session).await);
+            }
             _ => {
                 let path = req.uri().path();
                 let resource = self
@@ -670,152 +802,163 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
     let _in_flight = session.in_flight;
     let is_tls = session.stream.is_tls();
 
-    if let Err(http_err) = http1::Builder::new()
-        .keep_alive(true)
-        .serve_connection(
-            TokioIo::new(session.stream),
-            service_fn(|req: hyper::Request<body::Incoming>| {
-                let instance = session.instance.clone();
-                let inner = inner.clone();
-
-                async move {
-                    let server = inner.build_server();
-
-                    // Obtain remote IP
-                    let remote_ip = if !server.core.jmap.http_use_forwarded {
-                        trc::event!(
-                            Http(trc::HttpEvent::RequestUrl),
-                            SpanId = session.session_id,
-                            Url = req.uri().to_string(),
-                        );
-
-                        session.remote_ip
-                    } else if let Some(forwarded_for) = req
-                        .headers()
-                        .get(header::FORWARDED)
-                        .and_then(|h| h.to_str().ok())
-                        .and_then(|h| {
-                            let h = h.to_ascii_lowercase();
-                            h.split_once("for=").and_then(|(_, rest)| {
-                                let mut start_ip = usize::MAX;
-                                let mut end_ip = usize::MAX;
-
-                                for (pos, ch) in rest.char_indices() {
-                                    match ch {
-                                        '0'..='9' | 'a'..='f' | ':' | '.' => {
-                                            if start_ip == usize::MAX {
-                                                start_ip = pos;
-                                            }
-                                            end_ip = pos;
-                                        }
-                                        '"' | '[' | ' ' if start_ip == usize::MAX => {}
-                                        _ => {
-                                            break;
-                                        }
-                                    }
-                                }
-
-                                rest.get(start_ip..=end_ip)
-                                    .and_then(|h| h.parse::<IpAddr>().ok())
-                            })
-                        })
-                        .or_else(|| {
-                            req.headers()
-                                .get("X-Forwarded-For")
-                                .and_then(|h| h.to_str().ok())
-                                .map(|h| h.split_once(',').map_or(h, |(ip, _)| ip).trim())
-                                .and_then(|h| h.parse::<IpAddr>().ok())
-                        })
-                    {
-                        // Check if the forwarded IP has been blocked
-                        if server.is_ip_blocked(&forwarded_for) {
-                            trc::event!(
-                                Security(trc::SecurityEvent::IpBlocked),
-                                ListenerId = instance.id.clone(),
-                                RemoteIp = forwarded_for,
-                                SpanId = session.session_id,
-                            );
-
-                            return Ok::<_, hyper::Error>(
-                                JsonProblemResponse(StatusCode::FORBIDDEN)
-                                    .into_http_response()
-                                    .build(),
-                            );
-                        }
+    let is_grpc = inner.shared_core.load().network.grpc.is_some();
+    let service = service_fn(|req: hyper::Request<body::Incoming>| {
+        let instance = session.instance.clone();
+        let inner = inner.clone();
 
-                        trc::event!(
-                            Http(trc::HttpEvent::RequestUrl),
-                            SpanId = session.session_id,
-                            RemoteIp = forwarded_for,
-                            Url = req.uri().to_string(),
-                        );
+        async move {
+            let server = inner.build_server();
 
-                        forwarded_for
-                    } else {
-                        trc::event!(
-                            Http(trc::HttpEvent::XForwardedMissing),
-                            SpanId = session.session_id,
-                        );
-                        session.remote_ip
-                    };
+            // Obtain remote IP
+            let remote_ip = if !server.core.jmap.http_use_forwarded {
+                trc::event!(
+                    Http(trc::HttpEvent::RequestUrl),
+                    SpanId = session.session_id,
+                    Url = req.uri().to_string(),
+                );
 
-                    // Parse HTTP request
-                    let response = match Box::pin(server.parse_http_request(
-                        req,
-                        HttpSessionData {
-                            instance,
-                            local_ip: session.local_ip,
-                            local_port: session.local_port,
-                            remote_ip,
-                            remote_port: session.remote_port,
-                            is_tls,
-                            session_id: session.session_id,
-                        },
-                    ))
-                    .await
-                    {
-                        Ok(response) => response,
-                        Err(err) => {
-                            let response = err.into_http_response();
-                            trc::error!(err.span_id(session.session_id));
-                            response
+                session.remote_ip
+            } else if let Some(forwarded_for) = req
+                .headers()
+                .get(header::FORWARDED)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| {
+                    let h = h.to_ascii_lowercase();
+                    h.split_once("for=").and_then(|(_, rest)| {
+                        let mut start_ip = usize::MAX;
+                        let mut end_ip = usize::MAX;
+
+                        for (pos, ch) in rest.char_indices() {
+                            match ch {
+                                '0'..='9' | 'a'..='f' | ':' | '.' => {
+                                    if start_ip == usize::MAX {
+                                        start_ip = pos;
+                                    }
+                                    end_ip = pos;
+                                }
+                                '"' | '[' | ' ' if start_ip == usize::MAX => {}
+                                _ => {
+                                    break;
+                                }
+                            }
                         }
-                    };
 
+                        rest.get(start_ip..=end_ip)
+                            .and_then(|h| h.parse::<IpAddr>().ok())
+                    })
+                })
+                .or_else(|| {
+                    req.headers()
+                        .get("X-Forwarded-For")
+                        .and_then(|h| h.to_str().ok())
+                        .map(|h| h.split_once(',').map_or(h, |(ip, _)| ip).trim())
+                        .and_then(|h| h.parse::<IpAddr>().ok())
+                })
+            {
+                // Check if the forwarded IP has been blocked
+                if server.is_ip_blocked(&forwarded_for) {
                     trc::event!(
-                        Http(trc::HttpEvent::ResponseBody),
+                        Security(trc::SecurityEvent::IpBlocked),
+                        ListenerId = instance.id.clone(),
+                        RemoteIp = forwarded_for,
                         SpanId = session.session_id,
-                        Contents = match response.body() {
-                            HttpResponseBody::Text(value) =>
-                                trc::Value::String(value.as_str().into()),
-                            HttpResponseBody::Binary(_) =>
-                                trc::Value::String("[binary data]".into()),
-                            HttpResponseBody::Stream(_) => trc::Value::String("[stream]".into()),
-                            _ => trc::Value::None,
-                        },
-                        Code = response.status().as_u16(),
-                        Size = response.size(),
                     );
 
-                    // Build response
-                    let mut response = response.build();
+                    return Ok::<_, hyper::Error>(
+                        JsonProblemResponse(StatusCode::FORBIDDEN)
+                            .into_http_response()
+                            .build(),
+                    );
+                }
 
-                    // Add custom headers
-                    if !server.core.jmap.http_headers.is_empty() {
-                        let headers = response.headers_mut();
+                trc::event!(
+                    Http(trc::HttpEvent::RequestUrl),
+                    SpanId = session.session_id,
+                    RemoteIp = forwarded_for,
+                    Url = req.uri().to_string(),
+                );
 
-                        for (header, value) in &server.core.jmap.http_headers {
-                            headers.insert(header.clone(), value.clone());
-                        }
-                    }
+                forwarded_for
+            } else {
+                trc::event!(
+                    Http(trc::HttpEvent::XForwardedMissing),
+                    SpanId = session.session_id,
+                );
+                session.remote_ip
+            };
+
+            // Parse HTTP request
+            let response = match Box::pin(server.parse_http_request(
+                req,
+                HttpSessionData {
+                    instance,
+                    local_ip: session.local_ip,
+                    local_port: session.local_port,
+                    remote_ip,
+                    remote_port: session.remote_port,
+                    is_tls,
+                    session_id: session.session_id,
+                },
+            ))
+            .await
+            {
+                Ok(response) => response,
+                Err(err) => {
+                    let response = err.into_http_response();
+                    trc::error!(err.span_id(session.session_id));
+                    response
+                }
+            };
+
+            trc::event!(
+                Http(trc::HttpEvent::ResponseBody),
+                SpanId = session.session_id,
+                Contents = match response.body() {
+                    HttpResponseBody::Text(value) => trc::Value::String(value.as_str().into()),
+                    HttpResponseBody::Binary(_) => trc::Value::String("[binary data]".into()),
+                    HttpResponseBody::Stream(_) => trc::Value::String("[stream]".into()),
+                    _ => trc::Value::None,
+                },
+                Code = response.status().as_u16(),
+                Size = response.size(),
+            );
+
+            // Build response
+            let mut response = response.build();
 
-                    Ok::<_, hyper::Error>(response)
+            // Add custom headers
+            if !server.core.jmap.http_headers.is_empty() {
+                let headers = response.headers_mut();
+
+                for (header, value) in &server.core.jmap.http_headers {
+                    headers.insert(header.clone(), value.clone());
                 }
-            }),
-        )
-        .with_upgrades()
-        .await
-    {
+            }
+
+            Ok::<_, hyper::Error>(response)
+        }
+    });
+
+    let io = TokioIo::new(session.stream);
+    let result = if is_grpc {
+        // gRPC requires HTTP/2, detect the protocol from the connection preface
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder.http1().keep_alive(true);
+        builder
+            .serve_connection_with_upgrades(io, service)
+            .await
+            .map_err(|err| err.to_string())
+    } else {
+        http1::Builder::new()
+            .keep_alive(true)
+            .serve_connection(io, service)
+            .with_upgrades()
+            .await
+            .map_err(|err| err.to_string())
+    };
+
+    if let Err(http_err) = result {
         match inner
             .build_server()
             .is_scanner_fail2banned(session.remote_ip)
@@ -826,14 +969,14 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                     Security(SecurityEvent::ScanBan),
                     SpanId = session.session_id,
                     RemoteIp = session.remote_ip,
-                    Reason = http_err.to_string(),
+                    Reason = http_err,
                 );
             }
             Ok(false) => {
                 trc::event!(
                     Http(trc::HttpEvent::Error),
                     SpanId = session.session_id,
-                    Reason = http_err.to_string(),
+                    Reason = http_err,
                 );
             }
             Err(err) => {
@@ -855,6 +998,12 @@ impl SessionManager for HttpSessionManager {
     fn shutdown(&self) -> impl std::future::Future<Output = ()> + Send {
         async {
             let _ = self.inner.ipc.state_tx.send(StateEvent::Stop).await;
+            let _ = self
+                .inner
+                .ipc
+                .push_gateway_tx
+                .send(PushGatewayEvent::Stop)
+                .await;
         }
     }
 }
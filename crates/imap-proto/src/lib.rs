@@ -76,6 +76,16 @@ pub enum Command {
     // RFC 9208
     GetQuota,
     GetQuotaRoot,
+
+    // RFC 4978
+    Compress,
+
+    // RFC 5464
+    GetMetadata,
+    SetMetadata,
+
+    // Apple Mail push notifications
+    XApplePushService,
 }
 
 impl Command {
@@ -154,6 +164,14 @@ pub enum ResponseCode {
 
     // USEATTR
     UseAttr,
+
+    // COMPRESS
+    CompressionActive,
+
+    // METADATA
+    MetadataLongEntries {
+        size: u32,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -0,0 +1,166 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use compact_str::ToCompactString;
+
+use crate::{
+    Command,
+    protocol::{ProtocolVersion, apple_push},
+    receiver::{Request, Token, bad},
+    utf7::utf7_maybe_decode,
+};
+
+use super::parse_number;
+
+impl Request<Command> {
+    pub fn parse_apple_push(self, version: ProtocolVersion) -> trc::Result<apple_push::Arguments> {
+        let mut tokens = self.tokens.into_iter();
+        let mut aps_version = 1;
+        let mut account_id = None;
+        let mut device_token = None;
+        let mut subtopic = None;
+        let mut mailboxes = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            let key = token.unwrap_bytes();
+            if key.eq_ignore_ascii_case(b"mailboxes") {
+                if !tokens
+                    .next()
+                    .is_some_and(|token| token.is_parenthesis_open())
+                {
+                    return Err(bad(self.tag.to_compact_string(), "Expected mailbox list."));
+                }
+                loop {
+                    match tokens.next() {
+                        Some(Token::ParenthesisClose) => break,
+                        Some(token @ Token::Argument(_)) => {
+                            mailboxes.push(utf7_maybe_decode(
+                                token
+                                    .unwrap_string()
+                                    .map_err(|v| bad(self.tag.to_compact_string(), v))?,
+                                version,
+                            ));
+                        }
+                        _ => {
+                            return Err(bad(self.tag.to_compact_string(), "Invalid mailbox list."));
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let value = tokens
+                .next()
+                .ok_or_else(|| bad(self.tag.to_compact_string(), "Missing parameter value."))?
+                .unwrap_string()
+                .map_err(|v| bad(self.tag.to_compact_string(), v))?;
+            if key.eq_ignore_ascii_case(b"aps-version") {
+                aps_version = parse_number::<u32>(value.as_bytes())
+                    .map_err(|v| bad(self.tag.to_compact_string(), v))?;
+            } else if key.eq_ignore_ascii_case(b"aps-account-id") {
+                account_id = Some(value);
+            } else if key.eq_ignore_ascii_case(b"aps-device-token") {
+                device_token = Some(value);
+            } else if key.eq_ignore_ascii_case(b"aps-subtopic") {
+                subtopic = Some(value);
+            } else {
+                return Err(bad(
+                    self.tag.to_compact_string(),
+                    "Unsupported XAPPLEPUSHSERVICE parameter.",
+                ));
+            }
+        }
+
+        match (account_id, device_token, subtopic) {
+            (Some(account_id), Some(device_token), Some(subtopic))
+                if !account_id.is_empty() && !device_token.is_empty() =>
+            {
+                Ok(apple_push::Arguments {
+                    tag: self.tag,
+                    version: aps_version,
+                    account_id,
+                    device_token,
+                    subtopic,
+                    mailboxes,
+                })
+            }
+            _ => Err(bad(
+                self.tag.to_compact_string(),
+                "Missing XAPPLEPUSHSERVICE parameters.",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{ProtocolVersion, apple_push},
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_apple_push() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                concat!(
+                    "a XAPPLEPUSHSERVICE aps-version 2 aps-account-id 0715A26B-CA09-4730-A419-793000CA982E ",
+                    "aps-device-token 2918390218931890821908309283098109381029309829018310983092892829 ",
+                    "aps-subtopic com.apple.mobilemail mailboxes (INBOX \"Notes\")\r\n"
+                ),
+                apple_push::Arguments {
+                    tag: "a".into(),
+                    version: 2,
+                    account_id: "0715A26B-CA09-4730-A419-793000CA982E".into(),
+                    device_token:
+                        "2918390218931890821908309283098109381029309829018310983092892829".into(),
+                    subtopic: "com.apple.mobilemail".into(),
+                    mailboxes: vec!["INBOX".into(), "Notes".into()],
+                },
+            ),
+            (
+                concat!(
+                    "a XAPPLEPUSHSERVICE aps-version \"1\" aps-account-id \"abc\" ",
+                    "aps-device-token \"def\" aps-subtopic \"com.apple.mobilemail\"\r\n"
+                ),
+                apple_push::Arguments {
+                    tag: "a".into(),
+                    version: 1,
+                    account_id: "abc".into(),
+                    device_token: "def".into(),
+                    subtopic: "com.apple.mobilemail".into(),
+                    mailboxes: vec![],
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_apple_push(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{command}"
+            );
+        }
+
+        for command in [
+            "a XAPPLEPUSHSERVICE aps-version 2 aps-account-id abc\r\n",
+            "a XAPPLEPUSHSERVICE aps-version 2 aps-account-id abc aps-device-token def aps-subtopic x mailboxes INBOX\r\n",
+        ] {
+            assert!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_apple_push(ProtocolVersion::Rev2)
+                    .is_err(),
+                "{command}"
+            );
+        }
+    }
+}
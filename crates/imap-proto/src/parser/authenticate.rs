@@ -38,6 +38,7 @@ impl Mechanism {
             "DIGEST-MD5" => Self::DigestMd5,
             "SCRAM-SHA-1" => Self::ScramSha1,
             "SCRAM-SHA-256" => Self::ScramSha256,
+            "SCRAM-SHA-256-PLUS" => Self::ScramSha256Plus,
             "APOP" => Self::Apop,
             "NTLM" => Self::Ntlm,
             "GSSAPI" => Self::Gssapi,
@@ -0,0 +1,201 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use compact_str::ToCompactString;
+
+use crate::{
+    Command,
+    protocol::{
+        ProtocolVersion,
+        metadata::{self, Depth},
+    },
+    receiver::{Request, Token, bad},
+    utf7::utf7_maybe_decode,
+};
+
+use super::parse_number;
+
+impl Request<Command> {
+    pub fn parse_get_metadata(self, version: ProtocolVersion) -> trc::Result<metadata::Arguments> {
+        if self.tokens.len() < 2 {
+            return Err(self.into_error("Missing arguments."));
+        }
+
+        let mut tokens = self.tokens.into_iter().peekable();
+        let mut max_size = None;
+        let mut depth = Depth::Zero;
+
+        // Parse options
+        if tokens
+            .next_if(|token| token.is_parenthesis_open())
+            .is_some()
+        {
+            loop {
+                match tokens.next() {
+                    Some(Token::ParenthesisClose) => break,
+                    Some(Token::Argument(option)) => {
+                        let value = tokens
+                            .next()
+                            .ok_or_else(|| {
+                                bad(self.tag.to_compact_string(), "Missing option value.")
+                            })?
+                            .unwrap_bytes();
+                        if option.eq_ignore_ascii_case(b"MAXSIZE") {
+                            max_size = Some(
+                                parse_number::<u32>(&value)
+                                    .map_err(|v| bad(self.tag.to_compact_string(), v))?,
+                            );
+                        } else if option.eq_ignore_ascii_case(b"DEPTH") {
+                            depth = match value.as_slice() {
+                                b"0" => Depth::Zero,
+                                b"1" => Depth::One,
+                                _ if value.eq_ignore_ascii_case(b"infinity") => Depth::Infinity,
+                                _ => {
+                                    return Err(bad(
+                                        self.tag.to_compact_string(),
+                                        "Invalid DEPTH value.",
+                                    ));
+                                }
+                            };
+                        } else {
+                            return Err(bad(
+                                self.tag.to_compact_string(),
+                                "Unsupported GETMETADATA option.",
+                            ));
+                        }
+                    }
+                    _ => {
+                        return Err(bad(
+                            self.tag.to_compact_string(),
+                            "Invalid GETMETADATA options.",
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mailbox_name = utf7_maybe_decode(
+            tokens
+                .next()
+                .ok_or_else(|| bad(self.tag.to_compact_string(), "Missing mailbox name."))?
+                .unwrap_string()
+                .map_err(|v| bad(self.tag.to_compact_string(), v))?,
+            version,
+        );
+
+        // Parse entries
+        let mut entries = Vec::new();
+        let is_list = tokens
+            .next_if(|token| token.is_parenthesis_open())
+            .is_some();
+        for token in tokens {
+            match token {
+                Token::ParenthesisClose if is_list => break,
+                Token::Argument(_) => {
+                    entries.push(
+                        token
+                            .unwrap_string()
+                            .map_err(|v| bad(self.tag.to_compact_string(), v))?,
+                    );
+                    if !is_list {
+                        break;
+                    }
+                }
+                _ => {
+                    return Err(bad(self.tag.to_compact_string(), "Invalid metadata entry."));
+                }
+            }
+        }
+
+        if !entries.is_empty() {
+            Ok(metadata::Arguments {
+                tag: self.tag,
+                mailbox_name,
+                entries,
+                max_size,
+                depth,
+            })
+        } else {
+            Err(bad(
+                self.tag.to_compact_string(),
+                "At least one metadata entry is required.",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{
+            ProtocolVersion,
+            metadata::{self, Depth},
+        },
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_get_metadata() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                "a GETMETADATA INBOX /shared/comment\r\n",
+                metadata::Arguments {
+                    tag: "a".into(),
+                    mailbox_name: "INBOX".into(),
+                    entries: vec!["/shared/comment".into()],
+                    max_size: None,
+                    depth: Depth::Zero,
+                },
+            ),
+            (
+                "a GETMETADATA \"my box\" (/shared/comment /private/comment)\r\n",
+                metadata::Arguments {
+                    tag: "a".into(),
+                    mailbox_name: "my box".into(),
+                    entries: vec!["/shared/comment".into(), "/private/comment".into()],
+                    max_size: None,
+                    depth: Depth::Zero,
+                },
+            ),
+            (
+                "a GETMETADATA (MAXSIZE 1024 DEPTH infinity) INBOX /shared/vendor\r\n",
+                metadata::Arguments {
+                    tag: "a".into(),
+                    mailbox_name: "INBOX".into(),
+                    entries: vec!["/shared/vendor".into()],
+                    max_size: Some(1024),
+                    depth: Depth::Infinity,
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_get_metadata(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{command}"
+            );
+        }
+
+        for command in [
+            "a GETMETADATA INBOX\r\n",
+            "a GETMETADATA (DEPTH 2) INBOX /shared/comment\r\n",
+        ] {
+            assert!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_get_metadata(ProtocolVersion::Rev2)
+                    .is_err(),
+                "{command}"
+            );
+        }
+    }
+}
@@ -6,7 +6,9 @@
 
 pub mod acl;
 pub mod append;
+pub mod apple_push;
 pub mod authenticate;
+pub mod compress;
 pub mod copy_move;
 pub mod create;
 pub mod delete;
@@ -15,6 +17,7 @@ pub mod fetch;
 pub mod list;
 pub mod login;
 pub mod lsub;
+pub mod metadata;
 pub mod quota;
 pub mod rename;
 pub mod search;
@@ -80,6 +83,10 @@ impl CommandParser for Command {
             "ID" => Command::Id,
             "GETQUOTA" => Command::GetQuota,
             "GETQUOTAROOT" => Command::GetQuotaRoot,
+            "COMPRESS" => Command::Compress,
+            "GETMETADATA" => Command::GetMetadata,
+            "SETMETADATA" => Command::SetMetadata,
+            "XAPPLEPUSHSERVICE" => Command::XApplePushService,
         )
     }
 
@@ -104,6 +104,7 @@ pub fn parse_filters(
     let mut filters_len = 0;
     let mut filters_stack = Vec::new();
     let mut operator = Filter::And;
+    let mut is_fuzzy = false;
 
     while let Some(token) = tokens.next() {
         let mut found_parenthesis = false;
@@ -443,11 +444,32 @@ pub fn parse_filters(
                         operator = Filter::Not;
                         continue;
                     },
+                    "FUZZY" => {
+                        is_fuzzy = true;
+                        continue;
+                    },
                     _ => {
                         filters.push(Filter::Sequence(parse_sequence_set(&value)?, false));
                     }
                 );
 
+                // Fuzzy matching only applies to text search keys, others are matched exactly
+                if std::mem::take(&mut is_fuzzy) {
+                    if let Some(filter) = filters.pop() {
+                        filters.push(match filter {
+                            Filter::Bcc(_)
+                            | Filter::Body(_)
+                            | Filter::Cc(_)
+                            | Filter::From(_)
+                            | Filter::Header(_, _)
+                            | Filter::Subject(_)
+                            | Filter::Text(_)
+                            | Filter::To(_) => Filter::Fuzzy(Box::new(filter)),
+                            filter => filter,
+                        });
+                    }
+                }
+
                 filters_len += 1;
             }
             Token::ParenthesisOpen => {
@@ -526,6 +548,7 @@ impl ResultOption {
             "count" => Self::Count,
             "save" => Self::Save,
             "context" => Self::Context,
+            "relevancy" => Self::Relevancy,
         )
         .ok_or_else(|| {
             format!(
@@ -853,6 +876,20 @@ mod tests {
                     sort: None,
                 },
             ),
+            (
+                b"6 SEARCH RETURN (ALL RELEVANCY) FUZZY SUBJECT \"meeting\" FUZZY LARGER 100\r\n"
+                    .to_vec(),
+                search::Arguments {
+                    tag: "6".into(),
+                    result_options: vec![ResultOption::All, ResultOption::Relevancy],
+                    filter: vec![
+                        Filter::Fuzzy(Box::new(Filter::Subject("meeting".into()))),
+                        Filter::Larger(100),
+                    ],
+                    is_esearch: true,
+                    sort: None,
+                },
+            ),
         ] {
             let command_str = String::from_utf8_lossy(&command).into_owned();
             assert_eq!(
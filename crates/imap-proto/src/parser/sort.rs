@@ -113,6 +113,7 @@ impl Sort {
             "TO" => Self::To,
             "DISPLAYFROM" => Self::DisplayFrom,
             "DISPLAYTO" => Self::DisplayTo,
+            "RELEVANCY" => Self::Relevancy,
         )
         .ok_or_else(|| format!("Invalid sort criteria {:?}", String::from_utf8_lossy(value)).into())
     }
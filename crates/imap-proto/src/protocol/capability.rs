@@ -31,6 +31,7 @@ pub enum Capability {
     Within,
     Enable,
     SearchRes,
+    SearchFuzzy, //SEARCH=FUZZY
     Sort,
     Thread,       //THREAD=REFERENCES
     ListExtended, //LIST-EXTENDED
@@ -53,6 +54,9 @@ pub enum Capability {
     QuotaResource(QuotaResourceName),
     QuotaSet,
     JmapAccess,
+    CompressDeflate, //COMPRESS=DEFLATE
+    Metadata,
+    XApplePushService,
 }
 
 /*
@@ -105,6 +109,7 @@ impl Capability {
             Capability::Within => b"WITHIN",
             Capability::Enable => b"ENABLE",
             Capability::SearchRes => b"SEARCHRES",
+            Capability::SearchFuzzy => b"SEARCH=FUZZY",
             Capability::Sort => b"SORT",
             Capability::Thread => b"THREAD=REFERENCES",
             Capability::ListExtended => b"LIST-EXTENDED",
@@ -128,10 +133,19 @@ impl Capability {
             }
             Capability::QuotaSet => b"QUOTA=SET",
             Capability::JmapAccess => b"JMAPACCESS",
+            Capability::CompressDeflate => b"COMPRESS=DEFLATE",
+            Capability::Metadata => b"METADATA",
+            Capability::XApplePushService => b"XAPPLEPUSHSERVICE",
         });
     }
 
-    pub fn all_capabilities(is_authenticated: bool, offer_tls: bool) -> Vec<Capability> {
+    pub fn all_capabilities(
+        is_authenticated: bool,
+        offer_tls: bool,
+        offer_compress: bool,
+        offer_apple_push: bool,
+        mechanisms: &[Mechanism],
+    ) -> Vec<Capability> {
         let mut capabilities = vec![
             Capability::IMAP4rev2,
             Capability::IMAP4rev1,
@@ -156,6 +170,7 @@ impl Capability {
                 Capability::ESearch,
                 Capability::Within,
                 Capability::SearchRes,
+                Capability::SearchFuzzy,
                 Capability::Sort,
                 Capability::Thread,
                 Capability::ListExtended,
@@ -173,13 +188,19 @@ impl Capability {
                 Capability::Preview,
                 Capability::Quota,
                 Capability::QuotaResource(QuotaResourceName::Storage),
+                Capability::Metadata,
             ]);
+            if offer_compress {
+                capabilities.push(Capability::CompressDeflate);
+            }
+            if offer_apple_push {
+                capabilities.push(Capability::XApplePushService);
+            }
         } else {
-            capabilities.extend([
-                Capability::Auth(Mechanism::Plain),
-                Capability::Auth(Mechanism::OAuthBearer),
-                Capability::Auth(Mechanism::XOauth2),
-            ]);
+            capabilities.extend(mechanisms.iter().cloned().map(Capability::Auth));
+            if !mechanisms.contains(&Mechanism::Plain) {
+                capabilities.push(Capability::LoginDisabled);
+            }
         }
         if offer_tls {
             capabilities.push(Capability::StartTLS);
@@ -222,5 +243,12 @@ mod tests {
             .serialize(),
             concat!("* CAPABILITY IMAP4rev2 STARTTLS LOGINDISABLED\r\n",).as_bytes()
         );
+        assert_eq!(
+            &Response {
+                capabilities: vec![Capability::IMAP4rev2, Capability::CompressDeflate],
+            }
+            .serialize(),
+            concat!("* CAPABILITY IMAP4rev2 COMPRESS=DEFLATE\r\n",).as_bytes()
+        );
     }
 }
@@ -0,0 +1,115 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{ImapResponse, quoted_or_literal_string, quoted_string};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arguments {
+    pub tag: String,
+    pub mailbox_name: String,
+    pub entries: Vec<String>,
+    pub max_size: Option<u32>,
+    pub depth: Depth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Depth {
+    #[default]
+    Zero,
+    One,
+    Infinity,
+}
+
+pub struct Response {
+    pub mailbox_name: String,
+    pub entries: Vec<(String, String)>,
+}
+
+impl ImapResponse for Response {
+    fn serialize(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        if !self.entries.is_empty() {
+            buf.extend_from_slice(b"* METADATA ");
+            quoted_string(&mut buf, &self.mailbox_name);
+            buf.extend_from_slice(b" (");
+            for (pos, (entry, value)) in self.entries.iter().enumerate() {
+                if pos > 0 {
+                    buf.push(b' ');
+                }
+                buf.extend_from_slice(entry.as_bytes());
+                buf.push(b' ');
+                quoted_or_literal_string(&mut buf, value);
+            }
+            buf.extend_from_slice(b")\r\n");
+        }
+        buf
+    }
+}
+
+impl Depth {
+    pub fn includes(&self, prefix: &str, entry: &str) -> bool {
+        match entry
+            .strip_prefix(prefix)
+            .and_then(|child| child.strip_prefix('/'))
+        {
+            Some(child) => match self {
+                Depth::Zero => false,
+                Depth::One => !child.contains('/'),
+                Depth::Infinity => true,
+            },
+            None => prefix == entry,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::ImapResponse;
+
+    use super::Depth;
+
+    #[test]
+    fn serialize_metadata() {
+        for (response, expected) in [
+            (
+                super::Response {
+                    mailbox_name: "INBOX".into(),
+                    entries: vec![],
+                },
+                "",
+            ),
+            (
+                super::Response {
+                    mailbox_name: "INBOX".into(),
+                    entries: vec![
+                        (
+                            "/shared/vendor/vendor.stalwart/signature/1".into(),
+                            "signed/verified; method=smime; signer=jane@example.org".into(),
+                        ),
+                        (
+                            "/shared/vendor/vendor.stalwart/signature/7".into(),
+                            "signed/failed; method=pgp".into(),
+                        ),
+                    ],
+                },
+                concat!(
+                    "* METADATA \"INBOX\" (/shared/vendor/vendor.stalwart/signature/1 ",
+                    "\"signed/verified; method=smime; signer=jane@example.org\" ",
+                    "/shared/vendor/vendor.stalwart/signature/7 \"signed/failed; method=pgp\")\r\n"
+                ),
+            ),
+        ] {
+            assert_eq!(String::from_utf8(response.serialize()).unwrap(), expected);
+        }
+
+        assert!(Depth::Zero.includes("/shared/a", "/shared/a"));
+        assert!(!Depth::Zero.includes("/shared/a", "/shared/a/1"));
+        assert!(Depth::One.includes("/shared/a", "/shared/a/1"));
+        assert!(!Depth::One.includes("/shared/a", "/shared/a/1/2"));
+        assert!(!Depth::One.includes("/shared/a", "/shared/ab"));
+        assert!(Depth::Infinity.includes("/shared/a", "/shared/a/1/2"));
+    }
+}
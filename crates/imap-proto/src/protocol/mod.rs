@@ -16,8 +16,10 @@ use crate::{Command, ResponseCode, ResponseType, StatusResponse};
 
 pub mod acl;
 pub mod append;
+pub mod apple_push;
 pub mod authenticate;
 pub mod capability;
+pub mod compress;
 pub mod copy_move;
 pub mod create;
 pub mod delete;
@@ -26,6 +28,7 @@ pub mod expunge;
 pub mod fetch;
 pub mod list;
 pub mod login;
+pub mod metadata;
 pub mod namespace;
 pub mod quota;
 pub mod rename;
@@ -402,6 +405,12 @@ impl ResponseCode {
                 return;
             }
             ResponseCode::UseAttr => b"USEATTR",
+            ResponseCode::CompressionActive => b"COMPRESSIONACTIVE",
+            ResponseCode::MetadataLongEntries { size } => {
+                buf.extend_from_slice(b"METADATA LONGENTRIES ");
+                buf.extend_from_slice(size.to_string().as_bytes());
+                return;
+            }
         });
     }
 
@@ -445,6 +454,8 @@ impl ResponseCode {
             ResponseCode::MailboxId { .. } => "MAILBOXID",
             ResponseCode::HighestModseq { .. } => "HIGHESTMODSEQ",
             ResponseCode::UseAttr => "USEATTR",
+            ResponseCode::CompressionActive => "COMPRESSIONACTIVE",
+            ResponseCode::MetadataLongEntries { .. } => "METADATA",
         }
     }
 }
@@ -636,6 +647,10 @@ impl Display for Command {
             Command::Id => write!(f, "ID"),
             Command::GetQuota => write!(f, "GETQUOTA"),
             Command::GetQuotaRoot => write!(f, "GETQUOTAROOT"),
+            Command::Compress => write!(f, "COMPRESS"),
+            Command::GetMetadata => write!(f, "GETMETADATA"),
+            Command::SetMetadata => write!(f, "SETMETADATA"),
+            Command::XApplePushService => write!(f, "XAPPLEPUSHSERVICE"),
         }
     }
 }
@@ -28,6 +28,9 @@ pub enum Sort {
     Subject,
     To,
     DisplayTo,
+
+    // RFC 6203 - SEARCH=FUZZY
+    Relevancy,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -46,6 +49,7 @@ pub struct Response {
     pub max: Option<u32>,
     pub count: Option<u32>,
     pub highest_modseq: Option<u64>,
+    pub relevancy: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -56,6 +60,7 @@ pub enum ResultOption {
     Count,
     Save,
     Context,
+    Relevancy,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -112,6 +117,9 @@ pub enum Filter {
     // RFC 8474 - ObjectID
     EmailId(String),
     ThreadId(String),
+
+    // RFC 6203 - SEARCH=FUZZY
+    Fuzzy(Box<Filter>),
 }
 
 impl FilterItem for Filter {
@@ -124,7 +132,8 @@ impl FilterItem for Filter {
             | Filter::Subject(_)
             | Filter::Body(_)
             | Filter::Text(_)
-            | Filter::Header(_, _) => FilterType::Fts,
+            | Filter::Header(_, _)
+            | Filter::Fuzzy(_) => FilterType::Fts,
             Filter::And => FilterType::And,
             Filter::Or => FilterType::Or,
             Filter::Not => FilterType::Not,
@@ -194,6 +203,16 @@ impl Response {
                 buf.extend_from_slice(b" MODSEQ ");
                 buf.extend_from_slice(highest_modseq.to_string().as_bytes());
             }
+            if let Some(relevancy) = &self.relevancy {
+                buf.extend_from_slice(b" RELEVANCY (");
+                for (pos, score) in relevancy.iter().enumerate() {
+                    if pos > 0 {
+                        buf.push(b' ');
+                    }
+                    buf.extend_from_slice(score.to_string().as_bytes());
+                }
+                buf.push(b')');
+            }
         } else {
             if !self.is_sort {
                 buf.extend_from_slice(b"* SEARCH");
@@ -233,6 +252,7 @@ mod tests {
                     max: 11.into(),
                     count: 3.into(),
                     highest_modseq: None,
+                    relevancy: None,
                 },
                 "A283",
                 concat!("* ESEARCH (TAG \"A283\") COUNT 3 MIN 2 MAX 11 ALL 2,10:11\r\n",),
@@ -250,6 +270,7 @@ mod tests {
                     max: None,
                     count: None,
                     highest_modseq: None,
+                    relevancy: None,
                 },
                 "A283",
                 concat!("* ESEARCH (TAG \"A283\") ALL 1:3,5,10:13,90,92:99\r\n",),
@@ -265,6 +286,7 @@ mod tests {
                     max: None,
                     count: None,
                     highest_modseq: None,
+                    relevancy: None,
                 },
                 "A283",
                 concat!("* ESEARCH (TAG \"A283\")\r\n",),
@@ -280,11 +302,28 @@ mod tests {
                     max: None,
                     count: None,
                     highest_modseq: 12345.into(),
+                    relevancy: None,
                 },
                 "A283",
                 concat!("* ESEARCH (TAG \"A283\") ALL 10:13,21 MODSEQ 12345\r\n",),
                 concat!("* SEARCH 10 11 12 13 21 (MODSEQ 12345)\r\n",),
             ),
+            (
+                super::Response {
+                    is_uid: true,
+                    is_esearch: true,
+                    is_sort: false,
+                    ids: vec![4, 7, 9],
+                    min: None,
+                    max: None,
+                    count: None,
+                    highest_modseq: None,
+                    relevancy: vec![100, 50, 34].into(),
+                },
+                "A284",
+                concat!("* ESEARCH (TAG \"A284\") UID ALL 4,7,9 RELEVANCY (100 50 34)\r\n",),
+                concat!("* SEARCH 4 7 9\r\n"),
+            ),
         ] {
             let response_v2 = String::from_utf8(response.clone().serialize(tag)).unwrap();
             response.is_esearch = false;
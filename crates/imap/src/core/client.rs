@@ -10,6 +10,7 @@ use common::{
     KV_RATE_LIMIT_IMAP,
     listener::{SessionResult, SessionStream},
 };
+use directory::core::app_password::AuthProtocol;
 use imap_proto::{
     Command, ResponseType, StatusResponse,
     receiver::{self, Request},
@@ -239,6 +240,18 @@ impl<T: SessionStream> Session<T> {
                     .handle_get_quota_root(request)
                     .await
                     .map(|_| SessionResult::Continue),
+                Command::GetMetadata => self
+                    .handle_get_metadata(request)
+                    .await
+                    .map(|_| SessionResult::Continue),
+                Command::SetMetadata => self
+                    .handle_set_metadata(request)
+                    .await
+                    .map(|_| SessionResult::Continue),
+                Command::XApplePushService => self
+                    .handle_apple_push(request)
+                    .await
+                    .map(|_| SessionResult::Continue),
                 Command::Unauthenticate => self
                     .handle_unauthenticate(request)
                     .await
@@ -247,6 +260,10 @@ impl<T: SessionStream> Session<T> {
                     .handle_id(request)
                     .await
                     .map(|_| SessionResult::Continue),
+                Command::Compress => self
+                    .handle_compress(request)
+                    .await
+                    .map(|_| SessionResult::UpgradeCompress),
             };
 
             match result {
@@ -313,12 +330,30 @@ impl<T: SessionStream> Session<T> {
                     return Err(trc::LimitEvent::TooManyRequests.into_err());
                 }
             }
+
+            // Audit commands issued while impersonating an account
+            if data.access_token.impersonator.is_some() {
+                data.server
+                    .record_impersonation(
+                        &data.access_token,
+                        AuthProtocol::Imap,
+                        self.remote_addr,
+                        request.command.to_string(),
+                        self.session_id,
+                    )
+                    .await;
+            }
         }
 
         match &request.command {
             Command::Capability | Command::Noop | Command::Logout | Command::Id => Ok(request),
             Command::StartTls => {
-                if !self.is_tls {
+                if self.is_compressed {
+                    Err(trc::ImapEvent::Error
+                        .into_err()
+                        .details("STARTTLS is not allowed after COMPRESS.")
+                        .id(request.tag))
+                } else if !self.is_tls {
                     if self.instance.acceptor.is_tls() {
                         Ok(request)
                     } else {
@@ -346,13 +381,18 @@ impl<T: SessionStream> Session<T> {
             }
             Command::Login => {
                 if let State::NotAuthenticated { .. } = state {
-                    if self.is_tls || self.server.core.imap.allow_plain_auth {
+                    if self.allow_plain_auth {
                         Ok(request)
-                    } else {
+                    } else if !self.is_tls {
                         Err(trc::ImapEvent::Error
                             .into_err()
                             .details("LOGIN is disabled on the clear-text port.")
                             .id(request.tag))
+                    } else {
+                        Err(trc::ImapEvent::Error
+                            .into_err()
+                            .details("LOGIN is disabled for this TLS version.")
+                            .id(request.tag))
                     }
                 } else {
                     Err(trc::ImapEvent::Error
@@ -361,6 +401,21 @@ impl<T: SessionStream> Session<T> {
                         .id(request.tag))
                 }
             }
+            Command::Compress => {
+                if !self.server.core.imap.compress_enable {
+                    Err(trc::ImapEvent::Error
+                        .into_err()
+                        .details("Compression is not available.")
+                        .id(request.tag))
+                } else if let State::Authenticated { .. } | State::Selected { .. } = state {
+                    Ok(request)
+                } else {
+                    Err(trc::ImapEvent::Error
+                        .into_err()
+                        .details("Not authenticated.")
+                        .id(request.tag))
+                }
+            }
             Command::Enable
             | Command::Select
             | Command::Examine
@@ -382,7 +437,10 @@ impl<T: SessionStream> Session<T> {
             | Command::MyRights
             | Command::Unauthenticate
             | Command::GetQuota
-            | Command::GetQuotaRoot => {
+            | Command::GetQuotaRoot
+            | Command::GetMetadata
+            | Command::SetMetadata
+            | Command::XApplePushService => {
                 if let State::Authenticated { .. } | State::Selected { .. } = state {
                     Ok(request)
                 } else {
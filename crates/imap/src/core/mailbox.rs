@@ -16,11 +16,11 @@ use common::{
 
 use directory::backend::internal::manage::ManageDirectory;
 use email::{
-    cache::{MessageCacheFetch, email::MessageCacheAccess, mailbox::MailboxCacheAccess},
-    mailbox::INBOX_ID,
+    cache::{MessageCacheFetch, mailbox::MailboxCacheAccess},
+    mailbox::{INBOX_ID, counters::MailboxCounterAccess},
 };
 use imap_proto::protocol::list::Attribute;
-use jmap_proto::types::{acl::Acl, collection::Collection, id::Id, keyword::Keyword};
+use jmap_proto::types::{acl::Acl, collection::Collection, id::Id};
 use parking_lot::Mutex;
 use std::{
     collections::BTreeMap,
@@ -158,6 +158,11 @@ impl<T: SessionStream> SessionData<T> {
             account
                 .mailbox_names
                 .insert(mailbox_name, effective_mailbox_id);
+            let counters = self
+                .server
+                .mailbox_counters(account_id, mailbox.document_id)
+                .await
+                .caused_by(trc::location!())?;
             account.mailbox_state.insert(
                 mailbox.document_id,
                 Mailbox {
@@ -176,13 +181,9 @@ impl<T: SessionStream> SessionData<T> {
                         SpecialUse::Important => Some(Attribute::Important),
                         _ => None,
                     },
-                    total_messages: cache.in_mailbox(mailbox.document_id).count() as u64,
-                    total_unseen: cache
-                        .in_mailbox_without_keyword(mailbox.document_id, &Keyword::Seen)
-                        .count() as u64,
-                    total_deleted: cache
-                        .in_mailbox_with_keyword(mailbox.document_id, &Keyword::Deleted)
-                        .count() as u64,
+                    total_messages: counters.total,
+                    total_unseen: counters.unseen,
+                    total_deleted: counters.deleted,
                     uid_validity: mailbox.uid_validity as u64,
                     uid_next: self
                         .get_uid_next(&MailboxId {
@@ -13,7 +13,7 @@ use std::{
 use ahash::AHashMap;
 use common::{
     Inner, Server,
-    auth::AccessToken,
+    auth::{AccessToken, scram::ScramSession},
     listener::{ServerInstance, SessionStream, limiter::InFlight},
 };
 
@@ -51,8 +51,13 @@ pub struct Session<T: SessionStream> {
     pub version: ProtocolVersion,
     pub state: State<T>,
     pub is_tls: bool,
+    pub is_compressed: bool,
     pub is_condstore: bool,
     pub is_qresync: bool,
+    pub allow_plain_auth: bool,
+    pub channel_binding: Option<Vec<u8>>,
+    pub client_certificate: Option<Vec<u8>>,
+    pub scram: Option<ScramSession>,
     pub stream_rx: ReadHalf<T>,
     pub stream_tx: Arc<tokio::sync::Mutex<WriteHalf<T>>>,
     pub in_flight: InFlight,
@@ -75,10 +80,17 @@ pub struct SelectedMailbox {
     pub id: MailboxId,
     pub state: parking_lot::Mutex<MailboxState>,
     pub saved_search: parking_lot::Mutex<SavedSearch>,
+    pub thread_cache: parking_lot::Mutex<Option<Arc<ThreadCache>>>,
     pub is_select: bool,
     pub is_condstore: bool,
 }
 
+#[derive(Debug, Default)]
+pub struct ThreadCache {
+    pub change_id: u64,
+    pub threads: Vec<Vec<u32>>,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct AccountId {
     pub account_id: u32,
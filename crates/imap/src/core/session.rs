@@ -8,7 +8,10 @@ use std::sync::Arc;
 
 use common::{
     core::BuildServer,
-    listener::{SessionData, SessionManager, SessionResult, SessionStream, stream::NullIo},
+    listener::{
+        SessionData, SessionManager, SessionResult, SessionStream, compress::DeflateStream,
+        stream::NullIo,
+    },
 };
 use imap_proto::{
     protocol::{ProtocolVersion, SerializeResponse},
@@ -17,7 +20,7 @@ use imap_proto::{
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_rustls::server::TlsStream;
 
-use crate::{GREETING_WITH_TLS, GREETING_WITHOUT_TLS};
+use crate::{auth_mechanisms, greeting};
 
 use super::{ImapSessionManager, Session, State};
 
@@ -29,10 +32,22 @@ impl SessionManager for ImapSessionManager {
     ) -> impl std::future::Future<Output = ()> + Send {
         async move {
             if let Ok(mut session) = Session::new(session, self).await {
-                if session.handle_conn().await && session.instance.acceptor.is_tls() {
-                    if let Ok(mut session) = session.into_tls().await {
-                        session.handle_conn().await;
+                match session.handle_conn().await {
+                    SessionResult::UpgradeTls if session.instance.acceptor.is_tls() => {
+                        if let Ok(mut session) = session.into_tls().await {
+                            if session.handle_conn().await == SessionResult::UpgradeCompress {
+                                if let Ok(mut session) = session.into_compressed().await {
+                                    session.handle_conn().await;
+                                }
+                            }
+                        }
+                    }
+                    SessionResult::UpgradeCompress => {
+                        if let Ok(mut session) = session.into_compressed().await {
+                            session.handle_conn().await;
+                        }
                     }
+                    _ => (),
                 }
             }
         }
@@ -45,7 +60,7 @@ impl SessionManager for ImapSessionManager {
 }
 
 impl<T: SessionStream> Session<T> {
-    pub async fn handle_conn(&mut self) -> bool {
+    pub async fn handle_conn(&mut self) -> SessionResult {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
 
@@ -63,8 +78,9 @@ impl<T: SessionStream> Session<T> {
                             if bytes_read > 0 {
                                 match self.ingest(&buf[..bytes_read]).await {
                                     SessionResult::Continue => (),
-                                    SessionResult::UpgradeTls => {
-                                        return true;
+                                    result @ (SessionResult::UpgradeTls
+                                    | SessionResult::UpgradeCompress) => {
+                                        return result;
                                     }
                                     SessionResult::Close => {
                                         break;
@@ -112,7 +128,7 @@ impl<T: SessionStream> Session<T> {
             };
         }
 
-        false
+        SessionResult::Close
     }
 
     pub async fn new(
@@ -121,13 +137,22 @@ impl<T: SessionStream> Session<T> {
     ) -> Result<Session<T>, ()> {
         // Write greeting
         let is_tls = session.stream.is_tls();
-        let greeting = if !is_tls && session.instance.acceptor.is_tls() {
-            &GREETING_WITH_TLS
-        } else {
-            &GREETING_WITHOUT_TLS
-        };
+        let server = manager.inner.build_server();
+        let allow_plain_auth = session
+            .instance
+            .is_plain_auth_allowed(&session.stream, server.core.imap.allow_plain_auth);
+        let channel_binding = session.stream.tls_channel_binding();
+        let client_certificate = session.stream.tls_client_certificate();
+        let greeting = greeting(
+            !is_tls && session.instance.acceptor.is_tls(),
+            &auth_mechanisms(
+                allow_plain_auth,
+                channel_binding.is_some(),
+                client_certificate.is_some(),
+            ),
+        );
 
-        if let Err(err) = session.stream.write_all(greeting).await {
+        if let Err(err) = session.stream.write_all(&greeting).await {
             trc::event!(
                 Network(trc::NetworkEvent::WriteError),
                 Reason = err.to_string(),
@@ -140,15 +165,19 @@ impl<T: SessionStream> Session<T> {
 
         // Split stream into read and write halves
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
-        let server = manager.inner.build_server();
 
         Ok(Session {
             receiver: Receiver::with_max_request_size(server.core.imap.max_request_size),
             version: ProtocolVersion::Rev1,
             state: State::NotAuthenticated { auth_failures: 0 },
             is_tls,
+            is_compressed: false,
             is_condstore: false,
             is_qresync: false,
+            allow_plain_auth,
+            channel_binding,
+            client_certificate,
+            scram: None,
             server,
             instance: session.instance,
             session_id: session.session_id,
@@ -192,8 +221,13 @@ impl<T: SessionStream> Session<T> {
         };
 
         // Upgrade to TLS
-        let (stream_rx, stream_tx) =
-            tokio::io::split(self.instance.tls_accept(stream, self.session_id).await?);
+        let stream = self.instance.tls_accept(stream, self.session_id).await?;
+        let allow_plain_auth = self
+            .instance
+            .is_plain_auth_allowed(&stream, self.server.core.imap.allow_plain_auth);
+        let channel_binding = stream.tls_channel_binding();
+        let client_certificate = stream.tls_client_certificate();
+        let (stream_rx, stream_tx) = tokio::io::split(stream);
         let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
 
         Ok(Session {
@@ -203,8 +237,76 @@ impl<T: SessionStream> Session<T> {
             version: self.version,
             state: state.try_replace_stream_tx(stream_tx.clone()).unwrap(),
             is_tls: true,
+            is_compressed: false,
+            is_condstore: self.is_condstore,
+            is_qresync: self.is_qresync,
+            allow_plain_auth,
+            channel_binding,
+            client_certificate,
+            scram: None,
+            session_id: self.session_id,
+            in_flight: self.in_flight,
+            remote_addr: self.remote_addr,
+            stream_rx,
+            stream_tx,
+        })
+    }
+
+    pub async fn into_compressed(self) -> Result<Session<DeflateStream<T>>, ()> {
+        // Drop references to write half from state
+        let state = if let Some(state) =
+            self.state
+                .try_replace_stream_tx(Arc::new(tokio::sync::Mutex::new(
+                    tokio::io::split(NullIo::default()).1,
+                ))) {
+            state
+        } else {
+            trc::event!(
+                Network(trc::NetworkEvent::SplitError),
+                SpanId = self.session_id,
+                Details = "Failed to obtain write half state"
+            );
+            return Err(());
+        };
+
+        // Take ownership of WriteHalf and unsplit it from ReadHalf
+        let stream = if let Ok(stream_tx) =
+            Arc::try_unwrap(self.stream_tx).map(|mutex| mutex.into_inner())
+        {
+            self.stream_rx.unsplit(stream_tx)
+        } else {
+            trc::event!(
+                Network(trc::NetworkEvent::SplitError),
+                SpanId = self.session_id,
+                Details = "Failed to take ownership of write half"
+            );
+
+            return Err(());
+        };
+
+        // Wrap stream with DEFLATE compression
+        let stream = DeflateStream::new(
+            stream,
+            self.server.core.imap.compress_level,
+            self.server.core.imap.compress_buffer_size,
+        );
+        let (stream_rx, stream_tx) = tokio::io::split(stream);
+        let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
+
+        Ok(Session {
+            server: self.server,
+            instance: self.instance,
+            receiver: self.receiver,
+            version: self.version,
+            state: state.try_replace_stream_tx(stream_tx.clone()).unwrap(),
+            is_tls: self.is_tls,
+            is_compressed: true,
             is_condstore: self.is_condstore,
             is_qresync: self.is_qresync,
+            allow_plain_auth: self.allow_plain_auth,
+            channel_binding: self.channel_binding,
+            client_certificate: self.client_certificate,
+            scram: None,
             session_id: self.session_id,
             in_flight: self.in_flight,
             remote_addr: self.remote_addr,
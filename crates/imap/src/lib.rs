@@ -4,29 +4,46 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::LazyLock;
-
-use imap_proto::{ResponseCode, StatusResponse, protocol::capability::Capability};
+use imap_proto::{
+    ResponseCode, StatusResponse,
+    protocol::{authenticate::Mechanism, capability::Capability},
+};
 
 pub mod core;
 pub mod op;
 
 static SERVER_GREETING: &str = "Stalwart IMAP4rev2 at your service.";
 
-pub(crate) static GREETING_WITH_TLS: LazyLock<Vec<u8>> = LazyLock::new(|| {
+pub(crate) fn greeting(offer_tls: bool, mechanisms: &[Mechanism]) -> Vec<u8> {
     StatusResponse::ok(SERVER_GREETING)
         .with_code(ResponseCode::Capability {
-            capabilities: Capability::all_capabilities(false, true),
+            capabilities: Capability::all_capabilities(false, offer_tls, false, false, mechanisms),
         })
         .into_bytes()
-});
+}
 
-pub(crate) static GREETING_WITHOUT_TLS: LazyLock<Vec<u8>> = LazyLock::new(|| {
-    StatusResponse::ok(SERVER_GREETING)
-        .with_code(ResponseCode::Capability {
-            capabilities: Capability::all_capabilities(false, false),
-        })
-        .into_bytes()
-});
+pub(crate) fn auth_mechanisms(
+    allow_plain_auth: bool,
+    has_channel_binding: bool,
+    has_client_certificate: bool,
+) -> Vec<Mechanism> {
+    let mut mechanisms = Vec::with_capacity(6);
+    if has_client_certificate {
+        mechanisms.push(Mechanism::External);
+    }
+    if allow_plain_auth {
+        mechanisms.push(Mechanism::Plain);
+    }
+    if has_channel_binding {
+        mechanisms.push(Mechanism::ScramSha256Plus);
+    }
+    mechanisms.push(Mechanism::ScramSha256);
+
+    // Bearer tokens are sent in the clear, same as PLAIN credentials
+    if allow_plain_auth {
+        mechanisms.extend([Mechanism::OAuthBearer, Mechanism::XOauth2]);
+    }
+    mechanisms
+}
 
 pub struct ImapError;
@@ -0,0 +1,113 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use crate::{
+    core::{Session, SessionData},
+    op::ImapContext,
+    spawn_op,
+};
+use common::listener::SessionStream;
+use directory::Permission;
+use email::push::device::{PushDevice, PushDeviceStore, PushGateway};
+use imap_proto::{
+    Command, ResponseCode, StatusResponse,
+    protocol::{
+        ImapResponse,
+        apple_push::{Arguments, Response},
+    },
+    receiver::Request,
+};
+use store::write::now;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_apple_push(&mut self, request: Request<Command>) -> trc::Result<()> {
+        // Validate access
+        self.assert_has_permission(Permission::ImapIdle)?;
+
+        let data = self.state.session_data();
+        let version = self.version;
+
+        spawn_op!(data, {
+            let response = data.apple_push(request.parse_apple_push(version)?).await?;
+            data.write_bytes(response).await
+        })
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    pub async fn apple_push(&self, arguments: Arguments) -> trc::Result<Vec<u8>> {
+        let op_start = Instant::now();
+
+        let Some(topic) = self
+            .server
+            .core
+            .push_gateway
+            .apns
+            .as_ref()
+            .map(|apns| apns.topic.clone())
+        else {
+            return Err(trc::ImapEvent::Error
+                .into_err()
+                .details("Push notifications are not available.")
+                .code(ResponseCode::Cannot)
+                .id(arguments.tag));
+        };
+
+        // Resolve the mailboxes the device wants to be notified about,
+        // older clients do not send a list and expect INBOX notifications.
+        self.synchronize_mailboxes(false)
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
+        let mailboxes = if !arguments.mailboxes.is_empty() {
+            arguments
+                .mailboxes
+                .iter()
+                .filter_map(|name| self.get_mailbox_by_name(name))
+                .filter(|mailbox| mailbox.account_id == self.account_id)
+                .map(|mailbox| mailbox.mailbox_id)
+                .collect::<Vec<_>>()
+        } else {
+            self.get_mailbox_by_name("INBOX")
+                .map(|mailbox| vec![mailbox.mailbox_id])
+                .unwrap_or_default()
+        };
+
+        self.server
+            .register_push_device(
+                self.account_id,
+                PushDevice {
+                    gateway: PushGateway::Apns {
+                        account_id: arguments.account_id,
+                    },
+                    token: arguments.device_token,
+                    mailboxes,
+                    keys: None,
+                    registered_at: now(),
+                },
+            )
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
+
+        trc::event!(
+            Imap(trc::ImapEvent::XApplePushService),
+            SpanId = self.session_id,
+            Details = arguments.subtopic,
+            Elapsed = op_start.elapsed()
+        );
+
+        Ok(StatusResponse::completed(Command::XApplePushService)
+            .with_tag(arguments.tag)
+            .serialize(
+                Response {
+                    version: arguments.version.min(2),
+                    topic,
+                }
+                .serialize(),
+            ))
+    }
+}
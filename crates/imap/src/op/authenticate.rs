@@ -6,29 +6,48 @@
 
 use common::{
     auth::{
-        AuthRequest,
+        AccessToken, AuthRequest,
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
+        scram::{ScramSession, ScramStep},
     },
     listener::{SessionStream, limiter::LimiterResult},
 };
 
-use directory::Permission;
+use directory::{Permission, core::app_password::AuthProtocol};
 use imap_proto::{
     Command, ResponseCode, StatusResponse,
-    protocol::{authenticate::Mechanism, capability::Capability},
+    protocol::{
+        authenticate::{self, Mechanism},
+        capability::Capability,
+    },
     receiver::{self, Request},
 };
+use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 use std::sync::Arc;
 
-use crate::core::{Session, SessionData, State};
+use crate::{
+    auth_mechanisms,
+    core::{Session, SessionData, State},
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_authenticate(&mut self, request: Request<Command>) -> trc::Result<()> {
         let mut args = request.parse_authenticate()?;
 
         match args.mechanism {
+            Mechanism::Plain | Mechanism::OAuthBearer | Mechanism::XOauth2
+                if !self.allow_plain_auth =>
+            {
+                Err(trc::AuthEvent::Error
+                    .into_err()
+                    .details("Plain text authentication is disabled for this session.")
+                    .id(args.tag)
+                    .code(ResponseCode::PrivacyRequired))
+            }
+            Mechanism::ScramSha256 | Mechanism::ScramSha256Plus => self.handle_scram(args).await,
+            Mechanism::External => self.handle_external(args).await,
             Mechanism::Plain | Mechanism::OAuthBearer | Mechanism::XOauth2 => {
                 if !args.params.is_empty() {
                     let challenge = base64_decode(args.params.pop().unwrap().as_bytes())
@@ -54,13 +73,8 @@ impl<T: SessionStream> Session<T> {
 
                     self.authenticate(credentials, args.tag).await
                 } else {
-                    self.receiver.request = receiver::Request {
-                        tag: args.tag,
-                        command: Command::Authenticate,
-                        tokens: vec![receiver::Token::Argument(args.mechanism.into_bytes())],
-                    };
-                    self.receiver.state = receiver::State::Argument { last_ch: b' ' };
-                    self.write_bytes(b"+ \r\n".to_vec()).await
+                    self.request_sasl_continuation(args, b"+ \r\n".to_vec())
+                        .await
                 }
             }
             _ => Err(trc::AuthEvent::Error
@@ -71,20 +85,168 @@ impl<T: SessionStream> Session<T> {
         }
     }
 
+    async fn handle_scram(&mut self, mut args: authenticate::Arguments) -> trc::Result<()> {
+        // Continue an ongoing exchange or start a new one
+        let mut scram = self.scram.take().unwrap_or_else(|| {
+            ScramSession::new(
+                args.mechanism == Mechanism::ScramSha256Plus,
+                self.auth_mechanisms().contains(&Mechanism::ScramSha256Plus),
+                self.channel_binding.clone(),
+            )
+        });
+        if scram.is_plus() && self.channel_binding.is_none() {
+            return Err(trc::AuthEvent::Error
+                .into_err()
+                .details("Channel binding is not available for this session.")
+                .id(args.tag)
+                .code(ResponseCode::Cannot));
+        }
+
+        let message = match args.params.pop() {
+            Some(param) if param != "=" => base64_decode(param.as_bytes()).ok_or_else(|| {
+                trc::AuthEvent::Error
+                    .into_err()
+                    .details("Failed to decode challenge.")
+                    .id(args.tag.clone())
+                    .code(ResponseCode::Parse)
+            })?,
+            Some(_) => Vec::new(),
+            None if !scram.has_started() => {
+                // SCRAM is client-first, request the initial client message
+                self.scram = Some(scram);
+                return self
+                    .request_sasl_continuation(args, b"+ \r\n".to_vec())
+                    .await;
+            }
+            None => Vec::new(),
+        };
+
+        let result = self
+            .server
+            .authenticate_scram(
+                &mut scram,
+                &message,
+                self.session_id,
+                self.remote_addr,
+                None,
+            )
+            .await;
+
+        match result {
+            Ok(ScramStep::Challenge(challenge)) => {
+                self.scram = Some(scram);
+                let mut response = Vec::with_capacity(challenge.len() * 4 / 3 + 6);
+                response.extend_from_slice(b"+ ");
+                response.extend(base64_encode(&challenge).unwrap_or_default());
+                response.extend_from_slice(b"\r\n");
+                self.request_sasl_continuation(args, response).await
+            }
+            Ok(ScramStep::Success(access_token)) => {
+                self.handle_auth_result(Ok(access_token), args.tag).await
+            }
+            Err(err) => self.handle_auth_result(Err(err), args.tag).await,
+        }
+    }
+
+    async fn handle_external(&mut self, mut args: authenticate::Arguments) -> trc::Result<()> {
+        let Some(certificate) = self.client_certificate.clone() else {
+            return Err(trc::AuthEvent::Error
+                .into_err()
+                .details("No client certificate was presented.")
+                .id(args.tag)
+                .code(ResponseCode::Cannot));
+        };
+
+        let authzid = match args.params.pop() {
+            Some(param) if param.is_empty() || param == "=" => None,
+            Some(param) => Some(
+                base64_decode(param.as_bytes())
+                    .and_then(|authzid| String::from_utf8(authzid).ok())
+                    .ok_or_else(|| {
+                        trc::AuthEvent::Error
+                            .into_err()
+                            .details("Failed to decode challenge.")
+                            .id(args.tag.clone())
+                            .code(ResponseCode::Parse)
+                    })?,
+            )
+            .filter(|authzid| !authzid.is_empty()),
+            None => {
+                // Seed an empty authorization identity so that an empty continuation
+                // line is not mistaken for a missing initial response
+                self.receiver.request = receiver::Request {
+                    tag: args.tag,
+                    command: Command::Authenticate,
+                    tokens: vec![
+                        receiver::Token::Argument(args.mechanism.into_bytes()),
+                        receiver::Token::Argument(b"=".to_vec()),
+                    ],
+                };
+                self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+                return self.write_bytes(b"+ \r\n".to_vec()).await;
+            }
+        };
+
+        let result = self
+            .server
+            .authenticate_external(
+                &certificate,
+                authzid.as_deref(),
+                &self.instance,
+                self.session_id,
+                self.remote_addr,
+                None,
+            )
+            .await;
+
+        self.handle_auth_result(result, args.tag).await
+    }
+
+    async fn request_sasl_continuation(
+        &mut self,
+        args: authenticate::Arguments,
+        response: Vec<u8>,
+    ) -> trc::Result<()> {
+        self.receiver.request = receiver::Request {
+            tag: args.tag,
+            command: Command::Authenticate,
+            tokens: vec![receiver::Token::Argument(args.mechanism.into_bytes())],
+        };
+        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+        self.write_bytes(response).await
+    }
+
+    pub fn auth_mechanisms(&self) -> Vec<Mechanism> {
+        auth_mechanisms(
+            self.allow_plain_auth,
+            self.channel_binding.is_some(),
+            self.client_certificate.is_some(),
+        )
+    }
+
     pub async fn authenticate(
         &mut self,
         credentials: Credentials<String>,
         tag: String,
     ) -> trc::Result<()> {
         // Authenticate
-        let access_token = self
+        let result = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
-                credentials,
-                self.session_id,
-                self.remote_addr,
-            ))
-            .await
+            .authenticate(
+                &AuthRequest::from_credentials(credentials, self.session_id, self.remote_addr)
+                    .with_protocol(AuthProtocol::Imap),
+            )
+            .await;
+
+        self.handle_auth_result(result, tag).await
+    }
+
+    async fn handle_auth_result(
+        &mut self,
+        result: trc::Result<Arc<AccessToken>>,
+        tag: String,
+    ) -> trc::Result<()> {
+        let access_token = result
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
                     let auth_failures = self.state.auth_failures();
@@ -116,6 +278,16 @@ impl<T: SessionStream> Session<T> {
             LimiterResult::Disabled => None,
         };
 
+        self.server
+            .record_impersonation(
+                &access_token,
+                AuthProtocol::Imap,
+                self.remote_addr,
+                "AUTHENTICATE",
+                self.session_id,
+            )
+            .await;
+
         // Create session
         self.state = State::Authenticated {
             data: Arc::new(
@@ -130,6 +302,9 @@ impl<T: SessionStream> Session<T> {
                     capabilities: Capability::all_capabilities(
                         true,
                         !self.is_tls && self.instance.acceptor.is_tls(),
+                        self.server.core.imap.compress_enable && !self.is_compressed,
+                        self.server.core.push_gateway.apns.is_some(),
+                        &[],
                     ),
                 })
                 .with_tag(tag)
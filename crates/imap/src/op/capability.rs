@@ -28,7 +28,7 @@ impl<T: SessionStream> Session<T> {
             Imap(trc::ImapEvent::Capabilities),
             SpanId = self.session_id,
             Tls = self.is_tls,
-            Strict = !self.server.core.imap.allow_plain_auth,
+            Strict = !self.allow_plain_auth,
             Elapsed = op_start.elapsed()
         );
 
@@ -40,6 +40,9 @@ impl<T: SessionStream> Session<T> {
                         capabilities: Capability::all_capabilities(
                             self.state.is_authenticated(),
                             !self.is_tls && self.instance.acceptor.is_tls(),
+                            self.server.core.imap.compress_enable && !self.is_compressed,
+                            self.server.core.push_gateway.apns.is_some(),
+                            &self.auth_mechanisms(),
                         ),
                     }
                     .serialize(),
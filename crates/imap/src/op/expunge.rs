@@ -11,8 +11,8 @@ use common::{listener::SessionStream, storage::index::ObjectIndexBuilder};
 use directory::Permission;
 use email::{
     cache::{MessageCacheFetch, email::MessageCacheAccess},
-    mailbox::TOMBSTONE_ID,
-    message::metadata::MessageData,
+    mailbox::{HOLD_ID, TOMBSTONE_ID},
+    message::{hold::LegalHold, metadata::MessageData},
 };
 use imap_proto::{
     Command, ResponseCode, ResponseType, StatusResponse,
@@ -171,6 +171,19 @@ impl<T: SessionStream> SessionData<T> {
             .with_account_id(account_id)
             .with_collection(Collection::Email);
 
+        // Messages of accounts under legal hold are moved to the hold area
+        let legal_hold = self
+            .server
+            .legal_hold(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let tombstone_id = if legal_hold.is_some() {
+            HOLD_ID
+        } else {
+            TOMBSTONE_ID
+        };
+        let mut tombstoned_ids = RoaringBitmap::new();
+
         self.server
             .get_archives(
                 account_id,
@@ -194,8 +207,9 @@ impl<T: SessionStream> SessionData<T> {
                             batch
                                 .custom(ObjectIndexBuilder::<_, ()>::new().with_current(metadata))
                                 .caused_by(trc::location!())?
-                                .tag(Property::MailboxIds, TagValue::Id(TOMBSTONE_ID))
+                                .tag(Property::MailboxIds, TagValue::Id(tombstone_id))
                                 .commit_point();
+                            tombstoned_ids.insert(document_id);
                         } else {
                             // Untag message from this mailbox and remove Deleted flag
                             let mut new_metadata = metadata
@@ -222,6 +236,20 @@ impl<T: SessionStream> SessionData<T> {
             .await
             .caused_by(trc::location!())?;
 
+        if let Some(reason) = legal_hold.filter(|_| !tombstoned_ids.is_empty()) {
+            trc::event!(
+                Purge(trc::PurgeEvent::LegalHoldRetained),
+                SpanId = self.session_id,
+                AccountId = account_id,
+                DocumentId = tombstoned_ids
+                    .iter()
+                    .map(trc::Value::from)
+                    .collect::<Vec<_>>(),
+                Reason = reason,
+                Total = tombstoned_ids.len(),
+            );
+        }
+
         Ok(())
     }
 }
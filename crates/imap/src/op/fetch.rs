@@ -15,8 +15,9 @@ use directory::Permission;
 use email::{
     cache::{MessageCacheFetch, email::MessageCacheAccess},
     message::metadata::{
-        ArchivedMessageMetadata, ArchivedMessageMetadataContents, ArchivedMetadataPartType,
-        DecodedParts, MessageData, MessageMetadata,
+        ArchivedMessageBodyStats, ArchivedMessageMetadata, ArchivedMessageMetadataContents,
+        ArchivedMessagePartStats, ArchivedMetadataPartType, DecodedParts, MessageBodyStats,
+        MessageData, MessageMetadata,
     },
 };
 use imap_proto::{
@@ -252,6 +253,7 @@ impl<T: SessionStream> SessionData<T> {
         // Build properties list
         let mut set_seen_flags = false;
         let mut needs_blobs = false;
+        let mut needs_structure = false;
 
         for attribute in &arguments.attributes {
             match attribute {
@@ -259,7 +261,11 @@ impl<T: SessionStream> SessionData<T> {
                     if sections.first().is_some_and(|s| {
                         matches!(s, Section::Header | Section::HeaderFields { .. })
                     }) => {}
-                Attribute::Body | Attribute::BodyStructure | Attribute::BinarySize { .. } => {
+                Attribute::Body | Attribute::BodyStructure => {
+                    // Served from precomputed body statistics when available
+                    needs_structure = true;
+                }
+                Attribute::BinarySize { .. } => {
                     /*
                         Note that this did not result in \Seen being set, because
                         RFC822.HEADER response data occurs as a result of a FETCH
@@ -353,8 +359,29 @@ impl<T: SessionStream> SessionData<T> {
                 .unarchive::<MessageMetadata>()
                 .imap_ctx(&arguments.tag, trc::location!())?;
 
+            // Obtain precomputed body statistics
+            let body_stats_ = if needs_structure && !needs_blobs {
+                self.server
+                    .get_archive_by_property(account_id, Collection::Email, id, Property::BodyStats)
+                    .await
+                    .imap_ctx(&arguments.tag, trc::location!())?
+            } else {
+                None
+            };
+            let body_stats = if let Some(body_stats_) = &body_stats_ {
+                Some(
+                    body_stats_
+                        .unarchive::<MessageBodyStats>()
+                        .imap_ctx(&arguments.tag, trc::location!())?,
+                )
+                .filter(|body_stats| body_stats.matches(metadata))
+            } else {
+                None
+            };
+
             // Fetch and parse blob
-            let raw_message: Cow<[u8]> = if needs_blobs {
+            let raw_message: Cow<[u8]> = if needs_blobs || (needs_structure && body_stats.is_none())
+            {
                 // Retrieve raw message if needed
                 match self
                     .server
@@ -450,12 +477,12 @@ impl<T: SessionStream> SessionData<T> {
                     }
                     Attribute::Body => {
                         items.push(DataItem::Body {
-                            part: metadata.body_structure(&decoded, false),
+                            part: metadata.body_structure(&decoded, false, body_stats),
                         });
                     }
                     Attribute::BodyStructure => {
                         items.push(DataItem::BodyStructure {
-                            part: metadata.body_structure(&decoded, true),
+                            part: metadata.body_structure(&decoded, true, body_stats),
                         });
                     }
                     Attribute::BodySection {
@@ -623,7 +650,12 @@ impl<T: SessionStream> SessionData<T> {
 
 #[allow(clippy::result_unit_err)]
 pub trait AsImapDataItem {
-    fn body_structure(&self, decoded: &DecodedParts<'_>, is_extended: bool) -> BodyPart;
+    fn body_structure(
+        &self,
+        decoded: &DecodedParts<'_>,
+        is_extended: bool,
+        body_stats: Option<&ArchivedMessageBodyStats>,
+    ) -> BodyPart;
     fn body_section<'x>(
         &self,
         decoded: &'x DecodedParts<'x>,
@@ -647,6 +679,7 @@ pub trait AsImapDataItemPart {
         message_id: usize,
         part_id: usize,
         is_extended: bool,
+        part_stats: Option<&ArchivedMessagePartStats>,
     ) -> BodyPart;
 
     fn envelope(&self) -> Envelope;
@@ -659,9 +692,14 @@ impl AsImapDataItemPart for ArchivedMessageMetadataContents {
         message_id: usize,
         part_id: usize,
         is_extended: bool,
+        part_stats: Option<&ArchivedMessagePartStats>,
     ) -> BodyPart {
         let part = &self.parts[part_id];
-        let body = decoded.raw_message_section_arch(message_id, part.offset_body, part.offset_end);
+        let body = if part_stats.is_none() {
+            decoded.raw_message_section_arch(message_id, part.offset_body, part.offset_end)
+        } else {
+            None
+        };
         let (is_multipart, is_text) = match &part.body {
             ArchivedMetadataPartType::Text | ArchivedMetadataPartType::Html => (false, true),
             ArchivedMetadataPartType::Multipart(_) => (true, false),
@@ -706,7 +744,11 @@ impl AsImapDataItemPart for ArchivedMessageMetadataContents {
                 .header_value(&ArchivedHeaderName::ContentTransferEncoding)
                 .and_then(|ct| ct.as_text().map(|ct| ct.into()));
 
-            fields.body_size_octets = body.as_ref().map(|b| b.len()).unwrap_or(0);
+            fields.body_size_octets = if part_stats.is_some() {
+                u32::from(part.offset_end).saturating_sub(u32::from(part.offset_body)) as usize
+            } else {
+                body.as_ref().map(|b| b.len()).unwrap_or(0)
+            };
 
             if is_text {
                 if fields.body_subtype.is_none() {
@@ -723,9 +765,12 @@ impl AsImapDataItemPart for ArchivedMessageMetadataContents {
 
         if is_extended {
             if !is_multipart {
-                body_md5 = body
-                    .as_ref()
-                    .map(|b| format!("{:x}", md5::compute(b)).into());
+                body_md5 = if let Some(part_stats) = part_stats {
+                    Some(format!("{:x}", md5::Digest(part_stats.md5)).into())
+                } else {
+                    body.as_ref()
+                        .map(|b| format!("{:x}", md5::compute(b)).into())
+                };
             }
 
             extension.body_disposition = part
@@ -782,10 +827,13 @@ impl AsImapDataItemPart for ArchivedMessageMetadataContents {
                 if is_text {
                     BodyPart::Text {
                         fields,
-                        body_size_lines: body
-                            .as_ref()
-                            .map(|b| b.iter().filter(|&&ch| ch == b'\n').count())
-                            .unwrap_or(0),
+                        body_size_lines: if let Some(part_stats) = part_stats {
+                            u32::from(part_stats.lines) as usize
+                        } else {
+                            body.as_ref()
+                                .map(|b| b.iter().filter(|&&ch| ch == b'\n').count())
+                                .unwrap_or(0)
+                        },
                         body_md5,
                         extension,
                     }
@@ -850,7 +898,12 @@ impl AsImapDataItemPart for ArchivedMessageMetadataContents {
 }
 
 impl AsImapDataItem for ArchivedMessageMetadata {
-    fn body_structure(&self, decoded: &DecodedParts<'_>, is_extended: bool) -> BodyPart {
+    fn body_structure(
+        &self,
+        decoded: &DecodedParts<'_>,
+        is_extended: bool,
+        body_stats: Option<&ArchivedMessageBodyStats>,
+    ) -> BodyPart {
         let mut stack = Vec::new();
         let base_part = [u16_le::from_native(0)];
         let mut parts = base_part.as_slice().iter();
@@ -861,7 +914,13 @@ impl AsImapDataItem for ArchivedMessageMetadata {
         loop {
             while let Some(part_id) = parts.next() {
                 let part_id = u16::from(part_id) as usize;
-                let mut part = message.as_body_part(decoded, message_id, part_id, is_extended);
+                let mut part = message.as_body_part(
+                    decoded,
+                    message_id,
+                    part_id,
+                    is_extended,
+                    body_stats.and_then(|stats| stats.part(message_id, part_id)),
+                );
 
                 match &message.parts[part_id].body {
                     ArchivedMetadataPartType::Message(nested_message_id) => {
@@ -0,0 +1,192 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use crate::{
+    core::{Session, SessionData},
+    op::ImapContext,
+    spawn_op,
+};
+use common::listener::SessionStream;
+use directory::Permission;
+use email::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess},
+    message::signature::{ArchivedSignatureMethod, MessageSignature},
+};
+use imap_proto::{
+    Command, ResponseCode, StatusResponse,
+    protocol::{
+        ImapResponse,
+        metadata::{Arguments, Response},
+    },
+    receiver::Request,
+};
+use jmap_proto::types::{acl::Acl, collection::Collection, property::Property};
+
+// Signature verification results are exposed as read-only entries
+// named after the UID of each signed message in the mailbox.
+const SIGNATURE_ENTRY: &str = "/shared/vendor/vendor.stalwart/signature";
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_get_metadata(&mut self, request: Request<Command>) -> trc::Result<()> {
+        // Validate access
+        self.assert_has_permission(Permission::ImapStatus)?;
+
+        let data = self.state.session_data();
+        let version = self.version;
+
+        spawn_op!(data, {
+            match request.parse_get_metadata(version) {
+                Ok(argument) => match data.get_metadata(argument).await {
+                    Ok(response) => {
+                        data.write_bytes(response).await?;
+                    }
+                    Err(error) => {
+                        data.write_error(error).await?;
+                    }
+                },
+                Err(err) => data.write_error(err).await?,
+            }
+
+            Ok(())
+        })
+    }
+
+    pub async fn handle_set_metadata(&mut self, request: Request<Command>) -> trc::Result<()> {
+        Err(trc::ImapEvent::Error
+            .into_err()
+            .details("Metadata entries are read-only.")
+            .code(ResponseCode::NoPerm)
+            .id(request.tag))
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    pub async fn get_metadata(&self, arguments: Arguments) -> trc::Result<Vec<u8>> {
+        let op_start = Instant::now();
+
+        // Refresh mailboxes
+        self.synchronize_mailboxes(false)
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
+
+        // Validate mailbox
+        let mailbox = if let Some(mailbox) = self.get_mailbox_by_name(&arguments.mailbox_name) {
+            mailbox
+        } else {
+            return Err(trc::ImapEvent::Error
+                .into_err()
+                .details("Mailbox does not exist.")
+                .code(ResponseCode::TryCreate)
+                .id(arguments.tag));
+        };
+
+        // Validate ACL
+        if !self
+            .check_mailbox_acl(mailbox.account_id, mailbox.mailbox_id, Acl::ReadItems)
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?
+        {
+            return Err(trc::ImapEvent::Error
+                .into_err()
+                .details("You do not have the required permissions to read this mailbox.")
+                .code(ResponseCode::NoPerm)
+                .id(arguments.tag));
+        }
+
+        // Obtain the signature status of the requested messages
+        let requested = arguments
+            .entries
+            .iter()
+            .map(|entry| entry.to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        let cache = self
+            .server
+            .get_cached_messages(mailbox.account_id)
+            .await
+            .imap_ctx(&arguments.tag, trc::location!())?;
+        let mut messages = cache
+            .in_mailbox(mailbox.mailbox_id)
+            .filter_map(|item| {
+                let uid = item
+                    .mailboxes
+                    .iter()
+                    .find(|m| m.mailbox_id == mailbox.mailbox_id)?
+                    .uid;
+                let entry = format!("{SIGNATURE_ENTRY}/{uid}");
+                requested
+                    .iter()
+                    .any(|requested| arguments.depth.includes(requested, &entry))
+                    .then_some((uid, item.document_id, entry))
+            })
+            .collect::<Vec<_>>();
+        messages.sort_unstable_by_key(|(uid, _, _)| *uid);
+
+        let mut entries = Vec::with_capacity(messages.len());
+        let mut long_entries = 0;
+        for (_, document_id, entry) in messages {
+            let Some(signature_) = self
+                .server
+                .get_archive_by_property(
+                    mailbox.account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::SmimeStatusAtDelivery,
+                )
+                .await
+                .imap_ctx(&arguments.tag, trc::location!())?
+            else {
+                continue;
+            };
+            let signature = signature_
+                .unarchive::<MessageSignature>()
+                .imap_ctx(&arguments.tag, trc::location!())?;
+
+            let mut value = format!(
+                "{}; method={}",
+                signature.jmap_status(),
+                match signature.method {
+                    ArchivedSignatureMethod::SMIME => "smime",
+                    ArchivedSignatureMethod::PGP => "pgp",
+                }
+            );
+            if let Some(signer) = signature.signer.as_ref() {
+                value.push_str("; signer=");
+                value.push_str(signer);
+            }
+
+            if arguments
+                .max_size
+                .is_some_and(|max_size| value.len() > max_size as usize)
+            {
+                long_entries = long_entries.max(value.len() as u32);
+            } else {
+                entries.push((entry, value));
+            }
+        }
+
+        trc::event!(
+            Imap(trc::ImapEvent::GetMetadata),
+            SpanId = self.session_id,
+            MailboxName = arguments.mailbox_name.clone(),
+            Total = entries.len(),
+            Elapsed = op_start.elapsed()
+        );
+
+        // Build response
+        let response = Response {
+            mailbox_name: arguments.mailbox_name,
+            entries,
+        };
+        let mut status = StatusResponse::ok("GETMETADATA successful.").with_tag(arguments.tag);
+        if long_entries > 0 {
+            status = status.with_code(ResponseCode::MetadataLongEntries { size: long_entries });
+        }
+
+        Ok(status.serialize(response.serialize()))
+    }
+}
@@ -9,9 +9,11 @@ use imap_proto::ResponseCode;
 
 pub mod acl;
 pub mod append;
+pub mod apple_push;
 pub mod authenticate;
 pub mod capability;
 pub mod close;
+pub mod compress;
 pub mod copy_move;
 pub mod create;
 pub mod delete;
@@ -22,6 +24,7 @@ pub mod idle;
 pub mod list;
 pub mod login;
 pub mod logout;
+pub mod metadata;
 pub mod namespace;
 pub mod noop;
 pub mod quota;
@@ -9,6 +9,7 @@ use crate::{
     core::{ImapId, SavedSearch, SelectedMailbox, Session, SessionData},
     spawn_op,
 };
+use ahash::AHashMap;
 use common::listener::SessionStream;
 use directory::Permission;
 use email::cache::{MessageCacheFetch, email::MessageCacheAccess};
@@ -118,11 +119,33 @@ impl<T: SessionStream> SessionData<T> {
         is_uid: bool,
         op_start: Instant,
     ) -> trc::Result<search::Response> {
+        // Fuzzy terms are needed to rank results by relevancy
+        let fuzzy_filters = if arguments.result_options.contains(&ResultOption::Relevancy)
+            || arguments
+                .sort
+                .as_ref()
+                .is_some_and(|sort| sort.iter().any(|item| item.sort == search::Sort::Relevancy))
+        {
+            Some(fuzzy_filters(&arguments.filter))
+        } else {
+            None
+        };
+
         // Run query
         let (result_set, include_highest_modseq) = self
             .query(arguments.filter, &mailbox, &prev_saved_search)
             .await?;
 
+        // Calculate relevancy scores
+        let scores = if let Some(fuzzy_filters) = fuzzy_filters {
+            Some(
+                self.relevancy_scores(mailbox.id.account_id, fuzzy_filters, &result_set.results)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
         // Obtain modseq
         let highest_modseq = if include_highest_modseq {
             self.synchronize_messages(&mailbox)
@@ -145,45 +168,62 @@ impl<T: SessionStream> SessionData<T> {
         };
         let mut imap_ids = Vec::with_capacity(results_len);
         let is_sort = if let Some(sort) = arguments.sort {
+            // Relevancy can only be used as the primary sort key
+            let relevancy_order = sort
+                .first()
+                .filter(|item| item.sort == search::Sort::Relevancy)
+                .map(|item| item.ascending);
+            let mut comparators = sort
+                .into_iter()
+                .filter(|item| item.sort != search::Sort::Relevancy)
+                .map(|item| match item.sort {
+                    search::Sort::Arrival => {
+                        query::Comparator::field(Property::ReceivedAt, item.ascending)
+                    }
+                    search::Sort::Cc => query::Comparator::field(Property::Cc, item.ascending),
+                    search::Sort::Date => {
+                        query::Comparator::field(Property::SentAt, item.ascending)
+                    }
+                    search::Sort::From | search::Sort::DisplayFrom => {
+                        query::Comparator::field(Property::From, item.ascending)
+                    }
+                    search::Sort::Size => query::Comparator::field(Property::Size, item.ascending),
+                    search::Sort::Subject => {
+                        query::Comparator::field(Property::Subject, item.ascending)
+                    }
+                    search::Sort::To | search::Sort::DisplayTo => {
+                        query::Comparator::field(Property::To, item.ascending)
+                    }
+                    search::Sort::Relevancy => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            if comparators.is_empty() {
+                comparators.push(query::Comparator::field(Property::ReceivedAt, true));
+            }
+            let mut ids = self
+                .server
+                .core
+                .storage
+                .data
+                .sort(
+                    result_set,
+                    comparators,
+                    Pagination::new(results_len, 0, None, 0),
+                )
+                .await
+                .caused_by(trc::location!())?
+                .ids;
+            if let (Some(ascending), Some(scores)) = (relevancy_order, &scores) {
+                // Most relevant messages first, unless reversed
+                ids.sort_by(|a, b| {
+                    let a = scores.get(&(*a as u32)).copied().unwrap_or_default();
+                    let b = scores.get(&(*b as u32)).copied().unwrap_or_default();
+                    if ascending { b.cmp(&a) } else { a.cmp(&b) }
+                });
+            }
+
             mailbox.map_search_results(
-                self.server
-                    .core
-                    .storage
-                    .data
-                    .sort(
-                        result_set,
-                        sort.into_iter()
-                            .map(|item| match item.sort {
-                                search::Sort::Arrival => {
-                                    query::Comparator::field(Property::ReceivedAt, item.ascending)
-                                }
-                                search::Sort::Cc => {
-                                    query::Comparator::field(Property::Cc, item.ascending)
-                                }
-                                search::Sort::Date => {
-                                    query::Comparator::field(Property::SentAt, item.ascending)
-                                }
-                                search::Sort::From | search::Sort::DisplayFrom => {
-                                    query::Comparator::field(Property::From, item.ascending)
-                                }
-                                search::Sort::Size => {
-                                    query::Comparator::field(Property::Size, item.ascending)
-                                }
-                                search::Sort::Subject => {
-                                    query::Comparator::field(Property::Subject, item.ascending)
-                                }
-                                search::Sort::To | search::Sort::DisplayTo => {
-                                    query::Comparator::field(Property::To, item.ascending)
-                                }
-                            })
-                            .collect::<Vec<_>>(),
-                        Pagination::new(results_len, 0, None, 0),
-                    )
-                    .await
-                    .caused_by(trc::location!())?
-                    .ids
-                    .into_iter()
-                    .map(|id| id as u32),
+                ids.into_iter().map(|id| id as u32),
                 is_uid,
                 arguments.result_options.contains(&ResultOption::Min),
                 arguments.result_options.contains(&ResultOption::Max),
@@ -233,6 +273,30 @@ impl<T: SessionStream> SessionData<T> {
         );
 
         // Build response
+        let ids = if arguments.result_options.is_empty()
+            || arguments.result_options.contains(&ResultOption::All)
+        {
+            imap_ids
+        } else {
+            vec![]
+        };
+        let relevancy = scores
+            .filter(|_| arguments.result_options.contains(&ResultOption::Relevancy))
+            .map(|scores| {
+                let state = mailbox.state.lock();
+                let scores = scores
+                    .into_iter()
+                    .filter_map(|(document_id, score)| {
+                        state
+                            .map_result_id(document_id, is_uid)
+                            .map(|(id, _)| (id, score))
+                    })
+                    .collect::<AHashMap<_, _>>();
+                ids.iter()
+                    .map(|id| scores.get(id).copied().unwrap_or(1))
+                    .collect()
+            });
+
         Ok(Response {
             is_uid,
             min: min.map(|(id, _)| id),
@@ -242,16 +306,11 @@ impl<T: SessionStream> SessionData<T> {
             } else {
                 None
             },
-            ids: if arguments.result_options.is_empty()
-                || arguments.result_options.contains(&ResultOption::All)
-            {
-                imap_ids
-            } else {
-                vec![]
-            },
+            ids,
             is_sort,
             is_esearch: arguments.is_esearch,
             highest_modseq,
+            relevancy,
         })
     }
 
@@ -277,153 +336,25 @@ impl<T: SessionStream> SessionData<T> {
         filters.push(query::Filter::is_in_set(message_ids.clone()));
 
         // Convert query
+        let language = self.server.account_language(mailbox.id.account_id).await;
         let mut include_highest_modseq = false;
         for filter_group in imap_filter.into_filter_group() {
             match filter_group {
                 FilterGroup::Fts(conds) => {
                     let mut fts_filters = Vec::with_capacity(filters.len());
                     for cond in conds {
-                        match cond {
-                            search::Filter::Bcc(text) => {
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::Bcc),
-                                    text,
-                                    Language::None,
-                                ));
-                            }
-                            search::Filter::Body(text) => {
-                                fts_filters.push(FtsFilter::has_text_detect(
-                                    Field::Body,
-                                    text,
-                                    self.server.core.jmap.default_language,
-                                ));
-                            }
-                            search::Filter::Cc(text) => {
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::Cc),
-                                    text,
-                                    Language::None,
-                                ));
-                            }
-                            search::Filter::From(text) => {
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::From),
-                                    text,
-                                    Language::None,
-                                ));
-                            }
-                            search::Filter::Header(header, value) => {
-                                match HeaderName::parse(header) {
-                                    Some(HeaderName::Other(header_name)) => {
-                                        return Err(trc::ImapEvent::Error.into_err().details(
-                                            format!(
-                                                "Querying header '{header_name}' is not supported.",
-                                            ),
-                                        ));
-                                    }
-                                    Some(header_name) => {
-                                        if !value.is_empty() {
-                                            if matches!(
-                                                header_name,
-                                                HeaderName::MessageId
-                                                    | HeaderName::InReplyTo
-                                                    | HeaderName::References
-                                                    | HeaderName::ResentMessageId
-                                            ) {
-                                                fts_filters.push(FtsFilter::has_keyword(
-                                                    Field::Header(header_name),
-                                                    value,
-                                                ));
-                                            } else {
-                                                fts_filters.push(FtsFilter::has_text(
-                                                    Field::Header(header_name),
-                                                    value,
-                                                    Language::None,
-                                                ));
-                                            }
-                                        } else {
-                                            fts_filters.push(FtsFilter::has_keyword(
-                                                Field::Keyword,
-                                                header_name.as_str().to_lowercase(),
-                                            ));
-                                        }
-                                    }
-                                    None => (),
-                                }
-                            }
-                            search::Filter::Subject(text) => {
-                                fts_filters.push(FtsFilter::has_text_detect(
-                                    Field::Header(HeaderName::Subject),
-                                    text,
-                                    self.server.core.jmap.default_language,
-                                ));
-                            }
-                            search::Filter::Text(text) => {
-                                fts_filters.push(FtsFilter::Or);
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::From),
-                                    text.as_str(),
-                                    Language::None,
-                                ));
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::To),
-                                    text.as_str(),
-                                    Language::None,
-                                ));
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::Cc),
-                                    text.as_str(),
-                                    Language::None,
-                                ));
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::Bcc),
-                                    text.as_str(),
-                                    Language::None,
-                                ));
-                                fts_filters.push(FtsFilter::has_text_detect(
-                                    Field::Header(HeaderName::Subject),
-                                    text.as_str(),
-                                    self.server.core.jmap.default_language,
-                                ));
-                                fts_filters.push(FtsFilter::has_text_detect(
-                                    Field::Body,
-                                    text.as_str(),
-                                    self.server.core.jmap.default_language,
-                                ));
-                                fts_filters.push(FtsFilter::has_text_detect(
-                                    Field::Attachment,
-                                    text,
-                                    self.server.core.jmap.default_language,
-                                ));
-                                fts_filters.push(FtsFilter::End);
-                            }
-                            search::Filter::To(text) => {
-                                fts_filters.push(FtsFilter::has_text(
-                                    Field::Header(HeaderName::To),
-                                    text,
-                                    Language::None,
-                                ));
-                            }
-                            search::Filter::And => {
-                                fts_filters.push(FtsFilter::And);
-                            }
-                            search::Filter::Or => {
-                                fts_filters.push(FtsFilter::Or);
-                            }
-                            search::Filter::Not => {
-                                fts_filters.push(FtsFilter::Not);
-                            }
-                            search::Filter::End => {
-                                fts_filters.push(FtsFilter::End);
-                            }
-                            _ => (),
-                        }
+                        self.fts_filter(cond, &mut fts_filters, language)?;
                     }
 
                     filters.push(query::Filter::is_in_set(
                         self.server
                             .fts_store()
-                            .query(mailbox.id.account_id, Collection::Email, fts_filters)
+                            .query_masked(
+                                mailbox.id.account_id,
+                                Collection::Email,
+                                fts_filters,
+                                Some(&message_ids),
+                            )
                             .await?,
                     ));
                 }
@@ -684,6 +615,237 @@ impl<T: SessionStream> SessionData<T> {
             .map(|res| (res, include_highest_modseq))
             .caused_by(trc::location!())
     }
+
+    fn fts_filter(
+        &self,
+        cond: search::Filter,
+        fts_filters: &mut Vec<FtsFilter<HeaderName<'static>>>,
+        language: Language,
+    ) -> trc::Result<()> {
+        match cond {
+            search::Filter::Bcc(text) => {
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::Bcc),
+                    text,
+                    Language::None,
+                ));
+            }
+            search::Filter::Body(text) => {
+                fts_filters.push(FtsFilter::has_text_detect(
+                    Field::Body,
+                    text,
+                    language,
+                ));
+            }
+            search::Filter::Cc(text) => {
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::Cc),
+                    text,
+                    Language::None,
+                ));
+            }
+            search::Filter::From(text) => {
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::From),
+                    text,
+                    Language::None,
+                ));
+            }
+            search::Filter::Header(header, value) => match HeaderName::parse(header) {
+                Some(HeaderName::Other(header_name)) => {
+                    return Err(trc::ImapEvent::Error
+                        .into_err()
+                        .details(format!("Querying header '{header_name}' is not supported.",)));
+                }
+                Some(header_name) => {
+                    if !value.is_empty() {
+                        if matches!(
+                            header_name,
+                            HeaderName::MessageId
+                                | HeaderName::InReplyTo
+                                | HeaderName::References
+                                | HeaderName::ResentMessageId
+                        ) {
+                            fts_filters
+                                .push(FtsFilter::has_keyword(Field::Header(header_name), value));
+                        } else {
+                            fts_filters.push(FtsFilter::has_text(
+                                Field::Header(header_name),
+                                value,
+                                Language::None,
+                            ));
+                        }
+                    } else {
+                        fts_filters.push(FtsFilter::has_keyword(
+                            Field::Keyword,
+                            header_name.as_str().to_lowercase(),
+                        ));
+                    }
+                }
+                None => (),
+            },
+            search::Filter::Subject(text) => {
+                fts_filters.push(FtsFilter::has_text_detect(
+                    Field::Header(HeaderName::Subject),
+                    text,
+                    language,
+                ));
+            }
+            search::Filter::Text(text) => {
+                fts_filters.push(FtsFilter::Or);
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::From),
+                    text.as_str(),
+                    Language::None,
+                ));
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::To),
+                    text.as_str(),
+                    Language::None,
+                ));
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::Cc),
+                    text.as_str(),
+                    Language::None,
+                ));
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::Bcc),
+                    text.as_str(),
+                    Language::None,
+                ));
+                fts_filters.push(FtsFilter::has_text_detect(
+                    Field::Header(HeaderName::Subject),
+                    text.as_str(),
+                    language,
+                ));
+                fts_filters.push(FtsFilter::has_text_detect(
+                    Field::Body,
+                    text.as_str(),
+                    language,
+                ));
+                fts_filters.push(FtsFilter::has_text_detect(
+                    Field::Attachment,
+                    text,
+                    language,
+                ));
+                fts_filters.push(FtsFilter::End);
+            }
+            search::Filter::To(text) => {
+                fts_filters.push(FtsFilter::has_text(
+                    Field::Header(HeaderName::To),
+                    text,
+                    Language::None,
+                ));
+            }
+            search::Filter::And => {
+                fts_filters.push(FtsFilter::And);
+            }
+            search::Filter::Or => {
+                fts_filters.push(FtsFilter::Or);
+            }
+            search::Filter::Not => {
+                fts_filters.push(FtsFilter::Not);
+            }
+            search::Filter::End => {
+                fts_filters.push(FtsFilter::End);
+            }
+            search::Filter::Fuzzy(filter) => {
+                // Match any of the terms, relevancy is calculated separately
+                let terms = fuzzy_terms(*filter);
+                let is_multi_term = terms.len() > 1;
+                if is_multi_term {
+                    fts_filters.push(FtsFilter::Or);
+                }
+                for term in terms {
+                    self.fts_filter(term, fts_filters, language)?;
+                }
+                if is_multi_term {
+                    fts_filters.push(FtsFilter::End);
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    async fn relevancy_scores(
+        &self,
+        account_id: u32,
+        fuzzy_filters: Vec<Filter>,
+        document_ids: &RoaringBitmap,
+    ) -> trc::Result<AHashMap<u32, u8>> {
+        // Score each message by the fraction of fuzzy terms it matches
+        let language = self.server.account_language(account_id).await;
+        let mut matches: AHashMap<u32, u32> = AHashMap::with_capacity(document_ids.len() as usize);
+        let mut total_terms = 0;
+        for filter in fuzzy_filters {
+            for term in fuzzy_terms(filter) {
+                let mut fts_filters = Vec::with_capacity(1);
+                self.fts_filter(term, &mut fts_filters, language)?;
+                total_terms += 1;
+
+                let mut results = self
+                    .server
+                    .fts_store()
+                    .query(account_id, Collection::Email, fts_filters)
+                    .await?;
+                results &= document_ids;
+                for document_id in results {
+                    *matches.entry(document_id).or_default() += 1;
+                }
+            }
+        }
+
+        Ok(document_ids
+            .iter()
+            .map(|document_id| {
+                let score = if total_terms > 0 {
+                    matches.get(&document_id).copied().unwrap_or_default() * 100 / total_terms
+                } else {
+                    100
+                };
+                (document_id, score.clamp(1, 100) as u8)
+            })
+            .collect())
+    }
+}
+
+fn fuzzy_terms(filter: Filter) -> Vec<Filter> {
+    fn split(text: &str, filter: impl Fn(String) -> Filter) -> Vec<Filter> {
+        // Quoted phrases are matched as a whole
+        if text.starts_with('"') || text.starts_with('\'') {
+            vec![filter(text.to_string())]
+        } else {
+            text.split_whitespace()
+                .map(|word| filter(word.to_string()))
+                .collect()
+        }
+    }
+
+    match filter {
+        Filter::Bcc(text) => split(&text, Filter::Bcc),
+        Filter::Body(text) => split(&text, Filter::Body),
+        Filter::Cc(text) => split(&text, Filter::Cc),
+        Filter::From(text) => split(&text, Filter::From),
+        Filter::Subject(text) => split(&text, Filter::Subject),
+        Filter::Text(text) => split(&text, Filter::Text),
+        Filter::To(text) => split(&text, Filter::To),
+        Filter::Header(header, value) if !value.is_empty() => {
+            split(&value, |value| Filter::Header(header.clone(), value))
+        }
+        filter => vec![filter],
+    }
+}
+
+fn fuzzy_filters(filters: &[Filter]) -> Vec<Filter> {
+    filters
+        .iter()
+        .filter_map(|filter| match filter {
+            Filter::Fuzzy(filter) => Some(filter.as_ref().clone()),
+            _ => None,
+        })
+        .collect()
 }
 
 impl SelectedMailbox {
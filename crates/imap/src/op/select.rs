@@ -68,6 +68,7 @@ impl<T: SessionStream> Session<T> {
                 id: mailbox,
                 state: parking_lot::Mutex::new(state),
                 saved_search: parking_lot::Mutex::new(SavedSearch::None),
+                thread_cache: parking_lot::Mutex::new(None),
                 is_select,
                 is_condstore,
             });
@@ -5,13 +5,13 @@
  */
 
 use crate::{
-    core::{SelectedMailbox, Session, SessionData},
+    core::{SelectedMailbox, Session, SessionData, ThreadCache},
     spawn_op,
 };
 use ahash::AHashMap;
-use common::listener::SessionStream;
+use common::{MessageStoreCache, listener::SessionStream};
 use directory::Permission;
-use email::cache::MessageCacheFetch;
+use email::cache::{MessageCacheFetch, email::MessageCacheAccess};
 use imap_proto::{
     Command, StatusResponse,
     protocol::{
@@ -78,29 +78,31 @@ impl<T: SessionStream> SessionData<T> {
             });
         }
 
-        // Lock the cache
+        // Obtain the precomputed threads for this mailbox
         let cache = self
             .server
             .get_cached_messages(mailbox.id.account_id)
             .await
             .caused_by(trc::location!())?;
+        let thread_cache = mailbox.thread_cache(&cache);
 
-        // Group messages by thread
-        let mut threads: AHashMap<u32, Vec<u32>> = AHashMap::new();
+        // Keep only the messages matching the search criteria, then order the
+        // threads by their first matching message
         let state = mailbox.state.lock();
-        for item in &cache.emails.items {
-            if result_set.results.contains(item.document_id) {
-                if let Some((imap_id, _)) = state.map_result_id(item.document_id, is_uid) {
-                    threads.entry(item.thread_id).or_default().push(imap_id);
-                }
-            }
-        }
-
-        let mut threads = threads
-            .into_iter()
-            .map(|(_, mut messages)| {
-                messages.sort_unstable();
-                messages
+        let mut threads = thread_cache
+            .threads
+            .iter()
+            .filter_map(|thread| {
+                let messages = thread
+                    .iter()
+                    .filter(|document_id| result_set.results.contains(**document_id))
+                    .filter_map(|document_id| {
+                        state
+                            .map_result_id(*document_id, is_uid)
+                            .map(|(imap_id, _)| imap_id)
+                    })
+                    .collect::<Vec<_>>();
+                (!messages.is_empty()).then_some(messages)
             })
             .collect::<Vec<_>>();
         threads.sort_unstable();
@@ -118,3 +120,50 @@ impl<T: SessionStream> SessionData<T> {
         Ok(Response { is_uid, threads })
     }
 }
+
+impl SelectedMailbox {
+    /// Returns the mailbox messages grouped by their stored thread id, rebuilding
+    /// the groups only when the message cache has changed since the last call.
+    pub fn thread_cache(&self, cache: &MessageStoreCache) -> Arc<ThreadCache> {
+        let mut thread_cache = self.thread_cache.lock();
+        if let Some(thread_cache) = thread_cache
+            .as_ref()
+            .filter(|thread_cache| thread_cache.change_id == cache.emails.change_id)
+        {
+            return thread_cache.clone();
+        }
+
+        let mailbox_id = self.id.mailbox_id;
+        let mut threads: AHashMap<u32, Vec<(u32, u32)>> = AHashMap::new();
+        for item in cache.in_mailbox(mailbox_id) {
+            if let Some(uid) = item
+                .mailboxes
+                .iter()
+                .find(|m| m.mailbox_id == mailbox_id)
+                .map(|m| m.uid)
+            {
+                threads
+                    .entry(item.thread_id)
+                    .or_default()
+                    .push((uid, item.document_id));
+            }
+        }
+
+        // Order messages within a thread by UID, threads are ordered once filtered
+        let result = Arc::new(ThreadCache {
+            change_id: cache.emails.change_id,
+            threads: threads
+                .into_values()
+                .map(|mut messages| {
+                    messages.sort_unstable();
+                    messages
+                        .into_iter()
+                        .map(|(_, document_id)| document_id)
+                        .collect()
+                })
+                .collect(),
+        });
+        *thread_cache = Some(result.clone());
+        result
+    }
+}
@@ -8,6 +8,7 @@ use std::{borrow::Cow, collections::HashMap};
 
 use crate::{
     error::request::{RequestError, RequestErrorType, RequestLimitError},
+    method::changes::ChangesResponse,
     parser::{JsonObjectParser, Token, json::Parser},
     request::Call,
     response::{Response, ResponseMethod, serialize::serialize_hex},
@@ -53,6 +54,7 @@ pub enum WebSocketResponseType {
 pub struct WebSocketPushEnable {
     pub data_types: Vec<DataType>,
     pub push_state: Option<String>,
+    pub delta: bool,
 }
 
 #[derive(Debug)]
@@ -72,6 +74,8 @@ pub struct WebSocketStateChange {
     #[serde(rename = "@type")]
     pub type_: WebSocketStateChangeType,
     pub changed: VecMap<Id, VecMap<DataType, State>>,
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub delta: VecMap<Id, VecMap<DataType, ChangesResponse>>,
     #[serde(rename = "pushState")]
     #[serde(skip_serializing_if = "Option::is_none")]
     push_state: Option<String>,
@@ -150,6 +154,13 @@ impl WebSocketMessage {
                             .unwrap_string_or_null("pushState")?;
                         found_push_keys = true;
                     }
+                    0x0061_746c_6564 => {
+                        push_enable.delta = parser
+                            .next_token::<String>()?
+                            .unwrap_bool_or_null("delta")?
+                            .unwrap_or_default();
+                        found_push_keys = true;
+                    }
                     0x6469 => {
                         request.id = parser.next_token::<String>()?.unwrap_string_or_null("id")?;
                     }
@@ -224,6 +235,7 @@ impl WebSocketStateChange {
         WebSocketStateChange {
             type_: WebSocketStateChangeType::StateChange,
             changed: VecMap::new(),
+            delta: VecMap::new(),
             push_state,
         }
     }
@@ -119,6 +119,15 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    BodyStats,
+    SmimeStatus,
+    SmimeStatusAtDelivery,
+    SmimeErrors,
+    SmimeVerifiedAt,
+    MailSync,
+    MailSyncState,
+    EncryptionJob,
+    DeletedEmails,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -156,6 +165,7 @@ impl JsonObjectParser for Property {
     fn parse(parser: &mut Parser) -> trc::Result<Self> {
         let mut first_char = 0;
         let mut hash = 0;
+        let mut hash_ext = 0;
         let mut shift = 0;
 
         while let Some(ch) = parser.next_unescaped()? {
@@ -164,13 +174,16 @@ impl JsonObjectParser for Property {
                     if shift < 128 {
                         hash |= (ch as u128) << shift;
                         shift += 8;
+                    } else if shift < 256 {
+                        hash_ext |= (ch as u128) << (shift - 128);
+                        shift += 8;
                     } else {
                         return parser.invalid_property();
                     }
                 } else {
                     first_char = ch;
                 }
-            } else if ch == b':' {
+            } else if ch == b':' && hash_ext == 0 {
                 return if first_char == b'h' && hash == 0x0072_6564_6165 {
                     parse_header_property(parser)
                 } else {
@@ -181,7 +194,11 @@ impl JsonObjectParser for Property {
             }
         }
 
-        if let Some(property) = parse_property(first_char, hash) {
+        if let Some(property) = if hash_ext == 0 {
+            parse_property(first_char, hash)
+        } else {
+            parse_long_property(first_char, hash, hash_ext)
+        } {
             Ok(property)
         } else {
             parser.invalid_property()
@@ -439,6 +456,9 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x7265_6472_4f74_726f => Property::SortOrder,
             0x7463_656a_6275 => Property::Subject,
             0x7374_7261_5062_7573 => Property::SubParts,
+            0x7375_7461_7453_656d_696d => Property::SmimeStatus,
+            0x7372_6f72_7245_656d_696d => Property::SmimeErrors,
+            0x7441_6465_6966_6972_6556_656d_696d => Property::SmimeVerifiedAt,
             _ => return None,
         },
         b't' => match hash {
@@ -469,6 +489,16 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
     })
 }
 
+// Properties with names longer than 17 characters
+fn parse_long_property(first_char: u8, hash: u128, hash_ext: u128) -> Option<Property> {
+    Some(match (first_char, hash, hash_ext) {
+        (b's', 0x696c_6544_7441_7375_7461_7453_656d_696d, 0x7972_6576) => {
+            Property::SmimeStatusAtDelivery
+        }
+        _ => return None,
+    })
+}
+
 fn parse_header_property(parser: &mut Parser) -> trc::Result<Property> {
     let hdr_start_pos = parser.pos;
     let mut has_next = false;
@@ -723,6 +753,7 @@ impl Property {
     pub fn parse(value: &str) -> Property {
         let mut first_char = 0;
         let mut hash = 0;
+        let mut hash_ext = 0;
         let mut shift = 0;
 
         for &ch in value.as_bytes() {
@@ -731,6 +762,9 @@ impl Property {
                     if shift < 128 {
                         hash |= (ch as u128) << shift;
                         shift += 8;
+                    } else if shift < 256 {
+                        hash_ext |= (ch as u128) << (shift - 128);
+                        shift += 8;
                     } else {
                         return Property::_T(value.to_string());
                     }
@@ -742,7 +776,11 @@ impl Property {
             }
         }
 
-        if let Some(property) = parse_property(first_char, hash) {
+        if let Some(property) = if hash_ext == 0 {
+            parse_property(first_char, hash)
+        } else {
+            parse_long_property(first_char, hash, hash_ext)
+        } {
             property
         } else {
             Property::_T(value.to_string())
@@ -881,6 +919,15 @@ impl Display for Property {
             Property::Used => write!(f, "used"),
             Property::HardLimit => write!(f, "hardLimit"),
             Property::Scope => write!(f, "scope"),
+            Property::BodyStats => write!(f, "bodyStats"),
+            Property::SmimeStatus => write!(f, "smimeStatus"),
+            Property::SmimeStatusAtDelivery => write!(f, "smimeStatusAtDelivery"),
+            Property::SmimeErrors => write!(f, "smimeErrors"),
+            Property::SmimeVerifiedAt => write!(f, "smimeVerifiedAt"),
+            Property::MailSync => write!(f, "mailSync"),
+            Property::MailSyncState => write!(f, "mailSyncState"),
+            Property::EncryptionJob => write!(f, "encryptionJob"),
+            Property::DeletedEmails => write!(f, "deletedEmails"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
             Property::_T(s) => write!(f, "{s}"),
@@ -994,6 +1041,15 @@ impl Property {
             Property::WarnLimit => "warnLimit",
             Property::SoftLimit => "softLimit",
             Property::Scope => "scope",
+            Property::BodyStats => "bodyStats",
+            Property::SmimeStatus => "smimeStatus",
+            Property::SmimeStatusAtDelivery => "smimeStatusAtDelivery",
+            Property::SmimeErrors => "smimeErrors",
+            Property::SmimeVerifiedAt => "smimeVerifiedAt",
+            Property::MailSync => "mailSync",
+            Property::MailSyncState => "mailSyncState",
+            Property::EncryptionJob => "encryptionJob",
+            Property::DeletedEmails => "deletedEmails",
             Property::Data(data) => match data {
                 DataProperty::AsText => "data:asText",
                 DataProperty::AsBase64 => "data:asBase64",
@@ -1180,6 +1236,15 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::BodyStats => 104,
+            Property::SmimeStatus => 105,
+            Property::SmimeStatusAtDelivery => 106,
+            Property::SmimeErrors => 107,
+            Property::SmimeVerifiedAt => 108,
+            Property::MailSync => 109,
+            Property::MailSyncState => 110,
+            Property::EncryptionJob => 111,
+            Property::DeletedEmails => 112,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
@@ -1231,3 +1296,43 @@ impl From<Property> for ValueClass {
         ValueClass::Property(value.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parser::json::Parser;
+
+    use super::Property;
+
+    #[test]
+    fn parse_long_property() {
+        for property in [
+            Property::SmimeStatus,
+            Property::SmimeStatusAtDelivery,
+            Property::SmimeErrors,
+            Property::SmimeVerifiedAt,
+        ] {
+            assert_eq!(Property::parse(property.as_str()), property);
+            assert_eq!(
+                Parser::new(format!("\"{property}\"").as_bytes())
+                    .next_token::<Property>()
+                    .unwrap()
+                    .unwrap_string("")
+                    .unwrap(),
+                property
+            );
+        }
+
+        for name in ["smimeStatusAtDeliveryTime", "smimeStatusAtDeliver"] {
+            assert_eq!(Property::parse(name), Property::_T(name.to_string()));
+            assert_eq!(
+                Parser::new(format!("\"{name}\"").as_bytes())
+                    .next_token::<Property>()
+                    .unwrap()
+                    .unwrap_string("")
+                    .unwrap(),
+                Property::_T(name.to_string())
+            );
+        }
+    }
+}
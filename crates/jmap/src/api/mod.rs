@@ -101,8 +101,16 @@ impl ToRequestError for trc::Error {
                 | trc::SecurityEvent::ScanBan
                 | trc::SecurityEvent::AbuseBan
                 | trc::SecurityEvent::LoiterBan
-                | trc::SecurityEvent::IpBlocked => RequestError::too_many_auth_attempts(),
-                trc::SecurityEvent::Unauthorized => RequestError::forbidden(),
+                | trc::SecurityEvent::IpBlocked
+                | trc::SecurityEvent::AuthenticationLockout => {
+                    RequestError::too_many_auth_attempts()
+                }
+                trc::SecurityEvent::Unauthorized
+                | trc::SecurityEvent::AccountAnomaly
+                | trc::SecurityEvent::Impersonation => RequestError::forbidden(),
+                trc::SecurityEvent::IpListFeedUpdated | trc::SecurityEvent::IpListFeedError => {
+                    RequestError::internal_server_error()
+                }
             },
             trc::EventType::Resource(cause) => match cause {
                 trc::ResourceEvent::NotFound => RequestError::not_found(),
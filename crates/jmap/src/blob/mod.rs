@@ -9,6 +9,7 @@ use jmap_proto::types::{blob::BlobId, id::Id};
 pub mod copy;
 pub mod download;
 pub mod get;
+pub mod resumable;
 pub mod upload;
 
 #[derive(Debug, serde::Serialize)]
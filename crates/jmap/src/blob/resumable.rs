@@ -0,0 +1,451 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Resumable uploads following the tus 1.0 protocol (core, creation,
+// termination and expiration extensions), see https://tus.io/protocols/resumable-upload
+
+use std::{future::Future, sync::Arc};
+
+use common::{KV_LOCK_UPLOAD, KV_UPLOAD, Server, auth::AccessToken};
+use directory::Permission;
+use http_proto::{HttpRequest, HttpResponse, HttpSessionData, request::fetch_body};
+use hyper::{Method, StatusCode, header::CONTENT_TYPE};
+use jmap_proto::types::id::Id;
+use mail_parser::decoders::base64::base64_decode;
+use store::{
+    BlobClass,
+    dispatch::lookup::KeyValue,
+    rand::{Rng, distr::Alphanumeric, rng},
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, BlobOp, now},
+};
+use trc::AddContext;
+use utils::BlobHash;
+
+use super::UploadResponse;
+
+pub const TUS_RESUMABLE: &str = "Tus-Resumable";
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+
+const UPLOAD_LENGTH: &str = "Upload-Length";
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+const UPLOAD_METADATA: &str = "Upload-Metadata";
+const UPLOAD_EXPIRES: &str = "Upload-Expires";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+const UPLOAD_LOCK_EXPIRY: u64 = 300;
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug)]
+pub struct PartialUpload {
+    pub owner_id: u32,
+    pub content_type: String,
+    pub length: u64,
+    pub offset: u64,
+    pub expires: u64,
+    pub chunks: Vec<UploadChunk>,
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug)]
+pub struct UploadChunk {
+    pub hash: BlobHash,
+    pub until: u64,
+    pub size: u64,
+}
+
+pub trait ResumableUpload: Sync + Send {
+    fn handle_resumable_upload(
+        &self,
+        req: &mut HttpRequest,
+        account_id: Id,
+        upload_id: Option<&str>,
+        access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ResumableUpload for Server {
+    async fn handle_resumable_upload(
+        &self,
+        req: &mut HttpRequest,
+        account_id: Id,
+        upload_id: Option<&str>,
+        access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
+    ) -> trc::Result<HttpResponse> {
+        if req
+            .headers()
+            .get(TUS_RESUMABLE)
+            .and_then(|h| h.to_str().ok())
+            .is_none_or(|v| v != TUS_VERSION)
+        {
+            return Ok(tus_response(StatusCode::PRECONDITION_FAILED)
+                .with_header("Tus-Version", TUS_VERSION));
+        }
+
+        access_token.assert_is_member(account_id)?;
+
+        match (req.method(), upload_id) {
+            (&Method::POST, None) => self.create_upload(req, account_id, &access_token).await,
+            (&Method::HEAD, Some(upload_id)) => {
+                let upload = self
+                    .get_upload(account_id.document_id(), upload_id, &access_token)
+                    .await?;
+
+                Ok(tus_response(StatusCode::OK)
+                    .with_header(UPLOAD_OFFSET, upload.offset.to_string())
+                    .with_header(UPLOAD_LENGTH, upload.length.to_string())
+                    .with_header(UPLOAD_EXPIRES, http_date(upload.expires))
+                    .with_no_store())
+            }
+            (&Method::PATCH, Some(upload_id)) => {
+                let lock_key = upload_key(account_id.document_id(), upload_id);
+                if !self
+                    .in_memory_store()
+                    .try_lock(KV_LOCK_UPLOAD, &lock_key, UPLOAD_LOCK_EXPIRY)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    return Ok(tus_response(StatusCode::LOCKED));
+                }
+
+                let result = self
+                    .append_upload(req, account_id, upload_id, access_token, session)
+                    .await;
+
+                self.in_memory_store()
+                    .remove_lock(KV_LOCK_UPLOAD, &lock_key)
+                    .await
+                    .caused_by(trc::location!())?;
+
+                result
+            }
+            (&Method::DELETE, Some(upload_id)) => {
+                let upload = self
+                    .get_upload(account_id.document_id(), upload_id, &access_token)
+                    .await?;
+                self.release_upload(account_id.document_id(), upload_id, &upload)
+                    .await?;
+
+                Ok(tus_response(StatusCode::NO_CONTENT))
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+impl Server {
+    async fn create_upload(
+        &self,
+        req: &HttpRequest,
+        account_id: Id,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let length = req
+            .headers()
+            .get(UPLOAD_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.parse::<u64>().ok())
+            .ok_or_else(|| {
+                trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Missing or invalid Upload-Length header")
+            })?;
+        let has_unlimited_uploads = access_token.has_permission(Permission::UnlimitedUploads);
+        if length == 0 || (length > self.core.jmap.upload_max_size as u64 && !has_unlimited_uploads)
+        {
+            return Err(trc::LimitEvent::SizeUpload
+                .into_err()
+                .ctx(trc::Key::Size, length)
+                .ctx(trc::Key::Limit, self.core.jmap.upload_max_size));
+        }
+
+        // Reject uploads that would not fit in the temporary blob quota once completed
+        let used = self
+            .core
+            .storage
+            .data
+            .blob_quota(account_id.document_id())
+            .await
+            .caused_by(trc::location!())?;
+        if ((self.core.jmap.upload_tmp_quota_size > 0
+            && used.bytes + length as usize > self.core.jmap.upload_tmp_quota_size)
+            || (self.core.jmap.upload_tmp_quota_amount > 0
+                && used.count + 1 > self.core.jmap.upload_tmp_quota_amount))
+            && !has_unlimited_uploads
+        {
+            return Err(trc::LimitEvent::BlobQuota
+                .into_err()
+                .ctx(trc::Key::Size, self.core.jmap.upload_tmp_quota_size)
+                .ctx(trc::Key::Total, self.core.jmap.upload_tmp_quota_amount));
+        }
+
+        // Obtain content type from the upload metadata
+        let content_type = req
+            .headers()
+            .get(UPLOAD_METADATA)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|metadata| {
+                metadata.split(',').find_map(|item| {
+                    let (key, value) = item.trim().split_once(' ')?;
+                    if matches!(key, "filetype" | "type" | "contentType") {
+                        base64_decode(value.trim().as_bytes())
+                            .and_then(|value| String::from_utf8(value).ok())
+                            .filter(|value| !value.is_empty())
+                    } else {
+                        None
+                    }
+                })
+            })
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+
+        let upload_id = rng()
+            .sample_iter(Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect::<String>();
+        let expires = now() + self.core.jmap.upload_tmp_ttl;
+        self.store_upload(
+            account_id.document_id(),
+            &upload_id,
+            PartialUpload {
+                owner_id: access_token.primary_id(),
+                content_type,
+                length,
+                offset: 0,
+                expires,
+                chunks: vec![],
+            },
+        )
+        .await?;
+
+        let location = format!("{}/{upload_id}", req.uri().path().trim_end_matches('/'));
+
+        Ok(tus_response(StatusCode::CREATED)
+            .with_location(location)
+            .with_header(UPLOAD_EXPIRES, http_date(expires)))
+    }
+
+    async fn append_upload(
+        &self,
+        req: &mut HttpRequest,
+        account_id: Id,
+        upload_id: &str,
+        access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
+    ) -> trc::Result<HttpResponse> {
+        // Limit concurrent uploads
+        let _in_flight = self
+            .is_upload_allowed(&access_token)
+            .caused_by(trc::location!())?;
+
+        if req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .is_none_or(|v| v != OFFSET_CONTENT_TYPE)
+        {
+            return Ok(tus_response(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        }
+        let offset = req
+            .headers()
+            .get(UPLOAD_OFFSET)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.parse::<u64>().ok())
+            .ok_or_else(|| {
+                trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Missing or invalid Upload-Offset header")
+            })?;
+
+        let document_id = account_id.document_id();
+        let mut upload = self
+            .get_upload(document_id, upload_id, &access_token)
+            .await?;
+        if offset != upload.offset {
+            return Ok(tus_response(StatusCode::CONFLICT)
+                .with_header(UPLOAD_OFFSET, upload.offset.to_string()));
+        }
+
+        // Chunks are rejected if they go past the declared length
+        let remaining = upload.length - upload.offset;
+        let bytes = match fetch_body(req, remaining as usize, session.session_id).await {
+            Some(bytes) => bytes,
+            None => {
+                return Err(trc::LimitEvent::SizeUpload
+                    .into_err()
+                    .ctx(trc::Key::Limit, remaining));
+            }
+        };
+
+        if !bytes.is_empty() {
+            let blob_id = self
+                .put_blob(document_id, &bytes, true)
+                .await
+                .caused_by(trc::location!())?;
+            let until = match blob_id.class {
+                BlobClass::Reserved { expires, .. } => expires,
+                BlobClass::Linked { .. } => 0,
+            };
+            upload.offset += bytes.len() as u64;
+            upload.chunks.push(UploadChunk {
+                hash: blob_id.hash,
+                until,
+                size: bytes.len() as u64,
+            });
+        }
+
+        if upload.offset < upload.length {
+            let offset = upload.offset;
+            let expires = now() + self.core.jmap.upload_tmp_ttl;
+            upload.expires = expires;
+            self.store_upload(document_id, upload_id, upload).await?;
+
+            return Ok(tus_response(StatusCode::NO_CONTENT)
+                .with_header(UPLOAD_OFFSET, offset.to_string())
+                .with_header(UPLOAD_EXPIRES, http_date(expires)));
+        }
+
+        // Upload completed, assemble the chunks into a single blob
+        let mut data = Vec::with_capacity(upload.length as usize);
+        for chunk in &upload.chunks {
+            data.extend(
+                self.get_blob(&chunk.hash, 0..usize::MAX)
+                    .await
+                    .caused_by(trc::location!())?
+                    .ok_or_else(|| {
+                        trc::StoreEvent::NotFound
+                            .into_err()
+                            .details("Upload chunk not found")
+                            .caused_by(trc::location!())
+                    })?,
+            );
+        }
+        let blob_id = self
+            .put_blob(document_id, &data, true)
+            .await
+            .caused_by(trc::location!())?;
+        self.release_upload(document_id, upload_id, &upload).await?;
+
+        Ok(HttpResponse::new(StatusCode::OK)
+            .with_header(TUS_RESUMABLE, TUS_VERSION)
+            .with_header(UPLOAD_OFFSET, upload.offset.to_string())
+            .with_content_type("application/json; charset=utf-8")
+            .with_text_body(
+                serde_json::to_string(&UploadResponse {
+                    account_id,
+                    blob_id,
+                    c_type: upload.content_type,
+                    size: data.len(),
+                })
+                .unwrap_or_default(),
+            ))
+    }
+
+    async fn get_upload(
+        &self,
+        account_id: u32,
+        upload_id: &str,
+        access_token: &AccessToken,
+    ) -> trc::Result<PartialUpload> {
+        let upload = self
+            .in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_UPLOAD,
+                upload_key(account_id, upload_id),
+            ))
+            .await?
+            .map(|upload| upload.deserialize::<PartialUpload>())
+            .transpose()
+            .caused_by(trc::location!())?
+            .filter(|upload| upload.owner_id == access_token.primary_id())
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+        if upload.expires > now() {
+            Ok(upload)
+        } else {
+            Err(trc::ResourceEvent::NotFound.into_err())
+        }
+    }
+
+    async fn store_upload(
+        &self,
+        account_id: u32,
+        upload_id: &str,
+        upload: PartialUpload,
+    ) -> trc::Result<()> {
+        let expires_in = upload.expires.saturating_sub(now());
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_UPLOAD,
+                    upload_key(account_id, upload_id),
+                    Archiver::new(upload)
+                        .untrusted()
+                        .serialize()
+                        .caused_by(trc::location!())?,
+                )
+                .expires(expires_in),
+            )
+            .await
+            .caused_by(trc::location!())
+    }
+
+    // Removes the chunk reservations so the blob purge task can reclaim them.
+    // Chunks of uploads that are never completed or terminated are reclaimed
+    // once their reservation expires.
+    async fn release_upload(
+        &self,
+        account_id: u32,
+        upload_id: &str,
+        upload: &PartialUpload,
+    ) -> trc::Result<()> {
+        if !upload.chunks.is_empty() {
+            let mut batch = BatchBuilder::new();
+            batch.with_account_id(account_id);
+            for chunk in &upload.chunks {
+                batch.clear(BlobOp::Reserve {
+                    hash: chunk.hash.clone(),
+                    until: chunk.until,
+                });
+            }
+            self.core
+                .storage
+                .data
+                .write(batch.build_all())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        self.in_memory_store()
+            .key_delete(KeyValue::<()>::build_key(
+                KV_UPLOAD,
+                upload_key(account_id, upload_id),
+            ))
+            .await
+            .caused_by(trc::location!())
+    }
+}
+
+fn upload_key(account_id: u32, upload_id: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(upload_id.len() + 4);
+    key.extend_from_slice(&account_id.to_be_bytes());
+    key.extend_from_slice(upload_id.as_bytes());
+    key
+}
+
+fn tus_response(status: StatusCode) -> HttpResponse {
+    HttpResponse::new(status).with_header(TUS_RESUMABLE, TUS_VERSION)
+}
+
+fn http_date(timestamp: u64) -> String {
+    mail_parser::DateTime::from_timestamp(timestamp as i64).to_rfc822()
+}
+
+pub fn tus_options_response(server: &Server) -> HttpResponse {
+    tus_response(StatusCode::NO_CONTENT)
+        .with_header("Tus-Version", TUS_VERSION)
+        .with_header("Tus-Extension", TUS_EXTENSIONS)
+        .with_header("Tus-Max-Size", server.core.jmap.upload_max_size.to_string())
+}
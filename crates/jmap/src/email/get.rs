@@ -14,7 +14,10 @@ use crate::{
 use common::{Server, auth::AccessToken};
 use email::{
     cache::{MessageCacheFetch, email::MessageCacheAccess},
-    message::metadata::{ArchivedMetadataPartType, MessageMetadata},
+    message::{
+        metadata::{ArchivedMetadataPartType, MessageMetadata},
+        signature::MessageSignature,
+    },
 };
 use jmap_proto::{
     method::get::{GetRequest, GetResponse},
@@ -127,19 +130,43 @@ impl EmailGet for Server {
         // Check if we need to fetch the raw headers or body
         let mut needs_body = false;
         for property in &properties {
-            if matches!(
-                property,
+            match property {
                 Property::BodyValues
-                    | Property::TextBody
-                    | Property::HtmlBody
-                    | Property::Attachments
-                    | Property::BodyStructure
-            ) {
-                needs_body = true;
-                break;
+                    if fetch_text_body_values
+                        || fetch_html_body_values
+                        || fetch_all_body_values =>
+                {
+                    needs_body = true;
+                    break;
+                }
+                Property::TextBody
+                | Property::HtmlBody
+                | Property::Attachments
+                | Property::BodyStructure => {
+                    // Body parts are served from the stored metadata,
+                    // only part headers require the raw message
+                    if body_properties
+                        .iter()
+                        .any(|p| matches!(p, Property::Header(_) | Property::Headers))
+                    {
+                        needs_body = true;
+                        break;
+                    }
+                }
+                _ => (),
             }
         }
 
+        let needs_signature = properties.iter().any(|property| {
+            matches!(
+                property,
+                Property::SmimeStatus
+                    | Property::SmimeStatusAtDelivery
+                    | Property::SmimeErrors
+                    | Property::SmimeVerifiedAt
+            )
+        });
+
         for id in ids {
             // Obtain the email object
             if !message_ids.contains(id.document_id()) {
@@ -196,6 +223,22 @@ impl EmailGet for Server {
             } else {
                 metadata.raw_headers.as_slice().into()
             };
+            let signature_ = if needs_signature {
+                self.get_archive_by_property(
+                    account_id,
+                    Collection::Email,
+                    id.document_id(),
+                    &Property::SmimeStatusAtDelivery,
+                )
+                .await?
+            } else {
+                None
+            };
+            let signature = signature_
+                .as_ref()
+                .map(|signature| signature.unarchive::<MessageSignature>())
+                .transpose()
+                .caused_by(trc::location!())?;
             let blob_id = BlobId {
                 hash: blob_hash,
                 class: BlobClass::Linked {
@@ -396,6 +439,38 @@ impl EmailGet for Server {
                         }
                         email.append(Property::BodyValues, body_values);
                     }
+                    // Signatures are only verified at delivery time, PGP/MIME
+                    // signatures are reported using the same status values
+                    Property::SmimeStatus | Property::SmimeStatusAtDelivery => {
+                        email.append(
+                            property.clone(),
+                            signature.map(|signature| signature.jmap_status()),
+                        );
+                    }
+                    Property::SmimeErrors => {
+                        email.append(
+                            Property::SmimeErrors,
+                            signature
+                                .filter(|signature| !signature.errors.is_empty())
+                                .map(|signature| {
+                                    signature
+                                        .errors
+                                        .iter()
+                                        .map(|error| error.to_string())
+                                        .collect::<Vec<_>>()
+                                }),
+                        );
+                    }
+                    Property::SmimeVerifiedAt => {
+                        email.append(
+                            Property::SmimeVerifiedAt,
+                            signature.map(|signature| {
+                                Value::Date(UTCDate::from_timestamp(
+                                    u64::from(signature.verified_at) as i64,
+                                ))
+                            }),
+                        );
+                    }
 
                     _ => {
                         return Err(trc::JmapEvent::InvalidArguments
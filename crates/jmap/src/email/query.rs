@@ -39,12 +39,23 @@ impl EmailQuery for Server {
         access_token: &AccessToken,
     ) -> trc::Result<QueryResponse> {
         let account_id = request.account_id.document_id();
+        let language = self.account_language(account_id).await;
         let mut filters = Vec::with_capacity(request.filter.len());
         let cached_messages = self
             .get_cached_messages(account_id)
             .await
             .caused_by(trc::location!())?;
 
+        // Restrict shared accounts to the messages the user can read, the mask
+        // is applied within the store and full-text queries rather than on the results
+        let shared_messages = if access_token.is_shared(account_id) {
+            let shared_messages = cached_messages.shared_messages(access_token, Acl::ReadItems);
+            filters.push(query::Filter::is_in_set(shared_messages.clone()));
+            Some(shared_messages)
+        } else {
+            None
+        };
+
         for cond_group in std::mem::take(&mut request.filter).into_filter_group() {
             match cond_group {
                 FilterGroup::Fts(conds) => {
@@ -76,17 +87,17 @@ impl EmailQuery for Server {
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Header(HeaderName::Subject),
                                     &text,
-                                    self.core.jmap.default_language,
+                                    language,
                                 ));
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Body,
                                     &text,
-                                    self.core.jmap.default_language,
+                                    language,
                                 ));
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Attachment,
                                     text,
-                                    self.core.jmap.default_language,
+                                    language,
                                 ));
                                 fts_filters.push(FtsFilter::End);
                             }
@@ -113,12 +124,12 @@ impl EmailQuery for Server {
                             Filter::Subject(text) => fts_filters.push(FtsFilter::has_text_detect(
                                 Field::Header(HeaderName::Subject),
                                 text,
-                                self.core.jmap.default_language,
+                                language,
                             )),
                             Filter::Body(text) => fts_filters.push(FtsFilter::has_text_detect(
                                 Field::Body,
                                 text,
-                                self.core.jmap.default_language,
+                                language,
                             )),
                             Filter::Header(header) => {
                                 let mut header = header.into_iter();
@@ -177,8 +188,13 @@ impl EmailQuery for Server {
                         }
                     }
                     filters.push(query::Filter::is_in_set(
-                        self.fts_filter(account_id, Collection::Email, fts_filters)
-                            .await?,
+                        self.fts_filter(
+                            account_id,
+                            Collection::Email,
+                            fts_filters,
+                            shared_messages.as_ref(),
+                        )
+                        .await?,
                     ));
                 }
                 FilterGroup::Store(cond) => {
@@ -299,10 +315,7 @@ impl EmailQuery for Server {
             }
         }
 
-        let mut result_set = self.filter(account_id, Collection::Email, filters).await?;
-        if access_token.is_shared(account_id) {
-            result_set.apply_mask(cached_messages.shared_messages(access_token, Acl::ReadItems));
-        }
+        let result_set = self.filter(account_id, Collection::Email, filters).await?;
         let (response, paginate) = self
             .build_query_response(&result_set, cached_messages.get_state(false), &request)
             .await?;
@@ -44,14 +44,16 @@ impl EmailSearchSnippet for Server {
         let mut include_term = true;
         let mut terms = vec![];
         let mut is_exact = false;
-        let mut language = self.core.jmap.default_language;
+        let account_id = request.account_id.document_id();
+        let default_language = self.account_language(account_id).await;
+        let mut language = default_language;
 
         for cond in request.filter {
             match cond {
                 Filter::Text(text) | Filter::Subject(text) | Filter::Body(text) => {
                     if include_term {
                         let (text, language_) =
-                            Language::detect(text, self.core.jmap.default_language);
+                            Language::detect(text, default_language);
                         language = language_;
                         if (text.starts_with('"') && text.ends_with('"'))
                             || (text.starts_with('\'') && text.ends_with('\''))
@@ -85,7 +87,6 @@ impl EmailSearchSnippet for Server {
                 _ => (),
             }
         }
-        let account_id = request.account_id.document_id();
         let cached_messages = self
             .get_cached_messages(account_id)
             .await
@@ -72,11 +72,12 @@ impl JmapMethods for Server {
         account_id: u32,
         collection: Collection,
         filters: Vec<FtsFilter<T>>,
+        mask: Option<&RoaringBitmap>,
     ) -> trc::Result<RoaringBitmap> {
         self.core
             .storage
             .fts
-            .query(account_id, collection, filters)
+            .query_masked(account_id, collection, filters, mask)
             .await
             .add_context(|err| {
                 err.caused_by(trc::location!())
@@ -179,6 +180,7 @@ pub trait JmapMethods: Sync + Send {
         account_id: u32,
         collection: Collection,
         filters: Vec<FtsFilter<T>>,
+        mask: Option<&RoaringBitmap>,
     ) -> impl Future<Output = trc::Result<RoaringBitmap>> + Send;
 
     fn build_query_response<T: Sync + Send>(
@@ -5,7 +5,10 @@
  */
 
 use common::{Server, auth::AccessToken, sharing::EffectiveAcl};
-use email::cache::{MessageCacheFetch, email::MessageCacheAccess, mailbox::MailboxCacheAccess};
+use email::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess, mailbox::MailboxCacheAccess},
+    mailbox::counters::{MailboxCounterAccess, MailboxCounters},
+};
 use jmap_proto::{
     method::get::{GetRequest, GetResponse, RequestArguments},
     types::{
@@ -73,6 +76,10 @@ impl MailboxGet for Server {
             not_found: vec![],
         };
 
+        let fetch_counters = properties
+            .iter()
+            .any(|p| matches!(p, Property::TotalEmails | Property::UnreadEmails));
+
         for id in ids {
             // Obtain the mailbox object
             let document_id = id.document_id();
@@ -89,6 +96,11 @@ impl MailboxGet for Server {
             };
 
             let mut mailbox = Object::with_capacity(properties.len());
+            let counters = if fetch_counters {
+                self.mailbox_counters(account_id, document_id).await?
+            } else {
+                MailboxCounters::default()
+            };
 
             for property in &properties {
                 let value = match property {
@@ -109,14 +121,8 @@ impl MailboxGet for Server {
                             Value::Null
                         }
                     }
-                    Property::TotalEmails => {
-                        Value::UnsignedInt(cache.in_mailbox(document_id).count() as u64)
-                    }
-                    Property::UnreadEmails => Value::UnsignedInt(
-                        cache
-                            .in_mailbox_without_keyword(document_id, &Keyword::Seen)
-                            .count() as u64,
-                    ),
+                    Property::TotalEmails => Value::UnsignedInt(counters.total),
+                    Property::UnreadEmails => Value::UnsignedInt(counters.unseen),
                     Property::TotalThreads => Value::UnsignedInt(
                         cache
                             .in_mailbox(document_id)
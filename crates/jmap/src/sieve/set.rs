@@ -103,11 +103,12 @@ impl SieveScriptSet for Server {
                 .await?,
         };
         let will_destroy = request.unwrap_destroy();
+        let mut script_count = sieve_ids.len() as usize;
 
         // Process creates
         let mut batch = BatchBuilder::new();
         for (id, object) in request.unwrap_create() {
-            if sieve_ids.len() as usize <= self.core.jmap.sieve_max_scripts {
+            if script_count < self.core.jmap.sieve_max_scripts {
                 match self
                     .sieve_set_item(object, None, &ctx, session.session_id)
                     .await?
@@ -132,6 +133,7 @@ impl SieveScriptSet for Server {
                             .custom(builder.with_tenant_id(&ctx.resource_token))
                             .caused_by(trc::location!())?
                             .commit_point();
+                        script_count += 1;
 
                         // Add result with updated blobId
                         ctx.response.created.insert(
@@ -388,15 +390,17 @@ impl SieveScriptSet for Server {
                             .with_description(
                                 "The 'vacation' name is reserved, please use a different name.",
                             )));
-                    } else if update
-                        .as_ref()
-                        .is_none_or(|(_, obj)| obj.inner.name != value)
-                    {
+                    } else if update.as_ref().is_none_or(|(_, obj)| {
+                        obj.inner.name.to_lowercase() != value.to_lowercase()
+                    }) {
                         if let Some(id) = self
                             .filter(
                                 ctx.resource_token.account_id,
                                 Collection::SieveScript,
-                                vec![Filter::eq(Property::Name, value.as_bytes().to_vec())],
+                                vec![Filter::eq(
+                                    Property::Name,
+                                    value.to_lowercase().into_bytes(),
+                                )],
                             )
                             .await?
                             .results
@@ -9,6 +9,7 @@ use jmap_proto::{
     error::set::{SetError, SetErrorType},
     method::validate::{ValidateSieveScriptRequest, ValidateSieveScriptResponse},
 };
+use sieve::compiler::ErrorType;
 use std::future::Future;
 
 use crate::blob::download::BlobDownload;
@@ -35,9 +36,15 @@ impl SieveScriptValidate for Server {
                 .map(|bytes| self.core.sieve.untrusted_compiler.compile(&bytes))
             {
                 Some(Ok(_)) => None,
-                Some(Err(err)) => SetError::new(SetErrorType::InvalidScript)
+                Some(Err(err)) => {
+                    SetError::new(if let ErrorType::ScriptTooLong = &err.error_type() {
+                        SetErrorType::TooLarge
+                    } else {
+                        SetErrorType::InvalidScript
+                    })
                     .with_description(err.to_string())
-                    .into(),
+                    .into()
+                }
                 None => SetError::new(SetErrorType::BlobNotFound).into(),
             },
         })
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use common::{Server, auth::AccessToken};
 use futures_util::{SinkExt, StreamExt};
@@ -13,19 +13,26 @@ use hyper::upgrade::Upgraded;
 use hyper_util::rt::TokioIo;
 use jmap_proto::{
     error::request::RequestError,
+    method::changes::{ChangesRequest, ChangesResponse, RequestArguments},
     request::websocket::{
         WebSocketMessage, WebSocketRequestError, WebSocketResponse, WebSocketStateChange,
     },
-    types::type_state::DataType,
+    types::{ChangeId, id::Id, state::State, type_state::DataType},
 };
 use tokio_tungstenite::WebSocketStream;
 use trc::JmapEvent;
 use tungstenite::Message;
 use utils::map::bitmap::Bitmap;
 
-use crate::api::{ToRequestError, request::RequestHandler};
+use crate::{
+    api::{ToRequestError, request::RequestHandler},
+    changes::get::ChangesLookup,
+};
 use std::future::Future;
 
+// Maximum number of ids included in a delta before clients have to fall back to /changes
+const DELTA_MAX_CHANGES: usize = 100;
+
 pub trait WebSocketHandler: Sync + Send {
     fn handle_websocket_stream(
         &self,
@@ -83,6 +90,7 @@ impl WebSocketHandler for Server {
 
         let mut changes = WebSocketStateChange::new(None);
         let mut change_types: Bitmap<DataType> = Bitmap::new();
+        let mut delta_states: Option<HashMap<(Id, DataType), ChangeId>> = None;
 
         loop {
             tokio::select! {
@@ -114,6 +122,23 @@ impl WebSocketHandler for Server {
                                             } else {
                                                 Bitmap::all()
                                             };
+                                            delta_states = push_enable.delta.then(HashMap::new);
+
+                                            // Only wake up for the requested data types
+                                            match self
+                                                .subscribe_state_manager(access_token.primary_id(), change_types)
+                                                .await
+                                            {
+                                                Ok(new_change_rx) => {
+                                                    change_rx = new_change_rx;
+                                                }
+                                                Err(err) => {
+                                                    trc::error!(
+                                                        err.details("Failed to subscribe to state manager")
+                                                            .span_id(session.session_id)
+                                                    );
+                                                }
+                                            }
                                             continue;
                                         }
                                         Ok(WebSocketMessage::PushDisable) => {
@@ -203,6 +228,28 @@ impl WebSocketHandler for Server {
                 // Send any queued changes
                 let elapsed = last_changes_sent.elapsed();
                 if elapsed >= throttle {
+                    if let Some(delta_states) = &mut delta_states {
+                        for (account_id, states) in changes.changed.iter() {
+                            for (data_type, state) in states.iter() {
+                                if let Some(delta) = self
+                                    .state_delta(
+                                        *account_id,
+                                        *data_type,
+                                        state,
+                                        delta_states,
+                                        &access_token,
+                                    )
+                                    .await
+                                {
+                                    changes
+                                        .delta
+                                        .get_mut_or_insert(*account_id)
+                                        .set(*data_type, delta);
+                                }
+                            }
+                        }
+                    }
+
                     if let Err(err) = stream.send(Message::Text(changes.to_json().into())).await {
                         trc::event!(
                             Jmap(JmapEvent::WebsocketError),
@@ -212,6 +259,7 @@ impl WebSocketHandler for Server {
                         );
                     }
                     changes.changed.clear();
+                    changes.delta.clear();
                     last_changes_sent = Instant::now();
                     last_heartbeat = Instant::now();
                     next_event = heartbeat;
@@ -234,3 +282,56 @@ impl WebSocketHandler for Server {
         }
     }
 }
+
+impl Server {
+    // Returns the ids that changed since the last state pushed for this data type.
+    // The first notification for a data type only establishes the base state.
+    async fn state_delta(
+        &self,
+        account_id: Id,
+        data_type: DataType,
+        state: &State,
+        delta_states: &mut HashMap<(Id, DataType), ChangeId>,
+        access_token: &AccessToken,
+    ) -> Option<ChangesResponse> {
+        let arguments = match data_type {
+            DataType::Email => RequestArguments::Email,
+            DataType::Mailbox => RequestArguments::Mailbox,
+            DataType::Thread => RequestArguments::Thread,
+            DataType::Identity => RequestArguments::Identity,
+            DataType::EmailSubmission => RequestArguments::EmailSubmission,
+            _ => return None,
+        };
+        let State::Exact(change_id) = state else {
+            return None;
+        };
+        let since = delta_states.insert((account_id, data_type), *change_id)?;
+
+        match self
+            .changes(
+                ChangesRequest {
+                    account_id,
+                    since_state: State::Exact(since),
+                    max_changes: Some(DELTA_MAX_CHANGES),
+                    arguments,
+                },
+                access_token,
+            )
+            .await
+        {
+            Ok(delta) => {
+                if let State::Exact(change_id) = &delta.new_state {
+                    delta_states.insert((account_id, data_type), *change_id);
+                }
+                Some(delta)
+            }
+            Err(err) => {
+                trc::error!(
+                    err.details("Failed to calculate WebSocket push delta")
+                        .account_id(account_id.document_id())
+                );
+                None
+            }
+        }
+    }
+}
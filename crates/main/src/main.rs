@@ -55,8 +55,18 @@ async fn main() -> std::io::Result<()> {
     init.inner.build_server().log_license_details();
 
     // Spawn servers
+    let serve_users = init.inner.shared_core.load().network.roles.serve_users;
     let (shutdown_tx, shutdown_rx) = init.servers.spawn(|server, acceptor, shutdown_rx| {
         match &server.protocol {
+            ServerProtocol::Imap | ServerProtocol::Pop3 | ServerProtocol::ManageSieve
+                if !serve_users =>
+            {
+                trc::event!(
+                    Network(trc::NetworkEvent::ListenStop),
+                    ListenerId = server.id,
+                    Details = "Node does not have the frontend role",
+                );
+            }
             ServerProtocol::Smtp | ServerProtocol::Lmtp => server.spawn(
                 SmtpSessionManager::new(init.inner.clone()),
                 init.inner.clone(),
@@ -150,14 +150,7 @@ impl<T: SessionStream> Session<T> {
             Command::Capability | Command::Logout | Command::Noop => Ok(command),
             Command::Authenticate => {
                 if let State::NotAuthenticated { .. } = &self.state {
-                    if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
-                        Ok(command)
-                    } else {
-                        Err(trc::ManageSieveEvent::Error
-                            .into_err()
-                            .code(ResponseCode::EncryptNeeded)
-                            .details("Cannot authenticate over plain-text."))
-                    }
+                    Ok(command)
                 } else {
                     Err(trc::ManageSieveEvent::Error
                         .into_err()
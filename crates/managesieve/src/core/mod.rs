@@ -11,7 +11,7 @@ use std::{borrow::Cow, net::IpAddr, sync::Arc};
 
 use common::{
     Inner, Server,
-    auth::AccessToken,
+    auth::{AccessToken, scram::ScramSession},
     listener::{ServerInstance, limiter::InFlight},
 };
 
@@ -28,6 +28,7 @@ pub struct Session<T: AsyncRead + AsyncWrite> {
     pub stream: T,
     pub session_id: u64,
     pub in_flight: InFlight,
+    pub scram: Option<ScramSession>,
 }
 
 pub enum State {
@@ -127,7 +128,7 @@ pub enum ResponseCode {
     QuotaMaxScripts,
     QuotaMaxSize,
     Referral,
-    Sasl,
+    Sasl(String),
     TransitionNeeded,
     TryLater,
     Active,
@@ -146,7 +147,12 @@ impl ResponseCode {
             ResponseCode::QuotaMaxScripts => b"QUOTA/MAXSCRIPTS",
             ResponseCode::QuotaMaxSize => b"QUOTA/MAXSIZE",
             ResponseCode::Referral => b"REFERRAL",
-            ResponseCode::Sasl => b"SASL",
+            ResponseCode::Sasl(data) => {
+                buf.extend_from_slice(b"SASL \"");
+                buf.extend_from_slice(data.as_bytes());
+                buf.push(b'"');
+                return;
+            }
             ResponseCode::TransitionNeeded => b"TRANSITION-NEEDED",
             ResponseCode::TryLater => b"TRYLATER",
             ResponseCode::Active => b"ACTIVE",
@@ -171,7 +177,7 @@ impl ResponseCode {
             ResponseCode::QuotaMaxScripts => "QUOTA/MAXSCRIPTS",
             ResponseCode::QuotaMaxSize => "QUOTA/MAXSIZE",
             ResponseCode::Referral => "REFERRAL",
-            ResponseCode::Sasl => "SASL",
+            ResponseCode::Sasl(_) => "SASL",
             ResponseCode::TransitionNeeded => "TRANSITION-NEEDED",
             ResponseCode::TryLater => "TRYLATER",
             ResponseCode::Active => "ACTIVE",
@@ -34,6 +34,7 @@ impl SessionManager for ManageSieveSessionManager {
                 stream: session.stream,
                 in_flight: session.in_flight,
                 remote_addr: session.remote_ip,
+                scram: None,
             };
 
             if session
@@ -81,7 +82,7 @@ impl<T: SessionStream> Session<T> {
                                         SessionResult::UpgradeTls => {
                                             return true;
                                         }
-                                        SessionResult::Close => {
+                                        SessionResult::Close | SessionResult::UpgradeCompress => {
                                             break;
                                         }
                                     }
@@ -146,6 +147,7 @@ impl<T: SessionStream> Session<T> {
             server: self.server,
             receiver: self.receiver,
             remote_addr: self.remote_addr,
+            scram: None,
         })
     }
 }
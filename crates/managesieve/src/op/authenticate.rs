@@ -4,22 +4,26 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::sync::Arc;
+
 use common::{
     auth::{
-        AuthRequest,
+        AccessToken, AuthRequest,
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
+        scram::{ScramSession, ScramStep},
     },
     listener::{SessionStream, limiter::LimiterResult},
 };
 
-use directory::Permission;
+use directory::{Permission, core::app_password::AuthProtocol};
 use imap_proto::{
     protocol::authenticate::Mechanism,
     receiver::{self, Request},
 };
+use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 
-use crate::core::{Command, Session, State, StatusResponse};
+use crate::core::{Command, ResponseCode, Session, State, StatusResponse};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_authenticate(&mut self, request: Request<Command>) -> trc::Result<Vec<u8>> {
@@ -37,6 +41,17 @@ impl<T: SessionStream> Session<T> {
             .collect();
 
         let credentials = match mechanism {
+            Mechanism::Plain | Mechanism::OAuthBearer | Mechanism::XOauth2
+                if !self.is_plain_auth_allowed() =>
+            {
+                return Err(trc::ManageSieveEvent::Error
+                    .into_err()
+                    .code(ResponseCode::EncryptNeeded)
+                    .details("Cannot authenticate over plain-text."));
+            }
+            Mechanism::ScramSha256 | Mechanism::ScramSha256Plus => {
+                return self.handle_scram(mechanism, params.pop()).await;
+            }
             Mechanism::Plain | Mechanism::OAuthBearer | Mechanism::XOauth2 => {
                 if !params.is_empty() {
                     base64_decode(params.pop().unwrap().as_bytes())
@@ -53,13 +68,7 @@ impl<T: SessionStream> Session<T> {
                                 .details("Failed to decode challenge.")
                         })?
                 } else {
-                    self.receiver.request = receiver::Request {
-                        tag: "".into(),
-                        command: Command::Authenticate,
-                        tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
-                    };
-                    self.receiver.state = receiver::State::Argument { last_ch: b' ' };
-                    return Ok(b"{0}\r\n".to_vec());
+                    return Ok(self.request_sasl_continuation(mechanism, b"{0}\r\n".to_vec()));
                 }
             }
             _ => {
@@ -70,14 +79,113 @@ impl<T: SessionStream> Session<T> {
         };
 
         // Authenticate
-        let access_token = self
+        let result = self
+            .server
+            .authenticate(
+                &AuthRequest::from_credentials(credentials, self.session_id, self.remote_addr)
+                    .with_protocol(AuthProtocol::ManageSieve),
+            )
+            .await;
+
+        self.handle_auth_result(result, None).await
+    }
+
+    async fn handle_scram(
+        &mut self,
+        mechanism: Mechanism,
+        param: Option<String>,
+    ) -> trc::Result<Vec<u8>> {
+        // Continue an ongoing exchange or start a new one
+        let channel_binding = self.stream.tls_channel_binding();
+        let mut scram = self.scram.take().unwrap_or_else(|| {
+            ScramSession::new(
+                mechanism == Mechanism::ScramSha256Plus,
+                self.auth_mechanisms().contains(&Mechanism::ScramSha256Plus),
+                channel_binding.clone(),
+            )
+        });
+        if scram.is_plus() && channel_binding.is_none() {
+            return Err(trc::AuthEvent::Error
+                .into_err()
+                .details("Channel binding is not available for this session."));
+        }
+
+        let message = match param {
+            Some(param) if !param.is_empty() => {
+                base64_decode(param.as_bytes()).ok_or_else(|| {
+                    trc::AuthEvent::Error
+                        .into_err()
+                        .details("Failed to decode challenge.")
+                })?
+            }
+            _ if !scram.has_started() => {
+                // SCRAM is client-first, request the initial client message
+                self.scram = Some(scram);
+                return Ok(self.request_sasl_continuation(mechanism, b"\"\"\r\n".to_vec()));
+            }
+            _ => Vec::new(),
+        };
+
+        let challenge = match self
             .server
-            .authenticate(&AuthRequest::from_credentials(
-                credentials,
+            .authenticate_scram(
+                &mut scram,
+                &message,
                 self.session_id,
                 self.remote_addr,
-            ))
+                None,
+            )
             .await
+        {
+            Ok(ScramStep::Challenge(challenge)) => challenge,
+            Ok(ScramStep::Success(access_token)) => {
+                return self.handle_auth_result(Ok(access_token), None).await;
+            }
+            Err(err) => return self.handle_auth_result(Err(err), None).await,
+        };
+        let challenge =
+            String::from_utf8(base64_encode(&challenge).unwrap_or_default()).unwrap_or_default();
+
+        if scram.is_verified() {
+            // ManageSieve returns the server-final message in the OK response (RFC 5804)
+            let result = match self
+                .server
+                .authenticate_scram(&mut scram, &[], self.session_id, self.remote_addr, None)
+                .await
+            {
+                Ok(ScramStep::Success(access_token)) => Ok(access_token),
+                Ok(ScramStep::Challenge(_)) => Err(trc::AuthEvent::Error
+                    .into_err()
+                    .details("Unexpected SCRAM state.")),
+                Err(err) => Err(err),
+            };
+            self.handle_auth_result(result, Some(challenge)).await
+        } else {
+            self.scram = Some(scram);
+            let mut response = Vec::with_capacity(challenge.len() + 4);
+            response.push(b'"');
+            response.extend_from_slice(challenge.as_bytes());
+            response.extend_from_slice(b"\"\r\n");
+            Ok(self.request_sasl_continuation(mechanism, response))
+        }
+    }
+
+    fn request_sasl_continuation(&mut self, mechanism: Mechanism, response: Vec<u8>) -> Vec<u8> {
+        self.receiver.request = receiver::Request {
+            tag: "".into(),
+            command: Command::Authenticate,
+            tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
+        };
+        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+        response
+    }
+
+    async fn handle_auth_result(
+        &mut self,
+        result: trc::Result<Arc<AccessToken>>,
+        server_final: Option<String>,
+    ) -> trc::Result<Vec<u8>> {
+        let access_token = result
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
                     match &self.state {
@@ -117,7 +225,35 @@ impl<T: SessionStream> Session<T> {
             in_flight,
         };
 
-        Ok(StatusResponse::ok("Authentication successful").into_bytes())
+        let response = StatusResponse::ok("Authentication successful");
+        Ok(if let Some(server_final) = server_final {
+            response.with_code(ResponseCode::Sasl(server_final))
+        } else {
+            response
+        }
+        .into_bytes())
+    }
+
+    pub fn is_plain_auth_allowed(&self) -> bool {
+        self.instance
+            .is_plain_auth_allowed(&self.stream, self.server.core.imap.allow_plain_auth)
+    }
+
+    pub fn auth_mechanisms(&self) -> Vec<Mechanism> {
+        let mut mechanisms = Vec::with_capacity(5);
+        if self.is_plain_auth_allowed() {
+            mechanisms.push(Mechanism::Plain);
+        }
+        if self.stream.tls_channel_binding().is_some() {
+            mechanisms.push(Mechanism::ScramSha256Plus);
+        }
+        mechanisms.push(Mechanism::ScramSha256);
+
+        // Bearer tokens are sent in the clear, same as PLAIN credentials
+        if self.is_plain_auth_allowed() {
+            mechanisms.extend([Mechanism::OAuthBearer, Mechanism::XOauth2]);
+        }
+        mechanisms
     }
 
     pub async fn handle_unauthenticate(&mut self) -> trc::Result<Vec<u8>> {
@@ -21,11 +21,14 @@ impl<T: SessionStream> Session<T> {
         if !self.stream.is_tls() {
             response.extend_from_slice(b"\"STARTTLS\"\r\n");
         }
-        if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
-            response.extend_from_slice(b"\"SASL\" \"PLAIN OAUTHBEARER XOAUTH2\"\r\n");
-        } else {
-            response.extend_from_slice(b"\"SASL\" \"OAUTHBEARER XOAUTH2\"\r\n");
-        };
+        response.extend_from_slice(b"\"SASL\" \"");
+        for (pos, mechanism) in self.auth_mechanisms().into_iter().enumerate() {
+            if pos > 0 {
+                response.push(b' ');
+            }
+            mechanism.serialize(&mut response);
+        }
+        response.extend_from_slice(b"\"\r\n");
         if let Some(sieve) =
             self.server
                 .core
@@ -62,7 +65,7 @@ impl<T: SessionStream> Session<T> {
             ManageSieve(trc::ManageSieveEvent::Capabilities),
             SpanId = self.session_id,
             Tls = self.stream.is_tls(),
-            Strict = !self.server.core.imap.allow_plain_auth,
+            Strict = !self.is_plain_auth_allowed(),
             Elapsed = op_start.elapsed()
         );
 
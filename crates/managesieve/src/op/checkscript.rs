@@ -6,7 +6,7 @@
 
 use std::time::Instant;
 
-use common::listener::SessionStream;
+use common::{listener::SessionStream, scripts::diagnostic::SieveDiagnostic};
 use directory::Permission;
 use imap_proto::receiver::Request;
 
@@ -44,7 +44,7 @@ impl<T: SessionStream> Session<T> {
             .map_err(|err| {
                 trc::ManageSieveEvent::Error
                     .into_err()
-                    .details(err.to_string())
+                    .details(SieveDiagnostic::from(&err).to_string())
             })
     }
 }
@@ -46,10 +46,17 @@ impl<T: SessionStream> Session<T> {
         // Validate name
         let access_token = self.state.access_token();
         let account_id = access_token.primary_id();
-        self.validate_name(account_id, &name).await?;
+        if self.validate_name(account_id, &name).await?.is_none() {
+            self.assert_has_script_slot(account_id).await?;
+        }
 
         // Validate quota
-        if access_token.quota == 0
+        if size > self.server.core.jmap.sieve_max_script_size {
+            return Err(trc::ManageSieveEvent::Error
+                .into_err()
+                .details("Script is too large.")
+                .code(ResponseCode::QuotaMaxSize));
+        } else if access_token.quota == 0
             || size as i64
                 + self
                     .server
@@ -5,7 +5,10 @@
  */
 
 use crate::core::{Command, ResponseCode, Session, StatusResponse};
-use common::{listener::SessionStream, storage::index::ObjectIndexBuilder};
+use common::{
+    listener::SessionStream, scripts::diagnostic::SieveDiagnostic,
+    storage::index::ObjectIndexBuilder,
+};
 use directory::Permission;
 use email::sieve::SieveScript;
 use imap_proto::receiver::Request;
@@ -54,21 +57,6 @@ impl<T: SessionStream> Session<T> {
             .await
             .caused_by(trc::location!())?;
 
-        if self
-            .server
-            .get_document_ids(account_id, Collection::SieveScript)
-            .await
-            .caused_by(trc::location!())?
-            .map(|ids| ids.len() as usize)
-            .unwrap_or(0)
-            > self.server.core.jmap.sieve_max_scripts
-        {
-            return Err(trc::ManageSieveEvent::Error
-                .into_err()
-                .details("Too many scripts.")
-                .code(ResponseCode::QuotaMaxScripts));
-        }
-
         // Compile script
         match self
             .server
@@ -86,15 +74,14 @@ impl<T: SessionStream> Session<T> {
                 );
             }
             Err(err) => {
+                let diagnostic = SieveDiagnostic::from(&err).to_string();
                 return Err(if let ErrorType::ScriptTooLong = &err.error_type() {
                     trc::ManageSieveEvent::Error
                         .into_err()
-                        .details(err.to_string())
+                        .details(diagnostic)
                         .code(ResponseCode::QuotaMaxSize)
                 } else {
-                    trc::ManageSieveEvent::Error
-                        .into_err()
-                        .details(err.to_string())
+                    trc::ManageSieveEvent::Error.into_err().details(diagnostic)
                 });
             }
         }
@@ -159,6 +146,9 @@ impl<T: SessionStream> Session<T> {
                 Elapsed = op_start.elapsed(),
             );
         } else {
+            // Check script count quota
+            self.assert_has_script_slot(account_id).await?;
+
             // Write script blob
             let blob_hash = self
                 .server
@@ -206,6 +196,25 @@ impl<T: SessionStream> Session<T> {
         Ok(StatusResponse::ok("Success.").into_bytes())
     }
 
+    pub async fn assert_has_script_slot(&self, account_id: u32) -> trc::Result<()> {
+        if self
+            .server
+            .get_document_ids(account_id, Collection::SieveScript)
+            .await
+            .caused_by(trc::location!())?
+            .map(|ids| ids.len() as usize)
+            .unwrap_or(0)
+            < self.server.core.jmap.sieve_max_scripts
+        {
+            Ok(())
+        } else {
+            Err(trc::ManageSieveEvent::Error
+                .into_err()
+                .details("Too many scripts.")
+                .code(ResponseCode::QuotaMaxScripts))
+        }
+    }
+
     pub async fn validate_name(&self, account_id: u32, name: &str) -> trc::Result<Option<u32>> {
         if name.is_empty() {
             Err(trc::ManageSieveEvent::Error
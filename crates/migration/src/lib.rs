@@ -8,6 +8,7 @@ use crate::calendar::migrate_calendar_events;
 use changelog::reset_changelog;
 use common::{DATABASE_SCHEMA_VERSION, KV_LOCK_HOUSEKEEPER, Server};
 use jmap_proto::types::{collection::Collection, property::Property};
+use mailbox::migrate_mailbox_counters;
 use principal::{migrate_principal, migrate_principals};
 use queue::migrate_queue;
 use report::migrate_reports;
@@ -69,8 +70,16 @@ pub async fn try_migrate(server: &Server) -> trc::Result<()> {
         Some(DATABASE_SCHEMA_VERSION) => {
             return Ok(());
         }
+        Some(2) => {
+            migrate_mailbox_counters(server)
+                .await
+                .caused_by(trc::location!())?;
+        }
         Some(1) => {
             migrate_v0_12_0(server).await.caused_by(trc::location!())?;
+            migrate_mailbox_counters(server)
+                .await
+                .caused_by(trc::location!())?;
         }
         Some(version) => {
             panic!(
@@ -81,6 +90,9 @@ pub async fn try_migrate(server: &Server) -> trc::Result<()> {
         _ => {
             if !is_new_install(server).await.caused_by(trc::location!())? {
                 migrate_v0_11(server).await.caused_by(trc::location!())?;
+                migrate_mailbox_counters(server)
+                    .await
+                    .caused_by(trc::location!())?;
             }
         }
     }
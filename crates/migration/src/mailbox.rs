@@ -7,7 +7,7 @@
 use super::object::Object;
 use crate::object::FromLegacy;
 use common::{Server, config::jmap::settings::SpecialUse};
-use email::mailbox::Mailbox;
+use email::mailbox::{Mailbox, counters::MailboxCounterAccess};
 use jmap_proto::types::{collection::Collection, property::Property, value::Value};
 use store::{
     SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_INDEXES, Serialize, U64_LEN, ValueKey,
@@ -131,6 +131,40 @@ pub(crate) async fn migrate_mailboxes(server: &Server, account_id: u32) -> trc::
     }
 }
 
+pub(crate) async fn migrate_mailbox_counters(server: &Server) -> trc::Result<()> {
+    // Mailbox counters were previously derived from the message cache
+    let account_ids = server
+        .get_document_ids(u32::MAX, Collection::Principal)
+        .await
+        .caused_by(trc::location!())?
+        .unwrap_or_default();
+    let mut num_migrated = 0;
+
+    for account_id in account_ids {
+        if server
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await
+            .caused_by(trc::location!())?
+            .is_some_and(|ids| !ids.is_empty())
+        {
+            server
+                .rebuild_mailbox_counters(account_id)
+                .await
+                .caused_by(trc::location!())?;
+            num_migrated += 1;
+        }
+    }
+
+    if num_migrated > 0 {
+        trc::event!(
+            Server(trc::ServerEvent::Startup),
+            Details = format!("Rebuilt mailbox counters for {num_migrated} accounts")
+        );
+    }
+
+    Ok(())
+}
+
 impl FromLegacy for Mailbox {
     fn from_legacy(legacy: Object<Value>) -> Self {
         Mailbox {
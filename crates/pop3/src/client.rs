@@ -188,7 +188,10 @@ impl<T: SessionStream> Session<T> {
             | Command::Pass { .. }
             | Command::Apop { .. } => {
                 if let State::NotAuthenticated { username, .. } = &self.state {
-                    if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
+                    if self
+                        .instance
+                        .is_plain_auth_allowed(&self.stream, self.server.core.imap.allow_plain_auth)
+                    {
                         if !matches!(command, Command::Pass { .. }) || username.is_some() {
                             Ok(command)
                         } else {
@@ -11,7 +11,7 @@ use email::{
     cache::{MessageCacheFetch, mailbox::MailboxCacheAccess},
     mailbox::INBOX_ID,
 };
-use jmap_proto::types::{collection::Collection, property::Property};
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
 use store::{
     IndexKey, IterateParams, SerializeInfallible, U32_LEN, ahash::AHashMap,
     write::key::DeserializeBigEndian,
@@ -31,11 +31,21 @@ pub struct Mailbox {
 
 pub struct Message {
     pub id: u32,
+    pub thread_id: u32,
     pub uid: u32,
     pub size: u32,
     pub deleted: bool,
 }
 
+impl Message {
+    /// Unique identifier returned by UIDL. It is derived from the message's
+    /// JMAP id so that it does not change when the message is moved between
+    /// folders or when the INBOX UID validity is reset.
+    pub fn uidl(&self) -> String {
+        Id::from_parts(self.thread_id, self.id).to_string()
+    }
+}
+
 impl<T: SessionStream> Session<T> {
     pub async fn fetch_mailbox(&self, account_id: u32) -> trc::Result<Mailbox> {
         // Obtain UID validity
@@ -100,9 +110,9 @@ impl<T: SessionStream> Session<T> {
                     .mailboxes
                     .iter()
                     .find(|m| m.mailbox_id == INBOX_ID)
-                    .map(|m| (m.uid, message.document_id))
+                    .map(|m| (m.uid, (message.document_id, message.thread_id)))
             })
-            .collect::<BTreeMap<u32, u32>>();
+            .collect::<BTreeMap<u32, (u32, u32)>>();
 
         // Create mailbox
         let mut mailbox = Mailbox {
@@ -111,10 +121,11 @@ impl<T: SessionStream> Session<T> {
             account_id,
             ..Default::default()
         };
-        for (uid, id) in message_map {
+        for (uid, (id, thread_id)) in message_map {
             if let Some(size) = message_sizes.get(&id) {
                 mailbox.messages.push(Message {
                     id,
+                    thread_id,
                     uid,
                     size: *size,
                     deleted: false,
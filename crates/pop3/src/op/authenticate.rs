@@ -11,7 +11,7 @@ use common::{
     },
     listener::{SessionStream, limiter::LimiterResult},
 };
-use directory::Permission;
+use directory::{Permission, core::app_password::AuthProtocol};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 
@@ -68,11 +68,10 @@ impl<T: SessionStream> Session<T> {
         // Authenticate
         let access_token = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
-                credentials,
-                self.session_id,
-                self.remote_addr,
-            ))
+            .authenticate(
+                &AuthRequest::from_credentials(credentials, self.session_id, self.remote_addr)
+                    .with_protocol(AuthProtocol::Pop3),
+            )
             .await
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
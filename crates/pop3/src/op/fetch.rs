@@ -14,6 +14,9 @@ use trc::AddContext;
 
 use crate::{Session, protocol::response::Response};
 
+// Average line length used to size the initial partial read for TOP
+const TOP_LINE_ESTIMATE: usize = 256;
+
 impl<T: SessionStream> Session<T> {
     pub async fn handle_fetch(&mut self, msg: u32, lines: Option<u32>) -> trc::Result<()> {
         // Validate access
@@ -38,22 +41,76 @@ impl<T: SessionStream> Session<T> {
                 let metadata = metadata_
                     .unarchive::<MessageMetadata>()
                     .caused_by(trc::location!())?;
-                if let Some(bytes) = self
-                    .server
-                    .blob_store()
-                    .get_blob(metadata.blob_hash.0.as_slice(), 0..usize::MAX)
-                    .await
-                    .caused_by(trc::location!())?
-                {
+                let size = u32::from(metadata.size) as usize;
+                let blob_hash = metadata.blob_hash.0.as_slice();
+
+                // TOP only needs the first lines of the message, read the headers and
+                // an estimate of the body lines before falling back to the full blob
+                let bytes = match lines {
+                    Some(lines) if lines > 0 => {
+                        let body_offset = metadata.contents.first().map_or(0, |contents| {
+                            u32::from(contents.root_part().offset_body) as usize
+                        });
+                        let read_end = body_offset
+                            .saturating_add((lines as usize).saturating_mul(TOP_LINE_ESTIMATE));
+
+                        if read_end < size {
+                            match self
+                                .server
+                                .blob_store()
+                                .get_blob(blob_hash, 0..read_end)
+                                .await
+                                .caused_by(trc::location!())?
+                            {
+                                Some(mut bytes) if bytes.len() == read_end => {
+                                    if count_lines(&bytes) < lines {
+                                        match self
+                                            .server
+                                            .blob_store()
+                                            .get_blob(blob_hash, read_end..usize::MAX)
+                                            .await
+                                            .caused_by(trc::location!())?
+                                        {
+                                            Some(remainder) => {
+                                                bytes.extend_from_slice(&remainder);
+                                                Some(bytes)
+                                            }
+                                            None => None,
+                                        }
+                                    } else {
+                                        Some(bytes)
+                                    }
+                                }
+                                bytes => bytes,
+                            }
+                        } else {
+                            self.server
+                                .blob_store()
+                                .get_blob(blob_hash, 0..usize::MAX)
+                                .await
+                                .caused_by(trc::location!())?
+                        }
+                    }
+                    _ => self
+                        .server
+                        .blob_store()
+                        .get_blob(blob_hash, 0..usize::MAX)
+                        .await
+                        .caused_by(trc::location!())?,
+                };
+
+                if let Some(bytes) = bytes {
                     trc::event!(
                         Pop3(trc::Pop3Event::Fetch),
                         SpanId = self.session_id,
                         DocumentId = message.id,
+                        Size = bytes.len(),
                         Elapsed = op_start.elapsed()
                     );
 
                     self.write_bytes(
                         Response::Message::<u32> {
+                            size: size.max(bytes.len()),
                             bytes,
                             lines: lines.unwrap_or(0),
                         }
@@ -77,3 +134,7 @@ impl<T: SessionStream> Session<T> {
         }
     }
 }
+
+fn count_lines(bytes: &[u8]) -> u32 {
+    bytes.iter().filter(|&&byte| byte == b'\n').count() as u32
+}
@@ -68,12 +68,10 @@ impl<T: SessionStream> Session<T> {
                     SpanId = self.session_id,
                     DocumentId = message.id,
                     Uid = message.uid,
-                    UidValidity = mailbox.uid_validity,
                     Elapsed = op_start.elapsed()
                 );
 
-                self.write_ok(format!("{} {}{}", msg, mailbox.uid_validity, message.uid))
-                    .await
+                self.write_ok(format!("{} {}", msg, message.uidl())).await
             } else {
                 Err(trc::Pop3Event::Error
                     .into_err()
@@ -93,7 +91,7 @@ impl<T: SessionStream> Session<T> {
                     mailbox
                         .messages
                         .iter()
-                        .map(|m| format!("{}{}", mailbox.uid_validity, m.uid))
+                        .map(|m| m.uidl())
                         .collect::<Vec<_>>(),
                 )
                 .serialize(),
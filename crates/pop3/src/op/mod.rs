@@ -18,7 +18,10 @@ pub mod list;
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_capa(&mut self) -> trc::Result<()> {
-        let mechanisms = if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
+        let allow_plain_auth = self
+            .instance
+            .is_plain_auth_allowed(&self.stream, self.server.core.imap.allow_plain_auth);
+        let mechanisms = if allow_plain_auth {
             vec![Mechanism::Plain, Mechanism::OAuthBearer, Mechanism::XOauth2]
         } else {
             vec![Mechanism::OAuthBearer, Mechanism::XOauth2]
@@ -28,7 +31,7 @@ impl<T: SessionStream> Session<T> {
             Pop3(trc::Pop3Event::Capabilities),
             SpanId = self.session_id,
             Tls = self.stream.is_tls(),
-            Strict = !self.server.core.imap.allow_plain_auth,
+            Strict = !allow_plain_auth,
             Elapsed = trc::Value::Duration(0)
         );
 
@@ -14,6 +14,7 @@ pub enum Response<T> {
     List(Vec<T>),
     Message {
         bytes: Vec<u8>,
+        size: usize,
         lines: u32,
     },
     Capability {
@@ -51,10 +52,10 @@ impl<T: Display> Response<T> {
                 buf.extend_from_slice(b".\r\n");
                 buf
             }
-            Response::Message { bytes, lines } => {
+            Response::Message { bytes, size, lines } => {
                 let mut buf = Vec::with_capacity(bytes.len() + 10);
                 buf.extend_from_slice(b"+OK ");
-                buf.extend_from_slice(bytes.len().to_string().as_bytes());
+                buf.extend_from_slice(size.to_string().as_bytes());
                 buf.extend_from_slice(b" octets\r\n");
 
                 let mut line_count = 0;
@@ -209,10 +210,21 @@ mod tests {
                     bytes: "Subject: test\r\n\r\n.\r\ntest.\r\n.test\r\na"
                         .as_bytes()
                         .to_vec(),
+                    size: 35,
                     lines: 0,
                 },
                 "+OK 35 octets\r\nSubject: test\r\n\r\n..\r\ntest.\r\n..test\r\na\r\n.\r\n",
             ),
+            (
+                Response::Message {
+                    bytes: "Subject: test\r\n\r\nline 1\r\nline 2\r\n"
+                        .as_bytes()
+                        .to_vec(),
+                    size: 1024,
+                    lines: 3,
+                },
+                "+OK 1024 octets\r\nSubject: test\r\n\r\nline 1\r\n.\r\n",
+            ),
         ] {
             assert_eq!(expected, String::from_utf8(cmd.serialize()).unwrap());
         }
@@ -85,7 +85,7 @@ impl<T: SessionStream> Session<T> {
                                     SessionResult::UpgradeTls => {
                                         return true;
                                     }
-                                    SessionResult::Close => {
+                                    SessionResult::Close | SessionResult::UpgradeCompress => {
                                         break;
                                     }
                                 }
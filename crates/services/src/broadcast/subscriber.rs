@@ -151,6 +151,14 @@ pub fn spawn_broadcast_subscriber(inner: Arc<Inner>, mut shutdown_rx: watch::Rec
                                                 );
                                             }
                                         },
+                                        BroadcastEvent::ReloadCertificates => {
+                                            if let Err(err) = inner.build_server().reload_certificates().await {
+                                                trc::error!(
+                                                        err.details("Failed to reload certificates")
+                                                            .caused_by(trc::location!())
+                                                );
+                                            }
+                                        },
                                     }
                                 } else if !has_errors {
                                     trc::event!(
@@ -195,5 +203,6 @@ fn log_event(event: BroadcastEvent) -> trc::Value {
         ]),
         BroadcastEvent::ReloadSettings => CompactString::const_new("ReloadSettings").into(),
         BroadcastEvent::ReloadBlockedIps => CompactString::const_new("ReloadBlockedIps").into(),
+        BroadcastEvent::ReloadCertificates => CompactString::const_new("ReloadCertificates").into(),
     }
 }
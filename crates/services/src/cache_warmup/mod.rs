@@ -0,0 +1,214 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Instant};
+
+use common::{Inner, KV_ACTIVE_ACCOUNTS, Server, core::BuildServer};
+use email::cache::MessageCacheFetch;
+use smtp::queue::{ArchivedStatus, Message};
+use store::{
+    Deserialize, IterateParams, ValueKey,
+    ahash::AHashSet,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, QueueClass, ValueClass, now},
+};
+use tokio::task::JoinSet;
+use trc::AddContext;
+
+pub trait ActiveAccountSnapshot: Sync + Send {
+    fn snapshot_active_accounts(&self) -> impl Future<Output = trc::Result<()>> + Send;
+    fn active_accounts(&self) -> impl Future<Output = trc::Result<Vec<(u32, u64)>>> + Send;
+}
+
+impl ActiveAccountSnapshot for Server {
+    /// Merges the accounts that authenticated since the last snapshot into the
+    /// persisted list used to warm up caches on the next startup.
+    async fn snapshot_active_accounts(&self) -> trc::Result<()> {
+        let Some(config) = &self.core.network.cache_warm_up else {
+            return Ok(());
+        };
+        let active = std::mem::take(&mut *self.inner.data.active_accounts.lock());
+        if active.is_empty() {
+            return Ok(());
+        }
+
+        let now = now();
+        let min_last_seen = now.saturating_sub(config.window.as_secs());
+        let mut accounts = self
+            .active_accounts()
+            .await?
+            .into_iter()
+            .filter(|(account_id, last_seen)| {
+                *last_seen >= min_last_seen && !active.contains(account_id)
+            })
+            .collect::<Vec<_>>();
+        accounts.extend(active.into_iter().map(|account_id| (account_id, now)));
+        accounts.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+        accounts.truncate(config.max_accounts);
+
+        let value = accounts
+            .iter()
+            .map(|(account_id, last_seen)| format!("{account_id}:{last_seen}"))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        self.in_memory_store()
+            .key_set(
+                KeyValue::new(vec![KV_ACTIVE_ACCOUNTS], value.into_bytes())
+                    .expires(config.window.as_secs()),
+            )
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn active_accounts(&self) -> trc::Result<Vec<(u32, u64)>> {
+        Ok(self
+            .in_memory_store()
+            .key_get::<String>(vec![KV_ACTIVE_ACCOUNTS])
+            .await
+            .caused_by(trc::location!())?
+            .map(|value| {
+                value
+                    .split(',')
+                    .filter_map(|entry| {
+                        let (account_id, last_seen) = entry.split_once(':')?;
+                        Some((account_id.parse().ok()?, last_seen.parse().ok()?))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+pub fn spawn_cache_warmup(inner: Arc<Inner>) {
+    let server = inner.build_server();
+    let Some(config) = server.core.network.cache_warm_up.clone() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        tokio::time::sleep(config.delay).await;
+        let time = Instant::now();
+
+        // Build DKIM signers, which are otherwise resolved on first use
+        let mut total_signers = 0;
+        if config.dkim {
+            for name in server.core.smtp.mail_auth.signatures.keys() {
+                if server.get_dkim_signer(name, 0).is_some() {
+                    total_signers += 1;
+                }
+            }
+        }
+
+        // Resolve MX records for domains with queued mail
+        let mut total_domains = 0;
+        if config.dns {
+            match queued_domains(&server).await {
+                Ok(domains) => {
+                    let domains = domains.into_iter().collect::<Vec<_>>();
+                    for chunk in domains.chunks(config.concurrency) {
+                        let mut tasks = JoinSet::new();
+                        for domain in chunk {
+                            let server = server.clone();
+                            let domain = domain.clone();
+                            tasks.spawn(async move {
+                                server
+                                    .core
+                                    .smtp
+                                    .resolvers
+                                    .dns
+                                    .mx_lookup(&domain, Some(&server.inner.cache.dns_mx))
+                                    .await
+                                    .is_ok()
+                            });
+                        }
+                        total_domains += tasks
+                            .join_all()
+                            .await
+                            .into_iter()
+                            .filter(|is_ok| *is_ok)
+                            .count();
+                    }
+                }
+                Err(err) => {
+                    trc::error!(err.details("Failed to obtain queued domains for warm-up"));
+                }
+            }
+        }
+
+        // Load access tokens, mailboxes and messages of recently active accounts
+        let mut total_accounts = 0;
+        match server.active_accounts().await {
+            Ok(accounts) => {
+                for chunk in accounts.chunks(config.concurrency) {
+                    let mut tasks = JoinSet::new();
+                    for (account_id, _) in chunk {
+                        let server = server.clone();
+                        let account_id = *account_id;
+                        tasks.spawn(async move {
+                            server.get_access_token(account_id).await.is_ok()
+                                && server.get_cached_messages(account_id).await.is_ok()
+                        });
+                    }
+                    total_accounts += tasks
+                        .join_all()
+                        .await
+                        .into_iter()
+                        .filter(|is_ok| *is_ok)
+                        .count();
+                }
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to obtain active accounts for warm-up"));
+            }
+        }
+
+        trc::event!(
+            Server(trc::ServerEvent::CacheWarmUp),
+            Details = vec![
+                trc::Value::from(format!("accounts={total_accounts}")),
+                trc::Value::from(format!("domains={total_domains}")),
+                trc::Value::from(format!("signers={total_signers}")),
+            ],
+            Elapsed = time.elapsed(),
+        );
+    });
+}
+
+async fn queued_domains(server: &Server) -> trc::Result<AHashSet<String>> {
+    let mut domains = AHashSet::new();
+    server
+        .core
+        .storage
+        .data
+        .iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Queue(QueueClass::Message(0))),
+                ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX))),
+            )
+            .ascending(),
+            |key, value| {
+                let message_ = <Archive<AlignedBytes> as Deserialize>::deserialize(value)
+                    .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?;
+                let message = message_
+                    .unarchive::<Message>()
+                    .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?;
+                for domain in message.domains.iter() {
+                    if matches!(
+                        domain.status,
+                        ArchivedStatus::Scheduled | ArchivedStatus::TemporaryFailure(_)
+                    ) {
+                        domains.insert(domain.domain.to_string());
+                    }
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())
+        .map(|_| domains)
+}
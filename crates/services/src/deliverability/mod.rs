@@ -0,0 +1,466 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Deliverability probes send a test message for a local domain through the
+// regular outbound queue, including DKIM signing, and then poll a remote
+// mailbox over IMAP until the result arrives. The probe is either addressed to
+// an auth reflector (which replies to the sender with a report of its checks)
+// or to a seed address whose mailbox receives the probe itself. In both cases
+// the SPF, DKIM, DMARC and TLS results are extracted from the headers and the
+// report added by the receiving side. Probe state is kept in the in-memory
+// store and expires after PROBE_EXPIRY.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use common::{KV_DELIVERABILITY_PROBE, Server};
+use mail_builder::{MessageBuilder, headers::HeaderType};
+use mail_parser::MessageParser;
+use serde::{Deserialize, Serialize};
+use smtp::reporting::SmtpReporting;
+use store::{
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::AddContext;
+
+use crate::mail_sync::{MailSyncJob, Protocol, Security, imap::search_messages};
+
+const PROBE_EXPIRY: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const MAX_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_REPLY_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeRequest {
+    pub domain: String,
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub reflector: Option<String>,
+    pub mailbox: ProbeMailbox,
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeMailbox {
+    #[serde(default)]
+    pub address: Option<String>,
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    pub username: String,
+    pub secret: String,
+    #[serde(default = "default_folder")]
+    pub folder: String,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeState {
+    pub id: String,
+    pub domain: String,
+    pub from: String,
+    pub to: String,
+    pub is_reflector: bool,
+    pub status: ProbeStatus,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub results: Option<ProbeResults>,
+    pub error: Option<String>,
+}
+
+#[derive(
+    rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ProbeStatus {
+    Waiting,
+    Completed,
+    TimedOut,
+}
+
+#[derive(
+    rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Default, Clone, PartialEq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeResults {
+    pub spf: Option<String>,
+    pub dkim: Option<String>,
+    pub dmarc: Option<String>,
+    pub tls: Option<String>,
+    pub authentication_results: Vec<String>,
+}
+
+pub trait DeliverabilityProbe: Sync + Send {
+    fn probe_start(
+        &self,
+        request: ProbeRequest,
+    ) -> impl Future<Output = trc::Result<ProbeState>> + Send;
+
+    fn probe_state(&self, id: &str)
+    -> impl Future<Output = trc::Result<Option<ProbeState>>> + Send;
+}
+
+impl DeliverabilityProbe for Server {
+    async fn probe_start(&self, request: ProbeRequest) -> trc::Result<ProbeState> {
+        let domain = request.domain.trim().to_lowercase();
+        if !self
+            .core
+            .storage
+            .directory
+            .is_local_domain(&domain)
+            .await
+            .caused_by(trc::location!())?
+        {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Domain is not local")
+                .ctx(trc::Key::Domain, domain));
+        }
+        let from = request
+            .from
+            .map(|from| from.trim().to_lowercase())
+            .unwrap_or_else(|| format!("postmaster@{domain}"));
+        if from.rsplit_once('@').is_none_or(|(_, d)| d != domain) {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Sender address does not belong to the domain")
+                .ctx(trc::Key::From, from));
+        }
+
+        // Reflectors reply to the sender, seed mailboxes receive the probe itself
+        let mailbox = request.mailbox;
+        let (to, is_reflector) = match (&request.reflector, &mailbox.address) {
+            (Some(reflector), _) => (reflector.trim().to_lowercase(), true),
+            (None, Some(address)) => (address.trim().to_lowercase(), false),
+            (None, None) => {
+                return Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Either a reflector or a seed address is required"));
+            }
+        };
+        if mailbox.host.trim().is_empty() || mailbox.username.is_empty() {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Missing mailbox host or username"));
+        }
+
+        let id = format!("probe-{}", self.generate_snowflake_id());
+        let message = MessageBuilder::new()
+            .from(from.as_str())
+            .to(to.as_str())
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .message_id(format!("{id}@{domain}"))
+            .subject(format!("Deliverability probe {id}"))
+            .text_body(format!(
+                "This message was sent to verify the mail authentication setup of {domain}.\r\n\r\nProbe: {id}\r\n"
+            ))
+            .write_to_vec()
+            .unwrap_or_default();
+        let state = ProbeState {
+            id: id.clone(),
+            domain,
+            from: from.clone(),
+            to: to.clone(),
+            is_reflector,
+            status: ProbeStatus::Waiting,
+            started_at: now(),
+            finished_at: None,
+            results: None,
+            error: None,
+        };
+        store_state(self, &state).await?;
+
+        self.send_autogenerated(
+            from,
+            [to].into_iter(),
+            message,
+            Some(&self.core.smtp.mail_auth.dkim.sign),
+            0,
+        )
+        .await;
+
+        let timeout = request
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT)
+            .min(MAX_TIMEOUT);
+        let server = self.clone();
+        let mut state_ = state.clone();
+        tokio::spawn(async move {
+            let job = mailbox.to_job();
+            let started = Instant::now();
+
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+
+                match search_messages(
+                    &job,
+                    &mailbox.folder,
+                    &state_.id,
+                    IO_TIMEOUT,
+                    MAX_REPLY_SIZE,
+                )
+                .await
+                {
+                    Ok(messages) => {
+                        if let Some(results) = messages
+                            .iter()
+                            .find_map(|raw| parse_probe_message(raw, &state_))
+                        {
+                            state_.status = ProbeStatus::Completed;
+                            state_.results = Some(results);
+                            state_.error = None;
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        state_.error = Some(err.to_string());
+                        trc::error!(
+                            err.details("Failed to fetch deliverability probe results")
+                                .ctx(trc::Key::Id, state_.id.clone())
+                        );
+                    }
+                }
+
+                if started.elapsed() >= timeout {
+                    state_.status = ProbeStatus::TimedOut;
+                    break;
+                }
+            }
+
+            state_.finished_at = Some(now());
+            if let Err(err) = store_state(&server, &state_).await {
+                trc::error!(err.details("Failed to store deliverability probe state"));
+            }
+        });
+
+        Ok(state)
+    }
+
+    async fn probe_state(&self, id: &str) -> trc::Result<Option<ProbeState>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_DELIVERABILITY_PROBE,
+                id.as_bytes(),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|state| state.deserialize::<ProbeState>())
+            .transpose()
+            .caused_by(trc::location!())
+    }
+}
+
+impl ProbeMailbox {
+    fn to_job(&self) -> MailSyncJob {
+        MailSyncJob {
+            protocol: Protocol::Imap,
+            host: self.host.trim().to_string(),
+            port: self.port,
+            security: self.security,
+            allow_invalid_certs: self.allow_invalid_certs,
+            username: self.username.clone(),
+            secret: self.secret.clone(),
+            folders: vec![],
+            exclude_folders: vec![],
+            interval: None,
+            enabled: true,
+        }
+    }
+}
+
+async fn store_state(server: &Server, state: &ProbeState) -> trc::Result<()> {
+    server
+        .in_memory_store()
+        .key_set(
+            KeyValue::with_prefix(
+                KV_DELIVERABILITY_PROBE,
+                state.id.as_bytes(),
+                Archiver::new(state.clone())
+                    .untrusted()
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            )
+            .expires(PROBE_EXPIRY),
+        )
+        .await
+        .caused_by(trc::location!())
+}
+
+fn parse_probe_message(raw: &[u8], state: &ProbeState) -> Option<ProbeResults> {
+    let message = MessageParser::new().parse(raw)?;
+    let is_probe = message
+        .message_id()
+        .is_some_and(|id| id.starts_with(state.id.as_str()));
+
+    if state.is_reflector {
+        // Skip any copy of the probe itself, the report quotes its headers
+        if is_probe {
+            return None;
+        }
+        parse_probe_results(&message.body_text(0).unwrap_or_default(), true)
+    } else if is_probe {
+        let headers = raw.get(..message.root_part().raw_body_offset() as usize)?;
+        parse_probe_results(&String::from_utf8_lossy(headers), false)
+    } else {
+        None
+    }
+}
+
+// Extracts the authentication results from the headers added by the receiving
+// server or, for reflector reports, from the check summary and quoted headers
+pub fn parse_probe_results(text: &str, is_report: bool) -> Option<ProbeResults> {
+    let mut results = ProbeResults::default();
+
+    if is_report {
+        for line in text.lines() {
+            let Some((check, result)) = line.split_once(':') else {
+                continue;
+            };
+            let Some(check) = check
+                .trim()
+                .trim_matches('"')
+                .to_ascii_lowercase()
+                .strip_suffix(" check")
+                .map(|check| check.trim_matches('"').to_string())
+            else {
+                continue;
+            };
+            let result = result.trim().to_ascii_lowercase();
+            if !result.is_empty() {
+                results.set(&check, result);
+            }
+        }
+    }
+
+    for (name, value) in unfold_headers(text) {
+        if name.eq_ignore_ascii_case("Authentication-Results") {
+            for method in value.split(';').skip(1) {
+                if let Some((method, result)) = method.trim().split_once('=') {
+                    let result = result
+                        .split(|ch: char| ch.is_whitespace() || ch == '(')
+                        .next()
+                        .unwrap_or_default()
+                        .to_ascii_lowercase();
+                    results.set(&method.trim().to_ascii_lowercase(), result);
+                }
+            }
+            results.authentication_results.push(value);
+        } else if name.eq_ignore_ascii_case("Received") && results.tls.is_none() {
+            // Only the topmost trace header describes the hop to the receiving server
+            let value = value.to_ascii_uppercase();
+            results.tls = Some(
+                if value.contains("ESMTPS") || value.contains("TLS") {
+                    "pass"
+                } else {
+                    "none"
+                }
+                .to_string(),
+            );
+        }
+    }
+
+    (results.spf.is_some() || results.dkim.is_some() || results.dmarc.is_some()).then_some(results)
+}
+
+impl ProbeResults {
+    fn set(&mut self, method: &str, result: String) {
+        let field = match method {
+            "spf" => &mut self.spf,
+            "dkim" => &mut self.dkim,
+            "dmarc" => &mut self.dmarc,
+            _ => return,
+        };
+        if field.is_none() {
+            *field = Some(result);
+        }
+    }
+}
+
+fn unfold_headers(text: &str) -> Vec<(&str, String)> {
+    let mut headers: Vec<(&str, String)> = Vec::new();
+    let mut is_header = false;
+
+    for line in text.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut().filter(|_| is_header) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line
+            .split_once(':')
+            .filter(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+        {
+            headers.push((name, value.trim().to_string()));
+            is_header = true;
+        } else {
+            is_header = false;
+        }
+    }
+
+    headers
+}
+
+fn default_folder() -> String {
+    "INBOX".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_probe_results;
+
+    #[test]
+    fn parse_probe_reports() {
+        let headers = concat!(
+            "Received: from mx.example.org (mx.example.org [192.0.2.1])\r\n",
+            "\tby mx.example.net with ESMTPS id 4abc\r\n",
+            "Received: from internal by mx.example.org with ESMTP id 1xyz\r\n",
+            "Authentication-Results: mx.example.net;\r\n",
+            "\tdkim=pass header.d=example.org header.s=rsa;\r\n",
+            "\tspf=pass (mx.example.net: domain of postmaster@example.org) smtp.mailfrom=example.org;\r\n",
+            "\tdmarc=pass (p=REJECT) header.from=example.org\r\n",
+            "Subject: Deliverability probe\r\n"
+        );
+        let results = parse_probe_results(headers, false).unwrap();
+        assert_eq!(results.spf.as_deref(), Some("pass"));
+        assert_eq!(results.dkim.as_deref(), Some("pass"));
+        assert_eq!(results.dmarc.as_deref(), Some("pass"));
+        assert_eq!(results.tls.as_deref(), Some("pass"));
+        assert_eq!(results.authentication_results.len(), 1);
+
+        let report = concat!(
+            "==========================================================\n",
+            "Summary of Results\n",
+            "==========================================================\n",
+            "SPF check:          softfail\n",
+            "\"iprev\" check:      pass\n",
+            "DKIM check:         fail\n",
+            "\n",
+            "Received: from mx.example.org (192.0.2.1) by verifier.example.com\n",
+            "    id h5k2 for <check-auth@example.com>\n",
+        );
+        let results = parse_probe_results(report, true).unwrap();
+        assert_eq!(results.spf.as_deref(), Some("softfail"));
+        assert_eq!(results.dkim.as_deref(), Some("fail"));
+        assert_eq!(results.dmarc, None);
+        assert_eq!(results.tls.as_deref(), Some("none"));
+
+        assert!(parse_probe_results("Subject: test\r\n", false).is_none());
+    }
+}
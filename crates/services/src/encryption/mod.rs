@@ -0,0 +1,258 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Messages stored before encryption-at-rest was enabled are encrypted by a
+// background job. Each message is encrypted with the current account and
+// tenant recovery keys and its blob is replaced in place, keeping the document
+// id, IMAP UIDs, mailboxes and keywords. Messages that are already encrypted,
+// including those encrypted with keys that have since been rotated, are left
+// untouched as the server has no access to private keys. Only one job runs
+// per account at a time and the state of the last job is stored in the data
+// store.
+
+use common::{KV_LOCK_ENCRYPTION, Server};
+use email::{
+    cache::MessageCacheFetch,
+    message::crypto::{EncryptStoredEmail, EncryptionParams, StoredEmailEncryption},
+};
+use jmap_proto::types::{
+    collection::Collection, property::Property, state::StateChange, type_state::DataType,
+};
+use serde::Serialize;
+use std::future::Future;
+use store::{
+    rand,
+    write::{Archiver, BatchBuilder, now},
+};
+use trc::AddContext;
+
+use crate::portability::JobStatus;
+
+const LOCK_EXPIRY: u64 = 86400;
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionJob {
+    pub id: String,
+    pub account_id: u32,
+    pub status: JobStatus,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub stats: EncryptionStats,
+    pub error: Option<String>,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStats {
+    pub encrypted: u64,
+    pub already_encrypted: u64,
+    pub skipped: u64,
+}
+
+pub trait AccountEncryption: Sync + Send {
+    fn start_account_encryption(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<String>> + Send;
+
+    fn encrypt_account(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<EncryptionStats>> + Send;
+
+    fn encryption_job(
+        &self,
+        account_id: u32,
+        job_id: &str,
+    ) -> impl Future<Output = trc::Result<Option<EncryptionJob>>> + Send;
+}
+
+impl AccountEncryption for Server {
+    async fn start_account_encryption(&self, account_id: u32) -> trc::Result<String> {
+        if !self
+            .in_memory_store()
+            .try_lock(KV_LOCK_ENCRYPTION, &account_id.to_be_bytes(), LOCK_EXPIRY)
+            .await
+            .caused_by(trc::location!())?
+        {
+            return Err(trc::ManageEvent::Error
+                .into_err()
+                .details("An encryption job is already running for this account"));
+        }
+
+        let mut job = EncryptionJob::new(account_id);
+        if let Err(err) = store_job(self, &job).await {
+            let _ = self
+                .in_memory_store()
+                .remove_lock(KV_LOCK_ENCRYPTION, &account_id.to_be_bytes())
+                .await;
+            return Err(err.caused_by(trc::location!()));
+        }
+
+        let server = self.clone();
+        let job_id = job.id.clone();
+        tokio::spawn(async move {
+            match server.encrypt_account(account_id).await {
+                Ok(stats) => {
+                    job.stats = stats;
+                    job.status = JobStatus::Completed;
+                }
+                Err(err) => job.failed(err),
+            }
+            job.finished_at = Some(now());
+
+            if let Err(err) = store_job(&server, &job).await {
+                trc::error!(
+                    err.account_id(account_id)
+                        .details("Failed to store account encryption job")
+                );
+            }
+            if let Err(err) = server
+                .in_memory_store()
+                .remove_lock(KV_LOCK_ENCRYPTION, &account_id.to_be_bytes())
+                .await
+            {
+                trc::error!(err.details("Failed to remove account encryption lock"));
+            }
+        });
+
+        Ok(job_id)
+    }
+
+    async fn encrypt_account(&self, account_id: u32) -> trc::Result<EncryptionStats> {
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let tenant_id = access_token.tenant.map(|tenant| tenant.id);
+        let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Err(trc::ManageEvent::Error
+                .into_err()
+                .details("Encryption-at-rest is not enabled for this account"));
+        };
+        let params = params_
+            .unarchive::<EncryptionParams>()
+            .caused_by(trc::location!())?;
+        let recovery_params_ = if let Some(tenant_id) = tenant_id {
+            self.get_archive_by_property(tenant_id, Collection::Principal, 0, Property::Parameters)
+                .await
+                .caused_by(trc::location!())?
+        } else {
+            None
+        };
+        let recovery_params = recovery_params_
+            .as_ref()
+            .map(|params| params.unarchive::<EncryptionParams>())
+            .transpose()
+            .caused_by(trc::location!())?;
+
+        let document_ids = self
+            .get_cached_messages(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .emails
+            .items
+            .iter()
+            .map(|message| message.document_id)
+            .collect::<Vec<_>>();
+        let mut stats = EncryptionStats::default();
+        let mut last_change_id = None;
+        for document_id in document_ids {
+            match self
+                .encrypt_stored_email(account_id, tenant_id, document_id, params, recovery_params)
+                .await?
+            {
+                StoredEmailEncryption::Encrypted { change_id } => {
+                    stats.encrypted += 1;
+                    last_change_id = Some(change_id);
+                }
+                StoredEmailEncryption::AlreadyEncrypted => {
+                    stats.already_encrypted += 1;
+                }
+                StoredEmailEncryption::Skipped => {
+                    stats.skipped += 1;
+                }
+            }
+        }
+
+        if let Some(change_id) = last_change_id {
+            self.broadcast_state_change(
+                StateChange::new(account_id, change_id).with_change(DataType::Email),
+            )
+            .await;
+        }
+
+        Ok(stats)
+    }
+
+    async fn encryption_job(
+        &self,
+        account_id: u32,
+        job_id: &str,
+    ) -> trc::Result<Option<EncryptionJob>> {
+        self.get_archive_by_property(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::EncryptionJob,
+        )
+        .await?
+        .map(|job| job.deserialize::<EncryptionJob>())
+        .transpose()
+        .caused_by(trc::location!())
+        .map(|job| job.filter(|job| job.id == job_id))
+    }
+}
+
+impl EncryptionJob {
+    fn new(account_id: u32) -> Self {
+        EncryptionJob {
+            id: format!("{:016x}", rand::random::<u64>()),
+            account_id,
+            status: JobStatus::Running,
+            started_at: now(),
+            finished_at: None,
+            stats: EncryptionStats::default(),
+            error: None,
+        }
+    }
+
+    fn failed(&mut self, err: trc::Error) {
+        self.error = Some(err.to_string());
+        self.status = JobStatus::Failed;
+        trc::error!(
+            err.account_id(self.account_id)
+                .details("Account encryption job failed")
+        );
+    }
+}
+
+async fn store_job(server: &Server, job: &EncryptionJob) -> trc::Result<()> {
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(job.account_id)
+        .with_collection(Collection::Principal)
+        .update_document(0)
+        .set(
+            Property::EncryptionJob,
+            Archiver::new(job.clone())
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+    server
+        .core
+        .storage
+        .data
+        .write(batch.build_all())
+        .await
+        .map(|_| ())
+        .caused_by(trc::location!())
+}
@@ -24,8 +24,16 @@ use common::telemetry::{
     tracers::store::TracingStore,
 };
 
-use email::message::delete::EmailDeletion;
-use smtp::reporting::SmtpReporting;
+use crate::{cache_warmup::ActiveAccountSnapshot, mail_sync::MailSync};
+use directory::DirectoryInner;
+#[cfg(feature = "enterprise")]
+use email::message::tiering::EmailTiering;
+use email::{mailbox::digest::MailboxDigestBuild, message::delete::EmailDeletion};
+use groupware::contact::gal::GlobalAddressList;
+use smtp::{
+    inbound::large_files::SmtpLargeFiles, queue::quarantine::SmtpQuarantine,
+    reporting::SmtpReporting,
+};
 use store::{PurgeStore, write::now};
 use tokio::sync::mpsc;
 use trc::{Collector, MetricType, PurgeEvent};
@@ -45,6 +53,17 @@ enum ActionClass {
     #[cfg(feature = "enterprise")]
     InternalMetrics,
     CalculateMetrics,
+    PartitionHealth,
+    MailboxDigest(usize),
+    IpListFeed(String),
+    ActiveAccounts,
+    GlobalAddressList,
+    MailSync,
+    DirectorySync(String),
+    Certificates,
+    QuarantinePurge,
+    #[cfg(feature = "enterprise")]
+    BlobTiering,
     #[cfg(feature = "enterprise")]
     AlertMetrics,
     #[cfg(feature = "enterprise")]
@@ -58,6 +77,9 @@ struct Queue {
 
 #[cfg(feature = "enterprise")]
 const METRIC_ALERTS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MAIL_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+const CERTIFICATE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const QUARANTINE_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEvent>) {
     tokio::spawn(async move {
@@ -98,6 +120,66 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
             // Calculate expensive metrics
             queue.schedule(Instant::now(), ActionClass::CalculateMetrics);
 
+            // Storage partition health checks
+            if server.core.storage.partitions.is_enabled() {
+                queue.schedule(Instant::now(), ActionClass::PartitionHealth);
+            }
+
+            // Shared mailbox digests
+            if server.core.network.roles.purge_accounts {
+                for (idx, digest) in server.core.jmap.mailbox_digests.iter().enumerate() {
+                    queue.schedule(
+                        Instant::now() + digest.frequency.time_to_next(),
+                        ActionClass::MailboxDigest(idx),
+                    );
+                }
+            }
+
+            // Global address list synchronization
+            if server.core.network.roles.purge_accounts && server.core.groupware.gal.is_some() {
+                queue.schedule(Instant::now(), ActionClass::GlobalAddressList);
+            }
+
+            // Mail sync from remote servers
+            if server.core.network.roles.purge_accounts {
+                queue.schedule(Instant::now() + MAIL_SYNC_INTERVAL, ActionClass::MailSync);
+            }
+
+            // External directory synchronization
+            if server.core.network.roles.purge_accounts {
+                for (id, directory) in &server.core.storage.directories {
+                    if matches!(directory.store, DirectoryInner::Graph(_)) {
+                        queue.schedule(Instant::now(), ActionClass::DirectorySync(id.clone()));
+                    }
+                }
+            }
+
+            // IP list feeds
+            for list in server.core.network.ip_lists.values() {
+                if list.feed.is_some() {
+                    queue.schedule(Instant::now(), ActionClass::IpListFeed(list.id.clone()));
+                }
+            }
+
+            // Certificate file changes and OCSP responses
+            queue.schedule(Instant::now(), ActionClass::Certificates);
+
+            // Expired quarantined messages
+            if server.core.network.roles.purge_stores {
+                queue.schedule(
+                    Instant::now() + QUARANTINE_PURGE_INTERVAL,
+                    ActionClass::QuarantinePurge,
+                );
+            }
+
+            // Active account snapshots for cache warm-up
+            if let Some(warm_up) = &server.core.network.cache_warm_up {
+                queue.schedule(
+                    Instant::now() + warm_up.snapshot_interval,
+                    ActionClass::ActiveAccounts,
+                );
+            }
+
             // Add all ACME renewals to heap
             if server.core.network.roles.renew_acme {
                 for provider in server.core.acme.providers.values() {
@@ -121,6 +203,14 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
             // SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
             // SPDX-License-Identifier: LicenseRef-SEL
 
+            // Blob store tiering
+            #[cfg(feature = "enterprise")]
+            if server.core.network.roles.purge_accounts {
+                if let store::BlobBackend::Tiered(tiered) = &server.blob_store().backend {
+                    queue.schedule(Instant::now() + tiered.interval, ActionClass::BlobTiering);
+                }
+            }
+
             // Enterprise Edition license management
             #[cfg(feature = "enterprise")]
             if let Some(enterprise) = &server.core.enterprise {
@@ -171,9 +261,102 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 _ => {}
                             }
 
+                            // Reload storage partition health checks
+                            if server.core.storage.partitions.is_enabled()
+                                && !queue.has_action(&ActionClass::PartitionHealth)
+                            {
+                                queue.schedule(Instant::now(), ActionClass::PartitionHealth);
+                            }
+
+                            // Reload IP list feeds
+                            for list in server.core.network.ip_lists.values() {
+                                let action = ActionClass::IpListFeed(list.id.clone());
+                                if list.feed.is_some() && !queue.has_action(&action) {
+                                    queue.schedule(Instant::now(), action);
+                                }
+                            }
+
+                            // Reload active account snapshots
+                            if let Some(warm_up) = &server.core.network.cache_warm_up {
+                                if !queue.has_action(&ActionClass::ActiveAccounts) {
+                                    queue.schedule(
+                                        Instant::now() + warm_up.snapshot_interval,
+                                        ActionClass::ActiveAccounts,
+                                    );
+                                }
+                            }
+
+                            // Reload shared mailbox digests
+                            if server.core.network.roles.purge_accounts {
+                                for (idx, digest) in
+                                    server.core.jmap.mailbox_digests.iter().enumerate()
+                                {
+                                    if !queue.has_action(&ActionClass::MailboxDigest(idx)) {
+                                        queue.schedule(
+                                            Instant::now() + digest.frequency.time_to_next(),
+                                            ActionClass::MailboxDigest(idx),
+                                        );
+                                    }
+                                }
+                            }
+
+                            // Reload global address list synchronization
+                            if server.core.network.roles.purge_accounts
+                                && server.core.groupware.gal.is_some()
+                                && !queue.has_action(&ActionClass::GlobalAddressList)
+                            {
+                                queue.schedule(Instant::now(), ActionClass::GlobalAddressList);
+                            }
+
+                            // Reload mail sync
+                            if server.core.network.roles.purge_accounts
+                                && !queue.has_action(&ActionClass::MailSync)
+                            {
+                                queue.schedule(
+                                    Instant::now() + MAIL_SYNC_INTERVAL,
+                                    ActionClass::MailSync,
+                                );
+                            }
+
+                            // Reload external directory synchronization
+                            if server.core.network.roles.purge_accounts {
+                                for (id, directory) in &server.core.storage.directories {
+                                    let action = ActionClass::DirectorySync(id.clone());
+                                    if matches!(directory.store, DirectoryInner::Graph(_))
+                                        && !queue.has_action(&action)
+                                    {
+                                        queue.schedule(Instant::now(), action);
+                                    }
+                                }
+                            }
+
+                            // Reload quarantine purge
+                            if server.core.network.roles.purge_stores
+                                && !queue.has_action(&ActionClass::QuarantinePurge)
+                            {
+                                queue.schedule(
+                                    Instant::now() + QUARANTINE_PURGE_INTERVAL,
+                                    ActionClass::QuarantinePurge,
+                                );
+                            }
+
                             // SPDX-SnippetBegin
                             // SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
                             // SPDX-License-Identifier: LicenseRef-SEL
+                            #[cfg(feature = "enterprise")]
+                            if server.core.network.roles.purge_accounts
+                                && !queue.has_action(&ActionClass::BlobTiering)
+                            {
+                                if let store::BlobBackend::Tiered(tiered) =
+                                    &server.blob_store().backend
+                                {
+                                    queue.schedule(
+                                        Instant::now() + tiered.interval,
+                                        ActionClass::BlobTiering,
+                                    );
+                                }
+                            }
+
                             #[cfg(feature = "enterprise")]
                             if let Some(enterprise) = &server.core.enterprise {
                                 if !queue.has_action(&ActionClass::RenewLicense) {
@@ -239,6 +422,14 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 server.purge(purge, 0).await;
                             });
                         }
+                        HousekeeperEvent::Notify { from, to, body } => {
+                            let server = inner.build_server();
+                            tokio::spawn(async move {
+                                server
+                                    .send_autogenerated(from, to.into_iter(), body, None, 0)
+                                    .await;
+                            });
+                        }
                         HousekeeperEvent::Exit => {
                             trc::event!(Housekeeper(trc::HousekeeperEvent::Stop));
 
@@ -360,6 +551,248 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     });
                                 }
                             }
+                            ActionClass::MailboxDigest(idx) => {
+                                if let Some(digest) =
+                                    server.core.jmap.mailbox_digests.get(idx).cloned()
+                                {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "mailbox_digest",
+                                        Id = digest.id.clone()
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + digest.frequency.time_to_next(),
+                                        ActionClass::MailboxDigest(idx),
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        match server.build_mailbox_digest(&digest).await {
+                                            Ok(messages) => {
+                                                for message in messages {
+                                                    server
+                                                        .send_autogenerated(
+                                                            message.from,
+                                                            message.to.into_iter(),
+                                                            message.body,
+                                                            None,
+                                                            0,
+                                                        )
+                                                        .await;
+                                                }
+                                            }
+                                            Err(err) => {
+                                                trc::error!(
+                                                    err.details("Failed to build mailbox digest")
+                                                );
+                                            }
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::ActiveAccounts => {
+                                if let Some(warm_up) = &server.core.network.cache_warm_up {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "active_accounts"
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + warm_up.snapshot_interval,
+                                        ActionClass::ActiveAccounts,
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.snapshot_active_accounts().await {
+                                            trc::error!(err.details(
+                                                "Failed to store active accounts snapshot"
+                                            ));
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::GlobalAddressList => {
+                                if let Some(gal) = &server.core.groupware.gal {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "global_address_list"
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + gal.sync_interval,
+                                        ActionClass::GlobalAddressList,
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.sync_global_address_lists().await
+                                        {
+                                            trc::error!(err.details(
+                                                "Failed to synchronize global address lists"
+                                            ));
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::MailSync => {
+                                if server.core.network.roles.purge_accounts {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "mail_sync"
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + MAIL_SYNC_INTERVAL,
+                                        ActionClass::MailSync,
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.mail_sync_run_due().await {
+                                            trc::error!(
+                                                err.details("Failed to start scheduled mail sync")
+                                            );
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::Certificates => {
+                                trc::event!(
+                                    Housekeeper(trc::HousekeeperEvent::Run),
+                                    Type = "certificates"
+                                );
+
+                                queue.schedule(
+                                    Instant::now() + CERTIFICATE_REFRESH_INTERVAL,
+                                    ActionClass::Certificates,
+                                );
+
+                                let server = server.clone();
+                                tokio::spawn(async move {
+                                    server.refresh_certificates().await;
+                                });
+                            }
+                            ActionClass::QuarantinePurge => {
+                                if server.core.network.roles.purge_stores {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "quarantine"
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + QUARANTINE_PURGE_INTERVAL,
+                                        ActionClass::QuarantinePurge,
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.purge_expired_quarantine().await {
+                                            trc::error!(err.details(
+                                                "Failed to purge expired quarantined messages"
+                                            ));
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::DirectorySync(directory_id) => {
+                                let directory = server
+                                    .core
+                                    .storage
+                                    .directories
+                                    .get(&directory_id)
+                                    .filter(|_| server.core.network.roles.purge_accounts)
+                                    .cloned();
+                                if let Some(DirectoryInner::Graph(graph)) =
+                                    directory.as_ref().map(|directory| &directory.store)
+                                {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "directory_sync",
+                                        Id = directory_id.clone()
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + graph.sync_interval(),
+                                        ActionClass::DirectorySync(directory_id.clone()),
+                                    );
+
+                                    tokio::spawn(async move {
+                                        let Some(DirectoryInner::Graph(graph)) =
+                                            directory.as_ref().map(|directory| &directory.store)
+                                        else {
+                                            return;
+                                        };
+                                        let time = Instant::now();
+                                        match graph.sync().await {
+                                            Ok(result) => {
+                                                trc::event!(
+                                                    Store(trc::StoreEvent::DirectorySync),
+                                                    Id = directory_id,
+                                                    Total = result.users + result.groups,
+                                                    Details = vec![
+                                                        trc::Value::from(result.users),
+                                                        trc::Value::from(result.groups),
+                                                        trc::Value::from(result.skipped),
+                                                    ],
+                                                    Elapsed = time.elapsed(),
+                                                );
+                                            }
+                                            Err(err) => {
+                                                trc::error!(
+                                                    err.ctx(trc::Key::Id, directory_id)
+                                                        .details("Failed to synchronize directory")
+                                                );
+                                            }
+                                        }
+                                    });
+                                }
+                            }
+                            ActionClass::IpListFeed(list_id) => {
+                                if let Some(list) = server
+                                    .core
+                                    .network
+                                    .ip_lists
+                                    .get(&list_id)
+                                    .filter(|list| list.feed.is_some())
+                                    .cloned()
+                                {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "ip_list_feed",
+                                        Id = list_id.clone()
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + list.feed.as_ref().unwrap().refresh,
+                                        ActionClass::IpListFeed(list_id),
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        match server.refresh_ip_list_feed(&list).await {
+                                            Ok(total) => {
+                                                trc::event!(
+                                                    Security(trc::SecurityEvent::IpListFeedUpdated),
+                                                    Id = list.id,
+                                                    Total = total,
+                                                );
+                                            }
+                                            Err(err) => {
+                                                trc::event!(
+                                                    Security(trc::SecurityEvent::IpListFeedError),
+                                                    Id = list.id,
+                                                    Reason = err,
+                                                );
+                                            }
+                                        }
+                                    });
+                                } else {
+                                    // The list was removed or no longer has a feed
+                                    server.inner.data.ip_feeds.write().remove(&list_id);
+                                }
+                            }
                             ActionClass::OtelMetrics => {
                                 if let Some(otel) = &server.core.metrics.otel {
                                     trc::event!(
@@ -385,6 +818,20 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     });
                                 }
                             }
+                            ActionClass::PartitionHealth => {
+                                let partitions = &server.core.storage.partitions;
+                                if partitions.is_enabled() {
+                                    queue.schedule(
+                                        Instant::now() + partitions.check_interval,
+                                        ActionClass::PartitionHealth,
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        server.check_partitions().await;
+                                    });
+                                }
+                            }
                             ActionClass::CalculateMetrics => {
                                 trc::event!(
                                     Housekeeper(trc::HousekeeperEvent::Run),
@@ -520,6 +967,32 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 }
                             }
 
+                            #[cfg(feature = "enterprise")]
+                            ActionClass::BlobTiering => {
+                                if let store::BlobBackend::Tiered(tiered) =
+                                    &server.blob_store().backend
+                                {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "blob_tiering"
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + tiered.interval,
+                                        ActionClass::BlobTiering,
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        if let Err(err) = server.archive_message_blobs().await {
+                                            trc::error!(
+                                                err.details("Failed to archive message blobs")
+                                            );
+                                        }
+                                    });
+                                }
+                            }
+
                             #[cfg(feature = "enterprise")]
                             ActionClass::AlertMetrics => {
                                 trc::event!(
@@ -704,6 +1177,11 @@ impl Purge for Server {
                 if let Err(err) = store.purge_blobs(blob_store).await {
                     trc::error!(err.details("Failed to purge blob store"));
                 }
+
+                // Large attachments kept in a dedicated blob store
+                if let Err(err) = self.purge_large_files().await {
+                    trc::error!(err.details("Failed to purge large files"));
+                }
             }
             PurgeType::Lookup { store, prefix } => {
                 if let Some(prefix) = prefix {
@@ -5,19 +5,29 @@
  */
 
 use broadcast::publisher::spawn_broadcast_publisher;
+use cache_warmup::spawn_cache_warmup;
 use common::{
     Inner,
     manager::boot::{BootManager, IpcReceivers},
 };
 use housekeeper::spawn_housekeeper;
+use push_gateway::spawn_push_gateway;
 use state_manager::manager::spawn_state_manager;
 use std::sync::Arc;
 use task_manager::spawn_task_manager;
+use tls_ticket::spawn_tls_ticket_sync;
 
 pub mod broadcast;
+pub mod cache_warmup;
+pub mod deliverability;
+pub mod encryption;
 pub mod housekeeper;
+pub mod mail_sync;
+pub mod portability;
+pub mod push_gateway;
 pub mod state_manager;
 pub mod task_manager;
+pub mod tls_ticket;
 
 pub trait StartServices: Sync + Send {
     fn start_services(&mut self) -> impl Future<Output = ()> + Send;
@@ -62,6 +72,15 @@ impl SpawnServices for IpcReceivers {
             spawn_broadcast_publisher(inner.clone(), event_rx);
         }
 
+        // Spawn push gateway
+        spawn_push_gateway(inner.clone(), self.push_gateway_rx.take().unwrap());
+
+        // Spawn cache warm-up
+        spawn_cache_warmup(inner.clone());
+
+        // Spawn TLS ticket secret synchronization
+        spawn_tls_ticket_sync(inner.clone());
+
         // Spawn task manager
         spawn_task_manager(inner);
     }
@@ -0,0 +1,160 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use mail_send::smtp::tls::build_tls_connector;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_rustls::rustls::pki_types::ServerName;
+
+use super::{MailSyncJob, Security};
+
+// Protects against servers sending endless lines
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+pub(crate) trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub(crate) struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+    timeout: Duration,
+}
+
+impl Connection {
+    pub async fn connect(job: &MailSyncJob, timeout: Duration) -> trc::Result<Self> {
+        let stream =
+            tokio::time::timeout(timeout, TcpStream::connect((job.host.as_str(), job.port())))
+                .await
+                .map_err(|_| timeout_error())?
+                .map_err(|err| {
+                    trc::NetworkEvent::ReadError
+                        .into_err()
+                        .details("Failed to connect to remote server")
+                        .ctx(trc::Key::Hostname, job.host.clone())
+                        .reason(err)
+                })?;
+        let connection = Connection {
+            stream: BufReader::new(Box::new(stream)),
+            timeout,
+        };
+
+        if job.security == Security::Tls {
+            connection.start_tls(job).await
+        } else {
+            Ok(connection)
+        }
+    }
+
+    #[cfg(test)]
+    pub fn from_stream(stream: impl Stream + 'static, timeout: Duration) -> Self {
+        Connection {
+            stream: BufReader::new(Box::new(stream)),
+            timeout,
+        }
+    }
+
+    pub async fn start_tls(self, job: &MailSyncJob) -> trc::Result<Self> {
+        let server_name = ServerName::try_from(job.host.clone()).map_err(|_| {
+            trc::NetworkEvent::ReadError
+                .into_err()
+                .details("Invalid TLS server name")
+                .ctx(trc::Key::Hostname, job.host.clone())
+        })?;
+        let stream = tokio::time::timeout(
+            self.timeout,
+            build_tls_connector(job.allow_invalid_certs)
+                .connect(server_name, self.stream.into_inner()),
+        )
+        .await
+        .map_err(|_| timeout_error())?
+        .map_err(|err| {
+            trc::NetworkEvent::ReadError
+                .into_err()
+                .details("TLS handshake failed")
+                .ctx(trc::Key::Hostname, job.host.clone())
+                .reason(err)
+        })?;
+
+        Ok(Connection {
+            stream: BufReader::new(Box::new(stream)),
+            timeout: self.timeout,
+        })
+    }
+
+    // Returns a line including its CRLF terminator
+    pub async fn read_line(&mut self) -> trc::Result<Vec<u8>> {
+        let mut line = Vec::with_capacity(128);
+        tokio::time::timeout(self.timeout, async {
+            loop {
+                let buf = self.stream.fill_buf().await.map_err(read_error)?;
+                if buf.is_empty() {
+                    return Err(trc::NetworkEvent::Closed
+                        .into_err()
+                        .details("Connection closed by remote server"));
+                }
+                if let Some(pos) = buf.iter().position(|&ch| ch == b'\n') {
+                    line.extend_from_slice(&buf[..=pos]);
+                    self.stream.consume(pos + 1);
+                    return Ok(());
+                }
+                let len = buf.len();
+                line.extend_from_slice(buf);
+                self.stream.consume(len);
+                if line.len() > MAX_LINE_LENGTH {
+                    return Err(protocol_error("Line too long", &line[..128]));
+                }
+            }
+        })
+        .await
+        .map_err(|_| timeout_error())??;
+
+        Ok(line)
+    }
+
+    pub async fn read_exact(&mut self, len: usize) -> trc::Result<Vec<u8>> {
+        let mut bytes = vec![0u8; len];
+        tokio::time::timeout(self.timeout, self.stream.read_exact(&mut bytes))
+            .await
+            .map_err(|_| timeout_error())?
+            .map_err(read_error)?;
+        Ok(bytes)
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) -> trc::Result<()> {
+        tokio::time::timeout(self.timeout, async {
+            let stream = self.stream.get_mut();
+            stream.write_all(bytes).await?;
+            stream.flush().await
+        })
+        .await
+        .map_err(|_| timeout_error())?
+        .map_err(|err| trc::NetworkEvent::WriteError.into_err().reason(err))
+    }
+}
+
+pub(crate) fn protocol_error(details: &'static str, response: &[u8]) -> trc::Error {
+    trc::NetworkEvent::ReadError
+        .into_err()
+        .details(details)
+        .ctx(
+            trc::Key::Contents,
+            String::from_utf8_lossy(response).trim_end().to_string(),
+        )
+}
+
+fn read_error(err: std::io::Error) -> trc::Error {
+    trc::NetworkEvent::ReadError.into_err().reason(err)
+}
+
+fn timeout_error() -> trc::Error {
+    trc::NetworkEvent::Timeout
+        .into_err()
+        .details("Remote server timed out")
+}
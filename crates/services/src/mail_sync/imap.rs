@@ -0,0 +1,961 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{Server, auth::AccessToken, config::jmap::settings::SpecialUse};
+use email::{
+    cache::{MessageCacheFetch, mailbox::MailboxCacheAccess},
+    mailbox::manage::MailboxFnc,
+};
+use imap_proto::{parser::parse_datetime, utf7::utf7_decode};
+use jmap_proto::types::keyword::Keyword;
+use trc::AddContext;
+
+use super::{
+    FolderState, MailSyncJob, MailSyncState, Security, SyncRun, SyncedMessage, apply_flag_changes,
+    connection::{Connection, protocol_error},
+    flags_to_bits, ingest_message,
+};
+
+// Limits the size of each batch of message bodies fetched at once
+const FETCH_BATCH_SIZE: usize = 32 * 1024 * 1024;
+const FETCH_BATCH_MESSAGES: usize = 50;
+
+pub(crate) struct ImapSession {
+    conn: Connection,
+    tag: u32,
+    max_literal: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Atom(String),
+    String(Vec<u8>),
+    List(Vec<Token>),
+    Nil,
+}
+
+struct RemoteFolder {
+    name: String,
+    raw_name: Vec<u8>,
+    role: SpecialUse,
+}
+
+struct RemoteMessage {
+    uid: u32,
+    flags: Vec<String>,
+    internal_date: Option<u64>,
+    size: usize,
+}
+
+pub(crate) async fn sync_imap(
+    server: &Server,
+    access_token: &AccessToken,
+    job: &MailSyncJob,
+    state: &mut MailSyncState,
+    run: &mut SyncRun,
+) -> trc::Result<()> {
+    let mut session =
+        ImapSession::connect(job, run.timeout, server.core.jmap.mail_max_size).await?;
+    let folders = session.list().await?;
+    let account_id = access_token.primary_id;
+
+    for folder in folders {
+        if !job.sync_folder(&folder.name) {
+            continue;
+        }
+        let Some(mailbox_id) = local_mailbox(server, account_id, &folder).await? else {
+            run.stats.skipped += 1;
+            continue;
+        };
+        let (uid_validity, exists) = session.examine(&folder.raw_name).await?;
+        run.stats.folders += 1;
+        state.current_folder = Some(folder.name.clone());
+
+        // A new UIDVALIDITY invalidates all previously synced UIDs
+        let folder_idx = match state.folders.iter().position(|f| f.name == folder.name) {
+            Some(idx) => {
+                if state.folders[idx].uid_validity != uid_validity {
+                    state.folders[idx] = FolderState::new(folder.name.clone(), uid_validity);
+                }
+                idx
+            }
+            None => {
+                state
+                    .folders
+                    .push(FolderState::new(folder.name.clone(), uid_validity));
+                state.folders.len() - 1
+            }
+        };
+        if exists == 0 {
+            continue;
+        }
+
+        // Propagate flag changes of previously synced messages
+        let last_uid = state.folders[folder_idx].last_uid;
+        if last_uid > 0 {
+            let mut changes = Vec::new();
+            let folder_state = &mut state.folders[folder_idx];
+            for message in session
+                .fetch_metadata(&format!("1:{last_uid}"), "(UID FLAGS)")
+                .await?
+            {
+                if let Ok(idx) = folder_state
+                    .messages
+                    .binary_search_by_key(&message.uid, |m| m.uid)
+                {
+                    let synced = &mut folder_state.messages[idx];
+                    let flags = flags_to_bits(&message.flags);
+                    if synced.flags != flags {
+                        changes.push((synced.document_id, synced.flags, flags));
+                        synced.flags = flags;
+                    }
+                }
+            }
+            run.stats.flag_changes += apply_flag_changes(server, account_id, changes).await?;
+        }
+
+        // Fetch new messages
+        let mut messages = session
+            .fetch_metadata(
+                &format!("{}:*", last_uid + 1),
+                "(UID FLAGS INTERNALDATE RFC822.SIZE)",
+            )
+            .await?
+            .into_iter()
+            .filter(|message| message.uid > last_uid)
+            .collect::<Vec<_>>();
+        messages.sort_unstable_by_key(|message| message.uid);
+        state.pending = messages.len() as u64;
+
+        let mut messages = messages.into_iter().peekable();
+        while messages.peek().is_some() {
+            if run.is_expired() {
+                state.is_complete = false;
+                return session.logout().await;
+            }
+
+            // Messages exceeding the maximum message size are not fetched
+            let max_size = server.core.jmap.mail_max_size;
+            let mut batch = Vec::new();
+            let mut batch_size = 0;
+            while let Some(message) = messages
+                .next_if(|_| batch.len() < FETCH_BATCH_MESSAGES && batch_size < FETCH_BATCH_SIZE)
+            {
+                if message.size <= max_size {
+                    batch_size += message.size;
+                }
+                batch.push(message);
+            }
+            let uids = batch
+                .iter()
+                .filter(|message| message.size <= max_size)
+                .map(|message| message.uid.to_string())
+                .collect::<Vec<_>>();
+            let mut bodies = if !uids.is_empty() {
+                session.fetch_bodies(&uids.join(",")).await?
+            } else {
+                vec![]
+            };
+
+            for message in batch {
+                let folder_state = &mut state.folders[folder_idx];
+                folder_state.last_uid = message.uid;
+                state.pending -= 1;
+
+                let Some(contents) = bodies
+                    .iter()
+                    .position(|(uid, _)| *uid == message.uid)
+                    .map(|idx| bodies.swap_remove(idx).1)
+                else {
+                    run.stats.skipped += 1;
+                    continue;
+                };
+
+                match ingest_message(
+                    server,
+                    access_token,
+                    mailbox_id,
+                    &contents,
+                    message
+                        .flags
+                        .iter()
+                        .filter(|flag| !flag.eq_ignore_ascii_case("\\Recent"))
+                        .map(Keyword::from)
+                        .collect(),
+                    message.internal_date,
+                )
+                .await?
+                {
+                    Some(document_id) => {
+                        folder_state.messages.push(SyncedMessage {
+                            uid: message.uid,
+                            document_id,
+                            flags: flags_to_bits(&message.flags),
+                        });
+                        run.stats.messages += 1;
+                    }
+                    None => {
+                        run.stats.skipped += 1;
+                    }
+                }
+            }
+
+            // Store the progress so interrupted runs resume from here
+            run.checkpoint(server, state).await?;
+        }
+    }
+
+    state.current_folder = None;
+    state.is_complete = true;
+    session.logout().await
+}
+
+async fn local_mailbox(
+    server: &Server,
+    account_id: u32,
+    folder: &RemoteFolder,
+) -> trc::Result<Option<u32>> {
+    if folder.role != SpecialUse::None {
+        if let Some(mailbox) = server
+            .get_cached_messages(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .mailbox_by_role(&folder.role)
+        {
+            return Ok(Some(mailbox.document_id));
+        }
+    }
+
+    server
+        .mailbox_create_path(account_id, &folder.name)
+        .await
+        .caused_by(trc::location!())
+}
+
+// Fetches the messages of a remote folder containing the given text
+pub(crate) async fn search_messages(
+    job: &MailSyncJob,
+    folder: &str,
+    text: &str,
+    timeout: Duration,
+    max_message_size: usize,
+) -> trc::Result<Vec<Vec<u8>>> {
+    let mut session = ImapSession::connect(job, timeout, max_message_size).await?;
+    session.examine(folder.as_bytes()).await?;
+
+    let mut uids = Vec::new();
+    let query = quote(text.as_bytes())
+        .ok_or_else(|| protocol_error("Invalid search text", text.as_bytes()))?;
+    for response in session.command(&format!("UID SEARCH TEXT {query}")).await? {
+        let response = String::from_utf8_lossy(&response);
+        if let Some(results) = response
+            .get(..8)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("* SEARCH"))
+            .map(|_| &response[8..])
+        {
+            uids.extend(
+                results
+                    .split_ascii_whitespace()
+                    .filter(|uid| uid.parse::<u32>().is_ok())
+                    .map(|uid| uid.to_string()),
+            );
+        }
+    }
+
+    let messages = if !uids.is_empty() {
+        session
+            .fetch_bodies(&uids.join(","))
+            .await?
+            .into_iter()
+            .map(|(_, contents)| contents)
+            .collect()
+    } else {
+        vec![]
+    };
+    let _ = session.logout().await;
+
+    Ok(messages)
+}
+
+impl ImapSession {
+    async fn connect(
+        job: &MailSyncJob,
+        timeout: Duration,
+        max_message_size: usize,
+    ) -> trc::Result<Self> {
+        let mut session = ImapSession {
+            conn: Connection::connect(job, timeout).await?,
+            tag: 0,
+            max_literal: max_message_size + 1024,
+        };
+
+        let greeting = session.conn.read_line().await?;
+        let is_authenticated = if greeting.starts_with(b"* PREAUTH") {
+            true
+        } else if greeting.starts_with(b"* OK") {
+            false
+        } else {
+            return Err(protocol_error("Unexpected IMAP greeting", &greeting));
+        };
+
+        if job.security == Security::StartTls {
+            session.command("STARTTLS").await?;
+            session.conn = session.conn.start_tls(job).await?;
+        }
+
+        if !is_authenticated {
+            session.login(&job.username, &job.secret).await?;
+        }
+
+        Ok(session)
+    }
+
+    async fn login(&mut self, username: &str, secret: &str) -> trc::Result<()> {
+        let tag = self.next_tag();
+        let mut args = [username, secret].into_iter().peekable();
+        let mut command = format!("{tag} LOGIN");
+
+        while let Some(arg) = args.next() {
+            command.push(' ');
+            if let Some(quoted) = quote(arg.as_bytes()) {
+                command.push_str(&quoted);
+            } else {
+                // Non-ASCII credentials are sent as synchronizing literals
+                command.push_str(&format!("{{{}}}\r\n", arg.len()));
+                self.conn.write(command.as_bytes()).await?;
+                let line = self.conn.read_line().await?;
+                if !line.starts_with(b"+") {
+                    return Err(trc::AuthEvent::Failed
+                        .into_err()
+                        .details("Remote server rejected credentials")
+                        .ctx(
+                            trc::Key::Contents,
+                            String::from_utf8_lossy(&line).into_owned(),
+                        ));
+                }
+                command = arg.to_string();
+            }
+            if args.peek().is_none() {
+                command.push_str("\r\n");
+            }
+        }
+        self.conn.write(command.as_bytes()).await?;
+
+        self.read_response(&tag).await.map(|_| ()).map_err(|err| {
+            trc::AuthEvent::Failed
+                .into_err()
+                .details("Remote server rejected credentials")
+                .caused_by(err)
+        })
+    }
+
+    async fn list(&mut self) -> trc::Result<Vec<RemoteFolder>> {
+        let mut folders = Vec::new();
+
+        for response in self.command("LIST \"\" \"*\"").await? {
+            let mut tokens = Tokenizer::new(&response).parse().into_iter().skip(1);
+            if !matches!(tokens.next(), Some(Token::Atom(cmd)) if cmd.eq_ignore_ascii_case("LIST"))
+            {
+                continue;
+            }
+            let (Some(Token::List(attributes)), Some(delimiter), Some(name)) =
+                (tokens.next(), tokens.next(), tokens.next())
+            else {
+                continue;
+            };
+            let Some(raw_name) = name.into_bytes() else {
+                continue;
+            };
+
+            let mut role = SpecialUse::None;
+            let mut is_selectable = true;
+            for attribute in attributes {
+                if let Token::Atom(attribute) = attribute {
+                    match attribute.to_ascii_lowercase().as_str() {
+                        "\\noselect" | "\\nonexistent" => is_selectable = false,
+                        "\\sent" => role = SpecialUse::Sent,
+                        "\\drafts" => role = SpecialUse::Drafts,
+                        "\\trash" => role = SpecialUse::Trash,
+                        "\\junk" => role = SpecialUse::Junk,
+                        "\\archive" => role = SpecialUse::Archive,
+                        _ => {}
+                    }
+                }
+            }
+            if !is_selectable {
+                continue;
+            }
+
+            // Folder names are mapped to local paths using '/' as the hierarchy separator
+            let name = String::from_utf8_lossy(&raw_name).into_owned();
+            let name = utf7_decode(&name).unwrap_or(name);
+            let name = match delimiter.into_bytes() {
+                Some(delimiter) if delimiter != b"/" && delimiter.len() == 1 => {
+                    let delimiter = delimiter[0] as char;
+                    name.replace('/', "_").replace(delimiter, "/")
+                }
+                _ => name,
+            };
+            if name.eq_ignore_ascii_case("INBOX") {
+                role = SpecialUse::Inbox;
+            }
+
+            folders.push(RemoteFolder {
+                name,
+                raw_name,
+                role,
+            });
+        }
+
+        Ok(folders)
+    }
+
+    // Returns the UIDVALIDITY and number of messages of a folder
+    async fn examine(&mut self, raw_name: &[u8]) -> trc::Result<(u32, u32)> {
+        let tag = self.next_tag();
+        if let Some(quoted) = quote(raw_name) {
+            self.conn
+                .write(format!("{tag} EXAMINE {quoted}\r\n").as_bytes())
+                .await?;
+        } else {
+            self.conn
+                .write(format!("{tag} EXAMINE {{{}}}\r\n", raw_name.len()).as_bytes())
+                .await?;
+            let line = self.conn.read_line().await?;
+            if !line.starts_with(b"+") {
+                return Err(protocol_error("Failed to select folder", &line));
+            }
+            let mut command = raw_name.to_vec();
+            command.extend_from_slice(b"\r\n");
+            self.conn.write(&command).await?;
+        }
+
+        let mut uid_validity = 0;
+        let mut exists = 0;
+        for response in self.read_response(&tag).await? {
+            let response = String::from_utf8_lossy(&response);
+            let response = response.trim_end();
+            if let Some(value) = response
+                .split_once("[UIDVALIDITY ")
+                .and_then(|(_, value)| value.split_once(']'))
+                .and_then(|(value, _)| value.trim().parse().ok())
+            {
+                uid_validity = value;
+            } else if let Some(value) = response
+                .strip_prefix("* ")
+                .and_then(|response| response.split_once(' '))
+                .filter(|(_, cmd)| cmd.eq_ignore_ascii_case("EXISTS"))
+                .and_then(|(value, _)| value.parse().ok())
+            {
+                exists = value;
+            }
+        }
+
+        Ok((uid_validity, exists))
+    }
+
+    async fn fetch_metadata(
+        &mut self,
+        sequence: &str,
+        items: &str,
+    ) -> trc::Result<Vec<RemoteMessage>> {
+        let mut messages = Vec::new();
+
+        for response in self
+            .command(&format!("UID FETCH {sequence} {items}"))
+            .await?
+        {
+            let Some(items) = fetch_items(&response) else {
+                continue;
+            };
+            let mut message = RemoteMessage {
+                uid: 0,
+                flags: vec![],
+                internal_date: None,
+                size: 0,
+            };
+            let mut items = items.into_iter();
+            while let (Some(Token::Atom(name)), Some(value)) = (items.next(), items.next()) {
+                match (name.to_ascii_uppercase().as_str(), value) {
+                    ("UID", Token::Atom(uid)) => {
+                        message.uid = uid.parse().unwrap_or_default();
+                    }
+                    ("FLAGS", Token::List(flags)) => {
+                        message.flags = flags
+                            .into_iter()
+                            .filter_map(|flag| match flag {
+                                Token::Atom(flag) => Some(flag),
+                                _ => None,
+                            })
+                            .collect();
+                    }
+                    ("INTERNALDATE", Token::String(date)) => {
+                        message.internal_date = parse_datetime(&date)
+                            .ok()
+                            .filter(|date| *date > 0)
+                            .map(|date| date as u64);
+                    }
+                    ("RFC822.SIZE", Token::Atom(size)) => {
+                        message.size = size.parse().unwrap_or_default();
+                    }
+                    _ => {}
+                }
+            }
+            if message.uid != 0 {
+                messages.push(message);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    async fn fetch_bodies(&mut self, uids: &str) -> trc::Result<Vec<(u32, Vec<u8>)>> {
+        let mut bodies = Vec::new();
+
+        for response in self
+            .command(&format!("UID FETCH {uids} (UID BODY.PEEK[])"))
+            .await?
+        {
+            let Some(items) = fetch_items(&response) else {
+                continue;
+            };
+            let mut uid = 0;
+            let mut contents = None;
+            let mut items = items.into_iter();
+            while let (Some(Token::Atom(name)), Some(value)) = (items.next(), items.next()) {
+                match (name.to_ascii_uppercase().as_str(), value) {
+                    ("UID", Token::Atom(value)) => {
+                        uid = value.parse().unwrap_or_default();
+                    }
+                    ("BODY[]", Token::String(value)) => {
+                        contents = Some(value);
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(contents) = contents.filter(|_| uid != 0) {
+                bodies.push((uid, contents));
+            }
+        }
+
+        Ok(bodies)
+    }
+
+    async fn logout(&mut self) -> trc::Result<()> {
+        let tag = self.next_tag();
+        self.conn
+            .write(format!("{tag} LOGOUT\r\n").as_bytes())
+            .await
+    }
+
+    async fn command(&mut self, command: &str) -> trc::Result<Vec<Vec<u8>>> {
+        let tag = self.next_tag();
+        self.conn
+            .write(format!("{tag} {command}\r\n").as_bytes())
+            .await?;
+        self.read_response(&tag).await
+    }
+
+    // Returns the untagged responses preceding the tagged completion result
+    async fn read_response(&mut self, tag: &str) -> trc::Result<Vec<Vec<u8>>> {
+        let mut responses = Vec::new();
+
+        loop {
+            let response = self.read_full_response().await?;
+            if response.starts_with(b"* ") {
+                responses.push(response);
+            } else if response
+                .strip_prefix(tag.as_bytes())
+                .and_then(|response| response.strip_prefix(b" "))
+                .is_some_and(|status| status.len() >= 2 && status[..2].eq_ignore_ascii_case(b"OK"))
+            {
+                return Ok(responses);
+            } else if response.starts_with(tag.as_bytes()) {
+                return Err(protocol_error("IMAP command failed", &response));
+            }
+        }
+    }
+
+    // Reads a response line along with any literals it contains
+    async fn read_full_response(&mut self) -> trc::Result<Vec<u8>> {
+        let mut response = Vec::new();
+
+        loop {
+            let line = self.conn.read_line().await?;
+            let literal_len = literal_size(&line);
+            response.extend_from_slice(&line);
+
+            match literal_len {
+                Some(len) if len <= self.max_literal => {
+                    response.extend_from_slice(&self.conn.read_exact(len).await?);
+                }
+                Some(_) => {
+                    return Err(protocol_error("IMAP literal too large", &line));
+                }
+                None => return Ok(response),
+            }
+        }
+    }
+
+    fn next_tag(&mut self) -> String {
+        self.tag += 1;
+        format!("S{}", self.tag)
+    }
+}
+
+impl Token {
+    fn into_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            Token::Atom(value) => Some(value.into_bytes()),
+            Token::String(value) => Some(value),
+            Token::List(_) | Token::Nil => None,
+        }
+    }
+}
+
+struct Tokenizer<'x> {
+    bytes: &'x [u8],
+    pos: usize,
+}
+
+impl<'x> Tokenizer<'x> {
+    fn new(bytes: &'x [u8]) -> Self {
+        Tokenizer { bytes, pos: 0 }
+    }
+
+    fn parse(&mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+
+        while let Some(&ch) = self.bytes.get(self.pos) {
+            match ch {
+                b' ' | b'\r' | b'\n' => {
+                    self.pos += 1;
+                }
+                b'(' => {
+                    self.pos += 1;
+                    tokens.push(Token::List(self.parse()));
+                }
+                b')' => {
+                    self.pos += 1;
+                    break;
+                }
+                b'"' => {
+                    self.pos += 1;
+                    let mut value = Vec::new();
+                    while let Some(&ch) = self.bytes.get(self.pos) {
+                        self.pos += 1;
+                        match ch {
+                            b'\\' => {
+                                if let Some(&ch) = self.bytes.get(self.pos) {
+                                    value.push(ch);
+                                    self.pos += 1;
+                                }
+                            }
+                            b'"' => break,
+                            _ => value.push(ch),
+                        }
+                    }
+                    tokens.push(Token::String(value));
+                }
+                b'{' => {
+                    let start = self.pos + 1;
+                    let Some(end) = self.bytes[start..]
+                        .iter()
+                        .position(|&ch| ch == b'\n')
+                        .map(|pos| start + pos + 1)
+                    else {
+                        break;
+                    };
+                    let len = std::str::from_utf8(&self.bytes[start..end])
+                        .ok()
+                        .and_then(|value| value.split_once('}'))
+                        .and_then(|(value, _)| value.trim_end_matches('+').parse::<usize>().ok())
+                        .unwrap_or_default();
+                    let value_end = (end + len).min(self.bytes.len());
+                    tokens.push(Token::String(self.bytes[end..value_end].to_vec()));
+                    self.pos = value_end;
+                }
+                _ => {
+                    let start = self.pos;
+                    let mut in_brackets = false;
+                    while let Some(&ch) = self.bytes.get(self.pos) {
+                        match ch {
+                            b'[' => in_brackets = true,
+                            b']' => in_brackets = false,
+                            b' ' | b'(' | b')' if !in_brackets => break,
+                            b'\r' | b'\n' => break,
+                            _ => {}
+                        }
+                        self.pos += 1;
+                    }
+                    let value = String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned();
+                    tokens.push(if value.eq_ignore_ascii_case("NIL") {
+                        Token::Nil
+                    } else {
+                        Token::Atom(value)
+                    });
+                }
+            }
+        }
+
+        tokens
+    }
+}
+
+// Returns the items of a "* <seq> FETCH (...)" response
+fn fetch_items(response: &[u8]) -> Option<Vec<Token>> {
+    let mut tokens = Tokenizer::new(response).parse().into_iter().skip(2);
+    match (tokens.next(), tokens.next()) {
+        (Some(Token::Atom(cmd)), Some(Token::List(items))) if cmd.eq_ignore_ascii_case("FETCH") => {
+            Some(items)
+        }
+        _ => None,
+    }
+}
+
+fn literal_size(line: &[u8]) -> Option<usize> {
+    let line = line
+        .strip_suffix(b"\r\n")
+        .or_else(|| line.strip_suffix(b"\n"))?;
+    let line = line.strip_suffix(b"}")?;
+    let start = line.iter().rposition(|&ch| ch == b'{')?;
+    std::str::from_utf8(&line[start + 1..])
+        .ok()?
+        .trim_end_matches('+')
+        .parse()
+        .ok()
+}
+
+fn quote(value: &[u8]) -> Option<String> {
+    if value.iter().all(|&ch| (0x20..0x7f).contains(&ch)) {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for &ch in value {
+            if matches!(ch, b'"' | b'\\') {
+                quoted.push('\\');
+            }
+            quoted.push(ch as char);
+        }
+        quoted.push('"');
+        Some(quoted)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use common::config::jmap::settings::SpecialUse;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+    use super::{ImapSession, Token, Tokenizer, fetch_items, literal_size};
+    use crate::mail_sync::connection::Connection;
+
+    #[test]
+    fn parse_imap_responses() {
+        assert_eq!(
+            Tokenizer::new(b"* LIST (\\HasNoChildren \\Sent) \"/\" \"Sent Items\"\r\n").parse(),
+            vec![
+                Token::Atom("*".into()),
+                Token::Atom("LIST".into()),
+                Token::List(vec![
+                    Token::Atom("\\HasNoChildren".into()),
+                    Token::Atom("\\Sent".into())
+                ]),
+                Token::String(b"/".to_vec()),
+                Token::String(b"Sent Items".to_vec()),
+            ]
+        );
+
+        let response = b"* 3 FETCH (UID 12 FLAGS (\\Seen $Forwarded) BODY[] {9}\r\nSubject:\n)\r\n";
+        assert_eq!(literal_size(b"* 3 FETCH (UID 12 BODY[] {9}\r\n"), Some(9));
+        assert_eq!(literal_size(b"a LOGIN {5+}\r\n"), Some(5));
+        assert_eq!(literal_size(b"* OK done\r\n"), None);
+        assert_eq!(
+            fetch_items(response),
+            Some(vec![
+                Token::Atom("UID".into()),
+                Token::Atom("12".into()),
+                Token::Atom("FLAGS".into()),
+                Token::List(vec![
+                    Token::Atom("\\Seen".into()),
+                    Token::Atom("$Forwarded".into())
+                ]),
+                Token::Atom("BODY[]".into()),
+                Token::String(b"Subject:\n".to_vec()),
+            ])
+        );
+        assert_eq!(
+            Tokenizer::new(b"* 1 FETCH (INTERNALDATE NIL BODY[HEADER.FIELDS (FROM)] \"a\\\"b\")")
+                .parse(),
+            vec![
+                Token::Atom("*".into()),
+                Token::Atom("1".into()),
+                Token::Atom("FETCH".into()),
+                Token::List(vec![
+                    Token::Atom("INTERNALDATE".into()),
+                    Token::Nil,
+                    Token::Atom("BODY[HEADER.FIELDS (FROM)]".into()),
+                    Token::String(b"a\"b".to_vec()),
+                ]),
+            ]
+        );
+        assert_eq!(fetch_items(b"* 3 EXPUNGE\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn imap_session() {
+        let script = [
+            // Non-ASCII passwords are sent as literals
+            ("S1 LOGIN \"john\\\"s\" {9}\r\n", "+ Ready\r\n"),
+            ("sécret!!\r\n", "S1 OK Logged in\r\n"),
+            (
+                "S2 LIST \"\" \"*\"\r\n",
+                concat!(
+                    "* LIST (\\HasNoChildren) \".\" INBOX\r\n",
+                    "* LIST (\\HasNoChildren \\Sent) \".\" \"Sent Items\"\r\n",
+                    "* LIST (\\Noselect) \".\" Archive\r\n",
+                    "* LIST () \".\" \"Archive.2024/Q1\"\r\n",
+                    "* LIST (\\Junk) \".\" {4}\r\nSpam\r\n",
+                    "* LIST () \".\" \"Entw&APw-rfe\"\r\n",
+                    "S2 OK LIST done\r\n"
+                ),
+            ),
+            (
+                "S3 EXAMINE \"Sent Items\"\r\n",
+                concat!(
+                    "* 3 EXISTS\r\n",
+                    "* 0 RECENT\r\n",
+                    "* OK [UIDVALIDITY 1700000000] UIDs valid\r\n",
+                    "* OK [UIDNEXT 13] Predicted next UID\r\n",
+                    "S3 OK [READ-ONLY] EXAMINE completed\r\n"
+                ),
+            ),
+            (
+                "S4 UID FETCH 1:* (UID FLAGS INTERNALDATE RFC822.SIZE)\r\n",
+                concat!(
+                    "* 1 FETCH (UID 10 FLAGS (\\Seen \\Flagged) ",
+                    "INTERNALDATE \"17-Jul-1996 02:44:25 -0700\" RFC822.SIZE 4286)\r\n",
+                    "* 2 FETCH (FLAGS () UID 12 RFC822.SIZE 120)\r\n",
+                    "* 2 EXPUNGE\r\n",
+                    "S4 OK FETCH completed\r\n"
+                ),
+            ),
+            (
+                "S5 UID FETCH 10,12 (UID BODY.PEEK[])\r\n",
+                concat!(
+                    "* 1 FETCH (UID 10 BODY[] {19}\r\nSubject: one\r\n\r\nA\r\n)\r\n",
+                    "* 2 FETCH (BODY[] \"Subject: two\" UID 12)\r\n",
+                    "S5 OK FETCH completed\r\n"
+                ),
+            ),
+            (
+                "S6 EXAMINE \"Missing\"\r\n",
+                "S6 NO [NONEXISTENT] Unknown mailbox\r\n",
+            ),
+            (
+                "S7 UID FETCH 13 (UID BODY.PEEK[])\r\n",
+                "* 3 FETCH (UID 13 BODY[] {2048}\r\n",
+            ),
+        ];
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(mock_server(server, script.to_vec()));
+        let mut session = ImapSession {
+            conn: Connection::from_stream(client, Duration::from_secs(5)),
+            tag: 0,
+            max_literal: 1024,
+        };
+
+        session.login("john\"s", "sécret!!").await.unwrap();
+
+        let folders = session.list().await.unwrap();
+        assert_eq!(
+            folders
+                .iter()
+                .map(|folder| (
+                    folder.name.as_str(),
+                    folder.raw_name.as_slice(),
+                    folder.role
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("INBOX", b"INBOX".as_slice(), SpecialUse::Inbox),
+                ("Sent Items", b"Sent Items".as_slice(), SpecialUse::Sent),
+                (
+                    "Archive/2024_Q1",
+                    b"Archive.2024/Q1".as_slice(),
+                    SpecialUse::None
+                ),
+                ("Spam", b"Spam".as_slice(), SpecialUse::Junk),
+                ("Entwürfe", b"Entw&APw-rfe".as_slice(), SpecialUse::None),
+            ]
+        );
+
+        assert_eq!(
+            session.examine(b"Sent Items").await.unwrap(),
+            (1700000000, 3)
+        );
+
+        let messages = session
+            .fetch_metadata("1:*", "(UID FLAGS INTERNALDATE RFC822.SIZE)")
+            .await
+            .unwrap();
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| (
+                    message.uid,
+                    message.flags.clone(),
+                    message.internal_date,
+                    message.size
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    10,
+                    vec!["\\Seen".to_string(), "\\Flagged".to_string()],
+                    Some(837596665),
+                    4286
+                ),
+                (12, vec![], None, 120),
+            ]
+        );
+
+        assert_eq!(
+            session.fetch_bodies("10,12").await.unwrap(),
+            vec![
+                (10, b"Subject: one\r\n\r\nA\r\n".to_vec()),
+                (12, b"Subject: two".to_vec()),
+            ]
+        );
+
+        // Failed commands and oversized literals are reported as errors
+        assert!(session.examine(b"Missing").await.is_err());
+        assert!(session.fetch_bodies("13").await.is_err());
+
+        server.await.unwrap();
+    }
+
+    async fn mock_server(stream: DuplexStream, script: Vec<(&'static str, &'static str)>) {
+        let mut stream = BufReader::new(stream);
+
+        for (command, response) in script {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            assert_eq!(line, command);
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+}
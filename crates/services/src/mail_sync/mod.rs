@@ -0,0 +1,709 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Mail sync jobs pull messages from a legacy IMAP or POP3 server into a local
+// account. Job definitions and the sync state (UID validity and last synced UID
+// of each folder, POP3 UIDLs and statistics) are stored in the data store as
+// properties of the account, with the remote credentials encrypted. Accounts
+// with a sync job are tagged on the principal collection so that due jobs can
+// be found without scanning every account. Runs stop after MAX_RUN_TIME and
+// resume from the last stored checkpoint on the next housekeeper pass.
+
+pub mod connection;
+pub mod imap;
+pub mod pop3;
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use common::{
+    KV_LOCK_MAIL_SYNC, Server,
+    auth::{AccessToken, oauth::crypto::SymmetricEncrypt},
+    storage::index::ObjectIndexBuilder,
+};
+use email::message::{
+    ingest::{EmailIngest, IngestEmail, IngestSource},
+    metadata::MessageData,
+};
+use imap::sync_imap;
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    keyword::Keyword,
+    property::Property,
+    state::StateChange,
+    type_state::DataType,
+};
+use mail_parser::MessageParser;
+use pop3::sync_pop3;
+use serde::{Deserialize, Serialize};
+use store::{
+    BitmapKey,
+    ahash::AHashSet,
+    rand::{Rng, rng},
+    write::{Archiver, BatchBuilder, now},
+};
+use trc::AddContext;
+
+const SECRET_CONTEXT: &str = "mail-sync";
+
+const MAX_RUN_TIME: Duration = Duration::from_secs(50 * 60);
+const LOCK_EXPIRY: u64 = 60 * 60;
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(
+    rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, Serialize, Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct MailSyncJob {
+    pub protocol: Protocol,
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub secret: String,
+    #[serde(default)]
+    pub folders: Vec<String>,
+    #[serde(default)]
+    pub exclude_folders: Vec<String>,
+    #[serde(default)]
+    pub interval: Option<u64>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Deserialize,
+    rkyv::Serialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Protocol {
+    Imap,
+    Pop3,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Deserialize,
+    rkyv::Serialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Security {
+    #[default]
+    Tls,
+    StartTls,
+    None,
+}
+
+// The remote secret is stored encrypted instead of in the job
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug)]
+struct StoredMailSyncJob {
+    job: MailSyncJob,
+    secret: Vec<u8>,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MailSyncState {
+    pub status: SyncStatus,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub last_success: Option<u64>,
+    pub is_complete: bool,
+    pub current_folder: Option<String>,
+    pub pending: u64,
+    pub last_run: SyncStats,
+    pub total: SyncStats,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub folders: Vec<FolderState>,
+    #[serde(skip)]
+    pub pop3_uids: Vec<String>,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Deserialize,
+    rkyv::Serialize,
+    Serialize,
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncStatus {
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStats {
+    pub folders: u64,
+    pub messages: u64,
+    pub flag_changes: u64,
+    pub skipped: u64,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone)]
+pub struct FolderState {
+    pub name: String,
+    pub uid_validity: u32,
+    pub last_uid: u32,
+    pub messages: Vec<SyncedMessage>,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone)]
+pub struct SyncedMessage {
+    pub uid: u32,
+    pub document_id: u32,
+    pub flags: u8,
+}
+
+pub(crate) struct SyncRun {
+    account_id: u32,
+    started: Instant,
+    timeout: Duration,
+    stats: SyncStats,
+}
+
+// System flags tracked after the initial sync
+const SYNC_FLAGS: [(u8, Keyword); 5] = [
+    (1 << 0, Keyword::Seen),
+    (1 << 1, Keyword::Flagged),
+    (1 << 2, Keyword::Answered),
+    (1 << 3, Keyword::Draft),
+    (1 << 4, Keyword::Deleted),
+];
+
+pub trait MailSync: Sync + Send {
+    fn mail_sync_jobs(&self) -> impl Future<Output = trc::Result<Vec<(u32, MailSyncJob)>>> + Send;
+
+    fn mail_sync_job(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<MailSyncJob>>> + Send;
+
+    fn mail_sync_update(
+        &self,
+        account_id: u32,
+        job: MailSyncJob,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn mail_sync_delete(&self, account_id: u32) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn mail_sync_state(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<MailSyncState>>> + Send;
+
+    fn mail_sync_start(&self, account_id: u32) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn mail_sync_run_due(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl MailSync for Server {
+    async fn mail_sync_jobs(&self) -> trc::Result<Vec<(u32, MailSyncJob)>> {
+        let mut jobs = Vec::new();
+        for account_id in self
+            .core
+            .storage
+            .data
+            .get_bitmap(BitmapKey::tag(
+                u32::MAX,
+                Collection::Principal,
+                Property::MailSync,
+                (),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default()
+        {
+            match self.mail_sync_job(account_id).await {
+                Ok(Some(job)) => jobs.push((account_id, job)),
+                Ok(None) => {}
+                Err(err) => {
+                    trc::error!(
+                        err.account_id(account_id)
+                            .details("Failed to obtain mail sync job")
+                    );
+                }
+            }
+        }
+
+        Ok(jobs)
+    }
+
+    async fn mail_sync_job(&self, account_id: u32) -> trc::Result<Option<MailSyncJob>> {
+        let Some(job_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::MailSync)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let stored = job_
+            .deserialize::<StoredMailSyncJob>()
+            .caused_by(trc::location!())?;
+        let mut job = stored.job;
+        job.secret = decrypt_secret(self, &stored.secret)?;
+
+        Ok(Some(job))
+    }
+
+    async fn mail_sync_update(&self, account_id: u32, mut job: MailSyncJob) -> trc::Result<()> {
+        // Keep the stored secret when none is provided
+        if job.secret.is_empty() {
+            job.secret = self
+                .mail_sync_job(account_id)
+                .await?
+                .map(|job| job.secret)
+                .unwrap_or_default();
+        }
+        if job.host.trim().is_empty() || job.username.is_empty() || job.secret.is_empty() {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Missing host, username or secret"));
+        }
+
+        let secret = encrypt_secret(self, &job.secret)?;
+        job.secret = String::new();
+        let job = Archiver::new(StoredMailSyncJob { job, secret })
+            .serialize()
+            .caused_by(trc::location!())?;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .set(Property::MailSync, job)
+            .with_account_id(u32::MAX)
+            .update_document(account_id)
+            .tag(Property::MailSync, ());
+        self.core
+            .storage
+            .data
+            .write(batch.build_all())
+            .await
+            .map(|_| ())
+            .caused_by(trc::location!())
+    }
+
+    async fn mail_sync_delete(&self, account_id: u32) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .clear(Property::MailSync)
+            .clear(Property::MailSyncState)
+            .with_account_id(u32::MAX)
+            .update_document(account_id)
+            .untag(Property::MailSync, ());
+        self.core
+            .storage
+            .data
+            .write(batch.build_all())
+            .await
+            .map(|_| ())
+            .caused_by(trc::location!())
+    }
+
+    async fn mail_sync_state(&self, account_id: u32) -> trc::Result<Option<MailSyncState>> {
+        self.get_archive_by_property(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::MailSyncState,
+        )
+        .await?
+        .map(|state| state.deserialize::<MailSyncState>())
+        .transpose()
+        .caused_by(trc::location!())
+    }
+
+    async fn mail_sync_start(&self, account_id: u32) -> trc::Result<bool> {
+        let job = self
+            .mail_sync_job(account_id)
+            .await?
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+        start_sync(self, account_id, job).await
+    }
+
+    async fn mail_sync_run_due(&self) -> trc::Result<()> {
+        let now = now();
+
+        for (account_id, job) in self.mail_sync_jobs().await? {
+            if !job.enabled {
+                continue;
+            }
+            let state = self.mail_sync_state(account_id).await?.unwrap_or_default();
+
+            // Interrupted runs are resumed right away, failed runs wait for the next interval
+            let is_due = (state.status == SyncStatus::Completed && !state.is_complete)
+                || job.interval.is_some_and(|interval| {
+                    state
+                        .finished_at
+                        .is_none_or(|finished_at| finished_at + interval <= now)
+                });
+            if is_due {
+                start_sync(self, account_id, job).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn start_sync(server: &Server, account_id: u32, job: MailSyncJob) -> trc::Result<bool> {
+    if !server
+        .in_memory_store()
+        .try_lock(KV_LOCK_MAIL_SYNC, &account_id.to_be_bytes(), LOCK_EXPIRY)
+        .await
+        .caused_by(trc::location!())?
+    {
+        return Ok(false);
+    }
+
+    let mut state = server
+        .mail_sync_state(account_id)
+        .await?
+        .unwrap_or_default();
+    state.status = SyncStatus::Running;
+    state.started_at = Some(now());
+    state.error = None;
+    if let Err(err) = store_state(server, account_id, &state).await {
+        let _ = server
+            .in_memory_store()
+            .remove_lock(KV_LOCK_MAIL_SYNC, &account_id.to_be_bytes())
+            .await;
+        return Err(err);
+    }
+
+    let server = server.clone();
+    tokio::spawn(async move {
+        let mut run = SyncRun {
+            account_id,
+            started: Instant::now(),
+            timeout: IO_TIMEOUT,
+            stats: SyncStats::default(),
+        };
+        let result = match server.get_access_token(account_id).await {
+            Ok(access_token) => match job.protocol {
+                Protocol::Imap => {
+                    sync_imap(&server, &access_token, &job, &mut state, &mut run).await
+                }
+                Protocol::Pop3 => {
+                    sync_pop3(&server, &access_token, &job, &mut state, &mut run).await
+                }
+            },
+            Err(err) => Err(err),
+        };
+
+        let now = now();
+        match result {
+            Ok(_) => {
+                state.status = SyncStatus::Completed;
+                state.last_success = Some(now);
+            }
+            Err(err) => {
+                state.status = SyncStatus::Failed;
+                state.error = Some(err.to_string());
+                trc::error!(
+                    err.account_id(account_id)
+                        .details("Mail sync from remote server failed")
+                );
+            }
+        }
+        state.finished_at = Some(now);
+        state.total.folders = run.stats.folders.max(state.total.folders);
+        state.total.messages += run.stats.messages;
+        state.total.flag_changes += run.stats.flag_changes;
+        state.total.skipped += run.stats.skipped;
+        state.last_run = run.stats;
+
+        if let Err(err) = store_state(&server, account_id, &state).await {
+            trc::error!(
+                err.account_id(account_id)
+                    .details("Failed to store mail sync state")
+            );
+        }
+        if let Err(err) = server
+            .in_memory_store()
+            .remove_lock(KV_LOCK_MAIL_SYNC, &account_id.to_be_bytes())
+            .await
+        {
+            trc::error!(err.details("Failed to remove mail sync lock"));
+        }
+    });
+
+    Ok(true)
+}
+
+async fn store_state(server: &Server, account_id: u32, state: &MailSyncState) -> trc::Result<()> {
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .with_collection(Collection::Principal)
+        .update_document(0)
+        .set(
+            Property::MailSyncState,
+            Archiver::new(state.clone())
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+    server
+        .core
+        .storage
+        .data
+        .write(batch.build_all())
+        .await
+        .map(|_| ())
+        .caused_by(trc::location!())
+}
+
+// Remote secrets are encrypted using a key derived from the OAuth key, the
+// random nonce is stored in front of the ciphertext.
+fn encrypt_secret(server: &Server, secret: &str) -> trc::Result<Vec<u8>> {
+    let nonce = rng().random::<[u8; SymmetricEncrypt::NONCE_LEN]>();
+    let mut bytes = nonce.to_vec();
+    bytes.extend(
+        SymmetricEncrypt::new(server.core.oauth.oauth_key.as_bytes(), SECRET_CONTEXT)
+            .encrypt(secret.as_bytes(), &nonce)
+            .map_err(|err| {
+                trc::StoreEvent::CryptoError
+                    .into_err()
+                    .details("Failed to encrypt mail sync secret")
+                    .reason(err)
+            })?,
+    );
+    Ok(bytes)
+}
+
+fn decrypt_secret(server: &Server, bytes: &[u8]) -> trc::Result<String> {
+    bytes
+        .split_at_checked(SymmetricEncrypt::NONCE_LEN)
+        .and_then(|(nonce, secret)| {
+            SymmetricEncrypt::new(server.core.oauth.oauth_key.as_bytes(), SECRET_CONTEXT)
+                .decrypt(secret, nonce)
+                .ok()
+        })
+        .and_then(|secret| String::from_utf8(secret).ok())
+        .ok_or_else(|| {
+            trc::StoreEvent::CryptoError
+                .into_err()
+                .details("Failed to decrypt mail sync secret")
+                .caused_by(trc::location!())
+        })
+}
+
+impl SyncRun {
+    pub(crate) fn is_expired(&self) -> bool {
+        self.started.elapsed() >= MAX_RUN_TIME
+    }
+
+    pub(crate) async fn checkpoint(
+        &self,
+        server: &Server,
+        state: &mut MailSyncState,
+    ) -> trc::Result<()> {
+        state.last_run = self.stats.clone();
+        store_state(server, self.account_id, state).await
+    }
+}
+
+impl MailSyncJob {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match (self.protocol, self.security) {
+            (Protocol::Imap, Security::Tls) => 993,
+            (Protocol::Imap, _) => 143,
+            (Protocol::Pop3, Security::Tls) => 995,
+            (Protocol::Pop3, _) => 110,
+        })
+    }
+
+    // Folders are matched case-insensitively, including their subfolders
+    pub(crate) fn sync_folder(&self, name: &str) -> bool {
+        let matches = |folder: &String| {
+            name.eq_ignore_ascii_case(folder)
+                || name
+                    .get(..folder.len() + 1)
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&format!("{folder}/")))
+        };
+
+        (self.folders.is_empty() || self.folders.iter().any(matches))
+            && !self.exclude_folders.iter().any(matches)
+    }
+}
+
+impl FolderState {
+    pub(crate) fn new(name: String, uid_validity: u32) -> Self {
+        FolderState {
+            name,
+            uid_validity,
+            last_uid: 0,
+            messages: vec![],
+        }
+    }
+}
+
+pub(crate) fn flags_to_bits(flags: &[String]) -> u8 {
+    flags.iter().fold(0, |bits, flag| {
+        let keyword = Keyword::from(flag);
+        SYNC_FLAGS
+            .iter()
+            .find(|(_, sync_keyword)| sync_keyword == &keyword)
+            .map_or(bits, |(bit, _)| bits | bit)
+    })
+}
+
+pub(crate) async fn ingest_message(
+    server: &Server,
+    access_token: &AccessToken,
+    mailbox_id: u32,
+    contents: &[u8],
+    keywords: Vec<Keyword>,
+    received_at: Option<u64>,
+) -> trc::Result<Option<u32>> {
+    match server
+        .email_ingest(IngestEmail {
+            raw_message: contents,
+            message: MessageParser::new().parse(contents),
+            access_token,
+            mailbox_ids: vec![mailbox_id],
+            keywords,
+            received_at,
+            source: IngestSource::Imap,
+            spam_classify: false,
+            spam_train: false,
+            session_id: 0,
+        })
+        .await
+    {
+        Ok(email) => Ok(Some(email.id.document_id())),
+        Err(err)
+            if err.matches(trc::EventType::Limit(trc::LimitEvent::Quota))
+                || err.matches(trc::EventType::Limit(trc::LimitEvent::TenantQuota)) =>
+        {
+            Err(err)
+        }
+        Err(err) => {
+            trc::error!(
+                err.account_id(access_token.primary_id)
+                    .details("Failed to import message from remote server")
+            );
+            Ok(None)
+        }
+    }
+}
+
+// Applies remote flag changes to previously synced messages, changes made
+// locally to flags that did not change remotely are preserved.
+pub(crate) async fn apply_flag_changes(
+    server: &Server,
+    account_id: u32,
+    changes: Vec<(u32, u8, u8)>,
+) -> trc::Result<u64> {
+    let mut batch = BatchBuilder::new();
+    let mut changed_mailboxes = AHashSet::new();
+    let mut updated = 0;
+
+    for (document_id, old_flags, new_flags) in changes {
+        let Some(data_) = server
+            .get_archive(account_id, Collection::Email, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            continue;
+        };
+        let data = data_
+            .to_unarchived::<MessageData>()
+            .caused_by(trc::location!())?;
+        let mut new_data = data.deserialize().caused_by(trc::location!())?;
+
+        for (bit, keyword) in SYNC_FLAGS {
+            if new_flags & bit != 0 && old_flags & bit == 0 {
+                new_data.add_keyword(keyword);
+            } else if new_flags & bit == 0 && old_flags & bit != 0 {
+                new_data.remove_keyword(&keyword);
+            }
+        }
+        if !new_data.has_keyword_changes(data.inner) {
+            continue;
+        }
+
+        // Unread counters change when the Seen flag does
+        if (old_flags ^ new_flags) & SYNC_FLAGS[0].0 != 0 {
+            changed_mailboxes.extend(new_data.mailboxes.iter().map(|m| m.mailbox_id));
+        }
+
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .custom(
+                ObjectIndexBuilder::new()
+                    .with_current(data)
+                    .with_changes(new_data),
+            )
+            .caused_by(trc::location!())?
+            .commit_point();
+        updated += 1;
+    }
+
+    if !batch.is_empty() {
+        for mailbox_id in changed_mailboxes {
+            batch.log_container_property_change(SyncCollection::Email, mailbox_id);
+        }
+
+        let change_id = server
+            .commit_batch(batch)
+            .await
+            .and_then(|ids| ids.last_change_id(account_id))
+            .caused_by(trc::location!())?;
+
+        server
+            .broadcast_state_change(
+                StateChange::new(account_id, change_id)
+                    .with_change(DataType::Email)
+                    .with_change(DataType::Mailbox),
+            )
+            .await;
+    }
+
+    Ok(updated)
+}
+
+fn default_enabled() -> bool {
+    true
+}
@@ -0,0 +1,183 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{Server, auth::AccessToken};
+use email::mailbox::INBOX_ID;
+
+use super::{
+    MailSyncJob, MailSyncState, Security, SyncRun,
+    connection::{Connection, protocol_error},
+    ingest_message,
+};
+
+pub(crate) struct Pop3Session {
+    conn: Connection,
+}
+
+// POP3 has no folders or flags, messages are downloaded into the Inbox
+// once and left on the remote server.
+pub(crate) async fn sync_pop3(
+    server: &Server,
+    access_token: &AccessToken,
+    job: &MailSyncJob,
+    state: &mut MailSyncState,
+    run: &mut SyncRun,
+) -> trc::Result<()> {
+    let mut session = Pop3Session::connect(job, run.timeout).await?;
+    let sizes = session.list().await?;
+    let messages = session.uidl().await?;
+
+    // Forget UIDs of messages removed from the remote server
+    state
+        .pop3_uids
+        .retain(|uid| messages.iter().any(|(_, remote_uid)| remote_uid == uid));
+    let messages = messages
+        .into_iter()
+        .filter(|(_, uid)| !state.pop3_uids.contains(uid))
+        .collect::<Vec<_>>();
+    state.pending = messages.len() as u64;
+    run.stats.folders = 1;
+
+    for (idx, (number, uid)) in messages.into_iter().enumerate() {
+        if run.is_expired() {
+            state.is_complete = false;
+            return session.quit().await;
+        }
+        state.pending -= 1;
+
+        if sizes
+            .iter()
+            .find(|(n, _)| *n == number)
+            .is_some_and(|(_, size)| *size > server.core.jmap.mail_max_size)
+        {
+            state.pop3_uids.push(uid);
+            run.stats.skipped += 1;
+            continue;
+        }
+
+        let contents = session.retr(number, server.core.jmap.mail_max_size).await?;
+        if ingest_message(server, access_token, INBOX_ID, &contents, vec![], None)
+            .await?
+            .is_some()
+        {
+            run.stats.messages += 1;
+        } else {
+            run.stats.skipped += 1;
+        }
+        state.pop3_uids.push(uid);
+
+        // Store the progress so interrupted runs resume from here
+        if idx % 50 == 49 {
+            run.checkpoint(server, state).await?;
+        }
+    }
+
+    state.is_complete = true;
+    session.quit().await
+}
+
+impl Pop3Session {
+    async fn connect(job: &MailSyncJob, timeout: Duration) -> trc::Result<Self> {
+        let mut session = Pop3Session {
+            conn: Connection::connect(job, timeout).await?,
+        };
+        session.read_status().await?;
+
+        if job.security == Security::StartTls {
+            session.command("STLS").await?;
+            session.conn = session.conn.start_tls(job).await?;
+        }
+
+        if [&job.username, &job.secret]
+            .iter()
+            .any(|value| value.contains(['\r', '\n']))
+        {
+            return Err(trc::AuthEvent::Failed
+                .into_err()
+                .details("Invalid characters in credentials"));
+        }
+        let result = match session.command(&format!("USER {}", job.username)).await {
+            Ok(_) => session.command(&format!("PASS {}", job.secret)).await,
+            Err(err) => Err(err),
+        };
+
+        result.map(|_| session).map_err(|err| {
+            trc::AuthEvent::Failed
+                .into_err()
+                .details("Remote server rejected credentials")
+                .caused_by(err)
+        })
+    }
+
+    async fn list(&mut self) -> trc::Result<Vec<(u32, usize)>> {
+        self.command("LIST").await?;
+        Ok(self
+            .read_multiline(usize::MAX)
+            .await?
+            .split(|&ch| ch == b'\n')
+            .filter_map(|line| {
+                let line = std::str::from_utf8(line).ok()?;
+                let (number, size) = line.trim().split_once(' ')?;
+                Some((number.parse().ok()?, size.trim().parse().ok()?))
+            })
+            .collect())
+    }
+
+    async fn uidl(&mut self) -> trc::Result<Vec<(u32, String)>> {
+        self.command("UIDL").await?;
+        Ok(self
+            .read_multiline(usize::MAX)
+            .await?
+            .split(|&ch| ch == b'\n')
+            .filter_map(|line| {
+                let line = std::str::from_utf8(line).ok()?;
+                let (number, uid) = line.trim().split_once(' ')?;
+                Some((number.parse().ok()?, uid.trim().to_string()))
+            })
+            .collect())
+    }
+
+    async fn retr(&mut self, number: u32, max_size: usize) -> trc::Result<Vec<u8>> {
+        self.command(&format!("RETR {number}")).await?;
+        self.read_multiline(max_size).await
+    }
+
+    async fn quit(&mut self) -> trc::Result<()> {
+        self.conn.write(b"QUIT\r\n").await
+    }
+
+    async fn command(&mut self, command: &str) -> trc::Result<()> {
+        self.conn.write(format!("{command}\r\n").as_bytes()).await?;
+        self.read_status().await
+    }
+
+    async fn read_status(&mut self) -> trc::Result<()> {
+        let line = self.conn.read_line().await?;
+        if line.starts_with(b"+OK") {
+            Ok(())
+        } else {
+            Err(protocol_error("POP3 command failed", &line))
+        }
+    }
+
+    // Reads a dot-terminated response, removing dot-stuffing
+    async fn read_multiline(&mut self, max_size: usize) -> trc::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+
+        loop {
+            let line = self.conn.read_line().await?;
+            if line == b".\r\n" || line == b".\n" {
+                return Ok(contents);
+            }
+            contents.extend_from_slice(line.strip_prefix(b".").unwrap_or(&line));
+            if contents.len() > max_size {
+                return Err(protocol_error("POP3 response too large", b""));
+            }
+        }
+    }
+}
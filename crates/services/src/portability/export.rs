@@ -0,0 +1,586 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{
+    ARCHIVE_VERSION, AddressEntry, ArchiveStats, ExportedArchive, IdentityEntry, MailEntry,
+    MailFormat, MailIndex, Manifest, SieveEntry, VacationEntry, archive_path,
+};
+use chrono::DateTime;
+use common::{DavResources, Server, auth::AccessToken};
+use directory::backend::internal::manage::ManageDirectory;
+use email::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess},
+    identity::Identity,
+    message::metadata::MessageMetadata,
+    sieve::SieveScript,
+};
+use groupware::{cache::GroupwareCache, calendar::CalendarEvent, contact::ContactCard};
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    property::Property,
+};
+use serde::Serialize;
+use std::{
+    future::Future,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use store::{ahash::AHashMap, rand, write::now};
+use tokio::{io::AsyncReadExt, sync::mpsc};
+use trc::AddContext;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+// Archives are stored in the blob store split in parts of this size
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+pub trait AccountExport: Sync + Send {
+    fn export_account(
+        &self,
+        account_id: u32,
+        format: MailFormat,
+    ) -> impl Future<Output = trc::Result<(ExportedArchive, ArchiveStats)>> + Send;
+}
+
+enum ArchiveOp {
+    File(String),
+    Directory(String),
+    Data(Vec<u8>),
+}
+
+struct ArchiveWriter {
+    tx: mpsc::Sender<ArchiveOp>,
+    stats: ArchiveStats,
+}
+
+impl AccountExport for Server {
+    async fn export_account(
+        &self,
+        account_id: u32,
+        format: MailFormat,
+    ) -> trc::Result<(ExportedArchive, ArchiveStats)> {
+        // ZIP files require a seekable writer, so the archive is written to a
+        // temporary file and then copied to the blob store in parts.
+        let path = std::env::temp_dir().join(format!(
+            "account-export-{account_id}-{:016x}.zip",
+            rand::random::<u64>()
+        ));
+        let result = match export_to_file(self, account_id, format, &path).await {
+            Ok(stats) => store_archive(self, account_id, &path)
+                .await
+                .map(|archive| (archive, stats)),
+            Err(err) => Err(err),
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+
+        result
+    }
+}
+
+async fn export_to_file(
+    server: &Server,
+    account_id: u32,
+    format: MailFormat,
+    path: &Path,
+) -> trc::Result<ArchiveStats> {
+    let access_token = server
+        .get_access_token(account_id)
+        .await
+        .caused_by(trc::location!())?;
+    let account = server
+        .store()
+        .get_principal_name(account_id)
+        .await
+        .caused_by(trc::location!())?
+        .ok_or_else(|| trc::ManageEvent::NotFound.into_err().account_id(account_id))?;
+
+    let (tx, rx) = mpsc::channel(16);
+    let path = path.to_path_buf();
+    let handle = tokio::task::spawn_blocking(move || write_archive(&path, rx));
+    let mut writer = ArchiveWriter {
+        tx,
+        stats: ArchiveStats::default(),
+    };
+
+    let result = async {
+        writer
+            .write_json(
+                "manifest.json",
+                &Manifest {
+                    version: ARCHIVE_VERSION,
+                    account,
+                    created_at: now(),
+                    mail_format: format,
+                },
+            )
+            .await?;
+        export_mail(server, account_id, format, &mut writer).await?;
+        export_sieve(server, account_id, &mut writer).await?;
+        export_identities(server, account_id, &mut writer).await?;
+        export_dav(
+            server,
+            &access_token,
+            account_id,
+            SyncCollection::Calendar,
+            &mut writer,
+        )
+        .await?;
+        export_dav(
+            server,
+            &access_token,
+            account_id,
+            SyncCollection::AddressBook,
+            &mut writer,
+        )
+        .await
+    }
+    .await;
+
+    // Closing the channel finishes the archive, writer errors take precedence
+    let ArchiveWriter { tx, stats } = writer;
+    drop(tx);
+    handle
+        .await
+        .map_err(archive_error)
+        .and_then(|result| result)
+        .and(result)
+        .map(|_| stats)
+}
+
+fn write_archive(path: &Path, mut rx: mpsc::Receiver<ArchiveOp>) -> trc::Result<()> {
+    let file = std::fs::File::create(path).map_err(archive_error)?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+
+    while let Some(op) = rx.blocking_recv() {
+        match op {
+            ArchiveOp::File(name) => zip.start_file(name, options()),
+            ArchiveOp::Directory(name) => zip.add_directory(name, options()),
+            ArchiveOp::Data(bytes) => zip.write_all(&bytes).map_err(Into::into),
+        }
+        .map_err(archive_error)?;
+    }
+
+    zip.finish()
+        .map_err(archive_error)?
+        .flush()
+        .map_err(archive_error)
+}
+
+async fn store_archive(
+    server: &Server,
+    account_id: u32,
+    path: &Path,
+) -> trc::Result<ExportedArchive> {
+    let mut file = tokio::fs::File::open(path).await.map_err(archive_error)?;
+    let mut archive = ExportedArchive::default();
+
+    loop {
+        let mut part = Vec::with_capacity(PART_SIZE);
+        (&mut file)
+            .take(PART_SIZE as u64)
+            .read_to_end(&mut part)
+            .await
+            .map_err(archive_error)?;
+        if part.is_empty() {
+            break;
+        }
+
+        archive.size += part.len() as u64;
+        archive.parts.push(
+            server
+                .put_blob(account_id, &part, false)
+                .await
+                .caused_by(trc::location!())?
+                .hash,
+        );
+
+        if part.len() < PART_SIZE {
+            break;
+        }
+    }
+
+    Ok(archive)
+}
+
+async fn export_mail(
+    server: &Server,
+    account_id: u32,
+    format: MailFormat,
+    writer: &mut ArchiveWriter,
+) -> trc::Result<()> {
+    let cache = server
+        .get_cached_messages(account_id)
+        .await
+        .caused_by(trc::location!())?;
+    let mailbox_paths = cache
+        .mailboxes
+        .items
+        .iter()
+        .map(|mailbox| (mailbox.document_id, mailbox.path.as_str()))
+        .collect::<AHashMap<_, _>>();
+    let mut index = MailIndex {
+        mailboxes: cache
+            .mailboxes
+            .items
+            .iter()
+            .map(|mailbox| mailbox.path.clone())
+            .collect(),
+        messages: Vec::with_capacity(cache.emails.items.len()),
+    };
+    writer.stats.mailboxes = index.mailboxes.len() as u64;
+
+    // Messages are stored once, in the first mailbox they belong to. When
+    // exporting to mbox, messages are sorted by mailbox so that each mbox file
+    // is written in one go.
+    let mut messages = cache
+        .emails
+        .items
+        .iter()
+        .filter_map(|message| {
+            message
+                .mailboxes
+                .first()
+                .map(|mailbox| (mailbox.mailbox_id, message))
+        })
+        .collect::<Vec<_>>();
+    if format == MailFormat::Mbox {
+        messages.sort_by_key(|(mailbox_id, _)| *mailbox_id);
+    }
+    let mut mbox: Option<(u32, String, usize)> = None;
+
+    for (mailbox_id, message) in messages {
+        let Some(mailbox_path) = mailbox_paths.get(&mailbox_id) else {
+            writer.stats.skipped += 1;
+            continue;
+        };
+        let Some(metadata_) = server
+            .get_archive_by_property(
+                account_id,
+                Collection::Email,
+                message.document_id,
+                Property::BodyStructure,
+            )
+            .await
+            .caused_by(trc::location!())?
+        else {
+            writer.stats.skipped += 1;
+            continue;
+        };
+        let metadata = metadata_
+            .unarchive::<MessageMetadata>()
+            .caused_by(trc::location!())?;
+        let received_at = u64::from(metadata.received_at);
+        let Some(contents) = server
+            .blob_store()
+            .get_blob(metadata.blob_hash.0.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            trc::event!(
+                Store(trc::StoreEvent::NotFound),
+                AccountId = account_id,
+                DocumentId = message.document_id,
+                Details = "Message blob not found during account export",
+                CausedBy = trc::location!(),
+            );
+            writer.stats.skipped += 1;
+            continue;
+        };
+
+        let mut entry = MailEntry {
+            file: String::new(),
+            index: None,
+            mailboxes: message
+                .mailboxes
+                .iter()
+                .filter_map(|m| mailbox_paths.get(&m.mailbox_id).map(|p| p.to_string()))
+                .collect(),
+            keywords: cache
+                .expand_keywords(message)
+                .map(|keyword| keyword.to_string())
+                .collect(),
+            received_at: Some(received_at),
+        };
+
+        match format {
+            MailFormat::Mbox => {
+                if mbox.as_ref().is_none_or(|(id, _, _)| *id != mailbox_id) {
+                    let file = format!("mail/{}.mbox", archive_path(mailbox_path));
+                    writer.start_file(file.clone()).await?;
+                    mbox = Some((mailbox_id, file, 0));
+                }
+                if let Some((_, file, count)) = &mut mbox {
+                    let mut bytes = Vec::with_capacity(contents.len() + 64);
+                    write_mbox_message(&mut bytes, &contents, received_at);
+                    writer.write(bytes).await?;
+                    entry.file = file.clone();
+                    entry.index = Some(*count);
+                    *count += 1;
+                }
+            }
+            MailFormat::Eml => {
+                entry.file = format!(
+                    "mail/{}/{}.eml",
+                    archive_path(mailbox_path),
+                    message.document_id
+                );
+                writer.write_file(entry.file.clone(), contents).await?;
+            }
+        }
+        index.messages.push(entry);
+        writer.stats.messages += 1;
+    }
+
+    writer.write_json("mail.json", &index).await
+}
+
+async fn export_sieve(
+    server: &Server,
+    account_id: u32,
+    writer: &mut ArchiveWriter,
+) -> trc::Result<()> {
+    let mut entries = Vec::new();
+    for document_id in server
+        .get_document_ids(account_id, Collection::SieveScript)
+        .await
+        .caused_by(trc::location!())?
+        .unwrap_or_default()
+    {
+        let Some(script) = server
+            .get_archive(account_id, Collection::SieveScript, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            continue;
+        };
+        let script = script
+            .deserialize::<SieveScript>()
+            .caused_by(trc::location!())?;
+        let Some(contents) = server
+            .blob_store()
+            .get_blob(script.blob_hash.as_slice(), 0..script.size as usize)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            writer.stats.skipped += 1;
+            continue;
+        };
+
+        let file = format!("sieve/{}.sieve", archive_path(&script.name));
+        writer.write_file(file.clone(), contents).await?;
+        entries.push(SieveEntry {
+            name: script.name,
+            file,
+            is_active: script.is_active,
+            vacation_response: script.vacation_response.map(|vacation| VacationEntry {
+                from_date: vacation.from_date,
+                to_date: vacation.to_date,
+                subject: vacation.subject,
+                text_body: vacation.text_body,
+                html_body: vacation.html_body,
+            }),
+        });
+        writer.stats.sieve_scripts += 1;
+    }
+
+    writer.write_json("sieve.json", &entries).await
+}
+
+async fn export_identities(
+    server: &Server,
+    account_id: u32,
+    writer: &mut ArchiveWriter,
+) -> trc::Result<()> {
+    let mut entries = Vec::new();
+    for document_id in server
+        .get_document_ids(account_id, Collection::Identity)
+        .await
+        .caused_by(trc::location!())?
+        .unwrap_or_default()
+    {
+        let Some(identity) = server
+            .get_archive(account_id, Collection::Identity, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            continue;
+        };
+        let identity = identity
+            .deserialize::<Identity>()
+            .caused_by(trc::location!())?;
+        let addresses = |addresses: Option<Vec<email::identity::EmailAddress>>| {
+            addresses.map(|addresses| {
+                addresses
+                    .into_iter()
+                    .map(|addr| AddressEntry {
+                        name: addr.name,
+                        email: addr.email,
+                    })
+                    .collect()
+            })
+        };
+        entries.push(IdentityEntry {
+            name: identity.name,
+            email: identity.email,
+            reply_to: addresses(identity.reply_to),
+            bcc: addresses(identity.bcc),
+            text_signature: identity.text_signature,
+            html_signature: identity.html_signature,
+        });
+        writer.stats.identities += 1;
+    }
+
+    writer.write_json("identities.json", &entries).await
+}
+
+async fn export_dav(
+    server: &Server,
+    access_token: &AccessToken,
+    account_id: u32,
+    collection: SyncCollection,
+    writer: &mut ArchiveWriter,
+) -> trc::Result<()> {
+    let resources = server
+        .fetch_dav_resources(access_token, account_id, collection)
+        .await
+        .caused_by(trc::location!())?;
+    let (root, item_collection) = match collection {
+        SyncCollection::Calendar => ("calendars", Collection::CalendarEvent),
+        _ => ("contacts", Collection::ContactCard),
+    };
+
+    for resource in &resources.resources {
+        if let Some(name) = resource.container_name() {
+            writer
+                .add_directory(format!("{root}/{}", archive_path(name)))
+                .await?;
+            if collection == SyncCollection::Calendar {
+                writer.stats.calendars += 1;
+            } else {
+                writer.stats.address_books += 1;
+            }
+            continue;
+        }
+
+        // Items linked to several containers are exported once
+        let Some(path) = item_path(&resources, resource.child_names()) else {
+            continue;
+        };
+        let Some(archive) = server
+            .get_archive(account_id, item_collection, resource.document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            continue;
+        };
+        if collection == SyncCollection::Calendar {
+            let event = archive
+                .unarchive::<CalendarEvent>()
+                .caused_by(trc::location!())?;
+            writer
+                .write_file(
+                    format!("{root}/{}", archive_path(&path)),
+                    event.data.event.to_string().into_bytes(),
+                )
+                .await?;
+            writer.stats.events += 1;
+        } else {
+            let card = archive
+                .unarchive::<ContactCard>()
+                .caused_by(trc::location!())?;
+            let mut vcard = String::with_capacity(128);
+            let _ = card
+                .card
+                .write_to(&mut vcard, card.card.version().unwrap_or_default());
+            writer
+                .write_file(
+                    format!("{root}/{}", archive_path(&path)),
+                    vcard.into_bytes(),
+                )
+                .await?;
+            writer.stats.contacts += 1;
+        }
+    }
+
+    Ok(())
+}
+
+fn item_path(resources: &DavResources, names: Option<&[common::DavName]>) -> Option<String> {
+    names?.iter().find_map(|name| {
+        resources
+            .container_resource_by_id(name.parent_id)
+            .and_then(|container| container.container_name())
+            .map(|container| format!("{container}/{}", name.name))
+    })
+}
+
+// Writes a message in mboxrd format, quoting lines starting with "From "
+fn write_mbox_message(mbox: &mut Vec<u8>, contents: &[u8], received_at: u64) {
+    let date = DateTime::from_timestamp(received_at as i64, 0)
+        .unwrap_or_default()
+        .format("%a %b %e %H:%M:%S %Y");
+    mbox.extend_from_slice(format!("From MAILER-DAEMON {date}\n").as_bytes());
+    for line in contents.split_inclusive(|&ch| ch == b'\n') {
+        let unquoted = line
+            .iter()
+            .position(|&ch| ch != b'>')
+            .map_or(line, |pos| &line[pos..]);
+        if unquoted.starts_with(b"From ") {
+            mbox.push(b'>');
+        }
+        mbox.extend_from_slice(line);
+    }
+    if !mbox.ends_with(b"\n") {
+        mbox.push(b'\n');
+    }
+    mbox.push(b'\n');
+}
+
+impl ArchiveWriter {
+    async fn start_file(&self, name: String) -> trc::Result<()> {
+        self.send(ArchiveOp::File(name)).await
+    }
+
+    async fn add_directory(&self, name: String) -> trc::Result<()> {
+        self.send(ArchiveOp::Directory(name)).await
+    }
+
+    async fn write(&self, bytes: Vec<u8>) -> trc::Result<()> {
+        self.send(ArchiveOp::Data(bytes)).await
+    }
+
+    async fn write_file(&self, name: String, contents: Vec<u8>) -> trc::Result<()> {
+        self.start_file(name).await?;
+        self.write(contents).await
+    }
+
+    async fn write_json(&self, name: &str, value: &impl Serialize) -> trc::Result<()> {
+        let contents = serde_json::to_vec_pretty(value).map_err(|err| {
+            trc::StoreEvent::UnexpectedError
+                .into_err()
+                .details("Failed to serialize archive metadata")
+                .reason(err)
+        })?;
+        self.write_file(name.to_string(), contents).await
+    }
+
+    async fn send(&self, op: ArchiveOp) -> trc::Result<()> {
+        self.tx
+            .send(op)
+            .await
+            .map_err(|_| archive_error("Archive writer is no longer running"))
+    }
+}
+
+fn options() -> SimpleFileOptions {
+    SimpleFileOptions::default().large_file(true)
+}
+
+fn archive_error(err: impl std::fmt::Display) -> trc::Error {
+    trc::StoreEvent::UnexpectedError
+        .into_err()
+        .details("Failed to write account archive")
+        .reason(err)
+}
@@ -0,0 +1,715 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{
+    ARCHIVE_VERSION, AddressEntry, ArchiveStats, IdentityEntry, MailIndex, Manifest, SieveEntry,
+};
+use calcard::{Entry, Parser, common::timezone::Tz};
+use common::{DavName, Server, auth::AccessToken, storage::index::ObjectIndexBuilder};
+use email::{
+    identity::{EmailAddress, Identity},
+    mailbox::manage::MailboxFnc,
+    message::ingest::{EmailIngest, IngestEmail, IngestSource},
+    sieve::{SieveScript, VacationResponse, activate::SieveScriptActivate},
+};
+use groupware::{
+    cache::GroupwareCache,
+    calendar::{Calendar, CalendarEvent, CalendarEventData, CalendarPreferences},
+    contact::{AddressBook, ContactCard},
+};
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    keyword::Keyword,
+};
+use mail_parser::{MessageParser, mailbox::mbox::MessageIterator};
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    io::{Cursor, Read},
+};
+use store::{
+    ahash::{AHashMap, AHashSet},
+    write::{Archiver, BatchBuilder},
+};
+use trc::AddContext;
+use zip::ZipArchive;
+
+// Limits the decompressed size of each archive entry
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+pub trait AccountImport: Sync + Send {
+    fn import_account(
+        &self,
+        account_id: u32,
+        archive: Vec<u8>,
+    ) -> impl Future<Output = trc::Result<ArchiveStats>> + Send;
+}
+
+struct ArchiveReader {
+    zip: ZipArchive<Cursor<Vec<u8>>>,
+    stats: ArchiveStats,
+}
+
+impl AccountImport for Server {
+    async fn import_account(&self, account_id: u32, archive: Vec<u8>) -> trc::Result<ArchiveStats> {
+        let mut reader = ArchiveReader {
+            zip: ZipArchive::new(Cursor::new(archive)).map_err(archive_error)?,
+            stats: ArchiveStats::default(),
+        };
+        if let Some(version) = reader
+            .read_json::<Manifest>("manifest.json")?
+            .map(|manifest| manifest.version)
+            .filter(|version| *version > ARCHIVE_VERSION)
+        {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Unsupported account archive version")
+                .ctx(trc::Key::Version, version));
+        }
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        import_mail(self, &access_token, &mut reader).await?;
+        import_sieve(self, &access_token, &mut reader).await?;
+        import_identities(self, account_id, &mut reader).await?;
+        import_calendars(self, &access_token, &mut reader).await?;
+        import_contacts(self, &access_token, &mut reader).await?;
+
+        Ok(reader.stats)
+    }
+}
+
+struct ImportMessage {
+    contents: Vec<u8>,
+    mailboxes: Vec<String>,
+    keywords: Vec<Keyword>,
+    received_at: Option<u64>,
+}
+
+async fn import_mail(
+    server: &Server,
+    access_token: &AccessToken,
+    reader: &mut ArchiveReader,
+) -> trc::Result<()> {
+    let account_id = access_token.primary_id;
+    let mut mailbox_ids: AHashMap<String, Option<u32>> = AHashMap::new();
+    let mut messages = Vec::new();
+
+    if let Some(index) = reader.read_json::<MailIndex>("mail.json")? {
+        for path in &index.mailboxes {
+            mailbox_id(server, account_id, path, &mut mailbox_ids).await?;
+        }
+
+        let mut mbox_files: AHashMap<String, Vec<Option<Vec<u8>>>> = AHashMap::new();
+        for entry in index.messages {
+            let contents = if let Some(idx) = entry.index {
+                if !mbox_files.contains_key(&entry.file) {
+                    let messages = reader
+                        .read_file(&entry.file)?
+                        .map(|mbox| {
+                            MessageIterator::new(Cursor::new(mbox))
+                                .map(|message| message.ok().map(|m| m.unwrap_contents()))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    mbox_files.insert(entry.file.clone(), messages);
+                }
+                mbox_files
+                    .get_mut(&entry.file)
+                    .and_then(|messages| messages.get_mut(idx))
+                    .and_then(|message| message.take())
+            } else {
+                reader.read_file(&entry.file)?
+            };
+
+            if let Some(contents) = contents {
+                messages.push(ImportMessage {
+                    contents,
+                    mailboxes: entry.mailboxes,
+                    keywords: entry.keywords.iter().map(Keyword::from).collect(),
+                    received_at: entry.received_at,
+                });
+            } else {
+                reader.stats.skipped += 1;
+            }
+        }
+    } else {
+        // Derive the mailbox from the file path
+        for name in reader.file_names("mail/") {
+            let path = name.strip_prefix("mail/").unwrap_or_default();
+            if let Some(mailbox) = path.strip_suffix(".mbox") {
+                for message in
+                    MessageIterator::new(Cursor::new(reader.read_file(&name)?.unwrap_or_default()))
+                {
+                    match message {
+                        Ok(message) => messages.push(ImportMessage {
+                            received_at: Some(message.internal_date()).filter(|date| *date > 0),
+                            contents: message.unwrap_contents(),
+                            mailboxes: vec![mailbox.to_string()],
+                            keywords: vec![],
+                        }),
+                        Err(_) => reader.stats.skipped += 1,
+                    }
+                }
+            } else if path.ends_with(".eml") {
+                let mailbox = path
+                    .rsplit_once('/')
+                    .map_or("Inbox", |(mailbox, _)| mailbox);
+                if let Some(contents) = reader.read_file(&name)? {
+                    messages.push(ImportMessage {
+                        contents,
+                        mailboxes: vec![mailbox.to_string()],
+                        keywords: vec![],
+                        received_at: None,
+                    });
+                }
+            }
+        }
+    }
+
+    for message in messages {
+        let mut ids = Vec::with_capacity(message.mailboxes.len());
+        for path in &message.mailboxes {
+            if let Some(id) = mailbox_id(server, account_id, path, &mut mailbox_ids).await? {
+                ids.push(id);
+            }
+        }
+        if ids.is_empty() {
+            reader.stats.skipped += 1;
+            continue;
+        }
+
+        match server
+            .email_ingest(IngestEmail {
+                raw_message: &message.contents,
+                message: MessageParser::new().parse(&message.contents),
+                access_token,
+                mailbox_ids: ids,
+                keywords: message.keywords,
+                received_at: message.received_at,
+                source: IngestSource::Restore,
+                spam_classify: false,
+                spam_train: false,
+                session_id: 0,
+            })
+            .await
+        {
+            Ok(_) => {
+                reader.stats.messages += 1;
+            }
+            Err(err)
+                if err.matches(trc::EventType::Limit(trc::LimitEvent::Quota))
+                    || err.matches(trc::EventType::Limit(trc::LimitEvent::TenantQuota)) =>
+            {
+                return Err(err);
+            }
+            Err(err) => {
+                trc::error!(
+                    err.account_id(account_id)
+                        .details("Failed to import message from account archive")
+                );
+                reader.stats.skipped += 1;
+            }
+        }
+    }
+
+    reader.stats.mailboxes = mailbox_ids.values().filter(|id| id.is_some()).count() as u64;
+
+    Ok(())
+}
+
+async fn mailbox_id(
+    server: &Server,
+    account_id: u32,
+    path: &str,
+    mailbox_ids: &mut AHashMap<String, Option<u32>>,
+) -> trc::Result<Option<u32>> {
+    if let Some(id) = mailbox_ids.get(path) {
+        return Ok(*id);
+    }
+    let id = server
+        .mailbox_create_path(account_id, path)
+        .await
+        .caused_by(trc::location!())?;
+    mailbox_ids.insert(path.to_string(), id);
+    Ok(id)
+}
+
+async fn import_sieve(
+    server: &Server,
+    access_token: &AccessToken,
+    reader: &mut ArchiveReader,
+) -> trc::Result<()> {
+    let Some(entries) = reader.read_json::<Vec<SieveEntry>>("sieve.json")? else {
+        return Ok(());
+    };
+    let account_id = access_token.primary_id;
+    let mut names = AHashSet::new();
+    for document_id in server
+        .get_document_ids(account_id, Collection::SieveScript)
+        .await
+        .caused_by(trc::location!())?
+        .unwrap_or_default()
+    {
+        if let Some(script) = server
+            .get_archive(account_id, Collection::SieveScript, document_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            names.insert(
+                script
+                    .unarchive::<SieveScript>()
+                    .caused_by(trc::location!())?
+                    .name
+                    .to_lowercase(),
+            );
+        }
+    }
+
+    let mut activate_id = None;
+    for entry in entries {
+        // Existing scripts are not overwritten
+        if !names.insert(entry.name.to_lowercase()) {
+            reader.stats.skipped += 1;
+            continue;
+        }
+        let Some(mut bytes) = reader.read_file(&entry.file)? else {
+            reader.stats.skipped += 1;
+            continue;
+        };
+        let script = match server.core.sieve.untrusted_compiler.compile(&bytes) {
+            Ok(script) => script,
+            Err(err) => {
+                trc::event!(
+                    Store(trc::StoreEvent::UnexpectedError),
+                    AccountId = account_id,
+                    Details = "Failed to compile Sieve script from account archive",
+                    Id = entry.name,
+                    Reason = err.to_string(),
+                );
+                reader.stats.skipped += 1;
+                continue;
+            }
+        };
+        let size = bytes.len() as u32;
+        bytes.extend(
+            Archiver::new(script)
+                .untrusted()
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+        let blob_hash = server
+            .put_blob(account_id, &bytes, false)
+            .await
+            .caused_by(trc::location!())?
+            .hash;
+
+        let mut script = SieveScript::new(entry.name, blob_hash).with_size(size);
+        if let Some(vacation) = entry.vacation_response {
+            script = script.with_vacation_response(VacationResponse {
+                from_date: vacation.from_date,
+                to_date: vacation.to_date,
+                subject: vacation.subject,
+                text_body: vacation.text_body,
+                html_body: vacation.html_body,
+            });
+        }
+        let document_id = server
+            .store()
+            .assign_document_ids(account_id, Collection::SieveScript, 1)
+            .await
+            .caused_by(trc::location!())?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::SieveScript)
+            .create_document(document_id)
+            .custom(
+                ObjectIndexBuilder::<(), _>::new()
+                    .with_changes(script)
+                    .with_tenant_id(access_token),
+            )
+            .caused_by(trc::location!())?;
+        server
+            .commit_batch(batch)
+            .await
+            .caused_by(trc::location!())?;
+        if entry.is_active {
+            activate_id = Some(document_id);
+        }
+        reader.stats.sieve_scripts += 1;
+    }
+
+    if activate_id.is_some() {
+        server
+            .sieve_activate_script(account_id, activate_id)
+            .await
+            .caused_by(trc::location!())?;
+    }
+
+    Ok(())
+}
+
+async fn import_identities(
+    server: &Server,
+    account_id: u32,
+    reader: &mut ArchiveReader,
+) -> trc::Result<()> {
+    let Some(entries) = reader.read_json::<Vec<IdentityEntry>>("identities.json")? else {
+        return Ok(());
+    };
+    let mut existing = AHashSet::new();
+    for document_id in server
+        .get_document_ids(account_id, Collection::Identity)
+        .await
+        .caused_by(trc::location!())?
+        .unwrap_or_default()
+    {
+        if let Some(identity) = server
+            .get_archive(account_id, Collection::Identity, document_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            let identity = identity
+                .deserialize::<Identity>()
+                .caused_by(trc::location!())?;
+            existing.insert((identity.name, identity.email.to_lowercase()));
+        }
+    }
+
+    let addresses = |addresses: Option<Vec<AddressEntry>>| {
+        addresses.map(|addresses| {
+            addresses
+                .into_iter()
+                .map(|addr| EmailAddress {
+                    name: addr.name,
+                    email: addr.email,
+                })
+                .collect()
+        })
+    };
+    let mut batch = BatchBuilder::new();
+    for entry in entries {
+        if entry.email.is_empty()
+            || !existing.insert((entry.name.clone(), entry.email.to_lowercase()))
+        {
+            reader.stats.skipped += 1;
+            continue;
+        }
+        let document_id = server
+            .store()
+            .assign_document_ids(account_id, Collection::Identity, 1)
+            .await
+            .caused_by(trc::location!())?;
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Identity)
+            .create_document(document_id)
+            .custom(ObjectIndexBuilder::<(), _>::new().with_changes(Identity {
+                name: entry.name,
+                email: entry.email,
+                reply_to: addresses(entry.reply_to),
+                bcc: addresses(entry.bcc),
+                text_signature: entry.text_signature,
+                html_signature: entry.html_signature,
+            }))
+            .caused_by(trc::location!())?
+            .commit_point();
+        reader.stats.identities += 1;
+    }
+
+    if !batch.is_empty() {
+        server
+            .commit_batch(batch)
+            .await
+            .caused_by(trc::location!())?;
+    }
+
+    Ok(())
+}
+
+async fn import_calendars(
+    server: &Server,
+    access_token: &AccessToken,
+    reader: &mut ArchiveReader,
+) -> trc::Result<()> {
+    let account_id = access_token.primary_id;
+    let resources = server
+        .fetch_dav_resources(access_token, account_id, SyncCollection::Calendar)
+        .await
+        .caused_by(trc::location!())?;
+    let mut containers: AHashMap<String, u32> = AHashMap::new();
+    let mut items = AHashSet::new();
+
+    for name in reader.file_names("calendars/") {
+        let Some((calendar, event)) = dav_path(&name, "calendars/", ".ics") else {
+            continue;
+        };
+
+        let parent_id = if let Some(parent_id) = containers.get(calendar) {
+            *parent_id
+        } else {
+            let parent_id = if let Some(resource) = resources
+                .by_path(calendar)
+                .filter(|resource| resource.is_container())
+            {
+                resource.document_id()
+            } else {
+                let document_id = server
+                    .store()
+                    .assign_document_ids(account_id, Collection::Calendar, 1)
+                    .await
+                    .caused_by(trc::location!())?;
+                let mut batch = BatchBuilder::new();
+                Calendar {
+                    name: calendar.to_string(),
+                    preferences: vec![CalendarPreferences {
+                        account_id,
+                        name: calendar.to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }
+                .insert(access_token, account_id, document_id, &mut batch)
+                .caused_by(trc::location!())?;
+                server
+                    .commit_batch(batch)
+                    .await
+                    .caused_by(trc::location!())?;
+                reader.stats.calendars += 1;
+                document_id
+            };
+            containers.insert(calendar.to_string(), parent_id);
+            parent_id
+        };
+        let Some(event) = event else {
+            continue;
+        };
+
+        // Existing events are not overwritten
+        let path = format!("{calendar}/{event}");
+        if resources.by_path(&path).is_some() || !items.insert(path) {
+            reader.stats.skipped += 1;
+            continue;
+        }
+        let Some(ical) = reader
+            .read_file(&name)?
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+        else {
+            reader.stats.skipped += 1;
+            continue;
+        };
+        let Entry::ICalendar(event_data) = Parser::new(&ical).entry() else {
+            reader.stats.skipped += 1;
+            continue;
+        };
+
+        let mut next_email_alarm = None;
+        let document_id = server
+            .store()
+            .assign_document_ids(account_id, Collection::CalendarEvent, 1)
+            .await
+            .caused_by(trc::location!())?;
+        let mut batch = BatchBuilder::new();
+        CalendarEvent {
+            names: vec![DavName {
+                name: event.to_string(),
+                parent_id,
+            }],
+            data: CalendarEventData::new(
+                event_data,
+                Tz::Floating,
+                server.core.groupware.max_ical_instances,
+                &mut next_email_alarm,
+            ),
+            size: ical.len() as u32,
+            ..Default::default()
+        }
+        .insert(
+            access_token,
+            account_id,
+            document_id,
+            next_email_alarm,
+            &mut batch,
+        )
+        .caused_by(trc::location!())?;
+        server
+            .commit_batch(batch)
+            .await
+            .caused_by(trc::location!())?;
+        reader.stats.events += 1;
+    }
+
+    Ok(())
+}
+
+async fn import_contacts(
+    server: &Server,
+    access_token: &AccessToken,
+    reader: &mut ArchiveReader,
+) -> trc::Result<()> {
+    let account_id = access_token.primary_id;
+    let resources = server
+        .fetch_dav_resources(access_token, account_id, SyncCollection::AddressBook)
+        .await
+        .caused_by(trc::location!())?;
+    let mut containers: AHashMap<String, u32> = AHashMap::new();
+    let mut items = AHashSet::new();
+
+    for name in reader.file_names("contacts/") {
+        let Some((book, card)) = dav_path(&name, "contacts/", ".vcf") else {
+            continue;
+        };
+
+        let parent_id = if let Some(parent_id) = containers.get(book) {
+            *parent_id
+        } else {
+            let parent_id = if let Some(resource) = resources
+                .by_path(book)
+                .filter(|resource| resource.is_container())
+            {
+                resource.document_id()
+            } else {
+                let document_id = server
+                    .store()
+                    .assign_document_ids(account_id, Collection::AddressBook, 1)
+                    .await
+                    .caused_by(trc::location!())?;
+                let mut batch = BatchBuilder::new();
+                AddressBook {
+                    name: book.to_string(),
+                    ..Default::default()
+                }
+                .insert(access_token, account_id, document_id, &mut batch)
+                .caused_by(trc::location!())?;
+                server
+                    .commit_batch(batch)
+                    .await
+                    .caused_by(trc::location!())?;
+                reader.stats.address_books += 1;
+                document_id
+            };
+            containers.insert(book.to_string(), parent_id);
+            parent_id
+        };
+        let Some(card) = card else {
+            continue;
+        };
+
+        // Existing contacts are not overwritten
+        let path = format!("{book}/{card}");
+        if resources.by_path(&path).is_some() || !items.insert(path) {
+            reader.stats.skipped += 1;
+            continue;
+        }
+        let Some(vcard_raw) = reader
+            .read_file(&name)?
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+        else {
+            reader.stats.skipped += 1;
+            continue;
+        };
+        let Entry::VCard(vcard) = Parser::new(&vcard_raw).entry() else {
+            reader.stats.skipped += 1;
+            continue;
+        };
+
+        let document_id = server
+            .store()
+            .assign_document_ids(account_id, Collection::ContactCard, 1)
+            .await
+            .caused_by(trc::location!())?;
+        let mut batch = BatchBuilder::new();
+        ContactCard {
+            names: vec![DavName {
+                name: card.to_string(),
+                parent_id,
+            }],
+            card: vcard,
+            size: vcard_raw.len() as u32,
+            ..Default::default()
+        }
+        .insert(access_token, account_id, document_id, &mut batch)
+        .caused_by(trc::location!())?;
+        server
+            .commit_batch(batch)
+            .await
+            .caused_by(trc::location!())?;
+        reader.stats.contacts += 1;
+    }
+
+    Ok(())
+}
+
+// Splits "<root><container>/<item><extension>" into the container and item names,
+// directory entries return the container only
+fn dav_path<'x>(name: &'x str, root: &str, extension: &str) -> Option<(&'x str, Option<&'x str>)> {
+    let path = name.strip_prefix(root)?;
+    if let Some(container) = path.strip_suffix('/') {
+        (!container.is_empty() && !container.contains('/')).then_some((container, None))
+    } else {
+        let (container, item) = path.split_once('/')?;
+        (!container.is_empty()
+            && !item.is_empty()
+            && !item.contains('/')
+            && item.ends_with(extension))
+        .then_some((container, Some(item)))
+    }
+}
+
+impl ArchiveReader {
+    fn file_names(&self, prefix: &str) -> Vec<String> {
+        let mut names = self
+            .zip
+            .file_names()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    fn read_file(&mut self, name: &str) -> trc::Result<Option<Vec<u8>>> {
+        let file = match self.zip.by_name(name) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(archive_error(err)),
+        };
+        if file.size() > MAX_ENTRY_SIZE {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Account archive entry is too large")
+                .ctx(trc::Key::Path, name.to_string())
+                .ctx(trc::Key::Size, file.size()));
+        }
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        file.take(MAX_ENTRY_SIZE)
+            .read_to_end(&mut contents)
+            .map_err(archive_error)?;
+        Ok(Some(contents))
+    }
+
+    fn read_json<T: DeserializeOwned>(&mut self, name: &str) -> trc::Result<Option<T>> {
+        self.read_file(name)?
+            .map(|contents| {
+                serde_json::from_slice(&contents).map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                        .from_json_error(err)
+                        .ctx(trc::Key::Path, name.to_string())
+                })
+            })
+            .transpose()
+    }
+}
+
+fn archive_error(err: impl std::fmt::Display) -> trc::Error {
+    trc::ResourceEvent::BadParameters
+        .into_err()
+        .details("Failed to read account archive")
+        .reason(err)
+}
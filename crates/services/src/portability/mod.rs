@@ -0,0 +1,350 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Account archives are ZIP files with the following layout:
+//
+//   manifest.json                  Archive version, account name and mail format
+//   mail.json                      Mailboxes, keywords and received date of each message
+//   mail/<mailbox>.mbox            Messages in mbox format, or
+//   mail/<mailbox>/<id>.eml        one file per message in EML format
+//   sieve.json                     Script names, active script and vacation response
+//   sieve/<name>.sieve
+//   identities.json
+//   calendars/<calendar>/<event>.ics
+//   contacts/<address book>/<card>.vcf
+//
+// Archives without mail.json are also accepted on import, in which case each
+// mbox file is imported into the mailbox matching its path and EML files into
+// the mailbox matching their parent directory.
+
+pub mod export;
+pub mod import;
+
+use common::{KV_PORTABILITY_JOB, Server};
+use export::AccountExport;
+use import::AccountImport;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use store::{
+    SerializeInfallible,
+    dispatch::lookup::KeyValue,
+    rand,
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, BlobOp, now},
+};
+use trc::AddContext;
+use utils::BlobHash;
+
+pub(crate) const ARCHIVE_VERSION: u32 = 1;
+const JOB_EXPIRY: u64 = 86400;
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PortabilityJob {
+    pub account_id: u32,
+    #[serde(rename = "type")]
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub stats: ArchiveStats,
+    #[serde(skip)]
+    pub archive: Option<ExportedArchive>,
+    pub error: Option<String>,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Default, Clone)]
+pub struct ExportedArchive {
+    pub parts: Vec<BlobHash>,
+    pub size: u64,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    Export,
+    Import,
+}
+
+#[derive(
+    rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveStats {
+    pub mailboxes: u64,
+    pub messages: u64,
+    pub sieve_scripts: u64,
+    pub identities: u64,
+    pub calendars: u64,
+    pub events: u64,
+    pub address_books: u64,
+    pub contacts: u64,
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MailFormat {
+    Mbox,
+    Eml,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Manifest {
+    pub version: u32,
+    pub account: String,
+    pub created_at: u64,
+    pub mail_format: MailFormat,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MailIndex {
+    pub mailboxes: Vec<String>,
+    pub messages: Vec<MailEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MailEntry {
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    pub mailboxes: Vec<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub received_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SieveEntry {
+    pub name: String,
+    pub file: String,
+    #[serde(default)]
+    pub is_active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vacation_response: Option<VacationEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VacationEntry {
+    pub from_date: Option<u64>,
+    pub to_date: Option<u64>,
+    pub subject: Option<String>,
+    pub text_body: Option<String>,
+    pub html_body: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IdentityEntry {
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub reply_to: Option<Vec<AddressEntry>>,
+    #[serde(default)]
+    pub bcc: Option<Vec<AddressEntry>>,
+    #[serde(default)]
+    pub text_signature: String,
+    #[serde(default)]
+    pub html_signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AddressEntry {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+pub trait AccountPortability: Sync + Send {
+    fn start_account_export(
+        &self,
+        account_id: u32,
+        format: MailFormat,
+    ) -> impl Future<Output = trc::Result<String>> + Send;
+
+    fn start_account_import(
+        &self,
+        account_id: u32,
+        archive: Vec<u8>,
+    ) -> impl Future<Output = trc::Result<String>> + Send;
+
+    fn portability_job(
+        &self,
+        job_id: &str,
+    ) -> impl Future<Output = trc::Result<Option<PortabilityJob>>> + Send;
+}
+
+impl AccountPortability for Server {
+    async fn start_account_export(
+        &self,
+        account_id: u32,
+        format: MailFormat,
+    ) -> trc::Result<String> {
+        let job_id = format!("{:016x}", rand::random::<u64>());
+        let mut job = PortabilityJob::new(account_id, JobKind::Export);
+        store_job(self, &job_id, &job)
+            .await
+            .caused_by(trc::location!())?;
+
+        let server = self.clone();
+        let job_id_ = job_id.clone();
+        tokio::spawn(async move {
+            match server.export_account(account_id, format).await {
+                Ok((archive, stats)) => {
+                    job.stats = stats;
+                    job.archive = Some(archive);
+                    job.status = JobStatus::Completed;
+                }
+                Err(err) => job.failed(err),
+            }
+
+            finish_job(&server, &job_id_, job).await;
+        });
+
+        Ok(job_id)
+    }
+
+    async fn start_account_import(&self, account_id: u32, archive: Vec<u8>) -> trc::Result<String> {
+        let job_id = format!("{:016x}", rand::random::<u64>());
+        let mut job = PortabilityJob::new(account_id, JobKind::Import);
+        store_job(self, &job_id, &job)
+            .await
+            .caused_by(trc::location!())?;
+
+        let server = self.clone();
+        let job_id_ = job_id.clone();
+        tokio::spawn(async move {
+            match server.import_account(account_id, archive).await {
+                Ok(stats) => {
+                    job.stats = stats;
+                    job.status = JobStatus::Completed;
+                }
+                Err(err) => job.failed(err),
+            }
+
+            finish_job(&server, &job_id_, job).await;
+        });
+
+        Ok(job_id)
+    }
+
+    async fn portability_job(&self, job_id: &str) -> trc::Result<Option<PortabilityJob>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_PORTABILITY_JOB,
+                job_id.as_bytes(),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|job| job.deserialize::<PortabilityJob>())
+            .transpose()
+            .caused_by(trc::location!())
+    }
+}
+
+impl PortabilityJob {
+    fn new(account_id: u32, kind: JobKind) -> Self {
+        PortabilityJob {
+            account_id,
+            kind,
+            status: JobStatus::Running,
+            started_at: now(),
+            finished_at: None,
+            stats: ArchiveStats::default(),
+            archive: None,
+            error: None,
+        }
+    }
+
+    fn failed(&mut self, err: trc::Error) {
+        self.error = Some(err.to_string());
+        self.status = JobStatus::Failed;
+        trc::error!(
+            err.account_id(self.account_id)
+                .details("Account portability job failed")
+        );
+    }
+}
+
+async fn finish_job(server: &Server, job_id: &str, mut job: PortabilityJob) {
+    job.finished_at = Some(now());
+
+    // Keep the exported archive until the job expires
+    if let Some(archive) = &job.archive {
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(job.account_id);
+        for hash in &archive.parts {
+            batch.set(
+                BlobOp::Reserve {
+                    hash: hash.clone(),
+                    until: now() + JOB_EXPIRY,
+                },
+                0u32.serialize(),
+            );
+        }
+        if let Err(err) = server.store().write(batch.build_all()).await {
+            job.failed(err);
+        }
+    }
+
+    if let Err(err) = store_job(server, job_id, &job).await {
+        trc::error!(
+            err.account_id(job.account_id)
+                .details("Failed to store account portability job")
+        );
+    }
+}
+
+async fn store_job(server: &Server, job_id: &str, job: &PortabilityJob) -> trc::Result<()> {
+    server
+        .in_memory_store()
+        .key_set(
+            KeyValue::with_prefix(
+                KV_PORTABILITY_JOB,
+                job_id.as_bytes(),
+                Archiver::new(job.clone())
+                    .untrusted()
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            )
+            .expires(JOB_EXPIRY),
+        )
+        .await
+        .caused_by(trc::location!())
+}
+
+// Mailbox paths and DAV resource names are used as archive paths
+pub(crate) fn archive_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.trim() {
+            "" | "." | ".." => "_".to_string(),
+            segment => segment
+                .chars()
+                .map(|ch| {
+                    if ch.is_control() || ch == '\\' {
+                        '_'
+                    } else {
+                        ch
+                    }
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,100 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::config::push_gateway::ApnsConfig;
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use trc::PushSubscriptionEvent;
+
+use super::PushResult;
+
+// Apple Mail only expects the account identifier it registered with, the
+// client then connects to the server to fetch the new messages.
+pub async fn send(
+    config: &ApnsConfig,
+    device_token: &str,
+    aps_account_id: &str,
+    timeout: Duration,
+) -> PushResult {
+    let client = match reqwest::Identity::from_pem(&config.identity).and_then(|identity| {
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .identity(identity)
+            .build()
+    }) {
+        Ok(client) => client,
+        Err(err) => {
+            trc::event!(
+                PushSubscription(PushSubscriptionEvent::Error),
+                Details = "Failed to build APNs client",
+                Reason = err.to_string()
+            );
+            return PushResult::Failed;
+        }
+    };
+
+    let url = format!("{}/3/device/{device_token}", config.url);
+    match client
+        .post(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .header("apns-topic", &config.topic)
+        .header("apns-priority", "10")
+        .body(
+            serde_json::json!({
+                "aps": {
+                    "account-id": aps_account_id,
+                }
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+    {
+        Ok(response) => match response.status() {
+            status if status.is_success() => {
+                trc::event!(PushSubscription(PushSubscriptionEvent::Success), Url = url);
+
+                PushResult::Delivered
+            }
+            StatusCode::GONE => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::NotFound),
+                    Details = "APNs device token is no longer active",
+                    Url = url
+                );
+
+                PushResult::Unregistered
+            }
+            status => {
+                let reason = response.text().await.unwrap_or_default();
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Error),
+                    Details = "APNs request failed",
+                    Url = url,
+                    Code = status.as_u16(),
+                    Reason = reason.clone()
+                );
+
+                if reason.contains("BadDeviceToken") {
+                    PushResult::Unregistered
+                } else {
+                    PushResult::Failed
+                }
+            }
+        },
+        Err(err) => {
+            trc::event!(
+                PushSubscription(PushSubscriptionEvent::Error),
+                Details = "APNs request failed",
+                Url = url,
+                Reason = err.to_string()
+            );
+
+            PushResult::Failed
+        }
+    }
+}
@@ -0,0 +1,205 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use common::config::push_gateway::FcmConfig;
+use email::push::device::PushDevice;
+use reqwest::{
+    StatusCode,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+};
+use serde::Deserialize;
+use trc::PushSubscriptionEvent;
+
+use crate::state_manager::ece::ece_encrypt;
+
+use super::PushResult;
+
+const TOKEN_EXPIRY: u64 = 3600;
+const GRANT_TYPE: &str = "urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer";
+
+// OAuth access token shared by all FCM requests until it expires
+#[derive(Default)]
+pub struct FcmToken(Mutex<Option<(String, Instant)>>);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+pub async fn send(
+    config: &FcmConfig,
+    token: &FcmToken,
+    device: &PushDevice,
+    payload: &str,
+    timeout: Duration,
+) -> PushResult {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .unwrap_or_default();
+    let access_token = match token.get(config, &client).await {
+        Ok(access_token) => access_token,
+        Err(err) => {
+            trc::error!(err.details("Failed to obtain FCM access token"));
+            return PushResult::Failed;
+        }
+    };
+
+    // Data messages are limited to string values, encrypted payloads are
+    // sent base64 encoded.
+    let data = if let Some(keys) = &device.keys {
+        match ece_encrypt(&keys.p256dh, &keys.auth, payload.as_bytes()) {
+            Ok(payload) => serde_json::json!({
+                "encrypted": URL_SAFE_NO_PAD.encode(payload),
+            }),
+            Err(err) => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Error),
+                    Details = "Failed to encrypt FCM payload",
+                    Url = config.url.clone(),
+                    Reason = err
+                );
+                return PushResult::Failed;
+            }
+        }
+    } else {
+        serde_json::json!({
+            "payload": payload,
+        })
+    };
+
+    match client
+        .post(&config.url)
+        .header(AUTHORIZATION, format!("Bearer {access_token}"))
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({
+                "message": {
+                    "token": device.token,
+                    "data": data,
+                    "android": {
+                        "priority": "high",
+                    },
+                }
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+    {
+        Ok(response) => match response.status() {
+            status if status.is_success() => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Success),
+                    Url = config.url.clone()
+                );
+
+                PushResult::Delivered
+            }
+            StatusCode::NOT_FOUND => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::NotFound),
+                    Details = "FCM registration token is no longer valid",
+                    Url = config.url.clone()
+                );
+
+                PushResult::Unregistered
+            }
+            status => {
+                if status == StatusCode::UNAUTHORIZED {
+                    token.clear();
+                }
+
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Error),
+                    Details = "FCM request failed",
+                    Url = config.url.clone(),
+                    Code = status.as_u16(),
+                    Reason = response.text().await.unwrap_or_default()
+                );
+
+                PushResult::Failed
+            }
+        },
+        Err(err) => {
+            trc::event!(
+                PushSubscription(PushSubscriptionEvent::Error),
+                Details = "FCM request failed",
+                Url = config.url.clone(),
+                Reason = err.to_string()
+            );
+
+            PushResult::Failed
+        }
+    }
+}
+
+impl FcmToken {
+    async fn get(&self, config: &FcmConfig, client: &reqwest::Client) -> trc::Result<String> {
+        let cached = self
+            .0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(access_token, _)| access_token.clone());
+        if let Some(access_token) = cached {
+            return Ok(access_token);
+        }
+
+        let response = client
+            .post(&config.token_uri)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(format!(
+                "grant_type={GRANT_TYPE}&assertion={}",
+                config.assertion(TOKEN_EXPIRY)?
+            ))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                trc::PushSubscriptionEvent::Error
+                    .into_err()
+                    .reason(err)
+                    .ctx(trc::Key::Url, config.token_uri.clone())
+            })?
+            .bytes()
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| {
+                serde_json::from_slice::<TokenResponse>(&bytes).map_err(|err| err.to_string())
+            })
+            .map_err(|err| {
+                trc::PushSubscriptionEvent::Error
+                    .into_err()
+                    .reason(err)
+                    .ctx(trc::Key::Url, config.token_uri.clone())
+            })?;
+
+        // Renew the token a minute before it expires
+        let expires = Instant::now()
+            + Duration::from_secs(
+                response
+                    .expires_in
+                    .unwrap_or(TOKEN_EXPIRY)
+                    .saturating_sub(60),
+            );
+        *self.0.lock().unwrap() = Some((response.access_token.clone(), expires));
+
+        Ok(response.access_token)
+    }
+
+    fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
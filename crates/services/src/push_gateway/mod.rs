@@ -0,0 +1,186 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+pub mod apns;
+pub mod fcm;
+pub mod unified_push;
+
+use std::sync::Arc;
+
+use common::{
+    Inner, Server,
+    core::BuildServer,
+    ipc::{PushGatewayEvent, PushPreview},
+};
+use email::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess},
+    push::device::{PushDevice, PushDeviceStore, PushGateway},
+};
+use jmap_proto::types::id::Id;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use trc::AddContext;
+
+use self::fcm::FcmToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushResult {
+    Delivered,
+    Unregistered,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushPayload<'x> {
+    #[serde(rename = "@type")]
+    pub typ: &'static str,
+    pub account_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<&'x str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<&'x str>,
+}
+
+pub trait PushGatewayNotify: Sync + Send {
+    fn push_new_message(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        preview: Option<PushPreview>,
+        fcm_token: &FcmToken,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl PushGatewayNotify for Server {
+    async fn push_new_message(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        preview: Option<PushPreview>,
+        fcm_token: &FcmToken,
+    ) -> trc::Result<()> {
+        let devices = self
+            .push_devices(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        if devices.is_empty() {
+            return Ok(());
+        }
+
+        // Obtain the mailboxes the message was delivered to
+        let mailbox_ids = if devices.iter().any(|d| !d.mailboxes.is_empty()) {
+            self.get_cached_messages(account_id)
+                .await
+                .caused_by(trc::location!())?
+                .email_by_id(&document_id)
+                .map(|item| item.mailboxes.iter().map(|m| m.mailbox_id).collect())
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+
+        for device in devices {
+            if !device.mailboxes.is_empty()
+                && !device.mailboxes.iter().any(|id| mailbox_ids.contains(id))
+            {
+                continue;
+            }
+
+            let result = match &device.gateway {
+                PushGateway::Apns {
+                    account_id: aps_account_id,
+                } => match &self.core.push_gateway.apns {
+                    Some(config) => {
+                        apns::send(
+                            config,
+                            &device.token,
+                            aps_account_id,
+                            self.core.push_gateway.timeout,
+                        )
+                        .await
+                    }
+                    None => continue,
+                },
+                PushGateway::Fcm => match &self.core.push_gateway.fcm {
+                    Some(config) => {
+                        fcm::send(
+                            config,
+                            fcm_token,
+                            &device,
+                            &payload(account_id, &device, preview.as_ref()),
+                            self.core.push_gateway.timeout,
+                        )
+                        .await
+                    }
+                    None => continue,
+                },
+                PushGateway::UnifiedPush if self.core.push_gateway.unified_push => {
+                    unified_push::send(
+                        &device,
+                        &payload(account_id, &device, preview.as_ref()),
+                        self.core.push_gateway.timeout,
+                    )
+                    .await
+                }
+                PushGateway::UnifiedPush => continue,
+            };
+
+            if result == PushResult::Unregistered {
+                self.unregister_push_device(account_id, &device.token)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Previews are only included when they can be encrypted for the device,
+// push gateways never get to see the contents of a message.
+fn payload(account_id: u32, device: &PushDevice, preview: Option<&PushPreview>) -> String {
+    let preview = preview.filter(|_| device.keys.is_some());
+    serde_json::to_string(&PushPayload {
+        typ: "NewMessage",
+        account_id: Id::from(account_id).to_string(),
+        from: preview.and_then(|p| p.from.as_deref()),
+        subject: preview.and_then(|p| p.subject.as_deref()),
+    })
+    .unwrap_or_default()
+}
+
+pub fn spawn_push_gateway(inner: Arc<Inner>, mut event_rx: mpsc::Receiver<PushGatewayEvent>) {
+    tokio::spawn(async move {
+        let fcm_token = Arc::new(FcmToken::default());
+
+        while let Some(event) = event_rx.recv().await {
+            match event {
+                PushGatewayEvent::NewMessage {
+                    account_id,
+                    document_id,
+                    preview,
+                } => {
+                    let server = inner.build_server();
+                    let fcm_token = fcm_token.clone();
+
+                    tokio::spawn(async move {
+                        if let Err(err) = server
+                            .push_new_message(account_id, document_id, preview, &fcm_token)
+                            .await
+                        {
+                            trc::error!(
+                                err.account_id(account_id)
+                                    .details("Failed to send push gateway notification")
+                            );
+                        }
+                    });
+                }
+                PushGatewayEvent::Stop => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,100 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use email::push::device::PushDevice;
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+};
+use trc::PushSubscriptionEvent;
+
+use crate::state_manager::ece::ece_encrypt;
+
+use super::PushResult;
+
+// UnifiedPush distributors accept WebPush requests, the payload is
+// encrypted (RFC 8291) whenever the application registered its keys.
+pub async fn send(device: &PushDevice, payload: &str, timeout: Duration) -> PushResult {
+    let client_builder = reqwest::Client::builder().timeout(timeout);
+
+    #[cfg(feature = "test_mode")]
+    let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+    let mut request = client_builder
+        .build()
+        .unwrap_or_default()
+        .post(device.token.as_str())
+        .header("TTL", "86400")
+        .header("Urgency", "high");
+
+    if let Some(keys) = &device.keys {
+        match ece_encrypt(&keys.p256dh, &keys.auth, payload.as_bytes()) {
+            Ok(body) => {
+                request = request
+                    .header(CONTENT_ENCODING, "aes128gcm")
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(body);
+            }
+            Err(err) => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Error),
+                    Details = "Failed to encrypt UnifiedPush payload",
+                    Url = device.token.clone(),
+                    Reason = err
+                );
+                return PushResult::Failed;
+            }
+        }
+    } else {
+        request = request
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload.to_string());
+    }
+
+    match request.send().await {
+        Ok(response) => match response.status() {
+            status if status.is_success() => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Success),
+                    Url = device.token.clone()
+                );
+
+                PushResult::Delivered
+            }
+            StatusCode::NOT_FOUND | StatusCode::GONE => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::NotFound),
+                    Details = "UnifiedPush endpoint no longer exists",
+                    Url = device.token.clone()
+                );
+
+                PushResult::Unregistered
+            }
+            status => {
+                trc::event!(
+                    PushSubscription(PushSubscriptionEvent::Error),
+                    Details = "UnifiedPush request failed",
+                    Url = device.token.clone(),
+                    Code = status.as_u16()
+                );
+
+                PushResult::Failed
+            }
+        },
+        Err(err) => {
+            trc::event!(
+                PushSubscription(PushSubscriptionEvent::Error),
+                Details = "UnifiedPush request failed",
+                Url = device.token.clone(),
+                Reason = err.to_string()
+            );
+
+            PushResult::Failed
+        }
+    }
+}
@@ -6,11 +6,12 @@
 
 use std::time::Instant;
 
-use common::Server;
+use common::{KV_BAYES_TRAINED, KV_RATE_LIMIT_BAYES, Server};
 use email::message::bayes::EmailBayesTrain;
 use jmap_proto::types::collection::Collection;
 use mail_parser::MessageParser;
-use trc::{SpamEvent, TaskQueueEvent};
+use store::dispatch::lookup::KeyValue;
+use trc::{AddContext, SpamEvent, TaskQueueEvent};
 use utils::BlobHash;
 
 use super::Task;
@@ -22,11 +23,33 @@ pub trait BayesTrainTask: Sync + Send {
         hash: &BlobHash,
         learn_spam: bool,
     ) -> impl Future<Output = bool> + Send;
+
+    fn bayes_train_allowed(
+        &self,
+        task: &Task,
+        hash: &BlobHash,
+        learn_spam: bool,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
 }
 
 impl BayesTrainTask for Server {
     async fn bayes_train(&self, task: &Task, hash: &BlobHash, learn_spam: bool) -> bool {
         let op_start = Instant::now();
+
+        // Skip messages already learned with the same class and throttle training
+        match self.bayes_train_allowed(task, hash, learn_spam).await {
+            Ok(true) => {}
+            Ok(false) => return true,
+            Err(err) => {
+                trc::error!(
+                    err.account_id(task.account_id)
+                        .document_id(task.document_id)
+                        .caused_by(trc::location!())
+                );
+                return false;
+            }
+        }
+
         // Obtain raw message
         if let Ok(Some(raw_message)) = self
             .blob_store()
@@ -61,4 +84,73 @@ impl BayesTrainTask for Server {
             false
         }
     }
+
+    async fn bayes_train_allowed(
+        &self,
+        task: &Task,
+        hash: &BlobHash,
+        learn_spam: bool,
+    ) -> trc::Result<bool> {
+        let Some(config) = &self.core.spam.bayes else {
+            return Ok(false);
+        };
+        let key = bayes_trained_key(task.account_id, hash, learn_spam);
+        if self
+            .in_memory_store()
+            .key_exists(key.clone())
+            .await
+            .caused_by(trc::location!())?
+        {
+            trc::event!(
+                Spam(SpamEvent::TrainDuplicate),
+                AccountId = task.account_id,
+                DocumentId = task.document_id,
+                Details = if learn_spam { "spam" } else { "ham" },
+            );
+            return Ok(false);
+        }
+
+        if let Some(rate) = &config.account_train_rate {
+            if self
+                .in_memory_store()
+                .is_rate_allowed(
+                    KV_RATE_LIMIT_BAYES,
+                    &task.account_id.to_be_bytes(),
+                    rate,
+                    false,
+                )
+                .await
+                .caused_by(trc::location!())?
+                .is_some()
+            {
+                trc::event!(
+                    Spam(SpamEvent::TrainRateLimited),
+                    AccountId = task.account_id,
+                    DocumentId = task.document_id,
+                    Details = if learn_spam { "spam" } else { "ham" },
+                );
+                return Ok(false);
+            }
+        }
+
+        // Remember the class learned, allowing the user to revert a wrong decision
+        self.in_memory_store()
+            .key_delete(bayes_trained_key(task.account_id, hash, !learn_spam))
+            .await
+            .caused_by(trc::location!())?;
+        self.in_memory_store()
+            .key_set(KeyValue::new(key, vec![]).expires(config.account_train_dedup))
+            .await
+            .caused_by(trc::location!())
+            .map(|_| true)
+    }
+}
+
+fn bayes_trained_key(account_id: u32, hash: &BlobHash, learn_spam: bool) -> Vec<u8> {
+    let mut key = Vec::with_capacity(utils::BLOB_HASH_LEN + std::mem::size_of::<u32>() + 2);
+    key.push(KV_BAYES_TRAINED);
+    key.extend_from_slice(&account_id.to_be_bytes());
+    key.push(learn_spam as u8);
+    key.extend_from_slice(hash.as_slice());
+    key
 }
@@ -10,10 +10,11 @@ use common::Server;
 use directory::{Type, backend::internal::manage::ManageDirectory};
 use email::message::{index::IndexMessageText, metadata::MessageMetadata};
 use jmap_proto::types::{collection::Collection, property::Property};
+use nlp::language::Language;
 use store::{
     IterateParams, SerializeInfallible, U32_LEN, ValueKey,
     ahash::AHashMap,
-    fts::index::FtsDocument,
+    fts::{Field, index::FtsDocument},
     roaring::RoaringBitmap,
     write::{BatchBuilder, BlobOp, TaskQueueClass, ValueClass, key::DeserializeBigEndian, now},
 };
@@ -51,6 +52,9 @@ impl FtsIndexTask for Server {
             return false;
         };
 
+        // Messages are stemmed using the account's language when it cannot be detected
+        let language = self.account_language(task.account_id).await;
+
         match self
             .get_archive_by_property(
                 task.account_id,
@@ -63,13 +67,20 @@ impl FtsIndexTask for Server {
             Ok(Some(metadata_)) => {
                 match metadata_.unarchive::<MessageMetadata>() {
                     Ok(metadata) if metadata.blob_hash.0.as_slice() == hash.as_slice() => {
+                        // Extract text from attachments
+                        let attachments = metadata
+                            .extract_attachments(&self.core.storage.extractors, &raw_message)
+                            .await;
+
                         // Index message
-                        let document =
-                            FtsDocument::with_default_language(self.core.jmap.default_language)
-                                .with_account_id(task.account_id)
-                                .with_collection(Collection::Email)
-                                .with_document_id(task.document_id)
-                                .index_message(metadata, &raw_message);
+                        let mut document = FtsDocument::with_default_language(language)
+                            .with_account_id(task.account_id)
+                            .with_collection(Collection::Email)
+                            .with_document_id(task.document_id)
+                            .index_message(metadata, &raw_message);
+                        for text in attachments {
+                            document.index(Field::Attachment, text, Language::Unknown);
+                        }
                         if let Err(err) = self.core.storage.fts.index(document).await {
                             trc::error!(
                                 err.account_id(task.account_id)
@@ -188,15 +188,22 @@ async fn send_imip(
                                             ContentType::new("text/html"),
                                             BodyPart::Text(tpl.body.as_str().into()),
                                         ),
+                                        // Calendar clients such as Outlook only render the
+                                        // invitation when the iCalendar object is an inline
+                                        // alternative (RFC 6047, Section 2.4)
+                                        MimePart::new(
+                                            ContentType::new("text/calendar")
+                                                .attribute("method", itip_message.summary.method())
+                                                .attribute("charset", "utf-8"),
+                                            BodyPart::Text(itip_message.message.as_str().into()),
+                                        ),
                                     ]),
                                 ),
                                 logo.clone(),
                             ]),
                         ),
                         MimePart::new(
-                            ContentType::new("text/calendar")
-                                .attribute("method", itip_message.summary.method())
-                                .attribute("charset", "utf-8"),
+                            ContentType::new("application/ics"),
                             BodyPart::Text(itip_message.message.as_str().into()),
                         )
                         .attachment("event.ics"),
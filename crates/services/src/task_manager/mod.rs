@@ -90,6 +90,7 @@ pub fn spawn_task_manager(inner: Arc<Inner>) {
         shutdown_rx: watch::channel(false).1,
         proxy_networks: vec![],
         span_id_gen: Arc::new(SnowflakeIdGenerator::new()),
+        auth_policy: Default::default(),
     });
 
     for mut rx_index in [rx_index_1, rx_index_2, rx_index_3, rx_index_4] {
@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::{Inner, KV_TLS_TICKET_SECRET, Server, core::BuildServer};
+use store::dispatch::lookup::KeyValue;
+use trc::AddContext;
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+pub trait TlsTicketSecret: Sync + Send {
+    fn sync_tls_ticket_secret(&self) -> impl Future<Output = trc::Result<bool>> + Send;
+}
+
+impl TlsTicketSecret for Server {
+    /// Loads the ticket secret shared by all nodes in the cluster, creating
+    /// it if this is the first node to start. Returns whether the local
+    /// ticket keys changed.
+    async fn sync_tls_ticket_secret(&self) -> trc::Result<bool> {
+        let keys = &self.inner.data.tls_ticket_keys;
+        let store = self.in_memory_store();
+        let secret = match store
+            .key_get::<String>(vec![KV_TLS_TICKET_SECRET])
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(secret) => secret,
+            None => {
+                store
+                    .key_set(KeyValue::new(
+                        vec![KV_TLS_TICKET_SECRET],
+                        STANDARD.encode(keys.generate_secret()).into_bytes(),
+                    ))
+                    .await
+                    .caused_by(trc::location!())?;
+
+                // Read it back in case another node stored its secret concurrently
+                store
+                    .key_get::<String>(vec![KV_TLS_TICKET_SECRET])
+                    .await
+                    .caused_by(trc::location!())?
+                    .unwrap_or_default()
+            }
+        };
+
+        let secret = STANDARD.decode(secret.as_bytes()).map_err(|err| {
+            trc::StoreEvent::DataCorruption
+                .into_err()
+                .reason(err)
+                .details("Invalid TLS ticket secret")
+                .caused_by(trc::location!())
+        })?;
+        keys.set_secret(&secret).ok_or_else(|| {
+            trc::StoreEvent::DataCorruption
+                .into_err()
+                .details("Invalid TLS ticket secret length")
+                .caused_by(trc::location!())
+        })
+    }
+}
+
+pub fn spawn_tls_ticket_sync(inner: Arc<Inner>) {
+    tokio::spawn(async move {
+        loop {
+            let server = inner.build_server();
+            match server.sync_tls_ticket_secret().await {
+                Ok(true) => {
+                    trc::event!(Tls(trc::TlsEvent::TicketSecretSync));
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    trc::error!(err.details("Failed to synchronize TLS ticket secret"));
+                }
+            }
+
+            tokio::time::sleep(SYNC_INTERVAL).await;
+        }
+    });
+}
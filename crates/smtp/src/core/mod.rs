@@ -26,7 +26,7 @@ use smtp_proto::request::receiver::{
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
-    inbound::auth::SaslToken,
+    inbound::{auth::SaslToken, sending::RecipientAllowance},
     queue::{DomainPart, QueueId},
 };
 
@@ -78,8 +78,11 @@ pub struct SessionData {
 
     pub mail_from: Option<SessionAddress>,
     pub rcpt_to: Vec<SessionAddress>,
+    pub list_expansions: Vec<ListExpansion>,
+    pub rcpt_expanded: usize,
     pub rcpt_errors: usize,
     pub rcpt_oks: usize,
+    pub rcpt_allowance: Option<RecipientAllowance>,
     pub message: Vec<u8>,
 
     pub authenticated_as: Option<Arc<AccessToken>>,
@@ -97,6 +100,8 @@ pub struct SessionData {
     pub spf_ehlo: Option<SpfOutput>,
     pub spf_mail_from: Option<SpfOutput>,
     pub dnsbl_error: Option<Vec<u8>>,
+
+    pub proxy: Option<tokio::net::TcpStream>,
 }
 
 #[derive(Clone, Debug)]
@@ -108,6 +113,14 @@ pub struct SessionAddress {
     pub dsn_info: Option<String>,
 }
 
+// Members of a mailing list recipient, expanded by the list engine once
+// the message has been received.
+#[derive(Clone, Debug)]
+pub struct ListExpansion {
+    pub address_lcase: String,
+    pub members: Vec<String>,
+}
+
 #[derive(Debug, Default)]
 pub struct SessionParameters {
     // Global parameters
@@ -127,6 +140,8 @@ pub struct SessionParameters {
     pub rcpt_errors_max: usize,
     pub rcpt_errors_wait: Duration,
     pub rcpt_max: usize,
+    pub rcpt_max_expansion: usize,
+    pub rcpt_max_expansion_depth: usize,
     pub rcpt_dsn: bool,
     pub can_expn: bool,
     pub can_vrfy: bool,
@@ -159,11 +174,14 @@ impl SessionData {
             helo_domain: String::new(),
             mail_from: None,
             rcpt_to: Vec::new(),
+            list_expansions: Vec::new(),
+            rcpt_expanded: 0,
             authenticated_as: None,
             priority: 0,
             valid_until: Instant::now(),
             rcpt_errors: 0,
             rcpt_oks: 0,
+            rcpt_allowance: None,
             message: Vec::with_capacity(0),
             auth_errors: 0,
             messages_sent: 0,
@@ -174,6 +192,7 @@ impl SessionData {
             spf_ehlo: None,
             spf_mail_from: None,
             dnsbl_error: None,
+            proxy: None,
         }
     }
 }
@@ -237,6 +256,8 @@ impl Session<common::listener::stream::NullIo> {
                 rcpt_errors_max: Default::default(),
                 rcpt_errors_wait: Default::default(),
                 rcpt_max: Default::default(),
+                rcpt_max_expansion: Default::default(),
+                rcpt_max_expansion_depth: Default::default(),
                 rcpt_dsn: Default::default(),
                 max_message_size: Default::default(),
                 iprev: VerifyStrategy::Disable,
@@ -283,8 +304,11 @@ impl SessionData {
             helo_domain: "localhost".into(),
             mail_from,
             rcpt_to,
+            list_expansions: Vec::new(),
+            rcpt_expanded: 0,
             rcpt_errors: 0,
             rcpt_oks: 0,
+            rcpt_allowance: None,
             message,
             authenticated_as: Some(authenticated_as),
             auth_errors: 0,
@@ -298,6 +322,7 @@ impl SessionData {
             spf_ehlo: None,
             spf_mail_from: None,
             dnsbl_error: None,
+            proxy: None,
         }
     }
 }
@@ -140,6 +140,16 @@ impl<T: SessionStream> Session<T> {
             .eval_if(&rc.max_recipients, self, self.data.session_id)
             .await
             .unwrap_or(100);
+        self.params.rcpt_max_expansion = self
+            .server
+            .eval_if(&rc.max_expansion, self, self.data.session_id)
+            .await
+            .unwrap_or(1000);
+        self.params.rcpt_max_expansion_depth = self
+            .server
+            .eval_if(&rc.max_expansion_depth, self, self.data.session_id)
+            .await
+            .unwrap_or(5);
         self.params.rcpt_dsn = self
             .server
             .eval_if(
@@ -4,22 +4,38 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::future::Future;
+
 use common::{
-    KV_RATE_LIMIT_SMTP, ThrottleKey,
+    KV_RATE_LIMIT_SMTP, RateLimiterTokens, Server, ThrottleKey,
     config::smtp::*,
     expr::{functions::ResolveVariable, *},
     listener::SessionStream,
 };
 use queue::QueueQuota;
-use trc::SmtpEvent;
+use store::{InMemoryStore, dispatch::lookup::KeyValue, write::now};
+use trc::{AddContext, SmtpEvent};
 use utils::config::Rate;
 
 use super::Session;
 
+// Expired token reservations are purged once the cache grows past this size
+const MAX_CACHED_TOKENS: usize = 4096;
+
 pub trait NewKey: Sized {
     fn new_key(&self, e: &impl ResolveVariable, context: &str) -> ThrottleKey;
 }
 
+pub trait SmtpRateLimiter: Sync + Send {
+    /// Consumes a token from the rate limiter, returning the number of seconds
+    /// until the next refill when the rate has been exceeded.
+    fn check_rate_limit(
+        &self,
+        limiter: &QueueRateLimiter,
+        key: &ThrottleKey,
+    ) -> impl Future<Output = trc::Result<Option<u64>>> + Send;
+}
+
 impl NewKey for QueueQuota {
     fn new_key(&self, e: &impl ResolveVariable, _: &str) -> ThrottleKey {
         let mut hasher = blake3::Hasher::new();
@@ -139,6 +155,116 @@ impl NewKey for QueueRateLimiter {
     }
 }
 
+impl SmtpRateLimiter for Server {
+    async fn check_rate_limit(
+        &self,
+        limiter: &QueueRateLimiter,
+        key: &ThrottleKey,
+    ) -> trc::Result<Option<u64>> {
+        let (store, batch) = match &limiter.scope {
+            RateLimiterScope::Cluster { store, batch: 1 } => {
+                return in_memory_store(self, store.as_deref())
+                    .is_rate_allowed(KV_RATE_LIMIT_SMTP, key.as_ref(), &limiter.rate, false)
+                    .await;
+            }
+            RateLimiterScope::Cluster { store, batch } => {
+                (Some(in_memory_store(self, store.as_deref())), *batch)
+            }
+            RateLimiterScope::Node => (None, 0),
+        };
+
+        let now = now();
+        let period = limiter.rate.period.as_secs();
+        let window = now / period;
+        let expires = (window + 1) * period;
+
+        // Node scoped limiters start each window with the full allowance, while
+        // cluster scoped ones hand out the tokens reserved by a previous roundtrip
+        let refill = if store.is_none() {
+            limiter.rate.requests
+        } else {
+            0
+        };
+        if take_cached_token(self, key, window, expires, refill) {
+            return Ok(None);
+        }
+        let Some(store) = store else {
+            return Ok(Some(expires - now));
+        };
+
+        // Reserve a new batch of tokens from the shared counter
+        let mut bucket = Vec::with_capacity(key.hash.len() + std::mem::size_of::<u64>());
+        bucket.extend_from_slice(key.as_ref());
+        bucket.extend_from_slice(&window.to_be_bytes());
+        let reserved = store
+            .counter_incr(
+                KeyValue::with_prefix(KV_RATE_LIMIT_SMTP, bucket, batch as i64)
+                    .expires(expires - now),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        match granted_tokens(reserved, batch, limiter.rate.requests) {
+            0 => Ok(Some(expires - now)),
+            granted => {
+                if granted > 1 {
+                    self.inner.data.rate_limiter_tokens.lock().insert(
+                        key.clone(),
+                        RateLimiterTokens {
+                            window,
+                            tokens: granted - 1,
+                            expires,
+                        },
+                    );
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn in_memory_store<'x>(server: &'x Server, id: Option<&str>) -> &'x InMemoryStore {
+    id.and_then(|id| server.get_in_memory_store(id))
+        .unwrap_or_else(|| server.in_memory_store())
+}
+
+fn take_cached_token(
+    server: &Server,
+    key: &ThrottleKey,
+    window: u64,
+    expires: u64,
+    refill: u64,
+) -> bool {
+    let mut cache = server.inner.data.rate_limiter_tokens.lock();
+    if cache.len() >= MAX_CACHED_TOKENS && !cache.contains_key(key) {
+        let now = now();
+        cache.retain(|_, tokens| tokens.expires > now);
+    }
+
+    let tokens = cache.entry(key.clone()).or_default();
+    if tokens.window != window {
+        *tokens = RateLimiterTokens {
+            window,
+            tokens: refill,
+            expires,
+        };
+    }
+    if tokens.tokens > 0 {
+        tokens.tokens -= 1;
+        true
+    } else {
+        false
+    }
+}
+
+// Returns how many tokens of a batch reservation fall within the rate limit,
+// given the value of the shared counter after the reservation was made.
+fn granted_tokens(reserved: i64, batch: u64, requests: u64) -> u64 {
+    let start = (reserved.max(0) as u64).saturating_sub(batch);
+    requests.saturating_sub(start).min(batch)
+}
+
 impl<T: SessionStream> Session<T> {
     pub async fn is_allowed(&mut self) -> bool {
         let throttles = if !self.data.rcpt_to.is_empty() {
@@ -174,14 +300,7 @@ impl<T: SessionStream> Session<T> {
                 let key = t.new_key(self, "inbound");
 
                 // Check rate
-                match self
-                    .server
-                    .core
-                    .storage
-                    .lookup
-                    .is_rate_allowed(KV_RATE_LIMIT_SMTP, key.hash.as_slice(), &t.rate, false)
-                    .await
-                {
+                match self.server.check_rate_limit(t, &key).await {
                     Ok(Some(_)) => {
                         trc::event!(
                             Smtp(SmtpEvent::RateLimitExceeded),
@@ -241,3 +360,20 @@ impl<T: SessionStream> Session<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::granted_tokens;
+
+    #[test]
+    fn batch_reservation() {
+        // The whole batch fits within the limit
+        assert_eq!(granted_tokens(10, 10, 100), 10);
+        assert_eq!(granted_tokens(100, 10, 100), 10);
+        // Only part of the batch is left
+        assert_eq!(granted_tokens(105, 10, 100), 5);
+        // The limit was already reached by other nodes
+        assert_eq!(granted_tokens(110, 10, 100), 0);
+        assert_eq!(granted_tokens(250, 10, 100), 0);
+    }
+}
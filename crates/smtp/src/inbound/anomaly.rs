@@ -0,0 +1,302 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    KV_SUBMISSION_BASELINE, KV_SUBMISSION_LOCATION, KV_SUBMISSION_VOLUME,
+    config::smtp::session::{AccountAnomaly, AnomalyAction, VolumeBaseline},
+    listener::SessionStream,
+};
+use mail_builder::{
+    MessageBuilder,
+    headers::{HeaderType, address::Address},
+};
+use store::{dispatch::lookup::KeyValue, write::now};
+use trc::AddContext;
+
+use crate::{core::Session, inbound::FilterResponse, reporting::SmtpReporting};
+
+// Baselines are stored as fixed point numbers with two decimals
+const BASELINE_SCALE: i64 = 100;
+
+impl<T: SessionStream> Session<T> {
+    /// Looks for signs of a compromised account on authenticated submissions,
+    /// returning the reason to hold the message for review.
+    pub async fn check_account_anomaly(&mut self) -> Result<Option<String>, FilterResponse> {
+        let Some(anomaly) = &self.server.core.smtp.session.anomaly else {
+            return Ok(None);
+        };
+        let Some(account_id) = self
+            .data
+            .authenticated_as
+            .as_ref()
+            .map(|token| token.primary_id())
+        else {
+            return Ok(None);
+        };
+        if !self
+            .server
+            .eval_if(&anomaly.enable, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
+
+        let reason = match self.detect_account_anomaly(anomaly, account_id).await {
+            Ok(Some(reason)) => reason,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                trc::error!(
+                    err.span_id(self.data.session_id)
+                        .details("Failed to run account anomaly detection")
+                );
+                return Ok(None);
+            }
+        };
+
+        trc::event!(
+            Security(trc::SecurityEvent::AccountAnomaly),
+            SpanId = self.data.session_id,
+            AccountName = self.authenticated_as().unwrap_or_default().to_string(),
+            RemoteIp = self.data.remote_ip,
+            Reason = reason.clone(),
+        );
+
+        if anomaly.actions.contains(&AnomalyAction::Notify) && !anomaly.notify.is_empty() {
+            self.notify_account_anomaly(anomaly, &reason).await;
+        }
+
+        if anomaly.actions.contains(&AnomalyAction::Reauth) {
+            // Drop the authentication so the client has to sign in again
+            self.data.authenticated_as = None;
+            Err(FilterResponse {
+                message:
+                    "530 5.7.0 Unusual account activity detected, please authenticate again.\r\n"
+                        .into(),
+                disconnect: false,
+            })
+        } else if anomaly.actions.contains(&AnomalyAction::Hold) {
+            Ok(Some(format!("Suspicious account activity: {reason}")))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn detect_account_anomaly(
+        &self,
+        anomaly: &AccountAnomaly,
+        account_id: u32,
+    ) -> trc::Result<Option<String>> {
+        let store = self.server.in_memory_store();
+        let key = account_id.to_be_bytes();
+        let now = now();
+        let mut reasons = Vec::new();
+
+        // Submissions from a different country shortly after the previous one
+        if let (Some(window), Some(country)) =
+            (anomaly.travel_window, &self.data.asn_geo_data.country)
+        {
+            let last_location = store
+                .key_get::<String>(KeyValue::<()>::build_key(KV_SUBMISSION_LOCATION, key))
+                .await
+                .caused_by(trc::location!())?;
+            match last_location.as_deref().and_then(parse_location) {
+                Some((last_seen, last_country))
+                    if last_country != country.as_str()
+                        && now.saturating_sub(last_seen) < window.as_secs() =>
+                {
+                    // The last known location is kept until the window expires,
+                    // so that further submissions from the new country are flagged too
+                    reasons.push(format!(
+                        "submission from {country} {} minutes after a submission from {last_country}",
+                        now.saturating_sub(last_seen) / 60
+                    ));
+                }
+                _ => {
+                    store
+                        .key_set(
+                            KeyValue::with_prefix(
+                                KV_SUBMISSION_LOCATION,
+                                key,
+                                format!("{now}:{country}").into_bytes(),
+                            )
+                            .expires(window.as_secs()),
+                        )
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+        }
+
+        // Recipients sent within the current period compared to the historical baseline
+        if let Some(volume) = &anomaly.volume {
+            let recipients = self.data.rcpt_to.len() as i64;
+            let period = volume.period.as_secs();
+            let bucket = now / period;
+            let count = store
+                .counter_incr(
+                    KeyValue::with_prefix(
+                        KV_SUBMISSION_VOLUME,
+                        volume_key(key, bucket),
+                        recipients,
+                    )
+                    .expires(period * 2),
+                    true,
+                )
+                .await
+                .caused_by(trc::location!())?;
+            let mut baseline = store
+                .key_get::<i64>(KeyValue::<()>::build_key(KV_SUBMISSION_BASELINE, key))
+                .await
+                .caused_by(trc::location!())?
+                .unwrap_or_default();
+
+            // Fold the previous period into the baseline once a new one starts
+            if count == recipients {
+                let previous = store
+                    .counter_get(KeyValue::<()>::build_key(
+                        KV_SUBMISSION_VOLUME,
+                        volume_key(key, bucket.saturating_sub(1)),
+                    ))
+                    .await
+                    .caused_by(trc::location!())?;
+                baseline = update_baseline(volume, baseline, previous);
+                store
+                    .key_set(
+                        KeyValue::with_prefix(
+                            KV_SUBMISSION_BASELINE,
+                            key,
+                            baseline.to_be_bytes().to_vec(),
+                        )
+                        .expires(period * volume.samples * 2),
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+
+            let limit = volume_limit(volume, baseline);
+            if count > limit {
+                reasons.push(format!(
+                    "{count} recipients within {} minutes exceeds the limit of {limit}",
+                    period / 60
+                ));
+            }
+        }
+
+        Ok(if !reasons.is_empty() {
+            Some(reasons.join(", "))
+        } else {
+            None
+        })
+    }
+
+    async fn notify_account_anomaly(&self, anomaly: &AccountAnomaly, reason: &str) {
+        let account = self.authenticated_as().unwrap_or_default();
+        let mut body = format!(
+            "Suspicious activity was detected on the account {account}: {reason}.\r\n\r\n",
+        );
+        body.push_str(&format!("Remote address: {}\r\n", self.data.remote_ip));
+        if let Some(country) = &self.data.asn_geo_data.country {
+            body.push_str(&format!("Country: {country}\r\n"));
+        }
+        if let Some(from) = &self.data.mail_from {
+            body.push_str(&format!("Envelope sender: <{}>\r\n", from.address));
+        }
+        body.push_str(&format!("Recipients: {}\r\n", self.data.rcpt_to.len()));
+        body.push_str(&format!(
+            "Action: {}\r\n",
+            if anomaly.actions.contains(&AnomalyAction::Reauth) {
+                "rejected, re-authentication required"
+            } else if anomaly.actions.contains(&AnomalyAction::Hold) {
+                "quarantined"
+            } else {
+                "delivered"
+            }
+        ));
+
+        let report = MessageBuilder::new()
+            .from(anomaly.notify_from.as_str())
+            .header(
+                "To",
+                HeaderType::Address(Address::new_list(
+                    anomaly
+                        .notify
+                        .iter()
+                        .map(|to| Address::from(to.as_str()))
+                        .collect(),
+                )),
+            )
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .subject(format!("Suspicious activity on account {account}"))
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        self.server
+            .send_autogenerated(
+                anomaly.notify_from.clone(),
+                anomaly.notify.iter().cloned(),
+                report,
+                None,
+                self.data.session_id,
+            )
+            .await;
+    }
+}
+
+fn parse_location(value: &str) -> Option<(u64, &str)> {
+    value
+        .split_once(':')
+        .and_then(|(last_seen, country)| Some((last_seen.parse().ok()?, country)))
+}
+
+fn volume_key(account_id: [u8; 4], bucket: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(12);
+    key.extend_from_slice(&account_id);
+    key.extend_from_slice(&bucket.to_be_bytes());
+    key
+}
+
+/// Exponentially weighted moving average of the recipients sent per period.
+fn update_baseline(volume: &VolumeBaseline, baseline: i64, previous: i64) -> i64 {
+    baseline + (previous * BASELINE_SCALE - baseline) / volume.samples as i64
+}
+
+fn volume_limit(volume: &VolumeBaseline, baseline: i64) -> i64 {
+    ((baseline as f64 / BASELINE_SCALE as f64 * volume.factor).ceil() as i64)
+        .max(volume.min_volume as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn volume_baseline() {
+        let volume = VolumeBaseline {
+            period: Duration::from_secs(3600),
+            min_volume: 10,
+            factor: 5.0,
+            samples: 4,
+        };
+
+        let mut baseline = 0;
+        for previous in [8, 8, 8, 8, 8, 8, 8, 8] {
+            baseline = update_baseline(&volume, baseline, previous);
+        }
+        assert_eq!(baseline, 719);
+        assert_eq!(volume_limit(&volume, baseline), 36);
+        assert_eq!(volume_limit(&volume, 0), 10);
+
+        // Idle periods slowly bring the baseline back down
+        assert_eq!(update_baseline(&volume, baseline, 0), 540);
+
+        assert_eq!(parse_location("1700000000:ES"), Some((1700000000, "ES")));
+        assert_eq!(parse_location("ES"), None);
+    }
+}
@@ -0,0 +1,322 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{borrow::Cow, time::Instant};
+
+use common::{
+    config::smtp::session::{
+        Antivirus, AntivirusAction, AntivirusConnection, ScanKey, ScanVerdict,
+    },
+    listener::SessionStream,
+};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use trc::SmtpEvent;
+
+use crate::{core::Session, inbound::FilterResponse};
+
+const MAX_RESPONSE_LEN: usize = 4096;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn run_antivirus(&self, raw_message: &[u8]) -> Result<Vec<u8>, FilterResponse> {
+        let scanners = &self.server.core.smtp.session.antivirus;
+        let mut headers = Vec::new();
+        if scanners.is_empty() {
+            return Ok(headers);
+        }
+
+        for scanner in scanners {
+            if !self
+                .server
+                .eval_if(&scanner.enable, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            if raw_message.len() > scanner.max_size {
+                trc::event!(
+                    Smtp(SmtpEvent::VirusScanSkipped),
+                    SpanId = self.data.session_id,
+                    Id = scanner.id.clone(),
+                    Size = raw_message.len(),
+                    Limit = scanner.max_size,
+                );
+                continue;
+            }
+
+            // Verdicts are cached by the hash of the scanned message, so that the same
+            // message sent to multiple recipients or in separate transactions is only
+            // scanned once.
+            let time = Instant::now();
+            let cache_key = scanner.cache_ttl.map(|_| {
+                ScanKey(
+                    Sha256::new()
+                        .chain_update(scanner.id.as_bytes())
+                        .chain_update([0u8])
+                        .chain_update(raw_message)
+                        .finalize()
+                        .into(),
+                )
+            });
+            let verdict = match cache_key
+                .as_ref()
+                .and_then(|key| self.server.inner.cache.antivirus.get(key))
+            {
+                Some(verdict) => Ok(verdict),
+                None => {
+                    let result = scanner.scan(raw_message).await;
+                    if let (Ok(verdict), Some(key), Some(ttl)) =
+                        (&result, cache_key, scanner.cache_ttl)
+                    {
+                        self.server
+                            .inner
+                            .cache
+                            .antivirus
+                            .insert(key, verdict.clone(), ttl);
+                    }
+                    result
+                }
+            };
+
+            let (action, status, response) = match verdict {
+                Ok(ScanVerdict::Clean) => continue,
+                Ok(ScanVerdict::Infected(name)) => {
+                    trc::event!(
+                        Smtp(SmtpEvent::VirusFound),
+                        SpanId = self.data.session_id,
+                        Id = scanner.id.clone(),
+                        Details = name.clone(),
+                        Elapsed = time.elapsed(),
+                    );
+
+                    (
+                        scanner.action_infected,
+                        format!("Infected ({name})"),
+                        Cow::Owned(format!(
+                            "554 5.7.1 Message rejected: virus detected ({name}).\r\n"
+                        )),
+                    )
+                }
+                Err(err) => {
+                    trc::event!(
+                        Smtp(SmtpEvent::VirusScanError),
+                        SpanId = self.data.session_id,
+                        Id = scanner.id.clone(),
+                        Reason = err,
+                        Elapsed = time.elapsed(),
+                    );
+
+                    (
+                        scanner.action_error,
+                        "Error".to_string(),
+                        Cow::Borrowed(
+                            "554 5.7.1 Message rejected: unable to scan for viruses.\r\n",
+                        ),
+                    )
+                }
+            };
+
+            match action {
+                AntivirusAction::Accept => {}
+                AntivirusAction::Reject => {
+                    return Err(FilterResponse {
+                        message: response,
+                        disconnect: false,
+                    });
+                }
+                AntivirusAction::TempFail => return Err(FilterResponse::temp_fail()),
+                AntivirusAction::Discard => return Err(FilterResponse::accept()),
+                AntivirusAction::Quarantine => {
+                    headers.extend_from_slice(b"X-Quarantine: true\r\n");
+                    write_status(&mut headers, &status);
+                }
+                AntivirusAction::Tag => {
+                    write_status(&mut headers, &status);
+                }
+            }
+        }
+
+        Ok(headers)
+    }
+}
+
+fn write_status(headers: &mut Vec<u8>, status: &str) {
+    headers.extend_from_slice(b"X-Virus-Status: ");
+    headers.extend(
+        status
+            .bytes()
+            .filter(|ch| ch.is_ascii_graphic() || *ch == b' '),
+    );
+    headers.extend_from_slice(b"\r\n");
+}
+
+trait ClamdScan {
+    async fn scan(&self, raw_message: &[u8]) -> Result<ScanVerdict, String>;
+    async fn connect(&self) -> Result<AntivirusConnection, String>;
+    async fn scan_with(
+        &self,
+        conn: AntivirusConnection,
+        raw_message: &[u8],
+    ) -> Result<ScanVerdict, String>;
+}
+
+impl ClamdScan for Antivirus {
+    async fn scan(&self, raw_message: &[u8]) -> Result<ScanVerdict, String> {
+        // Reuse an idle session if available, clamd might have closed it in the
+        // meantime so retry once on a fresh connection before giving up.
+        let pooled = {
+            let mut pool = self.pool.lock();
+            let mut pooled = None;
+            while let Some(conn) = pool.pop() {
+                if conn.last_used.elapsed() < self.pool_idle_timeout {
+                    pooled = Some(conn);
+                    break;
+                }
+            }
+            pooled
+        };
+        if let Some(conn) = pooled {
+            if let Ok(verdict) = self.scan_with(conn, raw_message).await {
+                return Ok(verdict);
+            }
+        }
+
+        let conn = self.connect().await?;
+        self.scan_with(conn, raw_message).await
+    }
+
+    async fn connect(&self) -> Result<AntivirusConnection, String> {
+        tokio::time::timeout(self.timeout_connect, async {
+            let mut last_err = "No addresses available".to_string();
+            for addr in &self.addrs {
+                match TcpStream::connect(addr).await {
+                    Ok(mut stream) => {
+                        // Switch to session mode so the connection can be reused.
+                        stream
+                            .write_all(b"zIDSESSION\0")
+                            .await
+                            .map_err(|err| format!("Failed to start clamd session: {err}"))?;
+                        return Ok(AntivirusConnection {
+                            stream,
+                            next_id: 1,
+                            last_used: Instant::now(),
+                        });
+                    }
+                    Err(err) => {
+                        last_err = format!("Failed to connect to {addr}: {err}");
+                    }
+                }
+            }
+            Err(last_err)
+        })
+        .await
+        .map_err(|_| "Connection to clamd timed out".to_string())?
+    }
+
+    async fn scan_with(
+        &self,
+        mut conn: AntivirusConnection,
+        raw_message: &[u8],
+    ) -> Result<ScanVerdict, String> {
+        let response = tokio::time::timeout(self.timeout_scan, async {
+            let stream = &mut conn.stream;
+            stream.write_all(b"zINSTREAM\0").await?;
+            for chunk in raw_message.chunks(self.chunk_size) {
+                stream
+                    .write_all(&(chunk.len() as u32).to_be_bytes())
+                    .await?;
+                stream.write_all(chunk).await?;
+            }
+            stream.write_all(&[0, 0, 0, 0]).await?;
+            stream.flush().await?;
+
+            let mut response = Vec::with_capacity(128);
+            let mut buf = [0u8; 256];
+            loop {
+                let bytes_read = stream.read(&mut buf).await?;
+                if bytes_read == 0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+                }
+                let buf = &buf[..bytes_read];
+                if let Some(pos) = buf.iter().position(|&ch| ch == 0) {
+                    response.extend_from_slice(&buf[..pos]);
+                    break;
+                }
+                response.extend_from_slice(buf);
+                if response.len() > MAX_RESPONSE_LEN {
+                    return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
+                }
+            }
+
+            Ok(response)
+        })
+        .await
+        .map_err(|_| "Scan request to clamd timed out".to_string())?
+        .map_err(|err| format!("Failed to communicate with clamd: {err}"))?;
+
+        let verdict = parse_clamd_response(&String::from_utf8_lossy(&response), conn.next_id)?;
+
+        // Return the session to the pool
+        conn.next_id += 1;
+        conn.last_used = Instant::now();
+        let mut pool = self.pool.lock();
+        if pool.len() < self.pool_size {
+            pool.push(conn);
+        }
+
+        Ok(verdict)
+    }
+}
+
+fn parse_clamd_response(response: &str, expected_id: u32) -> Result<ScanVerdict, String> {
+    // Responses in session mode are prefixed with the request id, i.e. "1: stream: OK"
+    let (id, result) = response
+        .split_once(": ")
+        .ok_or_else(|| format!("Invalid clamd response {response:?}"))?;
+    if id.trim().parse::<u32>().ok() != Some(expected_id) {
+        return Err(format!("Unexpected clamd response {response:?}"));
+    }
+    let result = result
+        .trim()
+        .strip_prefix("stream:")
+        .map(|result| result.trim())
+        .unwrap_or(result);
+
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(name) = result.strip_suffix(" FOUND") {
+        Ok(ScanVerdict::Infected(name.trim().to_string()))
+    } else {
+        Err(format!("clamd returned {result:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::config::smtp::session::ScanVerdict;
+
+    use super::parse_clamd_response;
+
+    #[test]
+    fn parse_clamd_responses() {
+        assert_eq!(
+            parse_clamd_response("1: stream: OK", 1),
+            Ok(ScanVerdict::Clean)
+        );
+        assert_eq!(
+            parse_clamd_response("7: stream: Win.Test.EICAR_HDB-1 FOUND", 7),
+            Ok(ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string()))
+        );
+        assert!(parse_clamd_response("2: stream: OK", 1).is_err());
+        assert!(parse_clamd_response("1: INSTREAM size limit exceeded. ERROR", 1).is_err());
+        assert!(parse_clamd_response("garbage", 1).is_err());
+    }
+}
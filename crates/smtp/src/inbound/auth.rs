@@ -4,58 +4,121 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::sync::Arc;
+
 use common::{
     auth::{
-        AuthRequest,
+        AccessToken, AuthRequest,
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
+        scram::{ScramSession, ScramStep},
     },
+    config::smtp::session::Mechanism,
     listener::SessionStream,
 };
 
-use directory::Permission;
+use directory::{Permission, core::app_password::AuthProtocol};
+use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use smtp_proto::{AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2, IntoString};
+use smtp_proto::{
+    AUTH_EXTERNAL, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_SCRAM_SHA_256,
+    AUTH_SCRAM_SHA_256_PLUS, AUTH_XOAUTH2, IntoString,
+};
 use trc::{AuthEvent, SmtpEvent};
 
 use crate::core::Session;
 
 pub struct SaslToken {
     mechanism: u64,
-    credentials: Credentials<String>,
+    state: SaslState,
+}
+
+enum SaslState {
+    Credentials(Credentials<String>),
+    Scram {
+        session: Option<ScramSession>,
+        plus_advertised: bool,
+    },
+    External {
+        challenged: bool,
+    },
 }
 
 impl SaslToken {
-    pub fn from_mechanism(mechanism: u64) -> Option<SaslToken> {
-        match mechanism {
-            AUTH_PLAIN | AUTH_LOGIN => SaslToken {
-                mechanism,
-                credentials: Credentials::Plain {
-                    username: String::new(),
-                    secret: String::new(),
-                },
-            }
-            .into(),
-            AUTH_OAUTHBEARER | AUTH_XOAUTH2 => SaslToken {
-                mechanism,
-                credentials: Credentials::OAuthBearer {
-                    token: String::new(),
-                },
-            }
-            .into(),
-            _ => None,
-        }
+    pub fn from_mechanism(mechanism: u64, advertised: u64) -> Option<SaslToken> {
+        let state = match mechanism & advertised {
+            AUTH_PLAIN | AUTH_LOGIN => SaslState::Credentials(Credentials::Plain {
+                username: String::new(),
+                secret: String::new(),
+            }),
+            AUTH_OAUTHBEARER | AUTH_XOAUTH2 => SaslState::Credentials(Credentials::OAuthBearer {
+                token: String::new(),
+            }),
+            AUTH_SCRAM_SHA_256 | AUTH_SCRAM_SHA_256_PLUS => SaslState::Scram {
+                session: None,
+                plus_advertised: advertised & AUTH_SCRAM_SHA_256_PLUS != 0,
+            },
+            AUTH_EXTERNAL => SaslState::External { challenged: false },
+            _ => return None,
+        };
+
+        Some(SaslToken {
+            mechanism: mechanism & advertised,
+            state,
+        })
     }
 }
 
 impl<T: SessionStream> Session<T> {
+    pub async fn auth_mechanisms(&self) -> u64 {
+        let mut mechanisms: u64 = self
+            .server
+            .eval_if::<Mechanism, _>(
+                &self.server.core.smtp.session.auth.mechanisms,
+                self,
+                self.data.session_id,
+            )
+            .await
+            .unwrap_or_default()
+            .into();
+
+        // Do not advertise plain text mechanisms unless allowed by the listener policy,
+        // bearer tokens are sent in the clear, same as PLAIN credentials
+        if !self.instance.is_plain_auth_allowed(&self.stream, true) {
+            mechanisms &= !(AUTH_PLAIN | AUTH_LOGIN | AUTH_OAUTHBEARER | AUTH_XOAUTH2);
+        }
+
+        // Channel binding requires TLS 1.3
+        if mechanisms & AUTH_SCRAM_SHA_256_PLUS != 0 && self.stream.tls_channel_binding().is_none()
+        {
+            mechanisms &= !AUTH_SCRAM_SHA_256_PLUS;
+        }
+
+        // EXTERNAL requires a client certificate validated during the handshake
+        if mechanisms & AUTH_EXTERNAL != 0 && self.stream.tls_client_certificate().is_none() {
+            mechanisms &= !AUTH_EXTERNAL;
+        }
+
+        mechanisms
+    }
+
     pub async fn handle_sasl_response(
         &mut self,
         token: &mut SaslToken,
         response: &[u8],
     ) -> Result<bool, ()> {
+        let credentials = match &mut token.state {
+            SaslState::Credentials(credentials) => credentials,
+            SaslState::Scram { .. } => {
+                return self.handle_scram_response(token, response).await;
+            }
+            SaslState::External { .. } => {
+                return self.handle_external_response(token, response).await;
+            }
+        };
+
         if response.is_empty() {
-            match (token.mechanism, &token.credentials) {
+            match (token.mechanism, &*credentials) {
                 (AUTH_PLAIN | AUTH_XOAUTH2 | AUTH_OAUTHBEARER, _) => {
                     self.write(b"334 Go ahead.\r\n").await?;
                     return Ok(true);
@@ -69,7 +132,7 @@ impl<T: SessionStream> Session<T> {
                 _ => (),
             }
         } else if let Some(response) = base64_decode(response) {
-            match (token.mechanism, &mut token.credentials) {
+            match (token.mechanism, &mut *credentials) {
                 (AUTH_PLAIN, _) => {
                     if let Some(credentials) = sasl_decode_challenge_plain(&response) {
                         return self.authenticate(credentials).await;
@@ -82,8 +145,7 @@ impl<T: SessionStream> Session<T> {
                         Ok(true)
                     } else {
                         *secret = response.into_string();
-                        self.authenticate(std::mem::take(&mut token.credentials))
-                            .await
+                        self.authenticate(std::mem::take(credentials)).await
                     };
                 }
                 (AUTH_OAUTHBEARER | AUTH_XOAUTH2, _) => {
@@ -98,6 +160,138 @@ impl<T: SessionStream> Session<T> {
         self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await
     }
 
+    async fn handle_scram_response(
+        &mut self,
+        token: &mut SaslToken,
+        response: &[u8],
+    ) -> Result<bool, ()> {
+        let Some(directory) = self.params.auth_directory.clone() else {
+            trc::event!(
+                Smtp(SmtpEvent::MissingAuthDirectory),
+                SpanId = self.data.session_id,
+            );
+            self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                .await?;
+            return Ok(false);
+        };
+
+        let SaslState::Scram {
+            session,
+            plus_advertised,
+        } = &mut token.state
+        else {
+            return self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await;
+        };
+
+        // SCRAM is a client-first mechanism, request the initial message if missing
+        let scram = session.get_or_insert_with(|| {
+            ScramSession::new(
+                token.mechanism == AUTH_SCRAM_SHA_256_PLUS,
+                *plus_advertised,
+                self.stream.tls_channel_binding(),
+            )
+        });
+        if response.is_empty() && !scram.has_started() {
+            self.write(b"334 \r\n").await?;
+            return Ok(true);
+        }
+        let response = if !response.is_empty() {
+            match base64_decode(response) {
+                Some(response) => response,
+                None => return self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await,
+            }
+        } else {
+            Vec::new()
+        };
+
+        match self
+            .server
+            .authenticate_scram(
+                scram,
+                &response,
+                self.data.session_id,
+                self.data.remote_ip,
+                Some(&directory),
+            )
+            .await
+        {
+            Ok(ScramStep::Challenge(challenge)) => {
+                let mut buf = Vec::with_capacity(challenge.len() * 4 / 3 + 8);
+                buf.extend_from_slice(b"334 ");
+                buf.extend(base64_encode(&challenge).unwrap_or_default());
+                buf.extend_from_slice(b"\r\n");
+                self.write(&buf).await?;
+                Ok(true)
+            }
+            Ok(ScramStep::Success(access_token)) => {
+                self.handle_auth_result(
+                    access_token
+                        .assert_has_permission(Permission::EmailSend)
+                        .map(|_| access_token),
+                )
+                .await
+            }
+            Err(err) => self.handle_auth_result(Err(err)).await,
+        }
+    }
+
+    async fn handle_external_response(
+        &mut self,
+        token: &mut SaslToken,
+        response: &[u8],
+    ) -> Result<bool, ()> {
+        let Some(directory) = self.params.auth_directory.clone() else {
+            trc::event!(
+                Smtp(SmtpEvent::MissingAuthDirectory),
+                SpanId = self.data.session_id,
+            );
+            self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                .await?;
+            return Ok(false);
+        };
+        let Some(certificate) = self.stream.tls_client_certificate() else {
+            return self
+                .auth_error(b"535 5.7.8 No client certificate was presented.\r\n")
+                .await;
+        };
+
+        // The authorization identity is optional, prompt for it without an initial response
+        let SaslState::External { challenged } = &mut token.state else {
+            return self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await;
+        };
+        let authzid = match response {
+            b"" if !*challenged => {
+                *challenged = true;
+                self.write(b"334 \r\n").await?;
+                return Ok(true);
+            }
+            b"" | b"=" => None,
+            response => match base64_decode(response).map(|authzid| authzid.into_string()) {
+                Some(authzid) => Some(authzid).filter(|authzid| !authzid.is_empty()),
+                None => return self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await,
+            },
+        };
+
+        let result = self
+            .server
+            .authenticate_external(
+                &certificate,
+                authzid.as_deref(),
+                &self.instance,
+                self.data.session_id,
+                self.data.remote_ip,
+                Some(&directory),
+            )
+            .await
+            .and_then(|access_token| {
+                access_token
+                    .assert_has_permission(Permission::EmailSend)
+                    .map(|_| access_token)
+            });
+
+        self.handle_auth_result(result).await
+    }
+
     pub async fn authenticate(&mut self, credentials: Credentials<String>) -> Result<bool, ()> {
         if let Some(directory) = &self.params.auth_directory {
             // Authenticate
@@ -109,7 +303,8 @@ impl<T: SessionStream> Session<T> {
                         self.data.session_id,
                         self.data.remote_ip,
                     )
-                    .with_directory(directory),
+                    .with_directory(directory)
+                    .with_protocol(AuthProtocol::Smtp),
                 )
                 .await
                 .and_then(|access_token| {
@@ -118,58 +313,69 @@ impl<T: SessionStream> Session<T> {
                         .map(|_| access_token)
                 });
 
-            match result {
-                Ok(access_token) => {
-                    self.data.authenticated_as = access_token.into();
-                    self.eval_post_auth_params().await;
-                    self.write(b"235 2.7.0 Authentication succeeded.\r\n")
-                        .await?;
-                    return Ok(false);
-                }
-                Err(err) => {
-                    let reason = *err.as_ref();
-
-                    trc::error!(err.span_id(self.data.session_id));
-
-                    match reason {
-                        trc::EventType::Auth(trc::AuthEvent::Failed) => {
-                            return self
-                                .auth_error(b"535 5.7.8 Authentication credentials invalid.\r\n")
-                                .await;
-                        }
-                        trc::EventType::Auth(trc::AuthEvent::TokenExpired) => {
-                            return self.auth_error(b"535 5.7.8 OAuth token expired.\r\n").await;
-                        }
-                        trc::EventType::Auth(trc::AuthEvent::MissingTotp) => {
-                            return self
+            self.handle_auth_result(result).await
+        } else {
+            trc::event!(
+                Smtp(SmtpEvent::MissingAuthDirectory),
+                SpanId = self.data.session_id,
+            );
+            self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                .await?;
+
+            Ok(false)
+        }
+    }
+
+    async fn handle_auth_result(
+        &mut self,
+        result: trc::Result<Arc<AccessToken>>,
+    ) -> Result<bool, ()> {
+        match result {
+            Ok(access_token) => {
+                self.data.authenticated_as = access_token.into();
+                self.eval_post_auth_params().await;
+                self.write(b"235 2.7.0 Authentication succeeded.\r\n")
+                    .await?;
+                return Ok(false);
+            }
+            Err(err) => {
+                let reason = *err.as_ref();
+
+                trc::error!(err.span_id(self.data.session_id));
+
+                match reason {
+                    trc::EventType::Auth(trc::AuthEvent::Failed) => {
+                        return self
+                            .auth_error(b"535 5.7.8 Authentication credentials invalid.\r\n")
+                            .await;
+                    }
+                    trc::EventType::Auth(trc::AuthEvent::TokenExpired) => {
+                        return self.auth_error(b"535 5.7.8 OAuth token expired.\r\n").await;
+                    }
+                    trc::EventType::Auth(trc::AuthEvent::MissingTotp) => {
+                        return self
                             .auth_error(
                                 b"334 5.7.8 Missing TOTP token, try with 'secret$totp_code'.\r\n",
                             )
                             .await;
-                        }
-                        trc::EventType::Security(trc::SecurityEvent::Unauthorized) => {
-                            self.write(
-                                concat!(
-                                    "550 5.7.1 Your account is not authorized ",
-                                    "to use this service.\r\n"
-                                )
-                                .as_bytes(),
+                    }
+                    trc::EventType::Security(trc::SecurityEvent::Unauthorized) => {
+                        self.write(
+                            concat!(
+                                "550 5.7.1 Your account is not authorized ",
+                                "to use this service.\r\n"
                             )
-                            .await?;
-                            return Ok(false);
-                        }
-                        trc::EventType::Security(_) => {
-                            return Err(());
-                        }
-                        _ => (),
+                            .as_bytes(),
+                        )
+                        .await?;
+                        return Ok(false);
+                    }
+                    trc::EventType::Security(_) => {
+                        return Err(());
                     }
+                    _ => (),
                 }
             }
-        } else {
-            trc::event!(
-                Smtp(SmtpEvent::MissingAuthDirectory),
-                SpanId = self.data.session_id,
-            );
         }
         self.write(b"454 4.7.0 Temporary authentication failure\r\n")
             .await?;
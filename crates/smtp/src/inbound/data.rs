@@ -10,27 +10,34 @@ use crate::{
     inbound::milter::Modification,
     queue::{
         self, DMARC_AUTHENTICATED, Message, MessageSource, QueueEnvelope, Schedule,
+        list::MailingListDelivery,
+        quarantine::QuarantineEntry,
         quota::HasQueueQuota,
+        sandbox::{SANDBOX_REASON, SandboxPending, SmtpSandbox},
     },
-    reporting::analysis::AnalyzeReport,
+    reporting::{SmtpReporting, analysis::AnalyzeReport, bounce::VerpBounce},
     scripts::ScriptResult,
 };
 use common::{
     config::{
-        smtp::{auth::VerifyStrategy, session::Stage},
+        smtp::{
+            auth::VerifyStrategy,
+            session::{SandboxMode, Stage},
+        },
         spamfilter::SpamFilterAction,
     },
     listener::SessionStream,
     psl,
     scripts::ScriptModification,
 };
+use groupware::contact::collect::{CollectedAddress, ContactCollect};
 use mail_auth::{
     AuthenticatedMessage, AuthenticationResults, DkimResult, DmarcResult, ReceivedSpf,
     common::{headers::HeaderWriter, verify::VerifySignature},
     dmarc::{self, verify::DmarcParameters},
 };
 use mail_builder::headers::{date::Date, message_id::generate_message_id_header};
-use mail_parser::MessageParser;
+use mail_parser::{MessageParser, MimeHeaders, PartType};
 use sieve::runtime::Variable;
 use smtp_proto::{
     MAIL_BY_RETURN, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
@@ -70,25 +77,90 @@ impl<T: SessionStream> Session<T> {
         let has_date_header = auth_message.has_date_header();
         let has_message_id_header = auth_message.has_message_id_header();
 
+        // Obtain recipient display names for contact collection
+        let collect_names = if self.data.authenticated_as.is_some()
+            && self.server.core.groupware.collect.is_some()
+        {
+            [parsed_message.to(), parsed_message.cc()]
+                .into_iter()
+                .flatten()
+                .flat_map(|addr| addr.iter())
+                .filter_map(|addr| {
+                    Some((addr.address()?.to_lowercase(), addr.name()?.to_string()))
+                })
+                .collect::<Vec<_>>()
+        } else {
+            vec![]
+        };
+
         // Loop detection
         let dc = &self.server.core.smtp.session.data;
         let ac = &self.server.core.smtp.mail_auth;
         let rc = &self.server.core.smtp.report;
-        if auth_message.received_headers_count()
+        let is_bounce = self
+            .data
+            .mail_from
+            .as_ref()
+            .is_some_and(|mail_from| mail_from.address.is_empty());
+        let loop_reason = if auth_message.received_headers_count()
             > self
                 .server
                 .eval_if(&dc.max_received_headers, self, self.data.session_id)
                 .await
                 .unwrap_or(50)
         {
+            Some("Too many Received headers")
+        } else if self
+            .server
+            .eval_if(&dc.loop_delivered_to, self, self.data.session_id)
+            .await
+            .unwrap_or(true)
+            && self.has_delivered_to_loop(&parsed_message)
+        {
+            Some("Message was already delivered to this recipient")
+        } else {
+            None
+        };
+        if let Some(reason) = loop_reason {
             trc::event!(
                 Smtp(SmtpEvent::LoopDetected),
                 SpanId = self.data.session_id,
                 Total = auth_message.received_headers_count(),
+                Reason = reason,
             );
 
-            return (&b"450 4.4.6 Too many Received headers. Possible loop detected.\r\n"[..])
-                .into();
+            // Bounces are discarded, rejecting them would produce yet another bounce
+            return if !is_bounce {
+                format!("554 5.4.6 {reason}, mail loop detected.\r\n")
+                    .into_bytes()
+                    .into()
+            } else {
+                trc::event!(
+                    Smtp(SmtpEvent::LoopDropped),
+                    SpanId = self.data.session_id,
+                    Reason = reason,
+                );
+                self.data.messages_sent += 1;
+                (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
+            };
+        }
+
+        // Break DSN-to-DSN chains
+        if is_bounce
+            && self
+                .server
+                .eval_if(&dc.loop_dsn_chains, self, self.data.session_id)
+                .await
+                .unwrap_or(true)
+            && is_dsn_of_dsn(&parsed_message)
+        {
+            trc::event!(
+                Smtp(SmtpEvent::LoopDropped),
+                SpanId = self.data.session_id,
+                Reason = "Delivery status notification about another notification",
+            );
+            self.data.messages_sent += 1;
+            return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
         }
 
         // Verify DKIM
@@ -421,6 +493,37 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Run antivirus scanners
+        match self.run_antivirus(&raw_message).await {
+            Ok(av_headers) => {
+                if !av_headers.is_empty() {
+                    headers.extend_from_slice(&av_headers);
+                }
+            }
+            Err(response) => {
+                return response.into_bytes();
+            }
+        }
+
+        // Submit suspicious attachments for detonation
+        let sandbox_submissions = self.run_sandbox(&parsed_message).await;
+
+        // Run DLP rules on authenticated submissions
+        let dlp = match self.run_dlp(&parsed_message).await {
+            Ok(dlp) => dlp,
+            Err(response) => {
+                return response.into_bytes();
+            }
+        };
+
+        // Look for signs of a compromised account
+        let anomaly_reason = match self.check_account_anomaly().await {
+            Ok(reason) => reason,
+            Err(response) => {
+                return response.into_bytes();
+            }
+        };
+
         // Run SPAM filter
         if self.server.core.spam.enabled
             && self
@@ -485,6 +588,99 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
+        // Process bounces addressed to VERP return paths
+        if self
+            .data
+            .mail_from
+            .as_ref()
+            .is_some_and(|from| from.address_lcase.is_empty())
+        {
+            let mut consumed = Vec::new();
+            for rcpt in &self.data.rcpt_to {
+                if self
+                    .server
+                    .process_verp_bounce(&rcpt.address_lcase, &parsed_message, self.data.session_id)
+                    .await
+                {
+                    consumed.push(rcpt.address_lcase.clone());
+                }
+            }
+            if !consumed.is_empty() {
+                self.data
+                    .rcpt_to
+                    .retain(|rcpt| !consumed.contains(&rcpt.address_lcase));
+                if self.data.rcpt_to.is_empty() {
+                    self.data.messages_sent += 1;
+                    return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
+                }
+            }
+        }
+
+        // Hold messages flagged by content filters or anomaly detection for review
+        let mut quarantine = if let Some(reason) = anomaly_reason {
+            QuarantineEntry::new(reason)
+                .with_message(&parsed_message)
+                .into()
+        } else if let Some(reason) = dlp.quarantine {
+            // DLP rules with a quarantine action always hold the message
+            QuarantineEntry::new(reason)
+                .with_message(&parsed_message)
+                .into()
+        } else if let Some(reason) = quarantine_reason(&headers, &modifications) {
+            if self
+                .server
+                .eval_if(&dc.quarantine, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+            {
+                QuarantineEntry::new(reason)
+                    .with_message(&parsed_message)
+                    .into()
+            } else {
+                None
+            }
+        } else if let Some(moderator) = self.moderator_for(&parsed_message, dlp.matched).await {
+            QuarantineEntry::new("Awaiting moderator approval")
+                .with_message(&parsed_message)
+                .with_moderator(moderator)
+                .into()
+        } else {
+            None
+        };
+
+        // Hold messages awaiting a sandbox verdict, or deliver them as pending
+        let sandbox_pending = if let (Some(submissions), Some(sandbox)) =
+            (sandbox_submissions, &self.server.core.smtp.session.sandbox)
+        {
+            // Delivered copies are located by Message-ID once the verdict arrives
+            let message_id = parsed_message.message_id().map(|id| id.to_string());
+            let hold = sandbox.mode == SandboxMode::Hold || message_id.is_none();
+            if !hold {
+                headers.extend_from_slice(b"X-Sandbox-Status: pending\r\n");
+            } else if quarantine.is_none() {
+                quarantine = QuarantineEntry::new(SANDBOX_REASON)
+                    .with_message(&parsed_message)
+                    .into();
+            }
+
+            let now = now();
+            Some(SandboxPending {
+                created: now,
+                expires: now + sandbox.verdict_timeout.as_secs(),
+                hold,
+                submissions,
+                message_id: message_id.filter(|_| !hold),
+                recipients: self
+                    .data
+                    .rcpt_to
+                    .iter()
+                    .map(|rcpt| rcpt.address_lcase.clone())
+                    .collect(),
+            })
+        } else {
+            None
+        };
+
         // Apply modifications
         let mut edited_message = if !modifications.is_empty() {
             self.data
@@ -588,9 +784,66 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Apply attachment policy
+        if !dc.attachments.is_empty() {
+            if let Some(disarmed_message) = self
+                .apply_attachment_rules(edited_message.as_deref().unwrap_or(raw_message.as_slice()))
+                .await
+            {
+                edited_message = disarmed_message.into();
+            }
+        }
+
+        // Replace large attachments with download links
+        if let Some(linked_message) = self
+            .apply_large_file_links(edited_message.as_deref().unwrap_or(raw_message.as_slice()))
+            .await
+        {
+            edited_message = linked_message.into();
+        }
+
+        // Apply rewrite rules
+        if !dc.rewrite.is_empty() {
+            if let Some(rewritten_message) = self
+                .apply_rewrite_rules(edited_message.as_deref().unwrap_or(raw_message.as_slice()))
+                .await
+            {
+                edited_message = rewritten_message.into();
+            }
+        }
+
         // Build message
         let mail_from = self.data.mail_from.clone().unwrap();
-        let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let mut rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let num_recipients = rcpt_to.len();
+        let list_expansions = std::mem::take(&mut self.data.list_expansions);
+        let collect_addresses = self
+            .data
+            .authenticated_as
+            .as_ref()
+            .filter(|_| self.server.core.groupware.collect.is_some())
+            .map(|access_token| {
+                (
+                    access_token.clone(),
+                    rcpt_to
+                        .iter()
+                        .map(|rcpt| CollectedAddress {
+                            address: rcpt.address_lcase.clone(),
+                            name: collect_names
+                                .iter()
+                                .find(|(address, _)| address == &rcpt.address_lcase)
+                                .map(|(_, name)| name.clone()),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            });
+        if !list_expansions.is_empty() {
+            rcpt_to.retain(|rcpt| {
+                !list_expansions
+                    .iter()
+                    .any(|list| list.address_lcase == rcpt.address_lcase)
+            });
+        }
         let mut message = self
             .build_message(mail_from, rcpt_to, message_id, self.data.session_id)
             .await;
@@ -657,6 +910,66 @@ impl<T: SessionStream> Session<T> {
 
         // Update size
         message.size = (raw_message.len() + headers.len()) as u64;
+        let source = if !self.is_authenticated() {
+            MessageSource::Unauthenticated
+        } else {
+            MessageSource::Authenticated
+        };
+
+        // Distribute posts to mailing lists
+        if !list_expansions.is_empty() {
+            let mut list_message = Vec::with_capacity(headers.len() + raw_message.len());
+            list_message.extend_from_slice(&headers);
+            list_message.extend_from_slice(raw_message);
+
+            let mut list_queue_id = None;
+            for expansion in list_expansions {
+                let Some(list) = self
+                    .server
+                    .core
+                    .smtp
+                    .list
+                    .lists
+                    .get(&expansion.address_lcase)
+                else {
+                    continue;
+                };
+                match self
+                    .server
+                    .list_post(
+                        list,
+                        expansion.members,
+                        &message.return_path,
+                        &list_message,
+                        quarantine.clone(),
+                        source,
+                        self.data.session_id,
+                    )
+                    .await
+                {
+                    Some(queue_id) => {
+                        list_queue_id = Some(queue_id);
+                    }
+                    None => {
+                        return (b"451 4.3.5 Unable to accept message at this time.\r\n"[..])
+                            .into();
+                    }
+                }
+            }
+
+            // Posts addressed only to lists are not queued again
+            if message.recipients.is_empty() {
+                return if let Some(queue_id) = list_queue_id {
+                    self.state = State::Accepted(queue_id);
+                    self.data.messages_sent += 1;
+                    format!("250 2.0.0 Message queued with id {queue_id:x}.\r\n")
+                        .into_bytes()
+                        .into()
+                } else {
+                    (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into()
+                };
+            }
+        }
 
         // Verify queue quota
         if self.server.has_quota(&mut message).await {
@@ -664,18 +977,40 @@ impl<T: SessionStream> Session<T> {
             let queue_id = message.queue_id;
 
             // Queue message
-            let source = if !self.is_authenticated() {
-                MessageSource::Unauthenticated
-            } else {
-                MessageSource::Authenticated
-            };
             if self.is_authenticated()
                 || dmarc_result.is_some_and(|result| result == DmarcResult::Pass)
             {
                 message.flags |= DMARC_AUTHENTICATED;
             }
-            if message
-                .queue(
+            let moderation_request = quarantine.as_ref().and_then(|entry| {
+                entry.moderator.as_ref().map(|moderator| {
+                    (
+                        moderator.clone(),
+                        self.build_moderation_request(
+                            &message,
+                            moderator,
+                            entry.subject.as_deref(),
+                        ),
+                    )
+                })
+            });
+            // Encode each recipient in its own return path when VERP is enabled
+            let use_verp = quarantine.is_none()
+                && sandbox_pending.is_none()
+                && message.recipients.len() > 1
+                && !message.return_path.is_empty()
+                && self
+                    .server
+                    .eval_if::<bool, _>(
+                        &self.server.core.smtp.queue.verp.enable,
+                        &message,
+                        self.data.session_id,
+                    )
+                    .await
+                    .unwrap_or(false);
+            let queued = if use_verp {
+                Message::queue_copies(
+                    message.split_verp(&self.server),
                     Some(&headers),
                     raw_message,
                     self.data.session_id,
@@ -683,7 +1018,64 @@ impl<T: SessionStream> Session<T> {
                     source,
                 )
                 .await
-            {
+            } else {
+                message
+                    .queue_with_quarantine(
+                        Some(&headers),
+                        raw_message,
+                        self.data.session_id,
+                        &self.server,
+                        source,
+                        quarantine,
+                    )
+                    .await
+            };
+            if queued {
+                if let Some(pending) = sandbox_pending {
+                    if let Err(err) = self.server.write_sandbox_pending(queue_id, pending).await {
+                        trc::error!(
+                            err.span_id(self.data.session_id)
+                                .details("Failed to write pending sandbox verdict.")
+                        );
+                    }
+                }
+
+                if let Some((moderator, request)) = moderation_request {
+                    trc::event!(
+                        Queue(trc::QueueEvent::ModerationRequested),
+                        SpanId = self.data.session_id,
+                        QueueId = queue_id,
+                        To = moderator.clone(),
+                    );
+
+                    self.server
+                        .send_autogenerated(
+                            self.server.core.smtp.session.data.moderation.from.clone(),
+                            [moderator].into_iter(),
+                            request,
+                            None,
+                            self.data.session_id,
+                        )
+                        .await;
+                }
+
+                // Collect recipients into the sender's address book
+                if let Some((access_token, addresses)) = collect_addresses {
+                    let server = self.server.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = server.collect_addresses(&access_token, addresses).await
+                        {
+                            trc::error!(
+                                err.account_id(access_token.primary_id)
+                                    .details("Failed to collect recipient addresses")
+                            );
+                        }
+                    });
+                }
+
+                // Charge the message against the sending limits of the account
+                self.charge_sending_limits(num_recipients).await;
+
                 self.state = State::Accepted(queue_id);
                 self.data.messages_sent += 1;
                 format!("250 2.0.0 Message queued with id {queue_id:x}.\r\n")
@@ -870,7 +1262,7 @@ impl<T: SessionStream> Session<T> {
         }
     }
 
-    fn write_received(&self, headers: &mut Vec<u8>, id: u64) {
+    pub(crate) fn write_received(&self, headers: &mut Vec<u8>, id: u64) {
         headers.extend_from_slice(b"Received: from ");
         headers.extend_from_slice(self.data.helo_domain.as_bytes());
         headers.extend_from_slice(b" (");
@@ -929,3 +1321,95 @@ impl<T: SessionStream> Session<T> {
         headers.extend_from_slice(b"\r\n");
     }
 }
+
+fn quarantine_reason(headers: &[u8], modifications: &[Modification]) -> Option<String> {
+    // Headers added by the antivirus scanners
+    headers
+        .split(|&ch| ch == b'\n')
+        .find_map(|line| {
+            line.get(..13)
+                .filter(|name| name.eq_ignore_ascii_case(b"X-Quarantine:"))
+                .map(|_| String::from_utf8_lossy(&line[13..]).trim().to_string())
+        })
+        .or_else(|| {
+            // Verdicts returned by milters and MTA hooks
+            modifications
+                .iter()
+                .find_map(|modification| match modification {
+                    Modification::Quarantine { reason } => Some(reason.clone()),
+                    Modification::AddHeader { name, value }
+                    | Modification::InsertHeader { name, value, .. }
+                    | Modification::ChangeHeader { name, value, .. }
+                        if name.eq_ignore_ascii_case("X-Quarantine") =>
+                    {
+                        Some(value.trim().to_string())
+                    }
+                    _ => None,
+                })
+        })
+        .map(|reason| {
+            if reason.is_empty() || reason.eq_ignore_ascii_case("true") {
+                "Flagged for quarantine by a content filter".to_string()
+            } else {
+                reason
+            }
+        })
+}
+
+impl<T: SessionStream> Session<T> {
+    fn has_delivered_to_loop(&self, message: &mail_parser::Message<'_>) -> bool {
+        let raw = message.raw_message();
+        message.root_part().headers().iter().any(|header| {
+            header.name.as_str().eq_ignore_ascii_case("Delivered-To")
+                && raw
+                    .get(header.offset_start as usize..header.offset_end as usize)
+                    .and_then(|value| std::str::from_utf8(value).ok())
+                    .is_some_and(|value| {
+                        let value = value.trim().trim_start_matches('<').trim_end_matches('>');
+                        self.data
+                            .rcpt_to
+                            .iter()
+                            .any(|rcpt| rcpt.address_lcase.eq_ignore_ascii_case(value))
+                    })
+        })
+    }
+}
+
+fn is_dsn_of_dsn(message: &mail_parser::Message<'_>) -> bool {
+    if !is_delivery_report(message.root_part()) {
+        return false;
+    }
+
+    message.parts.iter().any(|part| match &part.body {
+        PartType::Message(original) => {
+            is_delivery_report(original.root_part())
+                || original
+                    .return_path()
+                    .is_some_and(|path| path.trim() == "<>")
+        }
+        PartType::Text(headers)
+            if part
+                .content_type()
+                .is_some_and(|ct| ct.subtype() == Some("rfc822-headers")) =>
+        {
+            headers.lines().any(|line| {
+                let line = line.to_ascii_lowercase();
+                (line.starts_with("content-type:") && line.contains("multipart/report"))
+                    || (line.starts_with("return-path:") && line.contains("<>"))
+            })
+        }
+        _ => false,
+    })
+}
+
+fn is_delivery_report(part: &mail_parser::MessagePart<'_>) -> bool {
+    part.content_type().is_some_and(|ct| {
+        ct.ctype().eq_ignore_ascii_case("multipart")
+            && ct
+                .subtype()
+                .is_some_and(|st| st.eq_ignore_ascii_case("report"))
+            && ct
+                .attribute("report-type")
+                .is_some_and(|rt| rt.eq_ignore_ascii_case("delivery-status"))
+    })
+}
@@ -0,0 +1,539 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::io::{Cursor, Read};
+
+use common::{
+    config::smtp::session::{AttachmentAction, AttachmentRule},
+    listener::SessionStream,
+};
+use mail_auth::zip;
+use mail_builder::encoders::base64::base64_encode_mime;
+use mail_parser::{Message, MessageParser, MessagePart, MimeHeaders};
+use trc::SmtpEvent;
+
+use crate::core::Session;
+
+const MAX_ARCHIVE_ENTRY_SIZE: u64 = 10 * 1024 * 1024;
+const MAGIC_BYTES_LEN: u64 = 8192;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn apply_attachment_rules(&self, raw_message: &[u8]) -> Option<Vec<u8>> {
+        // Obtain matching rules
+        let mut rules = Vec::new();
+        for rule in &self.server.core.smtp.session.data.attachments {
+            if self
+                .server
+                .eval_if(&rule.enable, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+            {
+                rules.push(rule);
+            }
+        }
+        if rules.is_empty() {
+            return None;
+        }
+
+        let message = MessageParser::new().parse(raw_message)?;
+        let disarmer = AttachmentDisarmer::new(&message, &rules);
+        if disarmer.matches.is_empty() {
+            return None;
+        }
+
+        for (_, rule, name) in &disarmer.matches {
+            trc::event!(
+                Smtp(SmtpEvent::AttachmentDisarmed),
+                SpanId = self.data.session_id,
+                Id = rule.id.clone(),
+                Details = name.clone(),
+            );
+        }
+
+        disarmer.build()
+    }
+}
+
+pub struct AttachmentDisarmer<'x> {
+    message: &'x Message<'x>,
+    matches: Vec<(u32, &'x AttachmentRule, String)>,
+}
+
+impl<'x> AttachmentDisarmer<'x> {
+    pub fn new(message: &'x Message<'x>, rules: &[&'x AttachmentRule]) -> Self {
+        let mut matches = Vec::new();
+
+        for part_id in &message.attachments {
+            let Some(part) = message.parts.get(*part_id as usize) else {
+                continue;
+            };
+            if let Some((rule, name)) = rules
+                .iter()
+                .find_map(|rule| rule_matches(rule, part).map(|name| (*rule, name)))
+            {
+                matches.push((*part_id, rule, name));
+            }
+        }
+
+        AttachmentDisarmer { message, matches }
+    }
+
+    pub fn with_matches(
+        message: &'x Message<'x>,
+        matches: Vec<(u32, &'x AttachmentRule, String)>,
+    ) -> Self {
+        AttachmentDisarmer { message, matches }
+    }
+
+    pub fn build(self) -> Option<Vec<u8>> {
+        if self.matches.is_empty() {
+            return None;
+        }
+
+        let raw = self.message.raw_message();
+        let root = self.message.root_part();
+        let body_offset = root.raw_body_offset() as usize;
+
+        // Split root headers
+        let mut headers = Vec::with_capacity(body_offset);
+        let mut content_headers = Vec::new();
+        for (name, header) in part_headers(raw, root) {
+            if name.to_ascii_lowercase().starts_with(b"content-") {
+                content_headers.extend_from_slice(header);
+            } else {
+                headers.extend_from_slice(header);
+            }
+        }
+
+        // Build edits
+        let mut edits = Vec::with_capacity(self.matches.len());
+        let mut body = None;
+        let mut notify = Vec::new();
+        for (part_id, rule, name) in &self.matches {
+            let Some(part) = self.message.parts.get(*part_id as usize) else {
+                continue;
+            };
+
+            let filename = part.attachment_name().unwrap_or(name.as_str());
+            if let Some(text) = &rule.notify {
+                let text = text.replace("{name}", filename);
+                if !notify.contains(&text) {
+                    notify.push(text);
+                }
+            }
+
+            let header_start = part.raw_header_offset() as usize;
+            let body_start = part.raw_body_offset() as usize;
+            let body_end = part.raw_end_offset() as usize;
+
+            if *part_id == 0 {
+                // The message itself is the attachment, keep the message headers
+                let (new_headers, new_body) = match &rule.action {
+                    AttachmentAction::Rename { suffix } => {
+                        let mut new_headers = Vec::new();
+                        for (name, header) in part_headers(raw, part) {
+                            if name.to_ascii_lowercase().starts_with(b"content-")
+                                && !is_type_header(name)
+                            {
+                                new_headers.extend_from_slice(header);
+                            }
+                        }
+                        renamed_headers(&mut new_headers, &format!("{filename}{suffix}"));
+                        (
+                            new_headers,
+                            raw.get(body_start..).unwrap_or_default().to_vec(),
+                        )
+                    }
+                    AttachmentAction::Replace { text } => replacement_part(text, filename),
+                    AttachmentAction::Drop => replacement_part("", filename),
+                };
+                content_headers = new_headers;
+                body = Some(new_body);
+                break;
+            }
+
+            match &rule.action {
+                AttachmentAction::Drop => {
+                    // Remove the part along with its boundary delimiter
+                    let start = raw
+                        .get(..header_start.saturating_sub(1))
+                        .and_then(|bytes| bytes.iter().rposition(|&ch| ch == b'\n'))
+                        .map(|pos| pos + 1);
+                    let end = raw
+                        .get(body_end..)
+                        .and_then(|bytes| bytes.windows(3).position(|w| w == b"\n--"))
+                        .map(|pos| body_end + pos + 1);
+                    if let (Some(start), Some(end)) = (start, end) {
+                        edits.push((start, end, Vec::new()));
+                    }
+                }
+                AttachmentAction::Rename { suffix } => {
+                    let mut new_headers = Vec::new();
+                    for (name, header) in part_headers(raw, part) {
+                        if !is_type_header(name) {
+                            new_headers.extend_from_slice(header);
+                        }
+                    }
+                    renamed_headers(&mut new_headers, &format!("{filename}{suffix}"));
+                    new_headers.extend_from_slice(b"\r\n");
+                    edits.push((header_start, body_start, new_headers));
+                }
+                AttachmentAction::Replace { text } => {
+                    let (mut new_part, new_body) = replacement_part(text, filename);
+                    new_part.extend_from_slice(b"\r\n");
+                    new_part.extend_from_slice(&new_body);
+                    edits.push((header_start, body_end, new_part));
+                }
+            }
+        }
+
+        // Apply edits to the body
+        let body = body.unwrap_or_else(|| {
+            edits.sort_unstable_by_key(|(start, _, _)| *start);
+            let mut body = Vec::with_capacity(raw.len().saturating_sub(body_offset));
+            let mut offset = body_offset;
+            for (start, end, contents) in edits {
+                if start >= offset {
+                    body.extend_from_slice(raw.get(offset..start).unwrap_or_default());
+                    body.extend_from_slice(&contents);
+                    offset = end;
+                }
+            }
+            body.extend_from_slice(raw.get(offset..).unwrap_or_default());
+            body
+        });
+
+        // Build message
+        let mut new_message = Vec::with_capacity(raw.len() + 512);
+        new_message.extend_from_slice(&headers);
+        if notify.is_empty() {
+            new_message.extend_from_slice(&content_headers);
+            new_message.extend_from_slice(b"\r\n");
+            new_message.extend_from_slice(&body);
+        } else {
+            // Wrap the message in a multipart/mixed container with the notification first
+            let boundary = format!("disarm_{:016x}", rand::random::<u64>());
+            if !headers
+                .windows(13)
+                .any(|w| w.eq_ignore_ascii_case(b"mime-version:"))
+            {
+                new_message.extend_from_slice(b"MIME-Version: 1.0\r\n");
+            }
+            new_message.extend_from_slice(
+                format!("Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n")
+                    .as_bytes(),
+            );
+            new_message.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+            let (text_headers, text_body) = text_part(&notify.join("\r\n"));
+            new_message.extend_from_slice(&text_headers);
+            new_message.extend_from_slice(b"\r\n");
+            new_message.extend_from_slice(&text_body);
+            new_message.extend_from_slice(format!("\r\n--{boundary}\r\n").as_bytes());
+            new_message.extend_from_slice(&content_headers);
+            new_message.extend_from_slice(b"\r\n");
+            new_message.extend_from_slice(&body);
+            if !body.ends_with(b"\n") {
+                new_message.extend_from_slice(b"\r\n");
+            }
+            new_message.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+        }
+
+        Some(new_message)
+    }
+}
+
+fn rule_matches(rule: &AttachmentRule, part: &MessagePart<'_>) -> Option<String> {
+    let name = part.attachment_name().unwrap_or_default();
+    if rule.extensions.contains(&extension(name)) {
+        return Some(name.to_string());
+    }
+
+    if !rule.content_types.is_empty() {
+        if let Some(ct) = part.content_type() {
+            let ctype = ct.ctype().to_ascii_lowercase();
+            let full_type = ct
+                .subtype()
+                .map(|subtype| format!("{ctype}/{}", subtype.to_ascii_lowercase()))
+                .unwrap_or_else(|| ctype.clone());
+            if rule.content_types.iter().any(|pattern| {
+                pattern == &full_type
+                    || pattern
+                        .strip_suffix("/*")
+                        .is_some_and(|prefix| prefix == ctype)
+            }) {
+                return Some(name.to_string());
+            }
+        }
+    }
+
+    let contents = part.contents();
+    if file_type_matches(rule, contents) {
+        return Some(name.to_string());
+    }
+
+    // Inspect archive contents
+    if rule.archive_depth > 0 && is_zip(name, contents) {
+        archive_matches(rule, contents, rule.archive_depth)
+            .map(|inner_name| format!("{name}/{inner_name}"))
+    } else {
+        None
+    }
+}
+
+fn archive_matches(rule: &AttachmentRule, contents: &[u8], depth: usize) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(contents)).ok()?;
+    for idx in 0..archive.len() {
+        let Ok(mut file) = archive.by_index(idx) else {
+            continue;
+        };
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        if rule.extensions.contains(&extension(&name)) {
+            return Some(name);
+        }
+
+        let is_archive = depth > 1 && extension(&name) == "zip";
+        if !rule.file_types.is_empty() || is_archive {
+            let max_size = if is_archive {
+                MAX_ARCHIVE_ENTRY_SIZE
+            } else {
+                MAGIC_BYTES_LEN
+            };
+            let mut bytes = Vec::new();
+            if (&mut file).take(max_size).read_to_end(&mut bytes).is_err() {
+                continue;
+            }
+            if file_type_matches(rule, &bytes) {
+                return Some(name);
+            }
+            if is_archive {
+                if let Some(inner_name) = archive_matches(rule, &bytes, depth - 1) {
+                    return Some(format!("{name}/{inner_name}"));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn file_type_matches(rule: &AttachmentRule, contents: &[u8]) -> bool {
+    !rule.file_types.is_empty()
+        && infer::get(contents).is_some_and(|t| {
+            rule.file_types.contains(t.extension()) || rule.file_types.contains(t.mime_type())
+        })
+}
+
+fn is_zip(name: &str, contents: &[u8]) -> bool {
+    extension(name) == "zip" || contents.starts_with(b"PK\x03\x04")
+}
+
+fn extension(name: &str) -> String {
+    name.rsplit_once('.')
+        .map(|(_, ext)| ext.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn part_headers<'x>(
+    raw: &'x [u8],
+    part: &MessagePart<'_>,
+) -> impl Iterator<Item = (&'x [u8], &'x [u8])> {
+    part.headers.iter().filter_map(move |header| {
+        let name = raw.get(header.offset_field as usize..header.offset_start as usize)?;
+        let name = name
+            .iter()
+            .position(|&ch| ch == b':')
+            .map_or(name, |pos| &name[..pos]);
+        let header = raw.get(header.offset_field as usize..header.offset_end as usize)?;
+        Some((name, header))
+    })
+}
+
+fn is_type_header(name: &[u8]) -> bool {
+    name.eq_ignore_ascii_case(b"Content-Type") || name.eq_ignore_ascii_case(b"Content-Disposition")
+}
+
+fn renamed_headers(headers: &mut Vec<u8>, filename: &str) {
+    let filename = quote_value(filename);
+    headers.extend_from_slice(
+        format!(
+            "Content-Type: application/octet-stream; name={filename}\r\nContent-Disposition: attachment; filename={filename}\r\n"
+        )
+        .as_bytes(),
+    );
+}
+
+fn replacement_part(text: &str, filename: &str) -> (Vec<u8>, Vec<u8>) {
+    let (mut headers, body) = text_part(text);
+    headers.extend_from_slice(
+        format!(
+            "Content-Disposition: attachment; filename={}\r\n",
+            quote_value(&format!("{filename}.txt"))
+        )
+        .as_bytes(),
+    );
+    (headers, body)
+}
+
+fn text_part(text: &str) -> (Vec<u8>, Vec<u8>) {
+    let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+    let mut headers = b"Content-Type: text/plain; charset=utf-8\r\n".to_vec();
+    if text.is_ascii() {
+        headers.extend_from_slice(b"Content-Transfer-Encoding: 7bit\r\n");
+        let mut body = text.into_bytes();
+        body.extend_from_slice(b"\r\n");
+        (headers, body)
+    } else {
+        headers.extend_from_slice(b"Content-Transfer-Encoding: base64\r\n");
+        let mut body = Vec::with_capacity(text.len() * 4 / 3 + 4);
+        let _ = base64_encode_mime(text.as_bytes(), &mut body, false);
+        (headers, body)
+    }
+}
+
+fn quote_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    if value.is_ascii() {
+        result.push('"');
+        for ch in value.chars() {
+            if matches!(ch, '"' | '\\') {
+                result.push('\\');
+            }
+            if !ch.is_ascii_control() {
+                result.push(ch);
+            }
+        }
+        result.push('"');
+    } else {
+        result.push_str("\"=?utf-8?b?");
+        let mut encoded = Vec::new();
+        let _ = base64_encode_mime(value.as_bytes(), &mut encoded, true);
+        result.push_str(std::str::from_utf8(&encoded).unwrap_or_default());
+        result.push_str("?=\"");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        config::smtp::session::{AttachmentAction, AttachmentRule},
+        expr::if_block::IfBlock,
+    };
+    use mail_parser::{MessageParser, MimeHeaders};
+
+    use super::AttachmentDisarmer;
+
+    const MESSAGE: &str = concat!(
+        "From: john@example.org\r\n",
+        "Subject: Invoice\r\n",
+        "MIME-Version: 1.0\r\n",
+        "Content-Type: multipart/mixed; boundary=\"b1\"\r\n",
+        "\r\n",
+        "--b1\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "See attached.\r\n",
+        "--b1\r\n",
+        "Content-Type: application/octet-stream; name=\"invoice.exe\"\r\n",
+        "Content-Disposition: attachment; filename=\"invoice.exe\"\r\n",
+        "Content-Transfer-Encoding: base64\r\n",
+        "\r\n",
+        "TVqQAAMAAAAEAAAA//8AALgAAAAAAAAAQAAAAAAAAAA=\r\n",
+        "--b1\r\n",
+        "Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+        "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+        "Content-Transfer-Encoding: base64\r\n",
+        "\r\n",
+        "JVBERi0xLjQK\r\n",
+        "--b1--\r\n"
+    );
+
+    fn rule(action: AttachmentAction, notify: Option<&str>) -> AttachmentRule {
+        AttachmentRule {
+            id: "exe".to_string(),
+            enable: IfBlock::new::<()>("enable", [], "true"),
+            extensions: ["exe".to_string()].into_iter().collect(),
+            content_types: vec![],
+            file_types: Default::default(),
+            archive_depth: 2,
+            action,
+            notify: notify.map(|n| n.to_string()),
+        }
+    }
+
+    fn attachment_names(raw: &[u8]) -> Vec<String> {
+        let message = MessageParser::new().parse(raw).unwrap();
+        message
+            .attachments()
+            .map(|part| part.attachment_name().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn disarm_attachments() {
+        let message = MessageParser::new().parse(MESSAGE.as_bytes()).unwrap();
+
+        // Drop
+        let rule_ = rule(AttachmentAction::Drop, None);
+        let result = AttachmentDisarmer::new(&message, &[&rule_])
+            .build()
+            .unwrap();
+        assert_eq!(attachment_names(&result), vec!["report.pdf"]);
+        let parsed = MessageParser::new().parse(&result).unwrap();
+        assert_eq!(parsed.body_text(0).unwrap(), "See attached.");
+        assert_eq!(parsed.subject(), Some("Invoice"));
+
+        // Rename
+        let rule_ = rule(
+            AttachmentAction::Rename {
+                suffix: ".disarmed".to_string(),
+            },
+            None,
+        );
+        let result = AttachmentDisarmer::new(&message, &[&rule_])
+            .build()
+            .unwrap();
+        assert_eq!(
+            attachment_names(&result),
+            vec!["invoice.exe.disarmed", "report.pdf"]
+        );
+
+        // Replace with notification
+        let rule_ = rule(
+            AttachmentAction::Replace {
+                text: "Removed.".to_string(),
+            },
+            Some("The attachment {name} was removed."),
+        );
+        let result = AttachmentDisarmer::new(&message, &[&rule_])
+            .build()
+            .unwrap();
+        let result_str = std::str::from_utf8(&result).unwrap();
+        assert!(
+            result_str.contains("filename=\"invoice.exe.txt\""),
+            "{result_str}"
+        );
+        assert!(result_str.contains("Removed."), "{result_str}");
+        let parsed = MessageParser::new().parse(&result).unwrap();
+        assert_eq!(
+            parsed.body_text(0).unwrap(),
+            "The attachment invoice.exe was removed."
+        );
+        assert_eq!(parsed.subject(), Some("Invoice"));
+
+        // No matches
+        let mut rule_ = rule(AttachmentAction::Drop, None);
+        rule_.extensions = ["js".to_string()].into_iter().collect();
+        assert!(
+            AttachmentDisarmer::new(&message, &[&rule_])
+                .build()
+                .is_none()
+        );
+    }
+}
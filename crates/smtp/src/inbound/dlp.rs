@@ -0,0 +1,362 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use ahash::AHashSet;
+use common::{
+    config::smtp::session::{DlpAction, DlpRule},
+    listener::SessionStream,
+};
+use mail_builder::{
+    MessageBuilder,
+    headers::{HeaderType, address::Address},
+};
+use mail_parser::{Message, MimeHeaders, PartType, decoders::html::html_to_text};
+use tokio::task::JoinSet;
+use trc::SmtpEvent;
+
+use crate::{core::Session, inbound::FilterResponse, reporting::SmtpReporting};
+
+const MAX_NESTED_MESSAGES: usize = 3;
+const MAX_REPORTED_MATCHES: usize = 10;
+const MAX_DICTIONARY_WORDS: usize = 10_000;
+const DICTIONARY_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Default)]
+pub struct DlpVerdict {
+    pub matched: bool,
+    pub quarantine: Option<String>,
+}
+
+impl<T: SessionStream> Session<T> {
+    /// Runs the DLP rules, returning whether any rule matched and the quarantine
+    /// reason of the first matching rule with a quarantine action.
+    pub async fn run_dlp(&self, message: &Message<'_>) -> Result<DlpVerdict, FilterResponse> {
+        let mut verdict = DlpVerdict::default();
+        let rules = &self.server.core.smtp.session.data.dlp;
+        if rules.is_empty() || !self.is_authenticated() {
+            return Ok(verdict);
+        }
+
+        // Decode text lazily, only once a rule is enabled for this submission
+        let mut texts: Option<Vec<(bool, Cow<'_, str>)>> = None;
+
+        for rule in rules {
+            if !self
+                .server
+                .eval_if(&rule.enable, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            let texts = texts.get_or_insert_with(|| {
+                let mut texts = Vec::new();
+                extract_texts(message, &mut texts, false, 0);
+                texts
+            });
+            let matches = self
+                .match_dlp_rule(
+                    rule,
+                    texts
+                        .iter()
+                        .filter(|(is_attachment, _)| rule.attachments || !is_attachment)
+                        .map(|(_, text)| text.as_ref()),
+                )
+                .await;
+            if matches.len() < rule.threshold {
+                continue;
+            }
+            verdict.matched = true;
+
+            let details = matches
+                .iter()
+                .take(MAX_REPORTED_MATCHES)
+                .cloned()
+                .collect::<Vec<_>>();
+            trc::event!(
+                Smtp(SmtpEvent::DlpMatch),
+                SpanId = self.data.session_id,
+                Id = rule.id.clone(),
+                Total = matches.len(),
+                Details = details.clone(),
+            );
+
+            if !rule.notify.is_empty() {
+                self.notify_dlp_auditors(rule, message, &details).await;
+            }
+
+            match &rule.action {
+                DlpAction::Block { reason } => {
+                    trc::event!(
+                        Smtp(SmtpEvent::DlpBlocked),
+                        SpanId = self.data.session_id,
+                        Id = rule.id.clone(),
+                    );
+
+                    return Err(FilterResponse {
+                        message: format!("550 5.7.0 {reason}\r\n").into(),
+                        disconnect: false,
+                    });
+                }
+                DlpAction::Quarantine => {
+                    if verdict.quarantine.is_none() {
+                        verdict.quarantine = Some(format!("DLP rule {} matched", rule.id));
+                    }
+                }
+                DlpAction::Notify => {}
+            }
+        }
+
+        Ok(verdict)
+    }
+
+    async fn match_dlp_rule<'x>(
+        &self,
+        rule: &DlpRule,
+        texts: impl Iterator<Item = &'x str>,
+    ) -> Vec<String> {
+        let mut matches = Vec::new();
+        let mut words = AHashSet::new();
+
+        for text in texts {
+            for regex in &rule.regex {
+                matches.extend(regex.find_iter(text).map(|m| mask(m.as_str())));
+            }
+
+            if !rule.keywords.is_empty() || rule.dictionary.is_some() {
+                let text = text.to_lowercase();
+                for keyword in &rule.keywords {
+                    matches.extend(std::iter::repeat_n(
+                        mask(keyword),
+                        text.matches(keyword.as_str()).count(),
+                    ));
+                }
+
+                if rule.dictionary.is_some() {
+                    words.extend(
+                        text.split(|ch: char| !ch.is_alphanumeric())
+                            .filter(|word| word.len() > 2)
+                            .take(MAX_DICTIONARY_WORDS.saturating_sub(words.len()))
+                            .map(|word| word.to_string()),
+                    );
+                }
+            }
+        }
+
+        if let Some(dictionary) = &rule.dictionary {
+            if let Some(store) = self.server.core.storage.lookups.get(dictionary) {
+                // Look up words concurrently, remote dictionaries would otherwise
+                // require one round trip per word
+                let words = words.into_iter().collect::<Vec<_>>();
+                'outer: for chunk in words.chunks(DICTIONARY_BATCH_SIZE) {
+                    let mut lookups = JoinSet::new();
+                    for word in chunk {
+                        let store = store.clone();
+                        let word = word.clone();
+                        lookups.spawn(async move {
+                            let result = store.key_exists(word.as_str()).await;
+                            (word, result)
+                        });
+                    }
+
+                    for (word, result) in lookups.join_all().await {
+                        match result {
+                            Ok(true) => matches.push(mask(&word)),
+                            Ok(false) => {}
+                            Err(err) => {
+                                trc::error!(
+                                    err.span_id(self.data.session_id)
+                                        .caused_by(trc::location!())
+                                        .details("Failed to query DLP dictionary")
+                                );
+                                break 'outer;
+                            }
+                        }
+                    }
+                }
+            } else {
+                trc::event!(
+                    Smtp(SmtpEvent::Error),
+                    SpanId = self.data.session_id,
+                    Id = rule.id.clone(),
+                    Details = format!("DLP dictionary {dictionary:?} not found"),
+                );
+            }
+        }
+
+        matches
+    }
+
+    async fn notify_dlp_auditors(&self, rule: &DlpRule, message: &Message<'_>, matches: &[String]) {
+        let mail_from = self
+            .data
+            .mail_from
+            .as_ref()
+            .map(|from| from.address.as_str())
+            .unwrap_or_default();
+        let mut body = format!(
+            "A message submitted by {} matched the data loss prevention rule {:?}.\r\n\r\n",
+            self.authenticated_as()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| mail_from.to_string()),
+            rule.id
+        );
+        body.push_str(&format!("Envelope sender: <{mail_from}>\r\n"));
+        for rcpt in &self.data.rcpt_to {
+            body.push_str(&format!("Recipient: <{}>\r\n", rcpt.address));
+        }
+        if let Some(subject) = message.subject() {
+            body.push_str(&format!("Subject: {subject}\r\n"));
+        }
+        body.push_str(&format!(
+            "Action: {}\r\n\r\nMatches:\r\n",
+            match rule.action {
+                DlpAction::Block { .. } => "blocked",
+                DlpAction::Quarantine => "quarantined",
+                DlpAction::Notify => "delivered",
+            }
+        ));
+        for m in matches {
+            body.push_str(&format!("  - {m}\r\n"));
+        }
+
+        let report = MessageBuilder::new()
+            .from(rule.notify_from.as_str())
+            .header(
+                "To",
+                HeaderType::Address(Address::new_list(
+                    rule.notify
+                        .iter()
+                        .map(|to| Address::from(to.as_str()))
+                        .collect(),
+                )),
+            )
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .subject(format!("DLP alert: rule {} matched", rule.id))
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        self.server
+            .send_autogenerated(
+                rule.notify_from.clone(),
+                rule.notify.iter().cloned(),
+                report,
+                None,
+                self.data.session_id,
+            )
+            .await;
+    }
+}
+
+fn extract_texts<'x>(
+    message: &'x Message<'_>,
+    texts: &mut Vec<(bool, Cow<'x, str>)>,
+    is_attachment: bool,
+    depth: usize,
+) {
+    if let Some(subject) = message.subject() {
+        texts.push((is_attachment, subject.into()));
+    }
+
+    for (part_id, part) in message.parts.iter().enumerate() {
+        let is_attachment = is_attachment
+            || !(message.text_body.contains(&(part_id as u32))
+                || message.html_body.contains(&(part_id as u32)));
+
+        match &part.body {
+            PartType::Text(text) => texts.push((is_attachment, text.as_ref().into())),
+            PartType::Html(html) => texts.push((is_attachment, html_to_text(html).into())),
+            PartType::Binary(bytes) | PartType::InlineBinary(bytes)
+                if part.content_type().is_some_and(|ct| {
+                    ct.ctype().eq_ignore_ascii_case("text")
+                        || ct.subtype().is_some_and(|st| {
+                            ["json", "xml", "csv", "x-csv"]
+                                .iter()
+                                .any(|t| st.eq_ignore_ascii_case(t))
+                        })
+                }) =>
+            {
+                texts.push((is_attachment, String::from_utf8_lossy(bytes)));
+            }
+            PartType::Message(nested) if depth < MAX_NESTED_MESSAGES => {
+                extract_texts(nested, texts, true, depth + 1);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Avoid leaking the matched secrets into logs and auditor notifications
+fn mask(value: &str) -> String {
+    let len = value.chars().count();
+    if len > 4 {
+        value
+            .chars()
+            .enumerate()
+            .map(|(idx, ch)| if idx < len - 4 { '*' } else { ch })
+            .collect()
+    } else {
+        "*".repeat(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use super::{extract_texts, mask};
+
+    #[test]
+    fn dlp_texts_and_masking() {
+        let message = MessageParser::new()
+            .parse(concat!(
+                "From: john@example.org\r\n",
+                "Subject: Quarterly numbers\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: multipart/mixed; boundary=\"b1\"\r\n",
+                "\r\n",
+                "--b1\r\n",
+                "Content-Type: text/html\r\n",
+                "\r\n",
+                "<p>See <b>attached</b></p>\r\n",
+                "--b1\r\n",
+                "Content-Type: text/csv; name=\"numbers.csv\"\r\n",
+                "Content-Disposition: attachment; filename=\"numbers.csv\"\r\n",
+                "\r\n",
+                "account,balance\r\n",
+                "--b1\r\n",
+                "Content-Type: image/png; name=\"logo.png\"\r\n",
+                "Content-Disposition: attachment; filename=\"logo.png\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "iVBORw0KGgo=\r\n",
+                "--b1--\r\n",
+            ))
+            .unwrap();
+        let mut texts = Vec::new();
+        extract_texts(&message, &mut texts, false, 0);
+        let texts = texts
+            .iter()
+            .map(|(is_attachment, text)| (*is_attachment, text.trim()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                (false, "Quarterly numbers"),
+                (false, "See attached"),
+                (true, "account,balance"),
+            ]
+        );
+
+        assert_eq!(mask("4111111111111111"), "************1111");
+        assert_eq!(mask("top secret"), "******cret");
+        assert_eq!(mask("abcd"), "****");
+    }
+}
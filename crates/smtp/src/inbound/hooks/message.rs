@@ -9,7 +9,7 @@ use std::time::Instant;
 use ahash::AHashMap;
 use common::{
     DAEMON_NAME,
-    config::smtp::session::{MTAHook, Stage},
+    config::smtp::session::{FilterState, MTAHook, Stage},
     listener::SessionStream,
 };
 
@@ -54,8 +54,42 @@ impl<T: SessionStream> Session<T> {
                 continue;
             }
 
+            let filter_id = format!("hook.{}", mta_hook.id);
+            let state = self.server.filter_state(&filter_id);
+            if state != FilterState::Available {
+                trc::event!(
+                    MtaHook(MtaHookEvent::Unavailable),
+                    SpanId = self.data.session_id,
+                    Id = mta_hook.id.clone(),
+                    Details = if state == FilterState::Disabled {
+                        "disabled"
+                    } else {
+                        "circuit-open"
+                    },
+                );
+
+                if mta_hook.fail_closed.contains(&stage) {
+                    return Err(FilterResponse::server_failure());
+                }
+                continue;
+            }
+
             let time = Instant::now();
-            match self.run_mta_hook(stage, mta_hook, message, queue_id).await {
+            let result = self.run_mta_hook(stage, mta_hook, message, queue_id).await;
+            if self.server.record_filter_result(
+                &filter_id,
+                time.elapsed(),
+                result.is_err(),
+                mta_hook.circuit_breaker.as_ref(),
+            ) {
+                trc::event!(
+                    MtaHook(MtaHookEvent::CircuitOpen),
+                    SpanId = self.data.session_id,
+                    Id = mta_hook.id.clone(),
+                );
+            }
+
+            match result {
                 Ok(response) => {
                     trc::event!(
                         MtaHook(match response.action {
@@ -92,6 +126,11 @@ impl<T: SessionStream> Session<T> {
                                     value: value.as_bytes().to_vec(),
                                 }
                             }
+                            super::Modification::ReplaceMessage { value } => {
+                                Modification::ReplaceMessage {
+                                    value: value.into_bytes(),
+                                }
+                            }
                             super::Modification::AddHeader { name, value } => {
                                 Modification::AddHeader { name, value }
                             }
@@ -114,12 +153,20 @@ impl<T: SessionStream> Session<T> {
                     if !modifications.is_empty() {
                         // The message body can only be replaced once, so we need to remove
                         // any previous replacements.
-                        if new_modifications
-                            .iter()
-                            .any(|m| matches!(m, Modification::ReplaceBody { .. }))
-                        {
-                            modifications
-                                .retain(|m| !matches!(m, Modification::ReplaceBody { .. }));
+                        if new_modifications.iter().any(|m| {
+                            matches!(
+                                m,
+                                Modification::ReplaceBody { .. }
+                                    | Modification::ReplaceMessage { .. }
+                            )
+                        }) {
+                            modifications.retain(|m| {
+                                !matches!(
+                                    m,
+                                    Modification::ReplaceBody { .. }
+                                        | Modification::ReplaceMessage { .. }
+                                )
+                            });
                         }
                         modifications.extend(new_modifications);
                     } else {
@@ -131,9 +178,14 @@ impl<T: SessionStream> Session<T> {
                         Action::Discard => FilterResponse::accept(),
                         Action::Reject => FilterResponse::reject(),
                         Action::Quarantine => {
-                            modifications.push(Modification::AddHeader {
-                                name: "X-Quarantine".into(),
-                                value: "true".into(),
+                            modifications.push(Modification::Quarantine {
+                                reason: response
+                                    .response
+                                    .as_ref()
+                                    .and_then(|response| response.message.as_ref())
+                                    .filter(|reason| !reason.is_empty())
+                                    .cloned()
+                                    .unwrap_or_else(|| "true".into()),
                             });
                             FilterResponse::accept()
                         }
@@ -161,7 +213,7 @@ impl<T: SessionStream> Session<T> {
                         Elapsed = time.elapsed(),
                     );
 
-                    if mta_hook.tempfail_on_error {
+                    if mta_hook.fail_closed.contains(&stage) {
                         return Err(FilterResponse::server_failure());
                     }
                 }
@@ -246,6 +298,9 @@ impl<T: SessionStream> Session<T> {
                     .collect(),
                 server_headers: vec![],
                 contents: String::from_utf8_lossy(message.raw_body()).into_owned(),
+                raw: mta_hook
+                    .full_message
+                    .then(|| String::from_utf8_lossy(message.raw_message()).into_owned()),
                 size: message.raw_message().len(),
             }),
         };
@@ -120,6 +120,9 @@ pub struct Message {
     #[serde(default)]
     pub server_headers: Vec<(String, String)>,
     pub contents: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub raw: Option<String>,
     pub size: usize,
 }
 
@@ -175,6 +178,8 @@ pub enum Modification {
     DeleteRecipient { value: String },
     #[serde(rename = "replaceContents")]
     ReplaceContents { value: String },
+    #[serde(rename = "replaceMessage")]
+    ReplaceMessage { value: String },
     #[serde(rename = "addHeader")]
     AddHeader { name: String, value: String },
     #[serde(rename = "insertHeader")]
@@ -0,0 +1,333 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Duration};
+
+use ahash::AHashSet;
+use common::{
+    Server,
+    config::smtp::session::{AttachmentAction, AttachmentRule},
+    expr::if_block::IfBlock,
+    listener::SessionStream,
+};
+use mail_builder::headers::date::Date;
+use mail_parser::{MessageParser, MimeHeaders};
+use store::{
+    BlobStore, IterateParams, SerializeInfallible, U32_LEN, U64_LEN, ValueKey,
+    write::{BatchBuilder, BlobOp, ValueClass, key::DeserializeBigEndian, now},
+};
+use trc::{AddContext, SmtpEvent};
+use utils::{
+    BLOB_HASH_LEN, BlobHash,
+    crypto::{derive_key, sign_token, verify_token},
+};
+
+use crate::{core::Session, inbound::disarm::AttachmentDisarmer};
+
+const TOKEN_CONTEXT: &str = "large-files";
+const TOKEN_LEN: usize = BLOB_HASH_LEN + std::mem::size_of::<u64>();
+
+// Files kept in a dedicated blob store are reserved under this account id, which
+// is outside the range of reservations purged along with the default blob store
+const LARGE_FILES_ACCOUNT_ID: u32 = u32::MAX;
+
+pub trait SmtpLargeFiles: Sync + Send {
+    fn store_large_file(
+        &self,
+        blob_store: Option<&BlobStore>,
+        hash: &BlobHash,
+        contents: &[u8],
+        expires: u64,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn purge_large_files(&self) -> impl Future<Output = trc::Result<usize>> + Send;
+}
+
+impl<T: SessionStream> Session<T> {
+    pub async fn apply_large_file_links(&self, raw_message: &[u8]) -> Option<Vec<u8>> {
+        let config = &self.server.core.smtp.session.data.large_files;
+        if !self.is_authenticated() {
+            return None;
+        }
+
+        // Thresholds and link templates are evaluated per sender domain
+        let threshold = self
+            .server
+            .eval_if::<usize, _>(&config.threshold, self, self.data.session_id)
+            .await
+            .filter(|threshold| raw_message.len() > *threshold)?;
+        let url = self
+            .server
+            .eval_if::<String, _>(&config.url, self, self.data.session_id)
+            .await?;
+        let expiry = self
+            .server
+            .eval_if::<Duration, _>(&config.expiry, self, self.data.session_id)
+            .await
+            .unwrap_or(Duration::from_secs(7 * 86400));
+        let blob_store = if let Some(store_id) = &config.store {
+            if let Some(store) = self.server.core.storage.blobs.get(store_id) {
+                Some(store)
+            } else {
+                trc::event!(
+                    Smtp(SmtpEvent::Error),
+                    SpanId = self.data.session_id,
+                    Details = format!("Large file blob store {store_id:?} not found"),
+                );
+                return None;
+            }
+        } else {
+            None
+        };
+
+        let message = MessageParser::new().parse(raw_message)?;
+        let expires = now() + expiry.as_secs();
+        let mut rules = Vec::new();
+
+        for part_id in &message.attachments {
+            let Some(part) = message.parts.get(*part_id as usize) else {
+                continue;
+            };
+            let contents = part.contents();
+            if contents.len() < config.min_size {
+                continue;
+            }
+
+            let hash = BlobHash::generate(contents);
+            if let Err(err) = self
+                .server
+                .store_large_file(blob_store, &hash, contents, expires)
+                .await
+            {
+                trc::error!(
+                    err.span_id(self.data.session_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to store large attachment")
+                );
+                return None;
+            }
+
+            let name = part.attachment_name().unwrap_or("attachment");
+            let link = url
+                .replace("{token}", &large_file_token(&self.server, &hash, expires))
+                .replace("{name}", &encode_path(name));
+
+            trc::event!(
+                Smtp(SmtpEvent::AttachmentLinked),
+                SpanId = self.data.session_id,
+                Details = name.to_string(),
+                Size = contents.len(),
+                Expires = trc::Value::Timestamp(expires),
+            );
+
+            rules.push((
+                *part_id,
+                AttachmentRule {
+                    id: "large-files".to_string(),
+                    enable: IfBlock::empty("session.data.large-files"),
+                    extensions: Default::default(),
+                    content_types: Default::default(),
+                    file_types: Default::default(),
+                    archive_depth: 0,
+                    action: AttachmentAction::Drop,
+                    notify: format!(
+                        "The attachment \"{{name}}\" ({} bytes) was too large to be sent and can be downloaded until {} from:\r\n{link}\r\n",
+                        contents.len(),
+                        Date::new(expires as i64).to_rfc822()
+                    )
+                    .into(),
+                },
+                name.to_string(),
+            ));
+        }
+
+        if !rules.is_empty() {
+            AttachmentDisarmer::with_matches(
+                &message,
+                rules
+                    .iter()
+                    .map(|(part_id, rule, name)| (*part_id, rule, name.clone()))
+                    .collect(),
+            )
+            .build()
+        } else {
+            None
+        }
+    }
+}
+
+impl SmtpLargeFiles for Server {
+    async fn store_large_file(
+        &self,
+        blob_store: Option<&BlobStore>,
+        hash: &BlobHash,
+        contents: &[u8],
+        expires: u64,
+    ) -> trc::Result<()> {
+        // Keep the blob around until the link expires
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(if blob_store.is_some() {
+                LARGE_FILES_ACCOUNT_ID
+            } else {
+                0
+            })
+            .set(
+                BlobOp::Reserve {
+                    hash: hash.clone(),
+                    until: expires,
+                },
+                0u32.serialize(),
+            );
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
+        if let Some(blob_store) = blob_store {
+            return blob_store
+                .put_blob(hash.as_slice(), contents)
+                .await
+                .caused_by(trc::location!());
+        }
+
+        // Blobs in the default store are committed so they are purged once
+        // the reservation expires, identical contents are stored only once
+        if !self
+            .store()
+            .blob_exists(hash)
+            .await
+            .caused_by(trc::location!())?
+        {
+            self.blob_store()
+                .put_blob(hash.as_slice(), contents)
+                .await
+                .caused_by(trc::location!())?;
+        }
+        let mut batch = BatchBuilder::new();
+        batch.set(BlobOp::Commit { hash: hash.clone() }, now().serialize());
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn purge_large_files(&self) -> trc::Result<usize> {
+        let Some(blob_store) = self
+            .core
+            .smtp
+            .session
+            .data
+            .large_files
+            .store
+            .as_ref()
+            .and_then(|store_id| self.core.storage.blobs.get(store_id))
+        else {
+            return Ok(0);
+        };
+
+        let now = now();
+        let mut expired = Vec::new();
+        let mut active = AHashSet::new();
+        self.store()
+            .iterate(
+                IterateParams::new(
+                    ValueKey {
+                        account_id: LARGE_FILES_ACCOUNT_ID,
+                        collection: 0,
+                        document_id: 0,
+                        class: ValueClass::Blob(BlobOp::Reserve {
+                            hash: BlobHash::default(),
+                            until: 0,
+                        }),
+                    },
+                    ValueKey {
+                        account_id: LARGE_FILES_ACCOUNT_ID,
+                        collection: 0,
+                        document_id: 0,
+                        class: ValueClass::Blob(BlobOp::Reserve {
+                            hash: BlobHash::new_max(),
+                            until: u64::MAX,
+                        }),
+                    },
+                )
+                .ascending()
+                .no_values(),
+                |key, _| {
+                    let hash = key
+                        .get(U32_LEN..U32_LEN + BLOB_HASH_LEN)
+                        .and_then(|hash| BlobHash::try_from_hash_slice(hash).ok())
+                        .ok_or_else(|| trc::Error::corrupted_key(key, None, trc::location!()))?;
+                    let until = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                    if until <= now {
+                        expired.push((hash, until));
+                    } else {
+                        active.insert(hash);
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Files shared by several links are deleted once all of them have expired
+        let mut total = 0;
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(LARGE_FILES_ACCOUNT_ID);
+        for (hash, until) in expired {
+            if !active.contains(&hash) {
+                if blob_store
+                    .delete_blob(hash.as_slice())
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    total += 1;
+                }
+                active.insert(hash.clone());
+            }
+            batch.clear(BlobOp::Reserve { hash, until });
+        }
+        if !batch.is_empty() {
+            self.store()
+                .write(batch.build_all())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(total)
+    }
+}
+
+/// Builds a signed token that grants access to a large file until it expires.
+pub fn large_file_token(server: &Server, hash: &BlobHash, expires: u64) -> String {
+    let mut token = Vec::with_capacity(TOKEN_LEN);
+    token.extend_from_slice(hash.as_slice());
+    token.extend_from_slice(&expires.to_be_bytes());
+    sign_token(&large_file_key(server), &token)
+}
+
+/// Validates a large file token, returning the blob hash if it has not expired.
+pub fn parse_large_file_token(server: &Server, token: &str) -> Option<BlobHash> {
+    let token = verify_token(&large_file_key(server), token, TOKEN_LEN)?;
+    let (hash, expires) = token.split_at(BLOB_HASH_LEN);
+    if u64::from_be_bytes(expires.try_into().ok()?) < now() {
+        return None;
+    }
+
+    BlobHash::try_from_hash_slice(hash).ok()
+}
+
+fn large_file_key(server: &Server) -> [u8; 32] {
+    derive_key(&server.core.oauth.oauth_key, TOKEN_CONTEXT)
+}
+
+fn encode_path(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}
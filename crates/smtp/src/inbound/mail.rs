@@ -505,6 +505,12 @@ impl<T: SessionStream> Session<T> {
                 }
             }
 
+            // Enforce the sending limits of the authenticated account
+            if let Some(response) = self.check_sending_limits().await {
+                self.data.mail_from = None;
+                return self.write(response).await;
+            }
+
             trc::event!(
                 Smtp(SmtpEvent::MailFrom),
                 SpanId = self.data.session_id,
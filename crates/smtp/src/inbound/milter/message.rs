@@ -8,7 +8,7 @@ use std::{borrow::Cow, time::Instant};
 
 use common::{
     DAEMON_NAME,
-    config::smtp::session::{Milter, Stage},
+    config::smtp::session::{FilterState, Milter, Stage},
     listener::SessionStream,
 };
 
@@ -53,8 +53,42 @@ impl<T: SessionStream> Session<T> {
                 continue;
             }
 
+            let filter_id = format!("milter.{}", milter.id);
+            let state = self.server.filter_state(&filter_id);
+            if state != FilterState::Available {
+                trc::event!(
+                    Milter(MilterEvent::Unavailable),
+                    SpanId = self.data.session_id,
+                    Id = milter.id.to_string(),
+                    Details = if state == FilterState::Disabled {
+                        "disabled"
+                    } else {
+                        "circuit-open"
+                    },
+                );
+
+                if milter.fail_closed.contains(&stage) {
+                    return Err(FilterResponse::server_failure());
+                }
+                continue;
+            }
+
             let time = Instant::now();
-            match self.connect_and_run(milter, message).await {
+            let result = self.connect_and_run(milter, message).await;
+            if self.server.record_filter_result(
+                &filter_id,
+                time.elapsed(),
+                matches!(result, Err(Rejection::Error(_))),
+                milter.circuit_breaker.as_ref(),
+            ) {
+                trc::event!(
+                    Milter(MilterEvent::CircuitOpen),
+                    SpanId = self.data.session_id,
+                    Id = milter.id.to_string(),
+                );
+            }
+
+            match result {
                 Ok(new_modifications) => {
                     trc::event!(
                         Milter(MilterEvent::ActionAccept),
@@ -146,7 +180,7 @@ impl<T: SessionStream> Session<T> {
                         Elapsed = time.elapsed(),
                     );
 
-                    if milter.tempfail_on_error {
+                    if milter.fail_closed.contains(&stage) {
                         return Err(FilterResponse::server_failure());
                     }
                 }
@@ -297,6 +331,7 @@ impl SessionData {
         message: &AuthenticatedMessage<'_>,
     ) -> Option<Vec<u8>> {
         let mut body = Vec::new();
+        let mut replaced_message = None;
         let mut header_changes = Vec::new();
         let mut needs_rewrite = false;
 
@@ -381,6 +416,9 @@ impl SessionData {
                 Modification::ReplaceBody { value } => {
                     body.extend(value);
                 }
+                Modification::ReplaceMessage { value } => {
+                    replaced_message = Some(value);
+                }
                 Modification::AddHeader { name, value } => {
                     header_changes.push((0, name, value, false));
                 }
@@ -407,6 +445,24 @@ impl SessionData {
             }
         }
 
+        // A rewritten message replaces both headers and body, only headers
+        // added on top of it are preserved
+        if let Some(replaced_message) = replaced_message {
+            let mut new_message = Vec::with_capacity(replaced_message.len() + 64);
+            for (index, header, value, is_change) in header_changes {
+                if index == 0 && !is_change {
+                    new_message.extend_from_slice(header.as_bytes());
+                    new_message.extend_from_slice(b": ");
+                    new_message.extend_from_slice(value.as_bytes());
+                    if !value.ends_with('\n') {
+                        new_message.extend_from_slice(b"\r\n");
+                    }
+                }
+            }
+            new_message.extend(replaced_message);
+            return Some(new_message);
+        }
+
         // If there are no header changes return
         if header_changes.is_empty() {
             return if !body.is_empty() {
@@ -125,6 +125,9 @@ pub enum Modification {
     ReplaceBody {
         value: Vec<u8>,
     },
+    ReplaceMessage {
+        value: Vec<u8>,
+    },
     AddHeader {
         name: String,
         value: String,
@@ -320,6 +323,9 @@ impl Display for Modification {
             Modification::ReplaceBody { value } => {
                 write!(f, "REPLACE_BODY ({} bytes)", value.len())
             }
+            Modification::ReplaceMessage { value } => {
+                write!(f, "REPLACE_MESSAGE ({} bytes)", value.len())
+            }
             Modification::AddHeader { name, value } => {
                 write!(f, "ADD_HEADER ({}: {})", name, value)
             }
@@ -400,6 +400,9 @@ impl Response {
                     buf.extend(value);
                     buf
                 }
+                Modification::ReplaceMessage { .. } => {
+                    unreachable!("Full message replacements are only returned by MTA hooks")
+                }
                 Modification::AddHeader { name, value } => {
                     let mut buf =
                         Command::build(SMFIR_ADDHEADER, name.len() as u32 + value.len() as u32 + 2);
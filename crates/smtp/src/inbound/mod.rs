@@ -12,14 +12,25 @@ use mail_auth::{
     SpfResult, arc::ArcSet, dkim::Signature, dmarc::Policy,
 };
 
+pub mod anomaly;
+pub mod antivirus;
 pub mod auth;
 pub mod data;
+pub mod disarm;
+pub mod dlp;
 pub mod ehlo;
 pub mod hooks;
+pub mod large_files;
 pub mod mail;
 pub mod milter;
+pub mod moderation;
+pub mod proxy;
 pub mod rcpt;
+pub mod rewrite;
+pub mod sandbox;
+pub mod sending;
 pub mod session;
+pub mod simulate;
 pub mod spam;
 pub mod spawn;
 pub mod vrfy;
@@ -0,0 +1,105 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::listener::SessionStream;
+use mail_builder::{MessageBuilder, headers::HeaderType};
+use mail_parser::Message;
+
+use crate::{
+    core::Session,
+    queue::{self, quarantine::moderation_instructions},
+};
+
+impl<T: SessionStream> Session<T> {
+    /// Returns the moderator address when the submission has to be held for approval.
+    pub async fn moderator_for(
+        &self,
+        message: &Message<'_>,
+        has_dlp_matches: bool,
+    ) -> Option<String> {
+        let config = &self.server.core.smtp.session.data.moderation;
+        if !self.is_authenticated() {
+            return None;
+        }
+        let moderator = self
+            .server
+            .eval_if::<String, _>(&config.moderator, self, self.data.session_id)
+            .await
+            .filter(|moderator| moderator.contains('@'))?;
+
+        // Without any criteria all submissions are moderated
+        if !config.external_recipients && !config.attachments && !config.dlp_hits {
+            return Some(moderator);
+        }
+        if (config.attachments && !message.attachments.is_empty())
+            || (config.dlp_hits && has_dlp_matches)
+        {
+            return Some(moderator);
+        }
+        if config.external_recipients {
+            for rcpt in &self.data.rcpt_to {
+                match self
+                    .server
+                    .core
+                    .storage
+                    .directory
+                    .is_local_domain(&rcpt.domain)
+                    .await
+                {
+                    Ok(true) => {}
+                    Ok(false) => return Some(moderator),
+                    Err(err) => {
+                        trc::error!(
+                            err.span_id(self.data.session_id)
+                                .caused_by(trc::location!())
+                                .details("Failed to lookup local domain")
+                        );
+                        return Some(moderator);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Builds the approval request sent to the moderator of a held message.
+    pub fn build_moderation_request(
+        &self,
+        message: &queue::Message,
+        moderator: &str,
+        subject: Option<&str>,
+    ) -> Vec<u8> {
+        let config = &self.server.core.smtp.session.data.moderation;
+        let mut body = format!(
+            "A message submitted by {} is awaiting your approval.\r\n\r\n",
+            self.authenticated_as()
+                .unwrap_or(message.return_path.as_str())
+        );
+        body.push_str(&format!("Queue id: {:x}\r\n", message.queue_id));
+        body.push_str(&format!("Sender: <{}>\r\n", message.return_path));
+        for rcpt in &message.recipients {
+            body.push_str(&format!("Recipient: <{}>\r\n", rcpt.address));
+        }
+        if let Some(subject) = subject {
+            body.push_str(&format!("Subject: {subject}\r\n"));
+        }
+        body.push_str(&format!("Size: {} bytes\r\n\r\n", message.size));
+        body.push_str(&moderation_instructions(&self.server, message));
+
+        MessageBuilder::new()
+            .from(config.from.as_str())
+            .to(moderator)
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .subject(format!(
+                "Approval required: {}",
+                subject.unwrap_or("(no subject)")
+            ))
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default()
+    }
+}
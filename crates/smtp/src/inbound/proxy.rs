@@ -0,0 +1,416 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    fmt::Write,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use common::{
+    config::smtp::session::{ProxyForward, ProxyPool},
+    listener::SessionStream,
+};
+use smtp_proto::{
+    MAIL_BODY_8BITMIME, MAIL_BODY_BINARYMIME, MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS,
+    MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use trc::{NetworkEvent, SmtpEvent};
+
+use crate::core::Session;
+
+const MAX_REPLY_SIZE: usize = 8192;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_proxy(&mut self, pool_id: &str) -> Result<(), ()> {
+        let Some(pool) = self
+            .server
+            .core
+            .smtp
+            .session
+            .proxy
+            .pools
+            .iter()
+            .find(|pool| pool.id == pool_id)
+        else {
+            trc::event!(
+                Smtp(SmtpEvent::ProxyError),
+                SpanId = self.data.session_id,
+                Id = pool_id.to_string(),
+                Reason = "Backend pool not found",
+            );
+            self.data.rcpt_to.pop();
+            return self
+                .write(b"451 4.3.0 Unable to relay transaction, please try again later.\r\n")
+                .await;
+        };
+
+        // Pick a pool member using the recipient domain, so the same domain
+        // consistently lands on the same backend while it is available
+        let domain = self
+            .data
+            .rcpt_to
+            .last()
+            .map(|rcpt| rcpt.domain.as_str())
+            .unwrap_or_default();
+        let start = domain.bytes().fold(0usize, |hash, ch| {
+            hash.wrapping_mul(31).wrapping_add(ch as usize)
+        }) % pool.hosts.len();
+
+        for host in pool.hosts.iter().cycle().skip(start).take(pool.hosts.len()) {
+            match self.proxy_connect(pool, host).await {
+                Ok((stream, reply)) => {
+                    if reply.starts_with(b"2") {
+                        trc::event!(
+                            Smtp(SmtpEvent::ProxyConnected),
+                            SpanId = self.data.session_id,
+                            Id = pool.id.clone(),
+                            Hostname = host.clone(),
+                            To = domain.to_string(),
+                        );
+                        self.data.proxy = Some(stream);
+                    } else {
+                        // The backend rejected the recipient, keep the session local
+                        self.data.rcpt_to.pop();
+                    }
+
+                    return self.write(&reply).await;
+                }
+                Err(err) => {
+                    trc::event!(
+                        Smtp(SmtpEvent::ProxyError),
+                        SpanId = self.data.session_id,
+                        Id = pool.id.clone(),
+                        Hostname = host.clone(),
+                        Reason = err,
+                    );
+                }
+            }
+        }
+
+        self.data.rcpt_to.pop();
+        self.write(b"451 4.4.1 Unable to relay transaction, please try again later.\r\n")
+            .await
+    }
+
+    async fn proxy_connect(
+        &self,
+        pool: &ProxyPool,
+        host: &str,
+    ) -> Result<(TcpStream, Vec<u8>), String> {
+        let mut stream = tokio::time::timeout(pool.timeout_connect, TcpStream::connect(host))
+            .await
+            .map_err(|_| "Connection timed out".to_string())?
+            .map_err(|err| format!("Failed to connect: {err}"))?;
+        let timeout = pool.timeout_command;
+
+        // Forward the client's address using the PROXY protocol
+        if pool.forward == ProxyForward::ProxyProtocol {
+            let family = if self.data.remote_ip.is_ipv4() && self.data.local_ip.is_ipv4() {
+                "TCP4"
+            } else {
+                "TCP6"
+            };
+            let header = format!(
+                "PROXY {family} {} {} {} {}\r\n",
+                proxy_addr(self.data.remote_ip, family),
+                proxy_addr(self.data.local_ip, family),
+                self.data.remote_port,
+                self.data.local_port
+            );
+            write_command(&mut stream, header.as_bytes(), timeout).await?;
+        }
+
+        // Greeting
+        expect_reply(&mut stream, timeout, b'2').await?;
+
+        // Forward the client's address, HELO and authenticated user using XCLIENT
+        let helo = if !self.data.helo_domain.is_empty() {
+            self.data.helo_domain.as_str()
+        } else {
+            self.hostname.as_str()
+        };
+        if pool.forward == ProxyForward::Xclient {
+            write_command(
+                &mut stream,
+                format!("EHLO {}\r\n", self.hostname).as_bytes(),
+                timeout,
+            )
+            .await?;
+            let capabilities = expect_reply(&mut stream, timeout, b'2').await?;
+            if !capabilities.split(|&ch| ch == b'\n').any(|line| {
+                line.get(4..11)
+                    .is_some_and(|cap| cap.eq_ignore_ascii_case(b"XCLIENT"))
+            }) {
+                return Err("Backend does not support XCLIENT".to_string());
+            }
+
+            let mut command = String::with_capacity(128);
+            command.push_str("XCLIENT ADDR=");
+            if self.data.remote_ip.is_ipv6() {
+                command.push_str("IPV6:");
+            }
+            let _ = write!(
+                command,
+                "{} PORT={} PROTO=ESMTP HELO=",
+                self.data.remote_ip, self.data.remote_port
+            );
+            xtext_encode(&mut command, helo);
+            if let Some(login) = self.authenticated_as() {
+                command.push_str(" LOGIN=");
+                xtext_encode(&mut command, login);
+            }
+            command.push_str("\r\n");
+            write_command(&mut stream, command.as_bytes(), timeout).await?;
+            expect_reply(&mut stream, timeout, b'2').await?;
+        }
+
+        write_command(&mut stream, format!("EHLO {helo}\r\n").as_bytes(), timeout).await?;
+        expect_reply(&mut stream, timeout, b'2').await?;
+
+        // Replay the transaction
+        let mail_from = self
+            .data
+            .mail_from
+            .as_ref()
+            .ok_or_else(|| "Missing sender".to_string())?;
+        let mut command = format!("MAIL FROM:<{}>", mail_from.address);
+        if mail_from.flags & MAIL_BODY_8BITMIME != 0 {
+            command.push_str(" BODY=8BITMIME");
+        } else if mail_from.flags & MAIL_BODY_BINARYMIME != 0 {
+            command.push_str(" BODY=BINARYMIME");
+        }
+        if mail_from.flags & MAIL_SMTPUTF8 != 0 {
+            command.push_str(" SMTPUTF8");
+        }
+        if mail_from.flags & MAIL_REQUIRETLS != 0 {
+            command.push_str(" REQUIRETLS");
+        }
+        if mail_from.flags & MAIL_RET_FULL != 0 {
+            command.push_str(" RET=FULL");
+        } else if mail_from.flags & MAIL_RET_HDRS != 0 {
+            command.push_str(" RET=HDRS");
+        }
+        if let Some(envid) = &mail_from.dsn_info {
+            command.push_str(" ENVID=");
+            xtext_encode(&mut command, envid);
+        }
+        command.push_str("\r\n");
+        write_command(&mut stream, command.as_bytes(), timeout).await?;
+        expect_reply(&mut stream, timeout, b'2').await?;
+
+        let rcpt = self
+            .data
+            .rcpt_to
+            .last()
+            .ok_or_else(|| "Missing recipient".to_string())?;
+        let mut command = format!("RCPT TO:<{}>", rcpt.address);
+        let notify = [
+            (RCPT_NOTIFY_NEVER, "NEVER"),
+            (RCPT_NOTIFY_SUCCESS, "SUCCESS"),
+            (RCPT_NOTIFY_FAILURE, "FAILURE"),
+            (RCPT_NOTIFY_DELAY, "DELAY"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| rcpt.flags & flag != 0)
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>();
+        if !notify.is_empty() {
+            command.push_str(" NOTIFY=");
+            command.push_str(&notify.join(","));
+        }
+        if let Some(orcpt) = &rcpt.dsn_info {
+            command.push_str(" ORCPT=rfc822;");
+            xtext_encode(&mut command, orcpt);
+        }
+        command.push_str("\r\n");
+        write_command(&mut stream, command.as_bytes(), timeout).await?;
+
+        read_reply(&mut stream, timeout)
+            .await
+            .map(|reply| (stream, reply))
+    }
+
+    // Relays the rest of the session between the client and the backend. The
+    // inactivity timeout, transfer quota and duration limits of the session keep
+    // applying to the client, everything else is left to the backend.
+    pub async fn proxy_relay(&mut self, mut backend: TcpStream, pending: &[u8]) {
+        let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut client_buf = vec![0; 8192];
+        let mut backend_buf = vec![0; 8192];
+        let mut relayed = pending.len();
+
+        // Forward any pipelined commands received along with the first recipient
+        if !pending.is_empty() && backend.write_all(pending).await.is_err() {
+            return;
+        }
+
+        loop {
+            tokio::select! {
+                result = tokio::time::timeout(self.params.timeout, self.read(&mut client_buf)) => {
+                    match result {
+                        Ok(Ok(bytes_read)) if bytes_read > 0 => {
+                            if bytes_read > self.data.bytes_left {
+                                self.write(format!("452 4.7.28 {} Session exceeded transfer quota.\r\n", self.hostname).as_bytes())
+                                    .await
+                                    .ok();
+                                trc::event!(
+                                    Smtp(SmtpEvent::TransferLimitExceeded),
+                                    SpanId = self.data.session_id,
+                                );
+                                break;
+                            } else if Instant::now() >= self.data.valid_until {
+                                self.write(format!("421 4.3.2 {} Session open for too long.\r\n", self.hostname).as_bytes())
+                                    .await
+                                    .ok();
+                                trc::event!(
+                                    Smtp(SmtpEvent::TimeLimitExceeded),
+                                    SpanId = self.data.session_id,
+                                );
+                                break;
+                            }
+
+                            self.data.bytes_left -= bytes_read;
+                            relayed += bytes_read;
+                            if let Err(err) = backend.write_all(&client_buf[..bytes_read]).await {
+                                trc::event!(
+                                    Smtp(SmtpEvent::ProxyError),
+                                    SpanId = self.data.session_id,
+                                    Reason = err.to_string(),
+                                );
+                                break;
+                            }
+                        }
+                        Ok(_) => {
+                            break;
+                        }
+                        Err(_) => {
+                            trc::event!(
+                                Network(NetworkEvent::Timeout),
+                                SpanId = self.data.session_id,
+                                CausedBy = trc::location!()
+                            );
+                            self.write(format!("221 2.0.0 {} Disconnecting inactive client.\r\n", self.hostname).as_bytes())
+                                .await
+                                .ok();
+                            break;
+                        }
+                    }
+                },
+                result = backend.read(&mut backend_buf) => {
+                    match result {
+                        Ok(bytes_read) if bytes_read > 0 => {
+                            relayed += bytes_read;
+                            if self.write(&backend_buf[..bytes_read]).await.is_err() {
+                                break;
+                            }
+                        }
+                        Ok(_) => {
+                            break;
+                        }
+                        Err(err) => {
+                            trc::event!(
+                                Smtp(SmtpEvent::ProxyError),
+                                SpanId = self.data.session_id,
+                                Reason = err.to_string(),
+                            );
+                            break;
+                        }
+                    }
+                },
+                _ = shutdown_rx.changed() => {
+                    self.write(format!("421 4.3.0 {} Server shutting down.\r\n", self.hostname).as_bytes())
+                        .await
+                        .ok();
+                    break;
+                }
+            }
+        }
+
+        trc::event!(
+            Network(NetworkEvent::Closed),
+            SpanId = self.data.session_id,
+            Size = relayed,
+        );
+    }
+}
+
+async fn write_command(
+    stream: &mut TcpStream,
+    command: &[u8],
+    timeout: Duration,
+) -> Result<(), String> {
+    tokio::time::timeout(timeout, stream.write_all(command))
+        .await
+        .map_err(|_| "Write timed out".to_string())?
+        .map_err(|err| format!("Failed to write to backend: {err}"))
+}
+
+async fn read_reply(stream: &mut TcpStream, timeout: Duration) -> Result<Vec<u8>, String> {
+    let mut reply = Vec::with_capacity(128);
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let bytes_read = tokio::time::timeout(timeout, stream.read(&mut buf))
+            .await
+            .map_err(|_| "Read timed out".to_string())?
+            .map_err(|err| format!("Failed to read from backend: {err}"))?;
+        if bytes_read == 0 {
+            return Err("Connection closed by backend".to_string());
+        }
+        reply.extend_from_slice(&buf[..bytes_read]);
+
+        // Multi-line replies are complete once a line has a space after the code
+        if let Some(last_line) = reply
+            .strip_suffix(b"\r\n")
+            .map(|reply| reply.rsplit(|&ch| ch == b'\n').next().unwrap_or_default())
+        {
+            if last_line.len() < 4 || last_line[3] == b' ' {
+                return Ok(reply);
+            }
+        }
+        if reply.len() > MAX_REPLY_SIZE {
+            return Err("Backend reply too long".to_string());
+        }
+    }
+}
+
+async fn expect_reply(
+    stream: &mut TcpStream,
+    timeout: Duration,
+    code: u8,
+) -> Result<Vec<u8>, String> {
+    let reply = read_reply(stream, timeout).await?;
+    if reply.first() == Some(&code) {
+        Ok(reply)
+    } else {
+        Err(format!(
+            "Unexpected backend reply: {}",
+            String::from_utf8_lossy(&reply).trim_end()
+        ))
+    }
+}
+
+fn proxy_addr(ip: IpAddr, family: &str) -> String {
+    match ip {
+        IpAddr::V4(ip) if family == "TCP6" => ip.to_ipv6_mapped().to_string(),
+        ip => ip.to_string(),
+    }
+}
+
+fn xtext_encode(buf: &mut String, value: &str) {
+    for ch in value.bytes() {
+        if (33..=126).contains(&ch) && ch != b'+' && ch != b'=' {
+            buf.push(ch as char);
+        } else {
+            let _ = write!(buf, "+{ch:02X}");
+        }
+    }
+}
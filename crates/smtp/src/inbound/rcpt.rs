@@ -8,7 +8,7 @@ use common::{
     KV_GREYLIST, config::smtp::session::Stage, listener::SessionStream, scripts::ScriptModification,
 };
 
-use directory::backend::RcptType;
+use directory::{Directory, backend::RcptType};
 use smtp_proto::{
     RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS, RcptTo,
 };
@@ -16,8 +16,9 @@ use store::dispatch::lookup::KeyValue;
 use trc::{SecurityEvent, SmtpEvent};
 
 use crate::{
-    core::{Session, SessionAddress},
+    core::{ListExpansion, Session, SessionAddress},
     queue::DomainPart,
+    reporting::bounce::VerpBounce,
     scripts::ScriptResult,
 };
 
@@ -52,6 +53,8 @@ impl<T: SessionStream> Session<T> {
                 Limit = self.params.rcpt_max,
             );
             return self.write(b"455 4.5.3 Too many recipients.\r\n").await;
+        } else if let Some(response) = self.exceeds_recipient_allowance() {
+            return self.write(response).await;
         }
 
         // Verify parameters
@@ -181,9 +184,20 @@ impl<T: SessionStream> Session<T> {
                 }
             }
 
-            // Check for duplicates
+            // Check for duplicates, including addresses that normalize to the same mailbox
             let rcpt = self.data.rcpt_to.last().unwrap();
-            if self.data.rcpt_to.iter().filter(|r| r == &rcpt).count() > 1 {
+            let rcpt_config = &self.server.core.smtp.session.rcpt;
+            let normalized = rcpt_config.normalize_address(&rcpt.address_lcase);
+            if self
+                .data
+                .rcpt_to
+                .iter()
+                .filter(|r| {
+                    r == &rcpt || rcpt_config.normalize_address(&r.address_lcase) == normalized
+                })
+                .count()
+                > 1
+            {
                 trc::event!(
                     Smtp(SmtpEvent::RcptToDuplicate),
                     SpanId = self.data.session_id,
@@ -195,10 +209,38 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Proxy the transaction to a backend pool. This happens before the recipient
+        // is verified locally: the backend owns the directory and is responsible for
+        // verifying recipients and enforcing rate limits, quotas and message policy.
+        // The front node only applies the connect, EHLO, AUTH and MAIL FROM stages.
+        if self.data.rcpt_to.len() == 1 && !self.server.core.smtp.session.proxy.backend.is_empty() {
+            if let Some(pool) = self
+                .server
+                .eval_if::<String, _>(
+                    &self.server.core.smtp.session.proxy.backend,
+                    self,
+                    self.data.session_id,
+                )
+                .await
+                .filter(|pool| !pool.is_empty())
+            {
+                return self.handle_proxy(&pool).await;
+            }
+        }
+
         // Verify address
         let rcpt = self.data.rcpt_to.last().unwrap();
         let mut rcpt_members = None;
-        if let Some(directory) = self
+        if self
+            .data
+            .mail_from
+            .as_ref()
+            .is_some_and(|from| from.address_lcase.is_empty())
+            && self.server.is_list_bounce(&rcpt.address_lcase)
+        {
+            // Bounces to the VERP return paths of mailing lists are
+            // processed once the message is received
+        } else if let Some(directory) = self
             .server
             .eval_if::<String, _>(
                 &self.server.core.smtp.session.rcpt.directory,
@@ -215,9 +257,76 @@ impl<T: SessionStream> Session<T> {
                         .rcpt(directory, &rcpt.address_lcase, self.data.session_id)
                         .await
                     {
-                        Ok(RcptType::Mailbox) => {}
+                        Ok(RcptType::Mailbox) => {
+                            if let Some(partition_id) = self
+                                .server
+                                .degraded_partition(&rcpt.address_lcase, self.data.session_id)
+                                .await
+                            {
+                                trc::event!(
+                                    Smtp(SmtpEvent::PartitionDegraded),
+                                    SpanId = self.data.session_id,
+                                    To = rcpt.address_lcase.clone(),
+                                    Id = partition_id,
+                                );
+
+                                self.data.rcpt_to.pop();
+                                return self
+                                    .write(b"451 4.3.0 Mailbox temporarily unavailable, try again later.\r\n")
+                                    .await;
+                            }
+                        }
                         Ok(RcptType::List(members)) => {
-                            rcpt_members = Some(members);
+                            let response: &[u8] = match self
+                                .expand_list(directory, &rcpt.address_lcase, members)
+                                .await
+                            {
+                                Ok(Expansion::Members(members)) => {
+                                    rcpt_members = Some(members);
+                                    b""
+                                }
+                                Ok(Expansion::Loop(member)) => {
+                                    trc::event!(
+                                        Smtp(SmtpEvent::ListExpansionLoop),
+                                        SpanId = self.data.session_id,
+                                        To = rcpt.address_lcase.clone(),
+                                        Details = member,
+                                    );
+                                    b"554 5.4.6 Mail loop detected while expanding list.\r\n"
+                                }
+                                Ok(Expansion::TooDeep) => {
+                                    trc::event!(
+                                        Smtp(SmtpEvent::ListExpansionTooDeep),
+                                        SpanId = self.data.session_id,
+                                        To = rcpt.address_lcase.clone(),
+                                        Limit = self.params.rcpt_max_expansion_depth,
+                                    );
+                                    b"554 5.4.6 Too many nested lists in list expansion.\r\n"
+                                }
+                                Ok(Expansion::TooLarge(total)) => {
+                                    trc::event!(
+                                        Smtp(SmtpEvent::ListExpansionTooLarge),
+                                        SpanId = self.data.session_id,
+                                        To = rcpt.address_lcase.clone(),
+                                        Total = total,
+                                        Limit = self.params.rcpt_max_expansion,
+                                    );
+                                    b"554 5.4.6 Too many recipients in list expansion.\r\n"
+                                }
+                                Err(err) => {
+                                    trc::error!(
+                                        err.span_id(self.data.session_id)
+                                            .caused_by(trc::location!())
+                                            .details("Failed to expand list.")
+                                    );
+                                    b"451 4.4.3 Unable to verify address at this time.\r\n"
+                                }
+                            };
+
+                            if !response.is_empty() {
+                                self.data.rcpt_to.pop();
+                                return self.write(response).await;
+                            }
                         }
                         Ok(RcptType::Invalid) => {
                             trc::event!(
@@ -391,8 +500,28 @@ impl<T: SessionStream> Session<T> {
                 .await;
         }
 
-        // Expand list
+        // Expand list, posts to configured mailing lists are expanded
+        // by the list engine once the message has been received.
         if let Some(members) = rcpt_members {
+            self.data.rcpt_expanded += members.len();
+            let list_addr = self.data.rcpt_to.last().unwrap();
+            if !members.is_empty()
+                && self
+                    .server
+                    .core
+                    .smtp
+                    .list
+                    .lists
+                    .contains_key(&list_addr.address_lcase)
+            {
+                self.data.list_expansions.push(ListExpansion {
+                    address_lcase: list_addr.address_lcase.clone(),
+                    members,
+                });
+                self.data.rcpt_oks += 1;
+                return self.write(b"250 2.1.5 OK\r\n").await;
+            }
+
             let list_addr = self.data.rcpt_to.pop().unwrap();
             let orcpt = format!("rfc822;{}", list_addr.address_lcase);
             for member in members {
@@ -411,6 +540,68 @@ impl<T: SessionStream> Session<T> {
         self.write(b"250 2.1.5 OK\r\n").await
     }
 
+    // Expands nested lists, stopping on loops or once the expansion budget
+    // of the message is exhausted
+    async fn expand_list(
+        &self,
+        directory: &Directory,
+        list: &str,
+        members: Vec<String>,
+    ) -> trc::Result<Expansion> {
+        let max_members = self
+            .params
+            .rcpt_max_expansion
+            .saturating_sub(self.data.rcpt_expanded);
+        let mut expanded: Vec<String> = Vec::with_capacity(members.len());
+        let mut path = vec![list.to_string()];
+        let mut stack = vec![members.into_iter()];
+
+        while let Some(members) = stack.last_mut() {
+            let Some(member) = members.next() else {
+                stack.pop();
+                path.pop();
+                continue;
+            };
+            let member = member.to_lowercase();
+            if path.contains(&member) {
+                return Ok(Expansion::Loop(member));
+            } else if expanded.contains(&member) {
+                continue;
+            }
+
+            // Configured mailing lists are expanded by the list engine
+            let nested = if !self.server.core.smtp.list.lists.contains_key(&member)
+                && directory.is_local_domain(member.domain_part()).await?
+            {
+                match self
+                    .server
+                    .rcpt(directory, &member, self.data.session_id)
+                    .await?
+                {
+                    RcptType::List(members) => Some(members),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+
+            if let Some(nested) = nested {
+                if path.len() >= self.params.rcpt_max_expansion_depth {
+                    return Ok(Expansion::TooDeep);
+                }
+                path.push(member);
+                stack.push(nested.into_iter());
+            } else {
+                expanded.push(member);
+                if expanded.len() > max_members {
+                    return Ok(Expansion::TooLarge(expanded.len()));
+                }
+            }
+        }
+
+        Ok(Expansion::Members(expanded))
+    }
+
     async fn rcpt_error(&mut self, response: &[u8], rcpt: String) -> Result<(), ()> {
         tokio::time::sleep(self.params.rcpt_errors_wait).await;
         self.data.rcpt_errors += 1;
@@ -457,3 +648,10 @@ impl<T: SessionStream> Session<T> {
         }
     }
 }
+
+enum Expansion {
+    Members(Vec<String>),
+    Loop(String),
+    TooDeep,
+    TooLarge(usize),
+}
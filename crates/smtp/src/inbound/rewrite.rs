@@ -0,0 +1,382 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use common::{config::smtp::session::RewriteRule, listener::SessionStream};
+use mail_builder::encoders::{
+    base64::base64_encode_mime, quoted_printable::quoted_printable_encode,
+};
+use mail_parser::{
+    Encoding, Message, MessageParser, MimeHeaders, PartType,
+    decoders::{base64::base64_decode, quoted_printable::quoted_printable_decode},
+};
+use trc::SmtpEvent;
+
+use crate::core::Session;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn apply_rewrite_rules(&self, raw_message: &[u8]) -> Option<Vec<u8>> {
+        // Obtain matching rules
+        let mut rules = Vec::new();
+        for rule in &self.server.core.smtp.session.data.rewrite {
+            if self
+                .server
+                .eval_if(&rule.enable, self, self.data.session_id)
+                .await
+                .unwrap_or(false)
+            {
+                let display_name = if let Some(display_name) = &rule.from_display_name {
+                    self.server
+                        .eval_if::<String, _>(display_name, self, self.data.session_id)
+                        .await
+                } else {
+                    None
+                };
+                rules.push((rule, display_name));
+            }
+        }
+        if rules.is_empty() {
+            return None;
+        }
+
+        let message = MessageParser::new().parse(raw_message)?;
+        let mut rewriter = MessageRewriter::new(&message);
+        for (rule, display_name) in rules {
+            rewriter.apply(rule, display_name.as_deref());
+
+            trc::event!(
+                Smtp(SmtpEvent::MessageRewritten),
+                SpanId = self.data.session_id,
+                Id = rule.id.clone(),
+            );
+        }
+
+        rewriter.build()
+    }
+}
+
+pub struct MessageRewriter<'x> {
+    message: &'x Message<'x>,
+    headers: Vec<(Cow<'x, [u8]>, Cow<'x, [u8]>)>,
+    footer_text: Vec<&'x str>,
+    footer_html: Vec<&'x str>,
+    has_changes: bool,
+}
+
+impl<'x> MessageRewriter<'x> {
+    pub fn new(message: &'x Message<'x>) -> Self {
+        let raw = message.raw_message();
+        let headers = message
+            .root_part()
+            .headers()
+            .iter()
+            .filter_map(|header| {
+                let name = raw.get(header.offset_field as usize..header.offset_start as usize)?;
+                let name = name
+                    .iter()
+                    .position(|&ch| ch == b':')
+                    .map_or(name, |pos| &name[..pos]);
+                let value = raw.get(header.offset_start as usize..header.offset_end as usize)?;
+                Some((Cow::from(name), Cow::from(value)))
+            })
+            .collect();
+
+        MessageRewriter {
+            message,
+            headers,
+            footer_text: Vec::new(),
+            footer_html: Vec::new(),
+            has_changes: false,
+        }
+    }
+
+    pub fn apply(&mut self, rule: &'x RewriteRule, display_name: Option<&str>) {
+        for name in &rule.headers_remove {
+            self.remove_header(name);
+        }
+        for (name, value) in &rule.headers_replace {
+            self.replace_header(name, value);
+        }
+        for (name, value) in &rule.headers_add {
+            self.add_header(name, value);
+        }
+        if let Some(display_name) = display_name {
+            self.set_from_display_name(display_name);
+        }
+        self.add_footer(rule.footer_text.as_deref(), rule.footer_html.as_deref());
+    }
+
+    pub fn add_footer(&mut self, text: Option<&'x str>, html: Option<&'x str>) {
+        if let Some(footer) = text {
+            self.footer_text.push(footer);
+        }
+        if let Some(footer) = html {
+            self.footer_html.push(footer);
+        }
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        let num_headers = self.headers.len();
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(name.as_bytes()));
+        self.has_changes |= num_headers != self.headers.len();
+    }
+
+    pub fn replace_header(&mut self, name: &str, value: &str) {
+        let mut found = false;
+        self.headers.retain_mut(|(header, header_value)| {
+            if header.eq_ignore_ascii_case(name.as_bytes()) {
+                if !found {
+                    *header_value = Cow::from(format!(" {value}\r\n").into_bytes());
+                    found = true;
+                    true
+                } else {
+                    false
+                }
+            } else {
+                true
+            }
+        });
+        if !found {
+            self.add_header(name, value);
+        } else {
+            self.has_changes = true;
+        }
+    }
+
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((
+            Cow::from(name.as_bytes().to_vec()),
+            Cow::from(format!(" {value}\r\n").into_bytes()),
+        ));
+        self.has_changes = true;
+    }
+
+    pub fn set_from_display_name(&mut self, display_name: &str) {
+        let message = self.message;
+        let Some(address) = message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address())
+        else {
+            return;
+        };
+
+        let mut value = String::with_capacity(display_name.len() + address.len() + 8);
+        value.push(' ');
+        if display_name.is_ascii() {
+            value.push('"');
+            for ch in display_name.chars() {
+                if matches!(ch, '"' | '\\') {
+                    value.push('\\');
+                }
+                if !ch.is_ascii_control() {
+                    value.push(ch);
+                }
+            }
+            value.push('"');
+        } else {
+            value.push_str("=?utf-8?b?");
+            let mut encoded = Vec::new();
+            let _ = base64_encode_mime(display_name.as_bytes(), &mut encoded, true);
+            value.push_str(std::str::from_utf8(&encoded).unwrap_or_default());
+            value.push_str("?=");
+        }
+        value.push_str(" <");
+        value.push_str(address);
+        value.push_str(">\r\n");
+
+        for (header, header_value) in self.headers.iter_mut() {
+            if header.eq_ignore_ascii_case(b"From") {
+                *header_value = Cow::from(value.into_bytes());
+                self.has_changes = true;
+                break;
+            }
+        }
+    }
+
+    pub fn build(self) -> Option<Vec<u8>> {
+        let raw = self.message.raw_message();
+        let body_offset = self.message.root_part().raw_body_offset() as usize;
+
+        // Obtain body parts to append footers to
+        let mut edits = Vec::new();
+        if !self.footer_text.is_empty() || !self.footer_html.is_empty() {
+            let mut part_ids = Vec::with_capacity(2);
+            for part_id in [self.message.text_body.last(), self.message.html_body.last()]
+                .into_iter()
+                .flatten()
+            {
+                if !part_ids.contains(part_id) {
+                    part_ids.push(*part_id);
+                }
+            }
+
+            for part_id in part_ids {
+                let Some(part) = self.message.parts.get(part_id as usize) else {
+                    continue;
+                };
+                let (footers, is_html) = match &part.body {
+                    PartType::Text(_) if !self.footer_text.is_empty() => (&self.footer_text, false),
+                    PartType::Html(_) if !self.footer_html.is_empty() => (&self.footer_html, true),
+                    _ => continue,
+                };
+                let footer = footers
+                    .join("\n")
+                    .replace("\r\n", "\n")
+                    .replace('\n', "\r\n");
+                let is_utf8 = part
+                    .content_type()
+                    .and_then(|ct| ct.attribute("charset"))
+                    .is_none_or(|cs| {
+                        cs.eq_ignore_ascii_case("utf-8")
+                            || cs.eq_ignore_ascii_case("utf8")
+                            || cs.eq_ignore_ascii_case("us-ascii")
+                    });
+                if !is_utf8 && !footer.is_ascii() {
+                    // Avoid mixing character sets
+                    continue;
+                }
+                let start = part.raw_body_offset() as usize;
+                let end = part.raw_end_offset() as usize;
+                if let Some(contents) = raw
+                    .get(start..end)
+                    .and_then(|bytes| add_footer(bytes, &part.encoding, &footer, is_html))
+                {
+                    edits.push((start, end, contents));
+                }
+            }
+        }
+
+        if !self.has_changes && edits.is_empty() {
+            return None;
+        }
+
+        // Write headers
+        let mut new_message = Vec::with_capacity(raw.len() + 256);
+        for (name, value) in &self.headers {
+            new_message.extend_from_slice(name.as_ref());
+            new_message.extend_from_slice(b":");
+            if value.first().is_some_and(|ch| !ch.is_ascii_whitespace()) {
+                new_message.extend_from_slice(b" ");
+            }
+            new_message.extend_from_slice(value.as_ref());
+            if value.last().is_none_or(|ch| *ch != b'\n') {
+                new_message.extend_from_slice(b"\r\n");
+            }
+        }
+        new_message.extend_from_slice(b"\r\n");
+
+        // Write body
+        edits.sort_unstable_by_key(|(start, _, _)| *start);
+        let mut offset = body_offset;
+        for (start, end, contents) in edits {
+            if start >= offset {
+                new_message.extend_from_slice(raw.get(offset..start).unwrap_or_default());
+                new_message.extend_from_slice(&contents);
+                offset = end;
+            }
+        }
+        new_message.extend_from_slice(raw.get(offset..).unwrap_or_default());
+
+        Some(new_message)
+    }
+}
+
+fn add_footer(bytes: &[u8], encoding: &Encoding, footer: &str, is_html: bool) -> Option<Vec<u8>> {
+    let mut contents = match encoding {
+        Encoding::None => bytes.to_vec(),
+        Encoding::QuotedPrintable => quoted_printable_decode(bytes)?,
+        Encoding::Base64 => base64_decode(bytes)?,
+    };
+
+    if is_html {
+        let insert_pos = contents
+            .windows(7)
+            .rposition(|w| w.eq_ignore_ascii_case(b"</body>"))
+            .unwrap_or(contents.len());
+        let mut html_footer = Vec::with_capacity(footer.len() + 2);
+        html_footer.extend_from_slice(b"\r\n");
+        html_footer.extend_from_slice(footer.as_bytes());
+        contents.splice(insert_pos..insert_pos, html_footer);
+    } else {
+        if !contents.ends_with(b"\n") {
+            contents.extend_from_slice(b"\r\n");
+        }
+        contents.extend_from_slice(footer.as_bytes());
+        contents.extend_from_slice(b"\r\n");
+    }
+
+    match encoding {
+        Encoding::None => Some(contents),
+        Encoding::QuotedPrintable => {
+            let mut encoded = Vec::with_capacity(contents.len() * 2);
+            quoted_printable_encode(&contents, &mut encoded, false, true).ok()?;
+            Some(encoded)
+        }
+        Encoding::Base64 => {
+            let mut encoded = Vec::with_capacity(contents.len() * 4 / 3 + 4);
+            base64_encode_mime(&contents, &mut encoded, false).ok()?;
+            Some(encoded)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use super::MessageRewriter;
+
+    #[test]
+    fn rewrite_headers_and_footers() {
+        let raw = concat!(
+            "From: John <john@example.org>\r\n",
+            "X-Mailer: Test\r\n",
+            "Subject: Hi\r\n",
+            "Content-Type: multipart/alternative; boundary=\"b1\"\r\n",
+            "\r\n",
+            "--b1\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "\r\n",
+            "Hello\r\n",
+            "--b1\r\n",
+            "Content-Type: text/html; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "PGh0bWw+PGJvZHk+SGVsbG88L2JvZHk+PC9odG1sPg==\r\n",
+            "--b1--\r\n"
+        );
+        let message = MessageParser::new().parse(raw.as_bytes()).unwrap();
+        let mut rewriter = MessageRewriter::new(&message);
+        rewriter.remove_header("x-mailer");
+        rewriter.replace_header("Subject", "Hello there");
+        rewriter.add_header("X-Disclaimer", "yes");
+        rewriter.set_from_display_name("Example \"Support\"");
+        rewriter.footer_text.push("-- \nDisclaimer");
+        rewriter.footer_html.push("<p>Disclaimer</p>");
+        let result = String::from_utf8(rewriter.build().unwrap()).unwrap();
+
+        assert!(!result.contains("X-Mailer"), "{result}");
+        assert!(result.contains("Subject: Hello there\r\n"), "{result}");
+        assert!(result.contains("X-Disclaimer: yes\r\n"), "{result}");
+        assert!(
+            result.contains("From: \"Example \\\"Support\\\"\" <john@example.org>\r\n"),
+            "{result}"
+        );
+        assert!(
+            result.contains("Hello\r\n-- \r\nDisclaimer\r\n"),
+            "{result}"
+        );
+
+        let message = MessageParser::new().parse(result.as_bytes()).unwrap();
+        assert_eq!(
+            message.body_html(0).unwrap(),
+            "<html><body>Hello\r\n<p>Disclaimer</p></body></html>"
+        );
+    }
+}
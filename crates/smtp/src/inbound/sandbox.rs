@@ -0,0 +1,74 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use common::listener::SessionStream;
+use mail_parser::{Message, MimeHeaders};
+use trc::SmtpEvent;
+
+use crate::{core::Session, queue::sandbox::SandboxClient};
+
+impl<T: SessionStream> Session<T> {
+    /// Submits suspicious attachments to the sandbox, returning the
+    /// submission ids to poll for a verdict.
+    pub async fn run_sandbox(&self, message: &Message<'_>) -> Option<Vec<String>> {
+        let sandbox = self.server.core.smtp.session.sandbox.as_ref()?;
+        if !self
+            .server
+            .eval_if(&sandbox.enable, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let time = Instant::now();
+        let mut submissions = Vec::new();
+        for part in message.attachments() {
+            let Some(name) = part.attachment_name() else {
+                continue;
+            };
+            let contents = part.contents();
+            if contents.len() > sandbox.max_size
+                || !name
+                    .rsplit_once('.')
+                    .is_some_and(|(_, ext)| sandbox.extensions.contains(&ext.to_ascii_lowercase()))
+            {
+                continue;
+            }
+
+            match sandbox.submit(name, contents).await {
+                Ok(id) => submissions.push(id),
+                Err(err) => {
+                    trc::event!(
+                        Smtp(SmtpEvent::SandboxError),
+                        SpanId = self.data.session_id,
+                        Reason = err,
+                        Elapsed = time.elapsed(),
+                    );
+                    return None;
+                }
+            }
+        }
+
+        if !submissions.is_empty() {
+            trc::event!(
+                Smtp(SmtpEvent::SandboxSubmitted),
+                SpanId = self.data.session_id,
+                Id = submissions
+                    .iter()
+                    .map(|id| trc::Value::from(id.clone()))
+                    .collect::<Vec<_>>(),
+                Elapsed = time.elapsed(),
+            );
+
+            Some(submissions)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,356 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    KV_SENDING_LIMIT, Server,
+    config::smtp::session::SendingLimits,
+    expr::{V_AUTHENTICATED_AS, Variable, functions::ResolveVariable, if_block::IfBlock},
+    listener::SessionStream,
+};
+use store::{dispatch::lookup::KeyValue, write::now};
+use trc::{AddContext, SmtpEvent};
+
+use crate::core::Session;
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 86400;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendingUsage {
+    pub messages_hour: u64,
+    pub messages_day: u64,
+    pub recipients_hour: u64,
+    pub recipients_day: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendingLimit {
+    pub messages_hour: Option<u64>,
+    pub messages_day: Option<u64>,
+    pub recipients_hour: Option<u64>,
+    pub recipients_day: Option<u64>,
+}
+
+// Recipients an account may still add to the current transaction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RecipientAllowance {
+    pub hour: Option<u64>,
+    pub day: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Counter {
+    Messages,
+    Recipients,
+}
+
+pub trait SmtpSendingLimits: Sync + Send {
+    fn sending_limit(
+        &self,
+        limits: &SendingLimits,
+        account_name: &str,
+    ) -> impl Future<Output = SendingLimit> + Send;
+
+    fn sending_usage(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<SendingUsage>> + Send;
+
+    fn charge_sending_usage(
+        &self,
+        account_id: u32,
+        recipients: u64,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn clear_sending_usage(&self, account_id: u32) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SmtpSendingLimits for Server {
+    async fn sending_limit(&self, limits: &SendingLimits, account_name: &str) -> SendingLimit {
+        let resolver = AccountResolver(account_name);
+        SendingLimit {
+            messages_hour: eval_limit(self, &limits.messages_hour, &resolver, 0).await,
+            messages_day: eval_limit(self, &limits.messages_day, &resolver, 0).await,
+            recipients_hour: eval_limit(self, &limits.recipients_hour, &resolver, 0).await,
+            recipients_day: eval_limit(self, &limits.recipients_day, &resolver, 0).await,
+        }
+    }
+
+    async fn sending_usage(&self, account_id: u32) -> trc::Result<SendingUsage> {
+        Ok(SendingUsage {
+            messages_hour: rolling_count(self, Counter::Messages, HOUR, account_id).await?,
+            messages_day: rolling_count(self, Counter::Messages, DAY, account_id).await?,
+            recipients_hour: rolling_count(self, Counter::Recipients, HOUR, account_id).await?,
+            recipients_day: rolling_count(self, Counter::Recipients, DAY, account_id).await?,
+        })
+    }
+
+    async fn charge_sending_usage(&self, account_id: u32, recipients: u64) -> trc::Result<()> {
+        let now = now();
+        for (counter, value) in [
+            (Counter::Messages, 1),
+            (Counter::Recipients, recipients as i64),
+        ] {
+            for window in [HOUR, DAY] {
+                self.in_memory_store()
+                    .counter_incr(
+                        KeyValue::with_prefix(
+                            KV_SENDING_LIMIT,
+                            counter_key(counter, window, account_id, now / window),
+                            value,
+                        )
+                        .expires(window * 2),
+                        false,
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn clear_sending_usage(&self, account_id: u32) -> trc::Result<()> {
+        let now = now();
+        for counter in [Counter::Messages, Counter::Recipients] {
+            for window in [HOUR, DAY] {
+                let bucket = now / window;
+                for bucket in [bucket, bucket.saturating_sub(1)] {
+                    self.in_memory_store()
+                        .counter_delete(KeyValue::<()>::build_key(
+                            KV_SENDING_LIMIT,
+                            counter_key(counter, window, account_id, bucket),
+                        ))
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: SessionStream> Session<T> {
+    /// Enforces the sending limits of the authenticated account when a new
+    /// transaction starts, returning the SMTP response on rejection.
+    pub async fn check_sending_limits(&mut self) -> Option<&'static [u8]> {
+        let limits = &self.server.core.smtp.session.mail.limits;
+        let account_id = self.data.authenticated_as.as_ref()?.primary_id();
+        if limits.is_empty() {
+            return None;
+        }
+
+        let session_id = self.data.session_id;
+        let limit = SendingLimit {
+            messages_hour: eval_limit(&self.server, &limits.messages_hour, self, session_id).await,
+            messages_day: eval_limit(&self.server, &limits.messages_day, self, session_id).await,
+            recipients_hour: eval_limit(&self.server, &limits.recipients_hour, self, session_id)
+                .await,
+            recipients_day: eval_limit(&self.server, &limits.recipients_day, self, session_id)
+                .await,
+        };
+        if limit == SendingLimit::default() {
+            return None;
+        }
+
+        let usage = match self.server.sending_usage(account_id).await {
+            Ok(usage) => usage,
+            Err(err) => {
+                trc::error!(
+                    err.span_id(session_id)
+                        .details("Failed to obtain account sending usage")
+                );
+                return None;
+            }
+        };
+
+        let allowance = RecipientAllowance {
+            hour: limit
+                .recipients_hour
+                .map(|limit| limit.saturating_sub(usage.recipients_hour)),
+            day: limit
+                .recipients_day
+                .map(|limit| limit.saturating_sub(usage.recipients_day)),
+        };
+        let (response, window, total) = if limit
+            .messages_day
+            .is_some_and(|limit| usage.messages_day >= limit)
+            || allowance.day == Some(0)
+        {
+            (
+                &b"550 5.4.5 Daily sending limit exceeded.\r\n"[..],
+                "day",
+                usage.messages_day,
+            )
+        } else if limit
+            .messages_hour
+            .is_some_and(|limit| usage.messages_hour >= limit)
+            || allowance.hour == Some(0)
+        {
+            (
+                &b"452 4.4.5 Hourly sending limit exceeded, try again later.\r\n"[..],
+                "hour",
+                usage.messages_hour,
+            )
+        } else {
+            self.data.rcpt_allowance = Some(allowance);
+            return None;
+        };
+
+        trc::event!(
+            Smtp(SmtpEvent::SendingLimitExceeded),
+            SpanId = session_id,
+            AccountName = self.authenticated_as().unwrap_or_default().to_string(),
+            Details = window,
+            Total = total,
+        );
+
+        Some(response)
+    }
+
+    /// Returns the SMTP response when adding another recipient would exceed
+    /// the recipient limits of the authenticated account.
+    pub fn exceeds_recipient_allowance(&self) -> Option<&'static [u8]> {
+        let allowance = self.data.rcpt_allowance?;
+        let recipients = self.data.rcpt_to.len() as u64;
+        let (response, window) = if allowance.day.is_some_and(|day| recipients >= day) {
+            (&b"550 5.4.5 Daily recipient limit exceeded.\r\n"[..], "day")
+        } else if allowance.hour.is_some_and(|hour| recipients >= hour) {
+            (
+                &b"452 4.5.3 Hourly recipient limit exceeded, try again later.\r\n"[..],
+                "hour",
+            )
+        } else {
+            return None;
+        };
+
+        trc::event!(
+            Smtp(SmtpEvent::SendingLimitExceeded),
+            SpanId = self.data.session_id,
+            AccountName = self.authenticated_as().unwrap_or_default().to_string(),
+            Details = window,
+            Limit = recipients,
+        );
+
+        Some(response)
+    }
+
+    pub async fn charge_sending_limits(&self, recipients: usize) {
+        if let Some(account_id) = self
+            .data
+            .authenticated_as
+            .as_ref()
+            .filter(|_| !self.server.core.smtp.session.mail.limits.is_empty())
+            .map(|token| token.primary_id())
+        {
+            if let Err(err) = self
+                .server
+                .charge_sending_usage(account_id, recipients as u64)
+                .await
+            {
+                trc::error!(
+                    err.span_id(self.data.session_id)
+                        .details("Failed to update account sending usage")
+                );
+            }
+        }
+    }
+}
+
+struct AccountResolver<'x>(&'x str);
+
+impl ResolveVariable for AccountResolver<'_> {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        match variable {
+            V_AUTHENTICATED_AS => self.0.into(),
+            _ => "".into(),
+        }
+    }
+
+    fn resolve_global(&self, _: &str) -> Variable<'_> {
+        Variable::Integer(0)
+    }
+}
+
+async fn eval_limit(
+    server: &Server,
+    if_block: &IfBlock,
+    resolver: &impl ResolveVariable,
+    session_id: u64,
+) -> Option<u64> {
+    server
+        .eval_if::<u64, _>(if_block, resolver, session_id)
+        .await
+        .filter(|limit| *limit > 0)
+}
+
+// Rolling windows are approximated by weighting the count of the previous
+// fixed window by the portion of it that still overlaps the rolling window.
+async fn rolling_count(
+    server: &Server,
+    counter: Counter,
+    window: u64,
+    account_id: u32,
+) -> trc::Result<u64> {
+    let now = now();
+    let bucket = now / window;
+    let store = server.in_memory_store();
+    let current = store
+        .counter_get(KeyValue::<()>::build_key(
+            KV_SENDING_LIMIT,
+            counter_key(counter, window, account_id, bucket),
+        ))
+        .await
+        .caused_by(trc::location!())?
+        .max(0) as u64;
+    let previous = store
+        .counter_get(KeyValue::<()>::build_key(
+            KV_SENDING_LIMIT,
+            counter_key(counter, window, account_id, bucket.saturating_sub(1)),
+        ))
+        .await
+        .caused_by(trc::location!())?
+        .max(0) as u64;
+
+    Ok(rolling_estimate(current, previous, now % window, window))
+}
+
+fn rolling_estimate(current: u64, previous: u64, elapsed: u64, window: u64) -> u64 {
+    current + (previous * (window - elapsed)).div_ceil(window)
+}
+
+fn counter_key(counter: Counter, window: u64, account_id: u32, bucket: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(14);
+    key.push(match counter {
+        Counter::Messages => b'm',
+        Counter::Recipients => b'r',
+    });
+    key.push(if window == HOUR { b'h' } else { b'd' });
+    key.extend_from_slice(&account_id.to_be_bytes());
+    key.extend_from_slice(&bucket.to_be_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_window() {
+        // At the start of a window the previous one is fully counted
+        assert_eq!(rolling_estimate(0, 100, 0, HOUR), 100);
+        // Halfway through, only half of the previous window still applies
+        assert_eq!(rolling_estimate(10, 100, HOUR / 2, HOUR), 60);
+        // Near the end, the previous window has almost no weight
+        assert_eq!(rolling_estimate(10, 100, HOUR - 1, HOUR), 11);
+        assert_eq!(rolling_estimate(5, 0, 1234, DAY), 5);
+    }
+}
@@ -5,7 +5,7 @@
  */
 
 use common::{
-    config::{server::ServerProtocol, smtp::session::Mechanism},
+    config::server::ServerProtocol,
     expr::{self, functions::ResolveVariable, *},
     listener::SessionStream,
 };
@@ -37,6 +37,12 @@ impl<T: SessionStream> Session<T> {
                         Ok(request) => match request {
                             Request::Rcpt { to } => {
                                 self.handle_rcpt_to(to).await?;
+
+                                // Hand the connection over to the backend
+                                if let Some(backend) = self.data.proxy.take() {
+                                    self.proxy_relay(backend, iter.as_slice()).await;
+                                    return Err(());
+                                }
                             }
                             Request::Mail { from } => {
                                 self.handle_mail_from(from).await?;
@@ -85,16 +91,7 @@ impl<T: SessionStream> Session<T> {
                                 mechanism,
                                 initial_response,
                             } => {
-                                let auth: u64 = self
-                                    .server
-                                    .eval_if::<Mechanism, _>(
-                                        &self.server.core.smtp.session.auth.mechanisms,
-                                        self,
-                                        self.data.session_id,
-                                    )
-                                    .await
-                                    .unwrap_or_default()
-                                    .into();
+                                let auth = self.auth_mechanisms().await;
                                 if auth == 0 || self.params.auth_directory.is_none() {
                                     trc::event!(
                                         Smtp(SmtpEvent::AuthNotAllowed),
@@ -111,7 +108,7 @@ impl<T: SessionStream> Session<T> {
 
                                     self.write(b"503 5.5.1 Already authenticated.\r\n").await?;
                                 } else if let Some(mut token) =
-                                    SaslToken::from_mechanism(mechanism & auth)
+                                    SaslToken::from_mechanism(mechanism, auth)
                                 {
                                     if self
                                         .handle_sasl_response(
@@ -464,11 +461,14 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
         self.data.mail_from = None;
         self.data.spf_mail_from = None;
         self.data.rcpt_to.clear();
+        self.data.list_expansions.clear();
+        self.data.rcpt_expanded = 0;
         self.data.message = Vec::with_capacity(0);
         self.data.priority = 0;
         self.data.delivery_by = 0;
         self.data.future_release = 0;
         self.data.rcpt_oks = 0;
+        self.data.rcpt_allowance = None;
     }
 
     #[inline(always)]
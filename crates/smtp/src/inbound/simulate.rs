@@ -0,0 +1,496 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+};
+
+use common::{
+    Server,
+    auth::AccessToken,
+    config::{
+        server::ServerProtocol,
+        smtp::{auth::VerifyStrategy, routing::ROUTE_RELAY_MX},
+        spamfilter::SpamFilterAction,
+    },
+    listener::{ServerInstance, TcpAcceptor, limiter::ConcurrencyLimiter, stream::NullIo},
+    psl,
+    scripts::ScriptModification,
+};
+use mail_auth::{
+    AuthenticatedMessage, AuthenticationResults, DkimResult, DmarcResult, ReceivedSpf,
+    common::headers::HeaderWriter,
+    dmarc::{self, verify::DmarcParameters},
+    spf::verify::SpfParameters,
+};
+use mail_parser::MessageParser;
+use serde::{Deserialize, Serialize};
+use spam_filter::analysis::{init::SpamFilterInit, score::SpamFilterAnalyzeScore};
+use tokio::sync::watch;
+use utils::snowflake::SnowflakeIdGenerator;
+
+use crate::{
+    core::{Session, SessionAddress, SessionData},
+    queue::QueueEnvelope,
+    scripts::ScriptResult,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationRequest {
+    pub message: String,
+
+    // Session details
+    pub remote_ip: IpAddr,
+    #[serde(default)]
+    pub ehlo_domain: String,
+    #[serde(default)]
+    pub listener: Option<String>,
+    #[serde(default)]
+    pub authenticated_as: Option<String>,
+
+    // Envelope
+    pub env_from: String,
+    pub env_rcpt_to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationResult {
+    pub response: String,
+    pub accepted: bool,
+    pub iprev: Option<String>,
+    pub spf_ehlo: Option<String>,
+    pub spf_mail_from: Option<String>,
+    pub dkim: Vec<String>,
+    pub arc: Option<String>,
+    pub dmarc: Option<String>,
+    pub dmarc_policy: Option<String>,
+    pub spam: Option<SimulatedSpamResult>,
+    pub sieve: Option<String>,
+    pub headers: String,
+    pub routes: Vec<SimulatedRoute>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedSpamResult {
+    pub score: f64,
+    pub tags: Vec<String>,
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedRoute {
+    pub domain: String,
+    pub recipients: Vec<String>,
+    pub next_hop: String,
+}
+
+impl Session<NullIo> {
+    /// Runs a message through the inbound pipeline without queuing it or
+    /// producing any other side effects. Milters, MTA hooks, content scanners
+    /// and report delivery are skipped.
+    pub async fn simulate(
+        server: Server,
+        request: SimulationRequest,
+        authenticated_as: Option<Arc<AccessToken>>,
+    ) -> SimulationResult {
+        let session_id = server.inner.data.span_id_gen.generate();
+        let mut data = SessionData::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            25,
+            request.remote_ip,
+            0,
+            server.lookup_asn_country(request.remote_ip).await,
+            session_id,
+        );
+        data.helo_domain = request.ehlo_domain.to_lowercase();
+        data.mail_from = SessionAddress::new(request.env_from).into();
+        data.rcpt_to = request
+            .env_rcpt_to
+            .into_iter()
+            .map(SessionAddress::new)
+            .collect();
+        data.authenticated_as = authenticated_as;
+        let instance = Arc::new(ServerInstance {
+            id: request.listener.unwrap_or_else(|| "smtp".to_string()),
+            protocol: ServerProtocol::Smtp,
+            acceptor: TcpAcceptor::Plain,
+            limiter: ConcurrencyLimiter::new(1),
+            shutdown_rx: watch::channel(false).1,
+            proxy_networks: vec![],
+            span_id_gen: Arc::new(SnowflakeIdGenerator::new()),
+            auth_policy: Default::default(),
+        });
+        let mut session = Session::local(server, instance, data);
+        session.hostname = session.server.core.network.server_name.clone();
+
+        session.simulate_message(request.message.into_bytes()).await
+    }
+
+    async fn simulate_message(&mut self, raw_message: Vec<u8>) -> SimulationResult {
+        let mut result = SimulationResult::default();
+        let Some(parsed_message) = MessageParser::new()
+            .parse(&raw_message)
+            .filter(|p| p.headers().iter().any(|h| !h.name.is_other()))
+        else {
+            result.response = "550 5.7.7 Failed to parse message.".to_string();
+            return result;
+        };
+        let mail_from = self.data.mail_from.clone().unwrap();
+        let dns = &self.server.core.smtp.resolvers.dns;
+        let cache = &self.server.inner.cache;
+
+        // Verify the session
+        let iprev = dns
+            .verify_iprev(cache.build_auth_parameters(self.data.remote_ip))
+            .await;
+        result.iprev = iprev.result().as_str().to_string().into();
+        self.data.iprev = iprev.into();
+        if !self.data.helo_domain.is_empty() {
+            let spf_ehlo = dns
+                .verify_spf(cache.build_auth_parameters(SpfParameters::verify_ehlo(
+                    self.data.remote_ip,
+                    &self.data.helo_domain,
+                    &self.hostname,
+                )))
+                .await;
+            result.spf_ehlo = spf_ehlo.result().as_str().to_string().into();
+            self.data.spf_ehlo = spf_ehlo.into();
+        }
+        let spf_mail_from = if !mail_from.domain.is_empty() {
+            dns.check_host(cache.build_auth_parameters(SpfParameters::new(
+                self.data.remote_ip,
+                &mail_from.domain,
+                &self.data.helo_domain,
+                &self.hostname,
+                &mail_from.address_lcase,
+            )))
+            .await
+        } else {
+            dns.check_host(cache.build_auth_parameters(SpfParameters::new(
+                self.data.remote_ip,
+                &self.data.helo_domain,
+                &self.data.helo_domain,
+                &self.hostname,
+                &format!("postmaster@{}", self.data.helo_domain),
+            )))
+            .await
+        };
+        result.spf_mail_from = spf_mail_from.result().as_str().to_string().into();
+        self.data.spf_mail_from = spf_mail_from.into();
+
+        // Verify DKIM and ARC, failure reports are not sent
+        let ac = &self.server.core.smtp.mail_auth;
+        let dc = &self.server.core.smtp.session.data;
+        let auth_message = AuthenticatedMessage::from_parsed(&parsed_message, ac.dkim.strict);
+        let dkim_strategy = self
+            .server
+            .eval_if(&ac.dkim.verify, self, self.data.session_id)
+            .await
+            .unwrap_or(VerifyStrategy::Relaxed);
+        let dkim_output = dns
+            .verify_dkim(cache.build_auth_parameters(&auth_message))
+            .await;
+        result.dkim = dkim_output
+            .iter()
+            .map(|output| output.result().as_str().to_string())
+            .collect();
+        if dkim_strategy.is_strict()
+            && !dkim_output
+                .iter()
+                .any(|d| matches!(d.result(), DkimResult::Pass))
+        {
+            result.response = "550 5.7.20 No passing DKIM signatures found.".to_string();
+            return result;
+        }
+
+        let arc_strategy = self
+            .server
+            .eval_if(&ac.arc.verify, self, self.data.session_id)
+            .await
+            .unwrap_or(VerifyStrategy::Relaxed);
+        let arc_output = dns
+            .verify_arc(cache.build_auth_parameters(&auth_message))
+            .await;
+        result.arc = arc_output.result().as_str().to_string().into();
+        if arc_strategy.is_strict()
+            && !matches!(arc_output.result(), DkimResult::Pass | DkimResult::None)
+        {
+            result.response = "550 5.7.29 ARC validation failed.".to_string();
+            return result;
+        }
+
+        // Verify DMARC, aggregate and failure reports are not sent
+        let mut auth_results = AuthenticationResults::new(&self.hostname)
+            .with_dkim_results(&dkim_output, auth_message.from())
+            .with_iprev_result(self.data.iprev.as_ref().unwrap(), self.data.remote_ip);
+        if let Some(spf_ehlo) = &self.data.spf_ehlo {
+            auth_results = auth_results.with_spf_ehlo_result(
+                spf_ehlo,
+                self.data.remote_ip,
+                &self.data.helo_domain,
+            );
+        }
+        let spf_output = self.data.spf_mail_from.as_ref().unwrap();
+        auth_results = auth_results.with_spf_mailfrom_result(
+            spf_output,
+            self.data.remote_ip,
+            &mail_from.address,
+            &self.data.helo_domain,
+        );
+        let dmarc_output = dns
+            .verify_dmarc(cache.build_auth_parameters(DmarcParameters {
+                message: &auth_message,
+                dkim_output: &dkim_output,
+                rfc5321_mail_from_domain: if !mail_from.domain.is_empty() {
+                    &mail_from.domain
+                } else {
+                    &self.data.helo_domain
+                },
+                spf_output,
+                domain_suffix_fn: |domain| psl::domain_str(domain).unwrap_or(domain),
+            }))
+            .await;
+        auth_results = auth_results.with_dmarc_result(&dmarc_output);
+        let dmarc_pass = matches!(dmarc_output.spf_result(), DmarcResult::Pass)
+            || matches!(dmarc_output.dkim_result(), DmarcResult::Pass);
+        let dmarc_result = if dmarc_pass {
+            DmarcResult::Pass
+        } else if dmarc_output.spf_result() != &DmarcResult::None {
+            dmarc_output.spf_result().clone()
+        } else if dmarc_output.dkim_result() != &DmarcResult::None {
+            dmarc_output.dkim_result().clone()
+        } else {
+            DmarcResult::None
+        };
+        let dmarc_policy = dmarc_output.policy();
+        result.dmarc = dmarc_result.as_str().to_string().into();
+        result.dmarc_policy = dmarc_policy.as_str().to_string().into();
+        if self
+            .server
+            .eval_if(&ac.dmarc.verify, self, self.data.session_id)
+            .await
+            .unwrap_or(VerifyStrategy::Relaxed)
+            .is_strict()
+            && dmarc_policy == dmarc::Policy::Reject
+            && !dmarc_pass
+        {
+            result.response = "550 5.7.1 Email rejected per DMARC policy.".to_string();
+            return result;
+        }
+
+        // Add trace headers
+        let mut headers = Vec::with_capacity(64);
+        if self
+            .server
+            .eval_if(&dc.add_received, self, self.data.session_id)
+            .await
+            .unwrap_or(true)
+        {
+            self.write_received(&mut headers, self.data.session_id)
+        }
+        if self
+            .server
+            .eval_if(&dc.add_auth_results, self, self.data.session_id)
+            .await
+            .unwrap_or(true)
+        {
+            auth_results.write_header(&mut headers);
+        }
+        if self
+            .server
+            .eval_if(&dc.add_received_spf, self, self.data.session_id)
+            .await
+            .unwrap_or(true)
+        {
+            ReceivedSpf::new(
+                spf_output,
+                self.data.remote_ip,
+                &self.data.helo_domain,
+                &mail_from.address_lcase,
+                &self.hostname,
+            )
+            .write_header(&mut headers);
+        }
+
+        // Classify the message without updating reputation or training data
+        if self.server.core.spam.enabled
+            && !self.is_authenticated()
+            && self
+                .server
+                .eval_if(&dc.spam_filter, self, self.data.session_id)
+                .await
+                .unwrap_or(true)
+        {
+            let overrides = self.spam_filter_overrides().await;
+            let mut input = self.build_spam_input(
+                &parsed_message,
+                &dkim_output,
+                Some(&arc_output),
+                Some(&dmarc_result),
+                Some(&dmarc_policy),
+            );
+            input.overrides = overrides.as_ref();
+            input.is_test = true;
+            let mut ctx = self.server.spam_filter_init(input);
+            let action = if overrides.as_ref().and_then(|o| o.enabled).unwrap_or(true) {
+                self.server.spam_filter_classify(&mut ctx).await
+            } else {
+                SpamFilterAction::Allow(String::new())
+            };
+            let mut tags = ctx
+                .result
+                .tags
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect::<Vec<_>>();
+            tags.sort_unstable();
+            result.spam = Some(SimulatedSpamResult {
+                score: ctx.result.score,
+                tags,
+                action: match &action {
+                    SpamFilterAction::Allow(_) => "allow",
+                    SpamFilterAction::Discard => "discard",
+                    SpamFilterAction::Reject => "reject",
+                }
+                .to_string(),
+            });
+
+            match action {
+                SpamFilterAction::Allow(spam_headers) => {
+                    headers.extend_from_slice(spam_headers.as_bytes());
+                }
+                SpamFilterAction::Discard => {
+                    result.response = "250 2.0.0 Message queued for delivery.".to_string();
+                    return result;
+                }
+                SpamFilterAction::Reject => {
+                    result.response =
+                        "550 5.7.1 Message rejected due to excessive spam score.".to_string();
+                    return result;
+                }
+            }
+        }
+
+        // Run the Sieve script in test mode
+        if let Some((script, script_id)) = self
+            .server
+            .eval_if::<String, _>(&dc.script, self, self.data.session_id)
+            .await
+            .and_then(|name| {
+                self.server
+                    .get_trusted_sieve_script(&name, self.data.session_id)
+                    .map(|s| (s, name))
+            })
+        {
+            let params = self
+                .build_script_parameters("data")
+                .with_auth_headers(&headers)
+                .set_variable("arc.result", arc_output.result().as_str())
+                .set_variable(
+                    "dkim.result",
+                    dkim_output
+                        .iter()
+                        .find(|r| matches!(r.result(), DkimResult::Pass))
+                        .or_else(|| dkim_output.first())
+                        .map(|r| r.result().as_str())
+                        .unwrap_or_default(),
+                )
+                .set_variable("dmarc.result", dmarc_result.as_str())
+                .set_variable("dmarc.policy", dmarc_policy.as_str())
+                .with_message(parsed_message)
+                .with_test(true);
+
+            let modifications = match self.run_script(script_id, script.clone(), params).await {
+                ScriptResult::Accept { modifications } => {
+                    result.sieve = "accept".to_string().into();
+                    modifications
+                }
+                ScriptResult::Replace { modifications, .. } => {
+                    result.sieve = "replace".to_string().into();
+                    modifications
+                }
+                ScriptResult::Reject(message) => {
+                    result.sieve = "reject".to_string().into();
+                    result.response = message.trim_end().to_string();
+                    return result;
+                }
+                ScriptResult::Discard => {
+                    result.sieve = "discard".to_string().into();
+                    result.response = "250 2.0.0 Message queued for delivery.".to_string();
+                    return result;
+                }
+            };
+
+            for modification in modifications {
+                match modification {
+                    ScriptModification::AddHeader { name, value } => {
+                        headers.extend_from_slice(name.as_bytes());
+                        headers.extend_from_slice(b": ");
+                        headers.extend_from_slice(value.as_bytes());
+                        if !value.ends_with('\n') {
+                            headers.extend_from_slice(b"\r\n");
+                        }
+                    }
+                    ScriptModification::SetEnvelope { name, value } => {
+                        self.data.apply_envelope_modification(name, value);
+                    }
+                }
+            }
+        }
+        result.headers = String::from_utf8_lossy(&headers).into_owned();
+
+        // Obtain the routing decision for each recipient domain
+        let mail_from = self.data.mail_from.clone().unwrap();
+        let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let message = self
+            .build_message(
+                mail_from,
+                rcpt_to,
+                self.data.session_id,
+                self.data.session_id,
+            )
+            .await;
+        let queue_config = &self.server.core.smtp.queue;
+        for (domain_idx, domain) in message.domains.iter().enumerate() {
+            let next_hop = match queue_config
+                .routes
+                .get(domain.domain.as_str())
+                .and_then(|route| route.relay.clone())
+            {
+                Some(relay) => relay,
+                None => self
+                    .server
+                    .eval_if::<String, _>(
+                        &queue_config.next_hop,
+                        &QueueEnvelope::new(&message, domain_idx),
+                        self.data.session_id,
+                    )
+                    .await
+                    .unwrap_or_else(|| ROUTE_RELAY_MX.to_string()),
+            };
+
+            result.routes.push(SimulatedRoute {
+                domain: domain.domain.clone(),
+                recipients: message
+                    .recipients
+                    .iter()
+                    .filter(|rcpt| rcpt.domain_idx == domain_idx as u32)
+                    .map(|rcpt| rcpt.address_lcase.clone())
+                    .collect(),
+                next_hop,
+            });
+        }
+
+        result.accepted = true;
+        result.response = "250 2.0.0 Message queued for delivery.".to_string();
+        result
+    }
+}
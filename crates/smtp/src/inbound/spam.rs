@@ -4,7 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::{config::spamfilter::SpamFilterAction, listener::SessionStream};
+use common::{
+    config::spamfilter::{SpamFilterAction, SpamFilterOverrides},
+    listener::SessionStream,
+};
 use mail_auth::{ArcOutput, DkimOutput, DmarcResult, dmarc::Policy};
 use mail_parser::Message;
 use spam_filter::{
@@ -27,17 +30,23 @@ impl<T: SessionStream> Session<T> {
         dmarc_policy: Option<&'x Policy>,
     ) -> SpamFilterAction<String> {
         let server = &self.server;
-        let mut ctx = server.spam_filter_init(self.build_spam_input(
-            message,
-            dkim_result,
-            arc_result,
-            dmarc_result,
-            dmarc_policy,
-        ));
+        let overrides = if !self.is_authenticated() {
+            self.spam_filter_overrides().await
+        } else {
+            None
+        };
+        let mut input =
+            self.build_spam_input(message, dkim_result, arc_result, dmarc_result, dmarc_policy);
+        input.overrides = overrides.as_ref();
+        let mut ctx = server.spam_filter_init(input);
 
         if !self.is_authenticated() {
             // Spam classification
-            server.spam_filter_classify(&mut ctx).await
+            if overrides.as_ref().and_then(|o| o.enabled).unwrap_or(true) {
+                server.spam_filter_classify(&mut ctx).await
+            } else {
+                SpamFilterAction::Allow(String::new())
+            }
         } else {
             // Trusted reply tracking
             server.spam_filter_analyze_reply_out(&mut ctx).await;
@@ -45,6 +54,50 @@ impl<T: SessionStream> Session<T> {
         }
     }
 
+    // Overrides are only applied when all recipients share the same settings
+    pub(crate) async fn spam_filter_overrides(&self) -> Option<SpamFilterOverrides> {
+        let mut result: Option<Option<SpamFilterOverrides>> = None;
+
+        for rcpt in &self.data.rcpt_to {
+            let overrides = match self
+                .server
+                .core
+                .storage
+                .directory
+                .email_to_id(&rcpt.address_lcase)
+                .await
+            {
+                Ok(Some(account_id)) => self
+                    .server
+                    .spam_filter_overrides(account_id)
+                    .await
+                    .unwrap_or_else(|err| {
+                        trc::error!(
+                            err.span_id(self.data.session_id)
+                                .caused_by(trc::location!())
+                        );
+                        None
+                    }),
+                Ok(None) => None,
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(self.data.session_id)
+                            .caused_by(trc::location!())
+                    );
+                    None
+                }
+            };
+
+            match &result {
+                Some(prev) if *prev != overrides => return None,
+                Some(_) => {}
+                None => result = Some(overrides),
+            }
+        }
+
+        result.flatten()
+    }
+
     pub fn build_spam_input<'x>(
         &'x self,
         message: &'x Message<'x>,
@@ -88,6 +141,7 @@ impl<T: SessionStream> Session<T> {
                 .map(|r| r.address_lcase.as_str())
                 .collect(),
             account_id: None,
+            overrides: None,
             is_test: false,
         }
     }
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::Instant;
+use std::{sync::atomic::Ordering, time::Instant};
 
 use common::{
     config::smtp::session::Stage,
@@ -12,6 +12,7 @@ use common::{
     listener::{self, SessionManager, SessionStream},
 };
 
+use store::write::now;
 use tokio_rustls::server::TlsStream;
 use trc::{SecurityEvent, SmtpEvent};
 
@@ -79,6 +80,39 @@ impl<T: SessionStream> Session<T> {
 
         let config = &self.server.core.smtp.session.connect;
 
+        // Obtain hostname
+        self.hostname = self
+            .server
+            .eval_if::<String, _>(&config.hostname, self, self.data.session_id)
+            .await
+            .unwrap_or_default();
+        if self.hostname.is_empty() {
+            trc::event!(
+                Smtp(SmtpEvent::MissingLocalHostname),
+                SpanId = self.data.session_id,
+            );
+            self.hostname = "localhost".into();
+        }
+
+        // Defer remote senders during maintenance windows
+        if self.is_maintenance_deferred().await {
+            trc::event!(
+                Smtp(SmtpEvent::MaintenanceDeferred),
+                SpanId = self.data.session_id,
+                RemoteIp = self.data.remote_ip,
+            );
+            let _ = self
+                .write(
+                    format!(
+                        "421 4.3.2 {} {}\r\n",
+                        self.hostname, self.server.core.smtp.session.maintenance.message
+                    )
+                    .as_bytes(),
+                )
+                .await;
+            return false;
+        }
+
         // Sieve filtering
         if let Some((script, script_id)) = self
             .server
@@ -115,20 +149,6 @@ impl<T: SessionStream> Session<T> {
             return false;
         }
 
-        // Obtain hostname
-        self.hostname = self
-            .server
-            .eval_if::<String, _>(&config.hostname, self, self.data.session_id)
-            .await
-            .unwrap_or_default();
-        if self.hostname.is_empty() {
-            trc::event!(
-                Smtp(SmtpEvent::MissingLocalHostname),
-                SpanId = self.data.session_id,
-            );
-            self.hostname = "localhost".into();
-        }
-
         // Obtain greeting
         let greeting = self
             .server
@@ -145,6 +165,32 @@ impl<T: SessionStream> Session<T> {
         true
     }
 
+    async fn is_maintenance_deferred(&self) -> bool {
+        let config = &self.server.core.smtp.session.maintenance;
+        if !self
+            .server
+            .inner
+            .data
+            .smtp_maintenance
+            .load(Ordering::Relaxed)
+        {
+            let now = now();
+            if !config
+                .windows
+                .iter()
+                .any(|(start, end)| (*start..*end).contains(&now))
+            {
+                return false;
+            }
+        }
+
+        !self
+            .server
+            .eval_if(&config.allow, self, self.data.session_id)
+            .await
+            .unwrap_or(false)
+    }
+
     pub async fn handle_conn(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
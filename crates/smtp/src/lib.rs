@@ -6,13 +6,14 @@
 
 #![warn(clippy::large_futures)]
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use common::{
     Inner,
+    core::BuildServer,
     manager::boot::{BootManager, IpcReceivers},
 };
-use queue::manager::SpawnQueue;
+use queue::{manager::SpawnQueue, sandbox::SmtpSandbox, tracking::spawn_delivery_tracker};
 use reporting::scheduler::SpawnReport;
 
 pub mod core;
@@ -42,6 +43,25 @@ impl SpawnQueueManager for IpcReceivers {
         self.queue_rx.take().unwrap().spawn(inner.clone());
 
         // Spawn report manager
-        self.report_rx.take().unwrap().spawn(inner);
+        self.report_rx.take().unwrap().spawn(inner.clone());
+
+        // Spawn delivery tracker
+        spawn_delivery_tracker(inner.clone());
+
+        // Spawn sandbox verdict poller
+        tokio::spawn(async move {
+            loop {
+                let poll_interval = inner
+                    .shared_core
+                    .load()
+                    .smtp
+                    .session
+                    .sandbox
+                    .as_ref()
+                    .map_or(Duration::from_secs(60), |sandbox| sandbox.poll_interval);
+                tokio::time::sleep(poll_interval).await;
+                inner.build_server().process_sandbox_verdicts().await;
+            }
+        });
     }
 }
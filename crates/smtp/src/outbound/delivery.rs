@@ -17,7 +17,7 @@ use crate::reporting::SmtpReporting;
 use common::Server;
 use common::config::{
     server::ServerProtocol,
-    smtp::{queue::RequireOptional, report::AggregateFrequency},
+    smtp::{queue::RequireOptional, report::AggregateFrequency, routing::ROUTE_RELAY_MX},
 };
 use common::ipc::{PolicyType, QueueEvent, QueueEventStatus, TlsEvent};
 
@@ -27,7 +27,7 @@ use mail_auth::{
     report::tlsrpt::{FailureDetails, ResultType},
 };
 use rand::Rng;
-use smtp_proto::MAIL_REQUIRETLS;
+use smtp_proto::{MAIL_REQUIRETLS, Response};
 use std::sync::Arc;
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
@@ -37,11 +37,20 @@ use store::write::{BatchBuilder, QueueClass, ValueClass, now};
 use trc::{DaneEvent, DeliveryEvent, MtaStsEvent, ServerEvent, TlsRptEvent};
 
 use crate::{
-    queue::{ErrorDetails, Message},
+    queue::{
+        ErrorDetails, HostResponse, Message, RCPT_STATUS_CHANGED,
+        budget::{BudgetStatus, RelayBudgetCheck},
+        suppression::SmtpSuppression,
+    },
     reporting::tls::TlsRptOptions,
 };
 
-use super::{NextHop, TlsStrategy, lookup::ToNextHop, mta_sts, session::SessionParams};
+use super::{
+    NextHop, TlsStrategy,
+    lookup::ToNextHop,
+    mta_sts,
+    session::{SessionParams, capabilities_mask},
+};
 use crate::queue::{Domain, Error, FROM_REPORT, QueueEnvelope, QueuedMessage, Status};
 
 impl QueuedMessage {
@@ -199,6 +208,7 @@ impl QueuedMessage {
         }
 
         let queue_config = &server.core.smtp.queue;
+        let route_override = server.route_override(message.queue_id).await;
         let no_ip = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
         let mut recipients = std::mem::take(&mut message.recipients);
         'next_domain: for domain_idx in 0..message.domains.len() {
@@ -217,6 +227,51 @@ impl QueuedMessage {
                 Total = domain.retry.inner,
             );
 
+            // Fail recipients on the suppression list
+            let mut has_pending_rcpts = false;
+            for rcpt in recipients.iter_mut().filter(|r| {
+                r.domain_idx == domain_idx as u32
+                    && matches!(r.status, Status::Scheduled | Status::TemporaryFailure(_))
+            }) {
+                match server.get_suppression(&rcpt.address_lcase).await {
+                    Ok(Some(entry)) => {
+                        trc::event!(
+                            Delivery(DeliveryEvent::RecipientSuppressed),
+                            SpanId = message.span_id,
+                            To = rcpt.address_lcase.clone(),
+                            Reason = entry.reason.clone(),
+                        );
+
+                        rcpt.status = Status::PermanentFailure(HostResponse {
+                            hostname: ErrorDetails {
+                                entity: "localhost".into(),
+                                details: format!("RCPT TO:<{}>", rcpt.address),
+                            },
+                            response: Response {
+                                code: 550,
+                                esc: [5, 1, 1],
+                                message: format!("Recipient suppressed: {}", entry.reason),
+                            },
+                        });
+                        rcpt.flags |= RCPT_STATUS_CHANGED;
+                    }
+                    Ok(None) => {
+                        has_pending_rcpts = true;
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.span_id(message.span_id)
+                                .details("Failed to check suppression list")
+                        );
+                        has_pending_rcpts = true;
+                    }
+                }
+            }
+            if !has_pending_rcpts {
+                message.domains[domain_idx].status = Status::Completed(());
+                continue 'next_domain;
+            }
+
             // Build envelope
             let mut envelope = QueueEnvelope::new(&message, domain_idx);
 
@@ -238,13 +293,44 @@ impl QueuedMessage {
                 }
             }
 
+            // Apply per-domain route overrides
+            let route = queue_config.routes.get(domain.domain.as_str());
+            if let Some(throttle) = route.and_then(|route| route.rate.as_ref()) {
+                if let Err(retry_at) = server
+                    .is_allowed(throttle, &envelope, message.span_id)
+                    .await
+                {
+                    trc::event!(
+                        Delivery(DeliveryEvent::RateLimitExceeded),
+                        Id = throttle.id.clone(),
+                        SpanId = span_id,
+                        Domain = domain.domain.clone(),
+                    );
+
+                    message.domains[domain_idx].set_rate_limiter_error(retry_at);
+                    continue 'next_domain;
+                }
+            }
+
             // Obtain next hop
-            let (mut remote_hosts, is_smtp) = match server
-                .eval_if::<String, _>(&queue_config.next_hop, &envelope, message.span_id)
-                .await
-                .and_then(|name| server.get_relay_host(&name, message.span_id))
+            let mut budget_charges = Vec::new();
+            let next_hop = match route_override
+                .as_ref()
+                .or_else(|| route.and_then(|route| route.relay.as_ref()))
             {
-                Some(next_hop) if next_hop.protocol == ServerProtocol::Http => {
+                Some(relay) => Some(relay.clone()).filter(|relay| relay != ROUTE_RELAY_MX),
+                None => {
+                    server
+                        .eval_if::<String, _>(&queue_config.next_hop, &envelope, message.span_id)
+                        .await
+                }
+            };
+            let (mut remote_hosts, is_smtp) = match next_hop.and_then(|name| {
+                server
+                    .get_relay_host(&name, message.span_id)
+                    .map(|host| (name, host))
+            }) {
+                Some((_, next_hop)) if next_hop.protocol == ServerProtocol::Http => {
                     // Deliver message locally
                     let delivery_result = message
                         .deliver_local(
@@ -267,25 +353,48 @@ impl QueuedMessage {
                     message.domains[domain_idx].set_status(delivery_result, &schedule);
                     continue 'next_domain;
                 }
-                Some(next_hop) => (
-                    vec![NextHop::Relay(next_hop)],
-                    next_hop.protocol == ServerProtocol::Smtp,
-                ),
+                Some((name, next_hop)) => {
+                    // Check relay budgets
+                    match server
+                        .check_relay_budget(&name, &message.return_path_domain, message.span_id)
+                        .await
+                    {
+                        BudgetStatus::Available(charges) => {
+                            budget_charges = charges;
+                            (
+                                vec![NextHop::Relay(next_hop)],
+                                next_hop.protocol == ServerProtocol::Smtp,
+                            )
+                        }
+                        BudgetStatus::Direct => (Vec::with_capacity(0), true),
+                        BudgetStatus::Defer(retry_at) => {
+                            message.domains[domain_idx].set_rate_limiter_error(retry_at);
+                            continue 'next_domain;
+                        }
+                    }
+                }
                 None => (Vec::with_capacity(0), true),
             };
 
             // Prepare TLS strategy
+            let route_tls = route.map(|route| &route.tls);
             let mut tls_strategy = TlsStrategy {
-                mta_sts: server
-                    .eval_if(&queue_config.tls.mta_sts, &envelope, message.span_id)
-                    .await
-                    .unwrap_or(RequireOptional::Optional),
+                mta_sts: match route_tls.and_then(|tls| tls.mta_sts) {
+                    Some(mta_sts) => mta_sts,
+                    None => server
+                        .eval_if(&queue_config.tls.mta_sts, &envelope, message.span_id)
+                        .await
+                        .unwrap_or(RequireOptional::Optional),
+                },
                 ..Default::default()
             };
-            let allow_invalid_certs = server
-                .eval_if(&queue_config.tls.invalid_certs, &envelope, message.span_id)
-                .await
-                .unwrap_or(false);
+            let allow_invalid_certs = match route_tls.and_then(|tls| tls.allow_invalid_certs) {
+                Some(allow_invalid_certs) => allow_invalid_certs,
+                None => server
+                    .eval_if(&queue_config.tls.invalid_certs, &envelope, message.span_id)
+                    .await
+                    .unwrap_or(false),
+            };
 
             // Obtain TLS reporting
             let tls_report = match server
@@ -680,14 +789,20 @@ impl QueuedMessage {
                 };
 
                 // Update TLS strategy
-                tls_strategy.dane = server
-                    .eval_if(&queue_config.tls.dane, &envelope, message.span_id)
-                    .await
-                    .unwrap_or(RequireOptional::Optional);
-                tls_strategy.tls = server
-                    .eval_if(&queue_config.tls.start, &envelope, message.span_id)
-                    .await
-                    .unwrap_or(RequireOptional::Optional);
+                tls_strategy.dane = match route_tls.and_then(|tls| tls.dane) {
+                    Some(dane) => dane,
+                    None => server
+                        .eval_if(&queue_config.tls.dane, &envelope, message.span_id)
+                        .await
+                        .unwrap_or(RequireOptional::Optional),
+                };
+                tls_strategy.tls = match route_tls.and_then(|tls| tls.starttls) {
+                    Some(starttls) => starttls,
+                    None => server
+                        .eval_if(&queue_config.tls.start, &envelope, message.span_id)
+                        .await
+                        .unwrap_or(RequireOptional::Optional),
+                };
 
                 // Lookup DANE policy
                 let dane_policy = if tls_strategy.try_dane() && is_smtp {
@@ -965,6 +1080,15 @@ impl QueuedMessage {
                             .eval_if(&queue_config.timeout.data, &envelope, message.span_id)
                             .await
                             .unwrap_or_else(|| Duration::from_secs(5 * 60)),
+                        disabled_capabilities: server
+                            .eval_if::<Vec<String>, _>(
+                                &queue_config.disable_capabilities,
+                                &envelope,
+                                message.span_id,
+                            )
+                            .await
+                            .map(|names| capabilities_mask(&names))
+                            .unwrap_or_default(),
                     };
 
                     // Prepare TLS connector
@@ -1302,6 +1426,19 @@ impl QueuedMessage {
                         .await
                         .unwrap_or_else(|| vec![Duration::from_secs(60)]);
                     message.domains[domain_idx].set_status(delivery_result, &schedule);
+
+                    // Charge relay budgets
+                    if !budget_charges.is_empty()
+                        && matches!(message.domains[domain_idx].status, Status::Completed(_))
+                    {
+                        server
+                            .charge_relay_budget(
+                                &budget_charges,
+                                &message.return_path_domain,
+                                message.span_id,
+                            )
+                            .await;
+                    }
                     continue 'next_domain;
                 }
             }
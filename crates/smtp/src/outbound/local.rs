@@ -7,7 +7,7 @@
 use common::Server;
 use email::message::delivery::{IngestMessage, LocalDeliveryStatus, MailDelivery};
 use smtp_proto::Response;
-use trc::SieveEvent;
+use trc::{DeliveryEvent, SieveEvent};
 
 use crate::{
     queue::{
@@ -37,10 +37,46 @@ impl Message {
                 total_completed += 1;
                 continue;
             }
+
+            // Defer delivery for recipients stored on degraded partitions
+            if let Some(partition_id) = server
+                .degraded_partition(&rcpt.address_lcase, self.span_id)
+                .await
+            {
+                trc::event!(
+                    Delivery(DeliveryEvent::PartitionDegraded),
+                    SpanId = self.span_id,
+                    To = rcpt.address_lcase.clone(),
+                    Id = partition_id,
+                );
+
+                rcpt.flags |= RCPT_STATUS_CHANGED;
+                rcpt.status = Status::TemporaryFailure(HostResponse {
+                    hostname: ErrorDetails {
+                        entity: "localhost".into(),
+                        details: format!("RCPT TO:<{}>", rcpt.address),
+                    },
+                    response: Response {
+                        code: 451,
+                        esc: [4, 3, 0],
+                        message: "Mailbox temporarily unavailable".into(),
+                    },
+                });
+                continue;
+            }
+
             recipient_addresses.push(rcpt.address_lcase.clone());
             pending_recipients.push(rcpt);
         }
 
+        if pending_recipients.is_empty() {
+            return if total_completed == total_rcpt {
+                Status::Completed(())
+            } else {
+                Status::Scheduled
+            };
+        }
+
         // Deliver message
         let delivery_result = server
             .deliver_message(IngestMessage {
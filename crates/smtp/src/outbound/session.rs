@@ -8,9 +8,9 @@ use common::Server;
 use common::config::smtp::queue::RequireOptional;
 use mail_send::Credentials;
 use smtp_proto::{
-    EXT_CHUNKING, EXT_DSN, EXT_REQUIRE_TLS, EXT_SIZE, EXT_SMTP_UTF8, EhloResponse, MAIL_REQUIRETLS,
-    MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE,
-    RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS, Severity,
+    EXT_8BIT_MIME, EXT_CHUNKING, EXT_DSN, EXT_PIPELINING, EXT_REQUIRE_TLS, EXT_SIZE, EXT_SMTP_UTF8,
+    EhloResponse, MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY,
+    RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS, Severity,
 };
 use std::time::Duration;
 use std::{fmt::Write, time::Instant};
@@ -34,6 +34,7 @@ pub struct SessionParams<'x> {
     pub timeout_mail: Duration,
     pub timeout_rcpt: Duration,
     pub timeout_data: Duration,
+    pub disabled_capabilities: u32,
     pub session_id: u64,
 }
 
@@ -47,7 +48,7 @@ impl Message {
         // Obtain capabilities
         let time = Instant::now();
         let capabilities = match smtp_client.say_helo(&params).await {
-            Ok(capabilities) => {
+            Ok(mut capabilities) => {
                 trc::event!(
                     Delivery(DeliveryEvent::Ehlo),
                     SpanId = params.session_id,
@@ -56,6 +57,9 @@ impl Message {
                     Elapsed = time.elapsed(),
                 );
 
+                // Ignore capabilities that are known to be broken on this host
+                capabilities.capabilities &= !params.disabled_capabilities;
+
                 capabilities
             }
             Err(status) => {
@@ -500,3 +504,20 @@ impl TlsStrategy {
             || self.is_mta_sts_required()
     }
 }
+
+pub fn capabilities_mask(names: &[String]) -> u32 {
+    let mut mask = 0;
+    for name in names {
+        mask |= match name.to_ascii_uppercase().as_str() {
+            "PIPELINING" => EXT_PIPELINING,
+            "8BITMIME" => EXT_8BIT_MIME,
+            "SMTPUTF8" => EXT_SMTP_UTF8,
+            "SIZE" => EXT_SIZE,
+            "CHUNKING" => EXT_CHUNKING,
+            "DSN" => EXT_DSN,
+            "REQUIRETLS" => EXT_REQUIRE_TLS,
+            _ => 0,
+        };
+    }
+    mask
+}
@@ -0,0 +1,381 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, path::Path, pin::Pin};
+
+use common::{
+    KV_ARCHIVE_CHAIN, Server,
+    config::smtp::queue::{ArchiveDestination, ArchivePolicy},
+};
+use mail_builder::{
+    MessageBuilder,
+    headers::{HeaderType, content_type::ContentType},
+    mime::{BodyPart, MimePart, make_boundary},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use store::{dispatch::lookup::KeyValue, write::now};
+use tokio::io::AsyncWriteExt;
+use trc::AddContext;
+
+use super::{JOURNAL_REPORT, Message, MessageSource, QueueEnvelope, spool::SmtpSpool};
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+pub trait MessageArchive: Sync + Send {
+    fn archive_message(
+        &self,
+        message: &Message,
+        raw_message: &[u8],
+        session_id: u64,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+// Each archived copy is linked to the previous one archived by the same node
+// under the same policy: chain = SHA-256(previous chain || message SHA-256 ||
+// sequence || archived at || retain until). Altering, removing or reordering
+// any archived message breaks the chain from that point on.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveRecord<'x> {
+    pub policy: &'x str,
+    pub node_id: u64,
+    pub sequence: u64,
+    pub queue_id: u64,
+    pub archived_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retain_until: Option<u64>,
+    pub from: &'x str,
+    pub recipients: Vec<&'x str>,
+    pub size: usize,
+    pub sha256: String,
+    pub previous: String,
+    pub chain: String,
+}
+
+impl MessageArchive for Server {
+    async fn archive_message(&self, message: &Message, raw_message: &[u8], session_id: u64) {
+        let envelope = QueueEnvelope::new(message, 0);
+
+        for policy in &self.core.smtp.queue.archive {
+            if !self
+                .eval_if::<bool, _>(&policy.enable, &envelope, session_id)
+                .await
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            match archive_with_policy(self, policy, message, raw_message, session_id).await {
+                Ok(record) => {
+                    trc::event!(
+                        Queue(trc::QueueEvent::Archived),
+                        SpanId = session_id,
+                        QueueId = message.queue_id,
+                        Id = policy.id.clone(),
+                        Total = record.sequence,
+                        Size = record.size,
+                    );
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(session_id)
+                            .ctx(trc::Key::QueueId, message.queue_id)
+                            .ctx(trc::Key::Id, policy.id.clone())
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn archive_with_policy<'x>(
+    server: &Server,
+    policy: &'x ArchivePolicy,
+    message: &'x Message,
+    raw_message: &[u8],
+    session_id: u64,
+) -> trc::Result<ArchiveRecord<'x>> {
+    // Chain updates are serialized so the sequence matches the archive order
+    let _lock = server.inner.data.archive_chain.lock().await;
+    let node_id = server.core.network.node_id;
+    let chain_key = format!("{}/{node_id}", policy.id);
+    let (sequence, previous) = match server
+        .in_memory_store()
+        .key_get::<String>(KeyValue::<()>::build_key(
+            KV_ARCHIVE_CHAIN,
+            chain_key.as_bytes(),
+        ))
+        .await
+        .caused_by(trc::location!())?
+        .as_deref()
+        .and_then(|head| head.split_once(' '))
+        .and_then(|(sequence, chain)| Some((sequence.parse::<u64>().ok()?, chain.to_string())))
+    {
+        Some((sequence, chain)) => (sequence + 1, chain),
+        None => (1, GENESIS_HASH.to_string()),
+    };
+
+    let archived_at = now();
+    let retain_until = policy
+        .retention
+        .map(|retention| archived_at + retention.as_secs());
+    let sha256 = format!("{:x}", Sha256::digest(raw_message));
+    let chain = format!(
+        "{:x}",
+        Sha256::new()
+            .chain_update(previous.as_bytes())
+            .chain_update(sha256.as_bytes())
+            .chain_update(sequence.to_be_bytes())
+            .chain_update(archived_at.to_be_bytes())
+            .chain_update(retain_until.unwrap_or_default().to_be_bytes())
+            .finalize()
+    );
+    let record = ArchiveRecord {
+        policy: &policy.id,
+        node_id,
+        sequence,
+        queue_id: message.queue_id,
+        archived_at,
+        retain_until,
+        from: &message.return_path,
+        recipients: message
+            .recipients
+            .iter()
+            .map(|rcpt| rcpt.address.as_str())
+            .collect(),
+        size: raw_message.len(),
+        sha256,
+        previous,
+        chain,
+    };
+
+    match &policy.destination {
+        ArchiveDestination::Smtp { address, from } => {
+            archive_to_smtp(server, &record, address, from, raw_message, session_id).await?
+        }
+        ArchiveDestination::Blob { store, prefix } => {
+            archive_to_blob(server, &record, store, prefix, raw_message).await?
+        }
+        ArchiveDestination::File { path } => archive_to_file(&record, path, raw_message).await?,
+    }
+
+    // Advance the chain head only once the copy was stored
+    server
+        .in_memory_store()
+        .key_set(KeyValue::with_prefix(
+            KV_ARCHIVE_CHAIN,
+            chain_key.as_bytes(),
+            format!("{} {}", record.sequence, record.chain).into_bytes(),
+        ))
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(record)
+}
+
+async fn archive_to_smtp(
+    server: &Server,
+    record: &ArchiveRecord<'_>,
+    address: &str,
+    from: &str,
+    raw_message: &[u8],
+    session_id: u64,
+) -> trc::Result<()> {
+    let mut body = format!("Sender: <{}>\r\n", record.from);
+    for rcpt in &record.recipients {
+        body.push_str(&format!("Recipient: <{rcpt}>\r\n"));
+    }
+    body.push_str(&format!(
+        "Queue-Id: {:x}\r\nArchived-At: {}\r\n",
+        record.queue_id,
+        mail_parser::DateTime::from_timestamp(record.archived_at as i64).to_rfc3339()
+    ));
+
+    let mut builder = MessageBuilder::new()
+        .from(from)
+        .header("To", HeaderType::Text(address.into()))
+        .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+        .header("X-Archive-Policy", HeaderType::Text(record.policy.into()))
+        .header(
+            "X-Archive-Sequence",
+            HeaderType::Text(format!("{}/{}", record.node_id, record.sequence).into()),
+        )
+        .header(
+            "X-Archive-Sha256",
+            HeaderType::Text(record.sha256.as_str().into()),
+        )
+        .header(
+            "X-Archive-Chain",
+            HeaderType::Text(format!("{} {}", record.previous, record.chain).into()),
+        )
+        .message_id(format!(
+            "<{}@{}>",
+            make_boundary("."),
+            server.core.network.server_name
+        ))
+        .subject(format!("Journal report {:x}", record.queue_id));
+    if let Some(retain_until) = record.retain_until {
+        builder = builder.header(
+            "X-Archive-Retain-Until",
+            HeaderType::Text(
+                mail_parser::DateTime::from_timestamp(retain_until as i64)
+                    .to_rfc3339()
+                    .into(),
+            ),
+        );
+    }
+    let report = builder
+        .body(MimePart::new(
+            ContentType::new("multipart/mixed"),
+            BodyPart::Multipart(vec![
+                MimePart::new(ContentType::new("text/plain"), BodyPart::Text(body.into())),
+                MimePart::new(
+                    ContentType::new("message/rfc822"),
+                    match std::str::from_utf8(raw_message) {
+                        Ok(text) => BodyPart::Text(text.into()),
+                        Err(_) => BodyPart::Binary(raw_message.into()),
+                    },
+                ),
+            ]),
+        ))
+        .write_to_vec()
+        .map_err(|err| {
+            trc::QueueEvent::ArchiveError
+                .into_err()
+                .details("Failed to build journal report")
+                .reason(err)
+        })?;
+
+    // Journal reports are never archived themselves
+    let from_lcase = from.to_lowercase();
+    let from_domain = from_lcase
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_string())
+        .unwrap_or_default();
+    let mut message = server.new_message(from, from_lcase, from_domain, session_id);
+    message.flags |= JOURNAL_REPORT;
+    message.add_recipient(address, server).await;
+
+    if queue_journal_report(server, message, report, session_id).await {
+        Ok(())
+    } else {
+        Err(trc::QueueEvent::ArchiveError
+            .into_err()
+            .details("Failed to queue journal report"))
+    }
+}
+
+async fn archive_to_blob(
+    server: &Server,
+    record: &ArchiveRecord<'_>,
+    store_id: &str,
+    prefix: &str,
+    raw_message: &[u8],
+) -> trc::Result<()> {
+    let store = server.core.storage.blobs.get(store_id).ok_or_else(|| {
+        trc::QueueEvent::ArchiveError
+            .into_err()
+            .details("Archive blob store not found")
+            .ctx(trc::Key::Id, store_id.to_string())
+    })?;
+    let key = format!(
+        "{prefix}/{}/{}/{:020}",
+        record.policy, record.node_id, record.sequence
+    );
+    let metadata = serde_json::to_vec(record).map_err(|err| {
+        trc::QueueEvent::ArchiveError
+            .into_err()
+            .details("Failed to serialize archive record")
+            .reason(err)
+    })?;
+
+    store
+        .put_blob(format!("{key}.eml").as_bytes(), raw_message)
+        .await
+        .caused_by(trc::location!())?;
+    store
+        .put_blob(format!("{key}.json").as_bytes(), &metadata)
+        .await
+        .caused_by(trc::location!())
+}
+
+// Messages are written once to their own file and their records are appended
+// to a per-node journal, existing files are never overwritten.
+async fn archive_to_file(
+    record: &ArchiveRecord<'_>,
+    path: &Path,
+    raw_message: &[u8],
+) -> trc::Result<()> {
+    let dir = path.join(record.policy);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|err| file_error(err, &dir))?;
+
+    let message_path = dir.join(format!("{}-{:020}.eml", record.node_id, record.sequence));
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&message_path)
+        .await
+        .map_err(|err| file_error(err, &message_path))?;
+    file.write_all(raw_message)
+        .await
+        .map_err(|err| file_error(err, &message_path))?;
+    file.sync_all()
+        .await
+        .map_err(|err| file_error(err, &message_path))?;
+
+    let mut line = serde_json::to_vec(record).map_err(|err| {
+        trc::QueueEvent::ArchiveError
+            .into_err()
+            .details("Failed to serialize archive record")
+            .reason(err)
+    })?;
+    line.push(b'\n');
+    let journal_path = dir.join(format!("journal-{}.jsonl", record.node_id));
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&journal_path)
+        .await
+        .map_err(|err| file_error(err, &journal_path))?;
+    file.write_all(&line)
+        .await
+        .map_err(|err| file_error(err, &journal_path))?;
+    file.sync_all()
+        .await
+        .map_err(|err| file_error(err, &journal_path))
+}
+
+// Queueing a journal report goes back through the spool, the boxed future
+// breaks the recursive type between the spool and the archiver.
+fn queue_journal_report(
+    server: &Server,
+    message: Message,
+    report: Vec<u8>,
+    session_id: u64,
+) -> Pin<Box<dyn Future<Output = bool> + Send + '_>> {
+    Box::pin(async move {
+        message
+            .queue(
+                None,
+                &report,
+                session_id,
+                server,
+                MessageSource::Autogenerated,
+            )
+            .await
+    })
+}
+
+fn file_error(err: std::io::Error, path: &Path) -> trc::Error {
+    trc::QueueEvent::ArchiveError
+        .into_err()
+        .details("Failed to write archive file")
+        .ctx(trc::Key::Path, path.to_string_lossy().into_owned())
+        .reason(err)
+}
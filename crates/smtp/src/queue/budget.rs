@@ -0,0 +1,176 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    KV_RELAY_BUDGET, Server,
+    config::smtp::queue::{BudgetAction, RelayBudget},
+};
+use mail_parser::DateTime;
+use store::{dispatch::lookup::KeyValue, write::now};
+use trc::DeliveryEvent;
+
+pub enum BudgetStatus {
+    Available(Vec<BudgetCharge>),
+    Direct,
+    Defer(u64),
+}
+
+pub struct BudgetCharge {
+    pub key: Vec<u8>,
+    pub budget_idx: usize,
+}
+
+// Budget counters are kept for two months so usage of the previous
+// period can still be queried after a new one starts
+const BUDGET_EXPIRY: u64 = 62 * 86400;
+
+pub trait RelayBudgetCheck: Sync + Send {
+    fn check_relay_budget(
+        &self,
+        relay: &str,
+        domain: &str,
+        span_id: u64,
+    ) -> impl Future<Output = BudgetStatus> + Send;
+
+    fn charge_relay_budget(
+        &self,
+        charges: &[BudgetCharge],
+        domain: &str,
+        span_id: u64,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn relay_budget_usage(
+        &self,
+        budget: &RelayBudget,
+        domain: &str,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+}
+
+impl RelayBudgetCheck for Server {
+    async fn check_relay_budget(&self, relay: &str, domain: &str, span_id: u64) -> BudgetStatus {
+        let mut charges = Vec::new();
+
+        for (budget_idx, budget) in self.core.smtp.queue.relay_budgets.iter().enumerate() {
+            if budget.relay != relay {
+                continue;
+            }
+
+            let used = match self.relay_budget_usage(budget, domain).await {
+                Ok(used) => used,
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(span_id)
+                            .details("Failed to obtain relay budget usage")
+                    );
+                    continue;
+                }
+            };
+
+            if used >= budget.messages {
+                trc::event!(
+                    Delivery(DeliveryEvent::RelayBudgetExceeded),
+                    SpanId = span_id,
+                    Id = budget.id.clone(),
+                    Domain = domain.to_string(),
+                    Total = used,
+                    Limit = budget.messages,
+                );
+
+                match budget.action {
+                    BudgetAction::Alert => {}
+                    BudgetAction::Direct => return BudgetStatus::Direct,
+                    BudgetAction::Defer => return BudgetStatus::Defer(next_period()),
+                }
+            }
+
+            charges.push(BudgetCharge {
+                key: budget_key(budget, domain),
+                budget_idx,
+            });
+        }
+
+        BudgetStatus::Available(charges)
+    }
+
+    async fn charge_relay_budget(&self, charges: &[BudgetCharge], domain: &str, span_id: u64) {
+        for charge in charges {
+            let Some(budget) = self.core.smtp.queue.relay_budgets.get(charge.budget_idx) else {
+                continue;
+            };
+
+            match self
+                .in_memory_store()
+                .counter_incr(
+                    KeyValue::new(charge.key.clone(), 1).expires(BUDGET_EXPIRY),
+                    true,
+                )
+                .await
+            {
+                Ok(used) => {
+                    if let Some(alert) = budget.alert {
+                        let threshold = budget.messages * alert / 100;
+                        if used as u64 == threshold.max(1) {
+                            trc::event!(
+                                Delivery(DeliveryEvent::RelayBudgetAlert),
+                                SpanId = span_id,
+                                Id = budget.id.clone(),
+                                Domain = domain.to_string(),
+                                Total = used,
+                                Limit = budget.messages,
+                            );
+                        }
+                    }
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(span_id)
+                            .details("Failed to update relay budget usage")
+                    );
+                }
+            }
+        }
+    }
+
+    async fn relay_budget_usage(&self, budget: &RelayBudget, domain: &str) -> trc::Result<u64> {
+        self.in_memory_store()
+            .counter_get(budget_key(budget, domain))
+            .await
+            .map(|used| used.max(0) as u64)
+    }
+}
+
+fn budget_key(budget: &RelayBudget, domain: &str) -> Vec<u8> {
+    let period = current_period();
+    let mut key = Vec::with_capacity(budget.id.len() + domain.len() + 8);
+    key.push(KV_RELAY_BUDGET);
+    key.extend_from_slice(budget.id.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&period.to_be_bytes());
+    key.extend_from_slice(domain.as_bytes());
+    key
+}
+
+fn current_period() -> u32 {
+    let dt = DateTime::from_timestamp(now() as i64);
+    (dt.year as u32 * 100) + dt.month as u32
+}
+
+fn next_period() -> u64 {
+    let mut dt = DateTime::from_timestamp(now() as i64);
+    if dt.month == 12 {
+        dt.year += 1;
+        dt.month = 1;
+    } else {
+        dt.month += 1;
+    }
+    dt.day = 1;
+    dt.hour = 0;
+    dt.minute = 0;
+    dt.second = 0;
+    dt.to_timestamp() as u64
+}
@@ -0,0 +1,397 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    config::smtp::list::{ListModeration, MailingList, ReplyToPolicy},
+};
+use directory::backend::RcptType;
+use mail_builder::{MessageBuilder, encoders::base64::base64_encode_mime, headers::HeaderType};
+use mail_parser::MessageParser;
+use trc::AddContext;
+
+use crate::{inbound::rewrite::MessageRewriter, reporting::SmtpReporting};
+
+use super::{
+    DomainPart, Message, MessageSource, QueueId,
+    quarantine::{QuarantineEntry, moderation_instructions},
+    quota::HasQueueQuota,
+    spool::SmtpSpool,
+};
+
+// Headers set by the list engine, any copies supplied by the poster are removed
+const LIST_HEADERS: &[&str] = &[
+    "Return-Path",
+    "List-Id",
+    "List-Post",
+    "List-Help",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+    "List-Subscribe",
+    "List-Archive",
+    "List-Owner",
+    "Precedence",
+];
+
+pub trait MailingListDelivery: Sync + Send {
+    fn list_post(
+        &self,
+        list: &MailingList,
+        members: Vec<String>,
+        sender: &str,
+        raw_message: &[u8],
+        quarantine: Option<QuarantineEntry>,
+        source: MessageSource,
+        session_id: u64,
+    ) -> impl Future<Output = Option<QueueId>> + Send;
+
+    fn list_distribute(
+        &self,
+        list: &MailingList,
+        members: Vec<String>,
+        raw_message: &[u8],
+        source: MessageSource,
+        session_id: u64,
+    ) -> impl Future<Output = Option<QueueId>> + Send;
+}
+
+impl MailingListDelivery for Server {
+    /// Accepts a post to a mailing list, posts that require moderation are
+    /// held in quarantine addressed to the list until a moderator releases them.
+    async fn list_post(
+        &self,
+        list: &MailingList,
+        members: Vec<String>,
+        sender: &str,
+        raw_message: &[u8],
+        quarantine: Option<QuarantineEntry>,
+        source: MessageSource,
+        session_id: u64,
+    ) -> Option<QueueId> {
+        let parsed = MessageParser::new().parse_headers(raw_message)?;
+        let from = parsed
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|from| from.address())
+            .map(|from| from.to_lowercase());
+        let is_member = members.iter().any(|member| {
+            member.eq_ignore_ascii_case(sender)
+                || from
+                    .as_deref()
+                    .is_some_and(|from| member.eq_ignore_ascii_case(from))
+        });
+        let quarantine = quarantine.or_else(|| {
+            let moderator = list.moderator.as_ref()?;
+            match list.moderation {
+                ListModeration::All => true,
+                ListModeration::NonMembers => !is_member,
+                ListModeration::None => false,
+            }
+            .then(|| {
+                QuarantineEntry::new("Awaiting list moderator approval")
+                    .with_from(from.as_deref())
+                    .with_subject(parsed.subject())
+                    .with_moderator(moderator.as_str())
+            })
+        });
+
+        let Some(quarantine) = quarantine else {
+            return self
+                .list_distribute(list, members, raw_message, source, session_id)
+                .await;
+        };
+
+        // Held posts are addressed to the list and expanded on release
+        let sender_lcase = sender.to_lowercase();
+        let sender_domain = sender_lcase.domain_part().to_string();
+        let mut message = self.new_message(sender, sender_lcase, sender_domain, session_id);
+        message.add_recipient(list.address.as_str(), self).await;
+        message.size = raw_message.len() as u64;
+        let queue_id = message.queue_id;
+        let moderation_request = quarantine.moderator.as_ref().map(|moderator| {
+            (
+                moderator.clone(),
+                build_moderation_request(
+                    self,
+                    list,
+                    &message,
+                    moderator,
+                    quarantine.subject.as_deref(),
+                ),
+            )
+        });
+
+        if !message
+            .queue_with_quarantine(
+                None,
+                raw_message,
+                session_id,
+                self,
+                source,
+                Some(quarantine),
+            )
+            .await
+        {
+            return None;
+        }
+
+        if let Some((moderator, request)) = moderation_request {
+            trc::event!(
+                Queue(trc::QueueEvent::ModerationRequested),
+                SpanId = session_id,
+                QueueId = queue_id,
+                To = moderator.clone(),
+            );
+
+            self.send_autogenerated(
+                self.core.smtp.session.data.moderation.from.clone(),
+                [moderator].into_iter(),
+                request,
+                None,
+                session_id,
+            )
+            .await;
+        }
+
+        Some(queue_id)
+    }
+
+    /// Queues a copy of a list post for each member. The list headers are added
+    /// once and, with VERP enabled, each copy gets its own return path while
+    /// sharing the same blob.
+    async fn list_distribute(
+        &self,
+        list: &MailingList,
+        members: Vec<String>,
+        raw_message: &[u8],
+        source: MessageSource,
+        session_id: u64,
+    ) -> Option<QueueId> {
+        let parsed = MessageParser::new().parse(raw_message)?;
+        let mut rewriter = MessageRewriter::new(&parsed);
+        for name in LIST_HEADERS {
+            rewriter.remove_header(name);
+        }
+        rewriter.add_header("List-Id", &list.list_id());
+        rewriter.add_header("List-Post", &format!("<mailto:{}>", list.address));
+        for (name, uri) in [
+            ("List-Help", &list.help),
+            ("List-Unsubscribe", &list.unsubscribe),
+            ("List-Subscribe", &list.subscribe),
+            ("List-Archive", &list.archive),
+            ("List-Owner", &list.owner),
+        ] {
+            if let Some(uri) = uri {
+                rewriter.add_header(name, &format!("<{uri}>"));
+            }
+        }
+        rewriter.add_header("Precedence", "list");
+        if let Some(prefix) = &list.subject_prefix {
+            let subject = parsed.subject().unwrap_or_default();
+            if !subject.contains(prefix.as_str()) {
+                rewriter.replace_header("Subject", &encode_text(&format!("{prefix} {subject}")));
+            }
+        }
+        match list.reply_to {
+            ReplyToPolicy::List => {
+                rewriter.replace_header("Reply-To", &format!("<{}>", list.address));
+            }
+            ReplyToPolicy::Sender => {
+                if let Some(from) = parsed
+                    .from()
+                    .and_then(|from| from.first())
+                    .and_then(|from| from.address())
+                {
+                    rewriter.replace_header("Reply-To", &format!("<{from}>"));
+                }
+            }
+            ReplyToPolicy::Keep => (),
+        }
+        rewriter.add_footer(list.footer_text.as_deref(), list.footer_html.as_deref());
+        let contents = rewriter.build().unwrap_or_else(|| raw_message.to_vec());
+
+        // Build one envelope per member with VERP, or a single one otherwise
+        let orcpt = format!("rfc822;{}", list.address);
+        let bounce_address = list.bounce_address();
+        let mut copies: Vec<Message> =
+            Vec::with_capacity(if list.verp { members.len() } else { 1 });
+        for member in members {
+            if member.eq_ignore_ascii_case(&list.address) {
+                continue;
+            }
+            if list.verp || copies.is_empty() {
+                let return_path = if list.verp {
+                    self.core.smtp.queue.verp.encode(&bounce_address, &member)
+                } else {
+                    bounce_address.clone()
+                };
+                let return_path_lcase = return_path.to_lowercase();
+                let return_path_domain = return_path_lcase.domain_part().to_string();
+                copies.push(self.new_message(
+                    return_path,
+                    return_path_lcase,
+                    return_path_domain,
+                    session_id,
+                ));
+            }
+            let copy = copies.last_mut().unwrap();
+            if !copy
+                .recipients
+                .iter()
+                .any(|rcpt| rcpt.address_lcase.eq_ignore_ascii_case(&member))
+            {
+                copy.add_recipient(member, self).await;
+                copy.recipients.last_mut().unwrap().orcpt = Some(orcpt.clone());
+            }
+        }
+        let queue_id = copies.first()?.queue_id;
+        let total = copies
+            .iter()
+            .map(|copy| copy.recipients.len())
+            .sum::<usize>();
+
+        // Sign the list copy on behalf of the list domain
+        let signature = self
+            .sign_message(
+                &mut copies[0],
+                &self.core.smtp.mail_auth.dkim.sign,
+                &contents,
+            )
+            .await;
+        let size = (contents.len() + signature.as_ref().map_or(0, |s| s.len())) as u64;
+        for copy in &mut copies {
+            copy.size = size;
+            if !self.has_quota(copy).await {
+                return None;
+            }
+        }
+
+        if !Message::queue_copies(
+            copies,
+            signature.as_deref(),
+            &contents,
+            session_id,
+            self,
+            source,
+        )
+        .await
+        {
+            return None;
+        }
+
+        trc::event!(
+            Queue(trc::QueueEvent::ListDistributed),
+            SpanId = session_id,
+            QueueId = queue_id,
+            To = list.address.clone(),
+            Total = total,
+        );
+
+        Some(queue_id)
+    }
+}
+
+impl Message {
+    /// Releases a post held for a mailing list, distributing it to the
+    /// current members of the list.
+    pub async fn release_list_post(self, list: &MailingList, server: &Server) -> trc::Result<bool> {
+        let members = match server
+            .rcpt(&server.core.storage.directory, &list.address, self.span_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            RcptType::List(members) => members,
+            _ => {
+                return Err(trc::StoreEvent::UnexpectedError
+                    .into_err()
+                    .details("Mailing list has no members")
+                    .ctx(trc::Key::To, list.address.clone())
+                    .ctx(trc::Key::QueueId, self.queue_id));
+            }
+        };
+        let raw_message = server
+            .blob_store()
+            .get_blob(self.blob_hash.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| {
+                trc::QueueEvent::BlobNotFound
+                    .into_err()
+                    .ctx(trc::Key::QueueId, self.queue_id)
+            })?;
+
+        if server
+            .list_distribute(
+                list,
+                members,
+                &raw_message,
+                MessageSource::Authenticated,
+                self.span_id,
+            )
+            .await
+            .is_some()
+        {
+            Ok(self.remove(server, 0).await)
+        } else {
+            Err(trc::StoreEvent::UnexpectedError
+                .into_err()
+                .details("Failed to distribute list post")
+                .ctx(trc::Key::QueueId, self.queue_id))
+        }
+    }
+}
+
+fn build_moderation_request(
+    server: &Server,
+    list: &MailingList,
+    message: &Message,
+    moderator: &str,
+    subject: Option<&str>,
+) -> Vec<u8> {
+    let config = &server.core.smtp.session.data.moderation;
+    let mut body = format!(
+        "A message posted to the list <{}> is awaiting your approval.\r\n\r\n",
+        list.address
+    );
+    body.push_str(&format!("Queue id: {:x}\r\n", message.queue_id));
+    body.push_str(&format!("Sender: <{}>\r\n", message.return_path));
+    if let Some(subject) = subject {
+        body.push_str(&format!("Subject: {subject}\r\n"));
+    }
+    body.push_str(&format!("Size: {} bytes\r\n\r\n", message.size));
+    body.push_str(&moderation_instructions(server, message));
+
+    MessageBuilder::new()
+        .from(config.from.as_str())
+        .to(moderator)
+        .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+        .subject(format!(
+            "Approval required for {}: {}",
+            list.address,
+            subject.unwrap_or("(no subject)")
+        ))
+        .text_body(body)
+        .write_to_vec()
+        .unwrap_or_default()
+}
+
+fn encode_text(value: &str) -> String {
+    if value.is_ascii() {
+        value
+            .chars()
+            .filter(|ch| !ch.is_ascii_control() || *ch == '\t')
+            .collect()
+    } else {
+        let mut encoded = Vec::new();
+        let _ = base64_encode_mime(value.as_bytes(), &mut encoded, true);
+        format!(
+            "=?utf-8?b?{}?=",
+            std::str::from_utf8(&encoded).unwrap_or_default()
+        )
+    }
+}
@@ -5,23 +5,27 @@
  */
 
 use std::{
+    collections::VecDeque,
     sync::{Arc, atomic::Ordering},
     time::{Duration, Instant},
 };
 
 use ahash::{AHashMap, AHashSet};
 use common::{
-    Inner,
+    Inner, Server,
+    config::smtp::queue::QueueFairShare,
     core::BuildServer,
     ipc::{QueueEvent, QueueEventStatus},
     listener::limiter::ConcurrencyLimiter,
 };
+use directory::backend::internal::manage::ManageDirectory;
 use rand::seq::SliceRandom;
 use store::write::now;
 use tokio::sync::mpsc;
 
 use super::{
-    Message, QueueId, Status,
+    Message, QueueId, QueuedMessage, Status,
+    ownership::QueueOwnership,
     spool::{QUEUE_REFRESH, SmtpSpool},
 };
 
@@ -30,6 +34,9 @@ pub struct Queue {
     pub on_hold: AHashMap<QueueId, OnHold>,
     pub next_wake_up: Instant,
     pub rx: mpsc::Receiver<QueueEvent>,
+    pub tenant_weights: AHashMap<u32, u32>,
+    pub next_weights_refresh: Instant,
+    pub ownership: QueueOwnership,
 }
 
 #[derive(Debug)]
@@ -54,6 +61,7 @@ impl SpawnQueue for mpsc::Receiver<QueueEvent> {
 
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
 const BACK_PRESSURE_WARN_INTERVAL: Duration = Duration::from_secs(60);
+const TENANT_WEIGHTS_REFRESH: Duration = Duration::from_secs(60);
 
 impl Queue {
     pub fn new(core: Arc<Inner>, rx: mpsc::Receiver<QueueEvent>) -> Self {
@@ -62,6 +70,9 @@ impl Queue {
             on_hold: AHashMap::with_capacity(128),
             next_wake_up: Instant::now(),
             rx,
+            tenant_weights: AHashMap::new(),
+            next_weights_refresh: Instant::now(),
+            ownership: QueueOwnership::default(),
         }
     }
 
@@ -73,8 +84,15 @@ impl Queue {
         let mut has_back_pressure = false;
 
         loop {
+            // Wake up in time to renew the queue node announcement
+            let next_wake_up = self
+                .ownership
+                .next_heartbeat()
+                .map_or(self.next_wake_up, |next_heartbeat| {
+                    next_heartbeat.min(self.next_wake_up)
+                });
             let refresh_queue = match tokio::time::timeout(
-                self.next_wake_up.duration_since(Instant::now()),
+                next_wake_up.duration_since(Instant::now()),
                 self.rx.recv(),
             )
             .await
@@ -117,11 +135,12 @@ impl Queue {
                 }
             };
 
-            if !is_paused {
+            if !is_paused && self.core.shared_core.load().network.roles.deliver_queue {
                 // Deliver scheduled messages
                 if refresh_queue || self.next_wake_up <= Instant::now() {
                     // If the number of in-flight messages is greater than the maximum allowed, skip the queue
                     let server = self.core.build_server();
+                    self.ownership.refresh(&server).await;
                     let max_in_flight = server.core.smtp.queue.max_threads;
                     has_back_pressure = in_flight_count >= max_in_flight;
                     if has_back_pressure {
@@ -160,10 +179,28 @@ impl Queue {
                     let mut next_wake_up = QUEUE_REFRESH;
                     let mut queue_events = server.next_event().await;
 
+                    // Skip events owned by other nodes in the cluster
+                    queue_events.retain(|event| self.ownership.is_owner(event.queue_id));
+
                     if queue_events.len() > 5 {
                         queue_events.shuffle(&mut rand::rng());
                     }
 
+                    // Interleave due messages across tenants
+                    if let Some(fair_share) = &server.core.smtp.queue.fair_share {
+                        if self.next_weights_refresh <= Instant::now() {
+                            self.next_weights_refresh = Instant::now() + TENANT_WEIGHTS_REFRESH;
+                            self.refresh_tenant_weights(&server, fair_share).await;
+                        }
+
+                        queue_events = fair_order(queue_events, now, |tenant_id| {
+                            tenant_id
+                                .and_then(|tenant_id| self.tenant_weights.get(&tenant_id))
+                                .copied()
+                                .unwrap_or(fair_share.default_weight)
+                        });
+                    }
+
                     for queue_event in &queue_events {
                         if queue_event.due <= now {
                             // Enforce global concurrency limits
@@ -261,13 +298,95 @@ impl Queue {
                     self.next_wake_up = now + Duration::from_secs(next_wake_up);
                 }
             } else {
-                // Queue is paused
+                // Queue is paused or this node does not deliver messages, stop
+                // announcing it so other nodes take over its share.
+                self.ownership = QueueOwnership::default();
                 self.next_wake_up = Instant::now() + Duration::from_secs(86400);
             }
         }
     }
 }
 
+impl Queue {
+    async fn refresh_tenant_weights(&mut self, server: &Server, fair_share: &QueueFairShare) {
+        self.tenant_weights.clear();
+        for (tenant, weight) in &fair_share.weights {
+            match server.store().get_principal_id(tenant).await {
+                Ok(Some(tenant_id)) => {
+                    self.tenant_weights.insert(tenant_id, *weight);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    trc::error!(
+                        err.details("Failed to obtain tenant id.")
+                            .caused_by(trc::location!())
+                    );
+                }
+            }
+        }
+    }
+}
+
+struct TenantEvents {
+    weight: i64,
+    current: i64,
+    events: VecDeque<QueuedMessage>,
+}
+
+/// Orders due events using smooth weighted round-robin across tenants, so
+/// each tenant is dispatched in proportion to its weight regardless of how
+/// many messages it has queued. Events that are not due yet are left last.
+fn fair_order(
+    events: Vec<QueuedMessage>,
+    now: u64,
+    weight: impl Fn(Option<u32>) -> u32,
+) -> Vec<QueuedMessage> {
+    let mut ordered = Vec::with_capacity(events.len());
+    let mut pending = Vec::new();
+    let mut tenants: Vec<TenantEvents> = Vec::new();
+    let mut tenant_pos: AHashMap<Option<u32>, usize> = AHashMap::new();
+
+    for event in events {
+        if event.due > now {
+            pending.push(event);
+        } else if let Some(pos) = tenant_pos.get(&event.tenant_id) {
+            tenants[*pos].events.push_back(event);
+        } else {
+            tenant_pos.insert(event.tenant_id, tenants.len());
+            tenants.push(TenantEvents {
+                weight: weight(event.tenant_id).max(1) as i64,
+                current: 0,
+                events: VecDeque::from([event]),
+            });
+        }
+    }
+
+    while tenants.len() > 1 {
+        let total = tenants.iter().map(|tenant| tenant.weight).sum::<i64>();
+        for tenant in tenants.iter_mut() {
+            tenant.current += tenant.weight;
+        }
+        let pos = tenants
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, tenant)| tenant.current)
+            .map(|(pos, _)| pos)
+            .unwrap_or_default();
+        let tenant = &mut tenants[pos];
+        tenant.current -= total;
+        ordered.extend(tenant.events.pop_front());
+        if tenant.events.is_empty() {
+            tenants.swap_remove(pos);
+        }
+    }
+    if let Some(tenant) = tenants.pop() {
+        ordered.extend(tenant.events);
+    }
+    ordered.extend(pending);
+
+    ordered
+}
+
 impl Message {
     pub fn next_event(&self) -> Option<u64> {
         let mut next_event = now();
@@ -380,3 +499,49 @@ impl Message {
 pub trait SpawnQueue {
     fn spawn(self, core: Arc<Inner>);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fair_order_by_weight() {
+        let events = (0..8)
+            .map(|queue_id| QueuedMessage {
+                due: 0,
+                queue_id,
+                tenant_id: Some(1),
+            })
+            .chain((8..12).map(|queue_id| QueuedMessage {
+                due: 0,
+                queue_id,
+                tenant_id: Some(2),
+            }))
+            .chain([QueuedMessage {
+                due: 100,
+                queue_id: 12,
+                tenant_id: Some(2),
+            }])
+            .collect::<Vec<_>>();
+
+        // Equal weights alternate between tenants
+        let ordered = fair_order(events.clone(), 10, |_| 1)
+            .into_iter()
+            .map(|event| event.tenant_id.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ordered, [2, 1, 2, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2]);
+
+        // A higher weight gets a proportionally larger share
+        let ordered = fair_order(
+            events,
+            10,
+            |tenant_id| {
+                if tenant_id == Some(2) { 3 } else { 1 }
+            },
+        )
+        .into_iter()
+        .map(|event| event.queue_id)
+        .collect::<Vec<_>>();
+        assert_eq!(ordered, [8, 9, 0, 10, 11, 1, 2, 3, 4, 5, 6, 7, 12]);
+    }
+}
@@ -17,11 +17,19 @@ use smtp_proto::{ArchivedResponse, Response};
 use store::write::now;
 use utils::BlobHash;
 
+pub mod archive;
+pub mod budget;
 pub mod dsn;
+pub mod list;
 pub mod manager;
+pub mod ownership;
+pub mod quarantine;
 pub mod quota;
+pub mod sandbox;
 pub mod spool;
+pub mod suppression;
 pub mod throttle;
+pub mod tracking;
 
 pub type QueueId = u64;
 
@@ -35,6 +43,7 @@ pub struct Schedule<T> {
 pub struct QueuedMessage {
     pub due: u64,
     pub queue_id: u64,
+    pub tenant_id: Option<u32>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -123,6 +132,8 @@ pub struct Recipient {
 
 pub const FROM_REPORT: u64 = 1 << 32;
 pub const DMARC_AUTHENTICATED: u64 = 2 << 32;
+pub const QUARANTINED: u64 = 4 << 32;
+pub const JOURNAL_REPORT: u64 = 8 << 32;
 
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
@@ -0,0 +1,194 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::{KV_QUEUE_NODE, Server};
+use store::{SerializeInfallible, dispatch::lookup::KeyValue, write::now};
+
+use super::QueueId;
+
+pub const NODE_HEARTBEAT: Duration = Duration::from_secs(10);
+const NODE_EXPIRY: u64 = 30;
+const VIRTUAL_NODES: u64 = 64;
+
+/// Consistent hash ring mapping queue ids to the nodes sharing the queue.
+#[derive(Debug, Default)]
+pub struct QueueRing {
+    nodes: Vec<u64>,
+    points: Vec<(u64, u64)>,
+}
+
+/// Tracks which queue events belong to this node. When `cluster.roles.queue`
+/// is set, the listed nodes announce themselves through the in-memory store
+/// and each queue id is processed by a single live node, instead of all nodes
+/// competing for the same event locks.
+#[derive(Debug, Default)]
+pub struct QueueOwnership {
+    node_id: u64,
+    ring: QueueRing,
+    next_heartbeat: Option<Instant>,
+}
+
+impl QueueOwnership {
+    pub fn next_heartbeat(&self) -> Option<Instant> {
+        self.next_heartbeat
+    }
+
+    pub fn is_owner(&self, queue_id: QueueId) -> bool {
+        self.next_heartbeat.is_none() || self.ring.owner(queue_id) == Some(self.node_id)
+    }
+
+    pub async fn refresh(&mut self, server: &Server) {
+        let queue_nodes = &server.core.network.roles.queue_nodes;
+        if queue_nodes.is_empty() {
+            if self.next_heartbeat.is_some() {
+                *self = Self::default();
+            }
+            return;
+        } else if self
+            .next_heartbeat
+            .is_some_and(|next_heartbeat| next_heartbeat > Instant::now())
+        {
+            return;
+        }
+
+        self.node_id = server.core.network.node_id;
+        self.next_heartbeat = Some(Instant::now() + NODE_HEARTBEAT);
+
+        // Announce this node and obtain the peers that are still alive
+        let mut live_nodes = Vec::with_capacity(queue_nodes.len());
+        for &node_id in queue_nodes {
+            if node_id == self.node_id {
+                if let Err(err) = server
+                    .in_memory_store()
+                    .key_set(
+                        KeyValue::with_prefix(
+                            KV_QUEUE_NODE,
+                            node_id.to_be_bytes(),
+                            now().serialize(),
+                        )
+                        .expires(NODE_EXPIRY),
+                    )
+                    .await
+                {
+                    trc::error!(
+                        err.details("Failed to announce queue node.")
+                            .caused_by(trc::location!())
+                    );
+                }
+                live_nodes.push(node_id);
+            } else {
+                match server
+                    .in_memory_store()
+                    .key_exists(KeyValue::<()>::build_key(
+                        KV_QUEUE_NODE,
+                        node_id.to_be_bytes(),
+                    ))
+                    .await
+                {
+                    Ok(true) => live_nodes.push(node_id),
+                    Ok(false) => {}
+                    Err(err) => {
+                        // Keep the current ring until membership can be verified
+                        trc::error!(
+                            err.details("Failed to obtain queue nodes.")
+                                .caused_by(trc::location!())
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+
+        if live_nodes != self.ring.nodes {
+            trc::event!(
+                Queue(trc::QueueEvent::Rebalanced),
+                Details = live_nodes
+                    .iter()
+                    .copied()
+                    .map(trc::Value::from)
+                    .collect::<Vec<_>>(),
+                Total = live_nodes.len(),
+            );
+
+            self.ring = QueueRing::new(live_nodes);
+        }
+    }
+}
+
+impl QueueRing {
+    pub fn new(nodes: Vec<u64>) -> Self {
+        let mut points = nodes
+            .iter()
+            .flat_map(|&node_id| {
+                (0..VIRTUAL_NODES).map(move |replica| (mix(mix(node_id) ^ replica), node_id))
+            })
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+
+        QueueRing { nodes, points }
+    }
+
+    pub fn owner(&self, queue_id: QueueId) -> Option<u64> {
+        let hash = mix(queue_id);
+        let pos = self.points.partition_point(|(point, _)| *point < hash);
+        self.points
+            .get(pos)
+            .or_else(|| self.points.first())
+            .map(|(_, node_id)| *node_id)
+    }
+}
+
+// Queue ids are generated from a timestamp, sequence and node id, so they are
+// mixed before being placed on the ring.
+fn mix(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9e3779b97f4a7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_ring_rebalance() {
+        let queue_ids = (0..10_000u64).map(|id| id << 22).collect::<Vec<_>>();
+        let ring = QueueRing::new(vec![1, 2, 3]);
+        let owners = queue_ids
+            .iter()
+            .map(|id| ring.owner(*id).unwrap())
+            .collect::<Vec<_>>();
+
+        // Queue ids are spread across all nodes
+        for node_id in [1, 2, 3] {
+            let owned = owners.iter().filter(|owner| **owner == node_id).count();
+            assert!(owned > 2_000, "node {node_id} owns {owned} ids");
+        }
+
+        // Adding a node only moves ids to the new node
+        let ring = QueueRing::new(vec![1, 2, 3, 4]);
+        let mut moved = 0;
+        for (queue_id, owner) in queue_ids.iter().zip(&owners) {
+            let new_owner = ring.owner(*queue_id).unwrap();
+            if new_owner != *owner {
+                assert_eq!(new_owner, 4);
+                moved += 1;
+            }
+        }
+        assert!(moved > 1_500 && moved < 4_000, "moved {moved} ids");
+
+        // Removing it restores the previous assignment
+        let ring = QueueRing::new(vec![1, 2, 3]);
+        for (queue_id, owner) in queue_ids.iter().zip(&owners) {
+            assert_eq!(ring.owner(*queue_id), Some(*owner));
+        }
+
+        assert_eq!(QueueRing::default().owner(1), None);
+    }
+}
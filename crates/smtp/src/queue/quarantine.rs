@@ -0,0 +1,375 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, ipc::QueueEvent};
+use store::{
+    Deserialize, IterateParams, Serialize, U64_LEN, ValueKey,
+    write::{
+        AlignedBytes, Archive, Archiver, BatchBuilder, QueueClass, ValueClass,
+        key::DeserializeBigEndian, now,
+    },
+};
+use trc::AddContext;
+use utils::crypto::{derive_key, sign_token, verify_token};
+
+use super::{
+    ArchivedMessage, Message, QUARANTINED, QueueId, Status,
+    spool::{SmtpSpool, queue_event_value},
+};
+
+const MODERATION_TOKEN_CONTEXT: &str = "moderation";
+const MODERATION_TOKEN_LEN: usize = U64_LEN + 1 + U64_LEN;
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct QuarantineEntry {
+    pub created: u64,
+    pub expires: u64,
+    pub reason: String,
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    pub moderator: Option<String>,
+    pub notes: Vec<QuarantineNote>,
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct QuarantineNote {
+    pub created: u64,
+    pub author: String,
+    pub text: String,
+}
+
+pub trait SmtpQuarantine: Sync + Send {
+    fn read_quarantine_entry(
+        &self,
+        id: QueueId,
+    ) -> impl Future<Output = trc::Result<Option<Archive<AlignedBytes>>>> + Send;
+
+    fn write_quarantine_entry(
+        &self,
+        id: QueueId,
+        entry: QuarantineEntry,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn purge_expired_quarantine(&self) -> impl Future<Output = trc::Result<usize>> + Send;
+}
+
+impl SmtpQuarantine for Server {
+    async fn read_quarantine_entry(
+        &self,
+        id: QueueId,
+    ) -> trc::Result<Option<Archive<AlignedBytes>>> {
+        self.store()
+            .get_value::<Archive<AlignedBytes>>(store::ValueKey::from(ValueClass::Queue(
+                QueueClass::Quarantine(id),
+            )))
+            .await
+    }
+
+    async fn write_quarantine_entry(&self, id: QueueId, entry: QuarantineEntry) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Queue(QueueClass::Quarantine(id)),
+            Archiver::new(entry)
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn purge_expired_quarantine(&self) -> trc::Result<usize> {
+        let now = now();
+        let mut expired = Vec::new();
+        self.store()
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Queue(QueueClass::Quarantine(0))),
+                    ValueKey::from(ValueClass::Queue(QueueClass::Quarantine(u64::MAX))),
+                )
+                .ascending(),
+                |key, value| {
+                    let entry = <Archive<AlignedBytes> as Deserialize>::deserialize(value)
+                        .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?;
+                    if u64::from(entry.unarchive::<QuarantineEntry>()?.expires) <= now {
+                        expired.push(key.deserialize_be_u64(1)?);
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        let mut total = 0;
+        for queue_id in expired {
+            // Skip messages that are being processed by a reviewer or another node
+            if !self.try_lock_event(queue_id).await {
+                continue;
+            }
+
+            let removed = match self.read_message(queue_id).await {
+                Some(message) if message.is_quarantined() => message.remove(self, 0).await,
+                Some(_) => false,
+                None => {
+                    // Drop entries left behind by messages that no longer exist
+                    let mut batch = BatchBuilder::new();
+                    batch.clear(ValueClass::Queue(QueueClass::Quarantine(queue_id)));
+                    self.store()
+                        .write(batch.build_all())
+                        .await
+                        .caused_by(trc::location!())?;
+                    false
+                }
+            };
+            self.unlock_event(queue_id).await;
+
+            if removed {
+                trc::event!(
+                    Queue(trc::QueueEvent::QuarantineExpired),
+                    QueueId = queue_id
+                );
+                total += 1;
+            }
+        }
+
+        Ok(total)
+    }
+}
+
+impl QuarantineEntry {
+    pub fn new(reason: impl Into<String>) -> Self {
+        QuarantineEntry {
+            created: now(),
+            expires: 0,
+            reason: reason.into(),
+            from: None,
+            subject: None,
+            moderator: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_from(mut self, from: Option<impl Into<String>>) -> Self {
+        self.from = from.map(Into::into);
+        self
+    }
+
+    pub fn with_subject(mut self, subject: Option<impl Into<String>>) -> Self {
+        self.subject = subject.map(Into::into);
+        self
+    }
+
+    pub fn with_message(self, message: &mail_parser::Message<'_>) -> Self {
+        self.with_from(
+            message
+                .from()
+                .and_then(|from| from.first())
+                .and_then(|from| from.address()),
+        )
+        .with_subject(message.subject())
+    }
+
+    pub fn with_moderator(mut self, moderator: impl Into<String>) -> Self {
+        self.moderator = Some(moderator.into());
+        self
+    }
+
+    pub fn with_expires(mut self, expires: u64) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// Entries without an explicit expiration are kept for the configured retention period,
+    /// messages awaiting a moderator expire along with their approval links.
+    pub(crate) fn with_default_expiry(mut self, server: &Server) -> Self {
+        if self.expires == 0 {
+            self.expires = self.created
+                + if self.moderator.is_some() {
+                    server.core.smtp.session.data.moderation.expiry
+                } else {
+                    server.core.smtp.queue.quarantine_retention
+                }
+                .as_secs();
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationAction {
+    Approve,
+    Reject,
+}
+
+/// Builds a signed token that allows a moderator to approve or reject
+/// a held message without logging in.
+pub fn moderation_token(
+    server: &Server,
+    queue_id: QueueId,
+    action: ModerationAction,
+    expires: u64,
+) -> String {
+    let mut token = Vec::with_capacity(MODERATION_TOKEN_LEN);
+    token.extend_from_slice(&queue_id.to_be_bytes());
+    token.push(action as u8);
+    token.extend_from_slice(&expires.to_be_bytes());
+    sign_token(&moderation_key(server), &token)
+}
+
+/// Validates a moderation token, returning the queue id and requested action.
+pub fn parse_moderation_token(server: &Server, token: &str) -> Option<(QueueId, ModerationAction)> {
+    let token = verify_token(&moderation_key(server), token, MODERATION_TOKEN_LEN)?;
+    let queue_id = QueueId::from_be_bytes(token[..U64_LEN].try_into().ok()?);
+    let action = match token[U64_LEN] {
+        0 => ModerationAction::Approve,
+        1 => ModerationAction::Reject,
+        _ => return None,
+    };
+    if u64::from_be_bytes(token[U64_LEN + 1..].try_into().ok()?) < now() {
+        return None;
+    }
+
+    Some((queue_id, action))
+}
+
+/// Describes how to approve or reject a held message, with signed links
+/// when a moderation URL is configured.
+pub fn moderation_instructions(server: &Server, message: &Message) -> String {
+    let config = &server.core.smtp.session.data.moderation;
+    if let Some(url) = &config.url {
+        let expires = message.created + config.expiry.as_secs();
+        [
+            (ModerationAction::Approve, "approve"),
+            (ModerationAction::Reject, "reject"),
+        ]
+        .into_iter()
+        .map(|(action, label)| {
+            format!(
+                "To {label} this message visit:\r\n{url}/moderate/{}\r\n\r\n",
+                moderation_token(server, message.queue_id, action, expires)
+            )
+        })
+        .collect()
+    } else {
+        "Use the quarantine management interface to approve or reject this message.\r\n".to_string()
+    }
+}
+
+fn moderation_key(server: &Server) -> [u8; 32] {
+    derive_key(&server.core.oauth.oauth_key, MODERATION_TOKEN_CONTEXT)
+}
+
+impl Message {
+    pub fn is_quarantined(&self) -> bool {
+        self.flags & QUARANTINED != 0
+    }
+
+    /// Holds a queued message in quarantine until it is released or deleted.
+    pub async fn hold(mut self, server: &Server, entry: QuarantineEntry) -> trc::Result<bool> {
+        if self.is_quarantined() {
+            return Ok(false);
+        }
+
+        let mut batch = BatchBuilder::new();
+        if let Some(prev_event) = self.next_event() {
+            batch.clear(ValueClass::Queue(QueueClass::MessageEvent(
+                store::write::QueueEvent {
+                    due: prev_event,
+                    queue_id: self.queue_id,
+                },
+            )));
+        }
+        self.flags |= QUARANTINED;
+        batch
+            .set(
+                ValueClass::Queue(QueueClass::Quarantine(self.queue_id)),
+                Archiver::new(entry.with_default_expiry(server))
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            )
+            .set(
+                ValueClass::Queue(QueueClass::Message(self.queue_id)),
+                Archiver::new(self)
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            );
+        server
+            .store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(true)
+    }
+
+    /// Releases a message held in quarantine, scheduling all pending
+    /// deliveries for immediate delivery.
+    pub async fn release_quarantine(mut self, server: &Server) -> trc::Result<bool> {
+        if !self.is_quarantined() {
+            return Ok(false);
+        }
+
+        // Posts held for a mailing list are distributed to its members
+        if let [rcpt] = self.recipients.as_slice() {
+            if let Some(list) = server.core.smtp.list.lists.get(&rcpt.address_lcase) {
+                return self.release_list_post(list, server).await;
+            }
+        }
+
+        let now = now();
+        self.flags &= !QUARANTINED;
+        for domain in &mut self.domains {
+            if matches!(
+                domain.status,
+                Status::Scheduled | Status::TemporaryFailure(_)
+            ) {
+                domain.retry.due = now;
+                if domain.expires < now {
+                    domain.expires = now + 10;
+                }
+            }
+        }
+
+        let mut batch = BatchBuilder::new();
+        if let Some(next_event) = self.next_event() {
+            batch.set(
+                ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
+                    due: next_event,
+                    queue_id: self.queue_id,
+                })),
+                queue_event_value(self.fair_share_tenant(server).await),
+            );
+        }
+        batch
+            .clear(ValueClass::Queue(QueueClass::Quarantine(self.queue_id)))
+            .set(
+                ValueClass::Queue(QueueClass::Message(self.queue_id)),
+                Archiver::new(self)
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            );
+        server
+            .store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
+        let _ = server.inner.ipc.queue_tx.send(QueueEvent::Refresh).await;
+
+        Ok(true)
+    }
+}
+
+impl ArchivedMessage {
+    pub fn is_quarantined(&self) -> bool {
+        u64::from(self.flags) & QUARANTINED != 0
+    }
+}
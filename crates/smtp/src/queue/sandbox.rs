@@ -0,0 +1,449 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    config::smtp::session::{Sandbox, SandboxAction},
+};
+use email::message::disposition::EmailDisposition;
+use hyper::header::CONTENT_TYPE;
+use store::{
+    Deserialize, IterateParams, Serialize, SerializeInfallible, ValueKey,
+    write::{
+        AlignedBytes, Archive, Archiver, BatchBuilder, BlobOp, QueueClass, ValueClass,
+        key::DeserializeBigEndian, now,
+    },
+};
+use trc::{AddContext, QueueEvent};
+use utils::{BlobHash, HttpLimitResponse};
+
+use super::{
+    Message, QueueId,
+    quarantine::{QuarantineEntry, QuarantineNote, SmtpQuarantine},
+    spool::SmtpSpool,
+};
+
+pub const SANDBOX_REASON: &str = "Awaiting sandbox verdict";
+pub const KEYWORD_RELEASED: &str = "$sandbox-released";
+pub const KEYWORD_FLAGGED: &str = "$sandbox-flagged";
+
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct SandboxPending {
+    pub created: u64,
+    pub expires: u64,
+    pub hold: bool,
+    pub submissions: Vec<String>,
+    pub message_id: Option<String>,
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SandboxVerdict {
+    Pending,
+    Clean,
+    Malicious(String),
+}
+
+#[derive(serde::Deserialize)]
+struct SubmitResponse {
+    id: String,
+}
+
+#[derive(serde::Deserialize)]
+struct VerdictResponse {
+    status: String,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+pub trait SmtpSandbox: Sync + Send {
+    fn write_sandbox_pending(
+        &self,
+        id: QueueId,
+        pending: SandboxPending,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn process_sandbox_verdicts(&self) -> impl Future<Output = ()> + Send;
+
+    fn apply_sandbox_disposition(
+        &self,
+        id: QueueId,
+        pending: SandboxPending,
+        action: SandboxAction,
+        status: &str,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SmtpSandbox for Server {
+    async fn write_sandbox_pending(&self, id: QueueId, pending: SandboxPending) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Queue(QueueClass::SandboxPending(id)),
+            Archiver::new(pending)
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn process_sandbox_verdicts(&self) {
+        let Some(sandbox) = &self.core.smtp.session.sandbox else {
+            return;
+        };
+
+        let mut entries = Vec::new();
+        if let Err(err) = self
+            .store()
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Queue(QueueClass::SandboxPending(0))),
+                    ValueKey::from(ValueClass::Queue(QueueClass::SandboxPending(u64::MAX))),
+                )
+                .ascending(),
+                |key, value| {
+                    entries.push((
+                        key.deserialize_be_u64(1)?,
+                        <Archive<AlignedBytes> as Deserialize>::deserialize(value)
+                            .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?,
+                    ));
+
+                    Ok(true)
+                },
+            )
+            .await
+        {
+            trc::error!(
+                err.details("Failed to read pending sandbox verdicts.")
+                    .caused_by(trc::location!())
+            );
+            return;
+        }
+
+        for (queue_id, pending_) in entries {
+            let pending = match pending_.deserialize::<SandboxPending>() {
+                Ok(pending) => pending,
+                Err(err) => {
+                    trc::error!(err.caused_by(trc::location!()));
+                    continue;
+                }
+            };
+
+            // Avoid processing the same message from multiple nodes
+            if !self.try_lock_event(queue_id).await {
+                continue;
+            }
+
+            let is_expired = pending.expires <= now();
+            let (action, status) = match sandbox.verdict(&pending.submissions).await {
+                Ok(SandboxVerdict::Clean) => {
+                    trc::event!(
+                        Queue(QueueEvent::SandboxVerdict),
+                        QueueId = queue_id,
+                        Result = "clean",
+                    );
+
+                    (SandboxAction::Release, "clean".to_string())
+                }
+                Ok(SandboxVerdict::Malicious(details)) => {
+                    trc::event!(
+                        Queue(QueueEvent::SandboxVerdict),
+                        QueueId = queue_id,
+                        Result = "malicious",
+                        Details = details.clone(),
+                    );
+
+                    (sandbox.action_malicious, format!("malicious ({details})"))
+                }
+                Ok(SandboxVerdict::Pending) if is_expired => {
+                    trc::event!(
+                        Queue(QueueEvent::SandboxTimeout),
+                        QueueId = queue_id,
+                        Expires = trc::Value::Timestamp(pending.expires),
+                    );
+
+                    (sandbox.action_timeout, "timeout".to_string())
+                }
+                Err(err) if is_expired => {
+                    trc::event!(
+                        Queue(QueueEvent::SandboxError),
+                        QueueId = queue_id,
+                        Reason = err,
+                    );
+
+                    (sandbox.action_timeout, "timeout".to_string())
+                }
+                Ok(SandboxVerdict::Pending) => {
+                    self.unlock_event(queue_id).await;
+                    continue;
+                }
+                Err(err) => {
+                    trc::event!(
+                        Queue(QueueEvent::SandboxError),
+                        QueueId = queue_id,
+                        Reason = err,
+                    );
+                    self.unlock_event(queue_id).await;
+                    continue;
+                }
+            };
+
+            if let Err(err) = self
+                .apply_sandbox_disposition(queue_id, pending, action, &status)
+                .await
+            {
+                trc::error!(
+                    err.details("Failed to apply sandbox verdict.")
+                        .ctx(trc::Key::QueueId, queue_id)
+                        .caused_by(trc::location!())
+                );
+            }
+
+            self.unlock_event(queue_id).await;
+        }
+    }
+
+    async fn apply_sandbox_disposition(
+        &self,
+        queue_id: QueueId,
+        pending: SandboxPending,
+        action: SandboxAction,
+        status: &str,
+    ) -> trc::Result<()> {
+        if pending.hold {
+            // Held messages are released or kept in quarantine
+            if let Some(message) = self
+                .read_message(queue_id)
+                .await
+                .filter(|message| message.is_quarantined())
+            {
+                let mut entry = self
+                    .read_quarantine_entry(queue_id)
+                    .await?
+                    .map(|entry| entry.deserialize::<QuarantineEntry>())
+                    .transpose()?;
+
+                // Messages held for other reasons remain in quarantine
+                let is_sandbox_hold = entry
+                    .as_ref()
+                    .is_some_and(|entry| entry.reason == SANDBOX_REASON);
+
+                match action {
+                    SandboxAction::Release if is_sandbox_hold => {
+                        message.release_quarantine(self).await?;
+                    }
+                    SandboxAction::Tag if is_sandbox_hold => {
+                        message
+                            .tag_and_release(
+                                self,
+                                format!("X-Sandbox-Status: {status}\r\n").as_bytes(),
+                            )
+                            .await?;
+                    }
+                    _ => {
+                        if let Some(mut entry) = entry.take() {
+                            if is_sandbox_hold {
+                                entry.reason = format!("Sandbox verdict: {status}");
+                            } else {
+                                entry.notes.push(QuarantineNote {
+                                    created: now(),
+                                    author: "sandbox".to_string(),
+                                    text: format!("Sandbox verdict: {status}"),
+                                });
+                            }
+                            self.write_quarantine_entry(queue_id, entry).await?;
+                        }
+                    }
+                }
+            }
+        } else if let Some(message_id) = &pending.message_id {
+            // Delivered messages are tagged in place, notifying any connected clients
+            let (keyword, move_to_junk) = match action {
+                SandboxAction::Release => (KEYWORD_RELEASED, false),
+                SandboxAction::Tag => (KEYWORD_FLAGGED, false),
+                SandboxAction::Quarantine => (KEYWORD_FLAGGED, true),
+            };
+
+            for rcpt in &pending.recipients {
+                if let Some(account_id) = self
+                    .email_to_id(&self.core.storage.directory, rcpt, 0)
+                    .await?
+                {
+                    self.email_set_disposition(account_id, message_id, keyword, move_to_junk)
+                        .await?;
+                }
+            }
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Queue(QueueClass::SandboxPending(queue_id)));
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+impl Message {
+    /// Prepends headers to a quarantined message before releasing it.
+    pub async fn tag_and_release(mut self, server: &Server, headers: &[u8]) -> trc::Result<bool> {
+        let Some(raw_message) = server
+            .blob_store()
+            .get_blob(self.blob_hash.as_slice(), 0..usize::MAX)
+            .await?
+        else {
+            return self.release_quarantine(server).await;
+        };
+
+        let mut message = Vec::with_capacity(headers.len() + raw_message.len());
+        message.extend_from_slice(headers);
+        message.extend_from_slice(&raw_message);
+        let prev_blob_hash = std::mem::replace(&mut self.blob_hash, BlobHash::generate(&message));
+        self.size += headers.len() as u64;
+
+        server
+            .blob_store()
+            .put_blob(self.blob_hash.as_slice(), &message)
+            .await?;
+
+        // Link the new blob before the message is updated, the previous
+        // one is unlinked only once the message no longer references it
+        let mut batch = BatchBuilder::new();
+        batch
+            .set(
+                BlobOp::LinkId {
+                    hash: self.blob_hash.clone(),
+                    id: self.queue_id,
+                },
+                vec![],
+            )
+            .set(
+                BlobOp::Commit {
+                    hash: self.blob_hash.clone(),
+                },
+                now().serialize(),
+            );
+        server
+            .store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
+        let queue_id = self.queue_id;
+        let result = self.release_quarantine(server).await?;
+
+        let mut batch = BatchBuilder::new();
+        batch.clear(BlobOp::LinkId {
+            hash: prev_blob_hash,
+            id: queue_id,
+        });
+        server
+            .store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(result)
+    }
+}
+
+pub(crate) trait SandboxClient {
+    async fn submit(&self, name: &str, contents: &[u8]) -> Result<String, String>;
+    async fn verdict(&self, submissions: &[String]) -> Result<SandboxVerdict, String>;
+}
+
+impl SandboxClient for Sandbox {
+    async fn submit(&self, name: &str, contents: &[u8]) -> Result<String, String> {
+        let response = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.tls_allow_invalid_certs)
+            .build()
+            .map_err(|err| format!("Failed to create HTTP client: {err}"))?
+            .post(format!("{}/submit", self.url))
+            .headers(self.headers.clone())
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header("X-Filename", name.replace(['\r', '\n'], ""))
+            .body(contents.to_vec())
+            .send()
+            .await
+            .map_err(|err| format!("Sandbox submission failed: {err}"))?;
+
+        if response.status().is_success() {
+            serde_json::from_slice::<SubmitResponse>(
+                response
+                    .bytes_with_limit(MAX_RESPONSE_SIZE)
+                    .await
+                    .map_err(|err| format!("Failed to read sandbox response: {err}"))?
+                    .ok_or_else(|| "Sandbox response too large".to_string())?
+                    .as_ref(),
+            )
+            .map(|response| response.id)
+            .map_err(|err| format!("Failed to parse sandbox response: {err}"))
+        } else {
+            Err(format!(
+                "Sandbox submission failed with code {}: {}",
+                response.status().as_u16(),
+                response.status().canonical_reason().unwrap_or("Unknown")
+            ))
+        }
+    }
+
+    async fn verdict(&self, submissions: &[String]) -> Result<SandboxVerdict, String> {
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.tls_allow_invalid_certs)
+            .build()
+            .map_err(|err| format!("Failed to create HTTP client: {err}"))?;
+        let mut verdict = SandboxVerdict::Clean;
+
+        // A single malicious attachment taints the whole message
+        for submission in submissions {
+            let response = client
+                .get(format!("{}/verdict/{submission}", self.url))
+                .headers(self.headers.clone())
+                .send()
+                .await
+                .map_err(|err| format!("Sandbox verdict request failed: {err}"))?;
+            if !response.status().is_success() {
+                return Err(format!(
+                    "Sandbox verdict request failed with code {}: {}",
+                    response.status().as_u16(),
+                    response.status().canonical_reason().unwrap_or("Unknown")
+                ));
+            }
+            let response = serde_json::from_slice::<VerdictResponse>(
+                response
+                    .bytes_with_limit(MAX_RESPONSE_SIZE)
+                    .await
+                    .map_err(|err| format!("Failed to read sandbox response: {err}"))?
+                    .ok_or_else(|| "Sandbox response too large".to_string())?
+                    .as_ref(),
+            )
+            .map_err(|err| format!("Failed to parse sandbox response: {err}"))?;
+
+            match response.status.as_str() {
+                "clean" => {}
+                "malicious" => {
+                    return Ok(SandboxVerdict::Malicious(
+                        response.details.unwrap_or_else(|| submission.clone()),
+                    ));
+                }
+                "pending" => verdict = SandboxVerdict::Pending,
+                status => return Err(format!("Unknown sandbox status {status:?}")),
+            }
+        }
+
+        Ok(verdict)
+    }
+}
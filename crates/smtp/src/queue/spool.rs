@@ -6,27 +6,40 @@
 
 use crate::queue::DomainPart;
 use common::ipc::QueueEvent;
-use common::{KV_LOCK_QUEUE_MESSAGE, Server};
+use common::{KV_LOCK_QUEUE_MESSAGE, KV_QUEUE_ROUTE, Server};
+use directory::backend::internal::manage::ManageDirectory;
 
 use std::borrow::Cow;
 use std::future::Future;
 use std::time::{Duration, SystemTime};
+use store::dispatch::lookup::KeyValue;
 use store::write::key::DeserializeBigEndian;
 use store::write::{
-    AlignedBytes, Archive, Archiver, BatchBuilder, BlobOp, QueueClass, ValueClass, now,
+    AlignedBytes, Archive, Archiver, BatchBuilder, BlobOp, QueueClass, ReportClass, ValueClass, now,
 };
 use store::{IterateParams, Serialize, SerializeInfallible, U64_LEN, ValueKey};
-use trc::ServerEvent;
+use trc::{AddContext, ServerEvent};
 use utils::BlobHash;
 
 use super::{
-    ArchivedMessage, ArchivedStatus, Domain, Message, MessageSource, QueueEnvelope, QueueId,
-    QueuedMessage, QuotaKey, Recipient, Schedule, Status,
+    ArchivedMessage, ArchivedStatus, Domain, JOURNAL_REPORT, Message, MessageSource, QUARANTINED,
+    QueueEnvelope, QueueId, QueuedMessage, QuotaKey, Recipient, Schedule, Status,
+    archive::MessageArchive,
+    quarantine::QuarantineEntry,
+    tracking::{QueuedEntry, TrackingEntry},
 };
 
 pub const LOCK_EXPIRY: u64 = 300;
 pub const QUEUE_REFRESH: u64 = 300;
 
+// Queue events store the tenant id plus one, zero is used for messages
+// that do not belong to a tenant.
+pub(crate) fn queue_event_value(tenant_id: Option<u32>) -> Vec<u8> {
+    tenant_id
+        .map_or(0u64, |tenant_id| tenant_id as u64 + 1)
+        .serialize()
+}
+
 pub trait SmtpSpool: Sync + Send {
     fn new_message(
         &self,
@@ -48,6 +61,8 @@ pub trait SmtpSpool: Sync + Send {
         &self,
         id: QueueId,
     ) -> impl Future<Output = trc::Result<Option<Archive<AlignedBytes>>>> + Send;
+
+    fn route_override(&self, id: QueueId) -> impl Future<Output = Option<String>> + Send;
 }
 
 impl SmtpSpool for Server {
@@ -99,12 +114,21 @@ impl SmtpSpool for Server {
         let result = self
             .store()
             .iterate(
-                IterateParams::new(from_key, to_key).ascending().no_values(),
-                |key, _| {
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
                     let due = key.deserialize_be_u64(0)?;
                     let queue_id = key.deserialize_be_u64(U64_LEN)?;
-
-                    events.push(QueuedMessage { due, queue_id });
+                    let tenant_id = value
+                        .deserialize_be_u64(0)
+                        .ok()
+                        .and_then(|tenant_id| tenant_id.checked_sub(1))
+                        .map(|tenant_id| tenant_id as u32);
+
+                    events.push(QueuedMessage {
+                        due,
+                        queue_id,
+                        tenant_id,
+                    });
 
                     Ok(due <= now)
                 },
@@ -184,16 +208,44 @@ impl SmtpSpool for Server {
             )))
             .await
     }
+
+    async fn route_override(&self, id: QueueId) -> Option<String> {
+        self.in_memory_store()
+            .key_get::<String>(KeyValue::<()>::build_key(KV_QUEUE_ROUTE, id.to_be_bytes()))
+            .await
+            .map_err(|err| {
+                trc::error!(
+                    err.details("Failed to read route override.")
+                        .caused_by(trc::location!())
+                );
+            })
+            .ok()
+            .flatten()
+    }
 }
 
 impl Message {
     pub async fn queue(
+        self,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        session_id: u64,
+        server: &Server,
+        source: MessageSource,
+    ) -> bool {
+        self.queue_with_quarantine(raw_headers, raw_message, session_id, server, source, None)
+            .await
+    }
+
+    /// Queues a message, holding it in quarantine for review when a quarantine entry is provided.
+    pub async fn queue_with_quarantine(
         mut self,
         raw_headers: Option<&[u8]>,
         raw_message: &[u8],
         session_id: u64,
         server: &Server,
         source: MessageSource,
+        quarantine: Option<QuarantineEntry>,
     ) -> bool {
         // Write blob
         let message = if let Some(raw_headers) = raw_headers {
@@ -212,38 +264,117 @@ impl Message {
         }
 
         // Reserve and write blob
-        let mut batch = BatchBuilder::new();
-        let reserve_until = now() + 120;
-        batch.set(
-            BlobOp::Reserve {
-                hash: self.blob_hash.clone(),
-                until: reserve_until,
-            },
-            0u32.serialize(),
-        );
-        if let Err(err) = server.store().write(batch.build_all()).await {
-            trc::error!(
-                err.details("Failed to write to store.")
-                    .span_id(session_id)
-                    .caused_by(trc::location!())
-            );
+        let Some(reserve_until) =
+            write_blob(&self.blob_hash, message.as_ref(), session_id, server).await
+        else {
+            return false;
+        };
 
+        self.write_entry(
+            message.as_ref(),
+            reserve_until,
+            session_id,
+            server,
+            source,
+            quarantine,
+        )
+        .await
+    }
+
+    /// Queues several envelopes that share the same contents, the blob is
+    /// written once and linked to each one of the queued messages.
+    pub async fn queue_copies(
+        messages: Vec<Message>,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        session_id: u64,
+        server: &Server,
+        source: MessageSource,
+    ) -> bool {
+        let message = if let Some(raw_headers) = raw_headers {
+            let mut message = Vec::with_capacity(raw_headers.len() + raw_message.len());
+            message.extend_from_slice(raw_headers);
+            message.extend_from_slice(raw_message);
+            Cow::Owned(message)
+        } else {
+            raw_message.into()
+        };
+        let blob_hash = BlobHash::generate(message.as_ref());
+        let Some(reserve_until) =
+            write_blob(&blob_hash, message.as_ref(), session_id, server).await
+        else {
             return false;
+        };
+
+        let mut result = true;
+        for mut copy in messages {
+            copy.blob_hash = blob_hash.clone();
+            if copy.size == 0 {
+                copy.size = message.len() as u64;
+            }
+            result &= copy
+                .write_entry(
+                    message.as_ref(),
+                    reserve_until,
+                    session_id,
+                    server,
+                    source,
+                    None,
+                )
+                .await;
         }
-        if let Err(err) = server
-            .blob_store()
-            .put_blob(self.blob_hash.as_slice(), message.as_ref())
-            .await
-        {
-            trc::error!(
-                err.details("Failed to write blob.")
-                    .span_id(session_id)
-                    .caused_by(trc::location!())
-            );
 
-            return false;
+        result
+    }
+
+    /// Splits a message into one copy per recipient, each one with the
+    /// recipient encoded in its return path. The first copy keeps the
+    /// queue id and quota reservations of the original message.
+    pub fn split_verp(self, server: &Server) -> Vec<Message> {
+        let verp = &server.core.smtp.queue.verp;
+        let mut copies = Vec::with_capacity(self.recipients.len());
+        for (pos, rcpt) in self.recipients.iter().enumerate() {
+            let return_path = verp.encode(&self.return_path, &rcpt.address_lcase);
+            copies.push(Message {
+                queue_id: if pos == 0 {
+                    self.queue_id
+                } else {
+                    server.inner.data.queue_id_gen.generate()
+                },
+                created: self.created,
+                blob_hash: self.blob_hash.clone(),
+                return_path_lcase: return_path.to_lowercase(),
+                return_path,
+                return_path_domain: self.return_path_domain.clone(),
+                recipients: vec![Recipient {
+                    domain_idx: 0,
+                    ..rcpt.clone()
+                }],
+                domains: vec![self.domains[rcpt.domain_idx as usize].clone()],
+                flags: self.flags,
+                env_id: self.env_id.clone(),
+                priority: self.priority,
+                size: self.size,
+                quota_keys: if pos == 0 {
+                    self.quota_keys.clone()
+                } else {
+                    Vec::new()
+                },
+                span_id: self.span_id,
+            });
         }
+        copies
+    }
 
+    async fn write_entry(
+        mut self,
+        message: &[u8],
+        reserve_until: u64,
+        session_id: u64,
+        server: &Server,
+        source: MessageSource,
+        quarantine: Option<QuarantineEntry>,
+    ) -> bool {
         trc::event!(
             Queue(match source {
                 MessageSource::Authenticated => trc::QueueEvent::QueueMessageAuthenticated,
@@ -270,9 +401,71 @@ impl Message {
             Expires = trc::Value::Timestamp(self.expires()),
         );
 
+        // Keep the envelope for archiving, copies are only taken once the message is queued
+        let archive = (!server.core.smtp.queue.archive.is_empty()
+            && self.flags & JOURNAL_REPORT == 0)
+            .then(|| self.clone());
+
         // Write message to queue
         let mut batch = BatchBuilder::new();
 
+        // Quarantined messages are not scheduled for delivery until released
+        if let Some(quarantine) = quarantine {
+            trc::event!(
+                Queue(trc::QueueEvent::Quarantined),
+                SpanId = session_id,
+                QueueId = self.queue_id,
+                Reason = quarantine.reason.clone(),
+            );
+
+            self.flags |= QUARANTINED;
+            batch.set(
+                ValueClass::Queue(QueueClass::Quarantine(self.queue_id)),
+                match Archiver::new(quarantine.with_default_expiry(server)).serialize() {
+                    Ok(data) => data,
+                    Err(err) => {
+                        trc::error!(
+                            err.details("Failed to serialize quarantine entry.")
+                                .span_id(session_id)
+                                .caused_by(trc::location!())
+                        );
+                        return false;
+                    }
+                },
+            );
+        } else {
+            batch.set(
+                ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
+                    due: self.next_event().unwrap_or_default(),
+                    queue_id: self.queue_id,
+                })),
+                queue_event_value(self.fair_share_tenant(server).await),
+            );
+        }
+
+        // Keep a record of the message for delivery tracking
+        if let Some(retention) = server.core.smtp.queue.tracking {
+            batch.set(
+                ValueClass::Report(ReportClass::Tracking {
+                    id: self.queue_id,
+                    expires: now() + retention.as_secs(),
+                }),
+                match Archiver::new(TrackingEntry::Queued(QueuedEntry::new(&self, message)))
+                    .serialize()
+                {
+                    Ok(data) => data,
+                    Err(err) => {
+                        trc::error!(
+                            err.details("Failed to serialize tracking entry.")
+                                .span_id(session_id)
+                                .caused_by(trc::location!())
+                        );
+                        return false;
+                    }
+                },
+            );
+        }
+
         // Reserve quotas
         for quota_key in &self.quota_keys {
             match quota_key {
@@ -288,13 +481,6 @@ impl Message {
             }
         }
         batch
-            .set(
-                ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
-                    due: self.next_event().unwrap_or_default(),
-                    queue_id: self.queue_id,
-                })),
-                0u64.serialize(),
-            )
             .clear(BlobOp::Reserve {
                 hash: self.blob_hash.clone(),
                 until: reserve_until,
@@ -310,7 +496,7 @@ impl Message {
                 BlobOp::Commit {
                     hash: self.blob_hash.clone(),
                 },
-                vec![],
+                now().serialize(),
             )
             .set(
                 ValueClass::Queue(QueueClass::Message(self.queue_id)),
@@ -354,6 +540,10 @@ impl Message {
             );
         }
 
+        if let Some(archive) = archive {
+            server.archive_message(&archive, message, session_id).await;
+        }
+
         true
     }
 
@@ -413,6 +603,67 @@ impl Message {
             .await;
     }
 
+    /// Returns the tenant owning the sender domain, used for scheduling
+    /// deliveries fairly across tenants.
+    pub async fn fair_share_tenant(&self, server: &Server) -> Option<u32> {
+        if server.core.smtp.queue.fair_share.is_none() || self.return_path_domain.is_empty() {
+            return None;
+        }
+
+        match server
+            .store()
+            .get_principal_info(&self.return_path_domain)
+            .await
+        {
+            Ok(info) => info.and_then(|info| info.tenant),
+            Err(err) => {
+                trc::error!(
+                    err.span_id(self.span_id)
+                        .details("Failed to obtain the tenant of the sender domain")
+                );
+                None
+            }
+        }
+    }
+
+    /// Delivers the pending recipients of a message through a different
+    /// relay host, scheduling them for immediate delivery.
+    pub async fn reroute(mut self, server: &Server, relay: &str) -> trc::Result<bool> {
+        let Some(prev_event) = self.next_event().filter(|_| !self.is_quarantined()) else {
+            return Ok(false);
+        };
+
+        let now = now();
+        let mut expires = now;
+        for domain in &mut self.domains {
+            if matches!(
+                domain.status,
+                Status::Scheduled | Status::TemporaryFailure(_)
+            ) {
+                domain.retry.due = now;
+                expires = expires.max(domain.expires);
+            }
+        }
+
+        server
+            .in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_QUEUE_ROUTE,
+                    self.queue_id.to_be_bytes(),
+                    relay.as_bytes().to_vec(),
+                )
+                .expires(expires.saturating_sub(now).max(LOCK_EXPIRY)),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        let next_event = self.next_event().unwrap_or_default();
+
+        Ok(self
+            .save_changes(server, prev_event.into(), next_event.into())
+            .await)
+    }
+
     pub async fn save_changes(
         mut self,
         server: &Server,
@@ -427,6 +678,7 @@ impl Message {
 
         // Update message queue
         if let (Some(prev_event), Some(next_event)) = (prev_event, next_event) {
+            let tenant_id = self.fair_share_tenant(server).await;
             batch
                 .clear(ValueClass::Queue(QueueClass::MessageEvent(
                     store::write::QueueEvent {
@@ -439,7 +691,7 @@ impl Message {
                         due: next_event,
                         queue_id: self.queue_id,
                     })),
-                    0u64.serialize(),
+                    queue_event_value(tenant_id),
                 );
         }
 
@@ -501,6 +753,9 @@ impl Message {
                 },
             )))
             .clear(ValueClass::Queue(QueueClass::Message(self.queue_id)));
+        if self.is_quarantined() {
+            batch.clear(ValueClass::Queue(QueueClass::Quarantine(self.queue_id)));
+        }
 
         if let Err(err) = server.store().write(batch.build_all()).await {
             trc::error!(
@@ -556,3 +811,44 @@ impl ArchivedMessage {
         next_delivery
     }
 }
+
+async fn write_blob(
+    blob_hash: &BlobHash,
+    message: &[u8],
+    session_id: u64,
+    server: &Server,
+) -> Option<u64> {
+    let mut batch = BatchBuilder::new();
+    let reserve_until = now() + 120;
+    batch.set(
+        BlobOp::Reserve {
+            hash: blob_hash.clone(),
+            until: reserve_until,
+        },
+        0u32.serialize(),
+    );
+    if let Err(err) = server.store().write(batch.build_all()).await {
+        trc::error!(
+            err.details("Failed to write to store.")
+                .span_id(session_id)
+                .caused_by(trc::location!())
+        );
+
+        return None;
+    }
+    if let Err(err) = server
+        .blob_store()
+        .put_blob(blob_hash.as_slice(), message)
+        .await
+    {
+        trc::error!(
+            err.details("Failed to write blob.")
+                .span_id(session_id)
+                .caused_by(trc::location!())
+        );
+
+        return None;
+    }
+
+    Some(reserve_until)
+}
@@ -0,0 +1,155 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::Server;
+use store::{
+    Deserialize, IterateParams, Serialize, ValueKey,
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, QueueClass, ValueClass, now},
+};
+use trc::AddContext;
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct SuppressionEntry {
+    pub created: u64,
+    pub expires: Option<u64>,
+    pub reason: String,
+}
+
+pub trait SmtpSuppression: Sync + Send {
+    fn get_suppression(
+        &self,
+        address: &str,
+    ) -> impl Future<Output = trc::Result<Option<SuppressionEntry>>> + Send;
+
+    fn add_suppression(
+        &self,
+        address: &str,
+        entry: SuppressionEntry,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn remove_suppression(&self, address: &str) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn list_suppressions(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> impl Future<Output = trc::Result<Vec<(String, SuppressionEntry)>>> + Send;
+}
+
+impl SmtpSuppression for Server {
+    async fn get_suppression(&self, address: &str) -> trc::Result<Option<SuppressionEntry>> {
+        let Some(archive) = self
+            .store()
+            .get_value::<Archive<AlignedBytes>>(ValueKey::from(ValueClass::Queue(
+                QueueClass::Suppression(address.to_lowercase().into_bytes()),
+            )))
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+
+        let entry = archive
+            .deserialize::<SuppressionEntry>()
+            .caused_by(trc::location!())?;
+
+        Ok(Some(entry).filter(|entry| !entry.is_expired(now())))
+    }
+
+    async fn add_suppression(&self, address: &str, entry: SuppressionEntry) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Queue(QueueClass::Suppression(address.to_lowercase().into_bytes())),
+            Archiver::new(entry)
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn remove_suppression(&self, address: &str) -> trc::Result<bool> {
+        if self.get_suppression(address).await?.is_none() {
+            return Ok(false);
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Queue(QueueClass::Suppression(
+            address.to_lowercase().into_bytes(),
+        )));
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| true)
+    }
+
+    async fn list_suppressions(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> trc::Result<Vec<(String, SuppressionEntry)>> {
+        let now = now();
+        let after = after.map(|address| address.to_lowercase().into_bytes());
+        let mut entries = Vec::new();
+
+        self.store()
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Queue(QueueClass::Suppression(
+                        after.clone().unwrap_or_default(),
+                    ))),
+                    ValueKey::from(ValueClass::Queue(QueueClass::Suppression(vec![
+                        u8::MAX;
+                        256
+                    ]))),
+                )
+                .ascending(),
+                |key, value| {
+                    let address = key.get(1..).unwrap_or_default();
+                    if after.as_deref() == Some(address) {
+                        return Ok(true);
+                    }
+
+                    let entry = <Archive<AlignedBytes> as Deserialize>::deserialize(value)
+                        .and_then(|archive| archive.deserialize::<SuppressionEntry>())
+                        .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?;
+                    if !entry.is_expired(now) {
+                        entries.push((String::from_utf8_lossy(address).into_owned(), entry));
+                    }
+
+                    Ok(limit == 0 || entries.len() < limit)
+                },
+            )
+            .await
+            .caused_by(trc::location!())
+            .map(|_| entries)
+    }
+}
+
+impl SuppressionEntry {
+    pub fn new(reason: impl Into<String>) -> Self {
+        SuppressionEntry {
+            created: now(),
+            expires: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn with_expires(mut self, expires: Option<u64>) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
@@ -6,12 +6,10 @@
 
 use std::future::Future;
 
-use common::{
-    KV_RATE_LIMIT_SMTP, Server, config::smtp::QueueRateLimiter, expr::functions::ResolveVariable,
-};
+use common::{Server, config::smtp::QueueRateLimiter, expr::functions::ResolveVariable};
 use store::write::now;
 
-use crate::core::throttle::NewKey;
+use crate::core::throttle::{NewKey, SmtpRateLimiter};
 
 use super::{Domain, Status};
 
@@ -39,13 +37,7 @@ impl IsAllowed for Server {
         {
             let key = throttle.new_key(envelope, "outbound");
 
-            match self
-                .core
-                .storage
-                .lookup
-                .is_rate_allowed(KV_RATE_LIMIT_SMTP, key.as_ref(), &throttle.rate, false)
-                .await
-            {
+            match self.check_rate_limit(throttle, &key).await {
                 Ok(Some(next_refill)) => {
                     trc::event!(
                         Queue(trc::QueueEvent::RateLimitExceeded),
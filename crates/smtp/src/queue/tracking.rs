@@ -0,0 +1,467 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use ahash::AHashMap;
+use common::{Inner, core::BuildServer};
+use mail_parser::MessageParser;
+use store::write::{Archiver, BatchBuilder, ReportClass, ValueClass, now};
+use trc::{
+    AddContext, DeliveryEvent, Event, EventDetails, EventType, Key, Value,
+    ipc::subscriber::{Interests, SubscriberBuilder},
+};
+
+use super::{Message, QueueId};
+
+const MAX_EVENTS: usize = 1024;
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub enum TrackingEntry {
+    Queued(QueuedEntry),
+    Attempt(DeliveryAttempt),
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct QueuedEntry {
+    pub queue_id: QueueId,
+    pub message_id: Option<String>,
+    pub from: String,
+    pub recipients: Vec<String>,
+    pub size: u64,
+    pub created: u64,
+}
+
+#[derive(
+    rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, serde::Serialize, Debug, Clone, PartialEq, Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryAttempt {
+    pub queue_id: QueueId,
+    pub started: u64,
+    pub duration: u64,
+    pub hosts: Vec<HostAttempt>,
+    pub recipients: Vec<RecipientResult>,
+}
+
+#[derive(
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    serde::Serialize,
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct HostAttempt {
+    pub domain: String,
+    pub hostname: String,
+    pub remote_ip: Option<String>,
+    pub tls_version: Option<String>,
+    pub tls_cipher: Option<String>,
+    pub code: Option<u16>,
+    pub response: Option<String>,
+}
+
+#[derive(
+    rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, serde::Serialize, Debug, Clone, PartialEq, Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientResult {
+    pub address: String,
+    pub hostname: String,
+    pub status: RecipientStatus,
+    pub code: Option<u16>,
+    pub response: Option<String>,
+}
+
+#[derive(
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    serde::Serialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+pub enum RecipientStatus {
+    #[serde(rename = "delivered")]
+    Delivered,
+    #[serde(rename = "temp_fail")]
+    TemporaryFailure,
+    #[serde(rename = "perm_fail")]
+    PermanentFailure,
+}
+
+impl TrackingEntry {
+    pub fn queue_id(&self) -> QueueId {
+        match self {
+            TrackingEntry::Queued(entry) => entry.queue_id,
+            TrackingEntry::Attempt(attempt) => attempt.queue_id,
+        }
+    }
+}
+
+impl QueuedEntry {
+    pub fn new(message: &Message, raw_message: &[u8]) -> Self {
+        QueuedEntry {
+            queue_id: message.queue_id,
+            message_id: MessageParser::new()
+                .parse_headers(raw_message)
+                .and_then(|parsed| parsed.message_id().map(|id| id.to_string())),
+            from: message.return_path.clone(),
+            recipients: message
+                .recipients
+                .iter()
+                .map(|rcpt| rcpt.address_lcase.clone())
+                .collect(),
+            size: message.size,
+            created: message.created,
+        }
+    }
+}
+
+impl DeliveryAttempt {
+    /// Builds the delivery history of an attempt from the events of its span.
+    pub fn from_events<'x>(
+        events: impl IntoIterator<Item = &'x Event<EventDetails>>,
+    ) -> Option<Self> {
+        let mut events = events.into_iter();
+        let start = events
+            .next()
+            .filter(|event| event.inner.typ == EventType::Delivery(DeliveryEvent::AttemptStart))?;
+        let mut attempt = DeliveryAttempt {
+            queue_id: start.value_as_uint(Key::QueueId)?,
+            started: start.inner.timestamp,
+            duration: 0,
+            hosts: Vec::new(),
+            recipients: Vec::new(),
+        };
+
+        for event in events {
+            let EventType::Delivery(typ) = event.inner.typ else {
+                continue;
+            };
+            match typ {
+                DeliveryEvent::Connect | DeliveryEvent::ConnectError => {
+                    let (code, response) = event_response(event);
+                    attempt.hosts.push(HostAttempt {
+                        domain: event_text(event, Key::Domain),
+                        hostname: event_text(event, Key::Hostname),
+                        remote_ip: event.value(Key::RemoteIp).map(|ip| ip.to_string()),
+                        code,
+                        response,
+                        ..Default::default()
+                    });
+                }
+                DeliveryEvent::StartTls => {
+                    if let Some(host) = attempt.host_mut(event) {
+                        host.tls_version = event.value_as_str(Key::Version).map(Into::into);
+                        host.tls_cipher = event.value_as_str(Key::Details).map(Into::into);
+                    }
+                }
+                DeliveryEvent::StartTlsUnavailable
+                | DeliveryEvent::StartTlsError
+                | DeliveryEvent::GreetingFailed
+                | DeliveryEvent::EhloRejected
+                | DeliveryEvent::MailFromRejected
+                | DeliveryEvent::MessageRejected => {
+                    if let Some(host) = attempt.host_mut(event) {
+                        (host.code, host.response) = event_response(event);
+                    }
+                }
+                DeliveryEvent::Delivered
+                | DeliveryEvent::RcptToRejected
+                | DeliveryEvent::RcptToFailed => {
+                    let (code, response) = event_response(event);
+                    let status = match (typ, code) {
+                        (DeliveryEvent::Delivered, _) => RecipientStatus::Delivered,
+                        (DeliveryEvent::RcptToRejected, Some(500..)) => {
+                            RecipientStatus::PermanentFailure
+                        }
+                        _ => RecipientStatus::TemporaryFailure,
+                    };
+                    if let Some(host) = attempt.host_mut(event) {
+                        host.code = code;
+                        host.response.clone_from(&response);
+                    }
+                    attempt.recipients.push(RecipientResult {
+                        address: event_text(event, Key::To),
+                        hostname: event_text(event, Key::Hostname),
+                        status,
+                        code,
+                        response,
+                    });
+                }
+                DeliveryEvent::AttemptEnd => {
+                    if let Some(Value::Duration(elapsed)) = event.value(Key::Elapsed) {
+                        attempt.duration = *elapsed;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Some(attempt)
+    }
+
+    fn host_mut(&mut self, event: &Event<EventDetails>) -> Option<&mut HostAttempt> {
+        let hostname = event.value_as_str(Key::Hostname)?;
+        self.hosts
+            .iter_mut()
+            .rev()
+            .find(|host| host.hostname == hostname)
+    }
+}
+
+/// Collects the events of each delivery attempt and stores them as a
+/// structured delivery history once the attempt ends.
+pub fn spawn_delivery_tracker(inner: Arc<Inner>) {
+    if inner.shared_core.load().smtp.queue.tracking.is_none() {
+        return;
+    }
+
+    let mut interests = Interests::default();
+    for event in EventType::variants() {
+        if matches!(event, EventType::Delivery(_)) {
+            interests.set(event);
+        }
+    }
+    let (_, mut rx) = SubscriberBuilder::new("delivery-tracker".to_string())
+        .with_interests(interests)
+        .with_global_interests(true)
+        .with_lossy(false)
+        .register();
+
+    tokio::spawn(async move {
+        let mut active_attempts: AHashMap<u64, Vec<Arc<Event<EventDetails>>>> = AHashMap::new();
+
+        while let Some(events) = rx.recv().await {
+            let server = inner.build_server();
+            let Some(retention) = server.core.smtp.queue.tracking else {
+                active_attempts.clear();
+                continue;
+            };
+            let mut batch = BatchBuilder::new();
+
+            for event in events {
+                let Some(span_id) = event.span_id() else {
+                    continue;
+                };
+                match event.inner.typ {
+                    EventType::Delivery(DeliveryEvent::AttemptStart) => {
+                        active_attempts.insert(span_id, vec![event]);
+                    }
+                    EventType::Delivery(DeliveryEvent::AttemptEnd) => {
+                        if let Some(attempt) = active_attempts.remove(&span_id).and_then(|events| {
+                            DeliveryAttempt::from_events(
+                                events.iter().chain([&event]).map(|event| event.as_ref()),
+                            )
+                        }) {
+                            match Archiver::new(TrackingEntry::Attempt(attempt)).serialize() {
+                                Ok(data) => {
+                                    batch.set(
+                                        ValueClass::Report(ReportClass::Tracking {
+                                            id: server.inner.data.queue_id_gen.generate(),
+                                            expires: now() + retention.as_secs(),
+                                        }),
+                                        data,
+                                    );
+                                }
+                                Err(err) => {
+                                    trc::error!(
+                                        err.details("Failed to serialize delivery attempt.")
+                                            .span_id(span_id)
+                                            .caused_by(trc::location!())
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        if let Some(events) = active_attempts.get_mut(&span_id) {
+                            if events.len() < MAX_EVENTS {
+                                events.push(event);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !batch.is_empty() {
+                if let Err(err) = server.store().write(batch.build_all()).await {
+                    trc::error!(err.caused_by(trc::location!()));
+                }
+            }
+        }
+    });
+}
+
+fn event_text(event: &Event<EventDetails>, key: Key) -> String {
+    event.value_as_str(key).unwrap_or_default().to_string()
+}
+
+// Obtains the reply code and text of an event, either from its own keys or
+// from the error that caused it.
+fn event_response(event: &Event<EventDetails>) -> (Option<u16>, Option<String>) {
+    match event.value(Key::CausedBy) {
+        Some(Value::Event(err)) => (
+            err.value_as_uint(Key::Code).map(|code| code as u16),
+            err.value_as_str(Key::Reason)
+                .or_else(|| err.value_as_str(Key::Details))
+                .map(Into::into),
+        ),
+        Some(value) => (None, Some(value.to_string())),
+        None => (
+            event.value_as_uint(Key::Code).map(|code| code as u16),
+            event
+                .value_as_str(Key::Details)
+                .or_else(|| event.value_as_str(Key::Reason))
+                .map(Into::into),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use trc::{Level, SmtpEvent};
+
+    use super::*;
+
+    fn event(typ: DeliveryEvent, keys: Vec<(Key, Value)>) -> Event<EventDetails> {
+        Event::with_keys(
+            EventDetails {
+                typ: EventType::Delivery(typ),
+                timestamp: 1000,
+                level: Level::Info,
+                span: None,
+            },
+            keys,
+        )
+    }
+
+    #[test]
+    fn attempt_from_events() {
+        let events = [
+            event(
+                DeliveryEvent::AttemptStart,
+                vec![
+                    (Key::SpanId, Value::UInt(1)),
+                    (Key::QueueId, Value::UInt(7)),
+                ],
+            ),
+            event(
+                DeliveryEvent::ConnectError,
+                vec![
+                    (Key::Domain, Value::String("example.org".into())),
+                    (Key::Hostname, Value::String("mx1.example.org".into())),
+                    (Key::RemoteIp, Value::Ipv4("10.0.0.1".parse().unwrap())),
+                    (
+                        Key::CausedBy,
+                        Value::Event(
+                            EventType::Smtp(SmtpEvent::Error)
+                                .into_err()
+                                .details("I/O Error")
+                                .reason("Connection refused"),
+                        ),
+                    ),
+                ],
+            ),
+            event(
+                DeliveryEvent::Connect,
+                vec![
+                    (Key::Domain, Value::String("example.org".into())),
+                    (Key::Hostname, Value::String("mx2.example.org".into())),
+                    (Key::RemoteIp, Value::Ipv4("10.0.0.2".parse().unwrap())),
+                ],
+            ),
+            event(
+                DeliveryEvent::StartTls,
+                vec![
+                    (Key::Hostname, Value::String("mx2.example.org".into())),
+                    (Key::Version, Value::String("TLSv1_3".into())),
+                    (
+                        Key::Details,
+                        Value::String("TLS13_AES_256_GCM_SHA384".into()),
+                    ),
+                ],
+            ),
+            event(
+                DeliveryEvent::RcptToRejected,
+                vec![
+                    (Key::Hostname, Value::String("mx2.example.org".into())),
+                    (Key::To, Value::String("jane@example.org".into())),
+                    (Key::Code, Value::UInt(550)),
+                    (Key::Details, Value::String("No such user".into())),
+                ],
+            ),
+            event(
+                DeliveryEvent::Delivered,
+                vec![
+                    (Key::Hostname, Value::String("mx2.example.org".into())),
+                    (Key::To, Value::String("john@example.org".into())),
+                    (Key::Code, Value::UInt(250)),
+                    (Key::Details, Value::String("Queued as 1234".into())),
+                ],
+            ),
+            event(
+                DeliveryEvent::AttemptEnd,
+                vec![(Key::Elapsed, Value::Duration(1500))],
+            ),
+        ];
+
+        assert_eq!(
+            DeliveryAttempt::from_events(events.iter()),
+            Some(DeliveryAttempt {
+                queue_id: 7,
+                started: 1000,
+                duration: 1500,
+                hosts: vec![
+                    HostAttempt {
+                        domain: "example.org".into(),
+                        hostname: "mx1.example.org".into(),
+                        remote_ip: Some("10.0.0.1".into()),
+                        response: Some("Connection refused".into()),
+                        ..Default::default()
+                    },
+                    HostAttempt {
+                        domain: "example.org".into(),
+                        hostname: "mx2.example.org".into(),
+                        remote_ip: Some("10.0.0.2".into()),
+                        tls_version: Some("TLSv1_3".into()),
+                        tls_cipher: Some("TLS13_AES_256_GCM_SHA384".into()),
+                        code: Some(250),
+                        response: Some("Queued as 1234".into()),
+                    },
+                ],
+                recipients: vec![
+                    RecipientResult {
+                        address: "jane@example.org".into(),
+                        hostname: "mx2.example.org".into(),
+                        status: RecipientStatus::PermanentFailure,
+                        code: Some(550),
+                        response: Some("No such user".into()),
+                    },
+                    RecipientResult {
+                        address: "john@example.org".into(),
+                        hostname: "mx2.example.org".into(),
+                        status: RecipientStatus::Delivered,
+                        code: Some(250),
+                        response: Some("Queued as 1234".into()),
+                    },
+                ],
+            })
+        );
+
+        // Events that do not start with an attempt are ignored
+        assert_eq!(DeliveryAttempt::from_events(events[1..].iter()), None);
+    }
+}
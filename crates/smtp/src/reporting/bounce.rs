@@ -0,0 +1,139 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::Server;
+use mail_parser::{Message, MimeHeaders};
+use store::write::now;
+
+use crate::queue::suppression::{SmtpSuppression, SuppressionEntry};
+
+pub trait VerpBounce: Sync + Send {
+    fn is_list_bounce(&self, address: &str) -> bool;
+
+    fn process_verp_bounce(
+        &self,
+        address: &str,
+        message: &Message<'_>,
+        session_id: u64,
+    ) -> impl Future<Output = bool> + Send;
+}
+
+impl VerpBounce for Server {
+    /// Returns true when the address is a VERP encoded bounce address of a mailing list.
+    fn is_list_bounce(&self, address: &str) -> bool {
+        self.core
+            .smtp
+            .queue
+            .verp
+            .decode(address)
+            .is_some_and(|(return_path, _)| {
+                self.core
+                    .smtp
+                    .list
+                    .lists
+                    .values()
+                    .any(|list| list.bounce_address().eq_ignore_ascii_case(&return_path))
+            })
+    }
+
+    /// Processes a bounce addressed to a VERP return path, suppressing the
+    /// recipient encoded in it on permanent failures. Returns true when the
+    /// bounce was addressed to a mailing list and should not be delivered.
+    async fn process_verp_bounce(
+        &self,
+        address: &str,
+        message: &Message<'_>,
+        session_id: u64,
+    ) -> bool {
+        let verp = &self.core.smtp.queue.verp;
+        let Some((return_path, rcpt)) = verp.decode(address) else {
+            return false;
+        };
+        let list = self
+            .core
+            .smtp
+            .list
+            .lists
+            .values()
+            .find(|list| list.bounce_address().eq_ignore_ascii_case(&return_path));
+
+        // Only permanent failures are acted upon
+        let Some(status) = permanent_failure(message) else {
+            return list.is_some();
+        };
+
+        trc::event!(
+            IncomingReport(trc::IncomingReportEvent::BounceReport),
+            SpanId = session_id,
+            From = return_path,
+            To = rcpt.clone(),
+            Code = status.clone(),
+            Id = list.map(|list| list.id.clone()),
+        );
+
+        if verp.suppress {
+            let reason = if let Some(list) = list {
+                format!("Bounced post to list {} ({status})", list.address)
+            } else {
+                format!("Bounced message ({status})")
+            };
+            if let Err(err) = self
+                .add_suppression(
+                    &rcpt,
+                    SuppressionEntry::new(reason)
+                        .with_expires(verp.suppress_expiry.map(|expiry| now() + expiry.as_secs())),
+                )
+                .await
+            {
+                trc::error!(
+                    err.span_id(session_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to add suppression entry")
+                );
+            }
+        }
+
+        list.is_some()
+    }
+}
+
+// Returns the status code of the first failed recipient in a delivery status notification
+fn permanent_failure(message: &Message<'_>) -> Option<String> {
+    for part in &message.parts {
+        if !part.content_type().is_some_and(|ct| {
+            ct.ctype().eq_ignore_ascii_case("message")
+                && ct
+                    .subtype()
+                    .is_some_and(|st| st.eq_ignore_ascii_case("delivery-status"))
+        }) {
+            continue;
+        }
+
+        // Per-recipient fields are separated by blank lines
+        let contents = String::from_utf8_lossy(part.contents());
+        for fields in contents.replace("\r\n", "\n").split("\n\n") {
+            let mut failed = false;
+            let mut status = None;
+            for line in fields.lines() {
+                if let Some((name, value)) = line.split_once(':') {
+                    let value = value.trim();
+                    if name.trim().eq_ignore_ascii_case("action") {
+                        failed = value.eq_ignore_ascii_case("failed");
+                    } else if name.trim().eq_ignore_ascii_case("status") {
+                        status = value.split_whitespace().next();
+                    }
+                }
+            }
+            if let Some(status) = status.filter(|status| failed && status.starts_with('5')) {
+                return Some(status.to_string());
+            }
+        }
+    }
+
+    None
+}
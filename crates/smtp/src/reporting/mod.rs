@@ -29,6 +29,7 @@ use crate::{
 };
 
 pub mod analysis;
+pub mod bounce;
 pub mod dkim;
 pub mod dmarc;
 pub mod scheduler;
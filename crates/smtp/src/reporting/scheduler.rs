@@ -33,7 +33,7 @@ impl SpawnReport for mpsc::Receiver<ReportingEvent> {
             loop {
                 let server = inner.build_server();
 
-                if refresh_queue {
+                if refresh_queue && server.core.network.roles.deliver_queue {
                     // Read events
                     let events = next_report_event(server.store()).await;
                     let now = now();
@@ -7,6 +7,7 @@
 use std::{borrow::Cow, future::Future, sync::Arc, time::Instant};
 
 use common::{Server, scripts::plugins::PluginContext};
+use email::sieve::SeenIdHash;
 
 use mail_auth::common::headers::HeaderWriter;
 use mail_parser::{Encoding, Message, MessagePart, PartType};
@@ -18,6 +19,7 @@ use smtp_proto::{
     MAIL_BY_TRACE, MAIL_RET_FULL, MAIL_RET_HDRS, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE,
     RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
+use store::{ahash::AHashMap, dispatch::lookup::KeyValue};
 use trc::SieveEvent;
 
 use crate::{
@@ -73,6 +75,7 @@ impl RunScript for Server {
         let mut reject_reason = None;
         let mut modifications = vec![];
         let mut keep_id = usize::MAX;
+        let mut checked_ids: AHashMap<SeenIdHash, bool> = AHashMap::new();
 
         // Start event loop
         while let Some(result) = instance.run(input) {
@@ -141,6 +144,38 @@ impl RunScript for Server {
                             )
                             .await;
                     }
+                    Event::DuplicateId { id, expiry, last } => {
+                        let id_hash = SeenIdHash::new_global(&script_id, &id);
+                        if let Some(result) = checked_ids.get(&id_hash) {
+                            input = (*result).into();
+                        } else {
+                            let exists =
+                                match self.in_memory_store().key_get::<()>(id_hash.key()).await {
+                                    Ok(exists) => exists.is_some(),
+                                    Err(err) => {
+                                        trc::error!(
+                                            err.span_id(session_id).caused_by(trc::location!())
+                                        );
+                                        false
+                                    }
+                                };
+
+                            if (!exists || last) && !params.is_test {
+                                if let Err(err) = self
+                                    .in_memory_store()
+                                    .key_set(KeyValue::new(id_hash.key(), vec![]).expires(expiry))
+                                    .await
+                                {
+                                    trc::error!(
+                                        err.span_id(session_id).caused_by(trc::location!())
+                                    );
+                                }
+                            }
+
+                            checked_ids.insert(id_hash, exists);
+                            input = exists.into();
+                        }
+                    }
                     Event::Keep { message_id, .. } => {
                         keep_id = message_id;
                         input = true.into();
@@ -279,7 +314,9 @@ impl RunScript for Server {
                         } else {
                             instance.message().raw_message().into()
                         };
-                        if let Some(raw_message) = raw_message.filter(|m| !m.is_empty()) {
+                        if let Some(raw_message) =
+                            raw_message.filter(|m| !m.is_empty() && !params.is_test)
+                        {
                             let headers = if !params.sign.is_empty() {
                                 let mut headers = Vec::new();
 
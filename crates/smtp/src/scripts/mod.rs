@@ -42,6 +42,7 @@ pub struct ScriptParameters<'x> {
     sign: Vec<String>,
     access_token: Option<&'x AccessToken>,
     session_id: u64,
+    is_test: bool,
 }
 
 impl<'x> ScriptParameters<'x> {
@@ -57,6 +58,7 @@ impl<'x> ScriptParameters<'x> {
             sign: Default::default(),
             access_token: None,
             session_id: Default::default(),
+            is_test: false,
         }
     }
 
@@ -121,6 +123,12 @@ impl<'x> ScriptParameters<'x> {
         self.session_id = session_id;
         self
     }
+
+    /// Scripts run in test mode do not send messages or record duplicate ids.
+    pub fn with_test(mut self, is_test: bool) -> Self {
+        self.is_test = is_test;
+        self
+    }
 }
 
 impl Default for ScriptParameters<'_> {
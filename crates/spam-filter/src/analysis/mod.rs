@@ -41,6 +41,7 @@ pub mod score;
 pub mod subject;
 pub mod trusted_reply;
 pub mod url;
+pub mod url_reputation;
 
 impl SpamFilterInput<'_> {
     pub fn header_as_address(&self, header: &Header<'_>) -> Option<Cow<'_, str>> {
@@ -84,6 +85,18 @@ impl SpamFilterResult {
         self.tags.insert(tag.into());
     }
 
+    pub fn add_weighted_tag(&mut self, tag: impl Into<CompactString>, weight: f64) {
+        let tag = tag.into();
+        if weight != 1.0 {
+            // Keep the highest weight when several sources add the same tag
+            self.tag_weights
+                .entry(tag.clone())
+                .and_modify(|w| *w = w.max(weight))
+                .or_insert(weight);
+        }
+        self.tags.insert(tag);
+    }
+
     pub fn has_tag(&self, tag: impl AsRef<str>) -> bool {
         self.tags.contains(tag.as_ref())
     }
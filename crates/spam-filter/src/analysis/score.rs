@@ -18,7 +18,7 @@ use crate::{
         recipient::SpamFilterAnalyzeRecipient, replyto::SpamFilterAnalyzeReplyTo,
         reputation::SpamFilterAnalyzeReputation, rules::SpamFilterAnalyzeRules,
         subject::SpamFilterAnalyzeSubject, trusted_reply::SpamFilterAnalyzeTrustedReply,
-        url::SpamFilterAnalyzeUrl,
+        url::SpamFilterAnalyzeUrl, url_reputation::SpamFilterAnalyzeUrlReputation,
     },
     modules::bayes::BayesClassifier,
 };
@@ -50,7 +50,9 @@ impl SpamFilterAnalyzeScore for Server {
 
         for tag in &ctx.result.tags {
             let score = match self.core.spam.lists.scores.get(tag) {
-                Some(SpamFilterAction::Allow(score)) => *score,
+                Some(SpamFilterAction::Allow(score)) => {
+                    *score * ctx.result.tag_weights.get(tag).copied().unwrap_or(1.0)
+                }
                 Some(SpamFilterAction::Discard) => {
                     return SpamFilterAction::Discard;
                 }
@@ -67,7 +69,12 @@ impl SpamFilterAnalyzeScore for Server {
         }
 
         // Write results header sorted by score
-        if let Some(header_name) = &self.core.spam.headers.result {
+        if let Some(header_name) = self.core.spam.headers.result.as_ref().filter(|_| {
+            ctx.input
+                .overrides
+                .and_then(|o| o.add_result_header)
+                .unwrap_or(true)
+        }) {
             let mut header = ctx
                 .result
                 .header
@@ -114,22 +121,38 @@ impl SpamFilterAnalyzeScore for Server {
             }
         }
 
-        if self.core.spam.scores.reject_threshold > 0.0
-            && ctx.result.score >= self.core.spam.scores.reject_threshold
-        {
+        // Thresholds may be overridden for the tenant, domain or account
+        let scores = &self.core.spam.scores;
+        let overrides = ctx.input.overrides;
+        let reject_threshold = overrides
+            .and_then(|o| o.reject_threshold)
+            .unwrap_or(scores.reject_threshold);
+        let discard_threshold = overrides
+            .and_then(|o| o.discard_threshold)
+            .unwrap_or(scores.discard_threshold);
+        let spam_threshold = overrides
+            .and_then(|o| o.spam_threshold)
+            .unwrap_or(scores.spam_threshold);
+
+        if reject_threshold > 0.0 && ctx.result.score >= reject_threshold {
             SpamFilterAction::Reject
-        } else if self.core.spam.scores.discard_threshold > 0.0
-            && ctx.result.score >= self.core.spam.scores.discard_threshold
-        {
+        } else if discard_threshold > 0.0 && ctx.result.score >= discard_threshold {
             SpamFilterAction::Discard
         } else {
             let mut header = std::mem::take(&mut ctx.result.header).unwrap_or_default();
-            if let Some(header_name) = &self.core.spam.headers.status {
+            if let Some(header_name) = self
+                .core
+                .spam
+                .headers
+                .status
+                .as_ref()
+                .filter(|_| overrides.and_then(|o| o.add_status_header).unwrap_or(true))
+            {
                 let _ = write!(
                     &mut header,
                     "{}: {}, score={:.2}\r\n",
                     header_name,
-                    if ctx.result.score >= self.core.spam.scores.spam_threshold {
+                    if ctx.result.score >= spam_threshold {
                         "Yes"
                     } else {
                         "No"
@@ -146,68 +169,115 @@ impl SpamFilterAnalyzeScore for Server {
         ctx: &mut SpamFilterContext<'_>,
     ) -> SpamFilterAction<String> {
         // IP address analysis
-        self.spam_filter_analyze_ip(ctx).await;
+        if ctx.is_module_enabled("ip") {
+            self.spam_filter_analyze_ip(ctx).await;
+        }
 
         // DMARC/SPF/DKIM/ARC analysis
-        self.spam_filter_analyze_dmarc(ctx).await;
+        if ctx.is_module_enabled("dmarc") {
+            self.spam_filter_analyze_dmarc(ctx).await;
+        }
 
         // EHLO hostname analysis
-        self.spam_filter_analyze_ehlo(ctx).await;
+        if ctx.is_module_enabled("ehlo") {
+            self.spam_filter_analyze_ehlo(ctx).await;
+        }
 
         // Generic header analysis
-        self.spam_filter_analyze_headers(ctx).await;
+        if ctx.is_module_enabled("headers") {
+            self.spam_filter_analyze_headers(ctx).await;
+        }
 
         // Received headers analysis
-        self.spam_filter_analyze_received(ctx).await;
+        if ctx.is_module_enabled("received") {
+            self.spam_filter_analyze_received(ctx).await;
+        }
 
         // Message-ID analysis
-        self.spam_filter_analyze_message_id(ctx).await;
+        if ctx.is_module_enabled("message-id") {
+            self.spam_filter_analyze_message_id(ctx).await;
+        }
 
         // Date header analysis
-        self.spam_filter_analyze_date(ctx).await;
+        if ctx.is_module_enabled("date") {
+            self.spam_filter_analyze_date(ctx).await;
+        }
 
         // Subject analysis
-        self.spam_filter_analyze_subject(ctx).await;
+        if ctx.is_module_enabled("subject") {
+            self.spam_filter_analyze_subject(ctx).await;
+        }
 
         // From and Envelope From analysis
-        self.spam_filter_analyze_from(ctx).await;
+        if ctx.is_module_enabled("from") {
+            self.spam_filter_analyze_from(ctx).await;
+        }
 
         // Reply-To analysis
-        self.spam_filter_analyze_reply_to(ctx).await;
+        if ctx.is_module_enabled("reply-to") {
+            self.spam_filter_analyze_reply_to(ctx).await;
+        }
 
         // Recipient analysis
-        self.spam_filter_analyze_recipient(ctx).await;
+        if ctx.is_module_enabled("recipient") {
+            self.spam_filter_analyze_recipient(ctx).await;
+        }
 
         // E-mail and domain analysis
-        self.spam_filter_analyze_domain(ctx).await;
+        if ctx.is_module_enabled("domain") {
+            self.spam_filter_analyze_domain(ctx).await;
+        }
 
         // URL analysis
-        self.spam_filter_analyze_url(ctx).await;
+        if ctx.is_module_enabled("url") {
+            self.spam_filter_analyze_url(ctx).await;
+        }
+
+        // URL reputation feeds
+        if ctx.is_module_enabled("url-reputation") {
+            self.spam_filter_analyze_url_reputation(ctx).await;
+        }
 
         // MIME part analysis
-        self.spam_filter_analyze_mime(ctx).await;
+        if ctx.is_module_enabled("mime") {
+            self.spam_filter_analyze_mime(ctx).await;
+        }
 
         // HTML content analysis
-        self.spam_filter_analyze_html(ctx).await;
+        if ctx.is_module_enabled("html") {
+            self.spam_filter_analyze_html(ctx).await;
+        }
 
         // LLM classification
         #[cfg(feature = "enterprise")]
-        self.spam_filter_analyze_llm(ctx).await;
+        if ctx.is_module_enabled("llm") {
+            self.spam_filter_analyze_llm(ctx).await;
+        }
 
         // Trusted reply analysis
-        self.spam_filter_analyze_reply_in(ctx).await;
+        if ctx.is_module_enabled("trusted-reply") {
+            self.spam_filter_analyze_reply_in(ctx).await;
+        }
 
         // Spam trap
-        self.spam_filter_analyze_spam_trap(ctx).await;
+        if ctx.is_module_enabled("spam-trap") {
+            self.spam_filter_analyze_spam_trap(ctx).await;
+        }
 
         // Pyzor checks
-        self.spam_filter_analyze_pyzor(ctx).await;
+        if ctx.is_module_enabled("pyzor") {
+            self.spam_filter_analyze_pyzor(ctx).await;
+        }
 
         // Bayes classification
-        self.spam_filter_analyze_bayes_classify(ctx).await;
+        if ctx.is_module_enabled("bayes") {
+            self.spam_filter_analyze_bayes_classify(ctx).await;
+        }
 
         // User-defined rules
-        self.spam_filter_analyze_rules(ctx).await;
+        if ctx.is_module_enabled("rules") {
+            self.spam_filter_analyze_rules(ctx).await;
+        }
 
         // Calculate score
         match self.spam_filter_score(ctx).await {
@@ -217,7 +287,9 @@ impl SpamFilterAnalyzeScore for Server {
         }
 
         // Reputation tracking and adjust score
-        self.spam_filter_analyze_reputation(ctx).await;
+        if ctx.is_module_enabled("reputation") {
+            self.spam_filter_analyze_reputation(ctx).await;
+        }
 
         // Final score calculation
         self.spam_filter_finalize(ctx).await
@@ -0,0 +1,142 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{KV_URL_REPUTATION, Server, config::spamfilter::UrlReputationFeed};
+use store::{ahash::AHashSet, dispatch::lookup::KeyValue};
+use trc::SpamEvent;
+
+use crate::SpamFilterContext;
+
+pub trait SpamFilterAnalyzeUrlReputation: Sync + Send {
+    fn spam_filter_analyze_url_reputation(
+        &self,
+        ctx: &mut SpamFilterContext<'_>,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl SpamFilterAnalyzeUrlReputation for Server {
+    async fn spam_filter_analyze_url_reputation(&self, ctx: &mut SpamFilterContext<'_>) {
+        let Some(config) = &self.core.spam.url_reputation else {
+            return;
+        };
+
+        // Build the list of keys to look up, URLs were extracted by the URL analysis
+        let mut urls = AHashSet::new();
+        let mut hosts = AHashSet::new();
+        let mut domains = AHashSet::new();
+        for url in ctx.output.urls.iter().take(config.max_urls) {
+            if let Some(url_parsed) = &url.element.url_parsed {
+                urls.insert(url.element.url.as_str());
+                if url_parsed.host.ip.is_none() {
+                    hosts.insert(url_parsed.host.fqdn.as_str());
+                    if let Some(sld) = &url_parsed.host.sld {
+                        domains.insert(sld.as_str());
+                    }
+                }
+            }
+        }
+        if urls.is_empty() {
+            return;
+        }
+
+        for feed in &config.feeds {
+            let keys = urls
+                .iter()
+                .filter(|_| feed.match_url)
+                .chain(hosts.iter().filter(|_| feed.match_host))
+                .chain(domains.iter().filter(|_| feed.match_domain));
+
+            for key in keys {
+                if self
+                    .is_url_listed(feed, key, config.cache_listed, config.cache_clean, ctx)
+                    .await
+                {
+                    trc::event!(
+                        Spam(SpamEvent::UrlReputationListed),
+                        SpanId = ctx.input.span_id,
+                        Id = feed.id.clone(),
+                        Details = key.to_string(),
+                    );
+                    ctx.result.add_tag(feed.tag.as_str());
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Server {
+    async fn is_url_listed(
+        &self,
+        feed: &UrlReputationFeed,
+        key: &str,
+        cache_listed: u64,
+        cache_clean: u64,
+        ctx: &SpamFilterContext<'_>,
+    ) -> bool {
+        let mut cache_key = Vec::with_capacity(feed.id.len() + key.len() + 2);
+        cache_key.push(KV_URL_REPUTATION);
+        cache_key.extend_from_slice(feed.id.as_bytes());
+        cache_key.push(0);
+        cache_key.extend_from_slice(key.as_bytes());
+
+        // Cached verdicts avoid querying the feed for every message
+        match self
+            .in_memory_store()
+            .key_get::<String>(cache_key.clone())
+            .await
+        {
+            Ok(Some(verdict)) => return verdict == "1",
+            Ok(None) => {}
+            Err(err) => {
+                trc::error!(err.span_id(ctx.input.span_id).caused_by(trc::location!()));
+            }
+        }
+
+        let Some(store) = self.core.storage.lookups.get(&feed.store) else {
+            trc::event!(
+                Spam(SpamEvent::UrlReputationError),
+                SpanId = ctx.input.span_id,
+                Id = feed.id.clone(),
+                Details = format!("Lookup store {:?} not found", feed.store),
+            );
+            return false;
+        };
+        let is_listed = match store.key_exists(key).await {
+            Ok(is_listed) => is_listed,
+            Err(err) => {
+                trc::error!(
+                    err.span_id(ctx.input.span_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to query URL reputation feed")
+                );
+                return false;
+            }
+        };
+
+        if let Err(err) = self
+            .in_memory_store()
+            .key_set(
+                KeyValue::new(
+                    cache_key,
+                    if is_listed {
+                        b"1".to_vec()
+                    } else {
+                        b"0".to_vec()
+                    },
+                )
+                .expires(if is_listed { cache_listed } else { cache_clean }),
+            )
+            .await
+        {
+            trc::error!(err.span_id(ctx.input.span_id).caused_by(trc::location!()));
+        }
+
+        is_listed
+    }
+}
@@ -14,12 +14,13 @@ use std::net::{IpAddr, Ipv4Addr};
 
 use analysis::ElementLocation;
 use analysis::url::UrlParts;
+use common::config::spamfilter::SpamFilterOverrides;
 use compact_str::CompactString;
 use mail_auth::{ArcOutput, DkimOutput, DmarcResult, IprevOutput, SpfOutput, dmarc::Policy};
 use mail_parser::Message;
 use modules::html::HtmlToken;
 use nlp::tokenizers::types::TokenType;
-use store::ahash::AHashSet;
+use store::ahash::{AHashMap, AHashSet};
 
 pub struct SpamFilterInput<'x> {
     pub message: &'x Message<'x>,
@@ -50,6 +51,7 @@ pub struct SpamFilterInput<'x> {
     pub env_rcpt_to: Vec<&'x str>,
 
     pub account_id: Option<u32>,
+    pub overrides: Option<&'x SpamFilterOverrides>,
     pub is_test: bool,
 }
 
@@ -102,6 +104,7 @@ pub enum TextPart<'x> {
 #[derive(Debug, Default)]
 pub struct SpamFilterResult {
     pub tags: AHashSet<CompactString>,
+    pub tag_weights: AHashMap<CompactString, f64>,
     pub score: f64,
     pub rbl_ip_checks: usize,
     pub rbl_domain_checks: usize,
@@ -158,6 +161,7 @@ impl<'x> SpamFilterInput<'x> {
             env_from_flags: 0,
             env_rcpt_to: vec![],
             account_id: None,
+            overrides: None,
             is_test: false,
         }
     }
@@ -183,11 +187,20 @@ impl<'x> SpamFilterInput<'x> {
             env_from_flags: 0,
             env_rcpt_to: vec![],
             account_id: Some(account_id),
+            overrides: None,
             is_test: false,
         }
     }
 }
 
+impl SpamFilterContext<'_> {
+    pub fn is_module_enabled(&self, module: &str) -> bool {
+        self.input
+            .overrides
+            .is_none_or(|overrides| overrides.is_module_enabled(module))
+    }
+}
+
 impl PartialEq for Hostname {
     fn eq(&self, other: &Self) -> bool {
         self.fqdn.eq(&other.fqdn)
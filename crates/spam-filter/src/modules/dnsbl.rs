@@ -17,6 +17,7 @@ use common::{
 };
 use compact_str::CompactString;
 use mail_auth::{Error, common::resolver::IntoFqdn};
+use store::write::now;
 use trc::SpamEvent;
 
 use crate::SpamFilterContext;
@@ -61,7 +62,7 @@ pub(crate) async fn check_dnsbl(
             )
             .await
             {
-                ctx.result.add_tag(tag);
+                ctx.result.add_weighted_tag(tag, dnsbl.weight);
             }
         }
     }
@@ -92,23 +93,21 @@ async fn is_dnsbl(
         if zone.contains(".11.20.") {
             let parts = zone.split('.').collect::<Vec<_>>();
 
-            return if config.tags.if_then.iter().any(|i| i.expr.items.len() == 3) && parts[0] != "2"
+            return if config
+                .tags
+                .as_ref()
+                .is_some_and(|tags| tags.if_then.iter().any(|i| i.expr.items.len() == 3))
+                && parts[0] != "2"
             {
                 None
             } else {
-                server
-                    .eval_if(
-                        &config.tags,
-                        &SpamFilterResolver::new(
-                            resolver.ctx,
-                            &IpResolver::new(
-                                format!("127.0.{}.{}", parts[1], parts[0]).parse().unwrap(),
-                            ),
-                            resolver.location,
-                        ),
-                        resolver.ctx.input.span_id,
-                    )
-                    .await
+                dnsbl_tag(
+                    server,
+                    config,
+                    &resolver,
+                    &IpResolver::new(format!("127.0.{}.{}", parts[1], parts[0]).parse().unwrap()),
+                )
+                .await
             };
         }
     }
@@ -117,17 +116,38 @@ async fn is_dnsbl(
         Some(Some(result)) => result,
         Some(None) => return None,
         None => {
+            // Skip lists that have been disabled after repeated failures
+            if server
+                .inner
+                .data
+                .dnsbl_status
+                .lock()
+                .get(&config.id)
+                .is_some_and(|status| status.disabled_until > now())
+            {
+                return None;
+            }
+
             *checks += 1;
 
-            match server
+            let lookup = server
                 .core
                 .smtp
                 .resolvers
                 .dns
-                .ipv4_lookup_raw((&zone).into_fqdn().as_ref())
-                .await
-            {
+                .ipv4_lookup_raw((&zone).into_fqdn().as_ref());
+            let result = if let Some(timeout) = config.timeout {
+                tokio::time::timeout(timeout, lookup)
+                    .await
+                    .unwrap_or_else(|_| Err(Error::Io("DNSBL lookup timed out".to_string())))
+            } else {
+                lookup.await
+            };
+
+            match result {
                 Ok(result) => {
+                    dnsbl_success(server, config);
+
                     trc::event!(
                         Spam(SpamEvent::Dnsbl),
                         Hostname = zone.clone(),
@@ -159,6 +179,8 @@ async fn is_dnsbl(
                     entry
                 }
                 Err(Error::DnsRecordNotFound(_)) => {
+                    dnsbl_success(server, config);
+
                     trc::event!(
                         Spam(SpamEvent::Dnsbl),
                         Hostname = zone.clone(),
@@ -176,6 +198,8 @@ async fn is_dnsbl(
                     return None;
                 }
                 Err(err) => {
+                    dnsbl_failure(server, config);
+
                     trc::event!(
                         Spam(SpamEvent::DnsblError),
                         Hostname = zone,
@@ -190,11 +214,54 @@ async fn is_dnsbl(
         }
     };
 
+    dnsbl_tag(server, config, &resolver, result.as_ref()).await
+}
+
+async fn dnsbl_tag(
+    server: &Server,
+    config: &DnsBlServer,
+    resolver: &SpamFilterResolver<'_, impl ResolveVariable>,
+    result: &IpResolver,
+) -> Option<CompactString> {
+    // Return code maps take precedence over tag expressions
+    if !config.return_codes.is_empty() {
+        let ip = result.ip();
+        if let Some((_, tag)) = config.return_codes.iter().find(|(code, _)| *code == ip) {
+            return Some(tag.as_str().into());
+        }
+    }
+
     server
         .eval_if(
-            &config.tags,
-            &SpamFilterResolver::new(resolver.ctx, result.as_ref(), resolver.location),
+            config.tags.as_ref()?,
+            &SpamFilterResolver::new(resolver.ctx, result, resolver.location),
             resolver.ctx.input.span_id,
         )
         .await
 }
+
+fn dnsbl_success(server: &Server, config: &DnsBlServer) {
+    let mut status_map = server.inner.data.dnsbl_status.lock();
+    if let Some(status) = status_map.remove(&config.id) {
+        if status.disabled_until != 0 {
+            trc::event!(Spam(SpamEvent::DnsblEnabled), Id = config.id.clone());
+        }
+    }
+}
+
+fn dnsbl_failure(server: &Server, config: &DnsBlServer) {
+    let dnsbl = &server.core.spam.dnsbl;
+    let mut status_map = server.inner.data.dnsbl_status.lock();
+    let status = status_map.entry(config.id.clone()).or_default();
+    status.failures += 1;
+
+    if status.failures >= dnsbl.max_failures && status.disabled_until <= now() {
+        status.disabled_until = now() + dnsbl.disable_for.as_secs();
+        trc::event!(
+            Spam(SpamEvent::DnsblDisabled),
+            Id = config.id.clone(),
+            Total = status.failures,
+            Expires = trc::Value::Timestamp(status.disabled_until),
+        );
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::ops::Range;
+
+use futures::TryStreamExt;
+
+use super::{CassandraStore, into_error};
+
+const BLOB_CHUNK_SIZE: usize = 512 * 1024;
+
+impl CassandraStore {
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let first_chunk = (range.start / BLOB_CHUNK_SIZE).min(i32::MAX as usize) as i32;
+        let last_chunk = if range.end != usize::MAX {
+            (range.end.saturating_sub(1) / BLOB_CHUNK_SIZE).min(i32::MAX as usize) as i32
+        } else {
+            i32::MAX
+        };
+        let s = self
+            .prepare(
+                "SELECT c, v FROM t WHERE k = ? AND c >= ? AND c <= ?".to_string(),
+                false,
+            )
+            .await?;
+        let mut rows = self
+            .session
+            .execute_iter(s, (key, first_chunk, last_chunk))
+            .await
+            .map_err(into_error)?
+            .rows_stream::<(i32, Vec<u8>)>()
+            .map_err(into_error)?;
+
+        let mut blob = Vec::new();
+        let mut found = false;
+        while let Some((chunk, bytes)) = rows.try_next().await.map_err(into_error)? {
+            let offset = chunk as usize * BLOB_CHUNK_SIZE;
+            let start = range.start.saturating_sub(offset).min(bytes.len());
+            let end = range.end.saturating_sub(offset).min(bytes.len());
+
+            if start == 0 && end == bytes.len() && blob.is_empty() {
+                blob = bytes;
+            } else {
+                blob.extend_from_slice(bytes.get(start..end).unwrap_or_default());
+            }
+            found = true;
+        }
+
+        if found || (first_chunk > 0 && self.has_blob(key).await?) {
+            Ok(Some(blob))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        let s = self
+            .prepare(
+                "INSERT INTO t (k, c, v) VALUES (?, ?, ?)".to_string(),
+                false,
+            )
+            .await?;
+
+        if !data.is_empty() {
+            for (chunk, bytes) in data.chunks(BLOB_CHUNK_SIZE).enumerate() {
+                self.session
+                    .execute_unpaged(&s, (key, chunk as i32, bytes))
+                    .await
+                    .map_err(into_error)?;
+            }
+        } else {
+            self.session
+                .execute_unpaged(&s, (key, 0i32, data))
+                .await
+                .map_err(into_error)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        if self.has_blob(key).await? {
+            let s = self
+                .prepare("DELETE FROM t WHERE k = ?".to_string(), false)
+                .await?;
+            self.session
+                .execute_unpaged(&s, (key,))
+                .await
+                .map_err(into_error)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn has_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        let s = self
+            .prepare("SELECT c FROM t WHERE k = ? LIMIT 1".to_string(), false)
+            .await?;
+        self.session
+            .execute_unpaged(&s, (key,))
+            .await
+            .map_err(into_error)?
+            .into_rows_result()
+            .map_err(into_error)?
+            .maybe_first_row::<(i32,)>()
+            .map_err(into_error)
+            .map(|row| row.is_some())
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use scylla::{
+    client::{execution_profile::ExecutionProfile, session_builder::SessionBuilder},
+    policies::load_balancing::DefaultPolicy,
+    statement::{Consistency, SerialConsistency},
+};
+use utils::config::{Config, utils::AsKey};
+
+use crate::*;
+
+use super::{CassandraStore, into_error};
+
+impl CassandraStore {
+    pub async fn open(
+        config: &mut Config,
+        prefix: impl AsKey,
+        create_tables: bool,
+    ) -> Option<Self> {
+        let prefix = prefix.as_key();
+        let nodes = config
+            .values((&prefix, "nodes"))
+            .map(|(_, node)| node.to_string())
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            config.new_build_error((&prefix, "nodes"), "No Cassandra nodes specified");
+            return None;
+        }
+        let keyspace = config.value_require((&prefix, "keyspace"))?.to_string();
+
+        let consistency = match config
+            .value((&prefix, "consistency.level"))
+            .unwrap_or("local-quorum")
+        {
+            "any" => Consistency::Any,
+            "one" => Consistency::One,
+            "two" => Consistency::Two,
+            "three" => Consistency::Three,
+            "quorum" => Consistency::Quorum,
+            "all" => Consistency::All,
+            "local-quorum" => Consistency::LocalQuorum,
+            "each-quorum" => Consistency::EachQuorum,
+            "local-one" => Consistency::LocalOne,
+            value => {
+                let err = format!("Invalid consistency level {value:?}");
+                config.new_build_error((&prefix, "consistency.level"), err);
+                return None;
+            }
+        };
+        let serial_consistency = match config
+            .value((&prefix, "consistency.serial"))
+            .unwrap_or("local-serial")
+        {
+            "serial" => SerialConsistency::Serial,
+            "local-serial" => SerialConsistency::LocalSerial,
+            value => {
+                let err = format!("Invalid serial consistency level {value:?}");
+                config.new_build_error((&prefix, "consistency.serial"), err);
+                return None;
+            }
+        };
+
+        let mut policy = DefaultPolicy::builder().token_aware(true);
+        if let Some(datacenter) = config.value((&prefix, "datacenter")) {
+            policy = policy.prefer_datacenter(datacenter.to_string());
+        }
+        let mut profile = ExecutionProfile::builder()
+            .consistency(consistency)
+            .serial_consistency(Some(serial_consistency))
+            .load_balancing_policy(policy.build());
+        if let Some(timeout) = config
+            .property::<Option<Duration>>((&prefix, "timeout"))
+            .unwrap_or_default()
+        {
+            profile = profile.request_timeout(Some(timeout));
+        }
+
+        let mut builder = SessionBuilder::new()
+            .known_nodes(nodes)
+            .default_execution_profile_handle(profile.build().into_handle());
+        if let Some(user) = config.value((&prefix, "user")) {
+            builder = builder.user(
+                user,
+                config.value((&prefix, "password")).unwrap_or_default(),
+            );
+        }
+        if let Some(timeout) = config
+            .property::<Option<Duration>>((&prefix, "connect-timeout"))
+            .unwrap_or_default()
+        {
+            builder = builder.connection_timeout(timeout);
+        }
+
+        let db = Self {
+            session: builder
+                .build()
+                .await
+                .map_err(|err| {
+                    config.new_build_error(
+                        prefix.as_str(),
+                        format!("Failed to connect to Cassandra: {err}"),
+                    )
+                })
+                .ok()?,
+            serial_consistency: match serial_consistency {
+                SerialConsistency::Serial => Consistency::Serial,
+                SerialConsistency::LocalSerial => Consistency::LocalSerial,
+            },
+            statements: Default::default(),
+            accounts: Default::default(),
+        };
+
+        if create_tables {
+            let replication_factor = config
+                .property_or_default::<u32>((&prefix, "replication-factor"), "1")
+                .unwrap_or(1);
+            if let Err(err) = db.create_keyspace(&keyspace, replication_factor).await {
+                config
+                    .new_build_error(prefix.as_str(), format!("Failed to create keyspace: {err}"));
+                return None;
+            }
+        }
+
+        if let Err(err) = db.session.use_keyspace(&keyspace, false).await {
+            config.new_build_error(
+                (&prefix, "keyspace"),
+                format!("Failed to use keyspace: {err}"),
+            );
+            return None;
+        }
+
+        if create_tables {
+            if let Err(err) = db.create_tables().await {
+                config.new_build_error(prefix.as_str(), format!("Failed to create tables: {err}"));
+            }
+        }
+
+        Some(db)
+    }
+
+    async fn create_keyspace(&self, keyspace: &str, replication_factor: u32) -> trc::Result<()> {
+        self.session
+            .query_unpaged(
+                format!(
+                    concat!(
+                        "CREATE KEYSPACE IF NOT EXISTS {} WITH replication = ",
+                        "{{'class': 'NetworkTopologyStrategy', 'replication_factor': {}}}"
+                    ),
+                    keyspace, replication_factor
+                ),
+                (),
+            )
+            .await
+            .map_err(into_error)?;
+
+        self.session
+            .await_schema_agreement()
+            .await
+            .map(|_| ())
+            .map_err(into_error)
+    }
+
+    pub(crate) async fn create_tables(&self) -> trc::Result<()> {
+        // All subspaces share the same table, keys are prefixed with their
+        // subspace so all the keys of an account can be stored in one partition
+        for query in [
+            concat!(
+                "CREATE TABLE IF NOT EXISTS data (p BLOB, k BLOB, v BLOB, ",
+                "PRIMARY KEY (p, k)) WITH CLUSTERING ORDER BY (k ASC)"
+            ),
+            concat!(
+                "CREATE TABLE IF NOT EXISTS accounts (p BLOB, k BLOB, ",
+                "PRIMARY KEY (p, k)) WITH CLUSTERING ORDER BY (k ASC)"
+            ),
+        ] {
+            self.session
+                .query_unpaged(query, ())
+                .await
+                .map_err(into_error)?;
+        }
+
+        self.session
+            .query_unpaged(
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        k BLOB,
+                        c INT,
+                        v BLOB,
+                        PRIMARY KEY (k, c)
+                    )",
+                    char::from(SUBSPACE_BLOBS)
+                ),
+                (),
+            )
+            .await
+            .map_err(into_error)?;
+
+        self.session
+            .await_schema_agreement()
+            .await
+            .map(|_| ())
+            .map_err(into_error)
+    }
+}
@@ -0,0 +1,249 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Display;
+
+use ahash::{AHashMap, AHashSet};
+use futures::{StreamExt, TryStreamExt, stream::BoxStream};
+use scylla::{
+    client::session::Session,
+    response::query_result::QueryResult,
+    statement::{Consistency, prepared::PreparedStatement, unprepared::Statement},
+    value::{CqlValue, Row},
+};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{
+    SUBSPACE_BITMAP_ID, SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_COUNTER,
+    SUBSPACE_FTS_INDEX, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_PROPERTY, U32_LEN,
+};
+
+pub mod blob;
+pub mod main;
+pub mod read;
+pub mod write;
+
+// Number of partitions the keys of each non-account subspace are spread across
+const GLOBAL_PARTITIONS: u8 = 16;
+
+// Partition of the table listing the accounts that have data stored
+const ACCOUNTS_PARTITION: &[u8] = &[0];
+
+// Keys are read in chunks to keep IN restrictions small
+const MAX_READ_KEYS: usize = 100;
+
+pub struct CassandraStore {
+    pub(crate) session: Session,
+    pub(crate) serial_consistency: Consistency,
+    pub(crate) statements: parking_lot::Mutex<AHashMap<(String, bool), PreparedStatement>>,
+    pub(crate) accounts: parking_lot::Mutex<AHashSet<Vec<u8>>>,
+}
+
+pub(crate) type RowStream = BoxStream<'static, trc::Result<(Vec<u8>, Vec<u8>)>>;
+
+impl CassandraStore {
+    pub(crate) async fn prepare(
+        &self,
+        query: impl Into<String>,
+        is_serial: bool,
+    ) -> trc::Result<PreparedStatement> {
+        let key = (query.into(), is_serial);
+        let statement = self.statements.lock().get(&key).cloned();
+        if let Some(statement) = statement {
+            return Ok(statement);
+        }
+
+        let mut statement = Statement::new(key.0.clone());
+        if is_serial {
+            statement.set_consistency(self.serial_consistency);
+        }
+        let statement = self.session.prepare(statement).await.map_err(into_error)?;
+        self.statements.lock().insert(key, statement.clone());
+
+        Ok(statement)
+    }
+
+    // Reads the current value of the given keys of a partition using a
+    // serial read, which includes the result of any in-progress transaction
+    pub(crate) async fn read_serial(
+        &self,
+        partition: &[u8],
+        keys: &[Vec<u8>],
+    ) -> trc::Result<AHashMap<Vec<u8>, Vec<u8>>> {
+        let s = self
+            .prepare("SELECT k, v FROM data WHERE p = ? AND k IN ?", true)
+            .await?;
+        let mut values = AHashMap::with_capacity(keys.len());
+
+        for keys in keys.chunks(MAX_READ_KEYS) {
+            let mut rows = self
+                .session
+                .execute_iter(s.clone(), (partition, keys))
+                .await
+                .map_err(into_error)?
+                .rows_stream::<(Vec<u8>, Vec<u8>)>()
+                .map_err(into_error)?;
+            while let Some((key, value)) = rows.try_next().await.map_err(into_error)? {
+                values.insert(key, value);
+            }
+        }
+
+        Ok(values)
+    }
+
+    // Streams the keys of a partition within the given range
+    pub(crate) async fn range(
+        &self,
+        statement: &PreparedStatement,
+        partition: &[u8],
+        begin: &[u8],
+        end: &[u8],
+        with_values: bool,
+    ) -> trc::Result<RowStream> {
+        let rows = self
+            .session
+            .execute_iter(statement.clone(), (partition, begin, end))
+            .await
+            .map_err(into_error)?;
+
+        Ok(if with_values {
+            rows.rows_stream::<(Vec<u8>, Vec<u8>)>()
+                .map_err(into_error)?
+                .map_err(into_error)
+                .boxed()
+        } else {
+            rows.rows_stream::<(Vec<u8>,)>()
+                .map_err(into_error)?
+                .map(|row| row.map(|(key,)| (key, vec![])).map_err(into_error))
+                .boxed()
+        })
+    }
+
+    // Returns the partitions that may hold keys within the given range. Ranges
+    // spanning multiple accounts are resolved using the accounts table instead
+    // of listing the partitions of the data table.
+    pub(crate) async fn partitions(&self, begin: &[u8], end: &[u8]) -> trc::Result<Vec<Vec<u8>>> {
+        let subspace = begin[0];
+        if !is_partitioned(subspace) {
+            return Ok((0..GLOBAL_PARTITIONS)
+                .map(|partition| vec![subspace, partition])
+                .collect());
+        }
+
+        let from = account_prefix(&begin[1..], 0);
+        let to = account_prefix(&end[1..], u8::MAX);
+        if from == to {
+            return Ok(vec![from]);
+        }
+
+        let s = self
+            .prepare(
+                "SELECT k FROM accounts WHERE p = ? AND k >= ? AND k <= ?",
+                false,
+            )
+            .await?;
+        let mut rows = self
+            .session
+            .execute_iter(s, (ACCOUNTS_PARTITION, &from, &to))
+            .await
+            .map_err(into_error)?
+            .rows_stream::<(Vec<u8>,)>()
+            .map_err(into_error)?;
+        let mut partitions = Vec::new();
+        while let Some((partition,)) = rows.try_next().await.map_err(into_error)? {
+            partitions.push(partition);
+        }
+
+        Ok(partitions)
+    }
+
+    // Adds account partitions to the accounts table. Entries are never removed,
+    // listing an account that no longer has any data is harmless.
+    pub(crate) async fn register_accounts(
+        &self,
+        partitions: impl IntoIterator<Item = &[u8]>,
+    ) -> trc::Result<()> {
+        let new_partitions = {
+            let accounts = self.accounts.lock();
+            partitions
+                .into_iter()
+                .filter(|partition| partition.len() == U32_LEN && !accounts.contains(*partition))
+                .map(|partition| partition.to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        if !new_partitions.is_empty() {
+            let s = self
+                .prepare("INSERT INTO accounts (p, k) VALUES (?, ?)", false)
+                .await?;
+            for partition in new_partitions {
+                self.session
+                    .execute_unpaged(&s, (ACCOUNTS_PARTITION, &partition))
+                    .await
+                    .map_err(into_error)?;
+                self.accounts.lock().insert(partition);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Keys starting with an account id are stored in the partition of that
+// account, which allows all changes to an account to be committed using a
+// single lightweight transaction. Keys in other subspaces are spread across
+// a fixed number of partitions to avoid hot partitions.
+#[inline(always)]
+pub(crate) fn partition(key: &[u8]) -> Vec<u8> {
+    let subspace = key[0];
+    match key.get(1..U32_LEN + 1) {
+        Some(account_id) if is_partitioned(subspace) => account_id.to_vec(),
+        _ => vec![
+            subspace,
+            (xxh3_64(&key[1..]) % GLOBAL_PARTITIONS as u64) as u8,
+        ],
+    }
+}
+
+#[inline(always)]
+pub(crate) fn is_partitioned(subspace: u8) -> bool {
+    matches!(
+        subspace,
+        SUBSPACE_PROPERTY
+            | SUBSPACE_FTS_INDEX
+            | SUBSPACE_INDEXES
+            | SUBSPACE_BITMAP_ID
+            | SUBSPACE_BITMAP_TAG
+            | SUBSPACE_BITMAP_TEXT
+            | SUBSPACE_LOGS
+            | SUBSPACE_COUNTER
+    )
+}
+
+fn account_prefix(key: &[u8], pad: u8) -> Vec<u8> {
+    let mut prefix = key.iter().take(U32_LEN).copied().collect::<Vec<_>>();
+    prefix.resize(U32_LEN, pad);
+    prefix
+}
+
+// Returns whether a conditional statement or batch was applied
+pub(crate) fn is_applied(result: QueryResult) -> trc::Result<bool> {
+    result
+        .into_rows_result()
+        .map_err(into_error)?
+        .maybe_first_row::<Row>()
+        .map_err(into_error)
+        .map(|row| {
+            row.is_some_and(|row| {
+                matches!(row.columns.first(), Some(Some(CqlValue::Boolean(true))))
+            })
+        })
+}
+
+#[inline(always)]
+fn into_error(err: impl Display) -> trc::Error {
+    trc::StoreEvent::CassandraError.reason(err)
+}
@@ -0,0 +1,164 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use futures::TryStreamExt;
+use roaring::RoaringBitmap;
+
+use crate::{
+    BitmapKey, Deserialize, IterateParams, Key, U32_LEN, ValueKey, WITH_SUBSPACE,
+    backend::deserialize_i64_le,
+    write::{BitmapClass, ValueClass, key::DeserializeBigEndian},
+};
+
+use super::{CassandraStore, RowStream, into_error, is_partitioned, partition};
+
+impl CassandraStore {
+    pub(crate) async fn get_value<U>(&self, key: impl Key) -> trc::Result<Option<U>>
+    where
+        U: Deserialize + 'static,
+    {
+        self.get_raw(key.serialize(WITH_SUBSPACE))
+            .await?
+            .map(U::deserialize_owned)
+            .transpose()
+    }
+
+    pub(crate) async fn get_bitmap(
+        &self,
+        mut key: BitmapKey<BitmapClass>,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        let begin = key.serialize(WITH_SUBSPACE);
+        key.document_id = u32::MAX;
+        let key_len = begin.len();
+        let end = key.serialize(WITH_SUBSPACE);
+        let s = self
+            .prepare(
+                "SELECT k FROM data WHERE p = ? AND k >= ? AND k <= ?",
+                false,
+            )
+            .await?;
+
+        let mut bm = RoaringBitmap::new();
+        let mut rows = self
+            .range(&s, &partition(&begin), &begin, &end, false)
+            .await?;
+
+        while let Some((key, _)) = rows.try_next().await? {
+            if key.len() == key_len {
+                bm.insert(key.deserialize_be_u32(key.len() - U32_LEN)?);
+            }
+        }
+        Ok(if !bm.is_empty() { Some(bm) } else { None })
+    }
+
+    pub(crate) async fn iterate<T: Key>(
+        &self,
+        params: IterateParams<T>,
+        mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+    ) -> trc::Result<()> {
+        let begin = params.begin.serialize(WITH_SUBSPACE);
+        let end = params.end.serialize(WITH_SUBSPACE);
+        let keys = if params.values { "k, v" } else { "k" };
+        let order = if params.ascending { "ASC" } else { "DESC" };
+        let limit = if params.first { " LIMIT 1" } else { "" };
+
+        let s = self
+            .prepare(
+                format!(
+                    "SELECT {keys} FROM data WHERE p = ? AND k >= ? AND k <= ? ORDER BY k {order}{limit}"
+                ),
+                false,
+            )
+            .await?;
+        let mut partitions = self.partitions(&begin, &end).await?;
+
+        if is_partitioned(begin[0]) {
+            // Account partitions are ordered by account id
+            if !params.ascending {
+                partitions.reverse();
+            }
+
+            for partition in partitions {
+                let mut rows = self
+                    .range(&s, &partition, &begin, &end, params.values)
+                    .await?;
+                while let Some((key, value)) = rows.try_next().await? {
+                    if !cb(&key[1..], &value)? || params.first {
+                        return Ok(());
+                    }
+                }
+            }
+        } else {
+            // Keys are spread across partitions, merge them in order
+            let mut streams: Vec<((Vec<u8>, Vec<u8>), RowStream)> =
+                Vec::with_capacity(partitions.len());
+            for partition in partitions {
+                let mut rows = self
+                    .range(&s, &partition, &begin, &end, params.values)
+                    .await?;
+                if let Some(row) = rows.try_next().await? {
+                    streams.push((row, rows));
+                }
+            }
+
+            loop {
+                let next = streams
+                    .iter()
+                    .enumerate()
+                    .map(|(pos, ((key, _), _))| (pos, key));
+                let next = if params.ascending {
+                    next.min_by(|a, b| a.1.cmp(b.1))
+                } else {
+                    next.max_by(|a, b| a.1.cmp(b.1))
+                };
+                let Some((pos, _)) = next else {
+                    break;
+                };
+
+                let ((key, value), rows) = &mut streams[pos];
+                if !cb(&key[1..], value)? || params.first {
+                    return Ok(());
+                }
+                match rows.try_next().await? {
+                    Some(row) => streams[pos].0 = row,
+                    None => {
+                        streams.swap_remove(pos);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_counter(
+        &self,
+        key: impl Into<ValueKey<ValueClass>> + Sync + Send,
+    ) -> trc::Result<i64> {
+        let key = key.into().serialize(WITH_SUBSPACE);
+        if let Some(bytes) = self.get_raw(key.clone()).await? {
+            deserialize_i64_le(&key, &bytes)
+        } else {
+            Ok(0)
+        }
+    }
+
+    async fn get_raw(&self, key: Vec<u8>) -> trc::Result<Option<Vec<u8>>> {
+        let s = self
+            .prepare("SELECT v FROM data WHERE p = ? AND k = ?", false)
+            .await?;
+
+        self.session
+            .execute_unpaged(&s, (partition(&key), &key))
+            .await
+            .map_err(into_error)?
+            .into_rows_result()
+            .map_err(into_error)?
+            .maybe_first_row::<(Vec<u8>,)>()
+            .map_err(into_error)
+            .map(|row| row.map(|(value,)| value))
+    }
+}
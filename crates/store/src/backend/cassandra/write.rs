@@ -0,0 +1,477 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use ahash::{AHashMap, AHashSet};
+use futures::TryStreamExt;
+use rand::Rng;
+use scylla::statement::batch::{Batch as CqlBatch, BatchType};
+
+use crate::{
+    IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_IN_MEMORY_COUNTER, SUBSPACE_QUOTA, U64_LEN,
+    WITH_SUBSPACE,
+    backend::deserialize_i64_le,
+    write::{
+        AssertValue, AssignedIds, Batch, BitmapClass, MAX_COMMIT_ATTEMPTS, MAX_COMMIT_TIME,
+        Operation, ValueClass, ValueOp,
+    },
+};
+
+use super::{CassandraStore, into_error, is_applied, partition};
+
+// Keys are deleted in chunks when clearing a range
+const MAX_DELETE_KEYS: usize = 256;
+
+enum Action {
+    Set(Vec<u8>),
+    Clear,
+    Add { by: i64, counter_id: Option<usize> },
+    Assert(AssertValue),
+    Reserve,
+}
+
+#[derive(Default)]
+struct PartitionWrite {
+    actions: Vec<(Vec<u8>, Action)>,
+    current: AHashMap<Vec<u8>, Option<Vec<u8>>>,
+    has_assertions: bool,
+}
+
+#[derive(Default)]
+struct Committed {
+    partitions: AHashSet<Vec<u8>>,
+    change_ids: AHashMap<u32, u64>,
+    counter_ids: Vec<(usize, i64)>,
+}
+
+impl CassandraStore {
+    // Cassandra has no multi-partition transactions, so the changes to each
+    // partition are committed using a single lightweight transaction batch.
+    // Every statement in the batch is conditional on the value read before
+    // the batch was built, which makes the batch fail if any of the keys
+    // were modified concurrently, in which case the write is retried.
+    //
+    // All the keys of an account are stored in the same partition, so most
+    // writes are atomic and isolated. Batches that span multiple partitions
+    // are committed one partition at a time, starting with the partition
+    // holding assertions. Assertions spanning multiple partitions cannot be
+    // honoured and are rejected.
+    pub(crate) async fn write(&self, mut batch: Batch<'_>) -> trc::Result<AssignedIds> {
+        let start = Instant::now();
+        let mut retry_count = 0;
+        let mut committed = Committed::default();
+
+        loop {
+            if self.try_write(&mut batch, &mut committed).await? {
+                let mut result = AssignedIds::default();
+                for account_id in batch.changes.keys() {
+                    if let Some(change_id) = committed.change_ids.get(account_id) {
+                        result.push_change_id(*account_id, *change_id);
+                    }
+                }
+                committed.counter_ids.sort_unstable_by_key(|(id, _)| *id);
+                for (_, value) in committed.counter_ids {
+                    result.push_counter_id(value);
+                }
+
+                return Ok(result);
+            } else if retry_count >= MAX_COMMIT_ATTEMPTS || start.elapsed() >= MAX_COMMIT_TIME {
+                return Err(trc::StoreEvent::AssertValueFailed
+                    .into_err()
+                    .caused_by(trc::location!()));
+            }
+
+            let backoff = rand::rng().random_range(50..=300);
+            tokio::time::sleep(Duration::from_millis(backoff)).await;
+            retry_count += 1;
+        }
+    }
+
+    async fn try_write(
+        &self,
+        batch: &mut Batch<'_>,
+        committed: &mut Committed,
+    ) -> trc::Result<bool> {
+        let mut account_id = u32::MAX;
+        let mut collection = u8::MAX;
+        let mut document_id = u32::MAX;
+        let mut change_id = 0u64;
+        let mut change_ids = AHashMap::new();
+        let mut writes: BTreeMap<Vec<u8>, PartitionWrite> = BTreeMap::new();
+        let mut counter_id = 0;
+
+        // Obtain the next change id of each account
+        for &account_id in batch.changes.keys() {
+            let change_id = if let Some(change_id) = committed.change_ids.get(&account_id) {
+                *change_id
+            } else {
+                let key = ValueClass::ChangeId.serialize(account_id, 0, 0, WITH_SUBSPACE);
+                let partition = partition(&key);
+                let current = self
+                    .read_serial(&partition, std::slice::from_ref(&key))
+                    .await?
+                    .remove(&key);
+                let change_id = current
+                    .as_deref()
+                    .map(|bytes| deserialize_i64_le(&key, bytes))
+                    .transpose()?
+                    .unwrap_or_default() as u64
+                    + 1;
+
+                let write = writes.entry(partition).or_default();
+                write.current.insert(key.clone(), current);
+                write
+                    .actions
+                    .push((key, Action::Set((change_id as i64).to_le_bytes().to_vec())));
+                change_id
+            };
+            change_ids.insert(account_id, change_id);
+        }
+
+        for op in batch.ops.iter_mut() {
+            let (key, action) = match op {
+                Operation::AccountId {
+                    account_id: account_id_,
+                } => {
+                    account_id = *account_id_;
+                    if let Some(change_id_) = change_ids.get(&account_id) {
+                        change_id = *change_id_;
+                    }
+                    continue;
+                }
+                Operation::Collection {
+                    collection: collection_,
+                } => {
+                    collection = *collection_;
+                    continue;
+                }
+                Operation::DocumentId {
+                    document_id: document_id_,
+                } => {
+                    document_id = *document_id_;
+                    continue;
+                }
+                Operation::Value { class, op } => {
+                    let key = class.serialize(account_id, collection, document_id, WITH_SUBSPACE);
+
+                    match op {
+                        ValueOp::Set {
+                            value,
+                            version_offset,
+                        } => {
+                            if let Some(offset) = version_offset {
+                                value[*offset..*offset + U64_LEN]
+                                    .copy_from_slice(&change_id.to_be_bytes());
+                            }
+
+                            (key, Action::Set(value.to_vec()))
+                        }
+                        ValueOp::AtomicAdd(by) => (
+                            key,
+                            Action::Add {
+                                by: *by,
+                                counter_id: None,
+                            },
+                        ),
+                        ValueOp::AddAndGet(by) => {
+                            counter_id += 1;
+                            (
+                                key,
+                                Action::Add {
+                                    by: *by,
+                                    counter_id: Some(counter_id),
+                                },
+                            )
+                        }
+                        ValueOp::Clear => (key, Action::Clear),
+                    }
+                }
+                Operation::Index { field, key, set } => {
+                    let key = IndexKey {
+                        account_id,
+                        collection,
+                        document_id,
+                        field: *field,
+                        key: &*key,
+                    }
+                    .serialize(WITH_SUBSPACE);
+
+                    (
+                        key,
+                        if *set {
+                            Action::Set(vec![])
+                        } else {
+                            Action::Clear
+                        },
+                    )
+                }
+                Operation::Bitmap { class, set } => {
+                    let key = class.serialize(account_id, collection, document_id, WITH_SUBSPACE);
+
+                    let action = if !*set {
+                        Action::Clear
+                    } else if matches!(class, BitmapClass::DocumentIds) {
+                        // Document ids are reserved only if they are not in use
+                        Action::Reserve
+                    } else {
+                        Action::Set(vec![])
+                    };
+
+                    (key, action)
+                }
+                Operation::Log { collection, set } => {
+                    let key = LogKey {
+                        account_id,
+                        collection: *collection,
+                        change_id,
+                    }
+                    .serialize(WITH_SUBSPACE);
+
+                    (key, Action::Set(set.to_vec()))
+                }
+                Operation::AssertValue {
+                    class,
+                    assert_value,
+                } => {
+                    let key = class.serialize(account_id, collection, document_id, WITH_SUBSPACE);
+
+                    (key, Action::Assert(*assert_value))
+                }
+            };
+
+            let partition = partition(&key);
+            if !committed.partitions.contains(&partition) {
+                let write = writes.entry(partition).or_default();
+                write.has_assertions |= matches!(action, Action::Assert(_) | Action::Reserve);
+                write.actions.push((key, action));
+            }
+        }
+
+        if writes
+            .values()
+            .filter(|write| write.has_assertions)
+            .nth(1)
+            .is_some()
+        {
+            return Err(trc::StoreEvent::CassandraError
+                .into_err()
+                .details("Assertions spanning multiple partitions are not supported")
+                .caused_by(trc::location!()));
+        }
+
+        // Build the batches, starting with the partition holding assertions
+        let mut batches = Vec::with_capacity(writes.len());
+        for (partition, mut write) in writes {
+            let keys = write
+                .actions
+                .iter()
+                .filter(|(key, _)| !write.current.contains_key(key))
+                .map(|(key, _)| key.clone())
+                .collect::<AHashSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let mut values = self.read_serial(&partition, &keys).await?;
+            for key in keys {
+                let value = values.remove(&key);
+                write.current.insert(key, value);
+            }
+
+            let mut counter_ids = Vec::new();
+            let mut state = BTreeMap::new();
+            for (key, action) in write.actions {
+                let value = state
+                    .get(&key)
+                    .or_else(|| write.current.get(&key))
+                    .cloned()
+                    .flatten();
+
+                match action {
+                    Action::Set(value) => {
+                        state.insert(key, Some(value));
+                    }
+                    Action::Clear => {
+                        state.insert(key, None);
+                    }
+                    Action::Add { by, counter_id } => {
+                        let value = value
+                            .as_deref()
+                            .map(|bytes| deserialize_i64_le(&key, bytes))
+                            .transpose()?
+                            .unwrap_or_default()
+                            + by;
+                        if let Some(counter_id) = counter_id {
+                            counter_ids.push((counter_id, value));
+                        }
+                        state.insert(key, Some(value.to_le_bytes().to_vec()));
+                    }
+                    Action::Assert(assert_value) => {
+                        let matches = match &value {
+                            Some(value) => assert_value.matches(value),
+                            None => assert_value.is_none(),
+                        };
+                        if !matches {
+                            return Err(trc::StoreEvent::AssertValueFailed
+                                .into_err()
+                                .caused_by(trc::location!()));
+                        }
+                        state.entry(key).or_insert(value);
+                    }
+                    Action::Reserve => {
+                        if value.is_some() {
+                            return Err(trc::StoreEvent::AssertValueFailed
+                                .into_err()
+                                .caused_by(trc::location!()));
+                        }
+                        state.insert(key, Some(vec![]));
+                    }
+                }
+            }
+
+            let mut cql_batch = CqlBatch::new(BatchType::Unlogged);
+            let mut rows: Vec<Vec<Option<Vec<u8>>>> = Vec::with_capacity(state.len());
+            for (key, value) in state {
+                let expected = write.current.remove(&key).flatten();
+                let (query, row) = match (value, expected) {
+                    (Some(value), None) => (
+                        "INSERT INTO data (p, k, v) VALUES (?, ?, ?) IF NOT EXISTS",
+                        vec![Some(partition.clone()), Some(key), Some(value)],
+                    ),
+                    (Some(value), expected) => (
+                        "UPDATE data SET v = ? WHERE p = ? AND k = ? IF v = ?",
+                        vec![Some(value), Some(partition.clone()), Some(key), expected],
+                    ),
+                    (None, expected) => (
+                        "DELETE FROM data WHERE p = ? AND k = ? IF v = ?",
+                        vec![Some(partition.clone()), Some(key), expected],
+                    ),
+                };
+                cql_batch.append_statement(self.prepare(query, false).await?);
+                rows.push(row);
+            }
+
+            let batch = (partition, cql_batch, rows, counter_ids);
+            if write.has_assertions {
+                batches.insert(0, batch);
+            } else {
+                batches.push(batch);
+            }
+        }
+
+        self.register_accounts(batches.iter().map(|(partition, ..)| partition.as_slice()))
+            .await?;
+
+        for (partition, cql_batch, rows, counter_ids) in batches {
+            if !rows.is_empty()
+                && !is_applied(
+                    self.session
+                        .batch(&cql_batch, rows)
+                        .await
+                        .map_err(into_error)?,
+                )?
+            {
+                return Ok(false);
+            }
+
+            // Keep the ids assigned by committed partitions in case
+            // the remaining partitions have to be retried
+            for (account_id, change_id) in &change_ids {
+                if partition == account_id.to_be_bytes() {
+                    committed.change_ids.insert(*account_id, *change_id);
+                }
+            }
+            committed.counter_ids.extend(counter_ids);
+            committed.partitions.insert(partition);
+        }
+
+        Ok(true)
+    }
+
+    pub(crate) async fn purge_store(&self) -> trc::Result<()> {
+        let s = self
+            .prepare(
+                "SELECT k, v FROM data WHERE p = ? AND k >= ? AND k <= ?",
+                false,
+            )
+            .await?;
+        let d = self
+            .prepare("DELETE FROM data WHERE p = ? AND k = ? IF v = ?", false)
+            .await?;
+        let zero = 0i64.to_le_bytes().to_vec();
+
+        for subspace in [SUBSPACE_QUOTA, SUBSPACE_COUNTER, SUBSPACE_IN_MEMORY_COUNTER] {
+            let from_key = vec![subspace, 0u8];
+            let to_key = vec![subspace, u8::MAX, u8::MAX, u8::MAX, u8::MAX, u8::MAX];
+
+            for partition in self.partitions(&from_key, &to_key).await? {
+                let mut rows = self.range(&s, &partition, &from_key, &to_key, true).await?;
+                let mut delete_keys = Vec::new();
+                while let Some((key, value)) = rows.try_next().await? {
+                    if value == zero {
+                        delete_keys.push(key);
+                    }
+                }
+
+                for key in delete_keys {
+                    self.session
+                        .execute_unpaged(&d, (&partition, key, &zero))
+                        .await
+                        .map_err(into_error)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
+        let from = from.serialize(WITH_SUBSPACE);
+        let to = to.serialize(WITH_SUBSPACE);
+        let s = self
+            .prepare("SELECT k FROM data WHERE p = ? AND k >= ? AND k < ?", false)
+            .await?;
+        let d = self
+            .prepare("DELETE FROM data WHERE p = ? AND k = ? IF EXISTS", false)
+            .await?;
+
+        // Keys are deleted using lightweight transactions rather than range
+        // deletions, as mixing both on the same keys can reorder writes
+        for partition in self.partitions(&from, &to).await? {
+            let mut rows = self.range(&s, &partition, &from, &to, false).await?;
+            let mut keys = Vec::new();
+            while let Some((key, _)) = rows.try_next().await? {
+                keys.push(key);
+            }
+
+            for keys in keys.chunks(MAX_DELETE_KEYS) {
+                let mut cql_batch = CqlBatch::new(BatchType::Unlogged);
+                for _ in keys {
+                    cql_batch.append_statement(d.clone());
+                }
+                let rows = keys.iter().map(|key| (&partition, key)).collect::<Vec<_>>();
+                if !is_applied(
+                    self.session
+                        .batch(&cql_batch, rows)
+                        .await
+                        .map_err(into_error)?,
+                )? {
+                    // Some keys were deleted concurrently
+                    for key in keys {
+                        self.session
+                            .execute_unpaged(&d, (&partition, key))
+                            .await
+                            .map_err(into_error)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
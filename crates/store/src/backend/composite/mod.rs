@@ -8,7 +8,8 @@
  *
  */
 
-#[cfg(any(feature = "postgres", feature = "mysql"))]
+#[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
 pub mod read_replica;
 pub mod sharded_blob;
 pub mod sharded_lookup;
+pub mod tiered_blob;
@@ -11,24 +11,38 @@
 use std::{
     future::Future,
     ops::Range,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
 use roaring::RoaringBitmap;
 use utils::config::{Config, utils::AsKey};
 
 use crate::{
-    BitmapKey, Deserialize, IterateParams, Key, Store, Stores, ValueKey,
-    write::{AssignedIds, Batch, BitmapClass, ValueClass},
+    BitmapKey, Deserialize, IterateParams, Key, SUBSPACE_INDEXES, SUBSPACE_LOGS, Store, Stores,
+    ValueKey,
+    write::{AssignedIds, Batch, BitmapClass, Operation, ValueClass},
 };
 
-pub struct SQLReadReplica {
+const WRITE_SLOTS: usize = 4096;
+
+/// Routes reads to replica stores and writes to the primary store.
+///
+/// Data written through this node is read from the primary until the configured
+/// maximum replication lag has elapsed, so read-your-writes is only guaranteed for
+/// writes made by the same node. Data written by other nodes may be read from a
+/// replica that has not caught up yet, in which case missing values are retried on
+/// the primary but outdated values are returned as-is.
+pub struct ReadReplica {
     primary: Store,
     replicas: Vec<Store>,
     last_used_replica: AtomicUsize,
+    max_lag: u64,
+    epoch: Instant,
+    recent_writes: Box<[AtomicU64]>,
 }
 
-impl SQLReadReplica {
+impl ReadReplica {
     pub async fn open(
         config: &mut Config,
         prefix: impl AsKey,
@@ -43,12 +57,12 @@ impl SQLReadReplica {
             .collect::<Vec<_>>();
 
         let primary = if let Some(store) = stores.stores.get(&primary_id) {
-            if store.is_pg_or_mysql() {
+            if store.is_replicable() {
                 store.clone()
             } else {
                 config.new_build_error(
                     (&prefix, "primary"),
-                    "Primary store must be a PostgreSQL or MySQL store",
+                    "Primary store must be a PostgreSQL, MySQL or FoundationDB store",
                 );
                 return None;
             }
@@ -62,12 +76,12 @@ impl SQLReadReplica {
         let mut replicas = Vec::with_capacity(replica_ids.len());
         for replica_id in replica_ids {
             if let Some(store) = stores.stores.get(&replica_id) {
-                if store.is_pg_or_mysql() {
+                if std::mem::discriminant(store) == std::mem::discriminant(&primary) {
                     replicas.push(store.clone());
                 } else {
                     config.new_build_error(
                         (&prefix, "replicas"),
-                        "Replica store must be a PostgreSQL or MySQL store",
+                        "Replica stores must be of the same type as the primary store",
                     );
                     return None;
                 }
@@ -86,7 +100,7 @@ impl SQLReadReplica {
                     Store::PostgreSQL(store) => store.create_tables().await,
                     #[cfg(feature = "mysql")]
                     Store::MySQL(store) => store.create_tables().await,
-                    _ => panic!("Invalid store type"),
+                    _ => Ok(()),
                 };
 
                 if let Err(err) = result {
@@ -97,10 +111,18 @@ impl SQLReadReplica {
                 }
             }
 
+            // Data written within the maximum replication lag is read from the primary
+            let max_lag = config
+                .property_or_default::<Duration>((&prefix, "max-replication-lag"), "2s")
+                .unwrap_or(Duration::from_secs(2));
+
             Some(Self {
                 primary,
                 replicas,
                 last_used_replica: AtomicUsize::new(0),
+                max_lag: max_lag.as_millis() as u64,
+                epoch: Instant::now(),
+                recent_writes: (0..WRITE_SLOTS).map(|_| AtomicU64::new(0)).collect(),
             })
         } else {
             config.new_build_error((&prefix, "replicas"), "No replica stores specified");
@@ -108,35 +130,62 @@ impl SQLReadReplica {
         }
     }
 
-    async fn run_op<'x, F, T, R>(&'x self, f: F) -> trc::Result<T>
+    pub fn is_sql(&self) -> bool {
+        self.primary.is_sql()
+    }
+
+    async fn run_op<'x, F, R, T>(
+        &'x self,
+        key: Option<(u8, Option<u32>)>,
+        is_missing: impl Fn(&T) -> bool,
+        f: F,
+    ) -> trc::Result<T>
     where
         F: Fn(&'x Store) -> R,
         R: Future<Output = trc::Result<T>>,
         T: 'static,
     {
-        let mut last_error = None;
-        for store in [
+        // Stale reads are retried on the primary when the replica has not
+        // received the data yet.
+        if let Some(replica) = self.replica(key) {
+            match f(replica).await {
+                Ok(result) if !is_missing(&result) => return Ok(result),
+                Err(err) if err.is_assertion_failure() => return Err(err),
+                _ => {}
+            }
+        }
+
+        f(&self.primary).await
+    }
+
+    fn replica(&self, key: Option<(u8, Option<u32>)>) -> Option<&Store> {
+        if let Some((subspace, account_id)) = key {
+            let written_at =
+                self.recent_writes[write_slot(subspace, account_id)].load(Ordering::Relaxed);
+            if written_at != 0 && self.elapsed() < written_at + self.max_lag {
+                return None;
+            }
+        }
+
+        Some(
             &self.replicas
                 [self.last_used_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len()],
-            &self.primary,
-        ] {
-            match f(store).await {
-                Ok(result) => return Ok(result),
-                Err(err) => {
-                    if err.is_assertion_failure() {
-                        return Err(err);
-                    } else {
-                        last_error = Some(err);
-                    }
-                }
-            }
+        )
+    }
+
+    fn mark_written(&self, slots: Vec<usize>) {
+        let written_at = self.elapsed();
+        for slot in slots {
+            self.recent_writes[slot].store(written_at, Ordering::Relaxed);
         }
+    }
 
-        Err(last_error.unwrap())
+    fn elapsed(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64 + 1
     }
 
     pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
-        self.run_op(move |store| {
+        self.run_op(None, Option::is_none, move |store| {
             let range = range.clone();
 
             async move {
@@ -145,7 +194,9 @@ impl SQLReadReplica {
                     Store::PostgreSQL(store) => store.get_blob(key, range).await,
                     #[cfg(feature = "mysql")]
                     Store::MySQL(store) => store.get_blob(key, range).await,
-                    _ => panic!("Invalid store type"),
+                    #[cfg(feature = "foundation")]
+                    Store::FoundationDb(store) => store.get_blob(key, range).await,
+                    _ => Err(trc::StoreEvent::NotSupported.into()),
                 }
             }
         })
@@ -158,7 +209,9 @@ impl SQLReadReplica {
             Store::PostgreSQL(store) => store.put_blob(key, data).await,
             #[cfg(feature = "mysql")]
             Store::MySQL(store) => store.put_blob(key, data).await,
-            _ => panic!("Invalid store type"),
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.put_blob(key, data).await,
+            _ => Err(trc::StoreEvent::NotSupported.into()),
         }
     }
 
@@ -168,7 +221,9 @@ impl SQLReadReplica {
             Store::PostgreSQL(store) => store.delete_blob(key).await,
             #[cfg(feature = "mysql")]
             Store::MySQL(store) => store.delete_blob(key).await,
-            _ => panic!("Invalid store type"),
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.delete_blob(key).await,
+            _ => Err(trc::StoreEvent::NotSupported.into()),
         }
     }
 
@@ -176,19 +231,25 @@ impl SQLReadReplica {
     where
         U: Deserialize + 'static,
     {
-        self.run_op(move |store| {
-            let key = key.clone();
+        self.run_op(
+            Some((key.subspace(), key.account_id())),
+            Option::is_none,
+            move |store| {
+                let key = key.clone();
 
-            async move {
-                match store {
-                    #[cfg(feature = "postgres")]
-                    Store::PostgreSQL(store) => store.get_value(key).await,
-                    #[cfg(feature = "mysql")]
-                    Store::MySQL(store) => store.get_value(key).await,
-                    _ => panic!("Invalid store type"),
+                async move {
+                    match store {
+                        #[cfg(feature = "postgres")]
+                        Store::PostgreSQL(store) => store.get_value(key).await,
+                        #[cfg(feature = "mysql")]
+                        Store::MySQL(store) => store.get_value(key).await,
+                        #[cfg(feature = "foundation")]
+                        Store::FoundationDb(store) => store.get_value(key).await,
+                        _ => Err(trc::StoreEvent::NotSupported.into()),
+                    }
                 }
-            }
-        })
+            },
+        )
         .await
     }
 
@@ -196,19 +257,25 @@ impl SQLReadReplica {
         &self,
         key: BitmapKey<BitmapClass>,
     ) -> trc::Result<Option<RoaringBitmap>> {
-        self.run_op(move |store| {
-            let key = key.clone();
+        self.run_op(
+            Some((key.subspace(), key.account_id())),
+            Option::is_none,
+            move |store| {
+                let key = key.clone();
 
-            async move {
-                match store {
-                    #[cfg(feature = "postgres")]
-                    Store::PostgreSQL(store) => store.get_bitmap(key).await,
-                    #[cfg(feature = "mysql")]
-                    Store::MySQL(store) => store.get_bitmap(key).await,
-                    _ => panic!("Invalid store type"),
+                async move {
+                    match store {
+                        #[cfg(feature = "postgres")]
+                        Store::PostgreSQL(store) => store.get_bitmap(key).await,
+                        #[cfg(feature = "mysql")]
+                        Store::MySQL(store) => store.get_bitmap(key).await,
+                        #[cfg(feature = "foundation")]
+                        Store::FoundationDb(store) => store.get_bitmap(key).await,
+                        _ => Err(trc::StoreEvent::NotSupported.into()),
+                    }
                 }
-            }
-        })
+            },
+        )
         .await
     }
 
@@ -217,18 +284,21 @@ impl SQLReadReplica {
         params: IterateParams<T>,
         mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
     ) -> trc::Result<()> {
+        let key = &params.begin;
         let mut last_error = None;
-        for store in [
-            &self.replicas
-                [self.last_used_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len()],
-            &self.primary,
-        ] {
+        for store in self
+            .replica(Some((key.subspace(), key.account_id())))
+            .into_iter()
+            .chain([&self.primary])
+        {
             match match store {
                 #[cfg(feature = "postgres")]
                 Store::PostgreSQL(store) => store.iterate(params.clone(), &mut cb).await,
                 #[cfg(feature = "mysql")]
                 Store::MySQL(store) => store.iterate(params.clone(), &mut cb).await,
-                _ => panic!("Invalid store type"),
+                #[cfg(feature = "foundation")]
+                Store::FoundationDb(store) => store.iterate(params.clone(), &mut cb).await,
+                _ => Err(trc::StoreEvent::NotSupported.into()),
             } {
                 Ok(result) => return Ok(result),
                 Err(err) => {
@@ -245,30 +315,43 @@ impl SQLReadReplica {
         key: impl Into<ValueKey<ValueClass>> + Sync + Send,
     ) -> trc::Result<i64> {
         let key = key.into();
-        self.run_op(move |store| {
-            let key = key.clone();
+        self.run_op(
+            Some((key.subspace(), key.account_id())),
+            |_| false,
+            move |store| {
+                let key = key.clone();
 
-            async move {
-                match store {
-                    #[cfg(feature = "postgres")]
-                    Store::PostgreSQL(store) => store.get_counter(key).await,
-                    #[cfg(feature = "mysql")]
-                    Store::MySQL(store) => store.get_counter(key).await,
-                    _ => panic!("Invalid store type"),
+                async move {
+                    match store {
+                        #[cfg(feature = "postgres")]
+                        Store::PostgreSQL(store) => store.get_counter(key).await,
+                        #[cfg(feature = "mysql")]
+                        Store::MySQL(store) => store.get_counter(key).await,
+                        #[cfg(feature = "foundation")]
+                        Store::FoundationDb(store) => store.get_counter(key).await,
+                        _ => Err(trc::StoreEvent::NotSupported.into()),
+                    }
                 }
-            }
-        })
+            },
+        )
         .await
     }
 
     pub async fn write(&self, batch: Batch<'_>) -> trc::Result<AssignedIds> {
-        match &self.primary {
+        let slots = write_slots(&batch);
+        let result = match &self.primary {
             #[cfg(feature = "postgres")]
             Store::PostgreSQL(store) => store.write(batch).await,
             #[cfg(feature = "mysql")]
             Store::MySQL(store) => store.write(batch).await,
-            _ => panic!("Invalid store type"),
-        }
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.write(batch).await,
+            _ => Err(trc::StoreEvent::NotSupported.into()),
+        }?;
+
+        self.mark_written(slots);
+
+        Ok(result)
     }
 
     pub async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
@@ -277,7 +360,9 @@ impl SQLReadReplica {
             Store::PostgreSQL(store) => store.delete_range(from, to).await,
             #[cfg(feature = "mysql")]
             Store::MySQL(store) => store.delete_range(from, to).await,
-            _ => panic!("Invalid store type"),
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.delete_range(from, to).await,
+            _ => Err(trc::StoreEvent::NotSupported.into()),
         }
     }
 
@@ -287,7 +372,135 @@ impl SQLReadReplica {
             Store::PostgreSQL(store) => store.purge_store().await,
             #[cfg(feature = "mysql")]
             Store::MySQL(store) => store.purge_store().await,
-            _ => panic!("Invalid store type"),
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(store) => store.purge_store().await,
+            _ => Err(trc::StoreEvent::NotSupported.into()),
+        }
+    }
+}
+
+// Obtains the slots of the data modified by a batch
+fn write_slots(batch: &Batch<'_>) -> Vec<usize> {
+    let mut account_id = None;
+    let mut collection = 0;
+    let mut slots = Vec::new();
+    for op in batch.ops.iter() {
+        let (subspace, account_id) = match op {
+            Operation::AccountId {
+                account_id: new_account_id,
+            } => {
+                account_id = Some(*new_account_id);
+                continue;
+            }
+            Operation::Collection {
+                collection: new_collection,
+            } => {
+                collection = *new_collection;
+                continue;
+            }
+            Operation::Value { class, .. } => (
+                class.subspace(collection),
+                account_id.filter(|_| class.is_account_scoped()),
+            ),
+            Operation::Index { .. } => (SUBSPACE_INDEXES, account_id),
+            Operation::Bitmap { class, .. } => (class.subspace(), account_id),
+            Operation::Log { .. } => (SUBSPACE_LOGS, account_id),
+            Operation::DocumentId { .. } | Operation::AssertValue { .. } => continue,
+        };
+        let slot = write_slot(subspace, account_id);
+        if !slots.contains(&slot) {
+            slots.push(slot);
+        }
+    }
+    slots
+}
+
+// Writes are tracked per subspace and account, collisions only cause
+// additional reads from the primary.
+fn write_slot(subspace: u8, account_id: Option<u32>) -> usize {
+    let key = ((account_id.unwrap_or(u32::MAX) as u64) << 8) | subspace as u64;
+    (key.wrapping_mul(0x9e3779b97f4a7c15) >> 52) as usize % WRITE_SLOTS
+}
+
+impl Store {
+    fn is_replicable(&self) -> bool {
+        match self {
+            #[cfg(feature = "postgres")]
+            Store::PostgreSQL(_) => true,
+            #[cfg(feature = "mysql")]
+            Store::MySQL(_) => true,
+            #[cfg(feature = "foundation")]
+            Store::FoundationDb(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU64, AtomicUsize},
+        time::Instant,
+    };
+
+    use crate::{
+        SUBSPACE_PROPERTY, SUBSPACE_QUEUE_MESSAGE, Store,
+        write::{BatchBuilder, QueueClass, ValueClass},
+    };
+
+    use super::{ReadReplica, WRITE_SLOTS, write_slot, write_slots};
+
+    #[test]
+    fn read_replica_stale_reads() {
+        let replica = ReadReplica {
+            primary: Store::None,
+            replicas: vec![Store::None],
+            last_used_replica: AtomicUsize::new(0),
+            max_lag: 100,
+            epoch: Instant::now(),
+            recent_writes: (0..WRITE_SLOTS).map(|_| AtomicU64::new(0)).collect(),
+        };
+        let account_1 = Some((SUBSPACE_PROPERTY, Some(1)));
+        let account_2 = Some((SUBSPACE_PROPERTY, Some(2)));
+        let queue = Some((SUBSPACE_QUEUE_MESSAGE, None));
+        assert_ne!(
+            write_slot(SUBSPACE_PROPERTY, Some(1)),
+            write_slot(SUBSPACE_PROPERTY, Some(2))
+        );
+
+        // Nothing was written yet, all reads go to the replica
+        for key in [account_1, account_2, queue, None] {
+            assert!(replica.replica(key).is_some());
+        }
+
+        // Account data written by this node is read from the primary
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(1)
+            .with_collection(2u8)
+            .update_document(3)
+            .set(ValueClass::Property(4), vec![1]);
+        let slots = write_slots(&batch.build_all());
+        assert_eq!(slots, vec![write_slot(SUBSPACE_PROPERTY, Some(1))]);
+        replica.mark_written(slots);
+        assert!(replica.replica(account_1).is_none());
+        assert!(replica.replica(account_2).is_some());
+        assert!(replica.replica(queue).is_some());
+
+        // Values outside of an account are tracked globally
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(5)
+            .set(ValueClass::Queue(QueueClass::Message(6)), vec![1]);
+        let slots = write_slots(&batch.build_all());
+        assert_eq!(slots, vec![write_slot(SUBSPACE_QUEUE_MESSAGE, None)]);
+        replica.mark_written(slots);
+        assert!(replica.replica(queue).is_none());
+
+        // Replicas are used again once the maximum lag has elapsed
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        for key in [account_1, account_2, queue] {
+            assert!(replica.replica(key).is_some());
         }
     }
 }
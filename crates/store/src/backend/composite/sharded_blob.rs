@@ -29,6 +29,16 @@ impl ShardedBlob {
         let mut blob_stores = Vec::with_capacity(store_ids.len());
         for store_id in store_ids {
             if let Some(store) = stores.blob_stores.get(&store_id) {
+                if matches!(
+                    store.backend,
+                    BlobBackend::Sharded(_) | BlobBackend::Tiered(_)
+                ) {
+                    config.new_build_error(
+                        (&prefix, "stores"),
+                        format!("Blob store {store_id} cannot be used as a shard"),
+                    );
+                    return None;
+                }
                 blob_stores.push(store.backend.clone());
             } else {
                 config.new_build_error(
@@ -71,11 +81,14 @@ impl ShardedBlob {
                     Store::MySQL(store) => store.get_blob(key, read_range).await,
                     #[cfg(feature = "rocks")]
                     Store::RocksDb(store) => store.get_blob(key, read_range).await,
+                    #[cfg(feature = "cassandra")]
+                    Store::Cassandra(store) => store.get_blob(key, read_range).await,
+                    Store::Volatile(store) => store.get_blob(key, read_range).await,
                     #[cfg(all(
                         feature = "enterprise",
-                        any(feature = "postgres", feature = "mysql")
+                        any(feature = "postgres", feature = "mysql", feature = "foundation")
                     ))]
-                    Store::SQLReadReplica(store) => store.get_blob(key, read_range).await,
+                    Store::ReadReplica(store) => store.get_blob(key, read_range).await,
                     Store::None => Err(trc::StoreEvent::NotConfigured.into()),
                 },
                 BlobBackend::Fs(store) => store.get_blob(key, read_range).await,
@@ -83,7 +96,9 @@ impl ShardedBlob {
                 BlobBackend::S3(store) => store.get_blob(key, read_range).await,
                 #[cfg(feature = "azure")]
                 BlobBackend::Azure(store) => store.get_blob(key, read_range).await,
-                BlobBackend::Sharded(_) => unimplemented!(),
+                BlobBackend::Sharded(_) | BlobBackend::Tiered(_) => {
+                    Err(trc::StoreEvent::NotSupported.into())
+                }
             }
         })
         .await
@@ -103,11 +118,14 @@ impl ShardedBlob {
                     Store::MySQL(store) => store.put_blob(key, data).await,
                     #[cfg(feature = "rocks")]
                     Store::RocksDb(store) => store.put_blob(key, data).await,
+                    #[cfg(feature = "cassandra")]
+                    Store::Cassandra(store) => store.put_blob(key, data).await,
+                    Store::Volatile(store) => store.put_blob(key, data).await,
                     #[cfg(all(
                         feature = "enterprise",
-                        any(feature = "postgres", feature = "mysql")
+                        any(feature = "postgres", feature = "mysql", feature = "foundation")
                     ))]
-                    Store::SQLReadReplica(store) => store.put_blob(key, data).await,
+                    Store::ReadReplica(store) => store.put_blob(key, data).await,
                     Store::None => Err(trc::StoreEvent::NotConfigured.into()),
                 },
                 BlobBackend::Fs(store) => store.put_blob(key, data).await,
@@ -115,7 +133,9 @@ impl ShardedBlob {
                 BlobBackend::S3(store) => store.put_blob(key, data).await,
                 #[cfg(feature = "azure")]
                 BlobBackend::Azure(store) => store.put_blob(key, data).await,
-                BlobBackend::Sharded(_) => unimplemented!(),
+                BlobBackend::Sharded(_) | BlobBackend::Tiered(_) => {
+                    Err(trc::StoreEvent::NotSupported.into())
+                }
             }
         })
         .await
@@ -135,11 +155,14 @@ impl ShardedBlob {
                     Store::MySQL(store) => store.delete_blob(key).await,
                     #[cfg(feature = "rocks")]
                     Store::RocksDb(store) => store.delete_blob(key).await,
+                    #[cfg(feature = "cassandra")]
+                    Store::Cassandra(store) => store.delete_blob(key).await,
+                    Store::Volatile(store) => store.delete_blob(key).await,
                     #[cfg(all(
                         feature = "enterprise",
-                        any(feature = "postgres", feature = "mysql")
+                        any(feature = "postgres", feature = "mysql", feature = "foundation")
                     ))]
-                    Store::SQLReadReplica(store) => store.delete_blob(key).await,
+                    Store::ReadReplica(store) => store.delete_blob(key).await,
                     Store::None => Err(trc::StoreEvent::NotConfigured.into()),
                 },
                 BlobBackend::Fs(store) => store.delete_blob(key).await,
@@ -147,7 +170,9 @@ impl ShardedBlob {
                 BlobBackend::S3(store) => store.delete_blob(key).await,
                 #[cfg(feature = "azure")]
                 BlobBackend::Azure(store) => store.delete_blob(key).await,
-                BlobBackend::Sharded(_) => unimplemented!(),
+                BlobBackend::Sharded(_) | BlobBackend::Tiered(_) => {
+                    Err(trc::StoreEvent::NotSupported.into())
+                }
             }
         })
         .await
@@ -0,0 +1,230 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: LicenseRef-SEL
+ *
+ * This file is subject to the Stalwart Enterprise License Agreement (SEL) and
+ * is NOT open source software.
+ *
+ */
+
+use std::{ops::Range, time::Duration};
+
+use trc::{AddContext, StoreEvent};
+use utils::config::{Config, utils::AsKey};
+
+use crate::{BlobBackend, Store, Stores};
+
+// Blobs are always written to the primary store and moved to the archive store
+// by the tiering job once the message they belong to is older than `archive_after`.
+pub struct TieredBlob {
+    pub primary: BlobBackend,
+    pub archive: BlobBackend,
+    pub archive_after: Duration,
+    pub interval: Duration,
+    pub restore_on_read: bool,
+}
+
+impl TieredBlob {
+    pub fn open(config: &mut Config, prefix: impl AsKey, stores: &Stores) -> Option<Self> {
+        let prefix = prefix.as_key();
+        let primary = Self::backend(config, (&prefix, "primary"), stores)?;
+        let archive = Self::backend(config, (&prefix, "archive"), stores)?;
+
+        Some(TieredBlob {
+            primary,
+            archive,
+            archive_after: config
+                .property_or_default::<Duration>((&prefix, "archive-after"), "90d")
+                .unwrap_or(Duration::from_secs(90 * 86400)),
+            interval: config
+                .property_or_default::<Duration>((&prefix, "interval"), "1d")
+                .unwrap_or(Duration::from_secs(86400)),
+            restore_on_read: config
+                .property_or_default((&prefix, "restore-on-read"), "false")
+                .unwrap_or(false),
+        })
+    }
+
+    fn backend(config: &mut Config, key: impl AsKey, stores: &Stores) -> Option<BlobBackend> {
+        let key = key.as_key();
+        let store_id = config.value_require(&key)?.to_string();
+        match stores.blob_stores.get(&store_id) {
+            Some(store) if !matches!(store.backend, BlobBackend::Tiered(_)) => {
+                Some(store.backend.clone())
+            }
+            Some(_) => {
+                config.new_build_error(&key, format!("Blob store {store_id} is already tiered"));
+                None
+            }
+            None => {
+                config.new_build_error(&key, format!("Blob store {store_id} not found"));
+                None
+            }
+        }
+    }
+
+    pub async fn get_blob(
+        &self,
+        key: &[u8],
+        read_range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        if let Some(data) = get_blob(&self.primary, key, read_range.clone()).await? {
+            return Ok(Some(data));
+        }
+
+        if self.restore_on_read && read_range.start == 0 && read_range.end == usize::MAX {
+            if let Some(data) = get_blob(&self.archive, key, read_range).await? {
+                put_blob(&self.primary, key, &data)
+                    .await
+                    .caused_by(trc::location!())?;
+                delete_blob(&self.archive, key)
+                    .await
+                    .caused_by(trc::location!())?;
+
+                trc::event!(Store(StoreEvent::BlobRestore), Key = key, Size = data.len());
+
+                Ok(Some(data))
+            } else {
+                Ok(None)
+            }
+        } else {
+            get_blob(&self.archive, key, read_range).await
+        }
+    }
+
+    pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        put_blob(&self.primary, key, data).await
+    }
+
+    pub async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        let primary = delete_blob(&self.primary, key).await?;
+        let archive = delete_blob(&self.archive, key).await?;
+        Ok(primary || archive)
+    }
+
+    // Moves a blob from the primary to the archive store, returns false if
+    // the blob is not present in the primary store.
+    pub async fn archive_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        let Some(data) = get_blob(&self.primary, key, 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+
+        put_blob(&self.archive, key, &data)
+            .await
+            .caused_by(trc::location!())?;
+        delete_blob(&self.primary, key)
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(Store(StoreEvent::BlobArchive), Key = key, Size = data.len());
+
+        Ok(true)
+    }
+}
+
+async fn get_blob(
+    backend: &BlobBackend,
+    key: &[u8],
+    read_range: Range<usize>,
+) -> trc::Result<Option<Vec<u8>>> {
+    Box::pin(async move {
+        match backend {
+            BlobBackend::Store(store) => match store {
+                #[cfg(feature = "sqlite")]
+                Store::SQLite(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "foundation")]
+                Store::FoundationDb(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "postgres")]
+                Store::PostgreSQL(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "mysql")]
+                Store::MySQL(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "rocks")]
+                Store::RocksDb(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "cassandra")]
+                Store::Cassandra(store) => store.get_blob(key, read_range).await,
+                Store::Volatile(store) => store.get_blob(key, read_range).await,
+                #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+                Store::ReadReplica(store) => store.get_blob(key, read_range).await,
+                Store::None => Err(trc::StoreEvent::NotConfigured.into()),
+            },
+            BlobBackend::Fs(store) => store.get_blob(key, read_range).await,
+            #[cfg(feature = "s3")]
+            BlobBackend::S3(store) => store.get_blob(key, read_range).await,
+            #[cfg(feature = "azure")]
+            BlobBackend::Azure(store) => store.get_blob(key, read_range).await,
+            BlobBackend::Sharded(store) => store.get_blob(key, read_range).await,
+            BlobBackend::Tiered(_) => Err(trc::StoreEvent::NotSupported.into()),
+        }
+    })
+    .await
+}
+
+async fn put_blob(backend: &BlobBackend, key: &[u8], data: &[u8]) -> trc::Result<()> {
+    Box::pin(async move {
+        match backend {
+            BlobBackend::Store(store) => match store {
+                #[cfg(feature = "sqlite")]
+                Store::SQLite(store) => store.put_blob(key, data).await,
+                #[cfg(feature = "foundation")]
+                Store::FoundationDb(store) => store.put_blob(key, data).await,
+                #[cfg(feature = "postgres")]
+                Store::PostgreSQL(store) => store.put_blob(key, data).await,
+                #[cfg(feature = "mysql")]
+                Store::MySQL(store) => store.put_blob(key, data).await,
+                #[cfg(feature = "rocks")]
+                Store::RocksDb(store) => store.put_blob(key, data).await,
+                #[cfg(feature = "cassandra")]
+                Store::Cassandra(store) => store.put_blob(key, data).await,
+                Store::Volatile(store) => store.put_blob(key, data).await,
+                #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+                Store::ReadReplica(store) => store.put_blob(key, data).await,
+                Store::None => Err(trc::StoreEvent::NotConfigured.into()),
+            },
+            BlobBackend::Fs(store) => store.put_blob(key, data).await,
+            #[cfg(feature = "s3")]
+            BlobBackend::S3(store) => store.put_blob(key, data).await,
+            #[cfg(feature = "azure")]
+            BlobBackend::Azure(store) => store.put_blob(key, data).await,
+            BlobBackend::Sharded(store) => store.put_blob(key, data).await,
+            BlobBackend::Tiered(_) => Err(trc::StoreEvent::NotSupported.into()),
+        }
+    })
+    .await
+}
+
+async fn delete_blob(backend: &BlobBackend, key: &[u8]) -> trc::Result<bool> {
+    Box::pin(async move {
+        match backend {
+            BlobBackend::Store(store) => match store {
+                #[cfg(feature = "sqlite")]
+                Store::SQLite(store) => store.delete_blob(key).await,
+                #[cfg(feature = "foundation")]
+                Store::FoundationDb(store) => store.delete_blob(key).await,
+                #[cfg(feature = "postgres")]
+                Store::PostgreSQL(store) => store.delete_blob(key).await,
+                #[cfg(feature = "mysql")]
+                Store::MySQL(store) => store.delete_blob(key).await,
+                #[cfg(feature = "rocks")]
+                Store::RocksDb(store) => store.delete_blob(key).await,
+                #[cfg(feature = "cassandra")]
+                Store::Cassandra(store) => store.delete_blob(key).await,
+                Store::Volatile(store) => store.delete_blob(key).await,
+                #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+                Store::ReadReplica(store) => store.delete_blob(key).await,
+                Store::None => Err(trc::StoreEvent::NotConfigured.into()),
+            },
+            BlobBackend::Fs(store) => store.delete_blob(key).await,
+            #[cfg(feature = "s3")]
+            BlobBackend::S3(store) => store.delete_blob(key).await,
+            #[cfg(feature = "azure")]
+            BlobBackend::Azure(store) => store.delete_blob(key).await,
+            BlobBackend::Sharded(store) => store.delete_blob(key).await,
+            BlobBackend::Tiered(_) => Err(trc::StoreEvent::NotSupported.into()),
+        }
+    })
+    .await
+}
@@ -14,13 +14,21 @@ use crate::fts::{Field, FtsFilter};
 
 use super::{ElasticSearchStore, INDEX_NAMES, assert_success};
 
+// Default value of the `index.max_terms_count` setting
+const MAX_TERMS_COUNT: u64 = 65536;
+
 impl ElasticSearchStore {
     pub async fn fts_query<T: Into<u8> + Display + Clone + std::fmt::Debug>(
         &self,
         account_id: u32,
         collection: impl Into<u8>,
         filters: Vec<FtsFilter<T>>,
+        mask: Option<&RoaringBitmap>,
     ) -> trc::Result<RoaringBitmap> {
+        if mask.is_some_and(|mask| mask.is_empty()) {
+            return Ok(RoaringBitmap::new());
+        }
+
         let mut stack: Vec<(FtsFilter<T>, Vec<Value>)> = vec![];
         let mut conditions = vec![json!({ "match": { "account_id": account_id } })];
         let mut logical_op = FtsFilter::And;
@@ -84,6 +92,11 @@ impl ElasticSearchStore {
             }
         }
 
+        // Restrict the search to the masked documents
+        if let Some(mask) = mask.filter(|mask| mask.len() <= MAX_TERMS_COUNT) {
+            conditions.push(json!({ "terms": { "document_id": mask.iter().collect::<Vec<_>>() } }));
+        }
+
         // TODO implement pagination
         let response = assert_success(
             self.index
@@ -117,6 +130,9 @@ impl ElasticSearchStore {
                 trc::StoreEvent::ElasticsearchError.reason("Invalid response from ElasticSearch")
             })? as u32);
         }
+        if let Some(mask) = mask {
+            results &= mask;
+        }
 
         Ok(results)
     }
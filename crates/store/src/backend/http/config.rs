@@ -54,6 +54,18 @@ impl Stores {
                         .property_or_default::<bool>(("http-lookup", id_, "skip-first"), "false")
                         .unwrap_or(false),
                 },
+                "json" => HttpStoreFormat::Json {
+                    pointer: config
+                        .value(("http-lookup", id_, "json.pointer"))
+                        .unwrap_or_default()
+                        .to_string(),
+                    key: config
+                        .value(("http-lookup", id_, "json.key"))
+                        .map(|v| v.to_string()),
+                    value: config
+                        .value(("http-lookup", id_, "json.value"))
+                        .map(|v| v.to_string()),
+                },
                 other => {
                     let message = format!("Invalid format: {other:?}");
                     config.new_build_error(("http-lookup", id_, "format"), message);
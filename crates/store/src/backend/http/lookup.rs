@@ -144,6 +144,63 @@ impl HttpStore {
         };
 
         let mut entries = AHashMap::new();
+        if let HttpStoreFormat::Json {
+            pointer,
+            key,
+            value,
+        } = &self.config.format
+        {
+            let json = serde_json::from_reader::<_, serde_json::Value>(reader).map_err(|err| {
+                trc::StoreEvent::HttpStoreError
+                    .into_err()
+                    .reason(err)
+                    .ctx(trc::Key::Url, self.config.url.to_compact_string())
+                    .ctx(trc::Key::Elapsed, time.elapsed())
+                    .details("Failed to parse JSON")
+            })?;
+            let items = json
+                .pointer(pointer)
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| {
+                    trc::StoreEvent::HttpStoreError
+                        .into_err()
+                        .ctx(trc::Key::Url, self.config.url.to_compact_string())
+                        .ctx(trc::Key::Elapsed, time.elapsed())
+                        .details("JSON pointer does not reference an array")
+                })?;
+
+            for item in items {
+                let entry_key = match key {
+                    Some(key) => item.get(key).and_then(json_to_string),
+                    None => json_to_string(item),
+                };
+                if let Some(entry_key) =
+                    entry_key.filter(|k| !k.is_empty() && k.len() <= self.config.max_entry_size)
+                {
+                    let entry_value = value
+                        .as_ref()
+                        .and_then(|value| item.get(value))
+                        .and_then(json_to_string)
+                        .filter(|v| !v.is_empty() && v.len() <= self.config.max_entry_size)
+                        .map(|v| Value::Text(v.into()))
+                        .unwrap_or(Value::Integer(1));
+                    entries.insert(entry_key, entry_value);
+                    if entries.len() == self.config.max_entries {
+                        break;
+                    }
+                }
+            }
+
+            trc::event!(
+                Store(trc::StoreEvent::HttpStoreFetch),
+                Url = self.config.url.to_compact_string(),
+                Total = entries.len(),
+                Elapsed = time.elapsed(),
+            );
+
+            return Ok(entries);
+        }
+
         for (pos, line) in BufReader::new(reader).lines().enumerate() {
             let line_ = line.map_err(|err| {
                 trc::StoreEvent::HttpStoreError
@@ -231,3 +288,11 @@ impl HttpStore {
         Ok(entries)
     }
 }
+
+fn json_to_string(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(v) => Some(v.trim().to_string()),
+        serde_json::Value::Number(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
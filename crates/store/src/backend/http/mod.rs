@@ -40,6 +40,11 @@ pub enum HttpStoreFormat {
         separator: char,
         skip_first: bool,
     },
+    Json {
+        pointer: String,
+        key: Option<String>,
+        value: Option<String>,
+    },
 }
 
 #[derive(Debug)]
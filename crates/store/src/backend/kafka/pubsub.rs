@@ -20,7 +20,7 @@ pub struct KafkaPubSubStream {
 }
 
 impl KafkaPubSub {
-    pub async fn publish(&self, topic: &'static str, message: Vec<u8>) -> trc::Result<()> {
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
         self.producer
             .send(
                 FutureRecord::<(), [u8]>::to(topic).payload(message.as_slice()),
@@ -6,6 +6,8 @@
 
 #[cfg(feature = "azure")]
 pub mod azure;
+#[cfg(feature = "cassandra")]
+pub mod cassandra;
 #[cfg(feature = "enterprise")]
 pub mod composite;
 #[cfg(feature = "elastic")]
@@ -31,6 +33,7 @@ pub mod rocksdb;
 pub mod s3;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
+pub mod volatile;
 #[cfg(feature = "zenoh")]
 pub mod zenoh;
 
@@ -14,9 +14,9 @@ pub struct NatsPubSubStream {
 }
 
 impl NatsPubSub {
-    pub async fn publish(&self, topic: &'static str, message: Vec<u8>) -> trc::Result<()> {
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
         self.client
-            .publish(topic, message.into())
+            .publish(topic.to_string(), message.into())
             .await
             .map_err(|err| Error::new(EventType::Cluster(ClusterEvent::PublisherError)).reason(err))
     }
@@ -20,7 +20,7 @@ pub struct RedisClusterPubSubStream {
 }
 
 impl RedisStore {
-    pub async fn publish(&self, topic: &'static str, message: Vec<u8>) -> trc::Result<()> {
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
         match &self.pool {
             RedisPool::Single(pool) => pool
                 .get()
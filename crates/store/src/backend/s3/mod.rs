@@ -4,9 +4,20 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fmt::Display, io::Write, ops::Range, time::Duration};
+use std::{
+    fmt::Display,
+    future::Future,
+    io::Write,
+    ops::Range,
+    time::{Duration, Instant},
+};
 
-use s3::{Bucket, Region, creds::Credentials};
+use futures::{StreamExt, TryStreamExt, stream};
+use rand::Rng;
+use s3::{
+    Bucket, Region, creds::Credentials, error::S3Error, request::ResponseData, serde_types::Part,
+};
+use trc::StoreEvent;
 use utils::{
     codec::base32_custom::Base32Writer,
     config::{Config, utils::AsKey},
@@ -16,8 +27,17 @@ pub struct S3Store {
     bucket: Box<Bucket>,
     prefix: Option<String>,
     max_retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    multipart_threshold: usize,
+    part_size: usize,
+    part_concurrency: usize,
+    verify_checksums: bool,
 }
 
+// S3 requires all parts except the last one to be at least 5MB
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
 impl S3Store {
     pub async fn open(config: &mut Config, prefix: impl AsKey) -> Option<Self> {
         // Obtain region and endpoint from config
@@ -68,6 +88,26 @@ impl S3Store {
             max_retries: config
                 .property_or_default((&prefix, "max-retries"), "3")
                 .unwrap_or(3),
+            backoff: config
+                .property_or_default((&prefix, "retry.backoff"), "500ms")
+                .unwrap_or_else(|| Duration::from_millis(500)),
+            max_backoff: config
+                .property_or_default((&prefix, "retry.max-backoff"), "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            multipart_threshold: config
+                .property_or_default((&prefix, "multipart.threshold"), "16777216")
+                .unwrap_or(16777216),
+            part_size: config
+                .property_or_default::<usize>((&prefix, "multipart.part-size"), "8388608")
+                .unwrap_or(8388608)
+                .max(MIN_PART_SIZE),
+            part_concurrency: config
+                .property_or_default::<usize>((&prefix, "multipart.concurrency"), "4")
+                .unwrap_or(4)
+                .max(1),
+            verify_checksums: config
+                .property_or_default((&prefix, "checksum.verify"), "true")
+                .unwrap_or(true),
             prefix: config.value((&prefix, "key-prefix")).map(|s| s.to_string()),
         })
     }
@@ -78,104 +118,254 @@ impl S3Store {
         range: Range<usize>,
     ) -> trc::Result<Option<Vec<u8>>> {
         let path = self.build_key(key);
-        let mut retries_left = self.max_retries;
-
-        loop {
-            let response = if range.start != 0 || range.end != usize::MAX {
-                self.bucket
-                    .get_object_range(
-                        &path,
-                        range.start as u64,
-                        Some(range.end.saturating_sub(1) as u64),
-                    )
-                    .await
-            } else {
-                self.bucket.get_object(&path).await
-            }
-            .map_err(into_error)?;
-
-            match response.status_code() {
-                200..=299 => return Ok(Some(response.to_vec())),
-                404 => return Ok(None),
-                500..=599 if retries_left > 0 => {
-                    // wait backoff
-                    tokio::time::sleep(Duration::from_secs(
-                        1 << (self.max_retries - retries_left).min(6),
-                    ))
-                    .await;
-
-                    retries_left -= 1;
+        let is_full = range.start == 0 && range.end == usize::MAX;
+        let response = self
+            .request("GET", || async {
+                if !is_full {
+                    self.bucket
+                        .get_object_range(
+                            &path,
+                            range.start as u64,
+                            Some(range.end.saturating_sub(1) as u64),
+                        )
+                        .await
+                } else {
+                    self.bucket.get_object(&path).await
                 }
-                code => {
-                    return Err(trc::StoreEvent::S3Error
-                        .reason(String::from_utf8_lossy(response.as_slice()))
-                        .ctx(trc::Key::Code, code));
+            })
+            .await?;
+
+        match response.status_code() {
+            200..=299 => {
+                let data = response.to_vec();
+                if is_full {
+                    self.verify_checksum(&path, &response, &data)?;
                 }
+                Ok(Some(data))
             }
+            404 => Ok(None),
+            code => Err(response_error(&response, code)),
         }
     }
 
     pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        let path = self.build_key(key);
+        if data.len() > self.multipart_threshold {
+            return self.put_blob_multipart(&path, data).await;
+        }
+
+        let response = self
+            .request("PUT", || self.bucket.put_object(&path, data))
+            .await?;
+
+        match response.status_code() {
+            200..=299 => self.verify_checksum(&path, &response, data),
+            code => Err(response_error(&response, code)),
+        }
+    }
+
+    async fn put_blob_multipart(&self, path: &str, data: &[u8]) -> trc::Result<()> {
         let mut retries_left = self.max_retries;
 
+        // Parts are retried as a whole, as a failed part aborts the upload
         loop {
-            let response = self
-                .bucket
-                .put_object(self.build_key(key), data)
-                .await
-                .map_err(into_error)?;
-
-            match response.status_code() {
-                200..=299 => return Ok(()),
-                500..=599 if retries_left > 0 => {
-                    // wait backoff
-                    tokio::time::sleep(Duration::from_secs(
-                        1 << (self.max_retries - retries_left).min(6),
-                    ))
-                    .await;
+            match self.try_put_blob_multipart(path, data).await {
+                Ok(()) => return Ok(()),
+                Err(err) if retries_left > 0 => {
+                    let attempt = self.max_retries - retries_left;
+                    let backoff = self.backoff(attempt);
+
+                    trc::event!(
+                        Store(StoreEvent::S3Retry),
+                        Type = "MULTIPART",
+                        Key = path.to_string(),
+                        Total = attempt + 1,
+                        NextRetry = backoff,
+                        CausedBy = err,
+                    );
 
+                    tokio::time::sleep(backoff).await;
                     retries_left -= 1;
                 }
-                code => {
-                    return Err(trc::StoreEvent::S3Error
-                        .reason(String::from_utf8_lossy(response.as_slice()))
-                        .ctx(trc::Key::Code, code));
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn try_put_blob_multipart(&self, path: &str, data: &[u8]) -> trc::Result<()> {
+        let time = Instant::now();
+        let upload_id = self
+            .bucket
+            .initiate_multipart_upload(path, "application/octet-stream")
+            .await
+            .map_err(into_error)?
+            .upload_id;
+
+        let result = stream::iter(data.chunks(self.part_size).enumerate())
+            .map(|(idx, chunk)| {
+                let upload_id = upload_id.as_str();
+                async move {
+                    let part = self
+                        .bucket
+                        .put_multipart_chunk(
+                            chunk.to_vec(),
+                            path,
+                            idx as u32 + 1,
+                            upload_id,
+                            "application/octet-stream",
+                        )
+                        .await
+                        .map_err(into_error)?;
+                    self.verify_etag(path, &part.etag, chunk)?;
+                    Ok::<_, trc::Error>(part)
                 }
+            })
+            .buffered(self.part_concurrency)
+            .try_collect::<Vec<Part>>()
+            .await;
+
+        let parts = match result {
+            Ok(parts) => parts,
+            Err(err) => {
+                let _ = self.bucket.abort_upload(path, &upload_id).await;
+                return Err(err);
+            }
+        };
+        let num_parts = parts.len();
+
+        let response = self
+            .bucket
+            .complete_multipart_upload(path, &upload_id, parts)
+            .await
+            .map_err(into_error)?;
+
+        trc::event!(
+            Store(StoreEvent::S3Request),
+            Type = "MULTIPART",
+            Key = path.to_string(),
+            Code = response.status_code(),
+            Size = data.len(),
+            Total = num_parts,
+            Elapsed = time.elapsed(),
+        );
+
+        match response.status_code() {
+            200..=299 => Ok(()),
+            code => {
+                let _ = self.bucket.abort_upload(path, &upload_id).await;
+                Err(response_error(&response, code))
             }
         }
     }
 
     pub(crate) async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        let path = self.build_key(key);
+        let response = self
+            .request("DELETE", || self.bucket.delete_object(&path))
+            .await?;
+
+        match response.status_code() {
+            200..=299 => Ok(true),
+            404 => Ok(false),
+            code => Err(response_error(&response, code)),
+        }
+    }
+
+    async fn request<F, Fut>(&self, method: &'static str, f: F) -> trc::Result<ResponseData>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<ResponseData, S3Error>>,
+    {
         let mut retries_left = self.max_retries;
 
         loop {
-            let response = self
-                .bucket
-                .delete_object(self.build_key(key))
-                .await
-                .map_err(into_error)?;
-
-            match response.status_code() {
-                200..=299 => return Ok(true),
-                404 => return Ok(false),
-                500..=599 if retries_left > 0 => {
-                    // wait backoff
-                    tokio::time::sleep(Duration::from_secs(
-                        1 << (self.max_retries - retries_left).min(6),
-                    ))
-                    .await;
+            let time = Instant::now();
+            let result = f().await;
+            let code = match &result {
+                Ok(response) => response.status_code(),
+                Err(S3Error::HttpFailWithBody(code, _)) => *code,
+                Err(_) => 0,
+            };
 
-                    retries_left -= 1;
-                }
-                code => {
-                    return Err(trc::StoreEvent::S3Error
-                        .reason(String::from_utf8_lossy(response.as_slice()))
-                        .ctx(trc::Key::Code, code));
-                }
+            trc::event!(
+                Store(StoreEvent::S3Request),
+                Type = method,
+                Code = code,
+                Size = result
+                    .as_ref()
+                    .map_or(0, |response| response.as_slice().len()),
+                Elapsed = time.elapsed(),
+            );
+
+            // Retry on transport errors, throttling (429, 503 SlowDown) and server errors
+            if matches!(code, 0 | 429 | 500..=599) && retries_left > 0 {
+                let attempt = self.max_retries - retries_left;
+                let backoff = self.backoff(attempt);
+
+                trc::event!(
+                    Store(StoreEvent::S3Retry),
+                    Type = method,
+                    Code = code,
+                    Total = attempt + 1,
+                    NextRetry = backoff,
+                    Reason = match &result {
+                        Ok(response) => String::from_utf8_lossy(response.as_slice()).into_owned(),
+                        Err(err) => err.to_string(),
+                    },
+                );
+
+                tokio::time::sleep(backoff).await;
+                retries_left -= 1;
+            } else {
+                return result.map_err(into_error);
             }
         }
     }
 
+    fn backoff(&self, attempt: u32) -> Duration {
+        // Exponential backoff with full jitter
+        let max = self
+            .backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff)
+            .as_millis() as u64;
+        Duration::from_millis(rand::rng().random_range(max / 2..=max))
+    }
+
+    fn verify_checksum(&self, path: &str, response: &ResponseData, data: &[u8]) -> trc::Result<()> {
+        let headers = response.headers();
+        if let Some((_, etag)) = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+        {
+            self.verify_etag(path, etag, data)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn verify_etag(&self, path: &str, etag: &str, data: &[u8]) -> trc::Result<()> {
+        // Only plain MD5 ETags can be verified, multipart and SSE-KMS objects use
+        // a different format.
+        let etag = etag.trim().trim_matches('"');
+        if !self.verify_checksums
+            || etag.len() != 32
+            || !etag.bytes().all(|ch| ch.is_ascii_hexdigit())
+        {
+            return Ok(());
+        }
+
+        let digest = format!("{:x}", md5::compute(data));
+        if etag.eq_ignore_ascii_case(&digest) {
+            Ok(())
+        } else {
+            Err(StoreEvent::S3ChecksumMismatch
+                .into_err()
+                .ctx(trc::Key::Key, path.to_string())
+                .details(format!("Expected {etag}, got {digest}")))
+        }
+    }
+
     fn build_key(&self, key: &[u8]) -> String {
         if let Some(prefix) = &self.prefix {
             let mut writer =
@@ -193,3 +383,10 @@ impl S3Store {
 fn into_error(err: impl Display) -> trc::Error {
     trc::StoreEvent::S3Error.reason(err)
 }
+
+#[inline(always)]
+fn response_error(response: &ResponseData, code: u16) -> trc::Error {
+    trc::StoreEvent::S3Error
+        .reason(String::from_utf8_lossy(response.as_slice()))
+        .ctx(trc::Key::Code, code)
+}
@@ -0,0 +1,53 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::ops::Range;
+
+use crate::{SUBSPACE_BLOBS, write::key::KeySerializer};
+
+use super::VolatileStore;
+
+impl VolatileStore {
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        Ok(self.data.read().get(&blob_key(key)).map(|bytes| {
+            if range.start == 0 && range.end == usize::MAX {
+                bytes.clone()
+            } else {
+                bytes
+                    .get(range.start..std::cmp::min(bytes.len(), range.end))
+                    .unwrap_or_default()
+                    .to_vec()
+            }
+        }))
+    }
+
+    pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        let _commit_lock = self.commit_lock.lock().await;
+        self.commit(vec![(blob_key(key), Some(data.to_vec()))])
+            .await
+    }
+
+    pub(crate) async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        let key = blob_key(key);
+        let _commit_lock = self.commit_lock.lock().await;
+        if self.data.read().contains_key(&key) {
+            self.commit(vec![(key, None)]).await.map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+fn blob_key(key: &[u8]) -> Vec<u8> {
+    KeySerializer::new(key.len() + 1)
+        .write(SUBSPACE_BLOBS)
+        .write(key)
+        .finalize()
+}
@@ -0,0 +1,68 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{path::PathBuf, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+use utils::config::{Config, utils::AsKey};
+
+use super::{VolatileStore, wal::WriteAheadLog};
+
+impl VolatileStore {
+    pub fn open(config: &mut Config, prefix: impl AsKey) -> Option<Self> {
+        let prefix = prefix.as_key();
+
+        let Some(path) = config.value((&prefix, "wal.path")).map(PathBuf::from) else {
+            config.new_build_warning(
+                prefix.as_str(),
+                concat!(
+                    "No write-ahead log configured for volatile store, ",
+                    "all data including queued messages will be lost on restart"
+                ),
+            );
+
+            return Some(Self {
+                data: Default::default(),
+                wal: None,
+                commit_lock: Default::default(),
+            });
+        };
+        let compact_size = config
+            .property_or_default::<u64>((&prefix, "wal.compact-size"), "67108864")
+            .unwrap_or(67108864);
+        let sync = config
+            .property_or_default::<bool>((&prefix, "wal.sync"), "true")
+            .unwrap_or(true);
+
+        let (wal, data, discarded) = WriteAheadLog::open(path.clone(), compact_size, sync)
+            .map_err(|err| {
+                config.new_build_error(
+                    (&prefix, "wal.path"),
+                    format!(
+                        "Failed to open write-ahead log {}: {:?}",
+                        path.display(),
+                        err
+                    ),
+                )
+            })
+            .ok()?;
+        if discarded > 0 {
+            config.new_build_warning(
+                (&prefix, "wal.path"),
+                format!(
+                    "Discarded {discarded} bytes of incomplete entries from write-ahead log {}",
+                    path.display()
+                ),
+            );
+        }
+
+        Some(Self {
+            data: Arc::new(RwLock::new(data)),
+            wal: Some(Arc::new(Mutex::new(wal))),
+            commit_lock: Default::default(),
+        })
+    }
+}
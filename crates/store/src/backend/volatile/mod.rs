@@ -0,0 +1,41 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+
+use self::wal::WriteAheadLog;
+
+pub mod blob;
+pub mod main;
+pub mod read;
+pub mod wal;
+pub mod write;
+
+/// Data store kept entirely in memory, intended for edge relays that only
+/// queue and forward messages.
+///
+/// Durability depends on the write-ahead log:
+/// - Without it, all data (including queued messages) is lost on restart.
+/// - With it, committed batches are appended to a local file and replayed on
+///   startup. Unless `wal.sync` is enabled, batches written shortly before a
+///   crash may still be lost.
+///
+/// In both cases the data set is limited by the available memory and cannot
+/// be shared between nodes, which is why mailbox access and local delivery
+/// are disabled when this store is used as the data store.
+pub struct VolatileStore {
+    data: Arc<RwLock<BTreeMap<Vec<u8>, Vec<u8>>>>,
+    wal: Option<Arc<Mutex<WriteAheadLog>>>,
+    // Serializes writers, changes are logged and applied in the same order
+    commit_lock: tokio::sync::Mutex<()>,
+}
+
+#[inline(always)]
+fn into_error(err: std::io::Error) -> trc::Error {
+    trc::StoreEvent::FilesystemError.reason(err)
+}
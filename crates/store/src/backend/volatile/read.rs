@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use roaring::RoaringBitmap;
+
+use super::VolatileStore;
+use crate::{
+    BitmapKey, Deserialize, IterateParams, Key, U32_LEN, ValueKey, WITH_SUBSPACE,
+    backend::deserialize_i64_le,
+    write::{BitmapClass, ValueClass, key::DeserializeBigEndian},
+};
+
+impl VolatileStore {
+    pub(crate) async fn get_value<U>(&self, key: impl Key) -> trc::Result<Option<U>>
+    where
+        U: Deserialize + 'static,
+    {
+        if let Some(value) = self.data.read().get(&key.serialize(WITH_SUBSPACE)) {
+            U::deserialize(value).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) async fn get_bitmap(
+        &self,
+        mut key: BitmapKey<BitmapClass>,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        let begin = key.serialize(WITH_SUBSPACE);
+        key.document_id = u32::MAX;
+        let end = key.serialize(WITH_SUBSPACE);
+        let key_len = begin.len();
+
+        let mut bm = RoaringBitmap::new();
+        for key in self.data.read().range(begin..=end).map(|(key, _)| key) {
+            if key.len() == key_len {
+                bm.insert(key.deserialize_be_u32(key.len() - U32_LEN)?);
+            }
+        }
+
+        Ok(if !bm.is_empty() { Some(bm) } else { None })
+    }
+
+    pub(crate) async fn iterate<T: Key>(
+        &self,
+        params: IterateParams<T>,
+        mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+    ) -> trc::Result<()> {
+        let begin = params.begin.serialize(WITH_SUBSPACE);
+        let end = params.end.serialize(WITH_SUBSPACE);
+        if begin > end {
+            return Ok(());
+        }
+
+        let data = self.data.read();
+        let mut rows = data.range(begin..=end);
+        while let Some((key, value)) = if params.ascending {
+            rows.next()
+        } else {
+            rows.next_back()
+        } {
+            if !cb(&key[1..], value.as_slice())? || params.first {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_counter(
+        &self,
+        key: impl Into<ValueKey<ValueClass>> + Sync + Send,
+    ) -> trc::Result<i64> {
+        let key = key.into().serialize(WITH_SUBSPACE);
+        if let Some(bytes) = self.data.read().get(&key) {
+            deserialize_i64_le(&key, bytes)
+        } else {
+            Ok(0)
+        }
+    }
+}
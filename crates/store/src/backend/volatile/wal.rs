@@ -0,0 +1,347 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+const FRAME_HEADER_LEN: usize = 12;
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+const ENTRY_DELETE: u8 = 0;
+const ENTRY_SET: u8 = 1;
+
+/// Append-only log of committed changes. Each batch is written as a frame
+/// containing its length, a checksum and the changed entries, which allows
+/// detecting frames that were only partially written before a crash.
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    compact_size: u64,
+    sync: bool,
+    // An incomplete frame could not be removed, the log has to be rewritten
+    torn: bool,
+}
+
+impl WriteAheadLog {
+    pub fn open(
+        path: PathBuf,
+        compact_size: u64,
+        sync: bool,
+    ) -> std::io::Result<(Self, BTreeMap<Vec<u8>, Vec<u8>>, u64)> {
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+
+        // Replay all complete frames
+        let mut data = BTreeMap::new();
+        let mut pos = 0;
+        while let Some((frame_len, payload)) = read_frame(&bytes[pos..]) {
+            if !replay_payload(payload, &mut data) {
+                break;
+            }
+            pos += frame_len;
+        }
+
+        // Discard incomplete frames left by an interrupted write
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        file.set_len(pos as u64)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok((
+            WriteAheadLog {
+                path,
+                file,
+                size: pos as u64,
+                compact_size,
+                sync,
+                torn: false,
+            },
+            data,
+            (bytes.len() - pos) as u64,
+        ))
+    }
+
+    pub fn append(&mut self, changes: &[(Vec<u8>, Option<Vec<u8>>)]) -> std::io::Result<()> {
+        if self.torn {
+            return Err(std::io::Error::other(
+                "Write-ahead log contains an incomplete entry",
+            ));
+        }
+
+        let mut payload = Vec::new();
+        for (key, value) in changes {
+            write_entry(&mut payload, key, value.as_deref());
+        }
+        let frame = build_frame(&payload);
+
+        match self.write_frame(&frame) {
+            Ok(_) => {
+                self.size += frame.len() as u64;
+                Ok(())
+            }
+            Err(err) => {
+                // Remove any partially written data, otherwise all the frames
+                // appended after it would be discarded on replay
+                if self.truncate().is_err() {
+                    self.torn = true;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(frame)?;
+        if self.sync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    // Truncates the log to the end of the last complete frame
+    fn truncate(&mut self) -> std::io::Result<()> {
+        self.file.set_len(self.size)?;
+        self.file.seek(SeekFrom::Start(self.size))?;
+        Ok(())
+    }
+
+    pub fn needs_compaction(&self) -> bool {
+        self.torn || self.size > self.compact_size
+    }
+
+    pub fn compact(&mut self, data: &BTreeMap<Vec<u8>, Vec<u8>>) -> std::io::Result<()> {
+        // Write a snapshot of the current data and replace the log with it
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        let mut payload = Vec::new();
+        let mut size = 0;
+        for (key, value) in data {
+            write_entry(&mut payload, key, Some(value));
+            if payload.len() >= MAX_FRAME_SIZE {
+                let frame = build_frame(&payload);
+                file.write_all(&frame)?;
+                size += frame.len() as u64;
+                payload.clear();
+            }
+        }
+        if !payload.is_empty() {
+            let frame = build_frame(&payload);
+            file.write_all(&frame)?;
+            size += frame.len() as u64;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        // Make the rename durable
+        if let Some(dir) = self.path.parent() {
+            File::open(if dir.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                dir
+            })?
+            .sync_all()?;
+        }
+
+        // The snapshot file is now the log
+        self.file = file;
+        self.size = size;
+        self.torn = false;
+
+        // Avoid compacting again until the log has doubled in size
+        self.compact_size = self.compact_size.max(size * 2);
+
+        Ok(())
+    }
+}
+
+fn build_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + FRAME_HEADER_LEN);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&xxh3_64(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn read_frame(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let payload_len = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let checksum = u64::from_le_bytes(bytes.get(4..FRAME_HEADER_LEN)?.try_into().ok()?);
+    let payload = bytes.get(FRAME_HEADER_LEN..FRAME_HEADER_LEN + payload_len)?;
+
+    (xxh3_64(payload) == checksum).then_some((FRAME_HEADER_LEN + payload_len, payload))
+}
+
+fn write_entry(buf: &mut Vec<u8>, key: &[u8], value: Option<&[u8]>) {
+    buf.push(if value.is_some() {
+        ENTRY_SET
+    } else {
+        ENTRY_DELETE
+    });
+    buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    buf.extend_from_slice(key);
+    if let Some(value) = value {
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(value);
+    }
+}
+
+fn replay_payload(mut payload: &[u8], data: &mut BTreeMap<Vec<u8>, Vec<u8>>) -> bool {
+    // Entries are only applied once the whole frame has been validated
+    let mut entries = Vec::new();
+    while let Some((&tag, bytes)) = payload.split_first() {
+        let Some((key, bytes)) = read_bytes(bytes) else {
+            return false;
+        };
+        match tag {
+            ENTRY_SET => {
+                let Some((value, bytes)) = read_bytes(bytes) else {
+                    return false;
+                };
+                entries.push((key, Some(value)));
+                payload = bytes;
+            }
+            ENTRY_DELETE => {
+                entries.push((key, None));
+                payload = bytes;
+            }
+            _ => return false,
+        }
+    }
+
+    for (key, value) in entries {
+        if let Some(value) = value {
+            data.insert(key.to_vec(), value.to_vec());
+        } else {
+            data.remove(key);
+        }
+    }
+
+    true
+}
+
+fn read_bytes(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let value = bytes.get(4..4 + len)?;
+    Some((value, &bytes[4 + len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wal_replay() {
+        let path = std::env::temp_dir().join(format!("wal-replay-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (mut wal, data, discarded) = WriteAheadLog::open(path.clone(), 1024, true).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(discarded, 0);
+        wal.append(&[
+            (b"a".to_vec(), Some(b"1".to_vec())),
+            (b"b".to_vec(), Some(b"2".to_vec())),
+        ])
+        .unwrap();
+        wal.append(&[(b"a".to_vec(), None)]).unwrap();
+        drop(wal);
+
+        // Simulate a frame that was only partially written
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&build_frame(b"\x01\x01\x00\x00\x00c")[..10])
+            .unwrap();
+        drop(file);
+
+        let (mut wal, data, discarded) = WriteAheadLog::open(path.clone(), 1024, true).unwrap();
+        assert_eq!(
+            data.into_iter().collect::<Vec<_>>(),
+            vec![(b"b".to_vec(), b"2".to_vec())]
+        );
+        assert_eq!(discarded, 10);
+
+        // Compaction keeps the current state only
+        let mut data = BTreeMap::new();
+        data.insert(b"c".to_vec(), b"3".to_vec());
+        wal.compact(&data).unwrap();
+        wal.append(&[(b"d".to_vec(), Some(b"4".to_vec()))]).unwrap();
+        drop(wal);
+
+        let (_, data, discarded) = WriteAheadLog::open(path.clone(), 1024, true).unwrap();
+        assert_eq!(
+            data.into_iter().collect::<Vec<_>>(),
+            vec![
+                (b"c".to_vec(), b"3".to_vec()),
+                (b"d".to_vec(), b"4".to_vec())
+            ]
+        );
+        assert_eq!(discarded, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wal_torn_write() {
+        let path = std::env::temp_dir().join(format!("wal-torn-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (mut wal, _, _) = WriteAheadLog::open(path.clone(), 1024, true).unwrap();
+        wal.append(&[(b"a".to_vec(), Some(b"1".to_vec()))]).unwrap();
+
+        // Simulate a write that failed after part of the frame was written
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&build_frame(b"\x01\x01\x00\x00\x00b")[..7])
+            .unwrap();
+        drop(file);
+        wal.truncate().unwrap();
+
+        // Frames appended after the failed write are replayed
+        wal.append(&[(b"c".to_vec(), Some(b"3".to_vec()))]).unwrap();
+        drop(wal);
+
+        let (mut wal, data, discarded) = WriteAheadLog::open(path.clone(), 1024, true).unwrap();
+        assert_eq!(
+            data.into_iter().collect::<Vec<_>>(),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"c".to_vec(), b"3".to_vec())
+            ]
+        );
+        assert_eq!(discarded, 0);
+
+        // A log that could not be truncated refuses new entries until compacted
+        wal.torn = true;
+        assert!(wal.needs_compaction());
+        assert!(wal.append(&[(b"d".to_vec(), None)]).is_err());
+        let mut data = BTreeMap::new();
+        data.insert(b"a".to_vec(), b"1".to_vec());
+        wal.compact(&data).unwrap();
+        wal.append(&[(b"d".to_vec(), Some(b"4".to_vec()))]).unwrap();
+        drop(wal);
+
+        let (_, data, discarded) = WriteAheadLog::open(path.clone(), 1024, true).unwrap();
+        assert_eq!(
+            data.into_iter().collect::<Vec<_>>(),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"d".to_vec(), b"4".to_vec())
+            ]
+        );
+        assert_eq!(discarded, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
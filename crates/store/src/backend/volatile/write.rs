@@ -0,0 +1,265 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::BTreeMap;
+
+use ahash::AHashMap;
+
+use super::{VolatileStore, into_error};
+use crate::{
+    IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_IN_MEMORY_COUNTER, SUBSPACE_QUOTA, U64_LEN,
+    WITH_SUBSPACE,
+    backend::deserialize_i64_le,
+    write::{AssignedIds, Batch, Operation, ValueClass, ValueOp},
+};
+
+impl VolatileStore {
+    pub(crate) async fn write(&self, mut batch: Batch<'_>) -> trc::Result<AssignedIds> {
+        let mut account_id = u32::MAX;
+        let mut collection = u8::MAX;
+        let mut document_id = u32::MAX;
+        let mut change_id = 0u64;
+        let mut result = AssignedIds::default();
+        let has_changes = !batch.changes.is_empty();
+
+        // Changes are staged and only applied once all assertions have passed
+        let _commit_lock = self.commit_lock.lock().await;
+        let data = self.data.read();
+        let mut changes = PendingChanges {
+            data: &data,
+            changes: AHashMap::new(),
+        };
+
+        if has_changes {
+            for &account_id in batch.changes.keys() {
+                let key = ValueClass::ChangeId.serialize(account_id, 0, 0, WITH_SUBSPACE);
+                let change_id = if let Some(bytes) = changes.get(&key) {
+                    deserialize_i64_le(&key, bytes)? + 1
+                } else {
+                    1
+                };
+                changes.set(key, change_id.to_le_bytes().to_vec());
+                result.push_change_id(account_id, change_id as u64);
+            }
+        }
+
+        for op in batch.ops.iter_mut() {
+            match op {
+                Operation::AccountId {
+                    account_id: account_id_,
+                } => {
+                    account_id = *account_id_;
+                    if has_changes {
+                        change_id = result.last_change_id(account_id)?;
+                    }
+                }
+                Operation::Collection {
+                    collection: collection_,
+                } => {
+                    collection = *collection_;
+                }
+                Operation::DocumentId {
+                    document_id: document_id_,
+                } => {
+                    document_id = *document_id_;
+                }
+                Operation::Value { class, op } => {
+                    let key = class.serialize(account_id, collection, document_id, WITH_SUBSPACE);
+
+                    match op {
+                        ValueOp::Set {
+                            value,
+                            version_offset,
+                        } => {
+                            if let Some(offset) = version_offset {
+                                value[*offset..*offset + U64_LEN]
+                                    .copy_from_slice(&change_id.to_be_bytes());
+                            }
+
+                            changes.set(key, value.clone());
+                        }
+                        ValueOp::AtomicAdd(by) => {
+                            let num = changes.get_counter(&key)? + *by;
+                            changes.set(key, num.to_le_bytes().to_vec());
+                        }
+                        ValueOp::AddAndGet(by) => {
+                            let num = changes.get_counter(&key)? + *by;
+                            changes.set(key, num.to_le_bytes().to_vec());
+                            result.push_counter_id(num);
+                        }
+                        ValueOp::Clear => {
+                            changes.clear(key);
+                        }
+                    }
+                }
+                Operation::Index { field, key, set } => {
+                    let key = IndexKey {
+                        account_id,
+                        collection,
+                        document_id,
+                        field: *field,
+                        key: &*key,
+                    }
+                    .serialize(WITH_SUBSPACE);
+
+                    if *set {
+                        changes.set(key, vec![]);
+                    } else {
+                        changes.clear(key);
+                    }
+                }
+                Operation::Bitmap { class, set } => {
+                    let key = class.serialize(account_id, collection, document_id, WITH_SUBSPACE);
+
+                    if *set {
+                        changes.set(key, vec![]);
+                    } else {
+                        changes.clear(key);
+                    }
+                }
+                Operation::Log { collection, set } => {
+                    let key = LogKey {
+                        account_id,
+                        collection: *collection,
+                        change_id,
+                    }
+                    .serialize(WITH_SUBSPACE);
+
+                    changes.set(key, set.clone());
+                }
+                Operation::AssertValue {
+                    class,
+                    assert_value,
+                } => {
+                    let key = class.serialize(account_id, collection, document_id, WITH_SUBSPACE);
+
+                    let matches = changes
+                        .get(&key)
+                        .map(|value| assert_value.matches(value))
+                        .unwrap_or_else(|| assert_value.is_none());
+
+                    if !matches {
+                        return Err(trc::StoreEvent::AssertValueFailed.into());
+                    }
+                }
+            }
+        }
+
+        let changes = changes.changes.into_iter().collect::<Vec<_>>();
+        drop(data);
+        self.commit(changes).await.map(|_| result)
+    }
+
+    pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
+        let from = from.serialize(WITH_SUBSPACE);
+        let to = to.serialize(WITH_SUBSPACE);
+        if from >= to {
+            return Ok(());
+        }
+
+        let _commit_lock = self.commit_lock.lock().await;
+        let changes = self
+            .data
+            .read()
+            .range(from..to)
+            .map(|(key, _)| (key.clone(), None))
+            .collect::<Vec<_>>();
+        self.commit(changes).await
+    }
+
+    pub(crate) async fn purge_store(&self) -> trc::Result<()> {
+        let _commit_lock = self.commit_lock.lock().await;
+        let mut changes = Vec::new();
+
+        {
+            let data = self.data.read();
+            for subspace in [SUBSPACE_QUOTA, SUBSPACE_COUNTER, SUBSPACE_IN_MEMORY_COUNTER] {
+                for (key, value) in data.range(vec![subspace]..vec![subspace + 1]) {
+                    if deserialize_i64_le(key, value)? == 0 {
+                        changes.push((key.clone(), None));
+                    }
+                }
+            }
+        }
+
+        self.commit(changes).await
+    }
+
+    // Callers must hold the commit lock, which guarantees that the data does
+    // not change between the time the changes were built and applied
+    pub(super) async fn commit(&self, changes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> trc::Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        // Log the changes before they become visible to readers, file I/O
+        // runs on a blocking thread to avoid stalling the async runtime
+        let changes = if let Some(wal) = &self.wal {
+            let wal = wal.clone();
+            let data = self.data.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut wal = wal.lock();
+
+                // Compact before logging the changes, as the snapshot
+                // only contains the changes applied so far
+                if wal.needs_compaction() {
+                    if let Err(err) = wal.compact(&data.read()) {
+                        trc::error!(
+                            into_error(err)
+                                .details("Failed to compact volatile store write-ahead log")
+                        );
+                    }
+                }
+
+                wal.append(&changes).map(|_| changes)
+            })
+            .await
+            .map_err(|err| trc::EventType::Server(trc::ServerEvent::ThreadError).reason(err))?
+            .map_err(into_error)?
+        } else {
+            changes
+        };
+
+        let mut data = self.data.write();
+        for (key, value) in changes {
+            if let Some(value) = value {
+                data.insert(key, value);
+            } else {
+                data.remove(&key);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct PendingChanges<'x> {
+    data: &'x BTreeMap<Vec<u8>, Vec<u8>>,
+    changes: AHashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl PendingChanges<'_> {
+    fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        match self.changes.get(key) {
+            Some(value) => value.as_deref(),
+            None => self.data.get(key).map(|value| value.as_slice()),
+        }
+    }
+
+    fn get_counter(&self, key: &[u8]) -> trc::Result<i64> {
+        self.get(key)
+            .map(|bytes| deserialize_i64_le(key, bytes))
+            .unwrap_or(Ok(0))
+    }
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.changes.insert(key, Some(value));
+    }
+
+    fn clear(&mut self, key: Vec<u8>) {
+        self.changes.insert(key, None);
+    }
+}
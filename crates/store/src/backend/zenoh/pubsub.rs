@@ -13,7 +13,7 @@ pub struct ZenohPubSubStream {
 }
 
 impl ZenohPubSub {
-    pub async fn publish(&self, topic: &'static str, message: Vec<u8>) -> trc::Result<()> {
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
         self.session
             .declare_publisher(topic)
             .await
@@ -6,16 +6,17 @@
 
 use crate::{
     BlobStore, CompressionAlgo, InMemoryStore, PurgeSchedule, PurgeStore, Store, Stores,
-    backend::fs::FsStore,
+    backend::fs::FsStore, dispatch::blob::ZstdOptions,
 };
 use utils::config::{Config, cron::SimpleCron, utils::ParseValue};
 
 #[cfg(feature = "enterprise")]
 enum CompositeStore {
-    #[cfg(any(feature = "postgres", feature = "mysql"))]
-    SQLReadReplica(String),
+    #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+    ReadReplica(String),
     ShardedBlob(String),
     ShardedInMemory(String),
+    TieredBlob(String),
 }
 
 impl Stores {
@@ -151,6 +152,25 @@ impl Stores {
                         self.in_memory_stores.insert(store_id.clone(), db.into());
                     }
                 }
+                #[cfg(feature = "cassandra")]
+                "cassandra" | "scylladb" => {
+                    if let Some(db) = crate::backend::cassandra::CassandraStore::open(
+                        config,
+                        prefix,
+                        config.is_active_store(id),
+                    )
+                    .await
+                    .map(Store::from)
+                    {
+                        self.stores.insert(store_id.clone(), db.clone());
+                        self.fts_stores.insert(store_id.clone(), db.clone().into());
+                        self.blob_stores.insert(
+                            store_id.clone(),
+                            BlobStore::from(db.clone()).with_compression(compression_algo),
+                        );
+                        self.in_memory_stores.insert(store_id.clone(), db.into());
+                    }
+                }
                 #[cfg(feature = "sqlite")]
                 "sqlite" => {
                     // Avoid opening the same store twice
@@ -175,6 +195,29 @@ impl Stores {
                         self.in_memory_stores.insert(store_id.clone(), db.into());
                     }
                 }
+                "volatile" => {
+                    // Avoid discarding the in-memory data on reload
+                    if is_reload
+                        && self
+                            .stores
+                            .values()
+                            .any(|store| matches!(store, Store::Volatile(_)))
+                    {
+                        continue;
+                    }
+
+                    if let Some(db) = crate::backend::volatile::VolatileStore::open(config, prefix)
+                        .map(Store::from)
+                    {
+                        self.stores.insert(store_id.clone(), db.clone());
+                        self.fts_stores.insert(store_id.clone(), db.clone().into());
+                        self.blob_stores.insert(
+                            store_id.clone(),
+                            BlobStore::from(db.clone()).with_compression(compression_algo),
+                        );
+                        self.in_memory_stores.insert(store_id.clone(), db.into());
+                    }
+                }
                 "fs" => {
                     if let Some(db) = FsStore::open(config, prefix).await.map(BlobStore::from) {
                         self.blob_stores
@@ -244,15 +287,19 @@ impl Stores {
                     }
                 }
                 #[cfg(feature = "enterprise")]
-                "sql-read-replica" => {
-                    #[cfg(any(feature = "postgres", feature = "mysql"))]
-                    composite_stores.push(CompositeStore::SQLReadReplica(store_id));
+                "read-replica" | "sql-read-replica" => {
+                    #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+                    composite_stores.push(CompositeStore::ReadReplica(store_id));
                 }
                 #[cfg(feature = "enterprise")]
                 "distributed-blob" | "sharded-blob" => {
                     composite_stores.push(CompositeStore::ShardedBlob(store_id));
                 }
                 #[cfg(feature = "enterprise")]
+                "tiered-blob" => {
+                    composite_stores.push(CompositeStore::TieredBlob(store_id));
+                }
+                #[cfg(feature = "enterprise")]
                 "sharded-in-memory" => {
                     composite_stores.push(CompositeStore::ShardedInMemory(store_id));
                 }
@@ -278,10 +325,10 @@ impl Stores {
         #[cfg(feature = "enterprise")]
         for composite_store in composite_stores {
             match composite_store {
-                #[cfg(any(feature = "postgres", feature = "mysql"))]
-                CompositeStore::SQLReadReplica(id) => {
+                #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+                CompositeStore::ReadReplica(id) => {
                     let prefix = ("store", id.as_str());
-                    if let Some(db) = crate::backend::composite::read_replica::SQLReadReplica::open(
+                    if let Some(db) = crate::backend::composite::read_replica::ReadReplica::open(
                         config,
                         prefix,
                         self,
@@ -289,7 +336,7 @@ impl Stores {
                     )
                     .await
                     {
-                        let db = Store::SQLReadReplica(db.into());
+                        let db = Store::ReadReplica(db.into());
                         self.stores.insert(id.to_string(), db.clone());
                         self.fts_stores.insert(id.to_string(), db.clone().into());
                         self.blob_stores.insert(
@@ -319,6 +366,25 @@ impl Stores {
                                     "none",
                                 )
                                 .unwrap_or(CompressionAlgo::None),
+                            zstd: None,
+                        };
+                        self.blob_stores.insert(id, store);
+                    }
+                }
+                CompositeStore::TieredBlob(id) => {
+                    let prefix = ("store", id.as_str());
+                    if let Some(db) = crate::backend::composite::tiered_blob::TieredBlob::open(
+                        config, prefix, self,
+                    ) {
+                        let store = BlobStore {
+                            backend: crate::BlobBackend::Tiered(db.into()),
+                            compression: config
+                                .property_or_default::<CompressionAlgo>(
+                                    ("store", id.as_str(), "compression"),
+                                    "none",
+                                )
+                                .unwrap_or(CompressionAlgo::None),
+                            zstd: None,
                         };
                         self.blob_stores.insert(id, store);
                     }
@@ -336,6 +402,13 @@ impl Stores {
                 }
             }
         }
+
+        // Zstd compression settings
+        for (id, store) in self.blob_stores.iter_mut() {
+            if matches!(store.compression, CompressionAlgo::Zstd) {
+                store.zstd = Some(std::sync::Arc::new(ZstdOptions::parse(config, id)));
+            }
+        }
     }
 
     pub async fn parse_in_memory(&mut self, config: &mut Config, is_reload: bool) {
@@ -4,18 +4,39 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, ops::Range, time::Instant};
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+    ops::Range,
+    path::PathBuf,
+    time::Instant,
+};
 
 use trc::{AddContext, StoreEvent};
-use utils::config::utils::ParseValue;
+use utils::config::{Config, utils::ParseValue};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
 
 use crate::{BlobBackend, BlobStore, CompressionAlgo, Store};
 
+pub struct ZstdOptions {
+    pub level: i32,
+    pub min_size: usize,
+    // New blobs are compressed using the first dictionary, the remaining
+    // ones are kept to decompress blobs written with older dictionaries.
+    pub dictionaries: Vec<ZstdDictionary>,
+}
+
+pub struct ZstdDictionary {
+    pub id: u32,
+    pub encoder: EncoderDictionary<'static>,
+    pub decoder: DecoderDictionary<'static>,
+}
+
 impl BlobStore {
     pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
         let read_range = match self.compression {
             CompressionAlgo::None => range.clone(),
-            CompressionAlgo::Lz4 => 0..usize::MAX,
+            CompressionAlgo::Lz4 | CompressionAlgo::Zstd => 0..usize::MAX,
         };
         let start_time = Instant::now();
         let result = match &self.backend {
@@ -30,8 +51,14 @@ impl BlobStore {
                 Store::MySQL(store) => store.get_blob(key, read_range).await,
                 #[cfg(feature = "rocks")]
                 Store::RocksDb(store) => store.get_blob(key, read_range).await,
-                #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-                Store::SQLReadReplica(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "cassandra")]
+                Store::Cassandra(store) => store.get_blob(key, read_range).await,
+                Store::Volatile(store) => store.get_blob(key, read_range).await,
+                #[cfg(all(
+                    feature = "enterprise",
+                    any(feature = "postgres", feature = "mysql", feature = "foundation")
+                ))]
+                Store::ReadReplica(store) => store.get_blob(key, read_range).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
             },
             BlobBackend::Fs(store) => store.get_blob(key, read_range).await,
@@ -41,6 +68,8 @@ impl BlobStore {
             BlobBackend::Azure(store) => store.get_blob(key, read_range).await,
             #[cfg(feature = "enterprise")]
             BlobBackend::Sharded(store) => store.get_blob(key, read_range).await,
+            #[cfg(feature = "enterprise")]
+            BlobBackend::Tiered(store) => store.get_blob(key, read_range).await,
         };
 
         trc::event!(
@@ -52,29 +81,39 @@ impl BlobStore {
                 .map_or(0, |data| data.as_ref().map_or(0, |data| data.len())),
         );
 
-        let decompressed = match self.compression {
-            CompressionAlgo::Lz4 => match result.caused_by(trc::location!())? {
-                Some(data)
-                    if data.last().copied().unwrap_or_default()
-                        == CompressionAlgo::Lz4.marker() =>
-                {
-                    lz4_flex::decompress_size_prepended(
-                        data.get(..data.len() - 1).unwrap_or_default(),
-                    )
-                    .map_err(|err| {
-                        trc::StoreEvent::DecompressError
-                            .reason(err)
-                            .ctx(trc::Key::Key, key)
-                            .ctx(trc::Key::CausedBy, trc::location!())
-                    })?
+        // Blobs are decompressed based on their header or marker, which allows
+        // changing the compression algorithm without having to rewrite existing blobs
+        let mut data = match self.compression {
+            CompressionAlgo::Lz4 | CompressionAlgo::Zstd => {
+                match result.caused_by(trc::location!())? {
+                    Some(data) => data,
+                    None => return Ok(None),
                 }
-                Some(data) => {
-                    trc::event!(Store(StoreEvent::BlobMissingMarker), Key = key,);
-                    data
-                }
-                None => return Ok(None),
-            },
-            _ => return result,
+            }
+            CompressionAlgo::None => return result,
+        };
+        let decompressed = if let Some(frame) = data.strip_prefix(&ZSTD_HEADER[..]) {
+            // Partial reads only decompress the frame up to the end of the range
+            return self.zstd_decompress(frame, range).map(Some).map_err(|err| {
+                trc::StoreEvent::DecompressError
+                    .reason(err)
+                    .ctx(trc::Key::Key, key)
+                    .ctx(trc::Key::CausedBy, trc::location!())
+            });
+        } else if data.starts_with(&STORED_HEADER) {
+            data.drain(..STORED_HEADER.len());
+            data
+        } else if data.last().copied().unwrap_or_default() == CompressionAlgo::Lz4.marker() {
+            lz4_flex::decompress_size_prepended(data.get(..data.len() - 1).unwrap_or_default())
+                .map_err(|err| {
+                    trc::StoreEvent::DecompressError
+                        .reason(err)
+                        .ctx(trc::Key::Key, key)
+                        .ctx(trc::Key::CausedBy, trc::location!())
+                })?
+        } else {
+            trc::event!(Store(StoreEvent::BlobMissingMarker), Key = key,);
+            data
         };
 
         if range.end > decompressed.len() {
@@ -97,6 +136,24 @@ impl BlobStore {
                 compressed.push(CompressionAlgo::Lz4.marker());
                 compressed.into()
             }
+            CompressionAlgo::Zstd
+                if data.len() >= self.zstd.as_ref().map_or(0, |zstd| zstd.min_size) =>
+            {
+                self.zstd_compress(data)
+                    .map_err(|err| {
+                        trc::StoreEvent::UnexpectedError
+                            .reason(err)
+                            .ctx(trc::Key::Key, key)
+                            .ctx(trc::Key::CausedBy, trc::location!())
+                    })?
+                    .into()
+            }
+            CompressionAlgo::Zstd => {
+                let mut stored = Vec::with_capacity(STORED_HEADER.len() + data.len());
+                stored.extend_from_slice(&STORED_HEADER);
+                stored.extend_from_slice(data);
+                stored.into()
+            }
         };
 
         let start_time = Instant::now();
@@ -112,8 +169,14 @@ impl BlobStore {
                 Store::MySQL(store) => store.put_blob(key, data.as_ref()).await,
                 #[cfg(feature = "rocks")]
                 Store::RocksDb(store) => store.put_blob(key, data.as_ref()).await,
-                #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-                Store::SQLReadReplica(store) => store.put_blob(key, data.as_ref()).await,
+                #[cfg(feature = "cassandra")]
+                Store::Cassandra(store) => store.put_blob(key, data.as_ref()).await,
+                Store::Volatile(store) => store.put_blob(key, data.as_ref()).await,
+                #[cfg(all(
+                    feature = "enterprise",
+                    any(feature = "postgres", feature = "mysql", feature = "foundation")
+                ))]
+                Store::ReadReplica(store) => store.put_blob(key, data.as_ref()).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
             },
             BlobBackend::Fs(store) => store.put_blob(key, data.as_ref()).await,
@@ -123,6 +186,8 @@ impl BlobStore {
             BlobBackend::Azure(store) => store.put_blob(key, data.as_ref()).await,
             #[cfg(feature = "enterprise")]
             BlobBackend::Sharded(store) => store.put_blob(key, data.as_ref()).await,
+            #[cfg(feature = "enterprise")]
+            BlobBackend::Tiered(store) => store.put_blob(key, data.as_ref()).await,
         }
         .caused_by(trc::location!());
 
@@ -150,8 +215,14 @@ impl BlobStore {
                 Store::MySQL(store) => store.delete_blob(key).await,
                 #[cfg(feature = "rocks")]
                 Store::RocksDb(store) => store.delete_blob(key).await,
-                #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-                Store::SQLReadReplica(store) => store.delete_blob(key).await,
+                #[cfg(feature = "cassandra")]
+                Store::Cassandra(store) => store.delete_blob(key).await,
+                Store::Volatile(store) => store.delete_blob(key).await,
+                #[cfg(all(
+                    feature = "enterprise",
+                    any(feature = "postgres", feature = "mysql", feature = "foundation")
+                ))]
+                Store::ReadReplica(store) => store.delete_blob(key).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
             },
             BlobBackend::Fs(store) => store.delete_blob(key).await,
@@ -161,6 +232,8 @@ impl BlobStore {
             BlobBackend::Azure(store) => store.delete_blob(key).await,
             #[cfg(feature = "enterprise")]
             BlobBackend::Sharded(store) => store.delete_blob(key).await,
+            #[cfg(feature = "enterprise")]
+            BlobBackend::Tiered(store) => store.delete_blob(key).await,
         }
         .caused_by(trc::location!());
 
@@ -177,17 +250,163 @@ impl BlobStore {
         Self {
             backend: self.backend,
             compression,
+            zstd: self.zstd,
+        }
+    }
+
+    fn zstd_compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(ZSTD_HEADER.len() + data.len() / 2);
+        output.extend_from_slice(&ZSTD_HEADER);
+        let mut encoder = match self
+            .zstd
+            .as_ref()
+            .and_then(|zstd| zstd.dictionaries.first())
+        {
+            Some(dictionary) => {
+                zstd::stream::Encoder::with_prepared_dictionary(output, &dictionary.encoder)?
+            }
+            None => zstd::stream::Encoder::new(
+                output,
+                self.zstd
+                    .as_ref()
+                    .map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |zstd| zstd.level),
+            )?,
+        };
+        encoder.include_contentsize(true)?;
+        encoder.set_pledged_src_size(Some(data.len() as u64))?;
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    fn zstd_decompress(&self, data: &[u8], range: Range<usize>) -> std::io::Result<Vec<u8>> {
+        let dictionary = zstd::zstd_safe::get_dict_id_from_frame(data).and_then(|id| {
+            self.zstd.as_ref().and_then(|zstd| {
+                zstd.dictionaries
+                    .iter()
+                    .find(|dictionary| dictionary.id == id.get())
+            })
+        });
+        let mut decoder = match dictionary {
+            Some(dictionary) => {
+                zstd::stream::Decoder::with_prepared_dictionary(data, &dictionary.decoder)?
+            }
+            None => zstd::stream::Decoder::new(data)?,
+        };
+
+        if range.start > 0 {
+            std::io::copy(
+                &mut (&mut decoder).take(range.start as u64),
+                &mut std::io::sink(),
+            )?;
+        }
+        let mut output = Vec::with_capacity(if range.end != usize::MAX {
+            range.end.saturating_sub(range.start)
+        } else {
+            data.len() * 3
+        });
+        decoder
+            .take(range.end.saturating_sub(range.start) as u64)
+            .read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    // Trains a dictionary using a sample of the blobs in the store, the resulting
+    // dictionary can be configured with the "store.<id>.zstd.dictionary" setting.
+    pub async fn train_dictionary(
+        &self,
+        store: &Store,
+        max_samples: usize,
+        max_size: usize,
+    ) -> trc::Result<Vec<u8>> {
+        let mut samples = Vec::with_capacity(max_samples);
+        for hash in store
+            .blob_hashes(max_samples)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if let Some(sample) = self
+                .get_blob(hash.as_ref(), 0..DICTIONARY_SAMPLE_SIZE)
+                .await
+                .caused_by(trc::location!())?
+            {
+                samples.push(sample);
+            }
+        }
+
+        zstd::dict::from_samples(&samples, max_size).map_err(|err| {
+            trc::StoreEvent::UnexpectedError
+                .reason(err)
+                .details("Failed to train dictionary")
+                .caused_by(trc::location!())
+        })
+    }
+}
+
+impl ZstdOptions {
+    pub fn parse(config: &mut Config, id: &str) -> Self {
+        let level = config
+            .property_or_default::<i32>(("store", id, "zstd.level"), "3")
+            .unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+        let mut dictionaries = Vec::new();
+        for path in config
+            .values(("store", id, "zstd.dictionary"))
+            .map(|(_, path)| PathBuf::from(path))
+            .collect::<Vec<_>>()
+        {
+            match std::fs::read(&path) {
+                Ok(data) => {
+                    if let Some(dict_id) = zstd::zstd_safe::get_dict_id_from_dict(&data) {
+                        dictionaries.push(ZstdDictionary {
+                            id: dict_id.get(),
+                            encoder: EncoderDictionary::copy(&data, level),
+                            decoder: DecoderDictionary::copy(&data),
+                        });
+                    } else {
+                        config.new_build_error(
+                            ("store", id, "zstd.dictionary"),
+                            format!("File {} is not a valid zstd dictionary", path.display()),
+                        );
+                    }
+                }
+                Err(err) => {
+                    config.new_build_error(
+                        ("store", id, "zstd.dictionary"),
+                        format!("Failed to read dictionary {}: {err}", path.display()),
+                    );
+                }
+            }
+        }
+
+        ZstdOptions {
+            level,
+            min_size: config
+                .property_or_default(("store", id, "zstd.min-size"), "1024")
+                .unwrap_or(1024),
+            dictionaries,
         }
     }
 }
 
 const MAGIC_MARKER: u8 = 0xa0;
 
+// Blobs written with zstd start with a versioned header instead of ending with a
+// marker byte, a single trailing byte is too easily matched by the last byte of
+// a blob that was stored before compression was enabled.
+const BLOB_FORMAT_VERSION: u8 = 1;
+const ZSTD_HEADER: [u8; 4] = [
+    MAGIC_MARKER,
+    b'B',
+    BLOB_FORMAT_VERSION,
+    CompressionAlgo::Zstd.marker(),
+];
+const STORED_HEADER: [u8; 4] = [MAGIC_MARKER, b'B', BLOB_FORMAT_VERSION, MAGIC_MARKER];
+const DICTIONARY_SAMPLE_SIZE: usize = 16 * 1024;
+
 impl CompressionAlgo {
-    pub fn marker(&self) -> u8 {
+    pub const fn marker(&self) -> u8 {
         match self {
             CompressionAlgo::Lz4 => MAGIC_MARKER | 0x01,
-            //CompressionAlgo::Zstd => MAGIC_MARKER | 0x02,
+            CompressionAlgo::Zstd => MAGIC_MARKER | 0x02,
             CompressionAlgo::None => 0,
         }
     }
@@ -197,7 +416,7 @@ impl ParseValue for CompressionAlgo {
     fn parse_value(value: &str) -> Result<Self, String> {
         match value {
             "lz4" => Ok(CompressionAlgo::Lz4),
-            //"zstd" => Ok(CompressionAlgo::Zstd),
+            "zstd" => Ok(CompressionAlgo::Zstd),
             "none" | "false" | "disable" | "disabled" => Ok(CompressionAlgo::None),
             algo => Err(format!("Invalid compression algorithm: {algo}",)),
         }
@@ -34,12 +34,25 @@ impl FtsStore {
         account_id: u32,
         collection: impl Into<u8>,
         filters: Vec<FtsFilter<T>>,
+    ) -> trc::Result<RoaringBitmap> {
+        self.query_masked(account_id, collection, filters, None)
+            .await
+    }
+
+    /// Runs the query only over the documents in `mask`, which is used to
+    /// restrict searches to the messages a shared account has access to.
+    pub async fn query_masked<T: Into<u8> + Display + Clone + std::fmt::Debug>(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+        filters: Vec<FtsFilter<T>>,
+        mask: Option<&RoaringBitmap>,
     ) -> trc::Result<RoaringBitmap> {
         match self {
-            FtsStore::Store(store) => store.fts_query(account_id, collection, filters).await,
+            FtsStore::Store(store) => store.fts_query(account_id, collection, filters, mask).await,
             #[cfg(feature = "elastic")]
             FtsStore::ElasticSearch(store) => {
-                store.fts_query(account_id, collection, filters).await
+                store.fts_query(account_id, collection, filters, mask).await
             }
         }
         .caused_by(trc::location!())
@@ -27,8 +27,14 @@ impl Store {
             Self::MySQL(_) => "mysql",
             #[cfg(feature = "rocks")]
             Self::RocksDb(_) => "rocksdb",
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(_) => "read_replica",
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(_) => "cassandra",
+            Self::Volatile(_) => "volatile",
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(_) => "read_replica",
             Self::None => "none",
         }
     }
@@ -36,7 +36,7 @@ pub enum Msg {
 
 #[allow(unused_variables)]
 impl PubSubStore {
-    pub async fn publish(&self, topic: &'static str, message: Vec<u8>) -> trc::Result<()> {
+    pub async fn publish(&self, topic: &str, message: Vec<u8>) -> trc::Result<()> {
         match self {
             #[cfg(feature = "redis")]
             PubSubStore::Redis(store) => store.publish(topic, message).await,
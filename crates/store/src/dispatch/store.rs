@@ -53,8 +53,14 @@ impl Store {
             Self::MySQL(store) => store.get_value(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_value(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_value(key).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.get_value(key).await,
+            Self::Volatile(store) => store.get_value(key).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.get_value(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -75,8 +81,14 @@ impl Store {
             Self::MySQL(store) => store.get_bitmap(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_bitmap(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_bitmap(key).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.get_bitmap(key).await,
+            Self::Volatile(store) => store.get_bitmap(key).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.get_bitmap(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -121,8 +133,14 @@ impl Store {
             Self::MySQL(store) => store.iterate(params, cb).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.iterate(params, cb).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.iterate(params, cb).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.iterate(params, cb).await,
+            Self::Volatile(store) => store.iterate(params, cb).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.iterate(params, cb).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!());
@@ -150,8 +168,14 @@ impl Store {
             Self::MySQL(store) => store.get_counter(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_counter(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_counter(key).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.get_counter(key).await,
+            Self::Volatile(store) => store.get_counter(key).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.get_counter(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -199,8 +223,14 @@ impl Store {
             Self::MySQL(store) => store.write(batch).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.write(batch).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.write(batch).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.write(batch).await,
+            Self::Volatile(store) => store.write(batch).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.write(batch).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         };
 
@@ -263,6 +293,39 @@ impl Store {
         )
         .await
         .caused_by(trc::location!())?;
+        self.delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Tracking {
+                id: 0,
+                expires: 0,
+            })),
+            ValueKey::from(ValueClass::Report(ReportClass::Tracking {
+                id: u64::MAX,
+                expires: now,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
+        self.delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Impersonation {
+                id: 0,
+                expires: 0,
+            })),
+            ValueKey::from(ValueClass::Report(ReportClass::Impersonation {
+                id: u64::MAX,
+                expires: now,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
+        self.delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Audit { id: 0, expires: 0 })),
+            ValueKey::from(ValueClass::Report(ReportClass::Audit {
+                id: u64::MAX,
+                expires: now,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
 
         match self {
             #[cfg(feature = "sqlite")]
@@ -275,8 +338,14 @@ impl Store {
             Self::MySQL(store) => store.purge_store().await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.purge_store().await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.purge_store().await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.purge_store().await,
+            Self::Volatile(store) => store.purge_store().await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.purge_store().await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -294,8 +363,14 @@ impl Store {
             Self::MySQL(store) => store.delete_range(from, to).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.delete_range(from, to).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.delete_range(from, to).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.delete_range(from, to).await,
+            Self::Volatile(store) => store.delete_range(from, to).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.delete_range(from, to).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -450,8 +525,14 @@ impl Store {
             Self::MySQL(store) => store.get_blob(key, range).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_blob(key, range).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_blob(key, range).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.get_blob(key, range).await,
+            Self::Volatile(store) => store.get_blob(key, range).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.get_blob(key, range).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -469,8 +550,14 @@ impl Store {
             Self::MySQL(store) => store.put_blob(key, data).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.put_blob(key, data).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.put_blob(key, data).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.put_blob(key, data).await,
+            Self::Volatile(store) => store.put_blob(key, data).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.put_blob(key, data).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -488,8 +575,14 @@ impl Store {
             Self::MySQL(store) => store.delete_blob(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.delete_blob(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.delete_blob(key).await,
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(store) => store.delete_blob(key).await,
+            Self::Volatile(store) => store.delete_blob(key).await,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(store) => store.delete_blob(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         }
         .caused_by(trc::location!())
@@ -52,8 +52,12 @@ impl Store {
         account_id: u32,
         collection: impl Into<u8>,
         filters: Vec<FtsFilter<T>>,
+        mask: Option<&RoaringBitmap>,
     ) -> trc::Result<RoaringBitmap> {
         let collection = collection.into();
+        if mask.is_some_and(|mask| mask.is_empty()) {
+            return Ok(RoaringBitmap::new());
+        }
 
         // Tokenize text
         let mut tokenized_filters = Vec::with_capacity(filters.len());
@@ -135,6 +139,7 @@ impl Store {
                         &tokens,
                         &token_count,
                         &mut token_cache,
+                        mask,
                         true,
                     )
                     .await?
@@ -153,6 +158,7 @@ impl Store {
                                 ],
                                 &token_count,
                                 &mut token_cache,
+                                mask,
                                 false,
                             )
                             .await?
@@ -184,6 +190,7 @@ impl Store {
                         &[(token, TokenType::word(field))],
                         &token_count,
                         &mut token_cache,
+                        mask,
                         false,
                     )
                     .await?
@@ -204,12 +211,16 @@ impl Store {
                 }
             };
 
-            // Only fetch not mask if we need it
+            // Only fetch not mask if we need it, when the query is restricted
+            // to a subset of documents negations are relative to that subset
             if matches!(state.op, FtsTokenized::Not) && !not_fetch {
-                not_mask = self
-                    .get_bitmap(BitmapKey::document_ids(account_id, collection))
-                    .await?
-                    .unwrap_or_else(RoaringBitmap::new);
+                not_mask = if let Some(mask) = mask {
+                    mask.clone()
+                } else {
+                    self.get_bitmap(BitmapKey::document_ids(account_id, collection))
+                        .await?
+                        .unwrap_or_else(RoaringBitmap::new)
+                };
                 not_fetch = true;
             }
 
@@ -259,7 +270,12 @@ impl Store {
             }
         }
 
-        Ok(state.bm.unwrap_or_default())
+        let mut results = state.bm.unwrap_or_default();
+        if let Some(mask) = mask {
+            results &= mask;
+        }
+
+        Ok(results)
     }
 
     async fn get_postings(
@@ -269,8 +285,13 @@ impl Store {
         tokens: &[(BitmapHash, u8)],
         token_count: &AHashMap<BitmapHash, u32>,
         token_cache: &mut AHashMap<BitmapHash, AHashMap<u32, SerializedPostings<Vec<u8>>>>,
+        mask: Option<&RoaringBitmap>,
         is_intersect: bool,
     ) -> trc::Result<Option<RoaringBitmap>> {
+        // Only scan the postings of documents within the mask
+        let (from_document_id, to_document_id) = mask
+            .and_then(|mask| mask.min().zip(mask.max()))
+            .unwrap_or((0, u32::MAX));
         let mut result_bm = RoaringBitmap::new();
         let mut position_candidates = AHashMap::new();
         let num_tokens = tokens.len();
@@ -328,13 +349,13 @@ impl Store {
                     ValueKey {
                         account_id,
                         collection,
-                        document_id: 0,
+                        document_id: from_document_id,
                         class: ValueClass::FtsIndex(*token),
                     },
                     ValueKey {
                         account_id,
                         collection,
-                        document_id: u32::MAX,
+                        document_id: to_document_id,
                         class: ValueClass::FtsIndex(*token),
                     },
                 ),
@@ -345,6 +366,9 @@ impl Store {
 
                     // Make sure this document contain the field
                     let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                    if mask.is_some_and(|mask| !mask.contains(document_id)) {
+                        return Ok(true);
+                    }
                     let postings = SerializedPostings::new(value);
                     if postings.has_field(*field) {
                         if is_intersect {
@@ -23,6 +23,7 @@ pub use xxhash_rust;
 
 use ahash::AHashMap;
 use backend::{fs::FsStore, http::HttpStore, memory::StaticMemoryStore};
+use dispatch::blob::ZstdOptions;
 use utils::config::cron::SimpleCron;
 use write::{BitmapClass, ValueClass};
 
@@ -47,6 +48,11 @@ pub(crate) const WITH_SUBSPACE: u32 = 1;
 pub trait Key: Sync + Send + Clone {
     fn serialize(&self, flags: u32) -> Vec<u8>;
     fn subspace(&self) -> u8;
+
+    /// Returns the account the key belongs to, if any.
+    fn account_id(&self) -> Option<u32> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -173,8 +179,14 @@ pub enum Store {
     MySQL(Arc<backend::mysql::MysqlStore>),
     #[cfg(feature = "rocks")]
     RocksDb(Arc<backend::rocksdb::RocksDbStore>),
-    #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-    SQLReadReplica(Arc<backend::composite::read_replica::SQLReadReplica>),
+    #[cfg(feature = "cassandra")]
+    Cassandra(Arc<backend::cassandra::CassandraStore>),
+    Volatile(Arc<backend::volatile::VolatileStore>),
+    #[cfg(all(
+        feature = "enterprise",
+        any(feature = "postgres", feature = "mysql", feature = "foundation")
+    ))]
+    ReadReplica(Arc<backend::composite::read_replica::ReadReplica>),
     #[default]
     None,
 }
@@ -183,12 +195,14 @@ pub enum Store {
 pub struct BlobStore {
     pub backend: BlobBackend,
     pub compression: CompressionAlgo,
+    pub zstd: Option<Arc<ZstdOptions>>,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum CompressionAlgo {
     None,
     Lz4,
+    Zstd,
 }
 
 #[derive(Clone)]
@@ -201,6 +215,8 @@ pub enum BlobBackend {
     Azure(Arc<backend::azure::AzureStore>),
     #[cfg(feature = "enterprise")]
     Sharded(Arc<backend::composite::sharded_blob::ShardedBlob>),
+    #[cfg(feature = "enterprise")]
+    Tiered(Arc<backend::composite::tiered_blob::TieredBlob>),
 }
 
 #[derive(Clone)]
@@ -270,11 +286,25 @@ impl From<backend::rocksdb::RocksDbStore> for Store {
     }
 }
 
+#[cfg(feature = "cassandra")]
+impl From<backend::cassandra::CassandraStore> for Store {
+    fn from(store: backend::cassandra::CassandraStore) -> Self {
+        Self::Cassandra(Arc::new(store))
+    }
+}
+
+impl From<backend::volatile::VolatileStore> for Store {
+    fn from(store: backend::volatile::VolatileStore) -> Self {
+        Self::Volatile(Arc::new(store))
+    }
+}
+
 impl From<FsStore> for BlobStore {
     fn from(store: FsStore) -> Self {
         BlobStore {
             backend: BlobBackend::Fs(Arc::new(store)),
             compression: CompressionAlgo::None,
+            zstd: None,
         }
     }
 }
@@ -285,6 +315,7 @@ impl From<backend::s3::S3Store> for BlobStore {
         BlobStore {
             backend: BlobBackend::S3(Arc::new(store)),
             compression: CompressionAlgo::None,
+            zstd: None,
         }
     }
 }
@@ -295,6 +326,7 @@ impl From<backend::azure::AzureStore> for BlobStore {
         BlobStore {
             backend: BlobBackend::Azure(Arc::new(store)),
             compression: CompressionAlgo::None,
+            zstd: None,
         }
     }
 }
@@ -324,6 +356,7 @@ impl From<Store> for BlobStore {
         BlobStore {
             backend: BlobBackend::Store(store),
             compression: CompressionAlgo::None,
+            zstd: None,
         }
     }
 }
@@ -339,6 +372,7 @@ impl Default for BlobStore {
         Self {
             backend: BlobBackend::Store(Store::None),
             compression: CompressionAlgo::None,
+            zstd: None,
         }
     }
 }
@@ -698,6 +732,10 @@ impl Store {
         matches!(self, Self::None)
     }
 
+    pub fn is_volatile(&self) -> bool {
+        matches!(self, Self::Volatile(_))
+    }
+
     pub fn is_sql(&self) -> bool {
         match self {
             #[cfg(feature = "sqlite")]
@@ -706,8 +744,11 @@ impl Store {
             Store::PostgreSQL(_) => true,
             #[cfg(feature = "mysql")]
             Store::MySQL(_) => true,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Store::SQLReadReplica(_) => true,
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Store::ReadReplica(store) => store.is_sql(),
             _ => false,
         }
     }
@@ -725,8 +766,8 @@ impl Store {
     #[cfg(feature = "enterprise")]
     pub fn is_enterprise_store(&self) -> bool {
         match self {
-            #[cfg(any(feature = "postgres", feature = "mysql"))]
-            Store::SQLReadReplica(_) => true,
+            #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
+            Store::ReadReplica(_) => true,
             _ => false,
         }
     }
@@ -750,8 +791,14 @@ impl std::fmt::Debug for Store {
             Self::MySQL(_) => f.debug_tuple("MySQL").finish(),
             #[cfg(feature = "rocks")]
             Self::RocksDb(_) => f.debug_tuple("RocksDb").finish(),
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(_) => f.debug_tuple("SQLReadReplica").finish(),
+            #[cfg(feature = "cassandra")]
+            Self::Cassandra(_) => f.debug_tuple("Cassandra").finish(),
+            Self::Volatile(_) => f.debug_tuple("Volatile").finish(),
+            #[cfg(all(
+                feature = "enterprise",
+                any(feature = "postgres", feature = "mysql", feature = "foundation")
+            ))]
+            Self::ReadReplica(_) => f.debug_tuple("ReadReplica").finish(),
             Self::None => f.debug_tuple("None").finish(),
         }
     }
@@ -783,11 +830,15 @@ impl Stores {
     pub fn disable_enterprise_only(&mut self) {
         #[cfg(feature = "enterprise")]
         {
-            #[cfg(any(feature = "postgres", feature = "mysql"))]
+            #[cfg(any(feature = "postgres", feature = "mysql", feature = "foundation"))]
             self.stores
-                .retain(|_, store| !matches!(store, Store::SQLReadReplica(_)));
-            self.blob_stores
-                .retain(|_, store| !matches!(store.backend, BlobBackend::Sharded(_)));
+                .retain(|_, store| !matches!(store, Store::ReadReplica(_)));
+            self.blob_stores.retain(|_, store| {
+                !matches!(
+                    store.backend,
+                    BlobBackend::Sharded(_) | BlobBackend::Tiered(_)
+                )
+            });
         }
     }
 }
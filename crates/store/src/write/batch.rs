@@ -416,6 +416,14 @@ impl BatchBuilder {
         self.current_account_id
     }
 
+    pub fn last_collection(&self) -> Option<u8> {
+        self.current_collection
+    }
+
+    pub fn last_document_id(&self) -> Option<u32> {
+        self.current_document_id
+    }
+
     pub fn commit_points(&mut self) -> CommitPointIterator {
         self.serialize_changes();
         CommitPointIterator {
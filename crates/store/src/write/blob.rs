@@ -15,6 +15,13 @@ use crate::{
 
 use super::{BlobOp, Operation, ValueClass, ValueOp, key::DeserializeBigEndian, now};
 
+// Unreferenced blobs are only deleted once this period has elapsed since their
+// last commit, which prevents deleting a blob that is being deduplicated.
+#[cfg(not(feature = "test_mode"))]
+pub const BLOB_PURGE_GRACE_PERIOD: u64 = 3600;
+#[cfg(feature = "test_mode")]
+pub const BLOB_PURGE_GRACE_PERIOD: u64 = 0;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct BlobQuota {
     pub bytes: usize,
@@ -36,6 +43,100 @@ impl Store {
         .caused_by(trc::location!())
     }
 
+    // Returns the time the blob was last committed or deduplicated,
+    // blobs committed by older versions have a timestamp of zero.
+    pub async fn blob_committed_at(
+        &self,
+        hash: impl AsRef<BlobHash> + Sync + Send,
+    ) -> trc::Result<Option<u64>> {
+        self.get_value::<CommittedAt>(ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Commit {
+                hash: hash.as_ref().clone(),
+            }),
+        })
+        .await
+        .map(|value| value.map(|value| value.0))
+        .caused_by(trc::location!())
+    }
+
+    // Number of documents and queued messages linked to a blob
+    pub async fn blob_references(
+        &self,
+        hash: impl AsRef<BlobHash> + Sync + Send,
+    ) -> trc::Result<u64> {
+        let hash = hash.as_ref();
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: u8::MAX,
+            document_id: u32::MAX,
+            class: ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+        };
+        let mut references = 0;
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending().no_values(),
+            |key, _| {
+                if key.deserialize_be_u32(key.len() - U32_LEN)? != u32::MAX {
+                    references += 1;
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(references)
+    }
+
+    // Returns up to `max_results` hashes of committed blobs
+    pub async fn blob_hashes(&self, max_results: usize) -> trc::Result<Vec<BlobHash>> {
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Link {
+                hash: BlobHash::default(),
+            }),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: u8::MAX,
+            document_id: u32::MAX,
+            class: ValueClass::Blob(BlobOp::Link {
+                hash: BlobHash::new_max(),
+            }),
+        };
+        let mut hashes = Vec::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending().no_values(),
+            |key, _| {
+                if key.deserialize_be_u32(key.len() - U32_LEN)? == u32::MAX
+                    && key.get(key.len() - U32_LEN - 1) == Some(&0)
+                {
+                    hashes.push(
+                        BlobHash::try_from_hash_slice(key.get(0..BLOB_HASH_LEN).ok_or_else(
+                            || trc::Error::corrupted_key(key, None, trc::location!()),
+                        )?)
+                        .unwrap(),
+                    );
+                }
+                Ok(hashes.len() < max_results)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(hashes)
+    }
+
     pub async fn blob_quota(&self, account_id: u32) -> trc::Result<BlobQuota> {
         let from_key = ValueKey {
             account_id,
@@ -176,9 +277,10 @@ impl Store {
             }),
         };
         let mut last_hash = BlobHash::default();
+        let mut unlinked = Vec::new();
         self.iterate(
-            IterateParams::new(from_key, to_key).ascending().no_values(),
-            |key, _| {
+            IterateParams::new(from_key, to_key).ascending(),
+            |key, value| {
                 let hash = BlobHash::try_from_hash_slice(
                     key.get(0..BLOB_HASH_LEN)
                         .ok_or_else(|| trc::Error::corrupted_key(key, None, trc::location!()))?,
@@ -191,8 +293,11 @@ impl Store {
                         last_hash = hash;
                     }
                 } else if last_hash != hash && !active_hashes.contains(&hash) {
-                    // Unlinked or expired blob, delete.
-                    delete_keys.push((0, BlobOp::Commit { hash }));
+                    // Unlinked or expired blob, delete once the grace period has elapsed
+                    let committed_at = CommittedAt::deserialize(value)?.0;
+                    if committed_at + BLOB_PURGE_GRACE_PERIOD <= now {
+                        unlinked.push((hash, committed_at));
+                    }
                 }
 
                 Ok(true)
@@ -201,13 +306,19 @@ impl Store {
         .await
         .caused_by(trc::location!())?;
 
-        // Delete expired or unlinked blobs
-        for (_, op) in &delete_keys {
-            if let BlobOp::Commit { hash } = op {
+        // Delete unlinked blobs that were not deduplicated since they were scanned
+        for (hash, committed_at) in unlinked {
+            if self
+                .blob_committed_at(&hash)
+                .await
+                .caused_by(trc::location!())?
+                .is_some_and(|value| value == committed_at)
+            {
                 blob_store
                     .delete_blob(hash.as_ref())
                     .await
                     .caused_by(trc::location!())?;
+                delete_keys.push((0, BlobOp::Commit { hash }));
             }
         }
 
@@ -317,3 +428,15 @@ impl Store {
         Ok(())
     }
 }
+
+struct CommittedAt(u64);
+
+impl Deserialize for CommittedAt {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        Ok(CommittedAt(if bytes.len() == U64_LEN {
+            bytes.deserialize_be_u64(0)?
+        } else {
+            0
+        }))
+    }
+}
@@ -200,6 +200,10 @@ impl Key for IndexKeyPrefix {
     fn subspace(&self) -> u8 {
         SUBSPACE_INDEXES
     }
+
+    fn account_id(&self) -> Option<u32> {
+        Some(self.account_id)
+    }
 }
 
 impl IndexKeyPrefix {
@@ -226,6 +230,10 @@ impl Key for LogKey {
         .write(self.change_id)
         .finalize()
     }
+
+    fn account_id(&self) -> Option<u32> {
+        Some(self.account_id)
+    }
 }
 
 impl<T: AsRef<ValueClass> + Sync + Send + Clone> Key for ValueKey<T> {
@@ -238,9 +246,26 @@ impl<T: AsRef<ValueClass> + Sync + Send + Clone> Key for ValueKey<T> {
             .as_ref()
             .serialize(self.account_id, self.collection, self.document_id, flags)
     }
+
+    fn account_id(&self) -> Option<u32> {
+        self.class
+            .as_ref()
+            .is_account_scoped()
+            .then_some(self.account_id)
+    }
 }
 
 impl ValueClass {
+    pub fn is_account_scoped(&self) -> bool {
+        matches!(
+            self,
+            ValueClass::Property(_)
+                | ValueClass::FtsIndex(_)
+                | ValueClass::DocumentId
+                | ValueClass::ChangeId
+        )
+    }
+
     pub fn serialize(
         &self,
         account_id: u32,
@@ -405,6 +430,9 @@ impl ValueClass {
                     .write(event.seq_id),
                 QueueClass::QuotaCount(key) => serializer.write(0u8).write(key.as_slice()),
                 QueueClass::QuotaSize(key) => serializer.write(1u8).write(key.as_slice()),
+                QueueClass::Quarantine(queue_id) => serializer.write(3u8).write(*queue_id),
+                QueueClass::SandboxPending(queue_id) => serializer.write(4u8).write(*queue_id),
+                QueueClass::Suppression(address) => serializer.write(5u8).write(address.as_slice()),
             },
             ValueClass::Report(report) => match report {
                 ReportClass::Tls { id, expires } => {
@@ -416,6 +444,15 @@ impl ValueClass {
                 ReportClass::Arf { id, expires } => {
                     serializer.write(2u8).write(*expires).write(*id)
                 }
+                ReportClass::Tracking { id, expires } => {
+                    serializer.write(3u8).write(*expires).write(*id)
+                }
+                ReportClass::Impersonation { id, expires } => {
+                    serializer.write(4u8).write(*expires).write(*id)
+                }
+                ReportClass::Audit { id, expires } => {
+                    serializer.write(5u8).write(*expires).write(*id)
+                }
             },
             ValueClass::Telemetry(telemetry) => match telemetry {
                 TelemetryClass::Span { span_id } => serializer.write(*span_id),
@@ -461,6 +498,10 @@ impl<T: AsRef<[u8]> + Sync + Send + Clone> Key for IndexKey<T> {
         .write(self.document_id)
         .finalize()
     }
+
+    fn account_id(&self) -> Option<u32> {
+        Some(self.account_id)
+    }
 }
 
 impl<T: AsRef<BitmapClass> + Sync + Send + Clone> Key for BitmapKey<T> {
@@ -473,6 +514,10 @@ impl<T: AsRef<BitmapClass> + Sync + Send + Clone> Key for BitmapKey<T> {
             .as_ref()
             .serialize(self.account_id, self.collection, self.document_id, flags)
     }
+
+    fn account_id(&self) -> Option<u32> {
+        Some(self.account_id)
+    }
 }
 
 impl BitmapClass {
@@ -615,6 +660,8 @@ impl ValueClass {
                     event.domain.len() + (U64_LEN * 3) + 1
                 }
                 QueueClass::QuotaCount(v) | QueueClass::QuotaSize(v) => v.len(),
+                QueueClass::Quarantine(_) | QueueClass::SandboxPending(_) => U64_LEN + 1,
+                QueueClass::Suppression(address) => address.len() + 1,
             },
             ValueClass::Report(_) => U64_LEN * 2 + 1,
             ValueClass::Telemetry(telemetry) => match telemetry {
@@ -661,7 +708,10 @@ impl ValueClass {
                 QueueClass::DmarcReportHeader(_)
                 | QueueClass::TlsReportHeader(_)
                 | QueueClass::DmarcReportEvent(_)
-                | QueueClass::TlsReportEvent(_) => SUBSPACE_REPORT_OUT,
+                | QueueClass::TlsReportEvent(_)
+                | QueueClass::Quarantine(_)
+                | QueueClass::SandboxPending(_)
+                | QueueClass::Suppression(_) => SUBSPACE_REPORT_OUT,
                 QueueClass::QuotaCount(_) | QueueClass::QuotaSize(_) => SUBSPACE_QUOTA,
             },
             ValueClass::Report(_) => SUBSPACE_REPORT_IN,
@@ -248,6 +248,9 @@ impl ImapEvent {
             ImapEvent::ConnectionStart => "IMAP connection started",
             ImapEvent::ConnectionEnd => "IMAP connection ended",
             ImapEvent::GetQuota => "IMAP GETQUOTA command",
+            ImapEvent::Compress => "IMAP COMPRESS command",
+            ImapEvent::GetMetadata => "IMAP GETMETADATA command",
+            ImapEvent::XApplePushService => "IMAP XAPPLEPUSHSERVICE command",
         }
     }
 
@@ -290,6 +293,9 @@ impl ImapEvent {
             ImapEvent::ConnectionStart => "IMAP connection started",
             ImapEvent::ConnectionEnd => "IMAP connection ended",
             ImapEvent::GetQuota => "Client requested mailbox quota",
+            ImapEvent::Compress => "Client enabled compression",
+            ImapEvent::GetMetadata => "Client requested mailbox metadata",
+            ImapEvent::XApplePushService => "Client registered a device for push notifications",
         }
     }
 }
@@ -477,6 +483,25 @@ impl SmtpEvent {
             SmtpEvent::RequestTooLarge => "Request too large",
             SmtpEvent::ConnectionStart => "SMTP connection started",
             SmtpEvent::ConnectionEnd => "SMTP connection ended",
+            SmtpEvent::MessageRewritten => "Message rewritten",
+            SmtpEvent::AttachmentDisarmed => "Attachment disarmed",
+            SmtpEvent::VirusFound => "Virus found in message",
+            SmtpEvent::VirusScanError => "Antivirus scan failed",
+            SmtpEvent::VirusScanSkipped => "Antivirus scan skipped",
+            SmtpEvent::DlpMatch => "DLP rule matched",
+            SmtpEvent::DlpBlocked => "Message blocked by DLP rule",
+            SmtpEvent::AttachmentLinked => "Attachment replaced by download link",
+            SmtpEvent::LoopDropped => "Looping message discarded",
+            SmtpEvent::MaintenanceDeferred => "Session deferred during maintenance",
+            SmtpEvent::PartitionDegraded => "Recipient storage partition degraded",
+            SmtpEvent::ProxyConnected => "Transaction proxied to backend",
+            SmtpEvent::ProxyError => "Backend proxy error",
+            SmtpEvent::SandboxSubmitted => "Attachments submitted to sandbox",
+            SmtpEvent::SandboxError => "Sandbox submission failed",
+            SmtpEvent::ListExpansionTooLarge => "Mailing list expansion too large",
+            SmtpEvent::ListExpansionLoop => "Loop detected in list expansion",
+            SmtpEvent::ListExpansionTooDeep => "List expansion too deep",
+            SmtpEvent::SendingLimitExceeded => "Account sending limit exceeded",
         }
     }
 
@@ -600,6 +625,59 @@ impl SmtpEvent {
             SmtpEvent::ConnectionStart => "A new SMTP connection was started",
             SmtpEvent::ConnectionEnd => "The SMTP connection was ended",
             SmtpEvent::StartTlsAlready => "TLS is already active",
+            SmtpEvent::MessageRewritten => {
+                "The message headers or body were modified by a rewrite rule"
+            }
+            SmtpEvent::AttachmentDisarmed => {
+                "An attachment matched an attachment policy rule and was dropped, renamed or replaced"
+            }
+            SmtpEvent::VirusFound => "The antivirus scanner detected malware in the message",
+            SmtpEvent::VirusScanError => "An error occurred while scanning the message for viruses",
+            SmtpEvent::VirusScanSkipped => {
+                "The message exceeds the maximum size accepted by the antivirus scanner"
+            }
+            SmtpEvent::DlpMatch => {
+                "A data loss prevention rule matched the contents of a submitted message"
+            }
+            SmtpEvent::DlpBlocked => {
+                "A submitted message was rejected by a data loss prevention rule"
+            }
+            SmtpEvent::AttachmentLinked => {
+                "A large attachment was uploaded to the blob store and replaced by an expiring download link"
+            }
+            SmtpEvent::LoopDropped => {
+                "A looping bounce or a notification about another notification was accepted and discarded to break the loop"
+            }
+            SmtpEvent::MaintenanceDeferred => {
+                "The connection was rejected with a temporary error because the server is in a maintenance window."
+            }
+            SmtpEvent::PartitionDegraded => {
+                "The recipient's storage partition is degraded and the recipient was temporarily rejected"
+            }
+            SmtpEvent::ProxyConnected => {
+                "The SMTP transaction is being proxied to a backend server"
+            }
+            SmtpEvent::ProxyError => {
+                "An error occurred while proxying the SMTP transaction to a backend server"
+            }
+            SmtpEvent::SandboxSubmitted => {
+                "Suspicious attachments were submitted to the sandbox for detonation"
+            }
+            SmtpEvent::SandboxError => {
+                "An error occurred while submitting attachments to the sandbox"
+            }
+            SmtpEvent::ListExpansionTooLarge => {
+                "The recipient is a list with more members than allowed for a single recipient"
+            }
+            SmtpEvent::ListExpansionLoop => {
+                "The recipient is a list that contains itself, directly or through nested lists"
+            }
+            SmtpEvent::ListExpansionTooDeep => {
+                "The recipient is a list with more levels of nested lists than allowed"
+            }
+            SmtpEvent::SendingLimitExceeded => {
+                "The authenticated account exceeded its hourly or daily sending limit"
+            }
         }
     }
 }
@@ -645,6 +723,10 @@ impl DeliveryEvent {
             DeliveryEvent::DsnPermFail => "DSN permanent failure notification",
             DeliveryEvent::RawInput => "Raw SMTP input received",
             DeliveryEvent::RawOutput => "Raw SMTP output sent",
+            DeliveryEvent::PartitionDegraded => "Recipient storage partition degraded",
+            DeliveryEvent::RelayBudgetAlert => "Relay budget alert",
+            DeliveryEvent::RelayBudgetExceeded => "Relay budget exceeded",
+            DeliveryEvent::RecipientSuppressed => "Recipient suppressed",
         }
     }
 
@@ -702,6 +784,18 @@ impl DeliveryEvent {
             }
             DeliveryEvent::RawInput => "Raw SMTP input received",
             DeliveryEvent::RawOutput => "Raw SMTP output sent",
+            DeliveryEvent::PartitionDegraded => {
+                "Local delivery was deferred because the recipient's storage partition is degraded"
+            }
+            DeliveryEvent::RelayBudgetAlert => {
+                "The message count for a relay budget has reached the alert threshold"
+            }
+            DeliveryEvent::RelayBudgetExceeded => {
+                "The monthly message budget for a relay host has been exhausted"
+            }
+            DeliveryEvent::RecipientSuppressed => {
+                "The recipient is on the suppression list and the message was not delivered"
+            }
         }
     }
 }
@@ -721,6 +815,19 @@ impl QueueEvent {
             QueueEvent::QueueDsn => "Queued DSN for delivery",
             QueueEvent::QueueAutogenerated => "Queued autogenerated message for delivery",
             QueueEvent::BackPressure => "Queue backpressure detected",
+            QueueEvent::Quarantined => "Message held in quarantine",
+            QueueEvent::QuarantineReleased => "Quarantined message released",
+            QueueEvent::QuarantineRejected => "Quarantined message rejected",
+            QueueEvent::QuarantineNoteAdded => "Quarantine note added",
+            QueueEvent::QuarantineExpired => "Quarantined message expired",
+            QueueEvent::ModerationRequested => "Message held for moderator approval",
+            QueueEvent::SandboxVerdict => "Sandbox verdict received",
+            QueueEvent::SandboxTimeout => "Sandbox verdict timed out",
+            QueueEvent::SandboxError => "Sandbox verdict lookup failed",
+            QueueEvent::Archived => "Message archived",
+            QueueEvent::ArchiveError => "Message archiving failed",
+            QueueEvent::ListDistributed => "Mailing list post distributed",
+            QueueEvent::Rebalanced => "Queue ownership rebalanced",
         }
     }
 
@@ -742,6 +849,43 @@ impl QueueEvent {
             QueueEvent::BackPressure => {
                 "Queue congested, processing can't keep up with incoming message rate"
             }
+            QueueEvent::Quarantined => {
+                "The message was flagged for quarantine and is being held for review instead of being delivered"
+            }
+            QueueEvent::QuarantineReleased => {
+                "A reviewer released a quarantined message for delivery"
+            }
+            QueueEvent::QuarantineRejected => {
+                "A reviewer rejected a quarantined message, which was removed from the queue"
+            }
+            QueueEvent::QuarantineNoteAdded => {
+                "A reviewer added a disposition note to a quarantined message"
+            }
+            QueueEvent::QuarantineExpired => {
+                "A quarantined message was not reviewed before its retention period ended and was removed"
+            }
+            QueueEvent::ModerationRequested => {
+                "A submitted message was held in quarantine until a moderator approves or rejects it"
+            }
+            QueueEvent::SandboxVerdict => {
+                "The sandbox returned a verdict for a message pending detonation"
+            }
+            QueueEvent::SandboxTimeout => {
+                "No sandbox verdict was received for a message before the timeout"
+            }
+            QueueEvent::SandboxError => {
+                "An error occurred while obtaining a verdict from the sandbox"
+            }
+            QueueEvent::Archived => "A copy of a queued message was sent to a compliance archive",
+            QueueEvent::ArchiveError => {
+                "A queued message matched an archiving policy but could not be archived"
+            }
+            QueueEvent::ListDistributed => {
+                "A message posted to a mailing list was queued for delivery to its members"
+            }
+            QueueEvent::Rebalanced => {
+                "Queue ownership was redistributed after a change in cluster membership"
+            }
         }
     }
 }
@@ -764,6 +908,7 @@ impl IncomingReportEvent {
             IncomingReportEvent::TlsRpcParseFailed => "Failed to parse TLS RPC report",
             IncomingReportEvent::ArfParseFailed => "Failed to parse ARF report",
             IncomingReportEvent::DecompressError => "Error decompressing report",
+            IncomingReportEvent::BounceReport => "Bounce received for VERP address",
         }
     }
 
@@ -792,6 +937,9 @@ impl IncomingReportEvent {
             IncomingReportEvent::TlsRpcParseFailed => "Failed to parse the TLS RPC report",
             IncomingReportEvent::ArfParseFailed => "Failed to parse the ARF report",
             IncomingReportEvent::DecompressError => "Error decompressing the report",
+            IncomingReportEvent::BounceReport => {
+                "A delivery failure notification was received for a VERP encoded return path"
+            }
         }
     }
 }
@@ -938,6 +1086,8 @@ impl MilterEvent {
             MilterEvent::TlsInvalidName => "Invalid TLS name for Milter",
             MilterEvent::Disconnected => "Milter disconnected",
             MilterEvent::ParseError => "Milter parse error",
+            MilterEvent::CircuitOpen => "Circuit breaker opened",
+            MilterEvent::Unavailable => "Filter unavailable",
         }
     }
 
@@ -962,6 +1112,12 @@ impl MilterEvent {
             MilterEvent::TlsInvalidName => "The Milter TLS name is invalid",
             MilterEvent::Disconnected => "The Milter disconnected",
             MilterEvent::ParseError => "An error occurred while parsing the Milter response",
+            MilterEvent::CircuitOpen => {
+                "The error budget was exhausted and requests are temporarily not being sent"
+            }
+            MilterEvent::Unavailable => {
+                "The filter was skipped because its circuit breaker is open or it was disabled"
+            }
         }
     }
 }
@@ -974,6 +1130,8 @@ impl MtaHookEvent {
             MtaHookEvent::ActionReject => "MTA hook action: Reject",
             MtaHookEvent::ActionQuarantine => "MTA hook action: Quarantine",
             MtaHookEvent::Error => "MTA hook error",
+            MtaHookEvent::CircuitOpen => "Circuit breaker opened",
+            MtaHookEvent::Unavailable => "Filter unavailable",
         }
     }
 
@@ -984,6 +1142,12 @@ impl MtaHookEvent {
             MtaHookEvent::ActionReject => "The MTA hook requested to reject the message",
             MtaHookEvent::ActionQuarantine => "The MTA hook requested to quarantine the message",
             MtaHookEvent::Error => "An error occurred with the MTA hook",
+            MtaHookEvent::CircuitOpen => {
+                "The error budget was exhausted and requests are temporarily not being sent"
+            }
+            MtaHookEvent::Unavailable => {
+                "The filter was skipped because its circuit breaker is open or it was disabled"
+            }
         }
     }
 }
@@ -1019,6 +1183,13 @@ impl SpamEvent {
             SpamEvent::Dnsbl => "DNSBL query",
             SpamEvent::DnsblError => "Error querying DNSBL",
             SpamEvent::TrainAccount => "Training spam filter for account",
+            SpamEvent::TrainDuplicate => "Bayes training skipped for already learned message",
+            SpamEvent::TrainRateLimited => "Bayes training rate limit exceeded",
+            SpamEvent::OverrideError => "Invalid spam filter override",
+            SpamEvent::UrlReputationListed => "URL listed by reputation feed",
+            SpamEvent::UrlReputationError => "URL reputation feed error",
+            SpamEvent::DnsblDisabled => "DNSBL server disabled",
+            SpamEvent::DnsblEnabled => "DNSBL server re-enabled",
         }
     }
 
@@ -1034,6 +1205,27 @@ impl SpamEvent {
             SpamEvent::Dnsbl => "The DNSBL query was successful",
             SpamEvent::DnsblError => "An error occurred while querying the DNSBL",
             SpamEvent::TrainAccount => "The spam filter has been trained for the account",
+            SpamEvent::TrainDuplicate => {
+                "The message was already used to train the classifier with the same class."
+            }
+            SpamEvent::TrainRateLimited => {
+                "The account exceeded the allowed number of training requests, the message was not used for training."
+            }
+            SpamEvent::OverrideError => {
+                "The spam filter settings of a principal could not be parsed and were ignored."
+            }
+            SpamEvent::UrlReputationListed => {
+                "A URL in the message is listed by a URL reputation feed"
+            }
+            SpamEvent::UrlReputationError => {
+                "An error occurred while querying a URL reputation feed"
+            }
+            SpamEvent::DnsblDisabled => {
+                "A DNSBL server stopped responding and was temporarily disabled"
+            }
+            SpamEvent::DnsblEnabled => {
+                "A previously disabled DNSBL server responded again and was re-enabled"
+            }
         }
     }
 }
@@ -1053,6 +1245,10 @@ impl SieveEvent {
             SieveEvent::UnexpectedError => "Unexpected Sieve error",
             SieveEvent::NotSupported => "Sieve action not supported",
             SieveEvent::QuotaExceeded => "Sieve quota exceeded",
+            SieveEvent::Notify => "Sieve notification sent",
+            SieveEvent::NotifyError => "Sieve notification failed",
+            SieveEvent::ForwardDenied => "Sieve forward to external domain denied",
+            SieveEvent::ForwardApprovalRequested => "Sieve forward approval requested",
         }
     }
 
@@ -1072,6 +1268,16 @@ impl SieveEvent {
             SieveEvent::UnexpectedError => "An unexpected error occurred with the Sieve script",
             SieveEvent::NotSupported => "The Sieve action is not supported",
             SieveEvent::QuotaExceeded => "The Sieve quota was exceeded",
+            SieveEvent::Notify => "A Sieve script sent a notification using the enotify extension",
+            SieveEvent::NotifyError => {
+                "A notification requested by a Sieve script could not be delivered"
+            }
+            SieveEvent::ForwardDenied => {
+                "A Sieve redirect to an external domain was blocked by the forward policy"
+            }
+            SieveEvent::ForwardApprovalRequested => {
+                "A Sieve redirect to an external address is awaiting administrator approval"
+            }
         }
     }
 }
@@ -1085,6 +1291,11 @@ impl TlsEvent {
             TlsEvent::CertificateNotFound => "TLS certificate not found",
             TlsEvent::NoCertificatesAvailable => "No TLS certificates available",
             TlsEvent::MultipleCertificatesAvailable => "Multiple TLS certificates available",
+            TlsEvent::TicketSecretSync => "TLS ticket secret synchronized",
+            TlsEvent::CertificateReloaded => "TLS certificate reloaded",
+            TlsEvent::CertificateReloadError => "TLS certificate reload failed",
+            TlsEvent::OcspResponseUpdated => "OCSP response updated",
+            TlsEvent::OcspResponseError => "OCSP response update failed",
         }
     }
 
@@ -1096,6 +1307,21 @@ impl TlsEvent {
             TlsEvent::CertificateNotFound => "The TLS certificate was not found",
             TlsEvent::NoCertificatesAvailable => "No TLS certificates are available",
             TlsEvent::MultipleCertificatesAvailable => "Multiple TLS certificates are available",
+            TlsEvent::TicketSecretSync => {
+                "The TLS session ticket secret was loaded from the cluster"
+            }
+            TlsEvent::CertificateReloaded => {
+                "A TLS certificate was reloaded after its files changed on disk"
+            }
+            TlsEvent::CertificateReloadError => {
+                "A changed TLS certificate could not be loaded, the previous one is still in use"
+            }
+            TlsEvent::OcspResponseUpdated => {
+                "The stapled OCSP response of a certificate was updated"
+            }
+            TlsEvent::OcspResponseError => {
+                "The OCSP response of a certificate could not be fetched"
+            }
         }
     }
 }
@@ -1150,6 +1376,7 @@ impl ServerEvent {
             ServerEvent::StartupError => "Server startup error",
             ServerEvent::ThreadError => "Server thread error",
             ServerEvent::Licensing => "Server licensing event",
+            ServerEvent::CacheWarmUp => "Cache warm-up completed",
         }
     }
 
@@ -1160,6 +1387,7 @@ impl ServerEvent {
             ServerEvent::StartupError => "An error occurred while starting the server",
             ServerEvent::ThreadError => "An error occurred with a server thread",
             ServerEvent::Licensing => "A licensing event occurred",
+            ServerEvent::CacheWarmUp => "Caches were preloaded from the store after startup",
         }
     }
 }
@@ -1171,6 +1399,7 @@ impl TelemetryEvent {
             TelemetryEvent::LogError => "Log collector error",
             TelemetryEvent::WebhookError => "Webhook collector error",
             TelemetryEvent::JournalError => "Journal collector error",
+            TelemetryEvent::EventBusError => "Event bus collector error",
             TelemetryEvent::OtelExporterError => "OpenTelemetry exporter error",
             TelemetryEvent::OtelMetricsExporterError => "OpenTelemetry metrics exporter error",
             TelemetryEvent::PrometheusExporterError => "Prometheus exporter error",
@@ -1183,6 +1412,7 @@ impl TelemetryEvent {
             TelemetryEvent::LogError => "An error occurred with the log collector",
             TelemetryEvent::WebhookError => "An error occurred with the webhook collector",
             TelemetryEvent::JournalError => "An error occurred with the journal collector",
+            TelemetryEvent::EventBusError => "An error occurred with the event bus collector",
             TelemetryEvent::OtelExporterError => {
                 "An error occurred with the OpenTelemetry exporter"
             }
@@ -1220,6 +1450,9 @@ impl AcmeEvent {
             AcmeEvent::DnsRecordLookupFailed => "ACME DNS record lookup failed",
             AcmeEvent::DnsRecordPropagated => "ACME DNS record propagated",
             AcmeEvent::DnsRecordPropagationTimeout => "ACME DNS record propagation timeout",
+            AcmeEvent::TlsaRecordsUpdated => "ACME TLSA records updated",
+            AcmeEvent::TlsaRecordsUpdateFailed => "ACME TLSA records update failed",
+            AcmeEvent::TlsaRolloverPending => "ACME TLSA rollover pending",
             AcmeEvent::ClientSuppliedSni => "ACME client supplied SNI",
             AcmeEvent::ClientMissingSni => "ACME client missing SNI",
             AcmeEvent::TlsAlpnReceived => "ACME TLS ALPN received",
@@ -1252,6 +1485,13 @@ impl AcmeEvent {
             AcmeEvent::DnsRecordLookupFailed => "Failed to look up ACME DNS record",
             AcmeEvent::DnsRecordPropagated => "ACME DNS record has propagated",
             AcmeEvent::DnsRecordPropagationTimeout => "ACME DNS record propagation timeout",
+            AcmeEvent::TlsaRecordsUpdated => "TLSA records for the certificate have been published",
+            AcmeEvent::TlsaRecordsUpdateFailed => {
+                "Failed to publish TLSA records for the certificate"
+            }
+            AcmeEvent::TlsaRolloverPending => {
+                "Certificate renewal is waiting for the new TLSA records to propagate"
+            }
             AcmeEvent::ClientSuppliedSni => "ACME client supplied SNI",
             AcmeEvent::ClientMissingSni => "ACME client missing SNI",
             AcmeEvent::TlsAlpnReceived => "ACME TLS ALPN received",
@@ -1272,6 +1512,11 @@ impl PurgeEvent {
             PurgeEvent::InProgress => "Active purge in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup executed",
+            PurgeEvent::Retention => "Retention rule applied",
+            PurgeEvent::RetentionDryRun => "Retention rule evaluated",
+            PurgeEvent::LegalHoldPlaced => "Legal hold placed",
+            PurgeEvent::LegalHoldReleased => "Legal hold released",
+            PurgeEvent::LegalHoldRetained => "Deletion retained by legal hold",
         }
     }
 
@@ -1284,6 +1529,17 @@ impl PurgeEvent {
             PurgeEvent::InProgress => "An active purge is in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge has been executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup has been executed",
+            PurgeEvent::Retention => "Messages older than a retention rule allows were removed",
+            PurgeEvent::RetentionDryRun => {
+                "A retention rule in dry-run mode matched messages that were left untouched"
+            }
+            PurgeEvent::LegalHoldPlaced => "A legal hold was placed on an account",
+            PurgeEvent::LegalHoldReleased => {
+                "A legal hold was released and the retained messages were scheduled for deletion"
+            }
+            PurgeEvent::LegalHoldRetained => {
+                "Messages deleted from an account under legal hold were moved to the hold area"
+            }
         }
     }
 }
@@ -1559,6 +1815,20 @@ impl StoreEvent {
             StoreEvent::CacheHit => "Cache hit",
             StoreEvent::CacheStale => "Cache is stale",
             StoreEvent::CacheUpdate => "Cache update",
+            StoreEvent::PartitionDegraded => "Storage partition degraded",
+            StoreEvent::PartitionRecovered => "Storage partition recovered",
+            StoreEvent::S3Request => "S3 request",
+            StoreEvent::S3Retry => "S3 request retry",
+            StoreEvent::S3ChecksumMismatch => "S3 checksum mismatch",
+            StoreEvent::BlobArchive => "Blob moved to archive storage",
+            StoreEvent::BlobRestore => "Blob restored from archive storage",
+            StoreEvent::BlobDeduplicate => "Blob deduplicated",
+            StoreEvent::BackupCompleted => "Backup completed",
+            StoreEvent::RestoreCompleted => "Restore completed",
+            StoreEvent::GraphError => "Microsoft Graph error",
+            StoreEvent::DirectorySync => "Directory synchronized",
+            StoreEvent::LdapWrite => "LDAP entry modified",
+            StoreEvent::CassandraError => "Cassandra error",
         }
     }
 
@@ -1600,6 +1870,34 @@ impl StoreEvent {
             StoreEvent::CacheHit => "Cache entry found for the account, no update needed",
             StoreEvent::CacheStale => "Cache is too old, rebuilding",
             StoreEvent::CacheUpdate => "Cache updated with latest database changes",
+            StoreEvent::PartitionDegraded => {
+                "A storage partition failed its health checks and local deliveries to its accounts are being deferred"
+            }
+            StoreEvent::PartitionRecovered => {
+                "A storage partition passed its health check and local deliveries to its accounts have resumed"
+            }
+            StoreEvent::S3Request => "An S3 request was completed",
+            StoreEvent::S3Retry => {
+                "An S3 request failed with a transient error and will be retried"
+            }
+            StoreEvent::S3ChecksumMismatch => {
+                "The checksum returned by the S3 server does not match the blob contents"
+            }
+            StoreEvent::BlobArchive => {
+                "A blob was moved from the primary store to the archive store"
+            }
+            StoreEvent::BlobRestore => {
+                "An archived blob was accessed and moved back to the primary store"
+            }
+            StoreEvent::BlobDeduplicate => {
+                "A blob with identical contents already exists and was not stored again"
+            }
+            StoreEvent::BackupCompleted => "A backup of the server data was written to disk",
+            StoreEvent::RestoreCompleted => "The server data was restored from a backup",
+            StoreEvent::GraphError => "An error occurred while querying Microsoft Graph",
+            StoreEvent::DirectorySync => "Principals were synchronized from an external directory",
+            StoreEvent::LdapWrite => "Principal changes were written back to an LDAP entry",
+            StoreEvent::CassandraError => "A Cassandra or ScyllaDB error occurred",
         }
     }
 }
@@ -1754,6 +2052,7 @@ impl AuthEvent {
             AuthEvent::Error => "Authentication error",
             AuthEvent::TokenExpired => "OAuth token expired",
             AuthEvent::ClientRegistration => "OAuth Client registration",
+            AuthEvent::TokenRevoked => "OAuth token revoked",
         }
     }
 
@@ -1766,6 +2065,7 @@ impl AuthEvent {
             AuthEvent::Error => "An error occurred with authentication",
             AuthEvent::TokenExpired => "OAuth authentication token has expired",
             AuthEvent::ClientRegistration => "OAuth client successfully registered",
+            AuthEvent::TokenRevoked => "An OAuth token was revoked before its expiration",
         }
     }
 }
@@ -1801,6 +2101,11 @@ impl SecurityEvent {
             SecurityEvent::IpBlocked => "Blocked IP address",
             SecurityEvent::ScanBan => "Banned due to scan",
             SecurityEvent::Unauthorized => "Unauthorized access",
+            SecurityEvent::IpListFeedUpdated => "IP list feed updated",
+            SecurityEvent::IpListFeedError => "IP list feed error",
+            SecurityEvent::AuthenticationLockout => "Locked out due to authentication errors",
+            SecurityEvent::AccountAnomaly => "Suspicious account activity detected",
+            SecurityEvent::Impersonation => "Impersonated account activity",
         }
     }
 
@@ -1816,6 +2121,21 @@ impl SecurityEvent {
             SecurityEvent::LoiterBan => "IP address was banned due to multiple loitering events",
             SecurityEvent::IpBlocked => "Rejected connection from blocked IP address",
             SecurityEvent::Unauthorized => "Account does not have permission to access resource",
+            SecurityEvent::IpListFeedUpdated => {
+                "The entries of an IP list were replaced with the contents of its feed"
+            }
+            SecurityEvent::IpListFeedError => {
+                "An error occurred while fetching the feed of an IP list"
+            }
+            SecurityEvent::AuthenticationLockout => {
+                "Account or IP address was temporarily locked out due to multiple authentication errors"
+            }
+            SecurityEvent::AccountAnomaly => {
+                "An authenticated submission showed signs of a compromised account"
+            }
+            SecurityEvent::Impersonation => {
+                "An administrator performed an action while impersonating an account"
+            }
         }
     }
 }
@@ -18,7 +18,8 @@ impl EventType {
                 | StoreEvent::BlobWrite
                 | StoreEvent::BlobDelete
                 | StoreEvent::SqlQuery
-                | StoreEvent::LdapQuery => Level::Trace,
+                | StoreEvent::LdapQuery
+                | StoreEvent::LdapWrite => Level::Trace,
                 StoreEvent::CacheMiss
                 | StoreEvent::CacheHit
                 | StoreEvent::CacheStale
@@ -45,8 +46,21 @@ impl EventType {
                 | StoreEvent::NotConfigured
                 | StoreEvent::NotSupported
                 | StoreEvent::UnexpectedError
-                | StoreEvent::CryptoError => Level::Error,
+                | StoreEvent::CryptoError
+                | StoreEvent::GraphError
+                | StoreEvent::CassandraError => Level::Error,
                 StoreEvent::BlobMissingMarker | StoreEvent::HttpStoreError => Level::Warn,
+                StoreEvent::PartitionDegraded => Level::Warn,
+                StoreEvent::PartitionRecovered => Level::Info,
+                StoreEvent::S3Request => Level::Trace,
+                StoreEvent::S3Retry => Level::Warn,
+                StoreEvent::S3ChecksumMismatch => Level::Error,
+                StoreEvent::BlobArchive | StoreEvent::BlobRestore | StoreEvent::BlobDeduplicate => {
+                    Level::Trace
+                }
+                StoreEvent::BackupCompleted
+                | StoreEvent::RestoreCompleted
+                | StoreEvent::DirectorySync => Level::Info,
             },
             EventType::Jmap(_) => Level::Debug,
             EventType::Imap(event) => match event {
@@ -83,7 +97,10 @@ impl EventType {
                 | ImapEvent::Error
                 | ImapEvent::IdleStart
                 | ImapEvent::IdleStop
-                | ImapEvent::GetQuota => Level::Debug,
+                | ImapEvent::GetQuota
+                | ImapEvent::Compress
+                | ImapEvent::GetMetadata
+                | ImapEvent::XApplePushService => Level::Debug,
                 ImapEvent::RawInput | ImapEvent::RawOutput => Level::Trace,
             },
             EventType::ManageSieve(event) => match event {
@@ -203,6 +220,25 @@ impl EventType {
                 | SmtpEvent::RequestTooLarge
                 | SmtpEvent::TooManyRecipients => Level::Info,
                 SmtpEvent::RawInput | SmtpEvent::RawOutput => Level::Trace,
+                SmtpEvent::MessageRewritten => Level::Info,
+                SmtpEvent::AttachmentDisarmed => Level::Info,
+                SmtpEvent::VirusFound => Level::Info,
+                SmtpEvent::VirusScanError => Level::Warn,
+                SmtpEvent::VirusScanSkipped => Level::Debug,
+                SmtpEvent::DlpMatch => Level::Info,
+                SmtpEvent::DlpBlocked => Level::Info,
+                SmtpEvent::AttachmentLinked => Level::Info,
+                SmtpEvent::LoopDropped => Level::Warn,
+                SmtpEvent::MaintenanceDeferred => Level::Info,
+                SmtpEvent::PartitionDegraded => Level::Info,
+                SmtpEvent::ProxyConnected => Level::Info,
+                SmtpEvent::ProxyError => Level::Warn,
+                SmtpEvent::SandboxSubmitted => Level::Info,
+                SmtpEvent::SandboxError => Level::Warn,
+                SmtpEvent::ListExpansionTooLarge => Level::Info,
+                SmtpEvent::ListExpansionLoop => Level::Info,
+                SmtpEvent::ListExpansionTooDeep => Level::Info,
+                SmtpEvent::SendingLimitExceeded => Level::Info,
             },
             EventType::Network(event) => match event {
                 NetworkEvent::ReadError
@@ -235,7 +271,9 @@ impl EventType {
                 AuthEvent::MissingTotp => Level::Trace,
                 AuthEvent::TooManyAttempts => Level::Warn,
                 AuthEvent::Error => Level::Error,
-                AuthEvent::Success | AuthEvent::ClientRegistration => Level::Info,
+                AuthEvent::Success | AuthEvent::ClientRegistration | AuthEvent::TokenRevoked => {
+                    Level::Info
+                }
             },
             EventType::Config(cause) => match cause {
                 ConfigEvent::ParseError
@@ -276,6 +314,11 @@ impl EventType {
                 PurgeEvent::InProgress | PurgeEvent::AutoExpunge | PurgeEvent::TombstoneCleanup => {
                     Level::Debug
                 }
+                PurgeEvent::Retention
+                | PurgeEvent::RetentionDryRun
+                | PurgeEvent::LegalHoldPlaced
+                | PurgeEvent::LegalHoldReleased
+                | PurgeEvent::LegalHoldRetained => Level::Info,
             },
             EventType::Eval(event) => match event {
                 EvalEvent::Error | EvalEvent::StoreNotFound => Level::Debug,
@@ -283,9 +326,10 @@ impl EventType {
                 EvalEvent::DirectoryNotFound => Level::Warn,
             },
             EventType::Server(event) => match event {
-                ServerEvent::Startup | ServerEvent::Shutdown | ServerEvent::Licensing => {
-                    Level::Info
-                }
+                ServerEvent::Startup
+                | ServerEvent::Shutdown
+                | ServerEvent::Licensing
+                | ServerEvent::CacheWarmUp => Level::Info,
                 ServerEvent::StartupError | ServerEvent::ThreadError => Level::Error,
             },
             EventType::Acme(event) => match event {
@@ -301,7 +345,9 @@ impl EventType {
                 | AcmeEvent::OrderReady
                 | AcmeEvent::OrderValid
                 | AcmeEvent::OrderStart
-                | AcmeEvent::OrderCompleted => Level::Info,
+                | AcmeEvent::OrderCompleted
+                | AcmeEvent::TlsaRecordsUpdated
+                | AcmeEvent::TlsaRolloverPending => Level::Info,
                 AcmeEvent::Error => Level::Error,
                 AcmeEvent::OrderInvalid
                 | AcmeEvent::AuthError
@@ -309,7 +355,8 @@ impl EventType {
                 | AcmeEvent::TokenNotFound
                 | AcmeEvent::DnsRecordPropagationTimeout
                 | AcmeEvent::TlsAlpnError
-                | AcmeEvent::DnsRecordCreationFailed => Level::Warn,
+                | AcmeEvent::DnsRecordCreationFailed
+                | AcmeEvent::TlsaRecordsUpdateFailed => Level::Warn,
                 AcmeEvent::RenewBackoff
                 | AcmeEvent::DnsRecordDeletionFailed
                 | AcmeEvent::ClientSuppliedSni
@@ -318,12 +365,16 @@ impl EventType {
                 | AcmeEvent::DnsRecordLookupFailed => Level::Debug,
             },
             EventType::Tls(event) => match event {
-                TlsEvent::Handshake => Level::Info,
+                TlsEvent::Handshake
+                | TlsEvent::TicketSecretSync
+                | TlsEvent::CertificateReloaded
+                | TlsEvent::OcspResponseUpdated => Level::Info,
                 TlsEvent::HandshakeError | TlsEvent::CertificateNotFound => Level::Debug,
                 TlsEvent::NotConfigured => Level::Error,
-                TlsEvent::NoCertificatesAvailable | TlsEvent::MultipleCertificatesAvailable => {
-                    Level::Warn
-                }
+                TlsEvent::NoCertificatesAvailable
+                | TlsEvent::MultipleCertificatesAvailable
+                | TlsEvent::CertificateReloadError
+                | TlsEvent::OcspResponseError => Level::Warn,
             },
             EventType::Sieve(event) => match event {
                 SieveEvent::NotSupported
@@ -338,6 +389,9 @@ impl EventType {
                 | SieveEvent::ActionAcceptReplace
                 | SieveEvent::ActionDiscard
                 | SieveEvent::ActionReject => Level::Debug,
+                SieveEvent::Notify => Level::Info,
+                SieveEvent::NotifyError => Level::Debug,
+                SieveEvent::ForwardDenied | SieveEvent::ForwardApprovalRequested => Level::Info,
             },
             EventType::Spam(event) => match event {
                 SpamEvent::PyzorError
@@ -350,6 +404,13 @@ impl EventType {
                 | SpamEvent::ClassifyError
                 | SpamEvent::TrainBalance
                 | SpamEvent::Dnsbl => Level::Debug,
+                SpamEvent::TrainDuplicate => Level::Debug,
+                SpamEvent::TrainRateLimited => Level::Info,
+                SpamEvent::OverrideError => Level::Warn,
+                SpamEvent::UrlReputationListed => Level::Info,
+                SpamEvent::UrlReputationError => Level::Debug,
+                SpamEvent::DnsblDisabled => Level::Warn,
+                SpamEvent::DnsblEnabled => Level::Info,
             },
             EventType::Http(event) => match event {
                 HttpEvent::ConnectionStart | HttpEvent::ConnectionEnd => Level::Debug,
@@ -402,6 +463,8 @@ impl EventType {
                 | MilterEvent::TlsInvalidName
                 | MilterEvent::Disconnected
                 | MilterEvent::ParseError => Level::Warn,
+                MilterEvent::CircuitOpen => Level::Warn,
+                MilterEvent::Unavailable => Level::Debug,
             },
             EventType::MtaHook(event) => match event {
                 MtaHookEvent::ActionAccept
@@ -409,6 +472,8 @@ impl EventType {
                 | MtaHookEvent::ActionReject
                 | MtaHookEvent::ActionQuarantine => Level::Info,
                 MtaHookEvent::Error => Level::Warn,
+                MtaHookEvent::CircuitOpen => Level::Warn,
+                MtaHookEvent::Unavailable => Level::Debug,
             },
             EventType::Dane(event) => match event {
                 DaneEvent::AuthenticationSuccess
@@ -460,6 +525,11 @@ impl EventType {
                 | DeliveryEvent::MailFrom
                 | DeliveryEvent::RcptTo => Level::Debug,
                 DeliveryEvent::RawInput | DeliveryEvent::RawOutput => Level::Trace,
+                DeliveryEvent::PartitionDegraded | DeliveryEvent::RecipientSuppressed => {
+                    Level::Info
+                }
+                DeliveryEvent::RelayBudgetAlert => Level::Warn,
+                DeliveryEvent::RelayBudgetExceeded => Level::Warn,
             },
             EventType::Queue(event) => match event {
                 QueueEvent::BackPressure => Level::Warn,
@@ -473,6 +543,19 @@ impl EventType {
                 | QueueEvent::Rescheduled
                 | QueueEvent::QuotaExceeded => Level::Info,
                 QueueEvent::Locked | QueueEvent::BlobNotFound => Level::Debug,
+                QueueEvent::Quarantined => Level::Info,
+                QueueEvent::QuarantineReleased => Level::Info,
+                QueueEvent::QuarantineRejected => Level::Info,
+                QueueEvent::QuarantineNoteAdded => Level::Info,
+                QueueEvent::QuarantineExpired => Level::Info,
+                QueueEvent::ModerationRequested => Level::Info,
+                QueueEvent::SandboxVerdict => Level::Info,
+                QueueEvent::SandboxTimeout => Level::Info,
+                QueueEvent::SandboxError => Level::Warn,
+                QueueEvent::Archived => Level::Info,
+                QueueEvent::ArchiveError => Level::Error,
+                QueueEvent::ListDistributed => Level::Info,
+                QueueEvent::Rebalanced => Level::Info,
             },
             EventType::TlsRpt(event) => match event {
                 TlsRptEvent::RecordFetch
@@ -502,7 +585,8 @@ impl EventType {
                 | IncomingReportEvent::DmarcParseFailed
                 | IncomingReportEvent::TlsRpcParseFailed
                 | IncomingReportEvent::ArfParseFailed
-                | IncomingReportEvent::DecompressError => Level::Info,
+                | IncomingReportEvent::DecompressError
+                | IncomingReportEvent::BounceReport => Level::Info,
             },
             EventType::OutgoingReport(event) => match event {
                 OutgoingReportEvent::Locked | OutgoingReportEvent::NotFound => Level::Info,
@@ -530,7 +614,18 @@ impl EventType {
                 | MessageIngestEvent::FtsIndex => Level::Info,
                 MessageIngestEvent::Error => Level::Error,
             },
-            EventType::Security(_) => Level::Info,
+            EventType::Security(event) => match event {
+                SecurityEvent::IpListFeedError | SecurityEvent::AccountAnomaly => Level::Warn,
+                SecurityEvent::AuthenticationBan
+                | SecurityEvent::AbuseBan
+                | SecurityEvent::ScanBan
+                | SecurityEvent::LoiterBan
+                | SecurityEvent::IpBlocked
+                | SecurityEvent::Unauthorized
+                | SecurityEvent::IpListFeedUpdated
+                | SecurityEvent::AuthenticationLockout
+                | SecurityEvent::Impersonation => Level::Info,
+            },
             EventType::Ai(event) => match event {
                 AiEvent::LlmResponse => Level::Trace,
                 AiEvent::ApiError => Level::Warn,
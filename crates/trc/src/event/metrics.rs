@@ -21,6 +21,8 @@ impl MetricType {
             Self::BlobReadTime => "store.blob-read-time",
             Self::BlobWriteTime => "store.blob-write-time",
             Self::DnsLookupTime => "dns.lookup-time",
+            Self::MtaHookTime => "mta-hook.time",
+            Self::MilterTime => "milter.time",
             Self::HttpRequestTime => "http.request-time",
             Self::ImapRequestTime => "imap.request-time",
             Self::Pop3RequestTime => "pop3.request-time",
@@ -53,6 +55,8 @@ impl MetricType {
             Self::BlobReadTime => "Blob store read time",
             Self::BlobWriteTime => "Blob store write time",
             Self::DnsLookupTime => "DNS lookup time",
+            Self::MtaHookTime => "MTA hook response time",
+            Self::MilterTime => "Milter response time",
             Self::HttpRequestTime => "HTTP request duration",
             Self::ImapRequestTime => "IMAP request duration",
             Self::Pop3RequestTime => "POP3 request duration",
@@ -82,6 +86,8 @@ impl MetricType {
             | Self::BlobReadTime
             | Self::BlobWriteTime
             | Self::DnsLookupTime
+            | Self::MtaHookTime
+            | Self::MilterTime
             | Self::HttpRequestTime
             | Self::ImapRequestTime
             | Self::Pop3RequestTime
@@ -132,6 +138,8 @@ impl MetricType {
             Self::QueueCount => 24,
             Self::UserCount => 25,
             Self::DomainCount => 26,
+            Self::MtaHookTime => 27,
+            Self::MilterTime => 28,
         }
     }
 
@@ -164,6 +172,8 @@ impl MetricType {
             24 => Some(Self::QueueCount),
             25 => Some(Self::UserCount),
             26 => Some(Self::DomainCount),
+            27 => Some(Self::MtaHookTime),
+            28 => Some(Self::MilterTime),
             _ => None,
         }
     }
@@ -182,6 +192,8 @@ impl MetricType {
             "store.blob-read-time" => Some(Self::BlobReadTime),
             "store.blob-write-time" => Some(Self::BlobWriteTime),
             "dns.lookup-time" => Some(Self::DnsLookupTime),
+            "mta-hook.time" => Some(Self::MtaHookTime),
+            "milter.time" => Some(Self::MilterTime),
             "http.request-time" => Some(Self::HttpRequestTime),
             "imap.request-time" => Some(Self::ImapRequestTime),
             "pop3.request-time" => Some(Self::Pop3RequestTime),
@@ -215,6 +227,8 @@ impl MetricType {
             Self::BlobReadTime,
             Self::BlobWriteTime,
             Self::DnsLookupTime,
+            Self::MtaHookTime,
+            Self::MilterTime,
             Self::HttpRequestTime,
             Self::ImapRequestTime,
             Self::Pop3RequestTime,
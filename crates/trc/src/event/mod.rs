@@ -350,6 +350,8 @@ impl StoreEvent {
             Self::RocksdbError => "RocksDB error",
             Self::SqliteError => "SQLite error",
             Self::LdapError => "LDAP error",
+            Self::GraphError => "Microsoft Graph error",
+            Self::CassandraError => "Cassandra error",
             Self::ElasticsearchError => "ElasticSearch error",
             Self::RedisError => "Redis error",
             Self::S3Error => "S3 error",
@@ -24,6 +24,8 @@ use crate::*;
 pub(crate) type GlobalInterests = AtomicBitset<{ TOTAL_EVENT_COUNT.div_ceil(USIZE_BITS) }>;
 
 pub(crate) static TRACE_INTERESTS: GlobalInterests = GlobalInterests::new();
+pub(crate) static CONFIGURED_INTERESTS: Mutex<Option<Interests>> = Mutex::new(None);
+pub(crate) static SUBSCRIBER_INTERESTS: Mutex<Vec<(String, Interests)>> = Mutex::new(Vec::new());
 pub(crate) type CollectorThread = JoinHandle<()>;
 pub(crate) static ACTIVE_SUBSCRIBERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
 pub(crate) static COLLECTOR_UPDATES: Mutex<Vec<Update>> = Mutex::new(Vec::new());
@@ -221,8 +223,16 @@ impl Collector {
 
                 // Send batched events
                 if !self.subscribers.is_empty() {
-                    self.subscribers
-                        .retain_mut(|subscriber| subscriber.send_batch().is_ok());
+                    self.subscribers.retain_mut(|subscriber| {
+                        if subscriber.send_batch().is_ok() {
+                            true
+                        } else {
+                            if subscriber.global_interests {
+                                remove_subscriber_interests(&subscriber.id);
+                            }
+                            false
+                        }
+                    });
                 }
             }
         }
@@ -245,7 +255,16 @@ impl Collector {
                 }
                 Update::UnregisterSubscriber { id } => {
                     ACTIVE_SUBSCRIBERS.lock().retain(|s| s != &id);
-                    self.subscribers.retain(|s| s.id != id);
+                    self.subscribers.retain(|s| {
+                        if s.id != id {
+                            true
+                        } else {
+                            if s.global_interests {
+                                remove_subscriber_interests(&s.id);
+                            }
+                            false
+                        }
+                    });
                 }
                 Update::UpdateSubscriber {
                     id,
@@ -254,6 +273,16 @@ impl Collector {
                 } => {
                     for subscriber in self.subscribers.iter_mut() {
                         if subscriber.id == id {
+                            if subscriber.global_interests {
+                                if let Some((_, global)) = SUBSCRIBER_INTERESTS
+                                    .lock()
+                                    .iter_mut()
+                                    .find(|(global_id, _)| global_id == &id)
+                                {
+                                    *global = interests.clone();
+                                }
+                                Collector::refresh_interests();
+                            }
                             subscriber.interests = interests;
                             subscriber.lossy = lossy;
                             break;
@@ -286,11 +315,26 @@ impl Collector {
             }
         }
 
-        TRACE_INTERESTS.update(interests);
+        *CONFIGURED_INTERESTS.lock() = Some(interests);
+        Collector::refresh_interests();
     }
 
     pub fn union_interests(interests: Interests) {
-        TRACE_INTERESTS.union(interests);
+        CONFIGURED_INTERESTS
+            .lock()
+            .get_or_insert_with(Default::default)
+            .union(&interests);
+        Collector::refresh_interests();
+    }
+
+    /// Recomputes the global interests from the configured tracers and the
+    /// subscribers that requested their interests to be enabled.
+    pub(crate) fn refresh_interests() {
+        let mut interests = CONFIGURED_INTERESTS.lock().clone().unwrap_or_default();
+        for (_, subscriber_interests) in SUBSCRIBER_INTERESTS.lock().iter() {
+            interests.union(subscriber_interests);
+        }
+        TRACE_INTERESTS.update(interests);
     }
 
     #[inline(always)]
@@ -354,3 +398,15 @@ impl Default for Collector {
         c
     }
 }
+
+fn remove_subscriber_interests(id: &str) {
+    let mut subscriber_interests = SUBSCRIBER_INTERESTS.lock();
+    if let Some(pos) = subscriber_interests
+        .iter()
+        .position(|(subscriber_id, _)| subscriber_id == id)
+    {
+        subscriber_interests.remove(pos);
+        drop(subscriber_interests);
+        Collector::refresh_interests();
+    }
+}
@@ -45,6 +45,11 @@ static STORE_BLOB_WRITE_TIME: AtomicHistogram<12> =
 static DNS_LOOKUP_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::DnsLookupTime);
 
+static MTA_HOOK_TIME: AtomicHistogram<12> =
+    AtomicHistogram::<10>::new_short_durations(MetricType::MtaHookTime);
+static MILTER_TIME: AtomicHistogram<12> =
+    AtomicHistogram::<10>::new_short_durations(MetricType::MilterTime);
+
 static SERVER_MEMORY: AtomicGauge = AtomicGauge::new(MetricType::ServerMemory);
 static QUEUE_COUNT: AtomicGauge = AtomicGauge::new(MetricType::QueueCount);
 static USER_COUNT: AtomicGauge = AtomicGauge::new(MetricType::UserCount);
@@ -193,6 +198,37 @@ impl Collector {
             EventType::Store(StoreEvent::DataIterate) => {
                 STORE_DATA_READ_TIME.observe(elapsed);
             }
+            EventType::MtaHook(
+                MtaHookEvent::ActionAccept
+                | MtaHookEvent::ActionDiscard
+                | MtaHookEvent::ActionReject
+                | MtaHookEvent::ActionQuarantine
+                | MtaHookEvent::Error,
+            ) => {
+                if elapsed > 0 {
+                    MTA_HOOK_TIME.observe(elapsed);
+                }
+            }
+            EventType::Milter(
+                MilterEvent::ActionAccept
+                | MilterEvent::ActionDiscard
+                | MilterEvent::ActionReject
+                | MilterEvent::ActionTempFail
+                | MilterEvent::ActionReplyCode
+                | MilterEvent::ActionShutdown
+                | MilterEvent::ActionConnectionFailure
+                | MilterEvent::IoError
+                | MilterEvent::FrameTooLarge
+                | MilterEvent::FrameInvalid
+                | MilterEvent::UnexpectedResponse
+                | MilterEvent::Timeout
+                | MilterEvent::TlsInvalidName
+                | MilterEvent::Disconnected,
+            ) => {
+                if elapsed > 0 {
+                    MILTER_TIME.observe(elapsed);
+                }
+            }
 
             _ => {}
         }
@@ -251,6 +287,8 @@ impl Collector {
             &STORE_BLOB_READ_TIME,
             &STORE_BLOB_WRITE_TIME,
             &DNS_LOOKUP_TIME,
+            &MTA_HOOK_TIME,
+            &MILTER_TIME,
         ];
         static C_HISTOGRAMS: &[&AtomicHistogram<12>] = &[
             &MESSAGE_DELIVERY_TIME,
@@ -293,6 +331,8 @@ impl Collector {
             MetricType::BlobReadTime => STORE_BLOB_READ_TIME.average(),
             MetricType::BlobWriteTime => STORE_BLOB_WRITE_TIME.average(),
             MetricType::DnsLookupTime => DNS_LOOKUP_TIME.average(),
+            MetricType::MtaHookTime => MTA_HOOK_TIME.average(),
+            MetricType::MilterTime => MILTER_TIME.average(),
             MetricType::HttpActiveConnections => {
                 CONNECTION_METRICS[CONN_HTTP].active_connections.get() as f64
             }
@@ -339,6 +379,8 @@ impl Collector {
             MetricType::DeliveryTotalTime => MESSAGE_DELIVERY_TIME.observe(value),
             MetricType::DeliveryTime => CONNECTION_METRICS[CONN_SMTP_OUT].elapsed.observe(value),
             MetricType::DnsLookupTime => DNS_LOOKUP_TIME.observe(value),
+            MetricType::MtaHookTime => MTA_HOOK_TIME.observe(value),
+            MetricType::MilterTime => MILTER_TIME.observe(value),
             _ => {}
         }
     }
@@ -423,6 +465,7 @@ impl EventType {
                 | AcmeEvent::DnsRecordCreationFailed
                 | AcmeEvent::DnsRecordDeletionFailed
                 | AcmeEvent::DnsRecordPropagationTimeout
+                | AcmeEvent::TlsaRecordsUpdateFailed
                 | AcmeEvent::ClientMissingSni
                 | AcmeEvent::TokenNotFound
                 | AcmeEvent::DnsRecordLookupFailed
@@ -437,6 +480,8 @@ impl EventType {
                 | StoreEvent::RocksdbError
                 | StoreEvent::SqliteError
                 | StoreEvent::LdapError
+                | StoreEvent::GraphError
+                | StoreEvent::CassandraError
                 | StoreEvent::ElasticsearchError
                 | StoreEvent::RedisError
                 | StoreEvent::S3Error
@@ -485,6 +530,7 @@ impl EventType {
                 | SmtpEvent::ConcurrencyLimitExceeded
                 | SmtpEvent::TransferLimitExceeded
                 | SmtpEvent::RateLimitExceeded
+                | SmtpEvent::SendingLimitExceeded
                 | SmtpEvent::TimeLimitExceeded
                 | SmtpEvent::MessageParseFailed
                 | SmtpEvent::MessageTooLarge
@@ -669,7 +715,8 @@ impl EventType {
                 | TelemetryEvent::OtelExporterError
                 | TelemetryEvent::OtelMetricsExporterError
                 | TelemetryEvent::PrometheusExporterError
-                | TelemetryEvent::JournalError,
+                | TelemetryEvent::JournalError
+                | TelemetryEvent::EventBusError,
             ) => true,
             EventType::Calendar(
                 CalendarEvent::AlarmSent
@@ -14,7 +14,7 @@ use super::{
     USIZE_BITS,
     bitset::Bitset,
     channel::ChannelError,
-    collector::{COLLECTOR_UPDATES, Collector, Update},
+    collector::{COLLECTOR_UPDATES, Collector, SUBSCRIBER_INTERESTS, Update},
 };
 
 const MAX_BATCH_SIZE: usize = 32768;
@@ -28,6 +28,7 @@ pub(crate) struct Subscriber {
     pub interests: Interests,
     pub tx: mpsc::Sender<EventBatch>,
     pub lossy: bool,
+    pub global_interests: bool,
     pub batch: EventBatch,
 }
 
@@ -35,6 +36,7 @@ pub struct SubscriberBuilder {
     pub id: String,
     pub interests: Interests,
     pub lossy: bool,
+    pub global_interests: bool,
 }
 
 impl Subscriber {
@@ -64,6 +66,8 @@ impl Subscriber {
                 }
                 Err(TrySendError::Closed(_)) => Err(ChannelError),
             }
+        } else if self.tx.is_closed() {
+            Err(ChannelError)
         } else {
             Ok(())
         }
@@ -76,6 +80,7 @@ impl SubscriberBuilder {
             id,
             interests: Default::default(),
             lossy: true,
+            global_interests: false,
         }
     }
 
@@ -105,15 +110,30 @@ impl SubscriberBuilder {
         self
     }
 
+    /// Enables the subscriber's interests globally for as long as it stays registered,
+    /// for events that are not collected unless someone is listening to them.
+    pub fn with_global_interests(mut self, global_interests: bool) -> Self {
+        self.global_interests = global_interests;
+        self
+    }
+
     pub fn register(self) -> (mpsc::Sender<EventBatch>, mpsc::Receiver<EventBatch>) {
         let (tx, rx) = mpsc::channel(8192);
 
+        if self.global_interests {
+            SUBSCRIBER_INTERESTS
+                .lock()
+                .push((self.id.clone(), self.interests.clone()));
+            Collector::refresh_interests();
+        }
+
         COLLECTOR_UPDATES.lock().push(Update::RegisterSubscriber {
             subscriber: Subscriber {
                 id: self.id,
                 interests: self.interests,
                 tx: tx.clone(),
                 lossy: self.lossy,
+                global_interests: self.global_interests,
                 batch: Vec::new(),
             },
         });
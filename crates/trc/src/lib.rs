@@ -108,6 +108,7 @@ pub enum Key {
     MessageId,
     NextDsn,
     NextRetry,
+    NodeId,
     Path,
     Policy,
     QueueId,
@@ -210,6 +211,11 @@ pub enum SecurityEvent {
     LoiterBan,
     IpBlocked,
     Unauthorized,
+    IpListFeedUpdated,
+    IpListFeedError,
+    AuthenticationLockout,
+    AccountAnomaly,
+    Impersonation,
 }
 
 #[event_type]
@@ -280,6 +286,9 @@ pub enum ImapEvent {
     Unsubscribe,
     Thread,
     GetQuota,
+    Compress,
+    GetMetadata,
+    XApplePushService,
 
     // Errors
     Error,
@@ -430,6 +439,25 @@ pub enum SmtpEvent {
     UnsupportedParameter,
     SyntaxError,
     RequestTooLarge,
+    MessageRewritten,
+    AttachmentDisarmed,
+    VirusFound,
+    VirusScanError,
+    VirusScanSkipped,
+    DlpMatch,
+    DlpBlocked,
+    AttachmentLinked,
+    LoopDropped,
+    MaintenanceDeferred,
+    PartitionDegraded,
+    ProxyConnected,
+    ProxyError,
+    SandboxSubmitted,
+    SandboxError,
+    ListExpansionTooLarge,
+    ListExpansionLoop,
+    ListExpansionTooDeep,
+    SendingLimitExceeded,
 }
 
 #[event_type]
@@ -472,6 +500,10 @@ pub enum DeliveryEvent {
     DsnPermFail,
     RawInput,
     RawOutput,
+    PartitionDegraded,
+    RelayBudgetAlert,
+    RelayBudgetExceeded,
+    RecipientSuppressed,
 }
 
 #[event_type]
@@ -488,6 +520,19 @@ pub enum QueueEvent {
     ConcurrencyLimitExceeded,
     QuotaExceeded,
     BackPressure,
+    Quarantined,
+    QuarantineReleased,
+    QuarantineRejected,
+    QuarantineNoteAdded,
+    QuarantineExpired,
+    ModerationRequested,
+    SandboxVerdict,
+    SandboxTimeout,
+    SandboxError,
+    Archived,
+    ArchiveError,
+    ListDistributed,
+    Rebalanced,
 }
 
 #[event_type]
@@ -507,6 +552,7 @@ pub enum IncomingReportEvent {
     TlsRpcParseFailed,
     ArfParseFailed,
     DecompressError,
+    BounceReport,
 }
 
 #[event_type]
@@ -578,6 +624,8 @@ pub enum MilterEvent {
     TlsInvalidName,
     Disconnected,
     ParseError,
+    CircuitOpen,
+    Unavailable,
 }
 
 #[event_type]
@@ -587,6 +635,8 @@ pub enum MtaHookEvent {
     ActionReject,
     ActionQuarantine,
     Error,
+    CircuitOpen,
+    Unavailable,
 }
 
 #[event_type]
@@ -608,6 +658,13 @@ pub enum SpamEvent {
     Classify,
     ClassifyError,
     TrainAccount,
+    TrainDuplicate,
+    TrainRateLimited,
+    OverrideError,
+    UrlReputationListed,
+    UrlReputationError,
+    DnsblDisabled,
+    DnsblEnabled,
 }
 
 #[event_type]
@@ -624,6 +681,10 @@ pub enum SieveEvent {
     UnexpectedError,
     NotSupported,
     QuotaExceeded,
+    Notify,
+    NotifyError,
+    ForwardDenied,
+    ForwardApprovalRequested,
 }
 
 #[event_type]
@@ -634,6 +695,11 @@ pub enum TlsEvent {
     CertificateNotFound,
     NoCertificatesAvailable,
     MultipleCertificatesAvailable,
+    TicketSecretSync,
+    CertificateReloaded,
+    CertificateReloadError,
+    OcspResponseUpdated,
+    OcspResponseError,
 }
 
 #[event_type]
@@ -660,6 +726,7 @@ pub enum ServerEvent {
     StartupError,
     ThreadError,
     Licensing,
+    CacheWarmUp,
 }
 
 #[event_type]
@@ -671,6 +738,7 @@ pub enum TelemetryEvent {
     OtelMetricsExporterError,
     PrometheusExporterError,
     JournalError,
+    EventBusError,
 }
 
 #[event_type]
@@ -696,6 +764,9 @@ pub enum AcmeEvent {
     DnsRecordLookupFailed,
     DnsRecordPropagated,
     DnsRecordPropagationTimeout,
+    TlsaRecordsUpdated,
+    TlsaRecordsUpdateFailed,
+    TlsaRolloverPending,
     ClientSuppliedSni,
     ClientMissingSni,
     TlsAlpnReceived,
@@ -713,6 +784,11 @@ pub enum PurgeEvent {
     InProgress,
     AutoExpunge,
     TombstoneCleanup,
+    Retention,
+    RetentionDryRun,
+    LegalHoldPlaced,
+    LegalHoldReleased,
+    LegalHoldRetained,
 }
 
 #[event_type]
@@ -859,6 +935,20 @@ pub enum StoreEvent {
     LdapQuery,
     LdapWarning,
     HttpStoreFetch,
+    PartitionDegraded,
+    PartitionRecovered,
+    S3Request,
+    S3Retry,
+    S3ChecksumMismatch,
+    BlobArchive,
+    BlobRestore,
+    BlobDeduplicate,
+    BackupCompleted,
+    RestoreCompleted,
+    GraphError,
+    DirectorySync,
+    LdapWrite,
+    CassandraError,
 }
 
 #[event_type]
@@ -938,6 +1028,7 @@ pub enum AuthEvent {
     MissingTotp,
     TooManyAttempts,
     ClientRegistration,
+    TokenRevoked,
     Error,
 }
 
@@ -1010,6 +1101,8 @@ pub enum MetricType {
     BlobReadTime,
     BlobWriteTime,
     DnsLookupTime,
+    MtaHookTime,
+    MilterTime,
     HttpActiveConnections,
     HttpRequestTime,
     ImapActiveConnections,
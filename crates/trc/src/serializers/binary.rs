@@ -895,6 +895,96 @@ impl EventType {
             EventType::Calendar(CalendarEvent::ItipMessageSent) => 583,
             EventType::Calendar(CalendarEvent::ItipMessageReceived) => 584,
             EventType::Calendar(CalendarEvent::ItipMessageError) => 585,
+            EventType::Smtp(SmtpEvent::MessageRewritten) => 586,
+            EventType::Smtp(SmtpEvent::AttachmentDisarmed) => 587,
+            EventType::Smtp(SmtpEvent::VirusFound) => 588,
+            EventType::Smtp(SmtpEvent::VirusScanError) => 589,
+            EventType::Smtp(SmtpEvent::VirusScanSkipped) => 590,
+            EventType::Queue(QueueEvent::Quarantined) => 591,
+            EventType::Queue(QueueEvent::QuarantineReleased) => 592,
+            EventType::Queue(QueueEvent::QuarantineRejected) => 593,
+            EventType::Queue(QueueEvent::QuarantineNoteAdded) => 594,
+            EventType::Smtp(SmtpEvent::DlpMatch) => 595,
+            EventType::Smtp(SmtpEvent::DlpBlocked) => 596,
+            EventType::Smtp(SmtpEvent::AttachmentLinked) => 597,
+            EventType::Queue(QueueEvent::ModerationRequested) => 598,
+            EventType::Smtp(SmtpEvent::LoopDropped) => 599,
+            EventType::Spam(SpamEvent::TrainDuplicate) => 600,
+            EventType::Spam(SpamEvent::TrainRateLimited) => 601,
+            EventType::Smtp(SmtpEvent::MaintenanceDeferred) => 602,
+            EventType::Spam(SpamEvent::OverrideError) => 603,
+            EventType::Spam(SpamEvent::UrlReputationListed) => 604,
+            EventType::Spam(SpamEvent::UrlReputationError) => 605,
+            EventType::Store(StoreEvent::PartitionDegraded) => 606,
+            EventType::Store(StoreEvent::PartitionRecovered) => 607,
+            EventType::Smtp(SmtpEvent::PartitionDegraded) => 608,
+            EventType::Delivery(DeliveryEvent::PartitionDegraded) => 609,
+            EventType::Spam(SpamEvent::DnsblDisabled) => 610,
+            EventType::Spam(SpamEvent::DnsblEnabled) => 611,
+            EventType::MtaHook(MtaHookEvent::CircuitOpen) => 612,
+            EventType::MtaHook(MtaHookEvent::Unavailable) => 613,
+            EventType::Milter(MilterEvent::CircuitOpen) => 614,
+            EventType::Milter(MilterEvent::Unavailable) => 615,
+            EventType::Sieve(SieveEvent::Notify) => 616,
+            EventType::Sieve(SieveEvent::NotifyError) => 617,
+            EventType::Delivery(DeliveryEvent::RelayBudgetAlert) => 618,
+            EventType::Delivery(DeliveryEvent::RelayBudgetExceeded) => 619,
+            EventType::Security(SecurityEvent::IpListFeedUpdated) => 620,
+            EventType::Security(SecurityEvent::IpListFeedError) => 621,
+            EventType::Smtp(SmtpEvent::ProxyConnected) => 622,
+            EventType::Smtp(SmtpEvent::ProxyError) => 623,
+            EventType::Smtp(SmtpEvent::SandboxSubmitted) => 624,
+            EventType::Smtp(SmtpEvent::SandboxError) => 625,
+            EventType::Queue(QueueEvent::SandboxVerdict) => 626,
+            EventType::Queue(QueueEvent::SandboxTimeout) => 627,
+            EventType::Queue(QueueEvent::SandboxError) => 628,
+            EventType::Imap(ImapEvent::Compress) => 629,
+            EventType::Store(StoreEvent::S3Request) => 630,
+            EventType::Store(StoreEvent::S3Retry) => 631,
+            EventType::Store(StoreEvent::S3ChecksumMismatch) => 632,
+            EventType::Server(ServerEvent::CacheWarmUp) => 633,
+            EventType::Tls(TlsEvent::TicketSecretSync) => 634,
+            EventType::Delivery(DeliveryEvent::RecipientSuppressed) => 635,
+            EventType::Store(StoreEvent::BlobArchive) => 636,
+            EventType::Store(StoreEvent::BlobRestore) => 637,
+            EventType::Store(StoreEvent::BlobDeduplicate) => 638,
+            EventType::Store(StoreEvent::BackupCompleted) => 639,
+            EventType::Store(StoreEvent::RestoreCompleted) => 640,
+            EventType::Queue(QueueEvent::Archived) => 641,
+            EventType::Queue(QueueEvent::ArchiveError) => 642,
+            EventType::Purge(PurgeEvent::Retention) => 643,
+            EventType::Purge(PurgeEvent::RetentionDryRun) => 644,
+            EventType::Purge(PurgeEvent::LegalHoldPlaced) => 645,
+            EventType::Purge(PurgeEvent::LegalHoldReleased) => 646,
+            EventType::Purge(PurgeEvent::LegalHoldRetained) => 647,
+            EventType::Smtp(SmtpEvent::ListExpansionTooLarge) => 648,
+            EventType::Queue(QueueEvent::ListDistributed) => 649,
+            EventType::IncomingReport(IncomingReportEvent::BounceReport) => 650,
+            EventType::Smtp(SmtpEvent::ListExpansionLoop) => 651,
+            EventType::Smtp(SmtpEvent::ListExpansionTooDeep) => 652,
+            EventType::Sieve(SieveEvent::ForwardDenied) => 653,
+            EventType::Sieve(SieveEvent::ForwardApprovalRequested) => 654,
+            EventType::Store(StoreEvent::GraphError) => 655,
+            EventType::Store(StoreEvent::DirectorySync) => 656,
+            EventType::Store(StoreEvent::LdapWrite) => 657,
+            EventType::Auth(AuthEvent::TokenRevoked) => 658,
+            EventType::Security(SecurityEvent::AuthenticationLockout) => 659,
+            EventType::Security(SecurityEvent::AccountAnomaly) => 660,
+            EventType::Smtp(SmtpEvent::SendingLimitExceeded) => 661,
+            EventType::Security(SecurityEvent::Impersonation) => 662,
+            EventType::Acme(AcmeEvent::TlsaRecordsUpdated) => 663,
+            EventType::Acme(AcmeEvent::TlsaRecordsUpdateFailed) => 664,
+            EventType::Acme(AcmeEvent::TlsaRolloverPending) => 665,
+            EventType::Tls(TlsEvent::CertificateReloaded) => 666,
+            EventType::Tls(TlsEvent::CertificateReloadError) => 667,
+            EventType::Tls(TlsEvent::OcspResponseUpdated) => 668,
+            EventType::Tls(TlsEvent::OcspResponseError) => 669,
+            EventType::Imap(ImapEvent::GetMetadata) => 670,
+            EventType::Imap(ImapEvent::XApplePushService) => 671,
+            EventType::Queue(QueueEvent::Rebalanced) => 672,
+            EventType::Telemetry(TelemetryEvent::EventBusError) => 673,
+            EventType::Store(StoreEvent::CassandraError) => 674,
+            EventType::Queue(QueueEvent::QuarantineExpired) => 675,
         }
     }
 
@@ -1526,6 +1616,96 @@ impl EventType {
             583 => Some(EventType::Calendar(CalendarEvent::ItipMessageSent)),
             584 => Some(EventType::Calendar(CalendarEvent::ItipMessageReceived)),
             585 => Some(EventType::Calendar(CalendarEvent::ItipMessageError)),
+            586 => Some(EventType::Smtp(SmtpEvent::MessageRewritten)),
+            587 => Some(EventType::Smtp(SmtpEvent::AttachmentDisarmed)),
+            588 => Some(EventType::Smtp(SmtpEvent::VirusFound)),
+            589 => Some(EventType::Smtp(SmtpEvent::VirusScanError)),
+            590 => Some(EventType::Smtp(SmtpEvent::VirusScanSkipped)),
+            591 => Some(EventType::Queue(QueueEvent::Quarantined)),
+            592 => Some(EventType::Queue(QueueEvent::QuarantineReleased)),
+            593 => Some(EventType::Queue(QueueEvent::QuarantineRejected)),
+            594 => Some(EventType::Queue(QueueEvent::QuarantineNoteAdded)),
+            595 => Some(EventType::Smtp(SmtpEvent::DlpMatch)),
+            596 => Some(EventType::Smtp(SmtpEvent::DlpBlocked)),
+            597 => Some(EventType::Smtp(SmtpEvent::AttachmentLinked)),
+            598 => Some(EventType::Queue(QueueEvent::ModerationRequested)),
+            599 => Some(EventType::Smtp(SmtpEvent::LoopDropped)),
+            600 => Some(EventType::Spam(SpamEvent::TrainDuplicate)),
+            601 => Some(EventType::Spam(SpamEvent::TrainRateLimited)),
+            602 => Some(EventType::Smtp(SmtpEvent::MaintenanceDeferred)),
+            603 => Some(EventType::Spam(SpamEvent::OverrideError)),
+            604 => Some(EventType::Spam(SpamEvent::UrlReputationListed)),
+            605 => Some(EventType::Spam(SpamEvent::UrlReputationError)),
+            606 => Some(EventType::Store(StoreEvent::PartitionDegraded)),
+            607 => Some(EventType::Store(StoreEvent::PartitionRecovered)),
+            608 => Some(EventType::Smtp(SmtpEvent::PartitionDegraded)),
+            609 => Some(EventType::Delivery(DeliveryEvent::PartitionDegraded)),
+            610 => Some(EventType::Spam(SpamEvent::DnsblDisabled)),
+            611 => Some(EventType::Spam(SpamEvent::DnsblEnabled)),
+            612 => Some(EventType::MtaHook(MtaHookEvent::CircuitOpen)),
+            613 => Some(EventType::MtaHook(MtaHookEvent::Unavailable)),
+            614 => Some(EventType::Milter(MilterEvent::CircuitOpen)),
+            615 => Some(EventType::Milter(MilterEvent::Unavailable)),
+            616 => Some(EventType::Sieve(SieveEvent::Notify)),
+            617 => Some(EventType::Sieve(SieveEvent::NotifyError)),
+            618 => Some(EventType::Delivery(DeliveryEvent::RelayBudgetAlert)),
+            619 => Some(EventType::Delivery(DeliveryEvent::RelayBudgetExceeded)),
+            620 => Some(EventType::Security(SecurityEvent::IpListFeedUpdated)),
+            621 => Some(EventType::Security(SecurityEvent::IpListFeedError)),
+            622 => Some(EventType::Smtp(SmtpEvent::ProxyConnected)),
+            623 => Some(EventType::Smtp(SmtpEvent::ProxyError)),
+            624 => Some(EventType::Smtp(SmtpEvent::SandboxSubmitted)),
+            625 => Some(EventType::Smtp(SmtpEvent::SandboxError)),
+            626 => Some(EventType::Queue(QueueEvent::SandboxVerdict)),
+            627 => Some(EventType::Queue(QueueEvent::SandboxTimeout)),
+            628 => Some(EventType::Queue(QueueEvent::SandboxError)),
+            629 => Some(EventType::Imap(ImapEvent::Compress)),
+            630 => Some(EventType::Store(StoreEvent::S3Request)),
+            631 => Some(EventType::Store(StoreEvent::S3Retry)),
+            632 => Some(EventType::Store(StoreEvent::S3ChecksumMismatch)),
+            633 => Some(EventType::Server(ServerEvent::CacheWarmUp)),
+            634 => Some(EventType::Tls(TlsEvent::TicketSecretSync)),
+            635 => Some(EventType::Delivery(DeliveryEvent::RecipientSuppressed)),
+            636 => Some(EventType::Store(StoreEvent::BlobArchive)),
+            637 => Some(EventType::Store(StoreEvent::BlobRestore)),
+            638 => Some(EventType::Store(StoreEvent::BlobDeduplicate)),
+            639 => Some(EventType::Store(StoreEvent::BackupCompleted)),
+            640 => Some(EventType::Store(StoreEvent::RestoreCompleted)),
+            641 => Some(EventType::Queue(QueueEvent::Archived)),
+            642 => Some(EventType::Queue(QueueEvent::ArchiveError)),
+            643 => Some(EventType::Purge(PurgeEvent::Retention)),
+            644 => Some(EventType::Purge(PurgeEvent::RetentionDryRun)),
+            645 => Some(EventType::Purge(PurgeEvent::LegalHoldPlaced)),
+            646 => Some(EventType::Purge(PurgeEvent::LegalHoldReleased)),
+            647 => Some(EventType::Purge(PurgeEvent::LegalHoldRetained)),
+            648 => Some(EventType::Smtp(SmtpEvent::ListExpansionTooLarge)),
+            649 => Some(EventType::Queue(QueueEvent::ListDistributed)),
+            650 => Some(EventType::IncomingReport(IncomingReportEvent::BounceReport)),
+            651 => Some(EventType::Smtp(SmtpEvent::ListExpansionLoop)),
+            652 => Some(EventType::Smtp(SmtpEvent::ListExpansionTooDeep)),
+            653 => Some(EventType::Sieve(SieveEvent::ForwardDenied)),
+            654 => Some(EventType::Sieve(SieveEvent::ForwardApprovalRequested)),
+            655 => Some(EventType::Store(StoreEvent::GraphError)),
+            656 => Some(EventType::Store(StoreEvent::DirectorySync)),
+            657 => Some(EventType::Store(StoreEvent::LdapWrite)),
+            658 => Some(EventType::Auth(AuthEvent::TokenRevoked)),
+            659 => Some(EventType::Security(SecurityEvent::AuthenticationLockout)),
+            660 => Some(EventType::Security(SecurityEvent::AccountAnomaly)),
+            661 => Some(EventType::Smtp(SmtpEvent::SendingLimitExceeded)),
+            662 => Some(EventType::Security(SecurityEvent::Impersonation)),
+            663 => Some(EventType::Acme(AcmeEvent::TlsaRecordsUpdated)),
+            664 => Some(EventType::Acme(AcmeEvent::TlsaRecordsUpdateFailed)),
+            665 => Some(EventType::Acme(AcmeEvent::TlsaRolloverPending)),
+            666 => Some(EventType::Tls(TlsEvent::CertificateReloaded)),
+            667 => Some(EventType::Tls(TlsEvent::CertificateReloadError)),
+            668 => Some(EventType::Tls(TlsEvent::OcspResponseUpdated)),
+            669 => Some(EventType::Tls(TlsEvent::OcspResponseError)),
+            670 => Some(EventType::Imap(ImapEvent::GetMetadata)),
+            671 => Some(EventType::Imap(ImapEvent::XApplePushService)),
+            672 => Some(EventType::Queue(QueueEvent::Rebalanced)),
+            673 => Some(EventType::Telemetry(TelemetryEvent::EventBusError)),
+            674 => Some(EventType::Store(StoreEvent::CassandraError)),
+            675 => Some(EventType::Queue(QueueEvent::QuarantineExpired)),
             _ => None,
         }
     }
@@ -1599,6 +1779,7 @@ impl Key {
             Key::ValidTo => 62,
             Key::Value => 63,
             Key::Version => 64,
+            Key::NodeId => 65,
         }
     }
 
@@ -1669,6 +1850,7 @@ impl Key {
             62 => Some(Key::ValidTo),
             63 => Some(Key::Value),
             64 => Some(Key::Version),
+            65 => Some(Key::NodeId),
             _ => None,
         }
     }
@@ -0,0 +1,94 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ring::hmac;
+
+use crate::codec::base32_custom::{Base32Reader, Base32Writer};
+
+const TOKEN_SIGNATURE_LEN: usize = 16;
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    result.copy_from_slice(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref());
+    result
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Derives a key for a specific purpose from a server secret, so that
+/// tokens issued for one feature cannot be replayed against another.
+pub fn derive_key(secret: &str, context: &str) -> [u8; 32] {
+    hmac_sha256(secret.as_bytes(), context.as_bytes())
+}
+
+/// Signs a payload and encodes it as an URL safe token.
+pub fn sign_token(key: &[u8], payload: &[u8]) -> String {
+    let mut token = Vec::with_capacity(payload.len() + TOKEN_SIGNATURE_LEN);
+    token.extend_from_slice(payload);
+    token.extend_from_slice(&hmac_sha256(key, payload)[..TOKEN_SIGNATURE_LEN]);
+    Base32Writer::from_bytes(&token).finalize()
+}
+
+/// Decodes a token created with [`sign_token`], returning its payload
+/// if it has the expected length and a valid signature.
+pub fn verify_token(key: &[u8], token: &str, payload_len: usize) -> Option<Vec<u8>> {
+    let mut token = Base32Reader::new(token.as_bytes()).collect::<Vec<_>>();
+    if token.len() != payload_len + TOKEN_SIGNATURE_LEN {
+        return None;
+    }
+    let signature = token.split_off(payload_len);
+    if constant_time_eq(&hmac_sha256(key, &token)[..TOKEN_SIGNATURE_LEN], &signature) {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_sha256_rfc4231() {
+        // Test case 2 from RFC 4231
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?"),
+            [
+                0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+                0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+                0x64, 0xec, 0x38, 0x43
+            ]
+        );
+    }
+
+    #[test]
+    fn signed_tokens() {
+        let key = derive_key("secret", "test");
+        let token = sign_token(&key, b"payload");
+        assert_eq!(
+            verify_token(&key, &token, 7).as_deref(),
+            Some(b"payload".as_slice())
+        );
+
+        // Wrong key, context or length
+        assert_eq!(verify_token(&derive_key("other", "test"), &token, 7), None);
+        assert_eq!(
+            verify_token(&derive_key("secret", "other"), &token, 7),
+            None
+        );
+        assert_eq!(verify_token(&key, &token, 6), None);
+
+        // Tampered payload
+        let mut tampered = Base32Reader::new(token.as_bytes()).collect::<Vec<_>>();
+        tampered[0] ^= 1;
+        assert_eq!(
+            verify_token(&key, &Base32Writer::from_bytes(&tampered).finalize(), 7),
+            None
+        );
+    }
+}
@@ -10,6 +10,7 @@ pub mod bimap;
 pub mod cache;
 pub mod codec;
 pub mod config;
+pub mod crypto;
 pub mod glob;
 pub mod json;
 pub mod map;
@@ -0,0 +1,210 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use common::config::telemetry::{TelemetrySubscriberType, Tracers};
+use imap_proto::ResponseType;
+use serde_json::{Value, json};
+use store::Stores;
+use trc::{
+    AuthEvent, Collector, EventType, ImapEvent, Key, TelemetryEvent,
+    ipc::subscriber::{Interests, SubscriberBuilder},
+};
+use utils::config::Config;
+
+use crate::imap::{ImapConnection, Type};
+
+use super::ClusterTest;
+
+const CONFIG: &str = r#"
+[event-bus."default"]
+store = "bus"
+topic = "stalwart.events"
+
+[event-bus."auth"]
+store = "bus"
+topic = "stalwart.auth"
+events = ["auth.failed", "auth.success"]
+lossy = true
+
+[event-bus."all"]
+store = "bus"
+topic = "stalwart.all"
+events = ["*"]
+
+[event-bus."missing"]
+store = "unknown"
+topic = "stalwart.missing"
+"#;
+
+const CONSUMER_CONFIG: &str = r#"
+[cluster]
+node-id = 100
+
+[event-bus."consumer"]
+store = "bus"
+topic = "stalwart.consumer"
+events = ["auth.failed"]
+consume = true
+"#;
+
+pub async fn test(cluster: &ClusterTest) {
+    println!("Running cluster event bus tests...");
+    let pubsub = cluster.server(0).core.storage.pubsub.clone();
+
+    // Parse event buses
+    let mut stores = Stores::default();
+    stores
+        .pubsub_stores
+        .insert("bus".to_string(), pubsub.clone());
+    let mut config = Config::new(CONFIG).unwrap();
+    let tracers = Tracers::parse(&mut config, &stores);
+    assert!(
+        config.errors.contains_key("event-bus.missing.store"),
+        "{:?}",
+        config.errors
+    );
+    let event_bus = |id: &str| {
+        tracers
+            .subscribers
+            .iter()
+            .find(|subscriber| subscriber.id == format!("b_{id}"))
+            .unwrap_or_else(|| panic!("Event bus {id} not found"))
+    };
+    assert_eq!(
+        tracers
+            .subscribers
+            .iter()
+            .filter(|subscriber| matches!(subscriber.typ, TelemetrySubscriberType::EventBus(_)))
+            .count(),
+        3
+    );
+
+    // Queue, delivery and authentication events are published by default
+    let default = event_bus("default");
+    assert!(!default.lossy);
+    let TelemetrySubscriberType::EventBus(settings) = &default.typ else {
+        panic!("Expected event bus");
+    };
+    assert_eq!(settings.topic, "stalwart.events");
+    assert!(default.interests.get(EventType::Auth(AuthEvent::Success)));
+    assert!(!default.interests.get(EventType::Imap(ImapEvent::Logout)));
+
+    // Events can be selected individually
+    let auth = event_bus("auth");
+    assert!(auth.lossy);
+    assert!(auth.interests.get(EventType::Auth(AuthEvent::Failed)));
+    assert!(auth.interests.get(EventType::Auth(AuthEvent::Success)));
+    assert!(!auth.interests.get(EventType::Auth(AuthEvent::Error)));
+
+    // Event bus errors are never published to avoid loops
+    let all = event_bus("all");
+    assert!(all.interests.get(EventType::Imap(ImapEvent::Logout)));
+    assert!(
+        !all.interests
+            .get(EventType::Telemetry(TelemetryEvent::EventBusError))
+    );
+
+    // Authentication events from all nodes are published to the event bus
+    let mut stream = pubsub.subscribe("stalwart-events").await.unwrap();
+    let mut imap = ImapConnection::connect_to(b"A1 ", "127.0.0.1:19001").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send("LOGIN john wrong-secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+    drop(imap);
+    cluster.imap_client("john", 2).await;
+
+    let mut types = AHashSet::new();
+    while types.len() < 2 {
+        let message = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("Timed out waiting for events")
+            .expect("Event stream closed");
+        let event = serde_json::from_slice::<Value>(message.payload()).unwrap();
+        let typ = event["type"].as_str().unwrap().to_string();
+        assert!(
+            typ.starts_with("auth."),
+            "Unexpected event published: {event}"
+        );
+        assert!(event["id"].is_string(), "Missing event id: {event}");
+        if typ == "auth.success" {
+            assert!(
+                event["data"].to_string().contains("john"),
+                "Missing account: {event}"
+            );
+        }
+        types.insert(typ);
+    }
+    assert!(types.contains("auth.failed") && types.contains("auth.success"));
+
+    // Events published by other nodes are dispatched to the local subscribers
+    let mut config = Config::new(CONSUMER_CONFIG).unwrap();
+    let mut tracers = Tracers::parse(&mut config, &stores);
+    let consumer = tracers.subscribers.pop().unwrap();
+    let TelemetrySubscriberType::EventBus(settings) = &consumer.typ else {
+        panic!("Expected event bus");
+    };
+    assert!(settings.consume);
+    assert_eq!(settings.node_id, 100);
+    let mut interests = Interests::default();
+    interests.set(EventType::Auth(AuthEvent::Failed));
+    let (_, mut rx) = SubscriberBuilder::new("event-bus-test".to_string())
+        .with_interests(interests.clone())
+        .register();
+    consumer.typ.spawn(
+        SubscriberBuilder::new(consumer.id.clone()).with_interests(consumer.interests),
+        false,
+    );
+    Collector::union_interests(interests);
+    Collector::reload();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut stream = pubsub.subscribe("stalwart.consumer").await.unwrap();
+    for message in [
+        json!({"node": 100, "type": "auth.failed", "data": {"accountName": "local"}}),
+        json!({"type": "auth.failed", "data": {"accountName": "anonymous"}}),
+        json!({"node": 7, "type": "auth.failed", "data": {"accountName": "remote", "spanId": 1}}),
+    ] {
+        pubsub
+            .publish("stalwart.consumer", serde_json::to_vec(&message).unwrap())
+            .await
+            .unwrap();
+    }
+    let event = loop {
+        let events = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("Timed out waiting for events")
+            .expect("Subscriber closed");
+        if let Some(event) = events
+            .into_iter()
+            .find(|event| event.value(Key::NodeId).is_some())
+        {
+            break event;
+        }
+    };
+    assert_eq!(event.inner.typ, EventType::Auth(AuthEvent::Failed));
+    assert_eq!(event.value_as_str(Key::AccountName), Some("remote"));
+    assert_eq!(event.value_as_uint(Key::NodeId), Some(7));
+    assert!(event.value(Key::SpanId).is_none());
+
+    // Received events are not published again
+    for _ in 0..3 {
+        tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("Timed out waiting for events")
+            .expect("Event stream closed");
+    }
+    assert!(
+        tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .is_err()
+    );
+
+    Collector::remove_subscriber(consumer.id);
+    Collector::remove_subscriber("event-bus-test".to_string());
+}
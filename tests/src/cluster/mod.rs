@@ -38,6 +38,8 @@ use crate::{
 };
 
 pub mod broadcast;
+pub mod event_bus;
+pub mod roles;
 pub mod stress;
 
 pub const NUM_NODES: usize = 3;
@@ -47,6 +49,8 @@ pub async fn cluster_tests() {
     let params = init_cluster_tests(true).await;
     //stress::test(params.server.clone(), params.client).await;
     broadcast::test(&params).await;
+    roles::test(&params).await;
+    event_bus::test(&params).await;
 }
 
 #[allow(dead_code)]
@@ -333,6 +337,11 @@ store = "{STORE}"
 [imap.auth]
 allow-plain-text = true
 
+[event-bus."events"]
+store = "{PUBSUB}"
+topic = "stalwart-events"
+events = ["auth.success", "auth.failed"]
+
 [oauth]
 key = "parerga_und_paralipomena"
 
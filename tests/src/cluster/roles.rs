@@ -0,0 +1,113 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::config::network::Network;
+use hyper::StatusCode;
+use utils::config::Config;
+
+use super::ClusterTest;
+
+const ROLES: &str = r#"
+[cluster.roles]
+housekeeper = [1]
+frontend = [2, 3]
+queue = [2, 1, 2]
+purge.accounts = [3]
+"#;
+
+pub async fn test(cluster: &ClusterTest) {
+    println!("Running cluster role tests...");
+
+    // Nodes have all roles unless assigned explicitly
+    let network = parse_network(3, "");
+    assert_eq!(roles(&network), [true; 7]);
+    assert!(network.roles.queue_nodes.is_empty());
+
+    // Housekeeping tasks default to the housekeeper nodes
+    let network = parse_network(1, ROLES);
+    assert_eq!(
+        roles(&network),
+        [true, false, true, true, true, true, false]
+    );
+    assert_eq!(network.roles.queue_nodes, vec![1, 2]);
+    let network = parse_network(3, ROLES);
+    assert_eq!(
+        roles(&network),
+        [false, true, false, false, false, false, true]
+    );
+    assert_eq!(network.roles.queue_nodes, vec![1, 2]);
+
+    // Nodes without the frontend role do not serve user traffic
+    let server = cluster.server(2);
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.network.roles.serve_users = false;
+    server.inner.shared_core.store(core.into());
+    for path in ["/jmap/session", "/dav/cal/john/"] {
+        assert_eq!(
+            http_status(2, path).await,
+            StatusCode::MISDIRECTED_REQUEST,
+            "{path}"
+        );
+        assert_eq!(
+            http_status(1, path).await,
+            StatusCode::UNAUTHORIZED,
+            "{path}"
+        );
+    }
+
+    // Management requests are still accepted
+    assert_eq!(
+        http_status(2, "/api/principal").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Restore roles
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.network.roles.serve_users = true;
+    server.inner.shared_core.store(core.into());
+    assert_eq!(
+        http_status(2, "/jmap/session").await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+fn parse_network(node_id: u64, roles: &str) -> Network {
+    let mut config = Config::new(format!(
+        "[server]\nhostname = \"node{node_id}.example.org\"\n[cluster]\nnode-id = {node_id}\n{roles}"
+    ))
+    .unwrap();
+    let network = Network::parse(&mut config);
+    assert!(config.errors.is_empty(), "{:?}", config.errors);
+    network
+}
+
+fn roles(network: &Network) -> [bool; 7] {
+    let roles = &network.roles;
+    [
+        roles.purge_stores,
+        roles.purge_accounts,
+        roles.renew_acme,
+        roles.calculate_metrics,
+        roles.push_metrics,
+        roles.deliver_queue,
+        roles.serve_users,
+    ]
+}
+
+async fn http_status(node_id: u32, path: &str) -> StatusCode {
+    reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .get(format!("https://127.0.0.1:1800{node_id}{path}"))
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
@@ -0,0 +1,295 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use directory::{
+    DirectoryInner, QueryBy, Type,
+    backend::{RcptType, graph::Template},
+};
+use http_proto::{JsonProblemResponse, JsonResponse, ToHttpResponse};
+use hyper::{Method, StatusCode};
+use mail_send::Credentials;
+use serde_json::json;
+use trc::{AuthEvent, EventType};
+use utils::config::utils::ParseValue;
+
+use crate::{
+    directory::DirectoryTest,
+    http_server::{HttpMessage, spawn_mock_http_server},
+};
+
+#[tokio::test]
+async fn graph_directory() {
+    // Obtain directory handle
+    let mut config = DirectoryTest::new("rocksdb".into()).await;
+    let directory = config.directories.directories.remove("graph").unwrap();
+    let DirectoryInner::Graph(graph) = &directory.store else {
+        panic!("Expected Graph directory");
+    };
+    assert_eq!(graph.sync_interval(), Duration::from_secs(900));
+
+    // Spawn mock Microsoft Graph server
+    let _tx = spawn_mock_http_server(Arc::new(move |req: HttpMessage| {
+        let path = req.uri.path().to_string();
+        if path == "/login/contoso/oauth2/v2.0/token" {
+            assert_eq!(req.method, Method::POST);
+            assert_eq!(
+                req.get_url_encoded("grant_type").as_deref(),
+                Some("client_credentials")
+            );
+            return if req.get_url_encoded("client_id").as_deref() == Some("stalwart")
+                && req.get_url_encoded("client_secret").as_deref() == Some("s3cr3t")
+            {
+                JsonResponse::new(json!({"access_token": "app-token", "expires_in": 3600}))
+                    .into_http_response()
+            } else {
+                JsonProblemResponse(StatusCode::UNAUTHORIZED).into_http_response()
+            };
+        }
+
+        // Users signed in with their own token can only read their profile
+        if path == "/graph/me" {
+            return match req.headers.get("authorization").map(|v| v.as_str()) {
+                Some("Bearer jane-token") => JsonResponse::new(json!({
+                    "id": "u-jane",
+                    "accountEnabled": true,
+                    "userPrincipalName": "jane@contoso.org",
+                    "mail": "jane@contoso.org",
+                    "displayName": "Jane Smith (Sales)",
+                    "proxyAddresses": ["SMTP:jane@contoso.org"],
+                }))
+                .into_http_response(),
+                _ => JsonProblemResponse(StatusCode::UNAUTHORIZED).into_http_response(),
+            };
+        }
+
+        assert_eq!(
+            req.headers.get("authorization").map(|v| v.as_str()),
+            Some("Bearer app-token")
+        );
+        let page = |value: serde_json::Value, next_link: Option<&str>| {
+            JsonResponse::new(json!({
+                "value": value,
+                "@odata.nextLink": next_link,
+            }))
+            .into_http_response()
+        };
+        let query = req.uri.query().unwrap_or_default();
+        match path.as_str() {
+            "/graph/users" if query.contains("skiptoken") => page(
+                json!([
+                    {
+                        "id": "u-jane",
+                        "accountEnabled": true,
+                        "userPrincipalName": "jane@contoso.org",
+                        "mail": "jane@contoso.org",
+                        "displayName": "Jane Smith",
+                    },
+                    {
+                        "id": "u-room",
+                        "accountEnabled": true,
+                        "userPrincipalName": "room@contoso.org",
+                        "displayName": "Meeting Room",
+                    },
+                ]),
+                None,
+            ),
+            "/graph/users" => {
+                let select = form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == "$select")
+                    .map(|(_, value)| value.into_owned())
+                    .unwrap_or_default();
+                assert_eq!(
+                    select,
+                    "id,accountEnabled,userPrincipalName,mail,displayName,proxyAddresses,quotaBytes"
+                );
+                page(
+                    json!([
+                        {
+                            "id": "u-john",
+                            "accountEnabled": true,
+                            "userPrincipalName": "John@Contoso.org",
+                            "mail": "John@Contoso.org",
+                            "displayName": "John Doe",
+                            "proxyAddresses": [
+                                "SMTP:john@contoso.org",
+                                "smtp:jdoe@contoso.org",
+                                "X500:/o=ExchangeLabs/cn=Recipients/cn=jdoe"
+                            ],
+                            "quotaBytes": "1048576",
+                        },
+                        {
+                            "id": "u-bill",
+                            "accountEnabled": false,
+                            "userPrincipalName": "bill@contoso.org",
+                            "mail": "bill@contoso.org",
+                            "displayName": "Bill Foobar",
+                        },
+                    ]),
+                    Some("https://127.0.0.1:9090/graph/users?$skiptoken=page2"),
+                )
+            }
+            "/graph/groups" => page(
+                json!([
+                    {
+                        "id": "g-sales",
+                        "displayName": "Sales Team",
+                        "mail": "sales@contoso.org",
+                        "mailNickname": "sales",
+                        "mailEnabled": true,
+                        "securityEnabled": false,
+                    },
+                    {
+                        "id": "g-staff",
+                        "displayName": "All Staff",
+                        "mailNickname": "Staff",
+                        "mailEnabled": false,
+                        "securityEnabled": true,
+                    },
+                ]),
+                None,
+            ),
+            "/graph/groups/g-sales/members" => page(
+                json!([
+                    {"id": "u-john"},
+                    {"id": "u-jane"},
+                ]),
+                None,
+            ),
+            "/graph/groups/g-staff/members" => page(
+                json!([
+                    {"id": "u-john"},
+                    {"id": "u-unknown"},
+                    {"id": "g-staff"},
+                ]),
+                None,
+            ),
+            _ => panic!("Unexpected request: {:?}", req),
+        }
+    }))
+    .await;
+
+    // Users and groups are imported into the internal directory
+    let result = graph.sync().await.unwrap();
+    assert_eq!(
+        (result.users, result.groups, result.skipped),
+        (2, 2, 2),
+        "{result:?}"
+    );
+    let john = directory
+        .query(QueryBy::Name("john@contoso.org"), true)
+        .await
+        .unwrap()
+        .expect("John was not synchronized");
+    assert_eq!(john.typ(), Type::Individual);
+    assert_eq!(john.description(), Some("John Doe"));
+    assert_eq!(john.quota(), 1048576);
+    assert_eq!(john.emails, vec!["john@contoso.org", "jdoe@contoso.org"]);
+
+    // Disabled users and users without a mailbox are skipped
+    for name in ["bill@contoso.org", "room@contoso.org"] {
+        assert!(
+            directory
+                .query(QueryBy::Name(name), false)
+                .await
+                .unwrap()
+                .is_none(),
+            "{name} was synchronized"
+        );
+    }
+
+    // Distribution groups become mailing lists
+    let sales = directory
+        .query(QueryBy::Name("sales"), false)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(sales.typ(), Type::List);
+    assert_eq!(sales.description(), Some("Sales Team"));
+    match directory.rcpt("sales@contoso.org").await.unwrap() {
+        RcptType::List(mut members) => {
+            members.sort();
+            assert_eq!(members, vec!["jane@contoso.org", "john@contoso.org"]);
+        }
+        other => panic!("Unexpected result {other:?}"),
+    }
+
+    // Security groups become groups, unknown members are ignored
+    let staff = directory
+        .query(QueryBy::Name("staff"), false)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(staff.typ(), Type::Group);
+    assert_eq!(john.member_of(), &[staff.id()]);
+    assert!(john.lists().contains(&sales.id()));
+
+    // Synchronizing again keeps the same principals
+    let result = graph.sync().await.unwrap();
+    assert_eq!((result.users, result.groups), (2, 2));
+    assert_eq!(
+        directory
+            .query(QueryBy::Name("john@contoso.org"), false)
+            .await
+            .unwrap()
+            .unwrap()
+            .id(),
+        john.id()
+    );
+
+    // Signed-in users are looked up with their own token
+    let err = directory
+        .query(
+            QueryBy::Credentials(&Credentials::OAuthBearer {
+                token: "invalid_or_expired_token".to_string(),
+            }),
+            false,
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        err.matches(EventType::Auth(AuthEvent::Failed)),
+        "Unexpected error: {:?}",
+        err
+    );
+    let jane = directory
+        .query(
+            QueryBy::Credentials(&Credentials::OAuthBearer {
+                token: "jane-token".to_string(),
+            }),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(jane.name(), "jane@contoso.org");
+    assert_eq!(
+        jane.emails.first().map(|s| s.as_str()),
+        Some("jane@contoso.org")
+    );
+
+    // Profile changes are stored in the internal directory
+    assert_eq!(
+        directory
+            .query(QueryBy::Name("jane@contoso.org"), false)
+            .await
+            .unwrap()
+            .unwrap()
+            .description(),
+        Some("Jane Smith (Sales)")
+    );
+
+    // Attribute templates must reference at least one attribute
+    assert!(Template::parse_value("{mailNickname}@contoso.org").is_ok());
+    assert!(Template::parse_value("{onPremisesExtensionAttributes.extensionAttribute1}").is_ok());
+    for invalid in ["sales@contoso.org", "{}", "{mail", "{a{b}}"] {
+        assert!(
+            Template::parse_value(invalid).is_err(),
+            "{invalid:?} was accepted"
+        );
+    }
+}
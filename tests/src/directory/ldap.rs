@@ -7,10 +7,15 @@
 use std::fmt::Debug;
 
 use directory::{
-    QueryBy, ROLE_USER, Type,
-    backend::{RcptType, internal::manage::ManageDirectory},
+    Directories, QueryBy, ROLE_USER, Type,
+    backend::{
+        RcptType,
+        internal::{PrincipalField, PrincipalUpdate, PrincipalValue, manage::ManageDirectory},
+    },
 };
 use mail_send::Credentials;
+use store::Stores;
+use utils::config::Config;
 
 use crate::directory::{
     DirectoryTest, IntoTestPrincipal, TestPrincipal, map_account_id, map_account_ids,
@@ -268,6 +273,148 @@ async fn ldap_directory() {
     );*/
 }
 
+const WRITE_CONFIG: &str = r#"
+[directory."attribute"]
+type = "ldap"
+url = "ldap://127.0.0.1:1"
+base-dn = "dc=example,dc=org"
+timeout = "1s"
+filter.name = "(&(objectClass=posixAccount)(uid=?))"
+filter.email = "(&(objectClass=posixAccount)(mail=?))"
+attributes.name = "uid"
+attributes.secret = "userPassword"
+attributes.email = "mail"
+attributes.email-alias = "mailAlias"
+attributes.quota = "diskQuota"
+write.enable = true
+write.secret.method = "attribute"
+write.bind.dn = "cn=admin,dc=example,dc=org"
+write.bind.secret = "secret"
+
+[directory."password-modify"]
+type = "ldap"
+url = "ldap://127.0.0.1:1"
+base-dn = "dc=example,dc=org"
+timeout = "1s"
+filter.name = "(&(objectClass=posixAccount)(uid=?))"
+filter.email = "(&(objectClass=posixAccount)(mail=?))"
+attributes.name = "uid"
+attributes.email = "mail"
+attributes.quota = "diskQuota"
+write.enable = true
+write.secret.method = "password-modify"
+write.attributes.quota = ""
+write.bind.dn = "cn=admin,dc=example,dc=org"
+write.bind.secret = "secret"
+
+[directory."read-only"]
+type = "ldap"
+url = "ldap://127.0.0.1:1"
+base-dn = "dc=example,dc=org"
+filter.name = "(&(objectClass=posixAccount)(uid=?))"
+filter.email = "(&(objectClass=posixAccount)(mail=?))"
+attributes.name = "uid"
+attributes.secret = "userPassword"
+attributes.email = "mail"
+attributes.quota = "diskQuota"
+
+[directory."invalid"]
+type = "ldap"
+url = "ldap://127.0.0.1:1"
+base-dn = "dc=example,dc=org"
+write.enable = true
+write.secret.method = "plaintext"
+write.bind.dn = "cn=admin,dc=example,dc=org"
+write.bind.secret = "secret"
+"#;
+
+#[tokio::test]
+async fn ldap_write_through() {
+    // Enable logging
+    crate::enable_logging();
+
+    let mut config = Config::new(WRITE_CONFIG).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let mut directories = Directories::parse(&mut config, &stores, Default::default(), true)
+        .await
+        .directories;
+
+    // Unknown secret write methods are rejected
+    assert!(!directories.contains_key("invalid"));
+    assert!(
+        config
+            .errors
+            .contains_key("directory.invalid.write.secret.method")
+    );
+
+    // Written fields depend on the configured attributes and secret method
+    let attribute = directories.remove("attribute").unwrap();
+    let password_modify = directories.remove("password-modify").unwrap();
+    let read_only = directories.remove("read-only").unwrap();
+    for (field, expected) in [
+        (PrincipalField::Quota, [true, false, false]),
+        (PrincipalField::Emails, [true, true, false]),
+        (PrincipalField::Secrets, [true, true, false]),
+        (PrincipalField::Description, [false, false, false]),
+        (PrincipalField::Name, [false, false, false]),
+    ] {
+        assert_eq!(
+            [
+                attribute.can_write_through(field),
+                password_modify.can_write_through(field),
+                read_only.can_write_through(field),
+            ],
+            expected,
+            "{field:?}"
+        );
+    }
+
+    // Changes to fields that are not written back do not contact the server
+    let local_changes = [PrincipalUpdate::set(
+        PrincipalField::Description,
+        PrincipalValue::String("John Doe".into()),
+    )];
+    attribute
+        .write_through("john", &local_changes)
+        .await
+        .unwrap();
+    password_modify
+        .write_through(
+            "john",
+            &[PrincipalUpdate::set(
+                PrincipalField::Quota,
+                PrincipalValue::Integer(1024),
+            )],
+        )
+        .await
+        .unwrap();
+
+    // Directories without write-through ignore all changes
+    let changes = [
+        PrincipalUpdate::set(PrincipalField::Quota, PrincipalValue::Integer(1024)),
+        PrincipalUpdate::add_item(
+            PrincipalField::Emails,
+            PrincipalValue::String("jdoe@example.org".into()),
+        ),
+        PrincipalUpdate::set(
+            PrincipalField::Secrets,
+            PrincipalValue::StringList(vec!["n3w-p4ss".into()]),
+        ),
+    ];
+    read_only.write_through("john", &changes).await.unwrap();
+
+    // Write failures are reported so the local change can be aborted
+    for directory in [&attribute, &password_modify] {
+        assert!(directory.write_through("john", &changes).await.is_err());
+        assert!(
+            directory
+                .write_through("john", &changes[2..])
+                .await
+                .is_err()
+        );
+    }
+}
+
 fn compare_sorted<T: Eq + Debug>(v1: Vec<T>, v2: Vec<T>) {
     for val in v1.iter() {
         assert!(v2.contains(val), "{v1:?} != {v2:?}");
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod graph;
 pub mod imap;
 pub mod internal;
 pub mod ldap;
@@ -11,7 +12,10 @@ pub mod oidc;
 pub mod smtp;
 pub mod sql;
 
-use common::{Core, Server, config::smtp::session::AddressMapping};
+use common::{
+    Core, Server,
+    config::smtp::session::{AddressMapping, SessionConfig},
+};
 use directory::{
     Directories, Principal, Type,
     backend::internal::{PrincipalField, PrincipalSet, manage::ManageDirectory},
@@ -38,6 +42,7 @@ store = "foundationdb"
 [directory."sqlite"]
 type = "sql"
 store = "sqlite"
+groups.max-depth = 2
 
 [directory."sqlite".columns]
 name = "name"
@@ -75,6 +80,7 @@ lookup = "sqlite"
 [directory."postgresql"]
 type = "sql"
 store = "postgresql"
+groups.max-depth = 2
 
 [directory."postgresql".columns]
 name = "name"
@@ -106,6 +112,7 @@ domains = "SELECT 1 FROM emails WHERE address LIKE '%@' || $1 LIMIT 1"
 [directory."mysql"]
 type = "sql"
 store = "mysql"
+groups.max-depth = 2
 
 [directory."mysql".columns]
 name = "name"
@@ -348,6 +355,29 @@ fields.email = "email"
 fields.username = "preferred_username"
 fields.full-name = "name"
 
+[directory."oidc-jwks"]
+type = "oidc"
+store = "rocksdb"
+timeout = "1s"
+endpoint.url = "https://127.0.0.1:9090/jwks"
+endpoint.method = "jwks"
+jwt.issuer = "https://idp.example.org"
+jwt.audience = ["stalwart", "other-app"]
+fields.email = "email"
+fields.username = "preferred_username"
+fields.full-name = "name"
+
+[directory."graph"]
+type = "graph"
+tenant-id = "contoso"
+timeout = "1s"
+auth.client-id = "stalwart"
+auth.secret = "s3cr3t"
+endpoint.login = "https://127.0.0.1:9090/login/"
+endpoint.graph = "https://127.0.0.1:9090/graph"
+sync.interval = "15m"
+fields.quota = "quotaBytes"
+
 "#;
 
 pub struct DirectoryStore {
@@ -766,6 +796,73 @@ async fn address_mappings() {
     }
 }
 
+#[tokio::test]
+async fn address_normalization() {
+    const CONFIG: &str = r#"
+    [session.rcpt]
+    delimiters = ["+", "-"]
+
+    [session.rcpt.normalize.dots]
+    domain = "example.org"
+    match = "\\."
+    replace = ""
+    "#;
+
+    let mut config = utils::config::Config::new(CONFIG).unwrap();
+    let mut core = Core::default();
+    core.smtp.session = SessionConfig::parse(&mut config);
+    config.assert_no_errors();
+    let server = Server {
+        inner: Default::default(),
+        core: core.into(),
+    };
+    let rcpt = &server.core.smtp.session.rcpt;
+
+    for (address, expected_canonical, expected_sieve) in [
+        (
+            "john.doe+alias@example.org",
+            "johndoe@example.org",
+            "johndoe+alias@example.org",
+        ),
+        (
+            "john.doe-alias@example.org",
+            "johndoe@example.org",
+            "johndoe+alias@example.org",
+        ),
+        (
+            "j.o.h.n.doe@example.org",
+            "johndoe@example.org",
+            "johndoe@example.org",
+        ),
+        (
+            "john.doe-alias@example.com",
+            "john.doe@example.com",
+            "john.doe+alias@example.com",
+        ),
+        ("jane@example.com", "jane@example.com", "jane@example.com"),
+    ] {
+        assert_eq!(
+            server.canonical_address(address, 0).await,
+            expected_canonical,
+            "failed canonical address for {address:?}"
+        );
+        assert_eq!(
+            rcpt.sieve_address(address),
+            expected_sieve,
+            "failed sieve address for {address:?}"
+        );
+    }
+
+    assert_eq!(
+        rcpt.normalize_address("john.doe@example.org"),
+        rcpt.normalize_address("johndoe@example.org")
+    );
+    assert_ne!(
+        rcpt.normalize_address("john.doe@example.com"),
+        rcpt.normalize_address("johndoe@example.com")
+    );
+}
+
 async fn map_account_ids(store: &Store, names: Vec<impl AsRef<str>>) -> Vec<u32> {
     let mut ids = Vec::with_capacity(names.len());
     for name in names {
@@ -8,14 +8,25 @@
  *
  */
 
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
 
-use base64::{Engine, engine::general_purpose};
+use base64::{
+    Engine,
+    engine::general_purpose::{self, URL_SAFE_NO_PAD},
+};
 use directory::QueryBy;
 use http_proto::{JsonProblemResponse, JsonResponse, ToHttpResponse};
 use hyper::{Method, StatusCode};
 use mail_send::Credentials;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
 use serde_json::json;
+use store::write::now;
 use trc::{AuthEvent, EventType};
 
 use crate::{
@@ -30,8 +41,27 @@ async fn oidc_directory() {
     // Obtain directory handle
     let mut config = DirectoryTest::new("rocksdb".into()).await;
 
+    // Generate JWT signing key
+    let key_pair = Ed25519KeyPair::from_pkcs8(
+        Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .unwrap()
+            .as_ref(),
+    )
+    .unwrap();
+    let jwks = json!({
+        "keys": [{
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "kid": "test-key",
+            "use": "sig",
+            "x": URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+        }]
+    });
+
     // Spawn mock OIDC server
-    let _tx = spawn_mock_http_server(Arc::new(|req: HttpMessage| {
+    let jwks_fetches = Arc::new(AtomicUsize::new(0));
+    let jwks_fetches_ = jwks_fetches.clone();
+    let _tx = spawn_mock_http_server(Arc::new(move |req: HttpMessage| {
         let success_response = JsonResponse::new(json!({
             "email": "john@example.org",
             "preferred_username": "jdoe",
@@ -88,6 +118,10 @@ async fn oidc_directory() {
                 Some(_) => JsonProblemResponse(StatusCode::UNAUTHORIZED).into_http_response(),
                 None => panic!("Missing Authorization header: {req:#?}"),
             },
+            (Method::GET, Some("jwks")) => {
+                jwks_fetches_.fetch_add(1, Ordering::Relaxed);
+                JsonResponse::new(jwks.clone()).into_http_response()
+            }
             _ => panic!("Unexpected request: {:?}", req),
         }
     }))
@@ -137,4 +171,96 @@ async fn oidc_directory() {
         );
         assert_eq!(principal.description(), Some("John Doe"));
     }
+
+    // Test JWTs validated against the key set
+    println!("Running OIDC test \"oidc-jwks\"...");
+    let directory = config.directories.directories.remove("oidc-jwks").unwrap();
+    let sign_jwt_with_kid = |kid: &str, claims: serde_json::Value| {
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(json!({"alg": "EdDSA", "kid": kid}).to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = URL_SAFE_NO_PAD.encode(key_pair.sign(message.as_bytes()).as_ref());
+        format!("{message}.{signature}")
+    };
+    let sign_jwt = |claims: serde_json::Value| sign_jwt_with_kid("test-key", claims);
+    let claims = |iss: &str, aud: &str, exp: u64| {
+        json!({
+            "iss": iss,
+            "aud": aud,
+            "exp": exp,
+            "email": "jane@example.org",
+            "preferred_username": "jsmith",
+            "name": "Jane Smith",
+        })
+    };
+    let valid_token = sign_jwt(claims("https://idp.example.org", "other-app", now() + 3600));
+    let forged_token = format!(
+        "{}.{}",
+        sign_jwt(claims("https://idp.example.org", "stalwart", now() + 7200))
+            .rsplit_once('.')
+            .unwrap()
+            .0,
+        valid_token.rsplit_once('.').unwrap().1
+    );
+
+    for token in [
+        TEST_TOKEN.to_string(),
+        forged_token,
+        sign_jwt(claims("https://evil.example.org", "stalwart", now() + 3600)),
+        sign_jwt(claims("https://idp.example.org", "evil-app", now() + 3600)),
+        sign_jwt(claims("https://idp.example.org", "stalwart", now() - 3600)),
+    ] {
+        let err = directory
+            .query(
+                QueryBy::Credentials(&Credentials::OAuthBearer { token }),
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.matches(EventType::Auth(AuthEvent::Failed)),
+            "Unexpected error: {:?}",
+            err
+        );
+    }
+
+    let principal = directory
+        .query(
+            QueryBy::Credentials(&Credentials::OAuthBearer { token: valid_token }),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(principal.name(), "jsmith");
+    assert_eq!(
+        principal.emails.first().map(|s| s.as_str()),
+        Some("jane@example.org")
+    );
+    assert_eq!(principal.description(), Some("Jane Smith"));
+    assert_eq!(jwks_fetches.load(Ordering::Relaxed), 1);
+
+    // Unknown key ids must not trigger a key set refresh on every request
+    for _ in 0..5 {
+        let err = directory
+            .query(
+                QueryBy::Credentials(&Credentials::OAuthBearer {
+                    token: sign_jwt_with_kid(
+                        "unknown-key",
+                        claims("https://idp.example.org", "stalwart", now() + 3600),
+                    ),
+                }),
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.matches(EventType::Auth(AuthEvent::Failed)),
+            "Unexpected error: {:?}",
+            err
+        );
+    }
+    assert_eq!(jwks_fetches.load(Ordering::Relaxed), 1);
 }
@@ -5,7 +5,7 @@
  */
 
 use directory::{
-    QueryBy, ROLE_USER, Type,
+    Directory, QueryBy, ROLE_USER, Type,
     backend::{RcptType, internal::manage::ManageDirectory},
 };
 use mail_send::Credentials;
@@ -250,6 +250,48 @@ async fn sql_directory() {
             }
         );
 
+        // Nested groups are resolved up to the configured depth
+        store.add_to_group("sales", "staff").await;
+        store.add_to_group("staff", "company").await;
+        store.add_to_group("company", "everyone").await;
+        store.add_to_group("staff", "sales").await;
+        assert_eq!(
+            member_of(&handle, "jane").await,
+            group_ids(base_store, &["sales", "support", "staff", "company"]).await
+        );
+        assert!(
+            base_store
+                .get_principal_id("everyone")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Group entries are cached until invalidated
+        store.add_to_group("support", "helpdesk").await;
+        assert_eq!(
+            member_of(&handle, "jane").await,
+            group_ids(base_store, &["sales", "support", "staff", "company"]).await
+        );
+        handle.invalidate_group_cache();
+        assert_eq!(
+            member_of(&handle, "jane").await,
+            group_ids(
+                base_store,
+                &["sales", "support", "staff", "company", "helpdesk"]
+            )
+            .await
+        );
+        for (name, group) in [
+            ("sales", "staff"),
+            ("staff", "company"),
+            ("company", "everyone"),
+            ("staff", "sales"),
+            ("support", "helpdesk"),
+        ] {
+            store.remove_from_group(name, group).await;
+        }
+
         // Ids by email
         assert_eq!(
             core.email_to_id(&handle, "jane@example.org", 0)
@@ -353,6 +395,27 @@ async fn sql_directory() {
     }
 }
 
+async fn member_of(handle: &Directory, name: &str) -> Vec<String> {
+    handle
+        .query(QueryBy::Name(name), true)
+        .await
+        .unwrap()
+        .unwrap()
+        .into_test()
+        .into_sorted()
+        .member_of
+}
+
+async fn group_ids(store: &Store, names: &[&str]) -> Vec<String> {
+    let mut ids = map_account_ids(store, names.to_vec())
+        .await
+        .into_iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids
+}
+
 impl DirectoryStore {
     pub async fn create_test_directory(&self) {
         // Create tables
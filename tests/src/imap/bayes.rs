@@ -96,6 +96,13 @@ pub async fn test(handle: &IMAPTest) {
     let w = handle.spam_weights(account_id).await;
     assert_eq!(w.ham, 11);
     assert_eq!(w.spam, 10);
+
+    // Copying an already learned message into Junk should not train again
+    imap.send_ok("SELECT \"Junk Mail\"").await;
+    imap.send_ok("COPY 1 \"Junk Mail\"").await;
+    let w = handle.spam_weights(account_id).await;
+    assert_eq!(w.ham, 11);
+    assert_eq!(w.spam, 10);
 }
 
 impl ImapConnection {
@@ -6,7 +6,7 @@
 
 use std::fs;
 
-use email::message::metadata::MessageMetadata;
+use email::message::metadata::{MessageBodyStats, MessageMetadata};
 use imap::op::fetch::AsImapDataItem;
 use imap_proto::{
     ResponseCode, StatusResponse,
@@ -33,6 +33,13 @@ fn imap_test_body_structure() {
         let mut buf = Vec::new();
         let raw_message = fs::read(&file_name).unwrap();
         let message_ = MessageParser::new().parse(&raw_message).unwrap();
+        let body_stats_ = Archive::deserialize_owned(
+            Archiver::new(MessageBodyStats::new(&message_))
+                .serialize()
+                .unwrap(),
+        )
+        .unwrap();
+        let body_stats = body_stats_.unarchive::<MessageBodyStats>().unwrap();
         let metadata = MessageMetadata {
             preview: Default::default(),
             size: message_.raw_message.len() as u32,
@@ -56,14 +63,34 @@ fn imap_test_body_structure() {
             Archive::deserialize_owned(Archiver::new(metadata).serialize().unwrap()).unwrap();
         let metadata = metadata_.unarchive::<MessageMetadata>().unwrap();
         let decoded = metadata.decode_contents(&raw_message);
+        assert!(body_stats.matches(metadata), "{}", file_name.display());
 
         //let c = println!("parts {:#?}", decoded);
 
+        // Body statistics produce the same structure using only the message headers
+        let decoded_headers = metadata.decode_contents(metadata.raw_headers.as_slice());
+        for is_extended in [false, true] {
+            let mut expected = Vec::new();
+            metadata
+                .body_structure(&decoded, is_extended, None)
+                .serialize(&mut expected, is_extended);
+            let mut result = Vec::new();
+            metadata
+                .body_structure(&decoded_headers, is_extended, Some(body_stats))
+                .serialize(&mut result, is_extended);
+            assert_eq!(
+                String::from_utf8(result).unwrap(),
+                String::from_utf8(expected).unwrap(),
+                "{}",
+                file_name.display()
+            );
+        }
+
         // Serialize body and bodystructure
         for is_extended in [false, true] {
             let mut buf_ = Vec::new();
             metadata
-                .body_structure(&decoded, is_extended)
+                .body_structure(&decoded, is_extended, None)
                 .serialize(&mut buf_, is_extended);
             if is_extended {
                 buf.extend_from_slice(b"BODYSTRUCTURE ");
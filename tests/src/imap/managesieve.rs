@@ -15,9 +15,9 @@ use tokio::{
 };
 use tokio_rustls::client::TlsStream;
 
-use super::AssertResult;
+use super::{AssertResult, IMAPTest};
 
-pub async fn test() {
+pub async fn test(handle: &IMAPTest) {
     println!("Running ManageSieve tests...");
 
     // Connect to ManageSieve
@@ -43,6 +43,32 @@ pub async fn test() {
     sieve.send("CHECKSCRIPT \"keep :invalidtag;\"").await;
     sieve.assert_read(ResponseType::No).await;
 
+    // Errors include structured diagnostics
+    sieve.send("CHECKSCRIPT \"fileinto \\\"Inbox\\\";\"").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains(", column ")
+        .assert_contains("[undeclared-capability]")
+        .assert_contains("Add the extension to the require statement");
+    sieve.send("CHECKSCRIPT \"keep\"").await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains("[unexpected-eof]")
+        .assert_contains("Check for a missing semicolon");
+    sieve
+        .send_literal(
+            "PUTSCRIPT \"broken\" ",
+            "require \"fileinto\";\r\nvacation \"x\";\r\n",
+        )
+        .await;
+    sieve
+        .assert_read(ResponseType::No)
+        .await
+        .assert_contains(", column ")
+        .assert_contains("[undeclared-capability]");
+
     // PutScript
     sieve
         .send_literal("PUTSCRIPT \"simple script\" ", "if true { keep; }\r\n")
@@ -126,6 +152,42 @@ pub async fn test() {
         .assert_contains("holidays")
         .assert_count("ACTIVE", 0);
 
+    // Script count and size limits are shared with JMAP
+    let mut core = handle.server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.sieve_max_scripts = 2;
+    core.jmap.sieve_max_script_size = 32;
+    handle.server.inner.shared_core.store(core.into());
+    let mut limited = SieveConnection::connect().await;
+    limited.assert_read(ResponseType::Ok).await;
+    limited
+        .send("AUTHENTICATE \"PLAIN\" \"AGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0\"")
+        .await;
+    limited.assert_read(ResponseType::Ok).await;
+    for (cmd, expected) in [
+        ("HAVESPACE \"holidays\" 10", None),
+        ("HAVESPACE \"new script\" 10", Some("QUOTA/MAXSCRIPTS")),
+        ("HAVESPACE \"holidays\" 100", Some("QUOTA/MAXSIZE")),
+        (
+            "PUTSCRIPT \"new script\" \"keep;\"",
+            Some("QUOTA/MAXSCRIPTS"),
+        ),
+        ("PUTSCRIPT \"holidays\" \"discard;\"", None),
+    ] {
+        limited.send(cmd).await;
+        if let Some(expected) = expected {
+            limited
+                .assert_read(ResponseType::No)
+                .await
+                .assert_contains(expected);
+        } else {
+            limited.assert_read(ResponseType::Ok).await;
+        }
+    }
+    let mut core = handle.server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.sieve_max_scripts = handle.server.core.jmap.sieve_max_scripts;
+    core.jmap.sieve_max_script_size = handle.server.core.jmap.sieve_max_script_size;
+    handle.server.inner.shared_core.store(core.into());
+
     // DeleteScript
     sieve.send("DELETESCRIPT \"holidays\"").await;
     sieve.assert_read(ResponseType::Ok).await;
@@ -113,7 +113,7 @@ pub async fn imap_tests() {
     bayes::test(&handle).await;
 
     // Run ManageSieve tests
-    managesieve::test().await;
+    managesieve::test(&handle).await;
 
     // Run POP3 tests
     pop::test().await;
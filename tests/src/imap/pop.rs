@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use imap_proto::ResponseType as ImapResponseType;
+use jmap_proto::types::id::Id;
 use mail_send::smtp::tls::build_tls_connector;
 use rustls_pki_types::ServerName;
 use std::time::Duration;
@@ -13,7 +15,11 @@ use tokio::{
 };
 use tokio_rustls::client::TlsStream;
 
-use crate::{jmap::delivery::SmtpConnection, smtp::session::VerifyResponse};
+use crate::{
+    imap::{ImapConnection, Type},
+    jmap::delivery::SmtpConnection,
+    smtp::session::VerifyResponse,
+};
 
 pub async fn test() {
     println!("Running POP3 tests...");
@@ -187,6 +193,119 @@ pub async fn test() {
         .await
         .assert_contains("+OK 0 0");
     pop3.send("QUIT").await;
+    pop3.assert_read(ResponseType::Ok).await;
+
+    // UIDLs are derived from message ids
+    ingest_messages(&[
+        test_message("Stable 0", "Stable UIDL 0.\r\n"),
+        test_message("Stable 1", "Stable UIDL 1.\r\n"),
+    ])
+    .await;
+    let mut pop3 = Pop3Connection::connect_and_login().await;
+    let uidls = pop3.uidls().await;
+    assert_eq!(uidls.len(), 2);
+    assert_ne!(uidls[0], uidls[1]);
+    for uidl in &uidls {
+        assert!(Id::from_bytes(uidl.as_bytes()).is_some(), "{uidl}");
+    }
+    pop3.send("QUIT").await;
+    pop3.assert_read(ResponseType::Ok).await;
+
+    // UIDLs do not change when messages are moved out of and back into the INBOX
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ImapResponseType::Ok).await;
+    imap.authenticate("popper@example.com", "secret").await;
+    for cmd in [
+        "CREATE \"POP Archive\"",
+        "SELECT INBOX",
+        "MOVE 1 \"POP Archive\"",
+        "SELECT \"POP Archive\"",
+        "MOVE 1 INBOX",
+        "UNSELECT",
+        "DELETE \"POP Archive\"",
+    ] {
+        imap.send(cmd).await;
+        imap.assert_read(Type::Tagged, ImapResponseType::Ok).await;
+    }
+    let mut pop3 = Pop3Connection::connect_and_login().await;
+    assert_eq!(pop3.uidls().await, [uidls[1].clone(), uidls[0].clone()]);
+    pop3.send("UIDL 2").await;
+    pop3.assert_read(ResponseType::Ok)
+        .await
+        .assert_contains(&format!("+OK 2 {}", uidls[0]));
+    pop3.send("DELE 1\r\nDELE 2").await;
+    pop3.assert_read(ResponseType::Ok).await;
+    pop3.assert_read(ResponseType::Ok).await;
+    pop3.send("QUIT").await;
+    pop3.assert_read(ResponseType::Ok).await;
+
+    // TOP returns the requested number of lines without reading the full message
+    ingest_messages(&[
+        test_message(
+            "Many lines",
+            &(0..2000)
+                .map(|i| format!("Line {i:04} {}\r\n", "x".repeat(70)))
+                .collect::<String>(),
+        ),
+        test_message(
+            "Long lines",
+            &(0..20)
+                .map(|i| format!("Long {i:02} {}\r\n", "y".repeat(1000)))
+                .collect::<String>(),
+        ),
+    ])
+    .await;
+    let mut pop3 = Pop3Connection::connect_and_login().await;
+    for (msg, lines, expected, not_expected) in [
+        (1, 5, "Subject: Many lines", "Line 0000"),
+        (1, 100, "Line 0050", "Line 0100"),
+        (1, 1999, "Line 1900", "Line 1999"),
+        (2, 20, "Long 04", "Long 19"),
+    ] {
+        pop3.send(&format!("LIST {msg}")).await;
+        let size = pop3.assert_read(ResponseType::Ok).await[0]
+            .rsplit_once(' ')
+            .unwrap()
+            .1
+            .to_string();
+        pop3.send(&format!("TOP {msg} {lines}")).await;
+        pop3.assert_read(ResponseType::Multiline)
+            .await
+            .assert_contains(&format!("+OK {size} octets"))
+            .assert_contains(expected)
+            .assert_not_contains(not_expected);
+    }
+    pop3.send("RETR 1").await;
+    pop3.assert_read(ResponseType::Multiline)
+        .await
+        .assert_contains("Line 1999");
+    pop3.send("DELE 1\r\nDELE 2").await;
+    pop3.assert_read(ResponseType::Ok).await;
+    pop3.assert_read(ResponseType::Ok).await;
+    pop3.send("QUIT").await;
+    pop3.assert_read(ResponseType::Ok).await;
+}
+
+fn test_message(subject: &str, body: &str) -> String {
+    format!(
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: popper@example.com\r\n",
+            "Subject: {}\r\n",
+            "X-Spam-Status: No\r\n",
+            "\r\n",
+            "{}"
+        ),
+        subject, body
+    )
+}
+
+async fn ingest_messages(messages: &[String]) {
+    for message in messages {
+        let mut lmtp = SmtpConnection::connect_port(11201).await;
+        lmtp.ingest("bill@example.com", &["popper@example.com"], message)
+            .await;
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -265,6 +384,19 @@ impl Pop3Connection {
         }
     }
 
+    pub async fn uidls(&mut self) -> Vec<String> {
+        self.send("UIDL").await;
+        self.assert_read(ResponseType::Multiline)
+            .await
+            .into_iter()
+            .filter_map(|line| {
+                line.split_once(' ')
+                    .filter(|(msg, _)| msg.parse::<u32>().is_ok())
+                    .map(|(_, uidl)| uidl.to_string())
+            })
+            .collect()
+    }
+
     pub async fn send(&mut self, text: &str) {
         //let c = println!("-> {:?}", text);
         self.writer.write_all(text.as_bytes()).await.unwrap();
@@ -120,4 +120,61 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_contains("COUNT 10 ALL 6,4:5,1,10,9,3,7:8,2");
+
+    // Fuzzy search
+    imap_check.send("CAPABILITY").await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("SEARCH=FUZZY");
+
+    imap_check
+        .send("UID SEARCH FUZZY SUBJECT \"multipart argentina\"")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SEARCH 1 3 7");
+
+    imap_check
+        .send("UID SEARCH RETURN (ALL RELEVANCY) FUZZY SUBJECT \"multipart argentina\"")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ALL 1,3,7 RELEVANCY (50 50 50)");
+
+    imap_check
+        .send("UID SEARCH RETURN (ALL RELEVANCY) OR FUZZY FROM nathaniel FUZZY SUBJECT multipart")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("ALL 1,4,6:7 RELEVANCY (100 50 50 50)");
+
+    // Fuzzy matching is ignored for non-text search keys
+    imap_check
+        .send("UID SEARCH FUZZY UID 0:6 LARGER 1000 SMALLER 2000")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SEARCH 1 2");
+
+    // Sort by relevancy
+    imap_check
+        .send("UID SORT (RELEVANCY SUBJECT) UTF-8 OR FUZZY FROM nathaniel FUZZY SUBJECT multipart")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SORT 1 4 7 6");
+
+    imap_check
+        .send("UID SORT (REVERSE RELEVANCY SUBJECT) UTF-8 OR FUZZY FROM nathaniel FUZZY SUBJECT multipart")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SORT 4 7 6 1");
 }
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use email::mailbox::counters::MailboxCounterAccess;
 use imap_proto::ResponseType;
 
 use crate::jmap::wait_for_index;
@@ -59,6 +60,42 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
         .assert_contains("UNSEEN 10")
         .assert_contains("UIDNEXT 11");
 
+    // Deleted messages are counted
+    imap.send("UID STORE 1:3 +FLAGS.SILENT (\\Deleted)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("STATUS INBOX (MESSAGES UNSEEN DELETED)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("MESSAGES 10")
+        .assert_contains("UNSEEN 10")
+        .assert_contains("DELETED 3");
+
+    // Counters are kept in sync with the messages
+    let account_id = handle
+        .server
+        .core
+        .storage
+        .data
+        .get_principal_id("jdoe@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        handle
+            .server
+            .rebuild_mailbox_counters(account_id)
+            .await
+            .unwrap(),
+        0
+    );
+    imap.send("UID STORE 1:3 -FLAGS.SILENT (\\Deleted)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("STATUS INBOX (MESSAGES DELETED)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("MESSAGES 10")
+        .assert_contains("DELETED 0");
+
     // Store using saved searches
     wait_for_index(&handle.server).await;
     imap.send("SEARCH RETURN (SAVE) FROM nathaniel").await;
@@ -105,11 +105,38 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("(1)")
         .assert_count("(", 1);
 
+    // Threads are ordered by their first matching message
+    let reply = "Message-ID: <23@domain>\nReferences: <1@domain>\nSubject: re: T1\n\nreply\n";
+    imap.send(&format!("APPEND Manchego {{{}}}", reply.len()))
+        .await;
+    imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+    imap.send_untagged(reply).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    imap.send("THREAD REFERENCES UTF-8 1:*").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* THREAD (1 2 3 4)(5 6 7 8 13)(9 10 11 12)");
+    imap.send("THREAD REFERENCES UTF-8 9:*").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* THREAD (9 10 11 12)(13)");
+    imap.send("UID THREAD REFERENCES UTF-8 UID 4,7,10,13").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* THREAD (4)(7 13)(10)");
+    imap.send("THREAD REFERENCES UTF-8 NOT SUBJECT T1").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* THREAD (1 2 3 4)(9 10 11 12)");
+
     // Delete all messages
     imap.send("STORE 1:* +FLAGS.SILENT (\\Deleted)").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
     imap.send("EXPUNGE").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
-        .assert_count("EXPUNGE", 13);
+        .assert_count("EXPUNGE", 14);
 }
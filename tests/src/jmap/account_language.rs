@@ -0,0 +1,108 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::core::BuildServer;
+use directory::backend::internal::{PrincipalField, PrincipalUpdate, PrincipalValue};
+use email::mailbox::INBOX_ID;
+use jmap_client::email::query::Filter;
+use jmap_proto::types::id::Id;
+use nlp::language::Language;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account, wait_for_index},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running account language tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Accounts without a locale use the default language
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "locale@example.com",
+            "secret",
+            "Juan Pérez",
+            &["locale@example.com"],
+        )
+        .await;
+    let default_language = server.core.jmap.default_language;
+    assert_eq!(server.account_language(account_id).await, default_language);
+
+    // The account locale is used as the fallback language
+    api.patch::<()>(
+        "/api/principal/locale@example.com",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Locale,
+            PrincipalValue::String("es_ES".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    assert_eq!(server.account_language(account_id).await, Language::Spanish);
+
+    // Messages are indexed and searched using the account language
+    let client = &mut params.client;
+    client.set_default_account_id(Id::from(account_id));
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: ana@example.com\r\n",
+                "To: locale@example.com\r\n",
+                "Subject: corriendo\r\n",
+                "\r\n",
+                "corriendo\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            [&Id::from(INBOX_ID).to_string()],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    wait_for_index(&server).await;
+    assert_eq!(
+        client
+            .email_query(Filter::body("corren").into(), None::<Vec<_>>)
+            .await
+            .unwrap()
+            .ids(),
+        [email_id.as_str()]
+    );
+
+    // Account languages can be disabled
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.account_language = false;
+    server.inner.shared_core.store(core.into());
+    assert_eq!(
+        server
+            .inner
+            .build_server()
+            .account_language(account_id)
+            .await,
+        default_language
+    );
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.account_language = true;
+    server.inner.shared_core.store(core.into());
+
+    // Clean up
+    destroy_all_mailboxes_for_account(account_id).await;
+    api.delete::<()>("/api/principal/locale@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_is_empty(server).await;
+}
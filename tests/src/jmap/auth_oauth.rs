@@ -234,6 +234,18 @@ pub async fn test(params: &mut JMAPTest) {
         .await;
     pop3.assert_read(pop::ResponseType::Ok).await;
 
+    // Revoked tokens should no longer be accepted
+    assert_eq!(
+        post_with_auth::<serde_json::Value>(
+            &metadata.revocation_endpoint,
+            token.as_str().into(),
+            &AHashMap::from_iter([("token".to_string(), token.to_string())]),
+        )
+        .await,
+        serde_json::json!({})
+    );
+    assert_unauthorized("https://127.0.0.1:8899", &token).await;
+
     // ------------------------
     // Device code flow
     // ------------------------
@@ -4,8 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose};
 use email::mailbox::INBOX_ID;
 use jmap_proto::types::id::Id;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
 use serde_json::Value;
 
 use crate::{
@@ -479,6 +483,131 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Resumable uploads
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .default_headers(HeaderMap::from_iter([
+            (
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!(
+                    "Basic {}",
+                    general_purpose::STANDARD.encode("jdoe@example.com:12345")
+                ))
+                .unwrap(),
+            ),
+            (
+                HeaderName::from_static("tus-resumable"),
+                HeaderValue::from_static("1.0.0"),
+            ),
+        ]))
+        .build()
+        .unwrap();
+    let upload_url = format!("https://127.0.0.1:8899/jmap/upload/{account_id}/");
+    let contents = b"Hello, this is a resumable upload split in chunks.";
+    let response = client
+        .post(&upload_url)
+        .header("Upload-Length", contents.len().to_string())
+        .header(
+            "Upload-Metadata",
+            format!(
+                "filetype {}",
+                general_purpose::STANDARD.encode("text/plain")
+            ),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let location = format!(
+        "https://127.0.0.1:8899{}",
+        response
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    );
+
+    for (offset, chunk, expected_status) in [
+        (0, &contents[..20], 204),
+        (0, &contents[..20], 409),
+        (20, &contents[20..], 200),
+    ] {
+        let response = client
+            .patch(&location)
+            .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+            .header("Upload-Offset", offset.to_string())
+            .body(chunk.to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), expected_status);
+
+        if expected_status == 200 {
+            let response =
+                serde_json::from_slice::<Value>(&response.bytes().await.unwrap()).unwrap();
+            assert_eq!(response["type"], "text/plain", "{response:?}");
+            assert_eq!(response["size"], contents.len(), "{response:?}");
+            let blob_id = response["blobId"].as_str().unwrap();
+            assert_eq!(
+                params.client.download(blob_id).await.unwrap(),
+                contents.to_vec()
+            );
+        } else {
+            let response = client.head(&location).send().await.unwrap();
+            assert_eq!(response.status().as_u16(), 200);
+            assert_eq!(response.headers().get("Upload-Offset").unwrap(), "20");
+        }
+    }
+    assert_eq!(
+        client
+            .head(&location)
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        404
+    );
+
+    // Terminated uploads are removed
+    let response = client
+        .post(&upload_url)
+        .header("Upload-Length", "100")
+        .send()
+        .await
+        .unwrap();
+    let location = format!(
+        "https://127.0.0.1:8899{}",
+        response
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    );
+    assert_eq!(
+        client
+            .delete(&location)
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        204
+    );
+    assert_eq!(
+        client
+            .head(&location)
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        404
+    );
+
     // Remove test data
     params.client.set_default_account_id(account_id.to_string());
     destroy_all_mailboxes(params).await;
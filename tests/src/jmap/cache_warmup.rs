@@ -0,0 +1,145 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{KV_ACTIVE_ACCOUNTS, config::network::CacheWarmUp, core::BuildServer};
+use jmap_client::client::{Client, Credentials};
+use services::cache_warmup::ActiveAccountSnapshot;
+use store::{dispatch::lookup::KeyValue, write::now};
+use utils::config::Config;
+
+use crate::{directory::internal::TestInternalDirectory, jmap::assert_is_empty};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running cache warm-up tests...");
+
+    // Validate settings
+    let mut config = Config::new("[cache.warm-up]\nenable = false\n").unwrap();
+    assert!(CacheWarmUp::parse(&mut config).is_none());
+    let mut config = Config::new("[cache.warm-up]\nconcurrency = 0\naccounts.max = 3\n").unwrap();
+    let warm_up = CacheWarmUp::parse(&mut config).unwrap();
+    assert_eq!(warm_up.delay, Duration::from_secs(5));
+    assert_eq!(warm_up.snapshot_interval, Duration::from_secs(15 * 60));
+    assert_eq!(warm_up.window, Duration::from_secs(86400));
+    assert_eq!(warm_up.max_accounts, 3);
+    assert_eq!(warm_up.concurrency, 1);
+    assert!(warm_up.dns);
+    assert!(warm_up.dkim);
+
+    // Accounts are only tracked on authentication while warm-up is enabled
+    let account_id = params
+        .server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "warmup@example.com",
+            "secret",
+            "Warm Up",
+            &["warmup@example.com"],
+        )
+        .await;
+    for (enabled, expected) in [(None, false), (Some(warm_up.clone()), true)] {
+        let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+        core.network.cache_warm_up = enabled;
+        params.server.inner.shared_core.store(core.into());
+        params.server.inner.cache.http_auth.clear();
+        params.server.inner.data.active_accounts.lock().clear();
+        Client::new()
+            .credentials(Credentials::basic("warmup@example.com", "secret"))
+            .accept_invalid_certs(true)
+            .connect("https://127.0.0.1:8899")
+            .await
+            .unwrap();
+        assert_eq!(
+            params
+                .server
+                .inner
+                .data
+                .active_accounts
+                .lock()
+                .contains(&account_id),
+            expected
+        );
+    }
+    let server = params.server.inner.build_server();
+
+    // Snapshots merge tracked accounts with recent entries
+    let store = server.in_memory_store();
+    let now = now();
+    store
+        .key_set(KeyValue::new(
+            vec![KV_ACTIVE_ACCOUNTS],
+            format!(
+                "1:{},2:{},3:{},4:{},invalid",
+                now - 10,
+                now - 20,
+                now - 2 * 86400,
+                now - 30
+            )
+            .into_bytes(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        server.active_accounts().await.unwrap(),
+        [
+            (1, now - 10),
+            (2, now - 20),
+            (3, now - 2 * 86400),
+            (4, now - 30)
+        ]
+    );
+    {
+        let mut active_accounts = server.inner.data.active_accounts.lock();
+        active_accounts.clear();
+        active_accounts.insert(2);
+        active_accounts.insert(5);
+    }
+    server.snapshot_active_accounts().await.unwrap();
+    assert!(server.inner.data.active_accounts.lock().is_empty());
+    let accounts = server.active_accounts().await.unwrap();
+    assert_eq!(accounts.len(), 3);
+    let mut recent_ids = accounts[..2]
+        .iter()
+        .map(|(account_id, last_seen)| {
+            assert!(*last_seen >= now);
+            *account_id
+        })
+        .collect::<Vec<_>>();
+    recent_ids.sort_unstable();
+    assert_eq!(recent_ids, [2, 5]);
+    assert_eq!(accounts[2], (1, now - 10));
+
+    // Snapshots are not updated when no accounts were active
+    server.snapshot_active_accounts().await.unwrap();
+    assert_eq!(server.active_accounts().await.unwrap(), accounts);
+
+    // Snapshots are not taken while warm-up is disabled
+    let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+    core.network.cache_warm_up = None;
+    params.server.inner.shared_core.store(core.into());
+    let server = params.server.inner.build_server();
+    server.inner.data.active_accounts.lock().insert(6);
+    server.snapshot_active_accounts().await.unwrap();
+    assert_eq!(server.active_accounts().await.unwrap(), accounts);
+
+    // Clean up
+    let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+    core.network.cache_warm_up = params.server.core.network.cache_warm_up.clone();
+    params.server.inner.shared_core.store(core.into());
+    server.inner.data.active_accounts.lock().clear();
+    store.key_delete(vec![KV_ACTIVE_ACCOUNTS]).await.unwrap();
+    ManagementApi::new(8899, "admin", "secret")
+        .delete::<()>("/api/principal/warmup@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_is_empty(params.server.clone()).await;
+}
@@ -70,6 +70,9 @@ pub async fn test(params: &mut JMAPTest) {
                     algo,
                     certs: certs.clone(),
                 },
+                EncryptionMethod::Age => EncryptionType::Age {
+                    certs: certs.clone(),
+                },
             };
 
             assert_eq!(
@@ -185,6 +188,7 @@ pub async fn import_certs_and_encrypt() {
         //("cert_pgp.der", EncryptionMethod::PGP, 1),
         ("cert_smime.pem", EncryptionMethod::SMIME, 3),
         ("cert_smime.der", EncryptionMethod::SMIME, 1),
+        ("cert_age.txt", EncryptionMethod::Age, 1),
     ] {
         let mut certs = try_parse_certs(
             method,
@@ -220,10 +224,16 @@ pub async fn import_certs_and_encrypt() {
             let arch =
                 Archive::deserialize_owned(Archiver::new(params.clone()).serialize().unwrap())
                     .unwrap();
-            message
-                .encrypt(arch.unarchive::<EncryptionParams>().unwrap())
+            let encrypted = message
+                .encrypt(arch.unarchive::<EncryptionParams>().unwrap(), None)
                 .await
                 .unwrap();
+            assert!(
+                MessageParser::new()
+                    .parse(&encrypted)
+                    .unwrap()
+                    .is_encrypted()
+            );
         }
     }
 
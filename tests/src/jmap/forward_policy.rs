@@ -0,0 +1,293 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{Server, config::scripts::ForwardPolicy, core::BuildServer};
+use directory::{
+    Type,
+    backend::internal::{PrincipalField, PrincipalSet, PrincipalUpdate, PrincipalValue},
+};
+use email::sieve::forward::{ExternalForward, ForwardApprovalStatus};
+use serde_json::{Value, json};
+use utils::config::utils::ParseValue;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running external forward policy tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Validate settings
+    assert_eq!(
+        ForwardPolicy::parse_value("Approval"),
+        Ok(ForwardPolicy::Approval)
+    );
+    assert!(ForwardPolicy::parse_value("sometimes").is_err());
+    assert_eq!(server.core.sieve.forward_external, ForwardPolicy::Allow);
+
+    // Create test account
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "forward@example.com",
+            "secret",
+            "John Doe",
+            &["forward@example.com"],
+        )
+        .await;
+    let access_token = server.get_access_token(account_id).await.unwrap();
+    let recipients = |addresses: &[&str]| {
+        addresses
+            .iter()
+            .map(|address| address.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // External forwards are allowed by default
+    assert_eq!(
+        server
+            .forward_filter(
+                &access_token,
+                recipients(&["jane@example.com", "Bill@Remote.org"]),
+                0
+            )
+            .await
+            .unwrap(),
+        ["jane@example.com", "Bill@Remote.org"]
+    );
+
+    // External forwards are dropped when denied
+    let server = set_forward_policy(&server, ForwardPolicy::Deny);
+    assert_eq!(
+        server
+            .forward_filter(
+                &access_token,
+                recipients(&["jane@example.com", "Bill@Remote.org"]),
+                0
+            )
+            .await
+            .unwrap(),
+        ["jane@example.com"]
+    );
+    assert!(server.forward_approvals().await.unwrap().is_empty());
+
+    // External forwards require approval, requests are created once
+    let server = set_forward_policy(&server, ForwardPolicy::Approval);
+    for _ in 0..2 {
+        assert_eq!(
+            server
+                .forward_filter(
+                    &access_token,
+                    recipients(&["jane@example.com", "Bill@Remote.org"]),
+                    0
+                )
+                .await
+                .unwrap(),
+            ["jane@example.com"]
+        );
+    }
+    let approvals = server.forward_approvals().await.unwrap();
+    assert_eq!(approvals.len(), 1);
+    assert_eq!(approvals[0].0, account_id);
+    assert_eq!(approvals[0].1, "bill@remote.org");
+    assert_eq!(approvals[0].2.status, ForwardApprovalStatus::Pending);
+    assert_eq!(approvals[0].2.tenant_id, None);
+
+    // Pending requests are listed by the management API
+    for query in [
+        "/api/forward-approval",
+        "/api/forward-approval?status=pending",
+    ] {
+        let items = api.get::<Value>(query).await.unwrap().unwrap_data();
+        assert_eq!(items["total"], 1);
+        assert_eq!(items["items"][0]["account"], "forward@example.com");
+        assert_eq!(items["items"][0]["address"], "bill@remote.org");
+        assert_eq!(items["items"][0]["status"], "pending");
+    }
+
+    // Approved addresses are forwarded to
+    api.post::<()>(
+        "/api/forward-approval/forward@example.com/Bill@Remote.org",
+        &json!({}),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    assert_eq!(
+        server
+            .forward_filter(
+                &access_token,
+                recipients(&["jane@example.com", "Bill@Remote.org"]),
+                0
+            )
+            .await
+            .unwrap(),
+        ["jane@example.com", "Bill@Remote.org"]
+    );
+    let items = api
+        .get::<Value>("/api/forward-approval?status=pending")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(items["total"], 0);
+    let items = api
+        .get::<Value>("/api/forward-approval")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(items["total"], 1);
+    assert_eq!(items["items"][0]["status"], "approved");
+
+    // Rejected requests are removed and can be requested again
+    assert!(
+        server
+            .forward_filter(&access_token, recipients(&["carl@remote.org"]), 0)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    api.delete::<()>("/api/forward-approval/forward@example.com/carl@remote.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(
+        server
+            .forward_approval(account_id, "carl@remote.org")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    api.post::<()>(
+        "/api/forward-approval/forward@example.com/carl@remote.org",
+        &json!({}),
+    )
+    .await
+    .unwrap()
+    .expect_error("notFound");
+
+    // Tenants can override the global policy
+    let server = set_forward_policy(&server, ForwardPolicy::Allow);
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Tenant)
+            .with_field(PrincipalField::Name, "forward-tenant")
+            .with_field(PrincipalField::ForwardPolicy, "sometimes"),
+    )
+    .await
+    .unwrap()
+    .expect_error("Invalid forward policy");
+    let tenant_id = api
+        .post::<u32>(
+            "/api/principal",
+            &PrincipalSet::new(u32::MAX, Type::Tenant)
+                .with_field(PrincipalField::Name, "forward-tenant")
+                .with_field(PrincipalField::ForwardPolicy, "deny"),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Domain)
+            .with_field(PrincipalField::Name, "forward.org")
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("forward-tenant".to_string()),
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let tenant_user_id = api
+        .post::<u32>(
+            "/api/principal",
+            &PrincipalSet::new(u32::MAX, Type::Individual)
+                .with_field(PrincipalField::Name, "user@forward.org")
+                .with_field(PrincipalField::Emails, "user@forward.org")
+                .with_field(PrincipalField::Roles, vec!["user".to_string()])
+                .with_field(
+                    PrincipalField::Tenant,
+                    PrincipalValue::String("forward-tenant".to_string()),
+                ),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let tenant_token = server.get_access_token(tenant_user_id).await.unwrap();
+    assert_eq!(
+        server
+            .forward_filter(
+                &tenant_token,
+                recipients(&["admin@forward.org", "bill@remote.org"]),
+                0
+            )
+            .await
+            .unwrap(),
+        ["admin@forward.org"]
+    );
+    api.patch::<()>(
+        "/api/principal/forward-tenant",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::ForwardPolicy,
+            PrincipalValue::String("approval".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    assert!(
+        server
+            .forward_filter(&tenant_token, recipients(&["bill@remote.org"]), 0)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        server
+            .forward_approval(tenant_user_id, "bill@remote.org")
+            .await
+            .unwrap()
+            .unwrap()
+            .tenant_id,
+        Some(tenant_id)
+    );
+
+    // Clean up
+    for (account_id, address) in [
+        (account_id, "bill@remote.org"),
+        (tenant_user_id, "bill@remote.org"),
+    ] {
+        server
+            .forward_approval_delete(account_id, address)
+            .await
+            .unwrap();
+    }
+    destroy_all_mailboxes_for_account(account_id).await;
+    destroy_all_mailboxes_for_account(tenant_user_id).await;
+    for query in [
+        "/api/principal/forward@example.com",
+        "/api/principal/user@forward.org",
+        "/api/principal/forward.org",
+        "/api/principal/forward-tenant",
+    ] {
+        api.delete::<()>(query).await.unwrap().unwrap_data();
+    }
+    assert_is_empty(server).await;
+}
+
+fn set_forward_policy(server: &Server, policy: ForwardPolicy) -> Server {
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.sieve.forward_external = policy;
+    server.inner.shared_core.store(core.into());
+    server.inner.build_server()
+}
@@ -0,0 +1,210 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose};
+use imap_proto::ResponseType;
+use jmap_client::client::{Client, Credentials};
+use jmap_proto::types::id::Id;
+use serde_json::Value;
+use store::{
+    ValueKey,
+    write::{ReportClass, ValueClass},
+};
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    imap::{
+        ImapConnection, Type,
+        pop::{self, Pop3Connection},
+    },
+    jmap::{ManagementApi, assert_is_empty, mailbox::destroy_all_mailboxes_for_account},
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running impersonation tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create test accounts
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "impersonated@example.com",
+            "secret",
+            "Jane Doe",
+            &["impersonated@example.com"],
+        )
+        .await;
+    let helpdesk_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "helpdesk@example.com",
+            "secret",
+            "Help Desk",
+            &["helpdesk@example.com"],
+        )
+        .await;
+
+    // Accounts without the impersonation permission cannot obtain tokens
+    let user_api = ManagementApi::new(8899, "helpdesk@example.com", "secret");
+    user_api
+        .post::<Value>("/api/impersonation/impersonated@example.com", &())
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+    user_api
+        .get::<Value>("/api/impersonation/log")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+    api.post::<Value>("/api/impersonation/nobody@example.com", &())
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Administrators can obtain a token to act as the account
+    let response = api
+        .post::<Value>(
+            "/api/impersonation/impersonated@example.com?expires-in=3",
+            &(),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(response["expiresIn"], 3);
+    let token = response["token"].as_str().unwrap().to_string();
+
+    // The token grants access over HTTP
+    let client = Client::new()
+        .credentials(Credentials::bearer(&token))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:8899")
+        .await
+        .unwrap();
+    assert_eq!(
+        client.default_account_id(),
+        Id::from(account_id).to_string()
+    );
+
+    // The token grants access over IMAP but not over other protocols
+    let sasl = general_purpose::STANDARD.encode(format!(
+        "n,a={},\u{1}auth=Bearer {}\u{1}\u{1}",
+        "impersonated@example.com", token
+    ));
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send(&format!("AUTHENTICATE OAUTHBEARER {sasl}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("SELECT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let mut pop3 = Pop3Connection::connect().await;
+    pop3.assert_read(pop::ResponseType::Ok).await;
+    pop3.send(&format!("AUTH OAUTHBEARER {sasl}")).await;
+    pop3.assert_read(pop::ResponseType::Err).await;
+
+    // Every action is recorded in the audit log
+    let log = api
+        .get::<Value>("/api/impersonation/log?account=impersonated@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    let items = log["items"].as_array().unwrap();
+    assert_eq!(log["total"].as_u64().unwrap() as usize, items.len());
+    for (protocol, action) in [
+        ("http", "Token issued"),
+        ("http", "GET /jmap"),
+        ("imap", "AUTHENTICATE"),
+    ] {
+        assert!(
+            items.iter().any(|item| item["protocol"] == protocol
+                && item["action"]
+                    .as_str()
+                    .is_some_and(|value| value.starts_with(action))),
+            "missing {protocol} {action:?} in {items:?}"
+        );
+    }
+    for item in items {
+        assert_eq!(item["adminName"], "admin");
+        assert_eq!(item["accountName"], "impersonated@example.com");
+    }
+    assert!(
+        items
+            .windows(2)
+            .all(|items| items[0]["timestamp"].as_u64() >= items[1]["timestamp"].as_u64())
+    );
+
+    // Log entries are paginated and filtered
+    let page = api
+        .get::<Value>("/api/impersonation/log?account=impersonated@example.com&limit=1&page=2")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(page["total"], log["total"]);
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    assert_eq!(page["items"][0], items[1]);
+    let page = api
+        .get::<Value>("/api/impersonation/log?account=impersonated@example.com&max-total=2")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(page["total"], 2);
+    let page = api
+        .get::<Value>("/api/impersonation/log?admin=helpdesk@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(page["total"], 0);
+
+    // Expired tokens are rejected
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert!(
+        Client::new()
+            .credentials(Credentials::bearer(&token))
+            .accept_invalid_certs(true)
+            .connect("https://127.0.0.1:8899")
+            .await
+            .is_err()
+    );
+    let mut imap = ImapConnection::connect(b"_y ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send(&format!("AUTHENTICATE OAUTHBEARER {sasl}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Clean up
+    server
+        .store()
+        .delete_range(
+            ValueKey::from(ValueClass::Report(ReportClass::Impersonation {
+                id: 0,
+                expires: 0,
+            })),
+            ValueKey::from(ValueClass::Report(ReportClass::Impersonation {
+                id: u64::MAX,
+                expires: u64::MAX,
+            })),
+        )
+        .await
+        .unwrap();
+    for (account_id, name) in [
+        (account_id, "impersonated@example.com"),
+        (helpdesk_id, "helpdesk@example.com"),
+    ] {
+        destroy_all_mailboxes_for_account(account_id).await;
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    assert_is_empty(server).await;
+}
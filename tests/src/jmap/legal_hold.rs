@@ -0,0 +1,175 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Server;
+use email::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess},
+    mailbox::INBOX_ID,
+    message::{delete::EmailDeletion, hold::LegalHold},
+};
+use imap_proto::ResponseType;
+use jmap_proto::types::{collection::Collection, id::Id};
+use serde_json::{Value, json};
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    imap::{ImapConnection, Type},
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running legal hold tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create test account
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "hold@example.com",
+            "secret",
+            "John Doe",
+            &["hold@example.com"],
+        )
+        .await;
+    let client = &mut params.client;
+    client.set_default_account_id(Id::from(account_id));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let mut email_ids = Vec::new();
+    for num in 0..4 {
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        concat!(
+                            "From: bill@example.com\r\n",
+                            "To: hold@example.com\r\n",
+                            "Subject: Legal hold test {}\r\n",
+                            "\r\n",
+                            "Legal hold test."
+                        ),
+                        num
+                    )
+                    .into_bytes(),
+                    [&inbox_id],
+                    None::<Vec<&str>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+
+    // Holds require a reason
+    api.post::<()>("/api/legal-hold/hold@example.com", &json!({"reason": ""}))
+        .await
+        .unwrap()
+        .expect_error("fieldMissing");
+    assert_eq!(server.legal_hold(account_id).await.unwrap(), None);
+
+    // Place the account under legal hold
+    api.post::<()>(
+        "/api/legal-hold/hold@example.com",
+        &json!({"reason": "Case 1234"}),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let status = api
+        .get::<Value>("/api/legal-hold/hold@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(status["hold"], true);
+    assert_eq!(status["reason"], "Case 1234");
+    assert_eq!(status["heldMessages"], 0);
+
+    // Messages destroyed over JMAP are moved to the hold area
+    for email_id in &email_ids[..2] {
+        client.email_destroy(email_id).await.unwrap();
+    }
+    let cache = server.get_cached_messages(account_id).await.unwrap();
+    assert_eq!(cache.in_mailbox(INBOX_ID).count(), 2);
+    assert_eq!(server.emails_held(account_id).await.unwrap().len(), 2);
+
+    // Messages expunged over IMAP are moved to the hold area
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.send("AUTHENTICATE PLAIN {32+}\r\nAGhvbGRAZXhhbXBsZS5jb20Ac2VjcmV0")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("SELECT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("STORE 1 +FLAGS (\\Deleted)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("EXPUNGE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("LOGOUT").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    assert_eq!(server.emails_held(account_id).await.unwrap().len(), 3);
+
+    // Held messages survive account purges
+    server.purge_account(account_id).await;
+    assert_eq!(count_documents(&server, account_id).await, 4);
+    let cache = server.get_cached_messages(account_id).await.unwrap();
+    assert_eq!(cache.in_mailbox(INBOX_ID).count(), 1);
+
+    // Accounts under legal hold cannot be deleted
+    api.delete::<()>("/api/principal/hold@example.com")
+        .await
+        .unwrap()
+        .expect_error("legal hold");
+
+    // Releasing the hold hands the held messages over to the purge
+    assert_eq!(
+        api.delete::<u64>("/api/legal-hold/hold@example.com")
+            .await
+            .unwrap()
+            .unwrap_data(),
+        3
+    );
+    let status = api
+        .get::<Value>("/api/legal-hold/hold@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(status["hold"], false);
+    assert_eq!(status["reason"], Value::Null);
+    assert_eq!(status["heldMessages"], 0);
+    server.purge_account(account_id).await;
+    assert_eq!(count_documents(&server, account_id).await, 1);
+
+    // Releasing an account that is not under legal hold fails
+    api.delete::<u64>("/api/legal-hold/hold@example.com")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Messages are tombstoned as usual once the hold is released
+    client.email_destroy(&email_ids[3]).await.unwrap();
+    assert!(server.emails_held(account_id).await.unwrap().is_empty());
+
+    // Clean up
+    destroy_all_mailboxes_for_account(account_id).await;
+    api.delete::<()>("/api/principal/hold@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_is_empty(server).await;
+}
+
+async fn count_documents(server: &Server, account_id: u32) -> u64 {
+    server
+        .get_document_ids(account_id, Collection::Email)
+        .await
+        .unwrap()
+        .unwrap_or_default()
+        .len()
+}
@@ -0,0 +1,190 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Server;
+use email::mailbox::{
+    INBOX_ID, TRASH_ID,
+    counters::{MailboxCounterAccess, MailboxCounters},
+};
+use jmap_client::{client::Client, mailbox};
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+use serde_json::Value;
+use store::write::BatchBuilder;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running mailbox counter tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create test account
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "counters@example.com",
+            "secret",
+            "John Doe",
+            &["counters@example.com"],
+        )
+        .await;
+    let client = &mut params.client;
+    client.set_default_account_id(Id::from(account_id));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let mut email_ids = Vec::new();
+    for num in 0..3 {
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        concat!(
+                            "From: bill@example.com\r\n",
+                            "To: counters@example.com\r\n",
+                            "Subject: Counter test {}\r\n",
+                            "\r\n",
+                            "Counter test."
+                        ),
+                        num
+                    )
+                    .into_bytes(),
+                    [&inbox_id],
+                    None::<Vec<&str>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+
+    // Counters are updated together with the messages
+    client
+        .email_set_keyword(&email_ids[0], "$seen", true)
+        .await
+        .unwrap();
+    client
+        .email_set_keyword(&email_ids[1], "$deleted", true)
+        .await
+        .unwrap();
+    assert_counters(client, &inbox_id, 3, 2).await;
+    assert_eq!(
+        server.mailbox_counters(account_id, INBOX_ID).await.unwrap(),
+        MailboxCounters {
+            total: 3,
+            unseen: 2,
+            deleted: 1
+        }
+    );
+    client
+        .email_set_mailboxes(&email_ids[2], [&Id::from(TRASH_ID).to_string()])
+        .await
+        .unwrap();
+    assert_counters(client, &inbox_id, 2, 1).await;
+    assert_counters(client, &Id::from(TRASH_ID).to_string(), 1, 1).await;
+    assert_eq!(
+        server.rebuild_mailbox_counters(account_id).await.unwrap(),
+        0
+    );
+
+    // Mailboxes without counters are rebuilt from the messages
+    set_counters(&server, account_id, INBOX_ID, None).await;
+    assert_counters(client, &inbox_id, 0, 0).await;
+    assert_eq!(
+        server.rebuild_mailbox_counters(account_id).await.unwrap(),
+        1
+    );
+    assert_counters(client, &inbox_id, 2, 1).await;
+
+    // Counters that drifted are reset by the management API
+    set_counters(&server, account_id, INBOX_ID, Some(5)).await;
+    let counters = api
+        .get::<Value>("/api/store/counters/counters@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(counters["Inbox"]["total"], 7);
+    assert_eq!(counters["Inbox"]["unseen"], 6);
+    let counters = api
+        .delete::<Value>("/api/store/counters/counters@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(counters["Inbox"]["total"], 2);
+    assert_eq!(counters["Inbox"]["unseen"], 1);
+    assert_eq!(counters["Inbox"]["deleted"], 1);
+    assert_counters(client, &inbox_id, 2, 1).await;
+
+    // Counters can only be read or rebuilt by administrators
+    let user_api = ManagementApi::new(8899, "counters@example.com", "secret");
+    user_api
+        .get::<Value>("/api/store/counters/counters@example.com")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+    user_api
+        .delete::<Value>("/api/store/counters/counters@example.com")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+
+    // Clean up
+    destroy_all_mailboxes_for_account(account_id).await;
+    api.delete::<()>("/api/principal/counters@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_is_empty(server).await;
+}
+
+async fn assert_counters(client: &Client, mailbox_id: &str, total: usize, unread: usize) {
+    let mailbox = client
+        .mailbox_get(
+            mailbox_id,
+            [
+                mailbox::Property::TotalEmails,
+                mailbox::Property::UnreadEmails,
+            ]
+            .into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (mailbox.total_emails(), mailbox.unread_emails()),
+        (total, unread),
+        "mailbox {mailbox_id}"
+    );
+}
+
+async fn set_counters(server: &Server, account_id: u32, mailbox_id: u32, value: Option<i64>) {
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .with_collection(Collection::Mailbox)
+        .update_document(mailbox_id);
+    for property in [
+        Property::TotalEmails,
+        Property::UnreadEmails,
+        Property::DeletedEmails,
+    ] {
+        match value {
+            Some(value) => {
+                batch.add(property, value);
+            }
+            None => {
+                batch.clear(property);
+            }
+        }
+    }
+    server.store().write(batch.build_all()).await.unwrap();
+}
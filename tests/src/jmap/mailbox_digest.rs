@@ -0,0 +1,391 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{
+    KV_MAILBOX_DIGEST, Server,
+    config::jmap::settings::{JmapConfig, MailboxDigest},
+    storage::index::ObjectIndexBuilder,
+};
+use email::mailbox::{
+    Mailbox,
+    digest::MailboxDigestBuild,
+    manage::MailboxFnc,
+    ticket::{Ticket, TicketStatus, assignee_from_keyword, assignee_keyword},
+};
+use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword};
+use store::write::{BatchBuilder, now};
+use utils::config::{Config, cron::SimpleCron, utils::ParseValue};
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running shared mailbox digest tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Validate settings
+    let mut config = Config::new(concat!(
+        "[server]\n",
+        "hostname = \"mx.example.org\"\n",
+        "[email.digest.\"support\"]\n",
+        "account = \"support@example.org\"\n",
+        "mailbox = \"Queue\"\n",
+        "[email.digest.\"sales\"]\n",
+        "mailbox = \"Leads\"\n",
+        "from.name = \"Sales Digest\"\n",
+        "from.address = \"sales@example.org\"\n",
+    ))
+    .unwrap();
+    let digests = JmapConfig::parse(&mut config).mailbox_digests;
+    assert!(config.errors.contains_key("email.digest.sales.account"));
+    assert_eq!(digests.len(), 1);
+    assert_eq!(digests[0].id, "support");
+    assert_eq!(digests[0].account, "support@example.org");
+    assert_eq!(digests[0].mailbox, "Queue");
+    assert_eq!(digests[0].from_name, "Mailbox Digest");
+    assert_eq!(digests[0].from_address, "no-reply@mx.example.org");
+
+    // Create test accounts
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "queue@example.com",
+            "secret",
+            "Support Queue",
+            &["queue@example.com"],
+        )
+        .await;
+    let mut member_ids = Vec::new();
+    for name in ["agent1@example.com", "agent2@example.com"] {
+        member_ids.push(
+            server
+                .core
+                .storage
+                .data
+                .create_test_user(name, "secret", name, &[name])
+                .await,
+        );
+    }
+    let mailbox_id = server
+        .mailbox_create_path(account_id, "Queue")
+        .await
+        .unwrap()
+        .unwrap();
+    let digest = MailboxDigest {
+        id: "queue-digest".to_string(),
+        account: "queue@example.com".to_string(),
+        mailbox: "Queue".to_string(),
+        frequency: SimpleCron::parse_value("0 9 *").unwrap(),
+        sla: None,
+        from_name: "Queue Digest".to_string(),
+        from_address: "no-reply@example.com".to_string(),
+    };
+
+    // Unknown accounts and mailboxes are skipped
+    for (account, mailbox) in [
+        ("unknown@example.com", "Queue"),
+        ("queue@example.com", "Unknown"),
+    ] {
+        assert!(
+            build_digests(
+                &server,
+                &MailboxDigest {
+                    account: account.to_string(),
+                    mailbox: mailbox.to_string(),
+                    ..digest.clone()
+                }
+            )
+            .await
+            .is_empty()
+        );
+    }
+
+    // Digests are not sent to mailboxes without subscribers
+    let client = &mut params.client;
+    client.set_default_account_id(Id::from(account_id));
+    let mailbox_id_str = Id::from(mailbox_id).to_string();
+    let mut email_ids = Vec::new();
+    email_ids.push(import_message(client, &mailbox_id_str, 0, None).await);
+    assert!(build_digests(&server, &digest).await.is_empty());
+
+    // Each subscribed member receives a digest
+    subscribe(&server, account_id, mailbox_id, &member_ids).await;
+    for num in 1..3 {
+        email_ids.push(import_message(client, &mailbox_id_str, num, None).await);
+    }
+    let digests = build_digests(&server, &digest).await;
+    assert_eq!(
+        digests
+            .iter()
+            .map(|(to, _)| to.as_str())
+            .collect::<Vec<_>>(),
+        ["agent1@example.com", "agent2@example.com"]
+    );
+    for (_, body) in &digests {
+        assert_digest(
+            body,
+            &[
+                "Subject: Activity digest for Queue",
+                "Auto-Submitted: auto-generated",
+                "New messages since the last digest: 2",
+                "Open tickets: 3",
+                "Unassigned tickets: 3",
+            ],
+        );
+    }
+
+    // Digests are sent while tickets remain open
+    let digests = build_digests(&server, &digest).await;
+    assert_eq!(digests.len(), 2);
+    for (_, body) in digests {
+        assert_digest(
+            &body,
+            &["New messages since the last digest: 0", "Open tickets: 3"],
+        );
+    }
+
+    // Each digest keeps track of its own messages
+    let other_digest = MailboxDigest {
+        id: "other-digest".to_string(),
+        ..digest.clone()
+    };
+    let digests = build_digests(&server, &other_digest).await;
+    assert_eq!(digests.len(), 2);
+    for (_, body) in digests {
+        assert_digest(&body, &["New messages since the last digest: 3"]);
+    }
+
+    // Ticket status and assignment are tracked using keywords
+    assert_eq!(
+        TicketStatus::Pending.keyword(),
+        Keyword::Other("$ticket-pending".to_string())
+    );
+    assert_eq!(
+        assignee_keyword("Agent1@Example.com"),
+        Keyword::Other("$assigned-agent1@example.com".to_string())
+    );
+    assert_eq!(
+        assignee_from_keyword("$Assigned-Agent1@Example.com").as_deref(),
+        Some("agent1@example.com")
+    );
+    assert_eq!(assignee_from_keyword("$assigned-"), None);
+    assert_eq!(assignee_from_keyword("$ticket-open"), None);
+    assert_eq!(
+        Ticket::from_keywords(&[
+            Keyword::Seen,
+            Keyword::Other("$ticket-done".to_string()),
+            Keyword::Other("$ticket-pending".to_string()),
+            assignee_keyword("agent1@example.com"),
+        ]),
+        Ticket {
+            status: TicketStatus::Done,
+            assignee: Some("agent1@example.com".to_string()),
+        }
+    );
+    assert!(!Ticket::from_keywords(&[Keyword::Other("$ticket-done".to_string())]).is_unassigned());
+    assert!(Ticket::from_keywords(&[Keyword::Seen]).is_unassigned());
+
+    // Digests summarize ticket status and new assignments for each member
+    for (email_id, keywords) in [
+        (&email_ids[0], &["$ticket-done"][..]),
+        (
+            &email_ids[1],
+            &["$ticket-pending", "$assigned-agent1@example.com"][..],
+        ),
+    ] {
+        for keyword in keywords {
+            client
+                .email_set_keyword(email_id, keyword, true)
+                .await
+                .unwrap();
+        }
+    }
+    let digests = build_digests(&server, &digest).await;
+    assert_eq!(digests.len(), 2);
+    for (to, body) in &digests {
+        assert_digest(
+            body,
+            &[
+                "Open tickets: 1",
+                "Pending tickets: 1",
+                "Closed tickets: 1",
+                "Unassigned tickets: 1",
+            ],
+        );
+        assert!(!body.contains("over SLA"), "{body}");
+        if to == "agent1@example.com" {
+            assert_digest(body, &["Assigned to you: 1 (1 new since the last digest)"]);
+        } else {
+            assert!(!body.contains("Assigned to you"), "{body}");
+        }
+    }
+
+    // Assignments are reported as new only once
+    let digests = build_digests(&server, &digest).await;
+    assert_eq!(digests[0].0, "agent1@example.com");
+    assert_digest(
+        &digests[0].1,
+        &["Assigned to you: 1 (0 new since the last digest)"],
+    );
+
+    // Open tickets that exceed the SLA are reported
+    let sla_digest = MailboxDigest {
+        sla: Some(Duration::from_secs(3600)),
+        ..digest.clone()
+    };
+    let email_id = import_message(client, &mailbox_id_str, 3, Some(now() as i64 - 2 * 3600)).await;
+    client
+        .email_set_keyword(&email_id, "$assigned-agent2@example.com", true)
+        .await
+        .unwrap();
+    email_ids.push(email_id);
+    let digests = build_digests(&server, &sla_digest).await;
+    assert_eq!(digests.len(), 2);
+    for (to, body) in &digests {
+        assert_digest(
+            body,
+            &[
+                "New messages since the last digest: 1",
+                "Open tickets: 2",
+                "Unassigned tickets: 1",
+                "Tickets over SLA: 1",
+            ],
+        );
+        if to == "agent1@example.com" {
+            assert_digest(
+                body,
+                &[
+                    "Assigned to you: 1 (0 new since the last digest)",
+                    "Your tickets over SLA: 0",
+                ],
+            );
+        } else {
+            assert_digest(
+                body,
+                &[
+                    "Assigned to you: 1 (1 new since the last digest)",
+                    "Your tickets over SLA: 1",
+                ],
+            );
+        }
+    }
+
+    // Digests are not sent once all tickets are closed
+    for email_id in &email_ids[1..] {
+        client
+            .email_set_keyword(email_id, "$ticket-done", true)
+            .await
+            .unwrap();
+    }
+    assert!(build_digests(&server, &sla_digest).await.is_empty());
+
+    // Clean up
+    server
+        .in_memory_store()
+        .key_delete_prefix(&[KV_MAILBOX_DIGEST])
+        .await
+        .unwrap();
+    destroy_all_mailboxes_for_account(account_id).await;
+    for member_id in member_ids {
+        destroy_all_mailboxes_for_account(member_id).await;
+    }
+    for name in [
+        "queue@example.com",
+        "agent1@example.com",
+        "agent2@example.com",
+    ] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    assert_is_empty(server).await;
+}
+
+async fn import_message(
+    client: &jmap_client::client::Client,
+    mailbox_id: &str,
+    num: u32,
+    received_at: Option<i64>,
+) -> String {
+    client
+        .email_import(
+            format!(
+                concat!(
+                    "From: customer@remote.org\r\n",
+                    "To: queue@example.com\r\n",
+                    "Subject: Ticket {}\r\n",
+                    "\r\n",
+                    "Please help."
+                ),
+                num
+            )
+            .into_bytes(),
+            [mailbox_id],
+            None::<Vec<&str>>,
+            received_at,
+        )
+        .await
+        .unwrap()
+        .take_id()
+}
+
+async fn subscribe(server: &Server, account_id: u32, mailbox_id: u32, member_ids: &[u32]) {
+    let mailbox_ = server
+        .get_archive(account_id, Collection::Mailbox, mailbox_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let mailbox = mailbox_.to_unarchived::<Mailbox>().unwrap();
+    let mut new_mailbox = mailbox.deserialize().unwrap();
+    for member_id in member_ids {
+        new_mailbox.add_subscriber(*member_id);
+    }
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .with_collection(Collection::Mailbox)
+        .update_document(mailbox_id)
+        .custom(
+            ObjectIndexBuilder::new()
+                .with_current(mailbox)
+                .with_changes(new_mailbox),
+        )
+        .unwrap();
+    server.commit_batch(batch).await.unwrap();
+}
+
+async fn build_digests(server: &Server, digest: &MailboxDigest) -> Vec<(String, String)> {
+    server
+        .build_mailbox_digest(digest)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| {
+            assert_eq!(message.from, digest.from_address);
+            assert_eq!(message.to.len(), 1);
+            (
+                message.to.into_iter().next().unwrap(),
+                String::from_utf8(message.body).unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn assert_digest(body: &str, expected: &[&str]) {
+    for expected in expected {
+        assert!(body.contains(expected), "{expected:?} not found in {body}");
+    }
+}
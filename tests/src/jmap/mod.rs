@@ -50,10 +50,12 @@ use crate::{
     AssertConfig, add_test_certs, directory::internal::TestInternalDirectory, store::TempDir,
 };
 
+pub mod account_language;
 pub mod auth_acl;
 pub mod auth_limits;
 pub mod auth_oauth;
 pub mod blob;
+pub mod cache_warmup;
 pub mod crypto;
 pub mod delivery;
 pub mod email_changes;
@@ -67,11 +69,20 @@ pub mod email_set;
 pub mod email_submission;
 pub mod enterprise;
 pub mod event_source;
+pub mod forward_policy;
+pub mod impersonation;
+pub mod legal_hold;
 pub mod mailbox;
+pub mod mailbox_counters;
+pub mod mailbox_digest;
 pub mod permissions;
+pub mod portability;
 pub mod purge;
 pub mod push_subscription;
+pub mod quarantine;
 pub mod quota;
+pub mod retention;
+pub mod sieve_filters;
 pub mod sieve_script;
 pub mod thread_get;
 pub mod thread_merge;
@@ -95,27 +106,38 @@ async fn jmap_tests() {
     email_set::test(&mut params).await;
     email_parse::test(&mut params).await;
     email_search_snippet::test(&mut params).await;
+    account_language::test(&mut params).await;
     email_changes::test(&mut params).await;
     email_query_changes::test(&mut params).await;
     email_copy::test(&mut params).await;
     thread_get::test(&mut params).await;
     thread_merge::test(&mut params).await;
     mailbox::test(&mut params).await;
+    mailbox_counters::test(&mut params).await;
+    mailbox_digest::test(&mut params).await;
     delivery::test(&mut params).await;
     auth_acl::test(&mut params).await;
     auth_limits::test(&mut params).await;
     auth_oauth::test(&mut params).await;
+    cache_warmup::test(&mut params).await;
+    impersonation::test(&mut params).await;
     event_source::test(&mut params).await;
     push_subscription::test(&mut params).await;
     sieve_script::test(&mut params).await;
+    sieve_filters::test(&mut params).await;
     vacation_response::test(&mut params).await;
     email_submission::test(&mut params).await;
     websocket::test(&mut params).await;
     quota::test(&mut params).await;
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
+    portability::test(&mut params).await;
+    quarantine::test(&mut params).await;
     permissions::test(&params).await;
     purge::test(&mut params).await;
+    retention::test(&mut params).await;
+    legal_hold::test(&mut params).await;
+    forward_policy::test(&mut params).await;
     enterprise::test(&mut params).await;
 
     if delete {
@@ -575,6 +597,23 @@ impl ManagementApi {
         })
     }
 
+    pub async fn put<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        body: &impl Serialize,
+    ) -> Result<Response<T>, String> {
+        self.request_raw(
+            Method::PUT,
+            query,
+            Some(serde_json::to_string(body).unwrap()),
+        )
+        .await
+        .map(|result| {
+            serde_json::from_str::<Response<T>>(&result)
+                .unwrap_or_else(|err| panic!("{err}: {result}"))
+        })
+    }
+
     pub async fn delete<T: DeserializeOwned>(&self, query: &str) -> Result<Response<T>, String> {
         self.request_raw(Method::DELETE, query, None)
             .await
@@ -603,12 +642,40 @@ impl ManagementApi {
         })
     }
 
+    pub async fn post_bytes<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        body: Vec<u8>,
+    ) -> Result<Response<T>, String> {
+        self.request_bytes(Method::POST, query, Some(body))
+            .await
+            .map(|result| {
+                serde_json::from_slice::<Response<T>>(&result)
+                    .unwrap_or_else(|err| panic!("{err}: {}", String::from_utf8_lossy(&result)))
+            })
+    }
+
+    pub async fn get_bytes(&self, query: &str) -> Result<Vec<u8>, String> {
+        self.request_bytes(Method::GET, query, None).await
+    }
+
     async fn request_raw(
         &self,
         method: Method,
         query: &str,
         body: Option<String>,
     ) -> Result<String, String> {
+        self.request_bytes(method, query, body.map(String::into_bytes))
+            .await
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+
+    async fn request_bytes(
+        &self,
+        method: Method,
+        query: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, String> {
         let mut request = reqwest::Client::builder()
             .timeout(Duration::from_millis(500))
             .danger_accept_invalid_certs(true)
@@ -633,7 +700,7 @@ impl ManagementApi {
             .map_err(|err| err.to_string())?
             .bytes()
             .await
-            .map(|bytes| String::from_utf8(bytes.to_vec()).unwrap())
+            .map(|bytes| bytes.to_vec())
             .map_err(|err| err.to_string())
     }
 }
@@ -0,0 +1,232 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    io::{Cursor, Read},
+    time::Duration,
+};
+
+use directory::{
+    Permission, Type,
+    backend::internal::{PrincipalField, PrincipalSet, PrincipalValue},
+};
+use email::cache::MessageCacheFetch;
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::jmap::{
+    assert_is_empty, delivery::SmtpConnection, mailbox::destroy_all_mailboxes_for_account,
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running account portability tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create test accounts
+    let mut account_ids = Vec::new();
+    for name in ["jane.export@example.com", "jane.import@example.com"] {
+        account_ids.push(
+            api.post::<u32>(
+                "/api/principal",
+                &PrincipalSet::new(u32::MAX, Type::Individual)
+                    .with_field(PrincipalField::Name, name)
+                    .with_field(PrincipalField::Emails, name)
+                    .with_field(PrincipalField::Roles, vec!["user".to_string()])
+                    .with_field(
+                        PrincipalField::Secrets,
+                        PrincipalValue::String("secret".to_string()),
+                    ),
+            )
+            .await
+            .unwrap()
+            .unwrap_data(),
+        );
+    }
+
+    // Deliver test messages, including one with a line that needs mbox quoting
+    let mut lmtp = SmtpConnection::connect().await;
+    for (num, body) in ["Hello!", "From here on\r\nit gets quoted", "Bye!"]
+        .into_iter()
+        .enumerate()
+    {
+        lmtp.ingest(
+            "bill@example.com",
+            &["jane.export@example.com"],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: jane.export@example.com\r\n",
+                    "Subject: Portability test {}\r\n",
+                    "\r\n",
+                    "{}\r\n",
+                ),
+                num, body
+            ),
+        )
+        .await;
+    }
+
+    // Export the account
+    let job_id = api
+        .post::<String>(
+            "/api/portability/export/jane.export@example.com?format=mbox",
+            &(),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let job = wait_for_job(&api, &job_id).await;
+    assert_eq!(job["stats"]["messages"], 3, "{job}");
+
+    // Download the archive and verify its contents
+    let archive = api
+        .get_bytes(&format!("/api/portability/jobs/{job_id}/download"))
+        .await
+        .unwrap();
+    let mut zip = ZipArchive::new(Cursor::new(archive.clone())).unwrap();
+    for file in [
+        "manifest.json",
+        "mail.json",
+        "sieve.json",
+        "identities.json",
+    ] {
+        assert!(zip.by_name(file).is_ok(), "missing {file}");
+    }
+    let mut mbox = String::new();
+    zip.by_name("mail/Inbox.mbox")
+        .unwrap()
+        .read_to_string(&mut mbox)
+        .unwrap();
+    assert_eq!(mbox.matches("From MAILER-DAEMON ").count(), 3, "{mbox}");
+    assert!(mbox.contains("\n>From here on"), "{mbox}");
+
+    // Tenant administrators cannot access jobs or accounts outside their tenant
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Tenant)
+            .with_field(PrincipalField::Name, "portability")
+            .with_field(
+                PrincipalField::Roles,
+                vec!["tenant-admin".to_string(), "user".to_string()],
+            )
+            .with_field(
+                PrincipalField::EnabledPermissions,
+                vec![
+                    Permission::AccountExport.name().to_string(),
+                    Permission::AccountImport.name().to_string(),
+                ],
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Domain)
+            .with_field(PrincipalField::Name, "portability.org")
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("portability".to_string()),
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "admin@portability.org")
+            .with_field(PrincipalField::Roles, vec!["tenant-admin".to_string()])
+            .with_field(
+                PrincipalField::EnabledPermissions,
+                vec![
+                    Permission::AccountExport.name().to_string(),
+                    Permission::AccountImport.name().to_string(),
+                ],
+            )
+            .with_field(
+                PrincipalField::Secrets,
+                PrincipalValue::String("tenantpass".to_string()),
+            )
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("portability".to_string()),
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let tenant_api = ManagementApi::new(8899, "admin@portability.org", "tenantpass");
+    for query in [
+        format!("/api/portability/jobs/{job_id}"),
+        format!("/api/portability/jobs/{job_id}/download"),
+    ] {
+        tenant_api
+            .get::<Value>(&query)
+            .await
+            .unwrap()
+            .expect_error("notFound");
+    }
+    tenant_api
+        .post::<String>("/api/portability/export/jane.export@example.com", &())
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Import the archive into another account
+    let job_id = api
+        .post_bytes::<String>("/api/portability/import/jane.import@example.com", archive)
+        .await
+        .unwrap()
+        .unwrap_data();
+    let job = wait_for_job(&api, &job_id).await;
+    assert_eq!(job["stats"]["messages"], 3, "{job}");
+    assert_eq!(
+        server
+            .get_cached_messages(account_ids[1])
+            .await
+            .unwrap()
+            .emails
+            .items
+            .len(),
+        3
+    );
+
+    // Clean up
+    for account_id in account_ids {
+        destroy_all_mailboxes_for_account(account_id).await;
+    }
+    for query in [
+        "/api/principal/jane.export@example.com",
+        "/api/principal/jane.import@example.com",
+        "/api/principal/admin@portability.org",
+        "/api/principal/portability.org",
+        "/api/principal/portability",
+    ] {
+        api.delete::<()>(query).await.unwrap().unwrap_data();
+    }
+    assert_is_empty(server).await;
+}
+
+async fn wait_for_job(api: &ManagementApi, job_id: &str) -> Value {
+    for _ in 0..50 {
+        let job = api
+            .get::<Value>(&format!("/api/portability/jobs/{job_id}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+        match job["status"].as_str() {
+            Some("running") => tokio::time::sleep(Duration::from_millis(200)).await,
+            Some("completed") => return job,
+            _ => panic!("Portability job failed: {job}"),
+        }
+    }
+
+    panic!("Timed out waiting for portability job {job_id}");
+}
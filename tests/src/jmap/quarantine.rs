@@ -0,0 +1,308 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::Server;
+use directory::{
+    Permission, Type,
+    backend::internal::{PrincipalField, PrincipalSet, PrincipalValue},
+};
+use email::cache::MessageCacheFetch;
+use serde_json::{Value, json};
+use smtp::queue::{
+    MessageSource, QueueId,
+    quarantine::{QuarantineEntry, SmtpQuarantine},
+    spool::SmtpSpool,
+};
+use store::write::now;
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running quarantine management tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let permissions = [
+        Permission::QuarantineList,
+        Permission::QuarantineGet,
+        Permission::QuarantineUpdate,
+        Permission::QuarantineRelease,
+        Permission::QuarantineReject,
+    ]
+    .iter()
+    .map(|permission| permission.name().to_string())
+    .collect::<Vec<_>>();
+
+    // Create a tenant with a domain, an account and an administrator
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Tenant)
+            .with_field(PrincipalField::Name, "quarantine")
+            .with_field(
+                PrincipalField::Roles,
+                vec!["tenant-admin".to_string(), "user".to_string()],
+            )
+            .with_field(PrincipalField::EnabledPermissions, permissions.clone()),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Domain)
+            .with_field(PrincipalField::Name, "quarantine.org")
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("quarantine".to_string()),
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let account_id = api
+        .post::<u32>(
+            "/api/principal",
+            &PrincipalSet::new(u32::MAX, Type::Individual)
+                .with_field(PrincipalField::Name, "jane@quarantine.org")
+                .with_field(PrincipalField::Emails, "jane@quarantine.org")
+                .with_field(PrincipalField::Roles, vec!["user".to_string()])
+                .with_field(
+                    PrincipalField::Secrets,
+                    PrincipalValue::String("secret".to_string()),
+                )
+                .with_field(
+                    PrincipalField::Tenant,
+                    PrincipalValue::String("quarantine".to_string()),
+                ),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "admin@quarantine.org")
+            .with_field(PrincipalField::Roles, vec!["tenant-admin".to_string()])
+            .with_field(PrincipalField::EnabledPermissions, permissions)
+            .with_field(
+                PrincipalField::Secrets,
+                PrincipalValue::String("tenantpass".to_string()),
+            )
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("quarantine".to_string()),
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let tenant_api = ManagementApi::new(8899, "admin@quarantine.org", "tenantpass");
+
+    // Quarantine one message for the tenant and another one outside of it
+    let tenant_id = quarantine_message(
+        &server,
+        "jane@quarantine.org",
+        QuarantineEntry::new("Tenant test"),
+    )
+    .await;
+    let other_id = quarantine_message(
+        &server,
+        "john@otherdomain.net",
+        QuarantineEntry::new("Other test"),
+    )
+    .await;
+
+    // Administrators see all quarantined messages, tenant administrators only their own
+    let list = api
+        .get::<Value>("/api/quarantine")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(list["total"], 2, "{list}");
+    let list = tenant_api
+        .get::<Value>("/api/quarantine")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(list["total"], 1, "{list}");
+    assert_eq!(list["items"][0]["id"], tenant_id, "{list}");
+    assert_eq!(list["items"][0]["reason"], "Tenant test", "{list}");
+    assert!(list["items"][0]["expires_at"].is_string(), "{list}");
+
+    // Messages outside the tenant cannot be read, released or rejected
+    tenant_api
+        .get::<Value>(&format!("/api/quarantine/{other_id}"))
+        .await
+        .unwrap()
+        .expect_error("notFound");
+    for action in ["note", "release", "reject"] {
+        tenant_api
+            .post::<bool>(
+                &format!("/api/quarantine/{other_id}/{action}"),
+                &json!({"note": "Not mine"}),
+            )
+            .await
+            .unwrap()
+            .expect_error("notFound");
+    }
+    assert!(server.read_message(other_id).await.is_some());
+
+    // Add a note to the tenant message
+    assert!(
+        tenant_api
+            .post::<bool>(
+                &format!("/api/quarantine/{tenant_id}/note"),
+                &json!({"note": "Looks legitimate"}),
+            )
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+    let message = tenant_api
+        .get::<Value>(&format!("/api/quarantine/{tenant_id}"))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(message["notes"][0]["text"], "Looks legitimate", "{message}");
+    assert_eq!(
+        message["notes"][0]["author"], "admin@quarantine.org",
+        "{message}"
+    );
+
+    // Releasing the tenant message delivers it
+    assert!(
+        tenant_api
+            .post::<bool>(&format!("/api/quarantine/{tenant_id}/release"), &json!({}))
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+    assert!(
+        server
+            .read_quarantine_entry(tenant_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let mut delivered = false;
+    for _ in 0..50 {
+        if server
+            .get_cached_messages(account_id)
+            .await
+            .unwrap()
+            .emails
+            .items
+            .len()
+            == 1
+        {
+            delivered = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(delivered, "Released message was not delivered");
+
+    // Rejecting a message removes it from the queue
+    assert!(
+        api.post::<bool>(&format!("/api/quarantine/{other_id}/reject"), &json!({}))
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+    assert!(server.read_message(other_id).await.is_none());
+    assert!(
+        server
+            .read_quarantine_entry(other_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let list = api
+        .get::<Value>("/api/quarantine")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(list["total"], 0, "{list}");
+
+    // Expired messages are purged, others are kept
+    let expired_id = quarantine_message(
+        &server,
+        "john@otherdomain.net",
+        QuarantineEntry::new("Expired test").with_expires(now() - 1),
+    )
+    .await;
+    let active_id = quarantine_message(
+        &server,
+        "john@otherdomain.net",
+        QuarantineEntry::new("Active test"),
+    )
+    .await;
+    assert_eq!(server.purge_expired_quarantine().await.unwrap(), 1);
+    assert!(server.read_message(expired_id).await.is_none());
+    assert!(
+        server
+            .read_quarantine_entry(expired_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(server.read_message(active_id).await.is_some());
+    assert!(
+        api.post::<bool>(&format!("/api/quarantine/{active_id}/reject"), &json!({}))
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+
+    // Clean up
+    destroy_all_mailboxes_for_account(account_id).await;
+    for query in [
+        "/api/principal/jane@quarantine.org",
+        "/api/principal/admin@quarantine.org",
+        "/api/principal/quarantine.org",
+        "/api/principal/quarantine",
+    ] {
+        api.delete::<()>(query).await.unwrap().unwrap_data();
+    }
+    assert_is_empty(server).await;
+}
+
+async fn quarantine_message(server: &Server, rcpt: &str, entry: QuarantineEntry) -> QueueId {
+    let mut message = server.new_message("bill@example.com", "bill@example.com", "example.com", 0);
+    let queue_id = message.queue_id;
+    message.add_recipient(rcpt, server).await;
+    assert!(
+        message
+            .queue_with_quarantine(
+                None,
+                format!(
+                    concat!(
+                        "From: bill@example.com\r\n",
+                        "To: {}\r\n",
+                        "Subject: Quarantine test\r\n",
+                        "\r\n",
+                        "Hello!\r\n",
+                    ),
+                    rcpt
+                )
+                .as_bytes(),
+                0,
+                server,
+                MessageSource::Authenticated,
+                Some(
+                    entry
+                        .with_from(Some("bill@example.com"))
+                        .with_subject(Some("Quarantine test")),
+                ),
+            )
+            .await
+    );
+
+    queue_id
+}
@@ -0,0 +1,242 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{
+    Server,
+    config::jmap::settings::{RetentionAction, RetentionRule, SpecialUse},
+    core::BuildServer,
+};
+use email::{
+    cache::{MessageCacheFetch, email::MessageCacheAccess, mailbox::MailboxCacheAccess},
+    mailbox::{INBOX_ID, TRASH_ID},
+    message::retention::EmailRetention,
+};
+use jmap_client::mailbox::Role;
+use jmap_proto::types::id::Id;
+use serde_json::Value;
+use store::write::now;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_for_account},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+const DAY: u64 = 86400;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running retention tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Configure retention rules
+    let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.mail_retention = vec![
+        RetentionRule {
+            id: "trash".to_string(),
+            tenant: None,
+            folders: vec!["trash".to_string()],
+            max_age: Duration::from_secs(30 * DAY),
+            action: RetentionAction::Delete,
+            dry_run: false,
+        },
+        RetentionRule {
+            id: "projects".to_string(),
+            tenant: None,
+            folders: vec!["Projects".to_string()],
+            max_age: Duration::from_secs(DAY),
+            action: RetentionAction::Archive,
+            dry_run: false,
+        },
+        RetentionRule {
+            id: "tenant".to_string(),
+            tenant: Some("retention-tenant".to_string()),
+            folders: vec![],
+            max_age: Duration::from_secs(1),
+            action: RetentionAction::Delete,
+            dry_run: false,
+        },
+        RetentionRule {
+            id: "all".to_string(),
+            tenant: None,
+            folders: vec![],
+            max_age: Duration::from_secs(365 * DAY),
+            action: RetentionAction::Delete,
+            dry_run: true,
+        },
+    ];
+    params.server.inner.shared_core.store(core.into());
+    let server = params.server.inner.build_server();
+
+    // Create test account and folders
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "retention@example.com",
+            "12345",
+            "John Doe",
+            &["retention@example.com"],
+        )
+        .await;
+    let client = &mut params.client;
+    client.set_default_account_id(Id::from(account_id));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let trash_id = Id::from(TRASH_ID).to_string();
+    let projects_id = client
+        .mailbox_create("Projects", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let reports_id = client
+        .mailbox_create("Reports", Some(&projects_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Import messages received at different dates
+    for (subject, mailbox_ids, age) in [
+        ("old trash", vec![&trash_id], 60 * DAY),
+        ("recent trash", vec![&trash_id], 0),
+        ("old trash and inbox", vec![&inbox_id, &trash_id], 60 * DAY),
+        ("old report", vec![&reports_id], 10 * DAY),
+        ("recent project", vec![&projects_id], 0),
+        ("old inbox", vec![&inbox_id], 10 * DAY),
+        ("ancient inbox", vec![&inbox_id], 400 * DAY),
+    ] {
+        client
+            .email_import(
+                format!(
+                    concat!(
+                        "From: bill@example.com\r\n",
+                        "To: retention@example.com\r\n",
+                        "Subject: {}\r\n",
+                        "\r\n",
+                        "Retention test."
+                    ),
+                    subject
+                )
+                .into_bytes(),
+                mailbox_ids,
+                None::<Vec<&str>>,
+                Some((now() - age) as i64),
+            )
+            .await
+            .unwrap();
+    }
+
+    // Dry runs report matches without changing anything
+    let reports = server
+        .emails_apply_retention(account_id, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| (report.rule.as_str(), report.matched, report.processed))
+            .collect::<Vec<_>>(),
+        vec![("trash", 2, 0), ("projects", 1, 0), ("all", 1, 0)]
+    );
+    assert!(reports.iter().all(|report| report.dry_run));
+    assert_eq!(count_emails(&server, account_id, None).await, 7);
+
+    // Dry runs can also be requested through the management API
+    let response = api
+        .post::<Value>("/api/retention/retention@example.com?dry-run", &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(response.as_array().unwrap().len(), 3);
+    assert_eq!(response[0]["matched"], 2);
+    assert_eq!(response[0]["dryRun"], true);
+    let rules = api
+        .get::<Value>("/api/retention")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(rules["total"], 4);
+    assert_eq!(rules["items"][1]["action"], "archive");
+    assert_eq!(count_emails(&server, account_id, None).await, 7);
+
+    // Apply the rules
+    let reports = server
+        .emails_apply_retention(account_id, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| (
+                report.rule.as_str(),
+                report.matched,
+                report.processed,
+                report.dry_run
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("trash", 2, 2, false),
+            ("projects", 1, 1, false),
+            ("all", 1, 0, true)
+        ]
+    );
+
+    // Expired messages are removed from the trash, and only deleted when
+    // they are not filed in any other folder
+    assert_eq!(count_emails(&server, account_id, None).await, 6);
+    assert_eq!(count_emails(&server, account_id, Some(TRASH_ID)).await, 1);
+    assert_eq!(count_emails(&server, account_id, Some(INBOX_ID)).await, 3);
+
+    // Expired messages in a folder and its subfolders are archived
+    let cache = server.get_cached_messages(account_id).await.unwrap();
+    let archive_id = cache
+        .mailbox_by_role(&SpecialUse::Archive)
+        .or_else(|| cache.mailbox_by_path("Archive"))
+        .expect("Archive folder not created")
+        .document_id;
+    let projects_id = Id::from_bytes(projects_id.as_bytes())
+        .unwrap()
+        .document_id();
+    let reports_id = Id::from_bytes(reports_id.as_bytes()).unwrap().document_id();
+    assert_eq!(count_emails(&server, account_id, Some(archive_id)).await, 1);
+    assert_eq!(count_emails(&server, account_id, Some(reports_id)).await, 0);
+    assert_eq!(
+        count_emails(&server, account_id, Some(projects_id)).await,
+        1
+    );
+
+    // Applying the rules again has no effect
+    let reports = server
+        .emails_apply_retention(account_id, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        reports.iter().map(|report| report.processed).sum::<u64>(),
+        0
+    );
+    assert_eq!(count_emails(&server, account_id, Some(archive_id)).await, 1);
+
+    // Clean up
+    let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.mail_retention = vec![];
+    params.server.inner.shared_core.store(core.into());
+    destroy_all_mailboxes_for_account(account_id).await;
+    api.delete::<()>("/api/principal/retention@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_is_empty(params.server.clone()).await;
+}
+
+async fn count_emails(server: &Server, account_id: u32, mailbox_id: Option<u32>) -> usize {
+    let cache = server.get_cached_messages(account_id).await.unwrap();
+    match mailbox_id {
+        Some(mailbox_id) => cache.in_mailbox(mailbox_id).count(),
+        None => cache.emails.items.len(),
+    }
+}
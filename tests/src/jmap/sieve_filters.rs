@@ -0,0 +1,388 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ::email::sieve::filters::{
+    FilterAction, FilterCondition, FilterField, FilterOp, FilterRule, FilterRules,
+};
+use jmap_client::{
+    email,
+    mailbox::Role,
+    sieve::query::{Comparator, Filter},
+};
+use jmap_proto::types::id::Id;
+use serde_json::Value;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, delivery::SmtpConnection, mailbox::destroy_all_mailboxes},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Sieve filter rules tests...");
+    let server = params.server.clone();
+
+    // Rules are compiled to Sieve and can be read back from the script
+    let rules = test_rules();
+    let script = String::from_utf8(rules.compile().unwrap()).unwrap();
+    for expected in [
+        "# Managed by Stalwart, do not edit.\r\n# rules: ",
+        "require [\"fileinto\", \"imap4flags\"];\r\n",
+        concat!(
+            "# Newsletters\r\n",
+            "if header :contains \"subject\" \"newsletter\" {\r\n",
+            "    fileinto \"Newsletters\";\r\n",
+            "    stop;\r\n",
+            "}\r\n"
+        ),
+        concat!(
+            "if anyof(address :all :contains \"from\" \"boss@\", ",
+            "header :is \"X-Priority\" \"1\") {\r\n",
+            "    addflag \"$important\";\r\n",
+            "}\r\n"
+        ),
+    ] {
+        assert!(
+            script.contains(expected),
+            "{expected:?} not found in {script}"
+        );
+    }
+    assert!(!script.contains("    discard;"), "{script}");
+    assert_eq!(FilterRules::parse(&script), Some(rules.clone()));
+    assert_eq!(FilterRules::parse("require \"fileinto\";\r\nkeep;"), None);
+
+    // Values are escaped
+    let script = String::from_utf8(
+        FilterRules {
+            rules: vec![rule(
+                vec![condition(
+                    FilterField::Subject,
+                    FilterOp::Is,
+                    "say \"hi\"\\\r\n",
+                )],
+                vec![FilterAction::Keep],
+            )],
+            include: Some("prev".to_string()),
+        }
+        .compile()
+        .unwrap(),
+    )
+    .unwrap();
+    for expected in [
+        "require [\"include\"];",
+        "if header :is \"subject\" \"say \\\"hi\\\"\\\\\" {\r\n    keep;\r\n}",
+        "include :personal \"prev\";",
+    ] {
+        assert!(
+            script.contains(expected),
+            "{expected:?} not found in {script}"
+        );
+    }
+
+    // Invalid rules are rejected
+    for (conditions, actions, expected_error) in [
+        (vec![], vec![], "has no actions"),
+        (
+            vec![condition(FilterField::Size, FilterOp::Over, "10K")],
+            vec![FilterAction::Keep],
+            "Invalid message size",
+        ),
+        (
+            vec![condition(FilterField::Size, FilterOp::Contains, "1000")],
+            vec![FilterAction::Keep],
+            "Size conditions only support",
+        ),
+        (
+            vec![condition(FilterField::Subject, FilterOp::Over, "1000")],
+            vec![FilterAction::Keep],
+            "Only size conditions",
+        ),
+        (
+            vec![condition(FilterField::Header, FilterOp::Is, "1")],
+            vec![FilterAction::Keep],
+            "valid header name",
+        ),
+        (
+            vec![],
+            vec![FilterAction::Redirect {
+                address: "nobody".to_string(),
+                copy: false,
+            }],
+            "Invalid redirect address",
+        ),
+        (
+            vec![],
+            vec![FilterAction::FileInto {
+                mailbox: " ".to_string(),
+                copy: false,
+            }],
+            "Missing mailbox name",
+        ),
+    ] {
+        let err = FilterRules {
+            rules: vec![rule(conditions, actions)],
+            include: None,
+        }
+        .compile()
+        .unwrap_err();
+        assert!(err.contains(expected_error), "{err}");
+    }
+
+    // Create test account
+    let account_id = Id::from(
+        server
+            .core
+            .storage
+            .data
+            .create_test_user(
+                "filters@example.com",
+                "secret",
+                "John Doe",
+                &["filters@example.com"],
+            )
+            .await,
+    )
+    .to_string();
+    let client = &mut params.client;
+    client.set_default_account_id(&account_id);
+    let api = ManagementApi::new(8899, "filters@example.com", "secret");
+
+    // No rules are defined initially
+    let response = api
+        .get::<Value>("/api/account/filters")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(response["managed"], true);
+    assert_eq!(response["rules"], Value::Array(vec![]));
+
+    // Scripts that were not created by the filter editor are not overwritten
+    let script_id = client
+        .sieve_script_create("filter-rules", b"keep;".to_vec(), false)
+        .await
+        .unwrap()
+        .take_id();
+    let response = api
+        .get::<Value>("/api/account/filters")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(response["managed"], false);
+    assert_eq!(response["script"], "keep;");
+    api.put::<()>("/api/account/filters", &rules)
+        .await
+        .unwrap()
+        .expect_error("modified outside of the filter editor");
+    client.sieve_script_destroy(&script_id).await.unwrap();
+
+    // Invalid rules are not stored
+    api.put::<()>(
+        "/api/account/filters",
+        &FilterRules {
+            rules: vec![rule(vec![], vec![])],
+            include: None,
+        },
+    )
+    .await
+    .unwrap()
+    .expect_error("has no actions");
+
+    // Store the rules, keeping the previously active script
+    client
+        .sieve_script_create(
+            "prev",
+            b"require \"imap4flags\";\r\naddflag \"$prev\";\r\n".to_vec(),
+            true,
+        )
+        .await
+        .unwrap();
+    let newsletters_id = client
+        .mailbox_create("Newsletters", None::<&str>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    api.put::<()>("/api/account/filters", &rules)
+        .await
+        .unwrap()
+        .unwrap_data();
+    let response = api
+        .get::<Value>("/api/account/filters")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(response["managed"], true);
+    assert_eq!(response["include"], "prev");
+    assert_eq!(
+        serde_json::from_value::<Vec<FilterRule>>(response["rules"].clone()).unwrap(),
+        rules.rules
+    );
+    let active_ids = client
+        .sieve_script_query(Filter::is_active(true).into(), [Comparator::name()].into())
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(active_ids.len(), 1);
+    assert_eq!(
+        client
+            .sieve_script_get(&active_ids[0], None::<Vec<_>>)
+            .await
+            .unwrap()
+            .unwrap()
+            .name()
+            .unwrap(),
+        "filter-rules"
+    );
+
+    // Updating the rules keeps the included script
+    api.put::<()>(
+        "/api/account/filters",
+        &FilterRules {
+            rules: rules.rules.clone(),
+            include: None,
+        },
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    assert_eq!(
+        api.get::<Value>("/api/account/filters")
+            .await
+            .unwrap()
+            .unwrap_data()["include"],
+        "prev"
+    );
+
+    // Incoming messages are filtered
+    let mut lmtp = SmtpConnection::connect().await;
+    for (from, subject) in [
+        ("news@remote.org", "Weekly newsletter"),
+        ("boss@example.com", "Quarterly report"),
+    ] {
+        lmtp.ingest(
+            from,
+            &["filters@example.com"],
+            &format!(
+                "From: {from}\r\nTo: filters@example.com\r\nSubject: {subject}\r\n\r\nHello.\r\n"
+            ),
+        )
+        .await;
+    }
+    lmtp.quit().await;
+    let newsletter_ids = client
+        .email_query(
+            email::query::Filter::in_mailbox(&newsletters_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(newsletter_ids.len(), 1);
+    let email_ids = client
+        .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(email_ids.len(), 2);
+    let email = client
+        .email_get(
+            email_ids
+                .iter()
+                .find(|id| **id != newsletter_ids[0])
+                .unwrap(),
+            [email::Property::Keywords].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    for keyword in ["$important", "$prev"] {
+        assert!(
+            email.keywords().contains(&keyword),
+            "Keyword {keyword} not found in {:?}.",
+            email.keywords()
+        );
+    }
+
+    // Clean up
+    client.sieve_script_deactivate().await.unwrap();
+    let mut request = client.build();
+    request.query_sieve_script();
+    for id in request.send_query_sieve_script().await.unwrap().take_ids() {
+        client.sieve_script_destroy(&id).await.unwrap();
+    }
+    destroy_all_mailboxes(params).await;
+    ManagementApi::new(8899, "admin", "secret")
+        .delete::<()>("/api/principal/filters@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_is_empty(server).await;
+}
+
+fn test_rules() -> FilterRules {
+    FilterRules {
+        rules: vec![
+            FilterRule {
+                name: "Newsletters".to_string(),
+                ..rule(
+                    vec![condition(
+                        FilterField::Subject,
+                        FilterOp::Contains,
+                        "newsletter",
+                    )],
+                    vec![
+                        FilterAction::FileInto {
+                            mailbox: "Newsletters".to_string(),
+                            copy: false,
+                        },
+                        FilterAction::Stop,
+                    ],
+                )
+            },
+            FilterRule {
+                name: "Important".to_string(),
+                match_any: true,
+                ..rule(
+                    vec![
+                        condition(FilterField::From, FilterOp::Contains, "boss@"),
+                        FilterCondition {
+                            header: Some("X-Priority".to_string()),
+                            ..condition(FilterField::Header, FilterOp::Is, "1")
+                        },
+                    ],
+                    vec![FilterAction::AddFlag {
+                        flag: "$important".to_string(),
+                    }],
+                )
+            },
+            FilterRule {
+                name: "Disabled".to_string(),
+                enabled: false,
+                ..rule(vec![], vec![FilterAction::Discard])
+            },
+        ],
+        include: None,
+    }
+}
+
+fn rule(conditions: Vec<FilterCondition>, actions: Vec<FilterAction>) -> FilterRule {
+    FilterRule {
+        name: "Test".to_string(),
+        enabled: true,
+        match_any: false,
+        conditions,
+        actions,
+    }
+}
+
+fn condition(field: FilterField, op: FilterOp, value: &str) -> FilterCondition {
+    FilterCondition {
+        field,
+        header: None,
+        op,
+        value: value.to_string(),
+    }
+}
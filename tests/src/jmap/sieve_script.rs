@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use ::email::sieve::activate::SieveScriptActivate;
 use jmap_client::{
     Error,
     core::set::{SetError, SetErrorType},
@@ -11,6 +12,7 @@ use jmap_client::{
     sieve::query::{Comparator, Filter},
 };
 use jmap_proto::types::id::Id;
+use serde_json::Value;
 use std::{
     fs,
     path::PathBuf,
@@ -28,7 +30,7 @@ use crate::{
     smtp::DnsCache,
 };
 
-use super::JMAPTest;
+use super::{JMAPTest, ManagementApi};
 
 pub async fn test(params: &mut JMAPTest) {
     println!("Running Sieve tests...");
@@ -67,6 +69,44 @@ pub async fn test(params: &mut JMAPTest) {
         }))
     ));
 
+    // Validate scripts using the management API
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let response = api
+        .post_bytes::<Value>("/api/sieve/validate", get_script("validate_ok"))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(response["valid"], true);
+    assert_eq!(response["diagnostics"], Value::Array(vec![]));
+    for (script, rule, suggestion) in [
+        (
+            "fileinto \"Inbox\";",
+            "undeclared-capability",
+            "Add the extension to the require statement at the top of the script.",
+        ),
+        (
+            "keep",
+            "unexpected-eof",
+            "Check for a missing semicolon or closing brace.",
+        ),
+    ] {
+        let response = api
+            .post_bytes::<Value>("/api/sieve/validate", script.as_bytes().to_vec())
+            .await
+            .unwrap()
+            .unwrap_data();
+        assert_eq!(response["valid"], false, "{script}");
+        let diagnostic = &response["diagnostics"][0];
+        assert_eq!(diagnostic["rule"], rule, "{script}: {diagnostic}");
+        assert!(diagnostic["line"].is_u64(), "{diagnostic}");
+        assert!(diagnostic["column"].is_u64(), "{diagnostic}");
+        assert!(
+            !diagnostic["message"].as_str().unwrap().is_empty(),
+            "{diagnostic}"
+        );
+        assert_eq!(diagnostic["suggestion"], suggestion);
+    }
+
     // Create 5 Sieve scripts, all deactivated.
     let mut script_ids = Vec::new();
     for i in 0..5 {
@@ -151,6 +191,82 @@ pub async fn test(params: &mut JMAPTest) {
         Vec::<String>::new()
     );
 
+    // Script names are unique regardless of case
+    assert!(matches!(
+        client
+            .sieve_script_create("SCRIPT_1", b"keep;".to_vec(), false)
+            .await,
+        Err(Error::Set(SetError {
+            type_: SetErrorType::AlreadyExists,
+            ..
+        }))
+    ));
+
+    // Activating a missing script leaves the active script unchanged
+    client
+        .sieve_script_activate(script_ids.first().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .sieve_activate_script(
+                Id::from_bytes(account_id.as_bytes()).unwrap().document_id(),
+                Some(u32::MAX)
+            )
+            .await
+            .unwrap(),
+        (0, vec![])
+    );
+    assert_eq!(
+        client
+            .sieve_script_query(Filter::is_active(true).into(), [Comparator::name()].into())
+            .await
+            .unwrap()
+            .ids(),
+        vec![script_ids.first().unwrap().to_string()]
+    );
+    client.sieve_script_deactivate().await.unwrap();
+
+    // Scripts exceeding the maximum size are rejected
+    assert!(matches!(
+        client
+            .sieve_script_validate(
+                format!(
+                    "# {}\r\nkeep;",
+                    "x".repeat(server.core.jmap.sieve_max_script_size)
+                )
+                .into_bytes()
+            )
+            .await,
+        Err(Error::Set(SetError {
+            type_: SetErrorType::TooLarge,
+            ..
+        }))
+    ));
+
+    // The number of scripts is limited
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.sieve_max_scripts = 6;
+    server.inner.shared_core.store(core.into());
+    let extra_id = client
+        .sieve_script_create("script_6", b"keep;".to_vec(), false)
+        .await
+        .unwrap()
+        .take_id();
+    assert!(matches!(
+        client
+            .sieve_script_create("script_7", b"keep;".to_vec(), false)
+            .await,
+        Err(Error::Set(SetError {
+            type_: SetErrorType::OverQuota,
+            ..
+        }))
+    ));
+    client.sieve_script_destroy(&extra_id).await.unwrap();
+    let mut core = server.inner.shared_core.load_full().as_ref().clone();
+    core.jmap.sieve_max_scripts = server.core.jmap.sieve_max_scripts;
+    server.inner.shared_core.store(core.into());
+
     // Connect to LMTP service
     let mut lmtp = SmtpConnection::connect().await;
 
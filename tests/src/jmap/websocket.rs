@@ -5,7 +5,9 @@
  */
 
 use ahash::AHashSet;
-use futures::StreamExt;
+use base64::{Engine, engine::general_purpose};
+use futures::{SinkExt, StreamExt};
+use hyper::header::AUTHORIZATION;
 use jmap_client::{
     TypeState,
     client_ws::WebSocketMessage,
@@ -13,11 +15,20 @@ use jmap_client::{
         response::{Response, TaggedMethodResponse},
         set::SetObject,
     },
+    mailbox::Role,
 };
 use jmap_proto::types::id::Id;
+use mail_send::smtp::tls::build_tls_connector;
+use rustls_pki_types::ServerName;
+use serde_json::{Value, json};
 use std::time::Duration;
 
-use tokio::sync::mpsc;
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_rustls::client::TlsStream;
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{Message, client::IntoClientRequest},
+};
 
 use crate::{
     directory::internal::TestInternalDirectory,
@@ -118,6 +129,64 @@ pub async fn test(params: &mut JMAPTest) {
         .unwrap();
     expect_nothing(&mut stream_rx).await;
 
+    // Enable push notifications with deltas for mailboxes only
+    let mailbox_id = client
+        .mailbox_create("WebSocket Delta Test", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let mut ws_raw = connect_raw_ws("jdoe@example.com", "12345").await;
+    ws_raw
+        .send(Message::Text(
+            json!({
+                "@type": "WebSocketPushEnable",
+                "dataTypes": ["Mailbox"],
+                "delta": true
+            })
+            .to_string()
+            .into(),
+        ))
+        .await
+        .unwrap();
+
+    // The first notification establishes the base state
+    client
+        .mailbox_update_sort_order(&mailbox_id, 1)
+        .await
+        .unwrap();
+    let changes = expect_raw_state(&mut ws_raw).await;
+    assert!(changes["changed"][&account_id]["Mailbox"].is_string());
+    assert!(changes.get("delta").is_none(), "{changes}");
+
+    // Following notifications include the changed ids
+    client
+        .mailbox_update_sort_order(&mailbox_id, 2)
+        .await
+        .unwrap();
+    let changes = expect_raw_state(&mut ws_raw).await;
+    let delta = &changes["delta"][&account_id]["Mailbox"];
+    assert_eq!(
+        delta["newState"],
+        changes["changed"][&account_id]["Mailbox"]
+    );
+    assert_eq!(delta["updated"], json!([mailbox_id]));
+    assert_eq!(delta["created"], json!([]));
+    assert_eq!(delta["destroyed"], json!([]));
+    assert_eq!(delta["hasMoreChanges"], false);
+
+    // Changes to other data types are not pushed
+    let identity_id = client
+        .identity_create("John Doe", "jdoe@example.com")
+        .await
+        .unwrap()
+        .take_id();
+    client.identity_destroy(&identity_id).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(1000), ws_raw.next())
+            .await
+            .is_err()
+    );
+
     params.client.set_default_account_id(account_id);
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
@@ -162,6 +231,48 @@ async fn assert_state(
     }
 }
 
+async fn connect_raw_ws(login: &str, secret: &str) -> WebSocketStream<TlsStream<TcpStream>> {
+    let stream = build_tls_connector(true)
+        .connect(
+            ServerName::try_from("jmap.example.org").unwrap().to_owned(),
+            TcpStream::connect("127.0.0.1:8899").await.unwrap(),
+        )
+        .await
+        .unwrap();
+    let mut request = "wss://127.0.0.1:8899/jmap/ws"
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        AUTHORIZATION,
+        format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode(format!("{login}:{secret}"))
+        )
+        .parse()
+        .unwrap(),
+    );
+    request
+        .headers_mut()
+        .insert("Sec-WebSocket-Protocol", "jmap".parse().unwrap());
+    tokio_tungstenite::client_async(request, stream)
+        .await
+        .unwrap()
+        .0
+}
+
+async fn expect_raw_state(ws: &mut WebSocketStream<TlsStream<TcpStream>>) -> Value {
+    match tokio::time::timeout(Duration::from_millis(1500), ws.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => {
+            let changes = serde_json::from_str::<Value>(&text).unwrap();
+            assert_eq!(changes["@type"], "StateChange", "{changes}");
+            changes
+        }
+        result => {
+            panic!("Timeout waiting for websocket: {:?}", result);
+        }
+    }
+}
+
 async fn expect_nothing(stream_rx: &mut mpsc::Receiver<WebSocketMessage>) {
     match tokio::time::timeout(Duration::from_millis(1000), stream_rx.recv()).await {
         Err(_) => {}
@@ -12,7 +12,8 @@ use common::{
         server::{Listener, Listeners, ServerProtocol, TcpListener},
         smtp::*,
     },
-    expr::{functions::ResolveVariable, if_block::*, tokenizer::TokenMap, *},
+    expr::{eval::EvalBranch, functions::ResolveVariable, if_block::*, tokenizer::TokenMap, *},
+    listener::AuthPolicy,
 };
 
 use compact_str::ToCompactString;
@@ -277,7 +278,8 @@ fn parse_throttles() {
                 rate: Rate {
                     requests: 50,
                     period: Duration::from_secs(30)
-                }
+                },
+                scope: RateLimiterScope::default(),
             },
             QueueRateLimiter {
                 id: "0001".into(),
@@ -286,7 +288,31 @@ fn parse_throttles() {
                 rate: Rate {
                     requests: 50,
                     period: Duration::from_secs(30)
-                }
+                },
+                scope: RateLimiterScope::default(),
+            },
+            QueueRateLimiter {
+                id: "0002".into(),
+                expr: Expression::default(),
+                keys: THROTTLE_RCPT_DOMAIN,
+                rate: Rate {
+                    requests: 100,
+                    period: Duration::from_secs(60)
+                },
+                scope: RateLimiterScope::Cluster {
+                    store: Some("redis".into()),
+                    batch: 10
+                },
+            },
+            QueueRateLimiter {
+                id: "0003".into(),
+                expr: Expression::default(),
+                keys: THROTTLE_REMOTE_IP,
+                rate: Rate {
+                    requests: 5,
+                    period: Duration::from_secs(1)
+                },
+                scope: RateLimiterScope::Node,
             }
         ]
     );
@@ -321,6 +347,7 @@ fn parse_servers() {
             max_connections: 8192,
             proxy_networks: vec![],
             span_id_gen: id_generator.clone(),
+            auth_policy: Default::default(),
         },
         Listener {
             id: "smtps".into(),
@@ -346,6 +373,7 @@ fn parse_servers() {
             max_connections: 1024,
             proxy_networks: vec![],
             span_id_gen: id_generator.clone(),
+            auth_policy: Default::default(),
         },
         Listener {
             id: "submission".into(),
@@ -361,6 +389,10 @@ fn parse_servers() {
             max_connections: 8192,
             proxy_networks: vec![],
             span_id_gen: id_generator.clone(),
+            auth_policy: AuthPolicy {
+                allow_plain_text: Some(false),
+                min_tls_version: Some(0x0304),
+            },
         },
     ];
 
@@ -375,6 +407,18 @@ fn parse_servers() {
             "failed for {}",
             expected_server.id
         );
+        assert_eq!(
+            (
+                server.auth_policy.allow_plain_text,
+                server.auth_policy.min_tls_version
+            ),
+            (
+                expected_server.auth_policy.allow_plain_text,
+                expected_server.auth_policy.min_tls_version
+            ),
+            "failed for {}",
+            expected_server.id
+        );
         for (listener, expected_listener) in
             server.listeners.into_iter().zip(expected_server.listeners)
         {
@@ -498,6 +542,106 @@ async fn eval_dynvalue() {
     }
 }
 
+#[tokio::test]
+async fn eval_if_trace() {
+    let mut config = Config::new(concat!(
+        "[test]\n",
+        "expr = [{if = \"sender_domain == 'foobar.org'\", then = \"'internal'\"},\n",
+        "        {if = \"rcpt_domain == 'example.org'\", then = \"'example'\"},\n",
+        "        {else = \"'default'\"}]\n",
+    ))
+    .unwrap();
+    let if_block = IfBlock::try_parse(
+        &mut config,
+        "test.expr",
+        &TokenMap::default().with_variables(&[V_SENDER_DOMAIN, V_RECIPIENT_DOMAIN]),
+    )
+    .unwrap();
+    let mut envelope = TestEnvelope {
+        local_ip: "127.0.0.1".parse().unwrap(),
+        remote_ip: "10.0.0.1".parse().unwrap(),
+        sender_domain: "remote.org".to_string(),
+        sender: "john@remote.org".to_string(),
+        rcpt_domain: "example.org".to_string(),
+        rcpt: "jane@example.org".to_string(),
+        helo_domain: "mx.remote.org".to_string(),
+        authenticated_as: String::new(),
+        mx: String::new(),
+        listener_id: "smtp".to_string(),
+        priority: 0,
+    };
+    let core = Server::default();
+
+    // Each evaluated condition and the matching branch are traced
+    let mut trace = Vec::new();
+    assert_eq!(
+        core.eval_if_trace(&if_block, &envelope, &mut trace, 0)
+            .await
+            .unwrap(),
+        Variable::from("example")
+    );
+    assert_eq!(
+        serde_json::to_value(&trace).unwrap(),
+        serde_json::json!([
+            {
+                "branch": "if",
+                "condition": 0,
+                "steps": [
+                    {"operation": "variable sender_domain", "stack": "remote.org"},
+                    {"operation": "constant \"foobar.org\"", "stack": "foobar.org"},
+                    {"operation": "operator ==", "stack": 0}
+                ],
+                "result": 0
+            },
+            {
+                "branch": "if",
+                "condition": 1,
+                "steps": [
+                    {"operation": "variable rcpt_domain", "stack": "example.org"},
+                    {"operation": "constant \"example.org\"", "stack": "example.org"},
+                    {"operation": "operator ==", "stack": 1}
+                ],
+                "result": 1
+            },
+            {
+                "branch": "then",
+                "condition": 1,
+                "steps": [
+                    {"operation": "constant \"example\"", "stack": "example"}
+                ],
+                "result": "example"
+            }
+        ])
+    );
+
+    // The default branch is traced when no condition matches
+    envelope.rcpt_domain = "other.org".to_string();
+    let mut trace = Vec::new();
+    assert_eq!(
+        core.eval_if_trace(&if_block, &envelope, &mut trace, 0)
+            .await
+            .unwrap(),
+        Variable::from("default")
+    );
+    assert_eq!(
+        trace
+            .iter()
+            .map(|trace| (trace.branch, trace.condition, trace.result.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (EvalBranch::If, Some(0), serde_json::json!(0)),
+            (EvalBranch::If, Some(1), serde_json::json!(0)),
+            (EvalBranch::Else, None, serde_json::json!("default")),
+        ]
+    );
+
+    // Tracing produces the same result as a regular evaluation
+    assert_eq!(
+        core.eval_if::<String, _>(&if_block, &envelope, 0).await,
+        Some("default".to_string())
+    );
+}
+
 impl ResolveVariable for TestEnvelope {
     fn resolve_variable(&self, variable: u32) -> Variable<'_> {
         match variable {
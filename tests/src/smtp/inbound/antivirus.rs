@@ -0,0 +1,173 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use common::Core;
+use smtp::core::Session;
+use store::Stores;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{TempDir, TestSMTP, session::TestSession},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "bill"
+description = "Bill Foobar"
+secret = "p4ssw0rd"
+email = "bill@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+
+[session.antivirus."clamd"]
+hostname = "127.0.0.1"
+port = {PORT}
+enable = true
+cache.ttl = "1h"
+"#;
+
+#[tokio::test]
+async fn antivirus() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start a clamd server that flags any stream containing the test signature
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let scans = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(mock_clamd(listener, scans.clone()));
+
+    let tmp_dir = TempDir::new("smtp_antivirus_test", true);
+    let mut config = Config::new(
+        tmp_dir
+            .update_config(CONFIG)
+            .replace("{PORT}", &port.to_string()),
+    )
+    .unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Clean messages are delivered
+    let message = concat!(
+        "From: john@remote.org\r\n",
+        "To: bill@foobar.org\r\n",
+        "Subject: Hello\r\n",
+        "\r\n",
+        "Nothing to see here.\r\n"
+    );
+    session
+        .send_message("john@remote.org", &["bill@foobar.org"], message, "250")
+        .await;
+    qr.expect_message().await;
+    assert_eq!(scans.load(Ordering::Relaxed), 1);
+
+    // Verdicts are cached for identical messages
+    session
+        .send_message("john@remote.org", &["bill@foobar.org"], message, "250")
+        .await;
+    qr.expect_message().await;
+    assert_eq!(scans.load(Ordering::Relaxed), 1);
+
+    // Messages that only differ in their headers are scanned again
+    session
+        .send_message(
+            "john@remote.org",
+            &["bill@foobar.org"],
+            &message.replace("Subject: Hello", "Subject: EICAR-TEST-SIGNATURE"),
+            "554 5.7.1",
+        )
+        .await;
+    qr.assert_no_events();
+    assert_eq!(scans.load(Ordering::Relaxed), 2);
+
+    qr.clear_queue(&test.server).await;
+}
+
+async fn mock_clamd(listener: TcpListener, scans: Arc<AtomicUsize>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let scans = scans.clone();
+        tokio::spawn(async move {
+            let _ = handle_clamd_session(stream, scans).await;
+        });
+    }
+}
+
+async fn handle_clamd_session(
+    mut stream: TcpStream,
+    scans: Arc<AtomicUsize>,
+) -> std::io::Result<()> {
+    let mut command = [0u8; 11];
+    stream.read_exact(&mut command).await?;
+    assert_eq!(&command, b"zIDSESSION\0");
+
+    for id in 1.. {
+        let mut command = [0u8; 10];
+        stream.read_exact(&mut command).await?;
+        assert_eq!(&command, b"zINSTREAM\0");
+
+        let mut contents = Vec::new();
+        loop {
+            let len = stream.read_u32().await? as usize;
+            if len == 0 {
+                break;
+            }
+            let offset = contents.len();
+            contents.resize(offset + len, 0);
+            stream.read_exact(&mut contents[offset..]).await?;
+        }
+        scans.fetch_add(1, Ordering::Relaxed);
+
+        let verdict = if contents
+            .windows(20)
+            .any(|window| window == b"EICAR-TEST-SIGNATURE")
+        {
+            "Eicar-Test-Signature FOUND"
+        } else {
+            "OK"
+        };
+        stream
+            .write_all(format!("{id}: stream: {verdict}\0").as_bytes())
+            .await?;
+    }
+
+    Ok(())
+}
@@ -16,7 +16,10 @@ use crate::{
         session::{TestSession, VerifyResponse},
     },
 };
-use smtp::core::{Session, State};
+use smtp::{
+    core::{Session, State},
+    inbound::sending::SmtpSendingLimits,
+};
 
 const CONFIG: &str = r#"
 [storage]
@@ -66,6 +69,12 @@ wait = "100ms"
 [session.extensions]
 future-release = [{if = '!is_empty(authenticated_as)', then = '1d'},
                   {else = false}]
+
+[session.mail.limits]
+messages.hour = [{if = "authenticated_as = 'jane'", then = 1},
+                 {else = false}]
+recipients.day = [{if = "authenticated_as = 'jane'", then = 2},
+                  {else = false}]
 "#;
 
 #[tokio::test]
@@ -147,6 +156,34 @@ async fn auth() {
     session.cmd("amFuZQ==", "334").await;
     session.cmd("cDRzc3cwcmQ=", "235 2.7.0").await;
 
+    // Recipient limits should be enforced within the transaction
+    session.mail_from("jane@example.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session.rcpt_to("mike@foobar.org", "250").await;
+    session.rcpt_to("joe@foobar.org", "550 5.4.5").await;
+    session.reset();
+
+    // Sent messages should count towards the account limits
+    let account_id = session.data.authenticated_as.as_ref().unwrap().primary_id();
+    session
+        .server
+        .charge_sending_usage(account_id, 1)
+        .await
+        .unwrap();
+    let usage = session.server.sending_usage(account_id).await.unwrap();
+    assert_eq!(usage.messages_hour, 1);
+    assert_eq!(usage.recipients_day, 1);
+    session.mail_from("jane@example.org", "452 4.4.5").await;
+
+    // Clearing the usage should allow sending again
+    session
+        .server
+        .clear_sending_usage(account_id)
+        .await
+        .unwrap();
+    session.mail_from("jane@example.org", "250").await;
+    session.reset();
+
     // Login should not be advertised to 10.0.0.2
     session.data.remote_ip_str = "10.0.0.2".into();
     session.eval_session_params().await;
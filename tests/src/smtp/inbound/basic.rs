@@ -4,8 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::sync::atomic::Ordering;
+
 use common::Core;
 use smtp::core::Session;
+use store::write::now;
 
 use crate::smtp::{
     TestSMTP,
@@ -52,3 +55,53 @@ async fn basic_commands() {
     session.ingest(b"QUIT\r\n").await.unwrap_err();
     session.response().assert_code("221");
 }
+
+#[tokio::test]
+async fn maintenance_window() {
+    // Enable logging
+    crate::enable_logging();
+
+    let mut core = Core::default();
+    let now = now();
+    core.smtp.session.maintenance.windows = vec![(now - 60, now + 60)];
+    let server = TestSMTP::from_core(core).server;
+
+    // Remote senders are deferred during scheduled maintenance
+    let mut session = Session::test(server.clone());
+    session.data.local_port = 25;
+    session.data.remote_ip_str = "10.0.0.1".into();
+    assert!(!session.init_conn().await);
+    session.response().assert_code("421 4.3.2");
+
+    // Submission and local connections keep working
+    let mut session = Session::test(server.clone());
+    session.data.local_port = 587;
+    session.data.remote_ip_str = "10.0.0.1".into();
+    assert!(session.init_conn().await);
+    session.response().assert_code("220");
+    let mut session = Session::test(server.clone());
+    session.data.local_port = 25;
+    session.data.remote_ip_str = "127.0.0.1".into();
+    assert!(session.init_conn().await);
+    session.response().assert_code("220");
+
+    // Maintenance mode can also be toggled at runtime
+    let mut core = Core::default();
+    core.smtp.session.maintenance.windows = vec![(now + 3600, now + 7200)];
+    let server = TestSMTP::from_core(core).server;
+    let mut session = Session::test(server.clone());
+    session.data.local_port = 25;
+    session.data.remote_ip_str = "10.0.0.1".into();
+    assert!(session.init_conn().await);
+    session.response().assert_code("220");
+    server
+        .inner
+        .data
+        .smtp_maintenance
+        .store(true, Ordering::Relaxed);
+    let mut session = Session::test(server);
+    session.data.local_port = 25;
+    session.data.remote_ip_str = "10.0.0.1".into();
+    assert!(!session.init_conn().await);
+    session.response().assert_code("421 4.3.2");
+}
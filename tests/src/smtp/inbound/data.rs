@@ -137,7 +137,17 @@ async fn data() {
             "john@doe.org",
             &["bill@foobar.org"],
             "test:loop",
-            "450 4.4.6",
+            "554 5.4.6",
+        )
+        .await;
+
+    // Delivered-To loop detection
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            "test:delivered_to_loop",
+            "554 5.4.6",
         )
         .await;
 
@@ -231,6 +241,12 @@ async fn data() {
         )
         .await;
 
+    // Bounces about other bounces are discarded
+    session
+        .send_message("<>", &["bill@foobar.org"], "test:dsn_loop", "250")
+        .await;
+    qr.assert_no_events();
+
     // Make sure store is empty
     qr.clear_queue(&test.server).await;
     test.server
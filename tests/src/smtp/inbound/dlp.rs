@@ -0,0 +1,172 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use common::{Core, auth::AccessToken};
+use smtp::{
+    core::Session,
+    queue::{
+        QUARANTINED,
+        quarantine::{QuarantineEntry, SmtpQuarantine},
+    },
+};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{
+        TempDir, TestSMTP,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@foobar.org"
+
+[[directory."local".principals]]
+name = "bill"
+description = "Bill Foobar"
+secret = "p4ssw0rd"
+email = "bill@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+
+[session.data.dlp.cards]
+enable = true
+match.regex = ["\\b4[0-9]{12}(?:[0-9]{3})?\\b"]
+action = "block"
+block.reason = "Card numbers are not allowed."
+
+[session.data.dlp.secrets]
+enable = true
+match.keywords = ["top secret"]
+match.dictionary = "dlp-terms"
+threshold = 2
+action = "quarantine"
+
+[lookup]
+"dlp-terms" = {"zanzibar", "katmandu"}
+"#;
+
+#[tokio::test]
+async fn dlp() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_dlp_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // DLP rules only apply to authenticated submissions
+    let card_message = concat!(
+        "From: john@foobar.org\r\n",
+        "To: bill@foobar.org\r\n",
+        "Subject: Payment\r\n",
+        "\r\n",
+        "My card is 4111111111111111, thanks.\r\n"
+    );
+    session
+        .send_message("john@foobar.org", &["bill@foobar.org"], card_message, "250")
+        .await;
+    qr.expect_message().await;
+
+    // Block messages containing card numbers
+    session.data.authenticated_as = Some(Arc::new(AccessToken {
+        name: "john".into(),
+        ..Default::default()
+    }));
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@foobar.org"],
+            card_message,
+            "550 5.7.0 Card numbers are not allowed.",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Matches below the threshold are delivered
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@foobar.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@foobar.org\r\n",
+                "Subject: Plans\r\n",
+                "\r\n",
+                "This is top secret.\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.expect_message().await;
+    assert_eq!(message.flags & QUARANTINED, 0);
+
+    // Keyword and dictionary matches add up to the threshold
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@foobar.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@foobar.org\r\n",
+                "Subject: Travel plans\r\n",
+                "\r\n",
+                "Our TOP SECRET meeting will take place in Zanzibar.\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.expect_message().await;
+    assert_ne!(message.flags & QUARANTINED, 0);
+    let entry = test
+        .server
+        .read_quarantine_entry(message.queue_id)
+        .await
+        .unwrap()
+        .expect("missing quarantine entry")
+        .deserialize::<QuarantineEntry>()
+        .unwrap();
+    assert_eq!(entry.reason, "DLP rule secrets matched");
+    assert_eq!(entry.subject.as_deref(), Some("Travel plans"));
+    assert_eq!(entry.from.as_deref(), Some("john@foobar.org"));
+
+    qr.clear_queue(&test.server).await;
+}
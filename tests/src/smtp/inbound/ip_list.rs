@@ -0,0 +1,197 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::IpAddr;
+
+use common::{
+    Core,
+    listener::iplist::{IpListAction, IpListEntry, IpSet},
+};
+use smtp::core::Session;
+use store::{Stores, write::now};
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{
+        TempDir, TestSMTP,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[ip-list."blocked"]
+action = "block"
+description = "Blocked hosts"
+
+[ip-list."trusted"]
+action = "allow"
+
+[ip-list."senders"]
+action = "allow"
+
+[ip-list."disabled"]
+action = "block"
+enable = false
+
+[session.mail]
+is-allowed = "in_ip_list('senders', remote_ip)"
+"#;
+
+#[tokio::test]
+async fn ip_list() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_ip_list_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+    assert_eq!(core.network.ip_lists.len(), 3);
+
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+    // Entries added to a block list block the address
+    assert!(!server.is_ip_blocked(&ip("10.0.0.1")));
+    server
+        .ip_list_update(
+            "blocked",
+            vec![
+                ("10.0.0.1".to_string(), IpListEntry::default()),
+                ("192.168.1.0/24".to_string(), IpListEntry::default()),
+                (
+                    "10.0.0.9".to_string(),
+                    IpListEntry {
+                        expires: Some(now() - 1),
+                        ..Default::default()
+                    },
+                ),
+            ],
+            false,
+        )
+        .await
+        .unwrap();
+    assert!(server.is_ip_blocked(&ip("10.0.0.1")));
+    assert!(server.ip_list_contains("blocked", &ip("10.0.0.1")));
+    assert!(!server.ip_list_contains("trusted", &ip("10.0.0.1")));
+
+    // Networks match any address they contain
+    assert!(server.is_ip_blocked(&ip("192.168.1.25")));
+    assert!(!server.is_ip_blocked(&ip("192.168.2.25")));
+
+    // Expired entries are ignored
+    assert!(!server.is_ip_blocked(&ip("10.0.0.9")));
+
+    // Allow lists take precedence over block lists
+    server
+        .ip_list_update(
+            "trusted",
+            vec![("192.168.1.25".to_string(), IpListEntry::default())],
+            false,
+        )
+        .await
+        .unwrap();
+    assert!(server.is_ip_allowed(&ip("192.168.1.25")));
+    assert!(!server.is_ip_blocked(&ip("192.168.1.25")));
+    assert!(server.is_ip_blocked(&ip("192.168.1.26")));
+
+    // Removing an entry unblocks the address
+    server.ip_list_remove("blocked", "10.0.0.1").await.unwrap();
+    assert!(!server.is_ip_blocked(&ip("10.0.0.1")));
+    assert!(server.is_ip_blocked(&ip("192.168.1.26")));
+
+    // Replacing a list drops the entries not included in the new set
+    server
+        .ip_list_update(
+            "blocked",
+            vec![("10.0.0.2".to_string(), IpListEntry::default())],
+            true,
+        )
+        .await
+        .unwrap();
+    assert!(server.is_ip_blocked(&ip("10.0.0.2")));
+    assert!(!server.is_ip_blocked(&ip("192.168.1.26")));
+
+    // Entries in disabled lists are not enforced
+    server
+        .ip_list_update(
+            "disabled",
+            vec![("10.0.0.3".to_string(), IpListEntry::default())],
+            false,
+        )
+        .await
+        .unwrap();
+    assert!(!server.is_ip_listed(&ip("10.0.0.3"), IpListAction::Block));
+    assert!(!server.is_ip_blocked(&ip("10.0.0.3")));
+
+    // Entries survive a reload from the configuration store
+    server.reload_ip_lists().await.unwrap();
+    assert!(server.is_ip_blocked(&ip("10.0.0.2")));
+    assert!(server.is_ip_allowed(&ip("192.168.1.25")));
+
+    // Lists can be queried from expressions
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.5".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+    session
+        .ingest(b"MAIL FROM:<john@foobar.org>\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("550 5.7.1");
+    server
+        .ip_list_update(
+            "senders",
+            vec![("10.0.0.0/24".to_string(), IpListEntry::default())],
+            false,
+        )
+        .await
+        .unwrap();
+    session
+        .ingest(b"MAIL FROM:<john@foobar.org>\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("250");
+
+    // Feeds are parsed skipping comments and invalid lines
+    let feed = IpSet::parse_feed(
+        concat!(
+            "# Spamhaus DROP list\n",
+            "1.10.16.0/20 ; SBL256894\n",
+            "\n",
+            "not an address\n",
+            "203.0.113.7\n",
+            "2001:db8::/32 # documentation\n",
+            "198.51.100.1\n",
+        ),
+        3,
+    );
+    assert_eq!(feed.len(), 3);
+    assert!(feed.contains(&ip("1.10.20.1"), now()));
+    assert!(feed.contains(&ip("203.0.113.7"), now()));
+    assert!(feed.contains(&ip("2001:db8::1"), now()));
+    assert!(!feed.contains(&ip("198.51.100.1"), now()));
+}
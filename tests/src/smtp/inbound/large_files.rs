@@ -0,0 +1,237 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use common::{Core, auth::AccessToken};
+use mail_parser::MessageParser;
+use smtp::{
+    core::Session,
+    inbound::large_files::{SmtpLargeFiles, large_file_token, parse_large_file_token},
+};
+use store::{Stores, write::now};
+use utils::{BlobHash, config::Config};
+
+use crate::{
+    AssertConfig,
+    smtp::{
+        TempDir, TestSMTP,
+        inbound::TestMessage,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[store."files"]
+type = "fs"
+path = "{TMP}/files"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@foobar.org"
+
+[[directory."local".principals]]
+name = "bill"
+description = "Bill Foobar"
+secret = "p4ssw0rd"
+email = "bill@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+
+[session.data.large-files]
+threshold = 2000
+url = "'https://mail.foobar.org/download/{token}/{name}'"
+expiry = [{if = "authenticated_as = 'jane'", then = "0s"},
+          {else = "1d"}]
+min-size = 1000
+store = "files"
+"#;
+
+#[tokio::test]
+async fn large_files() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_large_files_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    let attachment = "0123456789".repeat(300);
+    let message = format!(
+        concat!(
+            "From: john@foobar.org\r\n",
+            "To: bill@foobar.org\r\n",
+            "Subject: Report\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n",
+            "\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "Please find the report attached.\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n",
+            "Content-Disposition: attachment; filename=\"annual report.txt\"\r\n",
+            "\r\n",
+            "{}\r\n",
+            "--boundary--\r\n"
+        ),
+        attachment
+    );
+
+    // Unauthenticated messages are delivered unchanged
+    session
+        .send_message("john@foobar.org", &["bill@foobar.org"], &message, "250")
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains(&attachment);
+
+    // Large attachments in submissions are replaced with download links
+    session.data.authenticated_as = Some(Arc::new(AccessToken {
+        name: "john".into(),
+        ..Default::default()
+    }));
+    session
+        .send_message("john@foobar.org", &["bill@foobar.org"], &message, "250")
+        .await;
+    let delivered = qr.expect_message().await.read_message(&qr).await;
+    assert!(!delivered.contains(&attachment), "{delivered}");
+    assert!(
+        delivered.contains("Please find the report attached."),
+        "{delivered}"
+    );
+    let notice = MessageParser::new()
+        .parse(delivered.as_bytes())
+        .unwrap()
+        .body_text(0)
+        .unwrap()
+        .into_owned();
+    let link = notice
+        .split_once("https://mail.foobar.org/download/")
+        .map(|(_, link)| link.trim())
+        .unwrap_or_else(|| panic!("Missing download link: {notice}"));
+    let (token, name) = link.split_once('/').unwrap();
+    assert_eq!(name, "annual%20report.txt");
+    assert!(notice.contains("annual report.txt"), "{notice}");
+
+    // The token grants access to the attachment kept in the configured store
+    let blob_store = test.server.core.storage.blobs.get("files").unwrap().clone();
+    let hash = parse_large_file_token(&test.server, token).expect("Invalid token");
+    assert_eq!(hash, BlobHash::generate(attachment.as_bytes()));
+    assert_eq!(
+        blob_store
+            .get_blob(hash.as_slice(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some(attachment.as_bytes())
+    );
+
+    // Expired or tampered tokens are rejected
+    assert!(
+        parse_large_file_token(
+            &test.server,
+            &large_file_token(&test.server, &hash, now() - 1)
+        )
+        .is_none()
+    );
+    let mut tampered = token.to_string();
+    tampered.replace_range(0..1, if token.starts_with('a') { "b" } else { "a" });
+    assert!(parse_large_file_token(&test.server, &tampered).is_none());
+
+    // Messages below the threshold are not modified
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@foobar.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@foobar.org\r\n",
+                "Subject: Hello\r\n",
+                "\r\n",
+                "Hi!\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_not_contains("download");
+
+    // Files are purged from the store once all their links have expired
+    let other_attachment = "9876543210".repeat(300);
+    session.data.authenticated_as = Some(Arc::new(AccessToken {
+        name: "jane".into(),
+        ..Default::default()
+    }));
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@foobar.org"],
+            &message.replace(&attachment, &other_attachment),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_not_contains(&other_attachment);
+    let other_hash = BlobHash::generate(other_attachment.as_bytes());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(test.server.purge_large_files().await.unwrap(), 1);
+    assert!(
+        blob_store
+            .get_blob(other_hash.as_slice(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        blob_store
+            .get_blob(hash.as_slice(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert_eq!(test.server.purge_large_files().await.unwrap(), 0);
+
+    qr.clear_queue(&test.server).await;
+}
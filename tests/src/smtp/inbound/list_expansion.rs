@@ -0,0 +1,151 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Core;
+use directory::backend::internal::{
+    PrincipalField, PrincipalUpdate, PrincipalValue,
+    manage::{ManageDirectory, UpdatePrincipal},
+};
+use smtp::core::Session;
+use store::{Store, Stores};
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    directory::internal::TestInternalDirectory,
+    smtp::{
+        TempDir, TestSMTP,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "internal"
+store = "rocksdb"
+
+[session.rcpt]
+directory = "'local'"
+max-expansion = 4
+max-expansion-depth = 2
+"#;
+
+#[tokio::test]
+async fn list_expansion() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_list_expansion_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    // Create users and nested lists
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let store = &server.core.storage.data;
+    for name in ["john", "jane", "bill"] {
+        let email = format!("{name}@foobar.org");
+        store
+            .create_test_user(&email, "secret", name, &[&email])
+            .await;
+    }
+    store
+        .create_test_list(
+            "staff@foobar.org",
+            "Staff",
+            &["jane@foobar.org", "bill@foobar.org"],
+        )
+        .await;
+    store
+        .create_test_list("team@foobar.org", "Team", &["john@foobar.org"])
+        .await;
+    set_external_members(store, "team@foobar.org", &["staff@foobar.org"]).await;
+    for (list, nested) in [
+        ("loop-a@foobar.org", "loop-b@foobar.org"),
+        ("loop-b@foobar.org", "loop-a@foobar.org"),
+        ("level-1@foobar.org", "level-2@foobar.org"),
+        ("level-2@foobar.org", "level-3@foobar.org"),
+        ("level-3@foobar.org", "john@foobar.org"),
+    ] {
+        store.create_test_list(list, list, &[]).await;
+        set_external_members(store, list, &[nested]).await;
+    }
+
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.remote.org").await;
+    session.mail_from("carl@remote.org", "250").await;
+
+    // Nested lists are expanded to their members
+    session.rcpt_to("team@foobar.org", "250").await;
+    let mut rcpts = session
+        .data
+        .rcpt_to
+        .iter()
+        .map(|rcpt| (rcpt.address_lcase.as_str(), rcpt.dsn_info.as_deref()))
+        .collect::<Vec<_>>();
+    rcpts.sort_unstable();
+    assert_eq!(
+        rcpts,
+        vec![
+            ("bill@foobar.org", Some("rfc822;team@foobar.org")),
+            ("jane@foobar.org", Some("rfc822;team@foobar.org")),
+            ("john@foobar.org", Some("rfc822;team@foobar.org")),
+        ]
+    );
+    assert_eq!(session.data.rcpt_expanded, 3);
+
+    // Loops are detected
+    session
+        .rcpt_to("loop-a@foobar.org", "554 5.4.6 Mail loop detected")
+        .await;
+
+    // Lists nested too deeply are rejected
+    session
+        .rcpt_to("level-1@foobar.org", "554 5.4.6 Too many nested lists")
+        .await;
+
+    // The expansion budget is shared by all recipients of a message
+    session
+        .rcpt_to("staff@foobar.org", "554 5.4.6 Too many recipients")
+        .await;
+    assert_eq!(session.data.rcpt_to.len(), 3);
+
+    // The budget is reset for each new message
+    session.rset().await;
+    session.mail_from("carl@remote.org", "250").await;
+    session.rcpt_to("staff@foobar.org", "250").await;
+    assert_eq!(session.data.rcpt_expanded, 2);
+}
+
+async fn set_external_members(store: &Store, list: &str, members: &[&str]) {
+    store
+        .update_principal(
+            UpdatePrincipal::by_name(list).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::ExternalMembers,
+                PrincipalValue::StringList(members.iter().map(|m| m.to_string()).collect()),
+            )]),
+        )
+        .await
+        .unwrap();
+}
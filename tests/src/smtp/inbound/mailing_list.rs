@@ -0,0 +1,329 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    Core,
+    config::smtp::list::{ListModeration, MailingListConfig, ReplyToPolicy},
+};
+use smtp::{
+    core::Session,
+    queue::{
+        QUARANTINED,
+        quarantine::{QuarantineEntry, SmtpQuarantine},
+        spool::SmtpSpool,
+    },
+};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    directory::internal::TestInternalDirectory,
+    smtp::{
+        TempDir, TestSMTP,
+        inbound::TestMessage,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "internal"
+store = "rocksdb"
+
+[session.rcpt]
+directory = "'local'"
+max-expansion = [{if = "remote_ip = '10.0.0.2'", then = 2},
+                 {else = 100}]
+
+[mailing-list."dev"]
+address = "Dev@foobar.org"
+name = "Developers"
+subject-prefix = "[dev]"
+reply-to = "list"
+footer.text = "Manage your subscription at https://lists.foobar.org/dev"
+headers.help = "mailto:dev-help@foobar.org"
+headers.unsubscribe = "<mailto:dev-unsubscribe@foobar.org>"
+
+[mailing-list."announce"]
+address = "announce@foobar.org"
+moderation = "non-members"
+moderator = "moderator@foobar.org"
+verp = false
+"#;
+
+const MESSAGE: &str = concat!(
+    "From: carl@remote.org\r\n",
+    "To: dev@foobar.org\r\n",
+    "Subject: Release schedule\r\n",
+    "List-Id: <spoofed.remote.org>\r\n",
+    "Precedence: bulk\r\n",
+    "\r\n",
+    "The release is scheduled for next week.\r\n"
+);
+
+#[tokio::test]
+async fn mailing_list() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_mailing_list_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    // List addresses are normalized and the defaults applied
+    let lists = &core.smtp.list.lists;
+    assert_eq!(lists.len(), 2);
+    let dev = lists.get("dev@foobar.org").unwrap();
+    assert_eq!(dev.list_id(), "Developers <dev.foobar.org>");
+    assert_eq!(dev.bounce_address(), "dev-bounces@foobar.org");
+    assert_eq!(dev.reply_to, ReplyToPolicy::List);
+    assert_eq!(dev.moderation, ListModeration::None);
+    assert_eq!(
+        dev.unsubscribe.as_deref(),
+        Some("mailto:dev-unsubscribe@foobar.org")
+    );
+    assert!(dev.verp);
+    let announce = lists.get("announce@foobar.org").unwrap();
+    assert_eq!(announce.list_id(), "<announce.foobar.org>");
+    assert!(!announce.verp);
+
+    // Moderated lists require a moderator
+    let mut config = Config::new(concat!(
+        "[mailing-list.\"invalid\"]\n",
+        "address = \"invalid@foobar.org\"\n",
+        "moderation = \"all\"\n",
+    ))
+    .unwrap();
+    assert!(MailingListConfig::parse(&mut config).lists.is_empty());
+    assert!(config.errors.contains_key("mailing-list.invalid.moderator"));
+
+    // Create list members
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let qr = test.queue_receiver;
+    let store = &server.core.storage.data;
+    for name in ["john", "jane", "bill"] {
+        let email = format!("{name}@foobar.org");
+        store
+            .create_test_user(&email, "secret", name, &[&email])
+            .await;
+    }
+    store
+        .create_test_list(
+            "dev@foobar.org",
+            "Developers",
+            &["john@foobar.org", "jane@foobar.org", "bill@foobar.org"],
+        )
+        .await;
+    store
+        .create_test_list(
+            "announce@foobar.org",
+            "Announcements",
+            &["john@foobar.org", "jane@foobar.org"],
+        )
+        .await;
+
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.remote.org").await;
+
+    // Posts are distributed to each member with its own return path
+    session
+        .send_message("carl@remote.org", &["dev@foobar.org"], MESSAGE, "250")
+        .await;
+    let mut copies = qr.read_queued_messages().await;
+    copies.sort_by(|a, b| a.recipients[0].address.cmp(&b.recipients[0].address));
+    assert_eq!(
+        copies
+            .iter()
+            .map(|message| (
+                message.return_path.as_str(),
+                message.recipients.len(),
+                message.recipients[0].address.as_str(),
+                message.recipients[0].orcpt.as_deref()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "dev-bounces+bill=foobar.org@foobar.org",
+                1,
+                "bill@foobar.org",
+                Some("rfc822;dev@foobar.org")
+            ),
+            (
+                "dev-bounces+jane=foobar.org@foobar.org",
+                1,
+                "jane@foobar.org",
+                Some("rfc822;dev@foobar.org")
+            ),
+            (
+                "dev-bounces+john=foobar.org@foobar.org",
+                1,
+                "john@foobar.org",
+                Some("rfc822;dev@foobar.org")
+            ),
+        ]
+    );
+
+    // All copies share the same contents
+    assert!(
+        copies
+            .iter()
+            .all(|message| message.blob_hash == copies[0].blob_hash)
+    );
+
+    // List headers replace the ones supplied by the poster
+    let contents = copies[0].read_message(&qr).await;
+    for expected in [
+        "List-Id: Developers <dev.foobar.org>",
+        "List-Post: <mailto:dev@foobar.org>",
+        "List-Help: <mailto:dev-help@foobar.org>",
+        "List-Unsubscribe: <mailto:dev-unsubscribe@foobar.org>",
+        "Precedence: list",
+        "Subject: [dev] Release schedule",
+        "Reply-To: <dev@foobar.org>",
+        "Manage your subscription at https://lists.foobar.org/dev",
+    ] {
+        assert!(
+            contents.contains(expected),
+            "{expected:?} not in {contents}"
+        );
+    }
+    for unexpected in ["spoofed.remote.org", "Precedence: bulk", "List-Archive"] {
+        assert!(
+            !contents.contains(unexpected),
+            "{unexpected:?} in {contents}"
+        );
+    }
+    qr.clear_queue(&server).await;
+
+    // Subject prefixes are not added twice
+    session
+        .send_message(
+            "carl@remote.org",
+            &["dev@foobar.org"],
+            &MESSAGE.replace("Subject: ", "Subject: Re: [dev] "),
+            "250",
+        )
+        .await;
+    qr.last_queued_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("Subject: Re: [dev] Release schedule")
+        .assert_not_contains("[dev] [dev]");
+    qr.clear_queue(&server).await;
+
+    // Posts from members to moderated lists are distributed without VERP
+    session
+        .send_message(
+            "john@foobar.org",
+            &["announce@foobar.org"],
+            &MESSAGE.replace("carl@remote.org", "john@foobar.org"),
+            "250",
+        )
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].return_path, "announce-bounces@foobar.org");
+    assert_eq!(messages[0].flags & QUARANTINED, 0);
+    let mut recipients = messages[0]
+        .recipients
+        .iter()
+        .map(|rcpt| rcpt.address.as_str())
+        .collect::<Vec<_>>();
+    recipients.sort();
+    assert_eq!(recipients, vec!["jane@foobar.org", "john@foobar.org"]);
+    qr.clear_queue(&server).await;
+
+    // Posts from non-members are held and the moderator is notified
+    session
+        .send_message(
+            "carl@remote.org",
+            &["announce@foobar.org"],
+            &MESSAGE.replace("dev@foobar.org", "announce@foobar.org"),
+            "250",
+        )
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 2);
+    let held = messages
+        .iter()
+        .find(|message| message.flags & QUARANTINED != 0)
+        .expect("Post was not held");
+    assert_eq!(held.return_path, "carl@remote.org");
+    assert_eq!(held.recipients.len(), 1);
+    assert_eq!(held.recipients[0].address, "announce@foobar.org");
+    let held_id = held.queue_id;
+    let entry = server
+        .read_quarantine_entry(held_id)
+        .await
+        .unwrap()
+        .expect("missing quarantine entry")
+        .deserialize::<QuarantineEntry>()
+        .unwrap();
+    assert_eq!(entry.reason, "Awaiting list moderator approval");
+    assert_eq!(entry.moderator.as_deref(), Some("moderator@foobar.org"));
+    assert_eq!(entry.from.as_deref(), Some("carl@remote.org"));
+    let request = messages
+        .iter()
+        .find(|message| message.flags & QUARANTINED == 0)
+        .unwrap();
+    assert_eq!(request.recipients[0].address, "moderator@foobar.org");
+    request
+        .read_lines(&qr)
+        .await
+        .assert_contains("Approval required for announce@foobar.org: Release schedule");
+    qr.clear_queue(&server).await;
+
+    // Released posts are distributed to the current members of the list
+    assert!(
+        server
+            .read_message(held_id)
+            .await
+            .unwrap()
+            .release_quarantine(&server)
+            .await
+            .unwrap()
+    );
+    assert!(server.read_message(held_id).await.is_none());
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].return_path, "announce-bounces@foobar.org");
+    assert_eq!(messages[0].recipients.len(), 2);
+    messages[0]
+        .read_lines(&qr)
+        .await
+        .assert_contains("List-Id: <announce.foobar.org>");
+    qr.clear_queue(&server).await;
+
+    // Lists exceeding the expansion limit are rejected
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.2".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.remote.org").await;
+    session.mail_from("carl@remote.org", "250").await;
+    session.rcpt_to("announce@foobar.org", "250").await;
+    session.rcpt_to("dev@foobar.org", "554 5.4.6").await;
+}
@@ -94,6 +94,7 @@ relay = true
 url = "http://127.0.0.1:9333"
 enable = true
 stages = ["data"]
+options.full-message = true
 "#;
 
 #[tokio::test]
@@ -354,6 +355,24 @@ async fn mta_hook_session() {
         .await
         .assert_contains("X-Spam: Yes")
         .assert_contains("123456");
+
+    // Test accept with full message replacement
+    session
+        .send_message(
+            "6@doe.org",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250 2.0.0",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Filtered: Yes")
+        .assert_contains("Subject: Rewritten")
+        .assert_contains("Rewritten by hook.")
+        .assert_not_contains("Are you hungry yet?");
 }
 
 #[test]
@@ -562,12 +581,13 @@ async fn milter_client_test() {
             timeout_data: Duration::from_secs(30),
             tls: false,
             tls_allow_invalid_certs: false,
-            tempfail_on_error: false,
             max_frame_len: 5000000,
             protocol_version: MilterVersion::V6,
             flags_actions: None,
             flags_protocol: None,
             run_on_stage: AHashSet::from([Stage::Data]),
+            fail_closed: AHashSet::new(),
+            circuit_breaker: None,
         },
         0,
     )
@@ -840,6 +860,14 @@ pub fn spawn_mock_mta_hook_server() -> watch::Sender<bool> {
 }
 
 fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Response {
+    if let Some(message) = &request.message {
+        assert_eq!(
+            message.raw.as_ref().map(|raw| raw.len()),
+            Some(message.size),
+            "full message was not included in the request"
+        );
+    }
+
     match request
         .envelope
         .unwrap()
@@ -943,6 +971,9 @@ fn handle_mta_hook(request: Request, tests: Arc<Vec<HeaderTest>>) -> hooks::Resp
                     Modification::ReplaceBody { value } => hooks::Modification::ReplaceContents {
                         value: String::from_utf8(value.clone()).unwrap(),
                     },
+                    Modification::ReplaceMessage { value } => hooks::Modification::ReplaceMessage {
+                        value: String::from_utf8(value.clone()).unwrap(),
+                    },
                     Modification::AddHeader { name, value } => hooks::Modification::AddHeader {
                         name: name.clone(),
                         value: value.clone(),
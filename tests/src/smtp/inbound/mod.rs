@@ -23,20 +23,32 @@ use smtp::queue::{Message, QueueId, QueuedMessage};
 use super::{QueueReceiver, ReportReceiver};
 
 pub mod antispam;
+pub mod antivirus;
 pub mod asn;
 pub mod auth;
 pub mod basic;
 pub mod data;
+pub mod dlp;
 pub mod dmarc;
 pub mod ehlo;
+pub mod ip_list;
+pub mod large_files;
 pub mod limits;
+pub mod list_expansion;
 pub mod mail;
+pub mod mailing_list;
 pub mod milter;
+pub mod moderation;
+pub mod proxy;
 pub mod rcpt;
 pub mod rewrite;
+pub mod sandbox;
 pub mod scripts;
 pub mod sign;
+pub mod simulate;
 pub mod throttle;
+pub mod url_reputation;
+pub mod verp;
 pub mod vrfy;
 
 impl QueueReceiver {
@@ -143,6 +155,7 @@ impl QueueReceiver {
         QueuedMessage {
             due: self.message_due(queue_id).await,
             queue_id,
+            tenant_id: None,
         }
     }
 
@@ -0,0 +1,215 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use common::{Core, auth::AccessToken};
+use mail_parser::MessageParser;
+use smtp::{
+    core::Session,
+    queue::{
+        QUARANTINED,
+        quarantine::{
+            ModerationAction, QuarantineEntry, SmtpQuarantine, moderation_token,
+            parse_moderation_token,
+        },
+        spool::SmtpSpool,
+    },
+};
+use store::{Stores, write::now};
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{TempDir, TestSMTP, inbound::TestMessage, session::TestSession},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@foobar.org"
+
+[[directory."local".principals]]
+name = "bill"
+description = "Bill Foobar"
+secret = "p4ssw0rd"
+email = "bill@foobar.org"
+
+[[directory."local".principals]]
+name = "moderator"
+description = "Moderator"
+secret = "secret"
+email = "moderator@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+relay = true
+
+[session.data.moderation]
+moderator = "'moderator@foobar.org'"
+match.external-recipients = true
+url = "https://mail.foobar.org/"
+expiry = "1d"
+"#;
+
+#[tokio::test]
+async fn moderation() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_moderation_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+    let message = concat!(
+        "From: john@foobar.org\r\n",
+        "To: jane@external.org\r\n",
+        "Subject: Quarterly results\r\n",
+        "\r\n",
+        "See you soon.\r\n"
+    );
+
+    // Only authenticated submissions are moderated
+    session
+        .send_message("john@foobar.org", &["jane@external.org"], message, "250")
+        .await;
+    let delivered = qr.expect_message().await;
+    assert_eq!(delivered.flags & QUARANTINED, 0);
+    qr.clear_queue(&test.server).await;
+
+    // Submissions to local recipients are not moderated
+    session.data.authenticated_as = Some(Arc::new(AccessToken {
+        name: "john".into(),
+        ..Default::default()
+    }));
+    session
+        .send_message("john@foobar.org", &["bill@foobar.org"], message, "250")
+        .await;
+    let delivered = qr.expect_message().await;
+    assert_eq!(delivered.flags & QUARANTINED, 0);
+    qr.clear_queue(&test.server).await;
+
+    // Submissions to external recipients are held and the moderator is notified
+    session
+        .send_message("john@foobar.org", &["jane@external.org"], message, "250")
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 2);
+    let held = messages
+        .iter()
+        .find(|message| message.flags & QUARANTINED != 0)
+        .expect("Message was not held");
+    assert_eq!(held.recipients[0].address, "jane@external.org");
+    let held_id = held.queue_id;
+    let request = messages
+        .iter()
+        .find(|message| message.flags & QUARANTINED == 0)
+        .unwrap();
+    assert_eq!(request.recipients[0].address, "moderator@foobar.org");
+
+    // Held messages expire along with the approval links
+    let entry = test
+        .server
+        .read_quarantine_entry(held.queue_id)
+        .await
+        .unwrap()
+        .expect("missing quarantine entry")
+        .deserialize::<QuarantineEntry>()
+        .unwrap();
+    assert_eq!(entry.moderator.as_deref(), Some("moderator@foobar.org"));
+    assert_eq!(entry.subject.as_deref(), Some("Quarterly results"));
+    assert_eq!(entry.expires, entry.created + 86400);
+
+    // The request includes signed links to approve or reject the message
+    let request = request.read_message(&qr).await;
+    let body = MessageParser::new()
+        .parse(request.as_bytes())
+        .unwrap()
+        .body_text(0)
+        .unwrap()
+        .into_owned();
+    let tokens = body
+        .split("https://mail.foobar.org/moderate/")
+        .skip(1)
+        .map(|link| link.split_whitespace().next().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(tokens.len(), 2, "{body}");
+    assert_eq!(
+        parse_moderation_token(&test.server, &tokens[0]),
+        Some((held.queue_id, ModerationAction::Approve))
+    );
+    assert_eq!(
+        parse_moderation_token(&test.server, &tokens[1]),
+        Some((held.queue_id, ModerationAction::Reject))
+    );
+
+    // Expired or tampered tokens are rejected
+    assert_eq!(
+        parse_moderation_token(
+            &test.server,
+            &moderation_token(
+                &test.server,
+                held.queue_id,
+                ModerationAction::Approve,
+                now() - 1
+            )
+        ),
+        None
+    );
+    let mut tampered = tokens[1].clone();
+    tampered.replace_range(0..1, if tampered.starts_with('a') { "b" } else { "a" });
+    assert_eq!(parse_moderation_token(&test.server, &tampered), None);
+
+    // Approving the message schedules it for delivery
+    assert!(
+        test.server
+            .read_message(held_id)
+            .await
+            .unwrap()
+            .release_quarantine(&test.server)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        test.server.read_message(held_id).await.unwrap().flags & QUARANTINED,
+        0
+    );
+    assert!(
+        qr.read_queued_events()
+            .await
+            .iter()
+            .any(|event| event.queue_id == held_id)
+    );
+
+    qr.clear_queue(&test.server).await;
+}
@@ -0,0 +1,203 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::Core;
+
+use store::Stores;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+};
+use utils::config::Config;
+
+use smtp::core::Session;
+
+use crate::{
+    AssertConfig,
+    smtp::{
+        TempDir, TestSMTP,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/data.db"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+
+[session.extensions]
+dsn = true
+
+[session.proxy]
+backend = [{if = "rcpt_domain = 'proxy.org'", then = "'backend'"},
+           {else = "''"}]
+
+[session.proxy.pool."backend"]
+hosts = ["127.0.0.1:{PORT}"]
+forward = "xclient"
+timeout.connect = "5s"
+timeout.command = "5s"
+"#;
+
+#[tokio::test]
+async fn proxy() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start a backend that records the commands it receives
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (commands_tx, mut commands_rx) = mpsc::channel(8);
+    tokio::spawn(mock_backend(listener, commands_tx));
+
+    let tmp_dir = TempDir::new("smtp_proxy_test", true);
+    let mut config = Config::new(
+        tmp_dir
+            .update_config(CONFIG)
+            .replace("{PORT}", &port.to_string()),
+    )
+    .unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+    let server = TestSMTP::from_core(core).server;
+
+    // Local recipients are verified and accepted locally
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+    session.mail_from("bill@remote.org", "250").await;
+    session.rcpt_to("john@foobar.org", "250").await;
+    session.rcpt_to("unknown@foobar.org", "550 5.1.2").await;
+    session.rset().await;
+
+    // Recipients rejected by the backend keep the session local
+    session.mail_from("bill@remote.org", "250").await;
+    session.rcpt_to("reject@proxy.org", "550 5.1.1").await;
+    assert!(session.data.rcpt_to.is_empty());
+    assert!(session.data.proxy.is_none());
+    commands_rx.recv().await.unwrap();
+    session.rset().await;
+
+    // Transactions are replayed on the backend with the DSN parameters re-encoded,
+    // and pipelined commands are relayed once the backend accepts the recipient
+    session
+        .mail_from("<bill@remote.org> RET=HDRS ENVID=a+2Bb", "250")
+        .await;
+    assert!(
+        session
+            .ingest(
+                concat!(
+                    "RCPT TO:<jane+x@proxy.org> NOTIFY=SUCCESS,FAILURE ",
+                    "ORCPT=rfc822;jane+2Bx@proxy.org\r\nDATA\r\n"
+                )
+                .as_bytes()
+            )
+            .await
+            .is_err()
+    );
+    session
+        .response()
+        .assert_contains("250 2.1.5 OK")
+        .assert_contains("354 Go ahead");
+    let commands = commands_rx.recv().await.unwrap();
+    assert_eq!(
+        commands,
+        vec![
+            "EHLO localhost".to_string(),
+            "XCLIENT ADDR=10.0.0.1 PORT=0 PROTO=ESMTP HELO=mx.foobar.org".to_string(),
+            "EHLO mx.foobar.org".to_string(),
+            "MAIL FROM:<bill@remote.org> RET=HDRS ENVID=a+2Bb".to_string(),
+            concat!(
+                "RCPT TO:<jane+x@proxy.org> NOTIFY=SUCCESS,FAILURE ",
+                "ORCPT=rfc822;jane+2Bx@proxy.org"
+            )
+            .to_string(),
+            "DATA".to_string(),
+        ]
+    );
+
+    // The transfer quota of the session also applies to relayed traffic
+    let mut session = Session::test(server);
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+    session.mail_from("bill@remote.org", "250").await;
+    session.data.bytes_left = 10;
+    session.write_rx(&"X".repeat(100));
+    assert!(
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            session.ingest(b"RCPT TO:<jane@proxy.org>\r\n")
+        )
+        .await
+        .unwrap()
+        .is_err()
+    );
+    session
+        .response()
+        .assert_contains("250 2.1.5 OK")
+        .assert_contains("452 4.7.28");
+}
+
+async fn mock_backend(listener: TcpListener, commands_tx: mpsc::Sender<Vec<String>>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let commands_tx = commands_tx.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut commands = Vec::new();
+            writer.write_all(b"220 backend ready\r\n").await.unwrap();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let command = line
+                    .split_once(' ')
+                    .map_or(line.as_str(), |(command, _)| command)
+                    .to_ascii_uppercase();
+                let reply: &[u8] = match command.as_str() {
+                    "EHLO" => b"250-backend\r\n250 XCLIENT ADDR HELO LOGIN\r\n",
+                    "XCLIENT" => b"220 backend ready\r\n",
+                    "MAIL" => b"250 2.1.0 OK\r\n",
+                    "RCPT" if line.contains("reject@") => b"550 5.1.1 Unknown user\r\n",
+                    "RCPT" => b"250 2.1.5 OK\r\n",
+                    "DATA" => b"354 Go ahead\r\n",
+                    _ => b"500 5.5.1 Unknown command\r\n",
+                };
+                commands.push(line);
+                if writer.write_all(reply).await.is_err() || command == "DATA" {
+                    break;
+                }
+            }
+
+            commands_tx.send(commands).await.ok();
+        });
+    }
+}
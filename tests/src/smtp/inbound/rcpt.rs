@@ -6,7 +6,7 @@
 
 use std::time::Duration;
 
-use common::Core;
+use common::{Core, storage::partition::PartitionHealth};
 
 use smtp_proto::{RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_SUCCESS};
 use store::Stores;
@@ -26,6 +26,13 @@ lookup = "rocksdb"
 blob = "rocksdb"
 fts = "rocksdb"
 
+[storage.partition]
+assign = [{if = "rcpt == 'bill@foobar.org'", then = "'shard-b'"},
+          {else = "''"}]
+
+[storage.partition."shard-b"]
+store = "rocksdb"
+
 [store."rocksdb"]
 type = "rocksdb"
 path = "{TMP}/queue.db"
@@ -162,4 +169,17 @@ async fn rcpt() {
     let rcpt = session.data.rcpt_to.last().unwrap();
     assert!((rcpt.flags & (RCPT_NOTIFY_DELAY | RCPT_NOTIFY_SUCCESS | RCPT_NOTIFY_FAILURE)) != 0);
     assert_eq!(rcpt.dsn_info.as_ref().unwrap(), "Jane.Doe@Foobar.org");
+
+    // Recipients on degraded partitions are temporarily rejected
+    session.server.inner.data.partition_health.write().insert(
+        "shard-b".to_string(),
+        PartitionHealth {
+            degraded: true,
+            ..Default::default()
+        },
+    );
+    session.rset().await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("bill@foobar.org", "451 4.3.0").await;
+    session.rcpt_to("jane@foobar.org", "250").await;
 }
@@ -0,0 +1,268 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::{Arc, Mutex};
+
+use ahash::AHashMap;
+use common::{Core, Server};
+use http_proto::{JsonResponse, ToHttpResponse};
+use hyper::Method;
+use serde_json::json;
+use smtp::{
+    core::Session,
+    queue::{
+        QUARANTINED, QueueId,
+        quarantine::{QuarantineEntry, SmtpQuarantine},
+        sandbox::{SANDBOX_REASON, SandboxPending, SmtpSandbox},
+        spool::SmtpSpool,
+    },
+};
+use store::{Stores, write::now};
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    http_server::{HttpMessage, spawn_mock_http_server},
+    smtp::{
+        TempDir, TestSMTP,
+        inbound::TestMessage,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "bill"
+description = "Bill Foobar"
+secret = "p4ssw0rd"
+email = "bill@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+
+[session.sandbox]
+enable = true
+url = "https://127.0.0.1:9090/"
+allow-invalid-certs = true
+extensions = ["exe", ".PDF"]
+mode = "hold"
+action.malicious = "quarantine"
+action.timeout = "tag"
+"#;
+
+#[tokio::test]
+async fn sandbox() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start a sandbox that reports the verdicts set by the test
+    let submissions = Arc::new(Mutex::new(Vec::<String>::new()));
+    let verdicts = Arc::new(Mutex::new(AHashMap::<String, &'static str>::new()));
+    let submissions_ = submissions.clone();
+    let verdicts_ = verdicts.clone();
+    let _tx = spawn_mock_http_server(Arc::new(move |req: HttpMessage| {
+        if req.method == Method::POST {
+            assert_eq!(req.uri.path(), "/submit");
+            let name = req.headers.get("x-filename").unwrap().clone();
+            assert!(!req.body.as_ref().unwrap().is_empty());
+            submissions_.lock().unwrap().push(name.clone());
+            JsonResponse::new(json!({"id": name})).into_http_response()
+        } else {
+            let id = req.uri.path().strip_prefix("/verdict/").unwrap();
+            let status = verdicts_
+                .lock()
+                .unwrap()
+                .get(id)
+                .copied()
+                .unwrap_or("pending");
+            JsonResponse::new(json!({"status": status, "details": "Trojan.Generic"}))
+                .into_http_response()
+        }
+    }))
+    .await;
+
+    let tmp_dir = TempDir::new("smtp_sandbox_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Attachments with other extensions are not submitted
+    session
+        .send_message(
+            "john@remote.org",
+            &["bill@foobar.org"],
+            &message_with_attachment("notes.txt"),
+            "250",
+        )
+        .await;
+    let message = qr.expect_message().await;
+    assert!(!message.is_quarantined());
+    assert!(submissions.lock().unwrap().is_empty());
+    qr.clear_queue(&server).await;
+
+    // Messages with suspicious attachments are held until a verdict arrives
+    session
+        .send_message(
+            "john@remote.org",
+            &["bill@foobar.org"],
+            &message_with_attachment("invoice.exe"),
+            "250",
+        )
+        .await;
+    assert_eq!(*submissions.lock().unwrap(), vec!["invoice.exe"]);
+    let queue_id = qr.last_queued_message().await.queue_id;
+    assert!(is_held(&server, queue_id).await);
+    assert_eq!(quarantine_reason(&server, queue_id).await, SANDBOX_REASON);
+
+    // Pending verdicts keep the message on hold
+    server.process_sandbox_verdicts().await;
+    assert!(is_held(&server, queue_id).await);
+
+    // Clean verdicts release the message
+    verdicts
+        .lock()
+        .unwrap()
+        .insert("invoice.exe".into(), "clean");
+    server.process_sandbox_verdicts().await;
+    assert!(!is_held(&server, queue_id).await);
+    assert!(
+        qr.read_queued_events()
+            .await
+            .iter()
+            .any(|event| event.queue_id == queue_id)
+    );
+    qr.clear_queue(&server).await;
+
+    // Malicious verdicts keep the message in quarantine
+    session
+        .send_message(
+            "john@remote.org",
+            &["bill@foobar.org"],
+            &message_with_attachment("report.pdf"),
+            "250",
+        )
+        .await;
+    let queue_id = qr.last_queued_message().await.queue_id;
+    verdicts
+        .lock()
+        .unwrap()
+        .insert("report.pdf".into(), "malicious");
+    server.process_sandbox_verdicts().await;
+    assert!(is_held(&server, queue_id).await);
+    assert_eq!(
+        quarantine_reason(&server, queue_id).await,
+        "Sandbox verdict: malicious (Trojan.Generic)"
+    );
+
+    // Verdicts are only applied once
+    verdicts
+        .lock()
+        .unwrap()
+        .insert("report.pdf".into(), "clean");
+    server.process_sandbox_verdicts().await;
+    assert!(is_held(&server, queue_id).await);
+    qr.clear_queue(&server).await;
+
+    // Messages without a verdict are tagged and released once the verdict times out
+    session
+        .send_message(
+            "john@remote.org",
+            &["bill@foobar.org"],
+            &message_with_attachment("setup.exe"),
+            "250",
+        )
+        .await;
+    let queue_id = qr.last_queued_message().await.queue_id;
+    server
+        .write_sandbox_pending(
+            queue_id,
+            SandboxPending {
+                created: now() - 3600,
+                expires: now() - 1,
+                hold: true,
+                submissions: vec!["setup.exe".into()],
+                message_id: None,
+                recipients: vec!["bill@foobar.org".into()],
+            },
+        )
+        .await
+        .unwrap();
+    server.process_sandbox_verdicts().await;
+    assert!(!is_held(&server, queue_id).await);
+    qr.last_queued_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Sandbox-Status: timeout");
+
+    qr.clear_queue(&server).await;
+}
+
+fn message_with_attachment(name: &str) -> String {
+    format!(
+        concat!(
+            "From: john@remote.org\r\n",
+            "To: bill@foobar.org\r\n",
+            "Subject: Your documents\r\n",
+            "Message-ID: <{}@remote.org>\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n",
+            "\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "Please find the file attached.\r\n",
+            "--boundary\r\n",
+            "Content-Type: application/octet-stream\r\n",
+            "Content-Disposition: attachment; filename=\"{}\"\r\n",
+            "\r\n",
+            "MZ0123456789\r\n",
+            "--boundary--\r\n"
+        ),
+        name, name
+    )
+}
+
+async fn is_held(server: &Server, queue_id: QueueId) -> bool {
+    server.read_message(queue_id).await.unwrap().flags & QUARANTINED != 0
+}
+
+async fn quarantine_reason(server: &Server, queue_id: QueueId) -> String {
+    server
+        .read_quarantine_entry(queue_id)
+        .await
+        .unwrap()
+        .expect("missing quarantine entry")
+        .deserialize::<QuarantineEntry>()
+        .unwrap()
+        .reason
+}
@@ -158,12 +158,35 @@ async fn sieve_scripts() {
         match test.server.run_script(name.into(), script, params).await {
             ScriptResult::Accept { .. } => (),
             ScriptResult::Reject(message) => panic!("{}", message),
+            ScriptResult::Replace { .. } if name == "duplicate" => (),
             err => {
                 panic!("Unexpected script result {err:?}");
             }
         }
     }
 
+    // Duplicate IDs are persisted across script executions
+    let params = session
+        .build_script_parameters("data")
+        .with_envelope(&test.server, &session, 0)
+        .await;
+    match test
+        .server
+        .run_script(
+            "duplicate".into(),
+            test.server.core.sieve.trusted_scripts["duplicate"].clone(),
+            params,
+        )
+        .await
+    {
+        ScriptResult::Reject(message) => {
+            assert_eq!(message, "550 5.7.1 Duplicate message\r\n");
+        }
+        err => {
+            panic!("Unexpected script result {err:?}");
+        }
+    }
+
     // Test connect script
     session
         .response()
@@ -0,0 +1,230 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::Core;
+use mail_auth::{common::parse::TxtRecordParser, dmarc::Dmarc, spf::Spf};
+use smtp::{
+    core::Session,
+    inbound::simulate::{SimulationRequest, SimulationResult},
+};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{DnsCache, TempDir, TestSMTP},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[session.data]
+script = "'policy'"
+
+[session.data.add-headers]
+received = true
+received-spf = true
+auth-results = true
+
+[queue.outbound]
+next-hop = [{if = "rcpt_domain = 'foobar.org'", then = "'lmtp'"},
+            {else = "'mx'"}]
+
+[remote.lmtp]
+address = lmtp.foobar.org
+port = 9924
+protocol = 'lmtp'
+
+[sieve.trusted.scripts."policy"]
+contents = '''
+require ["reject", "vnd.stalwart.expressions"];
+
+if header :contains "subject" "reject me" {
+    reject "Message rejected by policy.";
+    stop;
+}
+eval "add_header('X-Policy', 'checked')";
+'''
+"#;
+
+const MESSAGE: &str = concat!(
+    "From: bill@example.com\r\n",
+    "To: jane@foobar.org\r\n",
+    "Subject: $SUBJECT\r\n",
+    "\r\n",
+    "Test message.\r\n"
+);
+
+#[tokio::test]
+async fn simulate() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_simulate_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let qr = test.queue_receiver;
+
+    // Add SPF, DMARC and reverse DNS records
+    server.txt_add(
+        "mx.example.com",
+        Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    server.txt_add(
+        "example.com",
+        Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    server.txt_add(
+        "_dmarc.example.com",
+        Dmarc::parse(b"v=DMARC1; p=reject;").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    server.ptr_add(
+        "10.0.0.1".parse().unwrap(),
+        vec!["mx.example.com.".to_string()],
+        Instant::now() + Duration::from_secs(5),
+    );
+    server.ipv4_add(
+        "mx.example.com.",
+        vec!["10.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(5),
+    );
+
+    // Accepted messages return the verdicts, added headers and routes
+    let result = simulate_message(
+        &server,
+        "10.0.0.1",
+        "Hello",
+        &["Jane@FooBar.org", "john@foobar.org", "bill@remote.org"],
+    )
+    .await;
+    assert!(result.accepted, "{result:?}");
+    assert_eq!(result.response, "250 2.0.0 Message queued for delivery.");
+    assert_eq!(result.iprev.as_deref(), Some("pass"));
+    assert_eq!(result.spf_ehlo.as_deref(), Some("pass"));
+    assert_eq!(result.spf_mail_from.as_deref(), Some("pass"));
+    assert_eq!(result.arc.as_deref(), Some("none"));
+    assert_eq!(result.dmarc.as_deref(), Some("pass"));
+    assert_eq!(result.dmarc_policy.as_deref(), Some("reject"));
+    assert!(result.spam.is_none());
+    assert_eq!(result.sieve.as_deref(), Some("accept"));
+    for header in [
+        "Received: from mx.example.com",
+        "Authentication-Results: ",
+        "Received-SPF: pass",
+        "X-Policy: checked",
+    ] {
+        assert!(
+            result.headers.contains(header),
+            "{header:?} not found in {result:?}"
+        );
+    }
+    let mut routes = result
+        .routes
+        .iter()
+        .map(|route| {
+            (
+                route.domain.as_str(),
+                route.recipients.join(","),
+                route.next_hop.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    routes.sort_unstable();
+    assert_eq!(
+        routes,
+        vec![
+            (
+                "foobar.org",
+                "jane@foobar.org,john@foobar.org".to_string(),
+                "lmtp"
+            ),
+            ("remote.org", "bill@remote.org".to_string(), "mx"),
+        ]
+    );
+
+    // Authentication failures are reported
+    let result = simulate_message(&server, "10.0.0.2", "Hello", &["jane@foobar.org"]).await;
+    assert_eq!(result.spf_ehlo.as_deref(), Some("fail"));
+    assert_eq!(result.spf_mail_from.as_deref(), Some("fail"));
+    assert_eq!(result.dmarc.as_deref(), Some("fail"));
+
+    // Rejections by the Sieve script are returned
+    let result = simulate_message(
+        &server,
+        "10.0.0.1",
+        "Please reject me",
+        &["jane@foobar.org"],
+    )
+    .await;
+    assert!(!result.accepted);
+    assert_eq!(result.sieve.as_deref(), Some("reject"));
+    assert_eq!(result.response, "503 5.5.3 Message rejected by policy.");
+    assert!(result.routes.is_empty());
+
+    // Messages that cannot be parsed are rejected
+    let result = Session::simulate(
+        server.clone(),
+        SimulationRequest {
+            message: "not a message".to_string(),
+            remote_ip: "10.0.0.1".parse().unwrap(),
+            ehlo_domain: "mx.example.com".to_string(),
+            listener: None,
+            authenticated_as: None,
+            env_from: "bill@example.com".to_string(),
+            env_rcpt_to: vec!["jane@foobar.org".to_string()],
+        },
+        None,
+    )
+    .await;
+    assert!(!result.accepted);
+    assert_eq!(result.response, "550 5.7.7 Failed to parse message.");
+
+    // Nothing is queued
+    qr.assert_no_events();
+    qr.assert_queue_is_empty().await;
+}
+
+async fn simulate_message(
+    server: &common::Server,
+    remote_ip: &str,
+    subject: &str,
+    rcpt_to: &[&str],
+) -> SimulationResult {
+    Session::simulate(
+        server.clone(),
+        SimulationRequest {
+            message: MESSAGE.replace("$SUBJECT", subject),
+            remote_ip: remote_ip.parse().unwrap(),
+            ehlo_domain: "mx.example.com".to_string(),
+            listener: None,
+            authenticated_as: None,
+            env_from: "bill@example.com".to_string(),
+            env_rcpt_to: rcpt_to.iter().map(|rcpt| rcpt.to_string()).collect(),
+        },
+        None,
+    )
+    .await
+}
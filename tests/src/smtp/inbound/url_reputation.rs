@@ -0,0 +1,196 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    Core, KV_URL_REPUTATION,
+    config::spamfilter::{SpamFilterAction, UrlReputationConfig},
+};
+use mail_parser::MessageParser;
+use smtp::core::{Session, SessionAddress};
+use spam_filter::analysis::{
+    init::SpamFilterInit, url::SpamFilterAnalyzeUrl, url_reputation::SpamFilterAnalyzeUrlReputation,
+};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{TempDir, TestSMTP},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter.url-reputation.feed."phish"]
+store = "phish-feed"
+
+[spam-filter.url-reputation.feed."malware"]
+store = "malware-feed"
+tag = "MALWARE_DOMAIN"
+score = 8.0
+match = "domain"
+
+[spam-filter.url-reputation.feed."disabled"]
+enable = false
+store = "phish-feed"
+
+[spam-filter.list.scores]
+"MALWARE_DOMAIN" = "reject"
+
+[lookup]
+"phish-feed" = {"https://phish.example.net/login", "tracker.example.com"}
+"malware-feed" = {"malware.org"}
+"#;
+
+const MESSAGE: &str = concat!(
+    "From: bill@remote.org\r\n",
+    "To: jane@foobar.org\r\n",
+    "Subject: Account update\r\n",
+    "\r\n",
+    "Please visit the following links:\r\n",
+    "\r\n",
+    "$URLS\r\n",
+    "\r\n",
+    "Thanks.\r\n"
+);
+
+#[tokio::test]
+async fn url_reputation() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Validate settings
+    assert!(UrlReputationConfig::parse(&mut Config::new("").unwrap()).is_none());
+    let mut config = Config::new(concat!(
+        "[spam-filter.url-reputation.feed.test-feed]\n",
+        "store = \"list\"\n",
+        "match = \"path\"\n",
+    ))
+    .unwrap();
+    let url_reputation = UrlReputationConfig::parse(&mut config).unwrap();
+    assert!(
+        config
+            .errors
+            .contains_key("spam-filter.url-reputation.feed.test-feed.match")
+    );
+    assert_eq!(url_reputation.max_urls, 50);
+    assert_eq!(url_reputation.cache_listed, 86400);
+    assert_eq!(url_reputation.cache_clean, 6 * 3600);
+    let feed = &url_reputation.feeds[0];
+    assert_eq!(feed.tag, "URL_REPUTATION_TEST_FEED");
+    assert_eq!(feed.score, 5.0);
+    assert!(feed.match_url && feed.match_host && !feed.match_domain);
+
+    let tmp_dir = TempDir::new("smtp_url_reputation_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+    let server = TestSMTP::from_core(core).server;
+
+    // Disabled feeds are skipped and feed tags are scored unless configured
+    let url_reputation = server.core.spam.url_reputation.as_ref().unwrap();
+    let mut feeds = url_reputation
+        .feeds
+        .iter()
+        .map(|feed| feed.id.as_str())
+        .collect::<Vec<_>>();
+    feeds.sort_unstable();
+    assert_eq!(feeds, ["malware", "phish"]);
+    assert!(matches!(
+        server.core.spam.lists.scores.get("URL_REPUTATION_PHISH"),
+        Some(SpamFilterAction::Allow(score)) if *score == 5.0
+    ));
+    assert!(matches!(
+        server.core.spam.lists.scores.get("MALWARE_DOMAIN"),
+        Some(SpamFilterAction::Reject)
+    ));
+
+    // URLs, hosts and domains are matched according to each feed
+    for (urls, expected_tags) in [
+        (
+            "https://phish.example.net/login",
+            &["URL_REPUTATION_PHISH"][..],
+        ),
+        (
+            "https://www.example.net/login\r\nhttp://tracker.example.com/pixel",
+            &["URL_REPUTATION_PHISH"][..],
+        ),
+        ("http://cdn.malware.org/payload", &["MALWARE_DOMAIN"][..]),
+        (
+            "https://phish.example.net/login\r\nhttps://www.malware.org/",
+            &["URL_REPUTATION_PHISH", "MALWARE_DOMAIN"][..],
+        ),
+        ("https://www.malware.org.example.com/", &[][..]),
+        ("https://safe.example.org/", &[][..]),
+    ] {
+        let tags = analyze(&server, urls).await;
+        for tag in ["URL_REPUTATION_PHISH", "MALWARE_DOMAIN"] {
+            assert_eq!(
+                tags.contains(&tag.to_string()),
+                expected_tags.contains(&tag),
+                "{tag} for {urls:?}"
+            );
+        }
+    }
+
+    // Verdicts are cached
+    for (feed, key, verdict) in [
+        ("phish", "https://phish.example.net/login", "1"),
+        ("phish", "tracker.example.com", "1"),
+        ("phish", "safe.example.org", "0"),
+        ("malware", "malware.org", "1"),
+        ("malware", "example.org", "0"),
+    ] {
+        let mut cache_key = vec![KV_URL_REPUTATION];
+        cache_key.extend_from_slice(feed.as_bytes());
+        cache_key.push(0);
+        cache_key.extend_from_slice(key.as_bytes());
+        assert_eq!(
+            server
+                .in_memory_store()
+                .key_get::<String>(cache_key)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(verdict),
+            "{feed} {key}"
+        );
+    }
+}
+
+async fn analyze(server: &common::Server, urls: &str) -> Vec<String> {
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.data.mail_from = Some(SessionAddress::new("bill@remote.org".to_string()));
+    session
+        .data
+        .rcpt_to
+        .push(SessionAddress::new("jane@foobar.org".to_string()));
+    let message = MESSAGE.replace("$URLS", urls);
+    let parsed_message = MessageParser::new().parse(&message).unwrap();
+    let mut spam_ctx =
+        server.spam_filter_init(session.build_spam_input(&parsed_message, &[], None, None, None));
+    server.spam_filter_analyze_url(&mut spam_ctx).await;
+    server
+        .spam_filter_analyze_url_reputation(&mut spam_ctx)
+        .await;
+    spam_ctx
+        .result
+        .tags
+        .iter()
+        .map(|tag| tag.to_string())
+        .collect()
+}
@@ -0,0 +1,285 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{Core, config::smtp::queue::QueueConfig};
+use mail_parser::MessageParser;
+use smtp::{core::Session, queue::suppression::SmtpSuppression, reporting::bounce::VerpBounce};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    directory::internal::TestInternalDirectory,
+    smtp::{
+        TempDir, TestSMTP,
+        session::{TestSession, VerifyResponse},
+    },
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "internal"
+store = "rocksdb"
+
+[session.rcpt]
+directory = "'local'"
+relay = true
+
+[queue.verp]
+enable = [{if = "sender_domain = 'foobar.org'", then = true},
+          {else = false}]
+suppress-expiry = "30d"
+
+[mailing-list."dev"]
+address = "dev@foobar.org"
+"#;
+
+const MESSAGE: &str = concat!(
+    "From: john@foobar.org\r\n",
+    "To: jane@remote.org, bill@remote.org\r\n",
+    "Subject: Release schedule\r\n",
+    "\r\n",
+    "The release is scheduled for next week.\r\n"
+);
+
+const BOUNCE: &str = concat!(
+    "From: MAILER-DAEMON@remote.org\r\n",
+    "To: $RCPT\r\n",
+    "Subject: Undelivered Mail Returned to Sender\r\n",
+    "MIME-Version: 1.0\r\n",
+    "Content-Type: multipart/report; report-type=delivery-status;\r\n",
+    "\tboundary=\"bounce-boundary\"\r\n",
+    "\r\n",
+    "--bounce-boundary\r\n",
+    "Content-Type: text/plain\r\n",
+    "\r\n",
+    "Your message could not be delivered.\r\n",
+    "\r\n",
+    "--bounce-boundary\r\n",
+    "Content-Type: message/delivery-status\r\n",
+    "\r\n",
+    "Reporting-MTA: dns; mx.remote.org\r\n",
+    "\r\n",
+    "Final-Recipient: rfc822; $FINAL\r\n",
+    "Action: $ACTION\r\n",
+    "Status: $STATUS\r\n",
+    "\r\n",
+    "--bounce-boundary--\r\n"
+);
+
+#[tokio::test]
+async fn verp() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Validate settings
+    let verp = QueueConfig::parse(&mut Config::new("").unwrap()).verp;
+    assert!(verp.suppress);
+    assert_eq!(verp.delimiter, '+');
+    assert_eq!(
+        verp.encode("bounces@foobar.org", "jane@remote.org"),
+        "bounces+jane=remote.org@foobar.org"
+    );
+    assert_eq!(
+        verp.decode("bounces+jane=remote.org@foobar.org"),
+        Some((
+            "bounces@foobar.org".to_string(),
+            "jane@remote.org".to_string()
+        ))
+    );
+    for invalid in [
+        "bounces@foobar.org",
+        "+jane=remote.org@foobar.org",
+        "bounces+jane@foobar.org",
+        "bounces+jane=localhost@foobar.org",
+    ] {
+        assert_eq!(verp.decode(invalid), None, "{invalid}");
+    }
+    let mut config = Config::new("[queue.verp]\ndelimiter = \"-\"\n").unwrap();
+    let verp = QueueConfig::parse(&mut config).verp;
+    config.assert_no_errors();
+    assert_eq!(
+        verp.encode("bounces@foobar.org", "jane@remote.org"),
+        "bounces-jane=remote.org@foobar.org"
+    );
+    for invalid in ["@", "=", "+-"] {
+        let mut config = Config::new(format!("[queue.verp]\ndelimiter = \"{invalid}\"\n")).unwrap();
+        assert_eq!(QueueConfig::parse(&mut config).verp.delimiter, '+');
+        assert!(config.errors.contains_key("queue.verp.delimiter"));
+    }
+
+    let tmp_dir = TempDir::new("smtp_verp_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let qr = test.queue_receiver;
+    server
+        .core
+        .storage
+        .data
+        .create_test_user("jane@foobar.org", "secret", "Jane", &["jane@foobar.org"])
+        .await;
+
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Multi-recipient messages are split into one copy per recipient
+    session
+        .send_message(
+            "john@foobar.org",
+            &["jane@remote.org", "bill@remote.org"],
+            MESSAGE,
+            "250",
+        )
+        .await;
+    let mut copies = qr.read_queued_messages().await;
+    copies.sort_by(|a, b| a.recipients[0].address.cmp(&b.recipients[0].address));
+    assert_eq!(
+        copies
+            .iter()
+            .map(|message| (
+                message.return_path.as_str(),
+                message.recipients.len(),
+                message.recipients[0].address.as_str(),
+                message.domains.len()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("john+bill=remote.org@foobar.org", 1, "bill@remote.org", 1),
+            ("john+jane=remote.org@foobar.org", 1, "jane@remote.org", 1),
+        ]
+    );
+    assert_eq!(copies[0].blob_hash, copies[1].blob_hash);
+    assert_ne!(copies[0].queue_id, copies[1].queue_id);
+    qr.clear_queue(&server).await;
+
+    // Single recipient messages and disabled senders keep their return path
+    session
+        .send_message("john@foobar.org", &["jane@remote.org"], MESSAGE, "250")
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].return_path, "john@foobar.org");
+    qr.clear_queue(&server).await;
+    session
+        .send_message(
+            "carl@remote.org",
+            &["jane@remote.org", "bill@remote.org"],
+            MESSAGE,
+            "250",
+        )
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].return_path, "carl@remote.org");
+    assert_eq!(messages[0].recipients.len(), 2);
+    qr.clear_queue(&server).await;
+
+    // Transient failures addressed to a list are consumed without suppressing the member
+    let bounce = |rcpt: &str, final_rcpt: &str, action: &str, status: &str| {
+        BOUNCE
+            .replace("$RCPT", rcpt)
+            .replace("$FINAL", final_rcpt)
+            .replace("$ACTION", action)
+            .replace("$STATUS", status)
+    };
+    session
+        .send_message(
+            "<>",
+            &["dev-bounces+jane=foobar.org@foobar.org"],
+            &bounce(
+                "dev-bounces+jane=foobar.org@foobar.org",
+                "jane@foobar.org",
+                "delayed",
+                "4.2.2",
+            ),
+            "250",
+        )
+        .await;
+    qr.assert_queue_is_empty().await;
+    assert!(
+        server
+            .get_suppression("jane@foobar.org")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Permanent failures suppress the member
+    session
+        .send_message(
+            "<>",
+            &["dev-bounces+jane=foobar.org@foobar.org"],
+            &bounce(
+                "dev-bounces+jane=foobar.org@foobar.org",
+                "jane@foobar.org",
+                "failed",
+                "5.1.1",
+            ),
+            "250",
+        )
+        .await;
+    qr.assert_queue_is_empty().await;
+    let entry = server
+        .get_suppression("jane@foobar.org")
+        .await
+        .unwrap()
+        .expect("member was not suppressed");
+    assert_eq!(entry.reason, "Bounced post to list dev@foobar.org (5.1.1)");
+    assert!((30 * 86400..=30 * 86400 + 1).contains(&(entry.expires.unwrap() - entry.created)));
+
+    // Bounces to other VERP addresses suppress the recipient but are delivered
+    let message = bounce(
+        "john+bill=remote.org@foobar.org",
+        "bill@remote.org",
+        "failed",
+        "5.1.1",
+    );
+    assert!(
+        !server
+            .process_verp_bounce(
+                "john+bill=remote.org@foobar.org",
+                &MessageParser::new().parse(message.as_bytes()).unwrap(),
+                0,
+            )
+            .await
+    );
+    assert_eq!(
+        server
+            .get_suppression("bill@remote.org")
+            .await
+            .unwrap()
+            .expect("recipient was not suppressed")
+            .reason,
+        "Bounced message (5.1.1)"
+    );
+
+    // Bounces to list addresses are not accepted from regular senders
+    session.mail_from("carl@remote.org", "250").await;
+    session
+        .rcpt_to("dev-bounces+jane=foobar.org@foobar.org", "550 5.1.2")
+        .await;
+}
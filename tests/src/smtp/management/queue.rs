@@ -12,7 +12,10 @@ use common::config::server::ServerProtocol;
 use http::management::queue::Message;
 use mail_auth::MX;
 use mail_parser::DateTime;
-use reqwest::{Method, StatusCode, header::AUTHORIZATION};
+use reqwest::{
+    Method, StatusCode,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+};
 
 use crate::{
     jmap::ManagementApi,
@@ -61,6 +64,8 @@ relay = true
 pub(super) struct List<T> {
     pub items: Vec<T>,
     pub total: usize,
+    #[serde(default)]
+    pub cursor: Option<QueueId>,
 }
 
 #[tokio::test]
@@ -280,6 +285,22 @@ async fn manage_queue() {
             format!("/api/queue/messages?after={test_search}"),
             vec!["d", "e", "f", "c"],
         ),
+        (
+            "/api/queue/messages?domain=example2.com".to_string(),
+            vec!["c"],
+        ),
+        (
+            "/api/queue/messages?domain=foobar.org".to_string(),
+            vec!["d", "e", "f"],
+        ),
+        (
+            "/api/queue/messages?status=temp_fail".to_string(),
+            vec!["f"],
+        ),
+        (
+            "/api/queue/messages?status=scheduled&domain=foobar.org".to_string(),
+            vec!["d", "e"],
+        ),
     ] {
         let expected_ids = HashSet::from_iter(expected_ids.into_iter().map(|s| s.to_string()));
         let ids = api
@@ -294,6 +315,76 @@ async fn manage_queue() {
         assert_eq!(ids, expected_ids, "failed for {query}");
     }
 
+    // Test cursor pagination
+    let page = api
+        .request::<List<QueueId>>(Method::GET, "/api/queue/messages?limit=4")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(page.items.len(), 4);
+    assert_eq!(page.total, 6);
+    let cursor = page.cursor.unwrap();
+    assert_eq!(Some(&cursor), page.items.last());
+    let next_page = api
+        .request::<List<QueueId>>(
+            Method::GET,
+            &format!("/api/queue/messages?limit=4&cursor={cursor}"),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(next_page.items.len(), 2);
+    assert_eq!(next_page.cursor, None);
+    assert!(next_page.items.iter().all(|id| !page.items.contains(id)));
+
+    // Bulk actions on the filtered set
+    #[derive(serde::Deserialize, Debug)]
+    struct BulkResult {
+        total: usize,
+    }
+    for (query, expected) in [
+        ("/api/queue/messages?action=unknown", "Invalid action"),
+        (
+            "/api/queue/messages?action=reroute&relay=unknown",
+            "Relay host not found",
+        ),
+    ] {
+        assert_eq!(
+            api.request::<BulkResult>(Method::POST, query)
+                .await
+                .unwrap()
+                .unwrap_error()
+                .1
+                .as_deref(),
+            Some(expected),
+            "failed for {query}"
+        );
+    }
+    assert_eq!(
+        api.request::<BulkResult>(
+            Method::POST,
+            "/api/queue/messages?action=retry&domain=example3.com&at=2200-01-01T00:00:00Z"
+        )
+        .await
+        .unwrap()
+        .unwrap_data()
+        .total,
+        1
+    );
+    for domain in api
+        .get_messages(&[*id_map.get("c").unwrap()])
+        .await
+        .pop()
+        .unwrap()
+        .unwrap()
+        .domains
+    {
+        assert_eq!(
+            domain.next_retry.as_ref().unwrap().to_rfc3339(),
+            "2200-01-01T00:00:00Z"
+        );
+    }
+
     // Retry delivery
     for id in [id_map.get("e").unwrap(), id_map.get("f").unwrap()] {
         assert!(
@@ -508,6 +599,124 @@ async fn manage_queue() {
     );
 }
 
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_queue_trace() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start remote test server
+    let mut remote = TestSMTP::new("smtp_manage_queue_trace_remote", REMOTE).await;
+    let _rx = remote.start(&[ServerProtocol::Smtp]).await;
+    let remote_core = remote.build_smtp();
+
+    // Start local management interface
+    let local = TestSMTP::new("smtp_manage_queue_trace_local", LOCAL).await;
+    let core = local.build_smtp();
+    core.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx1.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.ipv4_add(
+        "mx1.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+
+    // Queue a message held for later delivery
+    let mut session = local.new_session();
+    local
+        .queue_receiver
+        .queue_rx
+        .spawn(local.server.inner.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("foobar.net").await;
+    session
+        .send_message(
+            "<bill@foobar.net> HOLDFOR=1000",
+            &["john@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let api = ManagementApi::default();
+    let id = api
+        .request::<List<QueueId>>(Method::GET, "/api/queue/messages")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items
+        .pop()
+        .unwrap();
+
+    // Unknown messages cannot be traced
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    assert_eq!(
+        client
+            .get(format!("https://127.0.0.1:9980/api/queue/trace/{}", id + 1))
+            .header(AUTHORIZATION, "Basic YWRtaW46c2VjcmV0")
+            .send()
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // Stream the delivery trace while the message is delivered
+    let mut response = client
+        .get(format!("https://127.0.0.1:9980/api/queue/trace/{id}"))
+        .header(AUTHORIZATION, "Basic YWRtaW46c2VjcmV0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "text/event-stream"
+    );
+    assert!(
+        api.request::<bool>(Method::PATCH, &format!("/api/queue/messages/{id}"))
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+    let mut trace = String::new();
+    while !trace.contains("\"type\":\"delivery.completed\"") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+            .await
+            .expect("timed out waiting for the delivery trace")
+            .unwrap()
+            .expect("trace stream closed");
+        trace.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+    assert!(trace.starts_with("event: trace\ndata: "), "{trace}");
+    assert!(trace.contains(&format!("\"queueId\":{id}")), "{trace}");
+    assert!(
+        trace.contains("\"type\":\"delivery.attempt-start\""),
+        "{trace}"
+    );
+    assert_eq!(
+        remote
+            .queue_receiver
+            .consume_message(&remote_core)
+            .await
+            .recipients
+            .into_iter()
+            .map(|r| r.address)
+            .collect::<Vec<_>>(),
+        vec!["john@foobar.org".to_string()]
+    );
+}
+
 fn assert_timestamp(timestamp: &DateTime, expected: i64, ctx: &str, message: &Message) {
     let timestamp = timestamp.to_timestamp();
     let diff = timestamp - expected;
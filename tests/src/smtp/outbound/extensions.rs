@@ -22,6 +22,10 @@ relay = true
 
 [session.extensions]
 dsn = true
+
+[queue.outbound]
+disable-capabilities = [{if = "mx = 'mx.legacy.org'", then = "['smtputf8', 'dsn']"},
+                        {else = false}]
 "#;
 
 const REMOTE: &str = r#"
@@ -75,6 +79,19 @@ async fn extensions() {
         vec!["127.0.0.1".parse().unwrap()],
         Instant::now() + Duration::from_secs(10),
     );
+    core.mx_add(
+        "legacy.org",
+        vec![MX {
+            exchanges: vec!["mx.legacy.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.ipv4_add(
+        "mx.legacy.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
 
     let mut session = local.new_session();
     session.data.remote_ip_str = "10.0.0.1".into();
@@ -155,4 +172,24 @@ async fn extensions() {
     assert!((message.flags & MAIL_REQUIRETLS) != 0);
     assert!((message.flags & MAIL_SMTPUTF8) != 0);
     assert!((message.recipients.last().unwrap().flags & RCPT_NOTIFY_NEVER) != 0);
+
+    // Disabled capabilities should not be used with the remote host
+    session
+        .send_message(
+            "<john@test.org> ENVID=abc123 SMTPUTF8",
+            &["<bill@legacy.org> NOTIFY=NEVER"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone());
+    local.queue_receiver.read_event().await.assert_done();
+    let message = remote.queue_receiver.expect_message().await;
+    assert_eq!(message.env_id, None);
+    assert!((message.flags & MAIL_SMTPUTF8) == 0);
+    assert!((message.recipients.last().unwrap().flags & RCPT_NOTIFY_NEVER) == 0);
 }
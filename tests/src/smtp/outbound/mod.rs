@@ -10,6 +10,8 @@ pub mod fallback_relay;
 pub mod ip_lookup;
 pub mod lmtp;
 pub mod mta_sts;
+pub mod relay_budget;
+pub mod routing;
 pub mod smtp;
 pub mod throttle;
 pub mod tls;
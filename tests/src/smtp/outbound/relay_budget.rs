@@ -0,0 +1,213 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::{
+    config::{
+        server::ServerProtocol,
+        smtp::queue::{BudgetAction, QueueConfig},
+    },
+    core::BuildServer,
+};
+use smtp::queue::{
+    Error, Status,
+    budget::{BudgetStatus, RelayBudgetCheck},
+    spool::SmtpSpool,
+};
+use store::write::now;
+use utils::config::Config;
+
+use crate::smtp::{DnsCache, TestSMTP, inbound::TestQueueEvent, session::TestSession};
+
+const LOCAL: &str = r#"
+[queue.outbound]
+next-hop = "'paid'"
+
+[session.rcpt]
+relay = true
+max-recipients = 100
+
+[remote.paid]
+address = paid.foobar.org
+port = 9925
+protocol = 'smtp'
+concurrency = 5
+
+[remote.paid.tls]
+implicit = false
+allow-invalid-certs = true
+
+[queue.budget."monthly"]
+relay = "paid"
+messages = 1
+alert = 50
+"#;
+
+const REMOTE: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.ehlo]
+reject-non-fqdn = false
+
+[session.extensions]
+chunking = false
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn relay_budget() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Validate settings
+    let mut config = Config::new(concat!(
+        "[remote.paid]\n",
+        "address = \"paid.foobar.org\"\n",
+        "port = 25\n",
+        "protocol = \"smtp\"\n",
+        "[queue.budget.\"unknown-relay\"]\n",
+        "relay = \"missing\"\n",
+        "messages = 100\n",
+        "[queue.budget.\"disabled\"]\n",
+        "enable = false\n",
+        "relay = \"paid\"\n",
+        "messages = 100\n",
+        "[queue.budget.\"no-limit\"]\n",
+        "relay = \"paid\"\n",
+        "[queue.budget.\"defaults\"]\n",
+        "relay = \"paid\"\n",
+        "messages = 100\n",
+        "alert = 150\n",
+        "[queue.budget.\"direct\"]\n",
+        "relay = \"paid\"\n",
+        "messages = 200\n",
+        "alert = 80\n",
+        "action = \"direct\"\n",
+    ))
+    .unwrap();
+    let queue = QueueConfig::parse(&mut config);
+    for key in [
+        "queue.budget.unknown-relay.relay",
+        "queue.budget.no-limit.messages",
+    ] {
+        assert!(config.errors.contains_key(key), "{key}");
+    }
+    let mut budgets = queue.relay_budgets.iter().collect::<Vec<_>>();
+    budgets.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(
+        budgets
+            .iter()
+            .map(|budget| budget.id.as_str())
+            .collect::<Vec<_>>(),
+        ["defaults", "direct"]
+    );
+    assert_eq!(budgets[0].relay, "paid");
+    assert_eq!(budgets[0].messages, 100);
+    assert_eq!(budgets[0].alert, None);
+    assert_eq!(budgets[0].action, BudgetAction::Defer);
+    assert_eq!(budgets[1].messages, 200);
+    assert_eq!(budgets[1].alert, Some(80));
+    assert_eq!(budgets[1].action, BudgetAction::Direct);
+
+    // Start test server
+    let mut remote = TestSMTP::new("smtp_relay_budget_remote", REMOTE).await;
+    let _rx = remote.start(&[ServerProtocol::Smtp]).await;
+    let mut local = TestSMTP::new("smtp_relay_budget_local", LOCAL).await;
+
+    // Add mock DNS entries
+    let core = local.build_smtp();
+    core.ipv4_add(
+        "paid.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Messages are delivered through the relay while the budget is available
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone());
+    local
+        .queue_receiver
+        .read_event()
+        .await
+        .assert_refresh_or_done();
+    remote.queue_receiver.expect_message().await;
+    let budget = &core.core.smtp.queue.relay_budgets[0];
+    assert_eq!(budget.id, "monthly");
+    assert_eq!(
+        core.relay_budget_usage(budget, "test.org").await.unwrap(),
+        1
+    );
+    assert_eq!(
+        core.relay_budget_usage(budget, "example.org")
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Deliveries are deferred until the next period once the budget is exhausted
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    let queued = local.queue_receiver.expect_message_then_deliver().await;
+    let queue_id = queued.queue_id;
+    queued.try_deliver(core.clone());
+    local
+        .queue_receiver
+        .read_event()
+        .await
+        .assert_refresh_or_done();
+    let message = core.read_message(queue_id).await.unwrap();
+    assert!(matches!(
+        message.domains[0].status,
+        Status::TemporaryFailure(Error::RateLimited)
+    ));
+    assert!(message.domains[0].retry.due > now());
+    remote.queue_receiver.assert_no_events();
+    assert_eq!(
+        core.relay_budget_usage(budget, "test.org").await.unwrap(),
+        1
+    );
+
+    // Other budget actions
+    for (action, expected_direct) in [(BudgetAction::Direct, true), (BudgetAction::Alert, false)] {
+        let mut new_core = local.server.inner.shared_core.load_full().as_ref().clone();
+        new_core.smtp.queue.relay_budgets[0].action = action;
+        local.server.inner.shared_core.store(new_core.into());
+        let server = local.server.inner.build_server();
+        match server.check_relay_budget("paid", "test.org", 0).await {
+            BudgetStatus::Direct => assert!(expected_direct),
+            BudgetStatus::Available(charges) => {
+                assert!(!expected_direct);
+                assert_eq!(charges.len(), 1);
+            }
+            BudgetStatus::Defer(_) => panic!("Unexpected deferral for {action:?}"),
+        }
+
+        // Budgets only apply to their relay and sender domain
+        for (relay, domain) in [("other", "test.org"), ("paid", "example.org")] {
+            match server.check_relay_budget(relay, domain, 0).await {
+                BudgetStatus::Available(charges) => {
+                    assert_eq!(charges.is_empty(), relay == "other")
+                }
+                _ => panic!("Unexpected budget status for {relay} {domain}"),
+            }
+        }
+    }
+
+    local.queue_receiver.clear_queue(&core).await;
+}
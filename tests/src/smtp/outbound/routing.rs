@@ -0,0 +1,195 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::config::{server::ServerProtocol, smtp::routing::DomainRouteEntry};
+use mail_auth::MX;
+use smtp::queue::{Error, Status};
+use store::write::now;
+
+use crate::smtp::{
+    DnsCache, TestSMTP,
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+};
+
+const LOCAL: &str = r#"
+[session.rcpt]
+relay = true
+
+[queue.route]
+"foobar.org" = '{"address": "relay.foobar.org", "port": 9925, "allowInvalidCerts": true}'
+"foobar.net" = '{"relay": "mx", "rate": "1/1d"}'
+"#;
+
+const REMOTE: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.ehlo]
+reject-non-fqdn = false
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn domain_routes() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start test server
+    let mut remote = TestSMTP::new("smtp_routing_remote", REMOTE).await;
+    let _rx = remote.start(&[ServerProtocol::Smtp]).await;
+    let mut local = TestSMTP::new("smtp_routing_local", LOCAL).await;
+
+    // The MX of the routed domain is unreachable
+    let core = local.build_smtp();
+    core.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["_dns_error.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.ipv4_add(
+        "relay.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    assert_eq!(core.core.smtp.queue.routes.len(), 2);
+
+    // Messages to routed domains are delivered to the route's relay host
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone());
+    remote
+        .queue_receiver
+        .expect_message()
+        .await
+        .read_lines(&remote.queue_receiver)
+        .await
+        .assert_contains("To: bill@foobar.org");
+
+    // Routes with a rate limit throttle deliveries to the domain
+    core.mx_add(
+        "foobar.net",
+        vec![MX {
+            exchanges: vec!["_dns_error.foobar.net".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    for expected_status in [None, Some(Status::TemporaryFailure(Error::RateLimited))] {
+        session
+            .send_message("john@test.org", &["jane@foobar.net"], "test:no_dkim", "250")
+            .await;
+        local
+            .queue_receiver
+            .expect_message_then_deliver()
+            .await
+            .try_deliver(core.clone());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        local.queue_receiver.read_event().await.assert_refresh();
+        let message = local.queue_receiver.last_queued_message().await;
+        if let Some(expected_status) = expected_status {
+            assert_eq!(message.domains[0].status, expected_status);
+            assert!(message.domains[0].retry.due > now());
+        } else {
+            assert_ne!(
+                message.domains[0].status,
+                Status::TemporaryFailure(Error::RateLimited)
+            );
+        }
+        local.queue_receiver.clear_queue(&core).await;
+    }
+
+    // Route tables are validated before they are applied
+    let route = |json: &str| serde_json::from_str::<DomainRouteEntry>(json).unwrap();
+    let errors = core
+        .domain_routes_validate(vec![
+            ("invalid domain".to_string(), route("{}")),
+            ("example.org".to_string(), route(r#"{"relay": "mx"}"#)),
+            ("EXAMPLE.org.".to_string(), route(r#"{"relay": "mx"}"#)),
+            (
+                "example.net".to_string(),
+                route(r#"{"relay": "mx", "address": "10.0.0.1"}"#),
+            ),
+            ("example.com".to_string(), route(r#"{"relay": "unknown"}"#)),
+            (
+                "example.edu".to_string(),
+                route(r#"{"address": "10.0.0.1", "username": "john"}"#),
+            ),
+            (
+                "example.info".to_string(),
+                route(r#"{"starttls": "always"}"#),
+            ),
+        ])
+        .unwrap_err()
+        .into_iter()
+        .map(|(domain, _)| domain)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            "invalid domain",
+            "example.org",
+            "example.net",
+            "example.com",
+            "example.edu",
+            "example.info"
+        ]
+    );
+
+    // Imported routes replace the existing table
+    let keys = core
+        .domain_routes_validate(vec![
+            (
+                "Example.org".to_string(),
+                route(r#"{"relay": "mx", "starttls": "require"}"#),
+            ),
+            (
+                "example.net".to_string(),
+                route(r#"{"address": "10.0.0.1", "port": 2525, "rate": "10/1m"}"#),
+            ),
+        ])
+        .unwrap();
+    core.domain_routes_apply(keys, true).await.unwrap();
+    let mut routes = core.domain_routes_export().await.unwrap();
+    routes.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        routes,
+        vec![
+            (
+                "example.net".to_string(),
+                route(r#"{"address": "10.0.0.1", "port": 2525, "rate": "10/1m"}"#)
+            ),
+            (
+                "example.org".to_string(),
+                route(r#"{"relay": "mx", "starttls": "require"}"#)
+            ),
+        ]
+    );
+
+    // Merged imports keep the routes not included in the new set
+    let keys = core
+        .domain_routes_validate(vec![(
+            "example.com".to_string(),
+            route(r#"{"relay": "mx"}"#),
+        )])
+        .unwrap();
+    core.domain_routes_apply(keys, false).await.unwrap();
+    assert_eq!(core.domain_routes_export().await.unwrap().len(), 3);
+}
@@ -0,0 +1,225 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Core;
+use serde_json::Value;
+use smtp::{core::Session, queue::JOURNAL_REPORT};
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    AssertConfig,
+    smtp::{TempDir, TestSMTP, inbound::TestMessage, session::TestSession},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/queue.db"
+
+[store."archive"]
+type = "fs"
+path = "{TMP}/bucket"
+
+[spam-filter]
+enable = false
+
+[directory."local"]
+type = "memory"
+
+[session.rcpt]
+relay = true
+
+[queue.archive."journal"]
+enable = true
+destination.type = "smtp"
+destination.address = "Journal@foobar.org"
+destination.from = "archive@foobar.org"
+
+[queue.archive."files"]
+enable = true
+destination.type = "file"
+destination.path = "{TMP}/archive"
+retention = "7d"
+
+[queue.archive."bucket"]
+enable = "sender_domain = 'foobar.org'"
+destination.type = "blob"
+destination.store = "archive"
+destination.prefix = "/compliance/"
+
+[queue.archive."disabled"]
+enable = false
+destination.type = "file"
+destination.path = "{TMP}/disabled"
+"#;
+
+#[tokio::test]
+async fn archive() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_archive_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+    assert_eq!(core.smtp.queue.archive.len(), 4);
+
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let qr = test.queue_receiver;
+    let node_id = server.core.network.node_id;
+    let mut session = Session::test(server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Queued messages are archived and a journal report is sent
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@remote.org", "jane@remote.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 2);
+    let message = messages
+        .iter()
+        .find(|message| message.flags & JOURNAL_REPORT == 0)
+        .unwrap();
+    let report = messages
+        .iter()
+        .find(|message| message.flags & JOURNAL_REPORT != 0)
+        .expect("Journal report not queued");
+    assert_eq!(report.return_path, "archive@foobar.org");
+    assert_eq!(report.recipients.len(), 1);
+    assert_eq!(report.recipients[0].address, "journal@foobar.org");
+    let raw_message = message.read_message(&qr).await;
+    let report = report.read_message(&qr).await;
+    for expected in [
+        "X-Archive-Policy: journal",
+        &format!("X-Archive-Sequence: {node_id}/1"),
+        "Sender: <john@foobar.org>",
+        "Recipient: <bill@remote.org>",
+        "Recipient: <jane@remote.org>",
+        "Content-Type: message/rfc822",
+    ] {
+        assert!(report.contains(expected), "{expected:?} not in {report}");
+    }
+
+    // Copies are written once to the archive directory with a journal entry
+    let archive_dir = tmp_dir.temp_dir.join("archive").join("files");
+    assert_eq!(
+        std::fs::read_to_string(archive_dir.join(format!("{node_id}-{:020}.eml", 1))).unwrap(),
+        raw_message
+    );
+    let first = read_journal(&archive_dir, node_id);
+    assert_eq!(first.len(), 1);
+    let first = &first[0];
+    assert_eq!(first["policy"], "files");
+    assert_eq!(first["sequence"], 1);
+    assert_eq!(first["queueId"], message.queue_id);
+    assert_eq!(first["from"], "john@foobar.org");
+    assert_eq!(first["size"], raw_message.len());
+    assert_eq!(
+        first["retainUntil"].as_u64().unwrap(),
+        first["archivedAt"].as_u64().unwrap() + 7 * 86400
+    );
+    assert_eq!(first["previous"], "0".repeat(64));
+    assert_eq!(first["sha256"].as_str().unwrap().len(), 64);
+
+    // Blob store copies only include messages matching the policy
+    let blob_store = server.core.storage.blobs.get("archive").unwrap().clone();
+    let blob_key = format!("compliance/bucket/{node_id}/{:020}", 1);
+    assert_eq!(
+        blob_store
+            .get_blob(format!("{blob_key}.eml").as_bytes(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some(raw_message.as_bytes())
+    );
+    let record = serde_json::from_slice::<Value>(
+        &blob_store
+            .get_blob(format!("{blob_key}.json").as_bytes(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(record["policy"], "bucket");
+    assert_eq!(record["sha256"], first["sha256"]);
+    assert!(record.get("retainUntil").is_none());
+
+    // Disabled policies do not archive messages
+    assert!(!tmp_dir.temp_dir.join("disabled").exists());
+    qr.clear_queue(&server).await;
+
+    // Each archived copy is chained to the previous one
+    session
+        .send_message(
+            "jane@otherdomain.org",
+            &["bill@remote.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let journal = read_journal(&archive_dir, node_id);
+    assert_eq!(journal.len(), 2);
+    assert_eq!(journal[1]["sequence"], 2);
+    assert_eq!(journal[1]["previous"], journal[0]["chain"]);
+    assert_ne!(journal[1]["chain"], journal[0]["chain"]);
+    assert!(
+        archive_dir
+            .join(format!("{node_id}-{:020}.eml", 2))
+            .exists()
+    );
+    assert!(
+        blob_store
+            .get_blob(
+                format!("compliance/bucket/{node_id}/{:020}.eml", 2).as_bytes(),
+                0..usize::MAX
+            )
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Journal reports are not archived themselves
+    let report = qr
+        .read_queued_messages()
+        .await
+        .into_iter()
+        .find(|message| message.flags & JOURNAL_REPORT != 0)
+        .expect("Journal report not queued")
+        .read_message(&qr)
+        .await;
+    assert!(
+        report.contains(&format!("X-Archive-Sequence: {node_id}/2")),
+        "{report}"
+    );
+    assert_eq!(std::fs::read_dir(&archive_dir).unwrap().count(), 3);
+
+    qr.clear_queue(&server).await;
+}
+
+fn read_journal(dir: &std::path::Path, node_id: u64) -> Vec<Value> {
+    std::fs::read_to_string(dir.join(format!("journal-{node_id}.jsonl")))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
@@ -76,6 +76,14 @@ impl SessionStream for DummyIo {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         ("".into(), "".into())
     }
+
+    fn tls_channel_binding(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn tls_client_certificate(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 impl Unpin for DummyIo {}
@@ -363,6 +371,7 @@ impl TestServerInstance for ServerInstance {
             shutdown_rx,
             proxy_networks: vec![],
             span_id_gen: Arc::new(SnowflakeIdGenerator::new()),
+            auth_policy: Default::default(),
         }
     }
 }
@@ -6,13 +6,32 @@
 
 use ahash::AHashMap;
 use store::{
-    BlobClass, BlobStore, SerializeInfallible, Stores,
+    BlobClass, BlobStore, CompressionAlgo, SerializeInfallible, Stores,
     write::{BatchBuilder, BlobOp, blob::BlobQuota, now},
 };
 use utils::{BlobHash, config::Config};
 
 use crate::store::{CONFIG, TempDir};
 
+const COMPOSITE_CONFIG: &str = r#"
+[store."fs-primary"]
+type = "fs"
+path = "{TMP}/primary"
+
+[store."fs-archive"]
+type = "fs"
+path = "{TMP}/archive"
+
+[store."tiered"]
+type = "tiered-blob"
+primary = "fs-primary"
+archive = "fs-archive"
+
+[store."tiered-shards"]
+type = "sharded-blob"
+stores = ["fs-primary", "tiered"]
+"#;
+
 #[tokio::test]
 pub async fn blob_tests() {
     let temp_dir = TempDir::new("blob_tests", true);
@@ -23,8 +42,29 @@ pub async fn blob_tests() {
     for (store_id, blob_store) in &stores.blob_stores {
         println!("Testing blob store {}...", store_id);
         test_store(blob_store.clone()).await;
+        for compression in [CompressionAlgo::Lz4, CompressionAlgo::Zstd] {
+            println!(
+                "Testing blob store {} with {:?} compression...",
+                store_id, compression
+            );
+            test_store(blob_store.clone().with_compression(compression)).await;
+        }
+        test_compression_upgrade(blob_store.clone()).await;
     }
 
+    // Composite blob stores cannot be used as shards
+    let mut config =
+        Config::new(COMPOSITE_CONFIG.replace("{TMP}", temp_dir.path.as_path().to_str().unwrap()))
+            .unwrap();
+    let composite_stores = Stores::parse_all(&mut config, false).await;
+    assert!(composite_stores.blob_stores.contains_key("tiered"));
+    assert!(!composite_stores.blob_stores.contains_key("tiered-shards"));
+    assert!(
+        config.errors.contains_key("store.tiered-shards.stores"),
+        "{:?}",
+        config.errors
+    );
+
     for (store_id, store) in stores.stores {
         println!("Testing blob management on store {}...", store_id);
 
@@ -203,6 +243,17 @@ pub async fn blob_tests() {
             BlobQuota { bytes: 0, count: 0 }
         );
 
+        // Linked blobs have one reference each, reserved blobs have none
+        for (blob, references) in [(b"123", 1), (b"456", 1), (b"abc", 0)] {
+            assert_eq!(
+                store
+                    .blob_references(BlobHash::generate(blob.as_slice()))
+                    .await
+                    .unwrap(),
+                references
+            );
+        }
+
         // Purge expired blobs and make sure nothing else is deleted
         store.purge_blobs(blob_store.clone()).await.unwrap();
         for (pos, (blob, blob_class)) in [
@@ -493,3 +544,48 @@ async fn test_store(store: BlobStore) {
             .is_none()
     );
 }
+
+async fn test_compression_upgrade(store: BlobStore) {
+    // Blobs written before enabling compression are returned unchanged,
+    // even when they end with a byte that looks like a compression marker
+    let lz4 = store.clone().with_compression(CompressionAlgo::Lz4);
+    let zstd = store.clone().with_compression(CompressionAlgo::Zstd);
+    let mut hashes = Vec::new();
+    for marker in [0xa0u8, 0xa2] {
+        let mut data = b"Uncompressed blob ".to_vec();
+        data.push(marker);
+        let hash = BlobHash::generate(&data);
+        store.put_blob(hash.as_slice(), &data).await.unwrap();
+        for compressed_store in [&lz4, &zstd] {
+            assert_eq!(
+                compressed_store
+                    .get_blob(hash.as_slice(), 0..usize::MAX)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(data.as_slice())
+            );
+        }
+        hashes.push(hash);
+    }
+
+    // Blobs can be read after switching between compression algorithms
+    let data = b"Compressed blob ".repeat(512);
+    let hash = BlobHash::generate(&data);
+    for (writer, reader) in [(&lz4, &zstd), (&zstd, &lz4)] {
+        writer.put_blob(hash.as_slice(), &data).await.unwrap();
+        assert_eq!(
+            reader
+                .get_blob(hash.as_slice(), 0..usize::MAX)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(data.as_slice())
+        );
+    }
+    hashes.push(hash);
+
+    for hash in hashes {
+        store.delete_blob(hash.as_slice()).await.unwrap();
+    }
+}
@@ -5,7 +5,10 @@
  */
 
 use ahash::AHashSet;
-use common::{Core, manager::backup::BackupParams};
+use common::{
+    Core,
+    manager::{backup::BackupParams, restore::RestoreParams},
+};
 use jmap_proto::types::{
     collection::{Collection, SyncCollection},
     property::Property,
@@ -234,28 +237,60 @@ pub async fn test(db: Store) {
     let snapshot = Snapshot::new(&db).await;
     assert!(!snapshot.keys.is_empty(), "Store hash counts are empty",);
 
-    // Export store
-    println!("Exporting store...");
-    let temp_dir = TempDir::new("art_vandelay_tests", true);
-    core.backup(BackupParams::new(temp_dir.path.clone())).await;
-
-    // Destroy store
-    println!("Destroying store...");
-    db.destroy().await;
-    db.assert_is_empty(db.clone().into()).await;
-
-    // Import store
-    println!("Importing store...");
-    core.restore(temp_dir.path.clone()).await;
+    for encryption_key in [None, Some("the sea was angry that day")] {
+        // Export store
+        println!(
+            "Exporting store{}...",
+            if encryption_key.is_some() {
+                " with encryption"
+            } else {
+                ""
+            }
+        );
+        let temp_dir = TempDir::new("art_vandelay_tests", true);
+        let mut backup = BackupParams::new(temp_dir.path.clone());
+        let mut restore = RestoreParams::new(temp_dir.path.clone());
+        if let Some(encryption_key) = encryption_key {
+            backup = backup.with_encryption_key(encryption_key);
+            restore = restore.with_encryption_key(encryption_key);
+        }
+        core.backup(backup).await.unwrap();
+
+        // Destroy store
+        println!("Destroying store...");
+        db.destroy().await;
+        db.assert_is_empty(db.clone().into()).await;
+
+        // Import store
+        println!("Importing store...");
+        core.restore(restore).await.unwrap();
+
+        // Verify hash
+        print!("Verifying store hash...");
+        snapshot.assert_is_eq(&Snapshot::new(&db).await);
+        println!(" GREAT SUCCESS!");
+
+        // Encrypted backups cannot be restored without the right key
+        if encryption_key.is_some() {
+            assert!(
+                core.restore(RestoreParams::new(temp_dir.path.clone()))
+                    .await
+                    .is_err()
+            );
+            assert!(
+                core.restore(
+                    RestoreParams::new(temp_dir.path.clone()).with_encryption_key("serenity now")
+                )
+                .await
+                .is_err()
+            );
+        }
 
-    // Verify hash
-    print!("Verifying store hash...");
-    snapshot.assert_is_eq(&Snapshot::new(&db).await);
-    println!(" GREAT SUCCESS!");
+        temp_dir.delete();
+    }
 
     // Destroy store
     db.destroy().await;
-    temp_dir.delete();
 }
 
 #[derive(Debug, PartialEq, Eq)]
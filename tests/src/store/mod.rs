@@ -9,6 +9,8 @@ pub mod import_export;
 pub mod lookup;
 pub mod ops;
 pub mod query;
+#[cfg(feature = "s3")]
+pub mod s3;
 
 use std::io::Read;
 
@@ -61,6 +63,22 @@ database = "stalwart"
 user = "root"
 password = "password"
 
+[store."postgresql-replica"]
+type = "read-replica"
+primary = "postgresql"
+replicas = "postgresql"
+
+[store."mysql-replica"]
+type = "read-replica"
+primary = "mysql"
+replicas = "mysql"
+
+[store."cassandra"]
+type = "cassandra"
+nodes = ["localhost:9042"]
+keyspace = "stalwart"
+replication-factor = 1
+
 [store."redis"]
 type = "redis"
 urls = "redis://127.0.0.1"
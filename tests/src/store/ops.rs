@@ -98,6 +98,119 @@ pub async fn test(db: Store) {
         db.write(batch.build_all()).await.unwrap();
     }
 
+    #[cfg(feature = "cassandra")]
+    if matches!(db, Store::Cassandra(_)) {
+        use store::write::assert::AssertValue;
+
+        println!("Running Cassandra partition tests...");
+
+        // Assertions on a single account are committed atomically
+        db.write(
+            BatchBuilder::new()
+                .with_account_id(0)
+                .with_collection(0)
+                .update_document(0)
+                .assert_value(ValueClass::Property(1), AssertValue::None)
+                .set(ValueClass::Property(1), "a".as_bytes())
+                .build_all(),
+        )
+        .await
+        .unwrap();
+        assert!(
+            db.write(
+                BatchBuilder::new()
+                    .with_account_id(0)
+                    .with_collection(0)
+                    .update_document(0)
+                    .assert_value(ValueClass::Property(1), AssertValue::None)
+                    .set(ValueClass::Property(1), "b".as_bytes())
+                    .build_all(),
+            )
+            .await
+            .is_err()
+        );
+
+        // Assertions spanning multiple accounts are rejected without writing anything
+        assert!(
+            db.write(
+                BatchBuilder::new()
+                    .with_account_id(0)
+                    .with_collection(0)
+                    .update_document(0)
+                    .assert_value(ValueClass::Property(1), AssertValue::Some)
+                    .clear(ValueClass::Property(1))
+                    .with_account_id(1)
+                    .with_collection(0)
+                    .update_document(0)
+                    .assert_value(ValueClass::Property(1), AssertValue::None)
+                    .set(ValueClass::Property(1), "c".as_bytes())
+                    .build_all(),
+            )
+            .await
+            .is_err()
+        );
+        for (account_id, expected) in [(0, Some("a".to_string())), (1, None)] {
+            assert_eq!(
+                db.get_value::<String>(ValueKey {
+                    account_id,
+                    collection: 0,
+                    document_id: 0,
+                    class: ValueClass::Property(1),
+                })
+                .await
+                .unwrap(),
+                expected
+            );
+        }
+
+        // Keys spread across partitions are iterated in order
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(0)
+            .with_collection(0)
+            .update_document(0)
+            .clear(ValueClass::Property(1));
+        for n in 0..100 {
+            batch.set(
+                ValueClass::Config(format!("cassandra{n:03}").into_bytes()),
+                format!("{n}").into_bytes(),
+            );
+        }
+        db.write(batch.build_all()).await.unwrap();
+        for ascending in [true, false] {
+            let params = store::IterateParams::new(
+                ValueKey::from(ValueClass::Config(b"cassandra".to_vec())),
+                ValueKey::from(ValueClass::Config(b"cassandra\xFF".to_vec())),
+            );
+            let mut keys = Vec::new();
+            db.iterate(
+                if ascending {
+                    params.ascending()
+                } else {
+                    params.descending()
+                },
+                |key, _| {
+                    keys.push(String::from_utf8(key.to_vec()).unwrap());
+                    Ok(true)
+                },
+            )
+            .await
+            .unwrap();
+            let mut expected = (0..100)
+                .map(|n| format!("cassandra{n:03}"))
+                .collect::<Vec<_>>();
+            if !ascending {
+                expected.reverse();
+            }
+            assert_eq!(keys, expected);
+        }
+        let mut batch = BatchBuilder::new();
+        for n in 0..100 {
+            batch.clear(ValueClass::Config(format!("cassandra{n:03}").into_bytes()));
+        }
+        db.write(batch.build_all()).await.unwrap();
+    }
+
     // Increment a counter 1000 times concurrently
     let mut handles = Vec::new();
     let mut assigned_ids = HashSet::new();
@@ -459,6 +459,34 @@ pub async fn test_filter(db: Store, fts: FtsStore) {
         }
         assert_eq!(results, expected_results);
     }
+
+    // Masked queries only return documents within the mask
+    let filters = || {
+        vec![
+            FtsFilter::has_english_text(fields["title"].clone(), "study"),
+            FtsFilter::Not,
+            FtsFilter::has_english_text(fields["title"].clone(), "'anatomical'"),
+            FtsFilter::End,
+        ]
+    };
+    let unmasked = fts.query(0, COLLECTION_ID, filters()).await.unwrap();
+    assert!(unmasked.len() > 1);
+    let mut mask = unmasked.clone();
+    let excluded = mask.min().unwrap();
+    mask.remove(excluded);
+    mask.insert(u32::MAX - 1);
+    let masked = fts
+        .query_masked(0, COLLECTION_ID, filters(), Some(&mask))
+        .await
+        .unwrap();
+    assert_eq!(masked.len(), unmasked.len() - 1);
+    assert!(!masked.contains(excluded) && !masked.contains(u32::MAX - 1));
+    assert!(
+        fts.query_masked(0, COLLECTION_ID, filters(), Some(&Default::default()))
+            .await
+            .unwrap()
+            .is_empty()
+    );
 }
 
 pub async fn test_sort(db: Store) {
@@ -0,0 +1,242 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::{Arc, Mutex};
+
+use ahash::AHashMap;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use store::{BlobStore, Stores};
+use tokio::net::TcpListener;
+use utils::config::Config;
+
+use crate::AssertConfig;
+
+const CONFIG: &str = r#"
+[store."s3"]
+type = "s3"
+access-key = "minioadmin"
+secret-key = "minioadmin"
+region = "eu-central-1"
+endpoint = "http://127.0.0.1:{PORT}"
+bucket = "tmp"
+key-prefix = "blobs/"
+max-retries = 2
+retry.backoff = "10ms"
+retry.max-backoff = "50ms"
+
+[store."s3-unverified"]
+type = "s3"
+access-key = "minioadmin"
+secret-key = "minioadmin"
+region = "eu-central-1"
+endpoint = "http://127.0.0.1:{PORT}"
+bucket = "tmp"
+max-retries = 0
+checksum.verify = false
+"#;
+
+// MD5 digest of "abc"
+const ABC_MD5: &str = "\"900150983cd24fb0d6963f7d28e17f72\"";
+const BAD_MD5: &str = "\"00000000000000000000000000000000\"";
+
+#[derive(Default)]
+struct MockS3 {
+    objects: AHashMap<String, Vec<u8>>,
+    etag: Option<String>,
+    fail_next: usize,
+    requests: Vec<(Method, String)>,
+}
+
+#[tokio::test]
+async fn s3_requests() {
+    // Start mock S3 server
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mock = Arc::new(Mutex::new(MockS3::default()));
+    tokio::spawn(mock_s3(listener, mock.clone()));
+
+    let mut config = Config::new(CONFIG.replace("{PORT}", &port.to_string())).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    config.assert_no_errors();
+    let store = stores.blob_stores.get("s3").unwrap().clone();
+    let unverified = stores.blob_stores.get("s3-unverified").unwrap().clone();
+
+    // Objects are written using the configured key prefix
+    mock.lock().unwrap().etag = Some(ABC_MD5.to_string());
+    store.put_blob(b"blob", b"abc").await.unwrap();
+    let (method, path) = mock.lock().unwrap().requests.pop().unwrap();
+    assert_eq!(method, Method::PUT);
+    assert!(path.starts_with("/tmp/blobs/"), "{path}");
+    assert_eq!(get(&store, b"blob").await.unwrap(), Some(b"abc".to_vec()));
+    assert_eq!(
+        store.get_blob(b"blob", 1..3).await.unwrap(),
+        Some(b"bc".to_vec())
+    );
+    assert_eq!(get(&store, b"missing").await.unwrap(), None);
+
+    // Throttled and failed requests are retried
+    reset(&mock, 2);
+    store.put_blob(b"blob", b"abc").await.unwrap();
+    assert_eq!(count_requests(&mock, Method::PUT), 3);
+    reset(&mock, 2);
+    assert_eq!(get(&store, b"blob").await.unwrap(), Some(b"abc".to_vec()));
+    assert_eq!(count_requests(&mock, Method::GET), 3);
+
+    // Requests fail once all retries are exhausted
+    reset(&mock, 3);
+    assert!(
+        store
+            .put_blob(b"blob", b"abc")
+            .await
+            .unwrap_err()
+            .matches(trc::EventType::Store(trc::StoreEvent::S3Error))
+    );
+    assert_eq!(count_requests(&mock, Method::PUT), 3);
+    reset(&mock, 1);
+    assert!(unverified.put_blob(b"blob", b"abc").await.is_err());
+    assert_eq!(count_requests(&mock, Method::PUT), 1);
+
+    // Checksums are verified on writes and full reads
+    reset(&mock, 0);
+    mock.lock().unwrap().etag = Some(BAD_MD5.to_string());
+    for result in [
+        store.put_blob(b"blob", b"abc").await,
+        get(&store, b"blob").await.map(|_| ()),
+    ] {
+        assert!(
+            result
+                .unwrap_err()
+                .matches(trc::EventType::Store(trc::StoreEvent::S3ChecksumMismatch))
+        );
+    }
+    assert_eq!(
+        store.get_blob(b"blob", 1..3).await.unwrap(),
+        Some(b"bc".to_vec())
+    );
+
+    // Checksum verification can be disabled
+    unverified.put_blob(b"blob", b"abc").await.unwrap();
+    assert_eq!(
+        get(&unverified, b"blob").await.unwrap(),
+        Some(b"abc".to_vec())
+    );
+
+    // ETags that are not MD5 digests are not verified
+    for etag in ["\"9b2cf535f27731c974343645a3985328-2\"", "\"abc\""] {
+        mock.lock().unwrap().etag = Some(etag.to_string());
+        store.put_blob(b"blob", b"abc").await.unwrap();
+        assert_eq!(get(&store, b"blob").await.unwrap(), Some(b"abc".to_vec()));
+    }
+    mock.lock().unwrap().etag = None;
+    store.put_blob(b"blob", b"abc").await.unwrap();
+    assert_eq!(get(&store, b"blob").await.unwrap(), Some(b"abc".to_vec()));
+
+    // Delete objects
+    for blob_store in [&store, &unverified] {
+        assert!(blob_store.delete_blob(b"blob").await.unwrap());
+        assert_eq!(get(blob_store, b"blob").await.unwrap(), None);
+    }
+    assert!(mock.lock().unwrap().objects.is_empty());
+}
+
+async fn get(store: &BlobStore, key: &[u8]) -> trc::Result<Option<Vec<u8>>> {
+    store.get_blob(key, 0..usize::MAX).await
+}
+
+fn reset(mock: &Mutex<MockS3>, fail_next: usize) {
+    let mut mock = mock.lock().unwrap();
+    mock.fail_next = fail_next;
+    mock.requests.clear();
+}
+
+fn count_requests(mock: &Mutex<MockS3>, method: Method) -> usize {
+    mock.lock()
+        .unwrap()
+        .requests
+        .iter()
+        .filter(|(request_method, _)| *request_method == method)
+        .count()
+}
+
+async fn mock_s3(listener: TcpListener, mock: Arc<Mutex<MockS3>>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let mock = mock.clone();
+        tokio::spawn(async move {
+            let _ = http1::Builder::new()
+                .serve_connection(
+                    TokioIo::new(stream),
+                    service_fn(|req: Request<Incoming>| {
+                        let mock = mock.clone();
+                        async move { Ok::<_, hyper::Error>(handle_request(req, &mock).await) }
+                    }),
+                )
+                .await;
+        });
+    }
+}
+
+async fn handle_request(req: Request<Incoming>, mock: &Mutex<MockS3>) -> Response<Full<Bytes>> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let range = req
+        .headers()
+        .get(hyper::header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("bytes="))
+        .and_then(|value| value.split_once('-'))
+        .map(|(from, to)| from.parse::<usize>().unwrap()..to.parse::<usize>().unwrap() + 1);
+    let body = req.into_body().collect().await.unwrap().to_bytes();
+
+    let mut mock = mock.lock().unwrap();
+    mock.requests.push((method.clone(), path.clone()));
+    if mock.fail_next > 0 {
+        mock.fail_next -= 1;
+        return response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            None,
+            b"<Error><Code>SlowDown</Code></Error>".to_vec(),
+        );
+    }
+
+    let etag = mock.etag.clone();
+    match method {
+        Method::PUT => {
+            mock.objects.insert(path, body.to_vec());
+            response(StatusCode::OK, etag, vec![])
+        }
+        Method::GET => match (mock.objects.get(&path), range) {
+            (Some(data), Some(range)) => {
+                response(StatusCode::PARTIAL_CONTENT, etag, data[range].to_vec())
+            }
+            (Some(data), None) => response(StatusCode::OK, etag, data.clone()),
+            (None, _) => response(
+                StatusCode::NOT_FOUND,
+                None,
+                b"<Error><Code>NoSuchKey</Code></Error>".to_vec(),
+            ),
+        },
+        Method::DELETE => {
+            mock.objects.remove(&path);
+            response(StatusCode::NO_CONTENT, None, vec![])
+        }
+        _ => response(StatusCode::METHOD_NOT_ALLOWED, None, vec![]),
+    }
+}
+
+fn response(status: StatusCode, etag: Option<String>, body: Vec<u8>) -> Response<Full<Bytes>> {
+    let mut response = Response::builder().status(status);
+    if let Some(etag) = etag {
+        response = response.header(hyper::header::ETAG, etag);
+    }
+    response.body(Full::new(Bytes::from(body))).unwrap()
+}
@@ -0,0 +1,193 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::WebDavTest;
+use common::{config::scripts::Scripting, core::BuildServer};
+use email::sieve::ingest::SieveScriptIngest;
+use groupware::calendar::availability::CalendarAvailability;
+use hyper::StatusCode;
+use mail_parser::DateTime;
+use store::{Stores, write::now};
+use utils::config::Config;
+
+pub async fn test(test: &WebDavTest) {
+    println!("Running calendar availability tests...");
+    let client = test.client("john");
+    let john_token = test
+        .server
+        .get_access_token(client.account_id)
+        .await
+        .unwrap();
+    let jane_token = test
+        .server
+        .get_access_token(test.client("jane").account_id)
+        .await
+        .unwrap();
+
+    // Validate settings
+    let mut config = Config::new("").unwrap();
+    assert!(
+        !Scripting::parse(&mut config, &Stores::default())
+            .await
+            .vacation_calendar
+    );
+    let mut config = Config::new("[sieve.untrusted.vacation]\ncalendar-aware = true\n").unwrap();
+    assert!(
+        Scripting::parse(&mut config, &Stores::default())
+            .await
+            .vacation_calendar
+    );
+
+    // Create test events
+    for (name, event) in [
+        ("holidays", EVENT_OOF),
+        ("weekly-leave", EVENT_OOF_RECURRING),
+        ("meeting", EVENT_BUSY),
+    ] {
+        client
+            .request_with_headers(
+                "PUT",
+                &format!("/dav/cal/john/default/{name}.ics"),
+                [("content-type", "text/calendar; charset=utf-8")],
+                event,
+            )
+            .await
+            .with_status(StatusCode::CREATED);
+    }
+
+    // Users are out of office during events flagged as such
+    for (timestamp, expected) in [
+        ("2030-01-06T12:00:00Z", false),
+        ("2030-01-07T09:00:00Z", true),
+        ("2030-01-08T12:00:00Z", true),
+        ("2030-01-10T17:00:00Z", false),
+        ("2030-02-01T09:30:00Z", true),
+        ("2030-02-08T09:30:00Z", true),
+        ("2030-02-08T10:30:00Z", false),
+        ("2030-02-22T09:59:59Z", true),
+        ("2030-03-01T09:30:00Z", false),
+    ] {
+        assert_eq!(
+            test.server
+                .is_out_of_office(&john_token, timestamp_of(timestamp))
+                .await
+                .unwrap(),
+            expected,
+            "{timestamp}"
+        );
+    }
+    assert!(
+        !test
+            .server
+            .is_out_of_office(&jane_token, timestamp_of("2030-01-08T12:00:00Z"))
+            .await
+            .unwrap()
+    );
+
+    // Calendar-aware vacation responses are disabled by default
+    client
+        .request_with_headers(
+            "PUT",
+            "/dav/cal/john/default/today.ics",
+            [("content-type", "text/calendar; charset=utf-8")],
+            EVENT_OOF
+                .replace("20300107T090000Z", &ical_time(now() as i64 - 3600))
+                .replace("20300110T170000Z", &ical_time(now() as i64 + 3600))
+                .replace("holidays", "today"),
+        )
+        .await
+        .with_status(StatusCode::CREATED);
+    assert!(
+        test.server
+            .is_out_of_office(&john_token, now() as i64)
+            .await
+            .unwrap()
+    );
+    assert!(!test.server.core.sieve.vacation_calendar);
+    assert!(
+        test.server
+            .sieve_script_get_out_of_office(&john_token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // No script is enabled when the user does not have a vacation script
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.sieve.vacation_calendar = true;
+    test.server.inner.shared_core.store(core.into());
+    let server = test.server.inner.build_server();
+    assert!(
+        server
+            .sieve_script_get_out_of_office(&john_token)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Clean up
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.sieve.vacation_calendar = false;
+    test.server.inner.shared_core.store(core.into());
+    client
+        .request("DELETE", "/dav/cal/john/default", "")
+        .await
+        .with_status(StatusCode::NO_CONTENT);
+    test.assert_is_empty().await;
+}
+
+fn timestamp_of(value: &str) -> i64 {
+    DateTime::parse_rfc3339(value).unwrap().to_timestamp()
+}
+
+fn ical_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp)
+        .to_rfc3339()
+        .replace(['-', ':'], "")
+}
+
+const EVENT_OOF: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Stalwart Labs//Test//EN
+BEGIN:VEVENT
+UID:availability-holidays
+DTSTAMP:20291201T000000Z
+DTSTART:20300107T090000Z
+DTEND:20300110T170000Z
+SUMMARY:Out of office
+X-MICROSOFT-CDO-BUSYSTATUS:OOF
+END:VEVENT
+END:VCALENDAR
+"#;
+
+const EVENT_OOF_RECURRING: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Stalwart Labs//Test//EN
+BEGIN:VEVENT
+UID:availability-weekly-leave
+DTSTAMP:20291201T000000Z
+DTSTART:20300201T090000Z
+DTEND:20300201T100000Z
+RRULE:FREQ=WEEKLY;COUNT=4
+SUMMARY:School run
+X-MICROSOFT-CDO-INTENDEDSTATUS:oof
+END:VEVENT
+END:VCALENDAR
+"#;
+
+const EVENT_BUSY: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Stalwart Labs//Test//EN
+BEGIN:VEVENT
+UID:availability-meeting
+DTSTAMP:20291201T000000Z
+DTSTART:20300301T090000Z
+DTEND:20300301T170000Z
+SUMMARY:All-day workshop
+X-MICROSOFT-CDO-BUSYSTATUS:BUSY
+END:VEVENT
+END:VCALENDAR
+"#;
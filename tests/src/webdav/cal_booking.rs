@@ -0,0 +1,414 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::WebDavTest;
+use crate::{
+    jmap::mailbox::destroy_all_mailboxes_for_account,
+    webdav::{DummyWebDavClient, cal_scheduling::fetch_and_remove_itips},
+};
+use calcard::icalendar::ICalendar;
+use common::{
+    Server,
+    auth::AccessToken,
+    config::groupware::{BookingPolicy, GroupwareConfig},
+};
+use directory::{
+    QueryBy, Type,
+    backend::internal::{
+        PrincipalField, PrincipalSet, PrincipalUpdate, PrincipalValue,
+        manage::{ManageDirectory, UpdatePrincipal},
+    },
+};
+use groupware::{
+    cache::GroupwareCache, calendar::booking::CalendarBooking,
+    scheduling::inbound::itip_import_message,
+};
+use hyper::StatusCode;
+use jmap_proto::types::collection::SyncCollection;
+use mail_parser::DateTime;
+use store::{Store, write::now};
+use utils::config::{Config, utils::ParseValue};
+
+const HOUR: i64 = 3600;
+const DAY: i64 = 86400;
+
+pub async fn test(test: &WebDavTest) {
+    println!("Running calendar resource booking tests...");
+    let server = &test.server;
+    let store = server.store();
+    let john_client = test.client("john");
+
+    // Validate settings
+    assert_eq!(
+        BookingPolicy::parse_value("Accept-Always").unwrap(),
+        BookingPolicy::AcceptAlways
+    );
+    assert!(BookingPolicy::parse_value("maybe").is_err());
+    let config = GroupwareConfig::parse(&mut Config::new("").unwrap());
+    assert_eq!(config.booking_policy, BookingPolicy::Manual);
+    assert_eq!(config.booking_horizon, Some(365 * DAY));
+    let config = GroupwareConfig::parse(
+        &mut Config::new(concat!(
+            "[calendar.scheduling.booking]\n",
+            "policy = \"decline\"\n",
+            "horizon = \"30d\"\n",
+        ))
+        .unwrap(),
+    );
+    assert_eq!(config.booking_policy, BookingPolicy::Decline);
+    assert_eq!(config.booking_horizon, Some(30 * DAY));
+
+    // Booking settings are only accepted on valid resources and locations
+    for (typ, field, value) in [
+        (Type::Individual, PrincipalField::BookingPolicy, "accept"),
+        (Type::Resource, PrincipalField::BookingPolicy, "maybe"),
+        (Type::Location, PrincipalField::BookingHorizon, "soon"),
+        (Type::Resource, PrincipalField::BookingDelegate, "nobody"),
+    ] {
+        assert!(
+            store
+                .create_principal(
+                    PrincipalSet::new(u32::MAX, typ)
+                        .with_field(PrincipalField::Name, "invalid-booking")
+                        .with_field(field, value),
+                    None,
+                    None,
+                )
+                .await
+                .is_err(),
+            "{typ:?} {field:?} {value:?} was accepted"
+        );
+    }
+
+    // Create a meeting room that accepts bookings without conflicts
+    let room_id = store
+        .create_principal(
+            PrincipalSet::new(u32::MAX, Type::Location)
+                .with_field(PrincipalField::Name, "room")
+                .with_field(PrincipalField::Description, "Meeting Room")
+                .with_field(
+                    PrincipalField::Secrets,
+                    PrincipalValue::StringList(vec!["secret".into()]),
+                )
+                .with_field(
+                    PrincipalField::Emails,
+                    PrincipalValue::StringList(vec!["room@example.com".into()]),
+                )
+                .with_field(
+                    PrincipalField::Roles,
+                    PrincipalValue::StringList(vec!["user".into()]),
+                )
+                .with_field(PrincipalField::BookingPolicy, "accept")
+                .with_field(PrincipalField::BookingHorizon, "30d"),
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+        .id;
+    let room_client = DummyWebDavClient::new(room_id, "room", "secret", "room@example.com");
+    let room = server
+        .booking_principal(room_id)
+        .await
+        .unwrap()
+        .expect("Room is not a booking principal");
+    assert_eq!(room.booking_policy().map(|s| s.as_str()), Some("accept"));
+    assert_eq!(room.booking_horizon().map(|s| s.as_str()), Some("30d"));
+    assert!(
+        server
+            .booking_principal(john_client.account_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        store
+            .update_principal(UpdatePrincipal::by_id(room_id).with_updates(vec![
+                PrincipalUpdate::set(
+                    PrincipalField::BookingDelegate,
+                    PrincipalValue::String("not an address".into()),
+                )
+            ]))
+            .await
+            .is_err()
+    );
+
+    // Add an existing booking to the room's calendar
+    let mut base = now() as i64 + 2 * DAY;
+    base -= base % HOUR;
+    room_client
+        .request_with_headers(
+            "PUT",
+            "/dav/cal/room/default/existing.ics",
+            [("content-type", "text/calendar; charset=utf-8")],
+            build_event("existing-booking", base, base + HOUR, false),
+        )
+        .await
+        .with_status(StatusCode::CREATED);
+    let room_token = server.get_access_token(room_id).await.unwrap();
+    let john_token = server
+        .get_access_token(john_client.account_id)
+        .await
+        .unwrap();
+
+    // Free slots are accepted and the organizer is notified
+    let (ical, messages) = booking_reply(
+        server,
+        &room_token,
+        build_event("booking-1", base + 2 * HOUR, base + 3 * HOUR, true),
+    )
+    .await;
+    assert!(
+        ical.contains("PARTSTAT=ACCEPTED:mailto:room@example.com"),
+        "failed for {ical}"
+    );
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].0, vec!["jdoe@example.com".to_string()]);
+    assert!(
+        messages[0].1.contains("METHOD:REPLY")
+            && messages[0]
+                .1
+                .contains("PARTSTAT=ACCEPTED:mailto:room@example.com"),
+        "failed for {}",
+        messages[0].1
+    );
+
+    // Conflicting slots are declined, except for updates of the same booking
+    for (uid, expected) in [
+        ("booking-2", "PARTSTAT=DECLINED:mailto:room@example.com"),
+        (
+            "existing-booking",
+            "PARTSTAT=ACCEPTED:mailto:room@example.com",
+        ),
+    ] {
+        let (ical, messages) = booking_reply(
+            server,
+            &room_token,
+            build_event(uid, base + HOUR / 2, base + 2 * HOUR, true),
+        )
+        .await;
+        assert!(ical.contains(expected), "{expected:?} not in {ical}");
+        assert_eq!(messages.len(), 1);
+    }
+
+    // Slots beyond the booking horizon are declined
+    let (ical, _) = booking_reply(
+        server,
+        &room_token,
+        build_event("booking-3", base + 60 * DAY, base + 60 * DAY + HOUR, true),
+    )
+    .await;
+    assert!(
+        ical.contains("PARTSTAT=DECLINED:mailto:room@example.com"),
+        "failed for {ical}"
+    );
+
+    // Rooms with the accept-always policy ignore conflicts
+    set_booking(
+        store,
+        room_id,
+        PrincipalField::BookingPolicy,
+        "accept-always",
+    )
+    .await;
+    let (ical, _) = booking_reply(
+        server,
+        &room_token,
+        build_event("booking-4", base + HOUR / 2, base + 2 * HOUR, true),
+    )
+    .await;
+    assert!(
+        ical.contains("PARTSTAT=ACCEPTED:mailto:room@example.com"),
+        "failed for {ical}"
+    );
+
+    // Bookings are held tentatively until the delegate approves them
+    set_booking(
+        store,
+        room_id,
+        PrincipalField::BookingDelegate,
+        "jane.smith@example.com",
+    )
+    .await;
+    let (ical, messages) = booking_reply(
+        server,
+        &room_token,
+        build_event("booking-5", base + 4 * HOUR, base + 5 * HOUR, true),
+    )
+    .await;
+    assert!(
+        ical.contains("PARTSTAT=TENTATIVE:mailto:room@example.com"),
+        "failed for {ical}"
+    );
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[1].0, vec!["jane.smith@example.com".to_string()]);
+    assert!(
+        messages[1].1.contains("METHOD:REQUEST") && messages[1].1.contains("UID:booking-5"),
+        "failed for {}",
+        messages[1].1
+    );
+    set_booking(store, room_id, PrincipalField::BookingDelegate, "").await;
+
+    // Rooms with the decline policy decline all bookings
+    set_booking(store, room_id, PrincipalField::BookingPolicy, "decline").await;
+    let (ical, _) = booking_reply(
+        server,
+        &room_token,
+        build_event("booking-6", base + 4 * HOUR, base + 5 * HOUR, true),
+    )
+    .await;
+    assert!(
+        ical.contains("PARTSTAT=DECLINED:mailto:room@example.com"),
+        "failed for {ical}"
+    );
+
+    // Manually managed rooms and regular accounts do not answer automatically
+    set_booking(store, room_id, PrincipalField::BookingPolicy, "manual").await;
+    for access_token in [&room_token, &john_token] {
+        let (ical, messages) = booking_reply(
+            server,
+            access_token,
+            build_event("booking-7", base + 4 * HOUR, base + 5 * HOUR, true),
+        )
+        .await;
+        assert!(messages.is_empty());
+        assert!(
+            ical.contains("PARTSTAT=NEEDS-ACTION:mailto:room@example.com"),
+            "failed for {ical}"
+        );
+    }
+
+    // Invitations delivered to the room are answered automatically
+    set_booking(store, room_id, PrincipalField::BookingPolicy, "accept").await;
+    john_client
+        .request_with_headers(
+            "PUT",
+            "/dav/cal/john/default/booking.ics",
+            [("content-type", "text/calendar; charset=utf-8")],
+            build_event("booking-8", base + 6 * HOUR, base + 7 * HOUR, false)
+                .replace("END:VEVENT", &format!("{ATTENDEES}END:VEVENT")),
+        )
+        .await
+        .with_status(StatusCode::CREATED);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let itips = fetch_and_remove_itips(&room_client).await;
+    assert_eq!(itips.len(), 1);
+    assert!(itips[0].contains("METHOD:REQUEST"), "failed for {itips:?}");
+    assert_eq!(
+        server
+            .fetch_dav_resources(&room_token, room_id, SyncCollection::Calendar)
+            .await
+            .unwrap()
+            .resources
+            .len(),
+        3
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let itips = fetch_and_remove_itips(john_client).await;
+    assert_eq!(itips.len(), 1);
+    assert!(
+        itips[0].contains("METHOD:REPLY")
+            && itips[0].contains("PARTSTAT=ACCEPTED:mailto:room@example.com"),
+        "failed for {itips:?}"
+    );
+    let ical = john_client
+        .request("GET", "/dav/cal/john/default/booking.ics", "")
+        .await
+        .with_status(StatusCode::OK)
+        .body
+        .unwrap();
+    assert!(
+        ical.contains("PARTSTAT=ACCEPTED;SCHEDULE-STATUS=2.0:mailto:room@example.com"),
+        "failed for {ical}"
+    );
+
+    // Clean up
+    john_client
+        .request("DELETE", "/dav/cal/john/default/booking.ics", "")
+        .await
+        .with_status(StatusCode::NO_CONTENT);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    fetch_and_remove_itips(&room_client).await;
+    for client in [john_client, &room_client] {
+        client.delete_default_containers().await;
+        destroy_all_mailboxes_for_account(client.account_id).await;
+    }
+    store.delete_principal(QueryBy::Id(room_id)).await.unwrap();
+    test.assert_is_empty().await;
+}
+
+async fn booking_reply(
+    server: &Server,
+    access_token: &AccessToken,
+    itip: String,
+) -> (String, Vec<(Vec<String>, String)>) {
+    let itip = ICalendar::parse(&itip).unwrap();
+    let mut ical = itip.clone();
+    itip_import_message(&mut ical).unwrap();
+    let messages = server
+        .itip_booking_reply(access_token, &itip, &mut ical)
+        .await
+        .unwrap_or_else(|_| panic!("Failed to process booking"));
+    (
+        ical.to_string(),
+        messages
+            .into_iter()
+            .map(|message| (message.to, message.message.to_string()))
+            .collect(),
+    )
+}
+
+async fn set_booking(store: &Store, principal_id: u32, field: PrincipalField, value: &str) {
+    store
+        .update_principal(UpdatePrincipal::by_id(principal_id).with_updates(vec![
+            PrincipalUpdate::set(field, PrincipalValue::String(value.into())),
+        ]))
+        .await
+        .unwrap();
+}
+
+fn build_event(uid: &str, start: i64, end: i64, is_request: bool) -> String {
+    let event = TEST_EVENT
+        .replace("$UID", uid)
+        .replace(
+            "$START",
+            &DateTime::from_timestamp(start)
+                .to_rfc3339()
+                .replace(['-', ':'], ""),
+        )
+        .replace(
+            "$END",
+            &DateTime::from_timestamp(end)
+                .to_rfc3339()
+                .replace(['-', ':'], ""),
+        );
+    if is_request {
+        event
+            .replace("VERSION:2.0\n", "VERSION:2.0\nMETHOD:REQUEST\n")
+            .replace("END:VEVENT", &format!("{ATTENDEES}END:VEVENT"))
+    } else {
+        event
+    }
+}
+
+const ATTENDEES: &str = concat!(
+    "ORGANIZER:mailto:jdoe@example.com\n",
+    "ATTENDEE;PARTSTAT=ACCEPTED:mailto:jdoe@example.com\n",
+    "ATTENDEE;PARTSTAT=NEEDS-ACTION:mailto:room@example.com\n",
+);
+
+const TEST_EVENT: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Stalwart Labs//Booking Test//EN
+BEGIN:VEVENT
+UID:$UID
+DTSTAMP:20250101T000000Z
+SUMMARY:Planning meeting
+DTSTART:$START
+DTEND:$END
+END:VEVENT
+END:VCALENDAR
+"#;
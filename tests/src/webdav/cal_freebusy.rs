@@ -0,0 +1,359 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::WebDavTest;
+use crate::jmap::mailbox::destroy_all_mailboxes_for_account;
+use calcard::icalendar::ICalendar;
+use common::{
+    auth::TenantInfo,
+    config::groupware::{FreeBusyPrivacy, GroupwareConfig},
+    core::BuildServer,
+};
+use dav_proto::schema::property::TimeRange;
+use directory::{
+    QueryBy, Type,
+    backend::internal::{
+        PrincipalField, PrincipalSet, PrincipalUpdate, PrincipalValue,
+        manage::{ManageDirectory, UpdatePrincipal},
+    },
+};
+use groupware::{
+    calendar::{availability::CalendarAvailability, itip::ItipIngestError},
+    scheduling::ItipError,
+};
+use hyper::StatusCode;
+use utils::config::{Config, utils::ParseValue};
+
+pub async fn test(test: &WebDavTest) {
+    println!("Running calendar free/busy federation tests...");
+    let client = test.client("john");
+
+    // Validate settings
+    assert_eq!(
+        FreeBusyPrivacy::parse_value("None").unwrap(),
+        FreeBusyPrivacy::Hidden
+    );
+    assert_eq!(
+        FreeBusyPrivacy::parse_value("detailed").unwrap(),
+        FreeBusyPrivacy::Detailed
+    );
+    assert!(FreeBusyPrivacy::parse_value("public").is_err());
+    let config = GroupwareConfig::parse(&mut Config::new("").unwrap());
+    assert!(!config.freebusy_federation);
+    assert_eq!(config.freebusy_privacy, FreeBusyPrivacy::Busy);
+    assert_eq!(config.freebusy_granularity, 30 * 60);
+    assert_eq!(config.freebusy_max_range, 60 * 86400);
+    let config = GroupwareConfig::parse(
+        &mut Config::new(concat!(
+            "[calendar.free-busy.federation]\n",
+            "enable = true\n",
+            "privacy = \"detailed\"\n",
+            "granularity = \"1s\"\n",
+            "max-range = \"7d\"\n",
+        ))
+        .unwrap(),
+    );
+    assert!(config.freebusy_federation);
+    assert_eq!(config.freebusy_privacy, FreeBusyPrivacy::Detailed);
+    assert_eq!(config.freebusy_granularity, 60);
+    assert_eq!(config.freebusy_max_range, 7 * 86400);
+
+    // Add events to John's calendar
+    for (name, extra, start, end) in [
+        ("busy", "", "100700", "105200"),
+        ("tentative", "STATUS:TENTATIVE\n", "120000", "130000"),
+        (
+            "oof",
+            "X-MICROSOFT-CDO-BUSYSTATUS:OOF\n",
+            "140000",
+            "150000",
+        ),
+        ("free", "TRANSP:TRANSPARENT\n", "160000", "170000"),
+        ("cancelled", "STATUS:CANCELLED\n", "180000", "190000"),
+    ] {
+        client
+            .request_with_headers(
+                "PUT",
+                &format!("/dav/cal/john/default/{name}.ics"),
+                [("content-type", "text/calendar; charset=utf-8")],
+                TEST_EVENT
+                    .replace("$NAME", name)
+                    .replace("$EXTRA\n", extra)
+                    .replace("$START", start)
+                    .replace("$END", end),
+            )
+            .await
+            .with_status(StatusCode::CREATED);
+    }
+
+    // Free/busy information is not published unless federation is enabled
+    let url =
+        "/calendar/freebusy/jdoe@example.com?start=2030-01-07T00:00:00Z&end=2030-01-08T00:00:00Z";
+    client
+        .request("GET", url, "")
+        .await
+        .with_status(StatusCode::NOT_FOUND);
+
+    // Enable federation
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.freebusy_federation = true;
+    test.server.inner.shared_core.store(core.into());
+    let server = test.server.inner.build_server();
+
+    // Busy periods are rounded to the configured granularity and merged
+    let response = client
+        .request("GET", url, "")
+        .await
+        .with_status(StatusCode::OK)
+        .with_header("content-type", "text/calendar; charset=utf-8")
+        .body
+        .unwrap();
+    for expected in [
+        "BEGIN:VFREEBUSY",
+        "ORGANIZER:mailto:jdoe@example.com",
+        "DTSTART:20300107T000000Z",
+        "DTEND:20300108T000000Z",
+        "20300107T100000Z/20300107T110000Z",
+        "20300107T120000Z/20300107T130000Z",
+        "20300107T140000Z/20300107T150000Z",
+    ] {
+        assert!(
+            response.contains(expected),
+            "{expected:?} not in {response}"
+        );
+    }
+    for unexpected in [
+        "BUSY-TENTATIVE",
+        "BUSY-UNAVAILABLE",
+        "SUMMARY",
+        "20300107T160000Z",
+        "20300107T180000Z",
+    ] {
+        assert!(
+            !response.contains(unexpected),
+            "{unexpected:?} in {response}"
+        );
+    }
+
+    // Unknown accounts are not disclosed
+    client
+        .request(
+            "GET",
+            "/calendar/freebusy/unknown@example.com?start=2030-01-07T00:00:00Z",
+            "",
+        )
+        .await
+        .with_status(StatusCode::NOT_FOUND);
+
+    // Detailed free/busy information includes the exact times and types
+    let access_token = server.get_access_token(client.account_id).await.unwrap();
+    let range = TimeRange {
+        start: 1893974400,
+        end: 1893974400 + 86400,
+    };
+    let ical = server
+        .free_busy_object(&access_token, range, FreeBusyPrivacy::Detailed)
+        .await
+        .unwrap()
+        .to_string();
+    for expected in [
+        "FREEBUSY;FBTYPE=BUSY:20300107T100700Z/20300107T105200Z",
+        "FREEBUSY;FBTYPE=BUSY-TENTATIVE:20300107T120000Z/20300107T130000Z",
+        "FREEBUSY;FBTYPE=BUSY-UNAVAILABLE:20300107T140000Z/20300107T150000Z",
+    ] {
+        assert!(ical.contains(expected), "{expected:?} not in {ical}");
+    }
+    assert!(
+        !server
+            .free_busy_object(&access_token, range, FreeBusyPrivacy::Hidden)
+            .await
+            .unwrap()
+            .to_string()
+            .contains("FREEBUSY;"),
+    );
+
+    // Tenants can override the default privacy level
+    let store = server.store();
+    store
+        .create_principal(
+            PrincipalSet::new(u32::MAX, Type::Tenant)
+                .with_field(PrincipalField::Name, "fb-invalid")
+                .with_field(PrincipalField::FreeBusyPolicy, "public"),
+            None,
+            None,
+        )
+        .await
+        .unwrap_err();
+    let tenant_id = store
+        .create_principal(
+            PrincipalSet::new(u32::MAX, Type::Tenant)
+                .with_field(PrincipalField::Name, "fb-tenant")
+                .with_field(PrincipalField::FreeBusyPolicy, "none"),
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+        .id;
+    let mut tenant_token = access_token.as_ref().clone();
+    tenant_token.tenant = Some(TenantInfo {
+        id: tenant_id,
+        quota: 0,
+    });
+    assert_eq!(
+        server.free_busy_privacy(&access_token).await.unwrap(),
+        FreeBusyPrivacy::Busy
+    );
+    assert_eq!(
+        server.free_busy_privacy(&tenant_token).await.unwrap(),
+        FreeBusyPrivacy::Hidden
+    );
+    for (policy, expected) in [
+        ("detailed", FreeBusyPrivacy::Detailed),
+        ("", FreeBusyPrivacy::Busy),
+    ] {
+        store
+            .update_principal(UpdatePrincipal::by_id(tenant_id).with_updates(vec![
+                PrincipalUpdate::set(
+                    PrincipalField::FreeBusyPolicy,
+                    PrincipalValue::String(policy.into()),
+                ),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(
+            server.free_busy_privacy(&tenant_token).await.unwrap(),
+            expected
+        );
+    }
+    store
+        .update_principal(UpdatePrincipal::by_id(tenant_id).with_updates(vec![
+            PrincipalUpdate::set(
+                PrincipalField::FreeBusyPolicy,
+                PrincipalValue::String("public".into()),
+            ),
+        ]))
+        .await
+        .unwrap_err();
+
+    // Free/busy requests received over iMIP are answered on behalf of the attendee
+    let request = ICalendar::parse(TEST_FREEBUSY_REQUEST).unwrap();
+    let reply = server
+        .itip_free_busy_reply(&access_token, &request, "jane.smith@example.com")
+        .await
+        .unwrap_or_else(|_| panic!("Failed to build free/busy reply"));
+    assert_eq!(reply.from, "jdoe@example.com");
+    assert!(!reply.from_organizer);
+    assert_eq!(reply.to, vec!["jane.smith@example.com".to_string()]);
+    let reply = reply.message.to_string();
+    for expected in [
+        "METHOD:REPLY",
+        "UID:fb-request-1",
+        "ORGANIZER:mailto:jane.smith@example.com",
+        "ATTENDEE:mailto:jdoe@example.com",
+        "FREEBUSY;FBTYPE=BUSY:20300107T100000Z/20300107T110000Z",
+    ] {
+        assert!(reply.contains(expected), "{expected:?} not in {reply}");
+    }
+
+    // Replies are only sent to the organizer and for local attendees
+    assert!(matches!(
+        server
+            .itip_free_busy_reply(&access_token, &request, "bill@example.com")
+            .await,
+        Err(ItipIngestError::Message(
+            ItipError::SenderIsNotOrganizerNorAttendee
+        ))
+    ));
+    let request =
+        ICalendar::parse(&TEST_FREEBUSY_REQUEST.replace("jdoe@example.com", "mike@example.com"))
+            .unwrap();
+    assert!(matches!(
+        server
+            .itip_free_busy_reply(&access_token, &request, "jane.smith@example.com")
+            .await,
+        Err(ItipIngestError::Message(ItipError::NotOrganizerNorAttendee))
+    ));
+
+    // Accounts with hidden free/busy information are not published
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.freebusy_privacy = FreeBusyPrivacy::Hidden;
+    test.server.inner.shared_core.store(core.into());
+    client
+        .request("GET", url, "")
+        .await
+        .with_status(StatusCode::NOT_FOUND);
+    let server = test.server.inner.build_server();
+    assert!(matches!(
+        server
+            .itip_free_busy_reply(
+                &access_token,
+                &ICalendar::parse(TEST_FREEBUSY_REQUEST).unwrap(),
+                "jane.smith@example.com"
+            )
+            .await,
+        Err(ItipIngestError::Message(ItipError::NothingToSend))
+    ));
+
+    // Requested ranges are limited to the maximum range
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.freebusy_privacy = FreeBusyPrivacy::Busy;
+    core.groupware.freebusy_max_range = 12 * 3600;
+    test.server.inner.shared_core.store(core.into());
+    let response = client
+        .request("GET", url, "")
+        .await
+        .with_status(StatusCode::OK)
+        .body
+        .unwrap();
+    assert!(
+        response.contains("DTEND:20300107T120000Z")
+            && response.contains("20300107T100000Z/20300107T110000Z")
+            && !response.contains("20300107T120000Z/"),
+        "failed for {response}"
+    );
+
+    // Clean up
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.freebusy_federation = false;
+    core.groupware.freebusy_max_range = 60 * 86400;
+    test.server.inner.shared_core.store(core.into());
+    store
+        .delete_principal(QueryBy::Id(tenant_id))
+        .await
+        .unwrap();
+    client.delete_default_containers().await;
+    destroy_all_mailboxes_for_account(client.account_id).await;
+    test.assert_is_empty().await;
+}
+
+const TEST_EVENT: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Stalwart Labs//Free/Busy Test//EN
+BEGIN:VEVENT
+UID:freebusy-$NAME
+SUMMARY:Private $NAME
+$EXTRA
+DTSTART:20300107T$STARTZ
+DTEND:20300107T$ENDZ
+END:VEVENT
+END:VCALENDAR
+"#;
+
+const TEST_FREEBUSY_REQUEST: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Example Corp.//CalDAV Client//EN
+METHOD:REQUEST
+BEGIN:VFREEBUSY
+UID:fb-request-1
+DTSTAMP:20300101T000000Z
+DTSTART:20300107T000000Z
+DTEND:20300108T000000Z
+ORGANIZER:mailto:jane.smith@example.com
+ATTENDEE:mailto:jdoe@example.com
+END:VFREEBUSY
+END:VCALENDAR
+"#;
@@ -18,7 +18,11 @@ use calcard::{
 };
 use common::{Server, auth::AccessToken};
 use dav_proto::schema::property::{CalDavProperty, DavProperty, WebDavProperty};
-use email::cache::MessageCacheFetch;
+use email::{
+    cache::MessageCacheFetch,
+    mailbox::INBOX_ID,
+    message::ingest::{EmailIngest, IngestEmail, IngestSource},
+};
 use groupware::{
     cache::GroupwareCache,
     scheduling::{
@@ -27,7 +31,7 @@ use groupware::{
 };
 use hyper::StatusCode;
 use jmap_proto::types::collection::SyncCollection;
-use mail_parser::{DateTime, MessageParser};
+use mail_parser::{DateTime, MessageParser, MimeHeaders};
 use services::task_manager::{Task, TaskAction, imip::build_itip_template};
 use std::str::FromStr;
 use store::write::now;
@@ -328,6 +332,39 @@ pub async fn test(test: &WebDavTest) {
         .document_id;
     let contents = test.fetch_email(bill_client.account_id, document_id).await;
     let message = MessageParser::new().parse(&contents).unwrap();
+
+    // Invitations include the iCalendar object inline and as an attachment
+    let ical_parts = message
+        .parts
+        .iter()
+        .filter(|part| {
+            part.content_type().is_some_and(|ct| {
+                ct.subtype().is_some_and(|st| {
+                    (ct.ctype().eq_ignore_ascii_case("text") && st.eq_ignore_ascii_case("calendar"))
+                        || (ct.ctype().eq_ignore_ascii_case("application")
+                            && st.eq_ignore_ascii_case("ics"))
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ical_parts.len(), 2);
+    assert_eq!(
+        ical_parts[0]
+            .content_type()
+            .and_then(|ct| ct.attribute("method")),
+        Some("REQUEST")
+    );
+    assert_eq!(ical_parts[0].attachment_name(), None);
+    assert_eq!(ical_parts[1].content_type().unwrap().ctype(), "application");
+    assert_eq!(ical_parts[1].attachment_name(), Some("event.ics"));
+    for part in ical_parts {
+        let ical = std::str::from_utf8(part.contents()).unwrap();
+        assert!(
+            ical.contains("METHOD:REQUEST") && ical.contains("SUMMARY:Lunch"),
+            "failed for ical: {ical}"
+        );
+    }
+
     let contents = message
         .html_bodies()
         .next()
@@ -519,6 +556,84 @@ pub async fn test(test: &WebDavTest) {
         Vec::<String>::new()
     );
 
+    // iMIP invitations from external organizers are accepted without a method
+    // parameter and are only processed once when attached in several parts
+    let ical = TEST_IMIP_EXTERNAL
+        .replace(
+            "$START",
+            &DateTime::from_timestamp(now() as i64 + 60 * 60)
+                .to_rfc3339()
+                .replace(['-', ':'], ""),
+        )
+        .replace(
+            "$END",
+            &DateTime::from_timestamp(now() as i64 + 2 * 60 * 60)
+                .to_rfc3339()
+                .replace(['-', ':'], ""),
+        );
+    let raw_message = format!(
+        concat!(
+            "From: organizer@remote.org\r\n",
+            "To: bill@example.com\r\n",
+            "Subject: Invitation: Planning\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"imip\"\r\n",
+            "\r\n",
+            "--imip\r\n",
+            "Content-Type: text/calendar; charset=utf-8\r\n",
+            "\r\n",
+            "{ical}\r\n",
+            "--imip\r\n",
+            "Content-Type: application/ics\r\n",
+            "Content-Disposition: attachment; filename=\"invite.ics\"\r\n",
+            "\r\n",
+            "{ical}\r\n",
+            "--imip--\r\n"
+        ),
+        ical = ical
+    );
+    let access_token = test
+        .server
+        .get_access_token(bill_client.account_id)
+        .await
+        .unwrap();
+    test.server
+        .email_ingest(IngestEmail {
+            raw_message: raw_message.as_bytes(),
+            message: MessageParser::new().parse(raw_message.as_bytes()),
+            access_token: access_token.as_ref(),
+            mailbox_ids: vec![INBOX_ID],
+            keywords: vec![],
+            received_at: None,
+            source: IngestSource::Smtp {
+                deliver_to: "bill@example.com",
+                is_sender_authenticated: true,
+            },
+            spam_classify: false,
+            spam_train: false,
+            session_id: 0,
+        })
+        .await
+        .unwrap();
+    let itips = fetch_and_remove_itips(bill_client).await;
+    assert_eq!(itips.len(), 1, "failed for itips: {itips:?}");
+    assert!(
+        itips[0].contains("METHOD:REQUEST") && itips[0].contains("UID:imip-external-organizer"),
+        "failed for itip: {}",
+        itips[0]
+    );
+    let cals = fetch_icals(bill_client).await;
+    assert_eq!(cals.len(), 1);
+    assert!(
+        cals[0].ical.contains("SUMMARY:Planning"),
+        "failed for cal: {}",
+        cals[0].ical
+    );
+    bill_client
+        .request_with_headers("DELETE", &cals[0].href, [("Schedule-Reply", "F")], "")
+        .await
+        .with_status(StatusCode::NO_CONTENT);
+
     for client in [bill_client, jane_client, john_client] {
         client.delete_default_containers().await;
         destroy_all_mailboxes_for_account(client.account_id).await;
@@ -527,7 +642,7 @@ pub async fn test(test: &WebDavTest) {
     test.assert_is_empty().await;
 }
 
-async fn fetch_and_remove_itips(client: &DummyWebDavClient) -> Vec<String> {
+pub async fn fetch_and_remove_itips(client: &DummyWebDavClient) -> Vec<String> {
     let inbox_href = format!("/dav/itip/{}/inbox/", client.name);
     let response = client
         .propfind_with_headers(&inbox_href, ALL_DAV_PROPERTIES, [("depth", "1")])
@@ -828,6 +943,24 @@ END:VEVENT
 END:VCALENDAR
 "#;
 
+const TEST_IMIP_EXTERNAL: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Example Corp.//CalDAV Client//EN
+METHOD:REQUEST
+BEGIN:VEVENT
+UID:imip-external-organizer
+SEQUENCE:0
+DTSTART:$START
+DTEND:$END
+DTSTAMP:20090602T170000Z
+SUMMARY:Planning
+ORGANIZER:mailto:organizer@remote.org
+ATTENDEE;CUTYPE=INDIVIDUAL;PARTSTAT=ACCEPTED:mailto:organizer@remote.org
+ATTENDEE;CUTYPE=INDIVIDUAL;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:bill@example.com
+END:VEVENT
+END:VCALENDAR
+"#;
+
 const TEST_FREEBUSY: &str = r#"BEGIN:VCALENDAR
 VERSION:2.0
 PRODID:-//Example Corp.//CalDAV Client//EN
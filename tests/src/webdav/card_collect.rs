@@ -0,0 +1,178 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use super::WebDavTest;
+use common::{
+    KV_RATE_LIMIT_COLLECT,
+    config::groupware::{CollectConfig, GroupwareConfig},
+    core::BuildServer,
+};
+use groupware::contact::collect::{CollectedAddress, ContactCollect};
+use hyper::StatusCode;
+use utils::config::{Config, Rate};
+
+pub async fn test(test: &WebDavTest) {
+    println!("Running contact collection tests...");
+    let client = test.client("john");
+    let access_token = test
+        .server
+        .get_access_token(client.account_id)
+        .await
+        .unwrap();
+
+    // Validate settings
+    let config = GroupwareConfig::parse(&mut Config::new("").unwrap());
+    assert!(config.collect.is_none());
+    let config = GroupwareConfig::parse(
+        &mut Config::new(concat!(
+            "[contacts.collect]\n",
+            "enable = true\n",
+            "max-recipients = 10\n",
+            "rate = \"5/1h\"\n",
+        ))
+        .unwrap(),
+    );
+    let collect = config.collect.unwrap();
+    assert_eq!(collect.name, "collected");
+    assert_eq!(collect.display_name, "Collected Addresses");
+    assert_eq!(collect.max_recipients, 10);
+    assert_eq!(
+        collect.rate,
+        Some(Rate {
+            requests: 5,
+            period: Duration::from_secs(60 * 60)
+        })
+    );
+
+    // Nothing is collected unless enabled
+    assert_eq!(
+        test.server
+            .collect_addresses(&access_token, addresses(&[("bill@example.com", None)]))
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Collect recipients, skipping own addresses, duplicates and excess recipients
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.collect = Some(CollectConfig {
+        name: "collected".to_string(),
+        display_name: "Collected Addresses".to_string(),
+        max_recipients: 4,
+        rate: None,
+    });
+    test.server.inner.shared_core.store(core.into());
+    let server = test.server.inner.build_server();
+    assert_eq!(
+        server
+            .collect_addresses(
+                &access_token,
+                addresses(&[
+                    ("Bill@Example.com", Some("Bill Foobar")),
+                    ("jdoe@example.com", Some("John Doe")),
+                    ("bill@example.com", None),
+                    ("new.person@example.org", None),
+                    ("over@example.org", None),
+                ]),
+            )
+            .await
+            .unwrap(),
+        2
+    );
+    for (path, expected) in [
+        (
+            "/dav/card/john/collected/bill@example.com.vcf",
+            ["FN:Bill Foobar", "bill@example.com"],
+        ),
+        (
+            "/dav/card/john/collected/new.person@example.org.vcf",
+            ["FN:new.person@example.org", "new.person@example.org"],
+        ),
+    ] {
+        let body = client
+            .request("GET", path, "")
+            .await
+            .with_status(StatusCode::OK)
+            .body
+            .unwrap();
+        for expected in expected {
+            assert!(body.contains(expected), "{expected:?} not found in {body}");
+        }
+    }
+    for path in [
+        "/dav/card/john/collected/jdoe@example.com.vcf",
+        "/dav/card/john/collected/over@example.org.vcf",
+    ] {
+        client
+            .request("GET", path, "")
+            .await
+            .with_status(StatusCode::NOT_FOUND);
+    }
+
+    // Addresses already present in an address book are not collected again
+    assert_eq!(
+        server
+            .collect_addresses(
+                &access_token,
+                addresses(&[("bill@example.com", Some("Bill"))]),
+            )
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Collection stops once the rate limit is exceeded
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.collect.as_mut().unwrap().rate = Some(Rate {
+        requests: 1,
+        period: Duration::from_secs(60 * 60),
+    });
+    test.server.inner.shared_core.store(core.into());
+    let server = test.server.inner.build_server();
+    assert_eq!(
+        server
+            .collect_addresses(
+                &access_token,
+                addresses(&[("first@example.org", None), ("second@example.org", None)]),
+            )
+            .await
+            .unwrap(),
+        1
+    );
+    client
+        .request("GET", "/dav/card/john/collected/second@example.org.vcf", "")
+        .await
+        .with_status(StatusCode::NOT_FOUND);
+
+    // Disable collection and clean up
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.collect = None;
+    test.server.inner.shared_core.store(core.into());
+    test.server
+        .in_memory_store()
+        .key_delete_prefix(&[KV_RATE_LIMIT_COLLECT])
+        .await
+        .unwrap();
+    for path in ["/dav/card/john/collected", "/dav/card/john/default"] {
+        client
+            .request("DELETE", path, "")
+            .await
+            .with_status(StatusCode::NO_CONTENT);
+    }
+    test.assert_is_empty().await;
+}
+
+fn addresses(addresses: &[(&str, Option<&str>)]) -> Vec<CollectedAddress> {
+    addresses
+        .iter()
+        .map(|(address, name)| CollectedAddress {
+            address: address.to_string(),
+            name: name.map(|name| name.to_string()),
+        })
+        .collect()
+}
@@ -0,0 +1,171 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use super::WebDavTest;
+use crate::directory::internal::TestInternalDirectory;
+use common::{
+    config::groupware::{GalConfig, GroupwareConfig},
+    core::BuildServer,
+};
+use directory::{QueryBy, backend::internal::manage::ManageDirectory};
+use groupware::contact::gal::GlobalAddressList;
+use hyper::StatusCode;
+use utils::config::Config;
+
+pub async fn test(test: &WebDavTest) {
+    println!("Running global address list tests...");
+    let john = test.client("john");
+    let jane = test.client("jane");
+
+    // Validate settings
+    let config = GroupwareConfig::parse(&mut Config::new("").unwrap());
+    assert!(config.gal.is_none());
+    let config = GroupwareConfig::parse(
+        &mut Config::new(concat!(
+            "[contacts.directory]\n",
+            "enable = true\n",
+            "account = \"john\"\n",
+            "sync-interval = \"5m\"\n",
+        ))
+        .unwrap(),
+    );
+    let gal = config.gal.unwrap();
+    assert_eq!(gal.name, "directory");
+    assert_eq!(gal.display_name, "Global Address List");
+    assert_eq!(gal.account.as_deref(), Some("john"));
+    assert_eq!(gal.sync_interval, Duration::from_secs(5 * 60));
+
+    // Enable the global address list
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.gal = Some(GalConfig {
+        name: "directory".to_string(),
+        display_name: "Company Directory".to_string(),
+        account: Some("john".to_string()),
+        sync_interval: Duration::from_secs(60 * 60),
+    });
+    test.server.inner.shared_core.store(core.into());
+    let temp_id = test
+        .server
+        .store()
+        .create_test_user(
+            "gal-temp",
+            "secret",
+            "Temporary User",
+            &["temp@example.com"],
+        )
+        .await;
+    test.server
+        .inner
+        .build_server()
+        .sync_global_address_lists()
+        .await
+        .unwrap();
+
+    // Every principal is published as a card
+    let jane_card = format!("/dav/card/john/directory/{}.vcf", jane.account_id);
+    let support_id = test
+        .server
+        .store()
+        .get_principal_id("support")
+        .await
+        .unwrap()
+        .unwrap();
+    for (client, path, expected) in [
+        (
+            john,
+            jane_card.clone(),
+            [
+                "KIND:individual",
+                "FN:Jane Doe-Smith",
+                "jane.smith@example.com",
+            ],
+        ),
+        (
+            jane,
+            jane_card.clone(),
+            [
+                "KIND:individual",
+                "FN:Jane Doe-Smith",
+                "jane.smith@example.com",
+            ],
+        ),
+        (
+            jane,
+            format!("/dav/card/john/directory/{support_id}.vcf"),
+            ["KIND:group", "FN:Support Group", "support@example.com"],
+        ),
+        (
+            jane,
+            format!("/dav/card/john/directory/{temp_id}.vcf"),
+            ["KIND:individual", "FN:Temporary User", "temp@example.com"],
+        ),
+    ] {
+        let body = client
+            .request("GET", &path, "")
+            .await
+            .with_status(StatusCode::OK)
+            .body
+            .unwrap();
+        for expected in expected {
+            assert!(body.contains(expected), "{expected:?} not found in {body}");
+        }
+    }
+    let etag = jane
+        .request("GET", &jane_card, "")
+        .await
+        .with_status(StatusCode::OK)
+        .etag()
+        .to_string();
+
+    // The address book is read-only for other accounts
+    jane.request(
+        "PUT",
+        "/dav/card/john/directory/new.vcf",
+        "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:New\r\nUID:new\r\nEND:VCARD\r\n",
+    )
+    .await
+    .with_status(StatusCode::FORBIDDEN);
+    jane.request("DELETE", &jane_card, "")
+        .await
+        .with_status(StatusCode::FORBIDDEN);
+
+    // Unchanged principals are not updated and removed principals are deleted
+    test.server
+        .store()
+        .delete_principal(QueryBy::Id(temp_id))
+        .await
+        .unwrap();
+    test.server
+        .inner
+        .build_server()
+        .sync_global_address_lists()
+        .await
+        .unwrap();
+    jane.request("GET", &jane_card, "")
+        .await
+        .with_status(StatusCode::OK)
+        .with_header("etag", &etag);
+    jane.request(
+        "GET",
+        &format!("/dav/card/john/directory/{temp_id}.vcf"),
+        "",
+    )
+    .await
+    .with_status(StatusCode::NOT_FOUND);
+
+    // Disable the global address list and clean up
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.groupware.gal = None;
+    test.server.inner.shared_core.store(core.into());
+    for path in ["/dav/card/john/directory", "/dav/card/john/default"] {
+        john.request("DELETE", path, "")
+            .await
+            .with_status(StatusCode::NO_CONTENT);
+    }
+    test.assert_is_empty().await;
+}
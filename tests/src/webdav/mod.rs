@@ -49,9 +49,14 @@ use utils::config::Config;
 pub mod acl;
 pub mod basic;
 pub mod cal_alarm;
+pub mod cal_availability;
+pub mod cal_booking;
+pub mod cal_freebusy;
 pub mod cal_itip;
 pub mod cal_query;
 pub mod cal_scheduling;
+pub mod card_collect;
+pub mod card_gal;
 pub mod card_query;
 pub mod copy_move;
 pub mod lock;
@@ -60,6 +65,7 @@ pub mod multiget;
 pub mod principals;
 pub mod prop;
 pub mod put_get;
+pub mod sieve_lists;
 pub mod sync;
 
 #[test]
@@ -93,10 +99,16 @@ fn webdav_tests() {
             principals::test(&handle).await;
             acl::test(&handle).await;
             card_query::test(&handle).await;
+            card_gal::test(&handle).await;
+            card_collect::test(&handle).await;
+            sieve_lists::test(&handle).await;
             cal_query::test(&handle).await;
             cal_alarm::test(&handle).await;
+            cal_availability::test(&handle).await;
             cal_itip::test();
             cal_scheduling::test(&handle).await;
+            cal_freebusy::test(&handle).await;
+            cal_booking::test(&handle).await;
 
             // Print elapsed time
             let elapsed = start_time.elapsed();
@@ -0,0 +1,195 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs LLC <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::WebDavTest;
+use common::{
+    config::scripts::Scripting,
+    core::BuildServer,
+    scripts::{LIST_ADDRBOOK_DEFAULT, LIST_ADDRBOOK_GROUPS, LIST_ADDRBOOK_PERSONAL},
+};
+use email::sieve::lists::SieveListLookup;
+use hyper::StatusCode;
+use sieve::MatchAs;
+use store::{Stores, dispatch::lookup::KeyValue};
+use utils::config::Config;
+
+pub async fn test(test: &WebDavTest) {
+    println!("Running Sieve extlists tests...");
+    let client = test.client("john");
+    let john_token = test
+        .server
+        .get_access_token(client.account_id)
+        .await
+        .unwrap();
+    let jane_token = test
+        .server
+        .get_access_token(test.client("jane").account_id)
+        .await
+        .unwrap();
+
+    // Validate settings
+    let mut config = Config::new("[sieve.untrusted]\next-lists = [\"missing\"]\n").unwrap();
+    let scripting = Scripting::parse(&mut config, &Stores::default()).await;
+    assert!(scripting.untrusted_lists.contains("missing"));
+    assert!(config.errors.contains_key("sieve.untrusted.ext-lists"));
+
+    // Addresses are matched against the user's address books
+    client
+        .request(
+            "PUT",
+            "/dav/card/john/default/bill.vcf",
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                "UID:urn:uuid:sieve-lists-bill\r\n",
+                "FN:Bill Foobar\r\n",
+                "EMAIL:bill@remote.org\r\n",
+                "END:VCARD\r\n"
+            ),
+        )
+        .await
+        .with_status(StatusCode::CREATED);
+    for list in [LIST_ADDRBOOK_DEFAULT, LIST_ADDRBOOK_PERSONAL] {
+        for (values, expected) in [
+            (&["Bill@Remote.org"][..], true),
+            (&["unknown@remote.org", "bill@remote.org"][..], true),
+            (&["unknown@remote.org"][..], false),
+            (&["not an address"][..], false),
+        ] {
+            assert_eq!(
+                contains(test, &john_token, list, values, MatchAs::Octet).await,
+                Some(expected),
+                "{list} {values:?}"
+            );
+        }
+        assert_eq!(
+            contains(
+                test,
+                &jane_token,
+                list,
+                &["bill@remote.org"],
+                MatchAs::Octet
+            )
+            .await,
+            Some(false)
+        );
+    }
+
+    // Addresses are matched against the user's groups and their members
+    for (values, expected) in [
+        (&["Support@Example.com"][..], true),
+        (&["jane.smith@example.com"][..], true),
+        (&["jdoe@example.com"][..], false),
+        (&["bill@remote.org"][..], false),
+    ] {
+        assert_eq!(
+            contains(
+                test,
+                &jane_token,
+                LIST_ADDRBOOK_GROUPS,
+                values,
+                MatchAs::Octet
+            )
+            .await,
+            Some(expected),
+            "{values:?}"
+        );
+    }
+    assert_eq!(
+        contains(
+            test,
+            &john_token,
+            LIST_ADDRBOOK_GROUPS,
+            &["support@example.com"],
+            MatchAs::Octet
+        )
+        .await,
+        Some(false)
+    );
+
+    // Unknown lists are not available to untrusted scripts
+    assert_eq!(
+        contains(
+            test,
+            &john_token,
+            "blocked",
+            &["bill@remote.org"],
+            MatchAs::Octet
+        )
+        .await,
+        None
+    );
+
+    // Lists configured by the administrator are looked up in the in-memory store
+    let store = test.server.in_memory_store().clone();
+    store
+        .key_set(KeyValue::new("spammer@remote.org", b"1".to_vec()))
+        .await
+        .unwrap();
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.sieve.untrusted_lists.insert("blocked".to_string());
+    core.storage
+        .lookups
+        .insert("blocked".to_string(), store.clone());
+    test.server.inner.shared_core.store(core.into());
+    let server = test.server.inner.build_server();
+    for (values, match_as, expected) in [
+        (&["spammer@remote.org"][..], MatchAs::Octet, true),
+        (&["Spammer@Remote.org"][..], MatchAs::Octet, false),
+        (&["Spammer@Remote.org"][..], MatchAs::Lowercase, true),
+        (&["bill@remote.org"][..], MatchAs::Lowercase, false),
+    ] {
+        assert_eq!(
+            server
+                .sieve_list_contains(
+                    &john_token,
+                    "blocked",
+                    &values
+                        .iter()
+                        .map(|value| value.to_string())
+                        .collect::<Vec<_>>(),
+                    match_as,
+                )
+                .await
+                .unwrap(),
+            Some(expected),
+            "{values:?}"
+        );
+    }
+
+    // Clean up
+    let mut core = test.server.inner.shared_core.load_full().as_ref().clone();
+    core.sieve.untrusted_lists.remove("blocked");
+    core.storage.lookups.remove("blocked");
+    test.server.inner.shared_core.store(core.into());
+    store.key_delete("spammer@remote.org").await.unwrap();
+    client
+        .request("DELETE", "/dav/card/john/default", "")
+        .await
+        .with_status(StatusCode::NO_CONTENT);
+    test.assert_is_empty().await;
+}
+
+async fn contains(
+    test: &WebDavTest,
+    access_token: &common::auth::AccessToken,
+    list: &str,
+    values: &[&str],
+    match_as: MatchAs,
+) -> Option<bool> {
+    test.server
+        .sieve_list_contains(
+            access_token,
+            list,
+            &values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>(),
+            match_as,
+        )
+        .await
+        .unwrap()
+}